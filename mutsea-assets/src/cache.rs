@@ -54,6 +54,16 @@ impl AssetCache {
         }
     }
     
+    /// Get an asset even if its TTL has passed. Assets are immutable, so
+    /// an expired entry is still correct; used while the backend is down.
+    pub async fn get_stale(&self, asset_id: AssetId) -> Option<Asset> {
+        let mut cache = self.cache.write().await;
+        let cached = cache.get_mut(&asset_id)?;
+        cached.access_count += 1;
+        cached.last_accessed = Instant::now();
+        Some(cached.asset.clone())
+    }
+    
     /// Put an asset in cache
     pub async fn put(&self, asset: Asset) {
         let mut cache = self.cache.write().await;
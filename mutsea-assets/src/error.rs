@@ -1,5 +1,6 @@
 //! Asset management errors
 
+use mutsea_core::{ErrorCategory, ErrorClassification, MutseaError};
 use thiserror::Error;
 
 /// Asset management errors
@@ -42,5 +43,34 @@ pub enum AssetError {
     Generic(String),
 }
 
+impl ErrorClassification for AssetError {
+    fn code(&self) -> &'static str {
+        match self {
+            AssetError::Io(_) => "ASSET_IO",
+            AssetError::Serialization(_) => "ASSET_SERIALIZATION",
+            AssetError::NotFound(_) => "ASSET_NOT_FOUND",
+            AssetError::InvalidFormat(_) => "ASSET_INVALID_FORMAT",
+            AssetError::Storage(_) => "ASSET_STORAGE",
+            AssetError::Cache(_) => "ASSET_CACHE",
+            AssetError::PermissionDenied(_) => "ASSET_PERMISSION_DENIED",
+            AssetError::TooLarge(..) => "ASSET_TOO_LARGE",
+            AssetError::Generic(_) => "ASSET_INTERNAL",
+        }
+    }
+
+    fn category(&self) -> ErrorCategory {
+        match self {
+            AssetError::Io(_) | AssetError::Storage(_) => ErrorCategory::Retryable,
+            _ => ErrorCategory::Permanent,
+        }
+    }
+}
+
+impl From<AssetError> for MutseaError {
+    fn from(err: AssetError) -> Self {
+        MutseaError::classified(&err)
+    }
+}
+
 /// Result type for asset operations
 pub type AssetResult<T> = Result<T, AssetError>;
\ No newline at end of file
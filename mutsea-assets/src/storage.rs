@@ -1,8 +1,10 @@
 //! Asset storage backends
 
-use crate::AssetError;
-use mutsea_core::{Asset, AssetId};
+use crate::{AssetCache, AssetError};
+use mutsea_core::circuit_breaker::{BreakerError, CircuitBreaker};
+use mutsea_core::{retry::Retrier, Asset, AssetId};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 /// Asset storage backend trait
 #[async_trait::async_trait]
@@ -74,4 +76,111 @@ impl AssetStorage for LocalStorage {
         let path = self.asset_path(asset_id);
         Ok(path.exists())
     }
-}
\ No newline at end of file
+}
+
+/// Storage wrapper retrying transient backend failures (I/O errors,
+/// unreachable object stores). Every operation is idempotent.
+pub struct RetryingStorage<S> {
+    inner: S,
+    retrier: Retrier,
+}
+
+impl<S: AssetStorage> RetryingStorage<S> {
+    /// Wrap `inner`, retrying its failures under `retrier`
+    pub fn new(inner: S, retrier: Retrier) -> Self {
+        Self { inner, retrier }
+    }
+
+    /// Retry and give-up counts for this storage
+    pub fn retry_stats(&self) -> mutsea_core::retry::RetryStats {
+        self.retrier.stats()
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: AssetStorage> AssetStorage for RetryingStorage<S> {
+    async fn store(&self, asset: &Asset) -> Result<(), AssetError> {
+        self.retrier.run(|| self.inner.store(asset)).await
+    }
+
+    async fn retrieve(&self, asset_id: AssetId) -> Result<Option<Asset>, AssetError> {
+        self.retrier.run(|| self.inner.retrieve(asset_id)).await
+    }
+
+    async fn delete(&self, asset_id: AssetId) -> Result<(), AssetError> {
+        self.retrier.run(|| self.inner.delete(asset_id)).await
+    }
+
+    async fn exists(&self, asset_id: AssetId) -> Result<bool, AssetError> {
+        self.retrier.run(|| self.inner.exists(asset_id)).await
+    }
+}
+
+/// Storage guarded by a circuit breaker, with a cache of fetched assets.
+/// While the backend is degraded, reads are served from the cache (ignoring
+/// its TTL) and writes fail fast.
+pub struct BreakerStorage<S> {
+    inner: S,
+    breaker: Arc<CircuitBreaker>,
+    cache: Arc<AssetCache>,
+}
+
+impl<S: AssetStorage> BreakerStorage<S> {
+    /// Guard `inner` with `breaker`, caching fetched assets in `cache`
+    pub fn new(inner: S, breaker: Arc<CircuitBreaker>, cache: Arc<AssetCache>) -> Self {
+        Self { inner, breaker, cache }
+    }
+
+    fn unavailable(error: BreakerError<AssetError>) -> AssetError {
+        match error {
+            BreakerError::Open(open) => AssetError::Storage(open.to_string()),
+            BreakerError::Inner(e) => e,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: AssetStorage> AssetStorage for BreakerStorage<S> {
+    async fn store(&self, asset: &Asset) -> Result<(), AssetError> {
+        self.breaker.call(|| self.inner.store(asset)).await.map_err(Self::unavailable)?;
+        self.cache.put(asset.clone()).await;
+        Ok(())
+    }
+
+    async fn retrieve(&self, asset_id: AssetId) -> Result<Option<Asset>, AssetError> {
+        match self.breaker.call(|| self.inner.retrieve(asset_id)).await {
+            Ok(Some(asset)) => {
+                self.cache.put(asset.clone()).await;
+                Ok(Some(asset))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => match self.cache.get_stale(asset_id).await {
+                Some(asset) => {
+                    tracing::debug!("Serving cached asset {} while storage is degraded: {}", asset_id, e);
+                    Ok(Some(asset))
+                }
+                None => Err(Self::unavailable(e)),
+            },
+        }
+    }
+
+    async fn delete(&self, asset_id: AssetId) -> Result<(), AssetError> {
+        self.breaker.call(|| self.inner.delete(asset_id)).await.map_err(Self::unavailable)?;
+        self.cache.remove(asset_id).await;
+        Ok(())
+    }
+
+    async fn exists(&self, asset_id: AssetId) -> Result<bool, AssetError> {
+        match self.breaker.call(|| self.inner.exists(asset_id)).await {
+            Ok(exists) => Ok(exists),
+            Err(e) => {
+                if self.cache.get_stale(asset_id).await.is_some() {
+                    tracing::debug!("Answering exists for cached asset {} while storage is degraded: {}", asset_id, e);
+                    Ok(true)
+                } else {
+                    Err(Self::unavailable(e))
+                }
+            }
+        }
+    }
+}
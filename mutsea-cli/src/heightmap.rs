@@ -0,0 +1,340 @@
+//! mutsea-cli/src/heightmap.rs
+//! `mutsea region terrain import/export`: heightmap files in the formats
+//! other grids use, read into and written from a region's terrain
+//!
+//! Raw files are OpenSim's `.r32`: one little-endian f32 per metre, rows
+//! from the south edge. Images run from the north edge down, so their rows
+//! are flipped; 8- and 16-bit samples map linearly onto a height range,
+//! while 32-bit float TIFFs hold heights in metres.
+
+use mutsea_core::scene::Terrain;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Heightmap file format
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeightmapFormat {
+    /// OpenSim `.r32`: little-endian f32 heights
+    Raw,
+    /// 16-bit grayscale PNG (8-bit and RGB are read too)
+    Png,
+    /// 32-bit float grayscale TIFF (8- and 16-bit are read too)
+    Tiff,
+}
+
+impl HeightmapFormat {
+    /// The format a file name's extension suggests
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "r32" | "raw" => Some(Self::Raw),
+            "png" => Some(Self::Png),
+            "tif" | "tiff" => Some(Self::Tiff),
+            _ => None,
+        }
+    }
+}
+
+/// How heights relate to the values in a file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeightScale {
+    /// Height of the lowest 8- or 16-bit sample
+    pub min_height: f32,
+    /// Height of the highest 8- or 16-bit sample
+    pub max_height: f32,
+    /// Multiplier applied to heights read, and divided out of those written
+    pub scale: f32,
+    /// Added to heights read, after scaling, and taken off those written
+    pub offset: f32,
+}
+
+impl HeightScale {
+    fn sample_to_height(&self, fraction: f32) -> f32 {
+        self.value_to_height(self.min_height + fraction * (self.max_height - self.min_height))
+    }
+
+    fn height_to_sample(&self, height: f32) -> f32 {
+        let range = self.max_height - self.min_height;
+        ((self.height_to_value(height) - self.min_height) / range).clamp(0.0, 1.0)
+    }
+
+    fn value_to_height(&self, value: f32) -> f32 {
+        value * self.scale + self.offset
+    }
+
+    fn height_to_value(&self, height: f32) -> f32 {
+        (height - self.offset) / self.scale
+    }
+}
+
+/// Read a heightmap of any size
+pub fn decode(data: &[u8], format: HeightmapFormat, scale: &HeightScale) -> Result<Terrain, String> {
+    match format {
+        HeightmapFormat::Raw => {
+            let side = ((data.len() / 4) as f64).sqrt() as u32;
+            let mut terrain = Terrain::from_r32(data, side, side)
+                .ok_or_else(|| format!("{} bytes is not a square grid of f32 heights", data.len()))?;
+            for height in &mut terrain.heights {
+                *height = scale.value_to_height(*height);
+            }
+            Ok(terrain)
+        }
+        HeightmapFormat::Png => decode_png(data, scale),
+        HeightmapFormat::Tiff => decode_tiff(data, scale),
+    }
+}
+
+/// Write `terrain` as a heightmap
+pub fn encode(terrain: &Terrain, format: HeightmapFormat, scale: &HeightScale) -> Result<Vec<u8>, String> {
+    match format {
+        HeightmapFormat::Raw => Ok(terrain
+            .heights
+            .iter()
+            .flat_map(|height| scale.height_to_value(*height).to_le_bytes())
+            .collect()),
+        HeightmapFormat::Png => {
+            let mut samples = Vec::with_capacity(terrain.heights.len() * 2);
+            for height in north_first(terrain) {
+                let sample = (scale.height_to_sample(height) * u16::MAX as f32).round() as u16;
+                samples.extend_from_slice(&sample.to_be_bytes());
+            }
+            let mut out = Vec::new();
+            let mut encoder = png::Encoder::new(&mut out, terrain.size_x, terrain.size_y);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Sixteen);
+            let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+            writer.write_image_data(&samples).map_err(|e| e.to_string())?;
+            writer.finish().map_err(|e| e.to_string())?;
+            Ok(out)
+        }
+        HeightmapFormat::Tiff => {
+            let heights: Vec<f32> = north_first(terrain).map(|height| scale.height_to_value(height)).collect();
+            let mut out = Cursor::new(Vec::new());
+            tiff::encoder::TiffEncoder::new(&mut out)
+                .and_then(|mut encoder| {
+                    encoder.write_image::<tiff::encoder::colortype::Gray32Float>(
+                        terrain.size_x,
+                        terrain.size_y,
+                        &heights,
+                    )
+                })
+                .map_err(|e| e.to_string())?;
+            Ok(out.into_inner())
+        }
+    }
+}
+
+/// Resample `terrain` to `size_x` by `size_y`, interpolating bilinearly
+pub fn resize(terrain: &Terrain, size_x: u32, size_y: u32) -> Terrain {
+    let step_x = terrain.size_x.saturating_sub(1) as f32 / size_x.saturating_sub(1).max(1) as f32;
+    let step_y = terrain.size_y.saturating_sub(1) as f32 / size_y.saturating_sub(1).max(1) as f32;
+    let mut resized = Terrain::flat(size_x, size_y, 0.0);
+    for y in 0..size_y {
+        for x in 0..size_x {
+            let (fx, fy) = (x as f32 * step_x, y as f32 * step_y);
+            let (x0, y0) = (fx.floor() as u32, fy.floor() as u32);
+            let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+            let south = lerp(terrain.height_at(x0, y0), terrain.height_at(x0 + 1, y0), tx);
+            let north = lerp(terrain.height_at(x0, y0 + 1), terrain.height_at(x0 + 1, y0 + 1), tx);
+            resized.set_height(x, y, lerp(south, north, ty));
+        }
+    }
+    resized
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Heights of `terrain` in image order, from the north edge down
+fn north_first(terrain: &Terrain) -> impl Iterator<Item = f32> + '_ {
+    let width = terrain.size_x as usize;
+    terrain.heights.chunks(width).rev().flatten().copied()
+}
+
+/// Terrain from image rows, north edge first
+fn from_image_rows(width: u32, height: u32, heights: Vec<f32>) -> Result<Terrain, String> {
+    if width == 0 || heights.len() != (width * height) as usize {
+        return Err(format!("expected {}x{} samples, found {}", width, height, heights.len()));
+    }
+    Ok(Terrain {
+        size_x: width,
+        size_y: height,
+        heights: heights.chunks(width as usize).rev().flatten().copied().collect(),
+    })
+}
+
+fn decode_png(data: &[u8], scale: &HeightScale) -> Result<Terrain, String> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+    let buffer = &buffer[..info.buffer_size()];
+
+    // Colour images are taken to be gray, so only their first channel is read
+    let channels = info.color_type.samples();
+    let heights = match info.bit_depth {
+        png::BitDepth::Sixteen => buffer
+            .chunks_exact(2 * channels)
+            .map(|pixel| scale.sample_to_height(u16::from_be_bytes([pixel[0], pixel[1]]) as f32 / u16::MAX as f32))
+            .collect(),
+        png::BitDepth::Eight => buffer
+            .chunks_exact(channels)
+            .map(|pixel| scale.sample_to_height(pixel[0] as f32 / u8::MAX as f32))
+            .collect(),
+        depth => return Err(format!("unsupported PNG bit depth {:?}", depth)),
+    };
+    from_image_rows(info.width, info.height, heights)
+}
+
+fn decode_tiff(data: &[u8], scale: &HeightScale) -> Result<Terrain, String> {
+    use tiff::decoder::{Decoder, DecodingResult};
+    use tiff::ColorType;
+
+    let mut decoder = Decoder::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+    let (width, height) = decoder.dimensions().map_err(|e| e.to_string())?;
+    let channels = match decoder.colortype().map_err(|e| e.to_string())? {
+        ColorType::Gray(_) => 1,
+        ColorType::GrayA(_) => 2,
+        ColorType::RGB(_) => 3,
+        ColorType::RGBA(_) => 4,
+        other => return Err(format!("unsupported TIFF colour type {:?}", other)),
+    };
+    let heights = match decoder.read_image().map_err(|e| e.to_string())? {
+        DecodingResult::F32(values) => values.iter().step_by(channels).map(|v| scale.value_to_height(*v)).collect(),
+        DecodingResult::U16(values) => values
+            .iter()
+            .step_by(channels)
+            .map(|v| scale.sample_to_height(*v as f32 / u16::MAX as f32))
+            .collect(),
+        DecodingResult::U8(values) => values
+            .iter()
+            .step_by(channels)
+            .map(|v| scale.sample_to_height(*v as f32 / u8::MAX as f32))
+            .collect(),
+        _ => return Err("unsupported TIFF sample format".to_string()),
+    };
+    from_image_rows(width, height, heights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIT: HeightScale = HeightScale {
+        min_height: 0.0,
+        max_height: 100.0,
+        scale: 1.0,
+        offset: 0.0,
+    };
+
+    /// A 4x4 terrain rising to the north and, less steeply, to the east
+    fn slope() -> Terrain {
+        let mut terrain = Terrain::flat(4, 4, 0.0);
+        for y in 0..4 {
+            for x in 0..4 {
+                terrain.set_height(x, y, y as f32 * 20.0 + x as f32 * 5.0);
+            }
+        }
+        terrain
+    }
+
+    #[test]
+    fn test_r32_round_trip() {
+        let terrain = slope();
+        let data = encode(&terrain, HeightmapFormat::Raw, &UNIT).unwrap();
+        assert_eq!(data, terrain.to_r32());
+        assert_eq!(decode(&data, HeightmapFormat::Raw, &UNIT).unwrap(), terrain);
+        assert!(decode(&data[..data.len() - 4], HeightmapFormat::Raw, &UNIT).is_err());
+    }
+
+    #[test]
+    fn test_png_round_trip_at_16_bits() {
+        let terrain = slope();
+        let data = encode(&terrain, HeightmapFormat::Png, &UNIT).unwrap();
+        let info = png::Decoder::new(data.as_slice()).read_info().unwrap().info().clone();
+        assert_eq!(info.bit_depth, png::BitDepth::Sixteen);
+
+        let decoded = decode(&data, HeightmapFormat::Png, &UNIT).unwrap();
+        assert_eq!((decoded.size_x, decoded.size_y), (4, 4));
+        for (read, written) in decoded.heights.iter().zip(&terrain.heights) {
+            assert!((read - written).abs() < 100.0 / u16::MAX as f32, "{} read back as {}", written, read);
+        }
+    }
+
+    #[test]
+    fn test_tiff_round_trip() {
+        let terrain = slope();
+        let data = encode(&terrain, HeightmapFormat::Tiff, &UNIT).unwrap();
+        assert_eq!(decode(&data, HeightmapFormat::Tiff, &UNIT).unwrap(), terrain);
+    }
+
+    #[test]
+    fn test_images_start_at_the_north_edge() {
+        let terrain = slope();
+
+        let data = encode(&terrain, HeightmapFormat::Png, &UNIT).unwrap();
+        let mut reader = png::Decoder::new(data.as_slice()).read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut buffer).unwrap();
+        // The first pixel is the north-west corner, 60 m up
+        let first = u16::from_be_bytes([buffer[0], buffer[1]]);
+        assert_eq!(first, (0.6 * u16::MAX as f32).round() as u16);
+
+        let data = encode(&terrain, HeightmapFormat::Tiff, &UNIT).unwrap();
+        let mut decoder = tiff::decoder::Decoder::new(Cursor::new(data)).unwrap();
+        let tiff::decoder::DecodingResult::F32(values) = decoder.read_image().unwrap() else {
+            panic!("expected f32 samples");
+        };
+        assert_eq!(&values[..4], &[60.0, 65.0, 70.0, 75.0]);
+        assert_eq!(&values[12..], &[0.0, 5.0, 10.0, 15.0]);
+    }
+
+    #[test]
+    fn test_height_scale() {
+        let scale = HeightScale {
+            min_height: -10.0,
+            max_height: 40.0,
+            scale: 2.0,
+            offset: 5.0,
+        };
+
+        // An 8-bit image from black at the south edge to white at the north
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, 1, 2);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header().unwrap().write_image_data(&[255, 0]).unwrap();
+        let terrain = decode(&data, HeightmapFormat::Png, &scale).unwrap();
+        // Samples span -10 to 40, then are doubled and raised 5 m
+        assert_eq!(terrain.heights, vec![-15.0, 85.0]);
+
+        // Raw and float values are only scaled and offset
+        let raw = encode(&terrain, HeightmapFormat::Raw, &scale).unwrap();
+        assert_eq!(raw, Terrain { heights: vec![-10.0, 40.0], ..terrain.clone() }.to_r32());
+        let tiff = encode(&terrain, HeightmapFormat::Tiff, &scale).unwrap();
+        assert_eq!(decode(&tiff, HeightmapFormat::Tiff, &scale).unwrap(), terrain);
+
+        // Heights beyond the sample range are clamped to it
+        let flat = Terrain::flat(2, 2, 500.0);
+        let png = encode(&flat, HeightmapFormat::Png, &scale).unwrap();
+        assert!(decode(&png, HeightmapFormat::Png, &scale).unwrap().heights.iter().all(|h| *h == 85.0));
+    }
+
+    #[test]
+    fn test_resize_interpolates() {
+        let mut terrain = Terrain::flat(2, 2, 0.0);
+        terrain.set_height(1, 0, 10.0);
+        terrain.set_height(0, 1, 20.0);
+        terrain.set_height(1, 1, 30.0);
+
+        let resized = resize(&terrain, 3, 3);
+        assert_eq!((resized.size_x, resized.size_y), (3, 3));
+        assert_eq!(resized.height_at(0, 0), 0.0);
+        assert_eq!(resized.height_at(2, 2), 30.0);
+        assert_eq!(resized.height_at(1, 0), 5.0);
+        assert_eq!(resized.height_at(0, 1), 10.0);
+        assert_eq!(resized.height_at(1, 1), 15.0);
+    }
+}
@@ -0,0 +1,654 @@
+//! mutsea-cli/src/top.rs
+//! `mutsea top`: a live terminal view of a running server, polling the
+//! stats and usage admin endpoints
+
+use crossterm::{
+    cursor,
+    event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType},
+};
+use futures::StreamExt;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Anomalies kept on screen
+const ANOMALY_LIMIT: usize = 6;
+/// Share of frames over budget worth flagging
+const OVER_BUDGET_ALERT: f64 = 0.1;
+/// Database pool utilization worth flagging
+const POOL_ALERT: f64 = 0.9;
+/// Share of sent packets resent worth flagging
+const RESEND_ALERT: f64 = 0.05;
+/// Time allowed for each request to the server
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Column the circuit table is sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortColumn {
+    Circuit,
+    Agent,
+    Connected,
+    Sent,
+    Received,
+    OutRate,
+    InRate,
+}
+
+impl SortColumn {
+    const ALL: [SortColumn; 7] = [
+        SortColumn::Circuit,
+        SortColumn::Agent,
+        SortColumn::Connected,
+        SortColumn::Sent,
+        SortColumn::Received,
+        SortColumn::OutRate,
+        SortColumn::InRate,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            SortColumn::Circuit => "CIRCUIT",
+            SortColumn::Agent => "AGENT",
+            SortColumn::Connected => "CONNECTED",
+            SortColumn::Sent => "SENT",
+            SortColumn::Received => "RECEIVED",
+            SortColumn::OutRate => "OUT/S",
+            SortColumn::InRate => "IN/S",
+        }
+    }
+
+    fn width(self) -> usize {
+        match self {
+            SortColumn::Agent => 37,
+            _ => 11,
+        }
+    }
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|c| *c == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// An open circuit, with its rates since the previous poll
+#[derive(Debug, Clone)]
+struct CircuitRow {
+    circuit_code: u64,
+    agent_id: Option<String>,
+    connected_secs: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    out_rate: f64,
+    in_rate: f64,
+}
+
+impl CircuitRow {
+    fn from_usage(usage: &Value) -> Option<Self> {
+        Some(Self {
+            circuit_code: usage["circuit_code"].as_u64()?,
+            agent_id: usage["agent_id"].as_str().map(str::to_string),
+            connected_secs: usage["connected_secs"].as_u64().unwrap_or_default(),
+            bytes_sent: usage["bytes_sent"].as_u64().unwrap_or_default(),
+            bytes_received: usage["bytes_received"].as_u64().unwrap_or_default(),
+            out_rate: 0.0,
+            in_rate: 0.0,
+        })
+    }
+
+    fn cell(&self, column: SortColumn) -> String {
+        match column {
+            SortColumn::Circuit => self.circuit_code.to_string(),
+            SortColumn::Agent => self.agent_id.clone().unwrap_or_else(|| "-".to_string()),
+            SortColumn::Connected => duration(self.connected_secs),
+            SortColumn::Sent => bytes(self.bytes_sent as f64),
+            SortColumn::Received => bytes(self.bytes_received as f64),
+            SortColumn::OutRate => bytes(self.out_rate),
+            SortColumn::InRate => bytes(self.in_rate),
+        }
+    }
+
+    fn compare(&self, other: &Self, column: SortColumn) -> std::cmp::Ordering {
+        match column {
+            SortColumn::Circuit => self.circuit_code.cmp(&other.circuit_code),
+            SortColumn::Agent => self.agent_id.cmp(&other.agent_id),
+            SortColumn::Connected => self.connected_secs.cmp(&other.connected_secs),
+            SortColumn::Sent => self.bytes_sent.cmp(&other.bytes_sent),
+            SortColumn::Received => self.bytes_received.cmp(&other.bytes_received),
+            SortColumn::OutRate => self.out_rate.total_cmp(&other.out_rate),
+            SortColumn::InRate => self.in_rate.total_cmp(&other.in_rate),
+        }
+    }
+}
+
+/// Network counters the server keeps totals of
+#[derive(Debug, Clone, Copy, Default)]
+struct Counters {
+    packets_received: u64,
+    packets_sent: u64,
+    errors: u64,
+    reliable_resends: u64,
+    outbound_dropped: u64,
+}
+
+impl Counters {
+    fn from_network(network: &Value) -> Option<Self> {
+        Some(Self {
+            packets_received: network["packets_received"].as_u64()?,
+            packets_sent: network["packets_sent"].as_u64()?,
+            errors: network["errors"].as_u64().unwrap_or_default(),
+            reliable_resends: network["reliable_resends"].as_u64().unwrap_or_default(),
+            outbound_dropped: network["outbound_dropped"].as_u64().unwrap_or_default(),
+        })
+    }
+}
+
+/// Totals from the previous poll, to turn into rates
+struct Previous {
+    at: Instant,
+    counters: Option<Counters>,
+    circuits: HashMap<u64, (u64, u64)>,
+}
+
+/// What a key press asks of the event loop
+enum Action {
+    None,
+    Quit,
+    Kick(String),
+}
+
+/// Everything on screen
+struct Top {
+    server: String,
+    interval: Duration,
+    stats: Value,
+    circuits: Vec<CircuitRow>,
+    packets_in: f64,
+    packets_out: f64,
+    previous: Option<Previous>,
+    anomalies: VecDeque<(chrono::DateTime<chrono::Local>, String)>,
+    /// Anomalies still going on, flagged once until they clear
+    ongoing: HashSet<&'static str>,
+    sort: SortColumn,
+    descending: bool,
+    /// Circuit code of the selected row
+    selected: Option<u64>,
+    pending_kick: Option<String>,
+    message: Option<String>,
+}
+
+impl Top {
+    fn new(server: String, interval: Duration) -> Self {
+        Self {
+            server,
+            interval,
+            stats: Value::Null,
+            circuits: Vec::new(),
+            packets_in: 0.0,
+            packets_out: 0.0,
+            previous: None,
+            anomalies: VecDeque::new(),
+            ongoing: HashSet::new(),
+            sort: SortColumn::OutRate,
+            descending: true,
+            selected: None,
+            pending_kick: None,
+            message: None,
+        }
+    }
+
+    /// Take in a poll of `/api/admin/stats` and `/api/admin/usage`
+    fn refresh(&mut self, poll: Result<(Value, Value), String>, now: Instant) {
+        let (stats, usage) = match poll {
+            Ok(answers) => answers,
+            Err(e) => {
+                self.flag("unreachable", true, || format!("Server unreachable: {}", e));
+                return;
+            }
+        };
+        self.flag("unreachable", false, String::new);
+
+        let counters = Counters::from_network(&stats["network"]);
+        let mut circuits: Vec<CircuitRow> = usage["circuits"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(CircuitRow::from_usage)
+            .collect();
+
+        let previous = self.previous.take();
+        let elapsed = previous
+            .as_ref()
+            .map(|p| now.duration_since(p.at).as_secs_f64())
+            .unwrap_or_default();
+        let mut delta = Counters::default();
+        if let (Some(previous), Some(counters)) = (&previous, counters) {
+            if let Some(before) = previous.counters {
+                delta = Counters {
+                    packets_received: counters.packets_received.saturating_sub(before.packets_received),
+                    packets_sent: counters.packets_sent.saturating_sub(before.packets_sent),
+                    errors: counters.errors.saturating_sub(before.errors),
+                    reliable_resends: counters.reliable_resends.saturating_sub(before.reliable_resends),
+                    outbound_dropped: counters.outbound_dropped.saturating_sub(before.outbound_dropped),
+                };
+            }
+            for circuit in &mut circuits {
+                if let Some((sent, received)) = previous.circuits.get(&circuit.circuit_code) {
+                    circuit.out_rate = rate(circuit.bytes_sent.saturating_sub(*sent), elapsed);
+                    circuit.in_rate = rate(circuit.bytes_received.saturating_sub(*received), elapsed);
+                }
+            }
+        }
+        self.packets_in = rate(delta.packets_received, elapsed);
+        self.packets_out = rate(delta.packets_sent, elapsed);
+
+        self.detect(&stats, &delta);
+        self.previous = Some(Previous {
+            at: now,
+            counters,
+            circuits: circuits
+                .iter()
+                .map(|c| (c.circuit_code, (c.bytes_sent, c.bytes_received)))
+                .collect(),
+        });
+        self.stats = stats;
+        self.circuits = circuits;
+        self.sort_circuits();
+    }
+
+    /// Flag what looks wrong in the latest poll
+    fn detect(&mut self, stats: &Value, delta: &Counters) {
+        let over_budget = stats["frame"]["over_budget_ratio"].as_f64().unwrap_or_default();
+        self.flag("frame", over_budget >= OVER_BUDGET_ALERT, || {
+            format!(
+                "{:.0}% of frames over budget, heaviest section {}",
+                over_budget * 100.0,
+                stats["frame"]["heaviest_section"].as_str().unwrap_or("unknown")
+            )
+        });
+
+        let pool = &stats["database_pool"];
+        let utilization = pool["utilization"].as_f64().unwrap_or_default();
+        self.flag("pool", utilization >= POOL_ALERT, || {
+            format!(
+                "Database pool at {}/{} connections",
+                pool["active_connections"].as_u64().unwrap_or_default(),
+                pool["max_connections"].as_u64().unwrap_or_default()
+            )
+        });
+
+        self.flag("errors", delta.errors > 0, || format!("{} packet errors", delta.errors));
+        self.flag("dropped", delta.outbound_dropped > 0, || {
+            format!("{} outbound updates dropped", delta.outbound_dropped)
+        });
+        let resent = if delta.packets_sent == 0 {
+            0.0
+        } else {
+            delta.reliable_resends as f64 / delta.packets_sent as f64
+        };
+        self.flag("resends", resent >= RESEND_ALERT, || {
+            format!("{:.0}% of packets sent were resends", resent * 100.0)
+        });
+
+        let queued = stats["login_queue"]["queued"].as_u64().unwrap_or_default();
+        self.flag("login_queue", queued > 0, || {
+            format!("{} logins waiting in the queue", queued)
+        });
+        let maintenance = stats["maintenance"]["active"].as_bool().unwrap_or(false);
+        self.flag("maintenance", maintenance, || "Maintenance mode is on".to_string());
+    }
+
+    /// Note `key` as an anomaly when it starts firing
+    fn flag(&mut self, key: &'static str, firing: bool, describe: impl FnOnce() -> String) {
+        if !firing {
+            self.ongoing.remove(key);
+            return;
+        }
+        if self.ongoing.insert(key) {
+            self.anomalies.push_front((chrono::Local::now(), describe()));
+            self.anomalies.truncate(ANOMALY_LIMIT);
+        }
+    }
+
+    fn sort_circuits(&mut self) {
+        let (sort, descending) = (self.sort, self.descending);
+        self.circuits.sort_by(|a, b| {
+            let order = a.compare(b, sort);
+            if descending {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+        if !self.circuits.iter().any(|c| Some(c.circuit_code) == self.selected) {
+            self.selected = self.circuits.first().map(|c| c.circuit_code);
+        }
+    }
+
+    fn selected_index(&self) -> Option<usize> {
+        self.circuits.iter().position(|c| Some(c.circuit_code) == self.selected)
+    }
+
+    fn move_selection(&mut self, by: isize) {
+        if self.circuits.is_empty() {
+            return;
+        }
+        let index = self.selected_index().unwrap_or(0) as isize + by;
+        let index = index.clamp(0, self.circuits.len() as isize - 1) as usize;
+        self.selected = Some(self.circuits[index].circuit_code);
+    }
+
+    fn on_key(&mut self, key: KeyEvent) -> Action {
+        if let Some(agent) = self.pending_kick.take() {
+            if key.code == KeyCode::Char('y') {
+                return Action::Kick(agent);
+            }
+            self.message = Some("Kick cancelled".to_string());
+            return Action::None;
+        }
+        self.message = None;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Action::Quit,
+            KeyCode::Up => self.move_selection(-1),
+            KeyCode::Down => self.move_selection(1),
+            KeyCode::PageUp => self.move_selection(-10),
+            KeyCode::PageDown => self.move_selection(10),
+            KeyCode::Char('s') | KeyCode::Tab => {
+                self.sort = self.sort.next();
+                self.sort_circuits();
+            }
+            KeyCode::Char('r') => {
+                self.descending = !self.descending;
+                self.sort_circuits();
+            }
+            KeyCode::Char('k') => {
+                let agent = self.selected_index().and_then(|i| self.circuits[i].agent_id.clone());
+                match agent {
+                    Some(agent) => {
+                        self.message = Some(format!("Kick {}? Press y to confirm", agent));
+                        self.pending_kick = Some(agent);
+                    }
+                    None => self.message = Some("The selected circuit has no agent to kick".to_string()),
+                }
+            }
+            _ => {}
+        }
+        Action::None
+    }
+
+    /// Screen lines for a terminal `height` rows tall, with whether each is
+    /// highlighted
+    fn lines(&self, height: usize) -> Vec<(String, bool)> {
+        let stats = &self.stats;
+        let network = &stats["network"];
+        let frame = &stats["frame"];
+        let pool = &stats["database_pool"];
+        let mut lines = vec![
+            (
+                format!(
+                    "mutsea top - {} - every {}s - {}",
+                    self.server,
+                    self.interval.as_secs(),
+                    chrono::Local::now().format("%H:%M:%S")
+                ),
+                true,
+            ),
+            (
+                format!(
+                    "Circuits {}  Online {}  Sessions {}  Regions {}  Users {}",
+                    network["circuits"].as_u64().unwrap_or(self.circuits.len() as u64),
+                    stats["online_count"].as_u64().unwrap_or_default(),
+                    stats["active_sessions"].as_u64().unwrap_or_default(),
+                    stats["regions_count"].as_u64().unwrap_or_default(),
+                    stats["users_count"].as_u64().unwrap_or_default()
+                ),
+                false,
+            ),
+        ];
+        lines.push(if network.is_object() {
+            (
+                format!(
+                    "Packets/s in {:.1} out {:.1}  Resends {}  Errors {}  Queued {}  Dropped {}",
+                    self.packets_in,
+                    self.packets_out,
+                    network["reliable_resends"].as_u64().unwrap_or_default(),
+                    network["errors"].as_u64().unwrap_or_default(),
+                    network["outbound_queued"].as_u64().unwrap_or_default(),
+                    network["outbound_dropped"].as_u64().unwrap_or_default()
+                ),
+                false,
+            )
+        } else {
+            ("Packets/s -  (no region running)".to_string(), false)
+        });
+        lines.push(if frame.is_object() {
+            (
+                format!(
+                    "Frame avg {:.1} ms max {:.1} ms  Over budget {:.0}%  Heaviest {}",
+                    frame["avg_ms"].as_f64().unwrap_or_default(),
+                    frame["max_ms"].as_f64().unwrap_or_default(),
+                    frame["over_budget_ratio"].as_f64().unwrap_or_default() * 100.0,
+                    frame["heaviest_section"].as_str().unwrap_or("-")
+                ),
+                false,
+            )
+        } else {
+            ("Frame -  (profiler disabled)".to_string(), false)
+        });
+        lines.push(if pool.is_object() {
+            (
+                format!(
+                    "DB pool {}/{} ({:.0}%)  Avg query {:.1} ms  Failed {}",
+                    pool["active_connections"].as_u64().unwrap_or_default(),
+                    pool["max_connections"].as_u64().unwrap_or_default(),
+                    pool["utilization"].as_f64().unwrap_or_default() * 100.0,
+                    pool["avg_query_time_ms"].as_f64().unwrap_or_default(),
+                    pool["failed_queries"].as_u64().unwrap_or_default()
+                ),
+                false,
+            )
+        } else {
+            ("DB pool -  (no database)".to_string(), false)
+        });
+
+        lines.push((String::new(), false));
+        lines.push(("Recent anomalies".to_string(), true));
+        if self.anomalies.is_empty() {
+            lines.push(("  none".to_string(), false));
+        }
+        for (at, anomaly) in &self.anomalies {
+            lines.push((format!("  {} {}", at.format("%H:%M:%S"), anomaly), false));
+        }
+        lines.push((String::new(), false));
+
+        let header = SortColumn::ALL
+            .iter()
+            .map(|column| {
+                let mut title = column.title().to_string();
+                if *column == self.sort {
+                    title.push(if self.descending { 'v' } else { '^' });
+                }
+                format!("{:<width$}", title, width = column.width())
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        lines.push((header, true));
+
+        // Keep the selected row in view above the footer
+        let rows = height.saturating_sub(lines.len() + 1).max(1);
+        let selected = self.selected_index().unwrap_or(0);
+        let offset = selected.saturating_sub(rows - 1);
+        for (index, circuit) in self.circuits.iter().enumerate().skip(offset).take(rows) {
+            let row = SortColumn::ALL
+                .iter()
+                .map(|column| format!("{:<width$}", circuit.cell(*column), width = column.width()))
+                .collect::<Vec<_>>()
+                .join(" ");
+            lines.push((row, index == selected));
+        }
+        if self.circuits.is_empty() {
+            lines.push(("  no open circuits".to_string(), false));
+        }
+
+        while lines.len() + 1 < height {
+            lines.push((String::new(), false));
+        }
+        let footer = self
+            .message
+            .clone()
+            .unwrap_or_else(|| "s sort  r reverse  up/down select  k kick  q quit".to_string());
+        lines.push((footer, true));
+        lines
+    }
+}
+
+/// Show the dashboard for the server at `server` until the user quits
+pub async fn run(server: String, api_key: String, interval: Duration) -> Result<(), Box<dyn std::error::Error>> {
+    let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    let mut stdout = std::io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    let result = event_loop(&client, &server, &api_key, interval, &mut stdout).await;
+
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+async fn event_loop(
+    client: &reqwest::Client,
+    server: &str,
+    api_key: &str,
+    interval: Duration,
+    stdout: &mut std::io::Stdout,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut top = Top::new(server.to_string(), interval);
+    let mut events = EventStream::new();
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let poll = poll(client, server, api_key).await;
+                top.refresh(poll, Instant::now());
+            }
+            event = events.next() => match event {
+                Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => match top.on_key(key) {
+                    Action::Quit => return Ok(()),
+                    Action::Kick(agent) => top.message = Some(kick(client, server, api_key, &agent).await),
+                    Action::None => {}
+                },
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => return Ok(()),
+            },
+        }
+        draw(stdout, &top)?;
+    }
+}
+
+fn draw(stdout: &mut std::io::Stdout, top: &Top) -> std::io::Result<()> {
+    let (width, height) = terminal::size()?;
+    queue!(stdout, terminal::Clear(ClearType::All))?;
+    for (row, (line, highlighted)) in top.lines(height as usize).into_iter().enumerate().take(height as usize) {
+        let line: String = line.chars().take(width as usize).collect();
+        queue!(stdout, cursor::MoveTo(0, row as u16))?;
+        if highlighted {
+            queue!(
+                stdout,
+                SetAttribute(Attribute::Reverse),
+                Print(line),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(stdout, Print(line))?;
+        }
+    }
+    stdout.flush()
+}
+
+async fn poll(client: &reqwest::Client, server: &str, api_key: &str) -> Result<(Value, Value), String> {
+    let stats = answer(
+        client
+            .get(format!("{}api/admin/stats", server))
+            .header("x-api-key", api_key)
+            .send()
+            .await,
+    )
+    .await?;
+    let usage = answer(
+        client
+            .get(format!("{}api/admin/usage", server))
+            .header("x-api-key", api_key)
+            .send()
+            .await,
+    )
+    .await?;
+    Ok((stats, usage))
+}
+
+/// Disconnect `agent`, describing how it went
+async fn kick(client: &reqwest::Client, server: &str, api_key: &str, agent: &str) -> String {
+    let response = client
+        .post(format!("{}api/admin/avatars/{}/kick", server, agent))
+        .header("x-api-key", api_key)
+        .json(&serde_json::json!({}))
+        .send()
+        .await;
+    match answer(response).await {
+        Ok(_) => format!("Kicked {}", agent),
+        Err(e) => format!("Could not kick {}: {}", agent, e),
+    }
+}
+
+/// The JSON body of a successful response, or why there is none
+async fn answer(response: reqwest::Result<reqwest::Response>) -> Result<Value, String> {
+    let response = response.map_err(|e| e.to_string())?;
+    let status = response.status();
+    let body = response.json::<Value>().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(format!(
+            "{} {}",
+            status,
+            body["error"].as_str().unwrap_or("unknown error")
+        ));
+    }
+    Ok(body)
+}
+
+fn rate(amount: u64, secs: f64) -> f64 {
+    if secs <= 0.0 {
+        0.0
+    } else {
+        amount as f64 / secs
+    }
+}
+
+fn bytes(amount: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut amount = amount;
+    let mut unit = 0;
+    while amount >= 1024.0 && unit < UNITS.len() - 1 {
+        amount /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0} {}", amount, UNITS[unit])
+    } else {
+        format!("{:.1} {}", amount, UNITS[unit])
+    }
+}
+
+fn duration(secs: u64) -> String {
+    match secs {
+        s if s >= 3600 => format!("{}h{:02}m", s / 3600, s % 3600 / 60),
+        s if s >= 60 => format!("{}m{:02}s", s / 60, s % 60),
+        s => format!("{}s", s),
+    }
+}
@@ -0,0 +1,435 @@
+//! Circuit breakers for backend services
+//!
+//! A breaker counts consecutive transient failures of one backend (the
+//! database pool, an AI provider). Past the threshold it opens and calls
+//! fail fast, letting callers switch to a degraded mode instead of queueing
+//! behind timeouts. After `open_secs` it goes half-open and lets a few probe
+//! calls through; a successful probe closes it again, a failed one reopens
+//! it.
+
+use crate::config::CircuitBreakerConfig;
+use crate::error::{ErrorCategory, ErrorClassification};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Breaker position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Calls pass through
+    Closed,
+    /// Calls are rejected without reaching the backend
+    Open,
+    /// A limited number of probe calls are let through
+    HalfOpen,
+}
+
+/// Returned instead of calling a backend whose breaker is open
+#[derive(Debug, Clone, PartialEq)]
+pub struct BreakerOpen {
+    /// Backend the breaker guards
+    pub name: String,
+    /// Time until the breaker lets a probe through
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for BreakerOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is unavailable (circuit open), retry in {}s",
+            self.name,
+            self.retry_after.as_secs().max(1)
+        )
+    }
+}
+
+impl std::error::Error for BreakerOpen {}
+
+impl ErrorClassification for BreakerOpen {
+    fn code(&self) -> &'static str {
+        "CIRCUIT_OPEN"
+    }
+
+    fn category(&self) -> ErrorCategory {
+        ErrorCategory::Retryable
+    }
+}
+
+/// Failure of a call made through a breaker
+#[derive(Debug)]
+pub enum BreakerError<E> {
+    /// The breaker rejected the call
+    Open(BreakerOpen),
+    /// The backend returned an error
+    Inner(E),
+}
+
+impl<E: fmt::Display> fmt::Display for BreakerError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreakerError::Open(open) => open.fmt(f),
+            BreakerError::Inner(e) => e.fmt(f),
+        }
+    }
+}
+
+/// Breaker state for health checks
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerStatus {
+    /// Backend the breaker guards
+    pub name: String,
+    /// Whether calls are passing, rejected or probing
+    pub state: BreakerState,
+    /// Transient failures since the last success
+    pub consecutive_failures: u32,
+    /// Times the breaker has opened
+    pub trips: u64,
+    /// Calls rejected while open
+    pub rejected: u64,
+    /// Seconds until a probe is allowed, while open
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+}
+
+#[derive(Debug)]
+struct BreakerInner {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probes_in_flight: u32,
+    trips: u64,
+    rejected: u64,
+}
+
+/// Circuit breaker for one backend
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: String,
+    config: CircuitBreakerConfig,
+    inner: Mutex<BreakerInner>,
+}
+
+/// Admission to call the backend. Report the outcome with
+/// [`Permit::success`] or [`Permit::failure`]; dropping the permit without
+/// either releases a half-open probe slot without changing state.
+pub struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    reported: bool,
+}
+
+impl Permit<'_> {
+    /// The backend answered
+    pub fn success(mut self) {
+        self.reported = true;
+        self.breaker.on_success(self.probe);
+    }
+
+    /// The backend failed in a way that suggests it is degraded
+    pub fn failure(mut self) {
+        self.reported = true;
+        self.breaker.on_failure(self.probe);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.reported {
+            self.breaker.inner.lock().unwrap().probes_in_flight -= 1;
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// Closed breaker named `name` in logs and health checks
+    pub fn new(name: impl Into<String>, config: CircuitBreakerConfig) -> Self {
+        Self {
+            name: name.into(),
+            config,
+            inner: Mutex::new(BreakerInner {
+                state: BreakerState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                probes_in_flight: 0,
+                trips: 0,
+                rejected: 0,
+            }),
+        }
+    }
+
+    /// Breaker name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn open_duration(&self) -> Duration {
+        Duration::from_secs(self.config.open_secs)
+    }
+
+    /// Ask to call the backend
+    pub fn acquire(&self) -> Result<Permit<'_>, BreakerOpen> {
+        if !self.config.enabled {
+            return Ok(Permit { breaker: self, probe: false, reported: false });
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == BreakerState::Open {
+            let elapsed = inner.opened_at.map_or(Duration::MAX, |at| at.elapsed());
+            if elapsed < self.open_duration() {
+                inner.rejected += 1;
+                return Err(BreakerOpen {
+                    name: self.name.clone(),
+                    retry_after: self.open_duration() - elapsed,
+                });
+            }
+            inner.state = BreakerState::HalfOpen;
+            info!("Circuit for {} half-open, probing", self.name);
+        }
+
+        if inner.state == BreakerState::HalfOpen {
+            if inner.probes_in_flight >= self.config.half_open_probes.max(1) {
+                inner.rejected += 1;
+                return Err(BreakerOpen {
+                    name: self.name.clone(),
+                    retry_after: Duration::from_secs(1),
+                });
+            }
+            inner.probes_in_flight += 1;
+            return Ok(Permit { breaker: self, probe: true, reported: false });
+        }
+
+        Ok(Permit { breaker: self, probe: false, reported: false })
+    }
+
+    fn on_success(&self, probe: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        if probe {
+            inner.probes_in_flight -= 1;
+        }
+        // A late success from a call admitted before the breaker opened
+        // does not close it; only a probe does
+        if inner.state == BreakerState::Open {
+            return;
+        }
+        inner.consecutive_failures = 0;
+        if inner.state == BreakerState::HalfOpen {
+            inner.state = BreakerState::Closed;
+            inner.opened_at = None;
+            info!("Circuit for {} closed, backend recovered", self.name);
+        }
+    }
+
+    fn on_failure(&self, probe: bool) {
+        if !self.config.enabled {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        if probe {
+            inner.probes_in_flight -= 1;
+        }
+        inner.consecutive_failures += 1;
+
+        let trip = match inner.state {
+            BreakerState::HalfOpen => true,
+            BreakerState::Closed => inner.consecutive_failures >= self.config.failure_threshold,
+            BreakerState::Open => false,
+        };
+        if trip {
+            inner.state = BreakerState::Open;
+            inner.opened_at = Some(Instant::now());
+            inner.trips += 1;
+            warn!(
+                "Circuit for {} opened after {} consecutive failures, degrading for {}s",
+                self.name, inner.consecutive_failures, self.config.open_secs
+            );
+        }
+    }
+
+    /// Call the backend; only retryable errors count as failures, since a
+    /// permanent error (not found, bad input) means the backend answered
+    pub async fn call<T, E, F, Fut>(&self, operation: F) -> Result<T, BreakerError<E>>
+    where
+        E: ErrorClassification,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.call_if(operation, |e: &E| e.is_retryable()).await
+    }
+
+    /// Call the backend, counting errors for which `is_failure` is true
+    pub async fn call_if<T, E, F, Fut, P>(&self, operation: F, is_failure: P) -> Result<T, BreakerError<E>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        P: Fn(&E) -> bool,
+    {
+        let permit = self.acquire().map_err(BreakerError::Open)?;
+        match operation().await {
+            Ok(value) => {
+                permit.success();
+                Ok(value)
+            }
+            Err(e) => {
+                if is_failure(&e) {
+                    permit.failure();
+                } else {
+                    permit.success();
+                }
+                Err(BreakerError::Inner(e))
+            }
+        }
+    }
+
+    /// Current position. An open breaker whose timeout has passed still
+    /// reads as open until the next call probes it.
+    pub fn state(&self) -> BreakerState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Whether calls are currently being rejected
+    pub fn is_open(&self) -> bool {
+        self.retry_after().is_some()
+    }
+
+    /// Time until a probe is allowed, while open
+    pub fn retry_after(&self) -> Option<Duration> {
+        let inner = self.inner.lock().unwrap();
+        match (inner.state, inner.opened_at) {
+            (BreakerState::Open, Some(at)) => self.open_duration().checked_sub(at.elapsed()),
+            _ => None,
+        }
+    }
+
+    /// Snapshot for health checks
+    pub fn status(&self) -> BreakerStatus {
+        let retry_after_secs = self.retry_after().map(|d| d.as_secs().max(1));
+        let inner = self.inner.lock().unwrap();
+        BreakerStatus {
+            name: self.name.clone(),
+            state: inner.state,
+            consecutive_failures: inner.consecutive_failures,
+            trips: inner.trips,
+            rejected: inner.rejected,
+            retry_after_secs,
+        }
+    }
+}
+
+/// Named breakers shared across a process
+#[derive(Debug, Default)]
+pub struct CircuitBreakerRegistry {
+    config: CircuitBreakerConfig,
+    breakers: RwLock<BTreeMap<String, Arc<CircuitBreaker>>>,
+}
+
+impl CircuitBreakerRegistry {
+    /// Registry creating breakers with `config`
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            breakers: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Breaker for `name`, created on first use
+    pub fn breaker(&self, name: &str) -> Arc<CircuitBreaker> {
+        if let Some(breaker) = self.breakers.read().unwrap().get(name) {
+            return Arc::clone(breaker);
+        }
+        let mut breakers = self.breakers.write().unwrap();
+        Arc::clone(
+            breakers
+                .entry(name.to_string())
+                .or_insert_with(|| Arc::new(CircuitBreaker::new(name, self.config.clone()))),
+        )
+    }
+
+    /// Status of every breaker, by name
+    pub fn statuses(&self) -> Vec<BreakerStatus> {
+        self.breakers.read().unwrap().values().map(|b| b.status()).collect()
+    }
+
+    /// Whether any backend is currently cut off
+    pub fn any_open(&self) -> bool {
+        self.breakers.read().unwrap().values().any(|b| b.is_open())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NetworkError;
+
+    fn breaker(open_secs: u64) -> CircuitBreaker {
+        CircuitBreaker::new(
+            "db",
+            CircuitBreakerConfig {
+                enabled: true,
+                failure_threshold: 2,
+                open_secs,
+                half_open_probes: 1,
+            },
+        )
+    }
+
+    async fn fail(breaker: &CircuitBreaker) -> Result<(), BreakerError<NetworkError>> {
+        breaker.call(|| async { Err(NetworkError::Timeout("down".to_string())) }).await
+    }
+
+    #[tokio::test]
+    async fn test_trips_after_threshold_and_rejects() {
+        let closed = breaker(60);
+        let breaker = breaker(60);
+        assert!(matches!(fail(&breaker).await, Err(BreakerError::Inner(_))));
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(matches!(fail(&breaker).await, Err(BreakerError::Inner(_))));
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        let rejected = breaker.call(|| async { Ok::<_, NetworkError>(()) }).await;
+        assert!(matches!(rejected, Err(BreakerError::Open(_))));
+        let status = breaker.status();
+        assert_eq!((status.trips, status.rejected), (1, 1));
+        assert!(status.retry_after_secs.is_some());
+
+        // Permanent errors mean the backend answered
+        for _ in 0..5 {
+            let _ = closed.call(|| async { Err::<(), _>(NetworkError::InvalidPacket("bad".to_string())) }).await;
+        }
+        assert_eq!(closed.state(), BreakerState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_half_open_probe_closes_or_reopens() {
+        let breaker = breaker(0);
+        let _ = fail(&breaker).await;
+        let _ = fail(&breaker).await;
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        // A failed probe reopens
+        assert!(matches!(fail(&breaker).await, Err(BreakerError::Inner(_))));
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        // Only one probe at a time
+        let permit = breaker.acquire().unwrap();
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        assert!(breaker.acquire().is_err());
+        permit.success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn test_registry_shares_breakers() {
+        let registry = CircuitBreakerRegistry::new(CircuitBreakerConfig::default());
+        let a = registry.breaker("database");
+        let b = registry.breaker("database");
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(registry.statuses().len(), 1);
+        assert!(!registry.any_open());
+    }
+}
@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
 
+use crate::Maturity;
+
 /// Main configuration structure for Mutsea
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MutseaConfig {
@@ -26,6 +28,183 @@ pub struct MutseaConfig {
     /// AI configuration (Phase II)
     #[serde(default)]
     pub ai: AIConfig,
+    /// Region simulation loop configuration
+    #[serde(default)]
+    pub simulation: SimulationConfig,
+    /// Front page and viewer splash page configuration
+    #[serde(default)]
+    pub web: WebConfig,
+    /// Self-service account registration configuration
+    #[serde(default)]
+    pub registration: RegistrationConfig,
+    /// Scheduled region backup configuration
+    #[serde(default)]
+    pub backup: BackupConfig,
+    /// Simulation tick profiler configuration
+    #[serde(default)]
+    pub profiler: ProfilerConfig,
+    /// Retry policy for transient database, asset and HTTP failures
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Circuit breakers around the database and external providers
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// OpenTelemetry trace export
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+    /// Buffering of analytics writes between the simulation and the database
+    #[serde(default)]
+    pub analytics_ingest: AnalyticsIngestConfig,
+    /// Downsampling and retention of stored performance metrics
+    #[serde(default)]
+    pub metrics_retention: MetricsRetentionConfig,
+    /// Log of world state changes and its snapshots
+    #[serde(default)]
+    pub world_events: WorldEventsConfig,
+    /// Evaluation of outcomes reported for stored AI decisions
+    #[serde(default)]
+    pub ai_feedback: AIFeedbackConfig,
+    /// Species, resource and weather simulation of each region
+    #[serde(default)]
+    pub ecosystem: EcosystemConfig,
+    /// Day cycle, sky, water and wind sent to viewers
+    #[serde(default)]
+    pub environment: EnvironmentConfig,
+    /// AI-planned terrain and prims generated on request
+    #[serde(default)]
+    pub world_generation: WorldGenerationConfig,
+    /// Quests given to players, hand-written or AI-generated
+    #[serde(default)]
+    pub quests: QuestsConfig,
+    /// NPCs that answer avatars chatting near them
+    #[serde(default)]
+    pub npc_chat: NpcChatConfig,
+    /// How NPCs find their way around
+    #[serde(default)]
+    pub navigation: NavigationConfig,
+    /// Ambient NPCs spawned to populate parcels
+    #[serde(default)]
+    pub population: PopulationConfig,
+    /// Behavior trees that decide what NPCs do
+    #[serde(default)]
+    pub behaviors: BehaviorsConfig,
+    /// Scripted vehicles
+    #[serde(default)]
+    pub vehicles: VehiclesConfig,
+    /// Collision events for scripts and analytics
+    #[serde(default)]
+    pub collisions: CollisionsConfig,
+    /// Raycasts for scripts, NPC sight and click validation
+    #[serde(default)]
+    pub raycast: RaycastConfig,
+    /// Mesh assets decoded into physics shapes
+    #[serde(default)]
+    pub meshes: MeshesConfig,
+    /// Balances, sales of prims and payments
+    #[serde(default)]
+    pub economy: EconomyConfig,
+    /// Prims worn by avatars
+    #[serde(default)]
+    pub attachments: AttachmentsConfig,
+    /// Prim, script and upload limits, with admin overrides
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// Parcel sales and land auctions
+    #[serde(default)]
+    pub land: LandConfig,
+    /// Prims returned to their owners, by hand and by auto-return
+    #[serde(default)]
+    pub returns: ReturnsConfig,
+    /// Prims taken into inventory and rezzed from it
+    #[serde(default)]
+    pub object_items: ObjectItemsConfig,
+    /// Terraforming from the viewer's land tools
+    #[serde(default)]
+    pub terraform: TerraformConfig,
+    /// Inventory fetched by viewers over capabilities
+    #[serde(default)]
+    pub inventory: InventoryConfig,
+    /// Avatars' baked textures, made by the region from their outfits
+    #[serde(default)]
+    pub appearance: AppearanceConfig,
+    /// Photos saved to inventory and sent as postcards from the viewer
+    #[serde(default)]
+    pub snapshots: SnapshotsConfig,
+    /// Animations uploaded from the viewer, converted from BVH if need be
+    #[serde(default)]
+    pub animations: AnimationsConfig,
+    /// Prims and chat driven through the REST API
+    #[serde(default)]
+    pub scene_control: SceneControlConfig,
+    /// Prims hidden from others and temporary prims
+    #[serde(default)]
+    pub visibility: VisibilityConfig,
+    /// Region export to glTF
+    #[serde(default)]
+    pub gltf_export: GltfExportConfig,
+    /// Browser viewers over WebRTC
+    #[serde(default)]
+    pub webrtc: WebRtcConfig,
+    /// Internal gRPC services between nodes
+    #[serde(default)]
+    pub grpc: GrpcConfig,
+    /// Cluster mode: region servers under a grid controller
+    #[serde(default)]
+    pub cluster: ClusterConfig,
+    /// Scene checkpoints for crash recovery
+    #[serde(default)]
+    pub checkpoint: CheckpointConfig,
+    /// Recording of simulation inputs for deterministic replay
+    #[serde(default)]
+    pub replay: ReplayConfig,
+    /// Script sandbox quotas
+    #[serde(default)]
+    pub scripts: ScriptsConfig,
+    /// HTTP requests made by scripts and URLs served by them
+    #[serde(default)]
+    pub script_http: ScriptHttpConfig,
+    /// Email sent and received by scripts
+    #[serde(default)]
+    pub script_email: ScriptEmailConfig,
+    /// XML-RPC remote data channels opened by scripts
+    #[serde(default)]
+    pub script_xmlrpc: ScriptXmlRpcConfig,
+    /// Kicking and freezing agents from the viewer
+    #[serde(default)]
+    pub moderation: ModerationConfig,
+    /// Logging public chat and IMs for moderation
+    #[serde(default)]
+    pub chat_log: ChatLogConfig,
+    /// Content ratings of the region and of the agents entering it
+    #[serde(default)]
+    pub maturity: MaturityConfig,
+    /// Avatar profiles, picks and landmarks
+    #[serde(default)]
+    pub profiles: ProfilesConfig,
+    /// Classifieds and events listed in search
+    #[serde(default)]
+    pub directory: DirectoryConfig,
+    /// Sounds played by prims, scripts and gestures
+    #[serde(default)]
+    pub sounds: SoundsConfig,
+    /// Display names and name lookups
+    #[serde(default)]
+    pub names: NamesConfig,
+    /// Streaming a region's prims to arriving agents
+    #[serde(default)]
+    pub prim_streaming: PrimStreamingConfig,
+    /// Queueing logins while the grid is busy
+    #[serde(default)]
+    pub login_queue: LoginQueueConfig,
+    /// Closing the grid to everyone but administrators
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    /// Plugins extending the server
+    #[serde(default)]
+    pub plugins: PluginsConfig,
+    /// Posting key events to outside services
+    #[serde(default)]
+    pub webhooks: WebhooksConfig,
     /// Custom configuration values
     #[serde(default)]
     pub custom: HashMap<String, serde_json::Value>,
@@ -90,6 +269,27 @@ pub struct DatabaseConfig {
     pub auto_migrate: bool,
     /// Enable SQL query logging
     pub log_queries: bool,
+    /// Queries taking at least this long are counted and logged as slow
+    #[serde(default = "default_slow_query_ms")]
+    pub slow_query_ms: u64,
+    /// Seconds between slow-query log summaries
+    #[serde(default = "default_slow_query_log_secs")]
+    pub slow_query_log_secs: u64,
+    /// Queries taking at least this long have their plan captured with
+    /// EXPLAIN (ANALYZE for PostgreSQL reads); 0 disables plan capture
+    #[serde(default = "default_explain_threshold_ms")]
+    pub explain_threshold_ms: u64,
+    /// Connections checked out at least this long are logged as leaked,
+    /// with the stack that checked them out; 0 disables leak detection
+    #[serde(default = "default_connection_leak_secs")]
+    pub connection_leak_secs: u64,
+    /// Capture a stack trace at every checkout for leak reports, even
+    /// without RUST_BACKTRACE set
+    #[serde(default)]
+    pub capture_leak_backtraces: bool,
+    /// Hash-sharding of the assets table
+    #[serde(default)]
+    pub asset_sharding: AssetShardingConfig,
 }
 
 impl Default for DatabaseConfig {
@@ -102,10 +302,32 @@ impl Default for DatabaseConfig {
             query_timeout: 60,
             auto_migrate: true,
             log_queries: false,
+            slow_query_ms: default_slow_query_ms(),
+            slow_query_log_secs: default_slow_query_log_secs(),
+            explain_threshold_ms: default_explain_threshold_ms(),
+            connection_leak_secs: default_connection_leak_secs(),
+            capture_leak_backtraces: false,
+            asset_sharding: AssetShardingConfig::default(),
         }
     }
 }
 
+fn default_slow_query_ms() -> u64 {
+    500
+}
+
+fn default_slow_query_log_secs() -> u64 {
+    60
+}
+
+fn default_explain_threshold_ms() -> u64 {
+    2000
+}
+
+fn default_connection_leak_secs() -> u64 {
+    300
+}
+
 /// Cache configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheConfig {
@@ -163,6 +385,96 @@ pub struct LLUDPConfig {
     pub ping_interval: u64,
     /// Client timeout in seconds
     pub client_timeout: u64,
+    /// Accept IPv4 clients on an IPv6 wildcard bind (`::`)
+    #[serde(default = "default_dual_stack")]
+    pub dual_stack: bool,
+    /// IPv4 address advertised to viewers as `sim_ip`, when the bind
+    /// address is not reachable from outside (NAT, wildcard bind)
+    #[serde(default)]
+    pub external_address: Option<String>,
+    /// IPv6 address advertised to viewers that log in over IPv6
+    #[serde(default)]
+    pub external_address_v6: Option<String>,
+    /// DNS name resolved at startup for the advertised address (dynamic
+    /// DNS); used when `external_address` is not set
+    #[serde(default)]
+    pub external_hostname: Option<String>,
+    /// STUN servers (`host:port`) asked for the public IPv4 address when
+    /// neither `external_address` nor `external_hostname` is set
+    #[serde(default)]
+    pub stun_servers: Vec<String>,
+    /// Time to wait for each STUN server
+    #[serde(default = "default_stun_timeout_ms")]
+    pub stun_timeout_ms: u64,
+    /// Receive workers, each with its own socket on the port
+    /// (`SO_REUSEPORT`, Unix only); every circuit is handled by one worker
+    #[serde(default = "default_lludp_workers")]
+    pub workers: usize,
+    /// Bytes of packets each circuit may have waiting to be sent; past this
+    /// the least urgent updates are dropped
+    #[serde(default = "default_outbound_queue_kb")]
+    pub outbound_queue_kb: usize,
+    /// Per-circuit adaptive send-rate control
+    #[serde(default)]
+    pub throttle: ThrottleConfig,
+    /// Re-binding circuits to a new client address
+    #[serde(default)]
+    pub migration: MigrationConfig,
+}
+
+/// Re-binding a circuit to a new address when a viewer's IP changes
+/// mid-session (mobile networks). The viewer proves ownership by repeating
+/// UseCircuitCode with the circuit's session ID from the new address.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MigrationConfig {
+    /// Allow circuits to move to a new address
+    pub enabled: bool,
+    /// The old address must have been silent this long, so a live session
+    /// cannot be taken over from elsewhere
+    pub min_silence_ms: u64,
+    /// Minimum seconds between migrations of the same circuit
+    pub min_interval_secs: u64,
+}
+
+impl Default for MigrationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_silence_ms: 1500,
+            min_interval_secs: 10,
+        }
+    }
+}
+
+/// Per-circuit adaptive send-rate control. Each circuit starts at
+/// `initial_kbps` and moves between `min_kbps` and `max_kbps` based on
+/// measured round-trip time and packet loss.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThrottleConfig {
+    /// Adapt send rates to each client's connection
+    pub enabled: bool,
+    /// Starting send rate in kilobits per second
+    pub initial_kbps: u32,
+    /// Rate floor in kilobits per second
+    pub min_kbps: u32,
+    /// Rate ceiling in kilobits per second
+    pub max_kbps: u32,
+    /// Upper bound for the retransmission timeout in milliseconds
+    pub max_rto_ms: u64,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            initial_kbps: 512,
+            min_kbps: 64,
+            max_kbps: 2048,
+            max_rto_ms: 3000,
+        }
+    }
 }
 
 impl Default for LLUDPConfig {
@@ -176,6 +488,16 @@ impl Default for LLUDPConfig {
             ack_timeout: 1000,
             ping_interval: 5,
             client_timeout: 60,
+            dual_stack: true,
+            external_address: None,
+            external_address_v6: None,
+            external_hostname: None,
+            stun_servers: Vec::new(),
+            stun_timeout_ms: default_stun_timeout_ms(),
+            workers: default_lludp_workers(),
+            outbound_queue_kb: default_outbound_queue_kb(),
+            throttle: ThrottleConfig::default(),
+            migration: MigrationConfig::default(),
         }
     }
 }
@@ -197,6 +519,42 @@ pub struct HTTPConfig {
     pub enable_cors: bool,
     /// CORS allowed origins
     pub cors_origins: Vec<String>,
+    /// Accept IPv4 clients on an IPv6 wildcard bind (`::`)
+    #[serde(default = "default_dual_stack")]
+    pub dual_stack: bool,
+    /// Host name or address used in URLs handed to clients (login
+    /// capabilities, grid info); defaults to a reachable bind address
+    #[serde(default)]
+    pub external_host: Option<String>,
+}
+
+impl HTTPConfig {
+    /// Base URL clients use to reach this server, e.g. `http://[2001:db8::1]:8080/`
+    pub fn public_url(&self) -> String {
+        let host = match &self.external_host {
+            Some(host) => host.clone(),
+            None => crate::net::parse_ip(&self.bind_address)
+                .map(|ip| crate::net::reachable_ip(ip).to_string())
+                .unwrap_or_else(|_| self.bind_address.clone()),
+        };
+        crate::net::http_url(&host, self.port)
+    }
+}
+
+fn default_dual_stack() -> bool {
+    true
+}
+
+fn default_stun_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_lludp_workers() -> usize {
+    1
+}
+
+fn default_outbound_queue_kb() -> usize {
+    256
 }
 
 impl Default for HTTPConfig {
@@ -209,6 +567,8 @@ impl Default for HTTPConfig {
             key_file: None,
             enable_cors: true,
             cors_origins: vec!["*".to_string()],
+            dual_stack: true,
+            external_host: None,
         }
     }
 }
@@ -291,6 +651,60 @@ pub struct SecurityConfig {
     pub enable_ip_blacklist: bool,
     /// Blacklisted IP addresses
     pub ip_blacklist: Vec<String>,
+    /// External OpenID Connect identity provider for web and API logins
+    #[serde(default)]
+    pub oidc: Option<OidcConfig>,
+    /// File holding hashed API keys for the admin and analytics endpoints
+    #[serde(default = "default_api_keys_file")]
+    pub api_keys_file: String,
+    /// File holding account, IP and hardware bans
+    #[serde(default = "default_bans_file")]
+    pub bans_file: String,
+    /// File holding responses to admin API requests sent with an
+    /// `Idempotency-Key`, replayed when a client retries
+    #[serde(default = "default_idempotency_file")]
+    pub idempotency_file: String,
+    /// How long an idempotency key is remembered
+    #[serde(default = "default_idempotency_ttl_secs")]
+    pub idempotency_ttl_secs: u64,
+}
+
+/// OpenID Connect identity provider configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcConfig {
+    /// Issuer URL; discovery is read from `{issuer}/.well-known/openid-configuration`
+    pub issuer: String,
+    /// Client ID, checked against the token audience
+    pub client_id: String,
+    /// JWKS URL override when the provider does not support discovery
+    #[serde(default)]
+    pub jwks_uri: Option<String>,
+    /// Create a grid account the first time an external subject logs in
+    #[serde(default)]
+    pub auto_provision: bool,
+    /// Seconds between JWKS refreshes
+    #[serde(default = "default_jwks_refresh_secs")]
+    pub jwks_refresh_secs: u64,
+}
+
+fn default_jwks_refresh_secs() -> u64 {
+    3600
+}
+
+fn default_api_keys_file() -> String {
+    "data/api_keys.json".to_string()
+}
+
+fn default_bans_file() -> String {
+    "data/bans.json".to_string()
+}
+
+fn default_idempotency_file() -> String {
+    "data/idempotency.json".to_string()
+}
+
+fn default_idempotency_ttl_secs() -> u64 {
+    86400
 }
 
 impl Default for SecurityConfig {
@@ -305,6 +719,11 @@ impl Default for SecurityConfig {
             ip_whitelist: vec![],
             enable_ip_blacklist: false,
             ip_blacklist: vec![],
+            oidc: None,
+            api_keys_file: default_api_keys_file(),
+            bans_file: default_bans_file(),
+            idempotency_file: default_idempotency_file(),
+            idempotency_ttl_secs: default_idempotency_ttl_secs(),
         }
     }
 }
@@ -445,100 +864,1962 @@ impl Default for OpenSimConfig {
     }
 }
 
-/// AI configuration (Phase II)
+/// Region simulation loop configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AIConfig {
-    /// Enable AI features
-    pub enabled: bool,
-    /// Content generation AI configuration
-    pub content_generation: ContentGenerationConfig,
-    /// Social intelligence AI configuration
-    pub social_intelligence: SocialIntelligenceConfig,
-    /// Natural language processing configuration
-    pub nlp: NLPConfig,
-    /// MapleAI integration configuration
-    pub maple_ai: MapleAIConfig,
+pub struct SimulationConfig {
+    /// Simulation ticks per second
+    pub tick_rate: u32,
+    /// Frame-time budget in milliseconds
+    pub frame_budget_ms: u64,
+    /// Consecutive over-budget frames before update rates are degraded
+    pub overload_frames: u32,
+    /// Consecutive comfortably-under-budget frames before update rates recover
+    pub recovery_frames: u32,
+    /// Maximum degrade level (sheddable subsystems run every 2^level ticks),
+    /// at most [`SimulationConfig::MAX_DEGRADE_LEVEL`]
+    pub max_degrade_level: u8,
+    /// Interval in seconds between timing stat exports
+    pub stats_interval: u64,
 }
 
-/// Content generation AI configuration
+impl SimulationConfig {
+    /// Highest accepted `max_degrade_level`: subsystems still run every
+    /// 65536 ticks, about 100 minutes at the default tick rate
+    pub const MAX_DEGRADE_LEVEL: u8 = 16;
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            tick_rate: 11,
+            frame_budget_ms: 80,
+            overload_frames: 5,
+            recovery_frames: 50,
+            max_degrade_level: 3,
+            stats_interval: 10,
+        }
+    }
+}
+
+/// Front page and viewer login splash page configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ContentGenerationConfig {
-    /// Enable content generation
+#[serde(default)]
+pub struct WebConfig {
+    /// Path to a template replacing the built-in front page at `/`
+    pub home_template: Option<String>,
+    /// Path to a template replacing the built-in viewer login splash page
+    pub splash_template: Option<String>,
+    /// Registration link shown on both pages (defaults to `/register`)
+    pub register_url: Option<String>,
+    /// Message of the day shown on the splash page
+    pub welcome_message: String,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        Self {
+            home_template: None,
+            splash_template: None,
+            register_url: None,
+            welcome_message: "Welcome to Mutsea".to_string(),
+        }
+    }
+}
+
+/// Self-service account registration configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RegistrationConfig {
+    /// Allow new accounts to be created through `/register`
     pub enabled: bool,
-    /// Text-to-3D model endpoint
-    pub text_to_3d_endpoint: String,
-    /// Texture generation endpoint
-    pub texture_generation_endpoint: String,
-    /// Animation generation endpoint
-    pub animation_generation_endpoint: String,
-    /// Maximum generation time in seconds
-    pub max_generation_time: u64,
-    /// Quality level (low, medium, high)
-    pub quality_level: String,
-    /// Enable caching of generated content
-    pub enable_caching: bool,
+    /// Hold new accounts until the email address is verified
+    pub require_email_verification: bool,
+    /// Reject registrations without a valid invite code
+    pub require_invite: bool,
+    /// Single-use invite codes accepted at registration
+    pub invite_codes: Vec<String>,
+    /// Hours before an unverified registration expires
+    pub verification_ttl_hours: u64,
+    /// Registration attempts allowed per client address per hour
+    pub max_attempts_per_hour: u32,
+    /// Minimum password length
+    pub min_password_length: usize,
 }
 
-/// Social intelligence AI configuration
+impl Default for RegistrationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            require_email_verification: true,
+            require_invite: false,
+            invite_codes: Vec::new(),
+            verification_ttl_hours: 24,
+            max_attempts_per_hour: 5,
+            min_password_length: 8,
+        }
+    }
+}
+
+/// Scheduled region backup configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SocialIntelligenceConfig {
-    /// Enable social intelligence
+#[serde(default)]
+pub struct BackupConfig {
+    /// Write OAR snapshots of every region on a schedule
     pub enabled: bool,
-    /// Relationship analysis endpoint
-    pub relationship_analysis_endpoint: String,
-    /// Conversation facilitation endpoint
-    pub conversation_facilitation_endpoint: String,
-    /// Group dynamics prediction endpoint
-    pub group_dynamics_endpoint: String,
-    /// Update interval in seconds
-    pub update_interval: u64,
+    /// Directory holding one subdirectory of snapshots per region
+    pub directory: String,
+    /// Minutes between snapshots
+    pub interval_minutes: u64,
+    /// Number of most recent snapshots kept per region
+    pub keep_last: usize,
+    /// Snapshots older than this many days are deleted (0 = no age limit)
+    pub max_age_days: u64,
 }
 
-/// Natural language processing configuration
+impl BackupConfig {
+    /// Snapshot directory for a region
+    pub fn region_dir(&self, region_name: &str) -> PathBuf {
+        PathBuf::from(&self.directory).join(safe_file_name(region_name))
+    }
+
+    /// Snapshot staged by `mutsea region rollback`, applied at region start
+    pub fn pending_rollback_path(&self, region_name: &str) -> PathBuf {
+        self.region_dir(region_name).join("rollback.oar.pending")
+    }
+
+    /// Snapshot files for a region, newest first. File names end in a
+    /// `%Y%m%d-%H%M%S` timestamp, so name order is time order.
+    pub fn snapshots(&self, region_name: &str) -> std::io::Result<Vec<PathBuf>> {
+        let entries = match std::fs::read_dir(self.region_dir(region_name)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut snapshots: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "oar"))
+            .collect();
+        snapshots.sort();
+        snapshots.reverse();
+        Ok(snapshots)
+    }
+}
+
+/// `name` with everything but ASCII letters, digits, `-` and `_` replaced,
+/// for use as a file name
+fn safe_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: "data/backups".to_string(),
+            interval_minutes: 60,
+            keep_last: 24,
+            max_age_days: 7,
+        }
+    }
+}
+
+/// Simulation tick profiler configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct NLPConfig {
-    /// Enable NLP features
+#[serde(default)]
+pub struct ProfilerConfig {
+    /// Sample per-subsystem time on every simulation tick
     pub enabled: bool,
-    /// Intent classification model
-    pub intent_model: String,
-    /// Entity extraction model
-    pub entity_model: String,
-    /// Sentiment analysis model
-    pub sentiment_model: String,
-    /// Language detection model
-    pub language_model: String,
-    /// Default language
-    pub default_language: String,
-    /// Supported languages
-    pub supported_languages: Vec<String>,
+    /// Seconds between aggregated samples
+    pub flush_interval_secs: u64,
+    /// Write aggregated samples to the database `performance_metrics` table
+    pub record_to_database: bool,
+    /// Directory for folded-stack dumps of over-budget ticks
+    pub flamegraph_dir: String,
+    /// Number of most recent folded-stack dumps kept (0 = no dumps)
+    pub max_flamegraphs: usize,
 }
 
-/// MapleAI integration configuration
+impl Default for ProfilerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            flush_interval_secs: 60,
+            record_to_database: false,
+            flamegraph_dir: "data/profiles".to_string(),
+            max_flamegraphs: 50,
+        }
+    }
+}
+
+/// Retry policy for transient failures. Backoff doubles (by `multiplier`)
+/// from `initial_backoff_ms` up to `max_backoff_ms`, randomised by `jitter`.
+/// Retries draw from a budget refilled by `budget_ratio` per call, so a
+/// failing backend sees at most that fraction of extra load.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MapleAIConfig {
-    /// Enable MapleAI integration
+#[serde(default)]
+pub struct RetryConfig {
+    /// Total attempts including the first (1 = no retries)
+    pub max_attempts: u32,
+    /// Delay in milliseconds before the first retry
+    pub initial_backoff_ms: u64,
+    /// Cap in milliseconds on any one delay, jitter included
+    pub max_backoff_ms: u64,
+    /// Factor each delay is multiplied by over the one before, at least 1.0
+    pub multiplier: f64,
+    /// Fraction of each backoff randomised, 0.0 to 1.0
+    pub jitter: f64,
+    /// Retry tokens earned per call
+    pub budget_ratio: f64,
+    /// Retry tokens that can be saved up
+    pub budget_max_tokens: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 5000,
+            multiplier: 2.0,
+            jitter: 0.2,
+            budget_ratio: 0.1,
+            budget_max_tokens: 10.0,
+        }
+    }
+}
+
+/// Circuit breaker thresholds, shared by every backend breaker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    /// Whether backend calls go through breakers at all
     pub enabled: bool,
-    /// MapleAI server endpoint
-    pub server_endpoint: String,
-    /// Agent capabilities
-    pub agent_capabilities: Vec<String>,
-    /// Consensus timeout in seconds
-    pub consensus_timeout: u64,
-    /// Maximum concurrent consensus sessions
-    pub max_concurrent_sessions: u32,
+    /// Consecutive transient failures that open the breaker
+    pub failure_threshold: u32,
+    /// Seconds a breaker stays open before probing the backend
+    pub open_secs: u64,
+    /// Concurrent probe calls allowed while half-open
+    pub half_open_probes: u32,
 }
 
-impl Default for AIConfig {
+impl Default for CircuitBreakerConfig {
     fn default() -> Self {
         Self {
-            enabled: false,
-            content_generation: ContentGenerationConfig {
-                enabled: false,
-                text_to_3d_endpoint: "http://localhost:8001/text-to-3d".to_string(),
-                texture_generation_endpoint: "http://localhost:8001/generate-texture".to_string(),
-                animation_generation_endpoint: "http://localhost:8001/generate-animation"
-                    .to_string(),
-                max_generation_time: 30,
+            enabled: true,
+            failure_threshold: 5,
+            open_secs: 30,
+            half_open_probes: 1,
+        }
+    }
+}
+
+/// Export of tracing spans to an OpenTelemetry collector over OTLP
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// Whether spans are exported
+    pub enabled: bool,
+    /// OTLP/gRPC collector endpoint
+    pub otlp_endpoint: String,
+    /// `service.name` resource attribute
+    pub service_name: String,
+    /// Fraction of new traces sampled, 0.0 to 1.0
+    pub sample_ratio: f64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            otlp_endpoint: "http://localhost:4317".to_string(),
+            service_name: "mutsea-server".to_string(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+/// Hash-sharding of the assets table for grids with tens of millions of
+/// assets. Changing `shards` or `layout` on a populated database requires
+/// `mutsea database reshard` first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AssetShardingConfig {
+    /// Number of shards; 1 keeps the single OpenSim `assets` table
+    pub shards: u32,
+    /// `tables` (assets_0, assets_1, ...) or `schemas` (asset_shard_0.assets,
+    /// ...; PostgreSQL only)
+    pub layout: String,
+}
+
+impl Default for AssetShardingConfig {
+    fn default() -> Self {
+        Self {
+            shards: 1,
+            layout: "tables".to_string(),
+        }
+    }
+}
+
+/// Analytics write buffering. Records wait in a ring buffer of `capacity`
+/// entries, dropping the oldest when full, and are written in batches of
+/// `batch_size` every `flush_interval_ms` or as soon as a batch is ready.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalyticsIngestConfig {
+    /// Records held before the oldest are dropped
+    pub capacity: usize,
+    /// Milliseconds between flushes of a partial batch
+    pub flush_interval_ms: u64,
+    /// Records written per batch
+    pub batch_size: usize,
+}
+
+impl Default for AnalyticsIngestConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 65_536,
+            flush_interval_ms: 1000,
+            batch_size: 1000,
+        }
+    }
+}
+
+/// Rollups of stored performance metrics (raw -> 1 minute -> 1 hour ->
+/// 1 day) and how long each resolution is kept; 0 keeps it forever. Rows
+/// are only pruned once rolled up into the next resolution.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsRetentionConfig {
+    /// Run the rollup and compaction job
+    pub enabled: bool,
+    /// Seconds between compaction runs
+    pub compaction_interval_secs: u64,
+    /// Hours of raw samples kept
+    pub raw_hours: u64,
+    /// Days of one-minute rollups kept
+    pub minute_days: u64,
+    /// Days of one-hour rollups kept
+    pub hour_days: u64,
+    /// Days of one-day rollups kept
+    pub day_days: u64,
+}
+
+impl Default for MetricsRetentionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            compaction_interval_secs: 300,
+            raw_hours: 48,
+            minute_days: 14,
+            hour_days: 180,
+            day_days: 0,
+        }
+    }
+}
+
+/// The log of world state changes: a snapshot of a region is taken once
+/// `snapshot_every` events have been appended since the last, and events
+/// a snapshot covers are pruned after `retain_event_days` (0 keeps them
+/// forever, so the state can be rebuilt at any time)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldEventsConfig {
+    /// Run the snapshot and pruning job
+    pub enabled: bool,
+    /// Seconds between compaction runs
+    pub compaction_interval_secs: u64,
+    /// Events since the last snapshot before another is taken
+    pub snapshot_every: u64,
+    /// Days events are kept once a snapshot covers them
+    pub retain_event_days: u64,
+}
+
+impl Default for WorldEventsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            compaction_interval_secs: 600,
+            snapshot_every: 10_000,
+            retain_event_days: 90,
+        }
+    }
+}
+
+/// The job scoring AI decisions once their outcome is reported: every
+/// `evaluation_interval_secs` up to `batch_size` decisions get their
+/// `outcome_variance` and `learning_value` computed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AIFeedbackConfig {
+    /// Whether the job runs
+    pub enabled: bool,
+    /// Seconds between runs
+    pub evaluation_interval_secs: u64,
+    /// Most decisions scored per run
+    pub batch_size: usize,
+}
+
+impl Default for AIFeedbackConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            evaluation_interval_secs: 60,
+            batch_size: 500,
+        }
+    }
+}
+
+/// Ecosystem simulation: populations, resources and weather advance every
+/// `step_interval_secs` of simulation time and a snapshot of each region
+/// is stored every `snapshot_interval_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EcosystemConfig {
+    /// Whether the ecosystem is simulated
+    pub enabled: bool,
+    /// Seconds between simulation steps
+    pub step_interval_secs: u64,
+    /// Seconds between stored snapshots
+    pub snapshot_interval_secs: u64,
+    /// Ecosystem hours simulated per real hour
+    pub time_scale: f64,
+    /// Relative population change within one step reported as a shift
+    pub population_shift_threshold: f64,
+    /// Resource level below which depletion is reported
+    pub depletion_threshold: f64,
+    /// Seed of the weather; 0 picks one at random
+    pub seed: u64,
+}
+
+impl Default for EcosystemConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            step_interval_secs: 10,
+            snapshot_interval_secs: 300,
+            time_scale: 24.0,
+            population_shift_threshold: 0.25,
+            depletion_threshold: 0.1,
+            seed: 0,
+        }
+    }
+}
+
+/// Region environment: the day lasts `day_length_secs` real seconds and
+/// the sun, wind and clouds are sent to viewers every `update_interval_secs`.
+/// Sky and water follow the ecosystem's weather when it is simulated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentConfig {
+    /// Whether the environment is sent to viewers
+    pub enabled: bool,
+    /// Real seconds in one region day
+    pub day_length_secs: u32,
+    /// Shifts the region clock, in real seconds
+    pub day_offset_secs: u32,
+    /// Seconds between updates sent to viewers
+    pub update_interval_secs: u64,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            day_length_secs: 4 * 3600,
+            day_offset_secs: 0,
+            update_interval_secs: 5,
+        }
+    }
+}
+
+/// World generation: layouts come from the built-in `procedural` planner or
+/// from an `http` AI service posted the request at `endpoint`. The last
+/// `undo_history` batches can be undone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldGenerationConfig {
+    /// Accept world generation requests
+    pub enabled: bool,
+    /// Planner to use: `procedural` or `http`
+    pub provider: String,
+    /// URL of the `http` AI service
+    pub endpoint: String,
+    /// Timeout of a request to the AI service in seconds
+    pub timeout_secs: u64,
+    /// Most objects one batch may place
+    pub max_objects: usize,
+    /// Generated batches kept for undo
+    pub undo_history: usize,
+}
+
+impl Default for WorldGenerationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            provider: "procedural".to_string(),
+            endpoint: "http://localhost:8003/layout".to_string(),
+            timeout_secs: 30,
+            max_objects: 200,
+            undo_history: 20,
+        }
+    }
+}
+
+/// Quests: generated ones are written by the built-in `procedural`
+/// storyteller or an `http` AI service posted the request at `endpoint`.
+/// With `offer_on_login`, players without an active quest are given a new
+/// `default_theme` quest as they log in. Quest messages appear in chat as
+/// said by `narrator`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuestsConfig {
+    /// Offer and track quests
+    pub enabled: bool,
+    /// Storyteller to use: `procedural` or `http`
+    pub provider: String,
+    /// URL of the `http` AI service
+    pub endpoint: String,
+    /// Timeout of a request to the AI service in seconds
+    pub timeout_secs: u64,
+    /// Give players without an active quest one as they log in
+    pub offer_on_login: bool,
+    /// Theme of quests offered at login
+    pub default_theme: String,
+    /// Name quest messages are said by in chat
+    pub narrator: String,
+    /// Most quests a player may have active at once
+    pub max_active: usize,
+}
+
+impl Default for QuestsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            provider: "procedural".to_string(),
+            endpoint: "http://localhost:8003/quest".to_string(),
+            timeout_secs: 30,
+            offer_on_login: false,
+            default_theme: "exploration".to_string(),
+            narrator: "Narrator".to_string(),
+            max_active: 3,
+        }
+    }
+}
+
+/// NPC conversations: replies are written by the built-in `procedural`
+/// responder or an `http` AI service posted the request at `endpoint`,
+/// which streams back the reply text. An NPC hears avatars chatting on
+/// channel 0 within `hearing_range` metres.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NpcChatConfig {
+    /// Let NPCs answer chat
+    pub enabled: bool,
+    /// Responder to use: `procedural` or `http`
+    pub provider: String,
+    /// URL of the `http` AI service
+    pub endpoint: String,
+    /// Timeout of a request to the AI service in seconds
+    pub timeout_secs: u64,
+    /// Distance in metres within which an NPC hears chat
+    pub hearing_range: f32,
+    /// NPCs to place
+    pub npcs: Vec<NpcConfig>,
+}
+
+impl Default for NpcChatConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            provider: "procedural".to_string(),
+            endpoint: "http://localhost:8003/converse".to_string(),
+            timeout_secs: 30,
+            hearing_range: 20.0,
+            npcs: Vec::new(),
+        }
+    }
+}
+
+/// An NPC placed in the region; `id` keys its stored memory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NpcConfig {
+    /// NPC ID
+    pub id: uuid::Uuid,
+    /// Name the NPC chats as
+    pub name: String,
+    /// Where the NPC stands, in region coordinates
+    pub position: crate::Vector3,
+}
+
+/// NPC navigation over the region's terrain and prims. NPCs walk at
+/// `walk_speed` m/s, climb at most `max_step` metres per metre walked and
+/// keep `clearance` metres from obstacles; the navigation grid is rebuilt
+/// every `rebuild_interval_secs` to follow prims as they change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NavigationConfig {
+    /// Whether NPCs walk the region at all
+    pub enabled: bool,
+    /// Walking speed in metres per second
+    pub walk_speed: f32,
+    /// Steepest climb, in metres per metre walked
+    pub max_step: f32,
+    /// Metres kept from obstacles
+    pub clearance: f32,
+    /// Seconds between navigation grid rebuilds
+    pub rebuild_interval_secs: u64,
+}
+
+impl Default for NavigationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            walk_speed: 1.4,
+            max_step: 0.5,
+            clearance: 0.4,
+            rebuild_interval_secs: 5,
+        }
+    }
+}
+
+/// Ambient NPCs: each parcel is kept populated at `density` NPCs per
+/// hectare, or its entry in `parcel_density` by parcel name, with at most
+/// `max_npcs` in the region. Every `census_interval_secs` NPCs are spawned
+/// or despawned to match. Each NPC thinks every `think_interval_secs`,
+/// doing what the behavior definitions decide or else wandering within
+/// `wander_radius` of where it spawned; thinking gets
+/// `ai_budget_ms` a frame and NPCs over budget wait for the next frame.
+/// NPC counts and AI cost go to the performance analytics every
+/// `report_interval_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PopulationConfig {
+    /// Whether ambient NPCs are spawned at all
+    pub enabled: bool,
+    /// NPCs per hectare for parcels without their own density
+    pub density: f32,
+    /// Density overrides keyed by parcel name
+    pub parcel_density: HashMap<String, f32>,
+    /// Most ambient NPCs in the region at once
+    pub max_npcs: usize,
+    /// Milliseconds of NPC thinking allowed per frame
+    pub ai_budget_ms: f64,
+    /// Seconds between decisions for each NPC
+    pub think_interval_secs: u64,
+    /// Seconds between spawning or despawning NPCs to match the densities
+    pub census_interval_secs: u64,
+    /// Metres from its spawn point an idle NPC wanders
+    pub wander_radius: f32,
+    /// Seconds between reports to the performance analytics
+    pub report_interval_secs: u64,
+    /// Name ambient NPCs speak as
+    pub name: String,
+}
+
+impl Default for PopulationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            density: 0.5,
+            parcel_density: HashMap::new(),
+            max_npcs: 20,
+            ai_budget_ms: 2.0,
+            think_interval_secs: 5,
+            census_interval_secs: 10,
+            wander_radius: 15.0,
+            report_interval_secs: 60,
+            name: "Villager".to_string(),
+        }
+    }
+}
+
+/// NPC behavior definitions: every `.json` and `.toml` file under `path`
+/// defines behaviors, reloaded when the files change. The files are
+/// checked every `reload_interval_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BehaviorsConfig {
+    /// Whether behavior definitions are loaded
+    pub enabled: bool,
+    /// Directory of definition files
+    pub path: String,
+    /// Seconds between checks for changed files
+    pub reload_interval_secs: u64,
+}
+
+impl Default for BehaviorsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: "config/behaviors".to_string(),
+            reload_interval_secs: 5,
+        }
+    }
+}
+
+/// Vehicle physics for prims scripted as vehicles. Boats float and
+/// hovering vehicles keep their height over water at `water_height`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VehiclesConfig {
+    /// Run vehicle physics
+    pub enabled: bool,
+    /// Height of the water surface in metres
+    pub water_height: f32,
+}
+
+impl Default for VehiclesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            water_height: 20.0,
+        }
+    }
+}
+
+/// Collision detection between prims, avatars and the ground. With
+/// `record_player_collisions` every avatar bumping into a prim is written to
+/// the player behavior analytics, batched every `flush_interval_secs`, for
+/// collision heatmaps.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollisionsConfig {
+    /// Whether collisions are detected
+    pub enabled: bool,
+    /// Whether avatar collisions go to the analytics
+    pub record_player_collisions: bool,
+    /// Seconds between analytics batches
+    pub flush_interval_secs: u64,
+}
+
+impl Default for CollisionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            record_player_collisions: false,
+            flush_interval_secs: 30,
+        }
+    }
+}
+
+/// Region raycasts. A batch casts at most `max_batch` rays of up to
+/// `max_distance` metres, each answering at most `max_hits` hits; clicks on
+/// prims further than `click_range` from the camera are refused.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RaycastConfig {
+    /// Whether raycasts are answered
+    pub enabled: bool,
+    /// Most rays in one batch
+    pub max_batch: usize,
+    /// Longest ray in metres
+    pub max_distance: f32,
+    /// Most hits returned per ray
+    pub max_hits: usize,
+    /// Metres from the camera a click may reach
+    pub click_range: f32,
+}
+
+impl Default for RaycastConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_batch: 64,
+            max_distance: 256.0,
+            max_hits: 16,
+            click_range: 64.0,
+        }
+    }
+}
+
+/// Mesh prims. Each compressed block of a mesh asset may inflate to at
+/// most `max_block_bytes`, so a crafted upload can't exhaust memory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MeshesConfig {
+    /// Whether mesh assets are decoded into collision shapes
+    pub enabled: bool,
+    /// Largest inflated size of one block, in bytes
+    pub max_block_bytes: usize,
+}
+
+impl Default for MeshesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_block_bytes: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// The region economy. New accounts start with `starting_balance`; prims
+/// sell for at most `max_price` and payments move at most that much.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EconomyConfig {
+    /// Let residents pay each other and buy prims
+    pub enabled: bool,
+    /// Balance a new account starts with
+    pub starting_balance: i64,
+    /// Highest prim price and single payment accepted
+    pub max_price: i32,
+}
+
+impl Default for EconomyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            starting_balance: 1000,
+            max_price: 999_999,
+        }
+    }
+}
+
+/// Attachments worn by avatars. Each avatar wears at most `max_per_avatar`
+/// prims, put back on at login when `restore_on_login` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AttachmentsConfig {
+    /// Let avatars wear attachments
+    pub enabled: bool,
+    /// Most prims one avatar can wear at once
+    pub max_per_avatar: usize,
+    /// Put attachments worn at logout back on at login
+    pub restore_on_login: bool,
+}
+
+impl Default for AttachmentsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_per_avatar: 38,
+            restore_on_login: true,
+        }
+    }
+}
+
+/// Limits enforced on what agents build, run and upload. The region holds
+/// at most `max_prims_per_region` prims, and each parcel a share of them
+/// in proportion to its area; each owner runs at most
+/// `max_scripts_per_owner` scripts and uploads at most
+/// `max_upload_bytes_per_day` bytes of assets a day. Attachments are
+/// limited by `attachments.max_per_avatar`. Administrators override any of
+/// these per region, parcel or agent through the admin API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    /// Whether the limits are enforced
+    pub enabled: bool,
+    /// Prims the region holds, shared out to parcels by area
+    pub max_prims_per_region: u64,
+    /// Scripts each owner may run
+    pub max_scripts_per_owner: u64,
+    /// Asset bytes each owner may upload per day
+    pub max_upload_bytes_per_day: u64,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_prims_per_region: 15_000,
+            max_scripts_per_owner: 1_000,
+            max_upload_bytes_per_day: 100 * 1024 * 1024,
+        }
+    }
+}
+
+/// Parcel sales and land auctions. Owners sell parcels from the viewer;
+/// auctions started through the admin API are settled every
+/// `auction_interval_secs` once they end, the winner paying the seller.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LandConfig {
+    /// Let owners sell parcels and run auctions
+    pub enabled: bool,
+    /// Interval in seconds between checks for ended auctions
+    pub auction_interval_secs: u64,
+}
+
+impl Default for LandConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            auction_interval_secs: 60,
+        }
+    }
+}
+
+/// Returning prims to their owners' Lost And Found. Parcels with an
+/// auto-return time are checked every `sweep_interval_secs`, returning no
+/// more than `max_returns_per_sweep` prims at a time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReturnsConfig {
+    /// Let prims be returned, by hand and by parcel auto-return
+    pub enabled: bool,
+    /// Interval in seconds between auto-return sweeps
+    pub sweep_interval_secs: u64,
+    /// Most prims one sweep returns
+    pub max_returns_per_sweep: usize,
+}
+
+impl Default for ReturnsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sweep_interval_secs: 60,
+            max_returns_per_sweep: 500,
+        }
+    }
+}
+
+/// Taking prims into inventory and rezzing them again. One item holds at
+/// most `max_objects_per_item` prims; selections taken together become one
+/// coalesced item.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ObjectItemsConfig {
+    /// Let agents take prims into inventory and rez them
+    pub enabled: bool,
+    /// Most prims one object item holds
+    pub max_objects_per_item: usize,
+}
+
+impl Default for ObjectItemsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_objects_per_item: 256,
+        }
+    }
+}
+
+/// Terraforming from the viewer's land tools. Brushes move the terrain at
+/// up to `brush_rate` metres a second, between `min_height` and
+/// `max_height`. Each agent can undo its last `max_undo_steps` strokes.
+/// Edited terrain is saved to `{directory}/{region}.r32` at most every
+/// `save_interval_secs`, and loaded from there when the region starts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerraformConfig {
+    /// Let agents edit the terrain
+    pub enabled: bool,
+    /// Fastest a brush moves the terrain, in metres a second
+    pub brush_rate: f32,
+    /// Lowest terrain height in metres
+    pub min_height: f32,
+    /// Highest terrain height in metres
+    pub max_height: f32,
+    /// Strokes each agent can undo
+    pub max_undo_steps: usize,
+    /// Directory edited terrain is saved in
+    pub directory: String,
+    /// Shortest time in seconds between saves of a region's terrain
+    pub save_interval_secs: u64,
+}
+
+impl TerraformConfig {
+    /// Saved terrain of a region
+    pub fn region_path(&self, region_name: &str) -> PathBuf {
+        PathBuf::from(&self.directory).join(format!("{}.r32", safe_file_name(region_name)))
+    }
+}
+
+impl Default for TerraformConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            brush_rate: 4.0,
+            min_height: 0.0,
+            max_height: 512.0,
+            max_undo_steps: 16,
+            directory: "data/terrain".to_string(),
+            save_interval_secs: 30,
+        }
+    }
+}
+
+/// Inventory fetched through the FetchInventoryDescendents2 and
+/// FetchInventory2 capabilities. One answer holds at most
+/// `max_folders_per_fetch` folders and, past the first folder, no more than
+/// `max_entries_per_fetch` folders and items in all; viewers ask again for
+/// the rest. Answers of at least `compress_min_bytes` are gzipped for
+/// viewers that accept it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InventoryConfig {
+    /// Whether the fetch capabilities are offered
+    pub enabled: bool,
+    /// Most folders in one answer
+    pub max_folders_per_fetch: usize,
+    /// Most folders and items in one answer, past the first folder
+    pub max_entries_per_fetch: usize,
+    /// Smallest answer in bytes that is gzipped
+    pub compress_min_bytes: usize,
+}
+
+impl Default for InventoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_folders_per_fetch: 100,
+            max_entries_per_fetch: 5000,
+            compress_min_bytes: 1024,
+        }
+    }
+}
+
+/// Avatar appearance baked by the region. The textures of the wearables an
+/// agent wears are layered into `bake_size` square head, upper and lower
+/// body bakes; textures of more than `max_layer_bytes` are left out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppearanceConfig {
+    /// Whether the region bakes appearances
+    pub enabled: bool,
+    /// Width and height of each bake in pixels
+    pub bake_size: u32,
+    /// Largest wearable texture layered in, in bytes
+    pub max_layer_bytes: usize,
+}
+
+impl Default for AppearanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bake_size: 512,
+            max_layer_bytes: 4 * 1024 * 1024,
+        }
+    }
+}
+
+/// Photos from the viewer's snapshot floater, saved to inventory or sent
+/// as postcards, each at most `max_upload_bytes`. An upload not posted
+/// within `upload_timeout_secs` of being asked for is dropped. When
+/// `gallery_url` is set, postcards their senders allow to be published
+/// are posted there as JSON, with `gallery_api_key` as a bearer token if
+/// set, giving up after `gallery_timeout_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotsConfig {
+    /// Whether snapshots and postcards are taken
+    pub enabled: bool,
+    /// Largest image accepted, in bytes
+    pub max_upload_bytes: usize,
+    /// Seconds an uploader URL stays valid
+    pub upload_timeout_secs: u64,
+    /// Where publishable postcards are posted; none when empty
+    pub gallery_url: String,
+    /// Bearer token for the gallery, sent when set
+    pub gallery_api_key: String,
+    /// Seconds before a gallery post is abandoned
+    pub gallery_timeout_secs: u64,
+}
+
+impl Default for SnapshotsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_upload_bytes: 4 * 1024 * 1024,
+            upload_timeout_secs: 300,
+            gallery_url: String::new(),
+            gallery_api_key: String::new(),
+            gallery_timeout_secs: 10,
+        }
+    }
+}
+
+/// Animations uploaded through NewFileAgentInventory, as keyframe
+/// animations or as BVH files converted at `bvh_priority`. Uploads are at
+/// most `max_upload_bytes`, move at most `max_joints` joints and last at
+/// most `max_duration_secs`; priorities above `max_priority` are lowered to
+/// it. An upload not posted within `upload_timeout_secs` is dropped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimationsConfig {
+    /// Accept animation uploads
+    pub enabled: bool,
+    /// Largest upload accepted, in bytes
+    pub max_upload_bytes: usize,
+    /// Time in seconds an upload may take to be posted
+    pub upload_timeout_secs: u64,
+    /// Most joints one animation may move
+    pub max_joints: usize,
+    /// Longest animation accepted, in seconds
+    pub max_duration_secs: f32,
+    /// Highest priority kept; higher ones are lowered to it
+    pub max_priority: i32,
+    /// Priority BVH uploads are given
+    pub bvh_priority: i32,
+}
+
+impl Default for AnimationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_upload_bytes: 1024 * 1024,
+            upload_timeout_secs: 300,
+            max_joints: 133,
+            max_duration_secs: 60.0,
+            max_priority: 4,
+            bvh_priority: 3,
+        }
+    }
+}
+
+/// Prims made, moved and deleted and chat said through the REST API by
+/// keys with the `write:scene` scope. Prims are at most `max_prim_scale`
+/// metres on each side, and chat is heard within `chat_range` metres.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneControlConfig {
+    /// Whether the scene can be changed through the API
+    pub enabled: bool,
+    /// Largest side of a prim in metres
+    pub max_prim_scale: f32,
+    /// Metres within which API chat is heard
+    pub chat_range: f32,
+}
+
+impl Default for SceneControlConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_prim_scale: 64.0,
+            chat_range: 20.0,
+        }
+    }
+}
+
+/// Who prims are shown to. Temporary-on-rez prims are removed once they
+/// are `temp_on_rez_secs` old.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VisibilityConfig {
+    /// Whether prim visibility and temporary prims are managed
+    pub enabled: bool,
+    /// Seconds a temporary-on-rez prim lasts
+    pub temp_on_rez_secs: u64,
+}
+
+impl Default for VisibilityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            temp_on_rez_secs: 60,
+        }
+    }
+}
+
+/// Region export to glTF 2.0. The terrain is sampled every
+/// `terrain_step` meters and coloured as shore below `water_height`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GltfExportConfig {
+    /// Whether regions can be exported
+    pub enabled: bool,
+    /// Metres between terrain samples
+    pub terrain_step: u32,
+    /// Height in metres below which terrain is shore
+    pub water_height: f32,
+}
+
+impl Default for GltfExportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            terrain_step: 1,
+            water_height: 20.0,
+        }
+    }
+}
+
+/// Experimental WebRTC transport for browser viewers, in servers built with
+/// the `webrtc` feature. At most `max_peers` browsers connect at once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebRtcConfig {
+    /// Whether browsers may connect over WebRTC
+    pub enabled: bool,
+    /// STUN or TURN server URLs offered to browsers
+    pub ice_servers: Vec<String>,
+    /// Most browsers connected at once
+    pub max_peers: usize,
+}
+
+impl Default for WebRtcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ice_servers: vec!["stun:stun.l.google.com:19302".to_string()],
+            max_peers: 64,
+        }
+    }
+}
+
+/// Internal gRPC services (assets, users, presence, region control) so
+/// components can run on separate machines, in servers built with the `grpc`
+/// feature. Nodes authenticate each other with certificates signed by
+/// `ca_file` (mutual TLS).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrpcConfig {
+    /// Whether the gRPC services are served
+    pub enabled: bool,
+    /// Address and port to listen on
+    pub bind_address: String,
+    /// This node's certificate, PEM encoded
+    pub cert_file: String,
+    /// This node's private key, PEM encoded
+    pub key_file: String,
+    /// CA that signs every node's certificate, PEM encoded
+    pub ca_file: String,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: "0.0.0.0:9100".to_string(),
+            cert_file: "certs/node.pem".to_string(),
+            key_file: "certs/node.key".to_string(),
+            ca_file: "certs/ca.pem".to_string(),
+        }
+    }
+}
+
+/// Cluster mode, in servers built with the `grpc` feature. A `controller`
+/// tracks the grid's nodes and which regions each hosts; `region` nodes
+/// register with it at `controller_url` and heartbeat every
+/// `heartbeat_secs`. A node silent for `node_timeout_secs` is dead and its
+/// regions go to other nodes. `standalone` servers run on their own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClusterConfig {
+    /// `standalone`, `controller` or `region`
+    pub role: String,
+    /// Generated at startup when empty
+    pub node_id: String,
+    /// Controller's gRPC endpoint, for `region` nodes
+    pub controller_url: String,
+    /// This node's gRPC endpoint as other nodes reach it
+    pub advertise_url: String,
+    /// What this node offers; `regions` means it can host regions
+    pub capabilities: Vec<String>,
+    /// Most regions this node hosts at once
+    pub max_regions: u32,
+    /// Seconds between heartbeats to the controller
+    pub heartbeat_secs: u64,
+    /// Seconds without a heartbeat before a node is dead
+    pub node_timeout_secs: u64,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            role: "standalone".to_string(),
+            node_id: String::new(),
+            controller_url: String::new(),
+            advertise_url: String::new(),
+            capabilities: vec!["regions".to_string()],
+            max_regions: 4,
+            heartbeat_secs: 5,
+            node_timeout_secs: 20,
+        }
+    }
+}
+
+/// Scene checkpoints between backups. Every `interval_secs` each region's
+/// objects and NPC positions are written to `directory`, and a region
+/// restarting after a crash resumes from its checkpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CheckpointConfig {
+    /// Whether checkpoints are written and resumed from
+    pub enabled: bool,
+    /// Directory holding one checkpoint file per region
+    pub directory: String,
+    /// Seconds between checkpoints
+    pub interval_secs: u64,
+}
+
+impl CheckpointConfig {
+    /// Checkpoint file of a region
+    pub fn region_path(&self, region_name: &str) -> PathBuf {
+        PathBuf::from(&self.directory).join(format!("{}.ckpt", safe_file_name(region_name)))
+    }
+}
+
+impl Default for CheckpointConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            directory: "data/checkpoints".to_string(),
+            interval_secs: 30,
+        }
+    }
+}
+
+/// Recording of simulation inputs for debugging. The packets, timer ticks
+/// and RNG seeds of the last `window_secs` (up to twice that) are kept in
+/// memory, together with a scene checkpoint to start from, and can be
+/// dumped to `directory` as a replay bundle. At most `max_events` inputs
+/// are kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplayConfig {
+    /// Whether inputs are recorded
+    pub enabled: bool,
+    /// Directory replay bundles are dumped to
+    pub directory: String,
+    /// Seconds of inputs kept
+    pub window_secs: u64,
+    /// Most inputs kept, however short the window
+    pub max_events: usize,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: "data/replays".to_string(),
+            window_secs: 30,
+            max_events: 200_000,
+        }
+    }
+}
+
+/// Script sandbox quotas. Scripts share `frame_budget_ms` of each frame,
+/// and each may use `cpu_ms_per_sec` of it per second; a script over its
+/// quota for `throttle_after_secs` seconds in a row is suspended for
+/// `throttle_secs`. A script using more than `max_memory_kb` is stopped,
+/// and events beyond `max_queued_events` are dropped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptsConfig {
+    /// Whether scripts run at all
+    pub enabled: bool,
+    /// Milliseconds of each frame shared by all scripts
+    pub frame_budget_ms: f64,
+    /// CPU milliseconds per second one script may use
+    pub cpu_ms_per_sec: f64,
+    /// Memory in KB above which a script is stopped
+    pub max_memory_kb: usize,
+    /// Events queued per script before more are dropped
+    pub max_queued_events: usize,
+    /// Seconds over quota in a row before a script is suspended
+    pub throttle_after_secs: u32,
+    /// Seconds a suspended script stays suspended
+    pub throttle_secs: u64,
+}
+
+impl Default for ScriptsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            frame_budget_ms: 8.0,
+            cpu_ms_per_sec: 5.0,
+            max_memory_kb: 64,
+            max_queued_events: 64,
+            throttle_after_secs: 3,
+            throttle_secs: 30,
+        }
+    }
+}
+
+/// HTTP for scripts. Each owner's scripts may make `requests_per_minute`
+/// llHTTPRequest calls, only to `allowed_hosts` if any are listed
+/// (`*.example.com` matches subdomains) and never to loopback or private
+/// addresses. Responses are cut to `max_body_bytes`. Scripts may hold
+/// `max_urls` llRequestURL URLs between them, and must answer a request to
+/// one within `url_timeout_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptHttpConfig {
+    /// Whether scripts may make and serve HTTP requests
+    pub enabled: bool,
+    /// Hosts requests may go to; any public host when empty
+    pub allowed_hosts: Vec<String>,
+    /// llHTTPRequest calls per owner per minute
+    pub requests_per_minute: u32,
+    /// Seconds before an outgoing request is abandoned
+    pub timeout_secs: u64,
+    /// Response bytes kept
+    pub max_body_bytes: usize,
+    /// llRequestURL URLs held at once across all scripts
+    pub max_urls: usize,
+    /// Seconds a script has to answer a request to its URL
+    pub url_timeout_secs: u64,
+}
+
+impl Default for ScriptHttpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            allowed_hosts: Vec::new(),
+            requests_per_minute: 60,
+            timeout_secs: 30,
+            max_body_bytes: 16384,
+            max_urls: 100,
+            url_timeout_secs: 25,
+        }
+    }
+}
+
+/// llEmail and llGetNextEmail. Objects are addressed as
+/// `<object-id>@<domain>`; mail between them stays in the grid, and other
+/// mail is relayed through `smtp_host` if set. Each owner's objects may
+/// send `emails_per_hour`, of at most `max_message_bytes`, and each object
+/// keeps up to `max_inbox` unread emails.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptEmailConfig {
+    /// Let scripts send and receive email
+    pub enabled: bool,
+    /// Domain objects are addressed at
+    pub domain: String,
+    /// SMTP relay for mail leaving the grid; such mail is refused when empty
+    pub smtp_host: String,
+    /// Port of the SMTP relay
+    pub smtp_port: u16,
+    /// Emails one owner's objects may send an hour
+    pub emails_per_hour: u32,
+    /// Largest email accepted, in bytes
+    pub max_message_bytes: usize,
+    /// Unread emails kept per object
+    pub max_inbox: usize,
+}
+
+impl Default for ScriptEmailConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            domain: "lsl.mutsea.local".to_string(),
+            smtp_host: String::new(),
+            smtp_port: 25,
+            emails_per_hour: 30,
+            max_message_bytes: 4096,
+            max_inbox: 100,
+        }
+    }
+}
+
+/// llOpenRemoteDataChannel. XML-RPC `llRemoteData` calls to the login URI
+/// are passed to the script holding the channel, which has
+/// `reply_timeout_secs` to answer. Scripts may hold `max_channels` between
+/// them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptXmlRpcConfig {
+    /// Accept XML-RPC calls for scripts
+    pub enabled: bool,
+    /// Most channels open at once across all scripts
+    pub max_channels: usize,
+    /// Seconds a script has to answer a call
+    pub reply_timeout_secs: u64,
+}
+
+impl Default for ScriptXmlRpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_channels: 100,
+            reply_timeout_secs: 30,
+        }
+    }
+}
+
+/// Estate and god moderation from the viewer. Grid administrators are
+/// granted `god_level` when they ask for god powers, and gods may kick,
+/// freeze and unfreeze anyone but other gods. Administrators and the
+/// `estate_managers`, named "First Last", may kick and freeze from the
+/// estate tools without god powers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModerationConfig {
+    /// Handle kick, freeze and god power requests
+    pub enabled: bool,
+    /// Estate managers, as "First Last"
+    pub estate_managers: Vec<String>,
+    /// God level granted to grid administrators
+    pub god_level: u8,
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            estate_managers: Vec::new(),
+            god_level: 200,
+        }
+    }
+}
+
+/// Logging of public chat and instant messages to the database, off
+/// unless enabled. Only regions of the `estates` listed are logged (all
+/// estates when empty), and nothing said on the `excluded_parcels` is.
+/// Logged messages are purged after `retention_days`, checked every
+/// `purge_interval_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatLogConfig {
+    /// Whether anything is logged
+    pub enabled: bool,
+    /// Log public chat
+    pub log_public_chat: bool,
+    /// Log instant messages
+    pub log_ims: bool,
+    /// Estate IDs whose regions are logged; all when empty
+    pub estates: Vec<u32>,
+    /// Parcels nothing said on is logged
+    pub excluded_parcels: Vec<uuid::Uuid>,
+    /// Days messages are kept
+    pub retention_days: u32,
+    /// Seconds between purges of old messages
+    pub purge_interval_secs: u64,
+}
+
+impl Default for ChatLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            log_public_chat: true,
+            log_ims: false,
+            estates: Vec::new(),
+            excluded_parcels: Vec::new(),
+            retention_days: 30,
+            purge_interval_secs: 3600,
+        }
+    }
+}
+
+/// Content ratings. The region is rated `region`; agents may only log in
+/// or teleport to regions rated up to their own highest rating, which is
+/// `default_access_max` for accounts that haven't been given one. Search
+/// only lists places the searching agent could enter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaturityConfig {
+    /// Rating of this region
+    pub region: Maturity,
+    /// Highest rating for accounts without their own
+    pub default_access_max: Maturity,
+}
+
+impl Default for MaturityConfig {
+    fn default() -> Self {
+        Self {
+            region: Maturity::Pg,
+            default_access_max: Maturity::Adult,
+        }
+    }
+}
+
+/// Avatar profiles and their picks, kept in the userprofile and userpicks
+/// tables when there is a database. Each avatar has at most `max_picks`
+/// picks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfilesConfig {
+    /// Whether profiles and picks are served
+    pub enabled: bool,
+    /// Most picks per avatar
+    pub max_picks: usize,
+}
+
+impl Default for ProfilesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_picks: 10,
+        }
+    }
+}
+
+/// The classifieds and events directory. Each avatar may list up to
+/// `max_classifieds` classifieds, for `listing_days` days at a price of at
+/// least `min_listing_price`, and `max_events` events that haven't ended.
+/// With `charge_listings` and the economy enabled, listing prices and
+/// `event_fee` are taken from the lister's balance, and classifieds set to
+/// renew are charged again when they expire. Expired classifieds and ended
+/// events are removed every `expiry_interval_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DirectoryConfig {
+    /// List classifieds and events in search
+    pub enabled: bool,
+    /// Most classifieds one avatar may list
+    pub max_classifieds: usize,
+    /// Most upcoming events one avatar may list
+    pub max_events: usize,
+    /// Days a classified stays listed
+    pub listing_days: u32,
+    /// Lowest price a classified may be listed at
+    pub min_listing_price: i32,
+    /// Take listing prices and event fees from the lister's balance
+    pub charge_listings: bool,
+    /// Fee for listing an event
+    pub event_fee: i64,
+    /// Interval in seconds between removals of expired listings
+    pub expiry_interval_secs: u64,
+}
+
+impl Default for DirectoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_classifieds: 20,
+            max_events: 10,
+            listing_days: 7,
+            min_listing_price: 50,
+            charge_listings: false,
+            event_fee: 0,
+            expiry_interval_secs: 300,
+        }
+    }
+}
+
+/// Sounds played in the region. Agents further than `hearing_distance`
+/// from a sound aren't sent it; sounds triggered at a point are sent at a
+/// gain fading from `full_gain_distance` to silence at `hearing_distance`.
+/// Uploaded sounds must be Ogg Vorbis of at most `max_upload_bytes` and
+/// `max_duration_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoundsConfig {
+    /// Play and upload sounds
+    pub enabled: bool,
+    /// Distance in metres beyond which a sound isn't sent
+    pub hearing_distance: f32,
+    /// Distance in metres within which a triggered sound plays at full gain
+    pub full_gain_distance: f32,
+    /// Largest sound upload accepted, in bytes
+    pub max_upload_bytes: usize,
+    /// Longest sound upload accepted, in seconds
+    pub max_duration_secs: f32,
+}
+
+impl Default for SoundsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            hearing_distance: 100.0,
+            full_gain_distance: 20.0,
+            max_upload_bytes: 1024 * 1024,
+            max_duration_secs: 30.0,
+        }
+    }
+}
+
+/// Display names and name lookups. Agents may change their display name
+/// once every `rename_cooldown_days`, to at most `max_display_name_length`
+/// characters. Viewers cache the names they look up for
+/// `cache_max_age_secs`, and the avatar picker lists at most
+/// `max_picker_results` avatars. With `unique_display_names` no two
+/// avatars may go by the same name, display or legacy, and display names
+/// containing any of `blocked_words` are refused.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NamesConfig {
+    /// Serve display names and the name lookup capabilities
+    pub enabled: bool,
+    /// Days an agent must wait between display name changes
+    pub rename_cooldown_days: u32,
+    /// Longest display name accepted, in characters
+    pub max_display_name_length: usize,
+    /// How long in seconds viewers may cache a looked up name
+    pub cache_max_age_secs: u64,
+    /// Most avatars one avatar picker search lists
+    pub max_picker_results: usize,
+    /// Refuse a display name another agent already has
+    pub unique_display_names: bool,
+    /// Words display names may not contain, ignoring case
+    pub blocked_words: Vec<String>,
+}
+
+impl Default for NamesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rename_cooldown_days: 7,
+            max_display_name_length: 31,
+            cache_max_age_secs: 3600,
+            max_picker_results: 50,
+            unique_display_names: true,
+            blocked_words: Vec::new(),
+        }
+    }
+}
+
+/// How a region's prims reach an agent that arrives. Prims are sent
+/// nearest first, at most `prims_per_tick` per agent each simulation
+/// frame and within its object throttle, so logins don't wait for the
+/// whole region. Prims further than `near_radius` whose largest side is
+/// under `small_prim_size` meters are deferred until everything else is
+/// sent. The last `keep_finished` finished streams are kept for tuning.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrimStreamingConfig {
+    /// Whether prims are streamed nearest first
+    pub enabled: bool,
+    /// Metres within which small prims are not deferred
+    pub near_radius: f32,
+    /// Largest side in metres below which a distant prim is deferred
+    pub small_prim_size: f32,
+    /// Most prims sent to each agent per frame
+    pub prims_per_tick: usize,
+    /// Finished streams kept for tuning
+    pub keep_finished: usize,
+}
+
+impl Default for PrimStreamingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            near_radius: 64.0,
+            small_prim_size: 0.5,
+            prims_per_tick: 100,
+            keep_finished: 32,
+        }
+    }
+}
+
+/// How many logins are let through at once. An admitted login holds a
+/// slot until its circuit reaches the region, or for `arrival_timeout_secs`
+/// if it never does. While `max_concurrent` slots are held, or
+/// `max_per_second` logins were admitted in the last second, further logins
+/// fail with their place in the queue and are admitted in that order as
+/// they retry. A place is kept for `queue_timeout_secs` between retries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoginQueueConfig {
+    /// Whether logins are queued; all are let through when off
+    pub enabled: bool,
+    /// Logins that may be on their way to the region at once
+    pub max_concurrent: usize,
+    /// Logins admitted per second
+    pub max_per_second: usize,
+    /// Seconds an admitted login holds its slot without arriving
+    pub arrival_timeout_secs: u64,
+    /// Seconds a queued login keeps its place between retries
+    pub queue_timeout_secs: u64,
+}
+
+impl Default for LoginQueueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_concurrent: 20,
+            max_per_second: 5,
+            arrival_timeout_secs: 60,
+            queue_timeout_secs: 120,
+        }
+    }
+}
+
+/// Maintenance mode, in which only administrators and the avatars in
+/// `allowlist` ("First Last" or UUID) may log in. Others are refused with
+/// `message`. Agents drained from the grid are warned and logged out
+/// `drain_grace_secs` later. `enabled` starts the server in maintenance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    /// Whether the server starts in maintenance
+    pub enabled: bool,
+    /// Shown to avatars refused at login
+    pub message: String,
+    /// Avatars who may still log in, as "First Last" or UUID
+    pub allowlist: Vec<String>,
+    /// Seconds between the warning and the logout when draining
+    pub drain_grace_secs: u64,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            message: "The grid is closed for maintenance. Please try again later.".to_string(),
+            allowlist: Vec::new(),
+            drain_grace_secs: 60,
+        }
+    }
+}
+
+/// Plugins extending the server. Besides those built in, the plugins of the
+/// shared `libraries` are loaded at startup when the server is built with
+/// the `plugins` feature. Each plugin is given its entry in `settings`,
+/// keyed by plugin name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginsConfig {
+    /// Whether any plugins are loaded, built in or shared
+    pub enabled: bool,
+    /// Shared libraries to load plugins from
+    pub libraries: Vec<PathBuf>,
+    /// Settings for each plugin, by plugin name
+    pub settings: HashMap<String, serde_json::Value>,
+}
+
+impl Default for PluginsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            libraries: Vec::new(),
+            settings: HashMap::new(),
+        }
+    }
+}
+
+/// Webhooks posting key events to outside services, such as chat bridges
+/// or billing. Each endpoint is sent the events it lists, or every event
+/// when it lists none, signed with its `secret`. A failed delivery is tried
+/// up to `max_attempts` times, first after `initial_backoff_secs` and then
+/// twice as long each time up to `max_backoff_secs`. The last
+/// `delivery_log_size` deliveries are kept for the admin API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhooksConfig {
+    /// Post events to the configured endpoints
+    pub enabled: bool,
+    /// Timeout of a single delivery in seconds
+    pub timeout_secs: u64,
+    /// Deliveries of an event tried before it is given up
+    pub max_attempts: u32,
+    /// Delay in seconds before the first retry
+    pub initial_backoff_secs: u64,
+    /// Longest delay in seconds between retries
+    pub max_backoff_secs: u64,
+    /// Number of recent deliveries kept in the delivery log
+    pub delivery_log_size: usize,
+    /// Where events are posted
+    pub endpoints: Vec<WebhookEndpointConfig>,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout_secs: 10,
+            max_attempts: 6,
+            initial_backoff_secs: 10,
+            max_backoff_secs: 3600,
+            delivery_log_size: 500,
+            endpoints: Vec::new(),
+        }
+    }
+}
+
+/// A URL webhooks are posted to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEndpointConfig {
+    /// URL the event is POSTed to as JSON
+    pub url: String,
+    /// Key of the HMAC-SHA256 signature; requests go unsigned when empty
+    #[serde(default)]
+    pub secret: String,
+    /// Events sent, such as "agent.login"; all of them when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+/// AI configuration (Phase II)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AIConfig {
+    /// Enable AI features
+    pub enabled: bool,
+    /// Content generation AI configuration
+    pub content_generation: ContentGenerationConfig,
+    /// Social intelligence AI configuration
+    pub social_intelligence: SocialIntelligenceConfig,
+    /// Natural language processing configuration
+    pub nlp: NLPConfig,
+    /// MapleAI integration configuration
+    pub maple_ai: MapleAIConfig,
+}
+
+/// Content generation AI configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentGenerationConfig {
+    /// Enable content generation
+    pub enabled: bool,
+    /// Text-to-3D model endpoint
+    pub text_to_3d_endpoint: String,
+    /// Texture generation endpoint
+    pub texture_generation_endpoint: String,
+    /// Animation generation endpoint
+    pub animation_generation_endpoint: String,
+    /// Maximum generation time in seconds
+    pub max_generation_time: u64,
+    /// Quality level (low, medium, high)
+    pub quality_level: String,
+    /// Enable caching of generated content
+    pub enable_caching: bool,
+}
+
+/// Social intelligence AI configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocialIntelligenceConfig {
+    /// Enable social intelligence
+    pub enabled: bool,
+    /// Relationship analysis endpoint
+    pub relationship_analysis_endpoint: String,
+    /// Conversation facilitation endpoint
+    pub conversation_facilitation_endpoint: String,
+    /// Group dynamics prediction endpoint
+    pub group_dynamics_endpoint: String,
+    /// Update interval in seconds
+    pub update_interval: u64,
+}
+
+/// Natural language processing configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NLPConfig {
+    /// Enable NLP features
+    pub enabled: bool,
+    /// Intent classification model
+    pub intent_model: String,
+    /// Entity extraction model
+    pub entity_model: String,
+    /// Sentiment analysis model
+    pub sentiment_model: String,
+    /// Language detection model
+    pub language_model: String,
+    /// Default language
+    pub default_language: String,
+    /// Supported languages
+    pub supported_languages: Vec<String>,
+}
+
+/// MapleAI integration configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapleAIConfig {
+    /// Enable MapleAI integration
+    pub enabled: bool,
+    /// MapleAI server endpoint
+    pub server_endpoint: String,
+    /// Agent capabilities
+    pub agent_capabilities: Vec<String>,
+    /// Consensus timeout in seconds
+    pub consensus_timeout: u64,
+    /// Maximum concurrent consensus sessions
+    pub max_concurrent_sessions: u32,
+}
+
+impl Default for AIConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            content_generation: ContentGenerationConfig {
+                enabled: false,
+                text_to_3d_endpoint: "http://localhost:8001/text-to-3d".to_string(),
+                texture_generation_endpoint: "http://localhost:8001/generate-texture".to_string(),
+                animation_generation_endpoint: "http://localhost:8001/generate-animation"
+                    .to_string(),
+                max_generation_time: 30,
                 quality_level: "medium".to_string(),
                 enable_caching: true,
             },
@@ -597,6 +2878,65 @@ impl Default for MutseaConfig {
             assets: AssetConfig::default(),
             opensim: OpenSimConfig::default(),
             ai: AIConfig::default(),
+            simulation: SimulationConfig::default(),
+            web: WebConfig::default(),
+            registration: RegistrationConfig::default(),
+            backup: BackupConfig::default(),
+            profiler: ProfilerConfig::default(),
+            retry: RetryConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            telemetry: TelemetryConfig::default(),
+            analytics_ingest: AnalyticsIngestConfig::default(),
+            metrics_retention: MetricsRetentionConfig::default(),
+            world_events: WorldEventsConfig::default(),
+            ai_feedback: AIFeedbackConfig::default(),
+            ecosystem: EcosystemConfig::default(),
+            environment: EnvironmentConfig::default(),
+            world_generation: WorldGenerationConfig::default(),
+            quests: QuestsConfig::default(),
+            npc_chat: NpcChatConfig::default(),
+            navigation: NavigationConfig::default(),
+            population: PopulationConfig::default(),
+            behaviors: BehaviorsConfig::default(),
+            vehicles: VehiclesConfig::default(),
+            collisions: CollisionsConfig::default(),
+            raycast: RaycastConfig::default(),
+            meshes: MeshesConfig::default(),
+            economy: EconomyConfig::default(),
+            attachments: AttachmentsConfig::default(),
+            limits: LimitsConfig::default(),
+            land: LandConfig::default(),
+            returns: ReturnsConfig::default(),
+            object_items: ObjectItemsConfig::default(),
+            terraform: TerraformConfig::default(),
+            inventory: InventoryConfig::default(),
+            appearance: AppearanceConfig::default(),
+            snapshots: SnapshotsConfig::default(),
+            animations: AnimationsConfig::default(),
+            scene_control: SceneControlConfig::default(),
+            visibility: VisibilityConfig::default(),
+            gltf_export: GltfExportConfig::default(),
+            webrtc: WebRtcConfig::default(),
+            grpc: GrpcConfig::default(),
+            cluster: ClusterConfig::default(),
+            checkpoint: CheckpointConfig::default(),
+            replay: ReplayConfig::default(),
+            scripts: ScriptsConfig::default(),
+            script_http: ScriptHttpConfig::default(),
+            script_email: ScriptEmailConfig::default(),
+            script_xmlrpc: ScriptXmlRpcConfig::default(),
+            moderation: ModerationConfig::default(),
+            chat_log: ChatLogConfig::default(),
+            maturity: MaturityConfig::default(),
+            profiles: ProfilesConfig::default(),
+            directory: DirectoryConfig::default(),
+            sounds: SoundsConfig::default(),
+            names: NamesConfig::default(),
+            prim_streaming: PrimStreamingConfig::default(),
+            login_queue: LoginQueueConfig::default(),
+            maintenance: MaintenanceConfig::default(),
+            plugins: PluginsConfig::default(),
+            webhooks: WebhooksConfig::default(),
             custom: HashMap::new(),
         }
     }
@@ -676,11 +3016,392 @@ impl MutseaConfig {
         if self.database.url.is_empty() {
             errors.push("Database URL is required".to_string());
         }
+        if self.database.slow_query_log_secs == 0 {
+            errors.push("Database slow_query_log_secs must be greater than 0".to_string());
+        }
+        let sharding = &self.database.asset_sharding;
+        if sharding.shards == 0 {
+            errors.push("Database asset_sharding.shards must be at least 1".to_string());
+        }
+        match sharding.layout.as_str() {
+            "tables" => {}
+            "schemas" if self.database.url.starts_with("sqlite://") => {
+                errors.push("Database asset_sharding.layout 'schemas' requires PostgreSQL".to_string());
+            }
+            "schemas" => {}
+            other => errors.push(format!("Unknown asset_sharding.layout '{}', expected tables or schemas", other)),
+        }
+
+        // Validate simulation configuration
+        if self.simulation.tick_rate == 0 {
+            errors.push("Simulation tick rate must be greater than 0".to_string());
+        }
+        if self.simulation.max_degrade_level > SimulationConfig::MAX_DEGRADE_LEVEL {
+            errors.push(format!(
+                "Simulation max_degrade_level must be at most {}",
+                SimulationConfig::MAX_DEGRADE_LEVEL
+            ));
+        }
+
+        // Validate registration configuration
+        if self.registration.require_invite && self.registration.invite_codes.is_empty() {
+            errors.push("Registration requires invites but no invite codes are configured".to_string());
+        }
+
+        // Validate backup configuration
+        if self.backup.enabled && (self.backup.interval_minutes == 0 || self.backup.keep_last == 0) {
+            errors.push("Backup interval and keep_last must be greater than 0".to_string());
+        }
+
+        // Validate bind and advertised addresses
+        for (name, address) in [
+            ("LLUDP bind address", &self.network.lludp.bind_address),
+            ("HTTP bind address", &self.network.http.bind_address),
+        ] {
+            if crate::net::parse_ip(address).is_err() {
+                errors.push(format!("{} '{}' is not an IPv4 or IPv6 address", name, address));
+            }
+        }
+        if let Some(address) = &self.network.lludp.external_address {
+            if !matches!(crate::net::parse_ip(address), Ok(std::net::IpAddr::V4(_))) {
+                errors.push(format!("LLUDP external_address '{}' must be an IPv4 address", address));
+            }
+        }
+        if let Some(address) = &self.network.lludp.external_address_v6 {
+            if !matches!(crate::net::parse_ip(address), Ok(std::net::IpAddr::V6(_))) {
+                errors.push(format!("LLUDP external_address_v6 '{}' must be an IPv6 address", address));
+            }
+        }
+        if matches!(&self.network.lludp.external_hostname, Some(host) if host.trim().is_empty()) {
+            errors.push("LLUDP external_hostname must not be empty".to_string());
+        }
+        for server in &self.network.lludp.stun_servers {
+            if !matches!(server.rsplit_once(':'), Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok()) {
+                errors.push(format!("STUN server '{}' must be host:port", server));
+            }
+        }
+        if !self.network.lludp.stun_servers.is_empty() && self.network.lludp.stun_timeout_ms == 0 {
+            errors.push("LLUDP stun_timeout_ms must be greater than 0".to_string());
+        }
+        if self.network.lludp.workers == 0 {
+            errors.push("LLUDP workers must be at least 1".to_string());
+        }
+        if self.network.lludp.outbound_queue_kb == 0 {
+            errors.push("LLUDP outbound_queue_kb must be greater than 0".to_string());
+        }
+
+        // Validate LLUDP throttle configuration
+        let throttle = &self.network.lludp.throttle;
+        if throttle.enabled
+            && (throttle.min_kbps == 0
+                || throttle.min_kbps > throttle.max_kbps
+                || !(throttle.min_kbps..=throttle.max_kbps).contains(&throttle.initial_kbps))
+        {
+            errors.push("LLUDP throttle rates must satisfy 0 < min_kbps <= initial_kbps <= max_kbps".to_string());
+        }
+
+        // Validate profiler configuration
+        if self.profiler.enabled && self.profiler.flush_interval_secs == 0 {
+            errors.push("Profiler flush interval must be greater than 0".to_string());
+        }
+
+        // Validate retry policy
+        let retry = &self.retry;
+        if retry.max_attempts == 0 {
+            errors.push("Retry max_attempts must be at least 1".to_string());
+        }
+        if retry.multiplier < 1.0 || retry.initial_backoff_ms > retry.max_backoff_ms {
+            errors.push("Retry backoff must not shrink: multiplier >= 1 and initial_backoff_ms <= max_backoff_ms".to_string());
+        }
+        if !(0.0..=1.0).contains(&retry.jitter) {
+            errors.push("Retry jitter must be between 0.0 and 1.0".to_string());
+        }
+        if retry.budget_ratio < 0.0 || retry.budget_max_tokens < 0.0 {
+            errors.push("Retry budget_ratio and budget_max_tokens must not be negative".to_string());
+        }
+
+        // Validate circuit breakers
+        let breaker = &self.circuit_breaker;
+        if breaker.enabled && (breaker.failure_threshold == 0 || breaker.half_open_probes == 0) {
+            errors.push("Circuit breaker failure_threshold and half_open_probes must be greater than 0".to_string());
+        }
+
+        // Validate telemetry export
+        if self.telemetry.enabled && self.telemetry.otlp_endpoint.is_empty() {
+            errors.push("Telemetry otlp_endpoint is required when telemetry is enabled".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.telemetry.sample_ratio) {
+            errors.push("Telemetry sample_ratio must be between 0.0 and 1.0".to_string());
+        }
+
+        // Validate analytics buffering
+        if self.analytics_ingest.capacity == 0 || self.analytics_ingest.batch_size == 0 {
+            errors.push("Analytics ingest capacity and batch_size must be greater than 0".to_string());
+        }
+        if self.metrics_retention.enabled && self.metrics_retention.compaction_interval_secs == 0 {
+            errors.push("Metrics retention compaction_interval_secs must be greater than 0".to_string());
+        }
+        if self.world_events.enabled
+            && (self.world_events.compaction_interval_secs == 0 || self.world_events.snapshot_every == 0)
+        {
+            errors.push("World events compaction_interval_secs and snapshot_every must be greater than 0".to_string());
+        }
+        if self.ai_feedback.enabled
+            && (self.ai_feedback.evaluation_interval_secs == 0 || self.ai_feedback.batch_size == 0)
+        {
+            errors.push("AI feedback evaluation_interval_secs and batch_size must be greater than 0".to_string());
+        }
+        if self.ecosystem.enabled && (self.ecosystem.step_interval_secs == 0 || self.ecosystem.snapshot_interval_secs == 0) {
+            errors.push("Ecosystem step_interval_secs and snapshot_interval_secs must be greater than 0".to_string());
+        }
+        if self.ecosystem.time_scale <= 0.0 {
+            errors.push("Ecosystem time_scale must be greater than 0".to_string());
+        }
+        if self.environment.enabled && (self.environment.day_length_secs == 0 || self.environment.update_interval_secs == 0) {
+            errors.push("Environment day_length_secs and update_interval_secs must be greater than 0".to_string());
+        }
+        if !matches!(self.world_generation.provider.as_str(), "procedural" | "http") {
+            errors.push(format!(
+                "World generation provider must be procedural or http, got {}",
+                self.world_generation.provider
+            ));
+        }
+        if !matches!(self.quests.provider.as_str(), "procedural" | "http") {
+            errors.push(format!("Quest provider must be procedural or http, got {}", self.quests.provider));
+        }
+        if self.quests.max_active == 0 {
+            errors.push("Quest max_active must be greater than 0".to_string());
+        }
+        if !matches!(self.npc_chat.provider.as_str(), "procedural" | "http") {
+            errors.push(format!("NPC chat provider must be procedural or http, got {}", self.npc_chat.provider));
+        }
+        if self.npc_chat.hearing_range <= 0.0 {
+            errors.push("NPC chat hearing_range must be greater than 0".to_string());
+        }
+        if self.navigation.walk_speed <= 0.0 {
+            errors.push("Navigation walk_speed must be greater than 0".to_string());
+        }
+        if self.navigation.max_step <= 0.0 {
+            errors.push("Navigation max_step must be greater than 0".to_string());
+        }
+        if self.navigation.clearance < 0.0 {
+            errors.push("Navigation clearance must not be negative".to_string());
+        }
+        if self.population.density < 0.0 || self.population.parcel_density.values().any(|density| *density < 0.0) {
+            errors.push("Population densities must not be negative".to_string());
+        }
+        if self.population.ai_budget_ms <= 0.0 {
+            errors.push("Population ai_budget_ms must be greater than 0".to_string());
+        }
+        if self.population.enabled && !self.navigation.enabled {
+            errors.push("Population needs navigation to be enabled".to_string());
+        }
+        if self.raycast.max_distance <= 0.0 || self.raycast.click_range <= 0.0 {
+            errors.push("Raycast max_distance and click_range must be greater than 0".to_string());
+        }
+        if self.economy.starting_balance < 0 || self.economy.max_price <= 0 {
+            errors.push("Economy starting_balance can't be negative and max_price must be greater than 0".to_string());
+        }
+        if self.attachments.max_per_avatar == 0 {
+            errors.push("Attachments max_per_avatar must be greater than 0".to_string());
+        }
+        if self.limits.enabled && self.limits.max_prims_per_region == 0 {
+            errors.push("Limits max_prims_per_region must be greater than 0".to_string());
+        }
+        if self.land.enabled && self.land.auction_interval_secs == 0 {
+            errors.push("Land auction_interval_secs must be greater than 0".to_string());
+        }
+        if self.returns.enabled && (self.returns.sweep_interval_secs == 0 || self.returns.max_returns_per_sweep == 0) {
+            errors.push("Returns sweep_interval_secs and max_returns_per_sweep must be greater than 0".to_string());
+        }
+        if self.inventory.enabled && (self.inventory.max_folders_per_fetch == 0 || self.inventory.max_entries_per_fetch == 0) {
+            errors.push("Inventory max_folders_per_fetch and max_entries_per_fetch must be greater than 0".to_string());
+        }
+        if self.appearance.enabled && !(64..=1024).contains(&self.appearance.bake_size) {
+            errors.push("Appearance bake_size must be between 64 and 1024".to_string());
+        }
+        if self.animations.enabled && (self.animations.max_joints == 0 || self.animations.max_duration_secs <= 0.0) {
+            errors.push("Animations max_joints and max_duration_secs must be greater than 0".to_string());
+        }
+        if self.object_items.enabled && self.object_items.max_objects_per_item == 0 {
+            errors.push("Object items max_objects_per_item must be greater than 0".to_string());
+        }
+        if self.terraform.enabled {
+            if self.terraform.min_height >= self.terraform.max_height {
+                errors.push("Terraform min_height must be below max_height".to_string());
+            }
+            if self.terraform.save_interval_secs == 0 {
+                errors.push("Terraform save_interval_secs must be greater than 0".to_string());
+            }
+        }
+        if self.snapshots.enabled {
+            if self.snapshots.max_upload_bytes == 0 {
+                errors.push("Snapshots max_upload_bytes must be greater than 0".to_string());
+            }
+            if self.snapshots.upload_timeout_secs == 0 {
+                errors.push("Snapshots upload_timeout_secs must be greater than 0".to_string());
+            }
+            if !self.snapshots.gallery_url.is_empty()
+                && !self.snapshots.gallery_url.starts_with("http://")
+                && !self.snapshots.gallery_url.starts_with("https://")
+            {
+                errors.push("Snapshots gallery_url must be an http(s) URL".to_string());
+            }
+        }
+        if self.scene_control.enabled && (self.scene_control.max_prim_scale < 0.01 || self.scene_control.chat_range <= 0.0) {
+            errors.push("Scene control max_prim_scale must be at least 0.01 and chat_range above 0".to_string());
+        }
+        if self.visibility.temp_on_rez_secs == 0 {
+            errors.push("Visibility temp_on_rez_secs must be greater than 0".to_string());
+        }
+        if self.gltf_export.terrain_step == 0 {
+            errors.push("glTF export terrain_step must be greater than 0".to_string());
+        }
+        if self.webrtc.enabled && self.webrtc.max_peers == 0 {
+            errors.push("WebRTC max_peers must be greater than 0".to_string());
+        }
+        if self.grpc.enabled {
+            if self.grpc.bind_address.parse::<std::net::SocketAddr>().is_err() {
+                errors.push(format!("Invalid gRPC bind_address: {}", self.grpc.bind_address));
+            }
+            if self.grpc.cert_file.is_empty() || self.grpc.key_file.is_empty() || self.grpc.ca_file.is_empty() {
+                errors.push("gRPC needs cert_file, key_file and ca_file for mutual TLS".to_string());
+            }
+        }
+        match self.cluster.role.as_str() {
+            "standalone" => {}
+            "controller" => {
+                if !self.grpc.enabled {
+                    errors.push("A cluster controller needs grpc.enabled".to_string());
+                }
+            }
+            "region" => {
+                if self.cluster.controller_url.is_empty() {
+                    errors.push("Cluster region nodes need a controller_url".to_string());
+                }
+            }
+            role => errors.push(format!("Unknown cluster role: {}", role)),
+        }
+        if self.cluster.heartbeat_secs == 0 || self.cluster.node_timeout_secs <= self.cluster.heartbeat_secs {
+            errors.push("Cluster node_timeout_secs must be longer than a non-zero heartbeat_secs".to_string());
+        }
+        if self.checkpoint.enabled && self.checkpoint.interval_secs == 0 {
+            errors.push("Checkpoint interval_secs must be greater than 0".to_string());
+        }
+        if self.replay.enabled && (self.replay.window_secs == 0 || self.replay.max_events == 0) {
+            errors.push("Replay window_secs and max_events must be greater than 0".to_string());
+        }
+        if self.scripts.frame_budget_ms <= 0.0 || self.scripts.cpu_ms_per_sec <= 0.0 {
+            errors.push("Script frame_budget_ms and cpu_ms_per_sec must be greater than 0".to_string());
+        }
+        if self.scripts.max_queued_events == 0 || self.scripts.throttle_after_secs == 0 {
+            errors.push("Script max_queued_events and throttle_after_secs must be greater than 0".to_string());
+        }
+        if self.script_http.enabled && (self.script_http.requests_per_minute == 0 || self.script_http.timeout_secs == 0) {
+            errors.push("Script HTTP requests_per_minute and timeout_secs must be greater than 0".to_string());
+        }
+        if self.script_email.enabled && (self.script_email.domain.is_empty() || self.script_email.emails_per_hour == 0) {
+            errors.push("Script email needs a domain and emails_per_hour greater than 0".to_string());
+        }
+        if self.script_xmlrpc.enabled && self.script_xmlrpc.reply_timeout_secs == 0 {
+            errors.push("Script XML-RPC reply_timeout_secs must be greater than 0".to_string());
+        }
+        if self.moderation.enabled && self.moderation.god_level == 0 {
+            errors.push("Moderation god_level must be greater than 0".to_string());
+        }
+        if self.chat_log.enabled && (self.chat_log.retention_days == 0 || self.chat_log.purge_interval_secs == 0) {
+            errors.push("Chat log retention_days and purge_interval_secs must be greater than 0".to_string());
+        }
+        if self.profiles.enabled && self.profiles.max_picks == 0 {
+            errors.push("Profiles max_picks must be greater than 0".to_string());
+        }
+        if self.directory.enabled {
+            if self.directory.max_classifieds == 0 || self.directory.max_events == 0 {
+                errors.push("Directory max_classifieds and max_events must be greater than 0".to_string());
+            }
+            if self.directory.listing_days == 0 {
+                errors.push("Directory listing_days must be greater than 0".to_string());
+            }
+            if self.directory.min_listing_price < 0 || self.directory.event_fee < 0 {
+                errors.push("Directory prices can't be negative".to_string());
+            }
+            if self.directory.expiry_interval_secs == 0 {
+                errors.push("Directory expiry_interval_secs must be greater than 0".to_string());
+            }
+        }
+
+        if self.sounds.enabled {
+            if self.sounds.hearing_distance <= 0.0 {
+                errors.push("Sounds hearing_distance must be greater than 0".to_string());
+            }
+            if !(0.0..=self.sounds.hearing_distance).contains(&self.sounds.full_gain_distance) {
+                errors.push("Sounds full_gain_distance must be between 0 and hearing_distance".to_string());
+            }
+            if self.sounds.max_upload_bytes == 0 || self.sounds.max_duration_secs <= 0.0 {
+                errors.push("Sounds max_upload_bytes and max_duration_secs must be greater than 0".to_string());
+            }
+        }
+
+        if self.names.enabled {
+            if self.names.max_display_name_length == 0 {
+                errors.push("Names max_display_name_length must be greater than 0".to_string());
+            }
+            if self.names.max_picker_results == 0 {
+                errors.push("Names max_picker_results must be greater than 0".to_string());
+            }
+        }
+
+        if self.prim_streaming.enabled {
+            if self.prim_streaming.prims_per_tick == 0 {
+                errors.push("Prim streaming prims_per_tick must be greater than 0".to_string());
+            }
+            if self.prim_streaming.near_radius < 0.0 || self.prim_streaming.small_prim_size < 0.0 {
+                errors.push("Prim streaming near_radius and small_prim_size can't be negative".to_string());
+            }
+        }
+
+        if self.login_queue.enabled {
+            if self.login_queue.max_concurrent == 0 || self.login_queue.max_per_second == 0 {
+                errors.push("Login queue max_concurrent and max_per_second must be greater than 0".to_string());
+            }
+            if self.login_queue.arrival_timeout_secs == 0 || self.login_queue.queue_timeout_secs == 0 {
+                errors.push("Login queue timeouts must be greater than 0".to_string());
+            }
+        }
+
+        if self.maintenance.message.trim().is_empty() {
+            errors.push("Maintenance message can't be empty".to_string());
+        }
+
+        if self.plugins.enabled {
+            for library in &self.plugins.libraries {
+                if !library.is_file() {
+                    errors.push(format!("Plugin library {} does not exist", library.display()));
+                }
+            }
+        }
+
+        if self.webhooks.enabled {
+            if self.webhooks.max_attempts == 0 || self.webhooks.timeout_secs == 0 {
+                errors.push("Webhooks max_attempts and timeout_secs must be greater than 0".to_string());
+            }
+            if self.webhooks.initial_backoff_secs > self.webhooks.max_backoff_secs {
+                errors.push("Webhooks initial_backoff_secs can't exceed max_backoff_secs".to_string());
+            }
+            for endpoint in &self.webhooks.endpoints {
+                if !endpoint.url.starts_with("http://") && !endpoint.url.starts_with("https://") {
+                    errors.push(format!("Webhook URL {} must be http or https", endpoint.url));
+                }
+            }
+        }
 
         // Validate security configuration
         if self.security.enable_auth && self.security.jwt_secret == "change-me-in-production" {
            // errors.push("JWT secret must be changed in production".to_string());
         }
+        if self.security.idempotency_ttl_secs == 0 {
+            errors.push("Security idempotency_ttl_secs must be greater than 0".to_string());
+        }
 
         // Validate asset configuration
         match self.assets.backend.as_str() {
@@ -739,6 +3460,12 @@ mod tests {
 
         config.server.port = 0;
         assert!(config.validate().is_err());
+
+        let mut config = MutseaConfig::default();
+        config.simulation.max_degrade_level = SimulationConfig::MAX_DEGRADE_LEVEL;
+        assert!(config.validate().is_ok());
+        config.simulation.max_degrade_level = 64;
+        assert!(config.validate().is_err());
     }
 
     #[test]
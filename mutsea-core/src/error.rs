@@ -1,7 +1,83 @@
 //! Error types for Mutsea
+//!
+//! Every error type in the workspace implements [`ErrorClassification`],
+//! giving it a stable machine-readable code and a category that says
+//! whether retrying can help. [`ErrorReport`] is the serialized form used by
+//! the admin API and structured logs.
 
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use thiserror::Error;
 
+/// Whether an operation that failed may succeed if repeated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// Transient: connection loss, timeouts, rate limits
+    Retryable,
+    /// Retrying the same request fails the same way
+    Permanent,
+}
+
+/// Stable code and category for an error. Codes are part of the admin API
+/// and must not change once published.
+pub trait ErrorClassification: fmt::Display {
+    /// Stable machine-readable code, e.g. `DB_CONNECTION`
+    fn code(&self) -> &'static str;
+
+    /// Whether retrying may help
+    fn category(&self) -> ErrorCategory;
+
+    /// Shorthand for `category() == ErrorCategory::Retryable`
+    fn is_retryable(&self) -> bool {
+        self.category() == ErrorCategory::Retryable
+    }
+
+    /// Context attached on the way up, outermost first
+    fn context_chain(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Message of the underlying error, without the context chain
+    fn root_message(&self) -> String {
+        self.to_string()
+    }
+
+    /// Machine-readable form for APIs and logs
+    fn report(&self) -> ErrorReport {
+        ErrorReport {
+            code: self.code().to_string(),
+            category: self.category(),
+            message: self.root_message(),
+            context: self.context_chain(),
+        }
+    }
+}
+
+/// Serialized error for the admin API and structured logs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorReport {
+    /// Stable code such as `IO` or `DATABASE`
+    pub code: String,
+    /// Whether retrying may help
+    pub category: ErrorCategory,
+    /// Message of the innermost error
+    pub message: String,
+    /// What was being done when the error occurred, outermost first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<String>,
+}
+
+impl fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] ", self.code)?;
+        for context in &self.context {
+            write!(f, "{}: ", context)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
 /// Main error type for Mutsea operations
 #[derive(Error, Debug)]
 pub enum MutseaError {
@@ -52,12 +128,123 @@ pub enum MutseaError {
     /// Generic error with message
     #[error("{0}")]
     Generic(String),
+
+    /// Error from another layer, keeping its code and category
+    #[error("{message}")]
+    Classified {
+        /// Code of the original error
+        code: &'static str,
+        /// Category of the original error
+        category: ErrorCategory,
+        /// Message of the original error
+        message: String,
+    },
+
+    /// Error annotated with what was being done
+    #[error("{context}: {source}")]
+    Context {
+        /// What was being done
+        context: String,
+        /// The error itself
+        #[source]
+        source: Box<MutseaError>,
+    },
+}
+
+impl MutseaError {
+    /// Convert an error from another crate without losing its code
+    pub fn classified<E: ErrorClassification + ?Sized>(error: &E) -> Self {
+        MutseaError::Classified {
+            code: error.code(),
+            category: error.category(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Wrap this error with a description of the failed operation
+    pub fn context(self, context: impl Into<String>) -> Self {
+        MutseaError::Context {
+            context: context.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// The error underneath any context
+    pub fn root(&self) -> &MutseaError {
+        match self {
+            MutseaError::Context { source, .. } => source.root(),
+            other => other,
+        }
+    }
+}
+
+impl ErrorClassification for MutseaError {
+    fn code(&self) -> &'static str {
+        match self {
+            MutseaError::Io(_) => "IO",
+            MutseaError::Serialization(_) => "SERIALIZATION",
+            MutseaError::Database(_) => "DATABASE",
+            MutseaError::Network(_) => "NETWORK",
+            MutseaError::Authentication(_) => "AUTHENTICATION",
+            MutseaError::Authorization(_) => "AUTHORIZATION",
+            MutseaError::AssetNotFound(_) => "ASSET_NOT_FOUND",
+            MutseaError::UserNotFound(_) => "USER_NOT_FOUND",
+            MutseaError::RegionNotFound(_) => "REGION_NOT_FOUND",
+            MutseaError::InvalidConfiguration(_) => "INVALID_CONFIGURATION",
+            MutseaError::Protocol(_) => "PROTOCOL",
+            MutseaError::Generic(_) => "INTERNAL",
+            MutseaError::Classified { code, .. } => code,
+            MutseaError::Context { source, .. } => source.code(),
+        }
+    }
+
+    fn category(&self) -> ErrorCategory {
+        match self {
+            MutseaError::Io(_) | MutseaError::Database(_) | MutseaError::Network(_) => ErrorCategory::Retryable,
+            MutseaError::Classified { category, .. } => *category,
+            MutseaError::Context { source, .. } => source.category(),
+            _ => ErrorCategory::Permanent,
+        }
+    }
+
+    fn context_chain(&self) -> Vec<String> {
+        let mut chain = Vec::new();
+        let mut error = self;
+        while let MutseaError::Context { context, source } = error {
+            chain.push(context.clone());
+            error = source;
+        }
+        chain
+    }
+
+    fn root_message(&self) -> String {
+        self.root().to_string()
+    }
+}
+
+/// Attach context to the error of a result
+pub trait ResultExt<T> {
+    /// Describe the operation that failed
+    fn context(self, context: impl Into<String>) -> MutseaResult<T>;
+
+    /// Like [`ResultExt::context`], building the description only on error
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> MutseaResult<T>;
+}
+
+impl<T, E: Into<MutseaError>> ResultExt<T> for Result<T, E> {
+    fn context(self, context: impl Into<String>) -> MutseaResult<T> {
+        self.map_err(|e| e.into().context(context))
+    }
+
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> MutseaResult<T> {
+        self.map_err(|e| e.into().context(context()))
+    }
 }
 
 // Add conversion from NetworkError
 impl From<crate::NetworkError> for MutseaError {
     fn from(err: crate::NetworkError) -> Self {
-        MutseaError::Network(err.to_string())
+        MutseaError::classified(&err)
     }
 }
 
@@ -120,5 +307,62 @@ pub enum NetworkError {
     Generic(String),
 }
 
+impl ErrorClassification for NetworkError {
+    fn code(&self) -> &'static str {
+        match self {
+            NetworkError::Io(_) => "NET_IO",
+            NetworkError::InvalidAddress(_) => "NET_INVALID_ADDRESS",
+            NetworkError::Protocol(_) => "NET_PROTOCOL",
+            NetworkError::InvalidPacket(_) => "NET_INVALID_PACKET",
+            NetworkError::ClientNotFound(_) => "NET_CLIENT_NOT_FOUND",
+            NetworkError::Session(_) => "NET_SESSION",
+            NetworkError::Timeout(_) => "NET_TIMEOUT",
+            NetworkError::RateLimitExceeded => "NET_RATE_LIMITED",
+            NetworkError::AuthenticationFailed(_) => "NET_AUTHENTICATION",
+            NetworkError::AuthorizationFailed(_) => "NET_AUTHORIZATION",
+            NetworkError::Serialization(_) => "NET_SERIALIZATION",
+            NetworkError::Compression(_) => "NET_COMPRESSION",
+            NetworkError::Generic(_) => "NET_INTERNAL",
+        }
+    }
+
+    fn category(&self) -> ErrorCategory {
+        match self {
+            NetworkError::Io(_) | NetworkError::Timeout(_) | NetworkError::RateLimitExceeded => ErrorCategory::Retryable,
+            _ => ErrorCategory::Permanent,
+        }
+    }
+}
+
 /// Result type for network operations
-pub type NetworkResult<T> = Result<T, NetworkError>;
\ No newline at end of file
+pub type NetworkResult<T> = Result<T, NetworkError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_chain_keeps_root_code() {
+        let result: Result<(), NetworkError> = Err(NetworkError::Timeout("no reply".to_string()));
+        let error = result.context("sending circuit ack").context("closing session").unwrap_err();
+
+        assert_eq!(error.code(), "NET_TIMEOUT");
+        assert!(error.is_retryable());
+        assert_eq!(error.context_chain(), vec!["closing session", "sending circuit ack"]);
+        assert_eq!(error.to_string(), "closing session: sending circuit ack: Timeout: no reply");
+
+        let report = error.report();
+        assert_eq!(report.message, "Timeout: no reply");
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["category"], "retryable");
+        assert_eq!(json["context"][0], "closing session");
+    }
+
+    #[test]
+    fn test_permanent_errors_have_no_context() {
+        let report = MutseaError::UserNotFound("Test User".to_string()).report();
+        assert_eq!(report.code, "USER_NOT_FOUND");
+        assert_eq!(report.category, ErrorCategory::Permanent);
+        assert!(serde_json::to_value(&report).unwrap().get("context").is_none());
+    }
+}
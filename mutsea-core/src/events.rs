@@ -159,6 +159,33 @@ pub enum RegionEventData {
         object_id: ObjectId,
         remover_id: UserId,
     },
+    /// A species population grew or shrank sharply in one ecosystem step
+    PopulationShift {
+        /// Species name
+        species: String,
+        /// Population before the step
+        previous: f64,
+        /// Population after the step
+        population: f64,
+    },
+    /// A natural resource fell below its depletion threshold
+    ResourceDepleted {
+        /// Resource name, e.g. `water`
+        resource: String,
+        /// Level after the step, 0.0 to 1.0
+        level: f64,
+    },
+    /// The region's weather changed condition
+    WeatherChanged {
+        /// New condition, e.g. `rain` or `storm`
+        condition: String,
+        /// Air temperature in degrees Celsius
+        temperature_c: f32,
+        /// 0.0 (dry) to 1.0 (downpour)
+        precipitation: f32,
+        /// Metres per second, east and north
+        wind: (f32, f32),
+    },
 }
 
 /// Object-related events
@@ -205,6 +232,49 @@ pub enum ObjectEventData {
         event_name: String,
         parameters: HashMap<String, String>,
     },
+    /// Something began touching the object: `collision_start`, or
+    /// `land_collision_start` for the ground
+    CollisionStart {
+        /// What touched it
+        collider: Collider,
+        /// Where the contact happened, in region coordinates
+        position: Vector3,
+    },
+    /// Something stopped touching the object: `collision_end`, or
+    /// `land_collision_end` for the ground
+    CollisionEnd {
+        /// What stopped touching it
+        collider: Collider,
+    },
+    /// An avatar paid the object: `money`
+    Money {
+        /// Avatar who paid
+        payer_id: UserId,
+        /// Amount paid
+        amount: i32,
+    },
+    /// The object was taken out of the region and put back in its owner's
+    /// inventory
+    Returned {
+        /// Owner the object went back to
+        owner_id: UserId,
+        /// Who returned it, or None when the parcel returned it
+        /// automatically
+        returner_id: Option<UserId>,
+        /// The parcel it was on
+        parcel_id: Option<uuid::Uuid>,
+    },
+}
+
+/// What an object collided with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Collider {
+    /// Another prim
+    Object(ObjectId),
+    /// An avatar
+    Avatar(UserId),
+    /// The ground
+    Land,
 }
 
 /// Asset-related events
@@ -280,6 +350,623 @@ pub enum NetworkEventData {
         error_message: String,
         client_ip: String,
     },
+    /// A circuit moved to a new client address, such as after a network change
+    CircuitMigrated {
+        /// Circuit that moved
+        circuit_code: u32,
+        /// Agent on the circuit
+        agent_id: UserId,
+        /// Client address before the move
+        from: String,
+        /// Client address after the move
+        to: String,
+    },
+    /// A UseCircuitCode from a new address claimed a circuit but was refused
+    CircuitMigrationRejected {
+        /// Circuit the UseCircuitCode claimed
+        circuit_code: u32,
+        /// Agent the UseCircuitCode claimed to be
+        agent_id: UserId,
+        /// Client address the circuit stays on
+        from: String,
+        /// Address the UseCircuitCode came from
+        to: String,
+        /// Why the move was refused
+        reason: String,
+    },
+    /// An agent's circuit was authenticated
+    AgentLoggedIn {
+        /// Circuit that was authenticated
+        circuit_code: u32,
+        /// Agent on the circuit
+        agent_id: UserId,
+        /// Session issued at login
+        session_id: uuid::Uuid,
+    },
+    /// An agent said something in local chat
+    AgentChatted {
+        /// Circuit the chat arrived on
+        circuit_code: u32,
+        /// Agent speaking
+        agent_id: UserId,
+        /// Chat channel, 0 for public chat
+        channel: i32,
+        /// What was said
+        message: String,
+    },
+    /// An agent pressed a button on a script dialog
+    ScriptDialogReplied {
+        /// Circuit the reply arrived on
+        circuit_code: u32,
+        /// Agent pressing the button
+        agent_id: UserId,
+        /// The dialog's object ID
+        object_id: uuid::Uuid,
+        /// Channel the reply is said on
+        channel: i32,
+        /// Position of the button, from 0
+        button_index: i32,
+        /// Text of the button
+        button_label: String,
+    },
+    /// An agent put a prim up for sale, or took it off sale with sale
+    /// type 0
+    ObjectSaleInfoSet {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent setting the sale
+        agent_id: UserId,
+        /// Prim put up for sale
+        local_id: u32,
+        /// Sale type, see `SaleType`
+        sale_type: u8,
+        /// Asking price
+        price: i32,
+    },
+    /// An agent asked to buy a prim at the sale type and price it was shown
+    ObjectBuyRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Buying agent
+        agent_id: UserId,
+        /// Prim bought
+        local_id: u32,
+        /// Sale type the buyer was shown
+        sale_type: u8,
+        /// Price the buyer was shown
+        price: i32,
+    },
+    /// An agent applied a land tool to the terrain
+    LandModified {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent editing the terrain
+        agent_id: UserId,
+        /// Land tool, see `TerrainAction`
+        action: u8,
+        /// Brush radius in metres
+        brush_size: f32,
+        /// How long the brush was applied
+        seconds: f32,
+        /// Height to flatten to
+        height: f32,
+        /// `(west, south, east, north)` of each area the brush was applied
+        /// to, in region metres
+        areas: Vec<(f32, f32, f32, f32)>,
+    },
+    /// An agent asked to undo its last terrain edit
+    LandUndoRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent undoing its edit
+        agent_id: UserId,
+    },
+    /// An owner put a parcel up for sale, or took it off sale
+    ParcelSaleSet {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Owner setting the sale
+        agent_id: UserId,
+        /// Parcel put up for sale
+        local_id: i32,
+        /// Put up for sale if set, taken off sale otherwise
+        for_sale: bool,
+        /// Asking price
+        price: i32,
+        /// The only agent allowed to buy the parcel
+        auth_buyer_id: Option<uuid::Uuid>,
+        /// The owner's prims on the parcel are sold with it
+        include_objects: bool,
+    },
+    /// An agent asked to buy a parcel at the price and area it was shown
+    ParcelBuyRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Buying agent
+        agent_id: UserId,
+        /// Parcel bought
+        local_id: i32,
+        /// Price the buyer was shown
+        price: i32,
+        /// Area in square metres the buyer was shown
+        area: i32,
+    },
+    /// An agent asked to return prims on a parcel to their owners
+    ParcelObjectsReturnRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent returning the prims
+        agent_id: UserId,
+        /// Parcel the prims are on
+        local_id: i32,
+        /// ParcelReturnObjects return type flags
+        return_type: u32,
+        /// Prims to return, whatever their owner
+        task_ids: Vec<uuid::Uuid>,
+        /// Owners whose listed prims to return
+        owner_ids: Vec<UserId>,
+    },
+    /// An agent set how many minutes other people's prims may stay on a
+    /// parcel, 0 for ever
+    ParcelCleanTimeSet {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent changing the setting
+        agent_id: UserId,
+        /// Parcel changed
+        local_id: i32,
+        /// Minutes other people's prims may stay, 0 to never return them
+        minutes: i32,
+    },
+    /// An estate manager asked to return an agent's prims across the region
+    EstateObjectsReturnRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Estate manager asking
+        agent_id: UserId,
+        /// Agent whose prims to return
+        target_id: UserId,
+        /// `estateobjectreturn` flags
+        flags: u32,
+    },
+    /// An agent asked to pay an avatar or a prim
+    MoneyTransferRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Paying agent
+        agent_id: UserId,
+        /// The avatar or prim paid
+        destination: uuid::Uuid,
+        /// Amount to pay
+        amount: i32,
+        /// Viewer's transaction type, such as `TRANSACTION_PAY_OBJECT`
+        transaction_type: i32,
+        /// Description shown in the transaction history
+        description: String,
+    },
+    /// An agent's viewer asked for its balance
+    MoneyBalanceRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent asking
+        agent_id: UserId,
+        /// Echoed back in the balance reply
+        transaction_id: uuid::Uuid,
+    },
+    /// An agent asked to wear an inventory item at an attachment point,
+    /// 0 for where it was last worn
+    AttachmentRezRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent wearing the item
+        agent_id: UserId,
+        /// Inventory item to wear
+        item_id: uuid::Uuid,
+        /// Point to wear it on
+        attachment_point: u8,
+        /// Item name, given to the rezzed prim
+        name: String,
+    },
+    /// An agent asked to wear a prim in the region
+    ObjectAttachRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent wearing the prim
+        agent_id: UserId,
+        /// Prim to wear
+        local_id: u32,
+        /// Point to wear it on, 0 for where it was last worn
+        attachment_point: u8,
+        /// Rotation on the attachment point
+        rotation: Quaternion,
+    },
+    /// An agent asked to take off a worn prim
+    ObjectDetachRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent taking the prim off
+        agent_id: UserId,
+        /// Worn prim to take off
+        local_id: u32,
+    },
+    /// An agent asked to take off the prim worn from an inventory item
+    AttachmentDetachRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent taking the item off
+        agent_id: UserId,
+        /// Inventory item the worn prim was rezzed from
+        item_id: uuid::Uuid,
+    },
+    /// An agent asked to take prims into inventory, or one packet of them
+    ObjectsDerezRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent taking the prims
+        agent_id: UserId,
+        /// DeRezObject destination, such as take, take copy or delete
+        destination: u8,
+        /// Inventory folder to put the item in, nil for the default one
+        folder_id: uuid::Uuid,
+        /// Shared by the packets of one selection
+        transaction_id: uuid::Uuid,
+        /// Number of packets the selection was split into
+        packet_count: u8,
+        /// Which of them this is, from 0
+        packet_number: u8,
+        /// Prims in this packet
+        local_ids: Vec<u32>,
+    },
+    /// An agent asked to rez an object item from its inventory
+    ObjectRezRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent rezzing the item
+        agent_id: UserId,
+        /// Object item to rez
+        item_id: uuid::Uuid,
+        /// Where the agent's ray hit the ground or a prim
+        position: Vector3,
+        /// Whether to take the item out of inventory
+        remove_item: bool,
+    },
+    /// A moderator asked to kick an agent from the region
+    AgentKickRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Moderator asking
+        agent_id: UserId,
+        /// Agent to kick
+        target_id: UserId,
+        /// Message shown to the kicked agent
+        reason: String,
+        /// Asked with god powers rather than as an estate manager
+        godlike: bool,
+    },
+    /// A moderator asked to freeze or unfreeze an agent
+    AgentFreezeRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Moderator asking
+        agent_id: UserId,
+        /// Agent to freeze or unfreeze
+        target_id: UserId,
+        /// Freeze if set, unfreeze otherwise
+        frozen: bool,
+        /// Asked with god powers rather than as an estate manager
+        godlike: bool,
+    },
+    /// An agent asked to take up or give up its god powers
+    GodlikePowersRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent asking
+        agent_id: UserId,
+        /// Agent's session
+        session_id: uuid::Uuid,
+        /// Take up god powers if set, give them up otherwise
+        godlike: bool,
+        /// Echoed back in the grant
+        token: uuid::Uuid,
+    },
+    /// A resident filed an abuse report
+    AbuseReported {
+        /// Circuit the report arrived on
+        circuit_code: u32,
+        /// Reporting agent
+        agent_id: UserId,
+        /// Resident reported, nil if none was named
+        abuser_id: UserId,
+        /// Object reported, nil if none was named
+        object_id: uuid::Uuid,
+        /// Viewer's abuse category
+        category: u8,
+        /// Where the reporter stood, in region coordinates
+        position: Vector3,
+        /// One-line summary
+        summary: String,
+        /// Full description
+        details: String,
+        /// Uploaded screenshot asset, nil if none
+        screenshot_id: uuid::Uuid,
+    },
+    /// An agent searched for places by name
+    PlacesSearched {
+        /// Circuit the search arrived on
+        circuit_code: u32,
+        /// Agent searching
+        agent_id: UserId,
+        /// ID the viewer matches the reply to
+        query_id: uuid::Uuid,
+        /// Text searched for
+        text: String,
+        /// DirPlacesQuery flags, naming the content ratings to include
+        flags: u32,
+        /// Index of the first result wanted
+        start: i32,
+    },
+    /// An agent opened the profile of an avatar
+    AvatarPropertiesRequested {
+        /// Circuit the request came on
+        circuit_code: u32,
+        /// Agent asking
+        agent_id: UserId,
+        /// Avatar whose profile is wanted
+        avatar_id: UserId,
+    },
+    /// An agent saved its own profile
+    AvatarPropertiesUpdated {
+        /// Circuit the update came on
+        circuit_code: u32,
+        /// Agent saving its profile
+        agent_id: UserId,
+        /// Profile picture
+        image_id: uuid::Uuid,
+        /// The picture on the First Life tab
+        fl_image_id: uuid::Uuid,
+        /// The About text on the main tab
+        about_text: String,
+        /// The text on the First Life tab
+        fl_about_text: String,
+        /// Web profile address
+        profile_url: String,
+        /// Whether the profile shows in search
+        allow_publish: bool,
+        /// Whether the profile has mature content
+        mature_publish: bool,
+    },
+    /// An agent asked for the list of an avatar's picks
+    AvatarPicksRequested {
+        /// Circuit the request came on
+        circuit_code: u32,
+        /// Agent asking
+        agent_id: UserId,
+        /// Avatar whose picks are wanted
+        target_id: UserId,
+    },
+    /// An agent opened one of an avatar's picks
+    PickInfoRequested {
+        /// Circuit the request came on
+        circuit_code: u32,
+        /// Agent asking
+        agent_id: UserId,
+        /// Avatar that made the pick
+        creator_id: UserId,
+        /// Pick wanted
+        pick_id: uuid::Uuid,
+    },
+    /// An agent saved one of its picks
+    PickUpdated {
+        /// Circuit the update came on
+        circuit_code: u32,
+        /// Agent saving the pick
+        agent_id: UserId,
+        /// Pick identifier
+        pick_id: uuid::Uuid,
+        /// Whether the pick is an editor's choice
+        top_pick: bool,
+        /// Parcel picked
+        parcel_id: uuid::Uuid,
+        /// Title
+        name: String,
+        /// Description
+        description: String,
+        /// Texture shown with the pick
+        snapshot_id: uuid::Uuid,
+        /// Global position, in metres from the grid's origin
+        pos_global: [f64; 3],
+        /// Position among the agent's picks
+        sort_order: i32,
+        /// Whether the pick is shown
+        enabled: bool,
+    },
+    /// An agent removed one of its picks
+    PickDeleted {
+        /// Circuit the request came on
+        circuit_code: u32,
+        /// Agent removing the pick
+        agent_id: UserId,
+        /// Pick to remove
+        pick_id: uuid::Uuid,
+    },
+    /// An agent asked for a landmark to where it stands
+    LandmarkRequested {
+        /// Circuit the request came on
+        circuit_code: u32,
+        /// Agent asking
+        agent_id: UserId,
+        /// The inventory folder to put it in
+        folder_id: uuid::Uuid,
+        /// Name of the landmark
+        name: String,
+    },
+    /// An agent searched the classifieds
+    ClassifiedsSearched {
+        /// Circuit the search came on
+        circuit_code: u32,
+        /// Agent searching
+        agent_id: UserId,
+        /// Identifier the reply must carry
+        query_id: uuid::Uuid,
+        /// Search text
+        text: String,
+        /// DirClassifiedQuery flags, naming the content ratings to include
+        flags: u32,
+        /// 0 for any category
+        category: u32,
+        /// Index of the first result wanted
+        start: i32,
+    },
+    /// An agent searched the events
+    EventsSearched {
+        /// Circuit the search came on
+        circuit_code: u32,
+        /// Agent searching
+        agent_id: UserId,
+        /// Identifier the reply must carry
+        query_id: uuid::Uuid,
+        /// The viewer's `day|category|text` query
+        text: String,
+        /// DirFindQuery flags, naming the content ratings to include
+        flags: u32,
+        /// Index of the first result wanted
+        start: i32,
+    },
+    /// An agent asked for the list of an avatar's classifieds
+    AvatarClassifiedsRequested {
+        /// Circuit the request came on
+        circuit_code: u32,
+        /// Agent asking
+        agent_id: UserId,
+        /// Avatar whose classifieds are wanted
+        target_id: UserId,
+    },
+    /// An agent opened a classified
+    ClassifiedInfoRequested {
+        /// Circuit the request came on
+        circuit_code: u32,
+        /// Agent asking
+        agent_id: UserId,
+        /// Classified wanted
+        classified_id: uuid::Uuid,
+    },
+    /// An agent placed or edited one of its classifieds
+    ClassifiedUpdated {
+        /// Circuit the update came on
+        circuit_code: u32,
+        /// Agent placing the classified
+        agent_id: UserId,
+        /// Classified identifier
+        classified_id: uuid::Uuid,
+        /// Classified category
+        category: u32,
+        /// Title
+        name: String,
+        /// Body text
+        description: String,
+        /// Parcel the classified points to
+        parcel_id: uuid::Uuid,
+        /// Estate of that parcel
+        parent_estate: u32,
+        /// Texture shown with the classified
+        snapshot_id: uuid::Uuid,
+        /// Global position, in metres from the grid's origin
+        pos_global: [f64; 3],
+        /// ClassifiedFlags bits
+        flags: u8,
+        /// L$ offered to list the classified
+        price_for_listing: i32,
+    },
+    /// An agent removed one of its classifieds
+    ClassifiedDeleted {
+        /// Circuit the request came on
+        circuit_code: u32,
+        /// Agent removing the classified
+        agent_id: UserId,
+        /// Classified to remove
+        classified_id: uuid::Uuid,
+    },
+    /// An agent opened an event
+    EventInfoRequested {
+        /// Circuit the request came on
+        circuit_code: u32,
+        /// Agent asking
+        agent_id: UserId,
+        /// Event wanted
+        event_id: u32,
+    },
+    /// An agent played a sound, as gestures do, for everyone in earshot
+    SoundTriggered {
+        /// Circuit the sound arrived on
+        circuit_code: u32,
+        /// Agent playing it
+        agent_id: UserId,
+        /// Sound asset
+        sound_id: uuid::Uuid,
+        /// The prim or avatar playing it
+        object_id: uuid::Uuid,
+        /// The root prim of `object_id`, or nil
+        parent_id: uuid::Uuid,
+        /// Where it plays, in region coordinates
+        position: Vector3,
+        /// Volume, 0 to 1
+        gain: f32,
+    },
+    /// An agent searched for avatars by name in the avatar picker
+    AvatarPickerRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent searching
+        agent_id: UserId,
+        /// Echoed back in the reply
+        query_id: uuid::Uuid,
+        /// Name or part of one to look for
+        name: String,
+    },
+    /// An agent's viewer asked for full updates of prims it was told it
+    /// had cached but didn't
+    ObjectsRequested {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent asking
+        agent_id: UserId,
+        /// Region-local IDs of the prims wanted
+        local_ids: Vec<u32>,
+    },
+    /// An agent made where it stands its home
+    HomeSet {
+        /// Circuit the request arrived on
+        circuit_code: u32,
+        /// Agent setting its home
+        agent_id: UserId,
+        /// Region the agent is in, if known
+        region_id: Option<RegionId>,
+        /// Where the agent stands, in region coordinates
+        position: Vector3,
+        /// Direction the agent faces
+        look_at: Vector3,
+    },
+    /// An agent's circuit was closed by logout or timeout, having used
+    /// the bytes given by priority class. The region, position and look
+    /// direction are where it left.
+    AgentLoggedOut {
+        /// Circuit that was closed
+        circuit_code: u32,
+        /// Agent on the circuit
+        agent_id: UserId,
+        /// What closed it: `logout`, `timeout`, `kicked` or `tunnel closed`
+        reason: String,
+        /// Bytes sent to the agent by priority class name
+        sent_by_class: HashMap<String, u64>,
+        /// Bytes received from the agent by priority class name
+        received_by_class: HashMap<String, u64>,
+        /// Region the agent left, if known
+        region_id: Option<RegionId>,
+        /// Where the agent stood, in region coordinates
+        position: Vector3,
+        /// Direction the agent faced
+        look_at: Vector3,
+    },
 }
 
 /// System-related events
@@ -536,6 +1223,21 @@ impl EventFilter {
                     | ObjectEventData::Touched {
                         toucher_id: creator_id,
                         ..
+                    }
+                    | ObjectEventData::CollisionStart {
+                        collider: Collider::Avatar(creator_id),
+                        ..
+                    }
+                    | ObjectEventData::CollisionEnd {
+                        collider: Collider::Avatar(creator_id),
+                    }
+                    | ObjectEventData::Money {
+                        payer_id: creator_id,
+                        ..
+                    }
+                    | ObjectEventData::Returned {
+                        owner_id: creator_id,
+                        ..
                     } => {
                         if !user_ids.contains(creator_id) {
                             return false;
@@ -6,10 +6,15 @@
 #![warn(missing_docs)]
 #![warn(clippy::all)]
 
+pub mod circuit_breaker;
 pub mod config;
 pub mod error;
 pub mod events;
 pub mod math;
+pub mod net;
+pub mod opensim_ini;
+pub mod retry;
+pub mod scene;
 pub mod traits;
 pub mod types;
 
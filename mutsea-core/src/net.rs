@@ -0,0 +1,136 @@
+//! Address helpers for dual-stack (IPv4 and IPv6) operation
+//!
+//! Configured addresses are plain IP literals (`0.0.0.0`, `::`, `2001:db8::1`)
+//! or host names. These helpers turn them into socket addresses and into
+//! the host part of URLs, where IPv6 literals need brackets.
+
+use std::net::{AddrParseError, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Parse a bind address, accepting IPv6 literals with or without brackets
+pub fn parse_ip(address: &str) -> Result<IpAddr, AddrParseError> {
+    let trimmed = address.trim();
+    let unbracketed = trimmed
+        .strip_prefix('[')
+        .and_then(|a| a.strip_suffix(']'))
+        .unwrap_or(trimmed);
+    unbracketed.parse()
+}
+
+/// Socket address for a configured bind address and port
+pub fn socket_addr(address: &str, port: u16) -> Result<SocketAddr, AddrParseError> {
+    Ok(SocketAddr::new(parse_ip(address)?, port))
+}
+
+/// Host as it appears in a URL: bare IPv6 literals are bracketed, names and
+/// IPv4 addresses are unchanged
+pub fn url_host(host: &str) -> String {
+    match parse_ip(host) {
+        Ok(IpAddr::V6(ip)) => format!("[{}]", ip),
+        _ => host.trim().to_string(),
+    }
+}
+
+/// `http://host:port/` for a host name or IP literal
+pub fn http_url(host: &str, port: u16) -> String {
+    format!("http://{}:{}/", url_host(host), port)
+}
+
+/// Address clients can reach for a bind address: wildcard binds are
+/// replaced by the loopback address of the same family
+pub fn reachable_ip(bind: IpAddr) -> IpAddr {
+    match bind {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    }
+}
+
+/// Whether `ip` is IPv4 or an IPv4-mapped IPv6 address, and so can be sent
+/// in the 4-byte address fields of LLUDP messages
+pub fn as_ipv4(ip: IpAddr) -> Option<Ipv4Addr> {
+    match ip {
+        IpAddr::V4(ip) => Some(ip),
+        IpAddr::V6(ip) => ip.to_ipv4_mapped(),
+    }
+}
+
+/// How far an address reaches, from the local host outwards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AddressScope {
+    /// Loopback: only the same host
+    Host,
+    /// Private, unique-local or link-local: the same site or LAN
+    Site,
+    /// Globally routable
+    Global,
+}
+
+/// Reach of an IP address; IPv4-mapped addresses are classified as IPv4
+pub fn address_scope(ip: IpAddr) -> AddressScope {
+    match as_ipv4(ip).map(IpAddr::V4).unwrap_or(ip) {
+        IpAddr::V4(ip) if ip.is_loopback() || ip.is_unspecified() => AddressScope::Host,
+        IpAddr::V4(ip) if ip.is_private() || ip.is_link_local() || is_shared_v4(ip) => AddressScope::Site,
+        IpAddr::V6(ip) if ip.is_loopback() || ip.is_unspecified() => AddressScope::Host,
+        // fc00::/7 unique-local and fe80::/10 link-local
+        IpAddr::V6(ip) if (ip.segments()[0] & 0xfe00) == 0xfc00 || (ip.segments()[0] & 0xffc0) == 0xfe80 => {
+            AddressScope::Site
+        }
+        _ => AddressScope::Global,
+    }
+}
+
+/// 100.64.0.0/10 carrier-grade NAT space
+fn is_shared_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    a == 100 && (b & 0xc0) == 64
+}
+
+/// Whether a client at `source` can plausibly reach `advertised`: loopback
+/// addresses only from the same host, private addresses only from inside
+/// the site
+pub fn is_routable_from(advertised: IpAddr, source: IpAddr) -> bool {
+    address_scope(advertised) >= address_scope(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format_both_families() {
+        assert_eq!(socket_addr("0.0.0.0", 9000).unwrap().to_string(), "0.0.0.0:9000");
+        assert_eq!(socket_addr("::", 9000).unwrap().to_string(), "[::]:9000");
+        assert_eq!(socket_addr("[2001:db8::1]", 80).unwrap().to_string(), "[2001:db8::1]:80");
+        assert!(socket_addr("example.org", 80).is_err());
+
+        assert_eq!(http_url("2001:db8::1", 8080), "http://[2001:db8::1]:8080/");
+        assert_eq!(http_url("grid.example.org", 8080), "http://grid.example.org:8080/");
+        assert_eq!(http_url("10.0.0.1", 8080), "http://10.0.0.1:8080/");
+    }
+
+    #[test]
+    fn test_reachable_and_ipv4_mapping() {
+        assert_eq!(reachable_ip(parse_ip("::").unwrap()), IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(reachable_ip(parse_ip("0.0.0.0").unwrap()), IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(as_ipv4(parse_ip("::ffff:10.1.2.3").unwrap()), Some(Ipv4Addr::new(10, 1, 2, 3)));
+        assert_eq!(as_ipv4(parse_ip("2001:db8::1").unwrap()), None);
+    }
+
+    #[test]
+    fn test_routability_by_scope() {
+        let ip = |s: &str| parse_ip(s).unwrap();
+        assert_eq!(address_scope(ip("127.0.0.1")), AddressScope::Host);
+        assert_eq!(address_scope(ip("192.168.1.5")), AddressScope::Site);
+        assert_eq!(address_scope(ip("100.64.3.1")), AddressScope::Site);
+        assert_eq!(address_scope(ip("fd00::1")), AddressScope::Site);
+        assert_eq!(address_scope(ip("::ffff:10.0.0.1")), AddressScope::Site);
+        assert_eq!(address_scope(ip("203.0.113.7")), AddressScope::Global);
+        assert_eq!(address_scope(ip("2001:db8::1")), AddressScope::Global);
+
+        assert!(is_routable_from(ip("127.0.0.1"), ip("127.0.0.1")));
+        assert!(!is_routable_from(ip("127.0.0.1"), ip("192.168.1.9")));
+        assert!(is_routable_from(ip("192.168.1.5"), ip("192.168.1.9")));
+        assert!(!is_routable_from(ip("192.168.1.5"), ip("198.51.100.2")));
+        assert!(is_routable_from(ip("203.0.113.7"), ip("198.51.100.2")));
+    }
+}
@@ -0,0 +1,336 @@
+//! Retry with exponential backoff, jitter and a retry budget
+//!
+//! Only errors classified as [`ErrorCategory::Retryable`] are retried. A
+//! shared [`RetryBudget`] caps retries to a fraction of overall traffic so
+//! an outage does not turn into a retry storm against the failing backend.
+
+use crate::config::RetryConfig;
+use crate::error::{ErrorCategory, ErrorClassification};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Attempts and backoff for one kind of operation
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts including the first, at least 1
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    /// Cap on any one delay, jitter included
+    pub max_backoff: Duration,
+    /// Factor each delay grows by over the one before
+    pub multiplier: f64,
+    /// Fraction each delay is randomised by either way, 0.0 to 1.0
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry` (1-based), without jitter. It is
+    /// capped in seconds first, as late retries can grow past what a
+    /// `Duration` holds.
+    pub fn base_backoff(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.powi(retry.saturating_sub(1) as i32);
+        let secs = self.initial_backoff.as_secs_f64() * factor;
+        Duration::try_from_secs_f64(secs.min(self.max_backoff.as_secs_f64())).unwrap_or(self.max_backoff)
+    }
+
+    /// Delay before retry number `retry`, spread by up to `jitter` either way
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let base = self.base_backoff(retry);
+        if self.jitter <= 0.0 {
+            return base;
+        }
+        let spread = 1.0 + self.jitter * (rand::random::<f64>() * 2.0 - 1.0);
+        base.mul_f64(spread).min(self.max_backoff)
+    }
+}
+
+impl From<&RetryConfig> for RetryPolicy {
+    fn from(config: &RetryConfig) -> Self {
+        Self {
+            max_attempts: config.max_attempts.max(1),
+            initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+            multiplier: config.multiplier,
+            jitter: config.jitter,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::from(&RetryConfig::default())
+    }
+}
+
+/// Token bucket limiting retries: every call deposits `ratio` tokens and
+/// every retry withdraws one
+#[derive(Debug)]
+pub struct RetryBudget {
+    ratio: f64,
+    max_tokens: f64,
+    tokens: Mutex<f64>,
+}
+
+impl RetryBudget {
+    /// Budget starting full
+    pub fn new(ratio: f64, max_tokens: f64) -> Self {
+        Self {
+            ratio,
+            max_tokens,
+            tokens: Mutex::new(max_tokens),
+        }
+    }
+
+    fn deposit(&self) {
+        let mut tokens = self.tokens.lock().unwrap();
+        *tokens = (*tokens + self.ratio).min(self.max_tokens);
+    }
+
+    fn try_withdraw(&self) -> bool {
+        let mut tokens = self.tokens.lock().unwrap();
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Retry tokens currently available
+    pub fn available(&self) -> f64 {
+        *self.tokens.lock().unwrap()
+    }
+}
+
+impl From<&RetryConfig> for RetryBudget {
+    fn from(config: &RetryConfig) -> Self {
+        Self::new(config.budget_ratio, config.budget_max_tokens)
+    }
+}
+
+/// Retry counters for one operation
+#[derive(Debug, Default)]
+pub struct RetryMetrics {
+    calls: AtomicU64,
+    retries: AtomicU64,
+    give_ups: AtomicU64,
+    budget_exhausted: AtomicU64,
+}
+
+/// Point-in-time copy of [`RetryMetrics`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct RetryStats {
+    /// Operations started
+    pub calls: u64,
+    /// Extra attempts made after a retryable failure
+    pub retries: u64,
+    /// Operations that failed after exhausting their attempts or budget
+    pub give_ups: u64,
+    /// Retries skipped because the budget was empty
+    pub budget_exhausted: u64,
+}
+
+impl RetryMetrics {
+    /// Current counter values
+    pub fn snapshot(&self) -> RetryStats {
+        RetryStats {
+            calls: self.calls.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            give_ups: self.give_ups.load(Ordering::Relaxed),
+            budget_exhausted: self.budget_exhausted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Runs operations under a retry policy and budget, counting retries and
+/// give-ups. Cheap to clone; clones share the budget and counters.
+#[derive(Debug, Clone)]
+pub struct Retrier {
+    name: &'static str,
+    policy: RetryPolicy,
+    budget: Arc<RetryBudget>,
+    metrics: Arc<RetryMetrics>,
+}
+
+impl Retrier {
+    /// Retrier for operations labelled `name` in logs and metrics
+    pub fn new(name: &'static str, policy: RetryPolicy, budget: Arc<RetryBudget>) -> Self {
+        Self {
+            name,
+            policy,
+            budget,
+            metrics: Arc::new(RetryMetrics::default()),
+        }
+    }
+
+    /// Retrier with its own budget, built from configuration
+    pub fn from_config(name: &'static str, config: &RetryConfig) -> Self {
+        Self::new(name, RetryPolicy::from(config), Arc::new(RetryBudget::from(config)))
+    }
+
+    /// Retrier that never retries
+    pub fn disabled(name: &'static str) -> Self {
+        let policy = RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        };
+        Self::new(name, policy, Arc::new(RetryBudget::new(0.0, 0.0)))
+    }
+
+    /// Retry and give-up counts so far
+    pub fn stats(&self) -> RetryStats {
+        self.metrics.snapshot()
+    }
+
+    /// Run `operation`, retrying errors classified as retryable
+    pub async fn run<T, E, F, Fut>(&self, operation: F) -> Result<T, E>
+    where
+        E: ErrorClassification,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.run_if(operation, |e: &E| e.category() == ErrorCategory::Retryable).await
+    }
+
+    /// Run `operation`, retrying errors for which `retryable` returns true
+    pub async fn run_if<T, E, F, Fut, P>(&self, mut operation: F, retryable: P) -> Result<T, E>
+    where
+        E: std::fmt::Display,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        P: Fn(&E) -> bool,
+    {
+        self.metrics.calls.fetch_add(1, Ordering::Relaxed);
+        self.budget.deposit();
+
+        let mut attempt = 1;
+        loop {
+            let error = match operation().await {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+
+            if !retryable(&error) {
+                return Err(error);
+            }
+            if attempt >= self.policy.max_attempts {
+                self.give_up(&error, attempt, "attempts exhausted");
+                return Err(error);
+            }
+            if !self.budget.try_withdraw() {
+                self.metrics.budget_exhausted.fetch_add(1, Ordering::Relaxed);
+                self.give_up(&error, attempt, "retry budget exhausted");
+                return Err(error);
+            }
+
+            let delay = self.policy.backoff(attempt);
+            debug!("{} attempt {} failed, retrying in {:?}: {}", self.name, attempt, delay, error);
+            self.metrics.retries.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "metrics")]
+            metrics::counter!("mutsea_retries_total", "operation" => self.name).increment(1);
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    fn give_up(&self, error: &dyn std::fmt::Display, attempts: u32, reason: &str) {
+        self.metrics.give_ups.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("mutsea_retry_give_ups_total", "operation" => self.name).increment(1);
+        warn!("{} failed after {} attempt(s), {}: {}", self.name, attempts, reason, error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NetworkError;
+    use std::sync::atomic::AtomicU32;
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+            multiplier: 2.0,
+            jitter: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(10), Duration::from_millis(5000));
+
+        // Far past what a Duration holds before capping
+        let steep = RetryPolicy {
+            multiplier: 10.0,
+            ..policy
+        };
+        assert_eq!(steep.backoff(100), Duration::from_millis(5000));
+
+        let jittered = RetryPolicy::default();
+        assert!((0..20).all(|_| {
+            let delay = jittered.backoff(2);
+            delay >= Duration::from_millis(160) && delay <= Duration::from_millis(240)
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_retries_only_retryable_errors() {
+        let retrier = Retrier::new("test", fast_policy(3), Arc::new(RetryBudget::new(0.0, 10.0)));
+        let calls = AtomicU32::new(0);
+
+        let result: Result<u32, NetworkError> = retrier
+            .run(|| async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(NetworkError::Timeout("slow".to_string())),
+                    n => Ok(n),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 1);
+
+        calls.store(0, Ordering::SeqCst);
+        let result: Result<(), NetworkError> = retrier
+            .run(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(NetworkError::InvalidPacket("bad".to_string()))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let stats = retrier.stats();
+        assert_eq!((stats.calls, stats.retries, stats.give_ups), (2, 1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_budget_and_attempts_limit_retries() {
+        let retrier = Retrier::new("test", fast_policy(5), Arc::new(RetryBudget::new(0.0, 2.0)));
+        let calls = AtomicU32::new(0);
+
+        let result: Result<(), NetworkError> = retrier
+            .run(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(NetworkError::Timeout("down".to_string()))
+            })
+            .await;
+        assert!(result.is_err());
+        // One attempt plus the two retries the budget allows
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let stats = retrier.stats();
+        assert_eq!((stats.retries, stats.give_ups, stats.budget_exhausted), (2, 1, 1));
+    }
+}
@@ -0,0 +1,289 @@
+//! Region scene state
+//!
+//! The persistent contents of a region: terrain heightmap, scene objects and
+//! land parcels. This is what region backups capture and restore.
+
+use crate::{Maturity, ObjectId, RegionInfo, SceneObject, UserId, Vector3};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Default terrain height in meters
+pub const DEFAULT_TERRAIN_HEIGHT: f32 = 21.0;
+
+/// Terrain heightmap, one height per square meter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Terrain {
+    /// Width in meters (X)
+    pub size_x: u32,
+    /// Depth in meters (Y)
+    pub size_y: u32,
+    /// Heights in row-major order (`y * size_x + x`)
+    pub heights: Vec<f32>,
+}
+
+impl Terrain {
+    /// Flat terrain at `height`
+    pub fn flat(size_x: u32, size_y: u32, height: f32) -> Self {
+        Self {
+            size_x,
+            size_y,
+            heights: vec![height; (size_x * size_y) as usize],
+        }
+    }
+
+    /// Height at integer coordinates, clamped to the region edge
+    pub fn height_at(&self, x: u32, y: u32) -> f32 {
+        let x = x.min(self.size_x.saturating_sub(1));
+        let y = y.min(self.size_y.saturating_sub(1));
+        self.heights[(y * self.size_x + x) as usize]
+    }
+
+    /// Set the height at integer coordinates; out-of-range writes are ignored
+    pub fn set_height(&mut self, x: u32, y: u32, height: f32) {
+        if x < self.size_x && y < self.size_y {
+            self.heights[(y * self.size_x + x) as usize] = height;
+        }
+    }
+
+    /// The heights in OpenSim's `.r32` layout: little-endian `f32`s, row by
+    /// row from the south-west corner
+    pub fn to_r32(&self) -> Vec<u8> {
+        self.heights.iter().flat_map(|h| h.to_le_bytes()).collect()
+    }
+
+    /// Terrain of `size_x` by `size_y` from `.r32` bytes, or None if there
+    /// aren't as many heights as that
+    pub fn from_r32(data: &[u8], size_x: u32, size_y: u32) -> Option<Self> {
+        if data.len() != (size_x * size_y) as usize * 4 {
+            return None;
+        }
+        Some(Self {
+            size_x,
+            size_y,
+            heights: data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        })
+    }
+}
+
+/// A land parcel
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Parcel {
+    /// Global parcel ID
+    pub id: Uuid,
+    /// Region-local parcel ID used by the viewer
+    pub local_id: i32,
+    /// Parcel name
+    pub name: String,
+    /// Parcel description
+    pub description: String,
+    /// Owning user
+    pub owner_id: UserId,
+    /// Owning group, if group-owned
+    pub group_id: Option<Uuid>,
+    /// Area in square meters
+    pub area: u32,
+    /// Parcel flags (ParcelFlags bitfield)
+    pub flags: u32,
+    /// 64x64 ownership bitmap, one bit per 4x4 meter cell
+    pub bitmap: Vec<u8>,
+    /// Content rating, or None to follow the region's
+    #[serde(default)]
+    pub maturity: Option<Maturity>,
+    /// Minutes other people's prims may stay before they are returned to
+    /// their owners, 0 for ever
+    #[serde(default)]
+    pub other_clean_time: i32,
+}
+
+impl Parcel {
+    /// A parcel covering a whole region of the given size
+    pub fn whole_region(owner_id: UserId, size_x: u32, size_y: u32) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            local_id: 1,
+            name: "Your Parcel".to_string(),
+            description: String::new(),
+            owner_id,
+            group_id: None,
+            area: size_x * size_y,
+            flags: 0,
+            bitmap: vec![0xFF; 512],
+            maturity: None,
+            other_clean_time: 0,
+        }
+    }
+
+    /// Content rating of the parcel in a region rated `region`; a parcel
+    /// can't be rated above its region
+    pub fn effective_maturity(&self, region: Maturity) -> Maturity {
+        self.maturity.map_or(region, |maturity| maturity.min(region))
+    }
+
+    /// Whether the parcel covers the region-local point `x`, `y`
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        let cell_x = (x / 4.0).clamp(0.0, 63.0) as usize;
+        let cell_y = (y / 4.0).clamp(0.0, 63.0) as usize;
+        let bit = cell_y * 64 + cell_x;
+        self.bitmap
+            .get(bit / 8)
+            .is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+    }
+}
+
+/// Which objects to look for in a scene; criteria left unset match every
+/// object
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectQuery {
+    /// Part of the name, in any case
+    pub name: Option<String>,
+    /// The object's owner
+    pub owner_id: Option<UserId>,
+    /// Opposite corners of the box the object's position lies in
+    pub area: Option<(Vector3, Vector3)>,
+}
+
+impl ObjectQuery {
+    /// Whether `object` meets every criterion
+    pub fn matches(&self, object: &SceneObject) -> bool {
+        let named = self
+            .name
+            .as_ref()
+            .is_none_or(|name| object.name.to_lowercase().contains(&name.to_lowercase()));
+        let owned = self.owner_id.is_none_or(|owner| object.owner_id == owner);
+        let inside = self.area.is_none_or(|(a, b)| {
+            let p = object.position;
+            (a.x.min(b.x)..=a.x.max(b.x)).contains(&p.x)
+                && (a.y.min(b.y)..=a.y.max(b.y)).contains(&p.y)
+                && (a.z.min(b.z)..=a.z.max(b.z)).contains(&p.z)
+        });
+        named && owned && inside
+    }
+}
+
+/// Persistent contents of a region
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegionScene {
+    /// Region identity and placement
+    pub info: RegionInfo,
+    /// Terrain heightmap
+    pub terrain: Terrain,
+    /// Objects in the region
+    pub objects: HashMap<ObjectId, SceneObject>,
+    /// Land parcels
+    pub parcels: Vec<Parcel>,
+}
+
+impl RegionScene {
+    /// An empty region with flat terrain and a single parcel owned by `owner`
+    pub fn new(info: RegionInfo, owner: UserId) -> Self {
+        let terrain = Terrain::flat(info.size_x, info.size_y, DEFAULT_TERRAIN_HEIGHT);
+        let parcels = vec![Parcel::whole_region(owner, info.size_x, info.size_y)];
+        Self {
+            info,
+            terrain,
+            objects: HashMap::new(),
+            parcels,
+        }
+    }
+
+    /// The parcel at the region-local point `x`, `y`
+    pub fn parcel_at(&self, x: f32, y: f32) -> Option<&Parcel> {
+        self.parcels.iter().find(|parcel| parcel.contains(x, y))
+    }
+
+    /// The objects matching `query`, in local ID order
+    pub fn find_objects(&self, query: &ObjectQuery) -> Vec<&SceneObject> {
+        let mut found: Vec<&SceneObject> = self.objects.values().filter(|object| query.matches(object)).collect();
+        found.sort_by_key(|object| object.local_id);
+        found
+    }
+
+    /// Add or replace an object
+    pub fn add_object(&mut self, object: SceneObject) {
+        self.objects.insert(object.id, object);
+    }
+
+    /// Replace terrain, objects and parcels with those of `other`, keeping
+    /// this region's identity
+    pub fn restore_from(&mut self, other: RegionScene) {
+        self.terrain = other.terrain;
+        self.objects = other.objects;
+        self.parcels = other.parcels;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terrain_access_is_clamped() {
+        let mut terrain = Terrain::flat(4, 4, 20.0);
+        terrain.set_height(3, 3, 30.0);
+        terrain.set_height(10, 10, 99.0);
+        assert_eq!(terrain.height_at(3, 3), 30.0);
+        assert_eq!(terrain.height_at(100, 100), 30.0);
+        assert_eq!(terrain.height_at(0, 0), 20.0);
+    }
+
+    #[test]
+    fn test_parcel_maturity_is_capped_by_the_region() {
+        let mut parcel = Parcel::whole_region(UserId::new(), 256, 256);
+        assert_eq!(parcel.effective_maturity(Maturity::Mature), Maturity::Mature);
+        parcel.maturity = Some(Maturity::Pg);
+        assert_eq!(parcel.effective_maturity(Maturity::Adult), Maturity::Pg);
+        parcel.maturity = Some(Maturity::Adult);
+        assert_eq!(parcel.effective_maturity(Maturity::Mature), Maturity::Mature);
+        assert_eq!("Moderate".parse::<Maturity>(), Ok(Maturity::Mature));
+        assert_eq!(Maturity::from_sim_access(Maturity::Adult.sim_access()), Maturity::Adult);
+    }
+
+    #[test]
+    fn test_parcel_at_follows_the_bitmap() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let mut scene = RegionScene::new(info, UserId::new());
+        let mut corner = Parcel::whole_region(UserId::new(), 256, 256);
+        corner.local_id = 2;
+        corner.bitmap = vec![0; 512];
+        corner.bitmap[0] = 0b11;
+        scene.parcels[0].bitmap[0] = 0b1111_1100;
+        scene.parcels.insert(0, corner);
+
+        assert_eq!(scene.parcel_at(5.0, 1.0).map(|p| p.local_id), Some(2));
+        assert_eq!(scene.parcel_at(9.0, 1.0).map(|p| p.local_id), Some(1));
+        assert_eq!(scene.parcel_at(300.0, 300.0).map(|p| p.local_id), Some(1));
+    }
+
+    #[test]
+    fn test_find_objects() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let mut scene = RegionScene::new(info, UserId::new());
+        let (alice, bob) = (UserId::new(), UserId::new());
+        for (local_id, name, owner_id, x) in [(1, "Red Chair", alice, 10.0), (2, "Table", alice, 50.0), (3, "Blue chair", bob, 12.0)] {
+            scene.add_object(SceneObject {
+                position: Vector3::new(x, 10.0, 22.0),
+                ..SceneObject::new(local_id, name, owner_id)
+            });
+        }
+        let local_ids = |query: &ObjectQuery| -> Vec<u32> {
+            scene.find_objects(query).iter().map(|object| object.local_id).collect()
+        };
+
+        assert_eq!(local_ids(&ObjectQuery::default()), vec![1, 2, 3]);
+        let chairs = ObjectQuery {
+            name: Some("CHAIR".to_string()),
+            ..ObjectQuery::default()
+        };
+        assert_eq!(local_ids(&chairs), vec![1, 3]);
+        let alices = ObjectQuery {
+            owner_id: Some(alice),
+            area: Some((Vector3::new(20.0, 0.0, 0.0), Vector3::new(0.0, 20.0, 100.0))),
+            ..ObjectQuery::default()
+        };
+        assert_eq!(local_ids(&alices), vec![1]);
+    }
+}
@@ -1,6 +1,6 @@
 //! Core traits for Mutsea components
 
-use crate::{AssetId, MutseaResult, ObjectId, RegionId, UserId};
+use crate::{AssetId, MutseaError, MutseaResult, RegionId, UserId};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -232,6 +232,345 @@ pub trait MetricsCollector: Send + Sync {
     fn record_timing(&self, name: &str, duration: std::time::Duration, labels: &[(&str, &str)]);
 }
 
+/// One aggregated row for the performance metrics analytics table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PerformanceSample {
+    /// When the sampled window ended
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Component that was measured, e.g. `simulation.physics`
+    pub component_name: String,
+    /// What was measured, e.g. `tick_avg_ms`
+    pub metric_type: String,
+    /// Measured value
+    pub metric_value: f64,
+    /// Unit of `metric_value`
+    pub unit_of_measure: String,
+    /// Extra details about the sample window
+    pub context_data: serde_json::Value,
+    /// `info`, `warning` or `critical`
+    pub alert_level: Option<String>,
+}
+
+/// Sink for aggregated performance samples
+#[async_trait]
+pub trait PerformanceRecorder: Send + Sync {
+    /// Persist a batch of samples
+    async fn record_performance(&self, samples: &[PerformanceSample]) -> MutseaResult<()>;
+}
+
+/// One row for the player behavior analytics table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerBehaviorRecord {
+    /// Player the action belongs to
+    pub player_id: UserId,
+    /// Session the action happened in
+    pub session_id: uuid::Uuid,
+    /// When the action happened
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// What the player did, e.g. `session_end`
+    pub action_type: String,
+    /// Details of the action
+    pub action_data: serde_json::Value,
+    /// Where the action happened
+    pub context_data: serde_json::Value,
+}
+
+/// Sink for player behavior records
+#[async_trait]
+pub trait PlayerBehaviorRecorder: Send + Sync {
+    /// Persist a batch of records
+    async fn record_player_behaviors(&self, records: &[PlayerBehaviorRecord]) -> MutseaResult<()>;
+}
+
+/// One snapshot for the ecosystem states analytics table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EcosystemSnapshot {
+    /// When the snapshot was taken
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Each region is one biome, identified by the region's UUID
+    pub biome_id: uuid::Uuid,
+    /// Population by species
+    pub population_data: serde_json::Value,
+    /// Level of each natural resource, 0.0 to 1.0
+    pub resource_data: serde_json::Value,
+    /// Consumption between trophic levels in the last step
+    pub interaction_data: serde_json::Value,
+    /// Weather and overall health
+    pub health_metrics: serde_json::Value,
+    /// Evenness of the species mix, 0.0 to 1.0
+    pub biodiversity_index: f64,
+    /// Individuals the region can sustain, all species together
+    pub carrying_capacity: i64,
+}
+
+/// Sink for ecosystem snapshots
+#[async_trait]
+pub trait EcosystemRecorder: Send + Sync {
+    /// Persist a batch of snapshots
+    async fn record_ecosystem(&self, snapshots: &[EcosystemSnapshot]) -> MutseaResult<()>;
+}
+
+/// One row for the AI decisions table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AIDecisionRecord {
+    /// Decision ID, under which its outcome is reported
+    pub id: uuid::Uuid,
+    /// Kind of decision, e.g. `world_generation`
+    pub decision_type: String,
+    /// Hash of the inputs, grouping decisions made in the same situation
+    pub context_hash: String,
+    /// What the decision was made from
+    pub input_data: serde_json::Value,
+    /// What was decided
+    pub decision_data: serde_json::Value,
+    /// 0.0 to 1.0
+    pub confidence_score: f64,
+    /// Time taken to decide, in milliseconds
+    pub execution_time_ms: i64,
+    /// Model or planner that decided
+    pub model_version: String,
+    /// Anything else worth keeping with the decision
+    pub metadata: serde_json::Value,
+}
+
+/// Sink for AI decisions, whose outcomes are reported later
+#[async_trait]
+pub trait AIDecisionRecorder: Send + Sync {
+    /// Persist one decision
+    async fn record_ai_decision(&self, decision: &AIDecisionRecord) -> MutseaResult<()>;
+}
+
+/// Content to generate in a region
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenerationRequest {
+    /// Region to generate in
+    pub region_id: RegionId,
+    /// Landscape to shape, e.g. `forest`, `desert`, `tundra`
+    pub biome: String,
+    /// Style of what is placed, e.g. `ancient ruins`
+    pub theme: String,
+    /// How much to place, 0.0 (sparse) to 1.0 (dense)
+    pub density: f32,
+    /// Centre and radius in metres of the area to generate; the whole
+    /// region when absent
+    #[serde(default)]
+    pub area: Option<(f32, f32, f32)>,
+    /// Same seed and request, same plan; 0 picks one at random
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl GenerationRequest {
+    /// Check the request is one a planner can work with
+    pub fn validate(&self) -> Result<(), String> {
+        if self.biome.trim().is_empty() {
+            return Err("biome must not be empty".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.density) {
+            return Err(format!("density must be between 0.0 and 1.0, got {}", self.density));
+        }
+        if let Some((_, _, radius)) = self.area {
+            if radius <= 0.0 {
+                return Err("area radius must be greater than 0".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a terrain edit changes the ground within its radius
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TerrainEditKind {
+    /// Raise by up to `amount` metres at the centre
+    Raise,
+    /// Lower by up to `amount` metres at the centre
+    Lower,
+    /// Level towards `amount` metres above sea level
+    Flatten,
+}
+
+/// One brush stroke on the terrain, fading out towards its edge
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerrainEdit {
+    /// What the stroke does
+    pub kind: TerrainEditKind,
+    /// Centre of the stroke, in region metres
+    pub x: f32,
+    /// Centre of the stroke, in region metres
+    pub y: f32,
+    /// Radius in metres
+    pub radius: f32,
+    /// Metres to raise or lower by, or height to flatten to
+    pub amount: f32,
+}
+
+/// An object to place; it sits on the terrain below `x`, `y`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedObject {
+    /// Object name
+    pub name: String,
+    /// Primitive shape: `box`, `cylinder` or `sphere`
+    pub shape: String,
+    /// Position in region metres
+    pub x: f32,
+    /// Position in region metres
+    pub y: f32,
+    /// Size in metres
+    pub scale: crate::Vector3,
+    /// Rotation about the vertical axis, in radians
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+/// What an [`AIProvider`] proposes for a [`GenerationRequest`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LayoutPlan {
+    /// Terrain strokes, applied in order before objects are placed
+    #[serde(default)]
+    pub terrain: Vec<TerrainEdit>,
+    /// Objects to place
+    #[serde(default)]
+    pub objects: Vec<PlannedObject>,
+    /// 0.0 to 1.0
+    pub confidence: f32,
+    /// Model or planner that made the plan
+    pub model_version: String,
+    /// Why the layout looks the way it does
+    #[serde(default)]
+    pub rationale: String,
+}
+
+/// A quest to write for a player
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoryRequest {
+    /// Region the quest takes place in
+    pub region_id: RegionId,
+    /// Player the quest is for
+    pub player_id: UserId,
+    /// Kind of story, e.g. `mystery`, `exploration`
+    pub theme: String,
+    /// What is going on in the region, e.g. `weather: rain`
+    #[serde(default)]
+    pub context: Vec<String>,
+    /// Same seed and request, same quest; 0 picks one at random
+    #[serde(default)]
+    pub seed: u64,
+}
+
+/// What meets a quest objective
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QuestTrigger {
+    /// The player comes within `radius` metres of `x`, `y`
+    Reach {
+        /// Position in region metres
+        x: f32,
+        /// Position in region metres
+        y: f32,
+        /// Distance in metres
+        radius: f32,
+    },
+    /// The player says something containing `phrase` on `channel`,
+    /// ignoring case
+    Say {
+        /// Chat channel, 0 for public chat
+        channel: i32,
+        /// Text to look for
+        phrase: String,
+    },
+    /// The region reports an event, e.g. `weather_changed`, optionally one
+    /// whose detail (condition, species or resource) is `detail`
+    RegionEvent {
+        /// Event name
+        event: String,
+        /// Detail the event must have, any when absent
+        #[serde(default)]
+        detail: Option<String>,
+    },
+}
+
+/// One step of a quest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuestObjective {
+    /// Told to the player when the step begins
+    pub description: String,
+    /// What completes the step
+    pub trigger: QuestTrigger,
+}
+
+/// What an [`AIProvider`] proposes for a [`StoryRequest`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuestPlan {
+    /// Quest title
+    pub title: String,
+    /// What the quest is about
+    pub synopsis: String,
+    /// Completed in order
+    pub objectives: Vec<QuestObjective>,
+    /// 0.0 to 1.0
+    pub confidence: f32,
+    /// Model or storyteller that wrote the quest
+    pub model_version: String,
+}
+
+/// Something an avatar said to an NPC
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversationRequest {
+    /// NPC spoken to
+    pub npc_id: uuid::Uuid,
+    /// NPC's name
+    pub npc_name: String,
+    /// The NPC's stored personality profile
+    pub personality: serde_json::Value,
+    /// Earlier exchanges with the speaker the NPC remembers, oldest first
+    #[serde(default)]
+    pub memories: Vec<String>,
+    /// Avatar speaking
+    pub speaker_id: UserId,
+    /// What the avatar said
+    pub message: String,
+}
+
+/// An NPC's answer to a [`ConversationRequest`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConversationReply {
+    /// The reply
+    pub text: String,
+    /// 0.0 to 1.0
+    pub confidence: f32,
+    /// Model or responder that wrote the reply
+    pub model_version: String,
+}
+
+/// Source of AI-made plans for the world engine. Providers implement the
+/// plans they can make; the others fail.
+#[async_trait]
+pub trait AIProvider: Send + Sync {
+    /// Provider name reported with its decisions
+    fn name(&self) -> &str;
+
+    /// Lay out terrain and objects for `request`
+    async fn plan_layout(&self, _request: &GenerationRequest) -> MutseaResult<LayoutPlan> {
+        Err(MutseaError::Generic(format!("{} does not plan layouts", self.name())))
+    }
+
+    /// Write a quest for `request`
+    async fn plan_quest(&self, _request: &StoryRequest) -> MutseaResult<QuestPlan> {
+        Err(MutseaError::Generic(format!("{} does not write quests", self.name())))
+    }
+
+    /// Answer `request` in character, sending the text on `partial` as it
+    /// is written
+    async fn converse(
+        &self,
+        _request: &ConversationRequest,
+        _partial: tokio::sync::mpsc::Sender<String>,
+    ) -> MutseaResult<ConversationReply> {
+        Err(MutseaError::Generic(format!("{} does not converse", self.name())))
+    }
+}
+
 /// Trait for configuration management
 pub trait ConfigManager: Send + Sync {
     /// Get a configuration value
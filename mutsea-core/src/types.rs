@@ -278,6 +278,37 @@ impl Quaternion {
             *self
         }
     }
+
+    /// The inverse rotation, for a unit quaternion
+    pub fn conjugate(&self) -> Self {
+        Self {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+            w: self.w,
+        }
+    }
+
+    /// Rotate `v` by this rotation
+    pub fn rotate(&self, v: Vector3) -> Vector3 {
+        let axis = Vector3::new(self.x, self.y, self.z);
+        let t = axis.cross(&v) * 2.0;
+        v + t * self.w + axis.cross(&t)
+    }
+}
+
+impl std::ops::Mul for Quaternion {
+    type Output = Quaternion;
+
+    /// Rotation by `other` followed by `self`
+    fn mul(self, other: Quaternion) -> Quaternion {
+        Quaternion {
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+        }
+    }
 }
 
 /// Asset type enumeration
@@ -352,6 +383,8 @@ pub struct UserAccount {
     pub user_level: i32,
     pub user_flags: i32,
     pub user_title: Option<String>,
+    /// The name viewers show instead of first and last name, if chosen
+    pub display_name: Option<String>,
 }
 
 impl UserAccount {
@@ -372,12 +405,18 @@ impl UserAccount {
             user_level: 0,
             user_flags: 0,
             user_title: None,
+            display_name: None,
         }
     }
 
     pub fn full_name(&self) -> String {
         format!("{} {}", self.first_name, self.last_name)
     }
+
+    /// The display name, or the full name if none was chosen
+    pub fn shown_name(&self) -> String {
+        self.display_name.clone().unwrap_or_else(|| self.full_name())
+    }
 }
 
 /// Asset metadata and content
@@ -420,6 +459,74 @@ impl Asset {
     }
 }
 
+/// Content rating of a region or parcel, and the highest rating an agent
+/// may enter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Maturity {
+    /// General (PG) content
+    #[default]
+    Pg,
+    /// Moderate (Mature) content
+    Mature,
+    /// Adult content
+    Adult,
+}
+
+impl Maturity {
+    /// The SimAccess value sent to viewers
+    pub fn sim_access(self) -> u8 {
+        match self {
+            Maturity::Pg => 13,
+            Maturity::Mature => 21,
+            Maturity::Adult => 42,
+        }
+    }
+
+    /// The rating of a SimAccess value, rounding unknown values down
+    pub fn from_sim_access(access: u8) -> Self {
+        match access {
+            42.. => Maturity::Adult,
+            21.. => Maturity::Mature,
+            _ => Maturity::Pg,
+        }
+    }
+
+    /// The letter used by `agent_access` at login: "PG", "M" or "A"
+    pub fn access_letter(self) -> &'static str {
+        match self {
+            Maturity::Pg => "PG",
+            Maturity::Mature => "M",
+            Maturity::Adult => "A",
+        }
+    }
+}
+
+impl std::str::FromStr for Maturity {
+    type Err = String;
+
+    /// Parse a rating by name, old or new: "pg" or "general", "mature" or
+    /// "moderate", "adult"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "pg" | "general" => Ok(Maturity::Pg),
+            "mature" | "moderate" | "m" => Ok(Maturity::Mature),
+            "adult" | "a" => Ok(Maturity::Adult),
+            other => Err(format!("Unknown maturity rating: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for Maturity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Maturity::Pg => "General",
+            Maturity::Mature => "Moderate",
+            Maturity::Adult => "Adult",
+        })
+    }
+}
+
 /// Region information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegionInfo {
@@ -455,13 +562,18 @@ impl RegionInfo {
             size_y: 256,
             external_endpoint,
             internal_endpoint,
-            access: 1, // Public access
+            access: Maturity::Pg.sim_access(),
             scope_id: Uuid::new_v4(),
             estate_id: 1,
             flags: 0,
             last_seen: chrono::Utc::now(),
         }
     }
+
+    /// Content rating of the region, from its SimAccess
+    pub fn maturity(&self) -> Maturity {
+        Maturity::from_sim_access(self.access)
+    }
 }
 
 /// Scene object in the virtual world
@@ -487,6 +599,40 @@ pub struct SceneObject {
     pub last_updated: chrono::DateTime<chrono::Utc>,
 }
 
+impl SceneObject {
+    /// A plain one-metre box named `name` at the region origin, made and
+    /// owned by `owner_id`
+    pub fn new(local_id: u32, name: impl Into<String>, owner_id: UserId) -> Self {
+        let now = chrono::Utc::now();
+        Self {
+            id: ObjectId::new(),
+            local_id,
+            name: name.into(),
+            description: String::new(),
+            position: Vector3::ZERO,
+            rotation: Quaternion::IDENTITY,
+            scale: Vector3::ONE,
+            velocity: Vector3::ZERO,
+            angular_velocity: Vector3::ZERO,
+            owner_id,
+            creator_id: owner_id,
+            group_id: None,
+            flags: 0,
+            material: 0,
+            click_action: 0,
+            shape: ObjectShape::default(),
+            created: now,
+            last_updated: now,
+        }
+    }
+}
+
+impl Default for SceneObject {
+    fn default() -> Self {
+        Self::new(0, String::new(), UserId::new())
+    }
+}
+
 /// Object shape information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ObjectShape {
@@ -0,0 +1,45 @@
+CREATE TABLE IF NOT EXISTS performance_metrics_1m (
+    bucket_start TIMESTAMPTZ NOT NULL,
+    component_name VARCHAR(100) NOT NULL,
+    metric_type VARCHAR(100) NOT NULL,
+    unit_of_measure VARCHAR(50),
+    sample_count BIGINT NOT NULL,
+    value_sum DOUBLE PRECISION NOT NULL,
+    value_min DOUBLE PRECISION NOT NULL,
+    value_max DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (component_name, metric_type, bucket_start)
+);
+
+CREATE TABLE IF NOT EXISTS performance_metrics_1h (
+    bucket_start TIMESTAMPTZ NOT NULL,
+    component_name VARCHAR(100) NOT NULL,
+    metric_type VARCHAR(100) NOT NULL,
+    unit_of_measure VARCHAR(50),
+    sample_count BIGINT NOT NULL,
+    value_sum DOUBLE PRECISION NOT NULL,
+    value_min DOUBLE PRECISION NOT NULL,
+    value_max DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (component_name, metric_type, bucket_start)
+);
+
+CREATE TABLE IF NOT EXISTS performance_metrics_1d (
+    bucket_start TIMESTAMPTZ NOT NULL,
+    component_name VARCHAR(100) NOT NULL,
+    metric_type VARCHAR(100) NOT NULL,
+    unit_of_measure VARCHAR(50),
+    sample_count BIGINT NOT NULL,
+    value_sum DOUBLE PRECISION NOT NULL,
+    value_min DOUBLE PRECISION NOT NULL,
+    value_max DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (component_name, metric_type, bucket_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_performance_metrics_1m_bucket ON performance_metrics_1m(bucket_start);
+CREATE INDEX IF NOT EXISTS idx_performance_metrics_1h_bucket ON performance_metrics_1h(bucket_start);
+CREATE INDEX IF NOT EXISTS idx_performance_metrics_1d_bucket ON performance_metrics_1d(bucket_start);
+
+-- End of the last complete bucket rolled up into each resolution
+CREATE TABLE IF NOT EXISTS metric_rollup_state (
+    resolution VARCHAR(10) PRIMARY KEY,
+    rolled_until TIMESTAMPTZ NOT NULL
+);
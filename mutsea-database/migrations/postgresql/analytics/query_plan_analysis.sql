@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS query_plan_analysis (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    captured_at TIMESTAMPTZ NOT NULL,
+    query_fingerprint TEXT NOT NULL,
+    query_sql TEXT NOT NULL,
+    execution_time_ms DECIMAL(15,3) NOT NULL,
+    analyzed BOOLEAN NOT NULL DEFAULT FALSE,
+    plan TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_query_plan_analysis_fingerprint ON query_plan_analysis(query_fingerprint);
+CREATE INDEX IF NOT EXISTS idx_query_plan_analysis_captured_at ON query_plan_analysis(captured_at);
@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS economy_balances (
+    agent_id UUID PRIMARY KEY,
+    balance BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS economy_transactions (
+    id UUID PRIMARY KEY,
+    source_id UUID,
+    destination_id UUID,
+    amount BIGINT NOT NULL CHECK (amount >= 0),
+    transaction_type INTEGER NOT NULL,
+    object_id UUID,
+    description TEXT,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_economy_transactions_source_id ON economy_transactions(source_id);
+CREATE INDEX IF NOT EXISTS idx_economy_transactions_destination_id ON economy_transactions(destination_id);
+CREATE INDEX IF NOT EXISTS idx_economy_transactions_object_id ON economy_transactions(object_id);
+CREATE INDEX IF NOT EXISTS idx_economy_transactions_created_at ON economy_transactions(created_at);
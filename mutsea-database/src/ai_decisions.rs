@@ -0,0 +1,35 @@
+// mutsea-database/src/ai_decisions.rs
+//! Persistence of AI decisions into `ai_decisions`
+
+use crate::DatabaseManager;
+use async_trait::async_trait;
+use mutsea_core::{AIDecisionRecord, AIDecisionRecorder, MutseaError, MutseaResult};
+
+#[async_trait]
+impl AIDecisionRecorder for DatabaseManager {
+    async fn record_ai_decision(&self, decision: &AIDecisionRecord) -> MutseaResult<()> {
+        let backend = self
+            .get_backend()
+            .await
+            .map_err(|e| MutseaError::Database(e.to_string()))?;
+        let id = decision.id.to_string();
+        backend
+            .execute(
+                include_str!("sql/postgresql/ai_decisions/record_ai_decision.sql"),
+                &[
+                    &id,
+                    &decision.decision_type,
+                    &decision.context_hash,
+                    &decision.input_data,
+                    &decision.decision_data,
+                    &decision.confidence_score,
+                    &decision.execution_time_ms,
+                    &decision.model_version,
+                    &decision.metadata,
+                ],
+            )
+            .await
+            .map_err(|e| MutseaError::Database(e.to_string()))?;
+        Ok(())
+    }
+}
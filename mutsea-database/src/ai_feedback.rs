@@ -0,0 +1,241 @@
+//! Feedback loop for stored AI decisions
+//!
+//! Once a decision has played out, what actually happened (measured
+//! metrics, player satisfaction surveys) is attached to its `ai_decisions`
+//! row as an [`ActualOutcome`] and/or a feedback score. An evaluation job
+//! then compares each outcome with the decision's prediction and stores
+//! `outcome_variance` and `learning_value`, so training can favour the
+//! decisions the models got most wrong. New feedback resets the
+//! evaluation, so a decision is rescored whenever its outcome changes.
+
+use crate::backends::DatabaseBackend;
+use crate::models::ActualOutcome;
+use crate::{DatabaseError, DatabaseManager, Result};
+use mutsea_core::config::AIFeedbackConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// What was observed after a decision ran
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DecisionFeedback {
+    #[serde(default)]
+    pub actual_outcome: Option<ActualOutcome>,
+    /// 0.0 (harmful) to 1.0 (ideal)
+    #[serde(default)]
+    pub feedback_score: Option<f32>,
+}
+
+impl DecisionFeedback {
+    pub fn validate(&self) -> Result<()> {
+        if self.actual_outcome.is_none() && self.feedback_score.is_none() {
+            return Err(DatabaseError::Generic(
+                "Feedback needs an actual_outcome or a feedback_score".to_string(),
+            ));
+        }
+        if let Some(score) = self.feedback_score {
+            if !(0.0..=1.0).contains(&score) {
+                return Err(DatabaseError::Generic(format!(
+                    "feedback_score must be between 0.0 and 1.0, got {}",
+                    score
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Attach `feedback` to decision `decision_id`, queueing it for evaluation.
+/// Returns false if there is no such decision.
+pub async fn record_feedback(
+    backend: &dyn DatabaseBackend,
+    decision_id: Uuid,
+    feedback: &DecisionFeedback,
+) -> Result<bool> {
+    feedback.validate()?;
+    let outcome = feedback
+        .actual_outcome
+        .as_ref()
+        .map(|outcome| serde_json::to_value(outcome).map(|v| v.to_string()))
+        .transpose()
+        .map_err(|e| DatabaseError::Generic(format!("Unserializable outcome: {}", e)))?;
+    let score = feedback.feedback_score.map(|score| score as f64);
+    let id = decision_id.to_string();
+    let updated = backend
+        .execute(
+            include_str!("sql/postgresql/ai_decisions/record_decision_outcome.sql"),
+            &[&outcome, &score, &id],
+        )
+        .await?;
+    Ok(updated > 0)
+}
+
+/// Scores of a decision with a reported outcome
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LearningEvaluation {
+    /// Mean relative deviation of the actual metrics from the predicted
+    /// ones; None when no metric was both predicted and measured
+    pub outcome_variance: Option<f64>,
+    /// 0.0 to 1.0, how much the outcome surprised the model: prediction
+    /// error and miscalibration of its confidence, averaged
+    pub learning_value: f64,
+}
+
+/// Score a decision from its stored `decision_data` and `outcome_data`
+pub fn evaluate(
+    decision_data: &Value,
+    outcome_data: &Value,
+    confidence: Option<f64>,
+    feedback_score: Option<f64>,
+) -> LearningEvaluation {
+    let predicted = decision_data
+        .pointer("/predicted_outcome/predicted_metrics")
+        .or_else(|| decision_data.get("predicted_metrics"))
+        .map(metrics)
+        .unwrap_or_default();
+    let actual = outcome_data.get("actual_metrics").map(metrics).unwrap_or_default();
+
+    let deviations: Vec<f64> = predicted
+        .iter()
+        .filter_map(|(name, predicted)| {
+            let actual = actual.get(name)?;
+            Some((actual - predicted).abs() / predicted.abs().max(f64::EPSILON))
+        })
+        .collect();
+    let outcome_variance =
+        (!deviations.is_empty()).then(|| deviations.iter().sum::<f64>() / deviations.len() as f64);
+
+    // Feedback is the best measure of success; fall back to the outcome's verdict
+    let observed_success = feedback_score.or_else(|| {
+        outcome_data
+            .get("success_achieved")
+            .and_then(Value::as_bool)
+            .map(|success| if success { 1.0 } else { 0.0 })
+    });
+    let miscalibration = confidence
+        .zip(observed_success)
+        .map(|(confidence, success)| (confidence - success).abs());
+
+    let signals: Vec<f64> = outcome_variance
+        .map(|variance| variance.min(1.0))
+        .into_iter()
+        .chain(miscalibration)
+        .collect();
+    let learning_value = if signals.is_empty() {
+        0.0
+    } else {
+        (signals.iter().sum::<f64>() / signals.len() as f64).clamp(0.0, 1.0)
+    };
+
+    LearningEvaluation {
+        outcome_variance,
+        learning_value,
+    }
+}
+
+fn metrics(value: &Value) -> HashMap<String, f64> {
+    value
+        .as_object()
+        .map(|map| map.iter().filter_map(|(k, v)| Some((k.clone(), v.as_f64()?))).collect())
+        .unwrap_or_default()
+}
+
+/// Score up to `batch_size` decisions whose outcome is not yet evaluated.
+/// Returns how many were scored.
+pub async fn evaluate_pending(backend: &dyn DatabaseBackend, batch_size: usize) -> Result<usize> {
+    let limit = batch_size as i64;
+    let rows = backend
+        .query(include_str!("sql/postgresql/ai_decisions/select_pending_evaluations.sql"), &[&limit])
+        .await?;
+
+    let mut evaluated = 0;
+    for row in &rows {
+        let id: String = row.get(0)?;
+        let decision_data: Value = row.get(1)?;
+        let outcome_data: Option<Value> = row.get(2)?;
+        let confidence: Option<f64> = row.get(3)?;
+        let feedback_score: Option<f64> = row.get(4)?;
+        let recorded_at: String = row.get(5)?;
+
+        let evaluation = evaluate(
+            &decision_data,
+            &outcome_data.unwrap_or(Value::Null),
+            confidence,
+            feedback_score,
+        );
+        evaluated += backend
+            .execute(
+                include_str!("sql/postgresql/ai_decisions/update_decision_learning.sql"),
+                &[&evaluation.outcome_variance, &evaluation.learning_value, &id, &recorded_at],
+            )
+            .await? as usize;
+    }
+    Ok(evaluated)
+}
+
+/// Evaluate reported outcomes every `evaluation_interval_secs` in the background
+pub fn spawn_evaluation(database: Arc<DatabaseManager>, config: &AIFeedbackConfig) {
+    if !config.enabled {
+        return;
+    }
+    let batch_size = config.batch_size;
+    let interval = std::time::Duration::from_secs(config.evaluation_interval_secs.max(1));
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let backend = match database.get_backend().await {
+                Ok(backend) => backend,
+                Err(e) => {
+                    warn!("AI decision evaluation skipped: {}", e);
+                    continue;
+                }
+            };
+            match evaluate_pending(backend.as_ref(), batch_size).await {
+                Ok(0) => {}
+                Ok(count) => debug!("Evaluated {} AI decision outcomes", count),
+                Err(e) => warn!("AI decision evaluation failed: {}", e),
+            }
+        }
+    });
+    info!("AI decision outcomes evaluated every {}s", interval.as_secs());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_evaluate_compares_prediction_and_outcome() {
+        let decision = json!({ "predicted_outcome": { "predicted_metrics": { "primary": 10.0, "engagement": 0.5 } } });
+        let outcome = json!({ "actual_metrics": { "primary": 12.0, "unmeasured": 3.0 }, "success_achieved": false });
+
+        let evaluation = evaluate(&decision, &outcome, Some(0.9), None);
+        assert!((evaluation.outcome_variance.unwrap() - 0.2).abs() < 1e-9);
+        // Mean of the 0.2 variance and the 0.9 confidence in a failure
+        assert!((evaluation.learning_value - 0.55).abs() < 1e-9);
+
+        // An explicit feedback score overrides the outcome's verdict
+        let evaluation = evaluate(&decision, &outcome, Some(0.9), Some(0.9));
+        assert!((evaluation.learning_value - 0.1).abs() < 1e-9);
+
+        let nothing = evaluate(&json!({}), &Value::Null, Some(0.9), None);
+        assert_eq!(nothing.outcome_variance, None);
+        assert_eq!(nothing.learning_value, 0.0);
+    }
+
+    #[test]
+    fn test_feedback_validation() {
+        assert!(DecisionFeedback::default().validate().is_err());
+        let feedback = |score| DecisionFeedback {
+            actual_outcome: None,
+            feedback_score: Some(score),
+        };
+        assert!(feedback(0.75).validate().is_ok());
+        assert!(feedback(1.5).validate().is_err());
+    }
+}
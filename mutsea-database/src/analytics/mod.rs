@@ -354,6 +354,21 @@ pub struct TrendPoint {
     pub metadata: Option<HashMap<String, serde_json::Value>>,
 }
 
+impl From<&crate::rollups::RollupPoint> for TrendPoint {
+    /// Bucket average at the bucket start, with its spread as metadata
+    fn from(point: &crate::rollups::RollupPoint) -> Self {
+        Self {
+            timestamp: point.bucket_start,
+            value: point.average(),
+            metadata: Some(HashMap::from([
+                ("sample_count".to_string(), serde_json::Value::Number(point.sample_count.into())),
+                ("min".to_string(), serde_json::json!(point.min)),
+                ("max".to_string(), serde_json::json!(point.max)),
+            ])),
+        }
+    }
+}
+
 /// System anomaly detection
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemAnomaly {
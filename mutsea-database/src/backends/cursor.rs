@@ -0,0 +1,217 @@
+//! Streaming query results a page at a time
+//!
+//! [`stream`] backs [`DatabaseBackend::query_stream`]. On PostgreSQL rows are
+//! fetched in pages of `batch_size` through a server-side cursor
+//! (`DECLARE … CURSOR` / `FETCH FORWARD`) inside one transaction, so only a
+//! page is held in memory and the whole result comes from one snapshot;
+//! dropping the stream early rolls the transaction back. Backends without
+//! cursors run the query once and stream the loaded rows.
+
+use super::{BackendType, DatabaseBackend, Row, SqlValue, ToSql, Transaction};
+use crate::Result;
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Rows of a streamed query
+pub type RowStream<'a> = BoxStream<'a, Result<Row>>;
+
+/// Default page size for [`DatabaseBackend::query_stream`] callers
+pub const DEFAULT_BATCH_SIZE: usize = 500;
+
+static NEXT_CURSOR: AtomicU64 = AtomicU64::new(1);
+
+/// A result read a page at a time
+#[async_trait]
+trait Pages: Send {
+    type Item: Send;
+
+    /// Up to `batch_size` items following the previous page
+    async fn next_page(&mut self, batch_size: usize) -> Result<Vec<Self::Item>>;
+
+    /// Called once the last page has been read
+    async fn finish(&mut self) -> Result<()>;
+}
+
+/// Items of `pages`, fetched `batch_size` at a time until a page comes back
+/// short
+fn paged<'a, P: Pages + 'a>(pages: P, batch_size: usize) -> BoxStream<'a, Result<P::Item>> {
+    let batch_size = batch_size.max(1);
+    stream::try_unfold((pages, false), move |(mut pages, done)| async move {
+        if done {
+            return Ok(None);
+        }
+        let items = pages.next_page(batch_size).await?;
+        let done = items.len() < batch_size;
+        if done {
+            pages.finish().await?;
+        }
+        Ok((!items.is_empty()).then_some((items, (pages, done))))
+    })
+    .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+    .try_flatten()
+    .boxed()
+}
+
+/// Pages of a PostgreSQL cursor
+struct Pager<'a, B: ?Sized> {
+    backend: &'a B,
+    transaction: Option<Box<dyn Transaction>>,
+    query: String,
+    params: Vec<SqlValue>,
+    cursor: String,
+}
+
+/// Stream the rows of `query` from `backend`, `batch_size` at a time
+pub fn stream<'a, B>(backend: &'a B, query: &str, params: &[&dyn ToSql], batch_size: usize) -> RowStream<'a>
+where
+    B: DatabaseBackend + ?Sized,
+{
+    let query = query.trim().trim_end_matches(';').to_string();
+    let params: Vec<SqlValue> = params.iter().map(|p| p.to_sql()).collect();
+
+    if backend.backend_type() != BackendType::PostgreSQL {
+        return stream::once(async move {
+            let params: Vec<&dyn ToSql> = params.iter().map(|p| p as &dyn ToSql).collect();
+            backend.query(&query, &params).await
+        })
+        .map_ok(|rows| stream::iter(rows.into_iter().map(Ok)))
+        .try_flatten()
+        .boxed();
+    }
+
+    let pager = Pager {
+        backend,
+        transaction: None,
+        query,
+        params,
+        cursor: format!("mutsea_cursor_{}", NEXT_CURSOR.fetch_add(1, Ordering::Relaxed)),
+    };
+    paged(pager, batch_size)
+}
+
+#[async_trait]
+impl<B: DatabaseBackend + ?Sized> Pages for Pager<'_, B> {
+    type Item = Row;
+
+    async fn next_page(&mut self, batch_size: usize) -> Result<Vec<Row>> {
+        if self.transaction.is_none() {
+            let mut transaction = self.backend.begin_transaction().await?;
+            let declare = format!("DECLARE {} NO SCROLL CURSOR FOR {}", self.cursor, self.query);
+            let params: Vec<&dyn ToSql> = self.params.iter().map(|p| p as &dyn ToSql).collect();
+            transaction.execute(&declare, &params).await?;
+            self.transaction = Some(transaction);
+        }
+        let fetch = format!("FETCH FORWARD {} FROM {}", batch_size, self.cursor);
+        self.transaction
+            .as_mut()
+            .expect("transaction begun above")
+            .query(&fetch, &[])
+            .await
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        if let Some(mut transaction) = self.transaction.take() {
+            transaction.execute(&format!("CLOSE {}", self.cursor), &[]).await?;
+            transaction.commit().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DatabaseError;
+    use std::sync::{Arc, Mutex};
+
+    /// Numbers `0..count`, logging each page size and the finish
+    struct Numbers {
+        next: u32,
+        count: u32,
+        fail_at: Option<u32>,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Numbers {
+        fn new(count: u32) -> (Self, Arc<Mutex<Vec<String>>>) {
+            let log = Arc::new(Mutex::new(Vec::new()));
+            let numbers = Self {
+                next: 0,
+                count,
+                fail_at: None,
+                log: Arc::clone(&log),
+            };
+            (numbers, log)
+        }
+    }
+
+    #[async_trait]
+    impl Pages for Numbers {
+        type Item = u32;
+
+        async fn next_page(&mut self, batch_size: usize) -> Result<Vec<u32>> {
+            if self.fail_at == Some(self.next) {
+                return Err(DatabaseError::Internal("connection lost".to_string()));
+            }
+            let end = (self.next + batch_size as u32).min(self.count);
+            let page: Vec<u32> = (self.next..end).collect();
+            self.next = end;
+            self.log.lock().unwrap().push(format!("page {}", page.len()));
+            Ok(page)
+        }
+
+        async fn finish(&mut self) -> Result<()> {
+            self.log.lock().unwrap().push("finish".to_string());
+            Ok(())
+        }
+    }
+
+    fn collect(numbers: Numbers, batch_size: usize) -> Vec<u32> {
+        futures::executor::block_on(paged(numbers, batch_size).try_collect()).unwrap()
+    }
+
+    #[test]
+    fn test_pages_until_a_short_one() {
+        let (numbers, log) = Numbers::new(7);
+        assert_eq!(collect(numbers, 3), (0..7).collect::<Vec<_>>());
+        assert_eq!(*log.lock().unwrap(), ["page 3", "page 3", "page 1", "finish"]);
+    }
+
+    #[test]
+    fn test_batch_boundaries() {
+        // A result filling its last page exactly needs an empty page to end
+        let (numbers, log) = Numbers::new(6);
+        assert_eq!(collect(numbers, 3).len(), 6);
+        assert_eq!(*log.lock().unwrap(), ["page 3", "page 3", "page 0", "finish"]);
+
+        let (numbers, log) = Numbers::new(0);
+        assert!(collect(numbers, 3).is_empty());
+        assert_eq!(*log.lock().unwrap(), ["page 0", "finish"]);
+
+        // A batch size of 0 fetches a row at a time
+        let (numbers, log) = Numbers::new(2);
+        assert_eq!(collect(numbers, 0), vec![0, 1]);
+        assert_eq!(*log.lock().unwrap(), ["page 1", "page 1", "page 0", "finish"]);
+    }
+
+    #[test]
+    fn test_pages_are_fetched_only_as_read() {
+        let (numbers, log) = Numbers::new(7);
+        let first: Vec<u32> = futures::executor::block_on(paged(numbers, 3).take(4).try_collect()).unwrap();
+        assert_eq!(first, vec![0, 1, 2, 3]);
+        // Dropped before the end, so never finished
+        assert_eq!(*log.lock().unwrap(), ["page 3", "page 3"]);
+    }
+
+    #[test]
+    fn test_errors_end_the_stream() {
+        let (mut numbers, log) = Numbers::new(7);
+        numbers.fail_at = Some(3);
+        let items: Vec<Result<u32>> = futures::executor::block_on(paged(numbers, 3).collect());
+        assert_eq!(items.len(), 4);
+        assert!(items[..3].iter().all(|item| item.is_ok()));
+        assert!(items[3].is_err());
+        assert_eq!(*log.lock().unwrap(), ["page 3"]);
+    }
+}
@@ -4,6 +4,7 @@
 use async_trait::async_trait;
 use crate::{DatabaseError, Result};
 
+pub mod cursor;
 pub mod postgresql;
 pub mod sqlite;
 
@@ -33,6 +34,12 @@ pub trait DatabaseBackend: Send + Sync {
     
     /// Get backend type
     fn backend_type(&self) -> BackendType;
+
+    /// Stream the rows of a query, fetching `batch_size` at a time instead
+    /// of loading the whole result where the backend can; see [`cursor`]
+    fn query_stream<'a>(&'a self, query: &str, params: &[&dyn ToSql], batch_size: usize) -> cursor::RowStream<'a> {
+        cursor::stream(self, query, params, batch_size)
+    }
 }
 
 /// Transaction trait for database operations
@@ -176,6 +183,12 @@ impl ToSql for serde_json::Value {
     }
 }
 
+impl ToSql for SqlValue {
+    fn to_sql(&self) -> SqlValue {
+        self.clone()
+    }
+}
+
 impl<T: ToSql> ToSql for Option<T> {
     fn to_sql(&self) -> SqlValue {
         match self {
@@ -232,6 +245,12 @@ impl FromSql for serde_json::Value {
     }
 }
 
+impl FromSql for SqlValue {
+    fn from_sql(value: SqlValue) -> Result<Self> {
+        Ok(value)
+    }
+}
+
 impl<T: FromSql> FromSql for Option<T> {
     fn from_sql(value: SqlValue) -> Result<Self> {
         match value {
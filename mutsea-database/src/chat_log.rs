@@ -0,0 +1,239 @@
+//! Stored public chat and instant messages for moderation
+//!
+//! Logged messages go to `chat_log`, with the region and parcel they were
+//! said in, and are purged after the configured retention. Moderators
+//! search them by participant, place, time and text; the text is matched
+//! through the full-text index on the message (see [`crate::full_text`]).
+
+use crate::backends::{DatabaseBackend, SqlValue, ToSql};
+use crate::filter::Filter;
+use crate::full_text::{self, FullTextIndex};
+use crate::{DatabaseError, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const CREATE_CHAT_LOG: &str = "CREATE TABLE IF NOT EXISTS chat_log (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    kind VARCHAR(8) NOT NULL,
+    region_id VARCHAR(36) NOT NULL,
+    parcel_id VARCHAR(36),
+    sender_id VARCHAR(36) NOT NULL,
+    recipient_id VARCHAR(36),
+    channel INTEGER NOT NULL,
+    message TEXT NOT NULL,
+    sent_at BIGINT NOT NULL
+)";
+const CREATE_CHAT_LOG_INDEXES: [&str; 3] = [
+    "CREATE INDEX IF NOT EXISTS idx_chat_log_sent_at ON chat_log (sent_at)",
+    "CREATE INDEX IF NOT EXISTS idx_chat_log_sender ON chat_log (sender_id, sent_at)",
+    "CREATE INDEX IF NOT EXISTS idx_chat_log_recipient ON chat_log (recipient_id, sent_at)",
+];
+
+/// Logged messages by their text
+pub const CHAT_LOG: FullTextIndex = FullTextIndex {
+    table: "chat_log",
+    key: "id",
+    columns: &["message"],
+};
+
+/// Most entries one search returns
+pub const MAX_RESULTS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatKind {
+    /// Said in local chat
+    Chat,
+    /// Instant message to one agent
+    Im,
+}
+
+impl ChatKind {
+    fn name(&self) -> &'static str {
+        match self {
+            ChatKind::Chat => "chat",
+            ChatKind::Im => "im",
+        }
+    }
+}
+
+/// One logged message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatLogEntry {
+    pub id: Uuid,
+    pub kind: ChatKind,
+    pub region_id: Uuid,
+    pub parcel_id: Option<Uuid>,
+    pub sender_id: Uuid,
+    /// Who an IM was sent to
+    pub recipient_id: Option<Uuid>,
+    pub channel: i32,
+    pub message: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+/// Which logged messages a search returns; unset fields match everything
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatLogQuery {
+    /// Sent or received by this agent
+    pub participant: Option<Uuid>,
+    pub sender: Option<Uuid>,
+    pub kind: Option<ChatKind>,
+    pub region_id: Option<Uuid>,
+    pub parcel_id: Option<Uuid>,
+    /// Words the message contains
+    pub text: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Most entries returned, newest first; at most [`MAX_RESULTS`]
+    pub limit: Option<usize>,
+}
+
+impl ChatLogQuery {
+    /// SQL conditions and their parameters
+    fn conditions(&self) -> (Vec<String>, Vec<SqlValue>) {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        let mut condition = |sql: &str, values: Vec<SqlValue>| {
+            conditions.push(sql.to_string());
+            params.extend(values);
+        };
+        let id = |id: Uuid| SqlValue::Text(id.to_string());
+        if let Some(agent) = self.participant {
+            condition("(sender_id = ? OR recipient_id = ?)", vec![id(agent), id(agent)]);
+        }
+        if let Some(sender) = self.sender {
+            condition("sender_id = ?", vec![id(sender)]);
+        }
+        if let Some(kind) = self.kind {
+            condition("kind = ?", vec![SqlValue::Text(kind.name().to_string())]);
+        }
+        if let Some(region) = self.region_id {
+            condition("region_id = ?", vec![id(region)]);
+        }
+        if let Some(parcel) = self.parcel_id {
+            condition("parcel_id = ?", vec![id(parcel)]);
+        }
+        if let Some(from) = self.from {
+            condition("sent_at >= ?", vec![SqlValue::Int(from.timestamp_millis())]);
+        }
+        if let Some(to) = self.to {
+            condition("sent_at < ?", vec![SqlValue::Int(to.timestamp_millis())]);
+        }
+        (conditions, params)
+    }
+}
+
+/// Create the log table and its indexes
+pub async fn ensure_schema(backend: &dyn DatabaseBackend) -> Result<()> {
+    backend.execute(CREATE_CHAT_LOG, &[]).await?;
+    for statement in CREATE_CHAT_LOG_INDEXES {
+        backend.execute(statement, &[]).await?;
+    }
+    full_text::create_index(backend, &CHAT_LOG).await
+}
+
+/// Store `entries`
+pub async fn record(backend: &dyn DatabaseBackend, entries: &[ChatLogEntry]) -> Result<()> {
+    for entry in entries {
+        let parcel_id = entry.parcel_id.map(|id| id.to_string());
+        let recipient_id = entry.recipient_id.map(|id| id.to_string());
+        backend
+            .execute(
+                "INSERT INTO chat_log (id, kind, region_id, parcel_id, sender_id, recipient_id, channel, message, sent_at) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                &[
+                    &entry.id.to_string(),
+                    &entry.kind.name(),
+                    &entry.region_id.to_string(),
+                    &parcel_id,
+                    &entry.sender_id.to_string(),
+                    &recipient_id,
+                    &entry.channel,
+                    &entry.message,
+                    &entry.sent_at.timestamp_millis(),
+                ],
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Delete messages sent before `before`, returning how many
+pub async fn purge(backend: &dyn DatabaseBackend, before: DateTime<Utc>) -> Result<u64> {
+    backend
+        .execute("DELETE FROM chat_log WHERE sent_at < ?", &[&before.timestamp_millis()])
+        .await
+}
+
+/// Logged messages matching `query`, newest first
+pub async fn search(backend: &dyn DatabaseBackend, query: &ChatLogQuery) -> Result<Vec<ChatLogEntry>> {
+    let (mut conditions, mut params) = query.conditions();
+    if let Some(text) = query.text.as_deref().filter(|t| !t.trim().is_empty()) {
+        let (condition, text_params) = Filter::Matches(CHAT_LOG, text.to_string()).to_sql(backend.backend_type());
+        conditions.push(condition);
+        params.extend(text_params);
+    }
+    let limit = query.limit.unwrap_or(100).min(MAX_RESULTS);
+    params.push(SqlValue::Int(limit as i64));
+    let clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
+    };
+    let sql = format!(
+        "SELECT id, kind, region_id, parcel_id, sender_id, recipient_id, channel, message, sent_at \
+         FROM chat_log{} ORDER BY sent_at DESC LIMIT ?",
+        clause
+    );
+    let params: Vec<&dyn ToSql> = params.iter().map(|p| p as &dyn ToSql).collect();
+    let uuid = |text: String| Uuid::parse_str(&text).map_err(|e| DatabaseError::Serialization(e.to_string()));
+    backend
+        .query(&sql, &params)
+        .await?
+        .iter()
+        .map(|row| {
+            let kind: String = row.get(1)?;
+            let parcel_id: Option<String> = row.get(3)?;
+            let recipient_id: Option<String> = row.get(5)?;
+            let channel: i64 = row.get(6)?;
+            let sent_at: i64 = row.get(8)?;
+            Ok(ChatLogEntry {
+                id: uuid(row.get(0)?)?,
+                kind: if kind == "im" { ChatKind::Im } else { ChatKind::Chat },
+                region_id: uuid(row.get(2)?)?,
+                parcel_id: parcel_id.map(uuid).transpose()?,
+                sender_id: uuid(row.get(4)?)?,
+                recipient_id: recipient_id.map(uuid).transpose()?,
+                channel: channel as i32,
+                message: row.get(7)?,
+                sent_at: DateTime::from_timestamp_millis(sent_at).unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_conditions() {
+        let agent = Uuid::new_v4();
+        let query = ChatLogQuery {
+            participant: Some(agent),
+            kind: Some(ChatKind::Im),
+            from: DateTime::from_timestamp(1_000, 0),
+            ..Default::default()
+        };
+        let (conditions, params) = query.conditions();
+        assert_eq!(conditions, vec!["(sender_id = ? OR recipient_id = ?)", "kind = ?", "sent_at >= ?"]);
+        assert_eq!(params.len(), 4);
+        assert!(matches!(&params[2], SqlValue::Text(kind) if kind == "im"));
+        assert!(matches!(params[3], SqlValue::Int(1_000_000)));
+
+        assert!(ChatLogQuery::default().conditions().0.is_empty());
+    }
+}
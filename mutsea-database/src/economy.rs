@@ -0,0 +1,105 @@
+// mutsea-database/src/economy.rs
+//! The economy ledger in `economy_balances` and `economy_transactions`
+//!
+//! Every sale and payment is written as one transaction row together with
+//! the balances it left its payer and payee, in a single database
+//! transaction so the two can't disagree.
+
+use crate::backends::DatabaseBackend;
+use crate::error::DatabaseError;
+use crate::Result;
+use uuid::Uuid;
+
+/// Money moved from one account to another
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerTransaction {
+    pub id: Uuid,
+    /// `None` for money coming from the grid, such as a starting balance
+    pub source_id: Option<Uuid>,
+    pub destination_id: Option<Uuid>,
+    pub amount: i64,
+    /// As sent to viewers, e.g. 5000 for a prim bought
+    pub transaction_type: i32,
+    /// The prim sold or paid, if any
+    pub object_id: Option<Uuid>,
+    pub description: String,
+}
+
+/// The stored balance of `agent_id`, if it has an account
+pub async fn load_balance(backend: &dyn DatabaseBackend, agent_id: Uuid) -> Result<Option<i64>> {
+    let id = agent_id.to_string();
+    let rows = backend
+        .query(include_str!("sql/postgresql/economy/select_balance.sql"), &[&id])
+        .await?;
+    match rows.first() {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+/// Write `transaction` and the `balances` it left, by agent
+pub async fn record_transaction(
+    backend: &dyn DatabaseBackend,
+    transaction: &LedgerTransaction,
+    balances: &[(Uuid, i64)],
+) -> Result<()> {
+    let id = transaction.id.to_string();
+    let source = transaction.source_id.map(|id| id.to_string());
+    let destination = transaction.destination_id.map(|id| id.to_string());
+    let object = transaction.object_id.map(|id| id.to_string());
+
+    let mut tx = backend.begin_transaction().await?;
+    tx.execute(
+        include_str!("sql/postgresql/economy/insert_transaction.sql"),
+        &[
+            &id,
+            &source,
+            &destination,
+            &transaction.amount,
+            &transaction.transaction_type,
+            &object,
+            &transaction.description,
+        ],
+    )
+    .await?;
+    for (agent_id, balance) in balances {
+        let agent = agent_id.to_string();
+        tx.execute(
+            include_str!("sql/postgresql/economy/upsert_balance.sql"),
+            &[&agent, balance],
+        )
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// The latest `limit` transactions paid by or to `agent_id`, newest first
+pub async fn recent_transactions(
+    backend: &dyn DatabaseBackend,
+    agent_id: Uuid,
+    limit: i64,
+) -> Result<Vec<LedgerTransaction>> {
+    let id = agent_id.to_string();
+    let rows = backend
+        .query(
+            include_str!("sql/postgresql/economy/select_transactions.sql"),
+            &[&id, &id, &limit],
+        )
+        .await?;
+
+    let uuid = |text: String| Uuid::parse_str(&text).map_err(|e| DatabaseError::Serialization(e.to_string()));
+    rows.iter()
+        .map(|row| {
+            let transaction_type: i64 = row.get(4)?;
+            Ok(LedgerTransaction {
+                id: uuid(row.get(0)?)?,
+                source_id: row.get::<Option<String>>(1)?.map(uuid).transpose()?,
+                destination_id: row.get::<Option<String>>(2)?.map(uuid).transpose()?,
+                amount: row.get(3)?,
+                transaction_type: transaction_type as i32,
+                object_id: row.get::<Option<String>>(5)?.map(uuid).transpose()?,
+                description: row.get::<Option<String>>(6)?.unwrap_or_default(),
+            })
+        })
+        .collect()
+}
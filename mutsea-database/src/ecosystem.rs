@@ -0,0 +1,51 @@
+// mutsea-database/src/ecosystem.rs
+//! Persistence of ecosystem snapshots into `ecosystem_states`
+
+use crate::DatabaseManager;
+use async_trait::async_trait;
+use mutsea_core::{EcosystemRecorder, EcosystemSnapshot, MutseaError, MutseaResult};
+
+#[async_trait]
+impl EcosystemRecorder for DatabaseManager {
+    async fn record_ecosystem(&self, snapshots: &[EcosystemSnapshot]) -> MutseaResult<()> {
+        if snapshots.is_empty() {
+            return Ok(());
+        }
+
+        let backend = self
+            .get_backend()
+            .await
+            .map_err(|e| MutseaError::Database(e.to_string()))?;
+        let mut transaction = backend
+            .begin_transaction()
+            .await
+            .map_err(|e| MutseaError::Database(e.to_string()))?;
+        let query = include_str!("sql/postgresql/ecosystem/insert_ecosystem_snapshot.sql");
+
+        for snapshot in snapshots {
+            let timestamp = snapshot.timestamp.to_rfc3339();
+            let biome_id = snapshot.biome_id.to_string();
+            transaction
+                .execute(
+                    query,
+                    &[
+                        &timestamp,
+                        &biome_id,
+                        &snapshot.population_data,
+                        &snapshot.resource_data,
+                        &snapshot.interaction_data,
+                        &snapshot.health_metrics,
+                        &snapshot.biodiversity_index,
+                        &snapshot.carrying_capacity,
+                    ],
+                )
+                .await
+                .map_err(|e| MutseaError::Database(e.to_string()))?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| MutseaError::Database(e.to_string()))
+    }
+}
@@ -1,6 +1,7 @@
 // /mutsea/mutsea-database/src/error.rs
 //! Database error types
 
+use mutsea_core::{ErrorCategory, ErrorClassification, MutseaError};
 use std::fmt;
 
 #[derive(Debug)]
@@ -11,6 +12,12 @@ pub enum DatabaseError {
     NotFound(String),
     Validation(String),
     Internal(String),
+    /// Deadlock or serialization failure; the transaction can be retried
+    Transient(String),
+    /// The database circuit breaker is open; calls are not attempted
+    Unavailable(String),
+    UnsupportedBackend(String),
+    Generic(String),
 }
 
 impl fmt::Display for DatabaseError {
@@ -22,6 +29,10 @@ impl fmt::Display for DatabaseError {
             DatabaseError::NotFound(msg) => write!(f, "Not found: {}", msg),
             DatabaseError::Validation(msg) => write!(f, "Validation error: {}", msg),
             DatabaseError::Internal(msg) => write!(f, "Internal error: {}", msg),
+            DatabaseError::Transient(msg) => write!(f, "Transient database error: {}", msg),
+            DatabaseError::Unavailable(msg) => write!(f, "Database unavailable: {}", msg),
+            DatabaseError::UnsupportedBackend(msg) => write!(f, "Unsupported database backend: {}", msg),
+            DatabaseError::Generic(msg) => write!(f, "{}", msg),
         }
     }
 }
@@ -33,8 +44,17 @@ impl From<sqlx::Error> for DatabaseError {
     fn from(err: sqlx::Error) -> Self {
         match err {
             sqlx::Error::RowNotFound => DatabaseError::NotFound("Row not found".to_string()),
-            sqlx::Error::Database(db_err) => DatabaseError::Query(db_err.to_string()),
+            sqlx::Error::Database(db_err) => {
+                // SQLSTATE class 40 (transaction rollback) covers deadlocks and
+                // serialization failures; class 08 is a lost connection
+                match db_err.code().as_deref().map(|code| code.get(..2).unwrap_or(code)) {
+                    Some("40") => DatabaseError::Transient(db_err.to_string()),
+                    Some("08") => DatabaseError::Connection(db_err.to_string()),
+                    _ => DatabaseError::Query(db_err.to_string()),
+                }
+            }
             sqlx::Error::Io(io_err) => DatabaseError::Connection(io_err.to_string()),
+            sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed => DatabaseError::Connection(err.to_string()),
             _ => DatabaseError::Internal(err.to_string()),
         }
     }
@@ -46,5 +66,55 @@ impl From<serde_json::Error> for DatabaseError {
     }
 }
 
+impl ErrorClassification for DatabaseError {
+    fn code(&self) -> &'static str {
+        match self {
+            DatabaseError::Connection(_) => "DB_CONNECTION",
+            DatabaseError::Query(_) => "DB_QUERY",
+            DatabaseError::Serialization(_) => "DB_SERIALIZATION",
+            DatabaseError::NotFound(_) => "DB_NOT_FOUND",
+            DatabaseError::Validation(_) => "DB_VALIDATION",
+            DatabaseError::Internal(_) => "DB_INTERNAL",
+            DatabaseError::Transient(_) => "DB_TRANSIENT",
+            DatabaseError::Unavailable(_) => "DB_UNAVAILABLE",
+            DatabaseError::UnsupportedBackend(_) => "DB_UNSUPPORTED_BACKEND",
+            DatabaseError::Generic(_) => "DB_INTERNAL",
+        }
+    }
+
+    fn category(&self) -> ErrorCategory {
+        match self {
+            DatabaseError::Connection(_) | DatabaseError::Transient(_) | DatabaseError::Unavailable(_) => {
+                ErrorCategory::Retryable
+            }
+            _ => ErrorCategory::Permanent,
+        }
+    }
+}
+
+impl From<DatabaseError> for MutseaError {
+    fn from(err: DatabaseError) -> Self {
+        MutseaError::classified(&err)
+    }
+}
+
 /// Result type used throughout the database layer
 pub type DatabaseResult<T> = std::result::Result<T, DatabaseError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::ResultExt;
+
+    #[test]
+    fn test_database_errors_keep_code_through_context() {
+        let result: DatabaseResult<()> = Err(DatabaseError::Connection("refused".to_string()));
+        let error = result.context("loading region objects").unwrap_err();
+        let report = error.report();
+
+        assert_eq!(report.code, "DB_CONNECTION");
+        assert_eq!(report.category, ErrorCategory::Retryable);
+        assert_eq!(report.context, vec!["loading region objects"]);
+        assert_eq!(DatabaseError::NotFound("user".to_string()).category(), ErrorCategory::Permanent);
+    }
+}
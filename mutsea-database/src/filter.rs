@@ -0,0 +1,66 @@
+//! Conditions combined into a query's `WHERE` clause
+//!
+//! [`Filter`] gathers the operators the query layer renders per backend:
+//! conditions on JSON fields and full-text matches.
+
+use crate::backends::{BackendType, SqlValue};
+use crate::full_text::FullTextIndex;
+use crate::json_filter::JsonFilter;
+
+/// A condition on the rows of a table
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// A condition on a nested JSON field
+    Json(JsonFilter),
+    /// The indexed columns contain every word of the text as a prefix
+    Matches(FullTextIndex, String),
+}
+
+impl Filter {
+    /// SQL condition and its parameters for `backend`
+    pub fn to_sql(&self, backend: BackendType) -> (String, Vec<SqlValue>) {
+        match self {
+            Filter::Json(filter) => filter.to_sql(backend),
+            Filter::Matches(index, text) => index.matches(backend, text),
+        }
+    }
+}
+
+impl From<JsonFilter> for Filter {
+    fn from(filter: JsonFilter) -> Self {
+        Filter::Json(filter)
+    }
+}
+
+/// `WHERE` clause (empty without filters) and parameters for all `filters`
+pub fn where_clause(filters: &[Filter], backend: BackendType) -> (String, Vec<SqlValue>) {
+    if filters.is_empty() {
+        return (String::new(), Vec::new());
+    }
+    let (conditions, params): (Vec<String>, Vec<Vec<SqlValue>>) = filters.iter().map(|f| f.to_sql(backend)).unzip();
+    (
+        format!(" WHERE ({})", conditions.join(" AND ")),
+        params.into_iter().flatten().collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::full_text::CLASSIFIEDS;
+    use crate::json_filter::JsonPath;
+
+    #[test]
+    fn test_filters_combine_in_order() {
+        let filters = [
+            Filter::Matches(CLASSIFIEDS, "boat".to_string()),
+            JsonFilter::Exists(JsonPath::new("metadata", "tags").unwrap()).into(),
+        ];
+        let (sql, params) = where_clause(&filters, BackendType::SQLite);
+        assert_eq!(
+            sql,
+            " WHERE (classifieds.rowid IN (SELECT rowid FROM classifieds_fts WHERE classifieds_fts MATCH ?) AND json_extract(metadata, '$.tags') IS NOT NULL)"
+        );
+        assert_eq!(params.len(), 1);
+    }
+}
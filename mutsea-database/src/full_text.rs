@@ -0,0 +1,219 @@
+//! Full-text search over the text columns of directory tables
+//!
+//! A [`FullTextIndex`] names a table, its key and the columns searched.
+//! On PostgreSQL the columns are concatenated into a `tsvector` with a GIN
+//! expression index, and searches are `@@` against a prefix `tsquery`; on
+//! SQLite an external-content FTS5 table mirrors the columns, kept current
+//! by triggers, and searches are `MATCH` against it. Search text is split
+//! into words and every word must match as a prefix, so "moon caf" finds
+//! "Moonlight Café".
+
+use crate::backends::{BackendType, DatabaseBackend, SqlValue, ToSql};
+use crate::{DatabaseError, Result};
+use uuid::Uuid;
+
+/// The searchable text columns of a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FullTextIndex {
+    pub table: &'static str,
+    /// The UUID column identifying a row
+    pub key: &'static str,
+    pub columns: &'static [&'static str],
+}
+
+/// Classified ads by name and description
+pub const CLASSIFIEDS: FullTextIndex = FullTextIndex {
+    table: "classifieds",
+    key: "classifieduuid",
+    columns: &["name", "description"],
+};
+
+/// Parcels by name and description
+pub const PARCELS: FullTextIndex = FullTextIndex {
+    table: "land",
+    key: "uuid",
+    columns: &["name", "description"],
+};
+
+/// Profiles by their about, first life, wants and skills text
+pub const PROFILES: FullTextIndex = FullTextIndex {
+    table: "userprofile",
+    key: "useruuid",
+    columns: &["profileAboutText", "profileFirstText", "profileWantToText", "profileSkillsText"],
+};
+
+/// Every index the search service uses
+pub const SEARCH_INDEXES: [FullTextIndex; 3] = [CLASSIFIEDS, PARCELS, PROFILES];
+
+impl FullTextIndex {
+    fn fts_table(&self) -> String {
+        format!("{}_fts", self.table)
+    }
+
+    /// The `tsvector` the PostgreSQL index is built on; conditions must
+    /// repeat it exactly for the planner to use the index
+    fn document(&self) -> String {
+        let columns: Vec<String> = self.columns.iter().map(|c| format!("coalesce({}, '')", c)).collect();
+        format!("to_tsvector('simple', {})", columns.join(" || ' ' || "))
+    }
+
+    /// Statements creating the index, and on SQLite the triggers keeping it
+    /// in step with the table
+    pub fn create_statements(&self, backend: BackendType) -> Vec<String> {
+        match backend {
+            BackendType::PostgreSQL => vec![format!(
+                "CREATE INDEX IF NOT EXISTS {}_fts_idx ON {} USING GIN (({}))",
+                self.table,
+                self.table,
+                self.document()
+            )],
+            BackendType::SQLite => {
+                let fts = self.fts_table();
+                let columns = self.columns.join(", ");
+                let values = |row: &str| {
+                    self.columns
+                        .iter()
+                        .map(|c| format!("{}.{}", row, c))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                let delete = format!(
+                    "INSERT INTO {fts}({fts}, rowid, {columns}) VALUES ('delete', old.rowid, {});",
+                    values("old")
+                );
+                let insert = format!("INSERT INTO {fts}(rowid, {columns}) VALUES (new.rowid, {});", values("new"));
+                vec![
+                    format!(
+                        "CREATE VIRTUAL TABLE IF NOT EXISTS {} USING fts5({}, content='{}')",
+                        fts, columns, self.table
+                    ),
+                    format!(
+                        "CREATE TRIGGER IF NOT EXISTS {fts}_ai AFTER INSERT ON {} BEGIN {insert} END",
+                        self.table
+                    ),
+                    format!(
+                        "CREATE TRIGGER IF NOT EXISTS {fts}_ad AFTER DELETE ON {} BEGIN {delete} END",
+                        self.table
+                    ),
+                    format!(
+                        "CREATE TRIGGER IF NOT EXISTS {fts}_au AFTER UPDATE ON {} BEGIN {delete} {insert} END",
+                        self.table
+                    ),
+                ]
+            }
+        }
+    }
+
+    /// Condition matching rows containing every word of `text` as a prefix,
+    /// or every row if `text` has no words
+    pub fn matches(&self, backend: BackendType, text: &str) -> (String, Vec<SqlValue>) {
+        let words = words(text);
+        if words.is_empty() {
+            return ("1 = 1".to_string(), Vec::new());
+        }
+        match backend {
+            BackendType::PostgreSQL => {
+                let query: Vec<String> = words.iter().map(|w| format!("{}:*", w)).collect();
+                (
+                    format!("{} @@ to_tsquery('simple', ?)", self.document()),
+                    vec![SqlValue::Text(query.join(" & "))],
+                )
+            }
+            BackendType::SQLite => {
+                let fts = self.fts_table();
+                let query: Vec<String> = words.iter().map(|w| format!("\"{}\"*", w)).collect();
+                (
+                    format!("{}.rowid IN (SELECT rowid FROM {} WHERE {} MATCH ?)", self.table, fts, fts),
+                    vec![SqlValue::Text(query.join(" "))],
+                )
+            }
+        }
+    }
+}
+
+/// Lowercased words of search text; everything else is a separator, which
+/// also keeps query syntax characters out of the bound search
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Create `index` if it does not exist. A new SQLite index is filled from
+/// the rows already in the table.
+pub async fn create_index(backend: &dyn DatabaseBackend, index: &FullTextIndex) -> Result<()> {
+    let backend_type = backend.backend_type();
+    let existed = backend_type == BackendType::SQLite && backend.table_exists(&index.fts_table()).await?;
+    for statement in index.create_statements(backend_type) {
+        backend.execute(&statement, &[]).await?;
+    }
+    if backend_type == BackendType::SQLite && !existed {
+        let fts = index.fts_table();
+        backend
+            .execute(&format!("INSERT INTO {}({}) VALUES ('rebuild')", fts, fts), &[])
+            .await?;
+    }
+    Ok(())
+}
+
+/// Keys of up to `limit` rows of the index's table matching `text`
+pub async fn search(backend: &dyn DatabaseBackend, index: &FullTextIndex, text: &str, limit: usize) -> Result<Vec<Uuid>> {
+    let (condition, mut params) = index.matches(backend.backend_type(), text);
+    params.push(SqlValue::Int(limit as i64));
+    let query = format!(
+        "SELECT CAST({} AS TEXT) FROM {} WHERE {} LIMIT ?",
+        index.key, index.table, condition
+    );
+    let params: Vec<&dyn ToSql> = params.iter().map(|p| p as &dyn ToSql).collect();
+    backend
+        .query(&query, &params)
+        .await?
+        .iter()
+        .map(|row| {
+            let id: String = row.get(0)?;
+            Uuid::parse_str(&id).map_err(|e| DatabaseError::Serialization(e.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_text_becomes_prefix_words() {
+        assert_eq!(words("  Moon-light CAFÉ's "), vec!["moon", "light", "café", "s"]);
+        assert!(words("\"*\" OR -").is_empty());
+
+        let (sql, params) = CLASSIFIEDS.matches(BackendType::PostgreSQL, "moon caf");
+        assert_eq!(
+            sql,
+            "to_tsvector('simple', coalesce(name, '') || ' ' || coalesce(description, '')) @@ to_tsquery('simple', ?)"
+        );
+        assert!(matches!(&params[0], SqlValue::Text(q) if q == "moon:* & caf:*"));
+
+        let (sql, params) = CLASSIFIEDS.matches(BackendType::SQLite, "moon caf");
+        assert_eq!(
+            sql,
+            "classifieds.rowid IN (SELECT rowid FROM classifieds_fts WHERE classifieds_fts MATCH ?)"
+        );
+        assert!(matches!(&params[0], SqlValue::Text(q) if q == "\"moon\"* \"caf\"*"));
+
+        assert_eq!(PARCELS.matches(BackendType::SQLite, " ").0, "1 = 1");
+    }
+
+    #[test]
+    fn test_index_statements() {
+        let postgres = PARCELS.create_statements(BackendType::PostgreSQL);
+        assert_eq!(
+            postgres,
+            vec!["CREATE INDEX IF NOT EXISTS land_fts_idx ON land USING GIN ((to_tsvector('simple', coalesce(name, '') || ' ' || coalesce(description, ''))))"]
+        );
+
+        let sqlite = PARCELS.create_statements(BackendType::SQLite);
+        assert_eq!(sqlite[0], "CREATE VIRTUAL TABLE IF NOT EXISTS land_fts USING fts5(name, description, content='land')");
+        assert!(sqlite[3].contains("VALUES ('delete', old.rowid, old.name, old.description);"));
+        assert!(sqlite[3].ends_with("VALUES (new.rowid, new.name, new.description); END"));
+    }
+}
@@ -0,0 +1,431 @@
+//! Missing-index suggestions from recorded query statistics
+//!
+//! Each recorded query fingerprint is scanned for the columns it filters
+//! and joins on (`WHERE creator_id = ?`, `JOIN inventoryfolders f ON
+//! f.folderid = i.parentfolderid`). Columns that no existing index leads
+//! with become suggestions, ranked by the time their queries have spent
+//! scaled by how much of it a lookup instead of a scan could save. The
+//! estimate is deliberately rough: it ranks candidates, it does not
+//! predict the new query time.
+
+use crate::backends::{BackendType, DatabaseBackend};
+use crate::utils::{QueryStats, TableStats};
+use crate::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+/// Tables at which a scan costs as much as an index lookup; smaller tables
+/// are barely worth indexing
+const CHEAP_SCAN_ROWS: f64 = 10_000.0;
+
+/// Share of query time assumed saved on tables without statistics
+const UNKNOWN_TABLE_SAVING: f64 = 0.5;
+
+/// One suggested index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexSuggestion {
+    pub table: String,
+    /// Equality columns first, then range columns
+    pub columns: Vec<String>,
+    /// Fingerprints of the queries that would use the index
+    pub queries: Vec<String>,
+    /// Executions of those queries
+    pub calls: u64,
+    /// Time those queries have spent in total
+    pub time_spent: Duration,
+    /// Share of `time_spent` the index is expected to save
+    pub estimated_benefit: Duration,
+    /// Rows in the table, if known
+    pub table_rows: Option<u64>,
+    pub create_statement: String,
+}
+
+impl IndexSuggestion {
+    /// One-line summary for reports
+    pub fn summary(&self) -> String {
+        format!(
+            "Add an index on {}({}): {} calls spent {:.1}s, est. {:.1}s saved",
+            self.table,
+            self.columns.join(", "),
+            self.calls,
+            self.time_spent.as_secs_f64(),
+            self.estimated_benefit.as_secs_f64()
+        )
+    }
+}
+
+/// Suggest indexes for the queries in `stats`, best first. `tables` holds
+/// the existing indexes and row counts; tables missing from it are assumed
+/// to have no secondary indexes.
+pub fn advise(stats: &HashMap<String, QueryStats>, tables: &HashMap<String, TableStats>) -> Vec<IndexSuggestion> {
+    let mut candidates: HashMap<(String, Vec<String>), IndexSuggestion> = HashMap::new();
+
+    for query in stats.values() {
+        for (table, columns) in predicate_columns(&query.query_name) {
+            let table_stats = tables.get(&table);
+            if table_stats.is_some_and(|t| is_covered(t, &columns)) {
+                continue;
+            }
+
+            let entry = candidates
+                .entry((table.clone(), columns.clone()))
+                .or_insert_with(|| IndexSuggestion {
+                    create_statement: format!(
+                        "CREATE INDEX IF NOT EXISTS idx_{}_{} ON {} ({})",
+                        table,
+                        columns.join("_"),
+                        table,
+                        columns.join(", ")
+                    ),
+                    table,
+                    columns,
+                    queries: Vec::new(),
+                    calls: 0,
+                    time_spent: Duration::ZERO,
+                    estimated_benefit: Duration::ZERO,
+                    table_rows: table_stats.map(|t| t.row_count),
+                });
+            entry.queries.push(query.query_name.clone());
+            entry.calls += query.execution_count;
+            entry.time_spent += query.total_execution_time;
+        }
+    }
+
+    let mut suggestions: Vec<_> = candidates
+        .into_values()
+        .map(|mut s| {
+            let saving = match s.table_rows {
+                Some(rows) => rows as f64 / (rows as f64 + CHEAP_SCAN_ROWS),
+                None => UNKNOWN_TABLE_SAVING,
+            };
+            s.estimated_benefit = s.time_spent.mul_f64(saving);
+            s.queries.sort();
+            s
+        })
+        .filter(|s| !s.estimated_benefit.is_zero())
+        .collect();
+    suggestions.sort_by(|a, b| {
+        b.estimated_benefit
+            .cmp(&a.estimated_benefit)
+            .then_with(|| a.table.cmp(&b.table))
+            .then_with(|| a.columns.cmp(&b.columns))
+    });
+    suggestions
+}
+
+/// Whether an index of `table` leads with exactly `columns`, in any order
+fn is_covered(table: &TableStats, columns: &[String]) -> bool {
+    let wanted: BTreeSet<&str> = columns.iter().map(String::as_str).collect();
+    std::iter::once(&table.primary_index)
+        .chain(&table.secondary_indexes)
+        .map(|definition| index_columns(definition))
+        .any(|indexed| {
+            indexed.len() >= wanted.len()
+                && indexed[..wanted.len()].iter().map(String::as_str).collect::<BTreeSet<_>>() == wanted
+        })
+}
+
+/// Columns of an index written as `name(col1,col2)`
+fn index_columns(definition: &str) -> Vec<String> {
+    let Some(open) = definition.find('(') else {
+        return Vec::new();
+    };
+    let close = definition.rfind(')').unwrap_or(definition.len());
+    definition[open + 1..close.max(open + 1)]
+        .split(',')
+        .map(|c| c.trim().trim_matches('"').to_ascii_lowercase())
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+/// Tables and the columns a query filters or joins them on, equality
+/// columns before range columns
+pub fn predicate_columns(sql: &str) -> Vec<(String, Vec<String>)> {
+    let tokens = tokenize(sql);
+    let Some(first) = tokens.first() else {
+        return Vec::new();
+    };
+    // Inserts have nothing to look up
+    if !matches!(first.as_str(), "select" | "with" | "update" | "delete") {
+        return Vec::new();
+    }
+
+    // Tables and their aliases
+    let mut aliases: HashMap<String, String> = HashMap::new();
+    let mut tables: Vec<String> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if !matches!(token.as_str(), "from" | "join" | "update") {
+            continue;
+        }
+        let Some(table) = tokens.get(i + 1).filter(|t| is_identifier(t)) else {
+            continue;
+        };
+        let table = table.rsplit('.').next().unwrap_or(table).to_string();
+        let alias = match tokens.get(i + 2).map(String::as_str) {
+            Some("as") => tokens.get(i + 3),
+            Some(t) if is_identifier(t) && !is_keyword(t) => tokens.get(i + 2),
+            _ => None,
+        };
+        if let Some(alias) = alias {
+            aliases.insert(alias.clone(), table.clone());
+        }
+        aliases.insert(table.clone(), table.clone());
+        if !tables.contains(&table) {
+            tables.push(table);
+        }
+    }
+
+    // Columns compared against something, after WHERE or ON
+    let mut equality: HashMap<String, Vec<String>> = HashMap::new();
+    let mut range: HashMap<String, Vec<String>> = HashMap::new();
+    let mut in_predicate = false;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.as_str() {
+            "where" | "on" => in_predicate = true,
+            "group" | "order" | "limit" | "having" | "returning" | "set" | "union" => in_predicate = false,
+            _ => {}
+        }
+        if !in_predicate || !is_identifier(token) || is_keyword(token) {
+            continue;
+        }
+        let operator = tokens.get(i + 1).map(String::as_str).unwrap_or("");
+        let target = match operator {
+            "=" | "in" => &mut equality,
+            "<" | ">" | "<=" | ">=" | "between" => &mut range,
+            _ => continue,
+        };
+
+        let (table, column) = match token.split_once('.') {
+            Some((qualifier, column)) => match aliases.get(qualifier) {
+                Some(table) => (table.clone(), column.to_string()),
+                None => continue,
+            },
+            // Unqualified columns are only attributable with one table
+            None if tables.len() == 1 => (tables[0].clone(), token.clone()),
+            None => continue,
+        };
+        let columns = target.entry(table).or_default();
+        if !columns.contains(&column) {
+            columns.push(column);
+        }
+
+        // The other side of a join condition is a lookup too
+        if let Some((qualifier, column)) = tokens.get(i + 2).and_then(|t| t.split_once('.')) {
+            if let Some(table) = aliases.get(qualifier) {
+                let columns = target.entry(table.clone()).or_default();
+                if !columns.contains(&column.to_string()) {
+                    columns.push(column.to_string());
+                }
+            }
+        }
+    }
+
+    let mut result: Vec<(String, Vec<String>)> = tables
+        .into_iter()
+        .filter_map(|table| {
+            let mut columns = equality.remove(&table).unwrap_or_default();
+            // Only the first range column can use the index
+            if let Some(first_range) = range.remove(&table).and_then(|r| r.into_iter().find(|c| !columns.contains(c))) {
+                columns.push(first_range);
+            }
+            (!columns.is_empty()).then_some((table, columns))
+        })
+        .collect();
+    result.sort();
+    result
+}
+
+/// Lowercased identifiers, operators and punctuation of `sql`
+fn tokenize(sql: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_alphanumeric() || c == '_' || c == '"' || c == '.' || c == '$' {
+            let mut token = String::new();
+            if c != '"' {
+                token.push(c.to_ascii_lowercase());
+            }
+            while let Some(&next) = chars.peek() {
+                if !(next.is_alphanumeric() || next == '_' || next == '"' || next == '.' || next == '$') {
+                    break;
+                }
+                if next != '"' {
+                    token.push(next.to_ascii_lowercase());
+                }
+                chars.next();
+            }
+            tokens.push(token);
+        } else if matches!(c, '<' | '>' | '!') {
+            let mut token = c.to_string();
+            if chars.peek().is_some_and(|n| matches!(n, '=' | '>')) {
+                token.push(chars.next().unwrap());
+            }
+            tokens.push(token);
+        } else if !c.is_whitespace() {
+            tokens.push(c.to_string());
+        }
+    }
+    tokens
+}
+
+fn is_identifier(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+}
+
+fn is_keyword(token: &str) -> bool {
+    matches!(
+        token,
+        "select" | "from" | "where" | "and" | "or" | "not" | "on" | "join" | "inner" | "left" | "right"
+            | "outer" | "cross" | "as" | "in" | "is" | "null" | "group" | "order" | "by" | "limit"
+            | "offset" | "having" | "set" | "update" | "delete" | "returning" | "union" | "between"
+            | "like" | "exists" | "case" | "when" | "then" | "else" | "end" | "using" | "natural"
+    )
+}
+
+/// Row counts and index columns of every table in the current schema.
+/// Indexes are reported as `name(col1,col2)`.
+pub async fn table_stats(backend: &dyn DatabaseBackend) -> Result<HashMap<String, TableStats>> {
+    let query = match backend.backend_type() {
+        BackendType::PostgreSQL => include_str!("sql/postgresql/performance/select_table_indexes.sql"),
+        BackendType::SQLite => {
+            "SELECT m.name, 0, 0, COALESCE(il.name, ''), COALESCE(group_concat(ii.name, ','), '') \
+             FROM sqlite_master m \
+             LEFT JOIN pragma_index_list(m.name) il \
+             LEFT JOIN pragma_index_info(il.name) ii \
+             WHERE m.type = 'table' AND m.name NOT LIKE 'sqlite_%' \
+             GROUP BY m.name, il.name"
+        }
+    };
+
+    let mut tables: HashMap<String, TableStats> = HashMap::new();
+    for row in backend.query(query, &[]).await? {
+        let table_name: String = row.get(0)?;
+        let index_name: String = row.get(3)?;
+        let index_columns: String = row.get(4)?;
+        let table = tables.entry(table_name.clone()).or_insert_with(|| TableStats {
+            table_name,
+            row_count: 0,
+            avg_row_size: 0,
+            total_size_bytes: 0,
+            primary_index: String::new(),
+            secondary_indexes: Vec::new(),
+            last_analyzed: Utc::now(),
+        });
+        table.row_count = row.get::<i64>(1)?.max(0) as u64;
+        table.total_size_bytes = row.get::<i64>(2)?.max(0) as u64;
+        if index_name.is_empty() {
+            continue;
+        }
+        let definition = format!("{}({})", index_name, index_columns);
+        if table.primary_index.is_empty() && (index_name.ends_with("_pkey") || index_name.starts_with("sqlite_autoindex")) {
+            table.primary_index = definition;
+        } else {
+            table.secondary_indexes.push(definition);
+        }
+    }
+
+    // SQLite keeps no row estimate without ANALYZE, so count
+    if backend.backend_type() == BackendType::SQLite {
+        for table in tables.values_mut() {
+            let row = backend
+                .query_one(&format!("SELECT COUNT(*) FROM \"{}\"", table.table_name), &[])
+                .await?;
+            table.row_count = row.get::<i64>(0)?.max(0) as u64;
+        }
+    }
+    for table in tables.values_mut() {
+        if table.row_count > 0 {
+            table.avg_row_size = (table.total_size_bytes / table.row_count) as usize;
+        }
+    }
+    Ok(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(sql: &str, calls: u64, total: Duration) -> (String, QueryStats) {
+        (
+            sql.to_string(),
+            QueryStats {
+                query_name: sql.to_string(),
+                execution_count: calls,
+                total_execution_time: total,
+                average_execution_time: total / calls as u32,
+                min_execution_time: Duration::ZERO,
+                max_execution_time: total,
+                success_count: calls,
+                error_count: 0,
+                last_executed: Utc::now(),
+                parameters_hash: None,
+                rows_total: 0,
+                slow_count: 0,
+            },
+        )
+    }
+
+    fn table(name: &str, rows: u64, indexes: &[&str]) -> (String, TableStats) {
+        (
+            name.to_string(),
+            TableStats {
+                table_name: name.to_string(),
+                row_count: rows,
+                avg_row_size: 0,
+                total_size_bytes: 0,
+                primary_index: format!("{}_pkey(id)", name),
+                secondary_indexes: indexes.iter().map(|i| i.to_string()).collect(),
+                last_analyzed: Utc::now(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_predicate_columns() {
+        assert_eq!(
+            predicate_columns("SELECT id, name FROM assets WHERE creator_id = ? AND create_time > ?"),
+            vec![("assets".to_string(), vec!["creator_id".to_string(), "create_time".to_string()])]
+        );
+        assert_eq!(
+            predicate_columns(
+                "SELECT i.* FROM inventoryitems i JOIN inventoryfolders AS f ON f.folderID = i.parentFolderID WHERE f.agentID = ?"
+            ),
+            vec![
+                ("inventoryfolders".to_string(), vec!["folderid".to_string(), "agentid".to_string()]),
+                ("inventoryitems".to_string(), vec!["parentfolderid".to_string()]),
+            ]
+        );
+        assert!(predicate_columns("INSERT INTO assets (id) VALUES (?)").is_empty());
+    }
+
+    #[test]
+    fn test_advise_skips_indexed_and_ranks_by_benefit() {
+        let queries = HashMap::from([
+            stats("SELECT * FROM assets WHERE creator_id = ?", 100, Duration::from_secs(10)),
+            stats("SELECT * FROM inventoryitems WHERE parentFolderID = ?", 1000, Duration::from_secs(30)),
+            stats("SELECT * FROM users WHERE id = ?", 500, Duration::from_secs(20)),
+            stats("SELECT * FROM regions WHERE owner_id = ?", 10, Duration::from_secs(1)),
+        ]);
+        let tables = HashMap::from([
+            table("assets", 5_000_000, &[]),
+            table("inventoryitems", 2_000_000, &["idx_inv_owner(avatarid)"]),
+            table("users", 1_000_000, &[]),
+            table("regions", 0, &[]),
+        ]);
+
+        let suggestions = advise(&queries, &tables);
+        let names: Vec<_> = suggestions.iter().map(|s| (s.table.as_str(), s.columns.join(","))).collect();
+        assert_eq!(
+            names,
+            vec![("inventoryitems", "parentfolderid".to_string()), ("assets", "creator_id".to_string())]
+        );
+        assert_eq!(suggestions[0].calls, 1000);
+        assert!(suggestions[0].estimated_benefit > Duration::from_secs(29));
+        assert_eq!(
+            suggestions[1].create_statement,
+            "CREATE INDEX IF NOT EXISTS idx_assets_creator_id ON assets (creator_id)"
+        );
+    }
+}
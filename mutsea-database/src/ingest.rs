@@ -0,0 +1,275 @@
+//! Non-blocking analytics ingestion
+//!
+//! Analytics writes must never stall the simulation loop. Records go into
+//! a fixed-size lock-free ring buffer and a dedicated flusher task writes
+//! them to the database in batches. When the buffer is full the oldest
+//! record is dropped to make room, and the drop is counted, so a slow or
+//! unreachable database costs analytics data instead of frame time.
+
+use async_trait::async_trait;
+use crossbeam_queue::ArrayQueue;
+use mutsea_core::config::AnalyticsIngestConfig;
+use mutsea_core::{MutseaResult, PerformanceRecorder, PerformanceSample};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Fixed-capacity buffer that drops its oldest record when full
+#[derive(Debug)]
+pub struct RingBuffer<T> {
+    queue: ArrayQueue<T>,
+    enqueued: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl<T> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            queue: ArrayQueue::new(capacity.max(1)),
+            enqueued: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Add `record` without blocking, evicting the oldest record if full.
+    /// Returns whether a record was dropped.
+    pub fn push(&self, record: T) -> bool {
+        self.enqueued.fetch_add(1, Ordering::Relaxed);
+        if self.queue.force_push(record).is_some() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            metrics::counter!("mutsea_analytics_dropped_total").increment(1);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Take up to `max` of the oldest records
+    pub fn drain(&self, max: usize) -> Vec<T> {
+        std::iter::from_fn(|| self.queue.pop()).take(max).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    pub fn enqueued(&self) -> u64 {
+        self.enqueued.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Counters of an [`AnalyticsIngest`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IngestStats {
+    /// Records waiting for the flusher
+    pub buffered: usize,
+    pub capacity: usize,
+    /// Records accepted since start
+    pub enqueued: u64,
+    /// Records evicted unwritten because the buffer was full
+    pub dropped: u64,
+    /// Records written to the sink
+    pub flushed: u64,
+    /// Records lost because the sink rejected their batch
+    pub failed: u64,
+}
+
+/// Analytics ingestion facade: a [`PerformanceRecorder`] that only buffers,
+/// with a flusher task writing batches to the real recorder
+pub struct AnalyticsIngest {
+    buffer: RingBuffer<PerformanceSample>,
+    config: AnalyticsIngestConfig,
+    flushed: AtomicU64,
+    failed: AtomicU64,
+    /// Wakes the flusher early once a full batch is waiting
+    batch_ready: Notify,
+    running: AtomicBool,
+    flusher: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl AnalyticsIngest {
+    pub fn new(config: AnalyticsIngestConfig) -> Self {
+        Self {
+            buffer: RingBuffer::new(config.capacity),
+            config,
+            flushed: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            batch_ready: Notify::new(),
+            running: AtomicBool::new(false),
+            flusher: Mutex::new(None),
+        }
+    }
+
+    /// Buffer one sample; never blocks
+    pub fn push(&self, sample: PerformanceSample) {
+        self.buffer.push(sample);
+        if self.buffer.len() >= self.config.batch_size {
+            self.batch_ready.notify_one();
+        }
+    }
+
+    pub fn stats(&self) -> IngestStats {
+        IngestStats {
+            buffered: self.buffer.len(),
+            capacity: self.buffer.capacity(),
+            enqueued: self.buffer.enqueued(),
+            dropped: self.buffer.dropped(),
+            flushed: self.flushed.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Write everything buffered to `sink`, one batch at a time
+    pub async fn flush(&self, sink: &dyn PerformanceRecorder) {
+        loop {
+            let batch = self.buffer.drain(self.config.batch_size.max(1));
+            if batch.is_empty() {
+                return;
+            }
+            match sink.record_performance(&batch).await {
+                Ok(()) => {
+                    self.flushed.fetch_add(batch.len() as u64, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    self.failed.fetch_add(batch.len() as u64, Ordering::Relaxed);
+                    warn!("Dropped {} analytics records, write failed: {}", batch.len(), e);
+                    // Leave the rest for the next round rather than hammering
+                    // a failing database
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Flush to `sink` every `flush_interval_ms`, or sooner when a full
+    /// batch is waiting, until [`stop`](Self::stop)
+    pub fn spawn_flusher(self: &Arc<Self>, sink: Arc<dyn PerformanceRecorder>) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let ingest = Arc::clone(self);
+        let interval = Duration::from_millis(self.config.flush_interval_ms.max(1));
+        let handle = tokio::spawn(async move {
+            while ingest.running.load(Ordering::SeqCst) {
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = ingest.batch_ready.notified() => {}
+                }
+                ingest.flush(sink.as_ref()).await;
+            }
+            // Final flush of whatever arrived before stop
+            ingest.flush(sink.as_ref()).await;
+            debug!("Analytics flusher stopped: {:?}", ingest.stats());
+        });
+        *self.flusher.lock().unwrap() = Some(handle);
+    }
+
+    /// Stop the flusher and wait for its last flush
+    pub async fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+        self.batch_ready.notify_one();
+        let handle = self.flusher.lock().unwrap().take();
+        if let Some(handle) = handle {
+            let _ = handle.await;
+        }
+    }
+}
+
+#[async_trait]
+impl PerformanceRecorder for AnalyticsIngest {
+    async fn record_performance(&self, samples: &[PerformanceSample]) -> MutseaResult<()> {
+        for sample in samples {
+            self.push(sample.clone());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use mutsea_core::MutseaError;
+
+    fn sample(value: f64) -> PerformanceSample {
+        PerformanceSample {
+            timestamp: Utc::now(),
+            component_name: "simulation".to_string(),
+            metric_type: "tick_avg_ms".to_string(),
+            metric_value: value,
+            unit_of_measure: "ms".to_string(),
+            context_data: serde_json::Value::Null,
+            alert_level: None,
+        }
+    }
+
+    #[derive(Default)]
+    struct Sink {
+        written: Mutex<Vec<f64>>,
+        fail: AtomicBool,
+    }
+
+    #[async_trait]
+    impl PerformanceRecorder for Sink {
+        async fn record_performance(&self, samples: &[PerformanceSample]) -> MutseaResult<()> {
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(MutseaError::Database("down".to_string()));
+            }
+            self.written.lock().unwrap().extend(samples.iter().map(|s| s.metric_value));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_drops_oldest() {
+        let buffer = RingBuffer::new(3);
+        for i in 0..5 {
+            buffer.push(i);
+        }
+        assert_eq!(buffer.dropped(), 2);
+        assert_eq!(buffer.enqueued(), 5);
+        assert_eq!(buffer.drain(10), vec![2, 3, 4]);
+        assert!(buffer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_flush_in_batches_and_count_failures() {
+        let ingest = AnalyticsIngest::new(AnalyticsIngestConfig {
+            capacity: 4,
+            flush_interval_ms: 1000,
+            batch_size: 2,
+        });
+        let samples: Vec<_> = (0..6).map(|i| sample(i as f64)).collect();
+        ingest.record_performance(&samples).await.unwrap();
+
+        let sink = Sink::default();
+        ingest.flush(&sink).await;
+        assert_eq!(*sink.written.lock().unwrap(), vec![2.0, 3.0, 4.0, 5.0]);
+
+        ingest.push(sample(6.0));
+        sink.fail.store(true, Ordering::SeqCst);
+        ingest.flush(&sink).await;
+
+        let stats = ingest.stats();
+        assert_eq!(stats.dropped, 2);
+        assert_eq!(stats.flushed, 4);
+        assert_eq!(stats.failed, 1);
+        assert_eq!(stats.buffered, 0);
+    }
+}
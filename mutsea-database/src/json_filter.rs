@@ -0,0 +1,308 @@
+//! Filters on fields nested inside JSON columns
+//!
+//! AI models are stored as serialized structs in JSON columns (`JSONB` on
+//! PostgreSQL, `TEXT` holding JSON on SQLite). A [`JsonFilter`] names a
+//! column and a path into it, and renders to the dialect's operators:
+//! `#>`, `#>>` and `@>` on PostgreSQL, so the GIN indexes on those columns
+//! apply, and `json_extract`/`json_each` on SQLite. Paths are restricted to
+//! identifier and index segments since they are rendered into the SQL;
+//! values are always bound.
+
+use crate::backends::{BackendType, DatabaseBackend, SqlValue, ToSql};
+use crate::{DatabaseError, Result};
+use serde_json::Value;
+use uuid::Uuid;
+
+/// A column and a path of keys (or array indexes) inside it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonPath {
+    column: String,
+    path: Vec<String>,
+}
+
+impl JsonPath {
+    /// `column` at the dotted `path`, e.g. `("personality_data", "traits.openness")`
+    pub fn new(column: &str, path: &str) -> Result<Self> {
+        let valid = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid(column) {
+            return Err(DatabaseError::Validation(format!("invalid JSON column '{}'", column)));
+        }
+        let path: Vec<String> = if path.is_empty() {
+            Vec::new()
+        } else {
+            path.split('.').map(str::to_string).collect()
+        };
+        if let Some(bad) = path.iter().find(|s| !valid(s)) {
+            return Err(DatabaseError::Validation(format!("invalid JSON path segment '{}'", bad)));
+        }
+        Ok(Self {
+            column: column.to_string(),
+            path,
+        })
+    }
+
+    fn child(&self, key: &str) -> Self {
+        let mut path = self.path.clone();
+        path.push(key.to_string());
+        Self {
+            column: self.column.clone(),
+            path,
+        }
+    }
+
+    /// The JSON value at the path
+    fn json(&self, backend: BackendType) -> String {
+        match backend {
+            BackendType::PostgreSQL => format!("{} #> '{{{}}}'", self.column, self.path.join(",")),
+            BackendType::SQLite => format!("json_extract({}, '{}')", self.column, self.sqlite_path()),
+        }
+    }
+
+    /// The value at the path as a number
+    fn number(&self, backend: BackendType) -> String {
+        match backend {
+            BackendType::PostgreSQL => {
+                format!("CAST({} #>> '{{{}}}' AS DOUBLE PRECISION)", self.column, self.path.join(","))
+            }
+            BackendType::SQLite => format!("CAST({} AS REAL)", self.json(backend)),
+        }
+    }
+
+    fn sqlite_path(&self) -> String {
+        let mut path = "$".to_string();
+        for segment in &self.path {
+            if segment.chars().all(|c| c.is_ascii_digit()) {
+                path.push_str(&format!("[{}]", segment));
+            } else {
+                path.push('.');
+                path.push_str(segment);
+            }
+        }
+        path
+    }
+}
+
+/// A condition on a nested JSON field
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonFilter {
+    /// The field equals the value, compared as JSON
+    Eq(JsonPath, Value),
+    /// The field, as a number, compares against the value
+    Gt(JsonPath, f64),
+    Gte(JsonPath, f64),
+    Lt(JsonPath, f64),
+    Lte(JsonPath, f64),
+    /// The field contains the value: every key of an object value with an
+    /// equal value, or an array element equal to a scalar value
+    Contains(JsonPath, Value),
+    /// The field is present and not JSON null
+    Exists(JsonPath),
+}
+
+impl JsonFilter {
+    /// SQL condition and its parameters for `backend`
+    pub fn to_sql(&self, backend: BackendType) -> (String, Vec<SqlValue>) {
+        let compare = |path: &JsonPath, op: &str, value: f64| {
+            (format!("{} {} ?", path.number(backend), op), vec![SqlValue::Float(value)])
+        };
+        match self {
+            JsonFilter::Gt(path, v) => compare(path, ">", *v),
+            JsonFilter::Gte(path, v) => compare(path, ">=", *v),
+            JsonFilter::Lt(path, v) => compare(path, "<", *v),
+            JsonFilter::Lte(path, v) => compare(path, "<=", *v),
+            JsonFilter::Exists(path) => match backend {
+                BackendType::PostgreSQL => (format!("{} <> 'null'::jsonb", path.json(backend)), Vec::new()),
+                BackendType::SQLite => (format!("{} IS NOT NULL", path.json(backend)), Vec::new()),
+            },
+            JsonFilter::Eq(path, value) => match backend {
+                BackendType::PostgreSQL => (
+                    format!("{} = CAST(? AS JSONB)", path.json(backend)),
+                    vec![SqlValue::Text(value.to_string())],
+                ),
+                BackendType::SQLite => sqlite_eq(path, value),
+            },
+            JsonFilter::Contains(path, value) => match backend {
+                BackendType::PostgreSQL => (
+                    format!("{} @> CAST(? AS JSONB)", path.json(backend)),
+                    vec![SqlValue::Text(contained(value).to_string())],
+                ),
+                BackendType::SQLite => sqlite_contains(path, value),
+            },
+        }
+    }
+}
+
+/// What `@>` needs to match a scalar as an array element
+fn contained(value: &Value) -> Value {
+    match value {
+        Value::Object(_) | Value::Array(_) => value.clone(),
+        scalar => Value::Array(vec![scalar.clone()]),
+    }
+}
+
+fn sqlite_eq(path: &JsonPath, value: &Value) -> (String, Vec<SqlValue>) {
+    let field = path.json(BackendType::SQLite);
+    match value {
+        Value::Null => (format!("{} IS NULL", field), Vec::new()),
+        Value::Bool(b) => (format!("{} = ?", field), vec![SqlValue::Int(*b as i64)]),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => (format!("{} = ?", field), vec![SqlValue::Int(i)]),
+            None => (format!("{} = ?", field), vec![SqlValue::Float(n.as_f64().unwrap_or_default())]),
+        },
+        Value::String(s) => (format!("{} = ?", field), vec![SqlValue::Text(s.clone())]),
+        // json_extract returns containers as minified JSON text
+        container => (format!("{} = json(?)", field), vec![SqlValue::Text(container.to_string())]),
+    }
+}
+
+/// SQLite has no containment operator: objects become one condition per
+/// key, scalars an element search with `json_each`
+fn sqlite_contains(path: &JsonPath, value: &Value) -> (String, Vec<SqlValue>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            let parts: Vec<_> = map
+                .iter()
+                .map(|(key, v)| match v {
+                    Value::Object(_) | Value::Array(_) => sqlite_contains(&path.child(key), v),
+                    scalar => sqlite_eq(&path.child(key), scalar),
+                })
+                .collect();
+            combine(parts)
+        }
+        Value::Object(_) => (format!("json_type({}, '{}') = 'object'", path.column, path.sqlite_path()), Vec::new()),
+        Value::Array(items) => combine(items.iter().map(|item| sqlite_contains(path, item)).collect()),
+        scalar => {
+            let (condition, params) = sqlite_eq(&JsonPath::new("value", "").expect("valid column"), scalar);
+            (
+                format!(
+                    "EXISTS (SELECT 1 FROM json_each({}, '{}') WHERE {})",
+                    path.column,
+                    path.sqlite_path(),
+                    condition.replace("json_extract(value, '$')", "value")
+                ),
+                params,
+            )
+        }
+    }
+}
+
+fn combine(parts: Vec<(String, Vec<SqlValue>)>) -> (String, Vec<SqlValue>) {
+    if parts.is_empty() {
+        return ("1 = 1".to_string(), Vec::new());
+    }
+    let (conditions, params): (Vec<String>, Vec<Vec<SqlValue>>) = parts.into_iter().unzip();
+    (format!("({})", conditions.join(" AND ")), params.into_iter().flatten().collect())
+}
+
+/// `WHERE` clause (empty without filters) and parameters for all `filters`
+pub fn where_clause(filters: &[JsonFilter], backend: BackendType) -> (String, Vec<SqlValue>) {
+    if filters.is_empty() {
+        return (String::new(), Vec::new());
+    }
+    let (condition, params) = combine(filters.iter().map(|f| f.to_sql(backend)).collect());
+    (format!(" WHERE {}", condition), params)
+}
+
+/// IDs of the NPCs whose `npc_states` match every filter, e.g. on
+/// `personality_data` or `emotional_state`
+pub async fn find_npc_states(backend: &dyn DatabaseBackend, filters: &[JsonFilter], limit: usize) -> Result<Vec<Uuid>> {
+    select_ids(backend, "npc_id", "npc_states", "updated_at", filters, limit).await
+}
+
+/// IDs of the emergent behaviors matching every filter, e.g. on
+/// `behavior_data` or `impact_metrics`, newest first
+pub async fn find_emergent_behaviors(
+    backend: &dyn DatabaseBackend,
+    filters: &[JsonFilter],
+    limit: usize,
+) -> Result<Vec<Uuid>> {
+    select_ids(backend, "id", "emergent_behaviors", "detection_timestamp", filters, limit).await
+}
+
+async fn select_ids(
+    backend: &dyn DatabaseBackend,
+    id: &str,
+    table: &str,
+    newest: &str,
+    filters: &[JsonFilter],
+    limit: usize,
+) -> Result<Vec<Uuid>> {
+    let (clause, mut params) = where_clause(filters, backend.backend_type());
+    params.push(SqlValue::Int(limit as i64));
+    let query = format!(
+        "SELECT CAST({} AS TEXT) FROM {}{} ORDER BY {} DESC LIMIT ?",
+        id, table, clause, newest
+    );
+    let params: Vec<&dyn ToSql> = params.iter().map(|p| p as &dyn ToSql).collect();
+    backend
+        .query(&query, &params)
+        .await?
+        .iter()
+        .map(|row| {
+            let id: String = row.get(0)?;
+            Uuid::parse_str(&id).map_err(|e| DatabaseError::Serialization(e.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn path(p: &str) -> JsonPath {
+        JsonPath::new("personality_data", p).unwrap()
+    }
+
+    #[test]
+    fn test_paths_are_validated() {
+        assert!(JsonPath::new("personality_data", "traits.openness").is_ok());
+        assert!(JsonPath::new("personality_data", "traits'; DROP TABLE x; --").is_err());
+        assert!(JsonPath::new("bad column", "a").is_err());
+        assert_eq!(path("goals.0.name").sqlite_path(), "$.goals[0].name");
+    }
+
+    #[test]
+    fn test_postgres_operators() {
+        let (sql, params) = JsonFilter::Gte(path("traits.openness"), 0.7).to_sql(BackendType::PostgreSQL);
+        assert_eq!(sql, "CAST(personality_data #>> '{traits,openness}' AS DOUBLE PRECISION) >= ?");
+        assert!(matches!(params[0], SqlValue::Float(v) if v == 0.7));
+
+        let (sql, params) = JsonFilter::Contains(path("tags"), json!("merchant")).to_sql(BackendType::PostgreSQL);
+        assert_eq!(sql, "personality_data #> '{tags}' @> CAST(? AS JSONB)");
+        assert!(matches!(&params[0], SqlValue::Text(t) if t == "[\"merchant\"]"));
+    }
+
+    #[test]
+    fn test_sqlite_operators() {
+        let (sql, params) = JsonFilter::Eq(path("mood"), json!("calm")).to_sql(BackendType::SQLite);
+        assert_eq!(sql, "json_extract(personality_data, '$.mood') = ?");
+        assert!(matches!(&params[0], SqlValue::Text(t) if t == "calm"));
+
+        let (sql, params) = JsonFilter::Contains(path("tags"), json!("merchant")).to_sql(BackendType::SQLite);
+        assert_eq!(sql, "EXISTS (SELECT 1 FROM json_each(personality_data, '$.tags') WHERE value = ?)");
+        assert_eq!(params.len(), 1);
+
+        let (sql, params) =
+            JsonFilter::Contains(path(""), json!({"mood": "calm", "traits": {"loyal": true}})).to_sql(BackendType::SQLite);
+        assert_eq!(
+            sql,
+            "(json_extract(personality_data, '$.mood') = ? AND (json_extract(personality_data, '$.traits.loyal') = ?))"
+        );
+        assert!(matches!(params[1], SqlValue::Int(1)));
+    }
+
+    #[test]
+    fn test_where_clause_joins_filters() {
+        assert_eq!(where_clause(&[], BackendType::SQLite).0, "");
+        let (sql, params) = where_clause(
+            &[JsonFilter::Exists(path("goals")), JsonFilter::Lt(path("traits.anger"), 0.2)],
+            BackendType::SQLite,
+        );
+        assert_eq!(
+            sql,
+            " WHERE (json_extract(personality_data, '$.goals') IS NOT NULL AND CAST(json_extract(personality_data, '$.traits.anger') AS REAL) < ?)"
+        );
+        assert_eq!(params.len(), 1);
+    }
+}
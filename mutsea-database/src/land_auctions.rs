@@ -0,0 +1,318 @@
+//! Land auctions and their bids
+//!
+//! An auction sells one parcel to the highest bidder once it ends. Bids
+//! are kept in `land_auction_bids`; each must beat the highest so far and
+//! the starting bid, checked in the transaction that places it so two
+//! bidders can't both win. Closing an auction records how it ended and,
+//! if sold, the winner and the price paid.
+
+use crate::backends::{DatabaseBackend, Row};
+use crate::{DatabaseError, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const CREATE_AUCTIONS: &str = "CREATE TABLE IF NOT EXISTS land_auctions (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    region_id VARCHAR(36) NOT NULL,
+    parcel_id VARCHAR(36) NOT NULL,
+    seller_id VARCHAR(36) NOT NULL,
+    starting_bid BIGINT NOT NULL,
+    started_at BIGINT NOT NULL,
+    ends_at BIGINT NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    winner_id VARCHAR(36),
+    winning_bid BIGINT
+)";
+const CREATE_AUCTIONS_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS idx_land_auctions_open ON land_auctions (region_id, status, ends_at)";
+const CREATE_BIDS: &str = "CREATE TABLE IF NOT EXISTS land_auction_bids (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    auction_id VARCHAR(36) NOT NULL,
+    bidder_id VARCHAR(36) NOT NULL,
+    amount BIGINT NOT NULL,
+    placed_at BIGINT NOT NULL
+)";
+const CREATE_BIDS_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS idx_land_auction_bids ON land_auction_bids (auction_id, amount)";
+
+const AUCTION_COLUMNS: &str =
+    "id, region_id, parcel_id, seller_id, starting_bid, started_at, ends_at, status, winner_id, winning_bid";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuctionStatus {
+    /// Taking bids, or ended and waiting to be settled
+    Open,
+    /// Paid for by the winner, who owns the parcel
+    Sold,
+    /// Ended without a bid the bidder could pay
+    Unsold,
+    /// Called off before it ended
+    Cancelled,
+}
+
+impl AuctionStatus {
+    fn name(&self) -> &'static str {
+        match self {
+            AuctionStatus::Open => "open",
+            AuctionStatus::Sold => "sold",
+            AuctionStatus::Unsold => "unsold",
+            AuctionStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "open" => Ok(AuctionStatus::Open),
+            "sold" => Ok(AuctionStatus::Sold),
+            "unsold" => Ok(AuctionStatus::Unsold),
+            "cancelled" => Ok(AuctionStatus::Cancelled),
+            other => Err(DatabaseError::Serialization(format!("unknown auction status '{}'", other))),
+        }
+    }
+}
+
+/// A parcel auctioned off
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LandAuction {
+    pub id: Uuid,
+    pub region_id: Uuid,
+    pub parcel_id: Uuid,
+    /// Who is paid the winning bid
+    pub seller_id: Uuid,
+    pub starting_bid: i64,
+    pub started_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub status: AuctionStatus,
+    pub winner_id: Option<Uuid>,
+    pub winning_bid: Option<i64>,
+}
+
+/// A bid on an auction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bid {
+    pub id: Uuid,
+    pub auction_id: Uuid,
+    pub bidder_id: Uuid,
+    pub amount: i64,
+    pub placed_at: DateTime<Utc>,
+}
+
+/// The lowest bid accepted after `highest`
+pub fn minimum_bid(starting_bid: i64, highest: Option<i64>) -> i64 {
+    highest.map_or(starting_bid, |highest| starting_bid.max(highest + 1))
+}
+
+fn uuid(text: String) -> Result<Uuid> {
+    Uuid::parse_str(&text).map_err(|e| DatabaseError::Serialization(e.to_string()))
+}
+
+fn time(millis: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(millis).unwrap_or_default()
+}
+
+fn row_auction<R: Row + ?Sized>(row: &R) -> Result<LandAuction> {
+    let status: String = row.get(7)?;
+    let winner_id: Option<String> = row.get(8)?;
+    Ok(LandAuction {
+        id: uuid(row.get(0)?)?,
+        region_id: uuid(row.get(1)?)?,
+        parcel_id: uuid(row.get(2)?)?,
+        seller_id: uuid(row.get(3)?)?,
+        starting_bid: row.get(4)?,
+        started_at: time(row.get(5)?),
+        ends_at: time(row.get(6)?),
+        status: AuctionStatus::parse(&status)?,
+        winner_id: winner_id.map(uuid).transpose()?,
+        winning_bid: row.get(9)?,
+    })
+}
+
+/// Create the auction and bid tables
+pub async fn ensure_schema(backend: &dyn DatabaseBackend) -> Result<()> {
+    for statement in [CREATE_AUCTIONS, CREATE_AUCTIONS_INDEX, CREATE_BIDS, CREATE_BIDS_INDEX] {
+        backend.execute(statement, &[]).await?;
+    }
+    Ok(())
+}
+
+/// Store a new auction
+pub async fn create(backend: &dyn DatabaseBackend, auction: &LandAuction) -> Result<()> {
+    backend
+        .execute(
+            &format!("INSERT INTO land_auctions ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)", AUCTION_COLUMNS),
+            &[
+                &auction.id.to_string(),
+                &auction.region_id.to_string(),
+                &auction.parcel_id.to_string(),
+                &auction.seller_id.to_string(),
+                &auction.starting_bid,
+                &auction.started_at.timestamp_millis(),
+                &auction.ends_at.timestamp_millis(),
+                &auction.status.name(),
+                &auction.winner_id.map(|id| id.to_string()),
+                &auction.winning_bid,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// The auction `id`
+pub async fn get(backend: &dyn DatabaseBackend, id: Uuid) -> Result<Option<LandAuction>> {
+    let rows = backend
+        .query(
+            &format!("SELECT {} FROM land_auctions WHERE id = ?", AUCTION_COLUMNS),
+            &[&id.to_string()],
+        )
+        .await?;
+    rows.first().map(|row| row_auction(row.as_ref())).transpose()
+}
+
+/// The open auctions of `region_id`, ending first first
+pub async fn open(backend: &dyn DatabaseBackend, region_id: Uuid) -> Result<Vec<LandAuction>> {
+    backend
+        .query(
+            &format!(
+                "SELECT {} FROM land_auctions WHERE region_id = ? AND status = 'open' ORDER BY ends_at",
+                AUCTION_COLUMNS
+            ),
+            &[&region_id.to_string()],
+        )
+        .await?
+        .iter()
+        .map(|row| row_auction(row.as_ref()))
+        .collect()
+}
+
+/// Bid `amount` on auction `auction_id` for `bidder_id`, refused with a
+/// validation error when the auction is over or the bid too low
+pub async fn place_bid(
+    backend: &dyn DatabaseBackend,
+    auction_id: Uuid,
+    bidder_id: Uuid,
+    amount: i64,
+    now: DateTime<Utc>,
+) -> Result<Bid> {
+    let id = auction_id.to_string();
+    let mut transaction = backend.begin_transaction().await?;
+    let rows = transaction
+        .query(
+            "SELECT starting_bid, ends_at, status, \
+             (SELECT MAX(amount) FROM land_auction_bids WHERE auction_id = land_auctions.id) \
+             FROM land_auctions WHERE id = ?",
+            &[&id],
+        )
+        .await?;
+    let row = rows
+        .first()
+        .ok_or_else(|| DatabaseError::NotFound(format!("land auction {}", auction_id)))?;
+    let starting_bid: i64 = row.get(0)?;
+    let ends_at: i64 = row.get(1)?;
+    let status: String = row.get(2)?;
+    let highest: Option<i64> = row.get(3)?;
+    let minimum = minimum_bid(starting_bid, highest);
+    let refusal = if status != AuctionStatus::Open.name() || ends_at <= now.timestamp_millis() {
+        Some("The auction has ended".to_string())
+    } else if amount < minimum {
+        Some(format!("Bids must be at least L${}", minimum))
+    } else {
+        None
+    };
+    if let Some(refusal) = refusal {
+        transaction.rollback().await?;
+        return Err(DatabaseError::Validation(refusal));
+    }
+
+    let bid = Bid {
+        id: Uuid::new_v4(),
+        auction_id,
+        bidder_id,
+        amount,
+        placed_at: now,
+    };
+    transaction
+        .execute(
+            "INSERT INTO land_auction_bids (id, auction_id, bidder_id, amount, placed_at) VALUES (?, ?, ?, ?, ?)",
+            &[
+                &bid.id.to_string(),
+                &id,
+                &bidder_id.to_string(),
+                &amount,
+                &now.timestamp_millis(),
+            ],
+        )
+        .await?;
+    transaction.commit().await?;
+    Ok(bid)
+}
+
+/// The bids on auction `auction_id`, highest first
+pub async fn bids(backend: &dyn DatabaseBackend, auction_id: Uuid) -> Result<Vec<Bid>> {
+    backend
+        .query(
+            "SELECT id, bidder_id, amount, placed_at FROM land_auction_bids \
+             WHERE auction_id = ? ORDER BY amount DESC, placed_at",
+            &[&auction_id.to_string()],
+        )
+        .await?
+        .iter()
+        .map(|row| {
+            Ok(Bid {
+                id: uuid(row.get(0)?)?,
+                auction_id,
+                bidder_id: uuid(row.get(1)?)?,
+                amount: row.get(2)?,
+                placed_at: time(row.get(3)?),
+            })
+        })
+        .collect()
+}
+
+/// Record how auction `auction_id` ended, with the winner and what they
+/// paid if it sold
+pub async fn close(
+    backend: &dyn DatabaseBackend,
+    auction_id: Uuid,
+    status: AuctionStatus,
+    winner: Option<(Uuid, i64)>,
+) -> Result<()> {
+    backend
+        .execute(
+            "UPDATE land_auctions SET status = ?, winner_id = ?, winning_bid = ? WHERE id = ?",
+            &[
+                &status.name(),
+                &winner.map(|(id, _)| id.to_string()),
+                &winner.map(|(_, amount)| amount),
+                &auction_id.to_string(),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bids_must_beat_the_highest() {
+        assert_eq!(minimum_bid(100, None), 100);
+        assert_eq!(minimum_bid(100, Some(150)), 151);
+        assert_eq!(minimum_bid(100, Some(50)), 100);
+    }
+
+    #[test]
+    fn test_status_names_round_trip() {
+        for status in [
+            AuctionStatus::Open,
+            AuctionStatus::Sold,
+            AuctionStatus::Unsold,
+            AuctionStatus::Cancelled,
+        ] {
+            assert_eq!(AuctionStatus::parse(status.name()).unwrap(), status);
+        }
+        assert!(AuctionStatus::parse("pending").is_err());
+    }
+}
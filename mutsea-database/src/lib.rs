@@ -5,10 +5,33 @@
 //! with compatibility for OpenSim's existing database schema.
 
 pub mod error;
+pub mod ai_decisions;
+pub mod ai_feedback;
 pub mod backends;
+pub mod chat_log;
+pub mod economy;
+pub mod ecosystem;
+pub mod filter;
+pub mod full_text;
+pub mod index_advisor;
+pub mod ingest;
+pub mod land_auctions;
+pub mod json_filter;
+pub mod maintenance;
 pub mod manager;
 pub mod metrics;
+pub mod npc_memory;
+pub mod performance;
+pub mod player_behavior;
+pub mod pool_monitor;
+pub mod privacy;
+pub mod query_plans;
+pub mod query_stats;
+pub mod rollups;
+pub mod sharding;
+pub mod training_export;
 pub mod utils;
+pub mod world_events;
 
 // OpenSim Compatibility Layer
 #[cfg(feature = "opensim-compat")]
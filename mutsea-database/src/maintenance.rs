@@ -0,0 +1,382 @@
+//! Database maintenance: vacuum, statistics and integrity checks
+//!
+//! [`optimize`] runs what each backend needs to stay fast: `VACUUM
+//! (ANALYZE)` on PostgreSQL, and an integrity check, `ANALYZE` and `VACUUM`
+//! on SQLite. By default the whole database is handled in one pass; with
+//! [`OptimizeOptions::per_table`] every table gets its own step, so a busy
+//! table is put back in the queue and retried after the others instead of
+//! blocking the run. PostgreSQL steps check `pg_locks` before starting;
+//! SQLite steps are retried when the database reports itself locked.
+//! Every step is recorded in `maintenance_log`.
+
+use crate::backends::{BackendType, DatabaseBackend};
+use crate::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+const CREATE_MAINTENANCE_LOG: &str = "CREATE TABLE IF NOT EXISTS maintenance_log (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    run_id VARCHAR(36) NOT NULL,
+    started_at BIGINT NOT NULL,
+    table_name VARCHAR(128) NOT NULL,
+    operation VARCHAR(32) NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    attempts INTEGER NOT NULL,
+    detail TEXT NOT NULL
+)";
+
+const INSERT_MAINTENANCE_LOG: &str = "INSERT INTO maintenance_log (
+    id, run_id, started_at, table_name, operation, status, duration_ms, attempts, detail
+) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)";
+
+/// Locks held by other sessions that conflict with VACUUM's
+/// SHARE UPDATE EXCLUSIVE lock
+const SELECT_CONFLICTING_LOCKS: &str = "SELECT COUNT(*) FROM pg_locks l \
+     JOIN pg_class c ON c.oid = l.relation \
+     WHERE c.relname = ? AND l.granted AND l.pid <> pg_backend_pid() \
+     AND l.mode IN ('ShareUpdateExclusiveLock', 'ShareLock', 'ShareRowExclusiveLock', \
+     'ExclusiveLock', 'AccessExclusiveLock')";
+
+/// Table name recorded for whole-database steps
+pub const ALL_TABLES: &str = "*";
+
+/// How [`optimize`] runs
+#[derive(Debug, Clone)]
+pub struct OptimizeOptions {
+    /// One step per table instead of one for the whole database
+    pub per_table: bool,
+    /// Restrict to these tables; empty means every table
+    pub tables: Vec<String>,
+    /// Rewrite tables with `VACUUM FULL` (PostgreSQL); takes an exclusive lock
+    pub full: bool,
+    /// Times a busy table is put back in the queue before it is skipped
+    pub lock_retries: u32,
+    /// Pause before retrying a busy table
+    pub lock_wait: Duration,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        Self {
+            per_table: false,
+            tables: Vec::new(),
+            full: false,
+            lock_retries: 3,
+            lock_wait: Duration::from_secs(30),
+        }
+    }
+}
+
+/// One maintenance statement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    VacuumAnalyze,
+    VacuumFullAnalyze,
+    Vacuum,
+    /// Reclaim free pages of an `auto_vacuum = INCREMENTAL` SQLite file
+    IncrementalVacuum,
+    Analyze,
+    IntegrityCheck,
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::VacuumAnalyze => "vacuum_analyze",
+            Operation::VacuumFullAnalyze => "vacuum_full_analyze",
+            Operation::Vacuum => "vacuum",
+            Operation::IncrementalVacuum => "incremental_vacuum",
+            Operation::Analyze => "analyze",
+            Operation::IntegrityCheck => "integrity_check",
+        }
+    }
+
+    /// The statement for `table`, or the whole database when `None`
+    pub fn statement(&self, table: Option<&str>) -> String {
+        let target = table.map(|t| format!(" \"{}\"", t)).unwrap_or_default();
+        match self {
+            Operation::VacuumAnalyze => format!("VACUUM (ANALYZE){}", target),
+            Operation::VacuumFullAnalyze => format!("VACUUM (FULL, ANALYZE){}", target),
+            Operation::Vacuum => "VACUUM".to_string(),
+            Operation::IncrementalVacuum => "PRAGMA incremental_vacuum".to_string(),
+            Operation::Analyze => format!("ANALYZE{}", target),
+            Operation::IntegrityCheck => match table {
+                Some(t) => format!("PRAGMA integrity_check(\"{}\")", t),
+                None => "PRAGMA integrity_check".to_string(),
+            },
+        }
+    }
+}
+
+/// A planned step
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    pub table: Option<String>,
+    pub operation: Operation,
+}
+
+/// Outcome of a step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Ok,
+    /// The table stayed locked through every retry
+    Skipped,
+    Failed,
+}
+
+impl StepStatus {
+    fn name(&self) -> &'static str {
+        match self {
+            StepStatus::Ok => "ok",
+            StepStatus::Skipped => "skipped",
+            StepStatus::Failed => "failed",
+        }
+    }
+}
+
+/// A step as run and logged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceStep {
+    pub table: String,
+    pub operation: Operation,
+    pub status: StepStatus,
+    pub duration_ms: u64,
+    pub attempts: u32,
+    pub detail: String,
+}
+
+/// Outcome of an [`optimize`] run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptimizeReport {
+    pub run_id: String,
+    pub steps: Vec<MaintenanceStep>,
+}
+
+impl OptimizeReport {
+    pub fn failed(&self) -> usize {
+        self.steps.iter().filter(|s| s.status == StepStatus::Failed).count()
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.steps.iter().filter(|s| s.status == StepStatus::Skipped).count()
+    }
+}
+
+/// Steps for `backend` over `tables`, in the order they should run.
+/// SQLite vacuums the whole file in any case, so per-table runs end with
+/// one database-wide vacuum; `incremental` picks `PRAGMA incremental_vacuum`
+/// for it
+pub fn plan(backend: BackendType, options: &OptimizeOptions, tables: &[String], incremental: bool) -> Vec<Task> {
+    let task = |table: Option<&String>, operation| Task {
+        table: table.cloned(),
+        operation,
+    };
+    match (backend, options.per_table) {
+        (BackendType::PostgreSQL, per_table) => {
+            let operation = if options.full {
+                Operation::VacuumFullAnalyze
+            } else {
+                Operation::VacuumAnalyze
+            };
+            if per_table || !options.tables.is_empty() {
+                tables.iter().map(|t| task(Some(t), operation)).collect()
+            } else {
+                vec![task(None, operation)]
+            }
+        }
+        (BackendType::SQLite, true) => {
+            let mut tasks: Vec<Task> = tables
+                .iter()
+                .flat_map(|t| [task(Some(t), Operation::IntegrityCheck), task(Some(t), Operation::Analyze)])
+                .collect();
+            let vacuum = if incremental { Operation::IncrementalVacuum } else { Operation::Vacuum };
+            tasks.push(task(None, vacuum));
+            tasks
+        }
+        (BackendType::SQLite, false) => vec![
+            task(None, Operation::IntegrityCheck),
+            task(None, Operation::Vacuum),
+            task(None, Operation::Analyze),
+        ],
+    }
+}
+
+/// Run the maintenance planned for `backend` and record every step in
+/// `maintenance_log`
+pub async fn optimize(backend: &dyn DatabaseBackend, options: &OptimizeOptions) -> Result<OptimizeReport> {
+    backend.execute(CREATE_MAINTENANCE_LOG, &[]).await?;
+
+    let mut tables = list_tables(backend).await?;
+    if !options.tables.is_empty() {
+        tables.retain(|t| options.tables.contains(t));
+    }
+    let incremental = backend.backend_type() == BackendType::SQLite
+        && backend.query_one("PRAGMA auto_vacuum", &[]).await?.get::<i64>(0)? == 2;
+
+    let run_id = uuid::Uuid::new_v4().to_string();
+    let mut report = OptimizeReport {
+        run_id: run_id.clone(),
+        steps: Vec::new(),
+    };
+    let mut queue: VecDeque<(Task, u32)> = plan(backend.backend_type(), options, &tables, incremental)
+        .into_iter()
+        .map(|task| (task, 0))
+        .collect();
+    // A failed integrity check leaves the file alone
+    let mut corrupt = false;
+
+    while let Some((task, attempts)) = queue.pop_front() {
+        let table = task.table.clone().unwrap_or_else(|| ALL_TABLES.to_string());
+        if attempts > 0 {
+            tokio::time::sleep(options.lock_wait).await;
+        }
+        let started_at = Utc::now().timestamp();
+        let started = Instant::now();
+
+        let outcome = if corrupt && matches!(task.operation, Operation::Vacuum | Operation::IncrementalVacuum) {
+            Err((StepStatus::Skipped, "integrity check failed".to_string()))
+        } else {
+            run_task(backend, &task).await
+        };
+        let (status, detail) = match outcome {
+            Ok(detail) => (StepStatus::Ok, detail),
+            Err((StepStatus::Skipped, detail)) if attempts < options.lock_retries => {
+                info!("{} on {} is locked, retrying later: {}", task.operation.name(), table, detail);
+                queue.push_back((task, attempts + 1));
+                continue;
+            }
+            Err(failure) => failure,
+        };
+        if task.operation == Operation::IntegrityCheck && status == StepStatus::Failed {
+            corrupt = true;
+        }
+
+        let step = MaintenanceStep {
+            table,
+            operation: task.operation,
+            status,
+            duration_ms: started.elapsed().as_millis() as u64,
+            attempts: attempts + 1,
+            detail,
+        };
+        match step.status {
+            StepStatus::Ok => info!("{} on {}: done in {}ms", step.operation.name(), step.table, step.duration_ms),
+            _ => warn!("{} on {}: {} ({})", step.operation.name(), step.table, step.status.name(), step.detail),
+        }
+        backend
+            .execute(
+                INSERT_MAINTENANCE_LOG,
+                &[
+                    &uuid::Uuid::new_v4().to_string(),
+                    &run_id,
+                    &started_at,
+                    &step.table,
+                    &step.operation.name(),
+                    &step.status.name(),
+                    &(step.duration_ms as i64),
+                    &(step.attempts as i64),
+                    &step.detail,
+                ],
+            )
+            .await?;
+        report.steps.push(step);
+    }
+    Ok(report)
+}
+
+/// Run one step; `Err` carries `Skipped` when the table was busy
+async fn run_task(backend: &dyn DatabaseBackend, task: &Task) -> std::result::Result<String, (StepStatus, String)> {
+    let failed = |e: crate::DatabaseError| {
+        let message = e.to_string();
+        if message.to_lowercase().contains("lock") {
+            (StepStatus::Skipped, message)
+        } else {
+            (StepStatus::Failed, message)
+        }
+    };
+
+    if let (BackendType::PostgreSQL, Some(table)) = (backend.backend_type(), &task.table) {
+        let row = backend.query_one(SELECT_CONFLICTING_LOCKS, &[table]).await.map_err(failed)?;
+        let locks = row.get::<i64>(0).map_err(failed)?;
+        if locks > 0 {
+            return Err((StepStatus::Skipped, format!("{} conflicting lock(s) held", locks)));
+        }
+    }
+
+    let statement = task.operation.statement(task.table.as_deref());
+    if task.operation == Operation::IntegrityCheck {
+        let rows = backend.query(&statement, &[]).await.map_err(failed)?;
+        let messages = rows
+            .iter()
+            .map(|row| row.get::<String>(0))
+            .collect::<Result<Vec<_>>>()
+            .map_err(failed)?;
+        return match messages.as_slice() {
+            [ok] if ok == "ok" => Ok("ok".to_string()),
+            _ => Err((StepStatus::Failed, messages.join("; "))),
+        };
+    }
+    backend.execute(&statement, &[]).await.map_err(failed)?;
+    Ok(String::new())
+}
+
+async fn list_tables(backend: &dyn DatabaseBackend) -> Result<Vec<String>> {
+    let query = match backend.backend_type() {
+        BackendType::PostgreSQL => {
+            "SELECT tablename FROM pg_tables WHERE schemaname = current_schema() ORDER BY tablename"
+        }
+        BackendType::SQLite => {
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name"
+        }
+    };
+    backend
+        .query(query, &[])
+        .await?
+        .iter()
+        .map(|row| row.get::<String>(0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tables() -> Vec<String> {
+        vec!["assets".to_string(), "regions".to_string()]
+    }
+
+    #[test]
+    fn test_postgres_plan() {
+        let options = OptimizeOptions::default();
+        let whole = plan(BackendType::PostgreSQL, &options, &tables(), false);
+        assert_eq!(whole, vec![Task { table: None, operation: Operation::VacuumAnalyze }]);
+        assert_eq!(whole[0].operation.statement(None), "VACUUM (ANALYZE)");
+
+        let options = OptimizeOptions { per_table: true, full: true, ..Default::default() };
+        let per_table = plan(BackendType::PostgreSQL, &options, &tables(), false);
+        assert_eq!(per_table.len(), 2);
+        assert_eq!(
+            per_table[1].operation.statement(per_table[1].table.as_deref()),
+            "VACUUM (FULL, ANALYZE) \"regions\""
+        );
+    }
+
+    #[test]
+    fn test_sqlite_plan_checks_before_vacuum() {
+        let whole = plan(BackendType::SQLite, &OptimizeOptions::default(), &tables(), false);
+        let operations: Vec<_> = whole.iter().map(|t| t.operation).collect();
+        assert_eq!(operations, vec![Operation::IntegrityCheck, Operation::Vacuum, Operation::Analyze]);
+
+        let options = OptimizeOptions { per_table: true, ..Default::default() };
+        let per_table = plan(BackendType::SQLite, &options, &tables(), true);
+        assert_eq!(per_table.len(), 5);
+        assert_eq!(per_table[0].operation.statement(Some("assets")), "PRAGMA integrity_check(\"assets\")");
+        assert_eq!(per_table[4], Task { table: None, operation: Operation::IncrementalVacuum });
+    }
+}
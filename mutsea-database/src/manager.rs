@@ -2,14 +2,31 @@
 //! Database manager for coordinating operations
 
 use crate::{
+    ai_feedback::DecisionFeedback,
     backends::{DatabasePool, DatabaseBackend},
+    chat_log::{ChatLogEntry, ChatLogQuery},
     error::DatabaseResult,
+    index_advisor::IndexSuggestion,
+    full_text::FullTextIndex,
+    json_filter::JsonFilter,
+    land_auctions::{AuctionStatus, Bid, LandAuction},
     Result, DatabaseError,
     metrics::DatabaseMetrics,
+    pool_monitor::{MonitoredBackend, PoolMonitor},
+    privacy::{ForgetReport, PersonalDataBundle},
+    world_events::{WorldEvent, WorldState},
+    query_stats::{InstrumentedBackend, QueryAnalytics},
+    sharding::AssetSharding,
+    traits::query_builder::DatabaseDialect,
+    utils::{DatabaseUtils, ExecutionResult},
 };
 
+use mutsea_core::circuit_breaker::CircuitBreaker;
+use mutsea_core::config::{CircuitBreakerConfig, RetryConfig};
+use mutsea_core::ErrorClassification;
+use mutsea_core::retry::{Retrier, RetryStats};
 use std::sync::{atomic::{AtomicU64, Ordering}, Arc};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, info_span, Instrument};
 use std::time::Duration;
 use tokio::sync::RwLock;
 
@@ -21,6 +38,12 @@ pub struct DatabaseManager {
     failed_queries: AtomicU64,
     avg_query_time_ms: AtomicU64,
     metrics: Arc<RwLock<DatabaseMetrics>>, 
+    retrier: Retrier,
+    breaker: Arc<CircuitBreaker>,
+    next_query_id: AtomicU64,
+    analytics: Arc<QueryAnalytics>,
+    pool_monitor: Arc<PoolMonitor>,
+    asset_sharding: AssetSharding,
 }
 
 impl DatabaseManager {
@@ -39,23 +62,180 @@ impl DatabaseManager {
             failed_queries: AtomicU64::new(0),
             avg_query_time_ms: AtomicU64::new(0),
             metrics: Arc::new(RwLock::new(DatabaseMetrics::default())),
+            retrier: Retrier::from_config("database", &RetryConfig::default()),
+            breaker: Arc::new(CircuitBreaker::new("database", CircuitBreakerConfig::default())),
+            next_query_id: AtomicU64::new(1),
+            analytics: Arc::new(QueryAnalytics::default()),
+            pool_monitor: Arc::new(PoolMonitor::default()),
+            asset_sharding: AssetSharding::unsharded(),
         })
     }
 
+    /// Fail fast through `breaker` while the database is degraded, usually
+    /// the shared `database` breaker from a [`CircuitBreakerRegistry`]
+    ///
+    /// [`CircuitBreakerRegistry`]: mutsea_core::circuit_breaker::CircuitBreakerRegistry
+    pub fn with_circuit_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.breaker = breaker;
+        self
+    }
+
+    /// Breaker guarding this database
+    pub fn circuit_breaker(&self) -> &Arc<CircuitBreaker> {
+        &self.breaker
+    }
+
+    /// Route asset queries to the shards of `sharding`
+    pub fn with_asset_sharding(mut self, sharding: AssetSharding) -> Self {
+        self.asset_sharding = sharding;
+        self
+    }
+
+    /// How the assets table is sharded
+    pub fn asset_sharding(&self) -> &AssetSharding {
+        &self.asset_sharding
+    }
+
+    /// Retry transient failures (lost connections, deadlocks) with `retrier`
+    pub fn with_retrier(mut self, retrier: Retrier) -> Self {
+        self.retrier = retrier;
+        self
+    }
+
+    /// Record queries into `analytics`, which sets the slow-query and
+    /// plan-capture thresholds
+    pub fn with_query_analytics(mut self, analytics: QueryAnalytics) -> Self {
+        self.analytics = Arc::new(analytics);
+        self
+    }
+
+    /// Track checkouts and leaks with `monitor`, usually
+    /// [`PoolMonitor::from_config`]
+    pub fn with_pool_monitor(mut self, monitor: PoolMonitor) -> Self {
+        self.pool_monitor = Arc::new(monitor);
+        self
+    }
+
+    /// Checkout gauges and leak detection for this manager's backends
+    pub fn pool_monitor(&self) -> &Arc<PoolMonitor> {
+        &self.pool_monitor
+    }
+
+    /// Per-query statistics for everything run through this manager
+    pub fn query_analytics(&self) -> &Arc<QueryAnalytics> {
+        &self.analytics
+    }
+
+    /// Retry and give-up counts for database operations
+    pub fn retry_stats(&self) -> RetryStats {
+        self.retrier.stats()
+    }
+
     /// Run database migrations
     pub async fn migrate(&self) -> crate::DatabaseResult<()> {
         self.pool.migrate().await
     }
     
-    /// Get a database backend instance
+    /// Get a database backend instance; its queries are recorded in
+    /// [`query_analytics`](Self::query_analytics) and it counts as checked
+    /// out in [`pool_monitor`](Self::pool_monitor) until dropped
     pub async fn get_backend(&self) -> Result<Box<dyn DatabaseBackend>> {
-        Ok(self.pool.get_backend())
+        let instrumented = InstrumentedBackend::new(self.pool.get_backend(), Arc::clone(&self.analytics));
+        Ok(Box::new(MonitoredBackend::new(Box::new(instrumented), Arc::clone(&self.pool_monitor))))
     }
     
     /// Get the backend type
     pub fn backend_type(&self) -> crate::backends::BackendType {
         self.pool.backend_type()
     }
+
+    /// Indexes that would speed up the recorded queries, best first
+    pub async fn index_advice(&self) -> DatabaseResult<Vec<IndexSuggestion>> {
+        // Uninstrumented, so the catalog queries are not advised on
+        let backend = self.pool.get_backend();
+        let tables = crate::index_advisor::table_stats(backend.as_ref()).await?;
+        Ok(crate::index_advisor::advise(&self.analytics.snapshot(), &tables))
+    }
+
+    /// Attach an observed outcome or feedback score to a stored AI
+    /// decision. Returns false if there is no such decision.
+    pub async fn record_decision_feedback(&self, decision_id: uuid::Uuid, feedback: &DecisionFeedback) -> DatabaseResult<bool> {
+        let backend = self.get_backend().await?;
+        crate::ai_feedback::record_feedback(backend.as_ref(), decision_id, feedback).await
+    }
+
+    /// NPCs whose stored state matches every JSON filter, most recently
+    /// updated first
+    pub async fn find_npc_states(&self, filters: &[JsonFilter], limit: usize) -> DatabaseResult<Vec<uuid::Uuid>> {
+        let backend = self.get_backend().await?;
+        crate::json_filter::find_npc_states(backend.as_ref(), filters, limit).await
+    }
+
+    /// Emergent behaviors matching every JSON filter, newest first
+    pub async fn find_emergent_behaviors(&self, filters: &[JsonFilter], limit: usize) -> DatabaseResult<Vec<uuid::Uuid>> {
+        let backend = self.get_backend().await?;
+        crate::json_filter::find_emergent_behaviors(backend.as_ref(), filters, limit).await
+    }
+
+    /// Create the full-text indexes used by directory search
+    pub async fn create_search_indexes(&self) -> DatabaseResult<()> {
+        let backend = self.get_backend().await?;
+        for index in &crate::full_text::SEARCH_INDEXES {
+            crate::full_text::create_index(backend.as_ref(), index).await?;
+        }
+        Ok(())
+    }
+
+    /// Keys of up to `limit` rows of `index` matching the search `text`
+    pub async fn search_text(&self, index: &FullTextIndex, text: &str, limit: usize) -> DatabaseResult<Vec<uuid::Uuid>> {
+        let backend = self.get_backend().await?;
+        crate::full_text::search(backend.as_ref(), index, text, limit).await
+    }
+
+    /// Wrap the outcome of running `sql`, attaching the plan captured for
+    /// it if one has been
+    pub fn execution_result<T>(&self, sql: &str, data: T, execution_time: Duration) -> ExecutionResult<T> {
+        let result = ExecutionResult::new(data, execution_time);
+        match self.analytics.plan_for(sql) {
+            Some(captured) => result.with_query_plan(captured.plan),
+            None => result,
+        }
+    }
+
+    /// Plan and optimizer hints for `sql`: the plan captured when it last
+    /// ran slowly, or a fresh EXPLAIN without parameters
+    pub async fn optimize_query(&self, sql: &str) -> DatabaseResult<String> {
+        let (plan, source) = match self.analytics.plan_for(sql) {
+            Some(captured) => (
+                captured.plan,
+                format!("captured {} after a {:?} run", captured.captured_at.to_rfc3339(), captured.execution_time),
+            ),
+            None => {
+                let backend = self.pool.get_backend();
+                match crate::query_plans::explain(backend.as_ref(), sql, &[]).await? {
+                    Some((plan, _)) => (plan, "explained now".to_string()),
+                    None => {
+                        return Err(DatabaseError::Generic("Only SELECT, WITH, INSERT, UPDATE and DELETE can be explained".to_string()));
+                    }
+                }
+            }
+        };
+
+        let dialect = match self.backend_type() {
+            crate::backends::BackendType::PostgreSQL => DatabaseDialect::PostgreSQL,
+            crate::backends::BackendType::SQLite => DatabaseDialect::SQLite,
+        };
+        let hints = DatabaseUtils::generate_query_hints(sql, dialect, None);
+
+        let mut report = format!("Query plan ({}):\n{}\n", source, plan);
+        if !hints.is_empty() {
+            report.push_str("\nHints:\n");
+            for hint in hints {
+                report.push_str(&format!("  {:?}\n", hint));
+            }
+        }
+        Ok(report)
+    }
     
     /// Test database connectivity
     pub async fn test_connection(&self) -> Result<()> {
@@ -97,6 +277,7 @@ impl DatabaseManager {
 
         let sql_files = [
             include_str!("../migrations/postgresql/ai/ai_decisions.sql"),
+            include_str!("../migrations/postgresql/ai/ai_decision_learning.sql"),
             include_str!("../migrations/postgresql/ai/ai_global_mind_state.sql"),
             include_str!("../migrations/postgresql/ai/emergent_behaviors.sql"),
             include_str!("../migrations/postgresql/ai/learning_data.sql"),
@@ -111,6 +292,125 @@ impl DatabaseManager {
         Ok(())
     }
 
+    /// Initialize the economy ledger schema
+    pub async fn initialize_economy_schema(&self) -> DatabaseResult<()> {
+        if self.backend_type() != DatabaseBackend::PostgreSQL {
+            return Err(DatabaseError::UnsupportedBackend(self.backend_type().as_str().to_string()));
+        }
+        self.pool
+            .execute_raw(include_str!("../migrations/postgresql/gameplay/economy_ledger.sql"))
+            .await?;
+        Ok(())
+    }
+
+    /// Create the world event log and its snapshot table
+    pub async fn initialize_world_events(&self) -> DatabaseResult<()> {
+        let backend = self.get_backend().await?;
+        crate::world_events::ensure_schema(backend.as_ref()).await
+    }
+
+    /// Append changes to the world event log, returning them numbered
+    pub async fn append_world_events(&self, events: Vec<WorldEvent>) -> DatabaseResult<Vec<WorldEvent>> {
+        let backend = self.get_backend().await?;
+        crate::world_events::append(backend.as_ref(), events).await
+    }
+
+    /// The state of a region as it was at `at`, rebuilt from the world
+    /// event log
+    pub async fn world_state_at(&self, region_id: uuid::Uuid, at: chrono::DateTime<chrono::Utc>) -> DatabaseResult<WorldState> {
+        let backend = self.get_backend().await?;
+        crate::world_events::state_at(backend.as_ref(), region_id, at).await
+    }
+
+    /// Create the chat log table and its search index
+    pub async fn initialize_chat_log(&self) -> DatabaseResult<()> {
+        let backend = self.get_backend().await?;
+        crate::chat_log::ensure_schema(backend.as_ref()).await
+    }
+
+    /// Store logged chat and instant messages
+    pub async fn log_chat(&self, entries: &[ChatLogEntry]) -> DatabaseResult<()> {
+        let backend = self.get_backend().await?;
+        crate::chat_log::record(backend.as_ref(), entries).await
+    }
+
+    /// Delete logged messages sent before `before`
+    pub async fn purge_chat_log(&self, before: chrono::DateTime<chrono::Utc>) -> DatabaseResult<u64> {
+        let backend = self.get_backend().await?;
+        crate::chat_log::purge(backend.as_ref(), before).await
+    }
+
+    /// Logged messages matching `query`, newest first
+    pub async fn search_chat_log(&self, query: &ChatLogQuery) -> DatabaseResult<Vec<ChatLogEntry>> {
+        let backend = self.get_backend().await?;
+        crate::chat_log::search(backend.as_ref(), query).await
+    }
+
+    /// Create the land auction and bid tables
+    pub async fn initialize_land_auctions(&self) -> DatabaseResult<()> {
+        let backend = self.get_backend().await?;
+        crate::land_auctions::ensure_schema(backend.as_ref()).await
+    }
+
+    /// Store a new land auction
+    pub async fn create_land_auction(&self, auction: &LandAuction) -> DatabaseResult<()> {
+        let backend = self.get_backend().await?;
+        crate::land_auctions::create(backend.as_ref(), auction).await
+    }
+
+    /// The land auction `id`
+    pub async fn get_land_auction(&self, id: uuid::Uuid) -> DatabaseResult<Option<LandAuction>> {
+        let backend = self.get_backend().await?;
+        crate::land_auctions::get(backend.as_ref(), id).await
+    }
+
+    /// The open land auctions of `region_id`, ending first first
+    pub async fn open_land_auctions(&self, region_id: uuid::Uuid) -> DatabaseResult<Vec<LandAuction>> {
+        let backend = self.get_backend().await?;
+        crate::land_auctions::open(backend.as_ref(), region_id).await
+    }
+
+    /// Bid on a land auction, refused when it ended or the bid is too low
+    pub async fn place_land_bid(
+        &self,
+        auction_id: uuid::Uuid,
+        bidder_id: uuid::Uuid,
+        amount: i64,
+    ) -> DatabaseResult<Bid> {
+        let backend = self.get_backend().await?;
+        crate::land_auctions::place_bid(backend.as_ref(), auction_id, bidder_id, amount, chrono::Utc::now()).await
+    }
+
+    /// The bids on a land auction, highest first
+    pub async fn land_auction_bids(&self, auction_id: uuid::Uuid) -> DatabaseResult<Vec<Bid>> {
+        let backend = self.get_backend().await?;
+        crate::land_auctions::bids(backend.as_ref(), auction_id).await
+    }
+
+    /// Record how a land auction ended
+    pub async fn close_land_auction(
+        &self,
+        auction_id: uuid::Uuid,
+        status: AuctionStatus,
+        winner: Option<(uuid::Uuid, i64)>,
+    ) -> DatabaseResult<()> {
+        let backend = self.get_backend().await?;
+        crate::land_auctions::close(backend.as_ref(), auction_id, status, winner).await
+    }
+
+    /// Everything stored about a user
+    pub async fn export_user_data(&self, user_id: uuid::Uuid) -> DatabaseResult<PersonalDataBundle> {
+        let backend = self.get_backend().await?;
+        crate::privacy::export(backend.as_ref(), user_id).await
+    }
+
+    /// Delete or anonymize everything stored about a user, recording a
+    /// tombstone
+    pub async fn forget_user(&self, user_id: uuid::Uuid, reason: &str) -> DatabaseResult<ForgetReport> {
+        let backend = self.get_backend().await?;
+        crate::privacy::forget(backend.as_ref(), user_id, reason).await
+    }
+
     /// Get database metrics
     pub async fn get_metrics(&self) -> DatabaseMetrics {
         let mut metrics = DatabaseMetrics {
@@ -120,6 +420,8 @@ impl DatabaseManager {
             avg_query_time_ms: f64::from_bits(self.avg_query_time_ms.load(Ordering::Relaxed)),
             active_connections: 0,
             max_connections: 0,
+            query_stats: self.analytics.snapshot(),
+            pool: self.pool_monitor.snapshot(),
         };
 
         // Update connection pool metrics
@@ -156,45 +458,84 @@ impl DatabaseManager {
         }).ok();
     }
 
-    /// Execute a query with metrics tracking
-    async fn execute_with_metrics<F, T>(&self, operation: F) -> DatabaseResult<T>
+    /// Execute a query with metrics tracking, retrying transient errors.
+    /// Each attempt is counted as a query. Calls fail fast with
+    /// `DatabaseError::Unavailable` while the circuit breaker is open.
+    ///
+    /// Runs inside a `db.query` span named after `query`, with a query ID
+    /// unique to this manager, so it nests under the caller's request span.
+    async fn execute_with_metrics<F, Fut, T>(&self, query: &'static str, mut operation: F) -> DatabaseResult<T>
     where
-        F: std::future::Future<Output = DatabaseResult<T>>,
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = DatabaseResult<T>>,
     {
-        let start = std::time::Instant::now();
-        let result = operation.await;
-        let duration = start.elapsed();
-        
-        self.update_metrics(result.is_ok(), duration).await;
-        result
+        let span = info_span!(
+            "db.query",
+            query,
+            query_id = self.next_query_id.fetch_add(1, Ordering::Relaxed),
+            db.system = ?self.backend_type(),
+            error = tracing::field::Empty,
+        );
+
+        async {
+            let permit = self
+                .breaker
+                .acquire()
+                .map_err(|open| DatabaseError::Unavailable(open.to_string()))?;
+
+            let result = self
+                .retrier
+                .run(|| {
+                    let attempt = operation();
+                    async move {
+                        let start = std::time::Instant::now();
+                        let result = attempt.await;
+                        self.update_metrics(result.is_ok(), start.elapsed()).await;
+                        self.analytics.record(query, start.elapsed(), None, result.is_ok());
+                        result
+                    }
+                })
+                .await;
+
+            match &result {
+                Err(e) if e.is_retryable() => permit.failure(),
+                _ => permit.success(),
+            }
+            if let Err(e) = &result {
+                tracing::Span::current().record("error", e.code());
+            }
+            result
+        }
+        .instrument(span)
+        .await
     }
 
     // === USER MANAGEMENT ===
 
     /// Create a new user
     pub async fn create_user(&self, account: &UserAccount) -> DatabaseResult<()> {
-        self.execute_with_metrics(async {
+        self.execute_with_metrics("users.create", || async {
             self.user_queries.create(&self.pool, account).await
         }).await
     }
 
     /// Get user by ID
     pub async fn get_user(&self, user_id: UserId) -> DatabaseResult<Option<UserAccount>> {
-        self.execute_with_metrics(async {
+        self.execute_with_metrics("users.get_by_id", || async {
             self.user_queries.get_by_id(&self.pool, user_id).await
         }).await
     }
 
     /// Find user by name
     pub async fn find_user_by_name(&self, first_name: &str, last_name: &str) -> DatabaseResult<Option<UserAccount>> {
-        self.execute_with_metrics(async {
+        self.execute_with_metrics("users.find_by_name", || async {
             self.user_queries.find_by_name(&self.pool, first_name, last_name).await
         }).await
     }
 
     /// Update user
     pub async fn update_user(&self, account: &UserAccount) -> DatabaseResult<()> {
-        self.execute_with_metrics(async {
+        self.execute_with_metrics("users.update", || async {
             self.user_queries.update(&self.pool, account).await
         }).await
     }
@@ -293,17 +634,6 @@ pub struct OpenSimHealth {
     pub asset_count: u64,
 }
 
-/// Basic database metrics
-#[derive(Debug, Clone, Default)]
-pub struct DatabaseMetrics {
-    pub total_queries: u64,
-    pub successful_queries: u64,
-    pub failed_queries: u64,
-    pub avg_query_time_ms: f64,
-    pub active_connections: u32,
-    pub max_connections: u32,
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
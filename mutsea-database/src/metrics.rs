@@ -1,5 +1,9 @@
 //! Database metrics data structures
 
+use crate::pool_monitor::PoolGauges;
+use crate::utils::QueryStats;
+use std::collections::HashMap;
+
 /// Runtime metrics collected for database operations.
 #[derive(Debug, Clone, Default)]
 pub struct DatabaseMetrics {
@@ -15,5 +19,9 @@ pub struct DatabaseMetrics {
     pub active_connections: u32,
     /// Maximum number of connections allowed in the pool
     pub max_connections: u32,
+    /// Per-query statistics, keyed by SQL fingerprint
+    pub query_stats: HashMap<String, QueryStats>,
+    /// Connection checkouts, waits and leaks
+    pub pool: PoolGauges,
 }
 
@@ -18,6 +18,7 @@ pub mod npc_state;
 pub mod ecosystem_state;
 pub mod learning_data;
 pub mod performance_metrics;
+pub mod quest;
 
 // Re-export all models for convenience
 pub use world_state::*;
@@ -28,6 +29,7 @@ pub use npc_state::*;
 pub use ecosystem_state::*;
 pub use learning_data::*;
 pub use performance_metrics::*;
+pub use quest::*;
 
 /// Common ID types used throughout the system
 pub type EntityId = Uuid;
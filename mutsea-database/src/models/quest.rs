@@ -0,0 +1,130 @@
+// mutsea-database/src/models/quest.rs
+//! Quest models for the narrative system
+//!
+//! A quest definition is a titled series of objectives, written by hand or
+//! by the story generator, and a player's progress records how far they
+//! have got through one.
+
+use super::*;
+use mutsea_core::QuestObjective;
+use serde::{Deserialize, Serialize};
+
+/// A quest players can be given
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuestDefinition {
+    pub id: QuestId,
+    pub title: String,
+    pub synopsis: String,
+    /// Completed in order
+    pub objectives: Vec<QuestObjective>,
+    pub origin: QuestOrigin,
+    /// Region the quest takes place in; any region when absent
+    pub region_id: Option<EntityId>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
+impl QuestDefinition {
+    pub fn new(title: String, synopsis: String, objectives: Vec<QuestObjective>, origin: QuestOrigin) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4(),
+            title,
+            synopsis,
+            objectives,
+            origin,
+            region_id: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// Who wrote a quest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QuestOrigin {
+    /// Written by an administrator
+    Authored,
+    /// Written by the story generator, as the recorded AI decision
+    /// `decision_id`
+    Generated { decision_id: Uuid, model_version: String },
+}
+
+impl DatabaseModel for QuestDefinition {
+    fn id(&self) -> EntityId {
+        self.id
+    }
+
+    fn created_at(&self) -> Timestamp {
+        self.created_at
+    }
+
+    fn updated_at(&self) -> Timestamp {
+        self.updated_at
+    }
+
+    fn validate(&self) -> Result<(), ValidationError> {
+        let error = |field: &str, message: &str, code| ValidationError {
+            field: field.to_string(),
+            message: message.to_string(),
+            code,
+        };
+        if self.title.trim().is_empty() {
+            return Err(error("title", "A quest needs a title", ValidationErrorCode::Required));
+        }
+        if self.objectives.is_empty() {
+            return Err(error("objectives", "A quest needs at least one objective", ValidationErrorCode::Required));
+        }
+        if self.objectives.iter().any(|objective| objective.description.trim().is_empty()) {
+            return Err(error(
+                "objectives",
+                "Every objective needs a description",
+                ValidationErrorCode::Required,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// How far a player has got through a quest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuestProgress {
+    pub id: EntityId,
+    pub quest_id: QuestId,
+    pub player_id: PlayerId,
+    pub status: QuestStatus,
+    /// Index of the objective being worked on; the objective count once
+    /// completed
+    pub current_objective: usize,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+    pub completed_at: Option<Timestamp>,
+}
+
+impl QuestProgress {
+    /// A quest `player_id` has just been given
+    pub fn start(quest_id: QuestId, player_id: PlayerId) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4(),
+            quest_id,
+            player_id,
+            status: QuestStatus::Active,
+            current_objective: 0,
+            created_at: now,
+            updated_at: now,
+            completed_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestStatus {
+    Active,
+    Completed,
+    Abandoned,
+}
+
+impl_database_model!(QuestProgress, id, created_at, updated_at);
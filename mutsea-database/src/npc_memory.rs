@@ -0,0 +1,178 @@
+// mutsea-database/src/npc_memory.rs
+//! Persistence of what NPCs remember into `npc_states` and `learning_data`
+//!
+//! An NPC's personality and memory are kept in its `npc_states` row, with
+//! its AI learning data under `metadata`. Each conversation is remembered
+//! as an event in short-term memory and also stored in `learning_data`
+//! for training.
+
+use crate::backends::DatabaseBackend;
+use crate::models::{
+    AILearningData, EventMemory, ExperienceType, LearningExperience, MemoryContent, MemoryItem, MemoryState,
+    PersonalityProfile, Timestamp, WorldPosition,
+};
+use crate::Result;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// Most learning experiences kept with an NPC; older ones remain in
+/// `learning_data`
+pub const MAX_LEARNING_EXPERIENCES: usize = 100;
+
+/// What an NPC is like and remembers
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NpcMind {
+    pub personality: PersonalityProfile,
+    pub memory: MemoryState,
+    pub learning: AILearningData,
+}
+
+impl NpcMind {
+    /// Remember that `speaker_id` said `heard` at `location` and was told
+    /// `replied`, forgetting the oldest memories beyond short-term capacity
+    pub fn remember_conversation(
+        &mut self,
+        speaker_id: Uuid,
+        heard: &str,
+        replied: &str,
+        location: WorldPosition,
+        at: Timestamp,
+    ) {
+        let short_term = &mut self.memory.short_term_memory;
+        short_term.current_items.push(MemoryItem {
+            item_id: Uuid::new_v4(),
+            content: MemoryContent::Event(EventMemory {
+                event_description: format!("They said \"{}\" and I replied \"{}\"", heard, replied),
+                participants: vec![speaker_id],
+                location,
+                timestamp: at,
+                emotional_impact: 0.0,
+                significance: 0.5,
+            }),
+            strength: 1.0,
+            last_accessed: at,
+            emotional_weight: 0.0,
+            associations: Vec::new(),
+        });
+        let excess = short_term.current_items.len().saturating_sub(short_term.capacity.max(1) as usize);
+        short_term.current_items.drain(..excess);
+
+        let experiences = &mut self.learning.learning_experiences;
+        experiences.push(LearningExperience {
+            experience_id: Uuid::new_v4(),
+            experience_type: ExperienceType::Observation,
+            context: heard.to_string(),
+            outcome: replied.to_string(),
+            lessons_learned: Vec::new(),
+            confidence: 0.5,
+            transferability: 0.5,
+        });
+        let excess = experiences.len().saturating_sub(MAX_LEARNING_EXPERIENCES);
+        experiences.drain(..excess);
+    }
+
+    /// Conversations with `speaker_id` still in short-term memory, oldest first
+    pub fn recollections(&self, speaker_id: Uuid) -> Vec<String> {
+        self.memory
+            .short_term_memory
+            .current_items
+            .iter()
+            .filter_map(|item| match &item.content {
+                MemoryContent::Event(event) if event.participants.contains(&speaker_id) => {
+                    Some(event.event_description.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The stored mind of NPC `npc_id`, if it has one
+pub async fn load_npc_mind(backend: &dyn DatabaseBackend, npc_id: Uuid) -> Result<Option<NpcMind>> {
+    let id = npc_id.to_string();
+    let rows = backend
+        .query(include_str!("sql/postgresql/npc_states/select_npc_mind.sql"), &[&id])
+        .await?;
+    let Some(row) = rows.first() else {
+        return Ok(None);
+    };
+
+    let personality: Value = row.get(0)?;
+    let memory: Option<Value> = row.get(1)?;
+    let metadata: Option<Value> = row.get(2)?;
+    // Rows written before an NPC first talked have empty memory
+    let memory = match memory {
+        Some(memory) if memory != json!({}) => serde_json::from_value(memory)?,
+        _ => MemoryState::default(),
+    };
+    let learning = match metadata.and_then(|metadata| metadata.get("ai_learning_data").cloned()) {
+        Some(learning) => serde_json::from_value(learning)?,
+        None => AILearningData::default(),
+    };
+    Ok(Some(NpcMind {
+        personality: serde_json::from_value(personality)?,
+        memory,
+        learning,
+    }))
+}
+
+/// Store the mind of NPC `npc_id`, creating its row if needed
+pub async fn save_npc_mind(backend: &dyn DatabaseBackend, npc_id: Uuid, mind: &NpcMind) -> Result<()> {
+    let id = npc_id.to_string();
+    let personality = serde_json::to_value(&mind.personality)?;
+    let memory = serde_json::to_value(&mind.memory)?;
+    let metadata = json!({ "ai_learning_data": mind.learning });
+    backend
+        .execute(
+            include_str!("sql/postgresql/npc_states/upsert_npc_mind.sql"),
+            &[&id, &personality, &memory, &metadata],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Store one exchange of NPC `npc_id` in `learning_data`; a conversation's
+/// exchanges share `session_id`
+pub async fn record_npc_learning(
+    backend: &dyn DatabaseBackend,
+    npc_id: Uuid,
+    session_id: Uuid,
+    input: &Value,
+    output: &Value,
+) -> Result<()> {
+    let id = npc_id.to_string();
+    let session = session_id.to_string();
+    let algorithm = "conversation".to_string();
+    backend
+        .execute(
+            include_str!("sql/postgresql/npc_states/insert_npc_learning.sql"),
+            &[&id, &session, input, output, &algorithm],
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_conversations_are_remembered_within_capacity() {
+        let mut mind = NpcMind::default();
+        mind.memory.short_term_memory.capacity = 2;
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let here = WorldPosition::new(128.0, 128.0, 25.0);
+
+        mind.remember_conversation(alice, "Hello", "Well met", here.clone(), Utc::now());
+        mind.remember_conversation(bob, "Any news?", "None", here.clone(), Utc::now());
+        assert_eq!(mind.recollections(alice), vec!["They said \"Hello\" and I replied \"Well met\""]);
+
+        // The oldest memory makes way
+        mind.remember_conversation(bob, "Goodbye", "Farewell", here, Utc::now());
+        assert!(mind.recollections(alice).is_empty());
+        assert_eq!(mind.recollections(bob).len(), 2);
+        assert_eq!(mind.learning.learning_experiences.len(), 3);
+        assert_eq!(mind.learning.learning_experiences[2].outcome, "Farewell");
+    }
+}
@@ -0,0 +1,386 @@
+//! Import from an OpenSim FSAssets store
+//!
+//! FSAssets keeps every distinct asset body once on disk, usually gzipped,
+//! under a path built from its SHA-256 hash
+//! (`AB/CD/EF/0123/ABCDEF0123….gz`), and keeps the per-asset metadata in
+//! an `fsassets` table whose rows point at that hash. Several asset IDs can
+//! share one file.
+//!
+//! [`import`] walks the directory, checks every file against the hash in
+//! its name and copies it into Mutsea's asset store once per asset ID that
+//! references it. With the `fsassets` table at hand, names, types and
+//! timestamps come from there; files it does not reference (or every file,
+//! without it) get an ID derived from the hash and a type sniffed from the
+//! content, so reruns produce the same IDs. Assets already stored are left
+//! alone. Unreadable files, files whose content does not match their hash,
+//! and metadata rows whose file is missing end up in the report.
+
+use super::schema::Asset;
+use crate::backends::cursor::DEFAULT_BATCH_SIZE;
+use crate::backends::DatabaseBackend;
+use crate::{DatabaseManager, Result};
+use flate2::read::GzDecoder;
+use futures::TryStreamExt;
+use mutsea_core::AssetType;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tracing::{info, warn};
+
+const METADATA_QUERY: &str =
+    "SELECT id, name, description, type, hash, create_time, access_time, asset_flags FROM fsassets";
+
+const NULL_CREATOR: &str = "00000000-0000-0000-0000-000000000000";
+
+/// One `fsassets` row
+#[derive(Debug, Clone)]
+pub struct FsAssetMeta {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub asset_type: i32,
+    pub create_time: i32,
+    pub access_time: i32,
+    pub asset_flags: i32,
+}
+
+/// Why a file or metadata row was not imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+    /// The file or directory could not be read
+    Unreadable,
+    /// The file does not decompress, is empty, or does not match its hash
+    Corrupt,
+    /// A metadata row points at a hash with no file on disk
+    Missing,
+}
+
+/// A file or metadata row that was not imported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssetProblem {
+    pub kind: ProblemKind,
+    /// File path, or the asset ID for [`ProblemKind::Missing`]
+    pub location: String,
+    pub detail: String,
+}
+
+/// Outcome of an [`import`] run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+    /// Asset files found on disk
+    pub files: u64,
+    /// Assets written to the store
+    pub imported: u64,
+    /// Of those, assets whose metadata was reconstructed from the file
+    pub reconstructed: u64,
+    /// Assets skipped because the store already had them
+    pub existing: u64,
+    /// Files seen twice, e.g. both gzipped and still in the spool
+    pub duplicate_files: u64,
+    pub problems: Vec<AssetProblem>,
+}
+
+/// Import every asset file under `dir` into `database`, taking metadata
+/// from the `fsassets` table in `metadata` when given
+pub async fn import(
+    dir: &Path,
+    metadata: Option<&dyn DatabaseBackend>,
+    database: &DatabaseManager,
+) -> Result<ImportReport> {
+    let mut report = ImportReport::default();
+    let mut by_hash = match metadata {
+        Some(backend) => load_metadata(backend).await?,
+        None => HashMap::new(),
+    };
+
+    let mut files = Vec::new();
+    walk(dir, &mut files, &mut report.problems);
+    info!("Found {} FSAssets files under {}", files.len(), dir.display());
+
+    let mut seen = HashSet::new();
+    for (hash, path) in files {
+        report.files += 1;
+        if !seen.insert(hash.clone()) {
+            report.duplicate_files += 1;
+            continue;
+        }
+        let data = match read_asset(&path, &hash) {
+            Ok(data) => data,
+            Err(problem) => {
+                warn!("Skipping {}: {}", path.display(), problem.detail);
+                report.problems.push(problem);
+                // Leave its metadata rows out of the missing-file report
+                by_hash.remove(&hash);
+                continue;
+            }
+        };
+
+        let (assets, reconstructed): (Vec<Asset>, bool) = match by_hash.remove(&hash) {
+            Some(rows) => (rows.into_iter().map(|meta| meta.into_asset(data.clone())).collect(), false),
+            None => (vec![reconstruct(&hash, data, modified_time(&path))], true),
+        };
+        for asset in assets {
+            if database.asset_exists(&asset.id).await? {
+                report.existing += 1;
+                continue;
+            }
+            database.insert_asset(&asset).await?;
+            report.imported += 1;
+            if reconstructed {
+                report.reconstructed += 1;
+            }
+        }
+        if report.files % 1000 == 0 {
+            info!("Imported {} assets from {} files", report.imported, report.files);
+        }
+    }
+
+    for (hash, rows) in by_hash {
+        for meta in rows {
+            report.problems.push(AssetProblem {
+                kind: ProblemKind::Missing,
+                location: meta.id,
+                detail: format!("no file for hash {}", hash),
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Group the `fsassets` table by upper-case hash
+async fn load_metadata(backend: &dyn DatabaseBackend) -> Result<HashMap<String, Vec<FsAssetMeta>>> {
+    let mut by_hash: HashMap<String, Vec<FsAssetMeta>> = HashMap::new();
+    let mut rows = backend.query_stream(METADATA_QUERY, &[], DEFAULT_BATCH_SIZE);
+    while let Some(row) = rows.try_next().await? {
+        let hash = row.get::<String>(4)?.to_ascii_uppercase();
+        by_hash.entry(hash).or_default().push(FsAssetMeta {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            description: row.get(2)?,
+            asset_type: row.get::<i64>(3)? as i32,
+            create_time: row.get::<i64>(5)? as i32,
+            access_time: row.get::<i64>(6)? as i32,
+            asset_flags: row.get::<i64>(7)? as i32,
+        });
+    }
+    Ok(by_hash)
+}
+
+/// Collect `(hash, path)` for every file under `dir` named after a
+/// SHA-256 hash; anything else is ignored
+fn walk(dir: &Path, files: &mut Vec<(String, PathBuf)>, problems: &mut Vec<AssetProblem>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            problems.push(unreadable(dir, e));
+            return;
+        }
+    };
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in entries {
+        match entry {
+            Ok(entry) => paths.push(entry.path()),
+            Err(e) => problems.push(unreadable(dir, e)),
+        }
+    }
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            walk(&path, files, problems);
+        } else if let Some(hash) = file_hash(&path) {
+            files.push((hash, path));
+        }
+    }
+}
+
+/// Upper-case hash of an FSAssets file name such as `ABCD….gz`
+fn file_hash(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let stem = name.split('.').next()?;
+    (stem.len() == 64 && stem.bytes().all(|b| b.is_ascii_hexdigit())).then(|| stem.to_ascii_uppercase())
+}
+
+/// Read, decompress and hash-check one file
+fn read_asset(path: &Path, hash: &str) -> std::result::Result<Vec<u8>, AssetProblem> {
+    let raw = std::fs::read(path).map_err(|e| unreadable(path, e))?;
+    let data = decode(&raw).map_err(|detail| corrupt(path, detail))?;
+    if data.is_empty() {
+        return Err(corrupt(path, "empty asset".to_string()));
+    }
+    let actual = hex::encode_upper(Sha256::digest(&data));
+    if actual != hash {
+        return Err(corrupt(path, format!("content hashes to {}", actual)));
+    }
+    Ok(data)
+}
+
+/// Gunzip `raw` when it is gzipped; FSAssets stores compressed and plain
+/// files side by side depending on its settings
+fn decode(raw: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    if !raw.starts_with(&[0x1f, 0x8b]) {
+        return Ok(raw.to_vec());
+    }
+    let mut data = Vec::new();
+    GzDecoder::new(raw)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("gzip: {}", e))?;
+    Ok(data)
+}
+
+fn unreadable(path: &Path, e: std::io::Error) -> AssetProblem {
+    AssetProblem {
+        kind: ProblemKind::Unreadable,
+        location: path.display().to_string(),
+        detail: e.to_string(),
+    }
+}
+
+fn corrupt(path: &Path, detail: String) -> AssetProblem {
+    AssetProblem {
+        kind: ProblemKind::Corrupt,
+        location: path.display().to_string(),
+        detail,
+    }
+}
+
+fn modified_time(path: &Path) -> i32 {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs() as i32)
+}
+
+impl FsAssetMeta {
+    fn into_asset(self, data: Vec<u8>) -> Asset {
+        Asset {
+            id: self.id,
+            name: self.name,
+            description: self.description,
+            asset_type: self.asset_type,
+            local: false,
+            temporary: false,
+            data,
+            create_time: self.create_time,
+            access_time: self.access_time,
+            asset_flags: self.asset_flags,
+            creator_id: NULL_CREATOR.to_string(),
+        }
+    }
+}
+
+/// Stable asset ID for a file with no metadata: the first 16 bytes of its
+/// hash as a version 4 UUID
+pub fn hash_id(hash: &str) -> String {
+    let mut bytes = [0u8; 16];
+    if let Ok(decoded) = hex::decode(&hash[..32.min(hash.len())]) {
+        bytes[..decoded.len()].copy_from_slice(&decoded);
+    }
+    uuid::Builder::from_random_bytes(bytes).into_uuid().to_string()
+}
+
+/// An asset for a file the metadata table does not know about
+fn reconstruct(hash: &str, data: Vec<u8>, time: i32) -> Asset {
+    let asset_type = sniff_type(&data);
+    Asset {
+        id: hash_id(hash),
+        name: format!("Recovered {:?} {}", asset_type, &hash[..8]),
+        description: "Imported from FSAssets without metadata".to_string(),
+        asset_type: asset_type as i32,
+        local: false,
+        temporary: false,
+        data,
+        create_time: time,
+        access_time: time,
+        asset_flags: 0,
+        creator_id: NULL_CREATOR.to_string(),
+    }
+}
+
+/// Guess an asset's type from its content
+pub fn sniff_type(data: &[u8]) -> AssetType {
+    let text = std::str::from_utf8(&data[..data.len().min(512)]).unwrap_or("");
+    if data.starts_with(&[0xff, 0x4f, 0xff, 0x51]) || data.starts_with(b"\0\0\0\x0cjP  ") {
+        AssetType::Texture
+    } else if data.starts_with(b"OggS") {
+        AssetType::Sound
+    } else if data.starts_with(b"RIFF") {
+        AssetType::SoundWAV
+    } else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        AssetType::ImageJPEG
+    } else if text.starts_with("Linden text version") {
+        AssetType::Notecard
+    } else if text.starts_with("Landmark version") {
+        AssetType::Landmark
+    } else if text.starts_with("LLWearable version") {
+        // Wearable types 0-3 are shape, skin, hair and eyes
+        let wearable = text
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("type "))
+            .and_then(|t| t.trim().parse::<u32>().ok());
+        match wearable {
+            Some(0..=3) => AssetType::Bodypart,
+            _ => AssetType::Clothing,
+        }
+    } else if text.contains("<SceneObjectGroup") {
+        AssetType::Object
+    } else if text.contains("state_entry") || text.trim_start().starts_with("default") {
+        AssetType::LSLText
+    } else {
+        AssetType::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    #[test]
+    fn test_read_asset_checks_hash_and_gzip() {
+        let dir = std::env::temp_dir().join(format!("mutsea-fsassets-{}", uuid::Uuid::new_v4()));
+        let data = b"Linden text version 2\n{\n}\n".to_vec();
+        let hash = hex::encode_upper(Sha256::digest(&data));
+        let sub = dir.join(&hash[0..2]).join(&hash[2..4]).join(&hash[4..6]).join(&hash[6..10]);
+        std::fs::create_dir_all(&sub).unwrap();
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&data).unwrap();
+        let good = sub.join(format!("{}.gz", hash));
+        std::fs::write(&good, gz.finish().unwrap()).unwrap();
+        let wrong = "0".repeat(64);
+        std::fs::write(sub.join(&wrong), b"not what the name says").unwrap();
+        std::fs::write(sub.join("README"), b"ignored").unwrap();
+
+        let mut files = Vec::new();
+        let mut problems = Vec::new();
+        walk(&dir, &mut files, &mut problems);
+        assert!(problems.is_empty());
+        assert_eq!(files.len(), 2);
+
+        assert_eq!(read_asset(&good, &hash).unwrap(), data);
+        let problem = read_asset(&sub.join(&wrong), &wrong).unwrap_err();
+        assert_eq!(problem.kind, ProblemKind::Corrupt);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reconstructed_metadata_is_stable() {
+        let hash = hex::encode_upper(Sha256::digest(b"x"));
+        assert_eq!(hash_id(&hash), hash_id(&hash.to_ascii_lowercase()));
+        assert!(uuid::Uuid::parse_str(&hash_id(&hash)).is_ok());
+
+        assert_eq!(sniff_type(&[0xff, 0x4f, 0xff, 0x51, 0]), AssetType::Texture);
+        assert_eq!(sniff_type(b"OggS\0"), AssetType::Sound);
+        assert_eq!(sniff_type(b"LLWearable version 22\nShirt\n\n\tpermissions 0\n{\n}\ntype 4\n"), AssetType::Clothing);
+        assert_eq!(sniff_type(b"LLWearable version 22\nShape\ntype 0\n"), AssetType::Bodypart);
+        assert_eq!(sniff_type(b"default\n{\n    state_entry() {}\n}\n"), AssetType::LSLText);
+        assert_eq!(sniff_type(b"\x01\x02"), AssetType::Unknown);
+    }
+}
@@ -0,0 +1,681 @@
+//! Migration from an OpenSimulator database
+//!
+//! [`migrate`] copies users, regions, inventory, prims and assets from an
+//! OpenSim database into Mutsea's schema. Each stage pages through its
+//! source table by primary key, writes one target transaction per batch and
+//! records the last copied key in a [`Checkpoint`] file, so an interrupted
+//! run carries on where it stopped. Target rows are replaced rather than
+//! inserted, so copying a batch twice is harmless. Assets are fetched one at
+//! a time to keep a single blob in memory.
+//!
+//! [`verify`] compares the row count and an order-independent SHA-256
+//! digest of every copied column on both sides.
+//!
+//! OpenSim's `auth` table (password hashes) and `primshapes` have no
+//! counterpart in Mutsea's schema yet and are not copied.
+
+use crate::backends::{DatabaseBackend, Row, SqlValue, ToSql};
+use crate::sharding::AssetSharding;
+use crate::{DatabaseError, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::info;
+
+/// Region size in meters; OpenSim stores region positions in meters,
+/// Mutsea in region units
+const REGION_METERS: i64 = 256;
+
+/// One OpenSim table and where its columns go
+#[derive(Debug)]
+pub struct Stage {
+    pub name: &'static str,
+    source: &'static str,
+    /// Target table; `assets` is routed through the asset shards
+    target: &'static str,
+    /// (OpenSim column, Mutsea column), primary key first
+    columns: &'static [(&'static str, &'static str)],
+    /// Target columns converted from meters to region units
+    meters: &'static [&'static str],
+    /// Fetch rows one by one instead of a page at a time
+    streamed: bool,
+}
+
+/// Every stage, in copy order
+pub const STAGES: &[Stage] = &[
+    Stage {
+        name: "users",
+        source: "UserAccounts",
+        target: "user_accounts",
+        columns: &[
+            ("PrincipalID", "principal_id"),
+            ("ScopeID", "scope_id"),
+            ("FirstName", "first_name"),
+            ("LastName", "last_name"),
+            ("Email", "email"),
+            ("ServiceURLs", "service_urls"),
+            ("Created", "created"),
+            ("UserLevel", "user_level"),
+            ("UserFlags", "user_flags"),
+            ("UserTitle", "user_title"),
+            ("active", "active"),
+        ],
+        meters: &[],
+        streamed: false,
+    },
+    Stage {
+        name: "regions",
+        source: "regions",
+        target: "regions",
+        columns: &[
+            ("uuid", "uuid"),
+            ("regionName", "region_name"),
+            ("serverIP", "server_ip"),
+            ("serverPort", "server_port"),
+            ("serverURI", "server_uri"),
+            ("locX", "loc_x"),
+            ("locY", "loc_y"),
+            ("regionMapTexture", "region_map_texture"),
+            ("serverHttpPort", "server_http_port"),
+            ("ScopeID", "scope_id"),
+            ("sizeX", "size_x"),
+            ("sizeY", "size_y"),
+            ("flags", "flags"),
+            ("last_seen", "last_seen"),
+            ("parcelMapTexture", "parcel_map_texture"),
+        ],
+        meters: &["loc_x", "loc_y"],
+        streamed: false,
+    },
+    Stage {
+        name: "inventory_folders",
+        source: "inventoryfolders",
+        target: "inventoryfolders",
+        columns: &[
+            ("folderID", "folder_id"),
+            ("agentID", "agent_id"),
+            ("parentFolderID", "parent_folder_id"),
+            ("folderName", "folder_name"),
+            ("type", "type"),
+            ("version", "version"),
+        ],
+        meters: &[],
+        streamed: false,
+    },
+    Stage {
+        name: "inventory_items",
+        source: "inventoryitems",
+        target: "inventoryitems",
+        columns: &[
+            ("inventoryID", "inventory_id"),
+            ("assetID", "asset_id"),
+            ("assetType", "asset_type"),
+            ("parentFolderID", "parent_folder_id"),
+            ("avatarID", "avatar_id"),
+            ("inventoryName", "inventory_name"),
+            ("inventoryDescription", "inventory_description"),
+            ("inventoryNextPermissions", "inventory_next_permissions"),
+            ("inventoryCurrentPermissions", "inventory_current_permissions"),
+            ("invType", "inv_type"),
+            ("creatorID", "creator_id"),
+            ("inventoryBasePermissions", "inventory_base_permissions"),
+            ("inventoryEveryOnePermissions", "inventory_everyone_permissions"),
+            ("salePrice", "sale_price"),
+            ("saleType", "sale_type"),
+            ("creationDate", "creation_date"),
+            ("groupID", "group_id"),
+            ("groupOwned", "group_owned"),
+            ("inventoryGroupPermissions", "inventory_group_permissions"),
+        ],
+        meters: &[],
+        streamed: false,
+    },
+    Stage {
+        name: "prims",
+        source: "prims",
+        target: "primitives",
+        columns: &[
+            ("UUID", "uuid"),
+            ("RegionUUID", "region_uuid"),
+            ("CreationDate", "creation_date"),
+            ("Name", "name"),
+            ("SceneGroupID", "scene_group_id"),
+            ("Text", "text"),
+            ("Description", "description"),
+            ("SitName", "sit_name"),
+            ("TouchName", "touch_name"),
+            ("ObjectFlags", "object_flags"),
+            ("CreatorID", "creator_id"),
+            ("OwnerID", "owner_id"),
+            ("GroupID", "group_id"),
+            ("LastOwnerID", "last_owner_id"),
+            ("OwnerMask", "owner_mask"),
+            ("NextOwnerMask", "next_owner_mask"),
+            ("GroupMask", "group_mask"),
+            ("EveryoneMask", "everyone_mask"),
+            ("BaseMask", "base_mask"),
+            ("PositionX", "position_x"),
+            ("PositionY", "position_y"),
+            ("PositionZ", "position_z"),
+            ("GroupPositionX", "group_position_x"),
+            ("GroupPositionY", "group_position_y"),
+            ("GroupPositionZ", "group_position_z"),
+            ("VelocityX", "velocity_x"),
+            ("VelocityY", "velocity_y"),
+            ("VelocityZ", "velocity_z"),
+            ("AngularVelocityX", "angular_velocity_x"),
+            ("AngularVelocityY", "angular_velocity_y"),
+            ("AngularVelocityZ", "angular_velocity_z"),
+            ("AccelerationX", "acceleration_x"),
+            ("AccelerationY", "acceleration_y"),
+            ("AccelerationZ", "acceleration_z"),
+            ("RotationX", "rotation_x"),
+            ("RotationY", "rotation_y"),
+            ("RotationZ", "rotation_z"),
+            ("RotationW", "rotation_w"),
+            ("SitTargetOffsetX", "sit_target_offset_x"),
+            ("SitTargetOffsetY", "sit_target_offset_y"),
+            ("SitTargetOffsetZ", "sit_target_offset_z"),
+            ("SitTargetOrientW", "sit_target_orient_w"),
+            ("SitTargetOrientX", "sit_target_orient_x"),
+            ("SitTargetOrientY", "sit_target_orient_y"),
+            ("SitTargetOrientZ", "sit_target_orient_z"),
+            ("ClickAction", "click_action"),
+            ("Material", "material"),
+            ("CollisionSound", "collision_sound"),
+            ("CollisionSoundVolume", "collision_sound_volume"),
+            ("LinkNumber", "link_number"),
+        ],
+        meters: &[],
+        streamed: false,
+    },
+    Stage {
+        name: "assets",
+        source: "assets",
+        target: "assets",
+        columns: &[
+            ("id", "id"),
+            ("name", "name"),
+            ("description", "description"),
+            ("assetType", "asset_type"),
+            ("local", "local"),
+            ("temporary", "temporary"),
+            ("data", "data"),
+            ("create_time", "create_time"),
+            ("access_time", "access_time"),
+            ("asset_flags", "asset_flags"),
+            ("CreatorID", "creator_id"),
+        ],
+        meters: &[],
+        streamed: true,
+    },
+];
+
+impl Stage {
+    fn source_columns(&self) -> Vec<&'static str> {
+        self.columns.iter().map(|(source, _)| *source).collect()
+    }
+
+    fn target_columns(&self) -> Vec<&'static str> {
+        self.columns.iter().map(|(_, target)| *target).collect()
+    }
+
+    fn is_assets(&self) -> bool {
+        self.target == "assets"
+    }
+
+    fn target_table(&self, sharding: &AssetSharding, key: &str) -> String {
+        if self.is_assets() {
+            sharding.table_for(key)
+        } else {
+            self.target.to_string()
+        }
+    }
+
+    fn target_tables(&self, sharding: &AssetSharding) -> Vec<String> {
+        if self.is_assets() {
+            sharding.tables()
+        } else {
+            vec![self.target.to_string()]
+        }
+    }
+
+    /// Source row values as they should be stored in Mutsea
+    fn convert(&self, mut values: Vec<SqlValue>) -> Vec<SqlValue> {
+        for (value, (_, target)) in values.iter_mut().zip(self.columns) {
+            if self.meters.contains(target) {
+                *value = match &*value {
+                    SqlValue::Int(m) => SqlValue::Int(*m / REGION_METERS),
+                    SqlValue::Float(m) => SqlValue::Int(*m as i64 / REGION_METERS),
+                    other => other.clone(),
+                };
+            }
+        }
+        values
+    }
+}
+
+/// Copy progress of one stage
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StageProgress {
+    /// Last primary key copied
+    pub after: String,
+    pub copied: u64,
+    pub done: bool,
+}
+
+/// Resume point of a migration, saved after every batch
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub stages: BTreeMap<String, StageProgress>,
+}
+
+impl Checkpoint {
+    /// Load `path`, or start afresh when it does not exist
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(DatabaseError::Internal(format!(
+                "Failed to read {}: {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+
+    /// Write to `path` through a temporary file so a crash never leaves a
+    /// torn checkpoint
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let io = |e: std::io::Error| DatabaseError::Internal(format!("Failed to write {}: {}", path.display(), e));
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(io)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?).map_err(io)?;
+        std::fs::rename(&tmp, path).map_err(io)
+    }
+}
+
+/// Outcome of a [`migrate`] run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MigrationReport {
+    /// Rows copied by this run, per stage
+    pub copied: BTreeMap<String, u64>,
+    /// Stages finished by an earlier run and skipped
+    pub skipped: Vec<String>,
+}
+
+/// Copy every [`STAGES`] table from `source` to `target`, `batch_size`
+/// rows per transaction, resuming from the checkpoint at `checkpoint_path`
+pub async fn migrate(
+    source: &dyn DatabaseBackend,
+    target: &dyn DatabaseBackend,
+    sharding: &AssetSharding,
+    batch_size: usize,
+    checkpoint_path: &Path,
+) -> Result<MigrationReport> {
+    for statement in sharding.create_statements(crate::sharding::CREATE_ASSETS) {
+        target.execute(&statement, &[]).await?;
+    }
+
+    let mut checkpoint = Checkpoint::load(checkpoint_path)?;
+    let mut report = MigrationReport::default();
+    for stage in STAGES {
+        let progress = checkpoint.stages.entry(stage.name.to_string()).or_default().clone();
+        if progress.done {
+            info!(
+                "Skipping {}: {} rows copied by an earlier run",
+                stage.name, progress.copied
+            );
+            report.skipped.push(stage.name.to_string());
+            continue;
+        }
+        let copied = copy_stage(
+            source,
+            target,
+            sharding,
+            stage,
+            batch_size,
+            &mut checkpoint,
+            checkpoint_path,
+        )
+        .await?;
+        report.copied.insert(stage.name.to_string(), copied);
+    }
+    Ok(report)
+}
+
+async fn copy_stage(
+    source: &dyn DatabaseBackend,
+    target: &dyn DatabaseBackend,
+    sharding: &AssetSharding,
+    stage: &Stage,
+    batch_size: usize,
+    checkpoint: &mut Checkpoint,
+    checkpoint_path: &Path,
+) -> Result<u64> {
+    let total = count(source, stage.source).await?;
+    let source_columns = stage.source_columns();
+    let target_columns = stage.target_columns();
+    let (key, target_key) = stage.columns[0];
+    let select = page_query(stage.source, key, &source_columns, stage.streamed);
+    let select_one = format!(
+        "SELECT {} FROM {} WHERE {} = ?",
+        source_columns.join(", "),
+        stage.source,
+        key
+    );
+    let placeholders = vec!["?"; target_columns.len()].join(", ");
+
+    let mut copied = 0;
+    loop {
+        let progress = checkpoint.stages.entry(stage.name.to_string()).or_default();
+        let limit = batch_size as i64;
+        let rows = source.query(&select, &[&progress.after as &dyn ToSql, &limit]).await?;
+        if rows.is_empty() {
+            break;
+        }
+
+        let mut transaction = target.begin_transaction().await?;
+        let mut last = String::new();
+        for row in &rows {
+            let values = if stage.streamed {
+                let id = row.get::<String>(0)?;
+                read_values(&source.query_one(&select_one, &[&id]).await?, source_columns.len())?
+            } else {
+                read_values(row, source_columns.len())?
+            };
+            let values = stage.convert(values);
+            last = key_string(&values[0]);
+
+            let table = stage.target_table(sharding, &last);
+            transaction
+                .execute(&format!("DELETE FROM {} WHERE {} = ?", table, target_key), &[&last])
+                .await?;
+            let params: Vec<&dyn ToSql> = values.iter().map(|v| v as &dyn ToSql).collect();
+            transaction
+                .execute(
+                    &format!(
+                        "INSERT INTO {} ({}) VALUES ({})",
+                        table,
+                        target_columns.join(", "),
+                        placeholders
+                    ),
+                    &params,
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+
+        copied += rows.len() as u64;
+        progress.after = last;
+        progress.copied += rows.len() as u64;
+        let done = progress.copied;
+        checkpoint.save(checkpoint_path)?;
+        info!(
+            "Migrated {}: {}/{} rows ({:.0}%)",
+            stage.name,
+            done,
+            total,
+            done as f64 * 100.0 / total.max(1) as f64
+        );
+        if rows.len() < batch_size {
+            break;
+        }
+    }
+
+    checkpoint.stages.entry(stage.name.to_string()).or_default().done = true;
+    checkpoint.save(checkpoint_path)?;
+    info!("Finished {}: {} rows copied this run", stage.name, copied);
+    Ok(copied)
+}
+
+/// Comparison of one stage's source and target tables
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableCheck {
+    pub stage: String,
+    pub source_rows: u64,
+    pub target_rows: u64,
+    pub source_digest: String,
+    pub target_digest: String,
+}
+
+impl TableCheck {
+    pub fn matches(&self) -> bool {
+        self.source_rows == self.target_rows && self.source_digest == self.target_digest
+    }
+}
+
+/// Compare the row count and content digest of every stage's source and
+/// target tables
+pub async fn verify(
+    source: &dyn DatabaseBackend,
+    target: &dyn DatabaseBackend,
+    sharding: &AssetSharding,
+    batch_size: usize,
+) -> Result<Vec<TableCheck>> {
+    let mut checks = Vec::new();
+    for stage in STAGES {
+        let source_digest =
+            digest_table(source, stage.source, &stage.source_columns(), stage, true, batch_size).await?;
+        let mut target_digest = TableDigest::default();
+        for table in stage.target_tables(sharding) {
+            target_digest
+                .merge(&digest_table(target, &table, &stage.target_columns(), stage, false, batch_size).await?);
+        }
+        let check = TableCheck {
+            stage: stage.name.to_string(),
+            source_rows: source_digest.rows,
+            target_rows: target_digest.rows,
+            source_digest: source_digest.hex(),
+            target_digest: target_digest.hex(),
+        };
+        info!(
+            "Verified {}: {} source rows, {} target rows, {}",
+            stage.name,
+            check.source_rows,
+            check.target_rows,
+            if check.matches() { "match" } else { "MISMATCH" }
+        );
+        checks.push(check);
+    }
+    Ok(checks)
+}
+
+/// Digest every row of `table`; source rows are converted first so they
+/// hash like their copies
+async fn digest_table(
+    backend: &dyn DatabaseBackend,
+    table: &str,
+    columns: &[&str],
+    stage: &Stage,
+    convert: bool,
+    batch_size: usize,
+) -> Result<TableDigest> {
+    let select = page_query(table, columns[0], columns, stage.streamed);
+    let select_one = format!("SELECT {} FROM {} WHERE {} = ?", columns.join(", "), table, columns[0]);
+    let mut digest = TableDigest::default();
+    let mut after = String::new();
+    loop {
+        let limit = batch_size as i64;
+        let rows = backend.query(&select, &[&after as &dyn ToSql, &limit]).await?;
+        if rows.is_empty() {
+            break;
+        }
+        for row in &rows {
+            let values = if stage.streamed {
+                let id = row.get::<String>(0)?;
+                read_values(&backend.query_one(&select_one, &[&id]).await?, columns.len())?
+            } else {
+                read_values(row, columns.len())?
+            };
+            after = key_string(&values[0]);
+            digest.add(&if convert { stage.convert(values) } else { values });
+        }
+        if rows.len() < batch_size {
+            break;
+        }
+    }
+    Ok(digest)
+}
+
+/// Keyset page of `table`: whole rows, or just keys when `streamed`
+fn page_query(table: &str, key: &str, columns: &[&str], streamed: bool) -> String {
+    let selected = if streamed { key.to_string() } else { columns.join(", ") };
+    format!(
+        "SELECT {} FROM {} WHERE {} > ? ORDER BY {} LIMIT ?",
+        selected, table, key, key
+    )
+}
+
+async fn count(backend: &dyn DatabaseBackend, table: &str) -> Result<u64> {
+    let row = backend
+        .query_one(&format!("SELECT COUNT(*) FROM {}", table), &[])
+        .await?;
+    Ok(row.get::<i64>(0)?.max(0) as u64)
+}
+
+fn read_values<R: Row + ?Sized>(row: &R, columns: usize) -> Result<Vec<SqlValue>> {
+    (0..columns).map(|i| row.get::<SqlValue>(i)).collect()
+}
+
+fn key_string(value: &SqlValue) -> String {
+    match value {
+        SqlValue::Text(s) => s.clone(),
+        SqlValue::Bytes(b) => String::from_utf8_lossy(b).into_owned(),
+        SqlValue::Int(i) => i.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Order-independent digest of a table: the lane-wise sum of every row's
+/// SHA-256, so shards and differing row orders hash alike
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TableDigest {
+    rows: u64,
+    sum: [u64; 4],
+}
+
+impl TableDigest {
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    pub fn hex(&self) -> String {
+        self.sum.iter().map(|lane| format!("{:016x}", lane)).collect()
+    }
+
+    fn add(&mut self, values: &[SqlValue]) {
+        let mut hasher = Sha256::new();
+        for value in values {
+            // Backends disagree on booleans, integral doubles, text versus
+            // bytes and float widths, so hash a normalized form
+            match value {
+                SqlValue::Null => hasher.update([0]),
+                SqlValue::Bool(b) => hash_int(&mut hasher, *b as i64),
+                SqlValue::Int(i) => hash_int(&mut hasher, *i),
+                SqlValue::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => hash_int(&mut hasher, *f as i64),
+                SqlValue::Float(f) => {
+                    hasher.update([2]);
+                    hasher.update((*f as f32).to_bits().to_le_bytes());
+                }
+                SqlValue::Text(s) => hash_bytes(&mut hasher, s.as_bytes()),
+                SqlValue::Bytes(b) => hash_bytes(&mut hasher, b),
+                SqlValue::Json(j) => hash_bytes(&mut hasher, j.to_string().as_bytes()),
+            }
+        }
+        let hash = hasher.finalize();
+        for (lane, chunk) in self.sum.iter_mut().zip(hash.chunks(8)) {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(chunk);
+            *lane = lane.wrapping_add(u64::from_le_bytes(bytes));
+        }
+        self.rows += 1;
+    }
+
+    fn merge(&mut self, other: &TableDigest) {
+        for (lane, other) in self.sum.iter_mut().zip(other.sum) {
+            *lane = lane.wrapping_add(other);
+        }
+        self.rows += other.rows;
+    }
+}
+
+fn hash_int(hasher: &mut Sha256, value: i64) {
+    hasher.update([1]);
+    hasher.update(value.to_le_bytes());
+}
+
+fn hash_bytes(hasher: &mut Sha256, bytes: &[u8]) {
+    hasher.update([3]);
+    hasher.update((bytes.len() as u64).to_le_bytes());
+    hasher.update(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest_ignores_order_and_backend_types() {
+        let a = vec![SqlValue::Text("a".into()), SqlValue::Bool(true), SqlValue::Float(0.1)];
+        let b = vec![SqlValue::Text("b".into()), SqlValue::Float(3.0), SqlValue::Null];
+
+        let mut source = TableDigest::default();
+        source.add(&a);
+        source.add(&b);
+
+        let mut shard_0 = TableDigest::default();
+        shard_0.add(&[SqlValue::Bytes(b"b".to_vec()), SqlValue::Int(3), SqlValue::Null]);
+        let mut target = TableDigest::default();
+        target.add(&[
+            SqlValue::Text("a".into()),
+            SqlValue::Int(1),
+            SqlValue::Float(0.1f32 as f64),
+        ]);
+        target.merge(&shard_0);
+        assert_eq!(source, target);
+        assert_eq!(target.rows(), 2);
+
+        let mut changed = TableDigest::default();
+        changed.add(&a);
+        changed.add(&[SqlValue::Text("b".into()), SqlValue::Float(3.5), SqlValue::Null]);
+        assert_ne!(source.hex(), changed.hex());
+    }
+
+    #[test]
+    fn test_region_positions_become_region_units() {
+        let regions = STAGES.iter().find(|s| s.name == "regions").unwrap();
+        let mut values = vec![SqlValue::Null; regions.columns.len()];
+        values[5] = SqlValue::Int(256_000);
+        values[6] = SqlValue::Float(256_256.0);
+        values[10] = SqlValue::Int(512);
+        let values = regions.convert(values);
+        assert!(matches!(values[5], SqlValue::Int(1000)));
+        assert!(matches!(values[6], SqlValue::Int(1001)));
+        assert!(matches!(values[10], SqlValue::Int(512)));
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let path = std::env::temp_dir().join(format!("mutsea-migration-{}.json", uuid::Uuid::new_v4()));
+        assert!(Checkpoint::load(&path).unwrap().stages.is_empty());
+
+        let mut checkpoint = Checkpoint::default();
+        checkpoint.stages.insert(
+            "users".to_string(),
+            StageProgress {
+                after: "00000000-0000-0000-0000-000000000001".to_string(),
+                copied: 1,
+                done: true,
+            },
+        );
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        assert_eq!(loaded.stages["users"].after, checkpoint.stages["users"].after);
+        assert!(loaded.stages["users"].done);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -7,6 +7,8 @@
 pub mod schema;
 pub mod models;
 pub mod queries;
+pub mod fsassets;
+pub mod migration;
 
 use crate::{DatabaseManager, Result};
 
@@ -20,7 +22,12 @@ impl DatabaseManager {
         let table_queries = vec![
             include_str!("../sql/opensim/create_regions.sql"),
             include_str!("../sql/opensim/create_users.sql"),
-            include_str!("../sql/opensim/create_assets.sql"),
+            include_str!("../sql/opensim/create_griduser.sql"),
+            include_str!("../sql/opensim/create_avatarattachments.sql"),
+            include_str!("../sql/opensim/create_userprofile.sql"),
+            include_str!("../sql/opensim/create_directory.sql"),
+            include_str!("../sql/opensim/create_displaynames.sql"),
+            include_str!("../sql/opensim/create_displaynamehistory.sql"),
             include_str!("../sql/opensim/create_inventory.sql"),
             include_str!("../sql/opensim/create_primitives.sql"),
             include_str!("../sql/opensim/create_terrain.sql"),
@@ -31,6 +38,14 @@ impl DatabaseManager {
             backend.execute(query, &[]).await?;
         }
 
+        // Assets go into one table per shard
+        for query in self
+            .asset_sharding()
+            .create_statements(crate::sharding::CREATE_ASSETS)
+        {
+            backend.execute(&query, &[]).await?;
+        }
+
         Ok(())
     }
 
@@ -38,14 +53,18 @@ impl DatabaseManager {
     pub async fn verify_opensim_tables(&self) -> Result<bool> {
         let backend = self.get_backend().await?;
         
-        let required_tables = vec![
-            "regions", "users", "assets", "inventoryitems", 
+        let mut required_tables: Vec<String> = vec![
+            "regions", "users", "griduser", "avatarattachments", "userprofile", "userpicks", "classifieds", "events", "displaynames", "displaynamehistory", "inventoryitems", 
             "inventoryfolders", "primitives", "primshapes", 
             "terrain", "land", "landaccesslist"
-        ];
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        required_tables.extend(self.asset_sharding().tables());
 
         for table in required_tables {
-            let exists = backend.table_exists(table).await?;
+            let exists = backend.table_exists(&table).await?;
             if !exists {
                 return Ok(false);
             }
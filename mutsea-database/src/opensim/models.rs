@@ -81,4 +81,52 @@ impl Asset {
             creator_id: "00000000-0000-0000-0000-000000000000".to_string(),
         }
     }
-}
\ No newline at end of file
+}
+impl GridUser {
+    /// Create a new grid user record with no home or last location
+    pub fn new(user_id: String) -> Self {
+        Self {
+            user_id,
+            home_region_id: "00000000-0000-0000-0000-000000000000".to_string(),
+            home_position: "<0,0,0>".to_string(),
+            home_look_at: "<0,0,0>".to_string(),
+            last_region_id: "00000000-0000-0000-0000-000000000000".to_string(),
+            last_position: "<0,0,0>".to_string(),
+            last_look_at: "<0,0,0>".to_string(),
+            online: "false".to_string(),
+            login: "0".to_string(),
+            logout: "0".to_string(),
+            login_count: 0,
+        }
+    }
+
+    /// Whether a home region has been set for this user
+    pub fn has_home(&self) -> bool {
+        self.home_region_id != "00000000-0000-0000-0000-000000000000"
+    }
+
+    /// Whether a last region has been recorded for this user
+    pub fn has_last_location(&self) -> bool {
+        self.last_region_id != "00000000-0000-0000-0000-000000000000"
+    }
+
+    /// Whether the user is currently marked online
+    pub fn is_online(&self) -> bool {
+        self.online.eq_ignore_ascii_case("true")
+    }
+
+    /// Format a position using OpenSim's `<x,y,z>` vector notation
+    pub fn format_vector(x: f32, y: f32, z: f32) -> String {
+        format!("<{},{},{}>", x, y, z)
+    }
+
+    /// Parse OpenSim's `<x,y,z>` vector notation
+    pub fn parse_vector(value: &str) -> Option<(f32, f32, f32)> {
+        let trimmed = value.trim().trim_start_matches('<').trim_end_matches('>');
+        let mut parts = trimmed.split(',').map(|p| p.trim().parse::<f32>());
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(x)), Some(Ok(y)), Some(Ok(z)), None) => Some((x, y, z)),
+            _ => None,
+        }
+    }
+}
@@ -5,14 +5,16 @@ use super::super::{schema::*, models::*};
 use crate::{DatabaseManager, Result};
 
 impl DatabaseManager {
-    /// Insert a new asset
+    /// Insert a new asset into its shard
     pub async fn insert_asset(&self, asset: &Asset) -> Result<()> {
         let backend = self.get_backend().await?;
-        let query = include_str!("../../sql/opensim/insert_asset.sql");
+        let query = self
+            .asset_sharding()
+            .route(include_str!("../../sql/opensim/insert_asset.sql"), &asset.id);
 
         backend
             .execute(
-                query,
+                &query,
                 &[
                     &asset.id,
                     &asset.name,
@@ -32,12 +34,24 @@ impl DatabaseManager {
         Ok(())
     }
 
-    /// Get asset by ID
+    /// Whether the asset `id` is stored, without fetching its data
+    pub async fn asset_exists(&self, id: &str) -> Result<bool> {
+        let backend = self.get_backend().await?;
+        let query = self
+            .asset_sharding()
+            .route(include_str!("../../sql/opensim/select_asset_exists.sql"), id);
+
+        Ok(backend.query_optional(&query, &[&id]).await?.is_some())
+    }
+
+    /// Get asset by ID from its shard
     pub async fn get_asset(&self, id: &str) -> Result<Option<Asset>> {
         let backend = self.get_backend().await?;
-        let query = include_str!("../../sql/opensim/select_asset.sql");
+        let query = self
+            .asset_sharding()
+            .route(include_str!("../../sql/opensim/select_asset.sql"), id);
 
-        let row = backend.query_optional(query, &[&id]).await?;
+        let row = backend.query_optional(&query, &[&id]).await?;
 
         if let Some(row) = row {
             Ok(Some(Asset {
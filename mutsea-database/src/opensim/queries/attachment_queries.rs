@@ -0,0 +1,68 @@
+// src/opensim/queries/attachment_queries.rs
+//! Avatar attachment database queries
+
+use super::super::schema::*;
+use crate::{DatabaseManager, Result};
+
+impl DatabaseManager {
+    /// Get what a user wears, by attachment point
+    pub async fn get_avatar_attachments(&self, user_id: &str) -> Result<Vec<AvatarAttachment>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_avatar_attachments.sql");
+
+        let rows = backend.query(query, &[&user_id]).await?;
+        rows.iter()
+            .map(|row| {
+                Ok(AvatarAttachment {
+                    user_id: row.get(0)?,
+                    attach_point: row.get(1)?,
+                    item_id: row.get(2)?,
+                    asset_id: row.get(3)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Record an attachment as worn, replacing what was on its point
+    pub async fn set_avatar_attachment(&self, attachment: &AvatarAttachment) -> Result<()> {
+        let backend = self.get_backend().await?;
+        let mut tx = backend.begin_transaction().await?;
+        tx.execute(
+            include_str!("../../sql/opensim/delete_avatar_attachment.sql"),
+            &[&attachment.user_id, &attachment.attach_point],
+        )
+        .await?;
+        tx.execute(
+            include_str!("../../sql/opensim/insert_avatar_attachment.sql"),
+            &[
+                &attachment.user_id,
+                &attachment.attach_point,
+                &attachment.item_id,
+                &attachment.asset_id,
+            ],
+        )
+        .await?;
+        tx.commit().await
+    }
+
+    /// Forget what a user wears on an attachment point
+    pub async fn remove_avatar_attachment(&self, user_id: &str, attach_point: i32) -> Result<()> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/delete_avatar_attachment.sql");
+
+        backend.execute(query, &[&user_id, &attach_point]).await?;
+        Ok(())
+    }
+
+    /// Get the asset of a user's inventory item
+    pub async fn get_inventory_item_asset(&self, item_id: &str, user_id: &str) -> Result<Option<String>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_inventory_item_asset.sql");
+
+        let rows = backend.query(query, &[&item_id, &user_id]).await?;
+        match rows.first() {
+            Some(row) => row.get(0),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+// src/opensim/queries/directory_queries.rs
+//! Classified and event directory database queries
+
+use super::super::schema::*;
+use crate::{DatabaseManager, Result};
+
+impl DatabaseManager {
+    /// Get every classified, the best paid first
+    pub async fn get_classifieds(&self) -> Result<Vec<UserClassified>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_classifieds.sql");
+
+        let rows = backend.query(query, &[]).await?;
+        rows.iter()
+            .map(|row| {
+                let parent_estate: i64 = row.get(8)?;
+                let flags: i64 = row.get(13)?;
+                let price_for_listing: i64 = row.get(14)?;
+                Ok(UserClassified {
+                    classified_id: row.get(0)?,
+                    creator_id: row.get(1)?,
+                    creation_date: row.get(2)?,
+                    expiration_date: row.get(3)?,
+                    category: row.get(4)?,
+                    name: row.get(5)?,
+                    description: row.get(6)?,
+                    parcel_id: row.get(7)?,
+                    parent_estate: parent_estate as i32,
+                    snapshot_id: row.get(9)?,
+                    sim_name: row.get(10)?,
+                    pos_global: row.get(11)?,
+                    parcel_name: row.get(12)?,
+                    flags: flags as i32,
+                    price_for_listing: price_for_listing as i32,
+                })
+            })
+            .collect()
+    }
+
+    /// Save a classified, replacing the one with its ID
+    pub async fn set_classified(&self, classified: &UserClassified) -> Result<()> {
+        let backend = self.get_backend().await?;
+        let mut tx = backend.begin_transaction().await?;
+        tx.execute(
+            include_str!("../../sql/opensim/delete_classified.sql"),
+            &[&classified.classified_id],
+        )
+        .await?;
+        tx.execute(
+            include_str!("../../sql/opensim/insert_classified.sql"),
+            &[
+                &classified.classified_id,
+                &classified.creator_id,
+                &classified.creation_date,
+                &classified.expiration_date,
+                &classified.category,
+                &classified.name,
+                &classified.description,
+                &classified.parcel_id,
+                &classified.parent_estate,
+                &classified.snapshot_id,
+                &classified.sim_name,
+                &classified.pos_global,
+                &classified.parcel_name,
+                &classified.flags,
+                &classified.price_for_listing,
+            ],
+        )
+        .await?;
+        tx.commit().await
+    }
+
+    /// Delete a classified
+    pub async fn remove_classified(&self, classified_id: &str) -> Result<()> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/delete_classified.sql");
+
+        backend.execute(query, &[&classified_id]).await?;
+        Ok(())
+    }
+
+    /// Get every listed event, the soonest first
+    pub async fn get_events(&self) -> Result<Vec<ListedEvent>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_events.sql");
+
+        let rows = backend.query(query, &[]).await?;
+        rows.iter()
+            .map(|row| {
+                let category: i64 = row.get(4)?;
+                let duration: i64 = row.get(7)?;
+                let cover_charge: i64 = row.get(8)?;
+                let cover_amount: i64 = row.get(9)?;
+                let flags: i64 = row.get(13)?;
+                Ok(ListedEvent {
+                    owner_id: row.get(0)?,
+                    name: row.get(1)?,
+                    event_id: row.get(2)?,
+                    creator_id: row.get(3)?,
+                    category: category as i32,
+                    description: row.get(5)?,
+                    date_utc: row.get(6)?,
+                    duration: duration as i32,
+                    cover_charge: cover_charge as i32,
+                    cover_amount: cover_amount as i32,
+                    sim_name: row.get(10)?,
+                    parcel_id: row.get(11)?,
+                    pos_global: row.get(12)?,
+                    flags: flags as i32,
+                })
+            })
+            .collect()
+    }
+
+    /// Save an event, replacing the one with its ID
+    pub async fn set_event(&self, event: &ListedEvent) -> Result<()> {
+        let backend = self.get_backend().await?;
+        let mut tx = backend.begin_transaction().await?;
+        tx.execute(include_str!("../../sql/opensim/delete_event.sql"), &[&event.event_id])
+            .await?;
+        tx.execute(
+            include_str!("../../sql/opensim/insert_event.sql"),
+            &[
+                &event.owner_id,
+                &event.name,
+                &event.event_id,
+                &event.creator_id,
+                &event.category,
+                &event.description,
+                &event.date_utc,
+                &event.duration,
+                &event.cover_charge,
+                &event.cover_amount,
+                &event.sim_name,
+                &event.parcel_id,
+                &event.pos_global,
+                &event.flags,
+            ],
+        )
+        .await?;
+        tx.commit().await
+    }
+
+    /// Delete an event
+    pub async fn remove_event(&self, event_id: i64) -> Result<()> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/delete_event.sql");
+
+        backend.execute(query, &[&event_id]).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+// src/opensim/queries/griduser_queries.rs
+//! GridUser (home/last location and presence) database queries
+
+use super::super::{schema::*, models::*};
+use crate::{DatabaseManager, Result};
+
+impl DatabaseManager {
+    /// Insert a new grid user record
+    pub async fn insert_grid_user(&self, grid_user: &GridUser) -> Result<()> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/insert_griduser.sql");
+
+        backend
+            .execute(
+                query,
+                &[
+                    &grid_user.user_id,
+                    &grid_user.home_region_id,
+                    &grid_user.home_position,
+                    &grid_user.home_look_at,
+                    &grid_user.last_region_id,
+                    &grid_user.last_position,
+                    &grid_user.last_look_at,
+                    &grid_user.online,
+                    &grid_user.login,
+                    &grid_user.logout,
+                    &grid_user.login_count,
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get grid user by user ID
+    pub async fn get_grid_user(&self, user_id: &str) -> Result<Option<GridUser>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_griduser.sql");
+
+        let row = backend.query_optional(query, &[&user_id]).await?;
+
+        if let Some(row) = row {
+            Ok(Some(GridUser {
+                user_id: row.get("user_id")?,
+                home_region_id: row.get("home_region_id")?,
+                home_position: row.get("home_position")?,
+                home_look_at: row.get("home_look_at")?,
+                last_region_id: row.get("last_region_id")?,
+                last_position: row.get("last_position")?,
+                last_look_at: row.get("last_look_at")?,
+                online: row.get("online")?,
+                login: row.get("login")?,
+                logout: row.get("logout")?,
+                login_count: row.get("login_count").unwrap_or(0),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Get a grid user record, creating an empty one if none exists yet
+    pub async fn get_or_create_grid_user(&self, user_id: &str) -> Result<GridUser> {
+        if let Some(grid_user) = self.get_grid_user(user_id).await? {
+            return Ok(grid_user);
+        }
+
+        let grid_user = GridUser::new(user_id.to_string());
+        self.insert_grid_user(&grid_user).await?;
+        Ok(grid_user)
+    }
+
+    /// Set the home location for a user
+    pub async fn set_grid_user_home(
+        &self,
+        user_id: &str,
+        region_id: &str,
+        position: &str,
+        look_at: &str,
+    ) -> Result<()> {
+        self.get_or_create_grid_user(user_id).await?;
+
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/update_griduser_home.sql");
+
+        backend
+            .execute(query, &[&region_id, &position, &look_at, &user_id])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Set the last known location for a user
+    pub async fn set_grid_user_last_location(
+        &self,
+        user_id: &str,
+        region_id: &str,
+        position: &str,
+        look_at: &str,
+    ) -> Result<()> {
+        self.get_or_create_grid_user(user_id).await?;
+
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/update_griduser_last.sql");
+
+        backend
+            .execute(query, &[&region_id, &position, &look_at, &user_id])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Mark a user as logged in and bump their login count
+    pub async fn record_grid_user_login(&self, user_id: &str) -> Result<()> {
+        self.get_or_create_grid_user(user_id).await?;
+
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/update_griduser_login.sql");
+        let now = chrono::Utc::now().timestamp().to_string();
+
+        backend.execute(query, &[&now, &user_id]).await?;
+
+        Ok(())
+    }
+
+    /// Mark a user as logged out, recording where they left the grid
+    pub async fn record_grid_user_logout(
+        &self,
+        user_id: &str,
+        region_id: &str,
+        position: &str,
+        look_at: &str,
+    ) -> Result<()> {
+        self.get_or_create_grid_user(user_id).await?;
+
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/update_griduser_logout.sql");
+        let now = chrono::Utc::now().timestamp().to_string();
+
+        backend
+            .execute(query, &[&now, &region_id, &position, &look_at, &user_id])
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+// src/opensim/queries/inventory_queries.rs
+//! Inventory folder and item database queries
+
+use super::super::schema::*;
+use crate::{DatabaseManager, Result};
+
+impl DatabaseManager {
+    /// Get a user's system folder of a type, such as Lost And Found
+    pub async fn get_inventory_folder_of_type(&self, user_id: &str, folder_type: i32) -> Result<Option<String>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_inventory_folder_of_type.sql");
+
+        let rows = backend.query(query, &[&user_id, &folder_type]).await?;
+        match rows.first() {
+            Some(row) => row.get(0),
+            None => Ok(None),
+        }
+    }
+
+    /// Delete a user's inventory item
+    pub async fn delete_inventory_item(&self, item_id: &str, user_id: &str) -> Result<()> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/delete_inventory_item.sql");
+
+        backend.execute(query, &[&item_id, &user_id]).await?;
+        Ok(())
+    }
+
+    /// Get an inventory folder
+    pub async fn get_inventory_folder(&self, folder_id: &str) -> Result<Option<InventoryFolder>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_inventory_folder.sql");
+
+        let rows = backend.query(query, &[&folder_id]).await?;
+        match rows.first() {
+            Some(row) => Ok(Some(InventoryFolder {
+                folder_id: row.get(0)?,
+                agent_id: row.get(1)?,
+                parent_folder_id: row.get(2)?,
+                name: row.get(3)?,
+                folder_type: row.get(4)?,
+                version: row.get(5)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the folders in an inventory folder, by name
+    pub async fn get_inventory_subfolders(&self, folder_id: &str) -> Result<Vec<InventoryFolder>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_inventory_subfolders.sql");
+
+        let rows = backend.query(query, &[&folder_id]).await?;
+        rows.iter()
+            .map(|row| {
+                Ok(InventoryFolder {
+                    folder_id: row.get(0)?,
+                    agent_id: row.get(1)?,
+                    parent_folder_id: row.get(2)?,
+                    name: row.get(3)?,
+                    folder_type: row.get(4)?,
+                    version: row.get(5)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the items in an inventory folder, by name
+    pub async fn get_inventory_folder_items(&self, folder_id: &str) -> Result<Vec<InventoryItem>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_inventory_folder_items.sql");
+
+        let rows = backend.query(query, &[&folder_id]).await?;
+        rows.iter()
+            .map(|row| {
+                Ok(InventoryItem {
+                    inventory_id: row.get(0)?,
+                    asset_id: row.get(1)?,
+                    asset_type: row.get(2)?,
+                    parent_folder_id: row.get(3)?,
+                    avatar_id: row.get(4)?,
+                    name: row.get(5)?,
+                    description: row.get(6)?,
+                    next_permissions: row.get(7)?,
+                    current_permissions: row.get(8)?,
+                    inv_type: row.get(9)?,
+                    creator_id: row.get(10)?,
+                    base_permissions: row.get(11)?,
+                    everyone_permissions: row.get(12)?,
+                    creation_date: row.get(13)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Get a user's inventory item
+    pub async fn get_inventory_item(&self, item_id: &str, user_id: &str) -> Result<Option<InventoryItem>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_inventory_item.sql");
+
+        let rows = backend.query(query, &[&item_id, &user_id]).await?;
+        match rows.first() {
+            Some(row) => Ok(Some(InventoryItem {
+                inventory_id: row.get(0)?,
+                asset_id: row.get(1)?,
+                asset_type: row.get(2)?,
+                parent_folder_id: row.get(3)?,
+                avatar_id: row.get(4)?,
+                name: row.get(5)?,
+                description: row.get(6)?,
+                next_permissions: row.get(7)?,
+                current_permissions: row.get(8)?,
+                inv_type: row.get(9)?,
+                creator_id: row.get(10)?,
+                base_permissions: row.get(11)?,
+                everyone_permissions: row.get(12)?,
+                creation_date: row.get(13)?,
+            })),
+            None => Ok(None),
+        }
+    }
+}
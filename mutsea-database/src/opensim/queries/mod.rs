@@ -4,3 +4,9 @@
 pub mod user_queries;
 pub mod asset_queries;
 pub mod region_queries;
+pub mod griduser_queries;
+pub mod attachment_queries;
+pub mod profile_queries;
+pub mod directory_queries;
+pub mod name_queries;
+pub mod inventory_queries;
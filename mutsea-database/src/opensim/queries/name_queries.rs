@@ -0,0 +1,100 @@
+// src/opensim/queries/name_queries.rs
+//! Display name database queries
+
+use super::super::schema::*;
+use crate::{DatabaseManager, Result};
+
+impl DatabaseManager {
+    /// Get the display name of a user, if it ever set one
+    pub async fn get_display_name(&self, user_id: &str) -> Result<Option<DisplayName>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_displayname.sql");
+
+        let rows = backend.query(query, &[&user_id]).await?;
+        match rows.first() {
+            Some(row) => Ok(Some(DisplayName {
+                user_id: row.get(0)?,
+                display_name: row.get(1)?,
+                last_changed: row.get(2)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Get up to `limit` display names containing `text`, ignoring case
+    pub async fn search_display_names(&self, text: &str, limit: i64) -> Result<Vec<DisplayName>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/search_displaynames.sql");
+        let pattern = format!("%{}%", text.to_lowercase());
+
+        let rows = backend.query(query, &[&pattern, &limit]).await?;
+        rows.iter()
+            .map(|row| {
+                Ok(DisplayName {
+                    user_id: row.get(0)?,
+                    display_name: row.get(1)?,
+                    last_changed: row.get(2)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the display name equal to `display_name`, ignoring case, if
+    /// anyone goes by it
+    pub async fn find_display_name(&self, display_name: &str) -> Result<Option<DisplayName>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_displayname_owner.sql");
+        let name = display_name.to_lowercase();
+
+        let rows = backend.query(query, &[&name]).await?;
+        match rows.first() {
+            Some(row) => Ok(Some(DisplayName {
+                user_id: row.get(0)?,
+                display_name: row.get(1)?,
+                last_changed: row.get(2)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Save the display name of a user, replacing what it had, and record
+    /// the change in its history
+    pub async fn set_display_name(&self, name: &DisplayName, change: &DisplayNameChange) -> Result<()> {
+        let backend = self.get_backend().await?;
+        let mut tx = backend.begin_transaction().await?;
+        tx.execute(
+            include_str!("../../sql/opensim/delete_displayname.sql"),
+            &[&name.user_id],
+        )
+        .await?;
+        tx.execute(
+            include_str!("../../sql/opensim/insert_displayname.sql"),
+            &[&name.user_id, &name.display_name, &name.last_changed],
+        )
+        .await?;
+        tx.execute(
+            include_str!("../../sql/opensim/insert_displaynamehistory.sql"),
+            &[&change.user_id, &change.old_name, &change.new_name, &change.changed],
+        )
+        .await?;
+        tx.commit().await
+    }
+
+    /// Get the latest `limit` display name changes of a user, newest first
+    pub async fn get_display_name_history(&self, user_id: &str, limit: i64) -> Result<Vec<DisplayNameChange>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_displaynamehistory.sql");
+
+        let rows = backend.query(query, &[&user_id, &limit]).await?;
+        rows.iter()
+            .map(|row| {
+                Ok(DisplayNameChange {
+                    user_id: row.get(0)?,
+                    old_name: row.get(1)?,
+                    new_name: row.get(2)?,
+                    changed: row.get(3)?,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,154 @@
+// src/opensim/queries/profile_queries.rs
+//! Avatar profile, pick and landmark database queries
+
+use super::super::schema::*;
+use crate::{DatabaseManager, Result};
+
+impl DatabaseManager {
+    /// Get a user's profile, if it was ever saved
+    pub async fn get_user_profile(&self, user_id: &str) -> Result<Option<UserProfile>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_userprofile.sql");
+
+        let rows = backend.query(query, &[&user_id]).await?;
+        match rows.first() {
+            Some(row) => Ok(Some(UserProfile {
+                user_id: row.get(0)?,
+                partner_id: row.get(1)?,
+                allow_publish: row.get(2)?,
+                mature_publish: row.get(3)?,
+                url: row.get(4)?,
+                image_id: row.get(5)?,
+                about_text: row.get(6)?,
+                first_image_id: row.get(7)?,
+                first_text: row.get(8)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Save a user's profile, replacing what it had
+    pub async fn set_user_profile(&self, profile: &UserProfile) -> Result<()> {
+        let backend = self.get_backend().await?;
+        let mut tx = backend.begin_transaction().await?;
+        tx.execute(
+            include_str!("../../sql/opensim/delete_userprofile.sql"),
+            &[&profile.user_id],
+        )
+        .await?;
+        tx.execute(
+            include_str!("../../sql/opensim/insert_userprofile.sql"),
+            &[
+                &profile.user_id,
+                &profile.partner_id,
+                &profile.allow_publish,
+                &profile.mature_publish,
+                &profile.url,
+                &profile.image_id,
+                &profile.about_text,
+                &profile.first_image_id,
+                &profile.first_text,
+            ],
+        )
+        .await?;
+        tx.commit().await
+    }
+
+    /// Get the picks of a user's profile, in the order they are shown
+    pub async fn get_user_picks(&self, creator_id: &str) -> Result<Vec<UserPick>> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/select_userpicks.sql");
+
+        let rows = backend.query(query, &[&creator_id]).await?;
+        rows.iter()
+            .map(|row| {
+                let sort_order: i64 = row.get(11)?;
+                Ok(UserPick {
+                    pick_id: row.get(0)?,
+                    creator_id: row.get(1)?,
+                    top_pick: row.get(2)?,
+                    parcel_id: row.get(3)?,
+                    name: row.get(4)?,
+                    description: row.get(5)?,
+                    snapshot_id: row.get(6)?,
+                    user: row.get(7)?,
+                    original_name: row.get(8)?,
+                    sim_name: row.get(9)?,
+                    pos_global: row.get(10)?,
+                    sort_order: sort_order as i32,
+                    enabled: row.get(12)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Save a pick, replacing the one with its ID
+    pub async fn set_user_pick(&self, pick: &UserPick) -> Result<()> {
+        let backend = self.get_backend().await?;
+        let mut tx = backend.begin_transaction().await?;
+        tx.execute(
+            include_str!("../../sql/opensim/delete_userpick.sql"),
+            &[&pick.pick_id, &pick.creator_id],
+        )
+        .await?;
+        tx.execute(
+            include_str!("../../sql/opensim/insert_userpick.sql"),
+            &[
+                &pick.pick_id,
+                &pick.creator_id,
+                &pick.top_pick,
+                &pick.parcel_id,
+                &pick.name,
+                &pick.description,
+                &pick.snapshot_id,
+                &pick.user,
+                &pick.original_name,
+                &pick.sim_name,
+                &pick.pos_global,
+                &pick.sort_order,
+                &pick.enabled,
+            ],
+        )
+        .await?;
+        tx.commit().await
+    }
+
+    /// Delete one of a user's picks
+    pub async fn remove_user_pick(&self, pick_id: &str, creator_id: &str) -> Result<()> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/delete_userpick.sql");
+
+        backend.execute(query, &[&pick_id, &creator_id]).await?;
+        Ok(())
+    }
+
+    /// Add an item to a user's inventory
+    pub async fn insert_inventory_item(&self, item: &InventoryItem) -> Result<()> {
+        let backend = self.get_backend().await?;
+        let query = include_str!("../../sql/opensim/insert_inventory_item.sql");
+
+        backend
+            .execute(
+                query,
+                &[
+                    &item.inventory_id,
+                    &item.asset_id,
+                    &item.asset_type,
+                    &item.parent_folder_id,
+                    &item.avatar_id,
+                    &item.name,
+                    &item.description,
+                    &item.next_permissions,
+                    &item.current_permissions,
+                    &item.inv_type,
+                    &item.creator_id,
+                    &item.base_permissions,
+                    &item.everyone_permissions,
+                    &item.creation_date,
+                    &item.avatar_id,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -68,3 +68,162 @@ pub struct Asset {
     pub asset_flags: i32,
     pub creator_id: String,
 }
+
+/// A worn attachment compatible with OpenSim's avatarattachments table
+#[derive(Debug, Clone)]
+pub struct AvatarAttachment {
+    pub user_id: String,
+    pub attach_point: i32,
+    pub item_id: String,
+    pub asset_id: String,
+}
+
+/// An avatar profile compatible with OpenSim's userprofile table
+#[derive(Debug, Clone)]
+pub struct UserProfile {
+    pub user_id: String,
+    pub partner_id: String,
+    /// `"true"` or `"false"`, as OpenSim stores it
+    pub allow_publish: String,
+    pub mature_publish: String,
+    pub url: String,
+    pub image_id: String,
+    pub about_text: String,
+    /// The First Life tab
+    pub first_image_id: String,
+    pub first_text: String,
+}
+
+/// The display name an avatar goes by, kept in the displaynames table
+#[derive(Debug, Clone)]
+pub struct DisplayName {
+    pub user_id: String,
+    /// Empty while the avatar goes by its legacy name
+    pub display_name: String,
+    /// Unix time the name was last changed
+    pub last_changed: i64,
+}
+
+/// A display name change, kept in the displaynamehistory table
+#[derive(Debug, Clone)]
+pub struct DisplayNameChange {
+    pub user_id: String,
+    /// Empty for the legacy name
+    pub old_name: String,
+    /// Empty for the legacy name
+    pub new_name: String,
+    /// Unix time of the change
+    pub changed: i64,
+}
+
+/// A place picked in a profile, compatible with OpenSim's userpicks table
+#[derive(Debug, Clone)]
+pub struct UserPick {
+    pub pick_id: String,
+    pub creator_id: String,
+    pub top_pick: String,
+    pub parcel_id: String,
+    pub name: String,
+    pub description: String,
+    pub snapshot_id: String,
+    pub user: String,
+    pub original_name: String,
+    pub sim_name: String,
+    /// Global position in OpenSim's `<x,y,z>` vector notation
+    pub pos_global: String,
+    pub sort_order: i32,
+    pub enabled: String,
+}
+
+/// A classified ad compatible with OpenSim's classifieds table
+#[derive(Debug, Clone)]
+pub struct UserClassified {
+    pub classified_id: String,
+    pub creator_id: String,
+    /// Unix time
+    pub creation_date: i64,
+    pub expiration_date: i64,
+    /// The category number, as text
+    pub category: String,
+    pub name: String,
+    pub description: String,
+    pub parcel_id: String,
+    pub parent_estate: i32,
+    pub snapshot_id: String,
+    pub sim_name: String,
+    /// Global position in OpenSim's `<x,y,z>` vector notation
+    pub pos_global: String,
+    pub parcel_name: String,
+    pub flags: i32,
+    pub price_for_listing: i32,
+}
+
+/// A listed event compatible with the events table of OpenSim's search
+#[derive(Debug, Clone)]
+pub struct ListedEvent {
+    pub owner_id: String,
+    pub name: String,
+    pub event_id: i64,
+    pub creator_id: String,
+    pub category: i32,
+    pub description: String,
+    /// Unix time the event starts
+    pub date_utc: i64,
+    /// Minutes
+    pub duration: i32,
+    /// 1 when there is a cover charge
+    pub cover_charge: i32,
+    pub cover_amount: i32,
+    pub sim_name: String,
+    pub parcel_id: String,
+    /// Global position in OpenSim's `<x,y,z>` vector notation
+    pub pos_global: String,
+    pub flags: i32,
+}
+
+/// An inventory item compatible with OpenSim's inventoryitems table
+#[derive(Debug, Clone)]
+pub struct InventoryItem {
+    pub inventory_id: String,
+    pub asset_id: String,
+    pub asset_type: i32,
+    pub parent_folder_id: String,
+    pub avatar_id: String,
+    pub name: String,
+    pub description: String,
+    pub next_permissions: i32,
+    pub current_permissions: i32,
+    pub inv_type: i32,
+    pub creator_id: String,
+    pub base_permissions: i32,
+    pub everyone_permissions: i32,
+    pub creation_date: i32,
+}
+
+/// An inventory folder compatible with OpenSim's inventoryfolders table
+#[derive(Debug, Clone)]
+pub struct InventoryFolder {
+    pub folder_id: String,
+    pub agent_id: String,
+    pub parent_folder_id: String,
+    pub name: String,
+    /// The asset type of the system folder it is, -1 for a user's own folder
+    pub folder_type: i32,
+    pub version: i32,
+}
+
+/// Grid user presence and home/last location compatible with OpenSim's GridUser table
+#[derive(Debug, Clone)]
+pub struct GridUser {
+    pub user_id: String,
+    pub home_region_id: String,
+    pub home_position: String,
+    pub home_look_at: String,
+    pub last_region_id: String,
+    pub last_position: String,
+    pub last_look_at: String,
+    pub online: String,
+    pub login: String,
+    pub logout: String,
+    pub login_count: i32,
+}
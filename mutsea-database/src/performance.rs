@@ -0,0 +1,49 @@
+// mutsea-database/src/performance.rs
+//! Persistence of aggregated performance samples into `performance_metrics`
+
+use crate::DatabaseManager;
+use async_trait::async_trait;
+use mutsea_core::{MutseaError, MutseaResult, PerformanceRecorder, PerformanceSample};
+
+#[async_trait]
+impl PerformanceRecorder for DatabaseManager {
+    async fn record_performance(&self, samples: &[PerformanceSample]) -> MutseaResult<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        let backend = self
+            .get_backend()
+            .await
+            .map_err(|e| MutseaError::Database(e.to_string()))?;
+        let mut transaction = backend
+            .begin_transaction()
+            .await
+            .map_err(|e| MutseaError::Database(e.to_string()))?;
+        let query = include_str!("sql/postgresql/performance/insert_performance_sample.sql");
+
+        for sample in samples {
+            let timestamp = sample.timestamp.to_rfc3339();
+            transaction
+                .execute(
+                    query,
+                    &[
+                        &timestamp,
+                        &sample.component_name,
+                        &sample.metric_type,
+                        &sample.metric_value,
+                        &sample.unit_of_measure,
+                        &sample.context_data,
+                        &sample.alert_level,
+                    ],
+                )
+                .await
+                .map_err(|e| MutseaError::Database(e.to_string()))?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| MutseaError::Database(e.to_string()))
+    }
+}
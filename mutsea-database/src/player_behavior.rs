@@ -0,0 +1,87 @@
+// mutsea-database/src/player_behavior.rs
+//! Persistence of player behavior records into `player_behaviors`
+
+use crate::backends::DatabaseBackend;
+use crate::error::DatabaseError;
+use crate::{DatabaseManager, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use mutsea_core::{MutseaError, MutseaResult, PlayerBehaviorRecord, PlayerBehaviorRecorder, UserId};
+use uuid::Uuid;
+
+#[async_trait]
+impl PlayerBehaviorRecorder for DatabaseManager {
+    async fn record_player_behaviors(&self, records: &[PlayerBehaviorRecord]) -> MutseaResult<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let backend = self
+            .get_backend()
+            .await
+            .map_err(|e| MutseaError::Database(e.to_string()))?;
+        let mut transaction = backend
+            .begin_transaction()
+            .await
+            .map_err(|e| MutseaError::Database(e.to_string()))?;
+        let query = include_str!("sql/postgresql/player_behavior/insert_behavior_record.sql");
+
+        for record in records {
+            let player_id = record.player_id.0.to_string();
+            let session_id = record.session_id.to_string();
+            let timestamp = record.timestamp.to_rfc3339();
+            transaction
+                .execute(
+                    query,
+                    &[
+                        &player_id,
+                        &session_id,
+                        &timestamp,
+                        &record.action_type,
+                        &record.action_data,
+                        &record.context_data,
+                    ],
+                )
+                .await
+                .map_err(|e| MutseaError::Database(e.to_string()))?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| MutseaError::Database(e.to_string()))
+    }
+}
+
+/// The `session_end` records of `player_id` for sessions ended since
+/// `since`, newest first, at most `limit` of them
+pub async fn session_ends(
+    backend: &dyn DatabaseBackend,
+    player_id: Uuid,
+    since: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<PlayerBehaviorRecord>> {
+    let id = player_id.to_string();
+    let since = since.to_rfc3339();
+    let rows = backend
+        .query(
+            include_str!("sql/postgresql/player_behavior/select_session_ends.sql"),
+            &[&id, &since, &limit],
+        )
+        .await?;
+
+    rows.iter()
+        .map(|row| {
+            let session_id: String = row.get(0)?;
+            let ended_at: i64 = row.get(1)?;
+            Ok(PlayerBehaviorRecord {
+                player_id: UserId::from_uuid(player_id),
+                session_id: Uuid::parse_str(&session_id).map_err(|e| DatabaseError::Serialization(e.to_string()))?,
+                timestamp: Utc.timestamp_opt(ended_at, 0).single().unwrap_or_default(),
+                action_type: "session_end".to_string(),
+                action_data: row.get(2)?,
+                context_data: row.get(3)?,
+            })
+        })
+        .collect()
+}
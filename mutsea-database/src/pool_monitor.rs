@@ -0,0 +1,371 @@
+//! Connection pool gauges and leak detection
+//!
+//! Every backend handed out by [`DatabaseManager::get_backend`] and every
+//! transaction begun on one counts as a checked-out connection until it is
+//! dropped, including the transaction a streamed query reads its pages in.
+//! [`PoolMonitor`] keeps live gauges of those checkouts and of how
+//! long `begin_transaction` waited for a connection, and remembers where
+//! each checkout was taken: a checkout held past the leak threshold is
+//! logged once with that stack trace. Stacks are only captured with
+//! `RUST_BACKTRACE` set, or always with
+//! [`with_forced_backtraces`](PoolMonitor::with_forced_backtraces).
+//!
+//! [`DatabaseManager::get_backend`]: crate::DatabaseManager::get_backend
+
+use crate::backends::{BackendType, DatabaseBackend, Row, ToSql, Transaction};
+use crate::utils::ConnectionStats;
+use crate::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use mutsea_core::config::DatabaseConfig;
+use serde::{Deserialize, Serialize};
+use std::backtrace::Backtrace;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// An open checkout
+#[derive(Debug)]
+struct Held {
+    since: Instant,
+    what: &'static str,
+    backtrace: Backtrace,
+    reported: bool,
+}
+
+/// Live checkout and wait gauges for one pool
+#[derive(Debug)]
+pub struct PoolMonitor {
+    max_connections: u32,
+    leak_threshold: Option<Duration>,
+    force_backtraces: bool,
+    next_id: AtomicU64,
+    held: Mutex<HashMap<u64, Held>>,
+    peak: AtomicU64,
+    checkouts: AtomicU64,
+    waits: AtomicU64,
+    wait_total_us: AtomicU64,
+    wait_max_us: AtomicU64,
+    leaks: AtomicU64,
+}
+
+/// Point-in-time pool gauges
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PoolGauges {
+    pub checked_out: u32,
+    /// `max_connections` minus checked out
+    pub idle: u32,
+    pub max_connections: u32,
+    pub peak_checked_out: u32,
+    pub total_checkouts: u64,
+    pub average_wait: Duration,
+    pub max_wait: Duration,
+    /// Checkouts held past the leak threshold, ever
+    pub leaked: u64,
+    /// Age of the longest-held open checkout
+    pub oldest_checkout: Duration,
+}
+
+/// A checkout held past the leak threshold
+#[derive(Debug, Clone)]
+pub struct LeakedConnection {
+    pub held_for: Duration,
+    pub what: &'static str,
+    pub backtrace: String,
+}
+
+impl PoolMonitor {
+    /// Monitor for a pool of `max_connections`, reporting checkouts held
+    /// `leak_threshold` or longer
+    pub fn new(max_connections: u32, leak_threshold: Option<Duration>) -> Self {
+        Self {
+            max_connections,
+            leak_threshold,
+            force_backtraces: false,
+            next_id: AtomicU64::new(1),
+            held: Mutex::new(HashMap::new()),
+            peak: AtomicU64::new(0),
+            checkouts: AtomicU64::new(0),
+            waits: AtomicU64::new(0),
+            wait_total_us: AtomicU64::new(0),
+            wait_max_us: AtomicU64::new(0),
+            leaks: AtomicU64::new(0),
+        }
+    }
+
+    /// Monitor sized and thresholded by `[database]`
+    pub fn from_config(config: &DatabaseConfig) -> Self {
+        let threshold = (config.connection_leak_secs > 0).then(|| Duration::from_secs(config.connection_leak_secs));
+        Self::new(config.max_connections, threshold).with_forced_backtraces(config.capture_leak_backtraces)
+    }
+
+    /// Capture a stack trace at every checkout regardless of `RUST_BACKTRACE`
+    pub fn with_forced_backtraces(mut self, force: bool) -> Self {
+        self.force_backtraces = force;
+        self
+    }
+
+    pub fn leak_threshold(&self) -> Option<Duration> {
+        self.leak_threshold
+    }
+
+    /// Count a connection as checked out until the returned guard drops
+    pub fn checkout(self: &Arc<Self>, what: &'static str) -> Checkout {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let backtrace = if self.leak_threshold.is_none() {
+            Backtrace::disabled()
+        } else if self.force_backtraces {
+            Backtrace::force_capture()
+        } else {
+            Backtrace::capture()
+        };
+        let open = {
+            let mut held = self.held.lock().unwrap();
+            held.insert(
+                id,
+                Held {
+                    since: Instant::now(),
+                    what,
+                    backtrace,
+                    reported: false,
+                },
+            );
+            held.len() as u64
+        };
+        self.peak.fetch_max(open, Ordering::Relaxed);
+        self.checkouts.fetch_add(1, Ordering::Relaxed);
+        Checkout {
+            monitor: Arc::clone(self),
+            id,
+        }
+    }
+
+    /// Record how long acquiring a connection took
+    pub fn record_wait(&self, wait: Duration) {
+        let us = wait.as_micros() as u64;
+        self.waits.fetch_add(1, Ordering::Relaxed);
+        self.wait_total_us.fetch_add(us, Ordering::Relaxed);
+        self.wait_max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> PoolGauges {
+        let (checked_out, oldest_checkout) = {
+            let held = self.held.lock().unwrap();
+            let oldest = held.values().map(|h| h.since.elapsed()).max().unwrap_or_default();
+            (held.len() as u32, oldest)
+        };
+        let waits = self.waits.load(Ordering::Relaxed);
+        PoolGauges {
+            checked_out,
+            idle: self.max_connections.saturating_sub(checked_out),
+            max_connections: self.max_connections,
+            peak_checked_out: self.peak.load(Ordering::Relaxed) as u32,
+            total_checkouts: self.checkouts.load(Ordering::Relaxed),
+            average_wait: Duration::from_micros(self.wait_total_us.load(Ordering::Relaxed) / waits.max(1)),
+            max_wait: Duration::from_micros(self.wait_max_us.load(Ordering::Relaxed)),
+            leaked: self.leaks.load(Ordering::Relaxed),
+            oldest_checkout,
+        }
+    }
+
+    /// Checkouts newly past the leak threshold; each is returned once
+    pub fn detect_leaks(&self) -> Vec<LeakedConnection> {
+        let Some(threshold) = self.leak_threshold else {
+            return Vec::new();
+        };
+        let mut leaks = Vec::new();
+        for held in self.held.lock().unwrap().values_mut() {
+            let held_for = held.since.elapsed();
+            if held.reported || held_for < threshold {
+                continue;
+            }
+            held.reported = true;
+            leaks.push(LeakedConnection {
+                held_for,
+                what: held.what,
+                backtrace: held.backtrace.to_string(),
+            });
+        }
+        self.leaks.fetch_add(leaks.len() as u64, Ordering::Relaxed);
+        leaks
+    }
+
+    /// Log leaked checkouts every quarter of the leak threshold
+    pub fn spawn_leak_detector(self: &Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        let threshold = self.leak_threshold?;
+        let monitor = Arc::clone(self);
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval((threshold / 4).max(Duration::from_secs(1)));
+            loop {
+                interval.tick().await;
+                for leak in monitor.detect_leaks() {
+                    warn!(
+                        "Database connection ({}) checked out for {:?}, possible leak; checked out at:\n{}",
+                        leak.what, leak.held_for, leak.backtrace
+                    );
+                }
+            }
+        }))
+    }
+
+    fn release(&self, id: u64) {
+        self.held.lock().unwrap().remove(&id);
+    }
+}
+
+impl Default for PoolMonitor {
+    fn default() -> Self {
+        Self::from_config(&DatabaseConfig::default())
+    }
+}
+
+impl PoolGauges {
+    /// The gauges as [`ConnectionStats`], for the health report
+    pub fn connection_stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            total_connections: self.max_connections,
+            active_connections: self.checked_out,
+            idle_connections: self.idle,
+            failed_connections: 0,
+            average_connection_time: self.average_wait,
+            peak_connections: self.peak_checked_out,
+            total_queries_executed: 0,
+            average_query_time: Duration::ZERO,
+            last_updated: Utc::now(),
+            max_wait_time: self.max_wait,
+            leaked_connections: self.leaked,
+            oldest_checkout: self.oldest_checkout,
+        }
+    }
+}
+
+/// A checked-out connection; released on drop
+#[derive(Debug)]
+pub struct Checkout {
+    monitor: Arc<PoolMonitor>,
+    id: u64,
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        self.monitor.release(self.id);
+    }
+}
+
+/// Backend holding a checkout for as long as it lives; transactions begun
+/// on it hold their own
+pub struct MonitoredBackend {
+    inner: Box<dyn DatabaseBackend>,
+    monitor: Arc<PoolMonitor>,
+    _checkout: Checkout,
+}
+
+impl MonitoredBackend {
+    pub fn new(inner: Box<dyn DatabaseBackend>, monitor: Arc<PoolMonitor>) -> Self {
+        let checkout = monitor.checkout("backend");
+        Self {
+            inner,
+            monitor,
+            _checkout: checkout,
+        }
+    }
+}
+
+#[async_trait]
+impl DatabaseBackend for MonitoredBackend {
+    async fn execute(&self, query: &str, params: &[&dyn ToSql]) -> Result<u64> {
+        self.inner.execute(query, params).await
+    }
+
+    async fn query(&self, query: &str, params: &[&dyn ToSql]) -> Result<Vec<Row>> {
+        self.inner.query(query, params).await
+    }
+
+    async fn query_one(&self, query: &str, params: &[&dyn ToSql]) -> Result<Row> {
+        self.inner.query_one(query, params).await
+    }
+
+    async fn table_exists(&self, table_name: &str) -> Result<bool> {
+        self.inner.table_exists(table_name).await
+    }
+
+    async fn begin_transaction(&self) -> Result<Box<dyn Transaction>> {
+        let start = Instant::now();
+        let inner = self.inner.begin_transaction().await?;
+        self.monitor.record_wait(start.elapsed());
+        Ok(Box::new(MonitoredTransaction {
+            inner,
+            _checkout: self.monitor.checkout("transaction"),
+        }))
+    }
+
+    fn backend_type(&self) -> BackendType {
+        self.inner.backend_type()
+    }
+}
+
+struct MonitoredTransaction {
+    inner: Box<dyn Transaction>,
+    _checkout: Checkout,
+}
+
+#[async_trait]
+impl Transaction for MonitoredTransaction {
+    async fn execute(&mut self, query: &str, params: &[&dyn ToSql]) -> Result<u64> {
+        self.inner.execute(query, params).await
+    }
+
+    async fn query(&mut self, query: &str, params: &[&dyn ToSql]) -> Result<Vec<Row>> {
+        self.inner.query(query, params).await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        self.inner.commit().await
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<()> {
+        self.inner.rollback().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gauges_follow_checkouts() {
+        let monitor = Arc::new(PoolMonitor::new(4, None));
+        let a = monitor.checkout("backend");
+        let b = monitor.checkout("transaction");
+        monitor.record_wait(Duration::from_millis(10));
+        monitor.record_wait(Duration::from_millis(30));
+
+        let gauges = monitor.snapshot();
+        assert_eq!((gauges.checked_out, gauges.idle, gauges.peak_checked_out), (2, 2, 2));
+        assert_eq!(gauges.average_wait, Duration::from_millis(20));
+        assert_eq!(gauges.max_wait, Duration::from_millis(30));
+
+        drop(a);
+        drop(b);
+        let gauges = monitor.snapshot();
+        assert_eq!((gauges.checked_out, gauges.peak_checked_out, gauges.total_checkouts), (0, 2, 2));
+    }
+
+    #[test]
+    fn test_leaks_are_reported_once() {
+        let monitor = Arc::new(PoolMonitor::new(4, Some(Duration::ZERO)));
+        let held = monitor.checkout("backend");
+        let leaks = monitor.detect_leaks();
+        assert_eq!(leaks.len(), 1);
+        assert_eq!(leaks[0].what, "backend");
+        assert!(monitor.detect_leaks().is_empty());
+        assert_eq!(monitor.snapshot().leaked, 1);
+        drop(held);
+
+        let quiet = Arc::new(PoolMonitor::new(4, None));
+        let _held = quiet.checkout("backend");
+        assert!(quiet.detect_leaks().is_empty());
+    }
+}
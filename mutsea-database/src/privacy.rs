@@ -0,0 +1,322 @@
+//! Export and erasure of a user's personal data
+//!
+//! [`PERSONAL_DATA`] lists every table holding rows about a user, the
+//! column naming them, and what forgetting the user does to those rows:
+//! most are deleted, while rows others depend on (the account, ledger
+//! entries) are kept with the personal fields cleared. [`export`] gathers
+//! all of them into one [`PersonalDataBundle`]; [`forget`] applies the
+//! erasure in one transaction and leaves a tombstone holding only a hash
+//! of the user ID, so imports and restores can tell the user was
+//! forgotten without keeping who it was. Tables missing from a database
+//! (e.g. the PostgreSQL-only analytics on SQLite) are skipped.
+
+use crate::backends::cursor::DEFAULT_BATCH_SIZE;
+use crate::backends::{BackendType, DatabaseBackend, SqlValue, Transaction};
+use crate::{DatabaseError, Result};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// What forgetting a user does to one of their rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Erasure {
+    Delete,
+    /// Keep the row, running these `column = value` assignments
+    Anonymize(&'static [&'static str]),
+}
+
+/// Rows of `table` about the user whose ID is in `user_column`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PersonalData {
+    pub table: &'static str,
+    pub user_column: &'static str,
+    /// Further condition narrowing the rows, if the table also holds rows
+    /// about others
+    pub condition: Option<&'static str>,
+    pub erasure: Erasure,
+}
+
+const fn delete(table: &'static str, user_column: &'static str) -> PersonalData {
+    PersonalData {
+        table,
+        user_column,
+        condition: None,
+        erasure: Erasure::Delete,
+    }
+}
+
+/// Every table with personal data
+pub const PERSONAL_DATA: &[PersonalData] = &[
+    // The account stays so that names of creators and owners still resolve
+    PersonalData {
+        table: "user_accounts",
+        user_column: "principal_id",
+        condition: None,
+        erasure: Erasure::Anonymize(&[
+            "first_name = 'Forgotten'",
+            "last_name = 'Resident'",
+            "email = NULL",
+            "service_urls = NULL",
+            "user_title = NULL",
+            "active = 0",
+        ]),
+    },
+    delete("griduser", "user_id"),
+    delete("userprofile", "useruuid"),
+    delete("userpicks", "creatoruuid"),
+    delete("classifieds", "creatoruuid"),
+    delete("events", "creatoruuid"),
+    delete("events", "owneruuid"),
+    delete("displaynames", "useruuid"),
+    delete("displaynamehistory", "useruuid"),
+    delete("avatarattachments", "uuid"),
+    delete("inventoryitems", "avatar_id"),
+    delete("inventoryfolders", "agent_id"),
+    delete("im_offline", "principalid"),
+    delete("economy_balances", "agent_id"),
+    // Ledger entries are kept for the other party's history
+    PersonalData {
+        table: "economy_transactions",
+        user_column: "source_id",
+        condition: None,
+        erasure: Erasure::Anonymize(&["source_id = NULL", "description = NULL"]),
+    },
+    PersonalData {
+        table: "economy_transactions",
+        user_column: "destination_id",
+        condition: None,
+        erasure: Erasure::Anonymize(&["destination_id = NULL", "description = NULL"]),
+    },
+    delete("land_auction_bids", "bidder_id"),
+    delete("chat_log", "sender_id"),
+    delete("chat_log", "recipient_id"),
+    delete("player_behaviors", "player_id"),
+    PersonalData {
+        table: "learning_data",
+        user_column: "subject_id",
+        condition: Some("subject_type = 'player'"),
+        erasure: Erasure::Delete,
+    },
+];
+
+const CREATE_TOMBSTONES: &str = "CREATE TABLE IF NOT EXISTS user_tombstones (
+    user_hash VARCHAR(64) NOT NULL PRIMARY KEY,
+    forgotten_at BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    affected TEXT NOT NULL
+)";
+
+/// Everything stored about a user, by table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PersonalDataBundle {
+    pub user_id: Uuid,
+    pub exported_at: DateTime<Utc>,
+    pub tables: BTreeMap<String, Vec<Map<String, Value>>>,
+}
+
+/// Rows deleted or anonymized when forgetting a user
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForgetReport {
+    pub deleted: BTreeMap<String, u64>,
+    pub anonymized: BTreeMap<String, u64>,
+}
+
+/// Hash kept in the tombstone instead of the user ID
+pub fn tombstone_hash(user_id: Uuid) -> String {
+    hex::encode(Sha256::digest(user_id.as_bytes()))
+}
+
+impl PersonalData {
+    fn where_clause(&self) -> String {
+        let mut clause = format!("CAST({} AS TEXT) = ?", self.user_column);
+        if let Some(condition) = self.condition {
+            clause.push_str(" AND ");
+            clause.push_str(condition);
+        }
+        clause
+    }
+}
+
+async fn columns(backend: &dyn DatabaseBackend, table: &str) -> Result<Vec<String>> {
+    let query = match backend.backend_type() {
+        BackendType::PostgreSQL => {
+            "SELECT column_name FROM information_schema.columns WHERE table_name = ? ORDER BY ordinal_position"
+        }
+        BackendType::SQLite => "SELECT name FROM pragma_table_info(?)",
+    };
+    backend
+        .query(query, &[&table.to_lowercase()])
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect()
+}
+
+fn json_value(value: SqlValue) -> Value {
+    match value {
+        SqlValue::Null => Value::Null,
+        SqlValue::Bool(b) => Value::Bool(b),
+        SqlValue::Int(i) => Value::from(i),
+        SqlValue::Float(f) => Value::from(f),
+        SqlValue::Text(s) => Value::String(s),
+        SqlValue::Bytes(bytes) => Value::String(hex::encode(bytes)),
+        SqlValue::Json(value) => value,
+    }
+}
+
+/// Every row about `user_id`
+pub async fn export(backend: &dyn DatabaseBackend, user_id: Uuid) -> Result<PersonalDataBundle> {
+    let id = user_id.to_string();
+    let mut tables: BTreeMap<String, Vec<Map<String, Value>>> = BTreeMap::new();
+    for data in PERSONAL_DATA {
+        if !backend.table_exists(data.table).await? {
+            continue;
+        }
+        let columns = columns(backend, data.table).await?;
+        let query = format!(
+            "SELECT {} FROM {} WHERE {}",
+            columns.join(", "),
+            data.table,
+            data.where_clause()
+        );
+        let rows = tables.entry(data.table.to_string()).or_default();
+        let mut stream = backend.query_stream(&query, &[&id], DEFAULT_BATCH_SIZE);
+        while let Some(row) = stream.try_next().await? {
+            let mut record = Map::new();
+            for (index, column) in columns.iter().enumerate() {
+                let value: SqlValue = row.get(index)?;
+                record.insert(column.clone(), json_value(value));
+            }
+            // A ledger entry naming the user on both sides is listed once
+            if !rows.contains(&record) {
+                rows.push(record);
+            }
+        }
+    }
+    tables.retain(|_, rows| !rows.is_empty());
+    Ok(PersonalDataBundle {
+        user_id,
+        exported_at: Utc::now(),
+        tables,
+    })
+}
+
+/// Delete or anonymize every row about `user_id` and record a tombstone
+pub async fn forget(backend: &dyn DatabaseBackend, user_id: Uuid, reason: &str) -> Result<ForgetReport> {
+    backend.execute(CREATE_TOMBSTONES, &[]).await?;
+    let mut existing = Vec::new();
+    for data in PERSONAL_DATA {
+        if backend.table_exists(data.table).await? {
+            existing.push(data);
+        }
+    }
+
+    let id = user_id.to_string();
+    let mut report = ForgetReport::default();
+    let mut transaction = backend.begin_transaction().await?;
+    let result = erase(transaction.as_mut(), &existing, &id, &mut report).await;
+    let result = match result {
+        Ok(()) => {
+            let affected =
+                serde_json::to_string(&report).map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+            transaction
+                .execute(
+                    "INSERT INTO user_tombstones (user_hash, forgotten_at, reason, affected) VALUES (?, ?, ?, ?)",
+                    &[&tombstone_hash(user_id), &Utc::now().timestamp(), &reason, &affected],
+                )
+                .await
+                .map(drop)
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => transaction.commit().await?,
+        Err(e) => {
+            transaction.rollback().await?;
+            return Err(e);
+        }
+    }
+    Ok(report)
+}
+
+async fn erase(
+    transaction: &mut dyn Transaction,
+    existing: &[&PersonalData],
+    id: &str,
+    report: &mut ForgetReport,
+) -> Result<()> {
+    for data in existing {
+        match data.erasure {
+            Erasure::Delete => {
+                let rows = transaction
+                    .execute(&format!("DELETE FROM {} WHERE {}", data.table, data.where_clause()), &[&id])
+                    .await?;
+                *report.deleted.entry(data.table.to_string()).or_default() += rows;
+            }
+            Erasure::Anonymize(assignments) => {
+                let rows = transaction
+                    .execute(
+                        &format!(
+                            "UPDATE {} SET {} WHERE {}",
+                            data.table,
+                            assignments.join(", "),
+                            data.where_clause()
+                        ),
+                        &[&id],
+                    )
+                    .await?;
+                *report.anonymized.entry(data.table.to_string()).or_default() += rows;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether `user_id` has been forgotten
+pub async fn is_forgotten(backend: &dyn DatabaseBackend, user_id: Uuid) -> Result<bool> {
+    if !backend.table_exists("user_tombstones").await? {
+        return Ok(false);
+    }
+    let rows = backend
+        .query(
+            "SELECT 1 FROM user_tombstones WHERE user_hash = ?",
+            &[&tombstone_hash(user_id)],
+        )
+        .await?;
+    Ok(!rows.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conditions_narrow_shared_tables() {
+        let learning = PERSONAL_DATA.iter().find(|d| d.table == "learning_data").unwrap();
+        assert_eq!(
+            learning.where_clause(),
+            "CAST(subject_id AS TEXT) = ? AND subject_type = 'player'"
+        );
+        assert_eq!(delete("griduser", "user_id").where_clause(), "CAST(user_id AS TEXT) = ?");
+    }
+
+    #[test]
+    fn test_tombstones_do_not_hold_the_id() {
+        let id = Uuid::new_v4();
+        let hash = tombstone_hash(id);
+        assert_eq!(hash.len(), 64);
+        assert!(!hash.contains(&id.simple().to_string()));
+        assert_eq!(hash, tombstone_hash(id));
+    }
+
+    #[test]
+    fn test_values_become_json() {
+        assert_eq!(json_value(SqlValue::Bytes(vec![0xde, 0xad])), Value::String("dead".to_string()));
+        assert_eq!(json_value(SqlValue::Int(3)), Value::from(3));
+        assert_eq!(json_value(SqlValue::Null), Value::Null);
+    }
+}
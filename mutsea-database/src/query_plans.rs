@@ -0,0 +1,108 @@
+//! Query plan capture for slow queries
+//!
+//! When a query runs past the explain threshold its plan is captured in the
+//! background: `EXPLAIN (ANALYZE, BUFFERS)` for PostgreSQL reads, plain
+//! `EXPLAIN` for writes (ANALYZE would run the write a second time) and
+//! `EXPLAIN QUERY PLAN` on SQLite. Plans are kept per query fingerprint and,
+//! on PostgreSQL, stored in `query_plan_analysis`.
+
+use crate::backends::{BackendType, DatabaseBackend, SqlValue, ToSql};
+use crate::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Shortest interval between two captures for the same fingerprint, so a
+/// hot slow query is not re-explained on every execution
+pub const RECAPTURE_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Plan of one slow execution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturedPlan {
+    pub query_fingerprint: String,
+    pub plan: String,
+    /// Duration of the execution that triggered the capture
+    pub execution_time: Duration,
+    /// Whether the plan includes actual run times (EXPLAIN ANALYZE)
+    pub analyzed: bool,
+    pub captured_at: DateTime<Utc>,
+}
+
+/// EXPLAIN statement for `sql`, and whether it analyzes, or `None` for
+/// statements that cannot be explained
+pub fn explain_statement(backend: BackendType, sql: &str) -> Option<(String, bool)> {
+    let trimmed = sql.trim_start();
+    let keyword = trimmed
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or("")
+        .to_ascii_uppercase();
+    let read = matches!(keyword.as_str(), "SELECT" | "WITH");
+    let write = matches!(keyword.as_str(), "INSERT" | "UPDATE" | "DELETE");
+    if !read && !write {
+        return None;
+    }
+
+    match backend {
+        BackendType::PostgreSQL if read => Some((format!("EXPLAIN (ANALYZE, BUFFERS) {}", trimmed), true)),
+        BackendType::PostgreSQL => Some((format!("EXPLAIN {}", trimmed), false)),
+        BackendType::SQLite => Some((format!("EXPLAIN QUERY PLAN {}", trimmed), false)),
+    }
+}
+
+/// Run EXPLAIN for `sql` with its original parameters and return the plan
+/// text, one line per plan row
+pub async fn explain(backend: &dyn DatabaseBackend, sql: &str, params: &[SqlValue]) -> Result<Option<(String, bool)>> {
+    let Some((statement, analyzed)) = explain_statement(backend.backend_type(), sql) else {
+        return Ok(None);
+    };
+    let params: Vec<&dyn ToSql> = params.iter().map(|p| p as &dyn ToSql).collect();
+    let rows = backend.query(&statement, &params).await?;
+
+    // SQLite's EXPLAIN QUERY PLAN returns (id, parent, notused, detail)
+    let column = match backend.backend_type() {
+        BackendType::SQLite => 3,
+        BackendType::PostgreSQL => 0,
+    };
+    let lines = rows.iter().map(|row| row.get::<String>(column)).collect::<Result<Vec<_>>>()?;
+    Ok(Some((lines.join("\n"), analyzed)))
+}
+
+/// Store a captured plan in `query_plan_analysis` (PostgreSQL only)
+pub async fn persist(backend: &dyn DatabaseBackend, sql: &str, plan: &CapturedPlan) -> Result<()> {
+    if backend.backend_type() != BackendType::PostgreSQL {
+        return Ok(());
+    }
+    let query = include_str!("sql/postgresql/performance/insert_query_plan.sql");
+    let captured_at = plan.captured_at.to_rfc3339();
+    let execution_ms = plan.execution_time.as_secs_f64() * 1000.0;
+    backend
+        .execute(
+            query,
+            &[&captured_at, &plan.query_fingerprint, &sql, &execution_ms, &plan.analyzed, &plan.plan],
+        )
+        .await
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explain_statement_by_backend() {
+        assert_eq!(
+            explain_statement(BackendType::PostgreSQL, "  select * from users"),
+            Some(("EXPLAIN (ANALYZE, BUFFERS) select * from users".to_string(), true))
+        );
+        assert_eq!(
+            explain_statement(BackendType::PostgreSQL, "DELETE FROM sessions WHERE id = $1"),
+            Some(("EXPLAIN DELETE FROM sessions WHERE id = $1".to_string(), false))
+        );
+        assert_eq!(
+            explain_statement(BackendType::SQLite, "WITH x AS (SELECT 1) SELECT * FROM x"),
+            Some(("EXPLAIN QUERY PLAN WITH x AS (SELECT 1) SELECT * FROM x".to_string(), false))
+        );
+        assert_eq!(explain_statement(BackendType::PostgreSQL, "CREATE TABLE t (id INT)"), None);
+    }
+}
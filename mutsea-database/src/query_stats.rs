@@ -0,0 +1,469 @@
+//! Automatic per-query execution statistics
+//!
+//! Every call through an [`InstrumentedBackend`] or the manager's own query
+//! helpers is recorded against a fingerprint of its SQL: whitespace is
+//! collapsed and literals are replaced by `?`, so one statement run with
+//! different values shares a single [`QueryStats`] entry. Executions over
+//! the explain threshold also get their plan captured; see
+//! [`query_plans`](crate::query_plans).
+
+use crate::backends::cursor::{self, RowStream};
+use crate::backends::{BackendType, DatabaseBackend, Row, SqlValue, ToSql, Transaction};
+use crate::query_plans::{self, CapturedPlan, RECAPTURE_INTERVAL};
+use crate::utils::QueryStats;
+use crate::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Ordering for [`QueryAnalytics::top`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuerySort {
+    TotalTime,
+    AverageTime,
+    Count,
+    Errors,
+}
+
+impl FromStr for QuerySort {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "total" | "total_time" => Ok(Self::TotalTime),
+            "avg" | "average" | "average_time" => Ok(Self::AverageTime),
+            "count" | "calls" => Ok(Self::Count),
+            "errors" => Ok(Self::Errors),
+            other => Err(format!("unknown sort '{}', expected total, avg, count or errors", other)),
+        }
+    }
+}
+
+/// Execution statistics for every query fingerprint seen
+#[derive(Debug)]
+pub struct QueryAnalytics {
+    stats: Mutex<HashMap<String, QueryStats>>,
+    slow_threshold: Duration,
+    /// Slow executions per fingerprint since the last slow-query report
+    slow_since_report: Mutex<HashMap<String, u64>>,
+    explain_threshold: Option<Duration>,
+    plans: Mutex<HashMap<String, CapturedPlan>>,
+}
+
+impl QueryAnalytics {
+    /// Analytics counting executions at or above `slow_threshold` as slow
+    pub fn new(slow_threshold: Duration) -> Self {
+        Self {
+            stats: Mutex::new(HashMap::new()),
+            slow_threshold,
+            slow_since_report: Mutex::new(HashMap::new()),
+            explain_threshold: None,
+            plans: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Capture the plan of queries taking `threshold` or longer
+    pub fn with_explain_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.explain_threshold = threshold;
+        self
+    }
+
+    /// Most recent plan captured for `sql`'s fingerprint
+    pub fn plan_for(&self, sql: &str) -> Option<CapturedPlan> {
+        self.plans
+            .lock()
+            .unwrap()
+            .get(&fingerprint(sql))
+            .filter(|p| !p.plan.is_empty())
+            .cloned()
+    }
+
+    /// All captured plans, keyed by fingerprint
+    pub fn plans(&self) -> HashMap<String, CapturedPlan> {
+        self.plans
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, p)| !p.plan.is_empty())
+            .map(|(k, p)| (k.clone(), p.clone()))
+            .collect()
+    }
+
+    /// Whether an execution taking `elapsed` should have its plan captured
+    /// now, claiming the capture for `key` if so
+    fn claim_capture(&self, key: &str, elapsed: Duration) -> bool {
+        if !self.explain_threshold.is_some_and(|threshold| elapsed >= threshold) {
+            return false;
+        }
+        let mut plans = self.plans.lock().unwrap();
+        let recent = plans.get(key).is_some_and(|p| {
+            (Utc::now() - p.captured_at).to_std().unwrap_or_default() < RECAPTURE_INTERVAL
+        });
+        if recent {
+            return false;
+        }
+        // Empty placeholder until the plan arrives, so concurrent slow runs
+        // of the same query do not all explain it
+        plans.insert(
+            key.to_string(),
+            CapturedPlan {
+                query_fingerprint: key.to_string(),
+                plan: String::new(),
+                execution_time: elapsed,
+                analyzed: false,
+                captured_at: Utc::now(),
+            },
+        );
+        true
+    }
+
+    fn store_plan(&self, plan: CapturedPlan) {
+        self.plans.lock().unwrap().insert(plan.query_fingerprint.clone(), plan);
+    }
+
+    /// Record one execution of `sql`
+    pub fn record(&self, sql: &str, elapsed: Duration, rows: Option<u64>, success: bool) {
+        let key = fingerprint(sql);
+        let slow = elapsed >= self.slow_threshold;
+
+        let mut stats = self.stats.lock().unwrap();
+        let entry = stats.entry(key.clone()).or_insert_with(|| QueryStats {
+            query_name: key.clone(),
+            execution_count: 0,
+            total_execution_time: Duration::ZERO,
+            average_execution_time: Duration::ZERO,
+            min_execution_time: elapsed,
+            max_execution_time: elapsed,
+            success_count: 0,
+            error_count: 0,
+            last_executed: Utc::now(),
+            parameters_hash: None,
+            rows_total: 0,
+            slow_count: 0,
+        });
+
+        entry.execution_count += 1;
+        entry.total_execution_time += elapsed;
+        entry.average_execution_time = entry.total_execution_time / entry.execution_count as u32;
+        entry.min_execution_time = entry.min_execution_time.min(elapsed);
+        entry.max_execution_time = entry.max_execution_time.max(elapsed);
+        entry.last_executed = Utc::now();
+        entry.rows_total += rows.unwrap_or(0);
+        if success {
+            entry.success_count += 1;
+        } else {
+            entry.error_count += 1;
+        }
+        if slow {
+            entry.slow_count += 1;
+            drop(stats);
+            *self.slow_since_report.lock().unwrap().entry(key).or_insert(0) += 1;
+        }
+    }
+
+    /// Copy of all statistics, keyed by fingerprint
+    pub fn snapshot(&self) -> HashMap<String, QueryStats> {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// The `limit` heaviest queries by `sort`
+    pub fn top(&self, sort: QuerySort, limit: usize) -> Vec<QueryStats> {
+        let mut queries: Vec<QueryStats> = self.stats.lock().unwrap().values().cloned().collect();
+        match sort {
+            QuerySort::TotalTime => queries.sort_by(|a, b| b.total_execution_time.cmp(&a.total_execution_time)),
+            QuerySort::AverageTime => queries.sort_by(|a, b| b.average_execution_time.cmp(&a.average_execution_time)),
+            QuerySort::Count => queries.sort_by(|a, b| b.execution_count.cmp(&a.execution_count)),
+            QuerySort::Errors => queries.sort_by(|a, b| b.error_count.cmp(&a.error_count)),
+        }
+        queries.truncate(limit);
+        queries
+    }
+
+    /// Queries that ran slowly since the previous call, with how many times,
+    /// most frequent first
+    pub fn take_slow(&self) -> Vec<(QueryStats, u64)> {
+        let slow = std::mem::take(&mut *self.slow_since_report.lock().unwrap());
+        let stats = self.stats.lock().unwrap();
+        let mut report: Vec<_> = slow
+            .into_iter()
+            .filter_map(|(key, count)| stats.get(&key).map(|s| (s.clone(), count)))
+            .collect();
+        report.sort_by(|a, b| b.1.cmp(&a.1));
+        report
+    }
+
+    /// Log the queries that ran slowly every `every`
+    pub fn spawn_slow_query_log(self: &Arc<Self>, every: Duration) -> tokio::task::JoinHandle<()> {
+        let analytics = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                for (stats, count) in analytics.take_slow() {
+                    warn!(
+                        "Slow query ({}x over {:?} in the last {:?}, avg {:?}, max {:?}): {}",
+                        count,
+                        analytics.slow_threshold,
+                        every,
+                        stats.average_execution_time,
+                        stats.max_execution_time,
+                        stats.query_name
+                    );
+                }
+            }
+        })
+    }
+}
+
+impl Default for QueryAnalytics {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(500))
+    }
+}
+
+/// Normalized form of `sql` used to group executions
+pub fn fingerprint(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                // String literal; '' is an escaped quote
+                while let Some(c) = chars.next() {
+                    if c == '\'' {
+                        if chars.peek() == Some(&'\'') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                out.push('?');
+            }
+            c if c.is_ascii_digit() && !out.ends_with(|p: char| p.is_alphanumeric() || p == '_' || p == '$') => {
+                while chars.peek().is_some_and(|c| c.is_ascii_digit() || *c == '.') {
+                    chars.next();
+                }
+                out.push('?');
+            }
+            c if c.is_whitespace() => {
+                while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                    chars.next();
+                }
+                out.push(' ');
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A streamed query, recorded as one execution from when it was started to
+/// when its stream ends or is dropped
+struct StreamedExecution {
+    analytics: Arc<QueryAnalytics>,
+    sql: String,
+    start: Instant,
+    rows: u64,
+    success: bool,
+}
+
+impl StreamedExecution {
+    fn new(analytics: Arc<QueryAnalytics>, sql: &str) -> Self {
+        Self {
+            analytics,
+            sql: sql.to_string(),
+            start: Instant::now(),
+            rows: 0,
+            success: true,
+        }
+    }
+
+    fn observe(&mut self, ok: bool) {
+        if ok {
+            self.rows += 1;
+        } else {
+            self.success = false;
+        }
+    }
+}
+
+impl Drop for StreamedExecution {
+    fn drop(&mut self) {
+        self.analytics.record(&self.sql, self.start.elapsed(), Some(self.rows), self.success);
+    }
+}
+
+/// Backend wrapper recording every `execute`/`query` into [`QueryAnalytics`]
+/// and capturing plans of slow ones
+pub struct InstrumentedBackend {
+    inner: Arc<dyn DatabaseBackend>,
+    analytics: Arc<QueryAnalytics>,
+}
+
+impl InstrumentedBackend {
+    pub fn new(inner: Box<dyn DatabaseBackend>, analytics: Arc<QueryAnalytics>) -> Self {
+        Self {
+            inner: Arc::from(inner),
+            analytics,
+        }
+    }
+
+    fn finish(&self, query: &str, params: &[&dyn ToSql], elapsed: Duration, rows: Option<u64>, success: bool) {
+        self.analytics.record(query, elapsed, rows, success);
+        if success && self.analytics.claim_capture(&fingerprint(query), elapsed) {
+            self.capture_plan(query, params, elapsed);
+        }
+    }
+
+    /// Explain `query` in the background; the caller already has its result
+    fn capture_plan(&self, query: &str, params: &[&dyn ToSql], elapsed: Duration) {
+        let inner = Arc::clone(&self.inner);
+        let analytics = Arc::clone(&self.analytics);
+        let sql = query.to_string();
+        let params: Vec<SqlValue> = params.iter().map(|p| p.to_sql()).collect();
+
+        tokio::spawn(async move {
+            match query_plans::explain(inner.as_ref(), &sql, &params).await {
+                Ok(Some((plan, analyzed))) => {
+                    let captured = CapturedPlan {
+                        query_fingerprint: fingerprint(&sql),
+                        plan,
+                        execution_time: elapsed,
+                        analyzed,
+                        captured_at: Utc::now(),
+                    };
+                    debug!("Captured plan for slow query ({:?}): {}", elapsed, captured.query_fingerprint);
+                    if let Err(e) = query_plans::persist(inner.as_ref(), &sql, &captured).await {
+                        warn!("Failed to store query plan: {}", e);
+                    }
+                    analytics.store_plan(captured);
+                }
+                Ok(None) => {}
+                Err(e) => debug!("EXPLAIN failed for slow query {}: {}", fingerprint(&sql), e),
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl DatabaseBackend for InstrumentedBackend {
+    async fn execute(&self, query: &str, params: &[&dyn ToSql]) -> Result<u64> {
+        let start = Instant::now();
+        let result = self.inner.execute(query, params).await;
+        self.finish(query, params, start.elapsed(), result.as_ref().ok().copied(), result.is_ok());
+        result
+    }
+
+    async fn query(&self, query: &str, params: &[&dyn ToSql]) -> Result<Vec<Row>> {
+        let start = Instant::now();
+        let result = self.inner.query(query, params).await;
+        let rows = result.as_ref().ok().map(|rows| rows.len() as u64);
+        self.finish(query, params, start.elapsed(), rows, result.is_ok());
+        result
+    }
+
+    async fn query_one(&self, query: &str, params: &[&dyn ToSql]) -> Result<Row> {
+        let start = Instant::now();
+        let result = self.inner.query_one(query, params).await;
+        self.finish(query, params, start.elapsed(), result.is_ok().then_some(1), result.is_ok());
+        result
+    }
+
+    // Catalogue lookups and transaction control are not application queries
+    async fn table_exists(&self, table_name: &str) -> Result<bool> {
+        self.inner.table_exists(table_name).await
+    }
+
+    async fn begin_transaction(&self) -> Result<Box<dyn Transaction>> {
+        self.inner.begin_transaction().await
+    }
+
+    fn backend_type(&self) -> BackendType {
+        self.inner.backend_type()
+    }
+
+    // Pages are fetched lazily, so the whole stream counts as one execution
+    fn query_stream<'a>(&'a self, query: &str, params: &[&dyn ToSql], batch_size: usize) -> RowStream<'a> {
+        let mut execution = StreamedExecution::new(Arc::clone(&self.analytics), query);
+        cursor::stream(self, query, params, batch_size)
+            .inspect(move |row| execution.observe(row.is_ok()))
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_replaces_literals() {
+        assert_eq!(
+            fingerprint("SELECT * FROM  users\n WHERE name = 'O''Brien' AND age > 42"),
+            "SELECT * FROM users WHERE name = ? AND age > ?"
+        );
+        assert_eq!(fingerprint("SELECT col1 FROM t2 WHERE id = $1"), "SELECT col1 FROM t2 WHERE id = $1");
+        assert_eq!(fingerprint("UPDATE x SET v = 1.5"), "UPDATE x SET v = ?");
+    }
+
+    #[test]
+    fn test_records_and_ranks_queries() {
+        let analytics = QueryAnalytics::new(Duration::from_millis(100));
+        analytics.record("SELECT * FROM a WHERE id = 1", Duration::from_millis(10), Some(1), true);
+        analytics.record("SELECT * FROM a WHERE id = 2", Duration::from_millis(150), Some(1), true);
+        analytics.record("DELETE FROM b", Duration::from_millis(5), None, false);
+
+        let top = analytics.top(QuerySort::TotalTime, 10);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].query_name, "SELECT * FROM a WHERE id = ?");
+        assert_eq!((top[0].execution_count, top[0].rows_total, top[0].slow_count), (2, 2, 1));
+        assert_eq!(analytics.top(QuerySort::Errors, 1)[0].query_name, "DELETE FROM b");
+
+        let slow = analytics.take_slow();
+        assert_eq!(slow.len(), 1);
+        assert_eq!(slow[0].1, 1);
+        assert!(analytics.take_slow().is_empty());
+    }
+
+    #[test]
+    fn test_streamed_queries_are_recorded_once_done() {
+        let analytics = Arc::new(QueryAnalytics::new(Duration::from_secs(1)));
+        let mut execution = StreamedExecution::new(Arc::clone(&analytics), "SELECT * FROM a WHERE id > 7");
+        execution.observe(true);
+        execution.observe(true);
+        assert!(analytics.snapshot().is_empty());
+
+        drop(execution);
+        let stats = &analytics.snapshot()["SELECT * FROM a WHERE id > ?"];
+        assert_eq!((stats.execution_count, stats.rows_total, stats.success_count), (1, 2, 1));
+
+        let mut failed = StreamedExecution::new(Arc::clone(&analytics), "SELECT * FROM a WHERE id > 7");
+        failed.observe(true);
+        failed.observe(false);
+        drop(failed);
+        let stats = &analytics.snapshot()["SELECT * FROM a WHERE id > ?"];
+        assert_eq!((stats.execution_count, stats.rows_total, stats.error_count), (2, 3, 1));
+    }
+
+    #[test]
+    fn test_plan_capture_is_claimed_once() {
+        let analytics = QueryAnalytics::new(Duration::from_millis(100)).with_explain_threshold(Some(Duration::from_millis(200)));
+        let key = fingerprint("SELECT * FROM a WHERE id = 1");
+        assert!(!analytics.claim_capture(&key, Duration::from_millis(150)));
+        assert!(analytics.claim_capture(&key, Duration::from_millis(250)));
+        assert!(!analytics.claim_capture(&key, Duration::from_millis(900)));
+        // The claim is not a plan yet
+        assert!(analytics.plan_for("SELECT * FROM a WHERE id = 7").is_none());
+
+        let disabled = QueryAnalytics::default();
+        assert!(!disabled.claim_capture(&key, Duration::from_secs(60)));
+    }
+}
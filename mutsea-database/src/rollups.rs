@@ -0,0 +1,401 @@
+//! Downsampling and retention of performance metrics
+//!
+//! Raw samples in `performance_metrics` are rolled up into one-minute,
+//! one-hour and one-day buckets (`performance_metrics_1m`, `_1h`, `_1d`),
+//! each keeping count, sum, min and max so coarser buckets can be built
+//! from finer ones exactly. Every resolution rolls up only buckets that are
+//! complete in the resolution below it, tracked in `metric_rollup_state`,
+//! and rows are pruned after their retention but never before they have
+//! been rolled up. Re-running a rollup over the same window recomputes the
+//! same buckets, so an interrupted compaction is safe to repeat.
+
+use crate::backends::{BackendType, DatabaseBackend};
+use crate::{DatabaseManager, Result};
+use chrono::{DateTime, Duration, Utc};
+use mutsea_core::config::MetricsRetentionConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+/// How long raw samples may arrive after their timestamp (the analytics
+/// buffer flushes within seconds, profiler windows end on their timestamp)
+const LATE_SAMPLE_GRACE: Duration = Duration::minutes(2);
+
+/// Stored resolutions, finest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    Raw,
+    Minute,
+    Hour,
+    Day,
+}
+
+impl Resolution {
+    pub const ALL: [Resolution; 4] = [Resolution::Raw, Resolution::Minute, Resolution::Hour, Resolution::Day];
+
+    /// Key in `metric_rollup_state`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resolution::Raw => "raw",
+            Resolution::Minute => "1m",
+            Resolution::Hour => "1h",
+            Resolution::Day => "1d",
+        }
+    }
+
+    pub fn table(&self) -> &'static str {
+        match self {
+            Resolution::Raw => "performance_metrics",
+            Resolution::Minute => "performance_metrics_1m",
+            Resolution::Hour => "performance_metrics_1h",
+            Resolution::Day => "performance_metrics_1d",
+        }
+    }
+
+    fn time_column(&self) -> &'static str {
+        match self {
+            Resolution::Raw => "timestamp",
+            _ => "bucket_start",
+        }
+    }
+
+    /// Bucket width; raw samples have none
+    pub fn bucket(&self) -> Option<Duration> {
+        match self {
+            Resolution::Raw => None,
+            Resolution::Minute => Some(Duration::minutes(1)),
+            Resolution::Hour => Some(Duration::hours(1)),
+            Resolution::Day => Some(Duration::days(1)),
+        }
+    }
+
+    /// Resolution this one is rolled up from
+    pub fn finer(&self) -> Option<Resolution> {
+        match self {
+            Resolution::Raw => None,
+            Resolution::Minute => Some(Resolution::Raw),
+            Resolution::Hour => Some(Resolution::Minute),
+            Resolution::Day => Some(Resolution::Hour),
+        }
+    }
+
+    /// Resolution this one is rolled up into
+    pub fn coarser(&self) -> Option<Resolution> {
+        match self {
+            Resolution::Raw => Some(Resolution::Minute),
+            Resolution::Minute => Some(Resolution::Hour),
+            Resolution::Hour => Some(Resolution::Day),
+            Resolution::Day => None,
+        }
+    }
+}
+
+/// Start of the `bucket`-wide bucket containing `at`, aligned to the epoch
+pub fn bucket_start(at: DateTime<Utc>, bucket: Duration) -> DateTime<Utc> {
+    let width = bucket.num_seconds().max(1);
+    let secs = at.timestamp().div_euclid(width) * width;
+    DateTime::from_timestamp(secs, 0).unwrap_or(at)
+}
+
+/// One aggregated bucket of a metric
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RollupPoint {
+    pub bucket_start: DateTime<Utc>,
+    pub component_name: String,
+    pub metric_type: String,
+    pub sample_count: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl RollupPoint {
+    pub fn average(&self) -> f64 {
+        if self.sample_count == 0 {
+            0.0
+        } else {
+            self.sum / self.sample_count as f64
+        }
+    }
+}
+
+/// Merge `points` into `bucket`-wide buckets per metric, in time order; the
+/// same aggregation the rollup SQL performs
+pub fn downsample(points: &[RollupPoint], bucket: Duration) -> Vec<RollupPoint> {
+    let mut buckets: HashMap<(String, String, DateTime<Utc>), RollupPoint> = HashMap::new();
+    for point in points {
+        let start = bucket_start(point.bucket_start, bucket);
+        buckets
+            .entry((point.component_name.clone(), point.metric_type.clone(), start))
+            .and_modify(|b| {
+                b.sample_count += point.sample_count;
+                b.sum += point.sum;
+                b.min = b.min.min(point.min);
+                b.max = b.max.max(point.max);
+            })
+            .or_insert_with(|| RollupPoint {
+                bucket_start: start,
+                ..point.clone()
+            });
+    }
+    let mut merged: Vec<_> = buckets.into_values().collect();
+    merged.sort_by(|a, b| {
+        (a.bucket_start, &a.component_name, &a.metric_type).cmp(&(b.bucket_start, &b.component_name, &b.metric_type))
+    });
+    merged
+}
+
+/// How long each resolution is kept; `None` keeps it forever
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub raw: Option<Duration>,
+    pub minute: Option<Duration>,
+    pub hour: Option<Duration>,
+    pub day: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    pub fn from_config(config: &MetricsRetentionConfig) -> Self {
+        let keep = |d: Duration| (!d.is_zero()).then_some(d);
+        Self {
+            raw: keep(Duration::hours(config.raw_hours as i64)),
+            minute: keep(Duration::days(config.minute_days as i64)),
+            hour: keep(Duration::days(config.hour_days as i64)),
+            day: keep(Duration::days(config.day_days as i64)),
+        }
+    }
+
+    pub fn retention(&self, resolution: Resolution) -> Option<Duration> {
+        match resolution {
+            Resolution::Raw => self.raw,
+            Resolution::Minute => self.minute,
+            Resolution::Hour => self.hour,
+            Resolution::Day => self.day,
+        }
+    }
+}
+
+/// End of the last complete bucket rolled up into each resolution
+pub type Watermarks = HashMap<Resolution, DateTime<Utc>>;
+
+/// Window `[from, to)` of `resolution` buckets ready to be rolled up, if any
+pub fn rollup_window(
+    resolution: Resolution,
+    watermarks: &Watermarks,
+    now: DateTime<Utc>,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let bucket = resolution.bucket()?;
+    // Only what the finer resolution has completely
+    let complete = match resolution.finer()? {
+        Resolution::Raw => now - LATE_SAMPLE_GRACE,
+        finer => *watermarks.get(&finer)?,
+    };
+    let to = bucket_start(complete, bucket);
+    let from = watermarks.get(&resolution).copied().unwrap_or(DateTime::UNIX_EPOCH);
+    (to > from).then_some((from, to))
+}
+
+/// Rows of `resolution` older than this can be deleted: past retention and
+/// already rolled up into the next resolution
+pub fn prune_cutoff(
+    resolution: Resolution,
+    policy: &RetentionPolicy,
+    watermarks: &Watermarks,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let cutoff = now - policy.retention(resolution)?;
+    match resolution.coarser() {
+        Some(coarser) => watermarks.get(&coarser).map(|rolled| cutoff.min(*rolled)),
+        None => Some(cutoff),
+    }
+}
+
+/// Outcome of one [`compact`] run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompactionReport {
+    /// Buckets written per resolution
+    pub rolled_up: HashMap<String, u64>,
+    /// Rows deleted per resolution
+    pub pruned: HashMap<String, u64>,
+}
+
+/// Roll up every complete bucket and prune expired rows (PostgreSQL)
+pub async fn compact(
+    backend: &dyn DatabaseBackend,
+    policy: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> Result<CompactionReport> {
+    let mut report = CompactionReport::default();
+    if backend.backend_type() != BackendType::PostgreSQL {
+        return Ok(report);
+    }
+
+    let mut watermarks = Watermarks::new();
+    for row in backend
+        .query(include_str!("sql/postgresql/performance/select_rollup_state.sql"), &[])
+        .await?
+    {
+        let name: String = row.get(0)?;
+        let rolled_until: i64 = row.get(1)?;
+        let resolution = Resolution::ALL.into_iter().find(|r| r.name() == name);
+        if let (Some(resolution), Some(at)) = (resolution, DateTime::from_timestamp(rolled_until, 0)) {
+            watermarks.insert(resolution, at);
+        }
+    }
+
+    for resolution in [Resolution::Minute, Resolution::Hour, Resolution::Day] {
+        let Some((from, to)) = rollup_window(resolution, &watermarks, now) else {
+            continue;
+        };
+        let query = match resolution.finer() {
+            Some(Resolution::Raw) => include_str!("sql/postgresql/performance/rollup_raw_metrics.sql").to_string(),
+            Some(finer) => include_str!("sql/postgresql/performance/rollup_metric_buckets.sql")
+                .replace("{target}", resolution.table())
+                .replace("{source}", finer.table())
+                .replace(
+                    "{unit}",
+                    match resolution {
+                        Resolution::Hour => "hour",
+                        _ => "day",
+                    },
+                ),
+            None => continue,
+        };
+        let rows = backend.execute(&query, &[&from.to_rfc3339(), &to.to_rfc3339()]).await?;
+        backend
+            .execute(
+                include_str!("sql/postgresql/performance/upsert_rollup_state.sql"),
+                &[&resolution.name(), &to.timestamp()],
+            )
+            .await?;
+        watermarks.insert(resolution, to);
+        report.rolled_up.insert(resolution.name().to_string(), rows);
+    }
+
+    for resolution in Resolution::ALL {
+        let Some(cutoff) = prune_cutoff(resolution, policy, &watermarks, now) else {
+            continue;
+        };
+        let query = format!(
+            "DELETE FROM {} WHERE {} < CAST(? AS TIMESTAMPTZ)",
+            resolution.table(),
+            resolution.time_column()
+        );
+        let rows = backend.execute(&query, &[&cutoff.to_rfc3339()]).await?;
+        report.pruned.insert(resolution.name().to_string(), rows);
+    }
+
+    Ok(report)
+}
+
+/// Compact every `compaction_interval_secs` in the background
+pub fn spawn_compaction(database: Arc<DatabaseManager>, config: &MetricsRetentionConfig) {
+    if !config.enabled {
+        return;
+    }
+    let policy = RetentionPolicy::from_config(config);
+    let interval = std::time::Duration::from_secs(config.compaction_interval_secs.max(1));
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let backend = match database.get_backend().await {
+                Ok(backend) => backend,
+                Err(e) => {
+                    warn!("Metrics compaction skipped: {}", e);
+                    continue;
+                }
+            };
+            match compact(backend.as_ref(), &policy, Utc::now()).await {
+                Ok(report) => debug!("Metrics compaction: {:?}", report),
+                Err(e) => warn!("Metrics compaction failed: {}", e),
+            }
+        }
+    });
+    info!("Metrics rollups and retention every {}s", interval.as_secs());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn point(ts: &str, value: f64) -> RollupPoint {
+        RollupPoint {
+            bucket_start: at(ts),
+            component_name: "simulation".to_string(),
+            metric_type: "tick_avg_ms".to_string(),
+            sample_count: 1,
+            sum: value,
+            min: value,
+            max: value,
+        }
+    }
+
+    #[test]
+    fn test_downsample_keeps_exact_aggregates() {
+        let raw = vec![
+            point("2026-10-15T10:00:10Z", 10.0),
+            point("2026-10-15T10:00:50Z", 20.0),
+            point("2026-10-15T10:01:05Z", 30.0),
+            point("2026-10-15T11:30:00Z", 5.0),
+        ];
+        let minutes = downsample(&raw, Duration::minutes(1));
+        assert_eq!(minutes.len(), 3);
+        assert_eq!(minutes[0].bucket_start, at("2026-10-15T10:00:00Z"));
+        assert_eq!(minutes[0].average(), 15.0);
+
+        // Hours from minutes match hours from raw samples
+        let hours = downsample(&minutes, Duration::hours(1));
+        assert_eq!(hours, downsample(&raw, Duration::hours(1)));
+        assert_eq!(hours[0].sample_count, 3);
+        assert_eq!((hours[0].min, hours[0].max), (10.0, 30.0));
+        assert_eq!(hours[1].bucket_start, at("2026-10-15T11:00:00Z"));
+    }
+
+    #[test]
+    fn test_rollup_windows_follow_finer_watermarks() {
+        let now = at("2026-10-15T10:17:30Z");
+        let mut watermarks = Watermarks::new();
+
+        // Minutes up to the grace period; nothing coarser until minutes exist
+        let (from, to) = rollup_window(Resolution::Minute, &watermarks, now).unwrap();
+        assert_eq!((from, to), (DateTime::UNIX_EPOCH, at("2026-10-15T10:15:00Z")));
+        assert_eq!(rollup_window(Resolution::Hour, &watermarks, now), None);
+
+        watermarks.insert(Resolution::Minute, to);
+        assert_eq!(
+            rollup_window(Resolution::Hour, &watermarks, now),
+            Some((DateTime::UNIX_EPOCH, at("2026-10-15T10:00:00Z")))
+        );
+        watermarks.insert(Resolution::Hour, at("2026-10-15T10:00:00Z"));
+        assert_eq!(rollup_window(Resolution::Hour, &watermarks, now), None);
+    }
+
+    #[test]
+    fn test_prune_waits_for_rollup() {
+        let now = at("2026-10-15T12:00:00Z");
+        let policy = RetentionPolicy::from_config(&MetricsRetentionConfig::default());
+        let mut watermarks = Watermarks::new();
+
+        // Raw samples are never pruned before minutes have been rolled up
+        assert_eq!(prune_cutoff(Resolution::Raw, &policy, &watermarks, now), None);
+        watermarks.insert(Resolution::Minute, at("2026-10-12T00:00:00Z"));
+        assert_eq!(
+            prune_cutoff(Resolution::Raw, &policy, &watermarks, now),
+            Some(at("2026-10-12T00:00:00Z"))
+        );
+        watermarks.insert(Resolution::Minute, at("2026-10-15T11:58:00Z"));
+        assert_eq!(
+            prune_cutoff(Resolution::Raw, &policy, &watermarks, now),
+            Some(at("2026-10-13T12:00:00Z"))
+        );
+        // Days are kept forever by default
+        assert_eq!(prune_cutoff(Resolution::Day, &policy, &watermarks, now), None);
+    }
+}
@@ -0,0 +1,260 @@
+//! Hash-sharding of the assets table
+//!
+//! Assets are spread over N tables (`assets_0` ... `assets_{N-1}`) or N
+//! PostgreSQL schemas (`asset_shard_0.assets` ...) by an FNV-1a hash of
+//! their lowercased UUID. The hash must never change: it decides where
+//! every stored asset lives. With one shard the plain OpenSim `assets`
+//! table is used, so unsharded grids keep their schema.
+//!
+//! [`reshard`] moves rows between layouts in batches. Moved rows leave the
+//! source table, so an interrupted run can simply be started again.
+
+use crate::backends::{DatabaseBackend, ToSql};
+use crate::{DatabaseError, Result};
+use mutsea_core::config::AssetShardingConfig;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Unsharded OpenSim table name, as written in the asset SQL files
+const BASE_TABLE: &str = "assets";
+
+/// `CREATE TABLE` statement for the unsharded assets table
+pub const CREATE_ASSETS: &str = include_str!("sql/opensim/create_assets.sql");
+
+/// Where shards live
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShardLayout {
+    Tables,
+    Schemas,
+}
+
+/// Routing of asset IDs to tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssetSharding {
+    shards: u32,
+    layout: ShardLayout,
+}
+
+impl Default for AssetSharding {
+    fn default() -> Self {
+        Self::unsharded()
+    }
+}
+
+impl AssetSharding {
+    /// The single OpenSim `assets` table
+    pub fn unsharded() -> Self {
+        Self {
+            shards: 1,
+            layout: ShardLayout::Tables,
+        }
+    }
+
+    pub fn new(shards: u32, layout: ShardLayout) -> Result<Self> {
+        if shards == 0 {
+            return Err(DatabaseError::Generic("Asset shard count must be at least 1".to_string()));
+        }
+        Ok(Self { shards, layout })
+    }
+
+    pub fn from_config(config: &AssetShardingConfig) -> Result<Self> {
+        let layout = match config.layout.as_str() {
+            "tables" => ShardLayout::Tables,
+            "schemas" => ShardLayout::Schemas,
+            other => {
+                return Err(DatabaseError::Generic(format!(
+                    "Unknown asset shard layout '{}', expected tables or schemas",
+                    other
+                )))
+            }
+        };
+        Self::new(config.shards, layout)
+    }
+
+    pub fn shards(&self) -> u32 {
+        self.shards
+    }
+
+    pub fn layout(&self) -> ShardLayout {
+        self.layout
+    }
+
+    /// Shard holding the asset `id`
+    pub fn shard_for(&self, id: &str) -> u32 {
+        // FNV-1a, 64-bit
+        let hash = id
+            .bytes()
+            .map(|b| b.to_ascii_lowercase())
+            .fold(0xcbf2_9ce4_8422_2325u64, |hash, b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3));
+        (hash % self.shards as u64) as u32
+    }
+
+    /// Table of shard `shard`
+    pub fn table_name(&self, shard: u32) -> String {
+        match (self.shards, self.layout) {
+            (1, _) => BASE_TABLE.to_string(),
+            (_, ShardLayout::Tables) => format!("{}_{}", BASE_TABLE, shard),
+            (_, ShardLayout::Schemas) => format!("asset_shard_{}.{}", shard, BASE_TABLE),
+        }
+    }
+
+    /// Table holding the asset `id`
+    pub fn table_for(&self, id: &str) -> String {
+        self.table_name(self.shard_for(id))
+    }
+
+    /// Every shard table, in shard order
+    pub fn tables(&self) -> Vec<String> {
+        (0..self.shards).map(|shard| self.table_name(shard)).collect()
+    }
+
+    /// `sql`, written against the `assets` table, aimed at the shard of `id`
+    pub fn route(&self, sql: &str, id: &str) -> String {
+        replace_table(sql, &self.table_for(id))
+    }
+
+    /// Statements creating every shard (and, for schemas, its schema) from
+    /// the unsharded `CREATE TABLE` statement
+    pub fn create_statements(&self, create_table: &str) -> Vec<String> {
+        let mut statements = Vec::new();
+        for shard in 0..self.shards {
+            if self.shards > 1 && self.layout == ShardLayout::Schemas {
+                statements.push(format!("CREATE SCHEMA IF NOT EXISTS asset_shard_{}", shard));
+            }
+            statements.push(replace_table(create_table, &self.table_name(shard)));
+        }
+        statements
+    }
+}
+
+/// Replace the whole word `assets` (outside comments) with `table`
+fn replace_table(sql: &str, table: &str) -> String {
+    let mut out = String::with_capacity(sql.len() + table.len());
+    for line in sql.split_inclusive('\n') {
+        if line.trim_start().starts_with("--") {
+            out.push_str(line);
+            continue;
+        }
+        let mut rest = line;
+        while let Some(pos) = rest.find(BASE_TABLE) {
+            let before = rest[..pos].chars().next_back();
+            let after = rest[pos + BASE_TABLE.len()..].chars().next();
+            let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '.');
+            out.push_str(&rest[..pos]);
+            if is_word(before) || is_word(after) {
+                out.push_str(BASE_TABLE);
+            } else {
+                out.push_str(table);
+            }
+            rest = &rest[pos + BASE_TABLE.len()..];
+        }
+        out.push_str(rest);
+    }
+    out
+}
+
+/// Outcome of a [`reshard`] run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReshardReport {
+    /// Rows moved to another table
+    pub moved: u64,
+    /// Rows already in their target table
+    pub unchanged: u64,
+    /// Source tables emptied and dropped
+    pub dropped_tables: Vec<String>,
+}
+
+/// Move every asset from the `from` layout to the `to` layout, `batch_size`
+/// rows per transaction
+pub async fn reshard(
+    backend: &dyn DatabaseBackend,
+    from: &AssetSharding,
+    to: &AssetSharding,
+    batch_size: usize,
+) -> Result<ReshardReport> {
+    for statement in to.create_statements(CREATE_ASSETS) {
+        backend.execute(&statement, &[]).await?;
+    }
+
+    let targets = to.tables();
+    let mut report = ReshardReport::default();
+    for source in from.tables() {
+        let select = format!("SELECT id FROM {} WHERE id > ? ORDER BY id LIMIT ?", source);
+        let mut after = String::new();
+        loop {
+            let limit = batch_size as i64;
+            let rows = backend.query(&select, &[&after as &dyn ToSql, &limit]).await?;
+            if rows.is_empty() {
+                break;
+            }
+            let ids = rows.iter().map(|row| row.get::<String>(0)).collect::<Result<Vec<_>>>()?;
+
+            let mut transaction = backend.begin_transaction().await?;
+            for id in &ids {
+                let target = to.table_for(id);
+                if target == source {
+                    report.unchanged += 1;
+                    continue;
+                }
+                transaction
+                    .execute(&format!("INSERT INTO {} SELECT * FROM {} WHERE id = ?", target, source), &[id])
+                    .await?;
+                transaction
+                    .execute(&format!("DELETE FROM {} WHERE id = ?", source), &[id])
+                    .await?;
+                report.moved += 1;
+            }
+            transaction.commit().await?;
+
+            after = ids.last().cloned().unwrap_or_default();
+            if ids.len() < batch_size {
+                break;
+            }
+        }
+        info!("Resharded {}: {} assets moved so far", source, report.moved);
+
+        if !targets.contains(&source) {
+            backend.execute(&format!("DROP TABLE {}", source), &[]).await?;
+            report.dropped_tables.push(source);
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routing_is_stable_and_spread() {
+        let sharding = AssetSharding::new(16, ShardLayout::Tables).unwrap();
+        let id = "8E1E3A30-9E5F-4D7A-8D3B-1F7C0E0B6A11";
+        assert_eq!(sharding.shard_for(id), sharding.shard_for(&id.to_lowercase()));
+        assert_eq!(sharding.table_for(id), format!("assets_{}", sharding.shard_for(id)));
+
+        let mut counts = [0u32; 16];
+        for i in 0..1600u32 {
+            counts[sharding.shard_for(&uuid::Uuid::from_u128(i as u128 * 7919).to_string()) as usize] += 1;
+        }
+        assert!(counts.iter().all(|&c| c > 50), "uneven spread: {:?}", counts);
+
+        assert_eq!(AssetSharding::unsharded().table_for(id), "assets");
+    }
+
+    #[test]
+    fn test_route_rewrites_table_name() {
+        let sharding = AssetSharding::new(4, ShardLayout::Schemas).unwrap();
+        let id = "00000000-0000-0000-0000-000000000001";
+        let table = sharding.table_for(id);
+        assert_eq!(
+            sharding.route("-- src/sql/opensim/select_asset.sql\nSELECT * FROM assets WHERE id = ?;", id),
+            format!("-- src/sql/opensim/select_asset.sql\nSELECT * FROM {} WHERE id = ?;", table)
+        );
+
+        let statements = sharding.create_statements("CREATE TABLE IF NOT EXISTS assets (\n    id VARCHAR(36)\n);");
+        assert_eq!(statements.len(), 8);
+        assert_eq!(statements[0], "CREATE SCHEMA IF NOT EXISTS asset_shard_0");
+        assert!(statements[1].starts_with("CREATE TABLE IF NOT EXISTS asset_shard_0.assets ("));
+    }
+}
@@ -0,0 +1,9 @@
+-- src/sql/opensim/create_avatarattachments.sql
+-- OpenSim avatarattachments table (what each avatar wears, by point)
+CREATE TABLE IF NOT EXISTS avatarattachments (
+    UUID VARCHAR(36) NOT NULL,
+    attachpoint INTEGER NOT NULL,
+    item VARCHAR(36) NOT NULL,
+    asset VARCHAR(36) NOT NULL,
+    PRIMARY KEY (UUID, attachpoint)
+);
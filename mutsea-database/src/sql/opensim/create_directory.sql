@@ -0,0 +1,37 @@
+-- src/sql/opensim/create_directory.sql
+-- OpenSim classifieds and events tables (the search floater's directory)
+CREATE TABLE IF NOT EXISTS classifieds (
+    classifieduuid VARCHAR(36) NOT NULL PRIMARY KEY,
+    creatoruuid VARCHAR(36) NOT NULL,
+    creationdate INTEGER NOT NULL,
+    expirationdate INTEGER NOT NULL,
+    category VARCHAR(20) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    description TEXT NOT NULL,
+    parceluuid VARCHAR(36) NOT NULL,
+    parentestate INTEGER NOT NULL,
+    snapshotuuid VARCHAR(36) NOT NULL,
+    simname VARCHAR(255) NOT NULL,
+    posglobal VARCHAR(255) NOT NULL,
+    parcelname VARCHAR(255) NOT NULL,
+    classifiedflags INTEGER NOT NULL,
+    priceforlisting INTEGER NOT NULL,
+    KEY creatoruuid (creatoruuid)
+);
+
+CREATE TABLE IF NOT EXISTS events (
+    owneruuid VARCHAR(36) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    eventid INTEGER NOT NULL PRIMARY KEY,
+    creatoruuid VARCHAR(36) NOT NULL,
+    category INTEGER NOT NULL,
+    description TEXT NOT NULL,
+    dateUTC INTEGER NOT NULL,
+    duration INTEGER NOT NULL,
+    covercharge INTEGER NOT NULL,
+    coveramount INTEGER NOT NULL,
+    simname VARCHAR(255) NOT NULL,
+    parcelUUID VARCHAR(36) NOT NULL,
+    globalPos VARCHAR(255) NOT NULL,
+    eventflags INTEGER NOT NULL
+);
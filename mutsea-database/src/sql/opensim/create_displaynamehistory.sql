@@ -0,0 +1,8 @@
+-- src/sql/opensim/create_displaynamehistory.sql
+-- Every display name change, empty names meaning the legacy name
+CREATE TABLE IF NOT EXISTS displaynamehistory (
+    useruuid VARCHAR(36) NOT NULL,
+    oldname VARCHAR(255) NOT NULL,
+    newname VARCHAR(255) NOT NULL,
+    changed INTEGER NOT NULL
+);
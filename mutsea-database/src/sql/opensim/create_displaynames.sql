@@ -0,0 +1,7 @@
+-- src/sql/opensim/create_displaynames.sql
+-- Display names avatars go by instead of their legacy name
+CREATE TABLE IF NOT EXISTS displaynames (
+    useruuid VARCHAR(36) NOT NULL PRIMARY KEY,
+    displayname VARCHAR(255) NOT NULL,
+    lastchanged INTEGER NOT NULL
+);
@@ -0,0 +1,15 @@
+-- src/sql/opensim/create_griduser.sql
+-- OpenSim GridUser table (home/last location and presence)
+CREATE TABLE IF NOT EXISTS griduser (
+    user_id VARCHAR(255) NOT NULL PRIMARY KEY,
+    home_region_id VARCHAR(36) NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000',
+    home_position VARCHAR(64) NOT NULL DEFAULT '<0,0,0>',
+    home_look_at VARCHAR(64) NOT NULL DEFAULT '<0,0,0>',
+    last_region_id VARCHAR(36) NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000',
+    last_position VARCHAR(64) NOT NULL DEFAULT '<0,0,0>',
+    last_look_at VARCHAR(64) NOT NULL DEFAULT '<0,0,0>',
+    online CHAR(5) NOT NULL DEFAULT 'false',
+    login CHAR(16) NOT NULL DEFAULT '0',
+    logout CHAR(16) NOT NULL DEFAULT '0',
+    login_count INTEGER NOT NULL DEFAULT 0
+);
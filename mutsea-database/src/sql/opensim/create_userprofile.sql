@@ -0,0 +1,36 @@
+-- src/sql/opensim/create_userprofile.sql
+-- OpenSim userprofile and userpicks tables (avatar profiles and their picks)
+CREATE TABLE IF NOT EXISTS userprofile (
+    useruuid VARCHAR(36) NOT NULL PRIMARY KEY,
+    profilePartner VARCHAR(36) NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000',
+    profileAllowPublish CHAR(5) NOT NULL DEFAULT 'false',
+    profileMaturePublish CHAR(5) NOT NULL DEFAULT 'false',
+    profileURL VARCHAR(255) NOT NULL DEFAULT '',
+    profileWantToMask INTEGER NOT NULL DEFAULT 0,
+    profileWantToText TEXT NOT NULL,
+    profileSkillsMask INTEGER NOT NULL DEFAULT 0,
+    profileSkillsText TEXT NOT NULL,
+    profileLanguages TEXT NOT NULL,
+    profileImage VARCHAR(36) NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000',
+    profileAboutText TEXT NOT NULL,
+    profileFirstImage VARCHAR(36) NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000',
+    profileFirstText TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS userpicks (
+    pickuuid VARCHAR(36) NOT NULL PRIMARY KEY,
+    creatoruuid VARCHAR(36) NOT NULL,
+    toppick CHAR(5) NOT NULL DEFAULT 'false',
+    parceluuid VARCHAR(36) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    description TEXT NOT NULL,
+    snapshotuuid VARCHAR(36) NOT NULL,
+    user VARCHAR(255) NOT NULL,
+    originalname VARCHAR(255) NOT NULL,
+    simname VARCHAR(255) NOT NULL,
+    posglobal VARCHAR(255) NOT NULL,
+    sortorder INTEGER NOT NULL,
+    enabled CHAR(5) NOT NULL DEFAULT 'true',
+    gatekeeper VARCHAR(255),
+    KEY creatoruuid (creatoruuid)
+);
@@ -0,0 +1,2 @@
+-- src/sql/opensim/delete_avatar_attachment.sql
+DELETE FROM avatarattachments WHERE UUID = ? AND attachpoint = ?;
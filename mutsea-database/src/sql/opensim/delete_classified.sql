@@ -0,0 +1,2 @@
+-- src/sql/opensim/delete_classified.sql
+DELETE FROM classifieds WHERE classifieduuid = ?;
@@ -0,0 +1,2 @@
+-- src/sql/opensim/delete_displayname.sql
+DELETE FROM displaynames WHERE useruuid = ?;
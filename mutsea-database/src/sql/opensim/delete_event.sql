@@ -0,0 +1,2 @@
+-- src/sql/opensim/delete_event.sql
+DELETE FROM events WHERE eventid = ?;
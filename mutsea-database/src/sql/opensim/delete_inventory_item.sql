@@ -0,0 +1,2 @@
+-- src/sql/opensim/delete_inventory_item.sql
+DELETE FROM inventoryitems WHERE inventory_id = ? AND avatar_id = ?;
@@ -0,0 +1,2 @@
+-- src/sql/opensim/delete_userpick.sql
+DELETE FROM userpicks WHERE pickuuid = ? AND creatoruuid = ?;
@@ -0,0 +1,2 @@
+-- src/sql/opensim/delete_userprofile.sql
+DELETE FROM userprofile WHERE useruuid = ?;
@@ -0,0 +1,2 @@
+-- src/sql/opensim/insert_avatar_attachment.sql
+INSERT INTO avatarattachments (UUID, attachpoint, item, asset) VALUES (?, ?, ?, ?);
@@ -0,0 +1,5 @@
+-- src/sql/opensim/insert_classified.sql
+INSERT INTO classifieds (
+    classifieduuid, creatoruuid, creationdate, expirationdate, category, name, description,
+    parceluuid, parentestate, snapshotuuid, simname, posglobal, parcelname, classifiedflags, priceforlisting
+) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);
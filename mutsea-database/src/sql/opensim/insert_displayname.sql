@@ -0,0 +1,2 @@
+-- src/sql/opensim/insert_displayname.sql
+INSERT INTO displaynames (useruuid, displayname, lastchanged) VALUES (?, ?, ?);
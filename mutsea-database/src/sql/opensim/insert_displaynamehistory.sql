@@ -0,0 +1,2 @@
+-- src/sql/opensim/insert_displaynamehistory.sql
+INSERT INTO displaynamehistory (useruuid, oldname, newname, changed) VALUES (?, ?, ?, ?);
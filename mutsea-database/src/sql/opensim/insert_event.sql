@@ -0,0 +1,5 @@
+-- src/sql/opensim/insert_event.sql
+INSERT INTO events (
+    owneruuid, name, eventid, creatoruuid, category, description, dateUTC, duration,
+    covercharge, coveramount, simname, parcelUUID, globalPos, eventflags
+) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);
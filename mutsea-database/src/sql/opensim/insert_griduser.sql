@@ -0,0 +1,6 @@
+-- src/sql/opensim/insert_griduser.sql
+INSERT INTO griduser (
+    user_id, home_region_id, home_position, home_look_at,
+    last_region_id, last_position, last_look_at,
+    online, login, logout, login_count
+) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);
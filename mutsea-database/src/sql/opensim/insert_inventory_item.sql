@@ -0,0 +1,8 @@
+-- src/sql/opensim/insert_inventory_item.sql
+INSERT INTO inventoryitems (
+    inventory_id, asset_id, asset_type, parent_folder_id, avatar_id,
+    inventory_name, inventory_description, inventory_next_permissions,
+    inventory_current_permissions, inv_type, creator_id, inventory_base_permissions,
+    inventory_everyone_permissions, sale_price, sale_type, creation_date,
+    group_id, group_owned, last_owner_id, inventory_group_permissions
+) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0, 0, ?, '00000000-0000-0000-0000-000000000000', 0, ?, 0);
@@ -0,0 +1,5 @@
+-- src/sql/opensim/insert_userpick.sql
+INSERT INTO userpicks (
+    pickuuid, creatoruuid, toppick, parceluuid, name, description, snapshotuuid,
+    user, originalname, simname, posglobal, sortorder, enabled
+) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);
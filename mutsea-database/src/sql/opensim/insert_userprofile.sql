@@ -0,0 +1,6 @@
+-- src/sql/opensim/insert_userprofile.sql
+INSERT INTO userprofile (
+    useruuid, profilePartner, profileAllowPublish, profileMaturePublish, profileURL,
+    profileWantToText, profileSkillsText, profileLanguages,
+    profileImage, profileAboutText, profileFirstImage, profileFirstText
+) VALUES (?, ?, ?, ?, ?, '', '', '', ?, ?, ?, ?);
@@ -0,0 +1,3 @@
+-- src/sql/opensim/search_displaynames.sql
+SELECT useruuid, displayname, lastchanged FROM displaynames
+WHERE LOWER(displayname) LIKE ? ORDER BY displayname LIMIT ?;
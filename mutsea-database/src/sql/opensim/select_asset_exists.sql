@@ -0,0 +1,2 @@
+-- src/sql/opensim/select_asset_exists.sql
+SELECT id FROM assets WHERE id = ?;
\ No newline at end of file
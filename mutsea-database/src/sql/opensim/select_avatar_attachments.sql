@@ -0,0 +1,2 @@
+-- src/sql/opensim/select_avatar_attachments.sql
+SELECT UUID, attachpoint, item, asset FROM avatarattachments WHERE UUID = ? ORDER BY attachpoint;
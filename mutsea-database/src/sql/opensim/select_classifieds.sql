@@ -0,0 +1,4 @@
+-- src/sql/opensim/select_classifieds.sql
+SELECT classifieduuid, creatoruuid, creationdate, expirationdate, category, name, description,
+    parceluuid, parentestate, snapshotuuid, simname, posglobal, parcelname, classifiedflags, priceforlisting
+FROM classifieds ORDER BY priceforlisting DESC, creationdate;
@@ -0,0 +1,2 @@
+-- src/sql/opensim/select_displayname.sql
+SELECT useruuid, displayname, lastchanged FROM displaynames WHERE useruuid = ?;
@@ -0,0 +1,2 @@
+-- src/sql/opensim/select_displayname_owner.sql
+SELECT useruuid, displayname, lastchanged FROM displaynames WHERE LOWER(displayname) = ?;
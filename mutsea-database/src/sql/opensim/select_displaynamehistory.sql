@@ -0,0 +1,3 @@
+-- src/sql/opensim/select_displaynamehistory.sql
+SELECT useruuid, oldname, newname, changed FROM displaynamehistory
+WHERE useruuid = ? ORDER BY changed DESC LIMIT ?;
@@ -0,0 +1,4 @@
+-- src/sql/opensim/select_events.sql
+SELECT owneruuid, name, eventid, creatoruuid, category, description, dateUTC, duration,
+    covercharge, coveramount, simname, parcelUUID, globalPos, eventflags
+FROM events ORDER BY dateUTC, eventid;
@@ -0,0 +1,2 @@
+-- src/sql/opensim/select_griduser.sql
+SELECT * FROM griduser WHERE user_id = ?;
@@ -0,0 +1,3 @@
+-- src/sql/opensim/select_inventory_folder.sql
+SELECT folder_id, agent_id, parent_folder_id, folder_name, type, version
+FROM inventoryfolders WHERE folder_id = ?;
@@ -0,0 +1,2 @@
+-- src/sql/opensim/select_inventory_folder_of_type.sql
+SELECT folder_id FROM inventoryfolders WHERE agent_id = ? AND type = ? LIMIT 1;
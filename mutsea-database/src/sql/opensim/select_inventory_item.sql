@@ -0,0 +1,6 @@
+-- src/sql/opensim/select_inventory_item.sql
+SELECT inventory_id, asset_id, asset_type, parent_folder_id, avatar_id,
+    inventory_name, inventory_description, inventory_next_permissions,
+    inventory_current_permissions, inv_type, creator_id, inventory_base_permissions,
+    inventory_everyone_permissions, creation_date
+FROM inventoryitems WHERE inventory_id = ? AND avatar_id = ?;
@@ -0,0 +1,2 @@
+-- src/sql/opensim/select_inventory_item_asset.sql
+SELECT asset_id FROM inventoryitems WHERE inventory_id = ? AND avatar_id = ?;
@@ -0,0 +1,3 @@
+-- src/sql/opensim/select_inventory_subfolders.sql
+SELECT folder_id, agent_id, parent_folder_id, folder_name, type, version
+FROM inventoryfolders WHERE parent_folder_id = ? ORDER BY folder_name;
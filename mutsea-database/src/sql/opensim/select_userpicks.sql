@@ -0,0 +1,4 @@
+-- src/sql/opensim/select_userpicks.sql
+SELECT pickuuid, creatoruuid, toppick, parceluuid, name, description, snapshotuuid,
+    user, originalname, simname, posglobal, sortorder, enabled
+FROM userpicks WHERE creatoruuid = ? ORDER BY sortorder, name;
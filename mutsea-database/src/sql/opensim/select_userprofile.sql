@@ -0,0 +1,4 @@
+-- src/sql/opensim/select_userprofile.sql
+SELECT useruuid, profilePartner, profileAllowPublish, profileMaturePublish, profileURL,
+    profileImage, profileAboutText, profileFirstImage, profileFirstText
+FROM userprofile WHERE useruuid = ?;
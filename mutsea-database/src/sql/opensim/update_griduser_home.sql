@@ -0,0 +1,2 @@
+-- src/sql/opensim/update_griduser_home.sql
+UPDATE griduser SET home_region_id = ?, home_position = ?, home_look_at = ? WHERE user_id = ?;
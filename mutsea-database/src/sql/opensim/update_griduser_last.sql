@@ -0,0 +1,2 @@
+-- src/sql/opensim/update_griduser_last.sql
+UPDATE griduser SET last_region_id = ?, last_position = ?, last_look_at = ? WHERE user_id = ?;
@@ -0,0 +1,2 @@
+-- src/sql/opensim/update_griduser_login.sql
+UPDATE griduser SET online = 'true', login = ?, login_count = login_count + 1 WHERE user_id = ?;
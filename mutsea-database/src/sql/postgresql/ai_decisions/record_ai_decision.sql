@@ -0,0 +1,5 @@
+-- mutsea-database/src/sql/postgresql/ai_decisions/record_ai_decision.sql
+INSERT INTO ai_decisions (
+    id, decision_type, context_hash, input_data, decision_data,
+    confidence_score, execution_time_ms, model_version, metadata
+) VALUES (CAST(? AS UUID), ?, ?, ?, ?, ?, ?, ?, ?);
@@ -0,0 +1,8 @@
+-- mutsea-database/src/sql/postgresql/ai_decisions/record_decision_outcome.sql
+UPDATE ai_decisions SET
+    outcome_data = COALESCE(CAST(? AS JSONB), outcome_data),
+    feedback_score = COALESCE(CAST(? AS DOUBLE PRECISION), feedback_score),
+    outcome_recorded_at = NOW(),
+    learning_evaluated_at = NULL,
+    updated_at = NOW()
+WHERE id = CAST(? AS UUID);
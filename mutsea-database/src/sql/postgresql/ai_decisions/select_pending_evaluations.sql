@@ -0,0 +1,13 @@
+-- mutsea-database/src/sql/postgresql/ai_decisions/select_pending_evaluations.sql
+SELECT
+    id::text,
+    decision_data,
+    outcome_data,
+    confidence_score::float8,
+    feedback_score::float8,
+    outcome_recorded_at::text
+FROM ai_decisions
+WHERE outcome_recorded_at IS NOT NULL
+  AND learning_evaluated_at IS NULL
+ORDER BY outcome_recorded_at
+LIMIT ?;
@@ -0,0 +1,9 @@
+-- mutsea-database/src/sql/postgresql/ai_decisions/update_decision_learning.sql
+-- Skipped if new feedback arrived since the row was read
+UPDATE ai_decisions SET
+    outcome_variance = ?,
+    learning_value = ?,
+    learning_evaluated_at = NOW(),
+    updated_at = NOW()
+WHERE id = CAST(? AS UUID)
+  AND outcome_recorded_at = CAST(? AS TIMESTAMPTZ);
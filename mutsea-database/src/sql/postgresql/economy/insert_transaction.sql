@@ -0,0 +1,5 @@
+-- mutsea-database/src/sql/postgresql/economy/insert_transaction.sql
+INSERT INTO economy_transactions (
+    id, source_id, destination_id, amount, transaction_type, object_id, description
+)
+VALUES (CAST(? AS UUID), CAST(? AS UUID), CAST(? AS UUID), ?, ?, CAST(? AS UUID), ?);
@@ -0,0 +1,4 @@
+-- mutsea-database/src/sql/postgresql/economy/select_balance.sql
+SELECT balance
+FROM economy_balances
+WHERE agent_id = CAST(? AS UUID);
@@ -0,0 +1,13 @@
+-- mutsea-database/src/sql/postgresql/economy/select_transactions.sql
+SELECT
+    id::TEXT,
+    source_id::TEXT,
+    destination_id::TEXT,
+    amount,
+    transaction_type::BIGINT,
+    object_id::TEXT,
+    description
+FROM economy_transactions
+WHERE source_id = CAST(? AS UUID) OR destination_id = CAST(? AS UUID)
+ORDER BY created_at DESC
+LIMIT ?;
@@ -0,0 +1,6 @@
+-- mutsea-database/src/sql/postgresql/economy/upsert_balance.sql
+INSERT INTO economy_balances (agent_id, balance, updated_at)
+VALUES (CAST(? AS UUID), ?, NOW())
+ON CONFLICT (agent_id) DO UPDATE SET
+    balance = EXCLUDED.balance,
+    updated_at = NOW();
@@ -0,0 +1,5 @@
+-- mutsea-database/src/sql/postgresql/ecosystem/insert_ecosystem_snapshot.sql
+INSERT INTO ecosystem_states (
+    timestamp, biome_id, population_data, resource_data, interaction_data,
+    health_metrics, biodiversity_index, carrying_capacity
+) VALUES (CAST(? AS TIMESTAMPTZ), CAST(? AS UUID), ?, ?, ?, ?, ?, ?);
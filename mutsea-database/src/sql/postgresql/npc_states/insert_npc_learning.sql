@@ -0,0 +1,5 @@
+-- mutsea-database/src/sql/postgresql/npc_states/insert_npc_learning.sql
+INSERT INTO learning_data (
+    subject_type, subject_id, learning_session_id, timestamp,
+    input_data, output_data, learning_algorithm
+) VALUES ('npc', CAST(? AS UUID), CAST(? AS UUID), NOW(), ?, ?, ?);
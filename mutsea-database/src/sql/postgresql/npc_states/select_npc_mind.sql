@@ -0,0 +1,7 @@
+-- mutsea-database/src/sql/postgresql/npc_states/select_npc_mind.sql
+SELECT
+    personality_data,
+    memory_data,
+    metadata
+FROM npc_states
+WHERE npc_id = CAST(? AS UUID);
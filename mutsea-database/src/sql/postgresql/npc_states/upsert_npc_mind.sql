@@ -0,0 +1,9 @@
+-- mutsea-database/src/sql/postgresql/npc_states/upsert_npc_mind.sql
+INSERT INTO npc_states (npc_id, timestamp, personality_data, memory_data, metadata)
+VALUES (CAST(? AS UUID), NOW(), ?, ?, ?)
+ON CONFLICT (npc_id) DO UPDATE SET
+    timestamp = NOW(),
+    personality_data = EXCLUDED.personality_data,
+    memory_data = EXCLUDED.memory_data,
+    metadata = npc_states.metadata || EXCLUDED.metadata,
+    updated_at = NOW();
@@ -0,0 +1,5 @@
+-- mutsea-database/src/sql/postgresql/performance/insert_performance_sample.sql
+INSERT INTO performance_metrics (
+    timestamp, component_name, metric_type, metric_value,
+    unit_of_measure, context_data, alert_level
+) VALUES (CAST(? AS TIMESTAMPTZ), ?, ?, ?, ?, ?, ?);
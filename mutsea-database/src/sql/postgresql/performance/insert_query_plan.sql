@@ -0,0 +1,5 @@
+-- mutsea-database/src/sql/postgresql/performance/insert_query_plan.sql
+INSERT INTO query_plan_analysis (
+    captured_at, query_fingerprint, query_sql, execution_time_ms,
+    analyzed, plan
+) VALUES (CAST(? AS TIMESTAMPTZ), ?, ?, ?, ?, ?);
@@ -0,0 +1,24 @@
+-- mutsea-database/src/sql/postgresql/performance/rollup_metric_buckets.sql
+-- Roll {source} buckets in [?, ?) up into coarser {unit} buckets in {target}
+INSERT INTO {target} (
+    bucket_start, component_name, metric_type, unit_of_measure,
+    sample_count, value_sum, value_min, value_max
+)
+SELECT
+    date_trunc('{unit}', bucket_start),
+    component_name,
+    metric_type,
+    MAX(unit_of_measure),
+    SUM(sample_count),
+    SUM(value_sum),
+    MIN(value_min),
+    MAX(value_max)
+FROM {source}
+WHERE bucket_start >= CAST(? AS TIMESTAMPTZ) AND bucket_start < CAST(? AS TIMESTAMPTZ)
+GROUP BY 1, 2, 3
+ON CONFLICT (component_name, metric_type, bucket_start) DO UPDATE SET
+    unit_of_measure = EXCLUDED.unit_of_measure,
+    sample_count = EXCLUDED.sample_count,
+    value_sum = EXCLUDED.value_sum,
+    value_min = EXCLUDED.value_min,
+    value_max = EXCLUDED.value_max;
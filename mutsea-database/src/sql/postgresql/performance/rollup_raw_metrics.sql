@@ -0,0 +1,24 @@
+-- mutsea-database/src/sql/postgresql/performance/rollup_raw_metrics.sql
+-- Roll raw samples in [?, ?) up into one-minute buckets
+INSERT INTO performance_metrics_1m (
+    bucket_start, component_name, metric_type, unit_of_measure,
+    sample_count, value_sum, value_min, value_max
+)
+SELECT
+    date_trunc('minute', timestamp),
+    component_name,
+    metric_type,
+    MAX(unit_of_measure),
+    COUNT(*),
+    SUM(metric_value),
+    MIN(metric_value),
+    MAX(metric_value)
+FROM performance_metrics
+WHERE timestamp >= CAST(? AS TIMESTAMPTZ) AND timestamp < CAST(? AS TIMESTAMPTZ)
+GROUP BY 1, 2, 3
+ON CONFLICT (component_name, metric_type, bucket_start) DO UPDATE SET
+    unit_of_measure = EXCLUDED.unit_of_measure,
+    sample_count = EXCLUDED.sample_count,
+    value_sum = EXCLUDED.value_sum,
+    value_min = EXCLUDED.value_min,
+    value_max = EXCLUDED.value_max;
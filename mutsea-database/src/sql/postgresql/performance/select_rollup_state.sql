@@ -0,0 +1,3 @@
+-- mutsea-database/src/sql/postgresql/performance/select_rollup_state.sql
+SELECT resolution, EXTRACT(EPOCH FROM rolled_until)::BIGINT
+FROM metric_rollup_state;
@@ -0,0 +1,18 @@
+-- mutsea-database/src/sql/postgresql/performance/select_table_indexes.sql
+-- One row per index (or one row for an unindexed table) of every table in
+-- the current schema: table, estimated rows, size, index name, columns
+SELECT
+    c.relname,
+    GREATEST(c.reltuples, 0)::BIGINT,
+    pg_total_relation_size(c.oid),
+    COALESCE(ic.relname, ''),
+    COALESCE(string_agg(a.attname, ',' ORDER BY k.ord), '')
+FROM pg_class c
+JOIN pg_namespace n ON n.oid = c.relnamespace
+LEFT JOIN pg_index x ON x.indrelid = c.oid
+LEFT JOIN pg_class ic ON ic.oid = x.indexrelid
+LEFT JOIN LATERAL unnest(x.indkey) WITH ORDINALITY AS k(attnum, ord) ON TRUE
+LEFT JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum = k.attnum
+WHERE c.relkind = 'r' AND n.nspname = current_schema()
+GROUP BY c.relname, c.reltuples, c.oid, ic.relname
+ORDER BY c.relname, ic.relname;
@@ -0,0 +1,4 @@
+-- mutsea-database/src/sql/postgresql/performance/upsert_rollup_state.sql
+INSERT INTO metric_rollup_state (resolution, rolled_until)
+VALUES (?, to_timestamp(?))
+ON CONFLICT (resolution) DO UPDATE SET rolled_until = EXCLUDED.rolled_until;
@@ -0,0 +1,4 @@
+-- mutsea-database/src/sql/postgresql/player_behavior/insert_behavior_record.sql
+INSERT INTO player_behaviors (
+    player_id, session_id, timestamp, action_type, action_data, context_data
+) VALUES (CAST(? AS UUID), CAST(? AS UUID), CAST(? AS TIMESTAMPTZ), ?, ?, ?);
@@ -0,0 +1,12 @@
+-- mutsea-database/src/sql/postgresql/player_behavior/select_session_ends.sql
+SELECT
+    CAST(session_id AS TEXT),
+    EXTRACT(EPOCH FROM timestamp)::BIGINT,
+    action_data,
+    context_data
+FROM player_behaviors
+WHERE player_id = CAST(? AS UUID)
+  AND action_type = 'session_end'
+  AND timestamp >= CAST(? AS TIMESTAMPTZ)
+ORDER BY timestamp DESC
+LIMIT ?;
@@ -0,0 +1,9 @@
+-- mutsea-database/src/sql/postgresql/schema/create_ai_decision_learning.sql
+ALTER TABLE ai_decisions
+    ADD COLUMN IF NOT EXISTS outcome_variance DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS learning_value DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS outcome_recorded_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS learning_evaluated_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_ai_decisions_pending_evaluation
+    ON ai_decisions(outcome_recorded_at) WHERE learning_evaluated_at IS NULL;
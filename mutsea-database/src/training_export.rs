@@ -0,0 +1,461 @@
+//! Anonymized training datasets for the AI models
+//!
+//! [`export`] writes the learning data, scored AI decisions and player
+//! behavior recorded over a time range as JSON Lines, one [`TrainingRecord`]
+//! per line tagged with [`SCHEMA_VERSION`]. Before a record is written its
+//! JSON is scrubbed by [`ScrubRules`]: keys that hold personal data are
+//! dropped, e-mail and IP addresses in strings are masked, and every UUID
+//! (players, sessions, NPCs, and any mentioned in the data) is replaced by
+//! a pseudonym derived from a per-export salt, so records of one export
+//! still join while nothing links them back to accounts.
+
+use crate::backends::{BackendType, DatabaseBackend, Row, ToSql};
+use crate::{DatabaseError, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::net::IpAddr;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Version of the record layout; raised whenever a field changes meaning
+/// or is removed
+pub const SCHEMA_VERSION: u32 = 1;
+
+const BATCH_SIZE: usize = 1000;
+
+/// The model a dataset is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrainingModel {
+    /// NPC behavior: NPC learning data, every AI decision but world
+    /// generation, and player behavior
+    Npc,
+    /// World generation: ecosystem learning data, world generation
+    /// decisions, and player behavior
+    Worldgen,
+}
+
+impl TrainingModel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrainingModel::Npc => "npc",
+            TrainingModel::Worldgen => "worldgen",
+        }
+    }
+
+    fn learning_subject(&self) -> &'static str {
+        match self {
+            TrainingModel::Npc => "npc",
+            TrainingModel::Worldgen => "ecosystem",
+        }
+    }
+
+    fn decision_condition(&self) -> &'static str {
+        match self {
+            TrainingModel::Npc => "decision_type <> 'world_generation'",
+            TrainingModel::Worldgen => "decision_type = 'world_generation'",
+        }
+    }
+}
+
+impl FromStr for TrainingModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "npc" => Ok(TrainingModel::Npc),
+            "worldgen" => Ok(TrainingModel::Worldgen),
+            other => Err(format!("unknown model '{}', expected npc or worldgen", other)),
+        }
+    }
+}
+
+/// Half-open time range `[from, to)`; either end may be open
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl FromStr for TimeRange {
+    type Err = String;
+
+    /// `t1..t2`, each an RFC 3339 time or a `YYYY-MM-DD` date, e.g.
+    /// `2026-09-01..2026-10-01` or `2026-10-01T12:00:00Z..`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (from, to) = s
+            .split_once("..")
+            .ok_or_else(|| format!("expected a range like 2026-09-01..2026-10-01, got '{}'", s))?;
+        let parse = |t: &str| -> std::result::Result<Option<DateTime<Utc>>, String> {
+            let t = t.trim();
+            if t.is_empty() {
+                return Ok(None);
+            }
+            if let Ok(time) = DateTime::parse_from_rfc3339(t) {
+                return Ok(Some(time.with_timezone(&Utc)));
+            }
+            NaiveDate::parse_from_str(t, "%Y-%m-%d")
+                .map(|date| Some(date.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc()))
+                .map_err(|_| format!("'{}' is neither an RFC 3339 time nor a YYYY-MM-DD date", t))
+        };
+        let range = TimeRange {
+            from: parse(from)?,
+            to: parse(to)?,
+        };
+        if let (Some(from), Some(to)) = (range.from, range.to) {
+            if from >= to {
+                return Err(format!("the range {} is empty", s));
+            }
+        }
+        Ok(range)
+    }
+}
+
+impl TimeRange {
+    /// Bounds in milliseconds, open ends from the epoch to the year 9999
+    fn millis(&self) -> (i64, i64) {
+        (
+            self.from.map_or(0, |t| t.timestamp_millis()),
+            self.to.map_or(253_402_300_800_000, |t| t.timestamp_millis()),
+        )
+    }
+}
+
+/// What is removed or masked before a record is written
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrubRules {
+    /// Object keys dropped wherever they appear, compared case-insensitively
+    pub drop_keys: BTreeSet<String>,
+    /// Mask e-mail addresses inside strings
+    pub mask_emails: bool,
+    /// Mask IPv4 and IPv6 addresses inside strings
+    pub mask_ips: bool,
+    /// Replace UUIDs by salted pseudonyms
+    pub pseudonymize_ids: bool,
+}
+
+impl Default for ScrubRules {
+    fn default() -> Self {
+        let drop_keys = [
+            "name", "first_name", "last_name", "firstname", "lastname", "display_name", "username", "email",
+            "ip", "ip_address", "address", "password", "password_hash", "message", "chat", "im", "text",
+            "mac", "id0", "viewer_fingerprint",
+        ];
+        Self {
+            drop_keys: drop_keys.into_iter().map(str::to_string).collect(),
+            mask_emails: true,
+            mask_ips: true,
+            pseudonymize_ids: true,
+        }
+    }
+}
+
+/// Applies [`ScrubRules`] with one export's salt and counts what it changed
+pub struct Scrubber {
+    rules: ScrubRules,
+    salt: Vec<u8>,
+    dropped_keys: u64,
+    masked_values: u64,
+}
+
+impl Scrubber {
+    pub fn new(mut rules: ScrubRules, salt: &[u8]) -> Self {
+        rules.drop_keys = rules.drop_keys.iter().map(|k| k.to_lowercase()).collect();
+        Self {
+            rules,
+            salt: salt.to_vec(),
+            dropped_keys: 0,
+            masked_values: 0,
+        }
+    }
+
+    /// Stable pseudonym of `id` within this export
+    pub fn pseudonym(&self, id: Uuid) -> Uuid {
+        let digest = Sha256::new().chain_update(&self.salt).chain_update(id.as_bytes()).finalize();
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&digest[..16]);
+        uuid::Builder::from_random_bytes(bytes).into_uuid()
+    }
+
+    /// Scrub `value` in place
+    pub fn scrub(&mut self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                let before = map.len();
+                map.retain(|key, _| !self.rules.drop_keys.contains(&key.to_lowercase()));
+                self.dropped_keys += (before - map.len()) as u64;
+                for value in map.values_mut() {
+                    self.scrub(value);
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.scrub(item)),
+            Value::String(s) => {
+                let scrubbed = self.scrub_text(s);
+                if scrubbed != *s {
+                    self.masked_values += 1;
+                    *s = scrubbed;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn scrub_text(&self, text: &str) -> String {
+        text.split(' ')
+            .map(|word| {
+                let token = word.trim_matches(|c: char| matches!(c, ',' | ';' | '(' | ')' | '"' | '\'' | '<' | '>'));
+                let token = token.strip_suffix('.').unwrap_or(token);
+                let replacement = match Uuid::parse_str(token) {
+                    Ok(id) if self.rules.pseudonymize_ids => Some(self.pseudonym(id).to_string()),
+                    _ if self.rules.mask_emails && is_email(token) => Some("[email]".to_string()),
+                    _ if self.rules.mask_ips && token.parse::<IpAddr>().is_ok() => Some("[ip]".to_string()),
+                    _ => None,
+                };
+                match replacement {
+                    Some(replacement) if !token.is_empty() => word.replacen(token, &replacement, 1),
+                    _ => word.to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn id(&self, id: Uuid) -> Uuid {
+        if self.rules.pseudonymize_ids {
+            self.pseudonym(id)
+        } else {
+            id
+        }
+    }
+}
+
+fn is_email(token: &str) -> bool {
+    match token.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.')
+        }
+        None => false,
+    }
+}
+
+/// Where a record came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordSource {
+    LearningData,
+    AiDecision,
+    PlayerBehavior,
+}
+
+/// One line of a dataset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrainingRecord {
+    pub schema_version: u32,
+    pub model: TrainingModel,
+    pub source: RecordSource,
+    pub timestamp: DateTime<Utc>,
+    /// Pseudonym of the player, NPC or system the record is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Uuid>,
+    /// Pseudonym of the session or learning session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<Uuid>,
+    /// Learning algorithm, decision type or player action
+    pub kind: String,
+    pub input: Value,
+    pub output: Value,
+    /// Observed outcome or feedback
+    pub outcome: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+}
+
+/// Summary of an export, written next to the dataset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportManifest {
+    pub schema_version: u32,
+    pub model: TrainingModel,
+    pub range: TimeRange,
+    pub generated_at: DateTime<Utc>,
+    pub rules: ScrubRules,
+    pub records: BTreeMap<RecordSource, u64>,
+    pub dropped_keys: u64,
+    pub masked_values: u64,
+}
+
+/// Write the dataset for `model` over `range` to `out`, one JSON record per
+/// line, scrubbed by `rules` with `salt` (PostgreSQL)
+pub async fn export(
+    backend: &dyn DatabaseBackend,
+    model: TrainingModel,
+    range: TimeRange,
+    rules: ScrubRules,
+    salt: &[u8],
+    out: &mut dyn Write,
+) -> Result<ExportManifest> {
+    if backend.backend_type() != BackendType::PostgreSQL {
+        return Err(DatabaseError::UnsupportedBackend(
+            "training data is only recorded on PostgreSQL".to_string(),
+        ));
+    }
+    let mut scrubber = Scrubber::new(rules.clone(), salt);
+    let mut records = BTreeMap::new();
+    let (from, to) = range.millis();
+
+    let sources: [(RecordSource, String); 3] = [
+        (
+            RecordSource::LearningData,
+            format!(
+                "SELECT CAST(EXTRACT(EPOCH FROM timestamp) * 1000 AS BIGINT), CAST(subject_id AS TEXT), \
+                 CAST(learning_session_id AS TEXT), learning_algorithm, CAST(input_data AS TEXT), \
+                 CAST(output_data AS TEXT), CAST(feedback_data AS TEXT), CAST(NULL AS DOUBLE PRECISION) \
+                 FROM learning_data WHERE subject_type = '{}' AND {} ORDER BY timestamp",
+                model.learning_subject(),
+                time_condition("timestamp"),
+            ),
+        ),
+        (
+            RecordSource::AiDecision,
+            format!(
+                "SELECT CAST(EXTRACT(EPOCH FROM created_at) * 1000 AS BIGINT), CAST(NULL AS TEXT), \
+                 CAST(NULL AS TEXT), decision_type, CAST(input_data AS TEXT), CAST(decision_data AS TEXT), \
+                 CAST(outcome_data AS TEXT), CAST(feedback_score AS DOUBLE PRECISION) \
+                 FROM ai_decisions WHERE {} AND {} ORDER BY created_at",
+                model.decision_condition(),
+                time_condition("created_at"),
+            ),
+        ),
+        (
+            RecordSource::PlayerBehavior,
+            format!(
+                "SELECT CAST(EXTRACT(EPOCH FROM timestamp) * 1000 AS BIGINT), CAST(player_id AS TEXT), \
+                 CAST(session_id AS TEXT), action_type, CAST(context_data AS TEXT), CAST(action_data AS TEXT), \
+                 CAST(performance_metrics AS TEXT), CAST(NULL AS DOUBLE PRECISION) \
+                 FROM player_behaviors WHERE {} ORDER BY timestamp",
+                time_condition("timestamp"),
+            ),
+        ),
+    ];
+
+    for (source, query) in sources {
+        let params: [&dyn ToSql; 2] = [&from, &to];
+        let mut rows = backend.query_stream(&query, &params, BATCH_SIZE);
+        let mut count = 0u64;
+        while let Some(row) = rows.try_next().await? {
+            let record = training_record(&row, model, source, &mut scrubber)?;
+            serde_json::to_writer(&mut *out, &record).map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+            out.write_all(b"\n").map_err(|e| DatabaseError::Internal(e.to_string()))?;
+            count += 1;
+        }
+        records.insert(source, count);
+    }
+    out.flush().map_err(|e| DatabaseError::Internal(e.to_string()))?;
+
+    Ok(ExportManifest {
+        schema_version: SCHEMA_VERSION,
+        model,
+        range,
+        generated_at: Utc::now(),
+        rules,
+        records,
+        dropped_keys: scrubber.dropped_keys,
+        masked_values: scrubber.masked_values,
+    })
+}
+
+/// `column` within the bound `[from, to)` milliseconds
+fn time_condition(column: &str) -> String {
+    format!(
+        "{column} >= TO_TIMESTAMP(CAST(? AS BIGINT) / 1000.0) AND {column} < TO_TIMESTAMP(CAST(? AS BIGINT) / 1000.0)"
+    )
+}
+
+fn training_record<R: Row + ?Sized>(
+    row: &R,
+    model: TrainingModel,
+    source: RecordSource,
+    scrubber: &mut Scrubber,
+) -> Result<TrainingRecord> {
+    let json = |text: Option<String>| -> Result<Value> {
+        match text {
+            Some(text) => serde_json::from_str(&text).map_err(|e| DatabaseError::Serialization(e.to_string())),
+            None => Ok(Value::Object(Map::new())),
+        }
+    };
+    let id = |text: Option<String>, scrubber: &Scrubber| text.and_then(|t| Uuid::parse_str(&t).ok()).map(|id| scrubber.id(id));
+    let timestamp: i64 = row.get(0)?;
+    let mut input = json(row.get(4)?)?;
+    let mut output = json(row.get(5)?)?;
+    let mut outcome = json(row.get(6)?)?;
+    for value in [&mut input, &mut output, &mut outcome] {
+        scrubber.scrub(value);
+    }
+    Ok(TrainingRecord {
+        schema_version: SCHEMA_VERSION,
+        model,
+        source,
+        timestamp: DateTime::from_timestamp_millis(timestamp).unwrap_or_default(),
+        subject: id(row.get(1)?, scrubber),
+        session: id(row.get(2)?, scrubber),
+        kind: row.get(3)?,
+        input,
+        output,
+        outcome,
+        score: row.get(7)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_ranges_parse() {
+        let range: TimeRange = "2026-09-01..2026-10-01T12:00:00Z".parse().unwrap();
+        assert_eq!(range.from.unwrap().to_rfc3339(), "2026-09-01T00:00:00+00:00");
+        assert_eq!(range.to.unwrap().to_rfc3339(), "2026-10-01T12:00:00+00:00");
+        assert_eq!("..".parse::<TimeRange>().unwrap(), TimeRange::default());
+        assert!("2026-10-01..2026-09-01".parse::<TimeRange>().is_err());
+        assert!("yesterday".parse::<TimeRange>().is_err());
+        assert_eq!("worldgen".parse::<TrainingModel>(), Ok(TrainingModel::Worldgen));
+    }
+
+    #[test]
+    fn test_scrubbing_drops_masks_and_pseudonymizes() {
+        let player = Uuid::new_v4();
+        let mut scrubber = Scrubber::new(ScrubRules::default(), b"salt");
+        let mut value = json!({
+            "Email": "someone@example.com",
+            "context": {
+                "note": format!("met {} (mail someone@example.com) from 10.0.0.7.", player),
+                "names": [{"first_name": "Ada"}],
+                "distance": 4.5
+            }
+        });
+        scrubber.scrub(&mut value);
+
+        let pseudonym = scrubber.pseudonym(player);
+        assert_ne!(pseudonym, player);
+        assert_eq!(
+            value,
+            json!({
+                "context": {
+                    "note": format!("met {} (mail [email]) from [ip].", pseudonym),
+                    "names": [{}],
+                    "distance": 4.5
+                }
+            })
+        );
+        assert_eq!((scrubber.dropped_keys, scrubber.masked_values), (2, 1));
+
+        // Pseudonyms are stable within an export and differ between salts
+        assert_eq!(scrubber.pseudonym(player), pseudonym);
+        assert_ne!(Scrubber::new(ScrubRules::default(), b"other").pseudonym(player), pseudonym);
+    }
+}
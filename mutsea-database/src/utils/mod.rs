@@ -86,6 +86,15 @@ pub struct ConnectionStats {
     pub total_queries_executed: u64,
     pub average_query_time: Duration,
     pub last_updated: DateTime<Utc>,
+    /// Longest wait for a connection
+    #[serde(default)]
+    pub max_wait_time: Duration,
+    /// Connections held past the leak threshold
+    #[serde(default)]
+    pub leaked_connections: u64,
+    /// Age of the longest-held open connection
+    #[serde(default)]
+    pub oldest_checkout: Duration,
 }
 
 /// Query execution statistics
@@ -101,6 +110,12 @@ pub struct QueryStats {
     pub error_count: u64,
     pub last_executed: DateTime<Utc>,
     pub parameters_hash: Option<String>,
+    /// Rows returned or affected, summed over all executions
+    #[serde(default)]
+    pub rows_total: u64,
+    /// Executions at or over the slow-query threshold
+    #[serde(default)]
+    pub slow_count: u64,
 }
 
 /// Database performance metrics
@@ -400,6 +415,21 @@ impl DatabaseUtils {
             recommendations.push("Consider increasing max_connections".to_string());
         }
         
+        if metrics.connection_stats.leaked_connections > 0 {
+            issues.push(format!(
+                "{} database connection(s) held past the leak threshold",
+                metrics.connection_stats.leaked_connections
+            ));
+            recommendations.push("Check the logged checkout stack traces for handles that are never dropped".to_string());
+        }
+        if metrics.connection_stats.average_connection_time > Duration::from_secs(1) {
+            issues.push(format!(
+                "Waiting {:?} on average for a database connection",
+                metrics.connection_stats.average_connection_time
+            ));
+            recommendations.push("Consider increasing max_connections or shortening transactions".to_string());
+        }
+        
         // Analyze query performance
         let slow_queries: Vec<_> = metrics.query_stats
             .iter()
@@ -423,6 +453,10 @@ impl DatabaseUtils {
             recommendations.push("Review caching strategy".to_string());
         }
         
+        // Missing indexes behind the recorded queries
+        let index_suggestions = crate::index_advisor::advise(&metrics.query_stats, &HashMap::new());
+        recommendations.extend(index_suggestions.iter().take(3).map(|s| s.summary()));
+        
         // Overall health score
         let health_score = calculate_health_score(&metrics);
         
@@ -591,7 +625,8 @@ pub trait DatabaseUtilsExt {
     /// Get health report
     async fn get_health_report(&self) -> DatabaseResult<HealthReport>;
     
-    /// Optimize query performance
+    /// Optimize query performance; see `DatabaseManager::optimize_query`,
+    /// which reports the plan captured for the query's last slow run
     async fn optimize_query(&self, sql: &str) -> DatabaseResult<String>;
 }
 
@@ -656,6 +691,9 @@ mod tests {
                 total_queries_executed: 10000,
                 average_query_time: Duration::from_millis(200),
                 last_updated: Utc::now(),
+                max_wait_time: Duration::from_millis(20),
+                leaked_connections: 0,
+                oldest_checkout: Duration::from_secs(1),
             },
             query_stats: HashMap::new(),
             cache_stats: CacheStats {
@@ -0,0 +1,439 @@
+//! Append-only log of world state changes, compacted into snapshots
+//!
+//! Every change to a region's world state is appended to `world_events`
+//! with the next sequence number of its region; events are never updated.
+//! The state of an entity is a JSON value that an event sets, merges into
+//! (JSON merge patch) or removes. Compaction writes the state after the
+//! latest event to `world_event_snapshots` once enough events have
+//! accumulated, and prunes events past their retention that a snapshot
+//! covers. The state at any time is the latest snapshot taken at or before
+//! it with the later events up to that time replayed on top, so it is exact
+//! as far back as events are kept and at every snapshot before that.
+//!
+//! Columns are portable (text UUIDs and JSON, millisecond timestamps) so
+//! the log works the same on PostgreSQL and SQLite. Sequence numbers are
+//! assigned inside the appending transaction; a region's events are
+//! expected to come from the one simulator running it.
+
+use crate::backends::{DatabaseBackend, ToSql};
+use crate::{DatabaseError, DatabaseManager, Result};
+use chrono::{DateTime, Duration, Utc};
+use futures::TryStreamExt;
+use mutsea_core::config::WorldEventsConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+const CREATE_EVENTS: &str = "CREATE TABLE IF NOT EXISTS world_events (
+    region_id VARCHAR(36) NOT NULL,
+    sequence BIGINT NOT NULL,
+    entity VARCHAR(255) NOT NULL,
+    change_type VARCHAR(16) NOT NULL,
+    payload TEXT NOT NULL,
+    occurred_at BIGINT NOT NULL,
+    PRIMARY KEY (region_id, sequence)
+)";
+const CREATE_EVENTS_TIME_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS idx_world_events_time ON world_events (region_id, occurred_at)";
+const CREATE_SNAPSHOTS: &str = "CREATE TABLE IF NOT EXISTS world_event_snapshots (
+    region_id VARCHAR(36) NOT NULL,
+    sequence BIGINT NOT NULL,
+    taken_at BIGINT NOT NULL,
+    state TEXT NOT NULL,
+    PRIMARY KEY (region_id, sequence)
+)";
+
+/// How an event changes the state of its entity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Change {
+    /// Replace the state
+    Set(Value),
+    /// Merge into the state as a JSON merge patch (RFC 7386)
+    Merge(Value),
+    /// Remove the entity
+    Remove,
+}
+
+impl Change {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Change::Set(_) => "set",
+            Change::Merge(_) => "merge",
+            Change::Remove => "remove",
+        }
+    }
+
+    fn payload(&self) -> String {
+        match self {
+            Change::Set(value) | Change::Merge(value) => value.to_string(),
+            Change::Remove => "null".to_string(),
+        }
+    }
+
+    fn parse(type_name: &str, payload: &str) -> Result<Self> {
+        let value = || serde_json::from_str(payload).map_err(|e| DatabaseError::Serialization(e.to_string()));
+        match type_name {
+            "set" => Ok(Change::Set(value()?)),
+            "merge" => Ok(Change::Merge(value()?)),
+            "remove" => Ok(Change::Remove),
+            other => Err(DatabaseError::Serialization(format!("unknown world event change '{}'", other))),
+        }
+    }
+}
+
+/// A change to one entity of a region, e.g. `"object/<uuid>"` or
+/// `"parcel/3"`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldEvent {
+    pub region_id: Uuid,
+    /// Position in the region's log; assigned on append
+    pub sequence: i64,
+    pub entity: String,
+    pub change: Change,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl WorldEvent {
+    pub fn new(region_id: Uuid, entity: impl Into<String>, change: Change, occurred_at: DateTime<Utc>) -> Self {
+        Self {
+            region_id,
+            sequence: 0,
+            entity: entity.into(),
+            change,
+            occurred_at,
+        }
+    }
+}
+
+/// The state of every entity of a region after some event
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorldState {
+    /// Sequence of the last event applied, 0 before any
+    pub sequence: i64,
+    pub entities: BTreeMap<String, Value>,
+}
+
+impl WorldState {
+    /// Apply the next event
+    pub fn apply(&mut self, event: &WorldEvent) {
+        match &event.change {
+            Change::Set(value) => {
+                self.entities.insert(event.entity.clone(), value.clone());
+            }
+            Change::Merge(patch) => {
+                let state = self.entities.entry(event.entity.clone()).or_insert(Value::Null);
+                merge_patch(state, patch);
+            }
+            Change::Remove => {
+                self.entities.remove(&event.entity);
+            }
+        }
+        self.sequence = event.sequence;
+    }
+}
+
+/// RFC 7386: objects merge key by key, null removes a key, anything else
+/// replaces
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let target = target.as_object_mut().expect("made an object above");
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// When compaction snapshots and prunes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionPolicy {
+    /// Events since the last snapshot before another is taken
+    pub snapshot_every: u64,
+    /// How long events are kept once a snapshot covers them; `None` keeps
+    /// them forever
+    pub retain_events: Option<Duration>,
+}
+
+impl CompactionPolicy {
+    pub fn from_config(config: &WorldEventsConfig) -> Self {
+        Self {
+            snapshot_every: config.snapshot_every.max(1),
+            retain_events: (config.retain_event_days > 0).then(|| Duration::days(config.retain_event_days as i64)),
+        }
+    }
+}
+
+/// Outcome of compacting one region
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompactionReport {
+    /// Sequence of the snapshot written, if one was
+    pub snapshot: Option<i64>,
+    /// Events deleted
+    pub pruned: u64,
+}
+
+/// Create the event and snapshot tables
+pub async fn ensure_schema(backend: &dyn DatabaseBackend) -> Result<()> {
+    for statement in [CREATE_EVENTS, CREATE_EVENTS_TIME_INDEX, CREATE_SNAPSHOTS] {
+        backend.execute(statement, &[]).await?;
+    }
+    Ok(())
+}
+
+/// Append `events` to their regions' logs in one transaction, returning
+/// them with their sequence numbers
+pub async fn append(backend: &dyn DatabaseBackend, events: Vec<WorldEvent>) -> Result<Vec<WorldEvent>> {
+    let mut transaction = backend.begin_transaction().await?;
+    let mut next: BTreeMap<Uuid, i64> = BTreeMap::new();
+    let mut appended = Vec::with_capacity(events.len());
+    for mut event in events {
+        let region = event.region_id.to_string();
+        let sequence = match next.get(&event.region_id) {
+            Some(sequence) => *sequence,
+            None => {
+                // Snapshots keep counting once the events they cover are pruned
+                let rows = transaction
+                    .query(
+                        "SELECT COALESCE(MAX(sequence), 0) FROM (\
+                         SELECT sequence FROM world_events WHERE region_id = ? \
+                         UNION ALL SELECT sequence FROM world_event_snapshots WHERE region_id = ?) AS logged",
+                        &[&region, &region],
+                    )
+                    .await?;
+                let last: i64 = match rows.first() {
+                    Some(row) => row.get(0)?,
+                    None => 0,
+                };
+                last + 1
+            }
+        };
+        event.sequence = sequence;
+        transaction
+            .execute(
+                "INSERT INTO world_events (region_id, sequence, entity, change_type, payload, occurred_at) VALUES (?, ?, ?, ?, ?, ?)",
+                &[
+                    &region,
+                    &sequence,
+                    &event.entity,
+                    &event.change.type_name(),
+                    &event.change.payload(),
+                    &event.occurred_at.timestamp_millis(),
+                ],
+            )
+            .await?;
+        next.insert(event.region_id, sequence + 1);
+        appended.push(event);
+    }
+    transaction.commit().await?;
+    Ok(appended)
+}
+
+/// Latest snapshot of `region` taken at or before `at`
+async fn snapshot_at(backend: &dyn DatabaseBackend, region: Uuid, at: DateTime<Utc>) -> Result<WorldState> {
+    let rows = backend
+        .query(
+            "SELECT state FROM world_event_snapshots WHERE region_id = ? AND taken_at <= ? ORDER BY sequence DESC LIMIT 1",
+            &[&region.to_string(), &at.timestamp_millis()],
+        )
+        .await?;
+    match rows.first() {
+        Some(row) => {
+            let state: String = row.get(0)?;
+            serde_json::from_str(&state).map_err(|e| DatabaseError::Serialization(e.to_string()))
+        }
+        None => Ok(WorldState::default()),
+    }
+}
+
+/// Events of `region` after `after`, in order, that occurred by `until`
+pub async fn events(
+    backend: &dyn DatabaseBackend,
+    region: Uuid,
+    after: i64,
+    until: DateTime<Utc>,
+) -> Result<Vec<WorldEvent>> {
+    let region_text = region.to_string();
+    let until = until.timestamp_millis();
+    let params: [&dyn ToSql; 3] = [&region_text, &after, &until];
+    let mut rows = backend.query_stream(
+        "SELECT sequence, entity, change_type, payload, occurred_at FROM world_events \
+         WHERE region_id = ? AND sequence > ? AND occurred_at <= ? ORDER BY sequence",
+        &params,
+        crate::backends::cursor::DEFAULT_BATCH_SIZE,
+    );
+    let mut events = Vec::new();
+    while let Some(row) = rows.try_next().await? {
+        let change_type: String = row.get(2)?;
+        let payload: String = row.get(3)?;
+        let occurred_at: i64 = row.get(4)?;
+        events.push(WorldEvent {
+            region_id: region,
+            sequence: row.get(0)?,
+            entity: row.get(1)?,
+            change: Change::parse(&change_type, &payload)?,
+            occurred_at: DateTime::from_timestamp_millis(occurred_at).unwrap_or_default(),
+        });
+    }
+    Ok(events)
+}
+
+/// The state of `region` at `at`: its latest snapshot by then with the
+/// later events up to `at` replayed
+pub async fn state_at(backend: &dyn DatabaseBackend, region: Uuid, at: DateTime<Utc>) -> Result<WorldState> {
+    let mut state = snapshot_at(backend, region, at).await?;
+    for event in events(backend, region, state.sequence, at).await? {
+        state.apply(&event);
+    }
+    Ok(state)
+}
+
+/// Regions with events or snapshots
+pub async fn regions(backend: &dyn DatabaseBackend) -> Result<Vec<Uuid>> {
+    backend
+        .query(
+            "SELECT region_id FROM world_events UNION SELECT region_id FROM world_event_snapshots",
+            &[],
+        )
+        .await?
+        .iter()
+        .map(|row| {
+            let id: String = row.get(0)?;
+            Uuid::parse_str(&id).map_err(|e| DatabaseError::Serialization(e.to_string()))
+        })
+        .collect()
+}
+
+/// Snapshot `region` if enough events have accumulated and prune the
+/// events a snapshot covers that are past retention
+pub async fn compact(
+    backend: &dyn DatabaseBackend,
+    region: Uuid,
+    policy: &CompactionPolicy,
+    now: DateTime<Utc>,
+) -> Result<CompactionReport> {
+    let mut report = CompactionReport::default();
+    let region_text = region.to_string();
+    let state = snapshot_at(backend, region, now).await?;
+    let pending = events(backend, region, state.sequence, now).await?;
+    let mut covered = state.sequence;
+
+    if pending.len() as u64 >= policy.snapshot_every {
+        let mut state = state;
+        for event in &pending {
+            state.apply(event);
+        }
+        let taken_at = pending.last().map(|e| e.occurred_at).unwrap_or(now);
+        let serialized = serde_json::to_string(&state).map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+        backend
+            .execute(
+                "INSERT INTO world_event_snapshots (region_id, sequence, taken_at, state) VALUES (?, ?, ?, ?)",
+                &[&region_text, &state.sequence, &taken_at.timestamp_millis(), &serialized],
+            )
+            .await?;
+        report.snapshot = Some(state.sequence);
+        covered = state.sequence;
+    }
+
+    if let Some(retain) = policy.retain_events {
+        report.pruned = backend
+            .execute(
+                "DELETE FROM world_events WHERE region_id = ? AND sequence <= ? AND occurred_at < ?",
+                &[&region_text, &covered, &(now - retain).timestamp_millis()],
+            )
+            .await?;
+    }
+    Ok(report)
+}
+
+/// Compact every region every `compaction_interval_secs` in the background
+pub fn spawn_compaction(database: Arc<DatabaseManager>, config: &WorldEventsConfig) {
+    if !config.enabled {
+        return;
+    }
+    let policy = CompactionPolicy::from_config(config);
+    let interval = std::time::Duration::from_secs(config.compaction_interval_secs.max(1));
+    tokio::spawn(async move {
+        if let Err(e) = database.initialize_world_events().await {
+            warn!("Failed to create the world event tables: {}", e);
+        }
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let backend = match database.get_backend().await {
+                Ok(backend) => backend,
+                Err(e) => {
+                    warn!("World event compaction skipped: {}", e);
+                    continue;
+                }
+            };
+            let regions = match regions(backend.as_ref()).await {
+                Ok(regions) => regions,
+                Err(e) => {
+                    warn!("World event compaction failed: {}", e);
+                    continue;
+                }
+            };
+            for region in regions {
+                match compact(backend.as_ref(), region, &policy, Utc::now()).await {
+                    Ok(report) => debug!("World event compaction of {}: {:?}", region, report),
+                    Err(e) => warn!("World event compaction of {} failed: {}", region, e),
+                }
+            }
+        }
+    });
+    info!("World event snapshots every {} events", policy.snapshot_every);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(sequence: i64, entity: &str, change: Change) -> WorldEvent {
+        WorldEvent {
+            sequence,
+            ..WorldEvent::new(Uuid::nil(), entity, change, Utc::now())
+        }
+    }
+
+    #[test]
+    fn test_replay_sets_merges_and_removes() {
+        let mut state = WorldState::default();
+        state.apply(&event(1, "object/a", Change::Set(json!({"name": "Crate", "pos": [1, 2, 3]}))));
+        state.apply(&event(2, "object/b", Change::Set(json!({"name": "Lamp"}))));
+        state.apply(&event(3, "object/a", Change::Merge(json!({"pos": [4, 5, 6], "name": null, "owner": "x"}))));
+        state.apply(&event(4, "object/b", Change::Remove));
+
+        assert_eq!(state.sequence, 4);
+        assert_eq!(state.entities.len(), 1);
+        assert_eq!(state.entities["object/a"], json!({"pos": [4, 5, 6], "owner": "x"}));
+    }
+
+    #[test]
+    fn test_changes_round_trip_through_columns() {
+        for change in [Change::Set(json!([1, 2])), Change::Merge(json!({"a": 1})), Change::Remove] {
+            assert_eq!(Change::parse(change.type_name(), &change.payload()).unwrap(), change);
+        }
+        assert!(Change::parse("rename", "{}").is_err());
+    }
+
+    #[test]
+    fn test_snapshots_survive_serialization() {
+        let mut state = WorldState::default();
+        state.apply(&event(7, "parcel/3", Change::Merge(json!({"for_sale": true}))));
+        let restored: WorldState = serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+        assert_eq!(restored, state);
+    }
+}
@@ -1,5 +1,6 @@
 //! Update mutsea-network/src/lludp_server/circuit.rs
 
+use super::CongestionControl;
 use mutsea_core::{UserId, RegionId, Vector3, Quaternion};
 use std::collections::HashMap;
 use std::net::SocketAddr;
@@ -29,6 +30,26 @@ pub struct CircuitInfo {
     // Add missing ping fields
     pub last_ping_id: u8,
     pub last_ping_time: Instant,
+    /// Send rate and retransmission timeout for this circuit
+    pub congestion: CongestionControl,
+    /// When the circuit last moved to a new client address
+    pub last_migration: Option<Instant>,
+    /// Frozen by a moderator; movement is ignored
+    pub frozen: bool,
+}
+
+impl CircuitInfo {
+    /// Drop an acknowledged reliable packet, feeding its round trip into
+    /// congestion control. Returns false for unknown or duplicate acks.
+    pub fn acknowledge(&mut self, sequence: u32) -> bool {
+        match self.reliable_packets.remove(&sequence) {
+            Some(packet) => {
+                self.congestion.on_ack(packet.timestamp, packet.resend_count > 0);
+                true
+            }
+            None => false,
+        }
+    }
 }
 
 /// Client information
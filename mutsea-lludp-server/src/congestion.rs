@@ -0,0 +1,282 @@
+//! mutsea-network/src/lludp_server/congestion.rs
+//! Per-circuit congestion control
+//!
+//! Round-trip time is estimated from ack timing (RFC 6298 smoothing, with
+//! Karn's rule skipping resent packets) and loss from resends. The send
+//! rate grows additively while acks flow and halves at most once per RTT on
+//! loss. Texture, object and resend traffic draw from separate token
+//! buckets sized from the current rate, so a lossy client is slowed down
+//! without a resend storm spilling onto the rest of the region.
+
+use mutsea_core::config::ThrottleConfig;
+use std::time::{Duration, Instant};
+
+/// Additive rate increase per loss-free round trip, in bits per second
+const RATE_INCREASE_BPS: f64 = 8_000.0;
+/// Weight of the newest observation in the loss average
+const LOSS_WEIGHT: f64 = 0.05;
+/// Seconds of traffic a token bucket can hold
+const BURST_SECS: f64 = 0.25;
+/// Smallest bucket, so one full-size packet can always be sent eventually
+const MIN_BUCKET_BYTES: f64 = 1500.0;
+
+/// Throttled traffic classes, each with a fixed share of the circuit rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleCategory {
+    /// Retransmissions of unacked reliable packets
+    Resend,
+    /// Texture data
+    Texture,
+    /// Object updates
+    Object,
+}
+
+impl ThrottleCategory {
+    const ALL: [ThrottleCategory; 3] = [ThrottleCategory::Resend, ThrottleCategory::Texture, ThrottleCategory::Object];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Fraction of the circuit rate given to this category
+    fn share(self) -> f64 {
+        match self {
+            ThrottleCategory::Resend => 0.2,
+            ThrottleCategory::Texture => 0.4,
+            ThrottleCategory::Object => 0.4,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64) -> Self {
+        Self {
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn try_take(&mut self, bytes: usize, rate_bytes: f64) -> bool {
+        let capacity = (rate_bytes * BURST_SECS).max(MIN_BUCKET_BYTES);
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate_bytes).min(capacity);
+        self.last_refill = now;
+
+        if self.tokens >= bytes as f64 {
+            self.tokens -= bytes as f64;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// RTT, loss and send-rate state for one circuit
+#[derive(Debug, Clone)]
+pub struct CongestionControl {
+    config: ThrottleConfig,
+    min_rto: Duration,
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    rto: Duration,
+    rate_bps: f64,
+    loss_rate: f64,
+    last_increase: Instant,
+    last_decrease: Option<Instant>,
+    buckets: Vec<TokenBucket>,
+}
+
+impl CongestionControl {
+    /// Controller starting at the configured initial rate. `min_rto` is the
+    /// configured resend timeout.
+    pub fn new(config: &ThrottleConfig, min_rto: Duration) -> Self {
+        let rate_bps = config.initial_kbps as f64 * 1000.0;
+        let buckets = ThrottleCategory::ALL
+            .iter()
+            .map(|c| TokenBucket::new((rate_bps / 8.0 * c.share() * BURST_SECS).max(MIN_BUCKET_BYTES)))
+            .collect();
+
+        Self {
+            config: config.clone(),
+            min_rto,
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto: min_rto.max(Duration::from_secs(1)).min(Duration::from_millis(config.max_rto_ms)),
+            rate_bps,
+            loss_rate: 0.0,
+            last_increase: Instant::now(),
+            last_decrease: None,
+            buckets,
+        }
+    }
+
+    /// A reliable packet sent at `sent_at` was acknowledged. Resent packets
+    /// are ambiguous and do not produce an RTT sample.
+    pub fn on_ack(&mut self, sent_at: Instant, resent: bool) {
+        if !resent {
+            self.sample_rtt(sent_at.elapsed());
+        }
+        self.loss_rate *= 1.0 - LOSS_WEIGHT;
+
+        // Grow by one step per loss-free round trip
+        if self.last_increase.elapsed() >= self.round_trip() {
+            self.rate_bps = (self.rate_bps + RATE_INCREASE_BPS).min(self.max_bps());
+            self.last_increase = Instant::now();
+        }
+    }
+
+    /// A reliable packet timed out and is being resent
+    pub fn on_loss(&mut self) {
+        self.loss_rate = self.loss_rate * (1.0 - LOSS_WEIGHT) + LOSS_WEIGHT;
+        self.rto = (self.rto * 2).min(Duration::from_millis(self.config.max_rto_ms));
+
+        // Back off at most once per round trip so one burst of losses
+        // does not collapse the rate
+        let round_trip = self.round_trip();
+        if self.last_decrease.is_none_or(|at| at.elapsed() >= round_trip) {
+            self.rate_bps = (self.rate_bps / 2.0).max(self.min_bps());
+            self.last_decrease = Some(Instant::now());
+            self.last_increase = Instant::now();
+        }
+    }
+
+    /// Whether `bytes` of `category` traffic may be sent now. Always true
+    /// when throttling is disabled.
+    pub fn try_send(&mut self, category: ThrottleCategory, bytes: usize) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+        let rate_bytes = self.rate_bps / 8.0 * category.share();
+        self.buckets[category.index()].try_take(bytes, rate_bytes)
+    }
+
+    /// Current retransmission timeout
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    /// Smoothed round-trip time, once measured
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    /// Estimated fraction of reliable packets lost
+    pub fn loss_rate(&self) -> f64 {
+        self.loss_rate
+    }
+
+    /// Current send rate in kilobits per second
+    pub fn rate_kbps(&self) -> f64 {
+        self.rate_bps / 1000.0
+    }
+
+    fn sample_rtt(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                let delta = srtt.abs_diff(rtt);
+                self.rttvar = self.rttvar.mul_f64(0.75) + delta.mul_f64(0.25);
+                self.srtt = Some(srtt.mul_f64(0.875) + rtt.mul_f64(0.125));
+            }
+        }
+
+        let rto = self.srtt.unwrap_or(rtt) + self.rttvar * 4;
+        self.rto = rto.max(self.min_rto).min(Duration::from_millis(self.config.max_rto_ms));
+    }
+
+    fn round_trip(&self) -> Duration {
+        self.srtt.unwrap_or(self.rto)
+    }
+
+    fn min_bps(&self) -> f64 {
+        self.config.min_kbps as f64 * 1000.0
+    }
+
+    fn max_bps(&self) -> f64 {
+        self.config.max_kbps as f64 * 1000.0
+    }
+}
+
+impl Default for CongestionControl {
+    fn default() -> Self {
+        Self::new(&ThrottleConfig::default(), Duration::from_millis(100))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rtt_estimate_sets_rto() {
+        let mut cc = CongestionControl::default();
+        let sent = Instant::now() - Duration::from_millis(200);
+        cc.on_ack(sent, false);
+
+        let srtt = cc.srtt().unwrap();
+        assert!(srtt >= Duration::from_millis(200));
+        assert!(cc.rto() >= srtt * 2);
+
+        // Acks for resent packets are not sampled
+        cc.on_ack(Instant::now() - Duration::from_secs(2), true);
+        assert_eq!(cc.srtt(), Some(srtt));
+    }
+
+    #[test]
+    fn test_loss_halves_rate_once_per_round_trip() {
+        let config = ThrottleConfig::default();
+        let mut cc = CongestionControl::new(&config, Duration::from_millis(100));
+        cc.on_ack(Instant::now() - Duration::from_millis(50), false);
+
+        cc.on_loss();
+        assert_eq!(cc.rate_kbps(), config.initial_kbps as f64 / 2.0);
+        cc.on_loss();
+        cc.on_loss();
+        assert_eq!(cc.rate_kbps(), config.initial_kbps as f64 / 2.0);
+        assert!(cc.loss_rate() > 0.0);
+    }
+
+    #[test]
+    fn test_rate_never_drops_below_floor() {
+        let config = ThrottleConfig {
+            initial_kbps: 100,
+            min_kbps: 80,
+            ..ThrottleConfig::default()
+        };
+        let mut cc = CongestionControl::new(&config, Duration::from_millis(100));
+        cc.on_loss();
+        assert_eq!(cc.rate_kbps(), 80.0);
+    }
+
+    #[test]
+    fn test_buckets_limit_each_category() {
+        let config = ThrottleConfig {
+            initial_kbps: 64,
+            min_kbps: 64,
+            ..ThrottleConfig::default()
+        };
+        let mut cc = CongestionControl::new(&config, Duration::from_millis(100));
+
+        assert!(cc.try_send(ThrottleCategory::Object, 1200));
+        assert!(!cc.try_send(ThrottleCategory::Object, 1200));
+        // Other categories have their own budget
+        assert!(cc.try_send(ThrottleCategory::Texture, 1200));
+
+        let disabled = ThrottleConfig {
+            enabled: false,
+            ..config
+        };
+        let mut cc = CongestionControl::new(&disabled, Duration::from_millis(100));
+        assert!((0..100).all(|_| cc.try_send(ThrottleCategory::Object, 1200)));
+    }
+}
@@ -2,43 +2,79 @@
 //! Authentication and circuit management handler
 
 use crate::NetworkResult;
-use mutsea_core::{UserId, RegionId, Vector3};
+use mutsea_core::{Maturity, UserId, Vector3, config::LLUDPConfig, events::{NetworkEvent, NetworkEventData}};
 use mutsea_protocol::{Packet, constants::packet_types, login::LoginService};
 use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use std::time::Instant;
-use tokio::net::UdpSocket;
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, RwLock};
 use tracing::{info, warn, error};
 
-use super::{CircuitInfo, ClientInfo, ReliablePacketData, ServerStats};
+use super::{check_migration, CircuitInfo, CircuitSocket, ClientInfo, CongestionControl, ServerStats};
+
+/// Capacity of the circuit event channel
+const EVENT_CHANNEL_CAPACITY: usize = 256;
 
 /// Authentication handler for login and logout operations
 #[derive(Clone)]
-pub struct AuthHandler;
+pub struct AuthHandler {
+    /// Estate of the regions served here, for estate ban checks
+    estate_id: u32,
+    /// Circuit migration and agent session events
+    events: broadcast::Sender<NetworkEvent>,
+}
 
 impl AuthHandler {
     pub fn new() -> Self {
-        Self
+        // Regions default to estate 1, see `RegionInfo::new`
+        Self::with_estate(1)
+    }
+
+    /// Enforce estate bans for `estate_id` instead of the default estate
+    pub fn with_estate(estate_id: u32) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self { estate_id, events }
+    }
+
+    /// Subscribe to circuit migration events
+    pub fn subscribe_events(&self) -> broadcast::Receiver<NetworkEvent> {
+        self.events.subscribe()
+    }
+
+    /// Sender of this handler's events, for handlers reporting agent activity
+    pub fn event_sender(&self) -> broadcast::Sender<NetworkEvent> {
+        self.events.clone()
+    }
+
+    pub(crate) fn emit(&self, event_data: NetworkEventData) {
+        // No subscribers is fine; the event is also logged
+        let _ = self.events.send(NetworkEvent {
+            event_id: uuid::Uuid::new_v4(),
+            timestamp: chrono::Utc::now(),
+            event_data,
+        });
     }
 
     /// Handle UseCircuitCode message
     pub async fn handle_use_circuit_code(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
+        config: &LLUDPConfig,
         login_service: &LoginService,
     ) -> NetworkResult<()> {
-        if packet.payload.len() < 52 {
+        // CircuitCode (4) + SessionID (16) + ID (16); the message ID has
+        // already been stripped by Packet::deserialize
+        if packet.payload.len() < 36 {
             warn!("UseCircuitCode packet too short from {}", addr);
             return Ok(());
         }
 
         // Parse UseCircuitCode packet structure
-        let mut offset = 1; // Skip message ID
+        let mut offset = 0;
         
         let circuit_code = u32::from_le_bytes([
             packet.payload[offset], packet.payload[offset + 1], 
@@ -58,6 +94,10 @@ impl AuthHandler {
 
         info!("UseCircuitCode from {}: circuit={}, session={}, agent={}", 
               addr, circuit_code, session_id, agent_id);
+        // The packet span was opened before the circuit existed
+        tracing::Span::current()
+            .record("circuit_code", circuit_code)
+            .record("agent_id", tracing::field::display(agent_id));
 
         // Validate session with login service
         if !login_service.validate_session(&session_id.to_string(), &agent_id) {
@@ -66,9 +106,55 @@ impl AuthHandler {
             return Ok(());
         }
 
+        // Region entry: grid bans issued after login and estate bans
+        if let Some(ban) = login_service.bans().check_region_entry(agent_id, self.estate_id, addr.ip()) {
+            warn!("Refused region entry for banned agent {} from {} ({})", agent_id, addr, ban.id);
+            self.send_logout_response(socket, addr, &ban.message()).await?;
+            return Ok(());
+        }
+
         // Create or update circuit
         let mut circuits_guard = circuits.write().await;
-        
+        let address_in_use = circuits_guard
+            .values()
+            .any(|c| c.address == addr && c.circuit_code != circuit_code);
+
+        if let Some(existing_circuit) = circuits_guard.get_mut(&circuit_code).filter(|c| c.address != addr) {
+            // Known circuit from a new address: the viewer's IP changed
+            let from = existing_circuit.address;
+            if let Err(refusal) = check_migration(
+                existing_circuit, session_id, agent_id, addr, address_in_use, &config.migration,
+            ) {
+                warn!("Refused to move circuit {} from {} to {}: {}",
+                      circuit_code, from, addr, refusal.as_str());
+                self.emit(NetworkEventData::CircuitMigrationRejected {
+                    circuit_code,
+                    agent_id,
+                    from: from.to_string(),
+                    to: addr.to_string(),
+                    reason: refusal.as_str().to_string(),
+                });
+                return Ok(());
+            }
+
+            // The new path has its own RTT and capacity
+            existing_circuit.address = addr;
+            socket.move_usage(from, addr);
+            existing_circuit.last_activity = Instant::now();
+            existing_circuit.last_migration = Some(Instant::now());
+            existing_circuit.congestion = CongestionControl::new(
+                &config.throttle, Duration::from_millis(config.resend_timeout),
+            );
+            info!("Circuit {} for agent {} moved from {} to {}", circuit_code, agent_id, from, addr);
+            self.emit(NetworkEventData::CircuitMigrated {
+                circuit_code,
+                agent_id,
+                from: from.to_string(),
+                to: addr.to_string(),
+            });
+            return Ok(());
+        }
+
         if let Some(existing_circuit) = circuits_guard.get_mut(&circuit_code) {
             // Update existing circuit
             existing_circuit.user_id = Some(agent_id);
@@ -76,7 +162,6 @@ impl AuthHandler {
             existing_circuit.session_id = Some(session_id);
             existing_circuit.authenticated = true;
             existing_circuit.last_activity = Instant::now();
-            existing_circuit.address = addr;
         } else {
             // Create new circuit
             let circuit = CircuitInfo {
@@ -93,18 +178,26 @@ impl AuthHandler {
                 pending_acks: Vec::new(),
                 reliable_packets: HashMap::new(),
                 authenticated: true,
-                region_id: Some(RegionId::new()),
+                region_id: Some(login_service.default_location().region_id),
                 position: Vector3::new(128.0, 128.0, 21.0), // Default spawn position
                 look_at: Vector3::new(1.0, 0.0, 0.0),
                 client_info: None,
+                last_ping_id: 0,
+                last_ping_time: Instant::now(),
+                congestion: CongestionControl::new(&config.throttle, Duration::from_millis(config.resend_timeout)),
+                last_migration: None,
+                frozen: false,
             };
             circuits_guard.insert(circuit_code, circuit);
         }
+        drop(circuits_guard);
 
         info!("Circuit {} authenticated successfully from {}", circuit_code, addr);
+        self.emit(NetworkEventData::AgentLoggedIn { circuit_code, agent_id, session_id });
 
         // Send RegionHandshake to establish the connection
-        self.send_region_handshake(socket, addr, circuit_code).await?;
+        let maturity = login_service.region_maturity(&login_service.default_location().region_id);
+        self.send_region_handshake(socket, addr, circuit_code, maturity).await?;
 
         Ok(())
     }
@@ -113,6 +206,7 @@ impl AuthHandler {
     pub async fn handle_logout_request(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        socket: &CircuitSocket,
         addr: SocketAddr,
     ) -> NetworkResult<()> {
         info!("Logout request from {}", addr);
@@ -128,18 +222,36 @@ impl AuthHandler {
             }
         }
 
-        if let Some(circuit_code) = circuit_to_remove {
-            circuits_guard.remove(&circuit_code);
-            info!("Circuit {} logged out from {}", circuit_code, addr);
+        if let Some(circuit) = circuit_to_remove.and_then(|code| circuits_guard.remove(&code)) {
+            info!("Circuit {} logged out from {}", circuit.circuit_code, addr);
+            self.circuit_closed(&circuit, socket, "logout");
         }
 
         Ok(())
     }
 
+    /// Report the end of an agent's session on a removed circuit, with
+    /// the bandwidth it used
+    pub fn circuit_closed(&self, circuit: &CircuitInfo, socket: &CircuitSocket, reason: &str) {
+        let usage = socket.take_usage(circuit.address);
+        if let Some(agent_id) = circuit.agent_id {
+            self.emit(NetworkEventData::AgentLoggedOut {
+                circuit_code: circuit.circuit_code,
+                agent_id,
+                reason: reason.to_string(),
+                sent_by_class: usage.sent_by_class().into_iter().collect(),
+                received_by_class: usage.received_by_class().into_iter().collect(),
+                region_id: circuit.region_id,
+                position: circuit.position,
+                look_at: circuit.look_at,
+            });
+        }
+    }
+
     /// Send logout response
     async fn send_logout_response(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         reason: &str,
     ) -> NetworkResult<()> {
@@ -162,16 +274,17 @@ impl AuthHandler {
     /// Send region handshake to establish connection
     async fn send_region_handshake(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         circuit_code: u32,
+        maturity: Maturity,
     ) -> NetworkResult<()> {
         let mut payload = Vec::new();
         payload.push(packet_types::REGION_HANDSHAKE);
 
         // RegionInfo block
         payload.extend_from_slice(&128u32.to_le_bytes()); // RegionFlags
-        payload.extend_from_slice(&1u8.to_le_bytes());    // SimAccess
+        payload.push(maturity.sim_access());              // SimAccess
         
         // Region name (variable string)
         let region_name = "Mutsea Region".as_bytes();
@@ -317,22 +430,18 @@ impl AuthHandler {
         Ok(())
     }
 
-    /// Send EnableSimulator message to client
+    /// Send EnableSimulator message to client. The message has a 4-byte
+    /// address field, so the simulator must be reachable over IPv4 (or an
+    /// IPv4-mapped IPv6 address).
     pub async fn send_enable_simulator(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         region_handle: u64,
-        sim_ip: std::net::Ipv4Addr,
+        sim_ip: IpAddr,
         sim_port: u16,
     ) -> NetworkResult<()> {
-        let mut payload = Vec::new();
-        payload.push(packet_types::ENABLE_SIMULATOR);
-
-        // SimulatorInfo block
-        payload.extend_from_slice(&region_handle.to_le_bytes());
-        payload.extend_from_slice(&sim_ip.octets());
-        payload.extend_from_slice(&sim_port.to_le_bytes());
+        let payload = Self::enable_simulator_payload(region_handle, sim_ip, sim_port)?;
 
         let packet = Packet::reliable(1, payload);
         let packet_data = packet.serialize()
@@ -343,10 +452,25 @@ impl AuthHandler {
         Ok(())
     }
 
+    fn enable_simulator_payload(region_handle: u64, sim_ip: IpAddr, sim_port: u16) -> NetworkResult<Vec<u8>> {
+        let sim_ipv4 = mutsea_core::net::as_ipv4(sim_ip).ok_or_else(|| {
+            crate::NetworkError::Protocol(format!("EnableSimulator cannot carry IPv6 address {}", sim_ip))
+        })?;
+
+        let mut payload = Vec::new();
+        payload.push(packet_types::ENABLE_SIMULATOR as u8);
+
+        // SimulatorInfo block; IPADDR and IPPORT are in network byte order
+        payload.extend_from_slice(&region_handle.to_le_bytes());
+        payload.extend_from_slice(&sim_ipv4.octets());
+        payload.extend_from_slice(&sim_port.to_be_bytes());
+        Ok(payload)
+    }
+
     /// Send EstablishAgentCommunication message
     pub async fn send_establish_agent_communication(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         agent_id: UserId,
         session_id: uuid::Uuid,
@@ -378,4 +502,20 @@ impl Default for AuthHandler {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enable_simulator_address_encoding() {
+        let payload = AuthHandler::enable_simulator_payload(1, "10.1.2.3".parse().unwrap(), 9000).unwrap();
+        assert_eq!(&payload[9..13], &[10, 1, 2, 3]);
+        assert_eq!(&payload[13..15], &9000u16.to_be_bytes());
+
+        let mapped = AuthHandler::enable_simulator_payload(1, "::ffff:10.1.2.3".parse().unwrap(), 9000).unwrap();
+        assert_eq!(mapped, payload);
+
+        assert!(AuthHandler::enable_simulator_payload(1, "2001:db8::1".parse().unwrap(), 9000).is_err());
+    }
+}
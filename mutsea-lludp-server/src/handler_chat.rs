@@ -2,32 +2,40 @@
 //! Chat and communication handler
 
 use crate::NetworkResult;
-use mutsea_core::{Vector3, UserId};
+use mutsea_core::{Vector3, UserId, events::{NetworkEvent, NetworkEventData}};
 use mutsea_protocol::{Packet, constants::packet_types};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::time::Instant;
-use tokio::net::UdpSocket;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use std::sync::Arc;
 use tracing::{debug, warn, info};
 
-use super::{CircuitInfo, ServerStats};
+use super::{CircuitInfo, CircuitSocket, ServerStats};
 
 /// Chat handler for communication between agents
 #[derive(Clone)]
-pub struct ChatHandler;
+pub struct ChatHandler {
+    /// Where chat activity is reported, for session tracking
+    events: Option<broadcast::Sender<NetworkEvent>>,
+}
 
 impl ChatHandler {
     pub fn new() -> Self {
-        Self
+        Self { events: None }
+    }
+
+    /// Report chat from agents as [`NetworkEventData::AgentChatted`] events
+    pub fn with_events(mut self, events: broadcast::Sender<NetworkEvent>) -> Self {
+        self.events = Some(events);
+        self
     }
 
     /// Handle ChatFromViewer message
     pub async fn handle_chat_from_viewer(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -54,11 +62,25 @@ impl ChatHandler {
         
         // Update last activity
         let mut circuits_guard = circuits.write().await;
-        if let Some(circuit) = circuits_guard.get_mut(&circuit_code) {
+        let agent_id = circuits_guard.get_mut(&circuit_code).and_then(|circuit| {
             circuit.last_activity = Instant::now();
-        }
+            circuit.agent_id
+        });
         drop(circuits_guard);
 
+        if let (Some(events), Some(agent_id)) = (&self.events, agent_id) {
+            let _ = events.send(NetworkEvent {
+                event_id: uuid::Uuid::new_v4(),
+                timestamp: chrono::Utc::now(),
+                event_data: NetworkEventData::AgentChatted {
+                    circuit_code,
+                    agent_id,
+                    channel: chat_data.channel,
+                    message: chat_data.message.clone(),
+                },
+            });
+        }
+
         info!("Chat from circuit {}: {} says: '{}'", 
               circuit_code, chat_data.from_name, chat_data.message);
 
@@ -115,7 +137,7 @@ impl ChatHandler {
     pub async fn broadcast_chat_message(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         source_circuit: u32,
         chat_data: &ChatMessageData,
     ) -> NetworkResult<usize> {
@@ -126,6 +148,7 @@ impl ChatHandler {
         };
         
         let source_position = source.position;
+        let source_agent = source.agent_id.unwrap_or_default();
         let source_name = format!("Agent {}", source_circuit); // Would use actual name
         let chat_range = self.get_chat_range(chat_data.chat_type);
         
@@ -150,7 +173,7 @@ impl ChatHandler {
             &chat_data.message,
             chat_data.chat_type,
             source_position,
-            source.agent_id.unwrap_or_default(),
+            source_agent,
         )?;
         
         let packet_data = chat_packet.serialize()
@@ -227,7 +250,6 @@ impl ChatHandler {
     pub async fn handle_instant_message(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -266,7 +288,6 @@ impl ChatHandler {
     pub async fn handle_script_dialog(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -298,7 +319,7 @@ impl ChatHandler {
     /// Send system message to specific circuit
     pub async fn send_system_message(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         target_address: SocketAddr,
         message: &str,
         stats: &Arc<RwLock<ServerStats>>,
@@ -329,7 +350,7 @@ impl ChatHandler {
     pub async fn broadcast_system_announcement(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         message: &str,
         stats: &Arc<RwLock<ServerStats>>,
     ) -> NetworkResult<usize> {
@@ -14,6 +14,10 @@ use tracing::{info, debug, warn};
 
 use super::{CircuitInfo, ServerStats};
 
+/// AgentUpdate body size: AgentID, SessionID, two packed rotations, State,
+/// four camera vectors, Far, ControlFlags and Flags
+const AGENT_UPDATE_SIZE: usize = 114;
+
 /// Core movement handler for basic agent updates
 #[derive(Clone)]
 pub struct MovementHandler;
@@ -30,7 +34,7 @@ impl MovementHandler {
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
-        if packet.payload.len() < 65 { // Minimum size for AgentUpdate
+        if packet.payload.len() < AGENT_UPDATE_SIZE {
             warn!("AgentUpdate packet too short from {}", addr);
             return Ok(());
         }
@@ -54,9 +58,13 @@ impl MovementHandler {
         // Update circuit with movement data
         let mut circuits_guard = circuits.write().await;
         if let Some(circuit) = circuits_guard.get_mut(&circuit_code) {
+            circuit.last_activity = Instant::now();
+            if circuit.frozen {
+                debug!("Ignoring movement from frozen circuit {}", circuit_code);
+                return Ok(());
+            }
             circuit.position = movement_data.camera_center; // Use camera center as agent position
             circuit.look_at = movement_data.camera_at;
-            circuit.last_activity = Instant::now();
 
             debug!("Agent update for circuit {}: pos=({:.1}, {:.1}, {:.1}) flags=0x{:08X}", 
                    circuit_code, circuit.position.x, circuit.position.y, circuit.position.z, 
@@ -67,82 +75,56 @@ impl MovementHandler {
     }
 
     /// Parse AgentUpdate packet data
+    ///
+    /// The message ID has already been stripped by `Packet::deserialize`.
+    /// Rotations are packed quaternions (x, y, z) with w reconstructed.
     fn parse_agent_update_packet(&self, payload: &[u8]) -> NetworkResult<AgentUpdateData> {
-        let mut offset = 1; // Skip message ID
+        if payload.len() < AGENT_UPDATE_SIZE {
+            return Err(crate::NetworkError::InvalidPacket(format!(
+                "AgentUpdate is {} bytes, expected {}", payload.len(), AGENT_UPDATE_SIZE
+            )));
+        }
 
-        // AgentData block
-        let _agent_id_bytes = &payload[offset..offset + 16];
-        offset += 16;
-        
-        let _session_id_bytes = &payload[offset..offset + 16];
-        offset += 16;
-
-        // Body rotation (quaternion)
-        let body_rotation = Quaternion::new(
-            f32::from_le_bytes([payload[offset], payload[offset + 1], 
-                               payload[offset + 2], payload[offset + 3]]),
-            f32::from_le_bytes([payload[offset + 4], payload[offset + 5], 
-                               payload[offset + 6], payload[offset + 7]]),
-            f32::from_le_bytes([payload[offset + 8], payload[offset + 9], 
-                               payload[offset + 10], payload[offset + 11]]),
-            f32::from_le_bytes([payload[offset + 12], payload[offset + 13], 
-                               payload[offset + 14], payload[offset + 15]]),
+        let read_f32 = |offset: usize| f32::from_le_bytes([
+            payload[offset], payload[offset + 1], payload[offset + 2], payload[offset + 3]
+        ]);
+        let read_vector = |offset: usize| Vector3::new(
+            read_f32(offset), read_f32(offset + 4), read_f32(offset + 8)
         );
-        offset += 16;
-
-        // Head rotation (quaternion)
-        let head_rotation = Quaternion::new(
-            f32::from_le_bytes([payload[offset], payload[offset + 1], 
-                               payload[offset + 2], payload[offset + 3]]),
-            f32::from_le_bytes([payload[offset + 4], payload[offset + 5], 
-                               payload[offset + 6], payload[offset + 7]]),
-            f32::from_le_bytes([payload[offset + 8], payload[offset + 9], 
-                               payload[offset + 10], payload[offset + 11]]),
-            f32::from_le_bytes([payload[offset + 12], payload[offset + 13], 
-                               payload[offset + 14], payload[offset + 15]]),
-        );
-        offset += 16;
+        let read_rotation = |offset: usize| {
+            let (x, y, z) = (read_f32(offset), read_f32(offset + 4), read_f32(offset + 8));
+            let w = (1.0 - (x * x + y * y + z * z)).max(0.0).sqrt();
+            Quaternion::new(x, y, z, w)
+        };
+
+        // AgentData block: AgentID (16) + SessionID (16)
+        let mut offset = 32;
+
+        let body_rotation = read_rotation(offset);
+        offset += 12;
+
+        let head_rotation = read_rotation(offset);
+        offset += 12;
 
-        // State
         let state = payload[offset];
         offset += 1;
 
-        // Camera center (vector3)
-        let camera_center = Vector3::new(
-            f32::from_le_bytes([payload[offset], payload[offset + 1], 
-                               payload[offset + 2], payload[offset + 3]]),
-            f32::from_le_bytes([payload[offset + 4], payload[offset + 5], 
-                               payload[offset + 6], payload[offset + 7]]),
-            f32::from_le_bytes([payload[offset + 8], payload[offset + 9], 
-                               payload[offset + 10], payload[offset + 11]]),
-        );
+        let camera_center = read_vector(offset);
         offset += 12;
 
-        // Camera at axis (vector3)
-        let camera_at = Vector3::new(
-            f32::from_le_bytes([payload[offset], payload[offset + 1], 
-                               payload[offset + 2], payload[offset + 3]]),
-            f32::from_le_bytes([payload[offset + 4], payload[offset + 5], 
-                               payload[offset + 6], payload[offset + 7]]),
-            f32::from_le_bytes([payload[offset + 8], payload[offset + 9], 
-                               payload[offset + 10], payload[offset + 11]]),
-        );
+        let camera_at = read_vector(offset);
         offset += 12;
 
         // Skip camera left and up axis for now
         offset += 24;
 
-        // Far (f32)
-        let far = f32::from_le_bytes([payload[offset], payload[offset + 1], 
-                                     payload[offset + 2], payload[offset + 3]]);
+        let far = read_f32(offset);
         offset += 4;
 
-        // Control flags
         let control_flags = u32::from_le_bytes([payload[offset], payload[offset + 1], 
                                               payload[offset + 2], payload[offset + 3]]);
         offset += 4;
 
-        // Flags
         let flags = payload[offset];
 
         Ok(AgentUpdateData {
@@ -7,12 +7,11 @@ use mutsea_protocol::{Packet, constants::packet_types};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::time::Instant;
-use tokio::net::UdpSocket;
 use tokio::sync::RwLock;
 use std::sync::Arc;
 use tracing::{debug, warn, info};
 
-use super::{CircuitInfo, ServerStats};
+use super::{CircuitInfo, CircuitSocket, OutboundPacket, PriorityClass, ServerStats, ThrottleCategory};
 
 /// Object handler for managing scene objects and primitives
 #[derive(Clone)]
@@ -103,7 +102,7 @@ impl ObjectHandler {
     pub async fn handle_object_select(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -188,7 +187,7 @@ impl ObjectHandler {
     /// Send object properties response
     async fn send_object_properties(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         object_id: ObjectId,
         local_id: u32,
@@ -406,13 +405,13 @@ impl ObjectHandler {
     pub async fn send_object_update(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         object: &SceneObjectInfo,
         update_type: ObjectUpdateType,
         range: f32,
         stats: &Arc<RwLock<ServerStats>>,
     ) -> NetworkResult<usize> {
-        let circuits_guard = circuits.read().await;
+        let mut circuits_guard = circuits.write().await;
         let mut broadcast_count = 0;
 
         // Create object update packet
@@ -422,13 +421,23 @@ impl ObjectHandler {
             ObjectUpdateType::Compressed => self.create_compressed_object_update(object)?,
             ObjectUpdateType::Cached => self.create_cached_object_update(object)?,
         };
+        // A newer terse update replaces one still queued for the same prim
+        let mut outbound = OutboundPacket::new(packet_data.clone(), PriorityClass::Object);
+        if matches!(update_type, ObjectUpdateType::Terse) {
+            outbound = outbound.superseding(object.local_id);
+        }
 
-        // Send to nearby circuits
-        for circuit in circuits_guard.values() {
+        // Send to nearby circuits that have room in their object budget;
+        // a throttled client simply gets the next update instead
+        for circuit in circuits_guard.values_mut() {
             if circuit.authenticated {
                 let distance = (circuit.position - object.position).length();
                 if distance <= range {
-                    if let Err(e) = socket.send_to(&packet_data, circuit.address).await {
+                    if !circuit.congestion.try_send(ThrottleCategory::Object, packet_data.len()) {
+                        debug!("Object update for {} throttled on circuit {}", object.object_id, circuit.circuit_code);
+                        continue;
+                    }
+                    if let Err(e) = socket.send_class(outbound.clone(), circuit.address).await {
                         warn!("Failed to send object update to circuit {}: {}", 
                               circuit.circuit_code, e);
                     } else {
@@ -601,7 +610,7 @@ impl ObjectHandler {
     pub async fn kill_object(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         object_id: ObjectId,
         local_id: u32,
         stats: &Arc<RwLock<ServerStats>>,
@@ -3,18 +3,38 @@
 
 use crate::NetworkResult;
 use mutsea_core::config::LLUDPConfig;
-use mutsea_protocol::{Packet, constants::packet_types, login::LoginService};
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::Vector3;
+use mutsea_protocol::{Packet, constants::packet_types, dialog::ScriptDialogReply, login::LoginService};
+use mutsea_protocol::attachment::{DetachAttachmentIntoInv, ObjectAttach, ObjectDetach, RezSingleAttachmentFromInv};
+use mutsea_protocol::economy::{MoneyBalanceRequest, MoneyTransferRequest, ObjectBuy, ObjectSaleInfo};
+use mutsea_protocol::land::{self, ParcelBuy, ParcelReturnObjects, ParcelSaleUpdate, ParcelSetOtherCleanTime};
+use mutsea_protocol::moderation::{
+    self, EstateOwnerMessage, FreezeUser, GodKickUser, KickAction, RequestGodlikePowers, UserReport,
+};
+use mutsea_protocol::rez::{DeRezObject, RezObject};
+use mutsea_protocol::profile::{
+    self, AvatarPropertiesRequest, AvatarPropertiesUpdate, CreateLandmarkForEvent, GenericMessage, PickDelete, PickInfoUpdate,
+};
+use mutsea_protocol::directory::{
+    self, ClassifiedDelete, ClassifiedInfoRequest, ClassifiedInfoUpdate, DirClassifiedQuery, DirFindQuery, EventInfoRequest,
+};
+use mutsea_protocol::login::SetStartLocationRequest;
+use mutsea_protocol::names::AvatarPickerRequest;
+use mutsea_protocol::object_update::RequestMultipleObjects;
+use mutsea_protocol::search::DirPlacesQuery;
+use mutsea_protocol::sound::SoundTrigger;
+use mutsea_protocol::terraform::{parse_undo_land, ModifyLand};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::UdpSocket;
 use tokio::sync::RwLock;
-use tracing::{debug, error, warn};
+use tracing::{debug, info, warn};
 
 use super::{
-    CircuitInfo, ServerStats, AuthHandler, MovementHandler, 
+    CircuitInfo, CircuitSocket, ServerStats, AuthHandler, MovementHandler, 
     ChatHandler, PingHandler, RegionHandler, ObjectHandler,
-    AnimationHandler, TeleportHandler
+    AnimationHandler, TeleportHandler, OutboundPacket, PriorityClass, MessageHandler
 };
 
 /// Main packet handler that routes packets to specialized handlers
@@ -28,27 +48,47 @@ pub struct PacketHandler {
     object_handler: ObjectHandler,
     animation_handler: AnimationHandler,
     teleport_handler: TeleportHandler,
+    /// Handlers of messages added from outside, such as by plugins
+    extensions: Arc<std::sync::RwLock<HashMap<u32, Arc<dyn MessageHandler>>>>,
 }
 
 impl PacketHandler {
     pub fn new() -> Self {
+        let auth_handler = AuthHandler::new();
         Self {
-            auth_handler: AuthHandler::new(),
+            chat_handler: ChatHandler::new().with_events(auth_handler.event_sender()),
+            auth_handler,
             movement_handler: MovementHandler::new(),
-            chat_handler: ChatHandler::new(),
             ping_handler: PingHandler::new(),
             region_handler: RegionHandler::new(),
             object_handler: ObjectHandler::new(),
             animation_handler: AnimationHandler::new(),
             teleport_handler: TeleportHandler::new(),
+            extensions: Arc::new(std::sync::RwLock::new(HashMap::new())),
         }
     }
 
+    /// Subscribe to circuit migration and agent session events
+    pub fn subscribe_events(&self) -> tokio::sync::broadcast::Receiver<mutsea_core::events::NetworkEvent> {
+        self.auth_handler.subscribe_events()
+    }
+
+    /// Pass messages with `message_id` that aren't handled here to
+    /// `handler`, replacing any handler added before
+    pub fn add_message_handler(&self, message_id: u32, handler: Arc<dyn MessageHandler>) {
+        self.extensions.write().unwrap().insert(message_id, handler);
+    }
+
+    /// Report the end of an agent's session on a removed circuit
+    pub fn circuit_closed(&self, circuit: &CircuitInfo, socket: &CircuitSocket, reason: &str) {
+        self.auth_handler.circuit_closed(circuit, socket, reason);
+    }
+
     /// Main packet handling dispatch
     pub async fn handle_packet(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         data: &[u8],
         config: &LLUDPConfig,
@@ -66,12 +106,22 @@ impl PacketHandler {
 
         debug!("Received packet from {}: seq={}, size={}, reliable={}", 
                addr, packet.header.sequence, data.len(), packet.header.is_reliable());
+        socket.record_received(addr, PriorityClass::of_inbound(packet.message_id), data.len());
+
+        // Acknowledge reliable packets before dispatching so a handler error
+        // does not make the viewer resend
+        if packet.header.is_reliable() {
+            self.send_ack(socket, addr, packet.header.sequence).await?;
+        }
 
-        // Update stats
-        {
-            let mut stats_guard = stats.write().await;
-            stats_guard.packets_received += 1;
-            stats_guard.bytes_received += data.len() as u64;
+        // Acks piggybacked on any packet feed RTT and loss estimation
+        if !packet.appended_acks.is_empty() {
+            let mut circuits_guard = circuits.write().await;
+            if let Some(circuit) = circuits_guard.values_mut().find(|c| c.address == addr) {
+                for ack in &packet.appended_acks {
+                    circuit.acknowledge(*ack);
+                }
+            }
         }
 
         // Handle packet based on type
@@ -83,18 +133,26 @@ impl PacketHandler {
         } else {
             // Handle raw packet
             self.handle_raw_packet(
-                circuits, socket, addr, &packet, config, stats
+                circuits, addr, &packet, config, stats
             ).await?;
         }
 
         Ok(())
     }
 
+    /// Send a PacketAck for a single reliable sequence number
+    async fn send_ack(&self, socket: &CircuitSocket, addr: SocketAddr, sequence: u32) -> NetworkResult<()> {
+        let packet_data = Packet::ack(vec![sequence]).serialize()
+            .map_err(|e| crate::NetworkError::Protocol(format!("Failed to serialize PacketAck: {}", e)))?;
+        socket.send_class(OutboundPacket::new(packet_data, PriorityClass::Ack), addr).await?;
+        Ok(())
+    }
+
     /// Handle message packet with ID
     async fn handle_message_packet(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
         message_id: u32,
@@ -106,11 +164,19 @@ impl PacketHandler {
             // Authentication messages
             packet_types::USE_CIRCUIT_CODE => {
                 self.auth_handler.handle_use_circuit_code(
-                    circuits, socket, addr, packet, login_service
+                    circuits, socket, addr, packet, config, login_service
                 ).await?;
             }
             packet_types::LOGOUT_REQUEST => {
-                self.auth_handler.handle_logout_request(circuits, addr).await?;
+                self.auth_handler.handle_logout_request(circuits, socket, addr).await?;
+            }
+
+            // Ping messages
+            id if id == packet_types::START_PING_CHECK as u32 => {
+                self.ping_handler.handle_ping_check(socket, addr, packet).await?;
+            }
+            id if id == packet_types::COMPLETE_PING_CHECK as u32 => {
+                self.ping_handler.handle_ping_response(circuits, addr, packet).await?;
             }
 
             // Movement messages
@@ -138,12 +204,12 @@ impl PacketHandler {
             // Teleport messages
             packet_types::TELEPORT_REQUEST => {
                 self.teleport_handler.handle_teleport_request(
-                    circuits, socket, addr, packet
+                    circuits, socket, addr, packet, login_service
                 ).await?;
             }
             packet_types::TELEPORT_LOCAL => {
                 self.teleport_handler.handle_teleport_local(
-                    circuits, socket, addr, packet
+                    circuits, addr, packet
                 ).await?;
             }
 
@@ -160,6 +226,55 @@ impl PacketHandler {
             packet_types::OBJECT_DROP => {
                 self.handle_object_drop(circuits, addr, packet).await?;
             }
+            packet_types::OBJECT_SALE_INFO => {
+                self.handle_object_sale_info(circuits, addr, packet).await?;
+            }
+            packet_types::OBJECT_BUY => {
+                self.handle_object_buy(circuits, addr, packet).await?;
+            }
+            packet_types::MODIFY_LAND => {
+                self.handle_modify_land(circuits, addr, packet).await?;
+            }
+            packet_types::UNDO_LAND => {
+                self.handle_undo_land(circuits, addr, packet).await?;
+            }
+            packet_types::PARCEL_PROPERTIES_UPDATE => {
+                self.handle_parcel_properties_update(circuits, addr, packet).await?;
+            }
+            packet_types::PARCEL_BUY => {
+                self.handle_parcel_buy(circuits, addr, packet).await?;
+            }
+            packet_types::PARCEL_RETURN_OBJECTS => {
+                self.handle_parcel_return_objects(circuits, addr, packet).await?;
+            }
+            packet_types::PARCEL_SET_OTHER_CLEAN_TIME => {
+                self.handle_parcel_set_other_clean_time(circuits, addr, packet).await?;
+            }
+            packet_types::REQUEST_MULTIPLE_OBJECTS => {
+                self.handle_request_multiple_objects(circuits, addr, packet).await?;
+            }
+
+            // Attachment messages
+            packet_types::REZ_SINGLE_ATTACHMENT_FROM_INV => {
+                self.handle_rez_single_attachment(circuits, addr, packet).await?;
+            }
+            packet_types::OBJECT_ATTACH => {
+                self.handle_object_attach(circuits, addr, packet).await?;
+            }
+            packet_types::OBJECT_DETACH => {
+                self.handle_object_detach(circuits, addr, packet).await?;
+            }
+            packet_types::DETACH_ATTACHMENT_INTO_INV => {
+                self.handle_detach_attachment_into_inv(circuits, addr, packet).await?;
+            }
+
+            // Taking prims into inventory and rezzing them
+            packet_types::DEREZ_OBJECT => {
+                self.handle_derez_object(circuits, addr, packet).await?;
+            }
+            packet_types::REZ_OBJECT => {
+                self.handle_rez_object(circuits, addr, packet).await?;
+            }
 
             // Animation messages
             packet_types::AGENT_ANIMATION => {
@@ -181,17 +296,20 @@ impl PacketHandler {
 
             // Money/Economy messages
             packet_types::MONEY_BALANCE_REQUEST => {
-                self.handle_money_balance_request(circuits, socket, addr, packet).await?;
+                self.handle_money_balance_request(circuits, addr, packet).await?;
+            }
+            packet_types::MONEY_TRANSFER_REQUEST => {
+                self.handle_money_transfer_request(circuits, addr, packet).await?;
             }
 
             // Group messages
             packet_types::GROUP_MEMBERSHIP_DATA => {
-                self.handle_group_membership_data(circuits, socket, addr, packet).await?;
+                self.handle_group_membership_data(circuits, addr, packet).await?;
             }
 
             // Parcel messages
             packet_types::PARCEL_INFO_REQUEST => {
-                self.handle_parcel_info_request(circuits, socket, addr, packet).await?;
+                self.handle_parcel_info_request(circuits, addr, packet).await?;
             }
 
             // Map messages
@@ -204,12 +322,104 @@ impl PacketHandler {
                 self.handle_script_dialog_reply(circuits, addr, packet).await?;
             }
 
+            // Moderation messages
+            packet_types::ESTATE_OWNER_MESSAGE => {
+                self.handle_estate_owner_message(circuits, addr, packet, false).await?;
+            }
+            packet_types::GODLIKE_MESSAGE => {
+                self.handle_estate_owner_message(circuits, addr, packet, true).await?;
+            }
+            packet_types::FREEZE_USER => {
+                self.handle_freeze_user(circuits, addr, packet).await?;
+            }
+            packet_types::GOD_KICK_USER => {
+                self.handle_god_kick_user(circuits, addr, packet).await?;
+            }
+            packet_types::REQUEST_GODLIKE_POWERS => {
+                self.handle_request_godlike_powers(circuits, addr, packet).await?;
+            }
+            packet_types::USER_REPORT => {
+                self.handle_user_report(circuits, addr, packet).await?;
+            }
+
+            // Search messages
+            packet_types::DIR_PLACES_QUERY => {
+                self.handle_dir_places_query(circuits, addr, packet).await?;
+            }
+
+            packet_types::DIR_CLASSIFIED_QUERY => {
+                self.handle_dir_classified_query(circuits, addr, packet).await?;
+            }
+            packet_types::DIR_FIND_QUERY => {
+                self.handle_dir_find_query(circuits, addr, packet).await?;
+            }
+            packet_types::EVENT_INFO_REQUEST => {
+                self.handle_event_info_request(circuits, addr, packet).await?;
+            }
+
+            // Classified messages
+            packet_types::CLASSIFIED_INFO_REQUEST => {
+                self.handle_classified_info_request(circuits, addr, packet).await?;
+            }
+            packet_types::CLASSIFIED_INFO_UPDATE => {
+                self.handle_classified_info_update(circuits, addr, packet).await?;
+            }
+            packet_types::CLASSIFIED_DELETE => {
+                self.handle_classified_delete(circuits, addr, packet).await?;
+            }
+
+            // Home location
+            packet_types::SET_START_LOCATION_REQUEST => {
+                self.handle_set_start_location_request(circuits, addr, packet).await?;
+            }
+
+            // Sound messages
+            packet_types::SOUND_TRIGGER => {
+                self.handle_sound_trigger(circuits, addr, packet).await?;
+            }
+
+            // Name messages
+            packet_types::AVATAR_PICKER_REQUEST => {
+                self.handle_avatar_picker_request(circuits, addr, packet).await?;
+            }
+
+            // Profile messages
+            packet_types::AVATAR_PROPERTIES_REQUEST => {
+                self.handle_avatar_properties_request(circuits, addr, packet).await?;
+            }
+            packet_types::AVATAR_PROPERTIES_UPDATE => {
+                self.handle_avatar_properties_update(circuits, addr, packet).await?;
+            }
+            packet_types::GENERIC_MESSAGE => {
+                self.handle_generic_message(circuits, addr, packet).await?;
+            }
+            packet_types::PICK_INFO_UPDATE => {
+                self.handle_pick_info_update(circuits, addr, packet).await?;
+            }
+            packet_types::PICK_DELETE => {
+                self.handle_pick_delete(circuits, addr, packet).await?;
+            }
+            packet_types::CREATE_LANDMARK_FOR_EVENT => {
+                self.handle_create_landmark(circuits, addr, packet).await?;
+            }
+
             // Voice messages
             packet_types::PROVISION_VOICE_ACCOUNT_REQUEST => {
                 self.handle_provision_voice_account(circuits, socket, addr, packet).await?;
             }
 
             _ => {
+                let extension = self.extensions.read().unwrap().get(&message_id).cloned();
+                if let Some(handler) = extension {
+                    let circuit = circuits.read().await.values()
+                        .find(|c| c.address == addr && c.authenticated)
+                        .and_then(|c| Some((c.circuit_code, c.agent_id?)));
+                    match circuit {
+                        Some((circuit_code, agent_id)) => handler.handle(circuit_code, agent_id, &packet.payload),
+                        None => debug!("Dropped message 0x{:08X} from unauthenticated {}", message_id, addr),
+                    }
+                    return Ok(());
+                }
                 debug!("Unhandled message type: 0x{:08X} from {}", message_id, addr);
                 // Update stats for unhandled packets
                 let mut stats_guard = stats.write().await;
@@ -224,7 +434,6 @@ impl PacketHandler {
     async fn handle_raw_packet(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
         addr: SocketAddr,
         packet: &Packet,
         config: &LLUDPConfig,
@@ -237,12 +446,6 @@ impl PacketHandler {
         let message_type = packet.payload[0];
         
         match message_type {
-            packet_types::START_PING_CHECK => {
-                self.ping_handler.handle_ping_check(socket, addr, packet).await?;
-            }
-            packet_types::COMPLETE_PING_CHECK => {
-                self.ping_handler.handle_ping_response(circuits, addr, packet).await?;
-            }
             packet_types::PACKET_ACK => {
                 self.ping_handler.handle_packet_ack(circuits, addr, packet).await?;
             }
@@ -258,7 +461,7 @@ impl PacketHandler {
     async fn handle_complete_agent_movement(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -292,15 +495,15 @@ impl PacketHandler {
     /// Send AgentMovementComplete response
     async fn send_agent_movement_complete(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
     ) -> NetworkResult<()> {
         let mut payload = Vec::new();
         payload.push(packet_types::AGENT_MOVEMENT_COMPLETE as u8);
 
         // AgentData block
-        payload.extend_from_slice(&mutsea_core::UserId::new().as_uuid().as_bytes());
-        payload.extend_from_slice(&uuid::Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(mutsea_core::UserId::new().as_uuid().as_bytes());
+        payload.extend_from_slice(uuid::Uuid::new_v4().as_bytes());
 
         // Data block
         payload.extend_from_slice(&0u64.to_le_bytes()); // RegionHandle
@@ -386,7 +589,7 @@ impl PacketHandler {
     async fn handle_request_image(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -408,7 +611,7 @@ impl PacketHandler {
     async fn handle_transfer_request(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -419,10 +622,10 @@ impl PacketHandler {
         payload.push(packet_types::TRANSFER_INFO as u8);
 
         // TransferInfo block
-        payload.extend_from_slice(&uuid::Uuid::new_v4().as_bytes()); // TransferID
+        payload.extend_from_slice(uuid::Uuid::new_v4().as_bytes()); // TransferID
         payload.extend_from_slice(&2u32.to_le_bytes()); // ChannelType (Asset)
         payload.extend_from_slice(&(-1i32).to_le_bytes()); // Status (not found)
-        payload.extend_from_slice(&uuid::Uuid::new_v4().as_bytes()); // TargetID
+        payload.extend_from_slice(uuid::Uuid::new_v4().as_bytes()); // TargetID
         payload.extend_from_slice(&0u32.to_le_bytes()); // Size
         payload.extend_from_slice(&vec![0u8; 0]); // Params
 
@@ -438,7 +641,7 @@ impl PacketHandler {
     async fn handle_fetch_inventory(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -449,11 +652,11 @@ impl PacketHandler {
         payload.push(packet_types::INVENTORY_DESCENDENTS as u8);
 
         // AgentData block
-        payload.extend_from_slice(&mutsea_core::UserId::new().as_uuid().as_bytes());
+        payload.extend_from_slice(mutsea_core::UserId::new().as_uuid().as_bytes());
 
         // InventoryData block (empty)
-        payload.extend_from_slice(&uuid::Uuid::new_v4().as_bytes()); // FolderID
-        payload.extend_from_slice(&mutsea_core::UserId::new().as_uuid().as_bytes()); // OwnerID
+        payload.extend_from_slice(uuid::Uuid::new_v4().as_bytes()); // FolderID
+        payload.extend_from_slice(mutsea_core::UserId::new().as_uuid().as_bytes()); // OwnerID
         payload.extend_from_slice(&0u32.to_le_bytes()); // Version
         payload.extend_from_slice(&0u32.to_le_bytes()); // Descendents
 
@@ -465,38 +668,68 @@ impl PacketHandler {
         Ok(())
     }
 
-    /// Handle money balance request
+    /// Handle money balance request, reported as a
+    /// [`NetworkEventData::MoneyBalanceRequested`] event; the economy
+    /// service answers
     async fn handle_money_balance_request(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
-        debug!("MoneyBalanceRequest from {}", addr);
+        let request = match MoneyBalanceRequest::parse(&packet.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid MoneyBalanceRequest from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, request.agent_id).await else {
+            warn!("MoneyBalanceRequest from {} names another agent", addr);
+            return Ok(());
+        };
 
-        // Send money balance reply with 1000 credits
-        let mut payload = Vec::new();
-        payload.push(packet_types::MONEY_BALANCE_REPLY as u8);
-
-        // MoneyData block
-        payload.extend_from_slice(&mutsea_core::UserId::new().as_uuid().as_bytes()); // AgentID
-        payload.extend_from_slice(&uuid::Uuid::new_v4().as_bytes()); // TransactionID
-        payload.push(1u8); // TransactionSuccess
-        payload.extend_from_slice(&1000i32.to_le_bytes()); // MoneyBalance
-        payload.extend_from_slice(&0i32.to_le_bytes()); // SquareMetersCredit
-        payload.extend_from_slice(&0i32.to_le_bytes()); // SquareMetersCommitted
-        
-        // Description (variable string)
-        let desc = "Your current balance";
-        payload.push(desc.len() as u8);
-        payload.extend_from_slice(desc.as_bytes());
+        debug!("MoneyBalanceRequest from agent {}", request.agent_id);
+        self.auth_handler.emit(NetworkEventData::MoneyBalanceRequested {
+            circuit_code,
+            agent_id: request.agent_id,
+            transaction_id: request.transaction_id,
+        });
+        Ok(())
+    }
 
-        let packet = Packet::reliable(1, payload);
-        let packet_data = packet.serialize()
-            .map_err(|e| crate::NetworkError::Protocol(format!("Failed to serialize MoneyBalanceReply: {}", e)))?;
+    /// Handle money transfer request, reported as a
+    /// [`NetworkEventData::MoneyTransferRequested`] event
+    async fn handle_money_transfer_request(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let request = match MoneyTransferRequest::parse(&packet.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid MoneyTransferRequest from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        // Agents only pay from their own account
+        let circuit_code = Self::agent_circuit(circuits, addr, request.agent_id).await
+            .filter(|_| request.source_id == request.agent_id.as_uuid());
+        let Some(circuit_code) = circuit_code else {
+            warn!("MoneyTransferRequest from {} names another agent", addr);
+            return Ok(());
+        };
 
-        socket.send_to(&packet_data, addr).await?;
+        debug!("Agent {} pays {} L${}", request.agent_id, request.dest_id, request.amount);
+        self.auth_handler.emit(NetworkEventData::MoneyTransferRequested {
+            circuit_code,
+            agent_id: request.agent_id,
+            destination: request.dest_id,
+            amount: request.amount,
+            transaction_type: request.transaction_type,
+            description: request.description,
+        });
         Ok(())
     }
 
@@ -504,7 +737,6 @@ impl PacketHandler {
     async fn handle_group_membership_data(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -517,7 +749,6 @@ impl PacketHandler {
     async fn handle_parcel_info_request(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -530,7 +761,7 @@ impl PacketHandler {
     async fn handle_map_block_request(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -541,7 +772,7 @@ impl PacketHandler {
         payload.push(packet_types::MAP_BLOCK_REPLY as u8);
 
         // AgentData block
-        payload.extend_from_slice(&mutsea_core::UserId::new().as_uuid().as_bytes());
+        payload.extend_from_slice(mutsea_core::UserId::new().as_uuid().as_bytes());
         payload.extend_from_slice(&0u32.to_le_bytes()); // Flags
 
         // Data block (empty - no regions)
@@ -555,15 +786,1258 @@ impl PacketHandler {
         Ok(())
     }
 
-    /// Handle script dialog reply
-    async fn handle_script_dialog_reply(
+    /// Handle object sale info, reported as an
+    /// [`NetworkEventData::ObjectSaleInfoSet`] event for each prim
+    async fn handle_object_sale_info(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let info = match ObjectSaleInfo::parse(&packet.payload) {
+            Ok(info) => info,
+            Err(e) => {
+                warn!("Invalid ObjectSaleInfo from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, info.agent_id).await else {
+            warn!("ObjectSaleInfo from {} names another agent", addr);
+            return Ok(());
+        };
+
+        for sale in info.objects {
+            self.auth_handler.emit(NetworkEventData::ObjectSaleInfoSet {
+                circuit_code,
+                agent_id: info.agent_id,
+                local_id: sale.local_id,
+                sale_type: sale.sale_type as u8,
+                price: sale.price,
+            });
+        }
+        Ok(())
+    }
+
+    /// Handle object buy, reported as an
+    /// [`NetworkEventData::ObjectBuyRequested`] event for each prim
+    async fn handle_object_buy(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let buy = match ObjectBuy::parse(&packet.payload) {
+            Ok(buy) => buy,
+            Err(e) => {
+                warn!("Invalid ObjectBuy from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, buy.agent_id).await else {
+            warn!("ObjectBuy from {} names another agent", addr);
+            return Ok(());
+        };
+
+        for sale in buy.objects {
+            self.auth_handler.emit(NetworkEventData::ObjectBuyRequested {
+                circuit_code,
+                agent_id: buy.agent_id,
+                local_id: sale.local_id,
+                sale_type: sale.sale_type as u8,
+                price: sale.price,
+            });
+        }
+        Ok(())
+    }
+
+    /// Handle a land tool applied to the terrain, reported as a
+    /// [`NetworkEventData::LandModified`] event
+    async fn handle_modify_land(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let modify = match ModifyLand::parse(&packet.payload) {
+            Ok(modify) => modify,
+            Err(e) => {
+                warn!("Invalid ModifyLand from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, modify.agent_id).await else {
+            warn!("ModifyLand from {} names another agent", addr);
+            return Ok(());
+        };
+
+        self.auth_handler.emit(NetworkEventData::LandModified {
+            circuit_code,
+            agent_id: modify.agent_id,
+            action: modify.action,
+            brush_size: modify.brush_size,
+            seconds: modify.seconds,
+            height: modify.height,
+            areas: modify
+                .areas
+                .iter()
+                .map(|area| (area.west, area.south, area.east, area.north))
+                .collect(),
+        });
+        Ok(())
+    }
+
+    /// Handle a request to undo a terrain edit, reported as a
+    /// [`NetworkEventData::LandUndoRequested`] event
+    async fn handle_undo_land(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let agent_id = match parse_undo_land(&packet.payload) {
+            Ok(agent_id) => agent_id,
+            Err(e) => {
+                warn!("Invalid UndoLand from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, agent_id).await else {
+            warn!("UndoLand from {} names another agent", addr);
+            return Ok(());
+        };
+
+        self.auth_handler
+            .emit(NetworkEventData::LandUndoRequested { circuit_code, agent_id });
+        Ok(())
+    }
+
+    /// Handle the sale terms of a parcel, reported as a
+    /// [`NetworkEventData::ParcelSaleSet`] event
+    async fn handle_parcel_properties_update(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let update = match ParcelSaleUpdate::parse(&packet.payload) {
+            Ok(update) => update,
+            Err(e) => {
+                warn!("Invalid ParcelPropertiesUpdate from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, update.agent_id).await else {
+            warn!("ParcelPropertiesUpdate from {} names another agent", addr);
+            return Ok(());
+        };
+
+        self.auth_handler.emit(NetworkEventData::ParcelSaleSet {
+            circuit_code,
+            agent_id: update.agent_id,
+            local_id: update.local_id,
+            for_sale: update.for_sale(),
+            price: update.sale_price,
+            auth_buyer_id: update.auth_buyer_id,
+            include_objects: update.sells_objects(),
+        });
+        Ok(())
+    }
+
+    /// Handle parcel buy, reported as a
+    /// [`NetworkEventData::ParcelBuyRequested`] event
+    async fn handle_parcel_buy(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let buy = match ParcelBuy::parse(&packet.payload) {
+            Ok(buy) => buy,
+            Err(e) => {
+                warn!("Invalid ParcelBuy from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, buy.agent_id).await else {
+            warn!("ParcelBuy from {} names another agent", addr);
+            return Ok(());
+        };
+
+        self.auth_handler.emit(NetworkEventData::ParcelBuyRequested {
+            circuit_code,
+            agent_id: buy.agent_id,
+            local_id: buy.local_id,
+            price: buy.price,
+            area: buy.area,
+        });
+        Ok(())
+    }
+
+    /// Handle a request to return prims on a parcel, reported as a
+    /// [`NetworkEventData::ParcelObjectsReturnRequested`] event; the
+    /// returns service checks the sender may return them
+    async fn handle_parcel_return_objects(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let request = match ParcelReturnObjects::parse(&packet.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid ParcelReturnObjects from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, request.agent_id).await else {
+            warn!("ParcelReturnObjects from {} names another agent", addr);
+            return Ok(());
+        };
+
+        self.auth_handler.emit(NetworkEventData::ParcelObjectsReturnRequested {
+            circuit_code,
+            agent_id: request.agent_id,
+            local_id: request.local_id,
+            return_type: request.return_type,
+            task_ids: request.task_ids,
+            owner_ids: request.owner_ids,
+        });
+        Ok(())
+    }
+
+    /// Handle a parcel's auto-return time, reported as a
+    /// [`NetworkEventData::ParcelCleanTimeSet`] event
+    async fn handle_parcel_set_other_clean_time(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let request = match ParcelSetOtherCleanTime::parse(&packet.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid ParcelSetOtherCleanTime from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, request.agent_id).await else {
+            warn!("ParcelSetOtherCleanTime from {} names another agent", addr);
+            return Ok(());
+        };
+
+        self.auth_handler.emit(NetworkEventData::ParcelCleanTimeSet {
+            circuit_code,
+            agent_id: request.agent_id,
+            local_id: request.local_id,
+            minutes: request.minutes,
+        });
+        Ok(())
+    }
+
+    /// Handle an inventory item being worn, reported as an
+    /// [`NetworkEventData::AttachmentRezRequested`] event
+    async fn handle_rez_single_attachment(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let rez = match RezSingleAttachmentFromInv::parse(&packet.payload) {
+            Ok(rez) => rez,
+            Err(e) => {
+                warn!("Invalid RezSingleAttachmentFromInv from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, rez.agent_id).await else {
+            warn!("RezSingleAttachmentFromInv from {} names another agent", addr);
+            return Ok(());
+        };
+
+        self.auth_handler.emit(NetworkEventData::AttachmentRezRequested {
+            circuit_code,
+            agent_id: rez.agent_id,
+            item_id: rez.item_id,
+            attachment_point: rez.attachment_point,
+            name: rez.name,
+        });
+        Ok(())
+    }
+
+    /// Handle prims in the region being worn, reported as an
+    /// [`NetworkEventData::ObjectAttachRequested`] event for each prim
+    async fn handle_object_attach(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let attach = match ObjectAttach::parse(&packet.payload) {
+            Ok(attach) => attach,
+            Err(e) => {
+                warn!("Invalid ObjectAttach from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, attach.agent_id).await else {
+            warn!("ObjectAttach from {} names another agent", addr);
+            return Ok(());
+        };
+
+        for prim in attach.objects {
+            self.auth_handler.emit(NetworkEventData::ObjectAttachRequested {
+                circuit_code,
+                agent_id: attach.agent_id,
+                local_id: prim.local_id,
+                attachment_point: attach.attachment_point,
+                rotation: prim.rotation,
+            });
+        }
+        Ok(())
+    }
+
+    /// Handle worn prims being taken off, reported as an
+    /// [`NetworkEventData::ObjectDetachRequested`] event for each prim
+    async fn handle_object_detach(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
-        debug!("ScriptDialogReply from {}", addr);
-        // TODO: Process script dialog response
+        let detach = match ObjectDetach::parse(&packet.payload) {
+            Ok(detach) => detach,
+            Err(e) => {
+                warn!("Invalid ObjectDetach from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, detach.agent_id).await else {
+            warn!("ObjectDetach from {} names another agent", addr);
+            return Ok(());
+        };
+
+        for local_id in detach.local_ids {
+            self.auth_handler.emit(NetworkEventData::ObjectDetachRequested {
+                circuit_code,
+                agent_id: detach.agent_id,
+                local_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// Handle a worn item being taken off from inventory, reported as an
+    /// [`NetworkEventData::AttachmentDetachRequested`] event
+    async fn handle_detach_attachment_into_inv(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let detach = match DetachAttachmentIntoInv::parse(&packet.payload) {
+            Ok(detach) => detach,
+            Err(e) => {
+                warn!("Invalid DetachAttachmentIntoInv from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, detach.agent_id).await else {
+            warn!("DetachAttachmentIntoInv from {} names another agent", addr);
+            return Ok(());
+        };
+
+        self.auth_handler.emit(NetworkEventData::AttachmentDetachRequested {
+            circuit_code,
+            agent_id: detach.agent_id,
+            item_id: detach.item_id,
+        });
+        Ok(())
+    }
+
+    /// Handle DeRezObject, emitting an
+    /// [`NetworkEventData::ObjectsDerezRequested`] event for each packet
+    async fn handle_derez_object(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let derez = match DeRezObject::parse(&packet.payload) {
+            Ok(derez) => derez,
+            Err(e) => {
+                warn!("Invalid DeRezObject from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, derez.agent_id).await else {
+            warn!("DeRezObject from {} names another agent", addr);
+            return Ok(());
+        };
+
+        self.auth_handler.emit(NetworkEventData::ObjectsDerezRequested {
+            circuit_code,
+            agent_id: derez.agent_id,
+            destination: derez.destination,
+            folder_id: derez.destination_id,
+            transaction_id: derez.transaction_id,
+            packet_count: derez.packet_count,
+            packet_number: derez.packet_number,
+            local_ids: derez.local_ids,
+        });
+        Ok(())
+    }
+
+    /// Handle RezObject, emitting an
+    /// [`NetworkEventData::ObjectRezRequested`] event
+    async fn handle_rez_object(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let rez = match RezObject::parse(&packet.payload) {
+            Ok(rez) => rez,
+            Err(e) => {
+                warn!("Invalid RezObject from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, rez.agent_id).await else {
+            warn!("RezObject from {} names another agent", addr);
+            return Ok(());
+        };
+
+        self.auth_handler.emit(NetworkEventData::ObjectRezRequested {
+            circuit_code,
+            agent_id: rez.agent_id,
+            item_id: rez.item_id,
+            position: rez.ray_end,
+            remove_item: rez.remove_item,
+        });
+        Ok(())
+    }
+
+    /// The circuit at `addr`, if it belongs to `agent_id`
+    async fn agent_circuit(
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        agent_id: mutsea_core::UserId,
+    ) -> Option<u32> {
+        circuits.read().await.values()
+            .find(|c| c.address == addr && c.agent_id == Some(agent_id))
+            .map(|c| c.circuit_code)
+    }
+
+    /// Handle script dialog reply, reported as a
+    /// [`NetworkEventData::ScriptDialogReplied`] event
+    async fn handle_script_dialog_reply(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let reply = match ScriptDialogReply::parse(&packet.payload) {
+            Ok(reply) => reply,
+            Err(e) => {
+                warn!("Invalid ScriptDialogReply from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+
+        // Only the agent on this circuit may answer for itself
+        let circuit_code = circuits.read().await.values()
+            .find(|c| c.address == addr && c.agent_id == Some(reply.agent_id))
+            .map(|c| c.circuit_code);
+        let Some(circuit_code) = circuit_code else {
+            warn!("ScriptDialogReply from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} pressed \"{}\" on dialog {}", reply.agent_id, reply.button_label, reply.object_id);
+        self.auth_handler.emit(NetworkEventData::ScriptDialogReplied {
+            circuit_code,
+            agent_id: reply.agent_id,
+            object_id: reply.object_id,
+            channel: reply.chat_channel,
+            button_index: reply.button_index,
+            button_label: reply.button_label,
+        });
+        Ok(())
+    }
+
+    /// Handle an EstateOwnerMessage, or a GodlikeMessage when `godlike`.
+    /// `kickestate` is reported as a
+    /// [`NetworkEventData::AgentKickRequested`] event and
+    /// `estateobjectreturn` as an
+    /// [`NetworkEventData::EstateObjectsReturnRequested`] event; the
+    /// services handling them check the sender may.
+    async fn handle_estate_owner_message(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+        godlike: bool,
+    ) -> NetworkResult<()> {
+        let message = match EstateOwnerMessage::parse(&packet.payload) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Invalid EstateOwnerMessage from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, message.agent_id).await else {
+            warn!("EstateOwnerMessage from {} names another agent", addr);
+            return Ok(());
+        };
+        if message.method == land::METHOD_ESTATE_OBJECT_RETURN {
+            let flags = message.params.first().and_then(|param| param.trim().parse::<u32>().ok());
+            let (Some(flags), Some(target_id)) = (flags, message.agent_param(1)) else {
+                warn!("estateobjectreturn from agent {} names no agent", message.agent_id);
+                return Ok(());
+            };
+            debug!("Agent {} asks to return the prims of {}", message.agent_id, target_id);
+            self.auth_handler.emit(NetworkEventData::EstateObjectsReturnRequested {
+                circuit_code,
+                agent_id: message.agent_id,
+                target_id,
+                flags,
+            });
+            return Ok(());
+        }
+        if message.method != moderation::METHOD_KICK_ESTATE {
+            debug!("Unhandled estate method \"{}\" from agent {}", message.method, message.agent_id);
+            return Ok(());
+        }
+        let Some(target_id) = message.agent_param(0) else {
+            warn!("kickestate from agent {} names no agent", message.agent_id);
+            return Ok(());
+        };
+
+        debug!("Agent {} asks to kick {}", message.agent_id, target_id);
+        self.auth_handler.emit(NetworkEventData::AgentKickRequested {
+            circuit_code,
+            agent_id: message.agent_id,
+            target_id,
+            reason: String::new(),
+            godlike,
+        });
+        Ok(())
+    }
+
+    /// Handle FreezeUser, reported as a
+    /// [`NetworkEventData::AgentFreezeRequested`] event
+    async fn handle_freeze_user(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let freeze = match FreezeUser::parse(&packet.payload) {
+            Ok(freeze) => freeze,
+            Err(e) => {
+                warn!("Invalid FreezeUser from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, freeze.agent_id).await else {
+            warn!("FreezeUser from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} asks to freeze {} (unfreeze: {})", freeze.agent_id, freeze.target_id, freeze.unfreeze);
+        self.auth_handler.emit(NetworkEventData::AgentFreezeRequested {
+            circuit_code,
+            agent_id: freeze.agent_id,
+            target_id: freeze.target_id,
+            frozen: !freeze.unfreeze,
+            godlike: false,
+        });
+        Ok(())
+    }
+
+    /// Handle GodKickUser, reported as a
+    /// [`NetworkEventData::AgentKickRequested`] or
+    /// [`NetworkEventData::AgentFreezeRequested`] event depending on its flags
+    async fn handle_god_kick_user(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let kick = match GodKickUser::parse(&packet.payload) {
+            Ok(kick) => kick,
+            Err(e) => {
+                warn!("Invalid GodKickUser from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, kick.god_id).await else {
+            warn!("GodKickUser from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} asks to {:?} {}", kick.god_id, kick.action(), kick.target_id);
+        let event = match kick.action() {
+            KickAction::Kick => NetworkEventData::AgentKickRequested {
+                circuit_code,
+                agent_id: kick.god_id,
+                target_id: kick.target_id,
+                reason: kick.reason,
+                godlike: true,
+            },
+            action => NetworkEventData::AgentFreezeRequested {
+                circuit_code,
+                agent_id: kick.god_id,
+                target_id: kick.target_id,
+                frozen: action == KickAction::Freeze,
+                godlike: true,
+            },
+        };
+        self.auth_handler.emit(event);
+        Ok(())
+    }
+
+    /// Handle RequestGodlikePowers, reported as a
+    /// [`NetworkEventData::GodlikePowersRequested`] event; the moderation
+    /// service answers with the level granted
+    async fn handle_request_godlike_powers(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let request = match RequestGodlikePowers::parse(&packet.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid RequestGodlikePowers from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, request.agent_id).await else {
+            warn!("RequestGodlikePowers from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} asks for god powers: {}", request.agent_id, request.godlike);
+        self.auth_handler.emit(NetworkEventData::GodlikePowersRequested {
+            circuit_code,
+            agent_id: request.agent_id,
+            session_id: request.session_id,
+            godlike: request.godlike,
+            token: request.token,
+        });
+        Ok(())
+    }
+
+    /// Handle UserReport, reported as a
+    /// [`NetworkEventData::AbuseReported`] event
+    async fn handle_user_report(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let report = match UserReport::parse(&packet.payload) {
+            Ok(report) => report,
+            Err(e) => {
+                warn!("Invalid UserReport from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, report.agent_id).await else {
+            warn!("UserReport from {} names another agent", addr);
+            return Ok(());
+        };
+
+        info!("Agent {} reports abuse by {}: {}", report.agent_id, report.abuser_id, report.summary);
+        let [x, y, z] = report.position;
+        self.auth_handler.emit(NetworkEventData::AbuseReported {
+            circuit_code,
+            agent_id: report.agent_id,
+            abuser_id: report.abuser_id,
+            object_id: report.object_id,
+            category: report.category,
+            position: Vector3::new(x, y, z),
+            summary: report.summary,
+            details: report.details,
+            screenshot_id: report.screenshot_id,
+        });
+        Ok(())
+    }
+
+    /// Handle a place search, reported as a
+    /// [`NetworkEventData::PlacesSearched`] event; the search service
+    /// answers
+    async fn handle_dir_places_query(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let query = match DirPlacesQuery::parse(&packet.payload) {
+            Ok(query) => query,
+            Err(e) => {
+                warn!("Invalid DirPlacesQuery from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, query.agent_id).await else {
+            warn!("DirPlacesQuery from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} searches places for \"{}\"", query.agent_id, query.text);
+        self.auth_handler.emit(NetworkEventData::PlacesSearched {
+            circuit_code,
+            agent_id: query.agent_id,
+            query_id: query.query_id,
+            text: query.text,
+            flags: query.flags,
+            start: query.start,
+        });
+        Ok(())
+    }
+
+    /// Handle a classified search, reported as a
+    /// [`NetworkEventData::ClassifiedsSearched`] event; the directory
+    /// answers
+    async fn handle_dir_classified_query(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let query = match DirClassifiedQuery::parse(&packet.payload) {
+            Ok(query) => query,
+            Err(e) => {
+                warn!("Invalid DirClassifiedQuery from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, query.agent_id).await else {
+            warn!("DirClassifiedQuery from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} searches classifieds for \"{}\"", query.agent_id, query.text);
+        self.auth_handler.emit(NetworkEventData::ClassifiedsSearched {
+            circuit_code,
+            agent_id: query.agent_id,
+            query_id: query.query_id,
+            text: query.text,
+            flags: query.flags,
+            category: query.category,
+            start: query.start,
+        });
+        Ok(())
+    }
+
+    /// Handle a directory search, reported as an
+    /// [`NetworkEventData::EventsSearched`] event when it is for events.
+    /// People and group searches are not answered.
+    async fn handle_dir_find_query(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let query = match DirFindQuery::parse(&packet.payload) {
+            Ok(query) => query,
+            Err(e) => {
+                warn!("Invalid DirFindQuery from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, query.agent_id).await else {
+            warn!("DirFindQuery from {} names another agent", addr);
+            return Ok(());
+        };
+        if query.flags & directory::DFQ_EVENTS == 0 {
+            debug!("Unhandled directory search 0x{:08X} from agent {}", query.flags, query.agent_id);
+            return Ok(());
+        }
+
+        debug!("Agent {} searches events for \"{}\"", query.agent_id, query.text);
+        self.auth_handler.emit(NetworkEventData::EventsSearched {
+            circuit_code,
+            agent_id: query.agent_id,
+            query_id: query.query_id,
+            text: query.text,
+            flags: query.flags,
+            start: query.start,
+        });
+        Ok(())
+    }
+
+    /// Handle a request for one event, reported as an
+    /// [`NetworkEventData::EventInfoRequested`] event
+    async fn handle_event_info_request(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let request = match EventInfoRequest::parse(&packet.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid EventInfoRequest from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, request.agent_id).await else {
+            warn!("EventInfoRequest from {} names another agent", addr);
+            return Ok(());
+        };
+
+        self.auth_handler.emit(NetworkEventData::EventInfoRequested {
+            circuit_code,
+            agent_id: request.agent_id,
+            event_id: request.event_id,
+        });
+        Ok(())
+    }
+
+    /// Handle a sound played by an agent, reported as a
+    /// [`NetworkEventData::SoundTriggered`] event
+    async fn handle_sound_trigger(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let trigger = match SoundTrigger::parse(&packet.payload) {
+            Ok(trigger) => trigger,
+            Err(e) => {
+                warn!("Invalid SoundTrigger from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        // Viewers play sounds as their own agent
+        let agent_id = mutsea_core::UserId::from_uuid(trigger.owner_id);
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, agent_id).await else {
+            warn!("SoundTrigger from {} names another agent", addr);
+            return Ok(());
+        };
+
+        self.auth_handler.emit(NetworkEventData::SoundTriggered {
+            circuit_code,
+            agent_id,
+            sound_id: trigger.sound_id,
+            object_id: trigger.object_id,
+            parent_id: trigger.parent_id,
+            position: trigger.position,
+            gain: trigger.gain,
+        });
+        Ok(())
+    }
+
+    /// Handle a viewer setting its home where it stands, reported as a
+    /// [`NetworkEventData::HomeSet`] event
+    async fn handle_set_start_location_request(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let request = match SetStartLocationRequest::parse(&packet.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid SetStartLocationRequest from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, request.agent_id).await else {
+            warn!("SetStartLocationRequest from {} names another agent", addr);
+            return Ok(());
+        };
+        let region_id = circuits.read().await.get(&circuit_code).and_then(|c| c.region_id);
+
+        self.auth_handler.emit(NetworkEventData::HomeSet {
+            circuit_code,
+            agent_id: request.agent_id,
+            region_id,
+            position: request.position,
+            look_at: request.look_at,
+        });
+        Ok(())
+    }
+
+    /// Handle an avatar picker search, reported as a
+    /// [`NetworkEventData::AvatarPickerRequested`] event
+    async fn handle_avatar_picker_request(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let request = match AvatarPickerRequest::parse(&packet.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid AvatarPickerRequest from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, request.agent_id).await else {
+            warn!("AvatarPickerRequest from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} searches avatars for \"{}\"", request.agent_id, request.name);
+        self.auth_handler.emit(NetworkEventData::AvatarPickerRequested {
+            circuit_code,
+            agent_id: request.agent_id,
+            query_id: request.query_id,
+            name: request.name,
+        });
+        Ok(())
+    }
+
+    /// Handle a viewer's cache misses, reported as a
+    /// [`NetworkEventData::ObjectsRequested`] event
+    async fn handle_request_multiple_objects(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let request = match RequestMultipleObjects::parse(&packet.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid RequestMultipleObjects from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, request.agent_id).await else {
+            warn!("RequestMultipleObjects from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} missed {} cached prims", request.agent_id, request.local_ids.len());
+        self.auth_handler.emit(NetworkEventData::ObjectsRequested {
+            circuit_code,
+            agent_id: request.agent_id,
+            local_ids: request.local_ids,
+        });
+        Ok(())
+    }
+
+    /// Handle a request for one classified, reported as a
+    /// [`NetworkEventData::ClassifiedInfoRequested`] event
+    async fn handle_classified_info_request(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let request = match ClassifiedInfoRequest::parse(&packet.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid ClassifiedInfoRequest from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, request.agent_id).await else {
+            warn!("ClassifiedInfoRequest from {} names another agent", addr);
+            return Ok(());
+        };
+
+        self.auth_handler.emit(NetworkEventData::ClassifiedInfoRequested {
+            circuit_code,
+            agent_id: request.agent_id,
+            classified_id: request.classified_id,
+        });
+        Ok(())
+    }
+
+    /// Handle an agent placing or editing a classified, reported as a
+    /// [`NetworkEventData::ClassifiedUpdated`] event
+    async fn handle_classified_info_update(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let update = match ClassifiedInfoUpdate::parse(&packet.payload) {
+            Ok(update) => update,
+            Err(e) => {
+                warn!("Invalid ClassifiedInfoUpdate from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, update.agent_id).await else {
+            warn!("ClassifiedInfoUpdate from {} names another agent", addr);
+            return Ok(());
+        };
+
+        let classified = update.classified;
+        debug!("Agent {} saves classified \"{}\"", update.agent_id, classified.name);
+        self.auth_handler.emit(NetworkEventData::ClassifiedUpdated {
+            circuit_code,
+            agent_id: update.agent_id,
+            classified_id: classified.classified_id,
+            category: classified.category,
+            name: classified.name,
+            description: classified.description,
+            parcel_id: classified.parcel_id,
+            parent_estate: classified.parent_estate,
+            snapshot_id: classified.snapshot_id,
+            pos_global: classified.pos_global,
+            flags: classified.flags,
+            price_for_listing: classified.price_for_listing,
+        });
+        Ok(())
+    }
+
+    /// Handle an agent removing a classified, reported as a
+    /// [`NetworkEventData::ClassifiedDeleted`] event
+    async fn handle_classified_delete(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let delete = match ClassifiedDelete::parse(&packet.payload) {
+            Ok(delete) => delete,
+            Err(e) => {
+                warn!("Invalid ClassifiedDelete from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, delete.agent_id).await else {
+            warn!("ClassifiedDelete from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} removes classified {}", delete.agent_id, delete.classified_id);
+        self.auth_handler.emit(NetworkEventData::ClassifiedDeleted {
+            circuit_code,
+            agent_id: delete.agent_id,
+            classified_id: delete.classified_id,
+        });
+        Ok(())
+    }
+
+    /// Handle a request for an avatar's profile, reported as a
+    /// [`NetworkEventData::AvatarPropertiesRequested`] event; the profile
+    /// service answers
+    async fn handle_avatar_properties_request(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let request = match AvatarPropertiesRequest::parse(&packet.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid AvatarPropertiesRequest from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, request.agent_id).await else {
+            warn!("AvatarPropertiesRequest from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} opens the profile of {}", request.agent_id, request.avatar_id);
+        self.auth_handler.emit(NetworkEventData::AvatarPropertiesRequested {
+            circuit_code,
+            agent_id: request.agent_id,
+            avatar_id: request.avatar_id,
+        });
+        Ok(())
+    }
+
+    /// Handle an agent saving its profile, reported as a
+    /// [`NetworkEventData::AvatarPropertiesUpdated`] event
+    async fn handle_avatar_properties_update(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let update = match AvatarPropertiesUpdate::parse(&packet.payload) {
+            Ok(update) => update,
+            Err(e) => {
+                warn!("Invalid AvatarPropertiesUpdate from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, update.agent_id).await else {
+            warn!("AvatarPropertiesUpdate from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} saves its profile", update.agent_id);
+        let properties = update.properties;
+        self.auth_handler.emit(NetworkEventData::AvatarPropertiesUpdated {
+            circuit_code,
+            agent_id: update.agent_id,
+            image_id: properties.image_id,
+            fl_image_id: properties.fl_image_id,
+            about_text: properties.about_text,
+            fl_about_text: properties.fl_about_text,
+            profile_url: properties.profile_url,
+            allow_publish: properties.allow_publish,
+            mature_publish: properties.mature_publish,
+        });
+        Ok(())
+    }
+
+    /// Handle the GenericMessage methods asking for picks and classifieds,
+    /// reported as [`NetworkEventData::AvatarPicksRequested`],
+    /// [`NetworkEventData::PickInfoRequested`] and
+    /// [`NetworkEventData::AvatarClassifiedsRequested`] events
+    async fn handle_generic_message(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let message = match GenericMessage::parse(&packet.payload) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Invalid GenericMessage from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, message.agent_id).await else {
+            warn!("GenericMessage from {} names another agent", addr);
+            return Ok(());
+        };
+
+        let event = match message.method.as_str() {
+            profile::METHOD_AVATAR_PICKS_REQUEST => message.agent_param(0).map(|target_id| {
+                NetworkEventData::AvatarPicksRequested {
+                    circuit_code,
+                    agent_id: message.agent_id,
+                    target_id,
+                }
+            }),
+            profile::METHOD_PICK_INFO_REQUEST => {
+                let pick_id = message.params.get(1).and_then(|param| uuid::Uuid::parse_str(param.trim()).ok());
+                message.agent_param(0).zip(pick_id).map(|(creator_id, pick_id)| {
+                    NetworkEventData::PickInfoRequested {
+                        circuit_code,
+                        agent_id: message.agent_id,
+                        creator_id,
+                        pick_id,
+                    }
+                })
+            }
+            directory::METHOD_AVATAR_CLASSIFIEDS_REQUEST => message.agent_param(0).map(|target_id| {
+                NetworkEventData::AvatarClassifiedsRequested {
+                    circuit_code,
+                    agent_id: message.agent_id,
+                    target_id,
+                }
+            }),
+            method => {
+                debug!("Unhandled generic method \"{}\" from agent {}", method, message.agent_id);
+                return Ok(());
+            }
+        };
+        match event {
+            Some(event) => self.auth_handler.emit(event),
+            None => warn!("{} from agent {} has bad parameters", message.method, message.agent_id),
+        }
+        Ok(())
+    }
+
+    /// Handle an agent saving one of its picks, reported as a
+    /// [`NetworkEventData::PickUpdated`] event
+    async fn handle_pick_info_update(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let update = match PickInfoUpdate::parse(&packet.payload) {
+            Ok(update) => update,
+            Err(e) => {
+                warn!("Invalid PickInfoUpdate from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, update.agent_id).await else {
+            warn!("PickInfoUpdate from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} saves pick \"{}\"", update.agent_id, update.pick.name);
+        let pick = update.pick;
+        self.auth_handler.emit(NetworkEventData::PickUpdated {
+            circuit_code,
+            agent_id: update.agent_id,
+            pick_id: pick.pick_id,
+            top_pick: pick.top_pick,
+            parcel_id: pick.parcel_id,
+            name: pick.name,
+            description: pick.description,
+            snapshot_id: pick.snapshot_id,
+            pos_global: pick.pos_global,
+            sort_order: pick.sort_order,
+            enabled: pick.enabled,
+        });
+        Ok(())
+    }
+
+    /// Handle an agent removing one of its picks, reported as a
+    /// [`NetworkEventData::PickDeleted`] event
+    async fn handle_pick_delete(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let delete = match PickDelete::parse(&packet.payload) {
+            Ok(delete) => delete,
+            Err(e) => {
+                warn!("Invalid PickDelete from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, delete.agent_id).await else {
+            warn!("PickDelete from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} removes pick {}", delete.agent_id, delete.pick_id);
+        self.auth_handler.emit(NetworkEventData::PickDeleted {
+            circuit_code,
+            agent_id: delete.agent_id,
+            pick_id: delete.pick_id,
+        });
+        Ok(())
+    }
+
+    /// Handle a request for a landmark, reported as a
+    /// [`NetworkEventData::LandmarkRequested`] event; the profile service
+    /// makes it
+    async fn handle_create_landmark(
+        &self,
+        circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
+        addr: SocketAddr,
+        packet: &Packet,
+    ) -> NetworkResult<()> {
+        let request = match CreateLandmarkForEvent::parse(&packet.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid CreateLandmarkForEvent from {}: {}", addr, e);
+                return Ok(());
+            }
+        };
+        let Some(circuit_code) = Self::agent_circuit(circuits, addr, request.agent_id).await else {
+            warn!("CreateLandmarkForEvent from {} names another agent", addr);
+            return Ok(());
+        };
+
+        debug!("Agent {} asks for landmark \"{}\"", request.agent_id, request.name);
+        self.auth_handler.emit(NetworkEventData::LandmarkRequested {
+            circuit_code,
+            agent_id: request.agent_id,
+            folder_id: request.folder_id,
+            name: request.name,
+        });
         Ok(())
     }
 
@@ -571,7 +2045,7 @@ impl PacketHandler {
     async fn handle_provision_voice_account(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -582,7 +2056,7 @@ impl PacketHandler {
         payload.push(packet_types::PROVISION_VOICE_ACCOUNT_REPLY as u8);
 
         // AgentData block
-        payload.extend_from_slice(&mutsea_core::UserId::new().as_uuid().as_bytes());
+        payload.extend_from_slice(mutsea_core::UserId::new().as_uuid().as_bytes());
 
         // VoiceData block
         let voice_server_type = "none";
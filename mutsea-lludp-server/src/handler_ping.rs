@@ -6,12 +6,11 @@ use mutsea_protocol::{Packet, constants::packet_types};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::time::Instant;
-use tokio::net::UdpSocket;
 use tokio::sync::RwLock;
 use std::sync::Arc;
 use tracing::{debug, warn};
 
-use super::{CircuitInfo, ServerStats};
+use super::{CircuitInfo, CircuitSocket, ServerStats};
 
 /// Ping handler for connection health monitoring
 #[derive(Clone)]
@@ -25,19 +24,20 @@ impl PingHandler {
     /// Handle StartPingCheck message
     pub async fn handle_ping_check(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
-        if packet.payload.len() < 6 {
+        // Message ID has already been stripped by Packet::deserialize
+        if packet.payload.len() < 5 {
             warn!("StartPingCheck packet too short from {}", addr);
             return Ok(());
         }
 
-        let ping_id = packet.payload[1];
+        let ping_id = packet.payload[0];
         let oldest_unacked = u32::from_le_bytes([
-            packet.payload[2], packet.payload[3], 
-            packet.payload[4], packet.payload[5]
+            packet.payload[1], packet.payload[2], 
+            packet.payload[3], packet.payload[4]
         ]);
 
         debug!("Ping check from {}: ping_id={}, oldest_unacked={}", addr, ping_id, oldest_unacked);
@@ -51,7 +51,7 @@ impl PingHandler {
     /// Send CompletePingCheck response
     async fn send_ping_response(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         ping_id: u8,
     ) -> NetworkResult<()> {
@@ -75,12 +75,12 @@ impl PingHandler {
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
-        if packet.payload.len() < 2 {
+        if packet.payload.is_empty() {
             warn!("CompletePingCheck packet too short from {}", addr);
             return Ok(());
         }
 
-        let ping_id = packet.payload[1];
+        let ping_id = packet.payload[0];
 
         // Find circuit by address and update ping info
         let mut circuits_guard = circuits.write().await;
@@ -135,7 +135,7 @@ impl PingHandler {
             if circuit.address == addr {
                 // Remove acknowledged reliable packets
                 for ack in &acks {
-                    circuit.reliable_packets.remove(ack);
+                    circuit.acknowledge(*ack);
                 }
                 
                 circuit.last_activity = Instant::now();
@@ -150,7 +150,7 @@ impl PingHandler {
     /// Send ping check to circuit
     pub async fn send_ping_check(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         circuit: &mut CircuitInfo,
         stats: &Arc<RwLock<ServerStats>>,
     ) -> NetworkResult<()> {
@@ -185,7 +185,7 @@ impl PingHandler {
     /// Send heartbeat packet to maintain connection
     pub async fn send_heartbeat(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         circuit: &mut CircuitInfo,
         stats: &Arc<RwLock<ServerStats>>,
     ) -> NetworkResult<()> {
@@ -196,7 +196,7 @@ impl PingHandler {
     /// Process reliable packet resends
     pub async fn process_reliable_resends(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         circuit: &mut CircuitInfo,
         config: &mutsea_core::config::LLUDPConfig,
         stats: &Arc<RwLock<ServerStats>>,
@@ -270,7 +270,7 @@ impl PingHandler {
     /// Send keep-alive packet
     pub async fn send_keep_alive(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         circuit: &mut CircuitInfo,
         stats: &Arc<RwLock<ServerStats>>,
     ) -> NetworkResult<()> {
@@ -295,7 +295,7 @@ pub struct CircuitManager;
 impl CircuitManager {
     /// Send heartbeat to all circuits that need it
     pub async fn send_heartbeats_to_all(
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
         ping_interval: std::time::Duration,
         stats: &Arc<RwLock<ServerStats>>,
@@ -319,7 +319,7 @@ impl CircuitManager {
 
     /// Process reliable resends for all circuits
     pub async fn process_reliable_resends_for_all(
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
         config: &mutsea_core::config::LLUDPConfig,
         stats: &Arc<RwLock<ServerStats>>,
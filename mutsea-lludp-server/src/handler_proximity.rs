@@ -5,12 +5,11 @@ use crate::NetworkResult;
 use mutsea_core::Vector3;
 use mutsea_protocol::Packet;
 use std::collections::HashMap;
-use tokio::net::UdpSocket;
 use tokio::sync::RwLock;
 use std::sync::Arc;
 use tracing::{debug, warn};
 
-use super::{CircuitInfo, ServerStats};
+use super::{CircuitInfo, CircuitSocket, OutboundPacket, PriorityClass, ServerStats};
 
 /// Proximity handler for detecting nearby agents and broadcasting updates
 #[derive(Clone)]
@@ -97,7 +96,7 @@ impl ProximityHandler {
     pub async fn broadcast_agent_update(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         circuit_code: u32,
         range: f32,
         stats: &Arc<RwLock<ServerStats>>,
@@ -142,7 +141,8 @@ impl ProximityHandler {
         
         // Send updates to nearby circuits
         for (target_circuit, target_address) in nearby_circuits {
-            if let Err(e) = socket.send_to(&packet_data, target_address).await {
+            let outbound = OutboundPacket::new(packet_data.clone(), PriorityClass::Avatar);
+            if let Err(e) = socket.send_class(outbound, target_address).await {
                 warn!("Failed to send agent update to circuit {}: {}", target_circuit, e);
             }
         }
@@ -221,7 +221,7 @@ impl ProximityHandler {
     pub async fn broadcast_object_update(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         object_position: Vector3,
         object_data: &[u8],
         range: f32,
@@ -235,7 +235,8 @@ impl ProximityHandler {
         for circuit_code in interested_agents {
             if let Some(circuit) = circuits_guard.get(&circuit_code) {
                 if circuit.authenticated {
-                    if let Err(e) = socket.send_to(object_data, circuit.address).await {
+                    let outbound = OutboundPacket::new(object_data.to_vec(), PriorityClass::Object);
+                    if let Err(e) = socket.send_class(outbound, circuit.address).await {
                         warn!("Failed to send object update to circuit {}: {}", circuit_code, e);
                     } else {
                         broadcast_count += 1;
@@ -3,16 +3,15 @@
 
 use crate::NetworkResult;
 use mutsea_core::{Vector3, RegionId, UserId};
-use mutsea_protocol::{Packet, constants::packet_types};
+use mutsea_protocol::{Packet, constants::packet_types, layer_data};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::time::Instant;
-use tokio::net::UdpSocket;
 use tokio::sync::RwLock;
 use std::sync::Arc;
-use tracing::{debug, warn, info};
+use tracing::{debug, instrument, warn, info};
 
-use super::{CircuitInfo, ServerStats};
+use super::{CircuitInfo, CircuitSocket, ServerStats};
 
 /// Region handler for managing world state and region information
 #[derive(Clone)]
@@ -27,7 +26,7 @@ impl RegionHandler {
     pub async fn handle_region_handshake_reply(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -74,7 +73,7 @@ impl RegionHandler {
     /// Send region setup messages after handshake
     async fn send_region_setup_messages(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         circuit_code: u32,
     ) -> NetworkResult<()> {
@@ -94,7 +93,7 @@ impl RegionHandler {
     /// Send terrain layer data
     async fn send_layer_data(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
     ) -> NetworkResult<()> {
         let mut payload = Vec::new();
@@ -123,16 +122,12 @@ impl RegionHandler {
     /// Send wind data
     async fn send_wind_data(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
     ) -> NetworkResult<()> {
-        let mut payload = Vec::new();
-        payload.push(packet_types::SIMULATOR_FEATURES as u8);
-
-        // Simple wind data - no wind
-        payload.extend_from_slice(&Vector3::ZERO.x.to_le_bytes());
-        payload.extend_from_slice(&Vector3::ZERO.y.to_le_bytes());
-        payload.extend_from_slice(&Vector3::ZERO.z.to_le_bytes());
+        // Calm until the environment task sends the region's wind
+        let calm = vec![0.0; layer_data::PATCH_SIZE * layer_data::PATCH_SIZE];
+        let payload = layer_data::encode_wind(&calm, &calm);
 
         let packet = Packet::reliable(1, payload);
         let packet_data = packet.serialize()
@@ -146,16 +141,12 @@ impl RegionHandler {
     /// Send cloud data
     async fn send_cloud_data(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
     ) -> NetworkResult<()> {
-        let mut payload = Vec::new();
-        payload.push(packet_types::CLOUD_DATA as u8);
-
-        // Simple cloud data - clear skies
-        for _ in 0..4 {
-            payload.extend_from_slice(&0.0f32.to_le_bytes()); // Cloud coverage
-        }
+        // Clear skies until the environment task sends the region's clouds
+        let clear = vec![0.0; layer_data::PATCH_SIZE * layer_data::PATCH_SIZE];
+        let payload = layer_data::encode_clouds(&clear);
 
         let packet = Packet::reliable(1, payload);
         let packet_data = packet.serialize()
@@ -167,10 +158,11 @@ impl RegionHandler {
     }
 
     /// Handle teleport request
+    #[instrument(name = "lludp.teleport_request", skip_all)]
     pub async fn handle_teleport_request(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -199,7 +191,7 @@ impl RegionHandler {
     /// Send teleport start message
     async fn send_teleport_start(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
     ) -> NetworkResult<()> {
         let mut payload = Vec::new();
@@ -255,7 +247,7 @@ impl RegionHandler {
     pub async fn send_region_restart_notification(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         restart_in_seconds: u32,
         message: &str,
         stats: &Arc<RwLock<ServerStats>>,
@@ -292,7 +284,7 @@ impl RegionHandler {
     /// Send restart notification to specific client
     async fn send_restart_notification(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         restart_in_seconds: u32,
         message: &str,
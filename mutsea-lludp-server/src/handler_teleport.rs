@@ -2,17 +2,16 @@
 //! Teleport and region crossing handler
 
 use crate::NetworkResult;
-use mutsea_core::{Vector3, RegionId, UserId};
-use mutsea_protocol::{Packet, constants::packet_types};
+use mutsea_core::{Maturity, Vector3, RegionId};
+use mutsea_protocol::{Packet, constants::packet_types, login::LoginService};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::time::Instant;
-use tokio::net::UdpSocket;
 use tokio::sync::RwLock;
 use std::sync::Arc;
 use tracing::{debug, warn, info};
 
-use super::{CircuitInfo, ServerStats};
+use super::{CircuitInfo, CircuitSocket};
 
 /// Teleport handler for agent teleportation and region crossing
 #[derive(Clone)]
@@ -21,9 +20,15 @@ pub struct TeleportHandler;
 /// Teleport request data
 #[derive(Debug, Clone)]
 pub struct TeleportRequestData {
+    /// Destination region
     pub region_id: RegionId,
+    /// Destination region's handle, its corner in global metres
+    pub region_handle: u64,
+    /// Where to arrive, in region coordinates
     pub position: Vector3,
+    /// Direction to face on arrival
     pub look_at: Vector3,
+    /// `teleport_flags` bits sent with the request
     pub teleport_flags: u32,
 }
 
@@ -46,9 +51,10 @@ impl TeleportHandler {
     pub async fn handle_teleport_request(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
+        login_service: &LoginService,
     ) -> NetworkResult<()> {
         if packet.payload.len() < 69 { // Minimum size for TeleportRequest
             warn!("TeleportRequest packet too short from {}", addr);
@@ -56,20 +62,37 @@ impl TeleportHandler {
         }
 
         // Find circuit by address
-        let circuit_code = {
+        let circuit = {
             let circuits_guard = circuits.read().await;
             circuits_guard.iter()
                 .find(|(_, circuit)| circuit.address == addr)
-                .map(|(code, _)| *code)
+                .map(|(code, circuit)| (*code, circuit.agent_id))
         };
 
-        let Some(circuit_code) = circuit_code else {
+        let Some((circuit_code, agent_id)) = circuit else {
             warn!("No circuit found for address {}", addr);
             return Ok(());
         };
 
         // Parse teleport request
-        let teleport_data = self.parse_teleport_request(&packet.payload)?;
+        let mut teleport_data = self.parse_teleport_request(&packet.payload)?;
+
+        // Agents may only enter regions rated up to their maturity setting
+        let mut maturity = Maturity::Pg;
+        if let Some(region) = login_service.region_by_handle(teleport_data.region_handle) {
+            teleport_data.region_id = region.region_id;
+            maturity = login_service.region_maturity(&region.region_id);
+            let access_max = agent_id.map_or(Maturity::Pg, |agent_id| login_service.access_max(&agent_id));
+            if maturity > access_max {
+                let reason = format!(
+                    "{} is rated {}, above your maturity setting ({})",
+                    region.region_name, maturity, access_max
+                );
+                self.send_teleport_failed(socket, addr, &reason).await?;
+                info!("Refused teleport of circuit {} to {}: {}", circuit_code, region.region_name, reason);
+                return Ok(());
+            }
+        }
         
         info!("Teleport request from circuit {}: region={}, pos=({:.1}, {:.1}, {:.1})", 
               circuit_code, teleport_data.region_id, 
@@ -83,7 +106,7 @@ impl TeleportHandler {
         drop(circuits_guard);
 
         // Process teleport (simplified - in reality would validate destination)
-        self.process_teleport(circuits, socket, addr, circuit_code, &teleport_data).await?;
+        self.process_teleport(circuits, socket, addr, circuit_code, &teleport_data, maturity).await?;
 
         Ok(())
     }
@@ -136,6 +159,7 @@ impl TeleportHandler {
 
         Ok(TeleportRequestData {
             region_id,
+            region_handle,
             position,
             look_at,
             teleport_flags,
@@ -146,10 +170,11 @@ impl TeleportHandler {
     async fn process_teleport(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         circuit_code: u32,
         teleport_data: &TeleportRequestData,
+        maturity: Maturity,
     ) -> NetworkResult<()> {
         // Send teleport start
         self.send_teleport_start(socket, addr).await?;
@@ -170,7 +195,7 @@ impl TeleportHandler {
             }
 
             // Send teleport finish
-            self.send_teleport_finish(socket, addr, teleport_data).await?;
+            self.send_teleport_finish(socket, addr, teleport_data, maturity).await?;
             
             info!("Teleport completed for circuit {}", circuit_code);
         } else {
@@ -185,7 +210,7 @@ impl TeleportHandler {
     /// Send TeleportStart message
     async fn send_teleport_start(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
     ) -> NetworkResult<()> {
         let mut payload = Vec::new();
@@ -206,7 +231,7 @@ impl TeleportHandler {
     /// Send TeleportProgress message
     async fn send_teleport_progress(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         message: &str,
     ) -> NetworkResult<()> {
@@ -233,9 +258,10 @@ impl TeleportHandler {
     /// Send TeleportFinish message
     async fn send_teleport_finish(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         teleport_data: &TeleportRequestData,
+        maturity: Maturity,
     ) -> NetworkResult<()> {
         let mut payload = Vec::new();
         payload.push(packet_types::TELEPORT_FINISH as u8);
@@ -244,7 +270,7 @@ impl TeleportHandler {
         payload.extend_from_slice(&0u32.to_le_bytes()); // TeleportFlags
         payload.extend_from_slice(&(1000u32 * 256).to_le_bytes()); // RegionHandle X
         payload.extend_from_slice(&(1000u32 * 256).to_le_bytes()); // RegionHandle Y
-        payload.extend_from_slice(&13u16.to_le_bytes()); // SimAccess (PG)
+        payload.push(maturity.sim_access()); // SimAccess
         
         // SIM IP (32-bit IP)
         let sim_ip: [u8; 4] = [127, 0, 0, 1];
@@ -254,7 +280,7 @@ impl TeleportHandler {
         payload.extend_from_slice(&9000u16.to_le_bytes());
         
         // Location ID (16 bytes - can be random)
-        payload.extend_from_slice(&uuid::Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(uuid::Uuid::new_v4().as_bytes());
         
         // Seed capability (variable string)
         let seed_cap = format!("http://127.0.0.1:8080/caps/{}/", uuid::Uuid::new_v4());
@@ -274,7 +300,7 @@ impl TeleportHandler {
     /// Send TeleportFailed message
     async fn send_teleport_failed(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         reason: &str,
     ) -> NetworkResult<()> {
@@ -302,7 +328,6 @@ impl TeleportHandler {
     pub async fn handle_teleport_local(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -386,7 +411,7 @@ impl TeleportHandler {
     pub async fn handle_cross_region_teleport(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         circuit_code: u32,
         target_region: RegionId,
         position: Vector3,
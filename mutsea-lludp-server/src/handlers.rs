@@ -7,12 +7,11 @@ use mutsea_protocol::{Packet, constants::packet_types, login::LoginService};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::UdpSocket;
 use tokio::sync::RwLock;
-use tracing::{debug, error, warn};
+use tracing::{debug, info_span, instrument, warn, Instrument};
 
 use super::{
-    CircuitInfo, ServerStats, AuthHandler, MovementHandler, 
+    CircuitInfo, CircuitSocket, ServerStats, AuthHandler, MovementHandler, 
     ChatHandler, PingHandler, RegionHandler
 };
 
@@ -43,7 +42,7 @@ impl PacketHandlers {
         &self,
         session_manager: &crate::SessionManager,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         data: &[u8],
         config: &LLUDPConfig,
@@ -62,6 +61,29 @@ impl PacketHandlers {
         debug!("Received packet from {}: seq={}, size={}, reliable={}", 
                addr, packet.header.sequence, data.len(), packet.header.is_reliable());
 
+        // Root span for this packet; handler and database spans nest under it
+        let (circuit_code, agent_id) = circuits
+            .read()
+            .await
+            .values()
+            .find(|c| c.address == addr)
+            .map(|c| (Some(c.circuit_code), c.agent_id))
+            .unwrap_or((None, None));
+        let span = info_span!(
+            "lludp.packet",
+            %addr,
+            seq = packet.header.sequence,
+            message_id = tracing::field::Empty,
+            circuit_code,
+            agent_id = tracing::field::Empty,
+        );
+        if let Some(message_id) = packet.message_id {
+            span.record("message_id", format!("0x{:08X}", message_id).as_str());
+        }
+        if let Some(agent_id) = agent_id {
+            span.record("agent_id", tracing::field::display(agent_id));
+        }
+
         // Update stats
         {
             let mut stats_guard = stats.write().await;
@@ -70,26 +92,28 @@ impl PacketHandlers {
         }
 
         // Handle packet based on type
-        if let Some(message_id) = packet.message_id {
-            self.handle_message_packet(
-                circuits, socket, addr, &packet, message_id, 
-                config, login_service, stats
-            ).await?;
-        } else {
-            // Handle raw packet
-            self.handle_raw_packet(
-                circuits, socket, addr, &packet, config, stats
-            ).await?;
+        async {
+            if let Some(message_id) = packet.message_id {
+                self.handle_message_packet(
+                    circuits, socket, addr, &packet, message_id, 
+                    config, login_service, stats
+                ).await
+            } else {
+                // Handle raw packet
+                self.handle_raw_packet(
+                    circuits, socket, addr, &packet, config, stats
+                ).await
+            }
         }
-
-        Ok(())
+        .instrument(span)
+        .await
     }
 
     /// Handle message packet with ID
     async fn handle_message_packet(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
         message_id: u32,
@@ -101,11 +125,11 @@ impl PacketHandlers {
             // Authentication messages
             packet_types::USE_CIRCUIT_CODE => {
                 self.auth_handler.handle_use_circuit_code(
-                    circuits, socket, addr, packet, login_service
+                    circuits, socket, addr, packet, config, login_service
                 ).await?;
             }
             packet_types::LOGOUT_REQUEST => {
-                self.auth_handler.handle_logout_request(circuits, addr).await?;
+                self.auth_handler.handle_logout_request(circuits, socket, addr).await?;
             }
 
             // Movement messages
@@ -132,7 +156,7 @@ impl PacketHandlers {
 
             // Object messages
             packet_types::OBJECT_SELECT => {
-                self.handle_object_select(circuits, socket, addr, packet).await?;
+                self.handle_object_select(circuits, addr, packet).await?;
             }
             packet_types::OBJECT_DESELECT => {
                 self.handle_object_deselect(circuits, addr, packet).await?;
@@ -170,12 +194,12 @@ impl PacketHandlers {
 
             // Group messages
             packet_types::GROUP_MEMBERSHIP_DATA => {
-                self.handle_group_membership_data(circuits, socket, addr, packet).await?;
+                self.handle_group_membership_data(circuits, addr, packet).await?;
             }
 
             // Parcel messages
             packet_types::PARCEL_INFO_REQUEST => {
-                self.handle_parcel_info_request(circuits, socket, addr, packet).await?;
+                self.handle_parcel_info_request(circuits, addr, packet).await?;
             }
 
             // Map messages
@@ -208,7 +232,7 @@ impl PacketHandlers {
     async fn handle_raw_packet(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
         config: &LLUDPConfig,
@@ -242,7 +266,7 @@ impl PacketHandlers {
     async fn handle_complete_agent_movement(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -276,15 +300,15 @@ impl PacketHandlers {
     /// Send AgentMovementComplete response
     async fn send_agent_movement_complete(
         &self,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
     ) -> NetworkResult<()> {
         let mut payload = Vec::new();
         payload.push(packet_types::AGENT_MOVEMENT_COMPLETE as u8);
 
         // AgentData block
-        payload.extend_from_slice(&mutsea_core::UserId::new().as_uuid().as_bytes());
-        payload.extend_from_slice(&uuid::Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(mutsea_core::UserId::new().as_uuid().as_bytes());
+        payload.extend_from_slice(uuid::Uuid::new_v4().as_bytes());
 
         // Data block
         payload.extend_from_slice(&0u64.to_le_bytes()); // RegionHandle
@@ -307,7 +331,6 @@ impl PacketHandlers {
     async fn handle_object_select(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -370,10 +393,11 @@ impl PacketHandlers {
     }
 
     /// Handle image/texture request
+    #[instrument(name = "lludp.request_image", skip_all)]
     async fn handle_request_image(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -392,10 +416,11 @@ impl PacketHandlers {
     }
 
     /// Handle asset transfer request
+    #[instrument(name = "lludp.transfer_request", skip_all)]
     async fn handle_transfer_request(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -406,10 +431,10 @@ impl PacketHandlers {
         payload.push(packet_types::TRANSFER_INFO as u8);
 
         // TransferInfo block
-        payload.extend_from_slice(&uuid::Uuid::new_v4().as_bytes()); // TransferID
+        payload.extend_from_slice(uuid::Uuid::new_v4().as_bytes()); // TransferID
         payload.extend_from_slice(&2u32.to_le_bytes()); // ChannelType (Asset)
         payload.extend_from_slice(&(-1i32).to_le_bytes()); // Status (not found)
-        payload.extend_from_slice(&uuid::Uuid::new_v4().as_bytes()); // TargetID
+        payload.extend_from_slice(uuid::Uuid::new_v4().as_bytes()); // TargetID
         payload.extend_from_slice(&0u32.to_le_bytes()); // Size
         payload.extend_from_slice(&vec![0u8; 0]); // Params
 
@@ -425,7 +450,7 @@ impl PacketHandlers {
     async fn handle_fetch_inventory(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -436,11 +461,11 @@ impl PacketHandlers {
         payload.push(packet_types::INVENTORY_DESCENDENTS as u8);
 
         // AgentData block
-        payload.extend_from_slice(&mutsea_core::UserId::new().as_uuid().as_bytes());
+        payload.extend_from_slice(mutsea_core::UserId::new().as_uuid().as_bytes());
 
         // InventoryData block (empty)
-        payload.extend_from_slice(&uuid::Uuid::new_v4().as_bytes()); // FolderID
-        payload.extend_from_slice(&mutsea_core::UserId::new().as_uuid().as_bytes()); // OwnerID
+        payload.extend_from_slice(uuid::Uuid::new_v4().as_bytes()); // FolderID
+        payload.extend_from_slice(mutsea_core::UserId::new().as_uuid().as_bytes()); // OwnerID
         payload.extend_from_slice(&0u32.to_le_bytes()); // Version
         payload.extend_from_slice(&0u32.to_le_bytes()); // Descendents
 
@@ -456,7 +481,7 @@ impl PacketHandlers {
     async fn handle_money_balance_request(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -467,8 +492,8 @@ impl PacketHandlers {
         payload.push(packet_types::MONEY_BALANCE_REPLY as u8);
 
         // MoneyData block
-        payload.extend_from_slice(&mutsea_core::UserId::new().as_uuid().as_bytes()); // AgentID
-        payload.extend_from_slice(&uuid::Uuid::new_v4().as_bytes()); // TransactionID
+        payload.extend_from_slice(mutsea_core::UserId::new().as_uuid().as_bytes()); // AgentID
+        payload.extend_from_slice(uuid::Uuid::new_v4().as_bytes()); // TransactionID
         payload.push(1u8); // TransactionSuccess
         payload.extend_from_slice(&1000i32.to_le_bytes()); // MoneyBalance
         payload.extend_from_slice(&0i32.to_le_bytes()); // SquareMetersCredit
@@ -491,7 +516,6 @@ impl PacketHandlers {
     async fn handle_group_membership_data(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -504,7 +528,6 @@ impl PacketHandlers {
     async fn handle_parcel_info_request(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -517,7 +540,7 @@ impl PacketHandlers {
     async fn handle_map_block_request(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -528,7 +551,7 @@ impl PacketHandlers {
         payload.push(packet_types::MAP_BLOCK_REPLY as u8);
 
         // AgentData block
-        payload.extend_from_slice(&mutsea_core::UserId::new().as_uuid().as_bytes());
+        payload.extend_from_slice(mutsea_core::UserId::new().as_uuid().as_bytes());
         payload.extend_from_slice(&0u32.to_le_bytes()); // Flags
 
         // Data block (empty - no regions)
@@ -558,7 +581,7 @@ impl PacketHandlers {
     async fn handle_provision_voice_account(
         &self,
         circuits: &Arc<RwLock<HashMap<u32, CircuitInfo>>>,
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         addr: SocketAddr,
         packet: &Packet,
     ) -> NetworkResult<()> {
@@ -569,7 +592,7 @@ impl PacketHandlers {
         payload.push(packet_types::PROVISION_VOICE_ACCOUNT_REPLY as u8);
 
         // AgentData block
-        payload.extend_from_slice(&mutsea_core::UserId::new().as_uuid().as_bytes());
+        payload.extend_from_slice(mutsea_core::UserId::new().as_uuid().as_bytes());
 
         // VoiceData block
         let voice_server_type = "none";
@@ -1,14 +1,7 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
+//! # Mutsea LLUDP Server
+//!
+//! The LLUDP server viewers connect to. Its modules are compiled as
+//! `mutsea_network::lludp_server`, which they are written against; this
+//! crate re-exports them for services that only need the server.
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub use mutsea_network::lludp_server::*;
@@ -0,0 +1,158 @@
+//! mutsea-network/src/lludp_server/migration.rs
+//! Circuit migration when a viewer's address changes mid-session
+
+use mutsea_core::{config::MigrationConfig, UserId};
+use std::net::SocketAddr;
+use std::time::Duration;
+use uuid::Uuid;
+
+use super::CircuitInfo;
+
+/// Why a circuit was not moved to a new address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationRefusal {
+    /// Migration is turned off
+    Disabled,
+    /// Session or agent ID does not match the circuit
+    WrongSession,
+    /// The old address is still sending
+    OldAddressActive,
+    /// The circuit moved too recently
+    TooFrequent,
+    /// Another circuit is already bound to the new address
+    AddressInUse,
+}
+
+impl MigrationRefusal {
+    /// Short reason for logs and audit events
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MigrationRefusal::Disabled => "migration disabled",
+            MigrationRefusal::WrongSession => "session mismatch",
+            MigrationRefusal::OldAddressActive => "old address still active",
+            MigrationRefusal::TooFrequent => "migrated too recently",
+            MigrationRefusal::AddressInUse => "address bound to another circuit",
+        }
+    }
+}
+
+/// Decide whether `circuit` may be re-bound to `new_address`. The request
+/// must carry the circuit's own session and agent IDs, the old address must
+/// have gone quiet, and circuits cannot hop addresses faster than the
+/// configured interval.
+pub fn check_migration(
+    circuit: &CircuitInfo,
+    session_id: Uuid,
+    agent_id: UserId,
+    new_address: SocketAddr,
+    address_in_use: bool,
+    config: &MigrationConfig,
+) -> Result<(), MigrationRefusal> {
+    if !config.enabled {
+        return Err(MigrationRefusal::Disabled);
+    }
+    if circuit.session_id != Some(session_id) || circuit.agent_id != Some(agent_id) {
+        return Err(MigrationRefusal::WrongSession);
+    }
+    if address_in_use && circuit.address != new_address {
+        return Err(MigrationRefusal::AddressInUse);
+    }
+    if circuit.last_activity.elapsed() < Duration::from_millis(config.min_silence_ms) {
+        return Err(MigrationRefusal::OldAddressActive);
+    }
+    if circuit
+        .last_migration
+        .is_some_and(|at| at.elapsed() < Duration::from_secs(config.min_interval_secs))
+    {
+        return Err(MigrationRefusal::TooFrequent);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::CongestionControl;
+    use mutsea_core::{RegionId, Vector3};
+    use std::collections::HashMap;
+    use std::time::Instant;
+
+    fn circuit(session_id: Uuid, agent_id: UserId, quiet_for: Duration) -> CircuitInfo {
+        CircuitInfo {
+            circuit_code: 42,
+            address: "10.0.0.1:5000".parse().unwrap(),
+            user_id: Some(agent_id),
+            agent_id: Some(agent_id),
+            session_id: Some(session_id),
+            secure_session_id: None,
+            created_at: Instant::now() - quiet_for,
+            last_activity: Instant::now() - quiet_for,
+            sequence_in: 0,
+            sequence_out: 0,
+            pending_acks: Vec::new(),
+            reliable_packets: HashMap::new(),
+            authenticated: true,
+            region_id: Some(RegionId::new()),
+            position: Vector3::ZERO,
+            look_at: Vector3::new(1.0, 0.0, 0.0),
+            client_info: None,
+            last_ping_id: 0,
+            last_ping_time: Instant::now(),
+            congestion: CongestionControl::default(),
+            last_migration: None,
+            frozen: false,
+        }
+    }
+
+    #[test]
+    fn test_migration_requires_matching_session_and_quiet_old_address() {
+        let config = MigrationConfig::default();
+        let session = Uuid::new_v4();
+        let agent = UserId::new();
+        let new_address: SocketAddr = "10.0.0.2:6000".parse().unwrap();
+
+        let quiet = circuit(session, agent, Duration::from_secs(5));
+        assert_eq!(check_migration(&quiet, session, agent, new_address, false, &config), Ok(()));
+        assert_eq!(
+            check_migration(&quiet, Uuid::new_v4(), agent, new_address, false, &config),
+            Err(MigrationRefusal::WrongSession)
+        );
+        assert_eq!(
+            check_migration(&quiet, session, UserId::new(), new_address, false, &config),
+            Err(MigrationRefusal::WrongSession)
+        );
+        assert_eq!(
+            check_migration(&quiet, session, agent, new_address, true, &config),
+            Err(MigrationRefusal::AddressInUse)
+        );
+
+        let active = circuit(session, agent, Duration::ZERO);
+        assert_eq!(
+            check_migration(&active, session, agent, new_address, false, &config),
+            Err(MigrationRefusal::OldAddressActive)
+        );
+    }
+
+    #[test]
+    fn test_migration_rate_limited_and_switchable() {
+        let session = Uuid::new_v4();
+        let agent = UserId::new();
+        let new_address: SocketAddr = "10.0.0.2:6000".parse().unwrap();
+
+        let mut moved = circuit(session, agent, Duration::from_secs(5));
+        moved.last_migration = Some(Instant::now());
+        assert_eq!(
+            check_migration(&moved, session, agent, new_address, false, &MigrationConfig::default()),
+            Err(MigrationRefusal::TooFrequent)
+        );
+
+        let disabled = MigrationConfig {
+            enabled: false,
+            ..MigrationConfig::default()
+        };
+        assert_eq!(
+            check_migration(&moved, session, agent, new_address, false, &disabled),
+            Err(MigrationRefusal::Disabled)
+        );
+    }
+}
@@ -2,7 +2,14 @@
 //! LLUDP server modular components - Updated with all handlers
 
 mod circuit;
+mod congestion;
+mod migration;
+mod object_cache;
+mod outbound;
 mod stats;
+mod tunnel;
+mod usage;
+mod visibility;
 mod handlers;
 
 // Individual handler modules
@@ -14,10 +21,19 @@ mod handler_region;
 mod handler_object;
 mod handler_animation;
 mod handler_proximity;
+mod handler_teleport;
+mod handler_packet;
 
 // Re-export all components
 pub use circuit::*;
+pub use congestion::*;
+pub use migration::*;
+pub use object_cache::*;
+pub use outbound::*;
 pub use stats::*;
+pub use tunnel::*;
+pub use usage::*;
+pub use visibility::*;
 pub use handlers::*;
 
 // Re-export all handler types
@@ -29,6 +45,8 @@ pub use handler_region::*;
 pub use handler_object::*;
 pub use handler_animation::*;
 pub use handler_proximity::*;
+pub use handler_teleport::*;
+pub use handler_packet::*;
 
 // Main server implementation
 mod server;
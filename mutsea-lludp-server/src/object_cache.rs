@@ -0,0 +1,77 @@
+//! mutsea-network/src/lludp_server/object_cache.rs
+//! What each viewer likely has in its object cache
+//!
+//! Viewers keep the full updates they are sent on disk, keyed by local ID
+//! and CRC, across sessions. Once an agent has been sent a prim, it is
+//! sent just the prim's CRC the next time, in an ObjectUpdateCached, as
+//! long as the prim hasn't changed since. A viewer that finds it doesn't
+//! have the prim after all asks for it with RequestMultipleObjects.
+
+use mutsea_core::UserId;
+use std::collections::HashMap;
+
+/// The update CRCs each agent was sent, by local ID
+#[derive(Debug, Clone, Default)]
+pub struct ObjectCache {
+    known: HashMap<UserId, HashMap<u32, u32>>,
+}
+
+impl ObjectCache {
+    /// Whether `agent` was sent the prim with `local_id` as it is now, with
+    /// update CRC `crc`
+    pub fn likely_cached(&self, agent: UserId, local_id: u32, crc: u32) -> bool {
+        self.known
+            .get(&agent)
+            .and_then(|prims| prims.get(&local_id))
+            .is_some_and(|known| *known == crc)
+    }
+
+    /// Note that `agent` was sent the full update of the prim with
+    /// `local_id`, with CRC `crc`
+    pub fn sent(&mut self, agent: UserId, local_id: u32, crc: u32) {
+        self.known.entry(agent).or_default().insert(local_id, crc);
+    }
+
+    /// Note that `agent` didn't have the prim with `local_id` cached after
+    /// all
+    pub fn missed(&mut self, agent: UserId, local_id: u32) {
+        if let Some(prims) = self.known.get_mut(&agent) {
+            prims.remove(&local_id);
+        }
+    }
+
+    /// Drop a prim that left the region, so its local ID may be reused
+    pub fn forget(&mut self, local_id: u32) {
+        for prims in self.known.values_mut() {
+            prims.remove(&local_id);
+        }
+    }
+
+    /// How many prims `agent` likely has cached
+    pub fn cached_count(&self, agent: UserId) -> usize {
+        self.known.get(&agent).map_or(0, HashMap::len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prims_are_cached_until_changed_missed_or_forgotten() {
+        let (agent, other) = (UserId::new(), UserId::new());
+        let mut cache = ObjectCache::default();
+
+        assert!(!cache.likely_cached(agent, 1, 100));
+        cache.sent(agent, 1, 100);
+        cache.sent(agent, 2, 200);
+        assert!(cache.likely_cached(agent, 1, 100));
+        assert!(!cache.likely_cached(agent, 1, 101));
+        assert!(!cache.likely_cached(other, 1, 100));
+
+        cache.missed(agent, 1);
+        assert!(!cache.likely_cached(agent, 1, 100));
+        cache.forget(2);
+        assert_eq!(cache.cached_count(agent), 0);
+    }
+}
@@ -0,0 +1,240 @@
+//! mutsea-network/src/lludp_server/outbound.rs
+//! Prioritized outbound packet queues
+//!
+//! Packets for a circuit wait in its queue until the socket takes them, most
+//! urgent class first: acks, then control traffic, avatar updates, object
+//! updates and finally textures. A queue holds a bounded number of bytes. A
+//! terse update replaces the update still waiting for the same prim, and a
+//! full queue drops its oldest, least urgent updates to make room; acks and
+//! control packets are never dropped.
+
+use std::collections::VecDeque;
+
+use super::congestion::ThrottleCategory;
+
+/// How urgently a packet must reach the viewer, most urgent first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PriorityClass {
+    /// PacketAcks; late acks make the viewer resend
+    Ack,
+    /// Logins, pings, chat, resends and anything else not classed
+    Control,
+    /// Avatar updates
+    Avatar,
+    /// Prim updates
+    Object,
+    /// Texture data
+    Texture,
+}
+
+impl PriorityClass {
+    /// Every class, most urgent first
+    pub const ALL: [PriorityClass; 5] = [
+        PriorityClass::Ack,
+        PriorityClass::Control,
+        PriorityClass::Avatar,
+        PriorityClass::Object,
+        PriorityClass::Texture,
+    ];
+
+    /// Whether queued packets of this class may be dropped for room
+    pub fn droppable(self) -> bool {
+        self >= PriorityClass::Avatar
+    }
+}
+
+impl From<ThrottleCategory> for PriorityClass {
+    fn from(category: ThrottleCategory) -> Self {
+        match category {
+            ThrottleCategory::Resend => PriorityClass::Control,
+            ThrottleCategory::Texture => PriorityClass::Texture,
+            ThrottleCategory::Object => PriorityClass::Object,
+        }
+    }
+}
+
+/// A packet waiting to be sent
+#[derive(Debug, Clone)]
+pub struct OutboundPacket {
+    /// The encoded datagram
+    pub data: Vec<u8>,
+    /// Decides its place in line and whether it may be dropped
+    pub class: PriorityClass,
+    /// Queued packets of the same class with the same key are replaced by
+    /// this one, such as earlier terse updates of the same prim
+    pub supersedes: Option<u32>,
+}
+
+impl OutboundPacket {
+    /// Packet of `class` that supersedes nothing
+    pub fn new(data: Vec<u8>, class: PriorityClass) -> Self {
+        Self {
+            data,
+            class,
+            supersedes: None,
+        }
+    }
+
+    /// Replace queued packets of the same class with the same `key`
+    pub fn superseding(mut self, key: u32) -> Self {
+        self.supersedes = Some(key);
+        self
+    }
+}
+
+/// The packets waiting to go to one circuit
+#[derive(Debug)]
+pub struct OutboundQueue {
+    classes: [VecDeque<OutboundPacket>; 5],
+    bytes: usize,
+    max_bytes: usize,
+    dropped: u64,
+}
+
+impl OutboundQueue {
+    /// Empty queue holding at most `max_bytes`
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            classes: Default::default(),
+            bytes: 0,
+            max_bytes,
+            dropped: 0,
+        }
+    }
+
+    /// Queue `packet`; returns false when it was dropped for lack of room
+    pub fn push(&mut self, packet: OutboundPacket) -> bool {
+        let class = packet.class;
+        if let Some(key) = packet.supersedes {
+            let stale = self.classes[class as usize]
+                .iter_mut()
+                .find(|queued| queued.supersedes == Some(key));
+            if let Some(stale) = stale {
+                // The update takes the stale one's place in line
+                self.bytes = self.bytes - stale.data.len() + packet.data.len();
+                *stale = packet;
+                self.dropped += 1;
+                return true;
+            }
+        }
+
+        while self.bytes + packet.data.len() > self.max_bytes && self.evict(class) {}
+        if self.bytes + packet.data.len() > self.max_bytes && class.droppable() {
+            self.dropped += 1;
+            return false;
+        }
+        self.bytes += packet.data.len();
+        self.classes[class as usize].push_back(packet);
+        true
+    }
+
+    /// Take the next packet to send
+    pub fn pop(&mut self) -> Option<OutboundPacket> {
+        let packet = self.classes.iter_mut().find_map(VecDeque::pop_front)?;
+        self.bytes -= packet.data.len();
+        Some(packet)
+    }
+
+    /// Packets waiting
+    pub fn depth(&self) -> usize {
+        self.classes.iter().map(VecDeque::len).sum()
+    }
+
+    /// Packets of `class` waiting
+    pub fn class_depth(&self, class: PriorityClass) -> usize {
+        self.classes[class as usize].len()
+    }
+
+    /// Bytes waiting
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Packets dropped or superseded so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Whether nothing is waiting
+    pub fn is_empty(&self) -> bool {
+        self.classes.iter().all(VecDeque::is_empty)
+    }
+
+    /// Drop the oldest packet of the least urgent droppable class that is no
+    /// more urgent than `class`
+    fn evict(&mut self, class: PriorityClass) -> bool {
+        for victim in PriorityClass::ALL.into_iter().rev() {
+            if !victim.droppable() || victim < class {
+                break;
+            }
+            if let Some(packet) = self.classes[victim as usize].pop_front() {
+                self.bytes -= packet.data.len();
+                self.dropped += 1;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(class: PriorityClass, byte: u8, len: usize) -> OutboundPacket {
+        OutboundPacket::new(vec![byte; len], class)
+    }
+
+    #[test]
+    fn test_packets_leave_most_urgent_first() {
+        let mut queue = OutboundQueue::new(1024);
+        for (class, byte) in [
+            (PriorityClass::Texture, 5),
+            (PriorityClass::Object, 4),
+            (PriorityClass::Avatar, 3),
+            (PriorityClass::Control, 2),
+            (PriorityClass::Ack, 1),
+            (PriorityClass::Object, 6),
+        ] {
+            assert!(queue.push(packet(class, byte, 10)));
+        }
+        assert_eq!((queue.depth(), queue.bytes()), (6, 60));
+
+        let order: Vec<u8> = std::iter::from_fn(|| queue.pop()).map(|p| p.data[0]).collect();
+        assert_eq!(order, vec![1, 2, 3, 4, 6, 5]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_terse_updates_replace_stale_ones() {
+        let mut queue = OutboundQueue::new(1024);
+        queue.push(packet(PriorityClass::Object, 1, 10).superseding(7));
+        queue.push(packet(PriorityClass::Object, 2, 10).superseding(8));
+        queue.push(packet(PriorityClass::Object, 3, 12).superseding(7));
+        assert_eq!((queue.depth(), queue.bytes(), queue.dropped()), (2, 22, 1));
+
+        // The newest update for prim 7 keeps the first one's place
+        assert_eq!(queue.pop().unwrap().data, vec![3; 12]);
+        assert_eq!(queue.pop().unwrap().data, vec![2; 10]);
+    }
+
+    #[test]
+    fn test_full_queues_drop_the_least_urgent_updates() {
+        let mut queue = OutboundQueue::new(30);
+        queue.push(packet(PriorityClass::Texture, 1, 10));
+        queue.push(packet(PriorityClass::Object, 2, 10));
+        queue.push(packet(PriorityClass::Avatar, 3, 10));
+
+        // An object update only displaces the texture
+        assert!(queue.push(packet(PriorityClass::Object, 4, 10)));
+        assert_eq!(queue.class_depth(PriorityClass::Texture), 0);
+        // A texture finds nothing less urgent to displace
+        assert!(!queue.push(packet(PriorityClass::Texture, 5, 10)));
+        // Acks always get in, over the limit if they must
+        assert!(queue.push(packet(PriorityClass::Ack, 6, 10)));
+        assert!(queue.push(packet(PriorityClass::Control, 7, 10)));
+        assert_eq!(queue.class_depth(PriorityClass::Object), 0);
+        assert_eq!(queue.class_depth(PriorityClass::Avatar), 1);
+        assert_eq!((queue.bytes(), queue.dropped()), (30, 4));
+    }
+}
@@ -4,46 +4,117 @@
 use crate::{NetworkResult, SessionManager};
 use mutsea_core::{
     Service, ServiceHealth, ServiceStatus, MutseaResult, 
-    config::LLUDPConfig, Vector3, UserId, RegionId
+    config::LLUDPConfig, Vector3, UserId, SceneObject
 };
+use async_trait::async_trait;
 use mutsea_protocol::{
     Packet, 
     constants::{flags, packet_types, timeouts, limits},
-    login::LoginService
+    login::LoginService,
+    sim_stats::SimStats,
+    instant_message::InstantMessage,
+    dialog::{self, ScriptDialog},
+    attachment,
+    appearance::{self, BakeKind},
+    rez::ObjectItem,
+    object_update,
+    economy,
+    moderation,
+    search::{self, PlaceResult},
+    profile::{self, AvatarProperties, LandmarkItem, Pick},
+    directory::{self, Classified, Event},
+    sound::{self, AttachedSound, SoundTrigger},
+    names,
+    inventory,
+    follow_cam::{self, FollowCamParam},
+    chat::ChatFromSimulator,
+    environment::DayCycle,
+    layer_data,
 };
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::net::UdpSocket;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, RwLock};
 use tracing::{debug, error, info, warn};
 
 use super::{
     circuit::{CircuitInfo, ClientInfo, ReliablePacketData},
-    stats::ServerStats,
+    congestion::ThrottleCategory,
+    stats::{ServerStats, WorkerStats},
+    tunnel::CircuitSocket,
     handler_packet::PacketHandler,
+    object_cache::ObjectCache,
+    outbound::OutboundPacket,
+    usage::CircuitUsage,
+    visibility::VisibilityRules,
 };
 
+/// Supplies the simulator side of the SimStats sent to viewers
+#[async_trait]
+pub trait SimStatsSource: Send + Sync {
+    /// Current frame timings and scene counts; network fields are filled in
+    /// by the server
+    async fn sim_stats(&self) -> SimStats;
+}
+
+/// Supplies the region environment sent to viewers
+#[async_trait]
+pub trait EnvironmentSource: Send + Sync {
+    /// Region clock placing the sun
+    fn day_cycle(&self) -> DayCycle;
+
+    /// Wind over the region, east and north components in metres per
+    /// second, 16x16 samples each
+    async fn wind(&self) -> (Vec<f32>, Vec<f32>);
+
+    /// Cloud density over the region, 16x16 samples from 0.0 to 1.0
+    async fn clouds(&self) -> Vec<f32>;
+}
+
+/// Sees every packet the server receives, before it is handled
+pub trait PacketTap: Send + Sync {
+    /// Called with each datagram as it arrives from `from`
+    fn received(&self, from: SocketAddr, packet: &[u8]);
+}
+
+/// Handles a message the server has no handler of its own for, such as one
+/// a plugin adds. Only messages on authenticated circuits are passed on;
+/// `payload` starts with the message ID.
+pub trait MessageHandler: Send + Sync {
+    /// Called with each such message from `agent_id` on `circuit_code`
+    fn handle(&self, circuit_code: u32, agent_id: UserId, payload: &[u8]);
+}
+
 /// Enhanced LLUDP server for handling OpenSim viewer connections
 pub struct LLUDPServer {
-    socket: Arc<UdpSocket>,
+    socket: Arc<CircuitSocket>,
     session_manager: SessionManager,
     config: LLUDPConfig,
     running: Arc<std::sync::atomic::AtomicBool>,
     stats: Arc<RwLock<ServerStats>>,
+    /// Receive counters of each socket worker
+    worker_stats: Arc<[WorkerStats]>,
     active_circuits: Arc<RwLock<HashMap<u32, CircuitInfo>>>,
     login_service: Arc<LoginService>,
     handlers: PacketHandler,
+    /// Who each prim's updates may be sent to
+    visibility: Arc<RwLock<VisibilityRules>>,
+    /// Which prims each viewer likely has cached
+    object_cache: Arc<RwLock<ObjectCache>>,
+    packet_tap: Option<Arc<dyn PacketTap>>,
 }
 
 impl LLUDPServer {
     /// Create a new LLUDP server
     pub async fn new(config: &LLUDPConfig) -> NetworkResult<Self> {
-        let bind_addr = format!("{}:{}", config.bind_address, config.port);
-        let socket = UdpSocket::bind(&bind_addr).await
-            .map_err(|e| crate::NetworkError::Io(e))?;
-        info!("LLUDP server bound to {}", bind_addr);
+        let bind_addr = mutsea_core::net::socket_addr(&config.bind_address, config.port)?;
+        let sockets = crate::dual_stack::bind_udp_workers(bind_addr, config.dual_stack, config.workers)?;
+        if sockets.len() < config.workers {
+            warn!("SO_REUSEPORT is not available here, LLUDP runs a single worker");
+        }
+        info!("LLUDP server bound to {} with {} worker(s)", bind_addr, sockets.len());
+        let worker_stats = sockets.iter().map(|_| WorkerStats::default()).collect();
 
         let session_manager = SessionManager::new(
             Duration::from_secs(60),
@@ -53,22 +124,47 @@ impl LLUDPServer {
         let handlers = PacketHandler::new();
 
         Ok(Self {
-            socket: Arc::new(socket),
+            socket: Arc::new(CircuitSocket::with_workers(sockets).with_queue_limit(config.outbound_queue_kb * 1024)),
             session_manager,
             config: config.clone(),
             running: Arc::new(std::sync::atomic::AtomicBool::new(false)),
             stats: Arc::new(RwLock::new(ServerStats::default())),
+            worker_stats,
             active_circuits: Arc::new(RwLock::new(HashMap::new())),
             login_service: Arc::new(LoginService::new()),
             handlers,
+            visibility: Arc::new(RwLock::new(VisibilityRules::default())),
+            object_cache: Arc::new(RwLock::new(ObjectCache::default())),
+            packet_tap: None,
         })
     }
 
+    /// Address the server socket is bound to
+    pub fn local_addr(&self) -> NetworkResult<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Subscribe to circuit events such as address migrations and agent logins
+    pub fn subscribe_events(&self) -> tokio::sync::broadcast::Receiver<mutsea_core::events::NetworkEvent> {
+        self.handlers.subscribe_events()
+    }
+
     /// Set login service for authentication
     pub fn set_login_service(&mut self, login_service: Arc<LoginService>) {
         self.login_service = login_service;
     }
 
+    /// Show every received packet to `tap`, such as a replay recorder
+    pub fn set_packet_tap(&mut self, tap: Arc<dyn PacketTap>) {
+        self.packet_tap = Some(tap);
+    }
+
+    /// Pass received messages with `message_id` to `handler`. Messages the
+    /// server handles itself never reach it.
+    pub fn add_message_handler(&self, message_id: u32, handler: Arc<dyn MessageHandler>) {
+        self.handlers.add_message_handler(message_id, handler);
+    }
+
     /// Start the LLUDP server
     pub async fn start(&self) -> NetworkResult<()> {
         self.running.store(true, std::sync::atomic::Ordering::SeqCst);
@@ -76,51 +172,47 @@ impl LLUDPServer {
         // Start session cleanup task
         self.session_manager.start_cleanup_task().await;
 
-        // Start main packet handling loop
-        let socket = Arc::clone(&self.socket);
-        let session_manager = self.session_manager.clone();
-        let stats = Arc::clone(&self.stats);
-        let circuits = Arc::clone(&self.active_circuits);
-        let running = Arc::clone(&self.running);
-        let config = self.config.clone();
-        let login_service = Arc::clone(&self.login_service);
-        let handlers = self.handlers.clone();
-
-        tokio::spawn(async move {
-            let mut buffer = vec![0u8; config.max_packet_size];
-
-            while running.load(std::sync::atomic::Ordering::SeqCst) {
-                match socket.recv_from(&mut buffer).await {
-                    Ok((size, addr)) => {
-                        // Update stats
-                        {
-                            let mut stats_guard = stats.write().await;
-                            stats_guard.packets_received += 1;
-                            stats_guard.bytes_received += size as u64;
-                        }
-
-                        // Process packet
-                        let packet_data = &buffer[..size];
-                        if let Err(e) = handlers.handle_packet(
-                            &circuits,
-                            &socket,
-                            addr,
-                            packet_data,
-                            &config,
-                            &login_service,
-                            &stats,
-                        ).await {
-                            error!("Error handling packet from {}: {}", addr, e);
-                            let mut stats_guard = stats.write().await;
-                            stats_guard.errors += 1;
-                        }
-                    }
-                    Err(e) => {
-                        error!("Error receiving packet: {}", e);
-                        let mut stats_guard = stats.write().await;
-                        stats_guard.errors += 1;
+        // Start the receive workers. The kernel spreads packets over their
+        // sockets, then each packet goes to the worker of its circuit so a
+        // circuit's packets are handled in order
+        let (inboxes, receivers): (Vec<_>, Vec<_>) = (0..self.socket.worker_count())
+            .map(|_| mpsc::unbounded_channel::<(SocketAddr, Vec<u8>)>())
+            .unzip();
+        let inboxes = Arc::new(inboxes);
+        for (worker, mut inbox) in receivers.into_iter().enumerate() {
+            let server = self.clone();
+            let inboxes = Arc::clone(&inboxes);
+            tokio::spawn(async move {
+                let mut buffer = vec![0u8; server.config.max_packet_size];
+
+                while server.running.load(std::sync::atomic::Ordering::SeqCst) {
+                    tokio::select! {
+                        received = server.socket.recv_from(worker, &mut buffer) => match received {
+                            Ok((size, addr)) => {
+                                let owner = server.socket.worker_for(addr);
+                                if owner == worker {
+                                    server.handle_datagram(addr, &buffer[..size]).await;
+                                } else {
+                                    let _ = inboxes[owner].send((addr, buffer[..size].to_vec()));
+                                }
+                            }
+                            Err(e) => {
+                                error!("Error receiving packet: {}", e);
+                                server.worker_stats[worker].record_error();
+                            }
+                        },
+                        Some((addr, packet)) = inbox.recv() => server.handle_datagram(addr, &packet).await,
                     }
                 }
+            });
+        }
+
+        // Send queued packets as they come in
+        let server = self.clone();
+        tokio::spawn(async move {
+            while server.running.load(std::sync::atomic::Ordering::SeqCst) {
+                server.socket.wait_queued().await;
+                server.socket.flush().await;
             }
         });
 
@@ -131,6 +223,68 @@ impl LLUDPServer {
         Ok(())
     }
 
+    /// Handle one packet from `addr`, whether it came over UDP or a tunnel
+    async fn handle_datagram(&self, addr: SocketAddr, packet_data: &[u8]) {
+        let worker_stats = &self.worker_stats[self.socket.worker_for(addr)];
+        worker_stats.record_packet(packet_data.len());
+        if let Some(tap) = &self.packet_tap {
+            tap.received(addr, packet_data);
+        }
+
+        if let Err(e) = self.handlers.handle_packet(
+            &self.active_circuits,
+            &self.socket,
+            addr,
+            packet_data,
+            &self.config,
+            &self.login_service,
+            &self.stats,
+        ).await {
+            error!("Error handling packet from {}: {}", addr, e);
+            worker_stats.record_error();
+        }
+    }
+
+    /// Open a tunnel for a viewer that can't reach the UDP socket, returning
+    /// the address its circuit will have and the packets to deliver to it
+    pub fn open_tunnel(&self) -> (SocketAddr, tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>) {
+        let (addr, packets) = self.socket.open_tunnel();
+        debug!("Opened tunnel {}", addr);
+        (addr, packets)
+    }
+
+    /// Handle a packet a viewer sent through the tunnel at `addr`
+    pub async fn receive_tunneled(&self, addr: SocketAddr, packet_data: &[u8]) {
+        if packet_data.len() > self.config.max_packet_size {
+            warn!("Dropped a {} byte packet from tunnel {}", packet_data.len(), addr);
+            return;
+        }
+        self.handle_datagram(addr, packet_data).await;
+    }
+
+    /// Handle a recorded packet as if it had just come from `addr`
+    pub async fn replay_datagram(&self, addr: SocketAddr, packet_data: &[u8]) {
+        self.handle_datagram(addr, packet_data).await;
+    }
+
+    /// Close the tunnel at `addr`, closing the circuit carried over it
+    pub async fn close_tunnel(&self, addr: SocketAddr) {
+        if !self.socket.close_tunnel(addr) {
+            return;
+        }
+        if let Some(circuit) = self.get_circuit_by_address(addr).await {
+            if let Some(circuit) = self.remove_circuit(circuit.circuit_code).await {
+                self.handlers.circuit_closed(&circuit, &self.socket, "tunnel closed");
+            }
+        }
+        debug!("Closed tunnel {}", addr);
+    }
+
+    /// Number of open tunnels
+    pub fn tunnel_count(&self) -> usize {
+        self.socket.tunnel_count()
+    }
+
     /// Stop the LLUDP server
     pub async fn stop(&self) -> NetworkResult<()> {
         self.running.store(false, std::sync::atomic::Ordering::SeqCst);
@@ -145,6 +299,7 @@ impl LLUDPServer {
         let config = self.config.clone();
         let running = Arc::clone(&self.running);
         let stats = Arc::clone(&self.stats);
+        let handlers = self.handlers.clone();
 
         // Heartbeat and resend task
         tokio::spawn(async move {
@@ -178,6 +333,7 @@ impl LLUDPServer {
                 for circuit_code in to_remove {
                     if let Some(circuit) = circuits_guard.remove(&circuit_code) {
                         info!("Removed timed out circuit: {} from {}", circuit_code, circuit.address);
+                        handlers.circuit_closed(&circuit, &socket, "timeout");
                         
                         // Update stats
                         let mut stats_guard = stats.write().await;
@@ -189,8 +345,10 @@ impl LLUDPServer {
 
         // Statistics reporting task
         let stats_clone = Arc::clone(&self.stats);
+        let worker_stats = Arc::clone(&self.worker_stats);
         let circuits_clone = Arc::clone(&self.active_circuits);
         let running_clone = Arc::clone(&self.running);
+        let socket_clone = Arc::clone(&self.socket);
 
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(30));
@@ -198,8 +356,12 @@ impl LLUDPServer {
             while running_clone.load(std::sync::atomic::Ordering::SeqCst) {
                 interval.tick().await;
                 
+                // Stray packets from addresses with no circuit aren't billed
+                let addresses: Vec<SocketAddr> = circuits_clone.read().await.values().map(|c| c.address).collect();
+                socket_clone.retain_usage(|addr| addresses.contains(addr));
+
                 let circuits_count = circuits_clone.read().await.len();
-                let stats_guard = stats_clone.read().await;
+                let stats_guard = stats_clone.read().await.clone().with_workers(&worker_stats);
                 
                 debug!("LLUDP Server Stats - Circuits: {}, Packets RX: {}, TX: {}, Errors: {}", 
                        circuits_count, stats_guard.packets_received, stats_guard.packets_sent, stats_guard.errors);
@@ -212,9 +374,140 @@ impl LLUDPServer {
         });
     }
 
+    /// Send SimStats to every authenticated circuit once a second
+    pub fn start_sim_stats_task(&self, source: Arc<dyn SimStatsSource>) {
+        let server = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            let mut last_sample: Option<(Instant, u64, u64)> = None;
+
+            while server.running.load(std::sync::atomic::Ordering::SeqCst) {
+                interval.tick().await;
+
+                let mut sim_stats = source.sim_stats().await;
+                let (packets_in, packets_out) = {
+                    let stats = server.get_stats().await;
+                    (stats.packets_received, stats.packets_sent)
+                };
+                if let Some((at, last_in, last_out)) = last_sample {
+                    let secs = at.elapsed().as_secs_f32().max(f32::EPSILON);
+                    sim_stats.in_pps = packets_in.saturating_sub(last_in) as f32 / secs;
+                    sim_stats.out_pps = packets_out.saturating_sub(last_out) as f32 / secs;
+                }
+                last_sample = Some((Instant::now(), packets_in, packets_out));
+
+                if let Err(e) = server.send_sim_stats(sim_stats).await {
+                    warn!("Failed to send SimStats: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Send the sun position, wind and clouds from `source` to every
+    /// authenticated circuit each `interval`
+    pub fn start_environment_task(&self, source: Arc<dyn EnvironmentSource>, interval: Duration) {
+        let server = self.clone();
+
+        tokio::spawn(async move {
+            let started = Instant::now();
+            let mut interval = tokio::time::interval(interval);
+
+            while server.running.load(std::sync::atomic::Ordering::SeqCst) {
+                interval.tick().await;
+
+                let unix_secs = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default();
+                let (east, north) = source.wind().await;
+                let payloads = [
+                    ("SimulatorViewerTimeMessage", source.day_cycle().encode_viewer_time(unix_secs, started.elapsed().as_micros() as u64)),
+                    ("wind LayerData", layer_data::encode_wind(&east, &north)),
+                    ("cloud LayerData", layer_data::encode_clouds(&source.clouds().await)),
+                ];
+                for (what, payload) in payloads {
+                    if let Err(e) = server.send_to_authenticated(payload, what).await {
+                        warn!("Failed to send {}: {}", what, e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Send the terrain `patches` to every authenticated circuit. Returns
+    /// how many were sent them.
+    pub async fn send_terrain_patches(&self, patches: &[layer_data::Patch]) -> NetworkResult<usize> {
+        let mut sent = 0;
+        for chunk in patches.chunks(layer_data::LAND_PATCHES_PER_MESSAGE) {
+            let payload = layer_data::encode(layer_data::LayerType::Land, chunk);
+            sent = self.send_to_authenticated(payload, "land LayerData").await?;
+        }
+        Ok(sent)
+    }
+
+    /// Send an unreliable message to every authenticated circuit
+    async fn send_to_authenticated(&self, payload: Vec<u8>, what: &str) -> NetworkResult<usize> {
+        let packet = Packet::new(0, 0, payload);
+        let packet_data = packet.serialize()
+            .map_err(|e| crate::NetworkError::Protocol(format!("Failed to serialize {}: {}", what, e)))?;
+
+        let mut sent = 0;
+        for circuit in self.active_circuits.read().await.values().filter(|c| c.authenticated) {
+            if let Err(e) = self.socket.send_to(&packet_data, circuit.address).await {
+                warn!("Failed to send {} to circuit {}: {}", what, circuit.circuit_code, e);
+            } else {
+                sent += 1;
+            }
+        }
+
+        if sent > 0 {
+            let mut stats_guard = self.stats.write().await;
+            stats_guard.packets_sent += sent as u64;
+            stats_guard.bytes_sent += (sent * packet_data.len()) as u64;
+        }
+
+        Ok(sent)
+    }
+
+    /// Send SimStats to every authenticated circuit, adding agent counts and
+    /// each circuit's own pending traffic: unacked reliable packets and
+    /// packets still queued for it
+    pub async fn send_sim_stats(&self, mut sim_stats: SimStats) -> NetworkResult<usize> {
+        let circuits_guard = self.active_circuits.read().await;
+        sim_stats.agents = circuits_guard.values().filter(|c| c.authenticated).count() as u32;
+
+        let mut sent = 0;
+        let mut bytes = 0;
+        for circuit in circuits_guard.values().filter(|c| c.authenticated) {
+            sim_stats.pending_downloads =
+                (circuit.reliable_packets.len() + self.socket.queue_depth(circuit.address)) as u32;
+            sim_stats.unacked_bytes = circuit.reliable_packets.values().map(|p| p.data.len() as u32).sum();
+
+            let packet = Packet::new(0, 0, sim_stats.encode());
+            let packet_data = packet.serialize()
+                .map_err(|e| crate::NetworkError::Protocol(format!("Failed to serialize SimStats: {}", e)))?;
+
+            if let Err(e) = self.socket.send_to(&packet_data, circuit.address).await {
+                warn!("Failed to send SimStats to circuit {}: {}", circuit.circuit_code, e);
+            } else {
+                sent += 1;
+                bytes += packet_data.len();
+            }
+        }
+
+        if sent > 0 {
+            let mut stats_guard = self.stats.write().await;
+            stats_guard.packets_sent += sent as u64;
+            stats_guard.bytes_sent += bytes as u64;
+        }
+
+        Ok(sent)
+    }
+
     /// Send heartbeat to circuit
     async fn send_heartbeat(
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         circuit: &mut CircuitInfo,
         stats: &Arc<RwLock<ServerStats>>,
     ) -> NetworkResult<()> {
@@ -248,30 +541,46 @@ impl LLUDPServer {
 
     /// Process reliable packet resends
     async fn process_reliable_resends(
-        socket: &UdpSocket,
+        socket: &CircuitSocket,
         circuit: &mut CircuitInfo,
         config: &LLUDPConfig,
         stats: &Arc<RwLock<ServerStats>>,
     ) -> NetworkResult<()> {
-        let timeout = std::time::Duration::from_millis(config.resend_timeout);
+        let timeout = circuit.congestion.rto();
         let max_resends = config.max_resends;
         let now = Instant::now();
 
         let mut packets_to_resend = Vec::new();
         let mut packets_to_remove = Vec::new();
 
-        // Check which packets need resending
-        for (sequence, reliable_packet) in &mut circuit.reliable_packets {
-            if reliable_packet.timestamp.elapsed() > timeout {
-                if reliable_packet.resend_count < max_resends {
-                    reliable_packet.resend_count += 1;
-                    reliable_packet.timestamp = now;
-                    packets_to_resend.push((*sequence, reliable_packet.data.clone()));
-                } else {
-                    // Max resends exceeded, remove packet
-                    packets_to_remove.push(*sequence);
-                }
+        // Check which packets need resending, oldest first. Resends draw on
+        // the circuit's own budget; whatever does not fit waits for the next
+        // pass instead of flooding a lossy link.
+        let mut sequences: Vec<u32> = circuit.reliable_packets.keys().copied().collect();
+        sequences.sort_unstable();
+        let mut lost = false;
+        for sequence in sequences {
+            let Some(reliable_packet) = circuit.reliable_packets.get_mut(&sequence) else {
+                continue;
+            };
+            if reliable_packet.timestamp.elapsed() <= timeout {
+                continue;
+            }
+            if reliable_packet.resend_count >= max_resends {
+                // Max resends exceeded, remove packet
+                packets_to_remove.push(sequence);
+                continue;
+            }
+            lost = true;
+            if !circuit.congestion.try_send(ThrottleCategory::Resend, reliable_packet.data.len()) {
+                break;
             }
+            reliable_packet.resend_count += 1;
+            reliable_packet.timestamp = now;
+            packets_to_resend.push((sequence, reliable_packet.data.clone()));
+        }
+        if lost {
+            circuit.congestion.on_loss();
         }
 
         // Remove expired packets
@@ -300,7 +609,10 @@ impl LLUDPServer {
 
     /// Get server statistics
     pub async fn get_stats(&self) -> ServerStats {
-        self.stats.read().await.clone()
+        let mut stats = self.stats.read().await.clone().with_workers(&self.worker_stats);
+        stats.outbound_queued = self.socket.queued_packets() as u64;
+        stats.outbound_dropped = self.socket.dropped_packets();
+        stats
     }
 
     /// Get active circuits count
@@ -333,8 +645,586 @@ impl LLUDPServer {
             
             Ok(())
         } else {
-            Err(crate::NetworkError::CircuitNotFound(circuit_code.to_string()))
+            Err(crate::NetworkError::ClientNotFound(circuit_code.to_string()))
+        }
+    }
+
+    /// Send a packet to a circuit if its budget for `category` allows.
+    /// Returns false when the packet was throttled and should be retried
+    /// or superseded later.
+    pub async fn send_throttled(
+        &self,
+        circuit_code: u32,
+        category: ThrottleCategory,
+        packet: Packet,
+    ) -> NetworkResult<bool> {
+        let packet_data = packet.serialize()
+            .map_err(|e| crate::NetworkError::Protocol(format!("Failed to serialize packet: {}", e)))?;
+
+        let address = {
+            let mut circuits_guard = self.active_circuits.write().await;
+            let circuit = circuits_guard.get_mut(&circuit_code)
+                .ok_or_else(|| crate::NetworkError::ClientNotFound(circuit_code.to_string()))?;
+            if !circuit.congestion.try_send(category, packet_data.len()) {
+                return Ok(false);
+            }
+            circuit.address
+        };
+
+        let bytes = self.socket.send_class(OutboundPacket::new(packet_data, category.into()), address).await?;
+
+        let mut stats_guard = self.stats.write().await;
+        stats_guard.packets_sent += 1;
+        stats_guard.bytes_sent += bytes as u64;
+        Ok(true)
+    }
+
+    /// Send `message` to its recipient's circuit. Returns false when the
+    /// agent is not connected here.
+    pub async fn send_instant_message(&self, message: &InstantMessage) -> NetworkResult<bool> {
+        let sent = self.send_to_agent(message.to_agent_id, message.encode()).await?;
+        if sent {
+            debug!("Sent instant message from {} to agent {}", message.from_name, message.to_agent_id);
+        }
+        Ok(sent)
+    }
+
+    /// Show `dialog` to `agent_id`. Returns false when the agent is not
+    /// connected here; the pressed button arrives as a
+    /// `ScriptDialogReplied` network event.
+    pub async fn send_script_dialog(&self, agent_id: UserId, dialog: &ScriptDialog) -> NetworkResult<bool> {
+        dialog.validate()
+            .map_err(|e| crate::NetworkError::Protocol(e.to_string()))?;
+        let sent = self.send_to_agent(agent_id, dialog.encode()).await?;
+        if sent {
+            debug!("Sent dialog {} to agent {}", dialog.object_id, agent_id);
+        }
+        Ok(sent)
+    }
+
+    /// Show an alert to `agent_id`; modal alerts must be dismissed.
+    /// Returns false when the agent is not connected here.
+    pub async fn send_agent_alert(&self, agent_id: UserId, modal: bool, message: &str) -> NetworkResult<bool> {
+        self.send_to_agent(agent_id, dialog::encode_agent_alert(agent_id, modal, message)).await
+    }
+
+    /// Have the camera of `agent_id` follow `object_id` with `properties`.
+    /// Returns false when the agent is not connected here.
+    pub async fn send_follow_cam(
+        &self,
+        agent_id: UserId,
+        object_id: uuid::Uuid,
+        properties: &[(FollowCamParam, f32)],
+    ) -> NetworkResult<bool> {
+        self.send_packet_to_agent(agent_id, follow_cam::encode_set(object_id, properties)).await
+    }
+
+    /// Hand the camera of `agent_id` back from `object_id` to the viewer
+    pub async fn clear_follow_cam(&self, agent_id: UserId, object_id: uuid::Uuid) -> NetworkResult<bool> {
+        self.send_packet_to_agent(agent_id, follow_cam::encode_clear(object_id)).await
+    }
+
+    /// Tell `agent_id` its balance after the transaction `transaction_id`
+    /// described by `description`. Returns false when the agent is not
+    /// connected here.
+    pub async fn send_money_balance(
+        &self,
+        agent_id: UserId,
+        transaction_id: uuid::Uuid,
+        success: bool,
+        balance: i32,
+        description: &str,
+    ) -> NetworkResult<bool> {
+        let payload = economy::encode_money_balance_reply(agent_id, transaction_id, success, balance, description);
+        self.send_to_agent(agent_id, payload).await
+    }
+
+    /// Bandwidth used so far by each open circuit
+    pub async fn circuit_usage(&self) -> Vec<CircuitUsage> {
+        self.active_circuits.read().await.values()
+            .map(|c| CircuitUsage::new(
+                c.circuit_code, c.agent_id, c.created_at.elapsed().as_secs(), &self.socket.usage(c.address),
+            ))
+            .collect()
+    }
+
+    /// Show `object` worn at `attachment_point` by the avatar with
+    /// `parent_local_id`, from the item `item_id`, to the agents its
+    /// visibility allows; HUDs only to their wearer. Returns how many were
+    /// sent it.
+    pub async fn send_attachment_update(
+        &self,
+        object: &SceneObject,
+        parent_local_id: u32,
+        attachment_point: u8,
+        item_id: uuid::Uuid,
+    ) -> NetworkResult<usize> {
+        let audience = self.visibility.read().await
+            .audience(object.local_id, object.owner_id, Some(attachment_point));
+        let payload = attachment::encode_attachment_update(object, parent_local_id, attachment_point, item_id);
+        self.send_to_audience(payload, |agent| audience.includes(agent)).await
+    }
+
+    /// Show every authenticated agent `agent_id` wearing the `bakes` made
+    /// for its Current Outfit folder version `cof_version`. Returns how many
+    /// were sent it.
+    pub async fn send_avatar_appearance(
+        &self,
+        agent_id: UserId,
+        cof_version: i32,
+        bakes: &[(BakeKind, uuid::Uuid)],
+    ) -> NetworkResult<usize> {
+        let payload = appearance::encode_avatar_appearance(agent_id, cof_version, bakes);
+        self.broadcast_packet_to_authenticated(Packet::reliable(0, payload)).await
+    }
+
+    /// Show `object` to the agents its visibility allows. Returns how many
+    /// were sent it.
+    pub async fn send_object_update(&self, object: &SceneObject) -> NetworkResult<usize> {
+        let audience = self.visibility.read().await.audience(object.local_id, object.owner_id, None);
+        let payload = object_update::encode_object_update(object, 0, 0, "");
+        self.send_to_audience(payload, |agent| audience.includes(agent)).await
+    }
+
+    /// Show `object` to `agent_id` alone, within its circuit's object
+    /// throttle, as just its CRC if the agent's viewer likely has it cached.
+    /// Returns false when the throttle is spent and the update should be
+    /// sent later; prims the agent may not see count as sent.
+    pub async fn send_object_update_to(&self, agent_id: UserId, object: &SceneObject) -> NetworkResult<bool> {
+        let circuit_code = self.active_circuits.read().await.values()
+            .find(|c| c.authenticated && c.agent_id == Some(agent_id))
+            .map(|c| c.circuit_code)
+            .ok_or_else(|| crate::NetworkError::ClientNotFound(agent_id.to_string()))?;
+        let audience = self.visibility.read().await.audience(object.local_id, object.owner_id, None);
+        if !audience.includes(Some(agent_id)) {
+            return Ok(true);
+        }
+        let payload = object_update::encode_object_update(object, 0, 0, "");
+        let crc = object_update::update_crc(&payload).unwrap_or_default();
+
+        if self.object_cache.read().await.likely_cached(agent_id, object.local_id, crc) {
+            let cached = object_update::encode_object_update_cached(&[(object.local_id, crc, object.flags)]);
+            let saved = payload.len().saturating_sub(cached.len()) as u64;
+            let sent = self.send_throttled(circuit_code, ThrottleCategory::Object, Packet::reliable(0, cached)).await?;
+            if sent {
+                let mut stats_guard = self.stats.write().await;
+                stats_guard.object_updates_cached += 1;
+                stats_guard.object_cache_bytes_saved += saved;
+            }
+            return Ok(sent);
+        }
+
+        let sent = self.send_throttled(circuit_code, ThrottleCategory::Object, Packet::reliable(0, payload)).await?;
+        if sent {
+            self.object_cache.write().await.sent(agent_id, object.local_id, crc);
+        }
+        Ok(sent)
+    }
+
+    /// Send `agent_id` the full updates of `objects`, which its viewer
+    /// asked for after not finding them in its cache. Returns how many were
+    /// sent.
+    pub async fn send_cache_misses(&self, agent_id: UserId, objects: &[SceneObject]) -> NetworkResult<usize> {
+        let mut sent = 0;
+        for object in objects {
+            self.object_cache.write().await.missed(agent_id, object.local_id);
+            self.stats.write().await.object_cache_misses += 1;
+            let audience = self.visibility.read().await.audience(object.local_id, object.owner_id, None);
+            if !audience.includes(Some(agent_id)) {
+                continue;
+            }
+            let payload = object_update::encode_object_update(object, 0, 0, "");
+            let crc = object_update::update_crc(&payload).unwrap_or_default();
+            if !self.send_to_agent(agent_id, payload).await? {
+                break;
+            }
+            self.object_cache.write().await.sent(agent_id, object.local_id, crc);
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    /// Hide `object` from everyone but its owner, or show it to everyone
+    /// again, updating what viewers already show
+    pub async fn set_hidden_from_others(&self, object: &SceneObject, hidden: bool) -> NetworkResult<usize> {
+        let changed = {
+            let mut visibility = self.visibility.write().await;
+            let changed = visibility.is_hidden(object.local_id) != hidden;
+            visibility.set_hidden(object.local_id, object.owner_id, hidden);
+            changed
+        };
+        if !changed {
+            return Ok(0);
+        }
+        if hidden {
+            let payload = object_update::encode_kill_object(&[object.local_id]);
+            self.send_to_audience(payload, |agent| agent != Some(object.owner_id)).await
+        } else {
+            self.send_object_update(object).await
+        }
+    }
+
+    /// Whether the prim with `local_id` is hidden from everyone but its
+    /// owner
+    pub async fn is_hidden_from_others(&self, local_id: u32) -> bool {
+        self.visibility.read().await.is_hidden(local_id)
+    }
+
+    /// Remove the prims with `local_ids` from every authenticated agent's
+    /// view, dropping their visibility rules and cache entries. Returns how
+    /// many were sent it.
+    pub async fn send_kill_objects(&self, local_ids: &[u32]) -> NetworkResult<usize> {
+        {
+            let mut visibility = self.visibility.write().await;
+            let mut object_cache = self.object_cache.write().await;
+            for local_id in local_ids {
+                visibility.forget(*local_id);
+                object_cache.forget(*local_id);
+            }
+        }
+        let mut sent = 0;
+        for chunk in local_ids.chunks(u8::MAX as usize) {
+            let payload = object_update::encode_kill_object(chunk);
+            sent = self.broadcast_packet_to_authenticated(Packet::reliable(0, payload)).await?;
+        }
+        Ok(sent)
+    }
+
+    /// Send `payload` reliably to the authenticated circuits whose agent
+    /// `include` accepts. Returns how many were sent it.
+    async fn send_to_audience(
+        &self,
+        payload: Vec<u8>,
+        include: impl Fn(Option<UserId>) -> bool,
+    ) -> NetworkResult<usize> {
+        let circuit_codes: Vec<u32> = self.active_circuits.read().await.values()
+            .filter(|c| c.authenticated && include(c.agent_id))
+            .map(|c| c.circuit_code)
+            .collect();
+
+        let mut sent = 0;
+        for circuit_code in circuit_codes {
+            match self.send_packet_to_circuit(circuit_code, Packet::reliable(0, payload.clone())).await {
+                Ok(()) => sent += 1,
+                Err(e) => warn!("Failed to send object update to circuit {}: {}", circuit_code, e),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Move `agent_id` to `position`, as when carried by what it sits on.
+    /// Returns false when the agent is not connected here.
+    pub async fn set_agent_position(&self, agent_id: UserId, position: Vector3) -> bool {
+        let mut circuits = self.active_circuits.write().await;
+        match circuits.values_mut().find(|c| c.authenticated && c.agent_id == Some(agent_id)) {
+            Some(circuit) => {
+                circuit.position = position;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Where `agent_id` stands, if it is connected here
+    pub async fn agent_position(&self, agent_id: UserId) -> Option<Vector3> {
+        self.active_circuits.read().await.values()
+            .find(|c| c.authenticated && c.agent_id == Some(agent_id))
+            .map(|c| c.position)
+    }
+
+    /// Show an alert to every authenticated agent
+    pub async fn broadcast_alert(&self, message: &str) -> NetworkResult<usize> {
+        let count = self.broadcast_packet_to_authenticated(Packet::reliable(0, dialog::encode_alert(message))).await?;
+        info!("Broadcast alert to {} agents", count);
+        Ok(count)
+    }
+
+    /// Say `chat` to the authenticated agents within `range` metres of it.
+    /// Returns how many were sent it.
+    pub async fn send_local_chat(&self, chat: &ChatFromSimulator, range: f32) -> NetworkResult<usize> {
+        let listeners: Vec<u32> = self.active_circuits.read().await.values()
+            .filter(|c| c.authenticated && (c.position - chat.position).length() <= range)
+            .map(|c| c.circuit_code)
+            .collect();
+
+        let payload = chat.encode();
+        let mut sent = 0;
+        for circuit_code in listeners {
+            match self.send_packet_to_circuit(circuit_code, Packet::reliable(0, payload.clone())).await {
+                Ok(()) => sent += 1,
+                Err(e) => warn!("Failed to send chat from {} to circuit {}: {}", chat.from_name, circuit_code, e),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Play `trigger` to the authenticated agents within `hearing_distance`
+    /// metres of it, at a gain fading from `full_gain_distance` on. Returns
+    /// how many were sent it.
+    pub async fn send_sound_trigger(
+        &self,
+        trigger: &SoundTrigger,
+        full_gain_distance: f32,
+        hearing_distance: f32,
+    ) -> NetworkResult<usize> {
+        let listeners: Vec<(u32, f32)> = self.active_circuits.read().await.values()
+            .filter(|c| c.authenticated)
+            .filter_map(|c| {
+                let distance = (c.position - trigger.position).length();
+                sound::distance_gain(trigger.gain, distance, full_gain_distance, hearing_distance)
+                    .map(|gain| (c.circuit_code, gain))
+            })
+            .collect();
+
+        let mut sent = 0;
+        for (circuit_code, gain) in listeners {
+            let payload = SoundTrigger { gain, ..trigger.clone() }.encode();
+            match self.send_packet_to_circuit(circuit_code, Packet::reliable(0, payload)).await {
+                Ok(()) => sent += 1,
+                Err(e) => warn!("Failed to send sound {} to circuit {}: {}", trigger.sound_id, circuit_code, e),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Play, loop or stop the sound of a prim at `position` for the agents
+    /// within `hearing_distance` of it. The viewer fades it as the prim
+    /// moves. Returns how many were sent it.
+    pub async fn send_attached_sound(
+        &self,
+        sound: &AttachedSound,
+        position: Vector3,
+        hearing_distance: f32,
+    ) -> NetworkResult<usize> {
+        self.send_within(position, hearing_distance, sound.encode()).await
+    }
+
+    /// Play `sound` to `agent_id` alone, wherever they are in the region.
+    /// Returns whether they were connected.
+    pub async fn send_attached_sound_to(&self, agent_id: UserId, sound: &AttachedSound) -> NetworkResult<bool> {
+        self.send_to_agent(agent_id, sound.encode()).await
+    }
+
+    /// Change the volume of the sound of `object_id`, at `position`
+    pub async fn send_attached_sound_gain(
+        &self,
+        object_id: uuid::Uuid,
+        gain: f32,
+        position: Vector3,
+        hearing_distance: f32,
+    ) -> NetworkResult<usize> {
+        self.send_within(position, hearing_distance, sound::encode_attached_sound_gain_change(object_id, gain)).await
+    }
+
+    /// Have the viewers near `position` fetch `sound_id` before
+    /// `object_id` plays it
+    pub async fn send_preload_sound(
+        &self,
+        object_id: uuid::Uuid,
+        owner_id: uuid::Uuid,
+        sound_id: uuid::Uuid,
+        position: Vector3,
+        hearing_distance: f32,
+    ) -> NetworkResult<usize> {
+        let payload = sound::encode_preload_sound(object_id, owner_id, sound_id);
+        self.send_within(position, hearing_distance, payload).await
+    }
+
+    /// Send `payload` reliably to the authenticated agents within `range`
+    /// metres of `position`. Returns how many were sent it.
+    async fn send_within(&self, position: Vector3, range: f32, payload: Vec<u8>) -> NetworkResult<usize> {
+        let circuits: Vec<u32> = self.active_circuits.read().await.values()
+            .filter(|c| c.authenticated && (c.position - position).length() <= range)
+            .map(|c| c.circuit_code)
+            .collect();
+
+        let mut sent = 0;
+        for circuit_code in circuits {
+            match self.send_packet_to_circuit(circuit_code, Packet::reliable(0, payload.clone())).await {
+                Ok(()) => sent += 1,
+                Err(e) => warn!("Failed to send to circuit {}: {}", circuit_code, e),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Send `chat` to `agent_id` alone, wherever they are in the region.
+    /// Returns whether they were connected.
+    pub async fn send_chat_to_agent(&self, agent_id: UserId, chat: &ChatFromSimulator) -> NetworkResult<bool> {
+        self.send_to_agent(agent_id, chat.encode()).await
+    }
+
+    /// Disconnect `agent_id`, showing the viewer `reason`. Returns false
+    /// when the agent is not connected here.
+    pub async fn kick_agent(&self, agent_id: UserId, reason: &str) -> NetworkResult<bool> {
+        let circuit = self.active_circuits.read().await.values()
+            .find(|c| c.authenticated && c.agent_id == Some(agent_id))
+            .map(|c| (c.circuit_code, c.address));
+        let Some((circuit_code, address)) = circuit else {
+            return Ok(false);
+        };
+
+        self.send_shutdown_notification(address, reason).await?;
+        if let Some(circuit) = self.remove_circuit(circuit_code).await {
+            self.handlers.circuit_closed(&circuit, &self.socket, "kicked");
         }
+        info!("Kicked agent {}: {}", agent_id, reason);
+        Ok(true)
+    }
+
+    /// Freeze or unfreeze `agent_id`, ignoring its movement while frozen.
+    /// Returns false when the agent is not connected here.
+    pub async fn set_frozen(&self, agent_id: UserId, frozen: bool) -> bool {
+        let mut circuits = self.active_circuits.write().await;
+        let circuit = circuits.values_mut()
+            .find(|c| c.authenticated && c.agent_id == Some(agent_id));
+        match circuit {
+            Some(circuit) => {
+                circuit.frozen = frozen;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Tell `agent_id` the god level it now has, 0 for none, answering the
+    /// request with `token`
+    pub async fn send_godlike_powers(
+        &self,
+        agent_id: UserId,
+        session_id: uuid::Uuid,
+        god_level: u8,
+        token: uuid::Uuid,
+    ) -> NetworkResult<bool> {
+        let packet = moderation::encode_grant_godlike_powers(agent_id, session_id, god_level, token);
+        self.send_packet_to_agent(agent_id, packet).await
+    }
+
+    /// Answer the place search `query_id` of `agent_id` with `places`
+    pub async fn send_places_reply(
+        &self,
+        agent_id: UserId,
+        query_id: uuid::Uuid,
+        places: &[PlaceResult],
+    ) -> NetworkResult<bool> {
+        self.send_to_agent(agent_id, search::encode_dir_places_reply(agent_id, query_id, places)).await
+    }
+
+    /// Show `agent_id` the profile of `avatar_id`
+    pub async fn send_avatar_properties(
+        &self,
+        agent_id: UserId,
+        avatar_id: UserId,
+        properties: &AvatarProperties,
+        online: bool,
+    ) -> NetworkResult<bool> {
+        let payload = profile::encode_avatar_properties_reply(agent_id, avatar_id, properties, online);
+        self.send_to_agent(agent_id, payload).await
+    }
+
+    /// List the picks of `target_id` for `agent_id`, by ID and name
+    pub async fn send_avatar_picks(
+        &self,
+        agent_id: UserId,
+        target_id: UserId,
+        picks: &[(uuid::Uuid, String)],
+    ) -> NetworkResult<bool> {
+        self.send_to_agent(agent_id, profile::encode_avatar_picks_reply(agent_id, target_id, picks)).await
+    }
+
+    /// Answer the avatar search `query_id` of `agent_id` with `avatars`,
+    /// by ID, first and last name
+    pub async fn send_avatar_picker_reply(
+        &self,
+        agent_id: UserId,
+        query_id: uuid::Uuid,
+        avatars: &[(UserId, String, String)],
+    ) -> NetworkResult<bool> {
+        self.send_to_agent(agent_id, names::encode_avatar_picker_reply(agent_id, query_id, avatars)).await
+    }
+
+    /// Show `agent_id` one pick
+    pub async fn send_pick_info(&self, agent_id: UserId, pick: &Pick) -> NetworkResult<bool> {
+        self.send_to_agent(agent_id, profile::encode_pick_info_reply(agent_id, pick)).await
+    }
+
+    /// Tell `agent_id` of the landmark added to its inventory
+    pub async fn send_landmark_created(&self, agent_id: UserId, item: &LandmarkItem) -> NetworkResult<bool> {
+        let packet = inventory::encode_update_create_inventory_item(uuid::Uuid::nil(), &item.to_item_data(agent_id));
+        self.send_packet_to_agent(agent_id, packet).await
+    }
+
+    /// Tell `agent_id` of the object `item` made by its DeRezObject
+    /// `transaction_id`
+    pub async fn send_object_item_created(
+        &self,
+        agent_id: UserId,
+        transaction_id: uuid::Uuid,
+        item: &ObjectItem,
+    ) -> NetworkResult<bool> {
+        let packet = inventory::encode_update_create_inventory_item(transaction_id, &item.to_item_data(agent_id));
+        self.send_packet_to_agent(agent_id, packet).await
+    }
+
+    /// Answer the classified search `query_id` of `agent_id`
+    pub async fn send_classifieds_reply(
+        &self,
+        agent_id: UserId,
+        query_id: uuid::Uuid,
+        classifieds: &[Classified],
+    ) -> NetworkResult<bool> {
+        self.send_to_agent(agent_id, directory::encode_dir_classified_reply(agent_id, query_id, classifieds))
+            .await
+    }
+
+    /// Answer the event search `query_id` of `agent_id`
+    pub async fn send_events_reply(
+        &self,
+        agent_id: UserId,
+        query_id: uuid::Uuid,
+        events: &[Event],
+    ) -> NetworkResult<bool> {
+        self.send_to_agent(agent_id, directory::encode_dir_events_reply(agent_id, query_id, events)).await
+    }
+
+    /// List the classifieds of `target_id` for `agent_id`, by ID and name
+    pub async fn send_avatar_classifieds(
+        &self,
+        agent_id: UserId,
+        target_id: UserId,
+        classifieds: &[(uuid::Uuid, String)],
+    ) -> NetworkResult<bool> {
+        self.send_to_agent(agent_id, directory::encode_avatar_classified_reply(agent_id, target_id, classifieds))
+            .await
+    }
+
+    /// Show `agent_id` one classified
+    pub async fn send_classified_info(&self, agent_id: UserId, classified: &Classified) -> NetworkResult<bool> {
+        self.send_to_agent(agent_id, directory::encode_classified_info_reply(agent_id, classified)).await
+    }
+
+    /// Show `agent_id` one event
+    pub async fn send_event_info(&self, agent_id: UserId, event: &Event) -> NetworkResult<bool> {
+        self.send_to_agent(agent_id, directory::encode_event_info_reply(agent_id, event)).await
+    }
+
+    /// Send `payload`, whose first byte is its message ID, reliably to the
+    /// circuit of `agent_id`, if connected
+    async fn send_to_agent(&self, agent_id: UserId, payload: Vec<u8>) -> NetworkResult<bool> {
+        self.send_packet_to_agent(agent_id, Packet::reliable(0, payload)).await
+    }
+
+    /// Send `packet` to the circuit of `agent_id`, if connected
+    async fn send_packet_to_agent(&self, agent_id: UserId, packet: Packet) -> NetworkResult<bool> {
+        let circuit_code = self.active_circuits.read().await.values()
+            .find(|c| c.authenticated && c.agent_id == Some(agent_id))
+            .map(|c| c.circuit_code);
+        let Some(circuit_code) = circuit_code else {
+            return Ok(false);
+        };
+
+        self.send_packet_to_circuit(circuit_code, packet).await?;
+        Ok(true)
     }
 
     /// Broadcast packet to all authenticated circuits
@@ -496,8 +1386,9 @@ impl LLUDPServer {
         payload.push(packet_types::REGION_HANDSHAKE);
 
         // RegionInfo block
+        let maturity = self.login_service.region_maturity(&self.login_service.default_location().region_id);
         payload.extend_from_slice(&128u32.to_le_bytes()); // RegionFlags
-        payload.extend_from_slice(&1u8.to_le_bytes());    // SimAccess
+        payload.push(maturity.sim_access());              // SimAccess
         
         // Region name (variable string)
         let region_name = "Mutsea Region".as_bytes();
@@ -554,7 +1445,7 @@ impl LLUDPServer {
 
     /// Get server performance metrics
     pub async fn get_performance_metrics(&self) -> PerformanceMetrics {
-        let stats = self.stats.read().await;
+        let stats = self.get_stats().await;
         let circuits = self.active_circuits.read().await;
         
         PerformanceMetrics {
@@ -595,13 +1486,15 @@ impl Service for LLUDPServer {
         };
 
         let mut metrics = std::collections::HashMap::new();
-        let stats = self.stats.read().await;
+        let stats = self.get_stats().await;
         let circuits = self.active_circuits.read().await;
 
         metrics.insert("connections".to_string(), circuits.len() as f64);
         metrics.insert("packets_received".to_string(), stats.packets_received as f64);
         metrics.insert("packets_sent".to_string(), stats.packets_sent as f64);
         metrics.insert("errors".to_string(), stats.errors as f64);
+        metrics.insert("outbound_queued".to_string(), stats.outbound_queued as f64);
+        metrics.insert("outbound_dropped".to_string(), stats.outbound_dropped as f64);
         metrics.insert("packets_per_second".to_string(), stats.packets_per_second());
         metrics.insert("error_rate".to_string(), stats.error_rate());
 
@@ -638,9 +1531,13 @@ impl Clone for LLUDPServer {
             config: self.config.clone(),
             running: Arc::clone(&self.running),
             stats: Arc::clone(&self.stats),
+            worker_stats: Arc::clone(&self.worker_stats),
             active_circuits: Arc::clone(&self.active_circuits),
             login_service: Arc::clone(&self.login_service),
             handlers: self.handlers.clone(),
+            visibility: Arc::clone(&self.visibility),
+            object_cache: Arc::clone(&self.object_cache),
+            packet_tap: self.packet_tap.clone(),
         }
     }
 }
@@ -648,7 +1545,9 @@ impl Clone for LLUDPServer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::congestion::CongestionControl;
     use mutsea_core::config::LLUDPConfig;
+    use mutsea_core::RegionId;
 
     #[tokio::test]
     async fn test_lludp_server_creation() {
@@ -661,6 +1560,16 @@ mod tests {
             ack_timeout: 1000,
             ping_interval: 5,
             client_timeout: 60,
+            dual_stack: true,
+            external_address: None,
+            external_address_v6: None,
+            external_hostname: None,
+            stun_servers: Vec::new(),
+            stun_timeout_ms: 2000,
+            workers: 1,
+            outbound_queue_kb: 256,
+            throttle: Default::default(),
+            migration: Default::default(),
         };
 
         let server = LLUDPServer::new(&config).await;
@@ -707,6 +1616,9 @@ mod tests {
             client_info: None,
             last_ping_id: 0,
             last_ping_time: Instant::now(),
+            congestion: CongestionControl::default(),
+            last_migration: None,
+            frozen: false,
         };
 
         server.add_circuit(circuit).await;
@@ -717,4 +1629,40 @@ mod tests {
         assert!(removed.is_some());
         assert_eq!(server.get_active_circuits_count().await, 0);
     }
+
+    #[tokio::test]
+    async fn test_kick_agent_closes_its_circuit() {
+        let server = LLUDPServer::new(&LLUDPConfig::default()).await.unwrap();
+        let agent_id = UserId::new();
+        server.add_circuit(CircuitInfo {
+            circuit_code: 777,
+            address: "127.0.0.1:8080".parse().unwrap(),
+            user_id: Some(agent_id),
+            agent_id: Some(agent_id),
+            session_id: Some(uuid::Uuid::new_v4()),
+            secure_session_id: None,
+            created_at: Instant::now(),
+            last_activity: Instant::now(),
+            sequence_in: 0,
+            sequence_out: 0,
+            pending_acks: Vec::new(),
+            reliable_packets: HashMap::new(),
+            authenticated: true,
+            region_id: None,
+            position: Vector3::ZERO,
+            look_at: Vector3::new(1.0, 0.0, 0.0),
+            client_info: None,
+            last_ping_id: 0,
+            last_ping_time: Instant::now(),
+            congestion: CongestionControl::default(),
+            last_migration: None,
+            frozen: false,
+        }).await;
+
+        assert!(server.set_frozen(agent_id, true).await);
+        assert!(server.kick_agent(agent_id, "Kicked by the estate manager").await.unwrap());
+        assert_eq!(server.get_active_circuits_count().await, 0);
+        assert!(!server.kick_agent(agent_id, "Kicked again").await.unwrap());
+        assert!(!server.set_frozen(agent_id, true).await);
+    }
 }
\ No newline at end of file
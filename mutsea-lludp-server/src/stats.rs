@@ -19,6 +19,17 @@ pub struct ServerStats {
     pub successful_logins: u64,
     pub heartbeats_sent: u64,
     pub reliable_resends: u64,
+    /// Packets waiting in the circuits' outbound queues
+    pub outbound_queued: u64,
+    /// Updates dropped from full outbound queues or replaced by newer ones
+    pub outbound_dropped: u64,
+    /// Prims sent as just their CRC, to viewers likely to have them cached
+    pub object_updates_cached: u64,
+    /// Cached prims viewers asked for in full after all
+    pub object_cache_misses: u64,
+    /// Bytes not sent thanks to viewers' object caches
+    pub object_cache_bytes_saved: u64,
+    #[serde(skip)]
     pub start_time: Option<Instant>,
 }
 
@@ -62,6 +73,16 @@ impl ServerStats {
         }
     }
 
+    /// These stats with the receive workers' counters added in
+    pub fn with_workers(mut self, workers: &[WorkerStats]) -> Self {
+        for worker in workers {
+            self.packets_received += worker.packets_received.load(Ordering::Relaxed);
+            self.bytes_received += worker.bytes_received.load(Ordering::Relaxed);
+            self.errors += worker.errors.load(Ordering::Relaxed);
+        }
+        self
+    }
+
     /// Get uptime duration
     pub fn uptime(&self) -> std::time::Duration {
         if let Some(start_time) = self.start_time {
@@ -70,4 +91,29 @@ impl ServerStats {
             std::time::Duration::ZERO
         }
     }
-}
\ No newline at end of file
+}
+
+/// Receive counters of one worker, kept apart from [`ServerStats`] so the
+/// receive path never waits on a lock
+#[derive(Debug, Default)]
+pub struct WorkerStats {
+    /// Datagrams received
+    pub packets_received: AtomicU64,
+    /// Bytes received, UDP headers excluded
+    pub bytes_received: AtomicU64,
+    /// Failed receives and packets that could not be handled
+    pub errors: AtomicU64,
+}
+
+impl WorkerStats {
+    /// Count a received datagram of `bytes`
+    pub fn record_packet(&self, bytes: usize) {
+        self.packets_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Count a failed receive or a packet that could not be handled
+    pub fn record_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,313 @@
+//! mutsea-network/src/lludp_server/tunnel.rs
+//! Circuits carried over something other than the UDP socket
+//!
+//! Nothing is sent the moment it is handed over: packets wait in their
+//! circuit's [`OutboundQueue`] and a flusher sends them in priority order,
+//! taking one packet from each circuit in turn. What is sent and received
+//! is counted per address as [`BandwidthUsage`].
+//!
+//! The server may have several UDP sockets on its port, one per receive
+//! worker. Each client address belongs to one worker, picked by hashing the
+//! address, and packets to it are sent from that worker's socket.
+//!
+//! Browsers can't send UDP, so a browser viewer's LLUDP packets reach us
+//! through a tunnel such as a WebRTC data channel instead. Each tunnel is
+//! given an address no UDP peer can have, in the discard-only `100::/64`
+//! prefix, and packets sent to that address go into the tunnel. Circuits,
+//! sessions and packet handlers see tunneled viewers like any others.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, Notify};
+use tracing::debug;
+
+use super::outbound::{OutboundPacket, OutboundQueue, PriorityClass};
+use super::usage::BandwidthUsage;
+
+/// Bytes each circuit may have queued unless configured otherwise
+pub const DEFAULT_QUEUE_LIMIT: usize = 256 * 1024;
+
+/// The server's UDP sockets, its open tunnels and the packets waiting to go
+/// out over them
+pub struct CircuitSocket {
+    udp: Vec<UdpSocket>,
+    tunnels: RwLock<HashMap<SocketAddr, mpsc::UnboundedSender<Vec<u8>>>>,
+    next_tunnel: AtomicU64,
+    queues: Mutex<HashMap<SocketAddr, OutboundQueue>>,
+    queue_limit: usize,
+    queued: Notify,
+    dropped: AtomicU64,
+    usage: Mutex<HashMap<SocketAddr, BandwidthUsage>>,
+}
+
+impl CircuitSocket {
+    /// One socket and so one receive worker
+    pub fn new(udp: UdpSocket) -> Self {
+        Self::with_workers(vec![udp])
+    }
+
+    /// One socket per receive worker, all bound to the same port
+    pub fn with_workers(udp: Vec<UdpSocket>) -> Self {
+        assert!(!udp.is_empty(), "a circuit socket needs a UDP socket");
+        Self {
+            udp,
+            tunnels: RwLock::new(HashMap::new()),
+            next_tunnel: AtomicU64::new(1),
+            queues: Mutex::new(HashMap::new()),
+            queue_limit: DEFAULT_QUEUE_LIMIT,
+            queued: Notify::new(),
+            dropped: AtomicU64::new(0),
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Let each circuit queue up to `bytes` of packets
+    pub fn with_queue_limit(mut self, bytes: usize) -> Self {
+        self.queue_limit = bytes;
+        self
+    }
+
+    /// Queue `buf` for `addr` as control traffic
+    pub async fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        self.send_class(OutboundPacket::new(buf.to_vec(), PriorityClass::Control), addr).await
+    }
+
+    /// Queue `packet` for `addr`. Fails when `addr` is a closed tunnel's or
+    /// the circuit's queue had no room for the packet.
+    pub async fn send_class(&self, packet: OutboundPacket, addr: SocketAddr) -> io::Result<usize> {
+        if is_tunnel_address(addr) && !self.tunnels.read().unwrap().contains_key(&addr) {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "tunnel closed"));
+        }
+        let len = packet.data.len();
+        let queued = {
+            let mut queues = self.queues.lock().unwrap();
+            let queue = queues.entry(addr).or_insert_with(|| OutboundQueue::new(self.queue_limit));
+            let dropped = queue.dropped();
+            let queued = queue.push(packet);
+            self.dropped.fetch_add(queue.dropped() - dropped, Ordering::Relaxed);
+            queued
+        };
+        self.queued.notify_one();
+        if queued {
+            Ok(len)
+        } else {
+            Err(io::Error::new(io::ErrorKind::WouldBlock, "outbound queue full"))
+        }
+    }
+
+    /// Wait until packets have been queued since the last call
+    pub async fn wait_queued(&self) {
+        self.queued.notified().await
+    }
+
+    /// Send everything queued. Each round takes the most urgent packet of
+    /// every circuit, so a circuit with a deep queue doesn't hold up the rest.
+    pub async fn flush(&self) {
+        loop {
+            let round: Vec<(SocketAddr, OutboundPacket)> = {
+                let mut queues = self.queues.lock().unwrap();
+                let round = queues
+                    .iter_mut()
+                    .filter_map(|(addr, queue)| queue.pop().map(|packet| (*addr, packet)))
+                    .collect();
+                queues.retain(|_, queue| !queue.is_empty());
+                round
+            };
+            if round.is_empty() {
+                return;
+            }
+            for (addr, packet) in round {
+                match self.transmit(&packet.data, addr).await {
+                    Ok(bytes) => self.usage.lock().unwrap().entry(addr).or_default().record_sent(packet.class, bytes),
+                    Err(e) => debug!("Failed to send {:?} packet to {}: {}", packet.class, addr, e),
+                }
+            }
+        }
+    }
+
+    /// Packets waiting to go to `addr`
+    pub fn queue_depth(&self, addr: SocketAddr) -> usize {
+        self.queues.lock().unwrap().get(&addr).map_or(0, OutboundQueue::depth)
+    }
+
+    /// Packets waiting to go to any circuit
+    pub fn queued_packets(&self) -> usize {
+        self.queues.lock().unwrap().values().map(OutboundQueue::depth).sum()
+    }
+
+    /// Packets dropped or superseded in the queues so far
+    pub fn dropped_packets(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Count `bytes` received from `addr` in a packet of `class`
+    pub fn record_received(&self, addr: SocketAddr, class: PriorityClass, bytes: usize) {
+        self.usage.lock().unwrap().entry(addr).or_default().record_received(class, bytes);
+    }
+
+    /// What has been sent to and received from `addr` so far
+    pub fn usage(&self, addr: SocketAddr) -> BandwidthUsage {
+        self.usage.lock().unwrap().get(&addr).copied().unwrap_or_default()
+    }
+
+    /// Stop counting for `addr`, returning its counts
+    pub fn take_usage(&self, addr: SocketAddr) -> BandwidthUsage {
+        self.usage.lock().unwrap().remove(&addr).unwrap_or_default()
+    }
+
+    /// Count what was used at `from` towards `to`, a circuit's new address
+    pub fn move_usage(&self, from: SocketAddr, to: SocketAddr) {
+        let mut usage = self.usage.lock().unwrap();
+        if let Some(moved) = usage.remove(&from) {
+            usage.entry(to).or_default().merge(&moved);
+        }
+    }
+
+    /// Stop counting for addresses `keep` rejects, such as those of
+    /// packets from no circuit
+    pub fn retain_usage(&self, keep: impl Fn(&SocketAddr) -> bool) {
+        self.usage.lock().unwrap().retain(|addr, _| keep(addr));
+    }
+
+    /// Send `buf` to `addr` now, into its tunnel when it is a tunnel's
+    async fn transmit(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        let tunnel = self.tunnels.read().unwrap().get(&addr).cloned();
+        match tunnel {
+            Some(tunnel) => tunnel
+                .send(buf.to_vec())
+                .map(|()| buf.len())
+                .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "tunnel closed")),
+            None => self.udp[self.worker_for(addr)].send_to(buf, addr).await,
+        }
+    }
+
+    /// Receive a datagram from `worker`'s socket; tunneled packets are
+    /// handed to the server by their tunnel instead
+    pub async fn recv_from(&self, worker: usize, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.udp[worker].recv_from(buf).await
+    }
+
+    /// Address the server listens on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.udp[0].local_addr()
+    }
+
+    /// Number of receive workers
+    pub fn worker_count(&self) -> usize {
+        self.udp.len()
+    }
+
+    /// The worker handling the circuit at `addr`; the same for the life of
+    /// the server
+    pub fn worker_for(&self, addr: SocketAddr) -> usize {
+        if self.udp.len() == 1 {
+            return 0;
+        }
+        let mut hasher = DefaultHasher::new();
+        addr.hash(&mut hasher);
+        (hasher.finish() % self.udp.len() as u64) as usize
+    }
+
+    /// Open a tunnel, returning its address and the packets sent to it
+    pub fn open_tunnel(&self) -> (SocketAddr, mpsc::UnboundedReceiver<Vec<u8>>) {
+        let id = self.next_tunnel.fetch_add(1, Ordering::Relaxed);
+        let segments = [(id >> 48) as u16, (id >> 32) as u16, (id >> 16) as u16, id as u16];
+        let ip = Ipv6Addr::new(0x100, 0, 0, 0, segments[0], segments[1], segments[2], segments[3]);
+        let addr = SocketAddr::new(IpAddr::V6(ip), 0);
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.tunnels.write().unwrap().insert(addr, sender);
+        (addr, receiver)
+    }
+
+    /// Close the tunnel at `addr`; returns false if it was not open
+    pub fn close_tunnel(&self, addr: SocketAddr) -> bool {
+        self.queues.lock().unwrap().remove(&addr);
+        self.tunnels.write().unwrap().remove(&addr).is_some()
+    }
+
+    /// Number of open tunnels
+    pub fn tunnel_count(&self) -> usize {
+        self.tunnels.read().unwrap().len()
+    }
+}
+
+/// Whether `addr` is in the prefix tunnels are given addresses from
+fn is_tunnel_address(addr: SocketAddr) -> bool {
+    match addr.ip() {
+        IpAddr::V6(ip) => ip.segments()[..4] == [0x100, 0, 0, 0],
+        IpAddr::V4(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tunneled_packets_skip_the_socket() {
+        let socket = CircuitSocket::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let (first, mut packets) = socket.open_tunnel();
+        let (second, _) = socket.open_tunnel();
+        assert_ne!(first, second);
+        assert_eq!(socket.tunnel_count(), 2);
+
+        assert_eq!(socket.send_to(&[1, 2, 3], first).await.unwrap(), 3);
+        assert_eq!(socket.queue_depth(first), 1);
+        socket.flush().await;
+        assert_eq!(socket.queue_depth(first), 0);
+        assert_eq!(packets.recv().await, Some(vec![1, 2, 3]));
+
+        assert!(socket.close_tunnel(first));
+        assert!(!socket.close_tunnel(first));
+        // A closed tunnel's address is not routable over UDP either
+        assert!(socket.send_to(&[1], first).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_queued_packets_go_out_by_priority() {
+        let socket = CircuitSocket::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()).with_queue_limit(8);
+        let (addr, mut packets) = socket.open_tunnel();
+        socket.send_class(OutboundPacket::new(vec![3; 4], PriorityClass::Object), addr).await.unwrap();
+        socket.send_class(OutboundPacket::new(vec![2; 4], PriorityClass::Avatar), addr).await.unwrap();
+        socket.send_class(OutboundPacket::new(vec![1; 4], PriorityClass::Ack), addr).await.unwrap();
+        assert!(socket.send_class(OutboundPacket::new(vec![4; 4], PriorityClass::Texture), addr).await.is_err());
+        assert_eq!((socket.queue_depth(addr), socket.dropped_packets()), (2, 2));
+
+        socket.flush().await;
+        assert_eq!(packets.recv().await, Some(vec![1; 4]));
+        assert_eq!(packets.recv().await, Some(vec![2; 4]));
+        assert!(packets.try_recv().is_err());
+        assert_eq!(socket.queued_packets(), 0);
+
+        // Only what went out counts
+        socket.record_received(addr, PriorityClass::Control, 5);
+        let usage = socket.usage(addr);
+        assert_eq!((usage.bytes_sent(), usage.bytes_received()), (8, 5));
+        let moved = SocketAddr::from(([10, 0, 0, 2], 9000));
+        socket.move_usage(addr, moved);
+        assert_eq!(socket.take_usage(moved), usage);
+        assert_eq!(socket.usage(moved), BandwidthUsage::default());
+    }
+
+    #[tokio::test]
+    async fn test_addresses_keep_their_worker() {
+        let mut sockets = Vec::new();
+        for _ in 0..4 {
+            sockets.push(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        }
+        let socket = CircuitSocket::with_workers(sockets);
+        let addrs: Vec<SocketAddr> = (0..64).map(|port| SocketAddr::from(([10, 0, 0, 1], 20000 + port))).collect();
+        let workers: Vec<usize> = addrs.iter().map(|addr| socket.worker_for(*addr)).collect();
+        assert!(workers.iter().all(|worker| *worker < 4));
+        assert_eq!(workers, addrs.iter().map(|addr| socket.worker_for(*addr)).collect::<Vec<_>>());
+        // Circuits spread over every worker
+        assert!((0..4).all(|worker| workers.contains(&worker)));
+    }
+}
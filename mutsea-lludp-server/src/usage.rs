@@ -0,0 +1,154 @@
+//! mutsea-network/src/lludp_server/usage.rs
+//! Bandwidth each circuit has used
+//!
+//! Every packet sent or received is counted against its circuit's address,
+//! by the priority class it went out under or, for received packets, the
+//! class its message would have. Operators billing by bandwidth read the
+//! counts live per circuit, and the whole session's when it closes.
+
+use mutsea_core::UserId;
+use mutsea_protocol::constants::packet_types;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::outbound::PriorityClass;
+
+impl PriorityClass {
+    /// The class's name in usage reports
+    pub fn name(self) -> &'static str {
+        match self {
+            PriorityClass::Ack => "ack",
+            PriorityClass::Control => "control",
+            PriorityClass::Avatar => "avatar",
+            PriorityClass::Object => "object",
+            PriorityClass::Texture => "texture",
+        }
+    }
+
+    /// The class a received message is counted under; `None` for packets
+    /// carrying only acks
+    pub fn of_inbound(message_id: Option<u32>) -> Self {
+        match message_id {
+            None => PriorityClass::Ack,
+            Some(packet_types::AGENT_UPDATE | packet_types::AGENT_ANIMATION) => PriorityClass::Avatar,
+            Some(packet_types::REQUEST_MULTIPLE_OBJECTS) => PriorityClass::Object,
+            Some(packet_types::REQUEST_IMAGE | packet_types::TRANSFER_REQUEST) => PriorityClass::Texture,
+            Some(_) => PriorityClass::Control,
+        }
+    }
+}
+
+/// Bytes sent and received, by priority class
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BandwidthUsage {
+    sent: [u64; 5],
+    received: [u64; 5],
+}
+
+impl BandwidthUsage {
+    /// Count `bytes` sent in `class`
+    pub fn record_sent(&mut self, class: PriorityClass, bytes: usize) {
+        self.sent[class as usize] += bytes as u64;
+    }
+
+    /// Count `bytes` received in `class`
+    pub fn record_received(&mut self, class: PriorityClass, bytes: usize) {
+        self.received[class as usize] += bytes as u64;
+    }
+
+    /// Add the counts of `other`, as when a circuit moves address
+    pub fn merge(&mut self, other: &BandwidthUsage) {
+        for class in PriorityClass::ALL {
+            self.sent[class as usize] += other.sent[class as usize];
+            self.received[class as usize] += other.received[class as usize];
+        }
+    }
+
+    /// Bytes sent in every class
+    pub fn bytes_sent(&self) -> u64 {
+        self.sent.iter().sum()
+    }
+
+    /// Bytes received in every class
+    pub fn bytes_received(&self) -> u64 {
+        self.received.iter().sum()
+    }
+
+    /// Bytes sent by class name, leaving out unused classes
+    pub fn sent_by_class(&self) -> BTreeMap<String, u64> {
+        Self::by_class(&self.sent)
+    }
+
+    /// Bytes received by class name, leaving out unused classes
+    pub fn received_by_class(&self) -> BTreeMap<String, u64> {
+        Self::by_class(&self.received)
+    }
+
+    fn by_class(counts: &[u64; 5]) -> BTreeMap<String, u64> {
+        PriorityClass::ALL
+            .into_iter()
+            .filter(|class| counts[*class as usize] > 0)
+            .map(|class| (class.name().to_string(), counts[class as usize]))
+            .collect()
+    }
+}
+
+/// What one open circuit has used so far
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CircuitUsage {
+    /// The circuit
+    pub circuit_code: u32,
+    /// Agent on the circuit, once it has authenticated
+    pub agent_id: Option<UserId>,
+    /// Seconds since the circuit opened
+    pub connected_secs: u64,
+    /// Bytes sent in every class
+    pub bytes_sent: u64,
+    /// Bytes received in every class
+    pub bytes_received: u64,
+    /// Bytes sent by class name, leaving out unused classes
+    pub sent_by_class: BTreeMap<String, u64>,
+    /// Bytes received by class name, leaving out unused classes
+    pub received_by_class: BTreeMap<String, u64>,
+}
+
+impl CircuitUsage {
+    /// Usage of circuit `circuit_code` from its counts
+    pub fn new(circuit_code: u32, agent_id: Option<UserId>, connected_secs: u64, usage: &BandwidthUsage) -> Self {
+        Self {
+            circuit_code,
+            agent_id,
+            connected_secs,
+            bytes_sent: usage.bytes_sent(),
+            bytes_received: usage.bytes_received(),
+            sent_by_class: usage.sent_by_class(),
+            received_by_class: usage.received_by_class(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_is_counted_by_class() {
+        let mut usage = BandwidthUsage::default();
+        usage.record_sent(PriorityClass::Object, 1200);
+        usage.record_sent(PriorityClass::Object, 300);
+        usage.record_sent(PriorityClass::Ack, 10);
+        usage.record_received(PriorityClass::of_inbound(Some(packet_types::AGENT_UPDATE)), 100);
+        usage.record_received(PriorityClass::of_inbound(None), 12);
+
+        let mut moved = BandwidthUsage::default();
+        moved.record_received(PriorityClass::Control, 50);
+        usage.merge(&moved);
+
+        assert_eq!((usage.bytes_sent(), usage.bytes_received()), (1510, 162));
+        assert_eq!(
+            usage.sent_by_class(),
+            BTreeMap::from([("ack".to_string(), 10), ("object".to_string(), 1500)])
+        );
+        assert_eq!(usage.received_by_class().len(), 3);
+    }
+}
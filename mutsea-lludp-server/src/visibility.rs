@@ -0,0 +1,94 @@
+//! mutsea-network/src/lludp_server/visibility.rs
+//! Who may be sent an object update
+//!
+//! Every update leaving the server is checked against the rules for its
+//! prim. HUDs are drawn on their wearer's screen only, so their updates go
+//! to their owner alone, as do those of prims hidden from everyone else.
+//! Other prims are seen by every agent.
+
+use mutsea_core::UserId;
+use mutsea_protocol::attachment::is_hud;
+use std::collections::HashMap;
+
+/// The agents an object update is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Audience {
+    /// Every agent in the region
+    Everyone,
+    /// Only the prim's owner
+    Owner(UserId),
+}
+
+impl Audience {
+    /// Whether the circuit of `agent` may be sent the update
+    pub fn includes(&self, agent: Option<UserId>) -> bool {
+        match self {
+            Audience::Everyone => true,
+            Audience::Owner(owner) => agent == Some(*owner),
+        }
+    }
+}
+
+/// Per-prim visibility, by local ID
+#[derive(Debug, Clone, Default)]
+pub struct VisibilityRules {
+    /// Prims only their owner sees, with that owner
+    hidden: HashMap<u32, UserId>,
+}
+
+impl VisibilityRules {
+    /// The audience of an update for the prim with `local_id` owned by
+    /// `owner`, worn at `attachment_point` if it is worn
+    pub fn audience(&self, local_id: u32, owner: UserId, attachment_point: Option<u8>) -> Audience {
+        if attachment_point.is_some_and(is_hud) || self.hidden.contains_key(&local_id) {
+            Audience::Owner(owner)
+        } else {
+            Audience::Everyone
+        }
+    }
+
+    /// Hide the prim with `local_id` from everyone but `owner`, or show it
+    /// to everyone again
+    pub fn set_hidden(&mut self, local_id: u32, owner: UserId, hidden: bool) {
+        if hidden {
+            self.hidden.insert(local_id, owner);
+        } else {
+            self.hidden.remove(&local_id);
+        }
+    }
+
+    /// Whether the prim with `local_id` is hidden from everyone but its owner
+    pub fn is_hidden(&self, local_id: u32) -> bool {
+        self.hidden.contains_key(&local_id)
+    }
+
+    /// Drop the rules of a prim that left the region
+    pub fn forget(&mut self, local_id: u32) {
+        self.hidden.remove(&local_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_protocol::attachment::FIRST_HUD_POINT;
+
+    #[test]
+    fn test_huds_and_hidden_prims_go_to_their_owner() {
+        let (owner, other) = (UserId::new(), UserId::new());
+        let mut rules = VisibilityRules::default();
+
+        assert_eq!(rules.audience(1, owner, None), Audience::Everyone);
+        assert_eq!(rules.audience(1, owner, Some(2)), Audience::Everyone);
+        let hud = rules.audience(1, owner, Some(FIRST_HUD_POINT));
+        assert!(hud.includes(Some(owner)));
+        assert!(!hud.includes(Some(other)));
+        assert!(!hud.includes(None));
+
+        rules.set_hidden(7, owner, true);
+        assert_eq!(rules.audience(7, owner, None), Audience::Owner(owner));
+        rules.forget(7);
+        assert!(!rules.is_hidden(7));
+        assert_eq!(rules.audience(7, owner, None), Audience::Everyone);
+    }
+}
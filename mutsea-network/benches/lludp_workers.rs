@@ -0,0 +1,105 @@
+//! LLUDP receive throughput by worker count
+//!
+//! Many clients blast small packets at a server on loopback and each batch
+//! is timed until the server has counted every packet, so packets per
+//! second can be compared between one worker and several on a multi-core
+//! machine. Run with `cargo bench -p mutsea-network --bench lludp_workers`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use mutsea_core::config::LLUDPConfig;
+use mutsea_network::lludp_server::LLUDPServer;
+use mutsea_protocol::Packet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+/// Client sockets, each a separate circuit address
+const CLIENTS: usize = 64;
+/// Packets per client per batch
+const PACKETS_PER_CLIENT: usize = 100;
+
+async fn start_server(workers: usize) -> (LLUDPServer, SocketAddr) {
+    let config = LLUDPConfig {
+        bind_address: "127.0.0.1".to_string(),
+        port: 0,
+        workers,
+        ..Default::default()
+    };
+    let server = LLUDPServer::new(&config).await.expect("bind LLUDP server");
+    server.start().await.expect("start LLUDP server");
+    let address = server.local_addr().expect("server address");
+    (server, address)
+}
+
+/// Send one batch from every client and wait until the server counted it;
+/// packets lost on the way are given up on after a second
+async fn run_batch(
+    server: &LLUDPServer,
+    address: SocketAddr,
+    clients: &[Arc<UdpSocket>],
+    packet: &Arc<Vec<u8>>,
+) -> Duration {
+    let before = server.get_stats().await.packets_received;
+    let expected = before + (clients.len() * PACKETS_PER_CLIENT) as u64;
+    let start = Instant::now();
+
+    let senders: Vec<_> = clients
+        .iter()
+        .map(|client| {
+            let (client, packet) = (Arc::clone(client), Arc::clone(packet));
+            tokio::spawn(async move {
+                for _ in 0..PACKETS_PER_CLIENT {
+                    let _ = client.send_to(&packet, address).await;
+                }
+            })
+        })
+        .collect();
+    for sender in senders {
+        let _ = sender.await;
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while server.get_stats().await.packets_received < expected && Instant::now() < deadline {
+        tokio::task::yield_now().await;
+    }
+    start.elapsed()
+}
+
+fn receive_throughput(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let packet = Arc::new(Packet::ack(vec![1]).serialize().unwrap());
+
+    let mut group = c.benchmark_group("lludp_receive");
+    group.throughput(Throughput::Elements((CLIENTS * PACKETS_PER_CLIENT) as u64));
+    group.sample_size(20);
+    for workers in [1, 2, 4, 8] {
+        let (server, address, clients) = runtime.block_on(async {
+            let (server, address) = start_server(workers).await;
+            let mut clients = Vec::with_capacity(CLIENTS);
+            for _ in 0..CLIENTS {
+                clients.push(Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()));
+            }
+            (server, address, clients)
+        });
+        group.bench_with_input(BenchmarkId::from_parameter(workers), &workers, |b, _| {
+            b.iter_custom(|iters| {
+                runtime.block_on(async {
+                    let mut total = Duration::ZERO;
+                    for _ in 0..iters {
+                        total += run_batch(&server, address, &clients, &packet).await;
+                    }
+                    total
+                })
+            })
+        });
+        runtime.block_on(server.stop()).unwrap();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, receive_throughput);
+criterion_main!(benches);
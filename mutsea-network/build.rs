@@ -0,0 +1,11 @@
+// mutsea-network/build.rs
+// Generates the internal gRPC services when the `grpc` feature is on
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/internal.proto");
+        tonic_build::compile_protos("proto/internal.proto")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,294 @@
+//! Load test runner built on [`BotClient`]
+//!
+//! Spawns many bots against a login URI, ramps them up over a configurable
+//! window and aggregates their results into a [`BenchReport`].
+
+use super::bot::{BotClient, BotConfig, BotScript, BotStatsSnapshot};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Benchmark configuration
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Number of bots to run
+    pub bots: usize,
+    /// How long each bot stays connected running its script
+    pub duration: Duration,
+    /// Window over which bot logins are spread
+    pub ramp_up: Duration,
+    /// Maximum number of logins in flight at once
+    pub max_concurrent_logins: usize,
+    /// Template for every bot; the last name gets the bot index appended
+    /// (from 1) when `numbered_accounts` is set
+    pub bot: BotConfig,
+    /// Log each bot in as a different numbered account rather than sharing one
+    pub numbered_accounts: bool,
+    /// Textures requested by bots while they run
+    pub textures: Vec<Uuid>,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            bots: 10,
+            duration: Duration::from_secs(60),
+            ramp_up: Duration::from_secs(10),
+            max_concurrent_logins: 20,
+            bot: BotConfig::default(),
+            numbered_accounts: false,
+            textures: Vec::new(),
+        }
+    }
+}
+
+/// Outcome of a single bot run
+#[derive(Debug, Clone)]
+pub struct BotOutcome {
+    /// Position of the bot in the run
+    pub index: usize,
+    /// Time taken by the XML-RPC login; `None` if it failed
+    pub login_latency: Option<Duration>,
+    /// Time until the simulator confirmed the handshake; `None` if it didn't
+    pub connect_latency: Option<Duration>,
+    /// Counters at the end of the run
+    pub stats: BotStatsSnapshot,
+    /// Why the bot stopped early, if it did
+    pub error: Option<String>,
+}
+
+/// Aggregated benchmark results
+#[derive(Debug, Clone, Default)]
+pub struct BenchReport {
+    /// Number of bots run
+    pub bots: usize,
+    /// Bots that logged in and had their handshake confirmed
+    pub successful_logins: usize,
+    /// Bots whose XML-RPC login failed
+    pub failed_logins: usize,
+    /// Bots that logged in but whose handshake was never confirmed
+    pub failed_connections: usize,
+    /// Wall time of the whole run
+    pub elapsed: Duration,
+    /// Mean XML-RPC login time
+    pub login_latency_avg: Duration,
+    /// 95th percentile XML-RPC login time
+    pub login_latency_p95: Duration,
+    /// Slowest XML-RPC login
+    pub login_latency_max: Duration,
+    /// Counters summed over every bot
+    pub totals: BotStatsSnapshot,
+    /// One line per bot that reported an error
+    pub errors: Vec<String>,
+}
+
+impl BenchReport {
+    /// Build a report from individual bot outcomes
+    pub fn from_outcomes(outcomes: &[BotOutcome], elapsed: Duration) -> Self {
+        let mut report = Self {
+            bots: outcomes.len(),
+            elapsed,
+            ..Default::default()
+        };
+
+        let mut latencies: Vec<Duration> = Vec::new();
+        for outcome in outcomes {
+            match (outcome.login_latency, outcome.connect_latency) {
+                (Some(login), Some(_)) => {
+                    report.successful_logins += 1;
+                    latencies.push(login);
+                }
+                (Some(login), None) => {
+                    report.failed_connections += 1;
+                    latencies.push(login);
+                }
+                (None, _) => report.failed_logins += 1,
+            }
+
+            let s = &outcome.stats;
+            report.totals.packets_sent += s.packets_sent;
+            report.totals.packets_received += s.packets_received;
+            report.totals.bytes_sent += s.bytes_sent;
+            report.totals.bytes_received += s.bytes_received;
+            report.totals.chats_sent += s.chats_sent;
+            report.totals.textures_requested += s.textures_requested;
+            report.totals.agent_updates_sent += s.agent_updates_sent;
+            report.totals.pings_answered += s.pings_answered;
+
+            if let Some(error) = &outcome.error {
+                report.errors.push(format!("bot {}: {}", outcome.index, error));
+            }
+        }
+
+        if !latencies.is_empty() {
+            latencies.sort();
+            let total: Duration = latencies.iter().sum();
+            report.login_latency_avg = total / latencies.len() as u32;
+            let p95_index = ((latencies.len() as f64 * 0.95).ceil() as usize).saturating_sub(1);
+            report.login_latency_p95 = latencies[p95_index.min(latencies.len() - 1)];
+            report.login_latency_max = *latencies.last().unwrap();
+        }
+
+        report
+    }
+
+    /// Fraction of bots that logged in and completed the handshake
+    pub fn success_rate(&self) -> f64 {
+        if self.bots == 0 {
+            return 0.0;
+        }
+        self.successful_logins as f64 / self.bots as f64
+    }
+
+    /// Packets sent by all bots per second of wall time
+    pub fn packets_sent_per_sec(&self) -> f64 {
+        per_sec(self.totals.packets_sent, self.elapsed)
+    }
+
+    /// Packets received by all bots per second of wall time
+    pub fn packets_received_per_sec(&self) -> f64 {
+        per_sec(self.totals.packets_received, self.elapsed)
+    }
+}
+
+fn per_sec(count: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        count as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+/// Run a benchmark to completion
+pub async fn run_bench(config: BenchConfig) -> BenchReport {
+    let started = Instant::now();
+    let login_slots = Arc::new(Semaphore::new(config.max_concurrent_logins.max(1)));
+    let spacing = if config.bots > 1 {
+        config.ramp_up / config.bots as u32
+    } else {
+        Duration::ZERO
+    };
+
+    let mut handles = Vec::with_capacity(config.bots);
+    for index in 0..config.bots {
+        let mut bot_config = config.bot.clone();
+        if config.numbered_accounts {
+            bot_config.last_name = format!("{}{}", bot_config.last_name, index + 1);
+        }
+        let login_slots = Arc::clone(&login_slots);
+        let duration = config.duration;
+        let textures = config.textures.clone();
+        let delay = spacing * index as u32;
+
+        handles.push(tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            run_bot(index, bot_config, duration, &textures, login_slots).await
+        }));
+    }
+
+    let mut outcomes = Vec::with_capacity(handles.len());
+    for (index, handle) in handles.into_iter().enumerate() {
+        match handle.await {
+            Ok(outcome) => outcomes.push(outcome),
+            Err(e) => outcomes.push(BotOutcome {
+                index,
+                login_latency: None,
+                connect_latency: None,
+                stats: BotStatsSnapshot::default(),
+                error: Some(format!("bot task panicked: {}", e)),
+            }),
+        }
+    }
+
+    BenchReport::from_outcomes(&outcomes, started.elapsed())
+}
+
+async fn run_bot(
+    index: usize,
+    config: BotConfig,
+    duration: Duration,
+    textures: &[Uuid],
+    login_slots: Arc<Semaphore>,
+) -> BotOutcome {
+    let mut bot = BotClient::new(config);
+    let mut error = None;
+
+    let connected = {
+        let _slot = login_slots.acquire().await;
+        match bot.login().await {
+            Ok(_) => match bot.connect().await {
+                Ok(()) => true,
+                Err(e) => {
+                    error = Some(format!("handshake failed: {}", e));
+                    false
+                }
+            },
+            Err(e) => {
+                error = Some(format!("login failed: {}", e));
+                false
+            }
+        }
+    };
+
+    if connected {
+        debug!("Bot {} connected", index);
+        let script = BotScript::random_activity(duration, textures);
+        if let Err(e) = bot.run_script(&script).await {
+            warn!("Bot {} script error: {}", index, e);
+            error = Some(e.to_string());
+        }
+        if let Err(e) = bot.logout().await {
+            debug!("Bot {} logout error: {}", index, e);
+        }
+    }
+
+    BotOutcome {
+        index,
+        login_latency: bot.login_latency(),
+        connect_latency: bot.connect_latency(),
+        stats: bot.stats().snapshot(),
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(index: usize, login_ms: Option<u64>, connected: bool) -> BotOutcome {
+        BotOutcome {
+            index,
+            login_latency: login_ms.map(Duration::from_millis),
+            connect_latency: connected.then(|| Duration::from_millis(5)),
+            stats: BotStatsSnapshot {
+                packets_sent: 10,
+                ..Default::default()
+            },
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_report_aggregates_outcomes() {
+        let outcomes = vec![
+            outcome(0, Some(100), true),
+            outcome(1, Some(300), true),
+            outcome(2, Some(200), false),
+            outcome(3, None, false),
+        ];
+
+        let report = BenchReport::from_outcomes(&outcomes, Duration::from_secs(2));
+        assert_eq!(report.bots, 4);
+        assert_eq!(report.successful_logins, 2);
+        assert_eq!(report.failed_connections, 1);
+        assert_eq!(report.failed_logins, 1);
+        assert_eq!(report.login_latency_avg, Duration::from_millis(200));
+        assert_eq!(report.login_latency_max, Duration::from_millis(300));
+        assert_eq!(report.totals.packets_sent, 40);
+        assert_eq!(report.packets_sent_per_sec(), 20.0);
+        assert_eq!(report.success_rate(), 0.5);
+    }
+}
@@ -0,0 +1,776 @@
+//! Headless scriptable bot client
+//!
+//! A minimal viewer that performs the XML-RPC login, completes the
+//! UseCircuitCode/CompleteAgentMovement handshake over LLUDP and then drives
+//! a script of actions (walking, chatting, requesting textures). Used by
+//! `mutsea bench` to load-test a running server.
+
+use crate::{NetworkError, NetworkResult};
+use mutsea_protocol::constants::{packet_types, MAX_PACKET_SIZE};
+use mutsea_protocol::llsd::xml_escape;
+use mutsea_protocol::Packet;
+use rand::Rng;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::{Mutex, Notify};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Chat lines used by the default random activity script
+const DEFAULT_CHAT_LINES: &[&str] = &[
+    "Hello from a Mutsea bot",
+    "Load test in progress",
+    "Walking around",
+    "Anyone here?",
+];
+
+/// A single step in a bot script
+#[derive(Debug, Clone)]
+pub enum BotAction {
+    /// Walk in random directions for the given duration
+    RandomWalk(Duration),
+    /// Say something on the given chat channel
+    Chat {
+        /// Text to say
+        message: String,
+        /// Channel to say it on; 0 is public chat
+        channel: i32,
+    },
+    /// Request a texture by asset ID
+    RequestTexture(Uuid),
+    /// Idle for the given duration
+    Wait(Duration),
+}
+
+/// Ordered list of actions a bot executes after connecting
+#[derive(Debug, Clone, Default)]
+pub struct BotScript {
+    /// Actions in the order they run
+    pub actions: Vec<BotAction>,
+}
+
+impl BotScript {
+    /// Create an empty script
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append an action to the script
+    pub fn then(mut self, action: BotAction) -> Self {
+        self.actions.push(action);
+        self
+    }
+
+    /// Random walking interleaved with chat and texture requests until
+    /// `duration` has been filled
+    pub fn random_activity(duration: Duration, textures: &[Uuid]) -> Self {
+        let mut rng = rand::thread_rng();
+        let mut script = Self::new();
+        let mut planned = Duration::ZERO;
+
+        while planned < duration {
+            let walk = Duration::from_millis(rng.gen_range(1_000..5_000));
+            script.actions.push(BotAction::RandomWalk(walk));
+            planned += walk;
+
+            match rng.gen_range(0..3) {
+                0 => {
+                    let line = DEFAULT_CHAT_LINES[rng.gen_range(0..DEFAULT_CHAT_LINES.len())];
+                    script.actions.push(BotAction::Chat {
+                        message: line.to_string(),
+                        channel: 0,
+                    });
+                }
+                1 if !textures.is_empty() => {
+                    let texture = textures[rng.gen_range(0..textures.len())];
+                    script.actions.push(BotAction::RequestTexture(texture));
+                }
+                _ => {
+                    let wait = Duration::from_millis(rng.gen_range(200..1_000));
+                    script.actions.push(BotAction::Wait(wait));
+                    planned += wait;
+                }
+            }
+        }
+
+        script
+    }
+}
+
+/// Bot configuration
+#[derive(Debug, Clone)]
+pub struct BotConfig {
+    /// XML-RPC login URI, e.g. `http://127.0.0.1:9000/`
+    pub login_uri: String,
+    /// Account first name
+    pub first_name: String,
+    /// Account last name
+    pub last_name: String,
+    /// Account password, sent in the clear as the viewer does
+    pub password: String,
+    /// Start location sent with the login request
+    pub start: String,
+    /// Interval between AgentUpdate packets while walking
+    pub update_interval: Duration,
+    /// How long to wait for the login and handshake to complete
+    pub connect_timeout: Duration,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            login_uri: "http://127.0.0.1:9000/".to_string(),
+            first_name: "Test".to_string(),
+            last_name: "User".to_string(),
+            password: "password".to_string(),
+            start: "last".to_string(),
+            update_interval: Duration::from_millis(100),
+            connect_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Session details returned by a successful login
+#[derive(Debug, Clone)]
+pub struct BotSession {
+    /// Agent logged in as
+    pub agent_id: Uuid,
+    /// Session ID for this login
+    pub session_id: Uuid,
+    /// Secure session ID for this login
+    pub secure_session_id: Uuid,
+    /// Circuit code to present in UseCircuitCode
+    pub circuit_code: u32,
+    /// UDP address of the simulator to connect to
+    pub sim_address: SocketAddr,
+}
+
+/// Counters collected by a bot while it runs
+#[derive(Debug, Default)]
+pub struct BotStats {
+    /// Packets sent, including acks and ping replies
+    pub packets_sent: AtomicU64,
+    /// Packets received
+    pub packets_received: AtomicU64,
+    /// Bytes sent on the wire
+    pub bytes_sent: AtomicU64,
+    /// Bytes received on the wire
+    pub bytes_received: AtomicU64,
+    /// ChatFromViewer messages sent
+    pub chats_sent: AtomicU64,
+    /// RequestImage messages sent
+    pub textures_requested: AtomicU64,
+    /// AgentUpdate messages sent
+    pub agent_updates_sent: AtomicU64,
+    /// StartPingChecks answered
+    pub pings_answered: AtomicU64,
+}
+
+/// Point-in-time copy of [`BotStats`]
+#[derive(Debug, Clone, Default)]
+pub struct BotStatsSnapshot {
+    /// Packets sent, including acks and ping replies
+    pub packets_sent: u64,
+    /// Packets received
+    pub packets_received: u64,
+    /// Bytes sent on the wire
+    pub bytes_sent: u64,
+    /// Bytes received on the wire
+    pub bytes_received: u64,
+    /// ChatFromViewer messages sent
+    pub chats_sent: u64,
+    /// RequestImage messages sent
+    pub textures_requested: u64,
+    /// AgentUpdate messages sent
+    pub agent_updates_sent: u64,
+    /// StartPingChecks answered
+    pub pings_answered: u64,
+}
+
+impl BotStats {
+    /// Take a snapshot of the current counters
+    pub fn snapshot(&self) -> BotStatsSnapshot {
+        BotStatsSnapshot {
+            packets_sent: self.packets_sent.load(Ordering::Relaxed),
+            packets_received: self.packets_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            chats_sent: self.chats_sent.load(Ordering::Relaxed),
+            textures_requested: self.textures_requested.load(Ordering::Relaxed),
+            agent_updates_sent: self.agent_updates_sent.load(Ordering::Relaxed),
+            pings_answered: self.pings_answered.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Headless bot client
+pub struct BotClient {
+    config: BotConfig,
+    session: Option<BotSession>,
+    socket: Option<Arc<UdpSocket>>,
+    sequence: Arc<AtomicU32>,
+    pending_acks: Arc<Mutex<Vec<u32>>>,
+    stats: Arc<BotStats>,
+    running: Arc<AtomicBool>,
+    movement_complete: Arc<Notify>,
+    position: [f32; 3],
+    heading: f32,
+    login_latency: Option<Duration>,
+    connect_latency: Option<Duration>,
+}
+
+impl BotClient {
+    /// Create a new bot client
+    pub fn new(config: BotConfig) -> Self {
+        Self {
+            config,
+            session: None,
+            socket: None,
+            sequence: Arc::new(AtomicU32::new(1)),
+            pending_acks: Arc::new(Mutex::new(Vec::new())),
+            stats: Arc::new(BotStats::default()),
+            running: Arc::new(AtomicBool::new(false)),
+            movement_complete: Arc::new(Notify::new()),
+            position: [128.0, 128.0, 21.0],
+            heading: 0.0,
+            login_latency: None,
+            connect_latency: None,
+        }
+    }
+
+    /// Session established by [`BotClient::login`]
+    pub fn session(&self) -> Option<&BotSession> {
+        self.session.as_ref()
+    }
+
+    /// Shared counters for this bot
+    pub fn stats(&self) -> Arc<BotStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Time taken by the XML-RPC login
+    pub fn login_latency(&self) -> Option<Duration> {
+        self.login_latency
+    }
+
+    /// Time taken by the LLUDP handshake, up to the simulator's
+    /// AgentMovementComplete; `None` until [`BotClient::connect`] succeeds
+    pub fn connect_latency(&self) -> Option<Duration> {
+        self.connect_latency
+    }
+
+    /// Check if the bot is connected to a simulator
+    pub fn is_connected(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Perform the XML-RPC `login_to_simulator` call
+    pub async fn login(&mut self) -> NetworkResult<&BotSession> {
+        let started = Instant::now();
+        let body = build_login_request(&self.config);
+
+        let client = reqwest::Client::builder()
+            .timeout(self.config.connect_timeout)
+            .build()
+            .map_err(|e| NetworkError::Generic(e.to_string()))?;
+
+        let response = client
+            .post(&self.config.login_uri)
+            .header("Content-Type", "text/xml")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| NetworkError::Generic(format!("Login request failed: {}", e)))?
+            .text()
+            .await
+            .map_err(|e| NetworkError::Generic(format!("Login response unreadable: {}", e)))?;
+
+        let session = parse_login_response(&response)?;
+        self.login_latency = Some(started.elapsed());
+        self.session = Some(session);
+        Ok(self.session.as_ref().expect("session just set"))
+    }
+
+    /// Open the UDP circuit and complete the agent movement handshake
+    ///
+    /// Returns once the simulator answers with AgentMovementComplete. If it
+    /// hasn't within `connect_timeout` the circuit is closed and a
+    /// [`NetworkError::Timeout`] returned.
+    pub async fn connect(&mut self) -> NetworkResult<()> {
+        let session = self
+            .session
+            .clone()
+            .ok_or_else(|| NetworkError::Session("Bot is not logged in".to_string()))?;
+
+        let started = Instant::now();
+        let bind_addr = if session.sim_address.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+        let socket = Arc::new(UdpSocket::bind(bind_addr).await?);
+        socket.connect(session.sim_address).await?;
+        self.socket = Some(Arc::clone(&socket));
+        self.running.store(true, Ordering::SeqCst);
+        self.movement_complete = Arc::new(Notify::new());
+
+        self.spawn_receiver(Arc::clone(&socket));
+
+        let mut payload = Vec::with_capacity(36);
+        payload.extend_from_slice(&session.circuit_code.to_le_bytes());
+        payload.extend_from_slice(session.session_id.as_bytes());
+        payload.extend_from_slice(session.agent_id.as_bytes());
+        self.send(packet_types::USE_CIRCUIT_CODE, payload, true).await?;
+
+        let mut payload = Vec::with_capacity(36);
+        payload.extend_from_slice(session.agent_id.as_bytes());
+        payload.extend_from_slice(session.session_id.as_bytes());
+        payload.extend_from_slice(&session.circuit_code.to_le_bytes());
+        self.send(packet_types::COMPLETE_AGENT_MOVEMENT, payload, true).await?;
+
+        let remaining = self.config.connect_timeout.saturating_sub(started.elapsed());
+        if tokio::time::timeout(remaining, self.movement_complete.notified()).await.is_err() {
+            self.running.store(false, Ordering::SeqCst);
+            self.socket = None;
+            return Err(NetworkError::Timeout(format!(
+                "no AgentMovementComplete from {} within {:?}",
+                session.sim_address, self.config.connect_timeout
+            )));
+        }
+
+        self.connect_latency = Some(started.elapsed());
+        debug!("Bot {} {} connected to {}", self.config.first_name, self.config.last_name, session.sim_address);
+        Ok(())
+    }
+
+    /// Execute every action in the script in order
+    pub async fn run_script(&mut self, script: &BotScript) -> NetworkResult<()> {
+        for action in &script.actions {
+            if !self.is_connected() {
+                break;
+            }
+            match action {
+                BotAction::RandomWalk(duration) => self.walk_randomly(*duration).await?,
+                BotAction::Chat { message, channel } => self.chat(message, *channel).await?,
+                BotAction::RequestTexture(id) => self.request_texture(*id).await?,
+                BotAction::Wait(duration) => tokio::time::sleep(*duration).await,
+            }
+        }
+        Ok(())
+    }
+
+    /// Send AgentUpdate packets while wandering in random directions
+    pub async fn walk_randomly(&mut self, duration: Duration) -> NetworkResult<()> {
+        let deadline = Instant::now() + duration;
+        let step = self.config.update_interval.as_secs_f32() * 3.2; // walking speed in m/s
+
+        while Instant::now() < deadline && self.is_connected() {
+            self.heading += rand::thread_rng().gen_range(-0.5f32..0.5f32);
+            self.position[0] = (self.position[0] + self.heading.cos() * step).clamp(1.0, 255.0);
+            self.position[1] = (self.position[1] + self.heading.sin() * step).clamp(1.0, 255.0);
+
+            self.send_agent_update(AGENT_CONTROL_AT_POS).await?;
+            tokio::time::sleep(self.config.update_interval).await;
+        }
+
+        self.send_agent_update(0).await
+    }
+
+    /// Send a ChatFromViewer message
+    pub async fn chat(&mut self, message: &str, channel: i32) -> NetworkResult<()> {
+        let session = self.require_session()?;
+
+        let mut text = message.as_bytes().to_vec();
+        text.truncate(1023);
+        text.push(0);
+
+        let mut payload = Vec::with_capacity(40 + text.len());
+        payload.extend_from_slice(session.agent_id.as_bytes());
+        payload.extend_from_slice(session.session_id.as_bytes());
+        payload.extend_from_slice(&(text.len() as u16).to_le_bytes());
+        payload.extend_from_slice(&text);
+        payload.push(CHAT_TYPE_NORMAL);
+        payload.extend_from_slice(&channel.to_le_bytes());
+
+        self.send(packet_types::CHAT_FROM_VIEWER, payload, true).await?;
+        self.stats.chats_sent.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Send a RequestImage for a single texture
+    pub async fn request_texture(&mut self, texture_id: Uuid) -> NetworkResult<()> {
+        let session = self.require_session()?;
+
+        let mut payload = Vec::with_capacity(59);
+        payload.extend_from_slice(session.agent_id.as_bytes());
+        payload.extend_from_slice(session.session_id.as_bytes());
+        payload.push(1); // block count
+        payload.extend_from_slice(texture_id.as_bytes());
+        payload.push(0); // discard level
+        payload.extend_from_slice(&1_013_000.0f32.to_le_bytes()); // download priority
+        payload.extend_from_slice(&0u32.to_le_bytes()); // first packet
+        payload.push(0); // image type: normal
+
+        self.send(packet_types::REQUEST_IMAGE, payload, false).await?;
+        self.stats.textures_requested.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Send LogoutRequest and close the circuit
+    pub async fn logout(&mut self) -> NetworkResult<()> {
+        if !self.is_connected() {
+            return Ok(());
+        }
+        let session = self.require_session()?;
+
+        let mut payload = Vec::with_capacity(32);
+        payload.extend_from_slice(session.agent_id.as_bytes());
+        payload.extend_from_slice(session.session_id.as_bytes());
+        let result = self.send(packet_types::LOGOUT_REQUEST, payload, true).await;
+
+        self.running.store(false, Ordering::SeqCst);
+        self.socket = None;
+        result
+    }
+
+    async fn send_agent_update(&mut self, control_flags: u32) -> NetworkResult<()> {
+        let session = self.require_session()?;
+        let (sin, cos) = (self.heading / 2.0).sin_cos();
+        let rotation = [0.0f32, 0.0, sin];
+
+        let mut payload = Vec::with_capacity(114);
+        payload.extend_from_slice(session.agent_id.as_bytes());
+        payload.extend_from_slice(session.session_id.as_bytes());
+        for _ in 0..2 {
+            // body and head rotation (packed quaternion, w implied)
+            for v in rotation {
+                payload.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        payload.push(0); // state
+        for v in self.position {
+            payload.extend_from_slice(&v.to_le_bytes()); // camera center
+        }
+        for v in [cos, sin, 0.0, -sin, cos, 0.0, 0.0, 0.0, 1.0] {
+            payload.extend_from_slice(&v.to_le_bytes()); // camera at/left/up axes
+        }
+        payload.extend_from_slice(&64.0f32.to_le_bytes()); // draw distance
+        payload.extend_from_slice(&control_flags.to_le_bytes());
+        payload.push(0); // flags
+
+        self.send(packet_types::AGENT_UPDATE, payload, false).await?;
+        self.stats.agent_updates_sent.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn send(&self, message_id: u32, payload: Vec<u8>, reliable: bool) -> NetworkResult<()> {
+        let socket = self
+            .socket
+            .as_ref()
+            .ok_or_else(|| NetworkError::Session("Bot is not connected".to_string()))?;
+
+        let sequence = self.sequence.fetch_add(1, Ordering::SeqCst);
+        let flags = if reliable { mutsea_protocol::flags::RELIABLE } else { 0 };
+        let acks = std::mem::take(&mut *self.pending_acks.lock().await);
+
+        let packet = Packet::new(flags, sequence, payload)
+            .with_message_id(message_id)
+            .with_acks(acks);
+        let data = packet
+            .serialize()
+            .map_err(|e| NetworkError::Protocol(e.to_string()))?;
+
+        socket.send(&data).await?;
+        self.stats.packets_sent.fetch_add(1, Ordering::Relaxed);
+        self.stats.bytes_sent.fetch_add(data.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn spawn_receiver(&self, socket: Arc<UdpSocket>) {
+        let running = Arc::clone(&self.running);
+        let pending_acks = Arc::clone(&self.pending_acks);
+        let sequence = Arc::clone(&self.sequence);
+        let stats = Arc::clone(&self.stats);
+        let movement_complete = Arc::clone(&self.movement_complete);
+
+        tokio::spawn(async move {
+            let mut buffer = vec![0u8; MAX_PACKET_SIZE * 2];
+            while running.load(Ordering::SeqCst) {
+                let len = match tokio::time::timeout(Duration::from_millis(500), socket.recv(&mut buffer)).await {
+                    Ok(Ok(len)) => len,
+                    Ok(Err(e)) => {
+                        warn!("Bot receive error: {}", e);
+                        break;
+                    }
+                    Err(_) => {
+                        Self::flush_acks(&socket, &pending_acks, &stats).await;
+                        continue;
+                    }
+                };
+
+                stats.packets_received.fetch_add(1, Ordering::Relaxed);
+                stats.bytes_received.fetch_add(len as u64, Ordering::Relaxed);
+
+                let packet = match Packet::deserialize(&buffer[..len]) {
+                    Ok(packet) => packet,
+                    Err(e) => {
+                        debug!("Bot dropped malformed packet: {}", e);
+                        continue;
+                    }
+                };
+
+                if packet.header.is_reliable() {
+                    pending_acks.lock().await.push(packet.header.sequence);
+                }
+
+                if packet.message_id == Some(packet_types::AGENT_MOVEMENT_COMPLETE) {
+                    movement_complete.notify_one();
+                }
+
+                if packet.message_id == Some(packet_types::START_PING_CHECK as u32) {
+                    if let Some(&ping_id) = packet.payload.first() {
+                        let reply = Packet::new(0, sequence.fetch_add(1, Ordering::SeqCst), vec![ping_id])
+                            .with_message_id(packet_types::COMPLETE_PING_CHECK as u32);
+                        if let Ok(data) = reply.serialize() {
+                            if socket.send(&data).await.is_ok() {
+                                stats.packets_sent.fetch_add(1, Ordering::Relaxed);
+                                stats.bytes_sent.fetch_add(data.len() as u64, Ordering::Relaxed);
+                                stats.pings_answered.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                }
+
+                if pending_acks.lock().await.len() >= MAX_PENDING_ACKS {
+                    Self::flush_acks(&socket, &pending_acks, &stats).await;
+                }
+            }
+        });
+    }
+
+    async fn flush_acks(socket: &UdpSocket, pending_acks: &Mutex<Vec<u32>>, stats: &BotStats) {
+        let acks = std::mem::take(&mut *pending_acks.lock().await);
+        if acks.is_empty() {
+            return;
+        }
+        if let Ok(data) = Packet::ack(acks).serialize() {
+            if socket.send(&data).await.is_ok() {
+                stats.packets_sent.fetch_add(1, Ordering::Relaxed);
+                stats.bytes_sent.fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn require_session(&self) -> NetworkResult<BotSession> {
+        self.session
+            .clone()
+            .ok_or_else(|| NetworkError::Session("Bot is not logged in".to_string()))
+    }
+}
+
+impl Drop for BotClient {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+/// AGENT_CONTROL_AT_POS control flag (walk forward)
+const AGENT_CONTROL_AT_POS: u32 = 0x0000_0001;
+
+/// Normal chat type (20m range)
+const CHAT_TYPE_NORMAL: u8 = 1;
+
+/// Flush acknowledgements once this many are queued
+const MAX_PENDING_ACKS: usize = 32;
+
+/// Build the XML-RPC `login_to_simulator` request body
+fn build_login_request(config: &BotConfig) -> String {
+    let member = |name: &str, value: &str| {
+        format!(
+            "<member><name>{}</name><value><string>{}</string></value></member>",
+            name,
+            xml_escape(value)
+        )
+    };
+
+    format!(
+        "<?xml version=\"1.0\"?><methodCall><methodName>login_to_simulator</methodName>\
+         <params><param><value><struct>{}{}{}{}{}{}{}</struct></value></param></params></methodCall>",
+        member("first", &config.first_name),
+        member("last", &config.last_name),
+        member("passwd", &config.password),
+        member("start", &config.start),
+        member("channel", "Mutsea Bot"),
+        member("version", env!("CARGO_PKG_VERSION")),
+        member("platform", std::env::consts::OS),
+    )
+}
+
+/// Extract the session fields a bot needs from an XML-RPC login response
+fn parse_login_response(xml: &str) -> NetworkResult<BotSession> {
+    let login = extract_member(xml, "login").unwrap_or_default();
+    if login != "true" {
+        let message = extract_member(xml, "message").unwrap_or_else(|| "login failed".to_string());
+        return Err(NetworkError::AuthenticationFailed(message));
+    }
+
+    let uuid = |name: &str| -> NetworkResult<Uuid> {
+        extract_member(xml, name)
+            .and_then(|v| Uuid::parse_str(&v).ok())
+            .ok_or_else(|| NetworkError::Protocol(format!("Login response missing {}", name)))
+    };
+    let number = |name: &str| -> NetworkResult<u32> {
+        extract_member(xml, name)
+            .and_then(|v| v.parse::<i64>().ok())
+            .map(|v| v as u32)
+            .ok_or_else(|| NetworkError::Protocol(format!("Login response missing {}", name)))
+    };
+
+    let sim_ip = extract_member(xml, "sim_ip")
+        .ok_or_else(|| NetworkError::Protocol("Login response missing sim_ip".to_string()))?;
+    let sim_port = number("sim_port")? as u16;
+    let sim_address = format!("{}:{}", sim_ip, sim_port)
+        .parse::<SocketAddr>()
+        .or_else(|_| format!("[{}]:{}", sim_ip, sim_port).parse::<SocketAddr>())?;
+
+    Ok(BotSession {
+        agent_id: uuid("agent_id")?,
+        session_id: uuid("session_id")?,
+        secure_session_id: uuid("secure_session_id")?,
+        circuit_code: number("circuit_code")?,
+        sim_address,
+    })
+}
+
+/// Find `<name>NAME</name>` and return the text of the following `<value>`
+fn extract_member(xml: &str, name: &str) -> Option<String> {
+    let tag = format!("<name>{}</name>", name);
+    let start = xml.find(&tag)? + tag.len();
+    let rest = &xml[start..];
+    let value_start = rest.find("<value>")? + "<value>".len();
+    let value_end = rest[value_start..].find("</value>")? + value_start;
+    let mut value = rest[value_start..value_end].trim();
+
+    // Strip a typed wrapper such as <string>, <i4> or <int>
+    if value.starts_with('<') {
+        let open_end = value.find('>')?;
+        let inner = &value[open_end + 1..];
+        let close = inner.rfind("</")?;
+        value = inner[..close].trim();
+    }
+
+    Some(
+        value
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_login_response() {
+        let agent_id = Uuid::new_v4();
+        let session_id = Uuid::new_v4();
+        let xml = format!(
+            "<methodResponse><params><param><value><struct>\
+             <member><name>login</name><value><string>true</string></value></member>\
+             <member><name>agent_id</name><value><string>{}</string></value></member>\
+             <member><name>session_id</name><value><string>{}</string></value></member>\
+             <member><name>secure_session_id</name><value><string>{}</string></value></member>\
+             <member><name>circuit_code</name><value><i4>424242</i4></value></member>\
+             <member><name>sim_ip</name><value><string>127.0.0.1</string></value></member>\
+             <member><name>sim_port</name><value><i4>9000</i4></value></member>\
+             </struct></value></param></params></methodResponse>",
+            agent_id,
+            session_id,
+            Uuid::new_v4()
+        );
+
+        let session = parse_login_response(&xml).unwrap();
+        assert_eq!(session.agent_id, agent_id);
+        assert_eq!(session.session_id, session_id);
+        assert_eq!(session.circuit_code, 424242);
+        assert_eq!(session.sim_address, "127.0.0.1:9000".parse().unwrap());
+    }
+
+    #[test]
+    fn test_parse_failed_login() {
+        let xml = "<struct><member><name>login</name><value><string>false</string></value></member>\
+                   <member><name>message</name><value><string>Invalid credentials</string></value></member></struct>";
+        match parse_login_response(xml) {
+            Err(NetworkError::AuthenticationFailed(msg)) => assert_eq!(msg, "Invalid credentials"),
+            other => panic!("unexpected result: {:?}", other.map(|s| s.agent_id)),
+        }
+    }
+
+    /// A logged-in bot pointed at a local socket standing in for the simulator
+    async fn bot_with_sim(connect_timeout: Duration) -> (BotClient, UdpSocket) {
+        let sim = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut bot = BotClient::new(BotConfig {
+            connect_timeout,
+            ..Default::default()
+        });
+        bot.session = Some(BotSession {
+            agent_id: Uuid::new_v4(),
+            session_id: Uuid::new_v4(),
+            secure_session_id: Uuid::new_v4(),
+            circuit_code: 424242,
+            sim_address: sim.local_addr().unwrap(),
+        });
+        (bot, sim)
+    }
+
+    #[tokio::test]
+    async fn test_connect_waits_for_agent_movement_complete() {
+        let (mut bot, sim) = bot_with_sim(Duration::from_secs(5)).await;
+        let agent_id = bot.session().unwrap().agent_id;
+
+        let simulator = tokio::spawn(async move {
+            let mut buffer = vec![0u8; MAX_PACKET_SIZE];
+            loop {
+                let (len, viewer) = sim.recv_from(&mut buffer).await.unwrap();
+                let packet = Packet::deserialize(&buffer[..len]).unwrap();
+                if packet.message_id == Some(packet_types::COMPLETE_AGENT_MOVEMENT) {
+                    let reply = Packet::reliable(1, agent_id.as_bytes().to_vec())
+                        .with_message_id(packet_types::AGENT_MOVEMENT_COMPLETE);
+                    sim.send_to(&reply.serialize().unwrap(), viewer).await.unwrap();
+                    return;
+                }
+            }
+        });
+
+        bot.connect().await.unwrap();
+        simulator.await.unwrap();
+        assert!(bot.is_connected());
+        assert!(bot.connect_latency().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_connect_times_out_without_agent_movement_complete() {
+        let (mut bot, _sim) = bot_with_sim(Duration::from_millis(200)).await;
+
+        match bot.connect().await {
+            Err(NetworkError::Timeout(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(!bot.is_connected());
+        assert!(bot.connect_latency().is_none());
+    }
+
+    #[test]
+    fn test_random_activity_fills_duration() {
+        let script = BotScript::random_activity(Duration::from_secs(30), &[Uuid::new_v4()]);
+        let walked: Duration = script
+            .actions
+            .iter()
+            .filter_map(|a| match a {
+                BotAction::RandomWalk(d) | BotAction::Wait(d) => Some(*d),
+                _ => None,
+            })
+            .sum();
+        assert!(walked >= Duration::from_secs(30));
+    }
+}
@@ -1,5 +1,11 @@
 //! Client connection and management
 
+pub mod bench;
+pub mod bot;
+
+pub use bench::{run_bench, BenchConfig, BenchReport};
+pub use bot::{BotAction, BotClient, BotConfig, BotScript, BotSession, BotStats};
+
 use mutsea_core::UserId;
 use std::net::SocketAddr;
 use std::sync::Arc;
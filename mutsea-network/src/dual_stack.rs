@@ -0,0 +1,87 @@
+//! Dual-stack socket binding
+//!
+//! Binding the IPv6 wildcard (`::`) with `IPV6_V6ONLY` cleared accepts both
+//! IPv6 clients and IPv4 clients (as IPv4-mapped addresses) on one socket.
+//! The default for that option differs between platforms, so it is set
+//! explicitly.
+
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, UdpSocket};
+
+/// Backlog for listening TCP sockets
+const LISTEN_BACKLOG: i32 = 1024;
+
+fn socket(addr: SocketAddr, ty: Type, protocol: Protocol, dual_stack: bool) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(addr), ty, Some(protocol))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(!(dual_stack && addr.ip().is_unspecified()))?;
+    }
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// Bind a UDP socket; an IPv6 wildcard address also accepts IPv4 when
+/// `dual_stack` is set
+pub fn bind_udp(addr: SocketAddr, dual_stack: bool) -> io::Result<UdpSocket> {
+    let socket = socket(addr, Type::DGRAM, Protocol::UDP, dual_stack)?;
+    socket.bind(&addr.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Bind `workers` UDP sockets to one port with `SO_REUSEPORT`, so the kernel
+/// spreads incoming packets over them; platforms without the option get a
+/// single socket
+pub fn bind_udp_workers(mut addr: SocketAddr, dual_stack: bool, workers: usize) -> io::Result<Vec<UdpSocket>> {
+    #[cfg(unix)]
+    if workers > 1 {
+        let mut sockets = Vec::with_capacity(workers);
+        for _ in 0..workers {
+            let socket = socket(addr, Type::DGRAM, Protocol::UDP, dual_stack)?;
+            socket.set_reuse_port(true)?;
+            socket.bind(&addr.into())?;
+            let socket = UdpSocket::from_std(socket.into())?;
+            // Later workers join the port the first one was given
+            addr.set_port(socket.local_addr()?.port());
+            sockets.push(socket);
+        }
+        return Ok(sockets);
+    }
+    #[cfg(not(unix))]
+    let _ = workers;
+    Ok(vec![bind_udp(addr, dual_stack)?])
+}
+
+/// Bind a listening TCP socket; an IPv6 wildcard address also accepts IPv4
+/// when `dual_stack` is set
+pub fn bind_tcp(addr: SocketAddr, dual_stack: bool) -> io::Result<TcpListener> {
+    let socket = socket(addr, Type::STREAM, Protocol::TCP, dual_stack)?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    TcpListener::from_std(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ipv4_binds() {
+        let socket = bind_udp("127.0.0.1:0".parse().unwrap(), true).unwrap();
+        assert!(socket.local_addr().unwrap().is_ipv4());
+
+        let listener = bind_tcp("127.0.0.1:0".parse().unwrap(), true).unwrap();
+        assert!(listener.local_addr().unwrap().is_ipv4());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_workers_share_the_port() {
+        let sockets = bind_udp_workers("127.0.0.1:0".parse().unwrap(), true, 3).unwrap();
+        assert_eq!(sockets.len(), 3);
+        let port = sockets[0].local_addr().unwrap().port();
+        assert!(sockets.iter().all(|socket| socket.local_addr().unwrap().port() == port));
+    }
+}
@@ -1,5 +1,6 @@
 //! Update mutsea-network/src/error.rs
 
+use mutsea_core::{ErrorCategory, ErrorClassification};
 use thiserror::Error;
 
 /// Network-specific errors
@@ -84,5 +85,38 @@ impl From<mutsea_protocol::ProtocolError> for NetworkError {
     }
 }
 
+impl ErrorClassification for NetworkError {
+    fn code(&self) -> &'static str {
+        match self {
+            NetworkError::Io(_) => "NET_IO",
+            NetworkError::InvalidAddress(_) => "NET_INVALID_ADDRESS",
+            NetworkError::Protocol(_) => "NET_PROTOCOL",
+            NetworkError::InvalidPacket(_) => "NET_INVALID_PACKET",
+            NetworkError::ClientNotFound(_) => "NET_CLIENT_NOT_FOUND",
+            NetworkError::Session(_) => "NET_SESSION",
+            NetworkError::Timeout(_) => "NET_TIMEOUT",
+            NetworkError::RateLimitExceeded => "NET_RATE_LIMITED",
+            NetworkError::AuthenticationFailed(_) => "NET_AUTHENTICATION",
+            NetworkError::AuthorizationFailed(_) => "NET_AUTHORIZATION",
+            NetworkError::Serialization(_) => "NET_SERIALIZATION",
+            NetworkError::Compression(_) => "NET_COMPRESSION",
+            NetworkError::Generic(_) => "NET_INTERNAL",
+        }
+    }
+
+    fn category(&self) -> ErrorCategory {
+        match self {
+            NetworkError::Io(_) | NetworkError::Timeout(_) | NetworkError::RateLimitExceeded => ErrorCategory::Retryable,
+            _ => ErrorCategory::Permanent,
+        }
+    }
+}
+
+impl From<NetworkError> for mutsea_core::MutseaError {
+    fn from(err: NetworkError) -> Self {
+        mutsea_core::MutseaError::classified(&err)
+    }
+}
+
 /// Result type for network operations
 pub type NetworkResult<T> = Result<T, NetworkError>;
\ No newline at end of file
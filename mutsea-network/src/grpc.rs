@@ -0,0 +1,85 @@
+//! Internal gRPC services between Mutsea nodes
+//!
+//! The services are generated from `proto/internal.proto`. Every node has a
+//! certificate signed by the deployment's CA; servers only accept clients
+//! presenting one, and clients only trust servers presenting one, so the
+//! services are never exposed to anything outside the deployment.
+
+use crate::{NetworkError, NetworkResult};
+use mutsea_core::config::GrpcConfig;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity, Server, ServerTlsConfig};
+
+/// Generated messages, clients and server traits
+#[allow(missing_docs, clippy::all)]
+pub mod proto {
+    tonic::include_proto!("mutsea.internal.v1");
+}
+
+use proto::{
+    asset_service_client::AssetServiceClient, presence_service_client::PresenceServiceClient,
+    region_control_client::RegionControlClient, user_service_client::UserServiceClient,
+};
+
+/// A server builder that requires clients to present a node certificate
+pub async fn server(config: &GrpcConfig) -> NetworkResult<Server> {
+    let tls = ServerTlsConfig::new()
+        .identity(identity(config).await?)
+        .client_ca_root(ca(config).await?);
+    Server::builder()
+        .tls_config(tls)
+        .map_err(|e| NetworkError::Protocol(format!("gRPC TLS: {}", e)))
+}
+
+/// Connect to the node at `endpoint` (such as `https://assets.grid:9100`)
+pub async fn connect(config: &GrpcConfig, endpoint: &str) -> NetworkResult<Channel> {
+    let tls = ClientTlsConfig::new()
+        .identity(identity(config).await?)
+        .ca_certificate(ca(config).await?);
+    Channel::from_shared(endpoint.to_string())
+        .map_err(|e| NetworkError::Protocol(format!("Invalid gRPC endpoint {}: {}", endpoint, e)))?
+        .tls_config(tls)
+        .map_err(|e| NetworkError::Protocol(format!("gRPC TLS: {}", e)))?
+        .connect()
+        .await
+        .map_err(|e| NetworkError::Protocol(format!("Failed to connect to {}: {}", endpoint, e)))
+}
+
+/// Clients of every internal service, sharing one connection
+#[derive(Clone)]
+pub struct GrpcClients {
+    /// Asset storage
+    pub assets: AssetServiceClient<Channel>,
+    /// User accounts
+    pub users: UserServiceClient<Channel>,
+    /// Agents on the node
+    pub presence: PresenceServiceClient<Channel>,
+    /// Region status and control
+    pub regions: RegionControlClient<Channel>,
+}
+
+impl GrpcClients {
+    /// Connect to the node at `endpoint`
+    pub async fn connect(config: &GrpcConfig, endpoint: &str) -> NetworkResult<Self> {
+        Ok(Self::new(connect(config, endpoint).await?))
+    }
+
+    /// Clients over an established `channel`
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            assets: AssetServiceClient::new(channel.clone()),
+            users: UserServiceClient::new(channel.clone()),
+            presence: PresenceServiceClient::new(channel.clone()),
+            regions: RegionControlClient::new(channel),
+        }
+    }
+}
+
+async fn identity(config: &GrpcConfig) -> NetworkResult<Identity> {
+    let cert = tokio::fs::read(&config.cert_file).await?;
+    let key = tokio::fs::read(&config.key_file).await?;
+    Ok(Identity::from_pem(cert, key))
+}
+
+async fn ca(config: &GrpcConfig) -> NetworkResult<Certificate> {
+    Ok(Certificate::from_pem(tokio::fs::read(&config.ca_file).await?))
+}
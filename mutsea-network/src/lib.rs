@@ -6,6 +6,7 @@
 #![warn(missing_docs)]
 #![warn(clippy::all)]
 
+#[path = "../../mutsea-lludp-server/src/mod.rs"]
 pub mod lludp_server;
 pub mod http;
 pub mod websocket;
@@ -13,6 +14,13 @@ pub mod client;
 pub mod message;
 pub mod session;
 pub mod error;
+pub mod dual_stack;
+pub mod nat;
+pub mod plugin;
+#[cfg(feature = "webrtc")]
+pub mod webrtc;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 
 // Re-export commonly used types
 pub use error::*;
@@ -20,7 +28,7 @@ pub use message::*;
 pub use session::*;
 pub use client::*;
 
-use mutsea_core::{MutseaResult, Service, ServiceHealth, ServiceStatus, NetworkError};
+use mutsea_core::{MutseaResult, Service, ServiceHealth, ServiceStatus};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
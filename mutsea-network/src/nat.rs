@@ -0,0 +1,212 @@
+//! NAT traversal helpers
+//!
+//! A region behind NAT has to tell viewers its public address, which it
+//! cannot see on any local interface. It can be configured directly, taken
+//! from a DNS name (dynamic DNS setups) or discovered by asking a STUN
+//! server (RFC 5389) which address our packets arrive from.
+
+use crate::{NetworkError, NetworkResult};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::{lookup_host, UdpSocket};
+use tracing::{debug, warn};
+
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const HEADER_LEN: usize = 20;
+
+/// Encode a STUN Binding Request with the given transaction ID
+pub fn binding_request(transaction_id: [u8; 12]) -> Vec<u8> {
+    let mut request = Vec::with_capacity(HEADER_LEN);
+    request.extend_from_slice(&BINDING_REQUEST.to_be_bytes());
+    request.extend_from_slice(&0u16.to_be_bytes());
+    request.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    request.extend_from_slice(&transaction_id);
+    request
+}
+
+/// Mapped address from a STUN Binding Success response. XOR-MAPPED-ADDRESS
+/// is preferred; MAPPED-ADDRESS is accepted from older servers.
+pub fn parse_binding_response(response: &[u8], transaction_id: [u8; 12]) -> NetworkResult<SocketAddr> {
+    if response.len() < HEADER_LEN {
+        return Err(NetworkError::InvalidPacket("STUN response too short".to_string()));
+    }
+    let message_type = u16::from_be_bytes([response[0], response[1]]);
+    let length = u16::from_be_bytes([response[2], response[3]]) as usize;
+    if message_type != BINDING_SUCCESS {
+        return Err(NetworkError::Protocol(format!("unexpected STUN message type {:#06x}", message_type)));
+    }
+    if response[4..8] != MAGIC_COOKIE.to_be_bytes() || response[8..20] != transaction_id {
+        return Err(NetworkError::Protocol("STUN response does not match request".to_string()));
+    }
+    let body = response
+        .get(HEADER_LEN..HEADER_LEN + length)
+        .ok_or_else(|| NetworkError::InvalidPacket("truncated STUN response".to_string()))?;
+
+    let mut mapped = None;
+    let mut offset = 0;
+    while offset + 4 <= body.len() {
+        let attr_type = u16::from_be_bytes([body[offset], body[offset + 1]]);
+        let attr_len = u16::from_be_bytes([body[offset + 2], body[offset + 3]]) as usize;
+        let value = body
+            .get(offset + 4..offset + 4 + attr_len)
+            .ok_or_else(|| NetworkError::InvalidPacket("truncated STUN attribute".to_string()))?;
+
+        match attr_type {
+            ATTR_XOR_MAPPED_ADDRESS => return decode_address(value, Some(&transaction_id)),
+            ATTR_MAPPED_ADDRESS => mapped = Some(decode_address(value, None)?),
+            _ => {}
+        }
+        // Attributes are padded to a multiple of four bytes
+        offset += 4 + attr_len.div_ceil(4) * 4;
+    }
+
+    mapped.ok_or_else(|| NetworkError::Protocol("STUN response has no mapped address".to_string()))
+}
+
+fn decode_address(value: &[u8], xor_with: Option<&[u8; 12]>) -> NetworkResult<SocketAddr> {
+    if value.len() < 4 {
+        return Err(NetworkError::InvalidPacket("short STUN address".to_string()));
+    }
+    let cookie = MAGIC_COOKIE.to_be_bytes();
+    let mut port = u16::from_be_bytes([value[2], value[3]]);
+    if xor_with.is_some() {
+        port ^= (MAGIC_COOKIE >> 16) as u16;
+    }
+
+    let ip = match (value[1], &value[4..]) {
+        (0x01, octets) if octets.len() >= 4 => {
+            let mut bytes = [octets[0], octets[1], octets[2], octets[3]];
+            if xor_with.is_some() {
+                bytes.iter_mut().zip(cookie).for_each(|(b, k)| *b ^= k);
+            }
+            IpAddr::V4(Ipv4Addr::from(bytes))
+        }
+        (0x02, octets) if octets.len() >= 16 => {
+            let mut bytes = [0u8; 16];
+            bytes.copy_from_slice(&octets[..16]);
+            if let Some(transaction_id) = xor_with {
+                let key = cookie.iter().chain(transaction_id.iter());
+                bytes.iter_mut().zip(key).for_each(|(b, k)| *b ^= k);
+            }
+            IpAddr::V6(Ipv6Addr::from(bytes))
+        }
+        (family, _) => {
+            return Err(NetworkError::InvalidPacket(format!("bad STUN address family {}", family)));
+        }
+    };
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// Ask one STUN server (`host:port`) for our public address
+pub async fn stun_query(server: &str, timeout: Duration) -> NetworkResult<SocketAddr> {
+    let server_addr = lookup_host(server)
+        .await?
+        .find(|a| a.is_ipv4())
+        .ok_or_else(|| NetworkError::Generic(format!("STUN server {} has no IPv4 address", server)))?;
+
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let transaction_id: [u8; 12] = rand::random();
+    socket.send_to(&binding_request(transaction_id), server_addr).await?;
+
+    let mut buf = [0u8; 512];
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let (len, from) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf))
+            .await
+            .map_err(|_| NetworkError::Timeout(format!("no STUN response from {}", server)))??;
+        if from != server_addr {
+            continue;
+        }
+        return parse_binding_response(&buf[..len], transaction_id);
+    }
+}
+
+/// Public IPv4 address as seen by the first STUN server that answers
+pub async fn discover_external_ip(servers: &[String], timeout: Duration) -> Option<IpAddr> {
+    for server in servers {
+        match stun_query(server, timeout).await {
+            Ok(mapped) => {
+                debug!("STUN server {} reports external address {}", server, mapped);
+                return Some(mapped.ip());
+            }
+            Err(e) => warn!("STUN query to {} failed: {}", server, e),
+        }
+    }
+    None
+}
+
+/// Resolve a host name to its addresses
+pub async fn resolve_host(host: &str) -> NetworkResult<Vec<IpAddr>> {
+    if let Ok(ip) = mutsea_core::net::parse_ip(host) {
+        return Ok(vec![ip]);
+    }
+    let addresses: Vec<IpAddr> = lookup_host((host, 0)).await?.map(|a| a.ip()).collect();
+    if addresses.is_empty() {
+        return Err(NetworkError::Generic(format!("{} did not resolve", host)));
+    }
+    Ok(addresses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(transaction_id: [u8; 12], attr_type: u16, value: &[u8]) -> Vec<u8> {
+        let mut response = Vec::new();
+        response.extend_from_slice(&BINDING_SUCCESS.to_be_bytes());
+        response.extend_from_slice(&((4 + value.len()) as u16).to_be_bytes());
+        response.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        response.extend_from_slice(&transaction_id);
+        response.extend_from_slice(&attr_type.to_be_bytes());
+        response.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        response.extend_from_slice(value);
+        response
+    }
+
+    #[test]
+    fn test_parse_xor_and_plain_mapped_address() {
+        let transaction_id = [7u8; 12];
+
+        // 203.0.113.9:40000 XORed with the magic cookie
+        let port = 40000u16 ^ 0x2112;
+        let ip: Vec<u8> = [203u8, 0, 113, 9].iter().zip(MAGIC_COOKIE.to_be_bytes()).map(|(a, k)| a ^ k).collect();
+        let mut value = vec![0, 0x01];
+        value.extend_from_slice(&port.to_be_bytes());
+        value.extend_from_slice(&ip);
+        let mapped = parse_binding_response(&response(transaction_id, ATTR_XOR_MAPPED_ADDRESS, &value), transaction_id);
+        assert_eq!(mapped.unwrap(), "203.0.113.9:40000".parse().unwrap());
+
+        let mut value = vec![0, 0x01];
+        value.extend_from_slice(&40000u16.to_be_bytes());
+        value.extend_from_slice(&[198, 51, 100, 4]);
+        let mapped = parse_binding_response(&response(transaction_id, ATTR_MAPPED_ADDRESS, &value), transaction_id);
+        assert_eq!(mapped.unwrap(), "198.51.100.4:40000".parse().unwrap());
+
+        // Responses for other transactions are rejected
+        assert!(parse_binding_response(&response([1u8; 12], ATTR_MAPPED_ADDRESS, &value), transaction_id).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stun_query_against_local_server() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let (_, from) = server.recv_from(&mut buf).await.unwrap();
+            let mut transaction_id = [0u8; 12];
+            transaction_id.copy_from_slice(&buf[8..20]);
+
+            let mut value = vec![0, 0x01];
+            value.extend_from_slice(&from.port().to_be_bytes());
+            value.extend_from_slice(&[192, 0, 2, 77]);
+            server.send_to(&response(transaction_id, ATTR_MAPPED_ADDRESS, &value), from).await.unwrap();
+        });
+
+        let mapped = stun_query(&server_addr.to_string(), Duration::from_secs(2)).await.unwrap();
+        assert_eq!(mapped.ip(), "192.0.2.77".parse::<IpAddr>().unwrap());
+    }
+}
@@ -0,0 +1,387 @@
+//! Server plugins
+//!
+//! Third parties extend the server without forking it by implementing
+//! [`MutseaPlugin`]. A plugin is handed the server's shared services when
+//! it starts, sees every circuit and agent event, and may serve HTTP routes
+//! (under `/plugins/<name>`) and handle LLUDP messages the server doesn't.
+//!
+//! Plugins compiled into a build are registered with
+//! [`PluginHost::register`]. With the `plugins` feature, plugins can also be
+//! built as a `cdylib` declaring its plugin with [`export_plugin!`] and
+//! loaded at startup. Such a library must be built with the same compiler
+//! and Mutsea version as the server; only the plugin API version is checked.
+
+use crate::lludp_server::{LLUDPServer, MessageHandler};
+use async_trait::async_trait;
+use axum::Router;
+use mutsea_core::events::NetworkEvent;
+use serde::Serialize;
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, info, warn};
+
+/// Version of the plugin API, raised whenever [`MutseaPlugin`] or the
+/// services it is given change incompatibly
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+/// Plugin loading and startup errors
+#[derive(Debug, Error)]
+pub enum PluginError {
+    /// Another plugin has the same name
+    #[error("A plugin named {0} is already registered")]
+    Duplicate(String),
+    /// A plugin library couldn't be loaded
+    #[error("Failed to load plugin library {path}: {reason}")]
+    Load {
+        /// Library path
+        path: String,
+        /// Why it failed
+        reason: String,
+    },
+    /// A plugin library was built for another plugin API
+    #[error("Plugin library {path} was built for plugin API {found}, this server has {expected}")]
+    Incompatible {
+        /// Library path
+        path: String,
+        /// API version of the library
+        found: u32,
+        /// API version of the server
+        expected: u32,
+    },
+    /// A service a plugin needs isn't shared by this server
+    #[error("Service {0} is not available to plugins")]
+    MissingService(&'static str),
+    /// A plugin failed to start
+    #[error("{0}")]
+    Failed(String),
+}
+
+/// Services the server shares with plugins, one of each type
+#[derive(Clone, Default)]
+pub struct ServiceRegistry {
+    services: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl ServiceRegistry {
+    /// An empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Share `service`, replacing any of the same type
+    pub fn insert<T: Any + Send + Sync>(&mut self, service: Arc<T>) {
+        self.services.insert(TypeId::of::<T>(), service);
+    }
+
+    /// The shared service of type `T`
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        let service = Arc::clone(self.services.get(&TypeId::of::<T>())?);
+        service.downcast::<T>().ok()
+    }
+
+    /// The shared service of type `T`, for plugins that can't do without it
+    pub fn require<T: Any + Send + Sync>(&self) -> Result<Arc<T>, PluginError> {
+        self.get::<T>().ok_or(PluginError::MissingService(type_name::<T>()))
+    }
+}
+
+/// An extension of the server
+#[async_trait]
+pub trait MutseaPlugin: Send + Sync {
+    /// Unique name, also naming its settings and HTTP routes
+    fn name(&self) -> &str;
+
+    /// Version shown to operators
+    fn version(&self) -> &str;
+
+    /// Set the plugin up with the server's `services` and the plugin's
+    /// `settings` from the configuration (`null` when there are none). A
+    /// plugin that fails to start is left out.
+    async fn init(&self, services: &ServiceRegistry, settings: &serde_json::Value) -> Result<(), PluginError>;
+
+    /// Routes to serve under `/plugins/<name>`
+    fn routes(&self) -> Option<Router> {
+        None
+    }
+
+    /// LLUDP messages to handle, by message ID. Messages the server handles
+    /// itself never reach plugins.
+    fn message_handlers(&self) -> Vec<(u32, Arc<dyn MessageHandler>)> {
+        Vec::new()
+    }
+
+    /// A circuit or agent event, such as a login, chat or logout
+    async fn on_event(&self, _event: &NetworkEvent) {}
+
+    /// The server is stopping
+    async fn shutdown(&self) {}
+}
+
+/// Declare the plugin of a plugin library, built by `$constructor`
+#[macro_export]
+macro_rules! export_plugin {
+    ($constructor:expr) => {
+        #[no_mangle]
+        pub extern "C" fn mutsea_plugin_api_version() -> u32 {
+            $crate::plugin::PLUGIN_API_VERSION
+        }
+
+        #[no_mangle]
+        pub fn mutsea_plugin_create() -> Box<dyn $crate::plugin::MutseaPlugin> {
+            Box::new($constructor)
+        }
+    };
+}
+
+/// A plugin as reported to operators
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PluginInfo {
+    /// Plugin name
+    pub name: String,
+    /// Plugin version
+    pub version: String,
+    /// Whether it started
+    pub running: bool,
+    /// Whether it serves HTTP routes
+    pub routes: bool,
+    /// LLUDP messages it handles
+    pub message_ids: Vec<u32>,
+}
+
+/// Loads, starts and stops plugins
+#[derive(Default)]
+pub struct PluginHost {
+    lludp_server: Option<LLUDPServer>,
+    plugins: Vec<Arc<dyn MutseaPlugin>>,
+    info: Vec<PluginInfo>,
+}
+
+impl PluginHost {
+    /// A host without plugins
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deliver the LLUDP server's events and messages to plugins
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Add a plugin compiled into this build
+    pub fn register(&mut self, plugin: Arc<dyn MutseaPlugin>) -> Result<(), PluginError> {
+        if self.plugins.iter().any(|p| p.name() == plugin.name()) {
+            return Err(PluginError::Duplicate(plugin.name().to_string()));
+        }
+        self.plugins.push(plugin);
+        Ok(())
+    }
+
+    /// Add the plugin of the library at `path`. The library stays loaded
+    /// for the life of the process.
+    #[cfg(feature = "plugins")]
+    pub fn load(&mut self, path: &std::path::Path) -> Result<(), PluginError> {
+        let path_string = path.display().to_string();
+        let load_error = |reason: String| PluginError::Load {
+            path: path_string.clone(),
+            reason,
+        };
+
+        // SAFETY: plugin libraries are code the operator chose to run, built
+        // against this crate with the same compiler, as the module docs say
+        let plugin = unsafe {
+            let library = libloading::Library::new(path).map_err(|e| load_error(e.to_string()))?;
+            let version = library
+                .get::<extern "C" fn() -> u32>(b"mutsea_plugin_api_version\0")
+                .map_err(|e| load_error(e.to_string()))?();
+            if version != PLUGIN_API_VERSION {
+                return Err(PluginError::Incompatible {
+                    path: path_string,
+                    found: version,
+                    expected: PLUGIN_API_VERSION,
+                });
+            }
+            let plugin = library
+                .get::<fn() -> Box<dyn MutseaPlugin>>(b"mutsea_plugin_create\0")
+                .map_err(|e| load_error(e.to_string()))?();
+            // Code of the plugin may run until the process exits
+            std::mem::forget(library);
+            plugin
+        };
+        info!(
+            "Loaded plugin {} {} from {}",
+            plugin.name(),
+            plugin.version(),
+            path_string
+        );
+        self.register(Arc::from(plugin))
+    }
+
+    /// Start every plugin with `services` and its entry in `settings`,
+    /// leaving out those that fail. Returns the routes of those started.
+    pub async fn start(&mut self, services: &ServiceRegistry, settings: &HashMap<String, serde_json::Value>) -> Router {
+        let mut started = Vec::new();
+        let mut routes = Router::new();
+        self.info.clear();
+        for plugin in &self.plugins {
+            let name = plugin.name().to_string();
+            let mut info = PluginInfo {
+                name: name.clone(),
+                version: plugin.version().to_string(),
+                running: false,
+                routes: false,
+                message_ids: Vec::new(),
+            };
+            let plugin_settings = settings.get(&name).cloned().unwrap_or_default();
+            if let Err(e) = plugin.init(services, &plugin_settings).await {
+                error!("Plugin {} failed to start: {}", name, e);
+                self.info.push(info);
+                continue;
+            }
+
+            if let Some(plugin_routes) = plugin.routes() {
+                routes = routes.nest(&format!("/plugins/{}", name), plugin_routes);
+                info.routes = true;
+            }
+            for (message_id, handler) in plugin.message_handlers() {
+                match &self.lludp_server {
+                    Some(lludp_server) => lludp_server.add_message_handler(message_id, handler),
+                    None => warn!("Plugin {} handles messages, but there is no LLUDP server", name),
+                }
+                info.message_ids.push(message_id);
+            }
+            info.running = true;
+            info!("Started plugin {} {}", name, info.version);
+            self.info.push(info);
+            started.push(Arc::clone(plugin));
+        }
+
+        if let Some(lludp_server) = &self.lludp_server {
+            if !started.is_empty() {
+                Self::deliver_events(lludp_server, started);
+            }
+        }
+        routes
+    }
+
+    /// Hand every LLUDP event to the `plugins`, in order
+    fn deliver_events(lludp_server: &LLUDPServer, plugins: Vec<Arc<dyn MutseaPlugin>>) {
+        let mut events = lludp_server.subscribe_events();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        for plugin in &plugins {
+                            plugin.on_event(&event).await;
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => warn!("Plugins missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// The plugins and whether each started
+    pub fn plugins(&self) -> Vec<PluginInfo> {
+        self.info.clone()
+    }
+
+    /// Tell the started plugins the server is stopping
+    pub async fn shutdown(&self) {
+        for plugin in &self.plugins {
+            if self.info.iter().any(|info| info.name == plugin.name() && info.running) {
+                plugin.shutdown().await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use mutsea_core::UserId;
+    use std::sync::Mutex;
+
+    struct Greeter {
+        greeting: Mutex<String>,
+        fail: bool,
+    }
+
+    struct Echo;
+
+    impl MessageHandler for Echo {
+        fn handle(&self, _circuit_code: u32, _agent_id: UserId, _payload: &[u8]) {}
+    }
+
+    #[async_trait]
+    impl MutseaPlugin for Greeter {
+        fn name(&self) -> &str {
+            if self.fail {
+                "broken"
+            } else {
+                "greeter"
+            }
+        }
+
+        fn version(&self) -> &str {
+            "1.0.0"
+        }
+
+        async fn init(&self, services: &ServiceRegistry, settings: &serde_json::Value) -> Result<(), PluginError> {
+            if self.fail {
+                services.require::<LLUDPServer>()?;
+            }
+            let name = services.require::<String>()?;
+            let greeting = settings["greeting"].as_str().unwrap_or("Hello");
+            *self.greeting.lock().unwrap() = format!("{}, {}", greeting, name);
+            Ok(())
+        }
+
+        fn routes(&self) -> Option<Router> {
+            Some(Router::new().route("/hello", get(|| async { "hello" })))
+        }
+
+        fn message_handlers(&self) -> Vec<(u32, Arc<dyn MessageHandler>)> {
+            vec![(0xFFFF_0100, Arc::new(Echo))]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plugins_start_with_services_and_settings() {
+        let greeter = Arc::new(Greeter {
+            greeting: Mutex::new(String::new()),
+            fail: false,
+        });
+        let mut host = PluginHost::new();
+        host.register(greeter.clone()).unwrap();
+        host.register(Arc::new(Greeter {
+            greeting: Mutex::new(String::new()),
+            fail: true,
+        }))
+        .unwrap();
+        assert!(matches!(
+            host.register(greeter.clone()),
+            Err(PluginError::Duplicate(name)) if name == "greeter"
+        ));
+
+        let mut services = ServiceRegistry::new();
+        services.insert(Arc::new("Mutsea".to_string()));
+        assert!(services.get::<u32>().is_none());
+        let settings = HashMap::from([("greeter".to_string(), serde_json::json!({ "greeting": "Welcome" }))]);
+        let _routes = host.start(&services, &settings).await;
+
+        assert_eq!(*greeter.greeting.lock().unwrap(), "Welcome, Mutsea");
+        let plugins = host.plugins();
+        assert_eq!(plugins.len(), 2);
+        assert!(plugins[0].running && plugins[0].routes);
+        assert_eq!(plugins[0].message_ids, vec![0xFFFF_0100]);
+        // A plugin missing a service it requires is left out
+        assert!(!plugins[1].running);
+        host.shutdown().await;
+    }
+}
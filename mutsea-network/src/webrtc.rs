@@ -0,0 +1,189 @@
+//! WebRTC data channel transport for browser viewers
+//!
+//! Browsers can't send UDP, but they can open WebRTC data channels. A
+//! browser viewer posts an SDP offer to `/webrtc/offer` and gets our answer;
+//! ICE candidates are gathered before answering, so no trickle signaling is
+//! needed. The viewer then opens a data channel labelled `lludp` and sends
+//! the same LLUDP packets a desktop viewer would, one per message. Each peer
+//! is a tunnel of the LLUDP server, so its circuit, session and message
+//! stream (objects, chat, updates) are handled as any other. LLUDP does its
+//! own acks and resends, so the channel should be unordered with no
+//! retransmits, like UDP.
+
+use crate::lludp_server::LLUDPServer;
+use crate::{NetworkError, NetworkResult};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::post, Router};
+use bytes::Bytes;
+use mutsea_core::config::WebRtcConfig;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, info, warn};
+use webrtc::api::{APIBuilder, API};
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+
+/// Label of the data channel carrying LLUDP packets
+pub const DATA_CHANNEL_LABEL: &str = "lludp";
+
+/// Accepts browser peers and tunnels their data channels into the LLUDP
+/// server. Clones share the peers.
+#[derive(Clone)]
+pub struct WebRtcTransport {
+    config: WebRtcConfig,
+    lludp_server: LLUDPServer,
+    api: Arc<API>,
+    peers: Arc<Mutex<HashMap<SocketAddr, Arc<RTCPeerConnection>>>>,
+}
+
+impl WebRtcTransport {
+    /// A transport feeding `lludp_server`
+    pub fn new(config: WebRtcConfig, lludp_server: LLUDPServer) -> Self {
+        Self {
+            config,
+            lludp_server,
+            api: Arc::new(APIBuilder::new().build()),
+            peers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Number of connected browser peers
+    pub async fn peer_count(&self) -> usize {
+        self.peers.lock().await.len()
+    }
+
+    /// Answer a browser's SDP offer, returning our SDP answer
+    pub async fn accept(&self, offer_sdp: String) -> NetworkResult<String> {
+        if self.peer_count().await >= self.config.max_peers {
+            return Err(NetworkError::RateLimitExceeded);
+        }
+        let offer = RTCSessionDescription::offer(offer_sdp).map_err(webrtc_error)?;
+        let configuration = RTCConfiguration {
+            ice_servers: vec![RTCIceServer {
+                urls: self.config.ice_servers.clone(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let peer = Arc::new(self.api.new_peer_connection(configuration).await.map_err(webrtc_error)?);
+        let (addr, packets) = self.lludp_server.open_tunnel();
+        self.peers.lock().await.insert(addr, Arc::clone(&peer));
+
+        self.on_data_channel(&peer, addr, packets);
+        let transport = self.clone();
+        peer.on_peer_connection_state_change(Box::new(move |state: RTCPeerConnectionState| {
+            let transport = transport.clone();
+            Box::pin(async move {
+                debug!("WebRTC peer {} is {}", addr, state);
+                if matches!(state, RTCPeerConnectionState::Failed | RTCPeerConnectionState::Closed) {
+                    transport.disconnect(addr).await;
+                }
+            })
+        }));
+
+        match self.answer(&peer, offer).await {
+            Ok(answer) => {
+                info!("🌐 Browser peer connected through tunnel {}", addr);
+                Ok(answer)
+            }
+            Err(e) => {
+                self.disconnect(addr).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Routes for signaling, to merge into the HTTP server
+    pub fn routes(&self) -> Router {
+        Router::new()
+            .route("/webrtc/offer", post(offer_handler))
+            .with_state(self.clone())
+    }
+
+    /// Forget the peer with tunnel `addr`, closing its circuit
+    async fn disconnect(&self, addr: SocketAddr) {
+        let Some(peer) = self.peers.lock().await.remove(&addr) else {
+            return;
+        };
+        self.lludp_server.close_tunnel(addr).await;
+        if let Err(e) = peer.close().await {
+            debug!("Failed to close WebRTC peer {}: {}", addr, e);
+        }
+        info!("🌐 Browser peer {} disconnected", addr);
+    }
+
+    /// Carry packets between the peer's `lludp` channel and its tunnel
+    fn on_data_channel(&self, peer: &RTCPeerConnection, addr: SocketAddr, packets: mpsc::UnboundedReceiver<Vec<u8>>) {
+        // Only the first lludp channel gets the tunnel's outbound packets
+        let packets = Arc::new(Mutex::new(Some(packets)));
+        let lludp_server = self.lludp_server.clone();
+        peer.on_data_channel(Box::new(move |channel: Arc<RTCDataChannel>| {
+            let (packets, lludp_server) = (Arc::clone(&packets), lludp_server.clone());
+            Box::pin(async move {
+                if channel.label() != DATA_CHANNEL_LABEL {
+                    warn!("Ignoring WebRTC data channel '{}' from {}", channel.label(), addr);
+                    return;
+                }
+                let Some(mut packets) = packets.lock().await.take() else {
+                    warn!("Ignoring a second lludp data channel from {}", addr);
+                    return;
+                };
+
+                channel.on_message(Box::new(move |message: DataChannelMessage| {
+                    let lludp_server = lludp_server.clone();
+                    Box::pin(async move {
+                        lludp_server.receive_tunneled(addr, &message.data).await;
+                    })
+                }));
+
+                let outbound = Arc::clone(&channel);
+                tokio::spawn(async move {
+                    while let Some(packet) = packets.recv().await {
+                        if let Err(e) = outbound.send(&Bytes::from(packet)).await {
+                            debug!("WebRTC peer {} stopped taking packets: {}", addr, e);
+                            break;
+                        }
+                    }
+                });
+            })
+        }));
+    }
+
+    /// Set `offer` and answer it once our ICE candidates are gathered
+    async fn answer(&self, peer: &RTCPeerConnection, offer: RTCSessionDescription) -> NetworkResult<String> {
+        peer.set_remote_description(offer).await.map_err(webrtc_error)?;
+        let answer = peer.create_answer(None).await.map_err(webrtc_error)?;
+        let mut gathered = peer.gathering_complete_promise().await;
+        peer.set_local_description(answer).await.map_err(webrtc_error)?;
+        let _ = gathered.recv().await;
+
+        peer.local_description()
+            .await
+            .map(|description| description.sdp)
+            .ok_or_else(|| NetworkError::Protocol("no local WebRTC description".to_string()))
+    }
+}
+
+/// POST /webrtc/offer: an SDP offer in, our SDP answer out
+async fn offer_handler(State(transport): State<WebRtcTransport>, offer: String) -> axum::response::Response {
+    match transport.accept(offer).await {
+        Ok(answer) => ([(axum::http::header::CONTENT_TYPE, "application/sdp")], answer).into_response(),
+        Err(NetworkError::RateLimitExceeded) => {
+            (StatusCode::SERVICE_UNAVAILABLE, "Too many browser viewers").into_response()
+        }
+        Err(e) => {
+            warn!("Rejected WebRTC offer: {}", e);
+            (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+    }
+}
+
+fn webrtc_error(e: webrtc::Error) -> NetworkError {
+    NetworkError::Protocol(format!("WebRTC: {}", e))
+}
@@ -0,0 +1,380 @@
+//! LLUDP protocol conformance harness
+//!
+//! Replays scripted viewer sessions against a real `LLUDPServer` bound to
+//! localhost and checks the server's replies. Sessions are plain text files
+//! under `tests/fixtures/lludp`, one directive per line:
+//!
+//! ```text
+//! # comment
+//! send [reliable] seq=<n> msg=<id> <payload tokens...>
+//! expect ack=<n>
+//! expect msg=<id> [reliable] [payload=<hex prefix>]
+//! expect-none <ms>
+//! wait <ms>
+//! ```
+//!
+//! Payload tokens are hex byte strings, typed literals (`u8:1`, `u32:42`,
+//! `f32:128.0`, `zeros:12`) or session placeholders (`{agent_id}`,
+//! `{session_id}`, `{circuit_code}`) that are substituted with the values
+//! issued by the login service for the run.
+
+use mutsea_core::config::LLUDPConfig;
+use mutsea_network::lludp_server::LLUDPServer;
+use mutsea_protocol::login::{LoginService, ParsedLoginRequest};
+use mutsea_protocol::{flags, packet_types, Packet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use uuid::Uuid;
+
+/// How long to wait for an expected reply
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A payload token from a recorded session
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Bytes(Vec<u8>),
+    AgentId,
+    SessionId,
+    CircuitCode,
+}
+
+/// What a recorded session expects the server to send
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expectation {
+    /// An acknowledgement for a client sequence number
+    Ack(u32),
+    /// A message with the given ID
+    Message {
+        id: u32,
+        reliable: Option<bool>,
+        payload_prefix: Vec<u8>,
+    },
+}
+
+/// One line of a recorded session
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Send {
+        reliable: bool,
+        sequence: u32,
+        message_id: u32,
+        payload: Vec<Token>,
+    },
+    Expect(Expectation),
+    ExpectNone(Duration),
+    Wait(Duration),
+}
+
+/// A parsed recorded session
+#[derive(Debug, Clone)]
+pub struct RecordedSession {
+    pub name: String,
+    pub steps: Vec<Step>,
+}
+
+impl RecordedSession {
+    /// Parse a session file
+    pub fn parse(name: &str, source: &str) -> Result<Self, String> {
+        let mut steps = Vec::new();
+
+        for (line_no, raw) in source.lines().enumerate() {
+            let line = raw.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let step = parse_step(line).map_err(|e| format!("{}:{}: {}", name, line_no + 1, e))?;
+            steps.push(step);
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            steps,
+        })
+    }
+}
+
+fn parse_step(line: &str) -> Result<Step, String> {
+    let mut words = line.split_whitespace();
+    let directive = words.next().ok_or("empty line")?;
+    let words: Vec<&str> = words.collect();
+
+    match directive {
+        "send" => {
+            let mut reliable = false;
+            let mut sequence = None;
+            let mut message_id = None;
+            let mut payload = Vec::new();
+
+            for word in words {
+                if word == "reliable" {
+                    reliable = true;
+                } else if let Some(v) = word.strip_prefix("seq=") {
+                    sequence = Some(parse_number(v)?);
+                } else if let Some(v) = word.strip_prefix("msg=") {
+                    message_id = Some(parse_number(v)?);
+                } else {
+                    payload.push(parse_token(word)?);
+                }
+            }
+
+            Ok(Step::Send {
+                reliable,
+                sequence: sequence.ok_or("send is missing seq=")?,
+                message_id: message_id.ok_or("send is missing msg=")?,
+                payload,
+            })
+        }
+        "expect" => {
+            let mut id = None;
+            let mut reliable = None;
+            let mut payload_prefix = Vec::new();
+
+            for word in words {
+                if let Some(v) = word.strip_prefix("ack=") {
+                    return Ok(Step::Expect(Expectation::Ack(parse_number(v)?)));
+                } else if let Some(v) = word.strip_prefix("msg=") {
+                    id = Some(parse_number(v)?);
+                } else if word == "reliable" {
+                    reliable = Some(true);
+                } else if word == "unreliable" {
+                    reliable = Some(false);
+                } else if let Some(v) = word.strip_prefix("payload=") {
+                    payload_prefix = parse_hex(v)?;
+                } else {
+                    return Err(format!("unknown expect argument '{}'", word));
+                }
+            }
+
+            Ok(Step::Expect(Expectation::Message {
+                id: id.ok_or("expect needs ack= or msg=")?,
+                reliable,
+                payload_prefix,
+            }))
+        }
+        "expect-none" => Ok(Step::ExpectNone(parse_millis(&words)?)),
+        "wait" => Ok(Step::Wait(parse_millis(&words)?)),
+        other => Err(format!("unknown directive '{}'", other)),
+    }
+}
+
+fn parse_token(word: &str) -> Result<Token, String> {
+    match word {
+        "{agent_id}" => return Ok(Token::AgentId),
+        "{session_id}" => return Ok(Token::SessionId),
+        "{circuit_code}" => return Ok(Token::CircuitCode),
+        _ => {}
+    }
+
+    let bytes = if let Some(v) = word.strip_prefix("u8:") {
+        vec![v.parse::<u8>().map_err(|e| e.to_string())?]
+    } else if let Some(v) = word.strip_prefix("u32:") {
+        parse_number(v)?.to_le_bytes().to_vec()
+    } else if let Some(v) = word.strip_prefix("f32:") {
+        v.parse::<f32>().map_err(|e| e.to_string())?.to_le_bytes().to_vec()
+    } else if let Some(v) = word.strip_prefix("zeros:") {
+        vec![0u8; v.parse::<usize>().map_err(|e| e.to_string())?]
+    } else {
+        parse_hex(word)?
+    };
+
+    Ok(Token::Bytes(bytes))
+}
+
+fn parse_number(value: &str) -> Result<u32, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).map_err(|e| e.to_string()),
+        None => value.parse::<u32>().map_err(|e| e.to_string()),
+    }
+}
+
+fn parse_hex(value: &str) -> Result<Vec<u8>, String> {
+    if !value.len().is_multiple_of(2) {
+        return Err(format!("odd-length hex '{}'", value));
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).map_err(|e| e.to_string()))
+        .collect()
+}
+
+fn parse_millis(words: &[&str]) -> Result<Duration, String> {
+    let ms = words.first().ok_or("missing duration in ms")?;
+    Ok(Duration::from_millis(ms.parse::<u64>().map_err(|e| e.to_string())?))
+}
+
+/// Identifiers issued by the login service for a replayed session
+#[derive(Debug, Clone)]
+pub struct SessionVars {
+    pub agent_id: Uuid,
+    pub session_id: Uuid,
+    pub circuit_code: u32,
+}
+
+impl SessionVars {
+    fn render(&self, tokens: &[Token]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for token in tokens {
+            match token {
+                Token::Bytes(bytes) => payload.extend_from_slice(bytes),
+                Token::AgentId => payload.extend_from_slice(self.agent_id.as_bytes()),
+                Token::SessionId => payload.extend_from_slice(self.session_id.as_bytes()),
+                Token::CircuitCode => payload.extend_from_slice(&self.circuit_code.to_le_bytes()),
+            }
+        }
+        payload
+    }
+}
+
+/// An LLUDP server running on an ephemeral localhost port
+pub struct ConformanceServer {
+    server: LLUDPServer,
+    login_service: Arc<LoginService>,
+    address: SocketAddr,
+}
+
+impl ConformanceServer {
+    /// Start a server with a single test account
+    pub async fn start() -> Self {
+        let config = LLUDPConfig {
+            bind_address: "127.0.0.1".to_string(),
+            port: 0,
+            ..Default::default()
+        };
+
+        let login_service = Arc::new(LoginService::new());
+        login_service.add_test_user("Conformance".to_string(), "Viewer".to_string(), "password".to_string());
+
+        let mut server = LLUDPServer::new(&config).await.expect("bind LLUDP server");
+        server.set_login_service(Arc::clone(&login_service));
+        server.start().await.expect("start LLUDP server");
+        let address = server.local_addr().expect("server address");
+
+        Self {
+            server,
+            login_service,
+            address,
+        }
+    }
+
+    /// Log in the test account the same way the XML-RPC endpoint would
+    pub fn login(&self) -> SessionVars {
+        let request = ParsedLoginRequest {
+            first: "Conformance".to_string(),
+            last: "Viewer".to_string(),
+            passwd: "password".to_string(),
+            start: "last".to_string(),
+            channel: "Firestorm-Releasex64".to_string(),
+            version: "7.1.9".to_string(),
+            platform: "Linux".to_string(),
+            mac: String::new(),
+            id0: String::new(),
+            agree_to_tos: "true".to_string(),
+            read_critical: "true".to_string(),
+            viewer_digest: String::new(),
+            options: Vec::new(),
+        };
+
+        let response = self.login_service.authenticate(&request).expect("login");
+        assert_eq!(response.login, "true", "login failed: {}", response.reason);
+
+        SessionVars {
+            agent_id: Uuid::parse_str(response.agent_id.as_deref().unwrap()).unwrap(),
+            session_id: Uuid::parse_str(response.session_id.as_deref().unwrap()).unwrap(),
+            circuit_code: response.circuit_code.unwrap() as u32,
+        }
+    }
+
+    /// Replay a recorded session and panic on the first mismatch
+    pub async fn replay(&self, session: &RecordedSession) {
+        let vars = self.login();
+        let socket = UdpSocket::bind("127.0.0.1:0").await.expect("bind client socket");
+        socket.connect(self.address).await.expect("connect client socket");
+
+        let mut backlog: Vec<Packet> = Vec::new();
+
+        for (index, step) in session.steps.iter().enumerate() {
+            let context = format!("{} step {} ({:?})", session.name, index + 1, step);
+
+            match step {
+                Step::Send { reliable, sequence, message_id, payload } => {
+                    let flags = if *reliable { flags::RELIABLE } else { 0 };
+                    let packet = Packet::new(flags, *sequence, vars.render(payload))
+                        .with_message_id(*message_id);
+                    let data = packet.serialize().expect("serialize recorded packet");
+                    socket.send(&data).await.expect("send recorded packet");
+                }
+                Step::Expect(expectation) => {
+                    if let Some(pos) = backlog.iter().position(|p| matches(expectation, p)) {
+                        backlog.remove(pos);
+                        continue;
+                    }
+                    loop {
+                        let packet = recv(&socket, REPLY_TIMEOUT)
+                            .await
+                            .unwrap_or_else(|| panic!("{}: no matching reply; unmatched: {}", context, describe(&backlog)));
+                        if matches(expectation, &packet) {
+                            break;
+                        }
+                        backlog.push(packet);
+                    }
+                }
+                Step::ExpectNone(window) => {
+                    // Acks and server-initiated keepalive pings are allowed;
+                    // anything else is an unexpected reply
+                    while let Some(packet) = recv(&socket, *window).await {
+                        let keepalive = packet.message_id == Some(packet_types::START_PING_CHECK as u32);
+                        if packet.message_id.is_some() && !keepalive {
+                            panic!("{}: unexpected reply {}", context, describe(&[packet]));
+                        }
+                    }
+                }
+                Step::Wait(duration) => tokio::time::sleep(*duration).await,
+            }
+        }
+    }
+
+    /// Stop the server
+    pub async fn stop(self) {
+        self.server.stop().await.expect("stop LLUDP server");
+    }
+}
+
+async fn recv(socket: &UdpSocket, timeout: Duration) -> Option<Packet> {
+    let mut buffer = vec![0u8; 4096];
+    loop {
+        let len = tokio::time::timeout(timeout, socket.recv(&mut buffer)).await.ok()?.ok()?;
+        if let Ok(packet) = Packet::deserialize(&buffer[..len]) {
+            return Some(packet);
+        }
+    }
+}
+
+fn matches(expectation: &Expectation, packet: &Packet) -> bool {
+    match expectation {
+        Expectation::Ack(sequence) => packet.appended_acks.contains(sequence),
+        Expectation::Message { id, reliable, payload_prefix } => {
+            packet.message_id == Some(*id)
+                && reliable.is_none_or(|r| packet.header.is_reliable() == r)
+                && packet.payload.starts_with(payload_prefix)
+        }
+    }
+}
+
+fn describe(packets: &[Packet]) -> String {
+    packets
+        .iter()
+        .map(|p| {
+            format!(
+                "[msg={:?} seq={} reliable={} acks={:?} len={}]",
+                p.message_id,
+                p.header.sequence,
+                p.header.is_reliable(),
+                p.appended_acks,
+                p.payload.len()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
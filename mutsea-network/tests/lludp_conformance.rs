@@ -0,0 +1,52 @@
+//! Replays recorded viewer sessions against the LLUDP server
+
+mod conformance;
+
+use conformance::{ConformanceServer, Expectation, RecordedSession, Step, Token};
+
+async fn replay_fixture(name: &str, source: &str) {
+    let session = RecordedSession::parse(name, source).expect("parse fixture");
+    let server = ConformanceServer::start().await;
+    server.replay(&session).await;
+    server.stop().await;
+}
+
+#[test]
+fn session_parser_understands_directives() {
+    let session = RecordedSession::parse(
+        "inline",
+        "send reliable seq=1 msg=3 {circuit_code} u8:1 0a0b # trailing comment\n\
+         expect ack=1\n\
+         expect msg=148 reliable payload=80\n\
+         expect-none 100\n",
+    )
+    .unwrap();
+
+    assert_eq!(session.steps.len(), 4);
+    assert_eq!(
+        session.steps[0],
+        Step::Send {
+            reliable: true,
+            sequence: 1,
+            message_id: 3,
+            payload: vec![Token::CircuitCode, Token::Bytes(vec![1]), Token::Bytes(vec![0x0a, 0x0b])],
+        }
+    );
+    assert_eq!(session.steps[1], Step::Expect(Expectation::Ack(1)));
+    assert!(RecordedSession::parse("bad", "bogus 1").is_err());
+}
+
+#[tokio::test]
+async fn firestorm_circuit_handshake() {
+    replay_fixture("handshake.session", include_str!("fixtures/lludp/handshake.session")).await;
+}
+
+#[tokio::test]
+async fn firestorm_ping_keepalive() {
+    replay_fixture("ping.session", include_str!("fixtures/lludp/ping.session")).await;
+}
+
+#[tokio::test]
+async fn firestorm_movement_and_logout() {
+    replay_fixture("movement.session", include_str!("fixtures/lludp/movement.session")).await;
+}
@@ -0,0 +1,206 @@
+//! Collision detection
+//!
+//! Each frame the tracker finds which prims are touching something - another
+//! prim, an avatar or the ground - and reports the contacts that began or
+//! ended since the last frame, the `collision_start` and `collision_end`
+//! events of scripts. Prims and avatars are treated as boxes aligned with
+//! the region's axes. Only moving things collide: avatars, physical prims
+//! and prims with a velocity, so a build of prims resting against each
+//! other stays quiet.
+
+use crate::navigation::PHANTOM;
+use mutsea_core::events::Collider;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{BoundingBox, ObjectId, SceneObject, UserId, Vector3};
+use std::collections::HashMap;
+
+/// Object flag of prims moved by physics
+pub const PHYSICS: u32 = 0x1;
+
+/// Size of the box an avatar fills, centred on its position
+pub const AVATAR_SIZE: Vector3 = Vector3 { x: 0.6, y: 0.6, z: 1.8 };
+
+/// How close to the ground a prim's underside must come to touch it
+const LAND_MARGIN: f32 = 0.05;
+
+/// A contact that began or ended this frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CollisionChange {
+    Start {
+        object: ObjectId,
+        collider: Collider,
+        /// Middle of where the two touch
+        position: Vector3,
+    },
+    End {
+        object: ObjectId,
+        collider: Collider,
+    },
+}
+
+/// The box aligned with the region's axes holding `object` however it is
+/// turned
+pub fn bounds_of(object: &SceneObject) -> BoundingBox {
+    let rotation = object.rotation;
+    let axes = [
+        rotation.rotate(Vector3::new(object.scale.x, 0.0, 0.0)),
+        rotation.rotate(Vector3::new(0.0, object.scale.y, 0.0)),
+        rotation.rotate(Vector3::new(0.0, 0.0, object.scale.z)),
+    ];
+    let extent = |f: fn(&Vector3) -> f32| axes.iter().map(|axis| f(axis).abs()).sum::<f32>();
+    BoundingBox::from_center_size(
+        object.position,
+        Vector3::new(extent(|v| v.x), extent(|v| v.y), extent(|v| v.z)),
+    )
+}
+
+/// Middle of where `a` and `b` overlap, if they do
+fn overlap(a: &BoundingBox, b: &BoundingBox) -> Option<Vector3> {
+    a.intersects(b).then(|| {
+        let min = Vector3::new(a.min.x.max(b.min.x), a.min.y.max(b.min.y), a.min.z.max(b.min.z));
+        let max = Vector3::new(a.max.x.min(b.max.x), a.max.y.min(b.max.y), a.max.z.min(b.max.z));
+        BoundingBox::new(min, max).center()
+    })
+}
+
+/// Contacts of the last frame, compared against each new one
+#[derive(Debug, Default)]
+pub struct CollisionTracker {
+    touching: HashMap<(ObjectId, Collider), Vector3>,
+}
+
+impl CollisionTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many contacts are ongoing
+    pub fn contacts(&self) -> usize {
+        self.touching.len()
+    }
+
+    /// Find this frame's contacts between the prims of `scene` and the
+    /// `avatars` at their positions, answering those that began or ended
+    pub fn update(&mut self, scene: &RegionScene, avatars: &[(UserId, Vector3)]) -> Vec<CollisionChange> {
+        let solid: Vec<(&SceneObject, BoundingBox)> = scene
+            .objects
+            .values()
+            .filter(|object| object.flags & PHANTOM == 0)
+            .map(|object| (object, bounds_of(object)))
+            .collect();
+        let moving = |object: &SceneObject| object.flags & PHYSICS != 0 || object.velocity.length() > 0.0;
+
+        let mut touching = HashMap::new();
+        for (i, (object, bounds)) in solid.iter().enumerate() {
+            if !moving(object) {
+                continue;
+            }
+            for (j, (other, other_bounds)) in solid.iter().enumerate() {
+                // Pairs of moving prims are checked once
+                if i == j || (moving(other) && j < i) {
+                    continue;
+                }
+                if let Some(position) = overlap(bounds, other_bounds) {
+                    touching.insert((object.id, Collider::Object(other.id)), position);
+                    touching.insert((other.id, Collider::Object(object.id)), position);
+                }
+            }
+
+            let ground = scene
+                .terrain
+                .height_at(object.position.x.max(0.0) as u32, object.position.y.max(0.0) as u32);
+            if bounds.min.z <= ground + LAND_MARGIN {
+                touching.insert(
+                    (object.id, Collider::Land),
+                    Vector3::new(object.position.x, object.position.y, ground),
+                );
+            }
+        }
+
+        for (agent, position) in avatars {
+            let avatar = BoundingBox::from_center_size(*position, AVATAR_SIZE);
+            for (object, bounds) in &solid {
+                if let Some(contact) = overlap(bounds, &avatar) {
+                    touching.insert((object.id, Collider::Avatar(*agent)), contact);
+                }
+            }
+        }
+
+        let mut changes: Vec<CollisionChange> = touching
+            .iter()
+            .filter(|(key, _)| !self.touching.contains_key(key))
+            .map(|(&(object, collider), &position)| CollisionChange::Start {
+                object,
+                collider,
+                position,
+            })
+            .collect();
+        changes.extend(
+            self.touching
+                .keys()
+                .filter(|key| !touching.contains_key(key))
+                .map(|&(object, collider)| CollisionChange::End { object, collider }),
+        );
+        self.touching = touching;
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::RegionInfo;
+
+    fn cube(position: Vector3) -> SceneObject {
+        SceneObject {
+            position,
+            ..SceneObject::new(1, "Cube", UserId::new())
+        }
+    }
+
+    #[test]
+    fn test_collisions_start_and_end() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let mut scene = RegionScene::new(info, UserId::new());
+        let wall = cube(Vector3::new(100.0, 100.0, 30.0));
+        let resting = cube(Vector3::new(100.8, 100.0, 30.0));
+        let mut ball = cube(Vector3::new(110.0, 100.0, 30.0));
+        ball.flags = PHYSICS;
+        let (wall_id, ball_id) = (wall.id, ball.id);
+        for object in [wall, resting, ball] {
+            scene.add_object(object);
+        }
+        let mut tracker = CollisionTracker::new();
+        // Still prims touching each other don't collide
+        assert!(tracker.update(&scene, &[]).is_empty());
+
+        scene.objects.get_mut(&ball_id).unwrap().position = Vector3::new(99.2, 100.0, 30.0);
+        let changes = tracker.update(&scene, &[]);
+        assert_eq!(changes.len(), 2, "{:?}", changes);
+        assert!(changes.iter().any(|change| matches!(
+            change,
+            CollisionChange::Start { object, collider: Collider::Object(other), position }
+                if *object == wall_id && *other == ball_id && (position.x - 99.6).abs() < 1e-4
+        )));
+        // Touching goes on without new events
+        assert!(tracker.update(&scene, &[]).is_empty());
+
+        // The ball drops to the ground and an avatar walks into the wall
+        scene.objects.get_mut(&ball_id).unwrap().position = Vector3::new(90.0, 100.0, 21.4);
+        let agent = UserId::new();
+        let changes = tracker.update(&scene, &[(agent, Vector3::new(99.3, 100.0, 30.0))]);
+        assert!(changes.contains(&CollisionChange::End {
+            object: wall_id,
+            collider: Collider::Object(ball_id),
+        }));
+        assert!(changes.iter().any(|change| matches!(
+            change,
+            CollisionChange::Start { object, collider: Collider::Land, .. } if *object == ball_id
+        )));
+        assert!(changes.iter().any(|change| matches!(
+            change,
+            CollisionChange::Start { object, collider: Collider::Avatar(a), .. } if *object == wall_id && *a == agent
+        )));
+        assert_eq!(tracker.contacts(), 2);
+    }
+}
@@ -1,14 +1,17 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
+//! Mutsea physics
+//!
+//! Simulation-side physics for regions: navigation for NPC movement, the
+//! vehicle model scripted vehicles drive with, collision detection,
+//! raycasts and the hull shapes of mesh prims.
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+pub mod collision;
+pub mod navigation;
+pub mod raycast;
+pub mod shape;
+pub mod vehicle;
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub use collision::{CollisionChange, CollisionTracker};
+pub use navigation::{NavGrid, Route};
+pub use raycast::{RayHit, RayOptions, RayQuery, Raycaster};
+pub use shape::PhysicsShape;
+pub use vehicle::{Surface, Vehicle, VehicleBody, VehicleParams, VehicleType};
@@ -0,0 +1,589 @@
+//! Grid navigation for NPCs
+//!
+//! A `NavGrid` has one cell per square metre of the region, taking its
+//! height from the terrain and marking cells under prims as blocked. Mesh
+//! prims block only under their hulls, and hulls low enough to step onto
+//! become floor, so NPCs can walk into mesh buildings.
+//! Paths are found with A* over the cells and smoothed by skipping
+//! waypoints in line of sight. A `Route` follows a path and plans again
+//! when the way ahead becomes blocked.
+
+use crate::shape::PhysicsShape;
+use mutsea_core::config::NavigationConfig;
+use mutsea_core::scene::{RegionScene, Terrain};
+use mutsea_core::{ObjectId, SceneObject, Vector3};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+/// Object flag of prims avatars walk through
+pub const PHANTOM: u32 = 0x400;
+
+/// Prims whose underside is this far above the ground can be walked under
+pub const HEADROOM: f32 = 2.0;
+
+/// Most cells A* expands before giving up on a path
+const MAX_EXPANDED: usize = 1 << 17;
+
+/// Distance between samples when checking a straight line is walkable
+const LINE_STEP: f32 = 0.5;
+
+const NEIGHBOURS: [(i32, i32); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+/// Walkable cells of a region
+#[derive(Debug, Clone)]
+pub struct NavGrid {
+    size_x: u32,
+    size_y: u32,
+    heights: Vec<f32>,
+    blocked: Vec<bool>,
+    max_step: f32,
+    clearance: f32,
+}
+
+/// A cell on the open list, ordered so the lowest estimate pops first
+#[derive(PartialEq)]
+struct Open {
+    estimate: f32,
+    cell: usize,
+}
+
+impl Eq for Open {}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl NavGrid {
+    /// A grid over `terrain` with nothing blocked
+    pub fn new(terrain: &Terrain, config: &NavigationConfig) -> Self {
+        Self {
+            size_x: terrain.size_x,
+            size_y: terrain.size_y,
+            heights: terrain.heights.clone(),
+            blocked: vec![false; terrain.heights.len()],
+            max_step: config.max_step,
+            clearance: config.clearance,
+        }
+    }
+
+    /// A grid over the scene's terrain, blocked by its solid prims
+    pub fn from_scene(scene: &RegionScene, config: &NavigationConfig) -> Self {
+        let mut grid = Self::new(&scene.terrain, config);
+        for object in scene.objects.values() {
+            grid.add_obstacle(object);
+        }
+        grid
+    }
+
+    /// A grid over the scene's terrain, with the prims in `shapes` taking
+    /// their mesh shapes and the rest blocking as boxes
+    pub fn from_scene_with_shapes(
+        scene: &RegionScene,
+        config: &NavigationConfig,
+        shapes: &HashMap<ObjectId, PhysicsShape>,
+    ) -> Self {
+        let mut grid = Self::new(&scene.terrain, config);
+        for object in scene.objects.values() {
+            match shapes.get(&object.id) {
+                Some(shape) => grid.add_shape(object, shape),
+                None => grid.add_obstacle(object),
+            }
+        }
+        grid
+    }
+
+    /// Add the hulls of `object`'s mesh shape. Hulls whose top is within a
+    /// step of the ground become the ground there; the others block the
+    /// cells under them, widened by the clearance, unless high enough to
+    /// walk under.
+    pub fn add_shape(&mut self, object: &SceneObject, shape: &PhysicsShape) {
+        if object.flags & PHANTOM != 0 {
+            return;
+        }
+        let mut pieces = shape.world_bounds(object);
+        // Floors are laid before the walls standing on them
+        pieces.sort_by(|a, b| a.max.z.total_cmp(&b.max.z));
+
+        for piece in pieces {
+            let min_x = (piece.min.x - self.clearance).floor().max(0.0) as u32;
+            let min_y = (piece.min.y - self.clearance).floor().max(0.0) as u32;
+            let max_x = ((piece.max.x + self.clearance).ceil().max(0.0) as u32).min(self.size_x);
+            let max_y = ((piece.max.y + self.clearance).ceil().max(0.0) as u32).min(self.size_y);
+            for y in min_y..max_y {
+                for x in min_x..max_x {
+                    let index = self.index(x, y);
+                    let ground = self.heights[index];
+                    let (centre_x, centre_y) = (x as f32 + 0.5, y as f32 + 0.5);
+                    let under = (piece.min.x..=piece.max.x).contains(&centre_x)
+                        && (piece.min.y..=piece.max.y).contains(&centre_y);
+                    if piece.max.z - ground <= self.max_step {
+                        if under {
+                            self.heights[index] = ground.max(piece.max.z);
+                        }
+                    } else if piece.min.z - ground < HEADROOM {
+                        self.blocked[index] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Block the cells under `object`, widened by the clearance. Phantom
+    /// prims and prims high enough to walk under don't block.
+    pub fn add_obstacle(&mut self, object: &SceneObject) {
+        if object.flags & PHANTOM != 0 {
+            return;
+        }
+        let (half_x, half_y) = (object.scale.x / 2.0, object.scale.y / 2.0);
+        let underside = object.position.z - object.scale.z / 2.0;
+
+        // Footprint is the prim's box turned by its yaw
+        let rotation = object.rotation;
+        let yaw = (2.0 * (rotation.w * rotation.z + rotation.x * rotation.y))
+            .atan2(1.0 - 2.0 * (rotation.y * rotation.y + rotation.z * rotation.z));
+        let (sin, cos) = yaw.sin_cos();
+
+        let reach = half_x.hypot(half_y) + self.clearance;
+        let min_x = (object.position.x - reach).floor().max(0.0) as u32;
+        let min_y = (object.position.y - reach).floor().max(0.0) as u32;
+        let max_x = ((object.position.x + reach).ceil().max(0.0) as u32).min(self.size_x);
+        let max_y = ((object.position.y + reach).ceil().max(0.0) as u32).min(self.size_y);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let index = self.index(x, y);
+                if underside - self.heights[index] >= HEADROOM {
+                    continue;
+                }
+                // Cell centre in the prim's own frame
+                let dx = x as f32 + 0.5 - object.position.x;
+                let dy = y as f32 + 0.5 - object.position.y;
+                let local_x = dx * cos + dy * sin;
+                let local_y = -dx * sin + dy * cos;
+                if local_x.abs() <= half_x + self.clearance && local_y.abs() <= half_y + self.clearance {
+                    self.blocked[index] = true;
+                }
+            }
+        }
+    }
+
+    /// Whether an NPC can stand at `position`
+    pub fn is_walkable(&self, position: Vector3) -> bool {
+        self.cell_of(position).is_some_and(|(x, y)| !self.blocked[self.index(x, y)])
+    }
+
+    /// Ground height under `x`, `y`, clamped to the region edge
+    pub fn ground_height(&self, x: f32, y: f32) -> f32 {
+        let x = (x.max(0.0) as u32).min(self.size_x.saturating_sub(1));
+        let y = (y.max(0.0) as u32).min(self.size_y.saturating_sub(1));
+        self.heights[self.index(x, y)]
+    }
+
+    /// Waypoints on the ground from `from` to `to`, excluding `from`, or
+    /// `None` if `to` can't be reached. An NPC standing in a blocked cell
+    /// may still walk out of it.
+    pub fn find_path(&self, from: Vector3, to: Vector3) -> Option<Vec<Vector3>> {
+        let start = self.cell_of(from)?;
+        let goal = self.cell_of(to)?;
+        if !self.is_walkable(to) {
+            return None;
+        }
+        let goal_point = self.on_ground(to);
+        if start == goal {
+            return Some(vec![goal_point]);
+        }
+
+        let cells = self.search(start, goal)?;
+        let mut points = Vec::with_capacity(cells.len());
+        points.push(from);
+        for &(x, y) in &cells[1..cells.len() - 1] {
+            points.push(self.on_ground(Vector3::new(x as f32 + 0.5, y as f32 + 0.5, 0.0)));
+        }
+        points.push(goal_point);
+
+        // Skip every waypoint that can be seen past
+        let mut path = Vec::new();
+        let mut anchor = 0;
+        while anchor < points.len() - 1 {
+            let mut next = anchor + 1;
+            while next + 1 < points.len() && self.line_walkable(points[anchor], points[next + 1]) {
+                next += 1;
+            }
+            path.push(points[next]);
+            anchor = next;
+        }
+        Some(path)
+    }
+
+    /// Whether an NPC can walk straight from `from` to `to`. The cell it
+    /// starts in isn't checked for obstacles.
+    pub fn line_walkable(&self, from: Vector3, to: Vector3) -> bool {
+        let Some(mut previous) = self.cell_of(from) else {
+            return false;
+        };
+        let start = previous;
+        let (dx, dy) = (to.x - from.x, to.y - from.y);
+        let samples = ((dx.hypot(dy) / LINE_STEP).ceil() as u32).max(1);
+
+        for sample in 1..=samples {
+            let t = sample as f32 / samples as f32;
+            let Some(cell) = self.cell_of(Vector3::new(from.x + dx * t, from.y + dy * t, 0.0)) else {
+                return false;
+            };
+            if cell == previous {
+                continue;
+            }
+            if cell != start && self.blocked[self.index(cell.0, cell.1)] {
+                return false;
+            }
+            if !self.can_step(previous, cell) {
+                return false;
+            }
+            previous = cell;
+        }
+        true
+    }
+
+    /// A* from `start` to `goal`, returning every cell on the way
+    fn search(&self, start: (u32, u32), goal: (u32, u32)) -> Option<Vec<(u32, u32)>> {
+        let cells = self.heights.len();
+        let mut cost = vec![f32::INFINITY; cells];
+        let mut came_from = vec![usize::MAX; cells];
+        let mut open = BinaryHeap::new();
+
+        let start_index = self.index(start.0, start.1);
+        let goal_index = self.index(goal.0, goal.1);
+        cost[start_index] = 0.0;
+        open.push(Open {
+            estimate: octile(start, goal),
+            cell: start_index,
+        });
+
+        let mut expanded = 0;
+        while let Some(Open { cell, estimate }) = open.pop() {
+            if cell == goal_index {
+                let mut path = vec![goal];
+                let mut current = cell;
+                while current != start_index {
+                    current = came_from[current];
+                    path.push(self.coords(current));
+                }
+                path.reverse();
+                return Some(path);
+            }
+            let here = self.coords(cell);
+            // Skip entries superseded by a cheaper route
+            if estimate > cost[cell] + octile(here, goal) + 1e-3 {
+                continue;
+            }
+            expanded += 1;
+            if expanded > MAX_EXPANDED {
+                return None;
+            }
+
+            for (dx, dy) in NEIGHBOURS {
+                let Some(next) = self.offset(here, dx, dy) else {
+                    continue;
+                };
+                let next_index = self.index(next.0, next.1);
+                if self.blocked[next_index] || !self.can_step(here, next) {
+                    continue;
+                }
+                // Don't cut corners past obstacles
+                if dx != 0 && dy != 0 {
+                    let side_x = self.index(next.0, here.1);
+                    let side_y = self.index(here.0, next.1);
+                    if self.blocked[side_x] || self.blocked[side_y] {
+                        continue;
+                    }
+                }
+
+                let distance = if dx != 0 && dy != 0 { std::f32::consts::SQRT_2 } else { 1.0 };
+                // Climbing costs extra so gentle routes are preferred
+                let climb = (self.heights[next_index] - self.heights[cell]).abs();
+                let next_cost = cost[cell] + distance + climb;
+                if next_cost < cost[next_index] {
+                    cost[next_index] = next_cost;
+                    came_from[next_index] = cell;
+                    open.push(Open {
+                        estimate: next_cost + octile(next, goal),
+                        cell: next_index,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether the height change between neighbouring cells is climbable
+    fn can_step(&self, from: (u32, u32), to: (u32, u32)) -> bool {
+        let distance = ((to.0 as f32 - from.0 as f32).hypot(to.1 as f32 - from.1 as f32)).max(1.0);
+        let climb = (self.heights[self.index(to.0, to.1)] - self.heights[self.index(from.0, from.1)]).abs();
+        climb <= self.max_step * distance
+    }
+
+    fn on_ground(&self, position: Vector3) -> Vector3 {
+        Vector3::new(position.x, position.y, self.ground_height(position.x, position.y))
+    }
+
+    fn cell_of(&self, position: Vector3) -> Option<(u32, u32)> {
+        if position.x < 0.0 || position.y < 0.0 {
+            return None;
+        }
+        let (x, y) = (position.x as u32, position.y as u32);
+        (x < self.size_x && y < self.size_y).then_some((x, y))
+    }
+
+    fn offset(&self, (x, y): (u32, u32), dx: i32, dy: i32) -> Option<(u32, u32)> {
+        let x = x.checked_add_signed(dx)?;
+        let y = y.checked_add_signed(dy)?;
+        (x < self.size_x && y < self.size_y).then_some((x, y))
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.size_x + x) as usize
+    }
+
+    fn coords(&self, index: usize) -> (u32, u32) {
+        (index as u32 % self.size_x, index as u32 / self.size_x)
+    }
+}
+
+/// Shortest distance between cells moving in eight directions
+fn octile(from: (u32, u32), to: (u32, u32)) -> f32 {
+    let dx = from.0.abs_diff(to.0) as f32;
+    let dy = from.1.abs_diff(to.1) as f32;
+    dx.max(dy) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dy)
+}
+
+/// A path being walked towards a goal
+#[derive(Debug, Clone)]
+pub struct Route {
+    goal: Vector3,
+    waypoints: VecDeque<Vector3>,
+}
+
+impl Route {
+    /// Plan a route from `from` to `goal`, or `None` if it can't be reached
+    pub fn plan(grid: &NavGrid, from: Vector3, goal: Vector3) -> Option<Self> {
+        let waypoints = grid.find_path(from, goal)?;
+        Some(Self {
+            goal,
+            waypoints: waypoints.into(),
+        })
+    }
+
+    pub fn goal(&self) -> Vector3 {
+        self.goal
+    }
+
+    /// Waypoints still to reach, ending at the goal
+    pub fn waypoints(&self) -> Vec<Vector3> {
+        self.waypoints.iter().copied().collect()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.waypoints.is_empty()
+    }
+
+    /// Walk up to `distance` metres on from `position` and return where
+    /// that ends. If the way to the next waypoint has been blocked the
+    /// route is planned again; `None` means the goal can't be reached.
+    pub fn advance(&mut self, grid: &NavGrid, position: Vector3, distance: f32) -> Option<Vector3> {
+        let mut position = position;
+        let mut remaining = distance;
+        let mut replanned = false;
+
+        while remaining > 0.0 {
+            let Some(&next) = self.waypoints.front() else {
+                break;
+            };
+            if !grid.line_walkable(position, next) {
+                if replanned {
+                    return None;
+                }
+                self.waypoints = grid.find_path(position, self.goal)?.into();
+                replanned = true;
+                continue;
+            }
+
+            let (dx, dy) = (next.x - position.x, next.y - position.y);
+            let length = dx.hypot(dy);
+            if length <= remaining {
+                position = next;
+                remaining -= length;
+                self.waypoints.pop_front();
+            } else {
+                let t = remaining / length;
+                let (x, y) = (position.x + dx * t, position.y + dy * t);
+                position = Vector3::new(x, y, grid.ground_height(x, y));
+                remaining = 0.0;
+            }
+        }
+        Some(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::UserId;
+
+    fn wall(position: Vector3, scale: Vector3) -> SceneObject {
+        SceneObject {
+            position,
+            scale,
+            ..SceneObject::new(1, "Wall", UserId::new())
+        }
+    }
+
+    fn path_length(from: Vector3, path: &[Vector3]) -> f32 {
+        let mut length = 0.0;
+        let mut previous = from;
+        for &point in path {
+            length += (point.x - previous.x).hypot(point.y - previous.y);
+            previous = point;
+        }
+        length
+    }
+
+    #[test]
+    fn test_path_goes_around_walls() {
+        let config = NavigationConfig::default();
+        let mut grid = NavGrid::new(&Terrain::flat(32, 32, 20.0), &config);
+        let (from, to) = (Vector3::new(4.5, 16.5, 20.0), Vector3::new(28.5, 16.5, 20.0));
+
+        let straight = grid.find_path(from, to).unwrap();
+        assert_eq!(straight, vec![to]);
+
+        grid.add_obstacle(&wall(Vector3::new(16.0, 16.0, 22.0), Vector3::new(1.0, 24.0, 4.0)));
+        assert!(!grid.is_walkable(Vector3::new(16.0, 16.0, 20.0)));
+        let path = grid.find_path(from, to).unwrap();
+        assert_eq!(*path.last().unwrap(), to);
+        assert!(path.iter().any(|point| point.y < 4.0 || point.y > 28.0));
+        assert!(path_length(from, &path) > 24.0);
+        // Smoothing leaves a waypoint each side of the wall's end
+        assert!(path.len() <= 4);
+
+        let mut previous = from;
+        for &point in &path {
+            assert!(grid.line_walkable(previous, point));
+            previous = point;
+        }
+
+        // Prims overhead and phantom prims don't block
+        let mut grid = NavGrid::new(&Terrain::flat(32, 32, 20.0), &config);
+        grid.add_obstacle(&wall(Vector3::new(16.0, 16.0, 25.0), Vector3::new(8.0, 8.0, 1.0)));
+        let mut phantom = wall(Vector3::new(8.0, 8.0, 21.0), Vector3::new(4.0, 4.0, 2.0));
+        phantom.flags = PHANTOM;
+        grid.add_obstacle(&phantom);
+        assert!(grid.is_walkable(Vector3::new(16.0, 16.0, 20.0)));
+        assert!(grid.is_walkable(Vector3::new(8.0, 8.0, 20.0)));
+    }
+
+    #[test]
+    fn test_mesh_buildings_can_be_walked_into() {
+        let config = NavigationConfig::default();
+        let terrain = Terrain::flat(32, 32, 20.0);
+        // A 10m square room with a doorway in its west wall, a floor and a roof
+        let house = wall(Vector3::new(16.0, 16.0, 22.5), Vector3::new(10.0, 10.0, 5.0));
+        let piece = |min: (f32, f32, f32), max: (f32, f32, f32)| {
+            vec![Vector3::new(min.0, min.1, min.2), Vector3::new(max.0, max.1, max.2)]
+        };
+        let shape = PhysicsShape::new(vec![
+            piece((-0.5, -0.5, -0.5), (0.5, 0.5, -0.46)),
+            piece((-0.5, 0.45, -0.5), (0.5, 0.5, 0.5)),
+            piece((-0.5, -0.5, -0.5), (0.5, -0.45, 0.5)),
+            piece((0.45, -0.5, -0.5), (0.5, 0.5, 0.5)),
+            piece((-0.5, -0.5, -0.5), (-0.45, -0.2, 0.5)),
+            piece((-0.5, 0.2, -0.5), (-0.45, 0.5, 0.5)),
+            piece((-0.5, -0.5, 0.45), (0.5, 0.5, 0.5)),
+        ]);
+
+        let as_box = {
+            let mut grid = NavGrid::new(&terrain, &config);
+            grid.add_obstacle(&house);
+            grid
+        };
+        assert!(!as_box.is_walkable(Vector3::new(16.5, 16.5, 20.0)));
+
+        let mut scene = RegionScene::new(
+            mutsea_core::RegionInfo::new("Test".to_string(), 32, 32, String::new(), String::new()),
+            UserId::new(),
+        );
+        scene.terrain = terrain;
+        scene.add_object(house.clone());
+        let shapes = HashMap::from([(house.id, shape)]);
+        let grid = NavGrid::from_scene_with_shapes(&scene, &config, &shapes);
+        let inside = Vector3::new(16.5, 16.5, 20.0);
+        assert!(grid.is_walkable(inside));
+        assert!((grid.ground_height(16.5, 16.5) - 20.2).abs() < 1e-4);
+        let path = grid.find_path(Vector3::new(2.5, 16.5, 20.0), inside).unwrap();
+        assert!((path.last().unwrap().z - 20.2).abs() < 1e-4);
+        // In through the doorway, not the walls
+        assert!(grid.find_path(Vector3::new(16.5, 2.5, 20.0), inside).unwrap().len() > 1);
+    }
+
+    #[test]
+    fn test_cliffs_are_not_climbed() {
+        let config = NavigationConfig::default();
+        let mut terrain = Terrain::flat(32, 32, 20.0);
+        for y in 0..32 {
+            for x in 16..32 {
+                terrain.set_height(x, y, 25.0);
+            }
+        }
+        let grid = NavGrid::new(&terrain, &config);
+        assert!(grid.find_path(Vector3::new(4.5, 4.5, 20.0), Vector3::new(24.5, 4.5, 25.0)).is_none());
+
+        // A ramp gentle enough to walk makes the top reachable
+        for y in 0..4 {
+            for x in 4..16 {
+                terrain.set_height(x, y, 20.0 + (x - 4) as f32 * 5.0 / 12.0);
+            }
+        }
+        let grid = NavGrid::new(&terrain, &config);
+        let path = grid.find_path(Vector3::new(4.5, 20.5, 20.0), Vector3::new(24.5, 20.5, 25.0)).unwrap();
+        assert_eq!(path.last().unwrap().z, 25.0);
+        assert!(path.iter().any(|point| point.y < 4.0));
+    }
+
+    #[test]
+    fn test_route_replans_around_new_obstacles() {
+        let config = NavigationConfig::default();
+        let mut grid = NavGrid::new(&Terrain::flat(32, 32, 20.0), &config);
+        let (mut position, goal) = (Vector3::new(4.5, 16.5, 20.0), Vector3::new(28.5, 16.5, 20.0));
+        let mut route = Route::plan(&grid, position, goal).unwrap();
+
+        position = route.advance(&grid, position, 4.0).unwrap();
+        assert!((position.x - 8.5).abs() < 1e-3);
+        assert!(!route.is_finished());
+
+        grid.add_obstacle(&wall(Vector3::new(16.0, 16.0, 22.0), Vector3::new(1.0, 24.0, 4.0)));
+        let mut steps = 0;
+        while !route.is_finished() {
+            position = route.advance(&grid, position, 1.0).unwrap();
+            steps += 1;
+            assert!(steps < 100);
+        }
+        assert_eq!(position, goal);
+        assert!(steps > 20);
+
+        // Walling off the goal makes the route fail on the way
+        let mut route = Route::plan(&grid, position, Vector3::new(4.5, 16.5, 20.0)).unwrap();
+        grid.add_obstacle(&wall(Vector3::new(4.5, 16.5, 22.0), Vector3::new(6.0, 6.0, 4.0)));
+        let mut walking = Some(position);
+        while let Some(at) = walking.filter(|_| !route.is_finished()) {
+            walking = route.advance(&grid, at, 1.0);
+        }
+        assert!(walking.is_none());
+    }
+}
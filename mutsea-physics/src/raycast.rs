@@ -0,0 +1,316 @@
+//! Raycasts
+//!
+//! Rays are cast through a region against its terrain, its prims and the
+//! avatars in it, answering what they hit nearest first, as llCastRay does.
+//! The same casts check whether NPCs can see each other and whether a
+//! clicked prim is really in front of the viewer's camera. A `Raycaster`
+//! works out the bounds of everything once, so batches of rays cast against
+//! the same frame share that work. Prims and avatars are boxes aligned with
+//! the region's axes, as for collisions.
+
+use crate::collision::{bounds_of, AVATAR_SIZE};
+use crate::navigation::PHANTOM;
+use mutsea_core::events::Collider;
+use mutsea_core::scene::{RegionScene, Terrain};
+use mutsea_core::{BoundingBox, ObjectId, Ray, SceneObject, UserId, Vector3};
+use serde::{Deserialize, Serialize};
+
+/// Distance between the samples taken of the terrain along a ray
+const TERRAIN_STEP: f32 = 0.25;
+
+/// Halvings that narrow down where a ray enters the terrain
+const TERRAIN_REFINEMENT: usize = 8;
+
+/// Something a ray hit
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RayHit {
+    pub target: Collider,
+    pub position: Vector3,
+    /// Normal of the surface hit
+    pub normal: Vector3,
+    /// Metres from the start of the ray
+    pub distance: f32,
+}
+
+/// What a ray may hit, as the options of llCastRay
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RayOptions {
+    /// Hits answered, nearest first
+    pub max_hits: usize,
+    pub land: bool,
+    pub objects: bool,
+    pub avatars: bool,
+    /// Whether phantom prims stop the ray
+    pub phantom: bool,
+    /// Prims the ray passes through, such as the one casting it
+    pub ignore: Vec<ObjectId>,
+}
+
+impl Default for RayOptions {
+    fn default() -> Self {
+        Self {
+            max_hits: 1,
+            land: true,
+            objects: true,
+            avatars: true,
+            phantom: false,
+            ignore: Vec::new(),
+        }
+    }
+}
+
+/// A ray from `start` to `end`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RayQuery {
+    pub start: Vector3,
+    pub end: Vector3,
+    #[serde(default)]
+    pub options: RayOptions,
+}
+
+impl RayQuery {
+    pub fn new(start: Vector3, end: Vector3) -> Self {
+        Self {
+            start,
+            end,
+            options: RayOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: RayOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+/// Casts rays through one frame of a region
+pub struct Raycaster<'a> {
+    terrain: &'a Terrain,
+    objects: Vec<(&'a SceneObject, BoundingBox)>,
+    avatars: Vec<(UserId, BoundingBox)>,
+}
+
+impl<'a> Raycaster<'a> {
+    /// Cast against the prims and terrain of `scene` and the `avatars` at
+    /// their positions
+    pub fn new(scene: &'a RegionScene, avatars: &[(UserId, Vector3)]) -> Self {
+        Self {
+            terrain: &scene.terrain,
+            objects: scene
+                .objects
+                .values()
+                .map(|object| (object, bounds_of(object)))
+                .collect(),
+            avatars: avatars
+                .iter()
+                .map(|(agent, position)| (*agent, BoundingBox::from_center_size(*position, AVATAR_SIZE)))
+                .collect(),
+        }
+    }
+
+    /// What the ray of `query` hits, nearest first
+    pub fn cast(&self, query: &RayQuery) -> Vec<RayHit> {
+        let options = &query.options;
+        let length = (query.end - query.start).length();
+        if length < f32::EPSILON || options.max_hits == 0 {
+            return Vec::new();
+        }
+        let ray = Ray::new(query.start, query.end - query.start);
+
+        let mut hits = Vec::new();
+        if options.objects {
+            let solid = self.objects.iter().filter(|(object, _)| {
+                (options.phantom || object.flags & PHANTOM == 0) && !options.ignore.contains(&object.id)
+            });
+            for (object, bounds) in solid {
+                if let Some(hit) = box_hit(&ray, bounds, length, Collider::Object(object.id)) {
+                    hits.push(hit);
+                }
+            }
+        }
+        if options.avatars {
+            for (agent, bounds) in &self.avatars {
+                if let Some(hit) = box_hit(&ray, bounds, length, Collider::Avatar(*agent)) {
+                    hits.push(hit);
+                }
+            }
+        }
+        if options.land {
+            hits.extend(self.terrain_hit(&ray, length));
+        }
+
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits.truncate(options.max_hits);
+        hits
+    }
+
+    /// Cast every query against the same frame
+    pub fn cast_batch(&self, queries: &[RayQuery]) -> Vec<Vec<RayHit>> {
+        queries.iter().map(|query| self.cast(query)).collect()
+    }
+
+    /// Whether nothing solid stands between `from` and `to`; avatars don't
+    /// block the view, and the prims in `ignore` - those of whoever is
+    /// looking - are seen through
+    pub fn line_of_sight(&self, from: Vector3, to: Vector3, ignore: &[ObjectId]) -> bool {
+        let options = RayOptions {
+            avatars: false,
+            ignore: ignore.to_vec(),
+            ..RayOptions::default()
+        };
+        self.cast(&RayQuery::new(from, to).with_options(options)).is_empty()
+    }
+
+    /// Where the ray first goes below the ground, sampling the terrain
+    /// every `TERRAIN_STEP` and narrowing down the crossing
+    fn terrain_hit(&self, ray: &Ray, length: f32) -> Option<RayHit> {
+        let below = |t: f32| {
+            let point = ray.point_at(t);
+            ground_at(self.terrain, point.x, point.y).is_some_and(|ground| point.z <= ground)
+        };
+        if below(0.0) {
+            return None;
+        }
+
+        let mut before = 0.0;
+        let mut t = 0.0;
+        loop {
+            t = (t + TERRAIN_STEP).min(length);
+            if below(t) {
+                break;
+            }
+            if t >= length {
+                return None;
+            }
+            before = t;
+        }
+        let mut after = t;
+        for _ in 0..TERRAIN_REFINEMENT {
+            let middle = (before + after) / 2.0;
+            if below(middle) {
+                after = middle;
+            } else {
+                before = middle;
+            }
+        }
+
+        let position = ray.point_at(after);
+        Some(RayHit {
+            target: Collider::Land,
+            position,
+            normal: terrain_normal(self.terrain, position.x, position.y),
+            distance: after,
+        })
+    }
+}
+
+/// Where `ray` enters `bounds` within `length`
+fn box_hit(ray: &Ray, bounds: &BoundingBox, length: f32, target: Collider) -> Option<RayHit> {
+    let distance = ray.intersects_box(bounds).filter(|distance| *distance <= length)?;
+    let position = ray.point_at(distance);
+
+    // The face hit is the one the hit lies closest to
+    let faces = [
+        (position.x - bounds.min.x, Vector3::new(-1.0, 0.0, 0.0)),
+        (bounds.max.x - position.x, Vector3::new(1.0, 0.0, 0.0)),
+        (position.y - bounds.min.y, Vector3::new(0.0, -1.0, 0.0)),
+        (bounds.max.y - position.y, Vector3::new(0.0, 1.0, 0.0)),
+        (position.z - bounds.min.z, Vector3::new(0.0, 0.0, -1.0)),
+        (bounds.max.z - position.z, Vector3::new(0.0, 0.0, 1.0)),
+    ];
+    let normal = faces
+        .iter()
+        .min_by(|a, b| a.0.abs().total_cmp(&b.0.abs()))
+        .map(|(_, normal)| *normal)
+        .unwrap_or(Vector3::UP);
+    Some(RayHit {
+        target,
+        position,
+        normal,
+        distance,
+    })
+}
+
+/// Height of the ground at `x`, `y`, blending the four samples around it;
+/// `None` outside the region
+fn ground_at(terrain: &Terrain, x: f32, y: f32) -> Option<f32> {
+    if x < 0.0 || y < 0.0 || x >= terrain.size_x as f32 || y >= terrain.size_y as f32 {
+        return None;
+    }
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as u32, y0 as u32);
+    let height = |dx: u32, dy: u32| terrain.height_at(x0 + dx, y0 + dy);
+    let south = height(0, 0) + (height(1, 0) - height(0, 0)) * fx;
+    let north = height(0, 1) + (height(1, 1) - height(0, 1)) * fx;
+    Some(south + (north - south) * fy)
+}
+
+/// Normal of the ground at `x`, `y` from the slope either side
+fn terrain_normal(terrain: &Terrain, x: f32, y: f32) -> Vector3 {
+    let height = |x: f32, y: f32| ground_at(terrain, x, y);
+    let centre = height(x, y).unwrap_or_default();
+    let slope = |a: Option<f32>, b: Option<f32>| (b.unwrap_or(centre) - a.unwrap_or(centre)) / 2.0;
+    let dx = slope(height(x - 1.0, y), height(x + 1.0, y));
+    let dy = slope(height(x, y - 1.0), height(x, y + 1.0));
+    Vector3::new(-dx, -dy, 1.0).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::RegionInfo;
+
+    fn cube(position: Vector3) -> SceneObject {
+        SceneObject {
+            position,
+            scale: Vector3::new(2.0, 2.0, 2.0),
+            ..SceneObject::new(1, "Cube", UserId::new())
+        }
+    }
+
+    #[test]
+    fn test_rays_hit_prims_avatars_and_land() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let mut scene = RegionScene::new(info, UserId::new());
+        let near = cube(Vector3::new(110.0, 100.0, 30.0));
+        let mut far = cube(Vector3::new(120.0, 100.0, 30.0));
+        far.flags = PHANTOM;
+        let (near_id, far_id) = (near.id, far.id);
+        scene.add_object(near);
+        scene.add_object(far);
+        let agent = UserId::new();
+        let raycaster = Raycaster::new(&scene, &[(agent, Vector3::new(105.0, 100.0, 30.0))]);
+
+        let across = RayQuery::new(Vector3::new(100.0, 100.0, 30.0), Vector3::new(130.0, 100.0, 30.0));
+        let hits = raycaster.cast(&across);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].target, Collider::Avatar(agent));
+        assert!((hits[0].distance - 4.7).abs() < 1e-4);
+        assert_eq!(hits[0].normal, Vector3::new(-1.0, 0.0, 0.0));
+
+        let hits = raycaster.cast(&across.clone().with_options(RayOptions {
+            max_hits: 5,
+            avatars: false,
+            phantom: true,
+            ..RayOptions::default()
+        }));
+        let targets: Vec<Collider> = hits.iter().map(|hit| hit.target).collect();
+        assert_eq!(targets, vec![Collider::Object(near_id), Collider::Object(far_id)]);
+        assert!((hits[0].position.x - 109.0).abs() < 1e-4);
+
+        // Straight down onto the ground
+        let down = RayQuery::new(Vector3::new(50.0, 50.0, 40.0), Vector3::new(50.0, 50.0, 0.0));
+        let ground = ground_at(&scene.terrain, 50.0, 50.0).unwrap();
+        let hits = raycaster.cast_batch(&[down, across])[0].clone();
+        assert_eq!(hits[0].target, Collider::Land);
+        assert!((hits[0].position.z - ground).abs() < 0.01);
+        assert!(hits[0].normal.z > 0.0);
+
+        // The near cube blocks the view unless it is the looker's own
+        let (eye, target) = (Vector3::new(100.0, 100.0, 30.0), Vector3::new(115.0, 100.0, 30.0));
+        assert!(!raycaster.line_of_sight(eye, target, &[]));
+        assert!(raycaster.line_of_sight(eye, target, &[near_id]));
+    }
+}
@@ -0,0 +1,114 @@
+//! Physics shapes of mesh prims
+//!
+//! A mesh prim collides as a set of convex hulls rather than as its box, so
+//! the rooms of a mesh building can be walked into. Hulls are kept in the
+//! prim's own space, -0.5 to 0.5 on each axis, and follow the prim as it is
+//! moved, turned and resized. Meshes uploaded without hulls are split into
+//! a grid of pieces, each the hull of the triangles within it.
+
+use mutsea_core::{BoundingBox, SceneObject, Vector3};
+
+/// Pieces along each axis a mesh without hulls is split into
+const DECOMPOSITION_CELLS: f32 = 4.0;
+
+/// The convex hulls a prim collides as
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PhysicsShape {
+    pub hulls: Vec<Vec<Vector3>>,
+}
+
+impl PhysicsShape {
+    pub fn new(hulls: Vec<Vec<Vector3>>) -> Self {
+        Self {
+            hulls: hulls.into_iter().filter(|hull| !hull.is_empty()).collect(),
+        }
+    }
+
+    /// Split `triangles` into hulls, one for each grid cell their centres
+    /// fall in
+    pub fn decompose(triangles: &[[Vector3; 3]]) -> Self {
+        let cell = |v: f32| {
+            ((v + 0.5) * DECOMPOSITION_CELLS)
+                .floor()
+                .clamp(0.0, DECOMPOSITION_CELLS - 1.0) as usize
+        };
+        let cells = DECOMPOSITION_CELLS as usize;
+        let mut hulls = vec![Vec::new(); cells * cells * cells];
+        for triangle in triangles {
+            let centre = (triangle[0] + triangle[1] + triangle[2]) * (1.0 / 3.0);
+            hulls[(cell(centre.z) * cells + cell(centre.y)) * cells + cell(centre.x)].extend_from_slice(triangle);
+        }
+        Self::new(hulls)
+    }
+
+    /// Whether the shape has nothing to collide with
+    pub fn is_empty(&self) -> bool {
+        self.hulls.is_empty()
+    }
+
+    /// The box aligned with the region's axes around each hull of `object`
+    /// as it stands
+    pub fn world_bounds(&self, object: &SceneObject) -> Vec<BoundingBox> {
+        self.hulls
+            .iter()
+            .map(|hull| {
+                let mut points = hull.iter().map(|point| {
+                    let scaled = Vector3::new(
+                        point.x * object.scale.x,
+                        point.y * object.scale.y,
+                        point.z * object.scale.z,
+                    );
+                    object.position + object.rotation.rotate(scaled)
+                });
+                let first = points.next().unwrap_or(object.position);
+                points.fold(BoundingBox::new(first, first), |bounds, point| {
+                    BoundingBox::new(
+                        Vector3::new(
+                            bounds.min.x.min(point.x),
+                            bounds.min.y.min(point.y),
+                            bounds.min.z.min(point.z),
+                        ),
+                        Vector3::new(
+                            bounds.max.x.max(point.x),
+                            bounds.max.y.max(point.y),
+                            bounds.max.z.max(point.z),
+                        ),
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::UserId;
+
+    #[test]
+    fn test_decompose_and_place() {
+        let triangle = |offset: f32| {
+            [
+                Vector3::new(-0.45 + offset, -0.45, -0.45),
+                Vector3::new(-0.35 + offset, -0.45, -0.45),
+                Vector3::new(-0.45 + offset, -0.35, -0.45),
+            ]
+        };
+        let shape = PhysicsShape::decompose(&[triangle(0.0), triangle(0.02), triangle(0.8)]);
+        assert_eq!(shape.hulls.len(), 2);
+        assert_eq!(shape.hulls.iter().map(Vec::len).sum::<usize>(), 9);
+
+        let object = SceneObject {
+            position: Vector3::new(100.0, 100.0, 25.0),
+            scale: Vector3::new(10.0, 10.0, 10.0),
+            ..SceneObject::new(1, "House", UserId::new())
+        };
+        let floor = PhysicsShape::new(vec![vec![
+            Vector3::new(-0.5, -0.5, -0.5),
+            Vector3::new(0.5, 0.5, -0.48),
+        ]]);
+        let bounds = floor.world_bounds(&object);
+        assert_eq!(bounds[0].min, Vector3::new(95.0, 95.0, 20.0));
+        assert!((bounds[0].max.z - 20.2).abs() < 1e-4);
+    }
+}
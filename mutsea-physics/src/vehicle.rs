@@ -0,0 +1,623 @@
+//! Vehicles
+//!
+//! The vehicle model OpenSim content scripts with llSetVehicleType and the
+//! llSetVehicle*Param calls: linear and angular motors that decay, friction
+//! along each of the vehicle's axes, hover and buoyancy, vertical
+//! attraction, banking and deflection. Each behaviour is tuned by a
+//! timescale, roughly how long it takes to do most of its work, so the
+//! same few parameters make sleds, cars, boats, planes and balloons.
+
+use mutsea_core::{Quaternion, Vector3};
+use serde::{Deserialize, Serialize};
+
+/// Downward acceleration, in m/s²
+pub const GRAVITY: f32 = 9.80665;
+
+/// Timescales this long switch hover, attraction, banking and deflection off
+const DISABLED_TIMESCALE: f32 = 300.0;
+
+/// Fastest a linear motor may push, in m/s
+const MAX_LINEAR_MOTOR: f32 = 30.0;
+
+/// Fastest an angular motor may turn, in rad/s
+const MAX_ANGULAR_MOTOR: f32 = 4.0 * std::f32::consts::PI;
+
+/// Parameter numbers of the VEHICLE_* constants
+pub mod params {
+    pub const LINEAR_FRICTION_TIMESCALE: i32 = 16;
+    pub const ANGULAR_FRICTION_TIMESCALE: i32 = 17;
+    pub const LINEAR_MOTOR_DIRECTION: i32 = 18;
+    pub const ANGULAR_MOTOR_DIRECTION: i32 = 19;
+    pub const LINEAR_MOTOR_OFFSET: i32 = 20;
+    pub const HOVER_HEIGHT: i32 = 24;
+    pub const HOVER_EFFICIENCY: i32 = 25;
+    pub const HOVER_TIMESCALE: i32 = 26;
+    pub const BUOYANCY: i32 = 27;
+    pub const LINEAR_DEFLECTION_EFFICIENCY: i32 = 28;
+    pub const LINEAR_DEFLECTION_TIMESCALE: i32 = 29;
+    pub const LINEAR_MOTOR_TIMESCALE: i32 = 30;
+    pub const LINEAR_MOTOR_DECAY_TIMESCALE: i32 = 31;
+    pub const ANGULAR_DEFLECTION_EFFICIENCY: i32 = 32;
+    pub const ANGULAR_DEFLECTION_TIMESCALE: i32 = 33;
+    pub const ANGULAR_MOTOR_TIMESCALE: i32 = 34;
+    pub const ANGULAR_MOTOR_DECAY_TIMESCALE: i32 = 35;
+    pub const VERTICAL_ATTRACTION_EFFICIENCY: i32 = 36;
+    pub const VERTICAL_ATTRACTION_TIMESCALE: i32 = 37;
+    pub const BANKING_EFFICIENCY: i32 = 38;
+    pub const BANKING_MIX: i32 = 39;
+    pub const BANKING_TIMESCALE: i32 = 40;
+    pub const REFERENCE_FRAME: i32 = 44;
+}
+
+/// VEHICLE_FLAG_* bits
+pub mod flags {
+    /// Deflection never pushes the vehicle upwards
+    pub const NO_DEFLECTION_UP: u32 = 0x1;
+    /// Vertical attraction only rights the vehicle's roll
+    pub const LIMIT_ROLL_ONLY: u32 = 0x2;
+    /// Hover over water, ignoring the ground
+    pub const HOVER_WATER_ONLY: u32 = 0x4;
+    /// Hover over the ground, ignoring water
+    pub const HOVER_TERRAIN_ONLY: u32 = 0x8;
+    /// Hover height is a height above sea level
+    pub const HOVER_GLOBAL_HEIGHT: u32 = 0x10;
+    /// Hover only pushes up, letting the vehicle jump
+    pub const HOVER_UP_ONLY: u32 = 0x20;
+    /// The linear motor never pushes the vehicle upwards
+    pub const LIMIT_MOTOR_UP: u32 = 0x40;
+    pub const MOUSELOOK_STEER: u32 = 0x80;
+    pub const MOUSELOOK_BANK: u32 = 0x100;
+    pub const CAMERA_DECOUPLED: u32 = 0x200;
+}
+
+/// Vehicle types of llSetVehicleType
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VehicleType {
+    None,
+    Sled,
+    Car,
+    Boat,
+    Airplane,
+    Balloon,
+}
+
+impl VehicleType {
+    /// The type numbered `id` by the VEHICLE_TYPE_* constants
+    pub fn from_id(id: i32) -> Option<Self> {
+        Some(match id {
+            0 => Self::None,
+            1 => Self::Sled,
+            2 => Self::Car,
+            3 => Self::Boat,
+            4 => Self::Airplane,
+            5 => Self::Balloon,
+            _ => return None,
+        })
+    }
+}
+
+/// Everything llSetVehicle*Param can set
+#[derive(Debug, Clone, PartialEq)]
+pub struct VehicleParams {
+    pub linear_friction_timescale: Vector3,
+    pub angular_friction_timescale: Vector3,
+    /// Velocity the linear motor pushes towards, in the vehicle's frame
+    pub linear_motor_direction: Vector3,
+    /// Where on the vehicle the linear motor pushes from
+    pub linear_motor_offset: Vector3,
+    pub linear_motor_timescale: f32,
+    pub linear_motor_decay_timescale: f32,
+    /// Spin the angular motor turns towards, in the vehicle's frame
+    pub angular_motor_direction: Vector3,
+    pub angular_motor_timescale: f32,
+    pub angular_motor_decay_timescale: f32,
+    pub hover_height: f32,
+    /// 0 bounces about the hover height, 1 settles on it
+    pub hover_efficiency: f32,
+    pub hover_timescale: f32,
+    /// -1 doubles gravity, 0 leaves it, 1 cancels it
+    pub buoyancy: f32,
+    pub linear_deflection_efficiency: f32,
+    pub linear_deflection_timescale: f32,
+    pub angular_deflection_efficiency: f32,
+    pub angular_deflection_timescale: f32,
+    pub vertical_attraction_efficiency: f32,
+    pub vertical_attraction_timescale: f32,
+    /// How much rolling turns the vehicle; negative turns the other way
+    pub banking_efficiency: f32,
+    /// 0 banks only while moving, 1 banks standing still as well
+    pub banking_mix: f32,
+    pub banking_timescale: f32,
+    /// Rotation from the prim's axes to the vehicle's
+    pub reference_frame: Quaternion,
+    pub flags: u32,
+}
+
+impl VehicleParams {
+    /// The parameters llSetVehicleType gives `vehicle_type`
+    pub fn for_type(vehicle_type: VehicleType) -> Self {
+        let none = Self {
+            linear_friction_timescale: Vector3::new(1000.0, 1000.0, 1000.0),
+            angular_friction_timescale: Vector3::new(1000.0, 1000.0, 1000.0),
+            linear_motor_direction: Vector3::ZERO,
+            linear_motor_offset: Vector3::ZERO,
+            linear_motor_timescale: 1000.0,
+            linear_motor_decay_timescale: 120.0,
+            angular_motor_direction: Vector3::ZERO,
+            angular_motor_timescale: 1000.0,
+            angular_motor_decay_timescale: 120.0,
+            hover_height: 0.0,
+            hover_efficiency: 0.0,
+            hover_timescale: 1000.0,
+            buoyancy: 0.0,
+            linear_deflection_efficiency: 0.0,
+            linear_deflection_timescale: 1000.0,
+            angular_deflection_efficiency: 0.0,
+            angular_deflection_timescale: 1000.0,
+            vertical_attraction_efficiency: 0.0,
+            vertical_attraction_timescale: 1000.0,
+            banking_efficiency: 0.0,
+            banking_mix: 1.0,
+            banking_timescale: 1000.0,
+            reference_frame: Quaternion::IDENTITY,
+            flags: 0,
+        };
+        match vehicle_type {
+            VehicleType::None => none,
+            VehicleType::Sled => Self {
+                linear_friction_timescale: Vector3::new(30.0, 1.0, 1000.0),
+                hover_efficiency: 10.0,
+                hover_timescale: 10.0,
+                linear_deflection_efficiency: 1.0,
+                linear_deflection_timescale: 1.0,
+                angular_deflection_efficiency: 1.0,
+                banking_timescale: 10.0,
+                flags: flags::NO_DEFLECTION_UP | flags::LIMIT_ROLL_ONLY | flags::LIMIT_MOTOR_UP,
+                ..none
+            },
+            VehicleType::Car => Self {
+                linear_friction_timescale: Vector3::new(100.0, 2.0, 1000.0),
+                linear_motor_timescale: 1.0,
+                linear_motor_decay_timescale: 60.0,
+                angular_motor_timescale: 1.0,
+                angular_motor_decay_timescale: 0.8,
+                linear_deflection_efficiency: 1.0,
+                linear_deflection_timescale: 2.0,
+                angular_deflection_timescale: 10.0,
+                vertical_attraction_efficiency: 1.0,
+                vertical_attraction_timescale: 10.0,
+                banking_efficiency: -0.2,
+                banking_timescale: 1.0,
+                flags: flags::NO_DEFLECTION_UP | flags::LIMIT_ROLL_ONLY | flags::LIMIT_MOTOR_UP,
+                ..none
+            },
+            VehicleType::Boat => Self {
+                linear_friction_timescale: Vector3::new(10.0, 3.0, 2.0),
+                angular_friction_timescale: Vector3::new(10.0, 10.0, 10.0),
+                linear_motor_timescale: 5.0,
+                linear_motor_decay_timescale: 60.0,
+                angular_motor_timescale: 4.0,
+                angular_motor_decay_timescale: 4.0,
+                hover_efficiency: 0.5,
+                hover_timescale: 2.0,
+                buoyancy: 1.0,
+                linear_deflection_efficiency: 0.5,
+                linear_deflection_timescale: 3.0,
+                angular_deflection_efficiency: 0.5,
+                angular_deflection_timescale: 5.0,
+                vertical_attraction_efficiency: 0.5,
+                vertical_attraction_timescale: 5.0,
+                banking_efficiency: -0.3,
+                banking_mix: 0.8,
+                banking_timescale: 1.0,
+                flags: flags::NO_DEFLECTION_UP | flags::HOVER_WATER_ONLY | flags::LIMIT_MOTOR_UP,
+                ..none
+            },
+            VehicleType::Airplane => Self {
+                linear_friction_timescale: Vector3::new(200.0, 10.0, 5.0),
+                angular_friction_timescale: Vector3::new(20.0, 20.0, 20.0),
+                linear_motor_timescale: 2.0,
+                linear_motor_decay_timescale: 60.0,
+                angular_motor_timescale: 4.0,
+                angular_motor_decay_timescale: 4.0,
+                hover_efficiency: 0.5,
+                linear_deflection_efficiency: 0.5,
+                linear_deflection_timescale: 3.0,
+                angular_deflection_efficiency: 1.0,
+                angular_deflection_timescale: 2.0,
+                vertical_attraction_efficiency: 0.9,
+                vertical_attraction_timescale: 2.0,
+                banking_efficiency: 1.0,
+                banking_mix: 0.7,
+                banking_timescale: 2.0,
+                flags: flags::LIMIT_ROLL_ONLY,
+                ..none
+            },
+            VehicleType::Balloon => Self {
+                linear_friction_timescale: Vector3::new(5.0, 5.0, 5.0),
+                angular_friction_timescale: Vector3::new(10.0, 10.0, 10.0),
+                linear_motor_timescale: 5.0,
+                linear_motor_decay_timescale: 60.0,
+                angular_motor_timescale: 6.0,
+                angular_motor_decay_timescale: 10.0,
+                hover_height: 5.0,
+                hover_efficiency: 0.8,
+                hover_timescale: 10.0,
+                buoyancy: 1.0,
+                linear_deflection_timescale: 5.0,
+                angular_deflection_timescale: 5.0,
+                vertical_attraction_efficiency: 1.0,
+                vertical_attraction_timescale: 100.0,
+                banking_mix: 0.7,
+                banking_timescale: 5.0,
+                flags: flags::LIMIT_ROLL_ONLY | flags::HOVER_GLOBAL_HEIGHT,
+                ..none
+            },
+        }
+    }
+}
+
+/// Where a vehicle is and how it is moving, in region coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VehicleBody {
+    pub position: Vector3,
+    pub rotation: Quaternion,
+    pub velocity: Vector3,
+    pub angular_velocity: Vector3,
+}
+
+/// Heights of the ground and water under a vehicle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Surface {
+    pub ground: f32,
+    pub water: f32,
+}
+
+/// A prim driven by the vehicle model
+#[derive(Debug, Clone)]
+pub struct Vehicle {
+    vehicle_type: VehicleType,
+    params: VehicleParams,
+    /// Motor directions as they decay
+    linear_motor: Vector3,
+    angular_motor: Vector3,
+}
+
+impl Vehicle {
+    pub fn new(vehicle_type: VehicleType) -> Self {
+        Self {
+            vehicle_type,
+            params: VehicleParams::for_type(vehicle_type),
+            linear_motor: Vector3::ZERO,
+            angular_motor: Vector3::ZERO,
+        }
+    }
+
+    pub fn vehicle_type(&self) -> VehicleType {
+        self.vehicle_type
+    }
+
+    pub fn params(&self) -> &VehicleParams {
+        &self.params
+    }
+
+    /// llSetVehicleType: start over with the parameters of `vehicle_type`
+    pub fn set_type(&mut self, vehicle_type: VehicleType) {
+        *self = Self::new(vehicle_type);
+    }
+
+    /// llSetVehicleFloatParam. A float sets every axis of a vector
+    /// parameter. Returns false for unknown parameters.
+    pub fn set_float_param(&mut self, param: i32, value: f32) -> bool {
+        let p = &mut self.params;
+        match param {
+            params::LINEAR_FRICTION_TIMESCALE
+            | params::ANGULAR_FRICTION_TIMESCALE
+            | params::LINEAR_MOTOR_DIRECTION
+            | params::ANGULAR_MOTOR_DIRECTION
+            | params::LINEAR_MOTOR_OFFSET => {
+                return self.set_vector_param(param, Vector3::new(value, value, value));
+            }
+            params::HOVER_HEIGHT => p.hover_height = value,
+            params::HOVER_EFFICIENCY => p.hover_efficiency = value.clamp(0.0, 1.0),
+            params::HOVER_TIMESCALE => p.hover_timescale = value,
+            params::BUOYANCY => p.buoyancy = value.clamp(-1.0, 1.0),
+            params::LINEAR_DEFLECTION_EFFICIENCY => p.linear_deflection_efficiency = value.clamp(0.0, 1.0),
+            params::LINEAR_DEFLECTION_TIMESCALE => p.linear_deflection_timescale = value,
+            params::LINEAR_MOTOR_TIMESCALE => p.linear_motor_timescale = value,
+            params::LINEAR_MOTOR_DECAY_TIMESCALE => p.linear_motor_decay_timescale = value,
+            params::ANGULAR_DEFLECTION_EFFICIENCY => p.angular_deflection_efficiency = value.clamp(0.0, 1.0),
+            params::ANGULAR_DEFLECTION_TIMESCALE => p.angular_deflection_timescale = value,
+            params::ANGULAR_MOTOR_TIMESCALE => p.angular_motor_timescale = value,
+            params::ANGULAR_MOTOR_DECAY_TIMESCALE => p.angular_motor_decay_timescale = value,
+            params::VERTICAL_ATTRACTION_EFFICIENCY => p.vertical_attraction_efficiency = value.clamp(0.0, 1.0),
+            params::VERTICAL_ATTRACTION_TIMESCALE => p.vertical_attraction_timescale = value,
+            params::BANKING_EFFICIENCY => p.banking_efficiency = value.clamp(-1.0, 1.0),
+            params::BANKING_MIX => p.banking_mix = value.clamp(0.0, 1.0),
+            params::BANKING_TIMESCALE => p.banking_timescale = value,
+            _ => return false,
+        }
+        true
+    }
+
+    /// llSetVehicleVectorParam. Setting a motor direction restarts its
+    /// decay. Returns false for unknown parameters.
+    pub fn set_vector_param(&mut self, param: i32, value: Vector3) -> bool {
+        let p = &mut self.params;
+        match param {
+            params::LINEAR_FRICTION_TIMESCALE => p.linear_friction_timescale = value,
+            params::ANGULAR_FRICTION_TIMESCALE => p.angular_friction_timescale = value,
+            params::LINEAR_MOTOR_DIRECTION => {
+                p.linear_motor_direction = clamp_length(value, MAX_LINEAR_MOTOR);
+                self.linear_motor = p.linear_motor_direction;
+            }
+            params::ANGULAR_MOTOR_DIRECTION => {
+                p.angular_motor_direction = clamp_length(value, MAX_ANGULAR_MOTOR);
+                self.angular_motor = p.angular_motor_direction;
+            }
+            params::LINEAR_MOTOR_OFFSET => p.linear_motor_offset = value,
+            _ => return false,
+        }
+        true
+    }
+
+    /// llSetVehicleRotationParam. Returns false for unknown parameters.
+    pub fn set_rotation_param(&mut self, param: i32, value: Quaternion) -> bool {
+        if param != params::REFERENCE_FRAME {
+            return false;
+        }
+        self.params.reference_frame = value.normalize();
+        true
+    }
+
+    /// llSetVehicleFlags
+    pub fn set_flags(&mut self, flags: u32) {
+        self.params.flags |= flags;
+    }
+
+    /// llRemoveVehicleFlags
+    pub fn remove_flags(&mut self, flags: u32) {
+        self.params.flags &= !flags;
+    }
+
+    /// Whether the motors have anything left to push with
+    pub fn is_driven(&self) -> bool {
+        self.linear_motor.length() > 0.01 || self.angular_motor.length() > 0.01
+    }
+
+    /// Move `body` on by `dt` seconds over `surface`
+    pub fn step(&mut self, body: &mut VehicleBody, surface: Surface, dt: f32) {
+        if self.vehicle_type == VehicleType::None || dt <= 0.0 {
+            return;
+        }
+        let p = &self.params;
+        let has = |flag: u32| p.flags & flag != 0;
+        let frame = (body.rotation * p.reference_frame).normalize();
+        let inverse = frame.conjugate();
+        let forward = frame.rotate(Vector3::new(1.0, 0.0, 0.0));
+
+        // Motors fade away over their decay timescales
+        self.linear_motor = self.linear_motor * (1.0 - rate(dt, p.linear_motor_decay_timescale));
+        self.angular_motor = self.angular_motor * (1.0 - rate(dt, p.angular_motor_decay_timescale));
+
+        // Linear motor, then friction along each of the vehicle's axes
+        let local = inverse.rotate(body.velocity);
+        let mut push = frame.rotate((self.linear_motor - local) * rate(dt, p.linear_motor_timescale));
+        if has(flags::LIMIT_MOTOR_UP) {
+            push.z = push.z.min(0.0);
+        }
+        let local = inverse.rotate(body.velocity + push);
+        body.velocity = frame.rotate(damp(local, p.linear_friction_timescale, dt));
+
+        // Linear deflection turns sideways and vertical slip into travel
+        // along the vehicle's nose
+        if p.linear_deflection_timescale < DISABLED_TIMESCALE {
+            let along = body.velocity.dot(&forward);
+            let slip = body.velocity - forward * along;
+            let amount = p.linear_deflection_efficiency * rate(dt, p.linear_deflection_timescale);
+            let mut gained = forward * (slip.length() * amount * if along < 0.0 { -1.0 } else { 1.0 });
+            if has(flags::NO_DEFLECTION_UP) {
+                gained.z = gained.z.min(0.0);
+            }
+            body.velocity = body.velocity - slip * amount + gained;
+        }
+
+        // Gravity, less whatever buoyancy holds up
+        body.velocity.z -= GRAVITY * (1.0 - p.buoyancy) * dt;
+
+        // Hover eases the vehicle towards its height above ground or water
+        if p.hover_timescale < DISABLED_TIMESCALE {
+            let target = p.hover_height
+                + if has(flags::HOVER_GLOBAL_HEIGHT) {
+                    0.0
+                } else if has(flags::HOVER_WATER_ONLY) {
+                    surface.water
+                } else if has(flags::HOVER_TERRAIN_ONLY) {
+                    surface.ground
+                } else {
+                    surface.ground.max(surface.water)
+                };
+            let error = target - body.position.z;
+            if error > 0.0 || !has(flags::HOVER_UP_ONLY) {
+                let timescale = p.hover_timescale.max(dt);
+                let efficiency = p.hover_efficiency.clamp(0.0, 1.0);
+                let settle = (error / timescale - body.velocity.z) * rate(dt, timescale);
+                let spring = error / (timescale * timescale) * dt;
+                body.velocity.z += settle * efficiency + spring * (1.0 - efficiency);
+            }
+        }
+
+        // Angular motor, then angular friction
+        let local = inverse.rotate(body.angular_velocity);
+        let local = local + (self.angular_motor - local) * rate(dt, p.angular_motor_timescale);
+        body.angular_velocity = frame.rotate(damp(local, p.angular_friction_timescale, dt));
+
+        // Vertical attraction rights the vehicle
+        if p.vertical_attraction_timescale < DISABLED_TIMESCALE {
+            let timescale = p.vertical_attraction_timescale.max(dt);
+            let up = frame.rotate(Vector3::UP);
+            let mut correction = up.cross(&Vector3::UP);
+            if has(flags::LIMIT_ROLL_ONLY) {
+                correction = forward * correction.dot(&forward);
+            }
+            let tilting = body.angular_velocity - Vector3::UP * body.angular_velocity.z;
+            let amount = rate(dt, timescale);
+            body.angular_velocity = body.angular_velocity + correction * (amount / timescale)
+                - tilting * (p.vertical_attraction_efficiency * amount);
+        }
+
+        // Banking turns a rolled vehicle
+        if p.banking_efficiency != 0.0 && p.banking_timescale < DISABLED_TIMESCALE {
+            let left = frame.rotate(Vector3::new(0.0, 1.0, 0.0));
+            let speed = body.velocity.dot(&forward).abs().min(1.0);
+            let mix = p.banking_mix + (1.0 - p.banking_mix) * speed;
+            body.angular_velocity.z -= left.z * p.banking_efficiency * mix * rate(dt, p.banking_timescale);
+        }
+
+        // Angular deflection points the nose where the vehicle is going
+        let speed = body.velocity.length();
+        if p.angular_deflection_timescale < DISABLED_TIMESCALE && speed > 0.1 {
+            let heading = body.velocity * (if body.velocity.dot(&forward) < 0.0 { -1.0 } else { 1.0 } / speed);
+            let mut correction = forward.cross(&heading);
+            if has(flags::NO_DEFLECTION_UP) && heading.z > forward.z {
+                correction = Vector3::UP * correction.z;
+            }
+            body.angular_velocity = body.angular_velocity
+                + correction * (p.angular_deflection_efficiency * rate(dt, p.angular_deflection_timescale));
+        }
+
+        body.position = body.position + body.velocity * dt;
+        let angle = body.angular_velocity.length() * dt;
+        if angle > 0.0 {
+            body.rotation = (Quaternion::from_axis_angle(body.angular_velocity.normalize(), angle) * body.rotation)
+                .normalize();
+        }
+
+        if body.position.z < surface.ground {
+            body.position.z = surface.ground;
+            body.velocity.z = body.velocity.z.max(0.0);
+        }
+    }
+}
+
+/// The neighbouring region `position` has moved into, as an offset of -1,
+/// 0 or 1 regions in x and y, or None while it is still inside
+pub fn crossed_border(position: Vector3, size_x: f32, size_y: f32) -> Option<(i32, i32)> {
+    let offset = |v: f32, size: f32| {
+        if v < 0.0 {
+            -1
+        } else if v >= size {
+            1
+        } else {
+            0
+        }
+    };
+    match (offset(position.x, size_x), offset(position.y, size_y)) {
+        (0, 0) => None,
+        crossed => Some(crossed),
+    }
+}
+
+/// Fraction of a behaviour with `timescale` done over `dt`
+fn rate(dt: f32, timescale: f32) -> f32 {
+    if timescale <= dt {
+        1.0
+    } else {
+        dt / timescale
+    }
+}
+
+/// Slow each axis of `v` over its own timescale
+fn damp(v: Vector3, timescale: Vector3, dt: f32) -> Vector3 {
+    Vector3::new(
+        v.x * (1.0 - rate(dt, timescale.x)),
+        v.y * (1.0 - rate(dt, timescale.y)),
+        v.z * (1.0 - rate(dt, timescale.z)),
+    )
+}
+
+fn clamp_length(v: Vector3, max: f32) -> Vector3 {
+    let length = v.length();
+    if length > max {
+        v * (max / length)
+    } else {
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body_at(position: Vector3) -> VehicleBody {
+        VehicleBody {
+            position,
+            rotation: Quaternion::IDENTITY,
+            velocity: Vector3::ZERO,
+            angular_velocity: Vector3::ZERO,
+        }
+    }
+
+    fn run(vehicle: &mut Vehicle, body: &mut VehicleBody, surface: Surface, seconds: f32) {
+        for _ in 0..(seconds * 20.0) as usize {
+            vehicle.step(body, surface, 0.05);
+        }
+    }
+
+    #[test]
+    fn test_car_motor_drives_forward_and_decays() {
+        let surface = Surface { ground: 21.0, water: 20.0 };
+        let mut car = Vehicle::new(VehicleType::Car);
+        let mut body = body_at(Vector3::new(128.0, 128.0, 21.0));
+        assert!(car.set_vector_param(params::LINEAR_MOTOR_DIRECTION, Vector3::new(10.0, 0.0, 5.0)));
+        assert!(car.is_driven());
+
+        run(&mut car, &mut body, surface, 3.0);
+        assert!(body.velocity.x > 8.0, "{:?}", body.velocity);
+        assert!(body.position.x > 140.0);
+        // The car stays on the ground despite the upward motor
+        assert_eq!(body.position.z, 21.0);
+
+        // Cut the motor and friction brings it to a stop
+        car.set_vector_param(params::LINEAR_MOTOR_DIRECTION, Vector3::ZERO);
+        run(&mut car, &mut body, surface, 10.0);
+        assert!(body.velocity.length() < 0.1, "{:?}", body.velocity);
+        assert!(!car.set_float_param(99, 1.0));
+    }
+
+    #[test]
+    fn test_boat_floats_at_the_water_line() {
+        let surface = Surface { ground: 5.0, water: 20.0 };
+        let mut boat = Vehicle::new(VehicleType::Boat);
+        let mut body = body_at(Vector3::new(128.0, 128.0, 24.0));
+        run(&mut boat, &mut body, surface, 20.0);
+        assert!((body.position.z - 20.0).abs() < 0.2, "{:?}", body.position);
+
+        // Without buoyancy or hover it sinks to the bottom
+        boat.set_float_param(params::BUOYANCY, 0.0);
+        boat.set_float_param(params::HOVER_TIMESCALE, 1000.0);
+        run(&mut boat, &mut body, surface, 10.0);
+        assert_eq!(body.position.z, 5.0);
+    }
+
+    #[test]
+    fn test_vertical_attraction_rights_a_tilted_vehicle() {
+        let surface = Surface { ground: 0.0, water: 0.0 };
+        let mut plane = Vehicle::new(VehicleType::Airplane);
+        plane.set_float_param(params::BUOYANCY, 1.0);
+        plane.set_float_param(params::BANKING_EFFICIENCY, 0.0);
+        let mut body = body_at(Vector3::new(128.0, 128.0, 100.0));
+        body.rotation = Quaternion::from_axis_angle(Vector3::new(1.0, 0.0, 0.0), 0.5);
+        run(&mut plane, &mut body, surface, 15.0);
+        let up = body.rotation.rotate(Vector3::UP);
+        assert!(up.z > 0.99, "{:?}", up);
+    }
+
+    #[test]
+    fn test_crossed_border() {
+        assert_eq!(crossed_border(Vector3::new(10.0, 10.0, 0.0), 256.0, 256.0), None);
+        assert_eq!(crossed_border(Vector3::new(256.0, 10.0, 0.0), 256.0, 256.0), Some((1, 0)));
+        assert_eq!(crossed_border(Vector3::new(-0.5, 300.0, 0.0), 256.0, 256.0), Some((-1, 1)));
+    }
+}
@@ -0,0 +1,485 @@
+//! Animation assets and the BVH motion files they are made from
+//!
+//! Animations are kept in the viewer's keyframe format: a header with the
+//! base priority, duration, loop points and ease times, then for each joint
+//! its priority and its rotation and position keys, each quantized to 16
+//! bits, then any constraints. BVH files exported from motion capture and
+//! posing tools are converted to that format: joints are renamed to the
+//! avatar skeleton's, Euler rotations become quaternions, and the Y-up BVH
+//! axes are turned to the avatar's Z-up ones.
+
+use crate::{ProtocolError, ProtocolResult};
+
+/// The only keyframe format version there is
+const KEYFRAME_VERSION: u16 = 1;
+
+/// How far the pelvis can be moved from its place, in metres
+const MAX_PELVIS_OFFSET: f32 = 5.0;
+
+/// Size of one constraint in a keyframe animation
+const CONSTRAINT_SIZE: usize = 86;
+
+/// BVH translations are in inches
+const INCHES_TO_METRES: f32 = 0.0254;
+
+/// BVH joint names of the common posing tools, and the avatar skeleton's
+const BVH_JOINTS: [(&str, &str); 19] = [
+    ("hip", "mPelvis"),
+    ("abdomen", "mTorso"),
+    ("chest", "mChest"),
+    ("neck", "mNeck"),
+    ("head", "mHead"),
+    ("lCollar", "mCollarLeft"),
+    ("lShldr", "mShoulderLeft"),
+    ("lForeArm", "mElbowLeft"),
+    ("lHand", "mWristLeft"),
+    ("rCollar", "mCollarRight"),
+    ("rShldr", "mShoulderRight"),
+    ("rForeArm", "mElbowRight"),
+    ("rHand", "mWristRight"),
+    ("lThigh", "mHipLeft"),
+    ("lShin", "mKneeLeft"),
+    ("lFoot", "mAnkleLeft"),
+    ("rThigh", "mHipRight"),
+    ("rShin", "mKneeRight"),
+    ("rFoot", "mAnkleRight"),
+];
+
+/// A key: its time in seconds and its value
+pub type Key = (f32, [f32; 3]);
+
+/// The motion of one joint
+#[derive(Debug, Clone, PartialEq)]
+pub struct JointMotion {
+    /// Joint name, such as `mHead`
+    pub name: String,
+    /// Priority of this joint's motion
+    pub priority: i32,
+    /// The vector part of each rotation quaternion, its W being positive
+    pub rotations: Vec<Key>,
+    /// Offsets from the joint's place, in metres
+    pub positions: Vec<Key>,
+}
+
+/// A keyframe animation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    /// Priority of the whole animation, 0 to 6
+    pub priority: i32,
+    /// Seconds
+    pub duration: f32,
+    /// Facial expression played with it, empty for none
+    pub emote: String,
+    /// Start of the looped part, in seconds
+    pub loop_in: f32,
+    /// End of the looped part, in seconds
+    pub loop_out: f32,
+    /// Whether the looped part repeats
+    pub looping: bool,
+    /// Seconds blended in from the previous animation
+    pub ease_in: f32,
+    /// Seconds blended out to the next animation
+    pub ease_out: f32,
+    /// Hand pose held while it plays
+    pub hand_pose: u32,
+    /// Motion of each joint it moves
+    pub joints: Vec<JointMotion>,
+    /// Constraints, kept as they were read
+    pub constraints: Vec<Vec<u8>>,
+}
+
+impl Animation {
+    /// Parse a keyframe animation asset
+    pub fn parse(data: &[u8]) -> ProtocolResult<Self> {
+        let mut reader = Reader { data, at: 0 };
+        let (version, sub_version) = (reader.u16()?, reader.u16()?);
+        if version != KEYFRAME_VERSION || sub_version != 0 {
+            return Err(ProtocolError::Decoding(format!(
+                "Unknown animation version {}.{}",
+                version, sub_version
+            )));
+        }
+        let priority = reader.i32()?;
+        let duration = reader.f32()?;
+        if !(duration.is_finite() && duration >= 0.0) {
+            return Err(ProtocolError::Decoding("Animation has a bad duration".to_string()));
+        }
+        let emote = reader.string()?;
+        let (loop_in, loop_out, looping) = (reader.f32()?, reader.f32()?, reader.i32()? != 0);
+        let (ease_in, ease_out, hand_pose) = (reader.f32()?, reader.f32()?, reader.u32()?);
+
+        let joint_count = reader.u32()? as usize;
+        let mut joints = Vec::with_capacity(joint_count.min(256));
+        for _ in 0..joint_count {
+            let name = reader.string()?;
+            let priority = reader.i32()?;
+            let rotations = reader.keys(duration, 1.0)?;
+            let positions = reader.keys(duration, MAX_PELVIS_OFFSET)?;
+            joints.push(JointMotion {
+                name,
+                priority,
+                rotations,
+                positions,
+            });
+        }
+        let constraint_count = reader.i32()?.max(0) as usize;
+        let constraints = (0..constraint_count)
+            .map(|_| reader.take(CONSTRAINT_SIZE).map(<[u8]>::to_vec))
+            .collect::<ProtocolResult<_>>()?;
+
+        Ok(Self {
+            priority,
+            duration,
+            emote,
+            loop_in,
+            loop_out,
+            looping,
+            ease_in,
+            ease_out,
+            hand_pose,
+            joints,
+            constraints,
+        })
+    }
+
+    /// The animation as a keyframe animation asset
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&KEYFRAME_VERSION.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&self.priority.to_le_bytes());
+        out.extend_from_slice(&self.duration.to_le_bytes());
+        push_string(&mut out, &self.emote);
+        out.extend_from_slice(&self.loop_in.to_le_bytes());
+        out.extend_from_slice(&self.loop_out.to_le_bytes());
+        out.extend_from_slice(&(self.looping as i32).to_le_bytes());
+        out.extend_from_slice(&self.ease_in.to_le_bytes());
+        out.extend_from_slice(&self.ease_out.to_le_bytes());
+        out.extend_from_slice(&self.hand_pose.to_le_bytes());
+        out.extend_from_slice(&(self.joints.len() as u32).to_le_bytes());
+        for joint in &self.joints {
+            push_string(&mut out, &joint.name);
+            out.extend_from_slice(&joint.priority.to_le_bytes());
+            push_keys(&mut out, &joint.rotations, self.duration, 1.0);
+            push_keys(&mut out, &joint.positions, self.duration, MAX_PELVIS_OFFSET);
+        }
+        out.extend_from_slice(&(self.constraints.len() as i32).to_le_bytes());
+        for constraint in &self.constraints {
+            out.extend_from_slice(constraint);
+        }
+        out
+    }
+
+    /// Convert a BVH motion file at `priority`. Joints the avatar skeleton
+    /// doesn't have are left out, and only the pelvis is moved.
+    pub fn from_bvh(text: &str, priority: i32) -> ProtocolResult<Self> {
+        let bvh_error = |message: &str| ProtocolError::Decoding(format!("BVH {}", message));
+        let mut words = text.split_whitespace();
+        if words.next() != Some("HIERARCHY") {
+            return Err(bvh_error("must start with HIERARCHY"));
+        }
+
+        // Each joint with channels, in the order their values are in a frame
+        let mut bvh_joints: Vec<(Option<&str>, Vec<&str>)> = Vec::new();
+        loop {
+            match words.next() {
+                Some("ROOT" | "JOINT") => {
+                    let name = words.next().ok_or_else(|| bvh_error("joint has no name"))?;
+                    bvh_joints.push((skeleton_joint(name), Vec::new()));
+                }
+                Some("CHANNELS") => {
+                    let count: usize = words
+                        .next()
+                        .and_then(|count| count.parse().ok())
+                        .ok_or_else(|| bvh_error("has a bad channel count"))?;
+                    let joint = bvh_joints.last_mut().ok_or_else(|| bvh_error("has channels outside a joint"))?;
+                    joint.1 = words.by_ref().take(count).collect();
+                }
+                Some("MOTION") => break,
+                Some(_) => {}
+                None => return Err(bvh_error("has no MOTION")),
+            }
+        }
+
+        let mut number_after = |label: &str| -> ProtocolResult<f32> {
+            let mut label_words = label.split(' ');
+            if !label_words.all(|word| words.next() == Some(word)) {
+                return Err(bvh_error(&format!("needs {}", label)));
+            }
+            words
+                .next()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| bvh_error(&format!("has a bad {}", label)))
+        };
+        let frames = number_after("Frames:")? as usize;
+        let frame_time = number_after("Frame Time:")?;
+        if frames == 0 || !(frame_time.is_finite() && frame_time > 0.0) {
+            return Err(bvh_error("has no frames"));
+        }
+        let channels: usize = bvh_joints.iter().map(|(_, channels)| channels.len()).sum();
+        let values: Vec<f32> = words.map(|value| value.parse().unwrap_or(0.0)).collect();
+        if values.len() < frames * channels {
+            return Err(bvh_error("has fewer values than frames"));
+        }
+
+        let mut joints: Vec<JointMotion> = Vec::new();
+        let mut first_channel = 0;
+        for (name, joint_channels) in &bvh_joints {
+            let Some(name) = name else {
+                first_channel += joint_channels.len();
+                continue;
+            };
+            let mut motion = JointMotion {
+                name: name.to_string(),
+                priority,
+                rotations: Vec::with_capacity(frames),
+                positions: Vec::new(),
+            };
+            let mut start = None;
+            for (frame, row) in values.chunks_exact(channels).take(frames).enumerate() {
+                let time = frame as f32 * frame_time;
+                let mut rotation = [0.0, 0.0, 0.0, 1.0];
+                let mut position = [0.0; 3];
+                for (channel, value) in joint_channels.iter().zip(&row[first_channel..]) {
+                    match *channel {
+                        "Xposition" => position[0] = *value,
+                        "Yposition" => position[1] = *value,
+                        "Zposition" => position[2] = *value,
+                        "Xrotation" => rotation = multiply(rotation, axis_rotation(0, *value)),
+                        "Yrotation" => rotation = multiply(rotation, axis_rotation(1, *value)),
+                        "Zrotation" => rotation = multiply(rotation, axis_rotation(2, *value)),
+                        _ => {}
+                    }
+                }
+                let sign = if rotation[3] < 0.0 { -1.0 } else { 1.0 };
+                motion.rotations.push((time, z_up(rotation[0] * sign, rotation[1] * sign, rotation[2] * sign)));
+                if *name == "mPelvis" && joint_channels.iter().any(|channel| channel.ends_with("position")) {
+                    let start = *start.get_or_insert(position);
+                    let offset = [0, 1, 2].map(|axis| (position[axis] - start[axis]) * INCHES_TO_METRES);
+                    motion.positions.push((time, z_up(offset[0], offset[1], offset[2])));
+                }
+            }
+            first_channel += joint_channels.len();
+            joints.push(motion);
+        }
+
+        let duration = (frames - 1) as f32 * frame_time;
+        Ok(Self {
+            priority,
+            duration,
+            emote: String::new(),
+            loop_in: 0.0,
+            loop_out: duration,
+            looping: false,
+            ease_in: 0.3,
+            ease_out: 0.3,
+            hand_pose: 1,
+            joints,
+            constraints: Vec::new(),
+        })
+    }
+}
+
+/// Whether `data` looks like a BVH motion file rather than an animation
+pub fn is_bvh(data: &[u8]) -> bool {
+    let start = data.iter().position(|byte| !byte.is_ascii_whitespace()).unwrap_or(data.len());
+    data[start..].starts_with(b"HIERARCHY")
+}
+
+/// The avatar skeleton's name for BVH joint `name`; names already the
+/// skeleton's are kept
+fn skeleton_joint(name: &str) -> Option<&str> {
+    if name.starts_with('m') && name.len() > 1 && name[1..].starts_with(|c: char| c.is_ascii_uppercase()) {
+        return Some(name);
+    }
+    BVH_JOINTS
+        .iter()
+        .find(|(bvh, _)| bvh.eq_ignore_ascii_case(name))
+        .map(|(_, skeleton)| *skeleton)
+}
+
+/// The quaternion, X, Y, Z then W, turning `degrees` about `axis`
+fn axis_rotation(axis: usize, degrees: f32) -> [f32; 4] {
+    let half = degrees.to_radians() / 2.0;
+    let mut quaternion = [0.0, 0.0, 0.0, half.cos()];
+    quaternion[axis] = half.sin();
+    quaternion
+}
+
+fn multiply(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    let [ax, ay, az, aw] = a;
+    let [bx, by, bz, bw] = b;
+    [
+        aw * bx + ax * bw + ay * bz - az * by,
+        aw * by - ax * bz + ay * bw + az * bx,
+        aw * bz + ax * by - ay * bx + az * bw,
+        aw * bw - ax * bx - ay * by - az * bz,
+    ]
+}
+
+/// BVH X, Y, Z (Y up, Z forward) as the avatar's (Z up, X forward)
+fn z_up(x: f32, y: f32, z: f32) -> [f32; 3] {
+    [z, x, y]
+}
+
+fn push_string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(value.as_bytes());
+    out.push(0);
+}
+
+fn push_keys(out: &mut Vec<u8>, keys: &[Key], duration: f32, range: f32) {
+    out.extend_from_slice(&(keys.len() as i32).to_le_bytes());
+    for (time, value) in keys {
+        out.extend_from_slice(&quantize(*time, 0.0, duration).to_le_bytes());
+        for component in value {
+            out.extend_from_slice(&quantize(*component, -range, range).to_le_bytes());
+        }
+    }
+}
+
+/// `value` in `lower..=upper` as 16 bits
+fn quantize(value: f32, lower: f32, upper: f32) -> u16 {
+    if upper <= lower {
+        return 0;
+    }
+    ((value.clamp(lower, upper) - lower) / (upper - lower) * u16::MAX as f32).round() as u16
+}
+
+fn dequantize(value: u16, lower: f32, upper: f32) -> f32 {
+    lower + value as f32 / u16::MAX as f32 * (upper - lower)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> ProtocolResult<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.at..self.at + len)
+            .ok_or_else(|| ProtocolError::Decoding("Animation is too short".to_string()))?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> ProtocolResult<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> ProtocolResult<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> ProtocolResult<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> ProtocolResult<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> ProtocolResult<String> {
+        let rest = self.data.get(self.at..).unwrap_or_default();
+        let len = rest
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or_else(|| ProtocolError::Decoding("Animation has an unterminated name".to_string()))?;
+        self.at += len + 1;
+        Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+
+    fn keys(&mut self, duration: f32, range: f32) -> ProtocolResult<Vec<Key>> {
+        let count = self.i32()?.max(0) as usize;
+        let bytes = self.take(count * 8)?;
+        Ok(bytes
+            .chunks_exact(8)
+            .map(|key| {
+                let at = |i: usize| u16::from_le_bytes([key[i * 2], key[i * 2 + 1]]);
+                let value = [1, 2, 3].map(|i| dequantize(at(i), -range, range));
+                (dequantize(at(0), 0.0, duration), value)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAVE: &str = "HIERARCHY
+ROOT hip
+{
+\tOFFSET 0 0 0
+\tCHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation
+\tJOINT figureHair
+\t{
+\t\tOFFSET 0 1 0
+\t\tCHANNELS 3 Zrotation Xrotation Yrotation
+\t\tEnd Site
+\t\t{
+\t\t\tOFFSET 0 1 0
+\t\t}
+\t}
+\tJOINT rShldr
+\t{
+\t\tOFFSET -3 20 0
+\t\tCHANNELS 3 Zrotation Xrotation Yrotation
+\t\tEnd Site
+\t\t{
+\t\t\tOFFSET -10 0 0
+\t\t}
+\t}
+}
+MOTION
+Frames: 3
+Frame Time: 0.5
+0 40 0 0 0 0 5 5 5 0 0 0
+0 40 10 0 0 0 5 5 5 90 0 0
+0 40 20 0 0 0 5 5 5 180 0 0
+";
+
+    #[test]
+    fn test_bvh() {
+        let animation = Animation::from_bvh(WAVE, 3).unwrap();
+        assert_eq!(animation.duration, 1.0);
+        let names: Vec<&str> = animation.joints.iter().map(|joint| joint.name.as_str()).collect();
+        assert_eq!(names, vec!["mPelvis", "mShoulderRight"]);
+
+        // 10 inches forward along BVH Z is forward along the avatar's X
+        let pelvis = &animation.joints[0];
+        assert_eq!(pelvis.positions[1].0, 0.5);
+        assert!((pelvis.positions[1].1[0] - 0.254).abs() < 1e-5);
+
+        // 90 degrees about BVH Z is about the avatar's X
+        let shoulder = &animation.joints[1];
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let [x, y, z] = shoulder.rotations[1].1;
+        assert!((x - half).abs() < 1e-5 && y.abs() < 1e-5 && z.abs() < 1e-5);
+        assert!(is_bvh(WAVE.as_bytes()));
+        assert!(Animation::from_bvh("HIERARCHY\nROOT hip\n{\n}\n", 3).is_err());
+    }
+
+    #[test]
+    fn test_keyframes() {
+        let mut animation = Animation::from_bvh(WAVE, 3).unwrap();
+        animation.emote = "express_smile".to_string();
+        animation.constraints.push(vec![1; CONSTRAINT_SIZE]);
+        let data = animation.encode();
+        assert!(!is_bvh(&data));
+
+        let parsed = Animation::parse(&data).unwrap();
+        assert_eq!(parsed.emote, "express_smile");
+        assert_eq!(parsed.joints.len(), 2);
+        assert_eq!(parsed.constraints, animation.constraints);
+        for (parsed, joint) in parsed.joints.iter().zip(&animation.joints) {
+            for ((parsed_time, parsed_value), (time, value)) in parsed.rotations.iter().zip(&joint.rotations) {
+                assert!((parsed_time - time).abs() < 1e-3);
+                assert!(parsed_value.iter().zip(value).all(|(a, b)| (a - b).abs() < 1e-3));
+            }
+        }
+        assert!(Animation::parse(&data[..data.len() - 1]).is_err());
+        assert!(Animation::parse(b"HIERARCHY").is_err());
+    }
+}
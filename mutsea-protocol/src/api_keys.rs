@@ -0,0 +1,306 @@
+//! Per-user API keys for the admin and analytics HTTP endpoints
+//!
+//! Keys are shown once at issuance and stored only as SHA-256 hashes. The
+//! store is a JSON file shared by `mutsea apikey` and the running server;
+//! the server reloads it when the file changes.
+
+use crate::json_file::JsonFile;
+use crate::{ProtocolError, ProtocolResult};
+use mutsea_core::UserId;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::RwLock;
+use tracing::{info, warn};
+
+/// Prefix of every issued key, so leaked keys are easy to recognise
+pub const API_KEY_PREFIX: &str = "msk_";
+
+/// Permission granted to an API key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ApiScope {
+    /// Read server and analytics statistics
+    #[serde(rename = "read:stats")]
+    ReadStats,
+    /// Create and modify user accounts
+    #[serde(rename = "write:users")]
+    WriteUsers,
+    /// Manage regions
+    #[serde(rename = "admin:regions")]
+    AdminRegions,
+    /// Issue and revoke API keys
+    #[serde(rename = "admin:keys")]
+    AdminKeys,
+    /// Report outcomes of AI decisions
+    #[serde(rename = "write:ai_feedback")]
+    WriteAiFeedback,
+    /// Create, move and delete prims and chat in regions
+    #[serde(rename = "write:scene")]
+    WriteScene,
+    /// Look up objects in regions
+    #[serde(rename = "read:scene")]
+    ReadScene,
+}
+
+impl ApiScope {
+    /// All known scopes
+    pub const ALL: [ApiScope; 7] = [
+        ApiScope::ReadStats,
+        ApiScope::WriteUsers,
+        ApiScope::AdminRegions,
+        ApiScope::AdminKeys,
+        ApiScope::WriteAiFeedback,
+        ApiScope::WriteScene,
+        ApiScope::ReadScene,
+    ];
+
+    /// Wire name of the scope
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::ReadStats => "read:stats",
+            ApiScope::WriteUsers => "write:users",
+            ApiScope::AdminRegions => "admin:regions",
+            ApiScope::AdminKeys => "admin:keys",
+            ApiScope::WriteAiFeedback => "write:ai_feedback",
+            ApiScope::WriteScene => "write:scene",
+            ApiScope::ReadScene => "read:scene",
+        }
+    }
+}
+
+impl fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ApiScope {
+    type Err = ProtocolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ApiScope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| ProtocolError::Generic(format!("Unknown API scope: {}", s)))
+    }
+}
+
+/// A stored API key; the secret itself is never kept
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyRecord {
+    /// Public key identifier, also embedded in the key
+    pub id: String,
+    /// Owning user
+    pub owner: UserId,
+    /// Human-readable label
+    pub name: String,
+    /// Hex-encoded SHA-256 of the full key
+    pub key_hash: String,
+    /// Granted scopes
+    pub scopes: Vec<ApiScope>,
+    /// When the key was issued
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// When the key was revoked, if it has been
+    pub revoked_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl ApiKeyRecord {
+    /// Whether the key is still usable
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none()
+    }
+
+    /// Whether the key grants `scope`
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+/// API key store, optionally persisted to a JSON file
+pub struct ApiKeyStore {
+    file: JsonFile,
+    keys: RwLock<HashMap<String, ApiKeyRecord>>,
+}
+
+impl ApiKeyStore {
+    /// In-memory store, used by tests
+    pub fn in_memory() -> Self {
+        Self {
+            file: JsonFile::in_memory(),
+            keys: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Open the store at `path`; a missing file is an empty store
+    pub fn open(path: impl Into<PathBuf>) -> ProtocolResult<Self> {
+        let store = Self {
+            file: JsonFile::new(path.into()),
+            keys: RwLock::new(HashMap::new()),
+        };
+        store.reload_if_changed()?;
+        Ok(store)
+    }
+
+    /// Issue a new key, returning its record and the plaintext key. The
+    /// plaintext cannot be recovered afterwards.
+    pub fn issue(&self, owner: UserId, name: &str, scopes: Vec<ApiScope>) -> ProtocolResult<(ApiKeyRecord, String)> {
+        if scopes.is_empty() {
+            return Err(ProtocolError::Generic("An API key needs at least one scope".to_string()));
+        }
+        self.reload_if_changed()?;
+
+        let id: String = random_alphanumeric(12).to_lowercase();
+        let key = format!("{}{}_{}", API_KEY_PREFIX, id, random_alphanumeric(32));
+        let record = ApiKeyRecord {
+            id: id.clone(),
+            owner,
+            name: name.to_string(),
+            key_hash: hash_key(&key),
+            scopes,
+            created_at: chrono::Utc::now(),
+            revoked_at: None,
+        };
+
+        self.keys.write().unwrap().insert(id.clone(), record.clone());
+        self.save()?;
+        info!("🔑 Issued API key {} ({}) for {}", id, name, owner);
+
+        Ok((record, key))
+    }
+
+    /// Revoke a key by ID; returns false if no active key has that ID
+    pub fn revoke(&self, id: &str) -> ProtocolResult<bool> {
+        self.reload_if_changed()?;
+        let revoked = match self.keys.write().unwrap().get_mut(id) {
+            Some(record) if record.is_active() => {
+                record.revoked_at = Some(chrono::Utc::now());
+                true
+            }
+            _ => false,
+        };
+
+        if revoked {
+            self.save()?;
+            info!("🔑 Revoked API key {}", id);
+        }
+        Ok(revoked)
+    }
+
+    /// List keys, optionally only those owned by `owner`
+    pub fn list(&self, owner: Option<UserId>) -> Vec<ApiKeyRecord> {
+        if let Err(e) = self.reload_if_changed() {
+            warn!("Failed to reload API keys: {}", e);
+        }
+        let mut keys: Vec<_> = self
+            .keys
+            .read()
+            .unwrap()
+            .values()
+            .filter(|record| owner.is_none_or(|owner| record.owner == owner))
+            .cloned()
+            .collect();
+        keys.sort_by_key(|record| record.created_at);
+        keys
+    }
+
+    /// Look up the active record for a presented key
+    pub fn verify(&self, key: &str) -> Option<ApiKeyRecord> {
+        let id = key.strip_prefix(API_KEY_PREFIX)?.split('_').next()?;
+        if let Err(e) = self.reload_if_changed() {
+            warn!("Failed to reload API keys: {}", e);
+        }
+
+        let keys = self.keys.read().unwrap();
+        let record = keys.get(id)?;
+        let presented = hash_key(key);
+        if record.is_active() && constant_time_eq(presented.as_bytes(), record.key_hash.as_bytes()) {
+            Some(record.clone())
+        } else {
+            None
+        }
+    }
+
+    fn reload_if_changed(&self) -> ProtocolResult<()> {
+        if let Some(records) = self.file.reload_if_changed::<ApiKeyRecord>()? {
+            *self.keys.write().unwrap() = records.into_iter().map(|r| (r.id.clone(), r)).collect();
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> ProtocolResult<()> {
+        let keys = self.keys.read().unwrap();
+        let mut records: Vec<_> = keys.values().collect();
+        records.sort_by_key(|record| record.created_at);
+        self.file.save(&records)
+    }
+}
+
+fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn random_alphanumeric(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_issue_verify_revoke() {
+        let store = ApiKeyStore::in_memory();
+        let owner = UserId::new();
+        let (record, key) = store.issue(owner, "dashboard", vec![ApiScope::ReadStats]).unwrap();
+
+        assert!(key.starts_with(API_KEY_PREFIX));
+        assert!(!record.key_hash.contains(&key));
+        let verified = store.verify(&key).unwrap();
+        assert!(verified.has_scope(ApiScope::ReadStats));
+        assert!(!verified.has_scope(ApiScope::WriteUsers));
+
+        // Right ID, wrong secret
+        let forged = format!("{}{}_{}", API_KEY_PREFIX, record.id, "x".repeat(32));
+        assert!(store.verify(&forged).is_none());
+
+        assert!(store.revoke(&record.id).unwrap());
+        assert!(!store.revoke(&record.id).unwrap());
+        assert!(store.verify(&key).is_none());
+    }
+
+    #[test]
+    fn test_file_store_is_shared() {
+        let path = std::env::temp_dir().join(format!("mutsea-api-keys-{}.json", uuid::Uuid::new_v4()));
+        let cli = ApiKeyStore::open(&path).unwrap();
+        let server = ApiKeyStore::open(&path).unwrap();
+
+        let (record, key) = cli.issue(UserId::new(), "ops", vec![ApiScope::AdminRegions]).unwrap();
+        assert_eq!(server.verify(&key).unwrap().id, record.id);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains(&key));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_scope_parsing() {
+        assert_eq!("write:users".parse::<ApiScope>().unwrap(), ApiScope::WriteUsers);
+        assert!("root".parse::<ApiScope>().is_err());
+        assert_eq!(serde_json::to_string(&ApiScope::AdminRegions).unwrap(), "\"admin:regions\"");
+    }
+}
@@ -0,0 +1,318 @@
+//! Avatar appearance baked by the region
+//!
+//! With server-side appearance the viewer no longer bakes its own avatar:
+//! it posts `{cof_version}` to UpdateAvatarAppearance once its outfit
+//! changes, and the region layers the textures of the wearables in the
+//! agent's Current Outfit folder into one baked texture per body region.
+//! Everyone is then sent an AvatarAppearance whose TextureEntry names the
+//! bakes, and fetches them from the AgentTextures path.
+//!
+//! Wearables are kept as LLWearable text assets; only their type and
+//! textures matter for baking.
+
+use crate::constants::packet_types;
+use crate::{llsd, ProtocolError, ProtocolResult};
+use mutsea_core::UserId;
+use serde_json::json;
+use uuid::Uuid;
+
+/// Texture shown on avatar faces that have no bake
+pub const DEFAULT_AVATAR_TEXTURE: Uuid = Uuid::from_u128(0xc228d1cf_4b5d_4ba8_84f4_899a0796aa97);
+
+/// Avatar TextureEntry faces of the wearable layers and their bakes
+pub mod faces {
+    /// Skin of the head
+    pub const HEAD_BODYPAINT: u8 = 0;
+    /// Shirt
+    pub const UPPER_SHIRT: u8 = 1;
+    /// Pants
+    pub const LOWER_PANTS: u8 = 2;
+    /// Skin of the upper body
+    pub const UPPER_BODYPAINT: u8 = 5;
+    /// Skin of the lower body
+    pub const LOWER_BODYPAINT: u8 = 6;
+    /// Shoes
+    pub const LOWER_SHOES: u8 = 7;
+    /// Bake of the head
+    pub const HEAD_BAKED: u8 = 8;
+    /// Bake of the upper body
+    pub const UPPER_BAKED: u8 = 9;
+    /// Bake of the lower body
+    pub const LOWER_BAKED: u8 = 10;
+    /// Socks
+    pub const LOWER_SOCKS: u8 = 12;
+    /// Jacket over the upper body
+    pub const UPPER_JACKET: u8 = 13;
+    /// Jacket over the lower body
+    pub const LOWER_JACKET: u8 = 14;
+    /// Gloves
+    pub const UPPER_GLOVES: u8 = 15;
+    /// Undershirt
+    pub const UPPER_UNDERSHIRT: u8 = 16;
+    /// Underpants
+    pub const LOWER_UNDERPANTS: u8 = 17;
+    /// Alpha mask of the lower body
+    pub const LOWER_ALPHA: u8 = 21;
+    /// Alpha mask of the upper body
+    pub const UPPER_ALPHA: u8 = 22;
+    /// Alpha mask of the head
+    pub const HEAD_ALPHA: u8 = 23;
+    /// Tattoo on the head
+    pub const HEAD_TATTOO: u8 = 26;
+    /// Tattoo on the upper body
+    pub const UPPER_TATTOO: u8 = 27;
+    /// Tattoo on the lower body
+    pub const LOWER_TATTOO: u8 = 28;
+}
+
+/// A body region baked into one texture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BakeKind {
+    /// Head
+    Head,
+    /// Torso and arms
+    Upper,
+    /// Legs and feet
+    Lower,
+}
+
+impl BakeKind {
+    /// Every bake
+    pub const ALL: [BakeKind; 3] = [BakeKind::Head, BakeKind::Upper, BakeKind::Lower];
+
+    /// The name of the bake in AgentTextures paths
+    pub fn name(self) -> &'static str {
+        match self {
+            BakeKind::Head => "head",
+            BakeKind::Upper => "upper",
+            BakeKind::Lower => "lower",
+        }
+    }
+
+    /// The bake named `name` in AgentTextures paths
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// The face showing the bake
+    pub fn baked_face(self) -> u8 {
+        match self {
+            BakeKind::Head => faces::HEAD_BAKED,
+            BakeKind::Upper => faces::UPPER_BAKED,
+            BakeKind::Lower => faces::LOWER_BAKED,
+        }
+    }
+
+    /// The faces layered into the bake, skin first
+    pub fn layers(self) -> &'static [u8] {
+        use faces::*;
+        match self {
+            BakeKind::Head => &[HEAD_BODYPAINT, HEAD_TATTOO],
+            BakeKind::Upper => &[
+                UPPER_BODYPAINT,
+                UPPER_TATTOO,
+                UPPER_UNDERSHIRT,
+                UPPER_GLOVES,
+                UPPER_SHIRT,
+                UPPER_JACKET,
+            ],
+            BakeKind::Lower => &[
+                LOWER_BODYPAINT,
+                LOWER_TATTOO,
+                LOWER_UNDERPANTS,
+                LOWER_SOCKS,
+                LOWER_SHOES,
+                LOWER_PANTS,
+                LOWER_JACKET,
+            ],
+        }
+    }
+
+    /// The face of the alpha mask cutting the bake
+    pub fn alpha_face(self) -> u8 {
+        match self {
+            BakeKind::Head => faces::HEAD_ALPHA,
+            BakeKind::Upper => faces::UPPER_ALPHA,
+            BakeKind::Lower => faces::LOWER_ALPHA,
+        }
+    }
+}
+
+/// The parts of an LLWearable asset that baking needs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wearable {
+    /// Name of the wearable
+    pub name: String,
+    /// Shape, skin, shirt...: 0 to 3 are body parts, the rest clothing
+    pub wearable_type: u8,
+    /// Textures by the face they go on
+    pub textures: Vec<(u8, Uuid)>,
+}
+
+impl Wearable {
+    /// Parse an LLWearable text asset
+    pub fn parse(text: &str) -> ProtocolResult<Self> {
+        let mut lines = text.lines().map(str::trim);
+        if !lines.next().is_some_and(|line| line.starts_with("LLWearable version")) {
+            return Err(ProtocolError::Decoding("Not an LLWearable".to_string()));
+        }
+        let name = lines.next().unwrap_or_default().to_string();
+        let mut wearable_type = None;
+        let mut textures = Vec::new();
+        while let Some(line) = lines.next() {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next().and_then(|count| count.parse::<usize>().ok())) {
+                (Some("type"), Some(value)) => wearable_type = u8::try_from(value).ok(),
+                (Some("textures"), Some(count)) => {
+                    for line in lines.by_ref().take(count) {
+                        let mut words = line.split_whitespace();
+                        let face = words.next().and_then(|face| face.parse().ok());
+                        let texture = words.next().and_then(|id| Uuid::parse_str(id).ok());
+                        if let (Some(face), Some(texture)) = (face, texture) {
+                            textures.push((face, texture));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(Self {
+            name,
+            wearable_type: wearable_type
+                .ok_or_else(|| ProtocolError::Decoding("LLWearable has no type".to_string()))?,
+            textures,
+        })
+    }
+}
+
+/// The Current Outfit folder version an UpdateAvatarAppearance request was
+/// made for, from its body
+pub fn parse_update_appearance(body: &str) -> ProtocolResult<i32> {
+    let tag = "<key>cof_version</key>";
+    let start = body
+        .find(tag)
+        .ok_or_else(|| ProtocolError::Decoding("UpdateAvatarAppearance needs a cof_version".to_string()))?;
+    let value = body[start + tag.len()..].trim_start();
+    let value = value.strip_prefix("<integer>").unwrap_or(value);
+    let end = value.find('<').unwrap_or(value.len());
+    value[..end]
+        .trim()
+        .parse()
+        .map_err(|_| ProtocolError::Decoding("UpdateAvatarAppearance has a bad cof_version".to_string()))
+}
+
+/// The UpdateAvatarAppearance answer: the bakes made, or why none were
+pub fn encode_update_appearance_reply(cof_version: i32, error: Option<&str>) -> String {
+    let mut reply = json!({ "success": error.is_none(), "cof_version": cof_version });
+    if let Some(error) = error {
+        reply["error"] = error.into();
+    }
+    llsd::to_xml(&reply)
+}
+
+/// AvatarAppearance showing `agent_id` with the `bakes` of its Current
+/// Outfit folder version `cof_version`. Visual parameters are left out, so
+/// viewers keep the shape they have.
+pub fn encode_avatar_appearance(agent_id: UserId, cof_version: i32, bakes: &[(BakeKind, Uuid)]) -> Vec<u8> {
+    let mut payload = vec![packet_types::AVATAR_APPEARANCE as u8];
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    payload.push(0); // IsTrial
+    let faces: Vec<(u8, Uuid)> = bakes.iter().map(|(kind, texture)| (kind.baked_face(), *texture)).collect();
+    let texture_entry = encode_texture_entry(DEFAULT_AVATAR_TEXTURE, &faces);
+    payload.extend_from_slice(&(texture_entry.len() as u16).to_le_bytes());
+    payload.extend_from_slice(&texture_entry);
+    payload.push(0); // VisualParam
+    payload.push(1); // AppearanceData
+    payload.push(1); // AppearanceVersion
+    payload.extend_from_slice(&cof_version.to_le_bytes());
+    payload.extend_from_slice(&0u32.to_le_bytes()); // Flags
+    payload.push(0); // AppearanceHover
+    payload
+}
+
+/// A TextureEntry showing `default` but on `faces`, with every face white,
+/// unscaled and unlit
+fn encode_texture_entry(default: Uuid, faces: &[(u8, Uuid)]) -> Vec<u8> {
+    let mut entry = default.as_bytes().to_vec();
+    for (face, texture) in faces {
+        push_face_bits(&mut entry, 1u64 << face);
+        entry.extend_from_slice(texture.as_bytes());
+    }
+    entry.push(0);
+    // Colour (inverted), scale U and V, offset U and V, rotation, material,
+    // media, glow and material ID, each a default with no exceptions
+    let defaults: [&[u8]; 10] = [
+        &[0; 4],
+        &1f32.to_le_bytes(),
+        &1f32.to_le_bytes(),
+        &[0; 2],
+        &[0; 2],
+        &[0; 2],
+        &[0],
+        &[0],
+        &[0],
+        &[0; 16],
+    ];
+    for default in defaults {
+        entry.extend_from_slice(default);
+        entry.push(0);
+    }
+    entry
+}
+
+/// The faces an exception applies to, seven bits a byte, the highest
+/// first, every byte but the last flagged with 0x80
+fn push_face_bits(entry: &mut Vec<u8>, bits: u64) {
+    let mut groups = vec![(bits & 0x7f) as u8];
+    let mut rest = bits >> 7;
+    while rest != 0 {
+        groups.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    entry.extend(groups.into_iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_wearable() {
+        let shirt_texture = Uuid::new_v4();
+        let text = format!(
+            "LLWearable version 22\nBlue Shirt\n\n\tpermissions 0\n\t{{\n\t\tbase_mask\t7fffffff\n\t}}\n\
+             \tsale_info\t0\n\t{{\n\t\tsale_type\tnot\n\t}}\ntype 4\nparameters 2\n781 0\n800 .5\n\
+             textures 1\n1 {}\n",
+            shirt_texture
+        );
+        let wearable = Wearable::parse(&text).unwrap();
+        assert_eq!(wearable.name, "Blue Shirt");
+        assert_eq!(wearable.wearable_type, 4);
+        assert_eq!(wearable.textures, vec![(faces::UPPER_SHIRT, shirt_texture)]);
+        assert!(Wearable::parse("Not a wearable").is_err());
+        assert!(Wearable::parse("LLWearable version 22\nNo type\n").is_err());
+
+        let body = "<llsd><map><key>cof_version</key><integer>14</integer></map></llsd>";
+        assert_eq!(parse_update_appearance(body).unwrap(), 14);
+        assert!(parse_update_appearance("<llsd><map /></llsd>").is_err());
+    }
+
+    #[test]
+    fn test_texture_entry() {
+        let mut bits = Vec::new();
+        push_face_bits(&mut bits, 1 << faces::UPPER_BAKED);
+        assert_eq!(bits, vec![0x84, 0x00]);
+
+        let head = Uuid::new_v4();
+        let entry = encode_texture_entry(DEFAULT_AVATAR_TEXTURE, &[(faces::HEAD_BAKED, head)]);
+        assert_eq!(&entry[..16], DEFAULT_AVATAR_TEXTURE.as_bytes());
+        assert_eq!(&entry[16..18], &[0x82, 0x00]);
+        assert_eq!(&entry[18..34], head.as_bytes());
+        assert_eq!(entry[34], 0);
+
+        let payload = encode_avatar_appearance(UserId::new(), 3, &[(BakeKind::Head, head)]);
+        let length = u16::from_le_bytes([payload[18], payload[19]]) as usize;
+        assert_eq!(length, entry.len());
+        assert_eq!(&payload[payload.len() - 12..], &[0, 1, 1, 3, 0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,270 @@
+//! Attachments worn by avatars
+//!
+//! RezSingleAttachmentFromInv wears an inventory item at an attachment
+//! point, ObjectAttach wears prims already in the region, and ObjectDetach
+//! and DetachAttachmentIntoInv take them off again. Worn prims are sent as
+//! ObjectUpdates whose parent is the avatar and whose state is the point
+//! they are worn on; HUD points are drawn on their wearer's screen.
+
+use crate::object_update::encode_object_update;
+use crate::codec::MessageDecoder;
+use crate::ProtocolResult;
+use mutsea_core::{Quaternion, SceneObject, UserId};
+use uuid::Uuid;
+
+/// Attachment point 0: wherever the item was last worn
+pub const ATTACH_DEFAULT: u8 = 0;
+
+/// The highest body or HUD attachment point viewers know
+pub const MAX_ATTACHMENT_POINT: u8 = 55;
+
+/// The first HUD attachment point, top-right
+pub const FIRST_HUD_POINT: u8 = 31;
+
+/// The last HUD attachment point, bottom-right
+pub const LAST_HUD_POINT: u8 = 38;
+
+/// Flag viewers add to RezSingleAttachmentFromInv's point to keep what is
+/// already worn there
+pub const ATTACHMENT_ADD: u8 = 0x80;
+
+/// Whether `point` is an attachment point a viewer knows
+pub fn is_valid_point(point: u8) -> bool {
+    (1..=MAX_ATTACHMENT_POINT).contains(&point)
+}
+
+/// Whether `point` is drawn on the wearer's screen rather than its body
+pub fn is_hud(point: u8) -> bool {
+    (FIRST_HUD_POINT..=LAST_HUD_POINT).contains(&point)
+}
+
+/// An inventory item an agent asked to wear
+#[derive(Debug, Clone, PartialEq)]
+pub struct RezSingleAttachmentFromInv {
+    /// Agent wearing the item
+    pub agent_id: UserId,
+    /// Inventory item to wear
+    pub item_id: Uuid,
+    /// Owner of the item
+    pub owner_id: UserId,
+    /// Where to wear it, `ATTACH_DEFAULT` for where it was last worn
+    pub attachment_point: u8,
+    /// Whether to wear it alongside what is already on the point
+    pub add: bool,
+    /// Permissions the next owner gets
+    pub next_owner_mask: u32,
+    /// Item name
+    pub name: String,
+    /// Item description
+    pub description: String,
+}
+
+impl RezSingleAttachmentFromInv {
+    /// Parse a RezSingleAttachmentFromInv payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        let item_id = decoder.read_uuid()?;
+        let owner_id = UserId::from_uuid(decoder.read_uuid()?);
+        let point = decoder.read_u8()?;
+        decoder.skip(4 * 3)?; // ItemFlags, GroupMask, EveryoneMask
+
+        Ok(Self {
+            agent_id,
+            item_id,
+            owner_id,
+            attachment_point: point & !ATTACHMENT_ADD,
+            add: point & ATTACHMENT_ADD != 0,
+            next_owner_mask: decoder.read_u32()?,
+            name: decoder.read_variable1()?,
+            description: decoder.read_variable1()?,
+        })
+    }
+}
+
+/// A prim in the region an agent asked to wear
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AttachedPrim {
+    /// Prim to wear
+    pub local_id: u32,
+    /// Rotation on the attachment point
+    pub rotation: Quaternion,
+}
+
+/// Prims in the region an agent asked to wear at one point
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectAttach {
+    /// Agent wearing the prims
+    pub agent_id: UserId,
+    /// Point to wear them on, `ATTACH_DEFAULT` for where each was last worn
+    pub attachment_point: u8,
+    /// Whether to wear them alongside what is already on the point
+    pub add: bool,
+    /// Prims to wear
+    pub objects: Vec<AttachedPrim>,
+}
+
+impl ObjectAttach {
+    /// Parse an ObjectAttach payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        let point = decoder.read_u8()?;
+        // LocalID and a rotation packed as X, Y and Z for each prim
+        let count = decoder.read_u8()?;
+        let objects = (0..count)
+            .map(|_| {
+                let local_id = decoder.read_u32()?;
+                let rotation = decoder.read_vector3()?;
+                let (x, y, z) = (rotation.x, rotation.y, rotation.z);
+                let w = (1.0 - x * x - y * y - z * z).max(0.0).sqrt();
+                Ok(AttachedPrim {
+                    local_id,
+                    rotation: Quaternion::new(x, y, z, w),
+                })
+            })
+            .collect::<ProtocolResult<_>>()?;
+
+        Ok(Self {
+            agent_id,
+            attachment_point: point & !ATTACHMENT_ADD,
+            add: point & ATTACHMENT_ADD != 0,
+            objects,
+        })
+    }
+}
+
+/// Worn prims an agent asked to take off, by local ID
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectDetach {
+    /// Agent taking the prims off
+    pub agent_id: UserId,
+    /// Local IDs of the worn prims
+    pub local_ids: Vec<u32>,
+}
+
+impl ObjectDetach {
+    /// Parse an ObjectDetach payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        // ObjectLocalID for each prim
+        let count = decoder.read_u8()?;
+        Ok(Self {
+            agent_id,
+            local_ids: (0..count).map(|_| decoder.read_u32()).collect::<ProtocolResult<_>>()?,
+        })
+    }
+}
+
+/// A worn inventory item an agent asked to take off
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetachAttachmentIntoInv {
+    /// Agent taking the item off
+    pub agent_id: UserId,
+    /// Inventory item the worn prim was rezzed from
+    pub item_id: Uuid,
+}
+
+impl DetachAttachmentIntoInv {
+    /// Parse a DetachAttachmentIntoInv payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        Ok(Self {
+            agent_id: UserId::from_uuid(decoder.read_uuid()?),
+            item_id: decoder.read_uuid()?,
+        })
+    }
+}
+
+/// An ObjectUpdate for `object` worn at `attachment_point` by the avatar
+/// with `parent_local_id`, from the inventory item `item_id`. The prim's
+/// position and rotation are taken as its offset on the point.
+pub fn encode_attachment_update(
+    object: &SceneObject,
+    parent_local_id: u32,
+    attachment_point: u8,
+    item_id: Uuid,
+) -> Vec<u8> {
+    // Viewers read the point from the state with its nibbles swapped, and
+    // match the prim to the item it was worn from by its AttachItemID
+    encode_object_update(
+        object,
+        parent_local_id,
+        attachment_point.rotate_left(4),
+        &format!("AttachItemID STRING RW SV {}", item_id),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instant_message::push_variable1;
+
+    #[test]
+    fn test_parse_rez_and_attach() {
+        let agent_id = UserId::new();
+        let item_id = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(item_id.as_bytes());
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.push(ATTACHMENT_ADD | 2);
+        for mask in [0u32, 0, 0, 0x8000] {
+            payload.extend_from_slice(&mask.to_le_bytes());
+        }
+        push_variable1(&mut payload, "Hair");
+        push_variable1(&mut payload, "");
+
+        let rez = RezSingleAttachmentFromInv::parse(&payload).unwrap();
+        assert_eq!(rez.item_id, item_id);
+        assert_eq!(rez.attachment_point, 2);
+        assert!(rez.add);
+        assert_eq!(rez.next_owner_mask, 0x8000);
+        assert_eq!(rez.name, "Hair");
+        assert!(RezSingleAttachmentFromInv::parse(&payload[..payload.len() - 1]).is_err());
+
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.push(FIRST_HUD_POINT);
+        payload.push(1);
+        payload.extend_from_slice(&42u32.to_le_bytes());
+        payload.extend_from_slice(&[0; 12]);
+
+        let attach = ObjectAttach::parse(&payload).unwrap();
+        assert_eq!(attach.attachment_point, FIRST_HUD_POINT);
+        assert!(!attach.add);
+        assert_eq!(attach.objects[0].local_id, 42);
+        assert_eq!(attach.objects[0].rotation, Quaternion::IDENTITY);
+        assert!(ObjectAttach::parse(&payload[..payload.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_points_and_detach() {
+        assert!(is_valid_point(2));
+        assert!(!is_valid_point(ATTACH_DEFAULT));
+        assert!(!is_valid_point(56));
+        assert!(is_hud(35));
+        assert!(!is_hud(2));
+
+        let agent_id = UserId::new();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.push(2);
+        payload.extend_from_slice(&7u32.to_le_bytes());
+        payload.extend_from_slice(&9u32.to_le_bytes());
+        assert_eq!(ObjectDetach::parse(&payload).unwrap().local_ids, vec![7, 9]);
+
+        let item_id = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(item_id.as_bytes());
+        assert_eq!(DetachAttachmentIntoInv::parse(&payload).unwrap().item_id, item_id);
+    }
+}
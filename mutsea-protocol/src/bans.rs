@@ -0,0 +1,263 @@
+//! Account, IP and hardware bans
+//!
+//! Grid-wide bans are enforced at login; estate bans are enforced when an
+//! agent enters a region of that estate. The list is a JSON file shared by
+//! `mutsea user ban` and the running server, reloaded when it changes.
+
+use crate::json_file::JsonFile;
+use crate::ProtocolResult;
+use mutsea_core::UserId;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// What a ban applies to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum BanTarget {
+    /// A user account
+    Account(UserId),
+    /// A client IP address
+    Ip(IpAddr),
+    /// A viewer hardware hash (the login `mac` or `id0`)
+    Hardware(String),
+}
+
+impl BanTarget {
+    fn matches(&self, user_id: Option<UserId>, ip: Option<IpAddr>, hardware: &[&str]) -> bool {
+        match self {
+            BanTarget::Account(banned) => user_id == Some(*banned),
+            BanTarget::Ip(banned) => ip == Some(*banned),
+            BanTarget::Hardware(banned) => hardware.iter().any(|h| !h.is_empty() && h.eq_ignore_ascii_case(banned)),
+        }
+    }
+}
+
+/// Where a ban is enforced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "estate_id", rename_all = "snake_case")]
+pub enum BanScope {
+    /// Refused at login
+    Grid,
+    /// Refused entry to regions of this estate
+    Estate(u32),
+}
+
+/// A ban entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BanRecord {
+    /// Ban identifier
+    pub id: Uuid,
+    /// Banned account, address or hardware
+    pub target: BanTarget,
+    /// Where the ban applies
+    pub scope: BanScope,
+    /// Reason shown to the banned user
+    pub reason: String,
+    /// When the ban was created
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// When the ban lapses; `None` is permanent
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl BanRecord {
+    /// Whether the ban is in force at `now`
+    pub fn is_active_at(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.expires_at.is_none_or(|expires| expires > now)
+    }
+
+    /// Message shown to the viewer
+    pub fn message(&self) -> String {
+        let place = match self.scope {
+            BanScope::Grid => "this grid".to_string(),
+            BanScope::Estate(_) => "this estate".to_string(),
+        };
+        match self.expires_at {
+            Some(expires) => format!(
+                "You are banned from {} until {}: {}",
+                place,
+                expires.format("%Y-%m-%d %H:%M UTC"),
+                self.reason
+            ),
+            None => format!("You are banned from {}: {}", place, self.reason),
+        }
+    }
+}
+
+/// Ban list, optionally persisted to a JSON file
+pub struct BanList {
+    file: JsonFile,
+    bans: RwLock<Vec<BanRecord>>,
+}
+
+impl BanList {
+    /// In-memory ban list
+    pub fn in_memory() -> Self {
+        Self {
+            file: JsonFile::in_memory(),
+            bans: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Open the ban list at `path`; a missing file is an empty list
+    pub fn open(path: impl Into<PathBuf>) -> ProtocolResult<Self> {
+        let list = Self {
+            file: JsonFile::new(path.into()),
+            bans: RwLock::new(Vec::new()),
+        };
+        list.reload_if_changed()?;
+        Ok(list)
+    }
+
+    /// Add a ban; `duration` of `None` bans permanently
+    pub fn ban(
+        &self,
+        target: BanTarget,
+        scope: BanScope,
+        reason: &str,
+        duration: Option<chrono::Duration>,
+    ) -> ProtocolResult<BanRecord> {
+        self.reload_if_changed()?;
+        let now = chrono::Utc::now();
+        let record = BanRecord {
+            id: Uuid::new_v4(),
+            target,
+            scope,
+            reason: reason.to_string(),
+            created_at: now,
+            expires_at: duration.map(|d| now + d),
+        };
+
+        self.bans.write().unwrap().push(record.clone());
+        self.save()?;
+        info!("🚫 Banned {:?} ({:?}): {}", record.target, record.scope, record.reason);
+        Ok(record)
+    }
+
+    /// Remove a ban by ID; returns false if there was none
+    pub fn unban(&self, id: Uuid) -> ProtocolResult<bool> {
+        self.reload_if_changed()?;
+        let removed = {
+            let mut bans = self.bans.write().unwrap();
+            let before = bans.len();
+            bans.retain(|b| b.id != id);
+            bans.len() != before
+        };
+
+        if removed {
+            self.save()?;
+            info!("✅ Lifted ban {}", id);
+        }
+        Ok(removed)
+    }
+
+    /// Bans currently in force
+    pub fn list(&self) -> Vec<BanRecord> {
+        self.refresh();
+        let now = chrono::Utc::now();
+        self.bans
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|b| b.is_active_at(now))
+            .cloned()
+            .collect()
+    }
+
+    /// Grid ban matching a login attempt, if any
+    pub fn check_login(&self, user_id: UserId, ip: Option<IpAddr>, mac: &str, id0: &str) -> Option<BanRecord> {
+        self.find(BanScope::Grid, Some(user_id), ip, &[mac, id0])
+    }
+
+    /// Grid or estate ban matching a region entry, if any
+    pub fn check_region_entry(&self, user_id: UserId, estate_id: u32, ip: IpAddr) -> Option<BanRecord> {
+        self.find(BanScope::Grid, Some(user_id), Some(ip), &[])
+            .or_else(|| self.find(BanScope::Estate(estate_id), Some(user_id), Some(ip), &[]))
+    }
+
+    fn find(&self, scope: BanScope, user_id: Option<UserId>, ip: Option<IpAddr>, hardware: &[&str]) -> Option<BanRecord> {
+        self.refresh();
+        let now = chrono::Utc::now();
+        self.bans
+            .read()
+            .unwrap()
+            .iter()
+            .find(|b| b.scope == scope && b.is_active_at(now) && b.target.matches(user_id, ip, hardware))
+            .cloned()
+    }
+
+    fn refresh(&self) {
+        if let Err(e) = self.reload_if_changed() {
+            warn!("Failed to reload ban list: {}", e);
+        }
+    }
+
+    fn reload_if_changed(&self) -> ProtocolResult<()> {
+        if let Some(bans) = self.file.reload_if_changed()? {
+            *self.bans.write().unwrap() = bans;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> ProtocolResult<()> {
+        // Expired bans are dropped when the list is written
+        let mut bans = self.bans.write().unwrap();
+        let now = chrono::Utc::now();
+        bans.retain(|b| b.is_active_at(now));
+        self.file.save(&bans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_grid_bans_match_account_ip_and_hardware() {
+        let bans = BanList::in_memory();
+        let user = UserId::new();
+        let other = UserId::new();
+
+        bans.ban(BanTarget::Account(user), BanScope::Grid, "griefing", None).unwrap();
+        bans.ban(BanTarget::Ip(ip("10.0.0.5")), BanScope::Grid, "spam", None).unwrap();
+        bans.ban(BanTarget::Hardware("ABCDEF".to_string()), BanScope::Grid, "alts", None).unwrap();
+
+        assert_eq!(bans.check_login(user, None, "", "").unwrap().reason, "griefing");
+        assert_eq!(bans.check_login(other, Some(ip("10.0.0.5")), "", "").unwrap().reason, "spam");
+        assert_eq!(bans.check_login(other, None, "", "abcdef").unwrap().reason, "alts");
+        assert!(bans.check_login(other, Some(ip("10.0.0.6")), "", "").is_none());
+    }
+
+    #[test]
+    fn test_expired_and_lifted_bans_do_not_apply() {
+        let bans = BanList::in_memory();
+        let user = UserId::new();
+
+        bans.ban(BanTarget::Account(user), BanScope::Grid, "timeout", Some(chrono::Duration::seconds(-1)))
+            .unwrap();
+        assert!(bans.check_login(user, None, "", "").is_none());
+
+        let record = bans.ban(BanTarget::Account(user), BanScope::Grid, "again", Some(chrono::Duration::hours(1))).unwrap();
+        assert!(bans.check_login(user, None, "", "").unwrap().message().contains("until"));
+        assert!(bans.unban(record.id).unwrap());
+        assert!(bans.check_login(user, None, "", "").is_none());
+    }
+
+    #[test]
+    fn test_estate_bans_only_apply_to_that_estate() {
+        let bans = BanList::in_memory();
+        let user = UserId::new();
+        bans.ban(BanTarget::Account(user), BanScope::Estate(7), "not welcome", None).unwrap();
+
+        assert!(bans.check_login(user, None, "", "").is_none());
+        assert!(bans.check_region_entry(user, 1, ip("127.0.0.1")).is_none());
+        assert!(bans.check_region_entry(user, 7, ip("127.0.0.1")).is_some());
+    }
+}
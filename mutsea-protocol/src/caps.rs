@@ -49,6 +49,7 @@ impl CapabilityManager {
     }
     
     /// Process capability request
+    #[tracing::instrument(name = "caps.request", skip(self, data), fields(bytes = data.len()))]
     pub fn process_request(&self, capability_name: &str, data: &[u8]) -> ProtocolResult<Vec<u8>> {
         if let Some(handler) = self.handlers.get(capability_name) {
             handler.handle_request(data)
@@ -107,6 +108,12 @@ impl CapabilityManager {
             format!("{}/caps/web_fetch_inventory", base_url),
         ));
         
+        // EnvironmentSettings
+        self.add_capability(Capability::new(
+            "EnvironmentSettings".to_string(),
+            format!("{}/caps/environment_settings", base_url),
+        ));
+        
         // Add handlers for basic capabilities
         self.add_handler(EventQueueHandler::new());
         self.add_handler(TextureHandler::new());
@@ -0,0 +1,93 @@
+//! ChatFromSimulator encoding
+//!
+//! Chat the simulator says itself, such as NPC replies, is sent to each
+//! agent in range as ChatFromSimulator.
+
+use crate::constants::{chat_sources, chat_types, packet_types};
+use crate::instant_message::{push_variable1, push_variable2};
+use mutsea_core::Vector3;
+use uuid::Uuid;
+
+/// Metres whispered chat carries
+pub const WHISPER_RANGE: f32 = 10.0;
+
+/// Metres said chat carries
+pub const SAY_RANGE: f32 = 20.0;
+
+/// Metres shouted chat carries
+pub const SHOUT_RANGE: f32 = 100.0;
+
+/// One line of local chat
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatFromSimulator {
+    /// Name shown as the speaker
+    pub from_name: String,
+    /// Avatar or object speaking
+    pub source_id: Uuid,
+    /// Owner of an object speaker, nil otherwise
+    pub owner_id: Uuid,
+    /// One of [`chat_sources`]
+    pub source_type: u8,
+    /// One of [`chat_types`]
+    pub chat_type: u8,
+    /// Where the speaker is, in region coordinates
+    pub position: Vector3,
+    /// What was said
+    pub message: String,
+}
+
+impl ChatFromSimulator {
+    /// `message` said by object `source_id` at `position`
+    pub fn said_by_object(from_name: &str, source_id: Uuid, position: Vector3, message: &str) -> Self {
+        Self {
+            from_name: from_name.to_string(),
+            source_id,
+            owner_id: Uuid::nil(),
+            source_type: chat_sources::OBJECT,
+            chat_type: chat_types::SAY,
+            position,
+            message: message.to_string(),
+        }
+    }
+
+    /// Encode the ChatFromSimulator payload, starting with the message ID
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        payload.push(packet_types::CHAT_FROM_SIMULATOR);
+
+        // ChatData block
+        push_variable1(&mut payload, &self.from_name);
+        payload.extend_from_slice(self.source_id.as_bytes());
+        payload.extend_from_slice(self.owner_id.as_bytes());
+        payload.push(self.source_type);
+        payload.push(self.chat_type);
+        payload.push(1); // Audible: fully
+        payload.extend_from_slice(&self.position.x.to_le_bytes());
+        payload.extend_from_slice(&self.position.y.to_le_bytes());
+        payload.extend_from_slice(&self.position.z.to_le_bytes());
+        push_variable2(&mut payload, &self.message);
+
+        payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_layout() {
+        let source_id = Uuid::new_v4();
+        let chat = ChatFromSimulator::said_by_object("Mira", source_id, Vector3::new(128.0, 64.0, 25.0), "Well met!");
+        let payload = chat.encode();
+
+        assert_eq!(payload[0], packet_types::CHAT_FROM_SIMULATOR);
+        assert_eq!(&payload[1..7], b"\x05Mira\0");
+        assert_eq!(&payload[7..23], source_id.as_bytes());
+        // OwnerID, then SourceType, ChatType and Audible
+        assert_eq!(&payload[39..42], &[chat_sources::OBJECT, chat_types::SAY, 1]);
+        assert_eq!(f32::from_le_bytes(payload[42..46].try_into().unwrap()), 128.0);
+        assert_eq!(u16::from_le_bytes([payload[54], payload[55]]), 10);
+        assert_eq!(&payload[56..], b"Well met!\0");
+    }
+}
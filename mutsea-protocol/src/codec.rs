@@ -299,6 +299,31 @@ impl<'a> MessageDecoder<'a> {
     pub fn read_u64(&mut self) -> ProtocolResult<u64> {
         Ok(self.cursor.read_u64::<LittleEndian>()?)
     }
+
+    /// Read a signed byte
+    pub fn read_i8(&mut self) -> ProtocolResult<i8> {
+        Ok(self.cursor.read_i8()?)
+    }
+
+    /// Read a 16-bit signed integer
+    pub fn read_i16(&mut self) -> ProtocolResult<i16> {
+        Ok(self.cursor.read_i16::<LittleEndian>()?)
+    }
+
+    /// Read a 32-bit signed integer
+    pub fn read_i32(&mut self) -> ProtocolResult<i32> {
+        Ok(self.cursor.read_i32::<LittleEndian>()?)
+    }
+
+    /// Read a 64-bit signed integer
+    pub fn read_i64(&mut self) -> ProtocolResult<i64> {
+        Ok(self.cursor.read_i64::<LittleEndian>()?)
+    }
+
+    /// Read a BOOL, any non-zero byte being true
+    pub fn read_bool(&mut self) -> ProtocolResult<bool> {
+        Ok(self.read_u8()? != 0)
+    }
     
     /// Read a 32-bit float
     pub fn read_f32(&mut self) -> ProtocolResult<f32> {
@@ -355,10 +380,47 @@ impl<'a> MessageDecoder<'a> {
     /// Read binary data
     pub fn read_binary(&mut self) -> ProtocolResult<Vec<u8>> {
         let length = self.read_u32()? as usize;
+        self.read_bytes(length)
+    }
+
+    /// Read a Fixed field of `length` bytes
+    pub fn read_bytes(&mut self, length: usize) -> ProtocolResult<Vec<u8>> {
+        if length > self.remaining() {
+            return Err(ProtocolError::Decoding(format!(
+                "{} bytes wanted, {} left",
+                length,
+                self.remaining()
+            )));
+        }
         let mut data = vec![0u8; length];
         self.cursor.read_exact(&mut data)?;
         Ok(data)
     }
+
+    /// Read a Variable1 field (u8 length) as text, without the viewer's
+    /// trailing NUL
+    pub fn read_variable1(&mut self) -> ProtocolResult<String> {
+        let length = self.read_u8()? as usize;
+        self.read_text(length)
+    }
+
+    /// Read a Variable2 field (u16 length) as text, without the viewer's
+    /// trailing NUL
+    pub fn read_variable2(&mut self) -> ProtocolResult<String> {
+        let length = self.read_u16()? as usize;
+        self.read_text(length)
+    }
+
+    fn read_text(&mut self, length: usize) -> ProtocolResult<String> {
+        let bytes = self.read_bytes(length)?;
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(&bytes);
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    /// Skip `count` bytes, such as a message ID or fields not wanted
+    pub fn skip(&mut self, count: usize) -> ProtocolResult<()> {
+        self.read_bytes(count).map(|_| ())
+    }
     
     /// Get current position
     pub fn position(&self) -> u64 {
@@ -434,4 +496,27 @@ mod tests {
         assert_eq!(decoder.read_string().unwrap(), "test");
         assert!((decoder.read_f32().unwrap() - 3.14).abs() < 0.001);
     }
+
+    #[test]
+    fn test_decoder_reads_variable_fields() {
+        let mut data = vec![0xAB, 6];
+        data.extend_from_slice(b"hello\0");
+        data.extend_from_slice(&3u16.to_le_bytes());
+        data.extend_from_slice(b"hi\0");
+        data.extend_from_slice(&(-5i32).to_le_bytes());
+        data.push(1);
+
+        let mut decoder = MessageDecoder::new(&data);
+        decoder.skip(1).unwrap();
+        assert_eq!(decoder.read_variable1().unwrap(), "hello");
+        assert_eq!(decoder.read_variable2().unwrap(), "hi");
+        assert_eq!(decoder.read_i32().unwrap(), -5);
+        assert!(decoder.read_bool().unwrap());
+        assert!(!decoder.has_remaining());
+        assert!(decoder.skip(1).is_err());
+
+        // A length running past the end is refused before allocating
+        let mut decoder = MessageDecoder::new(&[0xFF, 0xFF, 0xFF, 0x7F]);
+        assert!(decoder.read_binary().is_err());
+    }
 }
\ No newline at end of file
@@ -47,8 +47,26 @@ pub mod packet_types {
     pub const OBJECT_UPDATE: u8 = 0x0C;
     pub const OBJECT_UPDATE_CACHED: u32 = 14;
     pub const OBJECT_UPDATE_COMPRESSED: u32 = 15;
+    /// RequestMultipleObjects, ask for full updates of prims missing from the cache
+    pub const REQUEST_MULTIPLE_OBJECTS: u32 = 13;
     pub const KILL_OBJECT: u32 = 78;
+    /// ObjectSelect, select prims for editing
+    pub const OBJECT_SELECT: u32 = 110;
+    /// ObjectDeselect, release prims selected for editing
+    pub const OBJECT_DESELECT: u32 = 112;
+    /// ObjectGrab, start dragging a prim
+    pub const OBJECT_GRAB: u32 = 119;
+    /// ObjectDrop, stop dragging a prim
+    pub const OBJECT_DROP: u32 = 120;
+    /// DeRezObject, take prims into inventory or delete them
+    pub const DEREZ_OBJECT: u32 = 291;
+    /// RezObject, rez an object item from inventory
+    pub const REZ_OBJECT: u32 = 293;
     pub const TERRAIN_PATCH: u32 = 87;
+    /// ModifyLand, a land tool stroke
+    pub const MODIFY_LAND: u32 = 124;
+    /// UndoLand, undo the last stroke
+    pub const UNDO_LAND: u32 = 128;
     
     // Chat and communication
     pub const CHAT_FROM_VIEWER: u32 = 80;
@@ -62,6 +80,8 @@ pub mod packet_types {
     pub const TRANSFER_REQUEST: u32 = 116;
     pub const TRANSFER_INFO: u32 = 117;
     pub const TRANSFER_PACKET: u32 = 118;
+    /// FetchInventoryDescendents, list the contents of an inventory folder
+    pub const FETCH_INVENTORY_DESCENDENTS: u32 = 277;
     
     // Physics and movement
     pub const SET_FOLLOW_CAM_PROPERTIES: u32 = 319;
@@ -73,6 +93,22 @@ pub mod packet_types {
     pub const MONEY_BALANCE_REPLY: u32 = 242;
     pub const PAY_MONEY_REQUEST: u32 = 243;
     pub const PAY_MONEY_REPLY: u32 = 244;
+    /// ObjectSaleInfo, put prims up for sale
+    pub const OBJECT_SALE_INFO: u32 = 106;
+    /// ObjectBuy, buy prims
+    pub const OBJECT_BUY: u32 = 107;
+    /// MoneyTransferRequest, pay an avatar or prim
+    pub const MONEY_TRANSFER_REQUEST: u32 = 311;
+    
+    // Attachments
+    /// RezSingleAttachmentFromInv, wear an inventory item
+    pub const REZ_SINGLE_ATTACHMENT_FROM_INV: u32 = 395;
+    /// ObjectAttach, wear prims in the region
+    pub const OBJECT_ATTACH: u32 = 111;
+    /// ObjectDetach, take off worn prims
+    pub const OBJECT_DETACH: u32 = 113;
+    /// DetachAttachmentIntoInv, take off the prim worn from an item
+    pub const DETACH_ATTACHMENT_INTO_INV: u32 = 400;
     
     // Group management
     pub const GROUP_MEMBERSHIP_DATA: u32 = 357;
@@ -84,6 +120,14 @@ pub mod packet_types {
     pub const PARCEL_INFO_REPLY: u32 = 435;
     pub const PARCEL_PROPERTIES_REQUEST: u32 = 436;
     pub const PARCEL_PROPERTIES: u32 = 437;
+    /// ParcelPropertiesUpdate, change a parcel's settings
+    pub const PARCEL_PROPERTIES_UPDATE: u32 = 198;
+    /// ParcelBuy, buy a parcel
+    pub const PARCEL_BUY: u32 = 213;
+    /// ParcelReturnObjects, return prims on a parcel
+    pub const PARCEL_RETURN_OBJECTS: u32 = 199;
+    /// ParcelSetOtherCleanTime, set a parcel's auto-return time
+    pub const PARCEL_SET_OTHER_CLEAN_TIME: u32 = 200;
     
     // Friends and social
     pub const ONLINE_NOTIFICATION: u32 = 138;
@@ -91,6 +135,80 @@ pub mod packet_types {
     pub const FIND_AGENT: u32 = 126;
     pub const TRACK_AGENT: u32 = 127;
     
+    // Moderation
+    /// GodKickUser, a god kicking or freezing an agent
+    pub const GOD_KICK_USER: u32 = 165;
+    /// FreezeUser, an estate manager freezing an agent
+    pub const FREEZE_USER: u32 = 168;
+    /// RequestGodlikePowers
+    pub const REQUEST_GODLIKE_POWERS: u32 = 257;
+    /// GrantGodlikePowers, the answer to RequestGodlikePowers
+    pub const GRANT_GODLIKE_POWERS: u32 = 258;
+    /// GodlikeMessage, a method call from the god tools
+    pub const GODLIKE_MESSAGE: u32 = 259;
+    /// EstateOwnerMessage, a method call from the estate tools
+    pub const ESTATE_OWNER_MESSAGE: u32 = 260;
+    /// UserReport, an abuse report from the viewer
+    pub const USER_REPORT: u32 = 133;
+    
+    // Search
+    /// DirPlacesQuery, a search for places by name
+    pub const DIR_PLACES_QUERY: u32 = 33;
+    /// DirPlacesReply, a page of places found
+    pub const DIR_PLACES_REPLY: u32 = 35;
+    /// DirFindQuery, a search for events
+    pub const DIR_FIND_QUERY: u32 = 31;
+    /// DirEventsReply, events found by DirFindQuery
+    pub const DIR_EVENTS_REPLY: u32 = 37;
+    /// DirClassifiedQuery, a search for classifieds
+    pub const DIR_CLASSIFIED_QUERY: u32 = 39;
+    /// DirClassifiedReply, classifieds found by DirClassifiedQuery
+    pub const DIR_CLASSIFIED_REPLY: u32 = 41;
+    /// EventInfoRequest, details of one event
+    pub const EVENT_INFO_REQUEST: u32 = 179;
+    /// EventInfoReply, the answer to EventInfoRequest
+    pub const EVENT_INFO_REPLY: u32 = 180;
+
+    // Classifieds
+    /// AvatarClassifiedReply, the classifieds an avatar lists
+    pub const AVATAR_CLASSIFIED_REPLY: u32 = 42;
+    /// ClassifiedInfoRequest, details of one classified
+    pub const CLASSIFIED_INFO_REQUEST: u32 = 43;
+    /// ClassifiedInfoReply, the answer to ClassifiedInfoRequest
+    pub const CLASSIFIED_INFO_REPLY: u32 = 44;
+    /// ClassifiedInfoUpdate, create or change a classified
+    pub const CLASSIFIED_INFO_UPDATE: u32 = 45;
+    /// ClassifiedDelete, remove a classified
+    pub const CLASSIFIED_DELETE: u32 = 46;
+    
+    // Profiles and picks
+    /// AvatarPropertiesRequest, ask for an avatar's profile
+    pub const AVATAR_PROPERTIES_REQUEST: u32 = 169;
+    /// AvatarPropertiesReply, an avatar's profile
+    pub const AVATAR_PROPERTIES_REPLY: u32 = 171;
+    /// AvatarPropertiesUpdate, change one's own profile
+    pub const AVATAR_PROPERTIES_UPDATE: u32 = 174;
+    /// AvatarPicksReply, the names of an avatar's picks
+    pub const AVATAR_PICKS_REPLY: u32 = 178;
+    /// PickInfoReply, the details of one pick
+    pub const PICK_INFO_REPLY: u32 = 184;
+    /// PickInfoUpdate, create or change a pick
+    pub const PICK_INFO_UPDATE: u32 = 185;
+    /// PickDelete, remove a pick
+    pub const PICK_DELETE: u32 = 186;
+    /// GenericMessage, a method call named in the message, such as `avatarpicksrequest`
+    pub const GENERIC_MESSAGE: u32 = 261;
+    /// CreateLandmarkForEvent, add a landmark to an event's location
+    pub const CREATE_LANDMARK_FOR_EVENT: u32 = 306;
+    /// UpdateCreateInventoryItem, tell the viewer about a new or changed item
+    pub const UPDATE_CREATE_INVENTORY_ITEM: u32 = 267;
+    
+    // Names
+    /// AvatarPickerRequest, a search for avatars by name
+    pub const AVATAR_PICKER_REQUEST: u32 = 26;
+    /// AvatarPickerReply, avatars found by AvatarPickerRequest
+    pub const AVATAR_PICKER_REPLY: u32 = 28;
+    
     // Map and teleport
     pub const MAP_BLOCK_REQUEST: u32 = 86;
     pub const MAP_BLOCK_REPLY: u32 = 153;
@@ -101,7 +219,11 @@ pub mod packet_types {
     pub const TELEPORT_PROGRESS: u32 = 73;
     pub const TELEPORT_FINISH: u32 = 65;
     pub const TELEPORT_LOCAL: u32 = 74;
+    /// TeleportFailed, tell the viewer why a teleport did not happen
+    pub const TELEPORT_FAILED: u32 = 75;
     pub const TELEPORT_LANDMARK_REQUEST: u32 = 84;
+    /// SetStartLocationRequest, set home or the last location
+    pub const SET_START_LOCATION_REQUEST: u32 = 324;
     
     // Avatar appearance
     pub const AVATAR_APPEARANCE: u32 = 158;
@@ -114,6 +236,10 @@ pub mod packet_types {
     pub const SCRIPT_CONTROL_CHANGE: u32 = 103;
     pub const SCRIPT_DIALOG: u32 = 104;
     pub const SCRIPT_DIALOG_REPLY: u32 = 105;
+    /// AlertMessage, a notice from the simulator
+    pub const ALERT_MESSAGE: u32 = 134;
+    /// AgentAlertMessage, a notice for one agent
+    pub const AGENT_ALERT_MESSAGE: u32 = 135;
     pub const LOAD_URL: u32 = 203;
     
     // Voice
@@ -140,6 +266,16 @@ pub mod packet_types {
     pub const MEDIA_DATA_REPLY: u32 = 481;
     pub const STREAMING_AUDIO_CONFIG: u32 = 482;
     
+    // Sound
+    /// SoundTrigger, play a sound once at a point
+    pub const SOUND_TRIGGER: u32 = 61;
+    /// AttachedSound, play a sound from a prim
+    pub const ATTACHED_SOUND: u32 = 62;
+    /// AttachedSoundGainChange, change a prim's sound volume
+    pub const ATTACHED_SOUND_GAIN_CHANGE: u32 = 63;
+    /// PreloadSound, fetch a sound ahead of playing it
+    pub const PRELOAD_SOUND: u32 = 64;
+    
     // Physics and collision
     pub const COLLISION_SOUND_TRIGGER: u32 = 490;
     pub const ATTACH_SOUND_TRIGGER: u32 = 491;
@@ -170,6 +306,8 @@ pub mod packet_types {
     pub const AGENT_MOVEMENT_COMPLETE: u32 = 249;
     pub const IMAGE_NOT_IN_DATABASE: u32 = 29;
     pub const INVENTORY_DESCENDENTS: u32 = 30;
+    /// SimulatorViewerTimeMessage, the sun position and region clock
+    pub const SIMULATOR_VIEWER_TIME: u32 = 150;
 }
 
 /// Chat types
@@ -242,6 +380,14 @@ pub mod control_flags {
     pub const ML_LBUTTON_UP: u32 = 1 << 31;
 }
 
+/// Prim flags sent in object updates
+pub mod prim_flags {
+    /// Collides with nothing
+    pub const PHANTOM: u32 = 0x400;
+    /// Removed a minute or so after being rezzed
+    pub const TEMPORARY_ON_REZ: u32 = 1 << 29;
+}
+
 /// Object update types
 pub mod object_update_types {
     pub const OUT_FULL: u8 = 0;
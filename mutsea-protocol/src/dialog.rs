@@ -0,0 +1,224 @@
+//! Script dialogs and alerts
+//!
+//! ScriptDialog shows a blue menu of buttons; the button pressed comes back
+//! as ScriptDialogReply with the dialog's object ID and chat channel.
+//! AlertMessage shows a notification to everyone in the region and
+//! AgentAlertMessage one to a single agent, optionally as a modal box.
+
+use crate::constants::packet_types;
+use crate::instant_message::{push_variable1, push_variable2};
+use crate::{ProtocolError, ProtocolResult};
+use mutsea_core::UserId;
+use uuid::Uuid;
+
+/// Most buttons a viewer shows on a dialog
+pub const MAX_BUTTONS: usize = 12;
+
+/// Longest button label viewers accept, in bytes
+pub const MAX_BUTTON_LABEL: usize = 24;
+
+/// Longest dialog message viewers show, in bytes
+pub const MAX_MESSAGE: usize = 512;
+
+/// A menu of buttons shown to one agent
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptDialog {
+    /// Identifies the dialog; the reply carries it back
+    pub object_id: Uuid,
+    /// Owner shown as the dialog's sender
+    pub first_name: String,
+    /// Owner's last name
+    pub last_name: String,
+    /// Name of the object showing the dialog
+    pub object_name: String,
+    /// Text above the buttons, at most [`MAX_MESSAGE`] bytes
+    pub message: String,
+    /// Channel the reply is said on
+    pub chat_channel: i32,
+    /// Texture shown in the dialog, nil for none
+    pub image_id: Uuid,
+    /// Button labels, in order
+    pub buttons: Vec<String>,
+    /// Owner of the object
+    pub owner_id: UserId,
+}
+
+impl ScriptDialog {
+    /// Check the dialog fits what viewers show
+    pub fn validate(&self) -> ProtocolResult<()> {
+        if self.buttons.is_empty() || self.buttons.len() > MAX_BUTTONS {
+            return Err(ProtocolError::InvalidMessage(format!(
+                "A dialog needs 1 to {} buttons, got {}",
+                MAX_BUTTONS,
+                self.buttons.len()
+            )));
+        }
+        if let Some(label) = self.buttons.iter().find(|label| label.trim().is_empty() || label.len() > MAX_BUTTON_LABEL) {
+            return Err(ProtocolError::InvalidMessage(format!(
+                "Button labels must be 1 to {} bytes, got \"{}\"",
+                MAX_BUTTON_LABEL, label
+            )));
+        }
+        if self.message.len() > MAX_MESSAGE {
+            return Err(ProtocolError::InvalidMessage(format!(
+                "Dialog messages are at most {} bytes",
+                MAX_MESSAGE
+            )));
+        }
+        Ok(())
+    }
+
+    /// Encode the ScriptDialog payload, starting with the message ID
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        payload.push(packet_types::SCRIPT_DIALOG as u8);
+
+        // Data block
+        payload.extend_from_slice(self.object_id.as_bytes());
+        push_variable1(&mut payload, &self.first_name);
+        push_variable1(&mut payload, &self.last_name);
+        push_variable1(&mut payload, &self.object_name);
+        push_variable2(&mut payload, &self.message);
+        payload.extend_from_slice(&self.chat_channel.to_le_bytes());
+        payload.extend_from_slice(self.image_id.as_bytes());
+
+        // Buttons block (variable)
+        payload.push(self.buttons.len() as u8);
+        for label in &self.buttons {
+            push_variable1(&mut payload, label);
+        }
+
+        // OwnerData block (variable)
+        payload.push(1);
+        payload.extend_from_slice(self.owner_id.as_uuid().as_bytes());
+
+        payload
+    }
+}
+
+/// The button an agent pressed on a dialog
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptDialogReply {
+    /// Agent pressing the button
+    pub agent_id: UserId,
+    /// The dialog's object ID
+    pub object_id: Uuid,
+    /// Channel the reply is said on
+    pub chat_channel: i32,
+    /// Position of the button, from 0
+    pub button_index: i32,
+    /// Text of the button
+    pub button_label: String,
+}
+
+impl ScriptDialogReply {
+    /// Parse a ScriptDialogReply payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let short = || ProtocolError::Decoding("ScriptDialogReply is too short".to_string());
+        // AgentID, SessionID, ObjectID, ChatChannel, ButtonIndex
+        let fixed = 16 + 16 + 16 + 4 + 4;
+        if payload.len() < fixed + 1 {
+            return Err(short());
+        }
+        let uuid_at = |offset: usize| Uuid::from_slice(&payload[offset..offset + 16]).map_err(|_| short());
+        let i32_at = |offset: usize| i32::from_le_bytes(payload[offset..offset + 4].try_into().unwrap());
+
+        let label_len = payload[fixed] as usize;
+        let label = payload.get(fixed + 1..fixed + 1 + label_len).ok_or_else(short)?;
+        let label = label.strip_suffix(&[0]).unwrap_or(label);
+
+        Ok(Self {
+            agent_id: UserId::from_uuid(uuid_at(0)?),
+            object_id: uuid_at(32)?,
+            chat_channel: i32_at(48),
+            button_index: i32_at(52),
+            button_label: String::from_utf8_lossy(label).into_owned(),
+        })
+    }
+}
+
+/// Notification shown to everyone in the region
+pub fn encode_alert(message: &str) -> Vec<u8> {
+    let mut payload = vec![packet_types::ALERT_MESSAGE as u8];
+    // AlertData block
+    push_variable1(&mut payload, message);
+    // AlertInfo and AgentInfo blocks (variable, unused)
+    payload.push(0);
+    payload.push(0);
+    payload
+}
+
+/// Notification shown to `agent_id`; modal alerts must be dismissed
+pub fn encode_agent_alert(agent_id: UserId, modal: bool, message: &str) -> Vec<u8> {
+    let mut payload = vec![packet_types::AGENT_ALERT_MESSAGE as u8];
+    // AgentData block
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    // AlertData block
+    payload.push(modal as u8);
+    push_variable1(&mut payload, message);
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dialog_layout_and_validation() {
+        let mut dialog = ScriptDialog {
+            object_id: Uuid::new_v4(),
+            first_name: "Mutsea".to_string(),
+            last_name: "Grid".to_string(),
+            object_name: "Narrator".to_string(),
+            message: "Accept the quest?".to_string(),
+            chat_channel: -4242,
+            image_id: Uuid::nil(),
+            buttons: vec!["Accept".to_string(), "Decline".to_string()],
+            owner_id: UserId::new(),
+        };
+        assert!(dialog.validate().is_ok());
+
+        let payload = dialog.encode();
+        assert_eq!(payload[0], packet_types::SCRIPT_DIALOG as u8);
+        assert_eq!(&payload[1..17], dialog.object_id.as_bytes());
+        // Names are null-terminated with a 1-byte length
+        assert_eq!(&payload[17..25], b"\x07Mutsea\0");
+        let channel = 17 + 8 + 6 + 10 + 2 + 18;
+        assert_eq!(i32::from_le_bytes(payload[channel..channel + 4].try_into().unwrap()), -4242);
+        let buttons = channel + 4 + 16;
+        assert_eq!(payload[buttons], 2);
+        assert_eq!(&payload[buttons + 1..buttons + 9], b"\x07Accept\0");
+        assert_eq!(payload.len(), buttons + 1 + 8 + 9 + 1 + 16);
+
+        dialog.buttons.push("A label far too long for a button".to_string());
+        assert!(dialog.validate().is_err());
+        dialog.buttons = Vec::new();
+        assert!(dialog.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let agent_id = UserId::new();
+        let object_id = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(object_id.as_bytes());
+        payload.extend_from_slice(&(-4242i32).to_le_bytes());
+        payload.extend_from_slice(&1i32.to_le_bytes());
+        payload.extend_from_slice(b"\x08Decline\0");
+
+        let reply = ScriptDialogReply::parse(&payload).unwrap();
+        assert_eq!(
+            reply,
+            ScriptDialogReply {
+                agent_id,
+                object_id,
+                chat_channel: -4242,
+                button_index: 1,
+                button_label: "Decline".to_string(),
+            }
+        );
+        assert!(ScriptDialogReply::parse(&payload[..60]).is_err());
+    }
+}
@@ -0,0 +1,555 @@
+//! Classifieds and events directory
+//!
+//! The Classifieds tab of the search floater sends DirClassifiedQuery,
+//! answered by DirClassifiedReply, and the Events tab sends DirFindQuery
+//! with [`DFQ_EVENTS`], answered by DirEventsReply. One classified or
+//! event is shown with ClassifiedInfoRequest or EventInfoRequest, answered
+//! by ClassifiedInfoReply or EventInfoReply.
+//!
+//! Agents place and edit their classifieds from their profile with
+//! ClassifiedInfoUpdate and remove them with ClassifiedDelete; the
+//! GenericMessage method `avatarclassifiedsrequest` lists an avatar's
+//! classifieds, answered by AvatarClassifiedReply.
+
+use crate::codec::MessageDecoder;
+use crate::constants::packet_types;
+use crate::instant_message::{push_variable1, push_variable2};
+use crate::ProtocolResult;
+use chrono::{DateTime, Utc};
+use mutsea_core::{Maturity, UserId};
+use uuid::Uuid;
+
+/// The GenericMessage method listing an avatar's classifieds
+pub const METHOD_AVATAR_CLASSIFIEDS_REQUEST: &str = "avatarclassifiedsrequest";
+
+/// DirFindQuery flag: search events
+pub const DFQ_EVENTS: u32 = 1 << 3;
+
+/// DirClassifiedQuery flag: include General (PG) classifieds
+pub const CLASSIFIED_QUERY_INC_PG: u32 = 1 << 2;
+/// DirClassifiedQuery flag: include Moderate (Mature) classifieds
+pub const CLASSIFIED_QUERY_INC_MATURE: u32 = 1 << 3;
+/// DirClassifiedQuery flag: include Adult classifieds
+pub const CLASSIFIED_QUERY_INC_ADULT: u32 = 1 << 6;
+
+/// Classified flag: the classified has mature content
+pub const CLASSIFIED_FLAG_MATURE: u8 = 1 << 1;
+/// Classified flag: list the classified again when it expires
+pub const CLASSIFIED_FLAG_AUTO_RENEW: u8 = 1 << 5;
+
+/// Event flag: the event has mature content
+pub const EVENT_FLAG_MATURE: u32 = 1 << 1;
+/// Event flag: the event has adult content
+pub const EVENT_FLAG_ADULT: u32 = 1 << 2;
+
+/// Results in each page of a classified or event search
+pub const RESULTS_PER_PAGE: usize = 100;
+
+/// A classified ad placed by an avatar
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classified {
+    /// Classified identifier
+    pub classified_id: Uuid,
+    /// Avatar who placed the classified
+    pub creator_id: UserId,
+    /// Unix time
+    pub creation_date: u32,
+    /// Unix time after which the classified is no longer listed
+    pub expiration_date: u32,
+    /// Classified category
+    pub category: u32,
+    /// Title
+    pub name: String,
+    /// Body text
+    pub description: String,
+    /// Parcel the classified points to
+    pub parcel_id: Uuid,
+    /// Estate of that parcel
+    pub parent_estate: u32,
+    /// Texture shown with the classified
+    pub snapshot_id: Uuid,
+    /// Region the classified points to
+    pub sim_name: String,
+    /// Global position, in metres from the grid's origin
+    pub pos_global: [f64; 3],
+    /// Name of the parcel
+    pub parcel_name: String,
+    /// `CLASSIFIED_FLAG_*` bits
+    pub flags: u8,
+    /// What was paid to list the classified, which orders the listing
+    pub price_for_listing: i32,
+}
+
+impl Classified {
+    /// The content rating of the classified
+    pub fn maturity(&self) -> Maturity {
+        if self.flags & CLASSIFIED_FLAG_MATURE != 0 {
+            Maturity::Mature
+        } else {
+            Maturity::Pg
+        }
+    }
+
+    /// Whether the classified is listed again when it expires
+    pub fn auto_renew(&self) -> bool {
+        self.flags & CLASSIFIED_FLAG_AUTO_RENEW != 0
+    }
+}
+
+/// An event listed in the directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Event identifier
+    pub event_id: u32,
+    /// Avatar hosting the event
+    pub owner_id: UserId,
+    /// Event name
+    pub name: String,
+    /// One of the viewer's event categories, see [`event_category_name`]
+    pub category: u32,
+    /// Event description
+    pub description: String,
+    /// Unix time the event starts
+    pub date_utc: u32,
+    /// Minutes
+    pub duration: u32,
+    /// L$ charged at the door
+    pub cover_charge: u32,
+    /// Region the event is held in
+    pub sim_name: String,
+    /// Parcel the event is held on
+    pub parcel_id: Uuid,
+    /// Global position, in metres from the grid's origin
+    pub pos_global: [f64; 3],
+    /// `EVENT_FLAG_*` bits
+    pub flags: u32,
+}
+
+impl Event {
+    /// The content rating of the event
+    pub fn maturity(&self) -> Maturity {
+        if self.flags & EVENT_FLAG_ADULT != 0 {
+            Maturity::Adult
+        } else if self.flags & EVENT_FLAG_MATURE != 0 {
+            Maturity::Mature
+        } else {
+            Maturity::Pg
+        }
+    }
+
+    /// Unix time the event ends
+    pub fn end_utc(&self) -> u32 {
+        self.date_utc.saturating_add(self.duration.saturating_mul(60))
+    }
+}
+
+/// The name the viewer gives an event category
+pub fn event_category_name(category: u32) -> &'static str {
+    match category {
+        18 => "Discussion",
+        19 => "Sports",
+        20 => "Live Music",
+        22 => "Commercial",
+        23 => "Nightlife/Entertainment",
+        24 => "Games/Contests",
+        25 => "Pageants",
+        26 => "Education",
+        27 => "Arts and Culture",
+        28 => "Charity/Support Groups",
+        _ => "Miscellaneous",
+    }
+}
+
+/// A search of the classifieds
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirClassifiedQuery {
+    /// Agent searching
+    pub agent_id: UserId,
+    /// Identifier the reply must carry
+    pub query_id: Uuid,
+    /// Search text
+    pub text: String,
+    /// `CLASSIFIED_QUERY_INC_*` bits naming the content ratings to include
+    pub flags: u32,
+    /// 0 for any category
+    pub category: u32,
+    /// Index of the first result wanted
+    pub start: i32,
+}
+
+impl DirClassifiedQuery {
+    /// Parse a DirClassifiedQuery payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            query_id: decoder.read_uuid()?,
+            text: decoder.read_variable1()?,
+            flags: decoder.read_u32()?,
+            category: decoder.read_u32()?,
+            start: decoder.read_i32()?,
+        })
+    }
+
+    /// Whether classifieds rated `maturity` were asked for. Queries that
+    /// name no rating include them all.
+    pub fn includes(&self, maturity: Maturity) -> bool {
+        let all = CLASSIFIED_QUERY_INC_PG | CLASSIFIED_QUERY_INC_MATURE | CLASSIFIED_QUERY_INC_ADULT;
+        if self.flags & all == 0 {
+            return true;
+        }
+        let flag = match maturity {
+            Maturity::Pg => CLASSIFIED_QUERY_INC_PG,
+            Maturity::Mature => CLASSIFIED_QUERY_INC_MATURE,
+            Maturity::Adult => CLASSIFIED_QUERY_INC_ADULT,
+        };
+        self.flags & flag != 0
+    }
+}
+
+/// A search of the directory by kind. Only event searches, flagged
+/// [`DFQ_EVENTS`], are answered here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirFindQuery {
+    /// Agent searching
+    pub agent_id: UserId,
+    /// Identifier the reply must carry
+    pub query_id: Uuid,
+    /// Search text; for events, see [`EventSearch`]
+    pub text: String,
+    /// `DFQ_*` bits naming what to search and the content ratings to include
+    pub flags: u32,
+    /// Index of the first result wanted
+    pub start: i32,
+}
+
+impl DirFindQuery {
+    /// Parse a DirFindQuery payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            query_id: decoder.read_uuid()?,
+            text: decoder.read_variable1()?,
+            flags: decoder.read_u32()?,
+            start: decoder.read_i32()?,
+        })
+    }
+}
+
+/// What an event search asks for. The viewer packs it in the query text
+/// as `day|category|text`, the day being `u` for events under way or to
+/// come, or a number of days from today.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventSearch {
+    /// `None` for events under way or to come
+    pub day: Option<i32>,
+    /// 0 for any category
+    pub category: u32,
+    /// Search text, matched against event names and descriptions
+    pub text: String,
+}
+
+impl EventSearch {
+    /// Unpack the query text of an event search
+    pub fn parse(query_text: &str) -> Self {
+        let mut parts = query_text.splitn(3, '|');
+        let day = parts.next().unwrap_or_default().trim();
+        let category = parts.next().unwrap_or_default().trim();
+        Self {
+            day: day.parse().ok(),
+            category: category.parse().unwrap_or(0),
+            text: parts.next().unwrap_or_default().trim().to_string(),
+        }
+    }
+}
+
+/// A request for one classified
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassifiedInfoRequest {
+    /// Agent asking
+    pub agent_id: UserId,
+    /// Classified wanted
+    pub classified_id: Uuid,
+}
+
+impl ClassifiedInfoRequest {
+    /// Parse a ClassifiedInfoRequest payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            classified_id: decoder.read_uuid()?,
+        })
+    }
+}
+
+/// An agent placing or editing one of its classifieds. Dates, creator,
+/// region and parcel names are not sent: the creator is the agent and the
+/// rest are left empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassifiedInfoUpdate {
+    /// Agent placing the classified
+    pub agent_id: UserId,
+    /// The classified as sent
+    pub classified: Classified,
+}
+
+impl ClassifiedInfoUpdate {
+    /// Parse a ClassifiedInfoUpdate payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            classified: Classified {
+                classified_id: decoder.read_uuid()?,
+                creator_id: agent_id,
+                creation_date: 0,
+                expiration_date: 0,
+                category: decoder.read_u32()?,
+                name: decoder.read_variable1()?,
+                description: decoder.read_variable2()?,
+                parcel_id: decoder.read_uuid()?,
+                parent_estate: decoder.read_u32()?,
+                snapshot_id: decoder.read_uuid()?,
+                sim_name: String::new(),
+                pos_global: [decoder.read_f64()?, decoder.read_f64()?, decoder.read_f64()?],
+                parcel_name: String::new(),
+                flags: decoder.read_u8()?,
+                price_for_listing: decoder.read_i32()?,
+            },
+        })
+    }
+}
+
+/// An agent removing one of its classifieds
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassifiedDelete {
+    /// Agent removing the classified
+    pub agent_id: UserId,
+    /// Classified to remove
+    pub classified_id: Uuid,
+}
+
+impl ClassifiedDelete {
+    /// Parse a ClassifiedDelete payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            classified_id: decoder.read_uuid()?,
+        })
+    }
+}
+
+/// A request for one event
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventInfoRequest {
+    /// Agent asking
+    pub agent_id: UserId,
+    /// Event wanted
+    pub event_id: u32,
+}
+
+impl EventInfoRequest {
+    /// Parse an EventInfoRequest payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            event_id: decoder.read_u32()?,
+        })
+    }
+}
+
+/// Answer the classified search `query_id` of `agent_id` with `classifieds`
+pub fn encode_dir_classified_reply(agent_id: UserId, query_id: Uuid, classifieds: &[Classified]) -> Vec<u8> {
+    let mut payload = vec![packet_types::DIR_CLASSIFIED_REPLY as u8];
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    // QueryData block
+    payload.extend_from_slice(query_id.as_bytes());
+    // QueryReplies blocks
+    payload.push(classifieds.len().min(u8::MAX as usize) as u8);
+    for classified in classifieds.iter().take(u8::MAX as usize) {
+        payload.extend_from_slice(classified.classified_id.as_bytes());
+        push_variable1(&mut payload, &classified.name);
+        payload.push(classified.flags);
+        payload.extend_from_slice(&classified.creation_date.to_le_bytes());
+        payload.extend_from_slice(&classified.expiration_date.to_le_bytes());
+        payload.extend_from_slice(&classified.price_for_listing.to_le_bytes());
+    }
+    // StatusData blocks
+    payload.push(0);
+    payload
+}
+
+/// List the classifieds of `target_id` for `agent_id`, by ID and name
+pub fn encode_avatar_classified_reply(agent_id: UserId, target_id: UserId, classifieds: &[(Uuid, String)]) -> Vec<u8> {
+    let mut payload = vec![packet_types::AVATAR_CLASSIFIED_REPLY as u8];
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    payload.extend_from_slice(target_id.as_uuid().as_bytes());
+    payload.push(classifieds.len().min(u8::MAX as usize) as u8);
+    for (classified_id, name) in classifieds.iter().take(u8::MAX as usize) {
+        payload.extend_from_slice(classified_id.as_bytes());
+        push_variable1(&mut payload, name);
+    }
+    payload
+}
+
+/// Show `agent_id` one classified
+pub fn encode_classified_info_reply(agent_id: UserId, classified: &Classified) -> Vec<u8> {
+    let mut payload = vec![packet_types::CLASSIFIED_INFO_REPLY as u8];
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    payload.extend_from_slice(classified.classified_id.as_bytes());
+    payload.extend_from_slice(classified.creator_id.as_uuid().as_bytes());
+    payload.extend_from_slice(&classified.creation_date.to_le_bytes());
+    payload.extend_from_slice(&classified.expiration_date.to_le_bytes());
+    payload.extend_from_slice(&classified.category.to_le_bytes());
+    push_variable1(&mut payload, &classified.name);
+    push_variable2(&mut payload, &classified.description);
+    payload.extend_from_slice(classified.parcel_id.as_bytes());
+    payload.extend_from_slice(&classified.parent_estate.to_le_bytes());
+    payload.extend_from_slice(classified.snapshot_id.as_bytes());
+    push_variable1(&mut payload, &classified.sim_name);
+    for value in classified.pos_global {
+        payload.extend_from_slice(&value.to_le_bytes());
+    }
+    push_variable1(&mut payload, &classified.parcel_name);
+    payload.push(classified.flags);
+    payload.extend_from_slice(&classified.price_for_listing.to_le_bytes());
+    payload
+}
+
+/// Answer the event search `query_id` of `agent_id` with `events`
+pub fn encode_dir_events_reply(agent_id: UserId, query_id: Uuid, events: &[Event]) -> Vec<u8> {
+    let mut payload = vec![packet_types::DIR_EVENTS_REPLY as u8];
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    // QueryData block
+    payload.extend_from_slice(query_id.as_bytes());
+    // QueryReplies blocks
+    payload.push(events.len().min(u8::MAX as usize) as u8);
+    for event in events.iter().take(u8::MAX as usize) {
+        payload.extend_from_slice(event.owner_id.as_uuid().as_bytes());
+        push_variable1(&mut payload, &event.name);
+        payload.extend_from_slice(&event.event_id.to_le_bytes());
+        push_variable1(&mut payload, &event_date(event.date_utc));
+        payload.extend_from_slice(&event.date_utc.to_le_bytes());
+        payload.extend_from_slice(&event.flags.to_le_bytes());
+    }
+    // StatusData blocks
+    payload.push(0);
+    payload
+}
+
+/// Show `agent_id` one event
+pub fn encode_event_info_reply(agent_id: UserId, event: &Event) -> Vec<u8> {
+    let mut payload = vec![packet_types::EVENT_INFO_REPLY as u8];
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    payload.extend_from_slice(&event.event_id.to_le_bytes());
+    push_variable1(&mut payload, &event.owner_id.to_string());
+    push_variable1(&mut payload, &event.name);
+    push_variable1(&mut payload, event_category_name(event.category));
+    push_variable2(&mut payload, &event.description);
+    push_variable1(&mut payload, &event_date(event.date_utc));
+    payload.extend_from_slice(&event.date_utc.to_le_bytes());
+    payload.extend_from_slice(&event.duration.to_le_bytes());
+    payload.extend_from_slice(&event.cover_charge.to_le_bytes());
+    // Amount
+    payload.extend_from_slice(&event.cover_charge.to_le_bytes());
+    push_variable1(&mut payload, &event.sim_name);
+    for value in event.pos_global {
+        payload.extend_from_slice(&value.to_le_bytes());
+    }
+    payload.extend_from_slice(&event.flags.to_le_bytes());
+    payload
+}
+
+/// An event's start as the viewer shows it, e.g. 10/15/2026 08:00 PM
+fn event_date(date_utc: u32) -> String {
+    DateTime::<Utc>::from_timestamp(date_utc as i64, 0)
+        .map(|date| date.format("%m/%d/%Y %I:%M %p").to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent_data(agent_id: UserId) -> Vec<u8> {
+        let mut payload = agent_id.as_uuid().as_bytes().to_vec();
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload
+    }
+
+    #[test]
+    fn test_parse_classified_update_and_encode_reply() {
+        let agent_id = UserId::new();
+        let classified_id = Uuid::new_v4();
+        let parcel_id = Uuid::new_v4();
+        let mut payload = agent_data(agent_id);
+        payload.extend_from_slice(classified_id.as_bytes());
+        payload.extend_from_slice(&3u32.to_le_bytes());
+        push_variable1(&mut payload, "Beach huts");
+        push_variable2(&mut payload, "Rent one today");
+        payload.extend_from_slice(parcel_id.as_bytes());
+        payload.extend_from_slice(&1u32.to_le_bytes());
+        payload.extend_from_slice(Uuid::nil().as_bytes());
+        for value in [256128.0f64, 256064.0, 22.0] {
+            payload.extend_from_slice(&value.to_le_bytes());
+        }
+        payload.push(CLASSIFIED_FLAG_MATURE | CLASSIFIED_FLAG_AUTO_RENEW);
+        payload.extend_from_slice(&50i32.to_le_bytes());
+
+        let update = ClassifiedInfoUpdate::parse(&payload).unwrap();
+        let classified = &update.classified;
+        assert_eq!(
+            (classified.classified_id, classified.creator_id),
+            (classified_id, agent_id)
+        );
+        assert_eq!((classified.category, classified.parcel_id), (3, parcel_id));
+        assert_eq!(classified.name, "Beach huts");
+        assert_eq!(classified.description, "Rent one today");
+        assert_eq!(classified.pos_global, [256128.0, 256064.0, 22.0]);
+        assert_eq!(classified.price_for_listing, 50);
+        assert_eq!(classified.maturity(), Maturity::Mature);
+        assert!(classified.auto_renew());
+        assert!(ClassifiedInfoUpdate::parse(&payload[..payload.len() - 1]).is_err());
+
+        let reply = encode_classified_info_reply(agent_id, classified);
+        assert_eq!(
+            reply.len(),
+            1 + 16 * 3 + 12 + 12 + 17 + 16 + 4 + 16 + 2 + 24 + 2 + 1 + 4
+        );
+    }
+
+    #[test]
+    fn test_parse_event_search() {
+        let agent_id = UserId::new();
+        let query_id = Uuid::new_v4();
+        let mut payload = agent_data(agent_id);
+        payload.extend_from_slice(query_id.as_bytes());
+        push_variable1(&mut payload, "u|20|jazz");
+        payload.extend_from_slice(&DFQ_EVENTS.to_le_bytes());
+        payload.extend_from_slice(&0i32.to_le_bytes());
+
+        let query = DirFindQuery::parse(&payload).unwrap();
+        assert_eq!((query.agent_id, query.query_id), (agent_id, query_id));
+        assert_eq!(query.flags, DFQ_EVENTS);
+        let search = EventSearch::parse(&query.text);
+        assert_eq!((search.day, search.category, search.text.as_str()), (None, 20, "jazz"));
+        assert_eq!(EventSearch::parse("-1|0|").day, Some(-1));
+        assert_eq!(event_date(0), "01/01/1970 12:00 AM");
+    }
+}
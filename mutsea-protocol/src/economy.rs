@@ -0,0 +1,339 @@
+//! Sales of prims and payments
+//!
+//! ObjectSaleInfo puts prims up for sale as the original, as a copy or for
+//! their contents, and ObjectBuy asks to buy them at the type and price the
+//! viewer was shown. MoneyTransferRequest pays an avatar or a prim, and
+//! MoneyBalanceRequest asks for the agent's balance, which comes back as
+//! MoneyBalanceReply after every transaction too.
+
+use crate::codec::MessageDecoder;
+use crate::constants::packet_types;
+use crate::instant_message::push_variable1;
+use crate::{ProtocolError, ProtocolResult};
+use mutsea_core::UserId;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Transaction type of a prim bought
+pub const TRANSACTION_OBJECT_SALE: i32 = 5000;
+
+/// Transaction type of a payment to an avatar
+pub const TRANSACTION_GIFT: i32 = 5001;
+
+/// Transaction type of the price of listing a classified
+pub const TRANSACTION_CLASSIFIED_CHARGE: i32 = 1103;
+
+/// Transaction type of a fee for listing in the directory
+pub const TRANSACTION_PARCEL_DIR_FEE: i32 = 2003;
+
+/// Transaction type of the price of renewing a classified
+pub const TRANSACTION_CLASSIFIED_RENEW: i32 = 2005;
+
+/// Transaction type of a payment to a prim
+pub const TRANSACTION_PAY_OBJECT: i32 = 5008;
+
+/// How a prim is sold
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SaleType {
+    /// Not for sale
+    Not = 0,
+    /// The buyer becomes the prim's owner
+    Original = 1,
+    /// The buyer is given a copy
+    Copy = 2,
+    /// The buyer is given what is inside
+    Contents = 3,
+}
+
+impl SaleType {
+    /// Sale type from its wire value
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Not),
+            1 => Some(Self::Original),
+            2 => Some(Self::Copy),
+            3 => Some(Self::Contents),
+            _ => None,
+        }
+    }
+}
+
+/// The sale of one prim, by its local ID
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObjectSale {
+    /// Prim sold
+    pub local_id: u32,
+    /// How it is sold
+    pub sale_type: SaleType,
+    /// Asking price
+    pub price: i32,
+}
+
+/// Prims an agent put up for sale or took off sale
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectSaleInfo {
+    /// Agent setting the sales
+    pub agent_id: UserId,
+    /// Sales of each prim
+    pub objects: Vec<ObjectSale>,
+}
+
+impl ObjectSaleInfo {
+    /// Parse an ObjectSaleInfo payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            objects: parse_sales(&mut decoder)?,
+        })
+    }
+}
+
+/// Prims an agent asked to buy, each at the type and price it was shown
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectBuy {
+    /// Buying agent
+    pub agent_id: UserId,
+    /// Group the purchase is made for, if any
+    pub group_id: Option<Uuid>,
+    /// Inventory folder bought copies and contents go in
+    pub category_id: Uuid,
+    /// Prims bought, with the sale each was shown
+    pub objects: Vec<ObjectSale>,
+}
+
+impl ObjectBuy {
+    /// Parse an ObjectBuy payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        let group_id = decoder.read_uuid()?;
+        Ok(Self {
+            agent_id,
+            group_id: (!group_id.is_nil()).then_some(group_id),
+            category_id: decoder.read_uuid()?,
+            objects: parse_sales(&mut decoder)?,
+        })
+    }
+}
+
+/// A payment an agent asked to make
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoneyTransferRequest {
+    /// Paying agent
+    pub agent_id: UserId,
+    /// Who the money comes from, normally the agent
+    pub source_id: Uuid,
+    /// The avatar or prim paid
+    pub dest_id: Uuid,
+    /// Transaction flags
+    pub flags: u8,
+    /// Amount to pay
+    pub amount: i32,
+    /// Viewer's transaction type, such as `TRANSACTION_PAY_OBJECT`
+    pub transaction_type: i32,
+    /// Description shown in the transaction history
+    pub description: String,
+}
+
+impl MoneyTransferRequest {
+    /// Parse a MoneyTransferRequest payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        let source_id = decoder.read_uuid()?;
+        let dest_id = decoder.read_uuid()?;
+        let flags = decoder.read_u8()?;
+        let amount = decoder.read_i32()?;
+        decoder.skip(2)?; // AggregatePermNextOwner, AggregatePermInventory
+        Ok(Self {
+            agent_id,
+            source_id,
+            dest_id,
+            flags,
+            amount,
+            transaction_type: decoder.read_i32()?,
+            description: decoder.read_variable1()?,
+        })
+    }
+}
+
+/// An agent's request for its balance
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoneyBalanceRequest {
+    /// Agent asking
+    pub agent_id: UserId,
+    /// Echoed back in the balance reply
+    pub transaction_id: Uuid,
+}
+
+impl MoneyBalanceRequest {
+    /// Parse a MoneyBalanceRequest payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            transaction_id: decoder.read_uuid()?,
+        })
+    }
+}
+
+/// Tell `agent_id` its balance, and whether the transaction
+/// `transaction_id` described by `description` went through
+pub fn encode_money_balance_reply(
+    agent_id: UserId,
+    transaction_id: Uuid,
+    success: bool,
+    balance: i32,
+    description: &str,
+) -> Vec<u8> {
+    let mut payload = vec![packet_types::MONEY_BALANCE_REPLY as u8];
+    // MoneyData block
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    payload.extend_from_slice(transaction_id.as_bytes());
+    payload.push(success as u8);
+    payload.extend_from_slice(&balance.to_le_bytes());
+    payload.extend_from_slice(&0i32.to_le_bytes()); // SquareMetersCredit
+    payload.extend_from_slice(&0i32.to_le_bytes()); // SquareMetersCommitted
+    push_variable1(&mut payload, description);
+    payload
+}
+
+/// The variable ObjectData block: LocalID, SaleType, SalePrice
+fn parse_sales(decoder: &mut MessageDecoder) -> ProtocolResult<Vec<ObjectSale>> {
+    let count = decoder.read_u8()?;
+    (0..count)
+        .map(|_| {
+            let local_id = decoder.read_u32()?;
+            let sale_type = decoder.read_u8()?;
+            let sale_type = SaleType::from_u8(sale_type)
+                .ok_or_else(|| ProtocolError::Decoding(format!("Unknown sale type {}", sale_type)))?;
+            Ok(ObjectSale {
+                local_id,
+                sale_type,
+                price: decoder.read_i32()?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::Packet;
+
+    fn push_sale(payload: &mut Vec<u8>, local_id: u32, sale_type: u8, price: i32) {
+        payload.extend_from_slice(&local_id.to_le_bytes());
+        payload.push(sale_type);
+        payload.extend_from_slice(&price.to_le_bytes());
+    }
+
+    #[test]
+    fn test_parse_sale_info_and_buy() {
+        let agent_id = UserId::new();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.push(2);
+        push_sale(&mut payload, 7, 2, 25);
+        push_sale(&mut payload, 8, 0, 0);
+
+        let info = ObjectSaleInfo::parse(&payload).unwrap();
+        assert_eq!(info.agent_id, agent_id);
+        assert_eq!(
+            info.objects,
+            vec![
+                ObjectSale {
+                    local_id: 7,
+                    sale_type: SaleType::Copy,
+                    price: 25,
+                },
+                ObjectSale {
+                    local_id: 8,
+                    sale_type: SaleType::Not,
+                    price: 0,
+                },
+            ]
+        );
+        assert!(ObjectSaleInfo::parse(&payload[..payload.len() - 1]).is_err());
+
+        let category_id = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(Uuid::nil().as_bytes());
+        payload.extend_from_slice(category_id.as_bytes());
+        payload.push(1);
+        push_sale(&mut payload, 7, 1, 100);
+
+        let buy = ObjectBuy::parse(&payload).unwrap();
+        assert_eq!(buy.group_id, None);
+        assert_eq!(buy.category_id, category_id);
+        assert_eq!(buy.objects[0].sale_type, SaleType::Original);
+        assert_eq!(buy.objects[0].price, 100);
+
+        let last = payload.len() - 5;
+        payload[last] = 9;
+        assert!(ObjectBuy::parse(&payload).is_err());
+    }
+
+    #[test]
+    fn test_parse_transfer_and_encode_balance() {
+        let agent_id = UserId::new();
+        let dest_id = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(dest_id.as_bytes());
+        payload.push(0);
+        payload.extend_from_slice(&50i32.to_le_bytes());
+        payload.extend_from_slice(&[0, 0]);
+        payload.extend_from_slice(&TRANSACTION_PAY_OBJECT.to_le_bytes());
+        payload.extend_from_slice(b"\x05Tip!\0");
+
+        let request = MoneyTransferRequest::parse(&payload).unwrap();
+        assert_eq!(request.agent_id, agent_id);
+        assert_eq!(request.dest_id, dest_id);
+        assert_eq!(request.amount, 50);
+        assert_eq!(request.transaction_type, TRANSACTION_PAY_OBJECT);
+        assert_eq!(request.description, "Tip!");
+        assert!(MoneyTransferRequest::parse(&payload[..70]).is_err());
+
+        let transaction_id = Uuid::new_v4();
+        let reply = encode_money_balance_reply(agent_id, transaction_id, true, 950, "You paid Tip Jar L$50");
+        assert_eq!(reply[0], packet_types::MONEY_BALANCE_REPLY as u8);
+        assert_eq!(&reply[17..33], transaction_id.as_bytes());
+        assert_eq!(reply[33], 1);
+        assert_eq!(i32::from_le_bytes(reply[34..38].try_into().unwrap()), 950);
+        assert_eq!(reply[46] as usize, "You paid Tip Jar L$50".len() + 1);
+    }
+
+    #[test]
+    fn test_parse_balance_request_as_received() {
+        let agent_id = UserId::new();
+        let transaction_id = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(transaction_id.as_bytes());
+        let wire = Packet::reliable(1, payload)
+            .with_message_id(packet_types::MONEY_BALANCE_REQUEST)
+            .serialize()
+            .unwrap();
+
+        // The handler is given the payload after the message ID
+        let packet = Packet::deserialize(&wire).unwrap();
+        assert_eq!(packet.message_id, Some(packet_types::MONEY_BALANCE_REQUEST));
+        let request = MoneyBalanceRequest::parse(&packet.payload).unwrap();
+        assert_eq!(request.agent_id, agent_id);
+        assert_eq!(request.transaction_id, transaction_id);
+    }
+}
@@ -0,0 +1,371 @@
+//! Region environment: day cycle, sky and water
+//!
+//! Viewers place the sun from the SimulatorViewerTimeMessage and draw the
+//! sky and water from the Windlight settings served by the
+//! EnvironmentSettings capability. Wind and clouds arrive separately as
+//! LayerData (see [`crate::layer_data`]). Sky and water presets are derived
+//! from a weather condition, so simulated weather shows up in the viewer.
+
+use crate::constants::packet_types;
+use mutsea_core::Vector3;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::f32::consts::TAU;
+use uuid::Uuid;
+
+/// Seconds per simulated year reported to viewers
+const SECONDS_PER_YEAR: u32 = 365 * 24 * 3600;
+
+/// Default water normal map
+const WATER_NORMAL_MAP: &str = "822ded49-9a6c-f61c-cb89-6df54f42cdf4";
+
+/// Region time of day
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DayCycle {
+    /// Real seconds per region day
+    pub day_length_secs: u32,
+    /// Shifts the region clock, in real seconds
+    pub offset_secs: u32,
+}
+
+impl Default for DayCycle {
+    fn default() -> Self {
+        Self {
+            day_length_secs: 4 * 3600,
+            offset_secs: 0,
+        }
+    }
+}
+
+impl DayCycle {
+    /// Fraction of the day elapsed at `unix_secs`: 0.0 is midnight, 0.25
+    /// sunrise, 0.5 noon
+    pub fn phase(&self, unix_secs: u64) -> f32 {
+        let day = self.day_length_secs.max(1) as u64;
+        ((unix_secs + self.offset_secs as u64) % day) as f32 / day as f32
+    }
+
+    /// Unit vector towards the sun, rising in the east
+    pub fn sun_direction(phase: f32) -> Vector3 {
+        let angle = (phase - 0.25) * TAU;
+        Vector3::new(angle.cos(), 0.0, angle.sin())
+    }
+
+    /// Sun angular velocity in radians per second
+    pub fn sun_angular_velocity(&self) -> Vector3 {
+        Vector3::new(0.0, -TAU / self.day_length_secs.max(1) as f32, 0.0)
+    }
+
+    /// Encode the SimulatorViewerTimeMessage payload, starting with the
+    /// message ID
+    pub fn encode_viewer_time(&self, unix_secs: u64, usec_since_start: u64) -> Vec<u8> {
+        let phase = self.phase(unix_secs);
+        let mut payload = Vec::with_capacity(1 + 8 + 4 + 4 + 12 + 4 + 12);
+        payload.push(packet_types::SIMULATOR_VIEWER_TIME as u8);
+        payload.extend_from_slice(&usec_since_start.to_le_bytes());
+        payload.extend_from_slice(&self.day_length_secs.to_le_bytes());
+        payload.extend_from_slice(&SECONDS_PER_YEAR.to_le_bytes());
+        push_vector(&mut payload, Self::sun_direction(phase));
+        payload.extend_from_slice(&(phase * TAU).to_le_bytes());
+        push_vector(&mut payload, self.sun_angular_velocity());
+        payload
+    }
+}
+
+fn push_vector(payload: &mut Vec<u8>, v: Vector3) {
+    payload.extend_from_slice(&v.x.to_le_bytes());
+    payload.extend_from_slice(&v.y.to_le_bytes());
+    payload.extend_from_slice(&v.z.to_le_bytes());
+}
+
+/// What the sky looks like, before time of day is applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeatherLook {
+    /// Weather condition: clear, cloudy, overcast, rain, snow or storm
+    pub condition: &'static str,
+    /// 0.0 to 1.0
+    pub cloud_cover: f32,
+    /// Metres per second, east and north
+    pub wind: (f32, f32),
+}
+
+impl Default for WeatherLook {
+    fn default() -> Self {
+        Self {
+            condition: "clear",
+            cloud_cover: 0.2,
+            wind: (0.0, 0.0),
+        }
+    }
+}
+
+impl WeatherLook {
+    /// Sunlight and sky brightness, haze density and cloud brightness of
+    /// the condition
+    fn tone(&self) -> (f32, f32, f32) {
+        match self.condition {
+            "cloudy" => (0.85, 0.9, 0.8),
+            "overcast" => (0.6, 1.4, 0.6),
+            "rain" => (0.5, 2.0, 0.45),
+            "snow" => (0.75, 2.5, 0.9),
+            "storm" => (0.3, 3.0, 0.25),
+            _ => (1.0, 0.7, 0.9),
+        }
+    }
+}
+
+/// Legacy Windlight sky settings, field names as viewers expect them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkySettings {
+    /// Ambient light colour
+    pub ambient: [f32; 4],
+    /// Sky colour overhead
+    pub blue_density: [f32; 4],
+    /// Sky colour at the horizon
+    pub blue_horizon: [f32; 4],
+    /// Cloud colour
+    pub cloud_color: [f32; 4],
+    /// Offset and density of the first cloud layer
+    pub cloud_pos_density1: [f32; 4],
+    /// Offset and density of the second cloud layer
+    pub cloud_pos_density2: [f32; 4],
+    /// Cloud size
+    pub cloud_scale: [f32; 4],
+    /// Cloud drift in X and Y; 10 is still
+    pub cloud_scroll_rate: [f32; 2],
+    /// How much clouds shade the ground
+    pub cloud_shadow: [f32; 4],
+    /// Overall haze and fog density
+    pub density_multiplier: [f32; 4],
+    /// How fast the view fades with distance
+    pub distance_multiplier: [f32; 4],
+    /// Rotation of the sun's path, in radians
+    pub east_angle: f32,
+    /// Whether clouds drift in X and Y
+    pub enable_cloud_scroll: [bool; 2],
+    /// Scene gamma
+    pub gamma: [f32; 4],
+    /// Size and focus of the sun's glow
+    pub glow: [f32; 4],
+    /// Haze density
+    pub haze_density: [f32; 4],
+    /// Haze at the horizon
+    pub haze_horizon: [f32; 4],
+    /// Direction of the sun or moon
+    pub lightnorm: [f32; 4],
+    /// Altitude of the top of the haze
+    pub max_y: [f32; 4],
+    /// Windlight preset number
+    pub preset_num: i32,
+    /// Star brightness, 0 to 2
+    pub star_brightness: f32,
+    /// Sun elevation, in radians
+    pub sun_angle: f32,
+    /// Sun and moon light colour
+    pub sunlight_color: [f32; 4],
+}
+
+impl SkySettings {
+    /// Sky for `look` at day `phase`
+    pub fn new(look: &WeatherLook, phase: f32) -> Self {
+        let (light, haze, cloud) = look.tone();
+        let sun = DayCycle::sun_direction(phase);
+        // Full daylight once the sun is a little above the horizon
+        let daylight = (sun.z * 4.0 + 0.3).clamp(0.0, 1.0);
+        let sunlight = light * daylight;
+        // Warmer light near the horizon
+        let warmth = 1.0 - (sun.z.abs() * 3.0).min(1.0);
+        let scroll = |speed: f32| (10.0 + speed / 4.0).clamp(0.0, 20.0);
+
+        Self {
+            ambient: scale([1.05, 1.05, 1.05], 0.2 + 0.8 * sunlight, 0.35),
+            blue_density: scale([0.2447, 0.4487, 0.7599], light.min(1.0), 0.38),
+            blue_horizon: scale([0.4954, 0.4954, 0.6399], light.min(1.0), 0.19),
+            cloud_color: scale([0.41, 0.41, 0.41], cloud, 0.41),
+            cloud_pos_density1: [1.0, 0.526, 0.5 + look.cloud_cover / 2.0, 1.0],
+            cloud_pos_density2: [1.0, 0.526, 0.125, 1.0],
+            cloud_scale: [0.42, 0.0, 0.0, 1.0],
+            cloud_scroll_rate: [scroll(look.wind.0), scroll(look.wind.1)],
+            cloud_shadow: [look.cloud_cover, 0.0, 0.0, 1.0],
+            density_multiplier: [0.00018 * haze.max(1.0), 0.0, 0.0, 1.0],
+            distance_multiplier: [0.8 * haze, 0.0, 0.0, 1.0],
+            east_angle: 0.0,
+            enable_cloud_scroll: [true, true],
+            gamma: [1.0, 0.0, 0.0, 1.0],
+            glow: [5.0, 0.001, -0.48, 1.0],
+            haze_density: [0.7 * haze, 0.0, 0.0, 1.0],
+            haze_horizon: [0.19, 0.199, 0.199, 1.0],
+            lightnorm: [sun.x, sun.z, -sun.y, 0.0],
+            max_y: [1605.0, 0.0, 0.0, 1.0],
+            preset_num: 22,
+            star_brightness: (1.0 - daylight) * (1.0 - look.cloud_cover) * 2.0,
+            sun_angle: (phase - 0.25) * TAU,
+            sunlight_color: [
+                0.7342 * sunlight * (1.0 + 0.4 * warmth),
+                0.7815 * sunlight,
+                0.9 * sunlight * (1.0 - 0.3 * warmth),
+                0.3,
+            ],
+        }
+    }
+}
+
+fn scale(rgb: [f32; 3], factor: f32, alpha: f32) -> [f32; 4] {
+    [rgb[0] * factor, rgb[1] * factor, rgb[2] * factor, alpha]
+}
+
+/// Legacy Windlight water settings, field names as viewers expect them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WaterSettings {
+    /// How much the water blurs what is under it
+    pub blur_multiplier: f32,
+    /// Reflection at a straight-down view
+    pub fresnel_offset: f32,
+    /// How fast reflection grows towards the horizon
+    pub fresnel_scale: f32,
+    /// Size of the three wave normal map layers
+    pub norm_scale: [f32; 3],
+    /// Asset ID of the wave normal map texture
+    pub normal_map: String,
+    /// Refraction seen from above the water
+    pub scale_above: f32,
+    /// Refraction seen from under the water
+    pub scale_below: f32,
+    /// Fog density multiplier under water
+    pub under_water_fog_mod: f32,
+    /// Colour of the water fog
+    pub water_fog_color: [f32; 4],
+    /// Density of the water fog
+    pub water_fog_density: f32,
+    /// Drift of the large waves in X and Y
+    pub wave1_dir: [f32; 2],
+    /// Drift of the small waves in X and Y
+    pub wave2_dir: [f32; 2],
+}
+
+impl WaterSettings {
+    /// Water for `look`: waves run with the wind and grow with it, rain
+    /// and storms stir up the water
+    pub fn new(look: &WeatherLook) -> Self {
+        let (wind_x, wind_y) = look.wind;
+        let speed = (wind_x * wind_x + wind_y * wind_y).sqrt();
+        let (dir_x, dir_y) = if speed > f32::EPSILON { (wind_x / speed, wind_y / speed) } else { (1.0, 0.0) };
+        let wave = 1.0 + (speed / 10.0).min(2.0);
+        let murk = match look.condition {
+            "rain" | "snow" => 1.5,
+            "storm" => 2.5,
+            _ => 1.0,
+        };
+
+        Self {
+            blur_multiplier: 0.04,
+            fresnel_offset: 0.5,
+            fresnel_scale: 0.4,
+            norm_scale: [2.0 * wave, 2.0 * wave, 2.0 * wave],
+            normal_map: WATER_NORMAL_MAP.to_string(),
+            scale_above: 0.03 * wave,
+            scale_below: 0.2,
+            under_water_fog_mod: 0.25,
+            water_fog_color: [0.0157 * murk, 0.149, 0.2509 / murk, 1.0],
+            water_fog_density: 10.0 * murk,
+            wave1_dir: [1.05 * dir_x * wave, 1.05 * dir_y * wave],
+            wave2_dir: [1.11 * dir_y * wave, -1.11 * dir_x * wave],
+        }
+    }
+}
+
+/// Response of the legacy EnvironmentSettings capability
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvironmentSettings {
+    /// Region the settings are for
+    pub region_id: Uuid,
+    /// `(day fraction, sky name)` keyframes
+    pub day_cycle: Vec<(f32, String)>,
+    /// Sky presets by the names the day cycle uses
+    pub skies: BTreeMap<String, SkySettings>,
+    /// Water settings
+    pub water: WaterSettings,
+}
+
+impl EnvironmentSettings {
+    /// A day cycle of `look` with midnight, sunrise, noon and sunset keyframes
+    pub fn new(region_id: Uuid, look: &WeatherLook) -> Self {
+        let keyframes = [(0.0, "Midnight"), (0.25, "Sunrise"), (0.5, "Midday"), (0.75, "Sunset")];
+        Self {
+            region_id,
+            day_cycle: keyframes.iter().map(|(phase, name)| (*phase, name.to_string())).collect(),
+            skies: keyframes
+                .iter()
+                .map(|(phase, name)| (name.to_string(), SkySettings::new(look, *phase)))
+                .collect(),
+            water: WaterSettings::new(look),
+        }
+    }
+
+    /// The settings as the LLSD array viewers expect: message IDs, day
+    /// cycle, sky presets and water
+    pub fn to_llsd(&self) -> Value {
+        json!([
+            { "messageID": Uuid::nil(), "regionID": self.region_id },
+            self.day_cycle.iter().map(|(phase, name)| json!([phase, name])).collect::<Vec<_>>(),
+            self.skies,
+            self.water,
+        ])
+    }
+
+    /// [`Self::to_llsd`] as LLSD XML
+    pub fn to_llsd_xml(&self) -> String {
+        crate::llsd::to_xml(&self.to_llsd())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_day_cycle() {
+        let day = DayCycle {
+            day_length_secs: 1000,
+            offset_secs: 250,
+        };
+        assert_eq!(day.phase(0), 0.25);
+        assert_eq!(day.phase(1250), 0.5);
+        assert!((DayCycle::sun_direction(0.5).z - 1.0).abs() < 1e-6);
+        assert!(DayCycle::sun_direction(0.0).z < 0.0);
+
+        let payload = day.encode_viewer_time(250, 7);
+        assert_eq!(payload[0], packet_types::SIMULATOR_VIEWER_TIME as u8);
+        assert_eq!(u64::from_le_bytes(payload[1..9].try_into().unwrap()), 7);
+        assert_eq!(u32::from_le_bytes(payload[9..13].try_into().unwrap()), 1000);
+        assert_eq!(payload.len(), 1 + 8 + 4 + 4 + 12 + 4 + 12);
+    }
+
+    #[test]
+    fn test_weather_changes_sky_and_water() {
+        let clear = WeatherLook::default();
+        let storm = WeatherLook {
+            condition: "storm",
+            cloud_cover: 0.95,
+            wind: (0.0, 15.0),
+        };
+        let (clear_sky, storm_sky) = (SkySettings::new(&clear, 0.5), SkySettings::new(&storm, 0.5));
+        assert!(storm_sky.sunlight_color[0] < clear_sky.sunlight_color[0]);
+        assert!(storm_sky.haze_density[0] > clear_sky.haze_density[0]);
+        assert_eq!(storm_sky.cloud_shadow[0], 0.95);
+        // Stars only at night
+        assert_eq!(clear_sky.star_brightness, 0.0);
+        assert!(SkySettings::new(&clear, 0.0).star_brightness > 0.0);
+
+        let water = WaterSettings::new(&storm);
+        assert_eq!(water.wave1_dir[0], 0.0);
+        assert!(water.wave1_dir[1] > 1.05);
+
+        let settings = EnvironmentSettings::new(Uuid::nil(), &storm).to_llsd();
+        assert_eq!(settings[1][2], json!([0.5, "Midday"]));
+        assert!(settings[2]["Sunset"]["sunlight_color"].is_array());
+        assert_eq!(settings[3]["normalMap"], WATER_NORMAL_MAP);
+    }
+}
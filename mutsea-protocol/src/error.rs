@@ -1,5 +1,6 @@
 //! Protocol error types
 
+use mutsea_core::{ErrorCategory, ErrorClassification, MutseaError};
 use thiserror::Error;
 
 /// Protocol-specific errors
@@ -50,5 +51,36 @@ pub enum ProtocolError {
     Generic(String),
 }
 
+impl ErrorClassification for ProtocolError {
+    fn code(&self) -> &'static str {
+        match self {
+            ProtocolError::Io(_) => "PROTO_IO",
+            ProtocolError::InvalidPacket(_) => "PROTO_INVALID_PACKET",
+            ProtocolError::UnsupportedVersion(_) => "PROTO_UNSUPPORTED_VERSION",
+            ProtocolError::InvalidMessage(_) => "PROTO_INVALID_MESSAGE",
+            ProtocolError::AuthenticationFailed(_) => "PROTO_AUTHENTICATION",
+            ProtocolError::CircuitNotFound(_) => "PROTO_CIRCUIT_NOT_FOUND",
+            ProtocolError::SequenceError(_) => "PROTO_SEQUENCE",
+            ProtocolError::Timeout(_) => "PROTO_TIMEOUT",
+            ProtocolError::Encoding(_) => "PROTO_ENCODING",
+            ProtocolError::Decoding(_) => "PROTO_DECODING",
+            ProtocolError::Generic(_) => "PROTO_INTERNAL",
+        }
+    }
+
+    fn category(&self) -> ErrorCategory {
+        match self {
+            ProtocolError::Io(_) | ProtocolError::Timeout(_) => ErrorCategory::Retryable,
+            _ => ErrorCategory::Permanent,
+        }
+    }
+}
+
+impl From<ProtocolError> for MutseaError {
+    fn from(err: ProtocolError) -> Self {
+        MutseaError::classified(&err)
+    }
+}
+
 /// Result type for protocol operations
 pub type ProtocolResult<T> = Result<T, ProtocolError>;
\ No newline at end of file
@@ -0,0 +1,84 @@
+//! Capability event queue
+//!
+//! Messages too large or too reliable for UDP reach the viewer through its
+//! EventQueueGet capability. The viewer long-polls it, posting
+//! `{ack: <id>, done: false}` with the ID of the last batch it received,
+//! and is answered with `{events: [{message, body}], id}`. A poll with
+//! nothing to deliver is answered 502 once it times out, which viewers take
+//! as "no events" and poll again.
+
+use crate::llsd;
+use serde_json::{json, Value};
+
+/// A poll of the event queue
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventQueuePoll {
+    /// The ID of the last batch the viewer received, if any
+    pub ack: Option<i64>,
+    /// Whether the viewer is leaving and wants no more events
+    pub done: bool,
+}
+
+impl EventQueuePoll {
+    /// Parse the LLSD XML the viewer posts. Anything unreadable polls
+    /// without an acknowledgement.
+    pub fn parse(body: &str) -> Self {
+        Self {
+            ack: value_of(body, "ack").and_then(|value| {
+                let text = value.strip_prefix("<integer>")?.split("</integer>").next()?;
+                text.trim().parse().ok()
+            }),
+            done: value_of(body, "done").is_some_and(|value| value.starts_with("<boolean>true")),
+        }
+    }
+}
+
+/// The event `message` carrying `body`
+pub fn event(message: &str, body: Value) -> Value {
+    json!({ "message": message, "body": body })
+}
+
+/// The batch `id` of `events` as LLSD XML
+pub fn encode_events(id: i64, events: &[Value]) -> String {
+    llsd::to_xml(&json!({ "events": events, "id": id }))
+}
+
+/// The XML after `<key>key</key>`
+fn value_of<'a>(body: &'a str, key: &str) -> Option<&'a str> {
+    let tag = format!("<key>{}</key>", key);
+    let start = body.find(&tag)? + tag.len();
+    Some(body[start..].trim_start())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_polls_and_batches() {
+        let poll = EventQueuePoll::parse(
+            "<llsd><map><key>ack</key><integer>7</integer><key>done</key><boolean>false</boolean></map></llsd>",
+        );
+        assert_eq!(
+            poll,
+            EventQueuePoll {
+                ack: Some(7),
+                done: false
+            }
+        );
+        let poll = EventQueuePoll::parse(
+            "<llsd><map><key>ack</key><undef /><key>done</key><boolean>true</boolean></map></llsd>",
+        );
+        assert_eq!(poll, EventQueuePoll { ack: None, done: true });
+        assert_eq!(EventQueuePoll::parse(""), EventQueuePoll::default());
+
+        let xml = encode_events(8, &[event("Hello", json!({ "n": 1 }))]);
+        assert_eq!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><llsd><map><key>events</key><array><map>\
+             <key>body</key><map><key>n</key><integer>1</integer></map>\
+             <key>message</key><string>Hello</string></map></array>\
+             <key>id</key><integer>8</integer></map></llsd>"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Follow camera properties
+//!
+//! SetFollowCamProperties tells a viewer how its camera should follow an
+//! object, typically the vehicle the agent is driving; the properties are
+//! those of llSetCameraParams. ClearFollowCamProperties hands the camera
+//! back to the viewer's own settings.
+
+use crate::constants::packet_types;
+use crate::packet::Packet;
+use uuid::Uuid;
+
+/// A follow camera property, numbered as the CAMERA_* constants of
+/// llSetCameraParams. The vector constants are sent as their components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum FollowCamParam {
+    /// Degrees to tilt the camera down, -45 to 80
+    Pitch = 0,
+    /// Focus offset from the object along its X axis, -10 to 10 metres
+    FocusOffsetX = 2,
+    /// Focus offset along the object's Y axis, -10 to 10 metres
+    FocusOffsetY = 3,
+    /// Focus offset along the object's Z axis, -10 to 10 metres
+    FocusOffsetZ = 4,
+    /// Seconds the camera position lags behind, 0 to 3
+    PositionLag = 5,
+    /// Seconds the camera focus lags behind, 0 to 3
+    FocusLag = 6,
+    /// Metres from the focus, 0.5 to 10
+    Distance = 7,
+    /// Degrees the camera may swing from behind the object, 0 to 180
+    BehindnessAngle = 8,
+    /// Seconds the camera lags swinging back behind the object, 0 to 3
+    BehindnessLag = 9,
+    /// Metres the object moves before the camera position follows, 0 to 4
+    PositionThreshold = 10,
+    /// Metres the object moves before the camera focus follows, 0 to 4
+    FocusThreshold = 11,
+    /// 1 to follow the object, 0 to stop
+    Active = 12,
+    /// Camera position X in region coordinates
+    PositionX = 14,
+    /// Camera position Y in region coordinates
+    PositionY = 15,
+    /// Camera position Z in region coordinates
+    PositionZ = 16,
+    /// Camera focus X in region coordinates
+    FocusX = 18,
+    /// Camera focus Y in region coordinates
+    FocusY = 19,
+    /// Camera focus Z in region coordinates
+    FocusZ = 20,
+    /// 1 to hold the camera at its position
+    PositionLocked = 21,
+    /// 1 to hold the camera focus where it is
+    FocusLocked = 22,
+}
+
+impl FollowCamParam {
+    /// The property numbered `id`; the vector constants have no single value
+    pub fn from_id(id: i32) -> Option<Self> {
+        use FollowCamParam::*;
+        Some(match id {
+            0 => Pitch,
+            2 => FocusOffsetX,
+            3 => FocusOffsetY,
+            4 => FocusOffsetZ,
+            5 => PositionLag,
+            6 => FocusLag,
+            7 => Distance,
+            8 => BehindnessAngle,
+            9 => BehindnessLag,
+            10 => PositionThreshold,
+            11 => FocusThreshold,
+            12 => Active,
+            14 => PositionX,
+            15 => PositionY,
+            16 => PositionZ,
+            18 => FocusX,
+            19 => FocusY,
+            20 => FocusZ,
+            21 => PositionLocked,
+            22 => FocusLocked,
+            _ => return None,
+        })
+    }
+}
+
+/// Encode SetFollowCamProperties for the camera following `object_id`
+pub fn encode_set(object_id: Uuid, properties: &[(FollowCamParam, f32)]) -> Packet {
+    let mut payload = Vec::with_capacity(17 + properties.len() * 8);
+    // ObjectData block
+    payload.extend_from_slice(object_id.as_bytes());
+    // CameraProperty blocks (variable)
+    payload.push(properties.len() as u8);
+    for (param, value) in properties {
+        payload.extend_from_slice(&(*param as i32).to_le_bytes());
+        payload.extend_from_slice(&value.to_le_bytes());
+    }
+    Packet::reliable(0, payload).with_message_id(packet_types::SET_FOLLOW_CAM_PROPERTIES)
+}
+
+/// Encode ClearFollowCamProperties, releasing the camera from `object_id`
+pub fn encode_clear(object_id: Uuid) -> Packet {
+    Packet::reliable(0, object_id.as_bytes().to_vec()).with_message_id(packet_types::CLEAR_FOLLOW_CAM_PROPERTIES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_follow_cam_layout() {
+        let object_id = Uuid::new_v4();
+        let packet = encode_set(object_id, &[(FollowCamParam::Distance, 6.0), (FollowCamParam::Active, 1.0)]);
+        assert_eq!(packet.message_id, Some(packet_types::SET_FOLLOW_CAM_PROPERTIES));
+        let payload = &packet.payload;
+        assert_eq!(&payload[..16], object_id.as_bytes());
+        assert_eq!(payload[16], 2);
+        assert_eq!(i32::from_le_bytes(payload[17..21].try_into().unwrap()), 7);
+        assert_eq!(f32::from_le_bytes(payload[21..25].try_into().unwrap()), 6.0);
+        assert_eq!(payload.len(), 17 + 2 * 8);
+
+        assert_eq!(FollowCamParam::from_id(12), Some(FollowCamParam::Active));
+        assert_eq!(FollowCamParam::from_id(13), None);
+        let clear = encode_clear(object_id);
+        assert_eq!(clear.message_id, Some(packet_types::CLEAR_FOLLOW_CAM_PROPERTIES));
+        assert_eq!(clear.payload, object_id.as_bytes());
+    }
+}
@@ -0,0 +1,137 @@
+//! ImprovedInstantMessage encoding
+//!
+//! Messages the simulator sends to a single agent, such as quest offers,
+//! travel as ImprovedInstantMessage. The dialog type decides how the
+//! viewer presents them.
+
+use crate::constants::packet_types;
+use mutsea_core::{RegionId, UserId, Vector3};
+use uuid::Uuid;
+
+/// Dialog types of an instant message
+pub mod im_dialogs {
+    /// Ordinary IM from another agent
+    pub const MESSAGE_FROM_AGENT: u8 = 0;
+    /// Alert box with an OK button
+    pub const MESSAGE_BOX: u8 = 1;
+    /// Shown in local chat as coming from a named object
+    pub const MESSAGE_FROM_OBJECT: u8 = 19;
+}
+
+/// One instant message to an agent
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstantMessage {
+    /// Sender, nil for an object
+    pub from_agent_id: UserId,
+    /// Name shown as the sender
+    pub from_name: String,
+    /// Recipient
+    pub to_agent_id: UserId,
+    /// Region the message was sent from
+    pub region_id: RegionId,
+    /// Where the sender stood, in region coordinates
+    pub position: Vector3,
+    /// Kind of message, one of [`im_dialogs`]
+    pub dialog: u8,
+    /// Conversation ID; replies to the message carry it back
+    pub id: Uuid,
+    /// Text of the message
+    pub message: String,
+}
+
+impl InstantMessage {
+    /// A message shown in local chat as said by `from_name`
+    pub fn from_object(from_name: &str, to_agent_id: UserId, region_id: RegionId, message: &str) -> Self {
+        Self {
+            from_agent_id: UserId::from_uuid(Uuid::nil()),
+            from_name: from_name.to_string(),
+            to_agent_id,
+            region_id,
+            position: Vector3::ZERO,
+            dialog: im_dialogs::MESSAGE_FROM_OBJECT,
+            id: Uuid::new_v4(),
+            message: message.to_string(),
+        }
+    }
+
+    /// Encode the ImprovedInstantMessage payload, starting with the message ID
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        payload.push(packet_types::INSTANT_MESSAGE as u8);
+
+        // AgentData block
+        payload.extend_from_slice(self.from_agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::nil().as_bytes());
+
+        // MessageBlock
+        payload.push(0); // FromGroup
+        payload.extend_from_slice(self.to_agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(&0u32.to_le_bytes()); // ParentEstateID
+        payload.extend_from_slice(self.region_id.0.as_bytes());
+        payload.extend_from_slice(&self.position.x.to_le_bytes());
+        payload.extend_from_slice(&self.position.y.to_le_bytes());
+        payload.extend_from_slice(&self.position.z.to_le_bytes());
+        payload.push(0); // Offline
+        payload.push(self.dialog);
+        payload.extend_from_slice(self.id.as_bytes());
+        payload.extend_from_slice(&0u32.to_le_bytes()); // Timestamp, 0 for now
+        push_variable1(&mut payload, &self.from_name);
+        push_variable2(&mut payload, &self.message);
+        payload.extend_from_slice(&0u16.to_le_bytes()); // BinaryBucket
+
+        payload
+    }
+}
+
+/// Null-terminated string with a 1-byte length, truncated to fit
+pub(crate) fn push_variable1(payload: &mut Vec<u8>, value: &str) {
+    let bytes = truncated(value, u8::MAX as usize - 1);
+    payload.push(bytes.len() as u8 + 1);
+    payload.extend_from_slice(bytes);
+    payload.push(0);
+}
+
+/// Null-terminated string with a 2-byte length; viewers accept 1024 bytes
+pub(crate) fn push_variable2(payload: &mut Vec<u8>, value: &str) {
+    let bytes = truncated(value, 1023);
+    payload.extend_from_slice(&(bytes.len() as u16 + 1).to_le_bytes());
+    payload.extend_from_slice(bytes);
+    payload.push(0);
+}
+
+/// At most `max` bytes of `value`, cut on a character boundary
+fn truncated(value: &str, max: usize) -> &[u8] {
+    let mut end = value.len().min(max);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    &value.as_bytes()[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_layout() {
+        let to = UserId::new();
+        let im = InstantMessage::from_object("Narrator", to, RegionId::new(), "Find the old well");
+        let payload = im.encode();
+
+        assert_eq!(payload[0], packet_types::INSTANT_MESSAGE as u8);
+        assert_eq!(&payload[34..50], to.as_uuid().as_bytes());
+        // FromGroup, ToAgentID, ParentEstateID, RegionID, Position, Offline
+        let dialog = 1 + 32 + 1 + 16 + 4 + 16 + 12 + 1;
+        assert_eq!(payload[dialog], im_dialogs::MESSAGE_FROM_OBJECT);
+        let name = dialog + 1 + 16 + 4;
+        assert_eq!(payload[name], 9);
+        assert_eq!(&payload[name + 1..name + 10], b"Narrator\0");
+        let message = name + 10;
+        assert_eq!(u16::from_le_bytes([payload[message], payload[message + 1]]), 18);
+        assert_eq!(payload.len(), message + 2 + 18 + 2);
+
+        let long = "é".repeat(600);
+        let im = InstantMessage { message: long, ..im };
+        assert!(im.encode().len() < payload.len() + 1024);
+    }
+}
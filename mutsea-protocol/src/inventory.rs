@@ -0,0 +1,444 @@
+//! Inventory over capabilities
+//!
+//! Viewers fetch inventory through capabilities rather than UDP.
+//! FetchInventoryDescendents2 is posted `{folders: [{folder_id, owner_id,
+//! fetch_folders, fetch_items, sort_order}]}` and answers each folder with
+//! its version, how many folders and items it holds, and those asked for;
+//! folders that can't be read are listed in `bad_folders`. Folders left out
+//! of an answer are asked for again, which is how large fetches are spread
+//! over several requests. FetchInventory2 is posted `{items: [{owner_id,
+//! item_id}]}` and answers the items found.
+//!
+//! Folders and items the region adds to an agent's inventory are sent to it
+//! as a BulkUpdateInventory event on its event queue.
+
+use crate::constants::packet_types;
+use crate::instant_message::push_variable1;
+use crate::packet::Packet;
+use crate::{llsd, ProtocolError, ProtocolResult};
+use mutsea_core::UserId;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// A folder as viewers are told of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FolderData {
+    /// Folder identifier
+    pub folder_id: Uuid,
+    /// Folder it is in, nil for the root
+    pub parent_id: Uuid,
+    /// Owner of the inventory
+    pub owner_id: UserId,
+    /// Folder name
+    pub name: String,
+    /// The asset type of the system folder it is, -1 for an agent's own
+    pub folder_type: i32,
+    /// Bumped whenever the folder's contents change
+    pub version: i32,
+}
+
+/// An item as viewers are told of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemData {
+    /// Item identifier
+    pub item_id: Uuid,
+    /// Folder it is in
+    pub parent_id: Uuid,
+    /// The asset the item refers to
+    pub asset_id: Uuid,
+    /// Owner of the item
+    pub owner_id: UserId,
+    /// Creator of the asset
+    pub creator_id: UserId,
+    /// Item name
+    pub name: String,
+    /// Item description
+    pub description: String,
+    /// Asset type of the asset
+    pub asset_type: i32,
+    /// Inventory type of the item
+    pub inv_type: i32,
+    /// Permissions anyone can ever have
+    pub base_mask: u32,
+    /// Permissions of the owner
+    pub owner_mask: u32,
+    /// Permissions of everyone else
+    pub everyone_mask: u32,
+    /// Permissions of the next owner
+    pub next_owner_mask: u32,
+    /// Unix time
+    pub created_at: i64,
+}
+
+impl ItemData {
+    /// The item as FetchInventory2 and FetchInventoryDescendents2 write it
+    pub fn to_llsd(&self) -> Value {
+        json!({
+            "item_id": self.item_id.to_string(),
+            "parent_id": self.parent_id.to_string(),
+            "asset_id": self.asset_id.to_string(),
+            "name": self.name,
+            "desc": self.description,
+            "type": self.asset_type,
+            "inv_type": self.inv_type,
+            "flags": 0,
+            "created_at": self.created_at,
+            "permissions": {
+                "creator_id": self.creator_id.to_string(),
+                "owner_id": self.owner_id.to_string(),
+                "last_owner_id": self.owner_id.to_string(),
+                "group_id": Uuid::nil().to_string(),
+                "is_owner_group": false,
+                "base_mask": self.base_mask as i32,
+                "owner_mask": self.owner_mask as i32,
+                "group_mask": 0,
+                "everyone_mask": self.everyone_mask as i32,
+                "next_owner_mask": self.next_owner_mask as i32,
+            },
+            "sale_info": { "sale_price": 0, "sale_type": 0 },
+        })
+    }
+}
+
+/// A folder a viewer asked FetchInventoryDescendents2 for
+#[derive(Debug, Clone, PartialEq)]
+pub struct FolderRequest {
+    /// Folder wanted
+    pub folder_id: Uuid,
+    /// Owner of the folder
+    pub owner_id: UserId,
+    /// Whether to send the folders in it
+    pub fetch_folders: bool,
+    /// Whether to send the items in it
+    pub fetch_items: bool,
+}
+
+/// An item a viewer asked FetchInventory2 for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ItemRequest {
+    /// Item wanted
+    pub item_id: Uuid,
+    /// Owner of the item
+    pub owner_id: UserId,
+}
+
+/// A folder answered by FetchInventoryDescendents2
+#[derive(Debug, Clone, PartialEq)]
+pub struct FolderContents {
+    /// The folder itself
+    pub folder: FolderData,
+    /// How many folders and items it holds, whether or not they are sent
+    pub descendents: usize,
+    /// Folders in it, if asked for
+    pub categories: Vec<FolderData>,
+    /// Items in it, if asked for
+    pub items: Vec<ItemData>,
+}
+
+/// A folder FetchInventoryDescendents2 couldn't answer, and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadFolder {
+    /// Folder asked for
+    pub folder_id: Uuid,
+    /// Why it couldn't be answered
+    pub error: String,
+}
+
+/// Parse a FetchInventoryDescendents2 request body
+pub fn parse_fetch_descendents(body: &str) -> ProtocolResult<Vec<FolderRequest>> {
+    let folders = entries_of(body, "folders")
+        .ok_or_else(|| ProtocolError::Decoding("FetchInventoryDescendents2 needs folders".to_string()))?;
+    Ok(folders
+        .into_iter()
+        .filter_map(|entry| {
+            Some(FolderRequest {
+                folder_id: uuid_of(entry, "folder_id")?,
+                owner_id: UserId::from_uuid(uuid_of(entry, "owner_id").unwrap_or_default()),
+                fetch_folders: bool_of(entry, "fetch_folders").unwrap_or(true),
+                fetch_items: bool_of(entry, "fetch_items").unwrap_or(true),
+            })
+        })
+        .collect())
+}
+
+/// Parse a FetchInventory2 request body
+pub fn parse_fetch_items(body: &str) -> ProtocolResult<Vec<ItemRequest>> {
+    let items = entries_of(body, "items")
+        .ok_or_else(|| ProtocolError::Decoding("FetchInventory2 needs items".to_string()))?;
+    Ok(items
+        .into_iter()
+        .filter_map(|entry| {
+            Some(ItemRequest {
+                item_id: uuid_of(entry, "item_id")?,
+                owner_id: UserId::from_uuid(uuid_of(entry, "owner_id").unwrap_or_default()),
+            })
+        })
+        .collect())
+}
+
+/// The FetchInventoryDescendents2 answer for `agent_id`
+pub fn encode_descendents(agent_id: UserId, folders: &[FolderContents], bad_folders: &[BadFolder]) -> String {
+    let folders: Vec<Value> = folders
+        .iter()
+        .map(|contents| {
+            json!({
+                "agent_id": agent_id.to_string(),
+                "owner_id": contents.folder.owner_id.to_string(),
+                "folder_id": contents.folder.folder_id.to_string(),
+                "descendents": contents.descendents,
+                "version": contents.folder.version,
+                "categories": contents.categories.iter().map(category_llsd).collect::<Vec<_>>(),
+                "items": contents.items.iter().map(ItemData::to_llsd).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    let mut reply = json!({ "folders": folders });
+    if !bad_folders.is_empty() {
+        reply["bad_folders"] = bad_folders
+            .iter()
+            .map(|bad| json!({ "folder_id": bad.folder_id.to_string(), "error": bad.error }))
+            .collect();
+    }
+    llsd::to_xml(&reply)
+}
+
+/// The FetchInventory2 answer for `agent_id`
+pub fn encode_items(agent_id: UserId, items: &[ItemData]) -> String {
+    llsd::to_xml(&json!({
+        "agent_id": agent_id.to_string(),
+        "items": items.iter().map(ItemData::to_llsd).collect::<Vec<_>>(),
+    }))
+}
+
+/// The BulkUpdateInventory event body telling `agent_id` of `folders` and
+/// `items` added to its inventory
+pub fn encode_bulk_update_inventory(agent_id: UserId, folders: &[FolderData], items: &[ItemData]) -> Value {
+    json!({
+        "AgentData": [{
+            "AgentID": agent_id.to_string(),
+            "TransactionID": Uuid::nil().to_string(),
+        }],
+        "FolderData": folders.iter().map(|folder| json!({
+            "FolderID": folder.folder_id.to_string(),
+            "ParentID": folder.parent_id.to_string(),
+            "Name": folder.name,
+            "Type": folder.folder_type,
+        })).collect::<Vec<_>>(),
+        "ItemData": items.iter().map(|item| json!({
+            "ItemID": item.item_id.to_string(),
+            "CallbackID": 0,
+            "FolderID": item.parent_id.to_string(),
+            "CreatorID": item.creator_id.to_string(),
+            "OwnerID": item.owner_id.to_string(),
+            "GroupID": Uuid::nil().to_string(),
+            "BaseMask": item.base_mask as i32,
+            "OwnerMask": item.owner_mask as i32,
+            "GroupMask": 0,
+            "EveryoneMask": item.everyone_mask as i32,
+            "NextOwnerMask": item.next_owner_mask as i32,
+            "GroupOwned": false,
+            "AssetID": item.asset_id.to_string(),
+            "Type": item.asset_type,
+            "InvType": item.inv_type,
+            "Flags": 0,
+            "SaleType": 0,
+            "SalePrice": 0,
+            "Name": item.name,
+            "Description": item.description,
+            "CreationDate": item.created_at,
+            "CRC": 0,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// UpdateCreateInventoryItem telling the owner of `item` the region made it
+/// for their request `transaction_id`, nil if there was none
+pub fn encode_update_create_inventory_item(transaction_id: Uuid, item: &ItemData) -> Packet {
+    let mut payload = Vec::new();
+    // AgentData block
+    payload.extend_from_slice(item.owner_id.as_uuid().as_bytes());
+    payload.push(1); // SimApproved
+    payload.extend_from_slice(transaction_id.as_bytes());
+    // InventoryData block (variable)
+    payload.push(1);
+    payload.extend_from_slice(item.item_id.as_bytes());
+    payload.extend_from_slice(item.parent_id.as_bytes());
+    payload.extend_from_slice(&0u32.to_le_bytes()); // CallbackID
+    payload.extend_from_slice(item.creator_id.as_uuid().as_bytes());
+    payload.extend_from_slice(item.owner_id.as_uuid().as_bytes());
+    payload.extend_from_slice(Uuid::nil().as_bytes()); // GroupID
+    for mask in [item.base_mask, item.owner_mask, 0, item.everyone_mask, item.next_owner_mask] {
+        payload.extend_from_slice(&mask.to_le_bytes());
+    }
+    payload.push(0); // GroupOwned
+    payload.extend_from_slice(item.asset_id.as_bytes());
+    payload.push(item.asset_type as u8);
+    payload.push(item.inv_type as u8);
+    payload.extend_from_slice(&0u32.to_le_bytes()); // Flags
+    payload.push(0); // SaleType
+    payload.extend_from_slice(&0i32.to_le_bytes()); // SalePrice
+    push_variable1(&mut payload, &item.name);
+    push_variable1(&mut payload, &item.description);
+    payload.extend_from_slice(&(item.created_at as i32).to_le_bytes());
+    payload.extend_from_slice(&0u32.to_le_bytes()); // CRC
+    Packet::reliable(0, payload).with_message_id(packet_types::UPDATE_CREATE_INVENTORY_ITEM)
+}
+
+fn category_llsd(folder: &FolderData) -> Value {
+    json!({
+        "category_id": folder.folder_id.to_string(),
+        "parent_id": folder.parent_id.to_string(),
+        "name": folder.name,
+        "type_default": folder.folder_type,
+        "version": folder.version,
+    })
+}
+
+/// The maps of the array after `<key>key</key>`
+fn entries_of<'a>(body: &'a str, key: &str) -> Option<Vec<&'a str>> {
+    let tag = format!("<key>{}</key>", key);
+    let start = body.find(&tag)? + tag.len();
+    let array = body[start..].trim_start();
+    if array.starts_with("<array />") || array.starts_with("<array/>") {
+        return Some(Vec::new());
+    }
+    let array = array.strip_prefix("<array>")?;
+    let array = &array[..array.find("</array>")?];
+    Some(array.split("<map>").skip(1).collect())
+}
+
+/// The text of the scalar after `<key>key</key>`, empty for `<uuid />`
+fn text_of<'a>(entry: &'a str, key: &str) -> Option<&'a str> {
+    let tag = format!("<key>{}</key>", key);
+    let start = entry.find(&tag)? + tag.len();
+    let value = entry[start..].trim_start();
+    let open = value.find('>')?;
+    if value[..open].ends_with('/') {
+        return Some("");
+    }
+    let close = value.find("</")?;
+    Some(value[open + 1..close].trim())
+}
+
+fn uuid_of(entry: &str, key: &str) -> Option<Uuid> {
+    Uuid::parse_str(text_of(entry, key)?).ok()
+}
+
+fn bool_of(entry: &str, key: &str) -> Option<bool> {
+    text_of(entry, key).map(|text| matches!(text, "1" | "true"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(parent_id: Uuid, owner_id: UserId) -> ItemData {
+        ItemData {
+            item_id: Uuid::new_v4(),
+            parent_id,
+            asset_id: Uuid::new_v4(),
+            owner_id,
+            creator_id: owner_id,
+            name: "Chair & table".to_string(),
+            description: String::new(),
+            asset_type: 6,
+            inv_type: 6,
+            base_mask: 0x7fff_ffff,
+            owner_mask: 0x7fff_ffff,
+            everyone_mask: 0,
+            next_owner_mask: 0x7fff_ffff,
+            created_at: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn test_parse_fetches() {
+        let (folder, owner, item) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let body = format!(
+            "<llsd><map><key>folders</key><array><map><key>fetch_folders</key><boolean>1</boolean>\
+             <key>fetch_items</key><boolean>0</boolean><key>folder_id</key><uuid>{}</uuid>\
+             <key>owner_id</key><uuid>{}</uuid><key>sort_order</key><integer>1</integer></map>\
+             <map><key>folder_id</key><uuid /></map></array></map></llsd>",
+            folder, owner
+        );
+        let requests = parse_fetch_descendents(&body).unwrap();
+        assert_eq!(
+            requests,
+            vec![FolderRequest {
+                folder_id: folder,
+                owner_id: UserId::from_uuid(owner),
+                fetch_folders: true,
+                fetch_items: false,
+            }]
+        );
+        assert!(parse_fetch_descendents("<llsd><map /></llsd>").is_err());
+
+        let body = format!(
+            "<llsd><map><key>agent_id</key><uuid>{}</uuid><key>items</key><array><map>\
+             <key>item_id</key><uuid>{}</uuid><key>owner_id</key><uuid>{}</uuid></map></array></map></llsd>",
+            owner, item, owner
+        );
+        assert_eq!(parse_fetch_items(&body).unwrap()[0].item_id, item);
+        assert!(parse_fetch_items("<llsd><map><key>items</key><array /></map></llsd>").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_encode_folders_and_updates() {
+        let agent_id = UserId::new();
+        let folder = FolderData {
+            folder_id: Uuid::new_v4(),
+            parent_id: Uuid::nil(),
+            owner_id: agent_id,
+            name: "My Inventory".to_string(),
+            folder_type: 8,
+            version: 3,
+        };
+        let chair = item(folder.folder_id, agent_id);
+        let bad = Uuid::new_v4();
+        let xml = encode_descendents(
+            agent_id,
+            &[FolderContents {
+                folder: folder.clone(),
+                descendents: 4,
+                categories: Vec::new(),
+                items: vec![chair.clone()],
+            }],
+            &[BadFolder {
+                folder_id: bad,
+                error: "Unknown folder".to_string(),
+            }],
+        );
+        assert!(xml.contains("<key>descendents</key><integer>4</integer>"));
+        assert!(xml.contains("<key>version</key><integer>3</integer>"));
+        assert!(xml.contains("<key>name</key><string>Chair &amp; table</string>"));
+        assert!(xml.contains(&format!("<key>folder_id</key><uuid>{}</uuid>", bad)));
+
+        let update = encode_bulk_update_inventory(agent_id, &[folder], std::slice::from_ref(&chair));
+        assert_eq!(update["ItemData"][0]["ItemID"], chair.item_id.to_string());
+        assert_eq!(update["FolderData"][0]["Type"], 8);
+        assert_eq!(update["ItemData"][0]["BaseMask"], 0x7fff_ffff);
+    }
+
+    #[test]
+    fn test_update_create_inventory_item_layout() {
+        let owner_id = UserId::new();
+        let transaction_id = Uuid::new_v4();
+        let item = item(Uuid::new_v4(), owner_id);
+        let packet = encode_update_create_inventory_item(transaction_id, &item);
+        assert_eq!(packet.message_id, Some(packet_types::UPDATE_CREATE_INVENTORY_ITEM));
+
+        let payload = &packet.payload;
+        assert_eq!(&payload[..16], owner_id.as_uuid().as_bytes());
+        assert_eq!(&payload[17..33], transaction_id.as_bytes());
+        assert_eq!(payload[33], 1);
+        assert_eq!(&payload[34..50], item.item_id.as_bytes());
+        // ItemID, FolderID, CallbackID, three agents, five masks, GroupOwned
+        let asset = 34 + 16 * 2 + 4 + 16 * 3 + 4 * 5 + 1;
+        assert_eq!(&payload[asset..asset + 16], item.asset_id.as_bytes());
+        assert_eq!((payload[asset + 16], payload[asset + 17]), (6, 6));
+        let name = asset + 18 + 4 + 1 + 4;
+        assert_eq!(payload[name] as usize, "Chair & table".len() + 1);
+        // Name, empty Description, CreationDate, CRC
+        assert_eq!(payload.len(), name + 1 + 14 + 2 + 4 + 4);
+    }
+}
@@ -0,0 +1,101 @@
+//! JSON files of records shared with the CLI
+//!
+//! The ban list and the API key store are each kept in a JSON file that
+//! both `mutsea` commands and the running server write. A file is read again
+//! only when its modification time differs from what was last loaded or
+//! saved.
+
+use crate::{ProtocolError, ProtocolResult};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// A list of records in a JSON file, or nowhere for in-memory stores
+pub(crate) struct JsonFile {
+    path: Option<PathBuf>,
+    loaded_at: Mutex<Option<SystemTime>>,
+}
+
+impl JsonFile {
+    /// No file; nothing is ever loaded and saving does nothing
+    pub(crate) fn in_memory() -> Self {
+        Self {
+            path: None,
+            loaded_at: Mutex::new(None),
+        }
+    }
+
+    /// The file at `path`, which need not exist yet
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path: Some(path),
+            loaded_at: Mutex::new(None),
+        }
+    }
+
+    /// The records in the file, or None if it is missing or hasn't changed
+    /// since it was last loaded or saved
+    pub(crate) fn reload_if_changed<T: DeserializeOwned>(&self) -> ProtocolResult<Option<Vec<T>>> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+        let modified = match std::fs::metadata(path) {
+            Ok(metadata) => metadata.modified().ok(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut loaded_at = self.loaded_at.lock().unwrap();
+        if modified.is_some() && *loaded_at == modified {
+            return Ok(None);
+        }
+
+        let contents = std::fs::read_to_string(path)?;
+        let records = serde_json::from_str(&contents).map_err(|e| ProtocolError::Decoding(e.to_string()))?;
+        *loaded_at = modified;
+        Ok(Some(records))
+    }
+
+    /// Replace the file's records with `records`
+    pub(crate) fn save<T: Serialize>(&self, records: &[T]) -> ProtocolResult<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let json = serde_json::to_string_pretty(records).map_err(|e| ProtocolError::Encoding(e.to_string()))?;
+
+        // Write-then-rename so a concurrent reader never sees a partial file
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, path)?;
+        *self.loaded_at.lock().unwrap() = std::fs::metadata(path)?.modified().ok();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_are_reloaded_only_when_the_file_changes() {
+        let path = std::env::temp_dir().join(format!("mutsea-json-file-{}", uuid::Uuid::new_v4())).join("records.json");
+        let file = JsonFile::new(path.clone());
+        assert!(file.reload_if_changed::<String>().unwrap().is_none());
+
+        file.save(&["one".to_string(), "two".to_string()]).unwrap();
+        // Saving counts as loading what was written
+        assert!(file.reload_if_changed::<String>().unwrap().is_none());
+
+        let other = JsonFile::new(path.clone());
+        assert_eq!(other.reload_if_changed::<String>().unwrap(), Some(vec!["one".to_string(), "two".to_string()]));
+        assert!(other.reload_if_changed::<String>().unwrap().is_none());
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,291 @@
+//! Selling land and returning prims
+//!
+//! Owners put a parcel up for sale from the About Land floater, which
+//! sends ParcelPropertiesUpdate with the ForSale parcel flag, the price and
+//! the only agent allowed to buy it, if any; ForSaleObjects sells the
+//! owner's prims on the parcel with it. Buyers answer with ParcelBuy at
+//! the price and area they were shown.
+//!
+//! Other people's prims are sent back to their owners from the same
+//! floater: ParcelReturnObjects returns the prims of the owners it names,
+//! and ParcelSetOtherCleanTime sets how many minutes they may stay before
+//! they are returned automatically. Estate managers return an agent's
+//! prims across the region with the EstateOwnerMessage method
+//! `estateobjectreturn`.
+
+use crate::codec::MessageDecoder;
+use crate::ProtocolResult;
+use mutsea_core::UserId;
+use uuid::Uuid;
+
+/// Parcel flag: the parcel is for sale
+pub const PARCEL_FLAG_FOR_SALE: u32 = 1 << 2;
+/// Parcel flag: the owner's prims on the parcel are sold with it
+pub const PARCEL_FLAG_FOR_SALE_OBJECTS: u32 = 1 << 7;
+
+/// Transaction type of land bought, from its owner or at auction
+pub const TRANSACTION_LAND_SALE: i32 = 5002;
+
+/// ParcelReturnObjects return type: the parcel owner's prims
+pub const RETURN_OWNER: u32 = 1 << 1;
+/// ParcelReturnObjects return type: prims set to the parcel's group
+pub const RETURN_GROUP: u32 = 1 << 2;
+/// ParcelReturnObjects return type: everyone else's prims
+pub const RETURN_OTHER: u32 = 1 << 3;
+/// ParcelReturnObjects return type: the prims of the owners listed
+pub const RETURN_LIST: u32 = 1 << 4;
+
+/// The EstateOwnerMessage method that returns an agent's prims
+pub const METHOD_ESTATE_OBJECT_RETURN: &str = "estateobjectreturn";
+/// `estateobjectreturn` flag: only from land the agent doesn't own
+pub const ESTATE_RETURN_OTHERS_LAND_ONLY: u32 = 1 << 0;
+/// `estateobjectreturn` flag: whatever the parcel settings
+pub const ESTATE_RETURN_ALWAYS: u32 = 1 << 1;
+/// `estateobjectreturn` flag: only prims running scripts
+pub const ESTATE_RETURN_SCRIPTED_ONLY: u32 = 1 << 2;
+
+/// The sale terms of a parcel, as set in the About Land floater
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParcelSaleUpdate {
+    /// Owner setting the sale
+    pub agent_id: UserId,
+    /// Parcel put up for sale
+    pub local_id: i32,
+    /// Parcel flags, see [`ParcelSaleUpdate::for_sale`]
+    pub parcel_flags: u32,
+    /// Asking price
+    pub sale_price: i32,
+    /// The only agent allowed to buy the parcel
+    pub auth_buyer_id: Option<Uuid>,
+}
+
+impl ParcelSaleUpdate {
+    /// Parse the sale terms out of a ParcelPropertiesUpdate payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        let local_id = decoder.read_i32()?;
+        decoder.skip(4)?; // Flags
+        let parcel_flags = decoder.read_u32()?;
+        let sale_price = decoder.read_i32()?;
+        // Name, Desc, MusicURL and MediaURL
+        for _ in 0..4 {
+            decoder.read_variable1()?;
+        }
+        // MediaID, MediaAutoScale, GroupID, PassPrice, PassHours, Category
+        decoder.skip(16 + 1 + 16 + 4 + 4 + 1)?;
+        let auth_buyer_id = decoder.read_uuid()?;
+
+        Ok(Self {
+            agent_id,
+            local_id,
+            parcel_flags,
+            sale_price,
+            auth_buyer_id: (!auth_buyer_id.is_nil()).then_some(auth_buyer_id),
+        })
+    }
+
+    /// Whether the update puts the parcel up for sale
+    pub fn for_sale(&self) -> bool {
+        self.parcel_flags & PARCEL_FLAG_FOR_SALE != 0
+    }
+
+    /// Whether the owner's prims are sold with the parcel
+    pub fn sells_objects(&self) -> bool {
+        self.parcel_flags & PARCEL_FLAG_FOR_SALE_OBJECTS != 0
+    }
+}
+
+/// An agent's request to buy a parcel at the price and area it was shown
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParcelBuy {
+    /// Buying agent
+    pub agent_id: UserId,
+    /// Group the parcel is bought for, if any
+    pub group_id: Option<Uuid>,
+    /// Parcel bought
+    pub local_id: i32,
+    /// Price the buyer was shown
+    pub price: i32,
+    /// Area in square metres the buyer was shown
+    pub area: i32,
+}
+
+impl ParcelBuy {
+    /// Parse a ParcelBuy payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        let group_id = decoder.read_uuid()?;
+        decoder.skip(2)?; // IsGroupOwned, RemoveContribution
+        let local_id = decoder.read_i32()?;
+        decoder.skip(1)?; // Final
+        Ok(Self {
+            agent_id,
+            group_id: (!group_id.is_nil()).then_some(group_id),
+            local_id,
+            price: decoder.read_i32()?,
+            area: decoder.read_i32()?,
+        })
+    }
+}
+
+/// An agent's request to return prims on a parcel to their owners
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParcelReturnObjects {
+    /// Agent returning the prims
+    pub agent_id: UserId,
+    /// Parcel the prims are on
+    pub local_id: i32,
+    /// `RETURN_*` flags naming whose prims to return
+    pub return_type: u32,
+    /// Prims to return, whatever their owner
+    pub task_ids: Vec<Uuid>,
+    /// Owners whose prims to return, with [`RETURN_LIST`]
+    pub owner_ids: Vec<UserId>,
+}
+
+impl ParcelReturnObjects {
+    /// Parse a ParcelReturnObjects payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        let local_id = decoder.read_i32()?;
+        let return_type = decoder.read_u32()?;
+        let task_ids = uuid_blocks(&mut decoder)?;
+        let owner_ids = uuid_blocks(&mut decoder)?;
+
+        Ok(Self {
+            agent_id,
+            local_id,
+            return_type,
+            task_ids: task_ids.into_iter().filter(|id| !id.is_nil()).collect(),
+            owner_ids: owner_ids
+                .into_iter()
+                .filter(|id| !id.is_nil())
+                .map(UserId::from_uuid)
+                .collect(),
+        })
+    }
+}
+
+/// An agent's request to change a parcel's auto-return time
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParcelSetOtherCleanTime {
+    /// Agent changing the setting
+    pub agent_id: UserId,
+    /// Parcel changed
+    pub local_id: i32,
+    /// Minutes other people's prims may stay, 0 to never return them
+    pub minutes: i32,
+}
+
+impl ParcelSetOtherCleanTime {
+    /// Parse a ParcelSetOtherCleanTime payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            local_id: decoder.read_i32()?,
+            minutes: decoder.read_i32()?,
+        })
+    }
+}
+
+/// A block count and that many UUIDs
+fn uuid_blocks(decoder: &mut MessageDecoder) -> ProtocolResult<Vec<Uuid>> {
+    let count = decoder.read_u8()?;
+    (0..count).map(|_| decoder.read_uuid()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_variable1(payload: &mut Vec<u8>, value: &str) {
+        payload.push(value.len() as u8);
+        payload.extend_from_slice(value.as_bytes());
+    }
+
+    #[test]
+    fn test_parse_sale_update() {
+        let agent_id = UserId::new();
+        let buyer = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(&3i32.to_le_bytes());
+        payload.extend_from_slice(&0u32.to_le_bytes());
+        payload.extend_from_slice(&(PARCEL_FLAG_FOR_SALE | PARCEL_FLAG_FOR_SALE_OBJECTS).to_le_bytes());
+        payload.extend_from_slice(&500i32.to_le_bytes());
+        for value in ["Beach", "By the sea", "", "http://media"] {
+            push_variable1(&mut payload, value);
+        }
+        payload.extend_from_slice(&[0; 16 + 1 + 16 + 4 + 4 + 1]);
+        payload.extend_from_slice(buyer.as_bytes());
+        payload.extend_from_slice(&[0; 16 + 12 + 12 + 1]);
+
+        let update = ParcelSaleUpdate::parse(&payload).unwrap();
+        assert_eq!(update.agent_id, agent_id);
+        assert_eq!(update.local_id, 3);
+        assert_eq!(update.sale_price, 500);
+        assert_eq!(update.auth_buyer_id, Some(buyer));
+        assert!(update.for_sale() && update.sells_objects());
+        assert!(ParcelSaleUpdate::parse(&payload[..60]).is_err());
+    }
+
+    #[test]
+    fn test_parse_parcel_buy() {
+        let agent_id = UserId::new();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(Uuid::nil().as_bytes());
+        payload.extend_from_slice(&[0, 0]);
+        payload.extend_from_slice(&3i32.to_le_bytes());
+        payload.push(1);
+        payload.extend_from_slice(&500i32.to_le_bytes());
+        payload.extend_from_slice(&1024i32.to_le_bytes());
+
+        let buy = ParcelBuy::parse(&payload).unwrap();
+        assert_eq!(
+            buy,
+            ParcelBuy {
+                agent_id,
+                group_id: None,
+                local_id: 3,
+                price: 500,
+                area: 1024,
+            }
+        );
+        assert!(ParcelBuy::parse(&payload[..40]).is_err());
+    }
+
+    #[test]
+    fn test_parse_return_objects() {
+        let agent_id = UserId::new();
+        let owner = UserId::new();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(&2i32.to_le_bytes());
+        payload.extend_from_slice(&RETURN_LIST.to_le_bytes());
+        payload.push(1);
+        payload.extend_from_slice(Uuid::nil().as_bytes());
+        payload.push(1);
+        payload.extend_from_slice(owner.as_uuid().as_bytes());
+
+        let request = ParcelReturnObjects::parse(&payload).unwrap();
+        assert_eq!(request.agent_id, agent_id);
+        assert_eq!(request.local_id, 2);
+        assert_eq!(request.return_type, RETURN_LIST);
+        assert!(request.task_ids.is_empty());
+        assert_eq!(request.owner_ids, vec![owner]);
+        assert!(ParcelReturnObjects::parse(&payload[..payload.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,333 @@
+//! LayerData message encoding
+//!
+//! Terrain, wind and cloud fields reach the viewer as LayerData messages:
+//! 16x16 patches run through a DCT, quantized and bit-packed the same way
+//! for every layer. Wind is sent as two patches, the east and north
+//! components over the whole region, and clouds as one density patch.
+
+use crate::constants::packet_types;
+use mutsea_core::scene::Terrain;
+
+/// Side of a patch in samples
+pub const PATCH_SIZE: usize = 16;
+
+/// Samples in a patch
+const PATCH_AREA: usize = PATCH_SIZE * PATCH_SIZE;
+
+/// Row stride written in the group header
+const STRIDE: u32 = 264;
+
+/// Marks the end of the patch list
+const END_OF_PATCHES: u32 = 97;
+
+/// Land patches sent in one LayerData message, so that rough ones still
+/// fit in a packet
+pub const LAND_PATCHES_PER_MESSAGE: usize = 2;
+
+/// Coefficient codes
+const ZERO_CODE: u32 = 0x0;
+const ZERO_EOB: u32 = 0x2;
+const POSITIVE_VALUE: u32 = 0x6;
+const NEGATIVE_VALUE: u32 = 0x7;
+
+/// Bits per sample before the DCT
+const PREQUANT: u32 = 10;
+
+/// Layer type byte of a LayerData message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LayerType {
+    /// Terrain heights
+    Land = 0x4C,
+    /// Water heights
+    Water = 0x57,
+    /// Wind velocity
+    Wind = 0x37,
+    /// Cloud density
+    Cloud = 0x38,
+}
+
+/// One 16x16 patch of a layer, row-major from the south-west corner
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patch {
+    /// Column in patches, from the west
+    pub x: u32,
+    /// Row in patches, from the south
+    pub y: u32,
+    /// 16x16 samples, row-major
+    pub data: Vec<f32>,
+}
+
+impl Patch {
+    /// Patch at `x`, `y` holding `data`, which must be 16x16 samples
+    pub fn new(x: u32, y: u32, data: Vec<f32>) -> Self {
+        debug_assert_eq!(data.len(), PATCH_AREA);
+        Self { x, y, data }
+    }
+}
+
+/// Encode a LayerData message payload, starting with the message ID
+pub fn encode(layer_type: LayerType, patches: &[Patch]) -> Vec<u8> {
+    let tables = Tables::get();
+    let mut bits = BitPacker::default();
+    bits.pack(STRIDE, 16);
+    bits.pack(PATCH_SIZE as u32, 8);
+    bits.pack(layer_type as u32, 8);
+    for patch in patches {
+        encode_patch(&mut bits, tables, patch);
+    }
+    bits.pack(END_OF_PATCHES, 8);
+    let data = bits.into_bytes();
+
+    let mut payload = Vec::with_capacity(data.len() + 4);
+    payload.push(packet_types::LAYER_DATA as u8);
+    // LayerID block
+    payload.push(layer_type as u8);
+    // LayerData block (variable, 2-byte length)
+    payload.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    payload.extend_from_slice(&data);
+    payload
+}
+
+/// Wind LayerData: east and north components in metres per second, 16x16
+/// samples each covering the region
+pub fn encode_wind(east: &[f32], north: &[f32]) -> Vec<u8> {
+    encode(
+        LayerType::Wind,
+        &[Patch::new(0, 0, east.to_vec()), Patch::new(0, 0, north.to_vec())],
+    )
+}
+
+/// Cloud LayerData: 16x16 cloud densities, 0.0 to 1.0, covering the region
+pub fn encode_clouds(density: &[f32]) -> Vec<u8> {
+    encode(LayerType::Cloud, &[Patch::new(0, 0, density.to_vec())])
+}
+
+/// The patch of `terrain` at `x`, `y` in patches, for land LayerData
+pub fn land_patch(terrain: &Terrain, x: u32, y: u32) -> Patch {
+    let size = PATCH_SIZE as u32;
+    let data = (0..size)
+        .flat_map(|row| (0..size).map(move |column| (column, row)))
+        .map(|(column, row)| terrain.height_at(x * size + column, y * size + row))
+        .collect();
+    Patch::new(x, y, data)
+}
+
+fn encode_patch(bits: &mut BitPacker, tables: &Tables, patch: &Patch) {
+    let (min, max) = patch
+        .data
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(min, max), &v| (min.min(v), max.max(v)));
+    let dc_offset = min;
+    let range = ((max - min) + 1.0) as u32;
+
+    // Scale to PREQUANT bits centred on zero, then transform
+    let premult = (1u32 << PREQUANT) as f32 / range as f32;
+    let sub = (1u32 << (PREQUANT - 1)) as f32 + dc_offset * premult;
+    let block: Vec<f32> = patch.data.iter().map(|v| v * premult - sub).collect();
+    let coefficients = tables.dct(&block);
+
+    let wbits = word_bits(&coefficients);
+    let quant_wbits = ((PREQUANT - 2) << 4) | (wbits - 2);
+    bits.pack(quant_wbits, 8);
+    bits.pack(dc_offset.to_bits(), 32);
+    bits.pack(range, 16);
+    bits.pack((patch.x << 5) | (patch.y & 0x1F), 10);
+
+    let limit = 1i32 << wbits;
+    for (i, &coefficient) in coefficients.iter().enumerate() {
+        if coefficient == 0 {
+            if coefficients[i..].iter().all(|&c| c == 0) {
+                bits.pack(ZERO_EOB, 2);
+                return;
+            }
+            bits.pack(ZERO_CODE, 1);
+        } else {
+            let code = if coefficient < 0 { NEGATIVE_VALUE } else { POSITIVE_VALUE };
+            bits.pack(code, 3);
+            bits.pack(coefficient.abs().min(limit) as u32, wbits);
+        }
+    }
+}
+
+/// Bits needed for the largest coefficient, within what the header allows
+fn word_bits(coefficients: &[i32]) -> u32 {
+    let max_wbits = PREQUANT + 5;
+    let min_wbits = PREQUANT / 2;
+    let mut wbits = min_wbits;
+    for &coefficient in coefficients {
+        let magnitude = coefficient.unsigned_abs();
+        if let Some(bit) = (min_wbits + 1..=max_wbits).rev().find(|&bit| magnitude & (1 << bit) != 0) {
+            wbits = wbits.max(bit);
+        }
+    }
+    (wbits + 1).clamp(2, 17)
+}
+
+/// Cosine, quantization and zigzag tables shared by every patch
+struct Tables {
+    cosines: [f32; PATCH_AREA],
+    quantize: [f32; PATCH_AREA],
+    /// Zigzag position of each coefficient
+    zigzag: [usize; PATCH_AREA],
+}
+
+impl Tables {
+    fn get() -> &'static Tables {
+        static TABLES: std::sync::OnceLock<Tables> = std::sync::OnceLock::new();
+        TABLES.get_or_init(Tables::new)
+    }
+
+    fn new() -> Self {
+        let mut cosines = [0.0; PATCH_AREA];
+        let mut quantize = [0.0; PATCH_AREA];
+        let half_period = std::f32::consts::PI * 0.5 / PATCH_SIZE as f32;
+        for u in 0..PATCH_SIZE {
+            for n in 0..PATCH_SIZE {
+                cosines[u * PATCH_SIZE + n] = ((2 * n + 1) as f32 * u as f32 * half_period).cos();
+                quantize[u * PATCH_SIZE + n] = 1.0 / (1.0 + 2.0 * (u + n) as f32);
+            }
+        }
+
+        let mut zigzag = [0; PATCH_AREA];
+        let (mut i, mut j) = (0usize, 0usize);
+        let (mut diagonal, mut right) = (false, true);
+        for count in 0..PATCH_AREA {
+            zigzag[j * PATCH_SIZE + i] = count;
+            if !diagonal {
+                if right {
+                    if i < PATCH_SIZE - 1 { i += 1 } else { j += 1 }
+                } else if j < PATCH_SIZE - 1 {
+                    j += 1
+                } else {
+                    i += 1
+                }
+                right = !right;
+                diagonal = true;
+            } else if right {
+                i += 1;
+                j -= 1;
+                diagonal = !(i == PATCH_SIZE - 1 || j == 0);
+            } else {
+                i -= 1;
+                j += 1;
+                diagonal = !(j == PATCH_SIZE - 1 || i == 0);
+            }
+        }
+
+        Self { cosines, quantize, zigzag }
+    }
+
+    /// Quantized 2D DCT of `block`, in zigzag order
+    fn dct(&self, block: &[f32]) -> [i32; PATCH_AREA] {
+        let scale = 2.0 / PATCH_SIZE as f32;
+        let mut rows = [0.0f32; PATCH_AREA];
+        for line in 0..PATCH_SIZE {
+            let samples = &block[line * PATCH_SIZE..(line + 1) * PATCH_SIZE];
+            for u in 0..PATCH_SIZE {
+                rows[line * PATCH_SIZE + u] = if u == 0 {
+                    std::f32::consts::FRAC_1_SQRT_2 * samples.iter().sum::<f32>()
+                } else {
+                    samples.iter().enumerate().map(|(n, s)| s * self.cosines[u * PATCH_SIZE + n]).sum()
+                };
+            }
+        }
+
+        let mut out = [0i32; PATCH_AREA];
+        for column in 0..PATCH_SIZE {
+            let sample = |n: usize| rows[n * PATCH_SIZE + column];
+            for u in 0..PATCH_SIZE {
+                let total: f32 = if u == 0 {
+                    std::f32::consts::FRAC_1_SQRT_2 * (0..PATCH_SIZE).map(sample).sum::<f32>()
+                } else {
+                    (0..PATCH_SIZE).map(|n| sample(n) * self.cosines[u * PATCH_SIZE + n]).sum()
+                };
+                let index = u * PATCH_SIZE + column;
+                out[self.zigzag[index]] = (total * scale * self.quantize[index]) as i32;
+            }
+        }
+        out
+    }
+}
+
+/// Writes values MSB first, least significant byte of each value first, as
+/// viewers unpack LayerData
+#[derive(Debug, Default)]
+struct BitPacker {
+    bytes: Vec<u8>,
+    /// Bits used in the last byte; 8 when it is full
+    used: u32,
+}
+
+impl BitPacker {
+    fn pack(&mut self, value: u32, count: u32) {
+        let bytes = value.to_le_bytes();
+        let mut remaining = count;
+        for byte in bytes {
+            if remaining == 0 {
+                break;
+            }
+            let bits = remaining.min(8);
+            remaining -= bits;
+            for bit in (0..bits).rev() {
+                self.push_bit(byte & (1 << bit) != 0);
+            }
+        }
+    }
+
+    fn push_bit(&mut self, set: bool) {
+        if self.bytes.is_empty() || self.used == 8 {
+            self.bytes.push(0);
+            self.used = 0;
+        }
+        if set {
+            *self.bytes.last_mut().unwrap() |= 0x80 >> self.used;
+        }
+        self.used += 1;
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bit_packing_order() {
+        let mut bits = BitPacker::default();
+        bits.pack(0b101, 3);
+        bits.pack(0x0102, 16);
+        // 101 | 00000010 | 00000001, least significant byte first
+        assert_eq!(bits.into_bytes(), vec![0b1010_0000, 0b0100_0000, 0b0010_0000]);
+    }
+
+    #[test]
+    fn test_zigzag_covers_patch() {
+        let tables = Tables::get();
+        let mut seen = tables.zigzag.to_vec();
+        seen.sort_unstable();
+        assert_eq!(seen, (0..PATCH_AREA).collect::<Vec<_>>());
+        assert_eq!(&tables.zigzag[..3], &[0, 1, 5]);
+        assert_eq!(tables.zigzag[PATCH_SIZE], 2);
+    }
+
+    #[test]
+    fn test_encode_wind_layout() {
+        let east = vec![3.0; PATCH_AREA];
+        let north: Vec<f32> = (0..PATCH_AREA).map(|i| (i % PATCH_SIZE) as f32 * 0.5).collect();
+        let payload = encode_wind(&east, &north);
+
+        assert_eq!(payload[0], packet_types::LAYER_DATA as u8);
+        assert_eq!(payload[1], LayerType::Wind as u8);
+        let len = u16::from_le_bytes([payload[2], payload[3]]) as usize;
+        assert_eq!(payload.len(), 4 + len);
+        // Group header: stride 264 low byte first, patch size, layer type
+        assert_eq!(&payload[4..8], &[0x08, 0x01, 16, LayerType::Wind as u8]);
+        // First patch header: quantization then the DC offset as an f32
+        let dc_offset = f32::from_le_bytes(payload[9..13].try_into().unwrap());
+        assert_eq!(dc_offset, 3.0);
+    }
+}
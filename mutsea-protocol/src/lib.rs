@@ -12,6 +12,35 @@ pub mod packet;
 pub mod codec;
 pub mod caps;
 pub mod login;
+pub mod api_keys;
+pub mod bans;
+mod json_file;
+pub mod sim_stats;
+pub mod layer_data;
+pub mod terraform;
+pub mod environment;
+pub mod llsd;
+pub mod mesh;
+pub mod instant_message;
+pub mod dialog;
+pub mod economy;
+pub mod land;
+pub mod attachment;
+pub mod rez;
+pub mod object_update;
+pub mod follow_cam;
+pub mod chat;
+pub mod moderation;
+pub mod search;
+pub mod profile;
+pub mod directory;
+pub mod sound;
+pub mod snapshot;
+pub mod inventory;
+pub mod appearance;
+pub mod animation;
+pub mod names;
+pub mod event_queue;
 pub mod error;
 pub mod constants;
 
@@ -21,7 +50,7 @@ pub use packet::*;
 pub use codec::*;
 pub use constants::*;
 
-use mutsea_core::{MutseaResult, UserId, RegionId};
+use mutsea_core::UserId;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -170,7 +199,9 @@ impl LoginResponse {
         agent_id: UserId,
         first_name: String,
         last_name: String,
-        region_id: RegionId,
+        start: &login::StartLocation,
+        home: &login::AgentLocation,
+        start_location: &login::AgentLocation,
         sim_ip: String,
         sim_port: i32,
         circuit_code: u32,
@@ -184,19 +215,18 @@ impl LoginResponse {
             agent_id: agent_id.to_string(),
             first_name,
             last_name,
-            start_location: "home".to_string(),
-            look_at: "[r1,r1,r0]".to_string(),
+            start_location: start.as_response_str().to_string(),
+            look_at: start_location.to_look_at_string(),
             seed_capability,
             agent_access: "M".to_string(),
             agent_access_max: "A".to_string(),
-            inventory_host: "127.0.0.1".to_string(),
+            inventory_host: sim_ip.clone(),
             sim_ip,
             sim_port,
-            region_x: 1000,
-            region_y: 1000,
+            region_x: start_location.region_x_meters() as i32,
+            region_y: start_location.region_y_meters() as i32,
             circuit_code: circuit_code as i32,
-            home: format!("{{'region_handle':[r{},r{}], 'position':[r128,r128,r21], 'look_at':[r1,r0,r0]}}", 
-                          1000 * 256, 1000 * 256),
+            home: home.to_home_string(),
             message: "Welcome to Mutsea!".to_string(),
             seconds_since_epoch: chrono::Utc::now().timestamp(),
             event_categories: Vec::new(),
@@ -0,0 +1,377 @@
+//! LLSD serialization
+//!
+//! Capabilities answer viewers in LLSD. Responses are built as JSON values
+//! and written out with the LLSD type each maps to; strings holding a UUID
+//! are written as `<uuid>`. Binary LLSD, which assets such as meshes are
+//! stored in, is read into `Llsd` values so binary blocks survive intact.
+
+use crate::{ProtocolError, ProtocolResult};
+use serde_json::Value;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Header some writers put before a binary LLSD document
+const BINARY_HEADER: &[u8] = b"<? LLSD/Binary ?>\n";
+
+/// A value read from binary LLSD
+#[derive(Debug, Clone, PartialEq)]
+pub enum Llsd {
+    /// No value
+    Undef,
+    /// A boolean
+    Boolean(bool),
+    /// A 32 bit integer
+    Integer(i32),
+    /// A 64 bit real
+    Real(f64),
+    /// A UUID
+    Uuid(Uuid),
+    /// A string
+    String(String),
+    /// A URI
+    Uri(String),
+    /// Seconds since the Unix epoch
+    Date(f64),
+    /// Raw bytes
+    Binary(Vec<u8>),
+    /// An array
+    Array(Vec<Llsd>),
+    /// A map
+    Map(HashMap<String, Llsd>),
+}
+
+impl Llsd {
+    /// The value under `key` of a map
+    pub fn get(&self, key: &str) -> Option<&Llsd> {
+        match self {
+            Llsd::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    /// The value as an integer, converting reals and booleans
+    pub fn as_integer(&self) -> Option<i32> {
+        match self {
+            Llsd::Integer(i) => Some(*i),
+            Llsd::Real(r) => Some(*r as i32),
+            Llsd::Boolean(b) => Some(*b as i32),
+            _ => None,
+        }
+    }
+
+    /// The value as a real, converting integers
+    pub fn as_real(&self) -> Option<f64> {
+        match self {
+            Llsd::Real(r) => Some(*r),
+            Llsd::Integer(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    /// Whether the value is true, as LLSD converts other types to booleans
+    pub fn as_bool(&self) -> bool {
+        match self {
+            Llsd::Boolean(b) => *b,
+            Llsd::Integer(i) => *i != 0,
+            Llsd::Real(r) => *r != 0.0,
+            Llsd::String(s) => !s.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// The bytes of a binary value
+    pub fn as_binary(&self) -> Option<&[u8]> {
+        match self {
+            Llsd::Binary(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// The items of an array
+    pub fn as_array(&self) -> Option<&[Llsd]> {
+        match self {
+            Llsd::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Read the binary LLSD document at the start of `data`, answering the
+/// value and how many bytes it took up
+pub fn from_binary(data: &[u8]) -> ProtocolResult<(Llsd, usize)> {
+    let start = if data.starts_with(BINARY_HEADER) { BINARY_HEADER.len() } else { 0 };
+    let mut reader = BinaryReader { data, position: start };
+    let value = reader.value()?;
+    Ok((value, reader.position))
+}
+
+/// Write `value` as binary LLSD, without the header
+pub fn to_binary(value: &Llsd) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_binary(&mut out, value);
+    out
+}
+
+fn write_binary(out: &mut Vec<u8>, value: &Llsd) {
+    let sized = |out: &mut Vec<u8>, marker: u8, bytes: &[u8]| {
+        out.push(marker);
+        out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        out.extend_from_slice(bytes);
+    };
+    match value {
+        Llsd::Undef => out.push(b'!'),
+        Llsd::Boolean(b) => out.push(if *b { b'1' } else { b'0' }),
+        Llsd::Integer(i) => {
+            out.push(b'i');
+            out.extend_from_slice(&i.to_be_bytes());
+        }
+        Llsd::Real(r) => {
+            out.push(b'r');
+            out.extend_from_slice(&r.to_be_bytes());
+        }
+        Llsd::Date(d) => {
+            out.push(b'd');
+            out.extend_from_slice(&d.to_be_bytes());
+        }
+        Llsd::Uuid(id) => {
+            out.push(b'u');
+            out.extend_from_slice(id.as_bytes());
+        }
+        Llsd::String(s) => sized(out, b's', s.as_bytes()),
+        Llsd::Uri(s) => sized(out, b'l', s.as_bytes()),
+        Llsd::Binary(bytes) => sized(out, b'b', bytes),
+        Llsd::Array(items) => {
+            out.push(b'[');
+            out.extend_from_slice(&(items.len() as u32).to_be_bytes());
+            for item in items {
+                write_binary(out, item);
+            }
+            out.push(b']');
+        }
+        Llsd::Map(map) => {
+            out.push(b'{');
+            out.extend_from_slice(&(map.len() as u32).to_be_bytes());
+            for (key, item) in map {
+                sized(out, b'k', key.as_bytes());
+                write_binary(out, item);
+            }
+            out.push(b'}');
+        }
+    }
+}
+
+struct BinaryReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BinaryReader<'_> {
+    fn take(&mut self, len: usize) -> ProtocolResult<&[u8]> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| ProtocolError::Decoding(format!("Binary LLSD truncated at byte {}", self.position)))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> ProtocolResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> ProtocolResult<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f64(&mut self) -> ProtocolResult<f64> {
+        Ok(f64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> ProtocolResult<Vec<u8>> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn string(&mut self) -> ProtocolResult<String> {
+        String::from_utf8(self.bytes()?).map_err(|e| ProtocolError::Decoding(format!("Binary LLSD string: {}", e)))
+    }
+
+    fn close(&mut self, marker: u8) -> ProtocolResult<()> {
+        match self.byte()? {
+            found if found == marker => Ok(()),
+            found => Err(ProtocolError::Decoding(format!(
+                "Binary LLSD expected '{}', found {:#04x}",
+                marker as char, found
+            ))),
+        }
+    }
+
+    fn value(&mut self) -> ProtocolResult<Llsd> {
+        Ok(match self.byte()? {
+            b'!' => Llsd::Undef,
+            b'1' => Llsd::Boolean(true),
+            b'0' => Llsd::Boolean(false),
+            b'i' => Llsd::Integer(self.u32()? as i32),
+            b'r' => Llsd::Real(self.f64()?),
+            b'd' => Llsd::Date(self.f64()?),
+            b'u' => Llsd::Uuid(Uuid::from_slice(self.take(16)?).unwrap()),
+            b's' => Llsd::String(self.string()?),
+            b'l' => Llsd::Uri(self.string()?),
+            b'b' => Llsd::Binary(self.bytes()?),
+            b'[' => {
+                let count = self.u32()? as usize;
+                // Counts aren't trusted for preallocation
+                let mut items = Vec::with_capacity(count.min(1024));
+                for _ in 0..count {
+                    items.push(self.value()?);
+                }
+                self.close(b']')?;
+                Llsd::Array(items)
+            }
+            b'{' => {
+                let count = self.u32()? as usize;
+                let mut map = HashMap::with_capacity(count.min(1024));
+                for _ in 0..count {
+                    match self.byte()? {
+                        b'k' | b's' => {}
+                        found => {
+                            return Err(ProtocolError::Decoding(format!("Binary LLSD map key marker {:#04x}", found)))
+                        }
+                    }
+                    let key = self.string()?;
+                    map.insert(key, self.value()?);
+                }
+                self.close(b'}')?;
+                Llsd::Map(map)
+            }
+            found => return Err(ProtocolError::Decoding(format!("Unknown binary LLSD type {:#04x}", found))),
+        })
+    }
+}
+
+/// Serialize `value` as an LLSD XML document
+pub fn to_xml(value: &Value) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?><llsd>");
+    write_value(&mut out, value);
+    out.push_str("</llsd>");
+    out
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Null => out.push_str("<undef />"),
+        Value::Bool(b) => out.push_str(if *b { "<boolean>true</boolean>" } else { "<boolean>false</boolean>" }),
+        Value::Number(n) if n.is_f64() => out.push_str(&format!("<real>{}</real>", n)),
+        Value::Number(n) => out.push_str(&format!("<integer>{}</integer>", n)),
+        Value::String(s) if Uuid::parse_str(s).is_ok() => out.push_str(&format!("<uuid>{}</uuid>", s)),
+        Value::String(s) => out.push_str(&format!("<string>{}</string>", xml_escape(s))),
+        Value::Array(items) => {
+            out.push_str("<array>");
+            for item in items {
+                write_value(out, item);
+            }
+            out.push_str("</array>");
+        }
+        Value::Object(map) => {
+            out.push_str("<map>");
+            for (key, item) in map {
+                out.push_str(&format!("<key>{}</key>", xml_escape(key)));
+                write_value(out, item);
+            }
+            out.push_str("</map>");
+        }
+    }
+}
+
+/// Escape text for an XML element or attribute value
+pub fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The `<string>` values of an LLSD XML document, in the order they appear.
+/// Enough for requests that carry only strings, such as SetDisplayName.
+pub fn xml_strings(xml: &str) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<string") {
+        rest = &rest[start + "<string".len()..];
+        if let Some(empty) = rest.strip_prefix(" />").or_else(|| rest.strip_prefix("/>")) {
+            strings.push(String::new());
+            rest = empty;
+            continue;
+        }
+        let Some(open) = rest.strip_prefix('>') else {
+            continue;
+        };
+        let Some(end) = open.find("</string>") else {
+            break;
+        };
+        strings.push(unescape(&open[..end]));
+        rest = &open[end + "</string>".len()..];
+    }
+    strings
+}
+
+pub(crate) fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_types() {
+        let xml = to_xml(&json!([
+            { "regionID": Uuid::nil(), "name": "a<b", "flag": true },
+            [0.5, 3, null]
+        ]));
+        assert_eq!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><llsd><array>\
+             <map><key>flag</key><boolean>true</boolean><key>name</key><string>a&lt;b</string>\
+             <key>regionID</key><uuid>00000000-0000-0000-0000-000000000000</uuid></map>\
+             <array><real>0.5</real><integer>3</integer><undef /></array></array></llsd>"
+        );
+    }
+
+    #[test]
+    fn test_xml_strings() {
+        let xml = "<?xml version=\"1.0\" ?><llsd><map><key>display_name</key><array>\
+                   <string>Old</string><string>Fish &amp; Chips</string><string /></array></map></llsd>";
+        assert_eq!(xml_strings(xml), vec!["Old", "Fish & Chips", ""]);
+    }
+
+    #[test]
+    fn test_binary() {
+        let mut data = BINARY_HEADER.to_vec();
+        data.extend_from_slice(b"{\0\0\0\x03k\0\0\0\x04size");
+        data.extend_from_slice(b"i\0\0\x01\0");
+        data.extend_from_slice(b"k\0\0\0\x04data[\0\0\0\x02b\0\0\0\x02\xff\x01r");
+        data.extend_from_slice(&0.5f64.to_be_bytes());
+        data.extend_from_slice(b"]k\0\0\0\x04none!}trailing");
+        let (value, len) = from_binary(&data).unwrap();
+        assert_eq!(&data[len..], b"trailing");
+        assert_eq!(value.get("size").and_then(Llsd::as_integer), Some(256));
+        let items = value.get("data").and_then(Llsd::as_array).unwrap();
+        assert_eq!(items[0].as_binary(), Some(&[0xff, 0x01][..]));
+        assert_eq!(items[1].as_real(), Some(0.5));
+        assert_eq!(value.get("none"), Some(&Llsd::Undef));
+
+        assert!(from_binary(b"[\0\0\0\x05i").is_err());
+
+        let written = to_binary(&value);
+        assert_eq!(from_binary(&written).unwrap(), (value, written.len()));
+    }
+}
@@ -2,10 +2,15 @@
 //! Login service implementation
 //! Unified login service with full OpenSim compatibility
 
+use crate::bans::BanList;
+use crate::codec::MessageDecoder;
+use crate::llsd::xml_escape;
 use crate::{ProtocolError, ProtocolResult};
-use mutsea_core::{UserId, UserAccount};
+use mutsea_core::circuit_breaker::CircuitBreaker;
+use mutsea_core::{Maturity, RegionId, UserId, Vector3};
 use std::collections::HashMap;
-use std::sync::RwLock;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
 
@@ -13,6 +18,58 @@ use serde::{Deserialize, Serialize};
 pub struct LoginService {
     test_users: RwLock<HashMap<String, TestUser>>,
     active_sessions: RwLock<HashMap<String, SessionInfo>>,
+    grid_users: RwLock<HashMap<UserId, GridUserInfo>>,
+    regions: RwLock<HashMap<String, AgentLocation>>,
+    region_maturity: RwLock<HashMap<RegionId, Maturity>>,
+    default_access_max: Maturity,
+    default_location: RwLock<AgentLocation>,
+    sim_address: RwLock<SimAddress>,
+    bans: Arc<BanList>,
+    backend_breaker: Option<Arc<CircuitBreaker>>,
+}
+
+/// Simulator addresses handed to viewers at login. A region may advertise
+/// an IPv4 address, an IPv6 address or both; each login gets the one
+/// matching the family the viewer connected with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimAddress {
+    /// IPv4 address for viewers connecting over IPv4
+    pub ipv4: Option<Ipv4Addr>,
+    /// IPv6 address for viewers connecting over IPv6
+    pub ipv6: Option<Ipv6Addr>,
+    /// LLUDP port
+    pub port: u16,
+    /// Public base URL of the HTTP server, ending in `/`
+    pub caps_base: String,
+}
+
+impl SimAddress {
+    /// Address to send to a viewer connecting from `client_ip`. IPv6
+    /// viewers get the IPv6 address when one is configured; everyone else
+    /// gets IPv4, falling back to whichever family is available.
+    pub fn select(&self, client_ip: Option<IpAddr>) -> IpAddr {
+        let ipv6_client = matches!(client_ip, Some(IpAddr::V6(ip)) if ip.to_ipv4_mapped().is_none());
+        let v4 = self.ipv4.map(IpAddr::V4);
+        let v6 = self.ipv6.map(IpAddr::V6);
+        let preferred = if ipv6_client { v6.or(v4) } else { v4.or(v6) };
+        preferred.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+    }
+
+    /// Seed capability URL for a new session
+    pub fn seed_capability(&self, id: Uuid) -> String {
+        format!("{}caps/{}/", self.caps_base, id)
+    }
+}
+
+impl Default for SimAddress {
+    fn default() -> Self {
+        Self {
+            ipv4: Some(Ipv4Addr::LOCALHOST),
+            ipv6: None,
+            port: 9000,
+            caps_base: "http://127.0.0.1:8080/".to_string(),
+        }
+    }
 }
 
 /// Test user for development and testing
@@ -24,6 +81,8 @@ struct TestUser {
     user_id: UserId,
     email: Option<String>,
     is_admin: bool,
+    /// Highest content rating the user may enter, if set for them
+    access_max: Option<Maturity>,
     created_at: chrono::DateTime<chrono::Utc>,
 }
 
@@ -33,10 +92,217 @@ struct SessionInfo {
     session_id: String,
     user_id: UserId,
     agent_id: UserId,
+    /// ID of the session's seed capability, which its caps are under
+    seed_cap: Uuid,
     created_at: chrono::DateTime<chrono::Utc>,
     last_activity: chrono::DateTime<chrono::Utc>,
 }
 
+/// A position inside a region, used for home, last and start locations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentLocation {
+    /// Region the location is in
+    pub region_id: RegionId,
+    /// Name of that region
+    pub region_name: String,
+    /// Region grid X coordinate (in region units, not meters)
+    pub region_x: u32,
+    /// Region grid Y coordinate (in region units, not meters)
+    pub region_y: u32,
+    /// Position in region coordinates
+    pub position: Vector3,
+    /// Direction to face
+    pub look_at: Vector3,
+}
+
+impl AgentLocation {
+    /// Create a location at the given region and position
+    pub fn new(region_id: RegionId, region_name: String, region_x: u32, region_y: u32, position: Vector3) -> Self {
+        Self {
+            region_id,
+            region_name,
+            region_x,
+            region_y,
+            position,
+            look_at: Vector3::new(1.0, 0.0, 0.0),
+        }
+    }
+
+    /// Region X position in meters, as sent to viewers
+    pub fn region_x_meters(&self) -> u32 {
+        self.region_x * 256
+    }
+
+    /// Region Y position in meters, as sent to viewers
+    pub fn region_y_meters(&self) -> u32 {
+        self.region_y * 256
+    }
+
+    /// 64-bit region handle (X meters in the high word, Y meters in the low word)
+    pub fn region_handle(&self) -> u64 {
+        ((self.region_x_meters() as u64) << 32) | self.region_y_meters() as u64
+    }
+
+    /// Format as the LLSD-ish `home` string expected by viewers
+    pub fn to_home_string(&self) -> String {
+        format!(
+            "{{'region_handle':[r{},r{}], 'position':[r{},r{},r{}], 'look_at':[r{},r{},r{}]}}",
+            self.region_x_meters(), self.region_y_meters(),
+            self.position.x, self.position.y, self.position.z,
+            self.look_at.x, self.look_at.y, self.look_at.z
+        )
+    }
+
+    /// Format the look-at vector as expected in the `look_at` login field
+    pub fn to_look_at_string(&self) -> String {
+        format!("[r{},r{},r{}]", self.look_at.x, self.look_at.y, self.look_at.z)
+    }
+}
+
+impl Default for AgentLocation {
+    fn default() -> Self {
+        Self::new(
+            RegionId::from_uuid(Uuid::nil()),
+            "Mutsea Region".to_string(),
+            1000,
+            1000,
+            Vector3::new(128.0, 128.0, 21.0),
+        )
+    }
+}
+
+/// Persistent per-user grid state (mirrors OpenSim's GridUser table)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GridUserInfo {
+    /// User the record is for
+    pub user_id: UserId,
+    /// Home location, once set
+    pub home: Option<AgentLocation>,
+    /// Where the user last logged out, once it has
+    pub last: Option<AgentLocation>,
+    /// Whether the user is logged in
+    pub online: bool,
+    /// Number of logins
+    pub login_count: u32,
+    /// Time of the latest login
+    pub last_login: Option<chrono::DateTime<chrono::Utc>>,
+    /// Time of the latest logout
+    pub last_logout: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl GridUserInfo {
+    /// Create an empty grid user record
+    pub fn new(user_id: UserId) -> Self {
+        Self {
+            user_id,
+            home: None,
+            last: None,
+            online: false,
+            login_count: 0,
+            last_login: None,
+            last_logout: None,
+        }
+    }
+}
+
+/// Requested start location from the viewer's `start` login parameter
+#[derive(Debug, Clone, PartialEq)]
+pub enum StartLocation {
+    /// Start at the user's home location
+    Home,
+    /// Start at the user's last location
+    Last,
+    /// Start at a named region, e.g. `uri:Region Name&128&128&25`
+    Region {
+        /// Region name
+        name: String,
+        /// Where in the region, in region coordinates
+        position: Vector3,
+    },
+}
+
+impl StartLocation {
+    /// Parse the viewer's `start` value; unknown values fall back to `Last`
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("home") {
+            return StartLocation::Home;
+        }
+        if value.eq_ignore_ascii_case("last") || value.is_empty() {
+            return StartLocation::Last;
+        }
+
+        let Some(uri) = value.strip_prefix("uri:") else {
+            return StartLocation::Last;
+        };
+
+        let mut parts = uri.split('&');
+        let name = parts.next().unwrap_or_default().trim().to_string();
+        if name.is_empty() {
+            return StartLocation::Last;
+        }
+
+        let mut coord = |default: f32| {
+            parts
+                .next()
+                .and_then(|p| p.trim().parse::<f32>().ok())
+                .unwrap_or(default)
+        };
+        let x = coord(128.0);
+        let y = coord(128.0);
+        let z = coord(0.0);
+
+        StartLocation::Region {
+            name,
+            position: Vector3::new(x, y, z),
+        }
+    }
+
+    /// Value echoed back to the viewer in the `start_location` field
+    pub fn as_response_str(&self) -> &'static str {
+        match self {
+            StartLocation::Home => "home",
+            StartLocation::Last => "last",
+            StartLocation::Region { .. } => "url",
+        }
+    }
+}
+
+/// A viewer's request to make where its agent stands its home
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetStartLocationRequest {
+    /// The agent setting its home
+    pub agent_id: UserId,
+    /// The agent's session
+    pub session_id: Uuid,
+    /// The region the viewer thinks it is in
+    pub sim_name: String,
+    /// Which start location is set; home is 1
+    pub location_id: u32,
+    /// Region-local position of the new home
+    pub position: Vector3,
+    /// Direction the agent faces there
+    pub look_at: Vector3,
+}
+
+impl SetStartLocationRequest {
+    /// Parse a SetStartLocationRequest payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        let session_id = decoder.read_uuid()?;
+        let sim_name = decoder.read_string()?;
+        Ok(Self {
+            agent_id,
+            session_id,
+            sim_name: sim_name.trim_end_matches('\0').to_string(),
+            location_id: decoder.read_u32()?,
+            position: decoder.read_vector3()?,
+            look_at: decoder.read_vector3()?,
+        })
+    }
+}
+
 /// Parsed login request structure for XMLRPC compatibility
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParsedLoginRequest {
@@ -107,9 +373,258 @@ impl LoginService {
         Self {
             test_users: RwLock::new(HashMap::new()),
             active_sessions: RwLock::new(HashMap::new()),
+            grid_users: RwLock::new(HashMap::new()),
+            regions: RwLock::new(HashMap::new()),
+            region_maturity: RwLock::new(HashMap::new()),
+            default_access_max: Maturity::Adult,
+            default_location: RwLock::new(AgentLocation::default()),
+            sim_address: RwLock::new(SimAddress::default()),
+            bans: Arc::new(BanList::in_memory()),
+            backend_breaker: None,
+        }
+    }
+
+    /// Refuse logins while `breaker` (the user database's) is open, rather
+    /// than letting viewers time out against a degraded backend
+    pub fn with_backend_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.backend_breaker = Some(breaker);
+        self
+    }
+
+    /// Advertise `address` to viewers instead of the loopback default
+    pub fn with_sim_address(self, address: SimAddress) -> Self {
+        self.set_sim_address(address);
+        self
+    }
+
+    /// Replace the advertised simulator address, e.g. after the external
+    /// address has been detected
+    pub fn set_sim_address(&self, address: SimAddress) {
+        *self.sim_address.write().unwrap() = address;
+    }
+
+    /// Simulator address currently advertised at login
+    pub fn sim_address(&self) -> SimAddress {
+        self.sim_address.read().unwrap().clone()
+    }
+
+    /// Use a shared (usually file-backed) ban list
+    pub fn with_ban_list(mut self, bans: Arc<BanList>) -> Self {
+        self.bans = bans;
+        self
+    }
+
+    /// Ban list enforced at login and region entry
+    pub fn bans(&self) -> &Arc<BanList> {
+        &self.bans
+    }
+
+    /// Register a region that logins can be routed to by name
+    pub fn register_region(&self, location: AgentLocation) {
+        let key = location.region_name.to_lowercase();
+        self.regions.write().unwrap().insert(key, location);
+    }
+
+    /// Rate the region `region_id`; unrated regions are PG
+    pub fn set_region_maturity(&self, region_id: RegionId, maturity: Maturity) {
+        self.region_maturity.write().unwrap().insert(region_id, maturity);
+    }
+
+    /// Content rating of the region `region_id`
+    pub fn region_maturity(&self, region_id: &RegionId) -> Maturity {
+        self.region_maturity.read().unwrap().get(region_id).copied().unwrap_or_default()
+    }
+
+    /// The registered region with `region_handle`, for teleports
+    pub fn region_by_handle(&self, region_handle: u64) -> Option<AgentLocation> {
+        self.regions
+            .read()
+            .unwrap()
+            .values()
+            .find(|region| region.region_handle() == region_handle)
+            .cloned()
+    }
+
+    /// Let users who haven't been given a rating enter regions rated up to
+    /// `maturity`
+    pub fn with_default_access_max(mut self, maturity: Maturity) -> Self {
+        self.default_access_max = maturity;
+        self
+    }
+
+    /// Highest content rating the user `user_id` may enter
+    pub fn access_max(&self, user_id: &UserId) -> Maturity {
+        self.test_users
+            .read()
+            .unwrap()
+            .values()
+            .find(|user| user.user_id == *user_id)
+            .and_then(|user| user.access_max)
+            .unwrap_or(self.default_access_max)
+    }
+
+    /// Set the highest content rating the user `user_id` may enter.
+    /// Returns false for unknown users.
+    pub fn set_access_max(&self, user_id: &UserId, maturity: Maturity) -> bool {
+        let mut users = self.test_users.write().unwrap();
+        match users.values_mut().find(|user| user.user_id == *user_id) {
+            Some(user) => {
+                user.access_max = Some(maturity);
+                true
+            }
+            None => false,
         }
     }
 
+    /// Whether the user `user_id` may enter the region `region_id`
+    pub fn may_enter(&self, user_id: &UserId, region_id: &RegionId) -> bool {
+        self.region_maturity(region_id) <= self.access_max(user_id)
+    }
+
+    /// Regions registered for login routing
+    pub fn list_regions(&self) -> Vec<AgentLocation> {
+        self.regions.read().unwrap().values().cloned().collect()
+    }
+
+    /// Set the fallback location used when a user has no home or last location
+    pub fn set_default_location(&self, location: AgentLocation) {
+        *self.default_location.write().unwrap() = location;
+    }
+
+    /// The fallback location used when a user has no home or last location
+    pub fn default_location(&self) -> AgentLocation {
+        self.default_location.read().unwrap().clone()
+    }
+
+    /// Load a persisted grid user record (e.g. from the `griduser` table)
+    pub fn load_grid_user(&self, grid_user: GridUserInfo) {
+        self.grid_users.write().unwrap().insert(grid_user.user_id, grid_user);
+    }
+
+    /// Get the grid user record for a user, if any
+    pub fn get_grid_user(&self, user_id: &UserId) -> Option<GridUserInfo> {
+        self.grid_users.read().unwrap().get(user_id).cloned()
+    }
+
+    /// Set a user's home location
+    pub fn set_home_location(&self, user_id: UserId, location: AgentLocation) {
+        self.grid_users
+            .write()
+            .unwrap()
+            .entry(user_id)
+            .or_insert_with(|| GridUserInfo::new(user_id))
+            .home = Some(location);
+    }
+
+    /// Record a user's last location (called on logout or region departure)
+    pub fn set_last_location(&self, user_id: UserId, location: AgentLocation) {
+        self.grid_users
+            .write()
+            .unwrap()
+            .entry(user_id)
+            .or_insert_with(|| GridUserInfo::new(user_id))
+            .last = Some(location);
+    }
+
+    /// Mark a user as logged out, storing where they left
+    pub fn record_logout(&self, user_id: UserId, location: Option<AgentLocation>) {
+        let mut grid_users = self.grid_users.write().unwrap();
+        let grid_user = grid_users
+            .entry(user_id)
+            .or_insert_with(|| GridUserInfo::new(user_id));
+        grid_user.online = false;
+        grid_user.last_logout = Some(chrono::Utc::now());
+        if location.is_some() {
+            grid_user.last = location;
+        }
+    }
+
+    /// Number of users currently marked online
+    pub fn online_count(&self) -> usize {
+        self.grid_users.read().unwrap().values().filter(|g| g.online).count()
+    }
+
+    /// Resolve the home and start locations for a login
+    fn resolve_locations(&self, user_id: &UserId, start: &StartLocation) -> (AgentLocation, AgentLocation) {
+        let default_location = self.default_location.read().unwrap().clone();
+        let grid_user = self.get_grid_user(user_id);
+
+        let home = grid_user
+            .as_ref()
+            .and_then(|g| g.home.clone())
+            .unwrap_or_else(|| default_location.clone());
+
+        let start_location = match start {
+            StartLocation::Home => home.clone(),
+            StartLocation::Last => grid_user
+                .as_ref()
+                .and_then(|g| g.last.clone())
+                .unwrap_or_else(|| home.clone()),
+            StartLocation::Region { name, position } => {
+                match self.regions.read().unwrap().get(&name.to_lowercase()) {
+                    Some(region) => AgentLocation {
+                        position: *position,
+                        ..region.clone()
+                    },
+                    None => {
+                        tracing::warn!("Unknown start region '{}', using home location", name);
+                        home.clone()
+                    }
+                }
+            }
+        };
+
+        (home, start_location)
+    }
+
+    /// Mark a user as logged in and bump their login count
+    fn record_login(&self, user_id: UserId) {
+        let mut grid_users = self.grid_users.write().unwrap();
+        let grid_user = grid_users
+            .entry(user_id)
+            .or_insert_with(|| GridUserInfo::new(user_id));
+        grid_user.online = true;
+        grid_user.login_count += 1;
+        grid_user.last_login = Some(chrono::Utc::now());
+    }
+
+    /// Create a user account, rejecting duplicate names
+    pub fn create_user(
+        &self,
+        first_name: &str,
+        last_name: &str,
+        password: &str,
+        email: Option<String>,
+        is_admin: bool,
+    ) -> ProtocolResult<UserId> {
+        let key = format!("{} {}", first_name, last_name);
+        let mut users = self.test_users.write().unwrap();
+        if users.keys().any(|k| k.eq_ignore_ascii_case(&key)) {
+            return Err(ProtocolError::Generic(format!("User {} already exists", key)));
+        }
+
+        let user_id = UserId::new();
+        users.insert(key, TestUser {
+            first_name: first_name.to_string(),
+            last_name: last_name.to_string(),
+            password: password.to_string(),
+            user_id,
+            email,
+            is_admin,
+            access_max: None,
+            created_at: chrono::Utc::now(),
+        });
+        tracing::info!("Created user: {} {}", first_name, last_name);
+
+        Ok(user_id)
+    }
+
+    /// Check whether a user with this name exists (case-insensitive)
+    pub fn user_exists(&self, first_name: &str, last_name: &str) -> bool {
+        let key = format!("{} {}", first_name, last_name);
+        self.test_users.read().unwrap().keys().any(|k| k.eq_ignore_ascii_case(&key))
+    }
+
     /// Add a test user
     pub fn add_test_user(&self, first_name: String, last_name: String, password: String) {
         let key = format!("{} {}", first_name, last_name);
@@ -120,6 +635,7 @@ impl LoginService {
             user_id: UserId::new(),
             email: None,
             is_admin: false,
+            access_max: None,
             created_at: chrono::Utc::now(),
         };
 
@@ -129,31 +645,77 @@ impl LoginService {
 
     /// Process OpenSim-compatible login request
     pub fn authenticate(&self, request: &ParsedLoginRequest) -> ProtocolResult<OpenSimLoginResponse> {
+        self.authenticate_from(request, None)
+    }
+
+    /// Authenticate a login from `client_ip`, refusing banned accounts,
+    /// addresses and viewer hardware
+    #[tracing::instrument(
+        name = "login.authenticate",
+        skip_all,
+        fields(first = %request.first, last = %request.last, client_ip = ?client_ip, agent_id = tracing::field::Empty)
+    )]
+    pub fn authenticate_from(
+        &self,
+        request: &ParsedLoginRequest,
+        client_ip: Option<IpAddr>,
+    ) -> ProtocolResult<OpenSimLoginResponse> {
+        if let Some(retry_after) = self.backend_breaker.as_ref().and_then(|b| b.retry_after()) {
+            tracing::warn!("Refused login for {} {}: backend circuit open", request.first, request.last);
+            return Ok(OpenSimLoginResponse::failure(format!(
+                "The grid is temporarily unable to reach its user database. Please try again in {} seconds.",
+                retry_after.as_secs().max(1)
+            )));
+        }
+
         let user_key = format!("{} {}", request.first, request.last);
 
         let users = self.test_users.read().unwrap();
         if let Some(user) = users.get(&user_key) {
             if user.password == request.passwd {
+                if let Some(ban) = self.bans.check_login(user.user_id, client_ip, &request.mac, &request.id0) {
+                    tracing::warn!("Refused banned login for {} ({})", user_key, ban.id);
+                    return Ok(OpenSimLoginResponse::failure(ban.message()));
+                }
+
+                let start = StartLocation::parse(&request.start);
+                let (home, start_location) = self.resolve_locations(&user.user_id, &start);
+                let access_max = user.access_max.unwrap_or(self.default_access_max);
+                let region_maturity = self.region_maturity(&start_location.region_id);
+                if region_maturity > access_max {
+                    tracing::info!(
+                        "Refused login for {} to {} region {}",
+                        user_key, region_maturity, start_location.region_name
+                    );
+                    return Ok(OpenSimLoginResponse::failure(format!(
+                        "{} is rated {}, above your maturity setting ({}). Please choose another start location.",
+                        start_location.region_name, region_maturity, access_max
+                    )));
+                }
+
                 // Successful login
                 let session_id = Uuid::new_v4();
                 let secure_session_id = Uuid::new_v4();
                 let circuit_code = rand::random::<u32>();
+                tracing::Span::current().record("agent_id", tracing::field::display(user.user_id));
 
                 // Store session for validation
+                let seed_cap = Uuid::new_v4();
                 let session_info = SessionInfo {
                     session_id: session_id.to_string(),
                     user_id: user.user_id,
                     agent_id: user.user_id, // Using same ID for simplicity
+                    seed_cap,
                     created_at: chrono::Utc::now(),
                     last_activity: chrono::Utc::now(),
                 };
                 
                 self.active_sessions.write().unwrap().insert(session_id.to_string(), session_info);
 
-                let seed_capability = format!(
-                    "http://127.0.0.1:8080/caps/{}/",
-                    Uuid::new_v4()
-                );
+                let sim_address = self.sim_address();
+                let seed_capability = sim_address.seed_capability(seed_cap);
+
+                self.record_login(user.user_id);
 
                 Ok(OpenSimLoginResponse::success(
                     session_id,
@@ -161,12 +723,15 @@ impl LoginService {
                     user.user_id,
                     user.first_name.clone(),
                     user.last_name.clone(),
-                    mutsea_core::RegionId::new(),
-                    "127.0.0.1".to_string(),
-                    9000, // LLUDP port
+                    &start,
+                    &home,
+                    &start_location,
+                    sim_address.select(client_ip).to_string(),
+                    sim_address.port as i32,
                     circuit_code,
                     seed_capability,
-                ))
+                )
+                .with_agent_access(access_max))
             } else {
                 Ok(OpenSimLoginResponse::failure("Invalid password".to_string()))
             }
@@ -176,6 +741,7 @@ impl LoginService {
     }
 
     /// Validate session for LLUDP circuit authentication
+    #[tracing::instrument(name = "login.validate_session", level = "debug", skip(self))]
     pub fn validate_session(&self, session_id: &str, agent_id: &UserId) -> bool {
         if let Ok(sessions) = self.active_sessions.read() {
             if let Some(session_info) = sessions.get(session_id) {
@@ -187,6 +753,19 @@ impl LoginService {
         false
     }
 
+    /// The agent whose live session has the seed capability `cap_id`
+    pub fn capability_agent(&self, cap_id: &Uuid) -> Option<UserId> {
+        let now = chrono::Utc::now();
+        self.active_sessions
+            .read()
+            .unwrap()
+            .values()
+            .find(|session| {
+                session.seed_cap == *cap_id && now.signed_duration_since(session.created_at).num_hours() < 24
+            })
+            .map(|session| session.agent_id)
+    }
+
     /// Update session activity
     pub fn update_session_activity(&self, session_id: &str) {
         if let Ok(mut sessions) = self.active_sessions.write() {
@@ -217,6 +796,70 @@ impl LoginService {
         self.test_users.read().unwrap().get(&user_key).map(|user| user.user_id)
     }
 
+    /// Name of the user with `user_id`, as "First Last"
+    pub fn get_user_name(&self, user_id: &UserId) -> Option<String> {
+        self.test_users
+            .read()
+            .unwrap()
+            .iter()
+            .find(|(_, user)| user.user_id == *user_id)
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Up to `limit` users whose first or last name starts with each word
+    /// of `query`, ignoring case, as (ID, first name, last name) sorted by
+    /// name
+    pub fn search_users(&self, query: &str, limit: usize) -> Vec<(UserId, String, String)> {
+        let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        if words.is_empty() {
+            return Vec::new();
+        }
+        let mut found: Vec<_> = self
+            .test_users
+            .read()
+            .unwrap()
+            .values()
+            .filter(|user| {
+                let (first, last) = (user.first_name.to_lowercase(), user.last_name.to_lowercase());
+                words.iter().all(|word| first.starts_with(word.as_str()) || last.starts_with(word.as_str()))
+            })
+            .map(|user| (user.user_id, user.first_name.clone(), user.last_name.clone()))
+            .collect();
+        found.sort_by(|a, b| (&a.1, &a.2).cmp(&(&b.1, &b.2)));
+        found.truncate(limit);
+        found
+    }
+
+    /// When the user with `user_id` was created, shown as its birth date
+    pub fn user_created(&self, user_id: &UserId) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.test_users
+            .read()
+            .unwrap()
+            .values()
+            .find(|user| user.user_id == *user_id)
+            .map(|user| user.created_at)
+    }
+
+    /// Whether the user with `user_id` is a grid administrator
+    pub fn is_admin(&self, user_id: &UserId) -> bool {
+        self.test_users
+            .read()
+            .unwrap()
+            .values()
+            .any(|user| user.user_id == *user_id && user.is_admin)
+    }
+
+    /// Check a user's password without starting a viewer session
+    pub fn verify_password(&self, first_name: &str, last_name: &str, password: &str) -> Option<UserId> {
+        let user_key = format!("{} {}", first_name, last_name);
+        self.test_users
+            .read()
+            .unwrap()
+            .get(&user_key)
+            .filter(|user| user.password == password)
+            .map(|user| user.user_id)
+    }
+
     /// Get active sessions count
     pub fn get_active_sessions_count(&self) -> usize {
         self.active_sessions.read().unwrap().len()
@@ -246,35 +889,34 @@ impl ParsedLoginRequest {
         };
 
         // Extract values from XMLRPC (simplified parsing)
-        if let Some(first_start) = xml.find("<name>first</name>") {
-            if let Some(value_start) = xml[first_start..].find("<value><string>") {
-                let start_pos = first_start + value_start + 14;
-                if let Some(value_end) = xml[start_pos..].find("</string></value>") {
-                    request.first = xml[start_pos..start_pos + value_end].to_string();
-                }
-            }
+        if let Some(first) = Self::extract_string_member(xml, "first") {
+            request.first = first;
         }
 
-        if let Some(last_start) = xml.find("<name>last</name>") {
-            if let Some(value_start) = xml[last_start..].find("<value><string>") {
-                let start_pos = last_start + value_start + 14;
-                if let Some(value_end) = xml[start_pos..].find("</string></value>") {
-                    request.last = xml[start_pos..start_pos + value_end].to_string();
-                }
-            }
+        if let Some(last) = Self::extract_string_member(xml, "last") {
+            request.last = last;
         }
 
-        if let Some(passwd_start) = xml.find("<name>passwd</name>") {
-            if let Some(value_start) = xml[passwd_start..].find("<value><string>") {
-                let start_pos = passwd_start + value_start + 14;
-                if let Some(value_end) = xml[start_pos..].find("</string></value>") {
-                    request.passwd = xml[start_pos..start_pos + value_end].to_string();
-                }
-            }
+        if let Some(passwd) = Self::extract_string_member(xml, "passwd") {
+            request.passwd = passwd;
+        }
+
+        if let Some(start) = Self::extract_string_member(xml, "start") {
+            request.start = start;
         }
 
         Ok(request)
     }
+
+    /// Extract a `<string>` member value from an XMLRPC struct
+    fn extract_string_member(xml: &str, name: &str) -> Option<String> {
+        const VALUE_OPEN: &str = "<value><string>";
+        let member_start = xml.find(&format!("<name>{}</name>", name))?;
+        let value_start = xml[member_start..].find(VALUE_OPEN)?;
+        let start_pos = member_start + value_start + VALUE_OPEN.len();
+        let value_end = xml[start_pos..].find("</string></value>")?;
+        Some(xml[start_pos..start_pos + value_end].to_string())
+    }
 }
 
 impl OpenSimLoginResponse {
@@ -285,7 +927,9 @@ impl OpenSimLoginResponse {
         agent_id: UserId,
         first_name: String,
         last_name: String,
-        region_id: mutsea_core::RegionId,
+        start: &StartLocation,
+        home: &AgentLocation,
+        start_location: &AgentLocation,
         sim_ip: String,
         sim_port: i32,
         circuit_code: u32,
@@ -299,19 +943,18 @@ impl OpenSimLoginResponse {
             agent_id: Some(agent_id.to_string()),
             first_name: Some(first_name),
             last_name: Some(last_name),
-            start_location: Some("home".to_string()),
-            look_at: Some("[r1,r1,r0]".to_string()),
+            start_location: Some(start.as_response_str().to_string()),
+            look_at: Some(start_location.to_look_at_string()),
             seed_capability: Some(seed_capability),
             agent_access: Some("M".to_string()),
             agent_access_max: Some("A".to_string()),
-            inventory_host: Some("127.0.0.1".to_string()),
+            inventory_host: Some(sim_ip.clone()),
             sim_ip: Some(sim_ip),
             sim_port: Some(sim_port),
-            region_x: Some(1000),
-            region_y: Some(1000),
+            region_x: Some(start_location.region_x_meters() as i32),
+            region_y: Some(start_location.region_y_meters() as i32),
             circuit_code: Some(circuit_code as i32),
-            home: Some(format!("{{'region_handle':[r{},r{}], 'position':[r128,r128,r21], 'look_at':[r1,r0,r0]}}",
-                               1000 * 256, 1000 * 256)),
+            home: Some(home.to_home_string()),
             message: "Welcome to Mutsea!".to_string(),
             seconds_since_epoch: chrono::Utc::now().timestamp(),
             event_categories: Vec::new(),
@@ -327,6 +970,13 @@ impl OpenSimLoginResponse {
         }
     }
 
+    /// Tell the viewer the highest content rating the agent may enter
+    pub fn with_agent_access(mut self, access_max: Maturity) -> Self {
+        self.agent_access = Some(access_max.access_letter().to_string());
+        self.agent_access_max = Some(access_max.access_letter().to_string());
+        self
+    }
+
     /// Create failed login response
     pub fn failure(reason: String) -> Self {
         Self {
@@ -401,6 +1051,22 @@ impl OpenSimLoginResponse {
                         <name>start_location</name>
                         <value><string>{}</string></value>
                     </member>
+                    <member>
+                        <name>look_at</name>
+                        <value><string>{}</string></value>
+                    </member>
+                    <member>
+                        <name>home</name>
+                        <value><string>{}</string></value>
+                    </member>
+                    <member>
+                        <name>region_x</name>
+                        <value><i4>{}</i4></value>
+                    </member>
+                    <member>
+                        <name>region_y</name>
+                        <value><i4>{}</i4></value>
+                    </member>
                     <member>
                         <name>sim_ip</name>
                         <value><string>{}</string></value>
@@ -449,6 +1115,10 @@ impl OpenSimLoginResponse {
                     self.first_name.as_ref().unwrap_or(&"".to_string()),
                     self.last_name.as_ref().unwrap_or(&"".to_string()),
                     self.start_location.as_ref().unwrap_or(&"home".to_string()),
+                    self.look_at.as_ref().unwrap_or(&"".to_string()),
+                    xml_escape(self.home.as_ref().unwrap_or(&"".to_string())),
+                    self.region_x.unwrap_or(0),
+                    self.region_y.unwrap_or(0),
                     self.sim_ip.as_ref().unwrap_or(&"127.0.0.1".to_string()),
                     self.sim_port.unwrap_or(9000),
                     self.circuit_code.unwrap_or(0),
@@ -479,8 +1149,8 @@ impl OpenSimLoginResponse {
         </param>
     </params>
 </methodResponse>"#,
-                    self.reason,
-                    self.message
+                    xml_escape(&self.reason),
+                    xml_escape(&self.message)
             )
         }
     }
@@ -553,5 +1223,240 @@ mod tests {
                 assert!(service.validate_session(session_id, &agent_id));
             }
         }
+
+        let seed = response.seed_capability.unwrap();
+        let cap_id = Uuid::parse_str(seed.trim_end_matches('/').rsplit('/').next().unwrap()).unwrap();
+        let agent_id = UserId::from_uuid(Uuid::parse_str(&response.agent_id.unwrap()).unwrap());
+        assert_eq!(service.capability_agent(&cap_id), Some(agent_id));
+        assert_eq!(service.capability_agent(&Uuid::new_v4()), None);
+    }
+
+    #[test]
+    fn test_search_users_by_name_prefixes() {
+        let service = LoginService::new();
+        let ada = service.create_user("Ada", "Lovelace", "secret", None, false).unwrap();
+        service.create_user("Adam", "Resident", "secret", None, false).unwrap();
+        service.create_user("Grace", "Hopper", "secret", None, false).unwrap();
+
+        let names = |query: &str| -> Vec<String> {
+            service
+                .search_users(query, 10)
+                .into_iter()
+                .map(|(_, first, last)| format!("{} {}", first, last))
+                .collect()
+        };
+        assert_eq!(names("ada"), vec!["Ada Lovelace", "Adam Resident"]);
+        assert_eq!(names("ADA love"), vec!["Ada Lovelace"]);
+        assert_eq!(names("hop"), vec!["Grace Hopper"]);
+        assert!(names("  ").is_empty());
+        assert_eq!(service.search_users("ada", 1)[0].0, ada);
+    }
+
+    #[test]
+    fn test_start_location_parsing() {
+        assert_eq!(StartLocation::parse("home"), StartLocation::Home);
+        assert_eq!(StartLocation::parse("last"), StartLocation::Last);
+        assert_eq!(StartLocation::parse("garbage"), StartLocation::Last);
+        assert_eq!(
+            StartLocation::parse("uri:Mutsea Plaza&64&32&25"),
+            StartLocation::Region {
+                name: "Mutsea Plaza".to_string(),
+                position: Vector3::new(64.0, 32.0, 25.0),
+            }
+        );
+    }
+
+    #[test]
+    fn test_set_start_location_request_parsing() {
+        let agent = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent.as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.push(7);
+        payload.extend_from_slice(b"Plaza\0\0");
+        payload.extend_from_slice(&1u32.to_le_bytes());
+        for value in [64.0f32, 32.0, 25.0, 1.0, 0.0, 0.0] {
+            payload.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let request = SetStartLocationRequest::parse(&payload).unwrap();
+        assert_eq!(request.agent_id, UserId::from_uuid(agent));
+        assert_eq!(request.sim_name, "Plaza");
+        assert_eq!(request.location_id, 1);
+        assert_eq!(request.position, Vector3::new(64.0, 32.0, 25.0));
+        assert_eq!(request.look_at, Vector3::new(1.0, 0.0, 0.0));
+        assert!(SetStartLocationRequest::parse(&payload[..payload.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_login_uses_stored_home_and_last_location() {
+        let service = LoginService::new();
+        service.add_test_user("Test".to_string(), "User".to_string(), "password".to_string());
+        let user_id = service.get_user_by_name("Test", "User").unwrap();
+
+        let home = AgentLocation::new(RegionId::new(), "Home".to_string(), 1001, 1002, Vector3::new(10.0, 20.0, 30.0));
+        let last = AgentLocation::new(RegionId::new(), "Last".to_string(), 1003, 1004, Vector3::new(1.0, 2.0, 3.0));
+        service.set_home_location(user_id, home.clone());
+        service.set_last_location(user_id, last.clone());
+
+        let mut request = ParsedLoginRequest::from_xmlrpc(
+            "<member><name>first</name><value><string>Test</string></value></member>\
+             <member><name>last</name><value><string>User</string></value></member>\
+             <member><name>passwd</name><value><string>password</string></value></member>\
+             <member><name>start</name><value><string>last</string></value></member>",
+        )
+        .unwrap();
+        assert_eq!(request.first, "Test");
+        assert_eq!(request.start, "last");
+
+        let response = service.authenticate(&request).unwrap();
+        assert_eq!(response.region_x, Some(1003 * 256));
+        assert_eq!(response.region_y, Some(1004 * 256));
+        assert_eq!(response.home, Some(home.to_home_string()));
+
+        request.start = "home".to_string();
+        let response = service.authenticate(&request).unwrap();
+        assert_eq!(response.region_x, Some(1001 * 256));
+        assert_eq!(service.get_grid_user(&user_id).unwrap().login_count, 2);
+    }
+
+    #[test]
+    fn test_login_refused_to_regions_above_access_max() {
+        let service = LoginService::new().with_default_access_max(Maturity::Mature);
+        service.add_test_user("Test".to_string(), "User".to_string(), "password".to_string());
+        let user_id = service.get_user_by_name("Test", "User").unwrap();
+        let adult = AgentLocation::new(RegionId::new(), "Adult".to_string(), 1001, 1002, Vector3::ZERO);
+        service.register_region(adult.clone());
+        service.set_region_maturity(adult.region_id, Maturity::Adult);
+        service.set_home_location(user_id, adult.clone());
+
+        let request = ParsedLoginRequest::from_xmlrpc(
+            "<member><name>first</name><value><string>Test</string></value></member>\
+             <member><name>last</name><value><string>User</string></value></member>\
+             <member><name>passwd</name><value><string>password</string></value></member>\
+             <member><name>start</name><value><string>home</string></value></member>",
+        )
+        .unwrap();
+        let response = service.authenticate(&request).unwrap();
+        assert_eq!(response.login, "false");
+        assert!(!service.may_enter(&user_id, &adult.region_id));
+        assert_eq!(service.region_by_handle(adult.region_handle()), Some(adult));
+
+        assert!(service.set_access_max(&user_id, Maturity::Adult));
+        let response = service.authenticate(&request).unwrap();
+        assert_eq!(response.login, "true");
+        assert_eq!(response.agent_access_max.as_deref(), Some("A"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_banned_login_is_refused_with_reason() {
+        let service = LoginService::new();
+        service.add_test_user("Test".to_string(), "User".to_string(), "password".to_string());
+        let user_id = service.get_user_by_name("Test", "User").unwrap();
+        let request = ParsedLoginRequest {
+            first: "Test".to_string(),
+            last: "User".to_string(),
+            passwd: "password".to_string(),
+            start: "home".to_string(),
+            channel: "Mutsea".to_string(),
+            version: "1.0.0".to_string(),
+            platform: "Test".to_string(),
+            mac: "00:00:00:00:00:00".to_string(),
+            id0: "banned-hardware".to_string(),
+            agree_to_tos: "true".to_string(),
+            read_critical: "true".to_string(),
+            viewer_digest: "test".to_string(),
+            options: vec![],
+        };
+
+        let ban = service
+            .bans()
+            .ban(
+                crate::bans::BanTarget::Hardware("banned-hardware".to_string()),
+                crate::bans::BanScope::Grid,
+                "alt accounts",
+                None,
+            )
+            .unwrap();
+        let response = service.authenticate_from(&request, Some("192.0.2.1".parse().unwrap())).unwrap();
+        assert_eq!(response.login, "false");
+        assert!(response.to_xmlrpc().contains("alt accounts"));
+        assert!(service.get_grid_user(&user_id).is_none_or(|g| !g.online));
+
+        service.bans().unban(ban.id).unwrap();
+        assert_eq!(service.authenticate(&request).unwrap().login, "true");
+    }
+
+    #[test]
+    fn test_sim_address_follows_client_family() {
+        let address = SimAddress {
+            ipv4: Some("203.0.113.5".parse().unwrap()),
+            ipv6: Some("2001:db8::5".parse().unwrap()),
+            port: 9010,
+            caps_base: "http://[2001:db8::5]:8080/".to_string(),
+        };
+        assert_eq!(address.select(Some("198.51.100.1".parse().unwrap())).to_string(), "203.0.113.5");
+        assert_eq!(address.select(Some("2001:db8::99".parse().unwrap())).to_string(), "2001:db8::5");
+        assert_eq!(address.select(Some("::ffff:198.51.100.1".parse().unwrap())).to_string(), "203.0.113.5");
+        assert_eq!(address.select(None).to_string(), "203.0.113.5");
+
+        let v6_only = SimAddress { ipv4: None, ..address.clone() };
+        assert_eq!(v6_only.select(Some("198.51.100.1".parse().unwrap())).to_string(), "2001:db8::5");
+
+        let service = LoginService::new().with_sim_address(address);
+        service.add_test_user("Six".to_string(), "Viewer".to_string(), "password".to_string());
+        let request = ParsedLoginRequest {
+            first: "Six".to_string(),
+            last: "Viewer".to_string(),
+            passwd: "password".to_string(),
+            start: "last".to_string(),
+            channel: "Mutsea".to_string(),
+            version: "1.0.0".to_string(),
+            platform: "Test".to_string(),
+            mac: String::new(),
+            id0: String::new(),
+            agree_to_tos: "true".to_string(),
+            read_critical: "true".to_string(),
+            viewer_digest: "test".to_string(),
+            options: vec![],
+        };
+        let response = service.authenticate_from(&request, Some("2001:db8::99".parse().unwrap())).unwrap();
+        assert_eq!(response.sim_ip.as_deref(), Some("2001:db8::5"));
+        assert_eq!(response.sim_port, Some(9010));
+        assert!(response.seed_capability.unwrap().starts_with("http://[2001:db8::5]:8080/caps/"));
+    }
+
+    #[test]
+    fn test_login_refused_while_backend_circuit_open() {
+        let breaker = Arc::new(CircuitBreaker::new(
+            "database",
+            mutsea_core::config::CircuitBreakerConfig {
+                failure_threshold: 1,
+                ..Default::default()
+            },
+        ));
+        let service = LoginService::new().with_backend_breaker(Arc::clone(&breaker));
+        service.add_test_user("Test".to_string(), "User".to_string(), "password".to_string());
+        let request = ParsedLoginRequest {
+            first: "Test".to_string(),
+            last: "User".to_string(),
+            passwd: "password".to_string(),
+            start: "last".to_string(),
+            channel: "Mutsea".to_string(),
+            version: "1.0.0".to_string(),
+            platform: "Test".to_string(),
+            mac: String::new(),
+            id0: String::new(),
+            agree_to_tos: "true".to_string(),
+            read_critical: "true".to_string(),
+            viewer_digest: "test".to_string(),
+            options: vec![],
+        };
+        assert_eq!(service.authenticate(&request).unwrap().login, "true");
+
+        breaker.acquire().unwrap().failure();
+        let response = service.authenticate(&request).unwrap();
+        assert_eq!(response.login, "false");
+        assert!(response.reason.contains("try again"));
+    }
+}
@@ -0,0 +1,341 @@
+//! Mesh assets
+//!
+//! A mesh asset starts with a binary LLSD header naming its blocks, each
+//! given as an offset past the header and a size. Every block is zlib
+//! compressed binary LLSD: four levels of detail, each a list of submeshes,
+//! and the physics shape the uploader chose, either as convex hulls or as a
+//! low detail mesh. Positions are stored as 16 bit values spread over a
+//! domain, normally -0.5 to 0.5 on each axis so the mesh scales with its
+//! prim.
+
+use crate::llsd::{self, Llsd};
+use crate::{ProtocolError, ProtocolResult};
+use flate2::read::ZlibDecoder;
+use mutsea_core::Vector3;
+use std::io::Read;
+
+/// Domain of positions when a block gives none
+const DEFAULT_MIN: Vector3 = Vector3 {
+    x: -0.5,
+    y: -0.5,
+    z: -0.5,
+};
+const DEFAULT_MAX: Vector3 = Vector3 { x: 0.5, y: 0.5, z: 0.5 };
+
+/// A level of detail, from the one shown close up to the one shown furthest
+/// away
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lod {
+    /// Shown close up
+    High,
+    /// Shown at middle distance
+    Medium,
+    /// Shown far away
+    Low,
+    /// Shown furthest away
+    Lowest,
+}
+
+impl Lod {
+    /// Every level, most detailed first
+    pub const ALL: [Lod; 4] = [Lod::High, Lod::Medium, Lod::Low, Lod::Lowest];
+
+    /// Name of the level's block in the header
+    pub fn block(self) -> &'static str {
+        match self {
+            Lod::High => "high_lod",
+            Lod::Medium => "medium_lod",
+            Lod::Low => "low_lod",
+            Lod::Lowest => "lowest_lod",
+        }
+    }
+}
+
+/// One face of a mesh
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Submesh {
+    /// Vertex positions in the mesh's domain
+    pub positions: Vec<Vector3>,
+    /// Triangles as indices into `positions`
+    pub triangles: Vec<[u16; 3]>,
+}
+
+/// A decoded mesh asset
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshAsset {
+    /// Submeshes of each level of detail present, most detailed first
+    pub lods: Vec<(Lod, Vec<Submesh>)>,
+    /// Convex hulls making up the physics shape
+    pub hulls: Vec<Vec<Vector3>>,
+    /// One hull around the whole physics shape
+    pub bounding_hull: Vec<Vector3>,
+    /// The physics shape when uploaded as a mesh instead of hulls
+    pub physics_mesh: Vec<Submesh>,
+}
+
+impl MeshAsset {
+    /// Decode a mesh asset, refusing blocks that inflate past
+    /// `max_block_size` bytes
+    pub fn decode(data: &[u8], max_block_size: usize) -> ProtocolResult<Self> {
+        let (header, header_len) = llsd::from_binary(data)?;
+        let body = &data[header_len..];
+        let block = |name: &str| read_block(&header, body, name, max_block_size);
+
+        let mut mesh = MeshAsset::default();
+        for lod in Lod::ALL {
+            if let Some(block) = block(lod.block())? {
+                mesh.lods.push((lod, submeshes(&block, lod.block())?));
+            }
+        }
+        if let Some(convex) = block("physics_convex")? {
+            let (min, max) = (
+                vector(convex.get("Min")).unwrap_or(DEFAULT_MIN),
+                vector(convex.get("Max")).unwrap_or(DEFAULT_MAX),
+            );
+            if let Some(bounding) = convex.get("BoundingVerts").and_then(Llsd::as_binary) {
+                mesh.bounding_hull = positions(bounding, min, max);
+            }
+            if let (Some(counts), Some(points)) = (
+                convex.get("HullList").and_then(Llsd::as_binary),
+                convex.get("Positions").and_then(Llsd::as_binary),
+            ) {
+                let mut points = positions(points, min, max).into_iter();
+                for &count in counts {
+                    // A count of 0 stands for 256
+                    let count = if count == 0 { 256 } else { count as usize };
+                    let hull: Vec<Vector3> = points.by_ref().take(count).collect();
+                    if hull.len() < count {
+                        return Err(ProtocolError::Decoding(
+                            "Mesh hull list overruns its positions".to_string(),
+                        ));
+                    }
+                    mesh.hulls.push(hull);
+                }
+            }
+        }
+        if let Some(block) = block("physics_mesh")? {
+            mesh.physics_mesh = submeshes(&block, "physics_mesh")?;
+        }
+        Ok(mesh)
+    }
+
+    /// The submeshes of `lod`, if the asset has it
+    pub fn lod(&self, lod: Lod) -> Option<&[Submesh]> {
+        self.lods
+            .iter()
+            .find(|(level, _)| *level == lod)
+            .map(|(_, submeshes)| submeshes.as_slice())
+    }
+
+    /// The most detailed level the asset has
+    pub fn highest_lod(&self) -> Option<(Lod, &[Submesh])> {
+        self.lods.first().map(|(lod, submeshes)| (*lod, submeshes.as_slice()))
+    }
+}
+
+/// Inflate and read the block `name` listed in `header`
+fn read_block(header: &Llsd, body: &[u8], name: &str, max_block_size: usize) -> ProtocolResult<Option<Llsd>> {
+    let Some(entry) = header.get(name) else {
+        return Ok(None);
+    };
+    let (Some(offset), Some(size)) = (
+        entry.get("offset").and_then(Llsd::as_integer),
+        entry.get("size").and_then(Llsd::as_integer),
+    ) else {
+        return Err(ProtocolError::Decoding(format!(
+            "Mesh block {} has no offset or size",
+            name
+        )));
+    };
+    if size <= 0 {
+        return Ok(None);
+    }
+    let compressed = usize::try_from(offset)
+        .ok()
+        .and_then(|offset| body.get(offset..offset.checked_add(size as usize)?))
+        .ok_or_else(|| ProtocolError::Decoding(format!("Mesh block {} lies outside the asset", name)))?;
+
+    let mut inflated = Vec::new();
+    ZlibDecoder::new(compressed)
+        .take(max_block_size as u64 + 1)
+        .read_to_end(&mut inflated)
+        .map_err(|e| ProtocolError::Decoding(format!("Mesh block {}: {}", name, e)))?;
+    if inflated.len() > max_block_size {
+        return Err(ProtocolError::Decoding(format!(
+            "Mesh block {} inflates past {} bytes",
+            name, max_block_size
+        )));
+    }
+    Ok(Some(llsd::from_binary(&inflated)?.0))
+}
+
+/// The submeshes of a level of detail or physics mesh block
+fn submeshes(block: &Llsd, name: &str) -> ProtocolResult<Vec<Submesh>> {
+    let faces = block
+        .as_array()
+        .ok_or_else(|| ProtocolError::Decoding(format!("Mesh block {} is not a list of submeshes", name)))?;
+    let mut submeshes = Vec::with_capacity(faces.len());
+    for face in faces {
+        if face.get("NoGeometry").is_some_and(Llsd::as_bool) {
+            continue;
+        }
+        let domain = face.get("PositionDomain");
+        let (min, max) = (
+            vector(domain.and_then(|domain| domain.get("Min"))).unwrap_or(DEFAULT_MIN),
+            vector(domain.and_then(|domain| domain.get("Max"))).unwrap_or(DEFAULT_MAX),
+        );
+        let positions = face
+            .get("Position")
+            .and_then(Llsd::as_binary)
+            .map(|bytes| positions(bytes, min, max))
+            .unwrap_or_default();
+        let triangles: Vec<[u16; 3]> = face
+            .get("TriangleList")
+            .and_then(Llsd::as_binary)
+            .unwrap_or_default()
+            .chunks_exact(6)
+            .map(|t| {
+                [
+                    u16::from_le_bytes([t[0], t[1]]),
+                    u16::from_le_bytes([t[2], t[3]]),
+                    u16::from_le_bytes([t[4], t[5]]),
+                ]
+            })
+            .collect();
+        if triangles
+            .iter()
+            .flatten()
+            .any(|index| *index as usize >= positions.len())
+        {
+            return Err(ProtocolError::Decoding(format!(
+                "Mesh block {} has triangles past its positions",
+                name
+            )));
+        }
+        submeshes.push(Submesh { positions, triangles });
+    }
+    Ok(submeshes)
+}
+
+/// Positions stored as 16 bit triples spread from `min` to `max`
+fn positions(bytes: &[u8], min: Vector3, max: Vector3) -> Vec<Vector3> {
+    let spread = |value: u16, min: f32, max: f32| min + (max - min) * value as f32 / u16::MAX as f32;
+    bytes
+        .chunks_exact(6)
+        .map(|p| {
+            Vector3::new(
+                spread(u16::from_le_bytes([p[0], p[1]]), min.x, max.x),
+                spread(u16::from_le_bytes([p[2], p[3]]), min.y, max.y),
+                spread(u16::from_le_bytes([p[4], p[5]]), min.z, max.z),
+            )
+        })
+        .collect()
+}
+
+/// A vector stored as an array of three reals
+fn vector(value: Option<&Llsd>) -> Option<Vector3> {
+    match value?.as_array()? {
+        [x, y, z] => Some(Vector3::new(
+            x.as_real()? as f32,
+            y.as_real()? as f32,
+            z.as_real()? as f32,
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::collections::HashMap;
+    use std::io::Write;
+
+    fn map(entries: Vec<(&str, Llsd)>) -> Llsd {
+        Llsd::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn quantized(points: &[[u16; 3]]) -> Llsd {
+        Llsd::Binary(points.iter().flatten().flat_map(|v| v.to_le_bytes()).collect())
+    }
+
+    fn compress(value: &Llsd) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&llsd::to_binary(value)).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn asset(blocks: Vec<(&str, Llsd)>) -> Vec<u8> {
+        let mut header = HashMap::new();
+        let mut body = Vec::new();
+        for (name, block) in blocks {
+            let compressed = compress(&block);
+            header.insert(
+                name.to_string(),
+                map(vec![
+                    ("offset", Llsd::Integer(body.len() as i32)),
+                    ("size", Llsd::Integer(compressed.len() as i32)),
+                ]),
+            );
+            body.extend_from_slice(&compressed);
+        }
+        let mut data = llsd::to_binary(&Llsd::Map(header));
+        data.extend_from_slice(&body);
+        data
+    }
+
+    #[test]
+    fn test_decode_lods_and_hulls() {
+        let face = map(vec![
+            ("Position", quantized(&[[0, 0, 0], [65535, 0, 0], [0, 65535, 65535]])),
+            (
+                "TriangleList",
+                Llsd::Binary([0u16, 1, 2].iter().flat_map(|i| i.to_le_bytes()).collect()),
+            ),
+        ]);
+        let convex = map(vec![
+            ("HullList", Llsd::Binary(vec![2, 1])),
+            (
+                "Positions",
+                quantized(&[[0, 0, 0], [65535, 65535, 65535], [0, 0, 65535]]),
+            ),
+            (
+                "Min",
+                Llsd::Array(vec![Llsd::Real(-1.0), Llsd::Real(-1.0), Llsd::Real(-1.0)]),
+            ),
+            (
+                "Max",
+                Llsd::Array(vec![Llsd::Real(1.0), Llsd::Real(1.0), Llsd::Real(1.0)]),
+            ),
+        ]);
+        let data = asset(vec![
+            (
+                "high_lod",
+                Llsd::Array(vec![face.clone(), map(vec![("NoGeometry", Llsd::Boolean(true))])]),
+            ),
+            ("lowest_lod", Llsd::Array(vec![face])),
+            ("physics_convex", convex),
+        ]);
+
+        let mesh = MeshAsset::decode(&data, 1 << 20).unwrap();
+        assert_eq!(mesh.lods.len(), 2);
+        let (lod, faces) = mesh.highest_lod().unwrap();
+        assert_eq!(lod, Lod::High);
+        assert_eq!(faces.len(), 1);
+        assert_eq!(faces[0].positions[1], Vector3::new(0.5, -0.5, -0.5));
+        assert_eq!(faces[0].triangles, vec![[0, 1, 2]]);
+        assert!(mesh.lod(Lod::Medium).is_none());
+        assert_eq!(mesh.hulls.len(), 2);
+        assert_eq!(mesh.hulls[0][1], Vector3::new(1.0, 1.0, 1.0));
+        assert_eq!(mesh.hulls[1], vec![Vector3::new(-1.0, -1.0, 1.0)]);
+
+        // Blocks inflating too far are refused
+        assert!(MeshAsset::decode(&data, 8).is_err());
+    }
+}
@@ -0,0 +1,347 @@
+//! Kicking and freezing agents
+//!
+//! Estate managers kick agents with the EstateOwnerMessage method
+//! `kickestate` and freeze them with FreezeUser. Gods first ask for their
+//! powers with RequestGodlikePowers, answered by GrantGodlikePowers with the
+//! level granted (0 when refused or given up), then kick, freeze or
+//! unfreeze with GodKickUser, or kick with the GodlikeMessage method
+//! `kickestate`. Residents report abuse with UserReport.
+
+use crate::codec::MessageDecoder;
+use crate::constants::packet_types;
+use crate::packet::Packet;
+use crate::ProtocolResult;
+use mutsea_core::UserId;
+use uuid::Uuid;
+
+/// GodKickUser flags: log the agent out
+pub const KICK_FLAGS_DEFAULT: u32 = 0;
+/// GodKickUser flags: stop the agent moving
+pub const KICK_FLAGS_FREEZE: u32 = 1;
+/// GodKickUser flags: let a frozen agent move again
+pub const KICK_FLAGS_UNFREEZE: u32 = 2;
+
+/// FreezeUser flag to unfreeze rather than freeze
+pub const FREEZE_FLAG_UNFREEZE: u32 = 1;
+
+/// The EstateOwnerMessage and GodlikeMessage method that kicks an agent
+pub const METHOD_KICK_ESTATE: &str = "kickestate";
+
+/// What a moderator asked to do to an agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KickAction {
+    /// Log the agent out
+    Kick,
+    /// Stop the agent moving
+    Freeze,
+    /// Let the agent move again
+    Unfreeze,
+}
+
+/// A method call from the estate tools; GodlikeMessage has the same layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct EstateOwnerMessage {
+    /// Agent calling
+    pub agent_id: UserId,
+    /// Agent's session
+    pub session_id: Uuid,
+    /// Method name, such as "kickestate"
+    pub method: String,
+    /// Method parameters, in order
+    pub params: Vec<String>,
+}
+
+/// A method call from the god tools
+pub type GodlikeMessage = EstateOwnerMessage;
+
+impl EstateOwnerMessage {
+    /// Parse an EstateOwnerMessage or GodlikeMessage payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        let session_id = decoder.read_uuid()?;
+        decoder.skip(16)?; // TransactionID
+        let method = decoder.read_variable1()?;
+        decoder.skip(16)?; // Invoice
+        // The Parameter of each ParamList block
+        let count = decoder.read_u8()?;
+        let params = (0..count)
+            .map(|_| decoder.read_variable1())
+            .collect::<ProtocolResult<_>>()?;
+
+        Ok(Self {
+            agent_id,
+            session_id,
+            method,
+            params,
+        })
+    }
+
+    /// The agent named by parameter `index`, as the kick methods take them
+    pub fn agent_param(&self, index: usize) -> Option<UserId> {
+        self.params
+            .get(index)
+            .and_then(|param| Uuid::parse_str(param.trim()).ok())
+            .map(UserId::from_uuid)
+    }
+}
+
+/// An estate manager's request to freeze or unfreeze an agent
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreezeUser {
+    /// Estate manager asking
+    pub agent_id: UserId,
+    /// Agent to freeze or unfreeze
+    pub target_id: UserId,
+    /// Unfreeze rather than freeze
+    pub unfreeze: bool,
+}
+
+impl FreezeUser {
+    /// Parse a FreezeUser payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            target_id: UserId::from_uuid(decoder.read_uuid()?),
+            unfreeze: decoder.read_u32()? & FREEZE_FLAG_UNFREEZE != 0,
+        })
+    }
+}
+
+/// A god's request to kick, freeze or unfreeze an agent
+#[derive(Debug, Clone, PartialEq)]
+pub struct GodKickUser {
+    /// God asking
+    pub god_id: UserId,
+    /// God's session
+    pub god_session_id: Uuid,
+    /// Agent to kick, freeze or unfreeze
+    pub target_id: UserId,
+    /// What to do, see [`GodKickUser::action`]
+    pub kick_flags: u32,
+    /// Message shown to the kicked agent
+    pub reason: String,
+}
+
+impl GodKickUser {
+    /// Parse a GodKickUser payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        Ok(Self {
+            god_id: UserId::from_uuid(decoder.read_uuid()?),
+            god_session_id: decoder.read_uuid()?,
+            target_id: UserId::from_uuid(decoder.read_uuid()?),
+            kick_flags: decoder.read_u32()?,
+            reason: decoder.read_variable2()?,
+        })
+    }
+
+    /// What the flags ask for; unknown flags kick
+    pub fn action(&self) -> KickAction {
+        match self.kick_flags {
+            KICK_FLAGS_FREEZE => KickAction::Freeze,
+            KICK_FLAGS_UNFREEZE => KickAction::Unfreeze,
+            _ => KickAction::Kick,
+        }
+    }
+}
+
+/// An agent's request to take up or give up its god powers
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestGodlikePowers {
+    /// Agent asking
+    pub agent_id: UserId,
+    /// Agent's session
+    pub session_id: Uuid,
+    /// Take up god powers if set, give them up otherwise
+    pub godlike: bool,
+    /// Echoed back in the grant
+    pub token: Uuid,
+}
+
+impl RequestGodlikePowers {
+    /// Parse a RequestGodlikePowers payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        Ok(Self {
+            agent_id: UserId::from_uuid(decoder.read_uuid()?),
+            session_id: decoder.read_uuid()?,
+            godlike: decoder.read_bool()?,
+            token: decoder.read_uuid()?,
+        })
+    }
+}
+
+/// A resident's abuse report
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserReport {
+    /// Reporting agent
+    pub agent_id: UserId,
+    /// Reporting agent's session
+    pub session_id: Uuid,
+    /// Report type; 1 for a bug, 2 for a complaint
+    pub report_type: u8,
+    /// Viewer's abuse category
+    pub category: u8,
+    /// Where the reporter stood, in region coordinates
+    pub position: [f32; 3],
+    /// Uploaded screenshot asset, nil if none
+    pub screenshot_id: Uuid,
+    /// Object reported, nil if none was named
+    pub object_id: Uuid,
+    /// Resident reported, nil if none was named
+    pub abuser_id: UserId,
+    /// Region the report was filed from
+    pub region_name: String,
+    /// ID of that region
+    pub region_id: Uuid,
+    /// One-line summary
+    pub summary: String,
+    /// Full description
+    pub details: String,
+    /// The reporting viewer's version
+    pub version: String,
+}
+
+impl UserReport {
+    /// Parse a UserReport payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        let session_id = decoder.read_uuid()?;
+        let report_type = decoder.read_u8()?;
+        let category = decoder.read_u8()?;
+        let position = decoder.read_vector3()?;
+        decoder.skip(1)?; // CheckFlags
+
+        Ok(Self {
+            agent_id,
+            session_id,
+            report_type,
+            category,
+            position: [position.x, position.y, position.z],
+            screenshot_id: decoder.read_uuid()?,
+            object_id: decoder.read_uuid()?,
+            abuser_id: UserId::from_uuid(decoder.read_uuid()?),
+            region_name: decoder.read_variable1()?,
+            region_id: decoder.read_uuid()?,
+            summary: decoder.read_variable1()?,
+            details: decoder.read_variable2()?,
+            version: decoder.read_variable1()?,
+        })
+    }
+}
+
+/// Grant `agent_id` god level `god_level`, 0 for none, answering the
+/// request with `token`
+pub fn encode_grant_godlike_powers(agent_id: UserId, session_id: Uuid, god_level: u8, token: Uuid) -> Packet {
+    let mut payload = Vec::with_capacity(16 * 3 + 1);
+    // AgentData block
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    payload.extend_from_slice(session_id.as_bytes());
+    // GrantData block
+    payload.push(god_level);
+    payload.extend_from_slice(token.as_bytes());
+    // Low frequency, so the ID doesn't fit in the payload's first byte
+    Packet::reliable(0, payload).with_message_id(packet_types::GRANT_GODLIKE_POWERS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instant_message::{push_variable1, push_variable2};
+
+    #[test]
+    fn test_parse_estate_kick_and_freeze() {
+        let agent_id = UserId::new();
+        let target_id = UserId::new();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        push_variable1(&mut payload, METHOD_KICK_ESTATE);
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.push(1);
+        push_variable1(&mut payload, &target_id.to_string());
+
+        let message = EstateOwnerMessage::parse(&payload).unwrap();
+        assert_eq!(message.agent_id, agent_id);
+        assert_eq!(message.method, METHOD_KICK_ESTATE);
+        assert_eq!(message.agent_param(0), Some(target_id));
+        assert_eq!(message.agent_param(1), None);
+        assert!(EstateOwnerMessage::parse(&payload[..payload.len() - 1]).is_err());
+
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(target_id.as_uuid().as_bytes());
+        payload.extend_from_slice(&FREEZE_FLAG_UNFREEZE.to_le_bytes());
+        let freeze = FreezeUser::parse(&payload).unwrap();
+        assert_eq!(freeze.target_id, target_id);
+        assert!(freeze.unfreeze);
+    }
+
+    #[test]
+    fn test_parse_god_requests() {
+        let god_id = UserId::new();
+        let token = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(god_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.push(1);
+        payload.extend_from_slice(token.as_bytes());
+        let request = RequestGodlikePowers::parse(&payload).unwrap();
+        assert!(request.godlike);
+        assert_eq!(request.token, token);
+
+        let target_id = UserId::new();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(god_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(target_id.as_uuid().as_bytes());
+        payload.extend_from_slice(&KICK_FLAGS_FREEZE.to_le_bytes());
+        push_variable2(&mut payload, "Griefing");
+        let kick = GodKickUser::parse(&payload).unwrap();
+        assert_eq!((kick.target_id, kick.action()), (target_id, KickAction::Freeze));
+        assert_eq!(kick.reason, "Griefing");
+
+        let grant = encode_grant_godlike_powers(god_id, request.session_id, 200, token);
+        assert_eq!(grant.message_id, Some(packet_types::GRANT_GODLIKE_POWERS));
+        assert_eq!(grant.payload.len(), 16 * 3 + 1);
+        assert_eq!(grant.payload[32], 200);
+    }
+
+    #[test]
+    fn test_parse_user_report() {
+        let (agent_id, abuser_id) = (UserId::new(), UserId::new());
+        let region_id = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(&[1, 19]);
+        for coordinate in [128.0f32, 64.5, 22.0] {
+            payload.extend_from_slice(&coordinate.to_le_bytes());
+        }
+        payload.push(0);
+        payload.extend_from_slice(Uuid::nil().as_bytes());
+        payload.extend_from_slice(Uuid::nil().as_bytes());
+        payload.extend_from_slice(abuser_id.as_uuid().as_bytes());
+        push_variable1(&mut payload, "Sandbox");
+        payload.extend_from_slice(region_id.as_bytes());
+        push_variable1(&mut payload, "Spamming chat");
+        push_variable2(&mut payload, "Repeats the same advert every few seconds");
+        push_variable1(&mut payload, "Firestorm 7.1");
+
+        let report = UserReport::parse(&payload).unwrap();
+        assert_eq!((report.agent_id, report.abuser_id), (agent_id, abuser_id));
+        assert_eq!((report.report_type, report.category), (1, 19));
+        assert_eq!(report.position, [128.0, 64.5, 22.0]);
+        assert_eq!((report.region_name.as_str(), report.region_id), ("Sandbox", region_id));
+        assert_eq!(report.summary, "Spamming chat");
+        assert_eq!(report.version, "Firestorm 7.1");
+        assert!(UserReport::parse(&payload[..payload.len() - 1]).is_err());
+    }
+}
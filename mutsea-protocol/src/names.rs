@@ -0,0 +1,252 @@
+//! Avatar names
+//!
+//! Every avatar has a legacy name, its first and last name, and may pick a
+//! display name that viewers show instead. The avatar picker, used to find
+//! someone to befriend, pay or ban, sends AvatarPickerRequest, answered by
+//! AvatarPickerReply with the avatars whose names match.
+//!
+//! Viewers look names up through the GetDisplayNames capability, asking
+//! for `?ids=<uuid>&ids=<uuid>`, and cache each answer until its
+//! `display_name_expires`. Agents change their display name through the
+//! SetDisplayName capability, posting their old and new name, and viewers
+//! that know the avatar hear of the change through a DisplayNameUpdate
+//! event, so name tags change without waiting for the cache to expire.
+
+use crate::codec::MessageDecoder;
+use crate::constants::packet_types;
+use crate::instant_message::push_variable1;
+use crate::llsd;
+use crate::{ProtocolError, ProtocolResult};
+use chrono::{DateTime, SecondsFormat, Utc};
+use mutsea_core::UserId;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// The last name of avatars registered with only a username
+pub const RESIDENT_LAST_NAME: &str = "Resident";
+
+/// The names of an avatar
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvatarName {
+    /// The avatar
+    pub agent_id: UserId,
+    /// Legacy first name
+    pub first_name: String,
+    /// Legacy last name
+    pub last_name: String,
+    /// None while the avatar goes by its legacy name
+    pub display_name: Option<String>,
+    /// When the avatar may next change its display name
+    pub next_update: DateTime<Utc>,
+}
+
+impl AvatarName {
+    /// "First Last"
+    pub fn legacy_name(&self) -> String {
+        format!("{} {}", self.first_name, self.last_name)
+    }
+
+    /// The lowercase login name: "first.last", or "first" for Residents
+    pub fn username(&self) -> String {
+        if self.last_name.eq_ignore_ascii_case(RESIDENT_LAST_NAME) {
+            self.first_name.to_lowercase()
+        } else {
+            format!("{}.{}", self.first_name, self.last_name).to_lowercase()
+        }
+    }
+
+    /// The name viewers show: the display name, or the legacy name
+    pub fn display_name(&self) -> String {
+        self.display_name.clone().unwrap_or_else(|| self.legacy_name())
+    }
+
+    /// The agent record GetDisplayNames and SetDisplayName answer with,
+    /// cached by the viewer until `expires`
+    pub fn to_llsd(&self, expires: DateTime<Utc>) -> Value {
+        json!({
+            "id": self.agent_id.to_string(),
+            "username": self.username(),
+            "display_name": self.display_name(),
+            "legacy_first_name": self.first_name,
+            "legacy_last_name": self.last_name,
+            "is_display_name_default": self.display_name.is_none(),
+            "display_name_next_update": llsd_date(self.next_update),
+            "display_name_expires": llsd_date(expires),
+        })
+    }
+}
+
+/// Answer GetDisplayNames with `names`, and the `bad_ids` that name no
+/// avatar, as an LLSD XML document
+pub fn encode_display_names(names: &[AvatarName], bad_ids: &[Uuid], expires: DateTime<Utc>) -> String {
+    let agents: Vec<Value> = names.iter().map(|name| name.to_llsd(expires)).collect();
+    let bad_ids: Vec<String> = bad_ids.iter().map(Uuid::to_string).collect();
+    llsd::to_xml(&json!({ "agents": agents, "bad_ids": bad_ids }))
+}
+
+/// The DisplayNameUpdate event body telling viewers `name` was known as
+/// `old_display_name`
+pub fn encode_display_name_update(name: &AvatarName, old_display_name: &str, expires: DateTime<Utc>) -> Value {
+    json!({
+        "agent_id": name.agent_id.to_string(),
+        "old_display_name": old_display_name,
+        "agent": name.to_llsd(expires),
+    })
+}
+
+/// The IDs of a GetDisplayNames query string, `ids=<uuid>&ids=<uuid>`.
+/// Values that aren't UUIDs are skipped.
+pub fn parse_display_name_ids(query: &str) -> Vec<Uuid> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .filter(|(key, _)| *key == "ids")
+        .filter_map(|(_, value)| Uuid::parse_str(value.trim()).ok())
+        .collect()
+}
+
+/// A SetDisplayName request: `{display_name: [old, new]}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetDisplayName {
+    /// Display name the viewer last saw
+    pub old_name: String,
+    /// Display name asked for
+    pub new_name: String,
+}
+
+impl SetDisplayName {
+    /// Parse the LLSD XML the viewer posts
+    pub fn parse(body: &str) -> ProtocolResult<Self> {
+        match <[String; 2]>::try_from(llsd::xml_strings(body)) {
+            Ok([old_name, new_name]) => Ok(Self { old_name, new_name }),
+            Err(_) => Err(ProtocolError::Decoding(
+                "SetDisplayName needs the old and new display name".to_string(),
+            )),
+        }
+    }
+}
+
+/// The SetDisplayNameReply body: `name` as it now is, with an HTTP-style
+/// `status` and the `reason` for it
+pub fn encode_set_display_name_reply(name: &AvatarName, status: u16, reason: &str, expires: DateTime<Utc>) -> String {
+    llsd::to_xml(&json!({
+        "content": name.to_llsd(expires),
+        "status": status,
+        "reason": reason,
+    }))
+}
+
+/// A search for avatars by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvatarPickerRequest {
+    /// Agent searching
+    pub agent_id: UserId,
+    /// Echoed back in the reply
+    pub query_id: Uuid,
+    /// Name or part of one to look for
+    pub name: String,
+}
+
+impl AvatarPickerRequest {
+    /// Parse an AvatarPickerRequest payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            query_id: decoder.read_uuid()?,
+            name: decoder.read_variable1()?,
+        })
+    }
+}
+
+/// Answer the avatar search `query_id` of `agent_id` with `avatars`, by
+/// ID, first and last name
+pub fn encode_avatar_picker_reply(agent_id: UserId, query_id: Uuid, avatars: &[(UserId, String, String)]) -> Vec<u8> {
+    let mut payload = vec![packet_types::AVATAR_PICKER_REPLY as u8];
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    payload.extend_from_slice(query_id.as_bytes());
+    // Data blocks
+    payload.push(avatars.len().min(u8::MAX as usize) as u8);
+    for (avatar_id, first_name, last_name) in avatars.iter().take(u8::MAX as usize) {
+        payload.extend_from_slice(avatar_id.as_uuid().as_bytes());
+        push_variable1(&mut payload, first_name);
+        push_variable1(&mut payload, last_name);
+    }
+    payload
+}
+
+/// A date as LLSD writes it, e.g. 2026-10-15T20:00:00Z
+fn llsd_date(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(first_name: &str, last_name: &str, display_name: Option<&str>) -> AvatarName {
+        AvatarName {
+            agent_id: UserId::new(),
+            first_name: first_name.to_string(),
+            last_name: last_name.to_string(),
+            display_name: display_name.map(str::to_string),
+            next_update: DateTime::from_timestamp(1_800_000_000, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_usernames_and_display_names() {
+        let resident = name("Some", "Resident", None);
+        assert_eq!(resident.username(), "some");
+        assert_eq!(resident.display_name(), "Some Resident");
+        let named = name("Ada", "Lovelace", Some("Countess"));
+        assert_eq!(named.username(), "ada.lovelace");
+        assert_eq!(named.display_name(), "Countess");
+
+        let expires = DateTime::from_timestamp(1_800_003_600, 0).unwrap();
+        let bad = Uuid::new_v4();
+        let xml = encode_display_names(std::slice::from_ref(&named), &[bad], expires);
+        assert!(xml.contains("<key>display_name</key><string>Countess</string>"));
+        assert!(xml.contains("<key>is_display_name_default</key><boolean>false</boolean>"));
+        assert!(xml.contains("<key>display_name_next_update</key><string>2027-01-15T08:00:00Z</string>"));
+        assert!(xml.contains(&format!("<key>bad_ids</key><array><uuid>{}</uuid></array>", bad)));
+
+        let update = encode_display_name_update(&named, "Ada Lovelace", expires);
+        assert_eq!(update["old_display_name"], "Ada Lovelace");
+        assert_eq!(update["agent"]["display_name"], "Countess");
+    }
+
+    #[test]
+    fn test_parse_requests() {
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let query = format!("ids={}&ids=nonsense&other=1&ids={}", first, second);
+        assert_eq!(parse_display_name_ids(&query), vec![first, second]);
+
+        let body = "<llsd><map><key>display_name</key><array>\
+                    <string>Some Resident</string><string>Sunny</string></array></map></llsd>";
+        let request = SetDisplayName::parse(body).unwrap();
+        assert_eq!(
+            (request.old_name.as_str(), request.new_name.as_str()),
+            ("Some Resident", "Sunny")
+        );
+        assert!(SetDisplayName::parse("<llsd><map /></llsd>").is_err());
+
+        let agent_id = UserId::new();
+        let query_id = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(query_id.as_bytes());
+        push_variable1(&mut payload, "ada love");
+        let request = AvatarPickerRequest::parse(&payload).unwrap();
+        assert_eq!((request.agent_id, request.query_id), (agent_id, query_id));
+        assert_eq!(request.name, "ada love");
+        assert!(AvatarPickerRequest::parse(&payload[..40]).is_err());
+
+        let avatars = [(UserId::new(), "Ada".to_string(), "Lovelace".to_string())];
+        let reply = encode_avatar_picker_reply(agent_id, query_id, &avatars);
+        assert_eq!(reply.len(), 1 + 16 + 16 + 1 + 16 + 5 + 10);
+    }
+}
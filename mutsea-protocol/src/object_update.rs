@@ -0,0 +1,214 @@
+//! Full object updates and kills
+//!
+//! An ObjectUpdate tells viewers everything about a prim: its shape, where
+//! it is and what it is attached to. KillObject removes prims from their
+//! view again.
+//!
+//! Viewers cache full updates by local ID and CRC. An ObjectUpdateCached
+//! lists just those, and the viewer draws the prims it has cached with a
+//! matching CRC, asking for the rest with RequestMultipleObjects.
+
+use crate::constants::packet_types;
+use crate::instant_message::push_variable2;
+use crate::{ProtocolError, ProtocolResult};
+use mutsea_core::{SceneObject, UserId};
+use uuid::Uuid;
+
+/// Where the CRC sits in an ObjectUpdate
+const CRC_OFFSET: usize = 33;
+
+/// An ObjectUpdate for `object`, a child of the prim or avatar with
+/// `parent_local_id` (0 for none) in `state`, carrying the newline
+/// separated `name_values`. Its CRC is that of the update itself, so it
+/// changes whenever anything sent does.
+pub fn encode_object_update(object: &SceneObject, parent_local_id: u32, state: u8, name_values: &str) -> Vec<u8> {
+    let mut payload = vec![packet_types::OBJECT_UPDATE];
+    // RegionData block
+    payload.extend_from_slice(&0u64.to_le_bytes()); // RegionHandle
+    payload.extend_from_slice(&0u16.to_le_bytes()); // TimeDilation
+
+    // ObjectData block
+    payload.push(1);
+    payload.extend_from_slice(&object.local_id.to_le_bytes());
+    payload.push(state);
+    payload.extend_from_slice(object.id.0.as_bytes());
+    payload.extend_from_slice(&0u32.to_le_bytes()); // CRC
+    payload.push(object.material);
+    payload.push(object.click_action);
+    for vector in [object.scale, object.position] {
+        for value in [vector.x, vector.y, vector.z] {
+            payload.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    for value in [
+        object.rotation.x,
+        object.rotation.y,
+        object.rotation.z,
+        object.rotation.w,
+    ] {
+        payload.extend_from_slice(&value.to_le_bytes());
+    }
+    for vector in [object.velocity, object.angular_velocity] {
+        for value in [vector.x, vector.y, vector.z] {
+            payload.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    payload.extend_from_slice(&parent_local_id.to_le_bytes());
+    payload.extend_from_slice(&object.flags.to_le_bytes());
+    payload.push(object.shape.path_curve);
+    payload.push(object.shape.profile_curve);
+    payload.extend_from_slice(&[0; 15]); // Path and profile parameters
+    payload.extend_from_slice(&4u32.to_le_bytes()); // TextureEntry
+    payload.extend_from_slice(&[0; 4]);
+    payload.extend_from_slice(&0u32.to_le_bytes()); // ExtraParams
+    payload.extend_from_slice(Uuid::nil().as_bytes()); // Sound
+    payload.extend_from_slice(&0.0f32.to_le_bytes()); // Gain
+    payload.extend_from_slice(&0.0f32.to_le_bytes()); // Radius
+    payload.push(0); // Sound flags
+    if name_values.is_empty() {
+        payload.extend_from_slice(&0u16.to_le_bytes());
+    } else {
+        push_variable2(&mut payload, name_values);
+    }
+    let crc = crc32fast::hash(&payload);
+    payload[CRC_OFFSET..CRC_OFFSET + 4].copy_from_slice(&crc.to_le_bytes());
+    payload
+}
+
+/// The CRC of an ObjectUpdate made by [`encode_object_update`]
+pub fn update_crc(update: &[u8]) -> Option<u32> {
+    let bytes = update.get(CRC_OFFSET..CRC_OFFSET + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// An ObjectUpdateCached listing `objects`, at most 255, by local ID,
+/// CRC and update flags
+pub fn encode_object_update_cached(objects: &[(u32, u32, u32)]) -> Vec<u8> {
+    let mut payload = vec![packet_types::OBJECT_UPDATE_CACHED as u8];
+    // RegionData block
+    payload.extend_from_slice(&0u64.to_le_bytes()); // RegionHandle
+    payload.extend_from_slice(&0u16.to_le_bytes()); // TimeDilation
+
+    // ObjectData blocks
+    payload.push(objects.len() as u8);
+    for (local_id, crc, update_flags) in objects {
+        payload.extend_from_slice(&local_id.to_le_bytes());
+        payload.extend_from_slice(&crc.to_le_bytes());
+        payload.extend_from_slice(&update_flags.to_le_bytes());
+    }
+    payload
+}
+
+/// A viewer asking for full updates of prims it didn't have cached, or
+/// had cached with another CRC
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestMultipleObjects {
+    /// Agent asking
+    pub agent_id: UserId,
+    /// Region-local IDs of the prims wanted
+    pub local_ids: Vec<u32>,
+}
+
+impl RequestMultipleObjects {
+    /// Parse a RequestMultipleObjects payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let too_short = || ProtocolError::Decoding("RequestMultipleObjects is too short".to_string());
+        // AgentID, SessionID, ObjectData count
+        let agent_id = payload
+            .get(0..16)
+            .and_then(|bytes| Uuid::from_slice(bytes).ok())
+            .ok_or_else(too_short)?;
+        let count = *payload.get(32).ok_or_else(too_short)? as usize;
+        // Each block is a cache miss type and a local ID; either way the
+        // viewer wants the full update
+        let local_ids = (0..count)
+            .map(|i| {
+                let offset = 33 + i * 5 + 1;
+                payload
+                    .get(offset..offset + 4)
+                    .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                    .ok_or_else(too_short)
+            })
+            .collect::<ProtocolResult<Vec<u32>>>()?;
+        Ok(Self {
+            agent_id: UserId::from_uuid(agent_id),
+            local_ids,
+        })
+    }
+}
+
+/// A KillObject removing the prims with `local_ids`, at most 255, from
+/// viewers
+pub fn encode_kill_object(local_ids: &[u32]) -> Vec<u8> {
+    let mut payload = vec![packet_types::KILL_OBJECT as u8];
+    payload.push(local_ids.len() as u8);
+    for local_id in local_ids {
+        payload.extend_from_slice(&local_id.to_le_bytes());
+    }
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::{UserId, Vector3};
+
+    #[test]
+    fn test_encode_update_and_kill() {
+        let object = SceneObject {
+            position: Vector3::new(128.0, 64.0, 22.0),
+            material: 3,
+            ..SceneObject::new(9, "Crate", UserId::new())
+        };
+
+        let update = encode_object_update(&object, 0, 0, "");
+        assert_eq!(update[0], packet_types::OBJECT_UPDATE);
+        assert_eq!(&update[12..16], &9u32.to_le_bytes());
+        assert_eq!(&update[17..33], object.id.0.as_bytes());
+        // Position follows CRC, material, click action and scale
+        assert_eq!(&update[51..55], &128.0f32.to_le_bytes());
+        assert_eq!(&update[update.len() - 2..], &[0, 0]);
+        let crc = update_crc(&update).unwrap();
+        assert_eq!(update_crc(&encode_object_update(&object, 0, 0, "")), Some(crc));
+        let moved = SceneObject {
+            position: Vector3::new(128.0, 65.0, 22.0),
+            ..object.clone()
+        };
+        assert_ne!(update_crc(&encode_object_update(&moved, 0, 0, "")), Some(crc));
+
+        let worn = encode_object_update(&object, 4, 0x20, "AttachItemID STRING RW SV x");
+        assert_eq!(worn.len(), update.len() + "AttachItemID STRING RW SV x".len() + 1);
+
+        let kill = encode_kill_object(&[7, 9]);
+        assert_eq!(kill[1], 2);
+        assert_eq!(kill.len(), 10);
+    }
+
+    #[test]
+    fn test_cached_updates_and_cache_misses() {
+        let cached = encode_object_update_cached(&[(9, 0xDEAD_BEEF, 0), (10, 1, 0)]);
+        assert_eq!(cached[0], packet_types::OBJECT_UPDATE_CACHED as u8);
+        assert_eq!(cached[11], 2);
+        assert_eq!(&cached[16..20], &0xDEAD_BEEFu32.to_le_bytes());
+        assert_eq!(cached.len(), 12 + 2 * 12);
+
+        let agent_id = UserId::new();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.push(2);
+        for (miss_type, local_id) in [(0u8, 9u32), (1, 10)] {
+            payload.push(miss_type);
+            payload.extend_from_slice(&local_id.to_le_bytes());
+        }
+        let request = RequestMultipleObjects::parse(&payload).unwrap();
+        assert_eq!(
+            request,
+            RequestMultipleObjects {
+                agent_id,
+                local_ids: vec![9, 10]
+            }
+        );
+        assert!(RequestMultipleObjects::parse(&payload[..39]).is_err());
+    }
+}
@@ -99,9 +99,7 @@ impl Packet {
 
     /// Create an acknowledgment packet
     pub fn ack(acks: Vec<u32>) -> Self {
-        let mut packet = Self::new(flags::ACK, 0, Vec::new());
-        packet.appended_acks = acks;
-        packet
+        Self::new(flags::ACK, 0, Vec::new()).with_acks(acks)
     }
 
     /// Add acknowledgments to packet
@@ -148,12 +146,12 @@ impl Packet {
             buffer.extend_from_slice(&self.payload);
         }
 
-        // Write appended acknowledgments
+        // Write appended acknowledgments, their count last
         if self.header.has_appended_acks() {
-            buffer.write_u8(self.appended_acks.len() as u8)?;
             for ack in &self.appended_acks {
                 buffer.write_u32::<BigEndian>(*ack)?;
             }
+            buffer.write_u8(self.appended_acks.len() as u8)?;
         }
 
         Ok(buffer)
@@ -165,12 +163,38 @@ impl Packet {
             return Err(ProtocolError::InvalidPacket("Packet too short".to_string()));
         }
 
-        let (header, mut offset) = PacketHeader::deserialize(data)?;
+        let (header, offset) = PacketHeader::deserialize(data)?;
+
+        // Strip appended acks first so they are never mistaken for a message ID
+        let mut payload_end = data.len();
+        let mut appended_acks = Vec::new();
+
+        if header.has_appended_acks() && payload_end > 0 {
+            // Find appended acks at the end
+            let ack_count_pos = payload_end - 1;
+            if ack_count_pos < data.len() {
+                let ack_count = data[ack_count_pos] as usize;
+                let acks_size = ack_count * 4 + 1; // 4 bytes per ack + count byte
+
+                if payload_end >= acks_size {
+                    payload_end -= acks_size;
+
+                    // Parse acknowledgments
+                    let acks_start = payload_end;
+                    let mut cursor = Cursor::new(&data[acks_start..acks_start + ack_count * 4]);
+                    for _ in 0..ack_count {
+                        appended_acks.push(cursor.read_u32::<BigEndian>()?);
+                    }
+                }
+            }
+        }
+
         let mut message_id = None;
         let mut payload_start = offset;
 
-        // Check if this is a special packet type
-        if offset < data.len() {
+        // Check if this is a special packet type. Ack-only packets carry
+        // nothing but appended acks, so there is no message ID to read.
+        if offset < payload_end {
             let first_byte = data[offset];
 
             // Parse message ID
@@ -197,30 +221,6 @@ impl Packet {
             }
         }
 
-        // Extract payload
-        let mut payload_end = data.len();
-        let mut appended_acks = Vec::new();
-
-        if header.has_appended_acks() && payload_end > 0 {
-            // Find appended acks at the end
-            let ack_count_pos = payload_end - 1;
-            if ack_count_pos < data.len() {
-                let ack_count = data[ack_count_pos] as usize;
-                let acks_size = ack_count * 4 + 1; // 4 bytes per ack + count byte
-
-                if payload_end >= acks_size {
-                    payload_end -= acks_size;
-
-                    // Parse acknowledgments
-                    let acks_start = payload_end;
-                    let mut cursor = Cursor::new(&data[acks_start..acks_start + ack_count * 4]);
-                    for _ in 0..ack_count {
-                        appended_acks.push(cursor.read_u32::<BigEndian>()?);
-                    }
-                }
-            }
-        }
-
         // Extract payload
         let mut payload = if payload_start < payload_end {
             data[payload_start..payload_end].to_vec()
@@ -412,13 +412,15 @@ mod tests {
 
     #[test]
     fn test_packet_serialization() {
+        // Every message on the wire starts with its ID
         let payload = b"Hello, World!".to_vec();
-        let packet = Packet::reliable(12345, payload.clone());
+        let packet = Packet::reliable(12345, payload.clone()).with_message_id(5);
 
         let serialized = packet.serialize().unwrap();
         let deserialized = Packet::deserialize(&serialized).unwrap();
 
         assert_eq!(packet.header.sequence, deserialized.header.sequence);
+        assert_eq!(deserialized.message_id, Some(5));
         assert_eq!(packet.payload, deserialized.payload);
         assert!(deserialized.header.is_reliable());
     }
@@ -447,6 +449,16 @@ mod tests {
         assert!(deserialized.header.has_appended_acks());
     }
 
+    #[test]
+    fn test_ack_only_packet() {
+        let serialized = Packet::ack(vec![7, 8]).serialize().unwrap();
+        let deserialized = Packet::deserialize(&serialized).unwrap();
+
+        assert_eq!(deserialized.message_id, None);
+        assert!(deserialized.payload.is_empty());
+        assert_eq!(deserialized.appended_acks, vec![7, 8]);
+    }
+
     #[test]
     fn test_reliable_packet_resend() {
         let packet = Packet::reliable(12345, b"Test".to_vec());
@@ -0,0 +1,450 @@
+//! Avatar profiles, picks and landmarks
+//!
+//! The profile floater asks for an avatar's profile with
+//! AvatarPropertiesRequest, answered by AvatarPropertiesReply, and saves
+//! the agent's own with AvatarPropertiesUpdate. Picks are listed with the
+//! GenericMessage method `avatarpicksrequest` and shown one at a time with
+//! `pickinforequest`, answered by AvatarPicksReply and PickInfoReply; they
+//! are saved with PickInfoUpdate and removed with PickDelete.
+//! CreateLandmarkForEvent asks for a landmark to where the agent stands,
+//! answered by UpdateCreateInventoryItem with the new item.
+
+use crate::codec::MessageDecoder;
+use crate::constants::{asset_types, inventory_types, packet_types};
+use crate::instant_message::{push_variable1, push_variable2};
+use crate::inventory::ItemData;
+use crate::ProtocolResult;
+use mutsea_core::{RegionId, UserId, Vector3};
+use uuid::Uuid;
+
+/// The GenericMessage method listing an avatar's picks
+pub const METHOD_AVATAR_PICKS_REQUEST: &str = "avatarpicksrequest";
+/// The GenericMessage method asking for one pick
+pub const METHOD_PICK_INFO_REQUEST: &str = "pickinforequest";
+
+/// AvatarPropertiesReply flag: show the profile in search
+pub const AVATAR_ALLOW_PUBLISH: u32 = 1 << 0;
+/// AvatarPropertiesReply flag: the profile has mature content
+pub const AVATAR_MATURE_PUBLISH: u32 = 1 << 1;
+/// AvatarPropertiesReply flag: the avatar is online
+pub const AVATAR_ONLINE: u32 = 1 << 4;
+
+/// Landmarks are made with all permissions
+pub const LANDMARK_PERMISSIONS: u32 = 0x7FFF_FFFF;
+
+/// A method call from the viewer; it has the layout of EstateOwnerMessage
+pub type GenericMessage = crate::moderation::EstateOwnerMessage;
+
+/// What an avatar tells about itself in its profile
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AvatarProperties {
+    /// Profile picture
+    pub image_id: Uuid,
+    /// The picture on the First Life tab
+    pub fl_image_id: Uuid,
+    /// Partner's avatar, or nil
+    pub partner_id: Uuid,
+    /// The About text on the main tab
+    pub about_text: String,
+    /// The text on the First Life tab
+    pub fl_about_text: String,
+    /// Date of birth as the viewer shows it, e.g. 10/15/2026
+    pub born_on: String,
+    /// Web profile address
+    pub profile_url: String,
+    /// Account kind, shown as the avatar's "payment info"
+    pub charter_member: String,
+    /// Whether the profile shows in search
+    pub allow_publish: bool,
+    /// Whether the profile has mature content
+    pub mature_publish: bool,
+}
+
+/// A request for an avatar's profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvatarPropertiesRequest {
+    /// Agent asking
+    pub agent_id: UserId,
+    /// Avatar whose profile is wanted
+    pub avatar_id: UserId,
+}
+
+impl AvatarPropertiesRequest {
+    /// Parse an AvatarPropertiesRequest payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            avatar_id: UserId::from_uuid(decoder.read_uuid()?),
+        })
+    }
+}
+
+/// An agent saving its own profile. Partner, birth date and account kind
+/// are not the agent's to change and are left empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvatarPropertiesUpdate {
+    /// Agent saving its profile
+    pub agent_id: UserId,
+    /// The profile as sent
+    pub properties: AvatarProperties,
+}
+
+impl AvatarPropertiesUpdate {
+    /// Parse an AvatarPropertiesUpdate payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            properties: AvatarProperties {
+                image_id: decoder.read_uuid()?,
+                fl_image_id: decoder.read_uuid()?,
+                about_text: decoder.read_variable2()?,
+                fl_about_text: decoder.read_variable1()?,
+                allow_publish: decoder.read_bool()?,
+                mature_publish: decoder.read_bool()?,
+                profile_url: decoder.read_variable1()?,
+                ..AvatarProperties::default()
+            },
+        })
+    }
+}
+
+/// Send `agent_id` the profile of `avatar_id`
+pub fn encode_avatar_properties_reply(
+    agent_id: UserId,
+    avatar_id: UserId,
+    properties: &AvatarProperties,
+    online: bool,
+) -> Vec<u8> {
+    let mut payload = vec![packet_types::AVATAR_PROPERTIES_REPLY as u8];
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    payload.extend_from_slice(avatar_id.as_uuid().as_bytes());
+    payload.extend_from_slice(properties.image_id.as_bytes());
+    payload.extend_from_slice(properties.fl_image_id.as_bytes());
+    payload.extend_from_slice(properties.partner_id.as_bytes());
+    push_variable2(&mut payload, &properties.about_text);
+    push_variable1(&mut payload, &properties.fl_about_text);
+    push_variable1(&mut payload, &properties.born_on);
+    push_variable1(&mut payload, &properties.profile_url);
+    push_variable1(&mut payload, &properties.charter_member);
+    let mut flags = 0;
+    if properties.allow_publish {
+        flags |= AVATAR_ALLOW_PUBLISH;
+    }
+    if properties.mature_publish {
+        flags |= AVATAR_MATURE_PUBLISH;
+    }
+    if online {
+        flags |= AVATAR_ONLINE;
+    }
+    payload.extend_from_slice(&flags.to_le_bytes());
+    payload
+}
+
+/// A place picked in a profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pick {
+    /// Pick identifier
+    pub pick_id: Uuid,
+    /// Avatar that made the pick
+    pub creator_id: UserId,
+    /// Whether the pick is an editor's choice
+    pub top_pick: bool,
+    /// Parcel picked
+    pub parcel_id: Uuid,
+    /// Title
+    pub name: String,
+    /// Description
+    pub description: String,
+    /// Texture shown with the pick
+    pub snapshot_id: Uuid,
+    /// Name of the avatar that made the pick
+    pub user: String,
+    /// Name of the parcel picked
+    pub original_name: String,
+    /// Region of the parcel picked
+    pub sim_name: String,
+    /// Global position, in metres from the grid's origin
+    pub pos_global: [f64; 3],
+    /// Position among the avatar's picks
+    pub sort_order: i32,
+    /// Whether the pick is shown
+    pub enabled: bool,
+}
+
+/// An agent saving one of its picks. The names of the creator, parcel and
+/// region are not sent and are left empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PickInfoUpdate {
+    /// Agent saving the pick
+    pub agent_id: UserId,
+    /// The pick as sent
+    pub pick: Pick,
+}
+
+impl PickInfoUpdate {
+    /// Parse a PickInfoUpdate payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        let pick_id = decoder.read_uuid()?;
+        let creator_id = UserId::from_uuid(decoder.read_uuid()?);
+        let top_pick = decoder.read_bool()?;
+        let parcel_id = decoder.read_uuid()?;
+        let pick_name = decoder.read_variable1()?;
+        let description = decoder.read_variable2()?;
+        let snapshot_id = decoder.read_uuid()?;
+        let pos_global = [decoder.read_f64()?, decoder.read_f64()?, decoder.read_f64()?];
+        Ok(Self {
+            agent_id,
+            pick: Pick {
+                pick_id,
+                creator_id,
+                top_pick,
+                parcel_id,
+                name: pick_name,
+                description,
+                snapshot_id,
+                user: String::new(),
+                original_name: String::new(),
+                sim_name: String::new(),
+                pos_global,
+                sort_order: decoder.read_i32()?,
+                enabled: decoder.read_bool()?,
+            },
+        })
+    }
+}
+
+/// An agent removing one of its picks
+#[derive(Debug, Clone, PartialEq)]
+pub struct PickDelete {
+    /// Agent removing the pick
+    pub agent_id: UserId,
+    /// Pick to remove
+    pub pick_id: Uuid,
+}
+
+impl PickDelete {
+    /// Parse a PickDelete payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            pick_id: decoder.read_uuid()?,
+        })
+    }
+}
+
+/// List the picks of `target_id` for `agent_id`, by ID and name
+pub fn encode_avatar_picks_reply(agent_id: UserId, target_id: UserId, picks: &[(Uuid, String)]) -> Vec<u8> {
+    let mut payload = vec![packet_types::AVATAR_PICKS_REPLY as u8];
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    payload.extend_from_slice(target_id.as_uuid().as_bytes());
+    payload.push(picks.len().min(u8::MAX as usize) as u8);
+    for (pick_id, name) in picks.iter().take(u8::MAX as usize) {
+        payload.extend_from_slice(pick_id.as_bytes());
+        push_variable1(&mut payload, name);
+    }
+    payload
+}
+
+/// Show `agent_id` one pick
+pub fn encode_pick_info_reply(agent_id: UserId, pick: &Pick) -> Vec<u8> {
+    let mut payload = vec![packet_types::PICK_INFO_REPLY as u8];
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    payload.extend_from_slice(pick.pick_id.as_bytes());
+    payload.extend_from_slice(pick.creator_id.as_uuid().as_bytes());
+    payload.push(pick.top_pick as u8);
+    payload.extend_from_slice(pick.parcel_id.as_bytes());
+    push_variable1(&mut payload, &pick.name);
+    push_variable2(&mut payload, &pick.description);
+    payload.extend_from_slice(pick.snapshot_id.as_bytes());
+    push_variable1(&mut payload, &pick.user);
+    push_variable1(&mut payload, &pick.original_name);
+    push_variable1(&mut payload, &pick.sim_name);
+    for value in pick.pos_global {
+        payload.extend_from_slice(&value.to_le_bytes());
+    }
+    payload.extend_from_slice(&pick.sort_order.to_le_bytes());
+    payload.push(pick.enabled as u8);
+    payload
+}
+
+/// A request for a landmark to where the agent stands
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateLandmarkForEvent {
+    /// Agent asking
+    pub agent_id: UserId,
+    /// Event the landmark is for, or 0
+    pub event_id: u32,
+    /// The inventory folder to put it in
+    pub folder_id: Uuid,
+    /// Name of the landmark
+    pub name: String,
+}
+
+impl CreateLandmarkForEvent {
+    /// Parse a CreateLandmarkForEvent payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        Ok(Self {
+            agent_id,
+            event_id: decoder.read_u32()?,
+            folder_id: decoder.read_uuid()?,
+            name: decoder.read_variable1()?,
+        })
+    }
+}
+
+/// The text of a landmark asset to `position` in a region
+pub fn landmark_asset(region_id: RegionId, region_handle: u64, position: Vector3) -> Vec<u8> {
+    format!(
+        "Landmark version 2\nregion_id {}\nlocal_pos {} {} {}\nregion_handle {}\n",
+        region_id, position.x, position.y, position.z, region_handle
+    )
+    .into_bytes()
+}
+
+/// A landmark just put in its owner's inventory
+#[derive(Debug, Clone, PartialEq)]
+pub struct LandmarkItem {
+    /// Inventory item identifier
+    pub item_id: Uuid,
+    /// Folder the item is in
+    pub folder_id: Uuid,
+    /// The landmark asset
+    pub asset_id: Uuid,
+    /// Item name
+    pub name: String,
+    /// Item description
+    pub description: String,
+    /// Unix time
+    pub creation_date: i32,
+}
+
+impl LandmarkItem {
+    /// The landmark as an item in the inventory of `owner_id`
+    pub fn to_item_data(&self, owner_id: UserId) -> ItemData {
+        ItemData {
+            item_id: self.item_id,
+            parent_id: self.folder_id,
+            asset_id: self.asset_id,
+            owner_id,
+            creator_id: owner_id,
+            name: self.name.clone(),
+            description: self.description.clone(),
+            asset_type: asset_types::LANDMARK as i32,
+            inv_type: inventory_types::LANDMARK as i32,
+            base_mask: LANDMARK_PERMISSIONS,
+            owner_mask: LANDMARK_PERMISSIONS,
+            everyone_mask: 0,
+            next_owner_mask: LANDMARK_PERMISSIONS,
+            created_at: self.creation_date as i64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent_data(agent_id: UserId) -> Vec<u8> {
+        let mut payload = agent_id.as_uuid().as_bytes().to_vec();
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload
+    }
+
+    #[test]
+    fn test_parse_properties_update_and_encode_reply() {
+        let agent_id = UserId::new();
+        let image_id = Uuid::new_v4();
+        let mut payload = agent_data(agent_id);
+        payload.extend_from_slice(image_id.as_bytes());
+        payload.extend_from_slice(Uuid::nil().as_bytes());
+        push_variable2(&mut payload, "Builder of things");
+        push_variable1(&mut payload, "");
+        payload.extend_from_slice(&[1, 0]);
+        push_variable1(&mut payload, "https://example.org");
+
+        let update = AvatarPropertiesUpdate::parse(&payload).unwrap();
+        assert_eq!(update.agent_id, agent_id);
+        assert_eq!(update.properties.image_id, image_id);
+        assert_eq!(update.properties.about_text, "Builder of things");
+        assert_eq!(update.properties.profile_url, "https://example.org");
+        assert!(update.properties.allow_publish && !update.properties.mature_publish);
+        assert!(AvatarPropertiesUpdate::parse(&payload[..payload.len() - 3]).is_err());
+
+        let reply = encode_avatar_properties_reply(agent_id, agent_id, &update.properties, true);
+        let flags = u32::from_le_bytes(reply[reply.len() - 4..].try_into().unwrap());
+        assert_eq!(flags, AVATAR_ALLOW_PUBLISH | AVATAR_ONLINE);
+    }
+
+    #[test]
+    fn test_parse_pick_update_and_encode_info() {
+        let agent_id = UserId::new();
+        let pick_id = Uuid::new_v4();
+        let mut payload = agent_data(agent_id);
+        payload.extend_from_slice(pick_id.as_bytes());
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.push(0);
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        push_variable1(&mut payload, "Sunny Beach");
+        push_variable2(&mut payload, "Sand and sea");
+        payload.extend_from_slice(Uuid::nil().as_bytes());
+        for value in [256000.5f64, 256128.0, 22.0] {
+            payload.extend_from_slice(&value.to_le_bytes());
+        }
+        payload.extend_from_slice(&3i32.to_le_bytes());
+        payload.push(1);
+
+        let update = PickInfoUpdate::parse(&payload).unwrap();
+        assert_eq!((update.agent_id, update.pick.pick_id), (agent_id, pick_id));
+        assert_eq!(update.pick.name, "Sunny Beach");
+        assert_eq!(update.pick.description, "Sand and sea");
+        assert_eq!(update.pick.pos_global, [256000.5, 256128.0, 22.0]);
+        assert_eq!(update.pick.sort_order, 3);
+        assert!(update.pick.enabled && !update.pick.top_pick);
+        assert!(PickInfoUpdate::parse(&payload[..payload.len() - 1]).is_err());
+
+        let info = encode_pick_info_reply(agent_id, &update.pick);
+        assert_eq!(info[0], packet_types::PICK_INFO_REPLY as u8);
+        assert_eq!(info.last(), Some(&1));
+        let list = encode_avatar_picks_reply(agent_id, agent_id, &[(pick_id, update.pick.name)]);
+        assert_eq!(list.len(), 1 + 32 + 1 + 16 + 13);
+    }
+
+    #[test]
+    fn test_parse_landmark_request() {
+        let agent_id = UserId::new();
+        let folder_id = Uuid::new_v4();
+        let mut payload = agent_data(agent_id);
+        payload.extend_from_slice(&0u32.to_le_bytes());
+        payload.extend_from_slice(folder_id.as_bytes());
+        push_variable1(&mut payload, "Home");
+
+        let request = CreateLandmarkForEvent::parse(&payload).unwrap();
+        assert_eq!((request.agent_id, request.folder_id), (agent_id, folder_id));
+        assert_eq!(request.name, "Home");
+
+        let region_id = RegionId::new();
+        let asset = String::from_utf8(landmark_asset(region_id, 42, Vector3::new(128.0, 64.5, 22.0))).unwrap();
+        assert_eq!(
+            asset,
+            format!(
+                "Landmark version 2\nregion_id {}\nlocal_pos 128 64.5 22\nregion_handle 42\n",
+                region_id
+            )
+        );
+    }
+}
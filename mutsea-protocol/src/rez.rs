@@ -0,0 +1,211 @@
+//! Taking prims into inventory and rezzing them again
+//!
+//! DeRezObject takes the selected prims out of the region, or copies them,
+//! into one inventory item; large selections arrive over several packets
+//! sharing a TransactionID. RezObject rezs an object item where the agent's
+//! ray from the camera hit. The new item is sent to the viewer with
+//! UpdateCreateInventoryItem.
+
+use crate::constants::{asset_types, inventory_types};
+use crate::inventory::ItemData;
+use crate::codec::MessageDecoder;
+use crate::ProtocolResult;
+use mutsea_core::{UserId, Vector3};
+use uuid::Uuid;
+
+/// DeRezObject destination: copy the prims into inventory, leaving them
+pub const DEREZ_TAKE_COPY: u8 = 1;
+
+/// DeRezObject destination: take the prims into inventory
+pub const DEREZ_TAKE: u8 = 4;
+
+/// DeRezObject destination: delete the prims into the Trash folder
+pub const DEREZ_DELETE: u8 = 6;
+
+/// Permissions an owner keeps on the objects it takes
+pub const OBJECT_PERMISSIONS: u32 = 0x7FFF_FFFF;
+
+/// Prims, by local ID, an agent asked to take into inventory, or one
+/// packet of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeRezObject {
+    /// Agent taking the prims
+    pub agent_id: UserId,
+    /// One of the `DEREZ_` destinations
+    pub destination: u8,
+    /// Inventory folder to put the item in, nil for the default one
+    pub destination_id: Uuid,
+    /// Shared by the packets of one selection
+    pub transaction_id: Uuid,
+    /// Number of packets the selection was split into
+    pub packet_count: u8,
+    /// Which of them this is, from 0
+    pub packet_number: u8,
+    /// Prims in this packet
+    pub local_ids: Vec<u32>,
+}
+
+impl DeRezObject {
+    /// Parse a DeRezObject payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16 * 2)?; // SessionID, GroupID
+        let destination = decoder.read_u8()?;
+        let destination_id = decoder.read_uuid()?;
+        let transaction_id = decoder.read_uuid()?;
+        let packet_count = decoder.read_u8()?;
+        let packet_number = decoder.read_u8()?;
+        // ObjectLocalID for each prim
+        let count = decoder.read_u8()?;
+
+        Ok(Self {
+            agent_id,
+            destination,
+            destination_id,
+            transaction_id,
+            packet_count,
+            packet_number,
+            local_ids: (0..count).map(|_| decoder.read_u32()).collect::<ProtocolResult<_>>()?,
+        })
+    }
+}
+
+/// An object inventory item an agent asked to rez
+#[derive(Debug, Clone, PartialEq)]
+pub struct RezObject {
+    /// Agent rezzing the item
+    pub agent_id: UserId,
+    /// Start of the agent's ray, usually its camera
+    pub ray_start: Vector3,
+    /// Where the agent's ray hit the ground or a prim
+    pub ray_end: Vector3,
+    /// Whether to take the item out of inventory, as for no-copy items
+    pub remove_item: bool,
+    /// Object item to rez
+    pub item_id: Uuid,
+    /// Folder the item is in
+    pub folder_id: Uuid,
+}
+
+impl RezObject {
+    /// Parse a RezObject payload. The item's own fields are left out: the
+    /// region reads them from inventory.
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16 * 3 + 1)?; // SessionID, GroupID, FromTaskID, BypassRaycast
+        let ray_start = decoder.read_vector3()?;
+        let ray_end = decoder.read_vector3()?;
+        decoder.skip(16 + 2)?; // RayTargetID, RayEndIsIntersection, RezSelected
+        let remove_item = decoder.read_bool()?;
+        // ItemFlags and the group, everyone and next owner masks
+        decoder.skip(4 * 4)?;
+
+        Ok(Self {
+            agent_id,
+            ray_start,
+            ray_end,
+            remove_item,
+            item_id: decoder.read_uuid()?,
+            folder_id: decoder.read_uuid()?,
+        })
+    }
+}
+
+/// An object item made from prims an agent took
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectItem {
+    /// The new item
+    pub item_id: Uuid,
+    /// Folder it was put in
+    pub folder_id: Uuid,
+    /// Asset holding the prims
+    pub asset_id: Uuid,
+    /// Creator of the first prim taken
+    pub creator_id: UserId,
+    /// Item name, after the first prim
+    pub name: String,
+    /// Item description, from the first prim
+    pub description: String,
+    /// Unix time
+    pub creation_date: i32,
+}
+
+impl ObjectItem {
+    /// The object as an item in the inventory of `owner_id`
+    pub fn to_item_data(&self, owner_id: UserId) -> ItemData {
+        ItemData {
+            item_id: self.item_id,
+            parent_id: self.folder_id,
+            asset_id: self.asset_id,
+            owner_id,
+            creator_id: self.creator_id,
+            name: self.name.clone(),
+            description: self.description.clone(),
+            asset_type: asset_types::OBJECT as i32,
+            inv_type: inventory_types::OBJECT as i32,
+            base_mask: OBJECT_PERMISSIONS,
+            owner_mask: OBJECT_PERMISSIONS,
+            everyone_mask: 0,
+            next_owner_mask: OBJECT_PERMISSIONS,
+            created_at: self.creation_date as i64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_derez() {
+        let agent_id = UserId::new();
+        let folder_id = Uuid::new_v4();
+        let transaction_id = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(Uuid::nil().as_bytes());
+        payload.push(DEREZ_TAKE);
+        payload.extend_from_slice(folder_id.as_bytes());
+        payload.extend_from_slice(transaction_id.as_bytes());
+        payload.extend_from_slice(&[2, 1]);
+        payload.push(2);
+        payload.extend_from_slice(&7u32.to_le_bytes());
+        payload.extend_from_slice(&9u32.to_le_bytes());
+
+        let derez = DeRezObject::parse(&payload).unwrap();
+        assert_eq!(derez.agent_id, agent_id);
+        assert_eq!(derez.destination, DEREZ_TAKE);
+        assert_eq!(derez.destination_id, folder_id);
+        assert_eq!(derez.transaction_id, transaction_id);
+        assert_eq!((derez.packet_count, derez.packet_number), (2, 1));
+        assert_eq!(derez.local_ids, vec![7, 9]);
+        assert!(DeRezObject::parse(&payload[..payload.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_parse_rez() {
+        let agent_id = UserId::new();
+        let item_id = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(&[0; 16 * 3 + 1]);
+        for value in [128.0f32, 128.0, 30.0, 130.0, 128.0, 21.5] {
+            payload.extend_from_slice(&value.to_le_bytes());
+        }
+        payload.extend_from_slice(&[0; 16]);
+        payload.extend_from_slice(&[1, 0, 1]);
+        payload.extend_from_slice(&[0; 16]);
+        payload.extend_from_slice(item_id.as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+
+        let rez = RezObject::parse(&payload).unwrap();
+        assert_eq!(rez.agent_id, agent_id);
+        assert_eq!(rez.ray_end, Vector3::new(130.0, 128.0, 21.5));
+        assert!(rez.remove_item);
+        assert_eq!(rez.item_id, item_id);
+        assert!(RezObject::parse(&payload[..payload.len() - 1]).is_err());
+    }
+}
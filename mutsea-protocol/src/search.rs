@@ -0,0 +1,150 @@
+//! Place search
+//!
+//! The viewer's search floater sends DirPlacesQuery with the text to look
+//! for and which content ratings to include, and is answered with one or
+//! more DirPlacesReply pages of matching parcels.
+
+use crate::codec::MessageDecoder;
+use crate::constants::packet_types;
+use crate::instant_message::push_variable1;
+use crate::ProtocolResult;
+use mutsea_core::{Maturity, UserId};
+use uuid::Uuid;
+
+/// Include General (PG) places
+pub const DFQ_INC_PG: u32 = 1 << 24;
+/// Include Moderate (Mature) places
+pub const DFQ_INC_MATURE: u32 = 1 << 25;
+/// Include Adult places
+pub const DFQ_INC_ADULT: u32 = 1 << 26;
+
+/// Results in each page of a place search
+pub const PLACES_PER_PAGE: usize = 100;
+
+/// A search for places by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirPlacesQuery {
+    /// Agent searching
+    pub agent_id: UserId,
+    /// ID the reply is matched to
+    pub query_id: Uuid,
+    /// Text searched for
+    pub text: String,
+    /// `DFQ_*` flags, including the content ratings wanted
+    pub flags: u32,
+    /// Index of the first result wanted
+    pub start: i32,
+}
+
+impl DirPlacesQuery {
+    /// Parse a DirPlacesQuery payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        let query_id = decoder.read_uuid()?;
+        let text = decoder.read_variable1()?;
+        let flags = decoder.read_u32()?;
+        decoder.skip(1)?; // Category
+        decoder.read_variable1()?; // SimName
+        Ok(Self {
+            agent_id,
+            query_id,
+            text,
+            flags,
+            start: decoder.read_i32()?,
+        })
+    }
+
+    /// Whether places rated `maturity` were asked for. Queries that name no
+    /// rating include them all.
+    pub fn includes(&self, maturity: Maturity) -> bool {
+        includes_maturity(self.flags, maturity)
+    }
+}
+
+/// Whether a search with DirFindQuery or DirPlacesQuery `flags` asks for
+/// results rated `maturity`. Searches that name no rating include them all.
+pub fn includes_maturity(flags: u32, maturity: Maturity) -> bool {
+    let all = DFQ_INC_PG | DFQ_INC_MATURE | DFQ_INC_ADULT;
+    if flags & all == 0 {
+        return true;
+    }
+    let flag = match maturity {
+        Maturity::Pg => DFQ_INC_PG,
+        Maturity::Mature => DFQ_INC_MATURE,
+        Maturity::Adult => DFQ_INC_ADULT,
+    };
+    flags & flag != 0
+}
+
+/// One parcel found by a place search
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaceResult {
+    /// Parcel found
+    pub parcel_id: Uuid,
+    /// Parcel name
+    pub name: String,
+    /// Whether the parcel is for sale
+    pub for_sale: bool,
+    /// Traffic, a measure of how popular the parcel is
+    pub dwell: f32,
+}
+
+/// Answer the search `query_id` of `agent_id` with `places`
+pub fn encode_dir_places_reply(agent_id: UserId, query_id: Uuid, places: &[PlaceResult]) -> Vec<u8> {
+    let mut payload = vec![packet_types::DIR_PLACES_REPLY as u8];
+    payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+    // QueryData block
+    payload.push(1);
+    payload.extend_from_slice(query_id.as_bytes());
+    // QueryReplies blocks
+    payload.push(places.len().min(u8::MAX as usize) as u8);
+    for place in places.iter().take(u8::MAX as usize) {
+        payload.extend_from_slice(place.parcel_id.as_bytes());
+        push_variable1(&mut payload, &place.name);
+        payload.push(place.for_sale as u8);
+        payload.push(0); // Auction
+        payload.extend_from_slice(&place.dwell.to_le_bytes());
+    }
+    // StatusData blocks
+    payload.push(0);
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_places_query_and_encode_reply() {
+        let agent_id = UserId::new();
+        let query_id = Uuid::new_v4();
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.extend_from_slice(query_id.as_bytes());
+        push_variable1(&mut payload, "beach");
+        payload.extend_from_slice(&(DFQ_INC_PG | DFQ_INC_MATURE).to_le_bytes());
+        payload.push(0);
+        push_variable1(&mut payload, "");
+        payload.extend_from_slice(&100i32.to_le_bytes());
+
+        let query = DirPlacesQuery::parse(&payload).unwrap();
+        assert_eq!((query.agent_id, query.query_id), (agent_id, query_id));
+        assert_eq!((query.text.as_str(), query.start), ("beach", 100));
+        assert!(query.includes(Maturity::Mature));
+        assert!(!query.includes(Maturity::Adult));
+        assert!(DirPlacesQuery::parse(&payload[..payload.len() - 1]).is_err());
+
+        let place = PlaceResult {
+            parcel_id: Uuid::new_v4(),
+            name: "Beach".to_string(),
+            for_sale: false,
+            dwell: 0.0,
+        };
+        let reply = encode_dir_places_reply(agent_id, query_id, &[place]);
+        assert_eq!(reply.len(), 1 + 16 + 1 + 16 + 1 + 16 + 7 + 2 + 4 + 1);
+        assert_eq!(reply[34], 1);
+    }
+}
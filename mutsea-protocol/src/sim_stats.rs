@@ -0,0 +1,207 @@
+//! SimStats message encoding
+//!
+//! Viewers draw their statistics bar from the SimStats message, a list of
+//! `(StatID, value)` pairs sent about once a second.
+
+use crate::constants::packet_types;
+
+/// Stat identifiers understood by viewers
+pub mod stat_ids {
+    /// Simulated seconds per real second
+    pub const TIME_DILATION: u32 = 0;
+    /// Simulation frames per second
+    pub const SIM_FPS: u32 = 1;
+    /// Physics frames per second
+    pub const PHYSICS_FPS: u32 = 2;
+    /// Agent updates per second
+    pub const AGENT_UPDATES: u32 = 3;
+    /// Milliseconds per frame
+    pub const FRAME_MS: u32 = 4;
+    /// Milliseconds per frame spent on the network
+    pub const NET_MS: u32 = 5;
+    /// Milliseconds per frame spent on anything else
+    pub const OTHER_MS: u32 = 6;
+    /// Milliseconds per frame spent on physics
+    pub const PHYSICS_MS: u32 = 7;
+    /// Milliseconds per frame spent on agents
+    pub const AGENT_MS: u32 = 8;
+    /// Milliseconds per frame spent on images
+    pub const IMAGE_MS: u32 = 9;
+    /// Milliseconds per frame spent on scripts
+    pub const SCRIPT_MS: u32 = 10;
+    /// Prims in the region
+    pub const TOTAL_PRIM: u32 = 11;
+    /// Physical or scripted prims
+    pub const ACTIVE_PRIM: u32 = 12;
+    /// Avatars in the region
+    pub const AGENTS: u32 = 13;
+    /// Avatars seeing the region from a neighbour
+    pub const CHILD_AGENTS: u32 = 14;
+    /// Running scripts
+    pub const ACTIVE_SCRIPTS: u32 = 15;
+    /// Packets received per second
+    pub const IN_PPS: u32 = 17;
+    /// Packets sent per second
+    pub const OUT_PPS: u32 = 18;
+    /// Asset downloads in flight
+    pub const PENDING_DOWNLOADS: u32 = 19;
+    /// Asset uploads in flight
+    pub const PENDING_UPLOADS: u32 = 20;
+    /// Bytes of reliable packets not yet acknowledged
+    pub const UNACKED_BYTES: u32 = 24;
+    /// Milliseconds per frame left unused
+    pub const SIM_SPARE_TIME: u32 = 32;
+    /// Milliseconds per frame spent sleeping
+    pub const SIM_SLEEP_TIME: u32 = 33;
+}
+
+/// One second of region statistics as shown in the viewer stats bar
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimStats {
+    /// Region grid X position in region units
+    pub region_x: u32,
+    /// Region grid Y position in region units
+    pub region_y: u32,
+    /// Region flags (RegionFlags bitfield)
+    pub region_flags: u32,
+    /// Maximum number of prims the region allows
+    pub object_capacity: u32,
+    /// Simulated seconds per real second, 0.0 to 1.0
+    pub time_dilation: f32,
+    /// Simulation frames per second
+    pub sim_fps: f32,
+    /// Physics frames per second
+    pub physics_fps: f32,
+    /// Agent updates per second
+    pub agent_updates: f32,
+    /// Milliseconds per frame
+    pub frame_ms: f32,
+    /// Milliseconds per frame spent on the network
+    pub net_ms: f32,
+    /// Milliseconds per frame spent on anything else
+    pub other_ms: f32,
+    /// Milliseconds per frame spent on physics
+    pub physics_ms: f32,
+    /// Milliseconds per frame spent on agents
+    pub agent_ms: f32,
+    /// Milliseconds per frame spent on images
+    pub image_ms: f32,
+    /// Milliseconds per frame spent on scripts
+    pub script_ms: f32,
+    /// Prims in the region
+    pub total_prims: u32,
+    /// Physical or scripted prims
+    pub active_prims: u32,
+    /// Avatars in the region
+    pub agents: u32,
+    /// Avatars seeing the region from a neighbour
+    pub child_agents: u32,
+    /// Running scripts
+    pub active_scripts: u32,
+    /// Packets received per second
+    pub in_pps: f32,
+    /// Packets sent per second
+    pub out_pps: f32,
+    /// Asset downloads in flight
+    pub pending_downloads: u32,
+    /// Asset uploads in flight
+    pub pending_uploads: u32,
+    /// Bytes of reliable packets not yet acknowledged
+    pub unacked_bytes: u32,
+    /// Milliseconds of each frame left unused
+    pub spare_ms: f32,
+    /// Milliseconds of each frame spent sleeping
+    pub sleep_ms: f32,
+}
+
+impl SimStats {
+    /// `(StatID, value)` pairs in the order they are sent
+    pub fn stats(&self) -> Vec<(u32, f32)> {
+        vec![
+            (stat_ids::TIME_DILATION, self.time_dilation),
+            (stat_ids::SIM_FPS, self.sim_fps),
+            (stat_ids::PHYSICS_FPS, self.physics_fps),
+            (stat_ids::AGENT_UPDATES, self.agent_updates),
+            (stat_ids::FRAME_MS, self.frame_ms),
+            (stat_ids::NET_MS, self.net_ms),
+            (stat_ids::OTHER_MS, self.other_ms),
+            (stat_ids::PHYSICS_MS, self.physics_ms),
+            (stat_ids::AGENT_MS, self.agent_ms),
+            (stat_ids::IMAGE_MS, self.image_ms),
+            (stat_ids::SCRIPT_MS, self.script_ms),
+            (stat_ids::TOTAL_PRIM, self.total_prims as f32),
+            (stat_ids::ACTIVE_PRIM, self.active_prims as f32),
+            (stat_ids::AGENTS, self.agents as f32),
+            (stat_ids::CHILD_AGENTS, self.child_agents as f32),
+            (stat_ids::ACTIVE_SCRIPTS, self.active_scripts as f32),
+            (stat_ids::IN_PPS, self.in_pps),
+            (stat_ids::OUT_PPS, self.out_pps),
+            (stat_ids::PENDING_DOWNLOADS, self.pending_downloads as f32),
+            (stat_ids::PENDING_UPLOADS, self.pending_uploads as f32),
+            (stat_ids::UNACKED_BYTES, self.unacked_bytes as f32),
+            (stat_ids::SIM_SPARE_TIME, self.spare_ms),
+            (stat_ids::SIM_SLEEP_TIME, self.sleep_ms),
+        ]
+    }
+
+    /// Encode the SimStats message payload, starting with the message ID
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        payload.push(packet_types::SIM_STATS as u8);
+
+        // Region block
+        payload.extend_from_slice(&self.region_x.to_le_bytes());
+        payload.extend_from_slice(&self.region_y.to_le_bytes());
+        payload.extend_from_slice(&self.region_flags.to_le_bytes());
+        payload.extend_from_slice(&self.object_capacity.to_le_bytes());
+
+        // Stat block (variable)
+        let stats = self.stats();
+        payload.push(stats.len() as u8);
+        for (id, value) in stats {
+            payload.extend_from_slice(&id.to_le_bytes());
+            payload.extend_from_slice(&value.to_le_bytes());
+        }
+
+        // PidStat block
+        payload.extend_from_slice(&(std::process::id() as i32).to_le_bytes());
+
+        // RegionInfo block (variable, unused)
+        payload.push(0);
+
+        payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_layout() {
+        let stats = SimStats {
+            region_x: 1000,
+            region_y: 1001,
+            time_dilation: 0.5,
+            agents: 3,
+            ..SimStats::default()
+        };
+        let payload = stats.encode();
+        let count = stats.stats().len();
+
+        assert_eq!(payload[0], packet_types::SIM_STATS as u8);
+        assert_eq!(u32::from_le_bytes(payload[1..5].try_into().unwrap()), 1000);
+        assert_eq!(payload[17] as usize, count);
+        assert_eq!(payload.len(), 1 + 16 + 1 + count * 8 + 4 + 1);
+
+        let stat_at = |i: usize| {
+            let offset = 18 + i * 8;
+            (
+                u32::from_le_bytes(payload[offset..offset + 4].try_into().unwrap()),
+                f32::from_le_bytes(payload[offset + 4..offset + 8].try_into().unwrap()),
+            )
+        };
+        assert_eq!(stat_at(0), (stat_ids::TIME_DILATION, 0.5));
+        assert_eq!(stat_at(13), (stat_ids::AGENTS, 3.0));
+    }
+}
@@ -0,0 +1,202 @@
+//! Snapshots and postcards
+//!
+//! The snapshot floater saves photos to inventory through the
+//! NewFileAgentInventory capability and sends them as postcards through
+//! SendPostcard. Both are two-step uploads: the viewer posts what it is
+//! about to upload and is answered `{state: "upload", uploader: <url>}`,
+//! then posts the image to the uploader URL: a JPEG-2000 texture for
+//! inventory, a JPEG for postcards. The uploader answers
+//! `{state: "complete"}` with the asset and item made.
+
+use crate::{llsd, ProtocolError, ProtocolResult};
+use serde_json::json;
+use uuid::Uuid;
+
+/// A photo about to be saved to the agent's inventory
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotUpload {
+    /// Folder to save into, nil for the default one
+    pub folder_id: Uuid,
+    /// Asset type name, such as "texture"
+    pub asset_type: String,
+    /// Inventory type name, such as "snapshot"
+    pub inventory_type: String,
+    /// Item name
+    pub name: String,
+    /// Item description
+    pub description: String,
+    /// Permissions given to everyone
+    pub everyone_mask: u32,
+    /// Permissions given to the next owner
+    pub next_owner_mask: u32,
+}
+
+impl SnapshotUpload {
+    /// Parse a NewFileAgentInventory request body
+    pub fn parse(body: &str) -> ProtocolResult<Self> {
+        let asset_type = text_of(body, "asset_type")
+            .ok_or_else(|| ProtocolError::Decoding("NewFileAgentInventory needs an asset_type".to_string()))?;
+        let mask = |key: &str| text_of(body, key).and_then(|text| text.trim().parse::<i64>().ok()).unwrap_or(0) as u32;
+        Ok(Self {
+            folder_id: text_of(body, "folder_id")
+                .and_then(|text| Uuid::parse_str(text.trim()).ok())
+                .unwrap_or_default(),
+            inventory_type: text_of(body, "inventory_type").unwrap_or_else(|| asset_type.clone()),
+            asset_type,
+            name: text_of(body, "name").unwrap_or_default(),
+            description: text_of(body, "description").unwrap_or_default(),
+            everyone_mask: mask("everyone_mask"),
+            next_owner_mask: mask("next_owner_mask"),
+        })
+    }
+
+    /// Whether the upload is an image, the only kind taken from snapshots
+    pub fn is_texture(&self) -> bool {
+        self.asset_type == "texture"
+    }
+
+    /// Whether the upload is an animation, or a BVH file to make one from
+    pub fn is_animation(&self) -> bool {
+        self.asset_type == "animation"
+    }
+}
+
+/// A postcard about to be sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct Postcard {
+    /// Where it was taken, in global metres
+    pub pos_global: [f64; 3],
+    /// Addressee
+    pub to: String,
+    /// Sender's address
+    pub from: String,
+    /// Sender's name
+    pub name: String,
+    /// Subject line
+    pub subject: String,
+    /// Message body
+    pub message: String,
+    /// Whether the sender allows it to be published
+    pub allow_publish: bool,
+    /// Whether it is mature content, if published
+    pub mature_publish: bool,
+}
+
+impl Postcard {
+    /// Parse a SendPostcard request body
+    pub fn parse(body: &str) -> ProtocolResult<Self> {
+        let mut pos_global = [0.0; 3];
+        if let Some(array) = value_of(body, "pos-global") {
+            let array = array.split("</array>").next().unwrap_or_default();
+            for (axis, real) in array.split("<real>").skip(1).take(3).enumerate() {
+                let text = real.split("</real>").next().unwrap_or_default();
+                pos_global[axis] = text.trim().parse().unwrap_or(0.0);
+            }
+        }
+        let flag = |key: &str| text_of(body, key).is_some_and(|text| matches!(text.trim(), "true" | "1"));
+        Ok(Self {
+            pos_global,
+            to: text_of(body, "to").unwrap_or_default(),
+            from: text_of(body, "from").unwrap_or_default(),
+            name: text_of(body, "name").unwrap_or_default(),
+            subject: text_of(body, "subject").unwrap_or_default(),
+            message: text_of(body, "msg").unwrap_or_default(),
+            allow_publish: flag("allow-publish"),
+            mature_publish: flag("mature-publish"),
+        })
+    }
+}
+
+/// Whether `data` starts like a JPEG-2000 codestream, as textures are
+pub fn is_j2c(data: &[u8]) -> bool {
+    data.starts_with(&[0xff, 0x4f, 0xff, 0x51])
+}
+
+/// Whether `data` starts like a JPEG, as postcards are
+pub fn is_jpeg(data: &[u8]) -> bool {
+    data.starts_with(&[0xff, 0xd8, 0xff])
+}
+
+/// The answer to an upload request: where to post the image
+pub fn encode_uploader(uploader: &str) -> String {
+    llsd::to_xml(&json!({ "state": "upload", "uploader": uploader }))
+}
+
+/// The answer to an image posted to the uploader: the asset stored and,
+/// for inventory uploads, the item made for it
+pub fn encode_upload_complete(asset_id: Uuid, item_id: Option<Uuid>) -> String {
+    let mut reply = json!({ "state": "complete", "new_asset": asset_id.to_string() });
+    if let Some(item_id) = item_id {
+        reply["new_inventory_item"] = item_id.to_string().into();
+    }
+    llsd::to_xml(&reply)
+}
+
+/// The answer to an upload that was refused
+pub fn encode_upload_error(message: &str) -> String {
+    llsd::to_xml(&json!({ "state": "error", "message": message }))
+}
+
+/// The XML after `<key>key</key>`
+fn value_of<'a>(body: &'a str, key: &str) -> Option<&'a str> {
+    let tag = format!("<key>{}</key>", key);
+    let start = body.find(&tag)? + tag.len();
+    Some(body[start..].trim_start())
+}
+
+/// The text of the scalar after `<key>key</key>`, empty for `<string />`
+fn text_of(body: &str, key: &str) -> Option<String> {
+    let value = value_of(body, key)?;
+    let open = value.find('>')?;
+    if value[..open].ends_with('/') {
+        return Some(String::new());
+    }
+    let close = value.find("</")?;
+    Some(llsd::unescape(&value[open + 1..close]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_snapshot_and_postcard() {
+        let folder = Uuid::new_v4();
+        let upload = SnapshotUpload::parse(&format!(
+            "<llsd><map><key>folder_id</key><uuid>{}</uuid><key>asset_type</key><string>texture</string>\
+             <key>inventory_type</key><string>snapshot</string><key>name</key><string>Sunset &amp; sea</string>\
+             <key>description</key><string /><key>everyone_mask</key><integer>0</integer>\
+             <key>next_owner_mask</key><integer>532480</integer></map></llsd>",
+            folder
+        ))
+        .unwrap();
+        assert_eq!(upload.folder_id, folder);
+        assert!(upload.is_texture());
+        assert_eq!(upload.inventory_type, "snapshot");
+        assert_eq!(upload.name, "Sunset & sea");
+        assert_eq!(upload.description, "");
+        assert_eq!(upload.next_owner_mask, 532480);
+        assert!(SnapshotUpload::parse("<llsd><map /></llsd>").is_err());
+
+        let postcard = Postcard::parse(
+            "<llsd><map><key>pos-global</key><array><real>256128.5</real><real>256064</real><real>22.25</real></array>\
+             <key>to</key><string>friend@example.com</string><key>from</key><string>me@example.com</string>\
+             <key>name</key><string>Ada</string><key>subject</key><string>Hello</string>\
+             <key>msg</key><string>Look at this</string><key>allow-publish</key><boolean>true</boolean>\
+             <key>mature-publish</key><boolean>false</boolean></map></llsd>",
+        )
+        .unwrap();
+        assert_eq!(postcard.pos_global, [256128.5, 256064.0, 22.25]);
+        assert_eq!(postcard.subject, "Hello");
+        assert_eq!(postcard.message, "Look at this");
+        assert!(postcard.allow_publish && !postcard.mature_publish);
+    }
+
+    #[test]
+    fn test_image_formats() {
+        assert!(is_j2c(&[0xff, 0x4f, 0xff, 0x51, 0x00]));
+        assert!(is_jpeg(&[0xff, 0xd8, 0xff, 0xe0]));
+        assert!(!is_jpeg(&[0xff, 0x4f, 0xff, 0x51]));
+        assert!(encode_upload_complete(Uuid::nil(), None).contains("<string>complete</string>"));
+    }
+}
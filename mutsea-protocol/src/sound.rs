@@ -0,0 +1,403 @@
+//! Sounds played in the region
+//!
+//! SoundTrigger plays a sound once at a point, sent by the region for
+//! llTriggerSound and by viewers for gesture sounds, which the region
+//! passes on to everyone in earshot. AttachedSound plays, loops or stops
+//! the sound of a prim, AttachedSoundGainChange changes its volume and
+//! PreloadSound asks viewers to fetch a sound before it is played.
+//!
+//! Sounds are Ogg Vorbis assets. The viewer encodes what it uploads at
+//! 44.1kHz, in mono or stereo; [`SoundInfo::decode`] checks an uploaded
+//! asset is such a stream before it is stored.
+
+use crate::codec::MessageDecoder;
+use crate::constants::packet_types;
+use crate::{ProtocolError, ProtocolResult};
+use mutsea_core::Vector3;
+use uuid::Uuid;
+
+/// AttachedSound flag: play the sound over and over
+pub const SOUND_FLAG_LOOP: u8 = 0x01;
+/// AttachedSound flag: other prims' sounds keep time with this one
+pub const SOUND_FLAG_SYNC_MASTER: u8 = 0x02;
+/// AttachedSound flag: keep time with the sync master
+pub const SOUND_FLAG_SYNC_SLAVE: u8 = 0x04;
+/// AttachedSound flag: wait for the sync master to loop
+pub const SOUND_FLAG_SYNC_PENDING: u8 = 0x08;
+/// AttachedSound flag: play after the prim's current sound ends
+pub const SOUND_FLAG_QUEUE: u8 = 0x10;
+/// AttachedSound flag: stop the prim's sound
+pub const SOUND_FLAG_STOP: u8 = 0x20;
+
+/// Sample rate the viewer encodes sounds at
+pub const SOUND_SAMPLE_RATE: u32 = 44_100;
+/// Most channels a sound may have
+pub const SOUND_MAX_CHANNELS: u8 = 2;
+
+/// Gain of a sound `distance` metres away: `gain` within
+/// `full_gain_distance`, fading to silence at `radius`. None beyond
+/// `radius`, where the sound isn't sent.
+pub fn distance_gain(gain: f32, distance: f32, full_gain_distance: f32, radius: f32) -> Option<f32> {
+    if distance > radius {
+        return None;
+    }
+    if distance <= full_gain_distance || radius <= full_gain_distance {
+        return Some(gain);
+    }
+    Some(gain * (1.0 - (distance - full_gain_distance) / (radius - full_gain_distance)))
+}
+
+/// A sound played once at a point
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoundTrigger {
+    /// Sound asset
+    pub sound_id: Uuid,
+    /// Owner of `object_id`
+    pub owner_id: Uuid,
+    /// The prim or avatar playing it
+    pub object_id: Uuid,
+    /// The root prim of `object_id`, or nil
+    pub parent_id: Uuid,
+    /// Region the sound plays in
+    pub region_handle: u64,
+    /// Region-local position
+    pub position: Vector3,
+    /// Volume, 0 to 1
+    pub gain: f32,
+}
+
+impl SoundTrigger {
+    /// Parse a SoundTrigger payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        Ok(Self {
+            sound_id: decoder.read_uuid()?,
+            owner_id: decoder.read_uuid()?,
+            object_id: decoder.read_uuid()?,
+            parent_id: decoder.read_uuid()?,
+            region_handle: decoder.read_u64()?,
+            position: decoder.read_vector3()?,
+            gain: decoder.read_f32()?,
+        })
+    }
+
+    /// SoundTrigger payload, starting with the message ID
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = vec![packet_types::SOUND_TRIGGER as u8];
+        payload.extend_from_slice(self.sound_id.as_bytes());
+        payload.extend_from_slice(self.owner_id.as_bytes());
+        payload.extend_from_slice(self.object_id.as_bytes());
+        payload.extend_from_slice(self.parent_id.as_bytes());
+        payload.extend_from_slice(&self.region_handle.to_le_bytes());
+        for axis in [self.position.x, self.position.y, self.position.z] {
+            payload.extend_from_slice(&axis.to_le_bytes());
+        }
+        payload.extend_from_slice(&self.gain.to_le_bytes());
+        payload
+    }
+}
+
+/// A sound played by a prim, moving with it
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttachedSound {
+    /// Sound asset, nil with `SOUND_FLAG_STOP`
+    pub sound_id: Uuid,
+    /// Prim playing it
+    pub object_id: Uuid,
+    /// Owner of the prim
+    pub owner_id: Uuid,
+    /// Volume, 0 to 1
+    pub gain: f32,
+    /// `SOUND_FLAG_*`
+    pub flags: u8,
+}
+
+impl AttachedSound {
+    /// Stop the sound `object_id` is playing
+    pub fn stop(object_id: Uuid, owner_id: Uuid) -> Self {
+        Self {
+            sound_id: Uuid::nil(),
+            object_id,
+            owner_id,
+            gain: 0.0,
+            flags: SOUND_FLAG_STOP,
+        }
+    }
+
+    /// AttachedSound payload, starting with the message ID
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = vec![packet_types::ATTACHED_SOUND as u8];
+        payload.extend_from_slice(self.sound_id.as_bytes());
+        payload.extend_from_slice(self.object_id.as_bytes());
+        payload.extend_from_slice(self.owner_id.as_bytes());
+        payload.extend_from_slice(&self.gain.to_le_bytes());
+        payload.push(self.flags);
+        payload
+    }
+}
+
+/// Change the volume of the sound `object_id` is playing
+pub fn encode_attached_sound_gain_change(object_id: Uuid, gain: f32) -> Vec<u8> {
+    let mut payload = vec![packet_types::ATTACHED_SOUND_GAIN_CHANGE as u8];
+    payload.extend_from_slice(object_id.as_bytes());
+    payload.extend_from_slice(&gain.to_le_bytes());
+    payload
+}
+
+/// Ask viewers to fetch `sound_id`, to be played by `object_id`
+pub fn encode_preload_sound(object_id: Uuid, owner_id: Uuid, sound_id: Uuid) -> Vec<u8> {
+    let mut payload = vec![packet_types::PRELOAD_SOUND as u8];
+    payload.push(1);
+    payload.extend_from_slice(object_id.as_bytes());
+    payload.extend_from_slice(owner_id.as_bytes());
+    payload.extend_from_slice(sound_id.as_bytes());
+    payload
+}
+
+/// Ogg page header flag: the page starts the stream
+const OGG_BEGINNING_OF_STREAM: u8 = 0x02;
+/// Ogg page header flag: the page ends the stream
+const OGG_END_OF_STREAM: u8 = 0x04;
+/// Length of an Ogg page header before its segment table
+const OGG_HEADER_LEN: usize = 27;
+/// Length of the Vorbis identification header
+const VORBIS_ID_HEADER_LEN: usize = 30;
+
+/// What a sound asset holds, as read from its Ogg Vorbis headers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoundInfo {
+    /// Number of audio channels
+    pub channels: u8,
+    /// Samples per second
+    pub sample_rate: u32,
+    /// Seconds
+    pub duration: f32,
+}
+
+impl SoundInfo {
+    /// Check `data` is a single Ogg Vorbis stream the viewer can play,
+    /// every page of it intact, and read its format and length
+    pub fn decode(data: &[u8]) -> ProtocolResult<Self> {
+        let invalid = |message: &str| ProtocolError::Decoding(format!("Invalid sound: {}", message));
+        if !data.starts_with(b"OggS") {
+            return Err(invalid("not an Ogg file"));
+        }
+
+        let mut offset = 0;
+        let mut pages = 0;
+        let mut serial = None;
+        let mut info = None;
+        let mut samples = 0;
+        let mut ended = false;
+        while offset < data.len() {
+            if ended {
+                return Err(invalid("data after the end of the stream"));
+            }
+            let header = data
+                .get(offset..offset + OGG_HEADER_LEN)
+                .ok_or_else(|| invalid("truncated page"))?;
+            if &header[..4] != b"OggS" || header[4] != 0 {
+                return Err(invalid("lost page sync"));
+            }
+            let flags = header[5];
+            let granule = i64::from_le_bytes(header[6..14].try_into().unwrap());
+            let page_serial = u32::from_le_bytes(header[14..18].try_into().unwrap());
+            let crc = u32::from_le_bytes(header[22..26].try_into().unwrap());
+            let segments = header[26] as usize;
+            let lacing = data
+                .get(offset + OGG_HEADER_LEN..offset + OGG_HEADER_LEN + segments)
+                .ok_or_else(|| invalid("truncated page"))?;
+            let body_len: usize = lacing.iter().map(|len| *len as usize).sum();
+            let page_len = OGG_HEADER_LEN + segments + body_len;
+            let page = data
+                .get(offset..offset + page_len)
+                .ok_or_else(|| invalid("truncated page"))?;
+            if ogg_crc(page) != crc {
+                return Err(invalid("page checksum mismatch"));
+            }
+            if *serial.get_or_insert(page_serial) != page_serial {
+                return Err(invalid("more than one stream"));
+            }
+            if (pages == 0) != (flags & OGG_BEGINNING_OF_STREAM != 0) {
+                return Err(invalid("stream doesn't begin on the first page"));
+            }
+
+            if pages == 0 {
+                // The identification header is the first packet, alone on
+                // the first page
+                let packet_len = lacing.iter().take_while(|len| **len == 255).count() * 255
+                    + lacing.iter().find(|len| **len < 255).map_or(0, |len| *len as usize);
+                let body = &page[OGG_HEADER_LEN + segments..];
+                info = Some(vorbis_id_header(&body[..packet_len.min(body.len())])?);
+            }
+            if granule >= 0 {
+                samples = granule as u64;
+            }
+            ended = flags & OGG_END_OF_STREAM != 0;
+            pages += 1;
+            offset += page_len;
+        }
+        if !ended {
+            return Err(invalid("stream doesn't end"));
+        }
+
+        let (channels, sample_rate) = info.ok_or_else(|| invalid("no Vorbis header"))?;
+        if channels == 0 || channels > SOUND_MAX_CHANNELS {
+            return Err(invalid(&format!("{} channels, not mono or stereo", channels)));
+        }
+        if sample_rate != SOUND_SAMPLE_RATE {
+            return Err(invalid(&format!(
+                "sampled at {}Hz, not {}Hz",
+                sample_rate, SOUND_SAMPLE_RATE
+            )));
+        }
+        Ok(Self {
+            channels,
+            sample_rate,
+            duration: samples as f32 / sample_rate as f32,
+        })
+    }
+}
+
+/// Channels and sample rate of the Vorbis identification header `packet`
+fn vorbis_id_header(packet: &[u8]) -> ProtocolResult<(u8, u32)> {
+    let invalid = |message: &str| ProtocolError::Decoding(format!("Invalid sound: {}", message));
+    if packet.len() < VORBIS_ID_HEADER_LEN || !packet.starts_with(b"\x01vorbis") {
+        return Err(invalid("not Vorbis audio"));
+    }
+    if u32::from_le_bytes(packet[7..11].try_into().unwrap()) != 0 {
+        return Err(invalid("unsupported Vorbis version"));
+    }
+    // The framing bit closes the header
+    if packet[29] & 1 == 0 {
+        return Err(invalid("malformed Vorbis header"));
+    }
+    Ok((packet[11], u32::from_le_bytes(packet[12..16].try_into().unwrap())))
+}
+
+/// The checksum of Ogg `page`, taken with its own checksum field zeroed
+fn ogg_crc(page: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for (i, byte) in page.iter().enumerate() {
+        let byte = if (22..26).contains(&i) { 0 } else { *byte };
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::Packet;
+
+    /// An Ogg page holding `packets`, each under 255 bytes
+    fn page(flags: u8, granule: i64, sequence: u32, packets: &[&[u8]]) -> Vec<u8> {
+        let mut page = b"OggS".to_vec();
+        page.push(0);
+        page.push(flags);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&7u32.to_le_bytes());
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(packets.len() as u8);
+        page.extend(packets.iter().map(|packet| packet.len() as u8));
+        for packet in packets {
+            page.extend_from_slice(packet);
+        }
+        let crc = ogg_crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        page
+    }
+
+    fn id_header(channels: u8, sample_rate: u32) -> Vec<u8> {
+        let mut header = b"\x01vorbis".to_vec();
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.push(channels);
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&[0; 12]);
+        header.push(0xB8);
+        header.push(1);
+        header
+    }
+
+    fn sound(channels: u8, sample_rate: u32, samples: i64) -> Vec<u8> {
+        let mut data = page(OGG_BEGINNING_OF_STREAM, 0, 0, &[&id_header(channels, sample_rate)]);
+        data.extend(page(0, 0, 1, &[b"\x03vorbis comments", b"\x05vorbis setup"]));
+        data.extend(page(0, samples / 2, 2, &[&[0x2A; 200]]));
+        data.extend(page(OGG_END_OF_STREAM, samples, 3, &[&[0x2A; 120]]));
+        data
+    }
+
+    #[test]
+    fn test_ogg_vorbis_sounds_are_checked() {
+        let info = SoundInfo::decode(&sound(1, 44_100, 3 * 44_100)).unwrap();
+        assert_eq!((info.channels, info.sample_rate), (1, 44_100));
+        assert!((info.duration - 3.0).abs() < 1e-6);
+        assert_eq!(SoundInfo::decode(&sound(2, 44_100, 44_100)).unwrap().channels, 2);
+
+        let invalid = |data: &[u8]| SoundInfo::decode(data).unwrap_err().to_string();
+        assert!(invalid(b"RIFF....WAVEfmt ").contains("not an Ogg file"));
+        assert!(invalid(&sound(6, 44_100, 44_100)).contains("6 channels"));
+        assert!(invalid(&sound(1, 22_050, 44_100)).contains("22050Hz"));
+
+        let mut corrupt = sound(1, 44_100, 44_100);
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xFF;
+        assert!(invalid(&corrupt).contains("checksum"));
+
+        let whole = sound(1, 44_100, 44_100);
+        assert!(invalid(&whole[..whole.len() - 10]).contains("truncated"));
+        let unended = &whole[..whole.len() - (OGG_HEADER_LEN + 1 + 120)];
+        assert!(invalid(unended).contains("doesn't end"));
+
+        let mut not_vorbis = page(
+            OGG_BEGINNING_OF_STREAM,
+            0,
+            0,
+            &[b"OpusHead\x01\x01\x38\x01\x44\xAC\x00\x00"],
+        );
+        not_vorbis.extend(page(OGG_END_OF_STREAM, 48_000, 1, &[&[0; 10]]));
+        assert!(invalid(&not_vorbis).contains("not Vorbis"));
+    }
+
+    #[test]
+    fn test_sound_packets_and_gain() {
+        let trigger = SoundTrigger {
+            sound_id: Uuid::new_v4(),
+            owner_id: Uuid::new_v4(),
+            object_id: Uuid::new_v4(),
+            parent_id: Uuid::nil(),
+            region_handle: (256_000u64 << 32) | 256_000,
+            position: Vector3::new(128.0, 64.0, 22.5),
+            gain: 0.75,
+        };
+        let payload = trigger.encode();
+        assert_eq!(payload.len(), 1 + 16 * 4 + 8 + 12 + 4);
+        // Received, the message ID is stripped off the front
+        let received = Packet::deserialize(&Packet::reliable(1, payload).serialize().unwrap()).unwrap();
+        assert_eq!(received.message_id, Some(packet_types::SOUND_TRIGGER));
+        assert_eq!(SoundTrigger::parse(&received.payload).unwrap(), trigger);
+        assert!(SoundTrigger::parse(&received.payload[..received.payload.len() - 1]).is_err());
+
+        let object_id = Uuid::new_v4();
+        let stop = AttachedSound::stop(object_id, Uuid::nil()).encode();
+        assert_eq!(stop.len(), 1 + 16 * 3 + 4 + 1);
+        assert_eq!(stop[stop.len() - 1], SOUND_FLAG_STOP);
+        assert_eq!(
+            encode_preload_sound(object_id, Uuid::nil(), trigger.sound_id).len(),
+            1 + 1 + 16 * 3
+        );
+
+        assert_eq!(distance_gain(0.8, 5.0, 10.0, 50.0), Some(0.8));
+        assert_eq!(distance_gain(0.8, 30.0, 10.0, 50.0), Some(0.4));
+        assert_eq!(distance_gain(0.8, 50.0, 10.0, 50.0), Some(0.0));
+        assert_eq!(distance_gain(0.8, 51.0, 10.0, 50.0), None);
+    }
+}
@@ -0,0 +1,186 @@
+//! Terraforming
+//!
+//! The viewer's land tools send ModifyLand while the mouse is held down,
+//! one message per brush application: the action, the brush size, how long
+//! it was applied and, for flattening, the height to flatten to. Each
+//! ParcelData block is the area it was applied to; a click gives a single
+//! point (west equal to east, south equal to north), a selection a
+//! rectangle. Newer viewers give the brush size in metres in
+//! ModifyBlockExtended. Edit > Undo in the land tools sends UndoLand.
+
+use crate::codec::MessageDecoder;
+use crate::ProtocolResult;
+use mutsea_core::UserId;
+
+/// Parcel flag: anyone may terraform the parcel
+pub const PARCEL_FLAG_ALLOW_TERRAFORM: u32 = 1 << 4;
+
+/// A land tool action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TerrainAction {
+    /// Level the terrain to a height
+    Flatten = 0,
+    /// Raise the terrain
+    Raise = 1,
+    /// Lower the terrain
+    Lower = 2,
+    /// Even out bumps
+    Smooth = 3,
+    /// Add bumps
+    Roughen = 4,
+    /// Move the terrain back toward how it was loaded
+    Revert = 5,
+}
+
+impl TerrainAction {
+    /// The action with the wire value `value`
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Flatten),
+            1 => Some(Self::Raise),
+            2 => Some(Self::Lower),
+            3 => Some(Self::Smooth),
+            4 => Some(Self::Roughen),
+            5 => Some(Self::Revert),
+            _ => None,
+        }
+    }
+}
+
+/// An area a brush was applied to, in region metres
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LandArea {
+    /// Local ID of the parcel the area is in
+    pub local_id: i32,
+    /// Western edge
+    pub west: f32,
+    /// Southern edge
+    pub south: f32,
+    /// Eastern edge
+    pub east: f32,
+    /// Northern edge
+    pub north: f32,
+}
+
+impl LandArea {
+    /// Whether the brush was applied at a point rather than over a
+    /// selection
+    pub fn is_point(&self) -> bool {
+        self.west == self.east && self.south == self.north
+    }
+}
+
+/// One application of a land tool
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModifyLand {
+    /// Agent editing the terrain
+    pub agent_id: UserId,
+    /// Land tool, see [`TerrainAction`]
+    pub action: u8,
+    /// Brush radius in metres
+    pub brush_size: f32,
+    /// How long the brush was applied
+    pub seconds: f32,
+    /// Height to flatten to
+    pub height: f32,
+    /// Areas the brush was applied to
+    pub areas: Vec<LandArea>,
+}
+
+impl ModifyLand {
+    /// Parse a ModifyLand payload
+    pub fn parse(payload: &[u8]) -> ProtocolResult<Self> {
+        let mut decoder = MessageDecoder::new(payload);
+        let agent_id = UserId::from_uuid(decoder.read_uuid()?);
+        decoder.skip(16)?; // SessionID
+        let action = decoder.read_u8()?;
+        let brush = decoder.read_u8()?;
+        let seconds = decoder.read_f32()?;
+        let height = decoder.read_f32()?;
+
+        let count = decoder.read_u8()?;
+        let areas = (0..count)
+            .map(|_| {
+                Ok(LandArea {
+                    local_id: decoder.read_i32()?,
+                    west: decoder.read_f32()?,
+                    south: decoder.read_f32()?,
+                    east: decoder.read_f32()?,
+                    north: decoder.read_f32()?,
+                })
+            })
+            .collect::<ProtocolResult<Vec<_>>>()?;
+
+        // Older viewers leave out ModifyBlockExtended and give the size as
+        // 0, 1 or 2 for small, medium and large
+        let extended = match decoder.has_remaining() {
+            true if decoder.read_u8()? > 0 => Some(decoder.read_f32()?),
+            _ => None,
+        };
+        let brush_size = extended
+            .filter(|size| *size > 0.0)
+            .unwrap_or_else(|| (1u32 << brush.min(2)) as f32);
+
+        Ok(Self {
+            agent_id,
+            action,
+            brush_size,
+            seconds,
+            height,
+            areas,
+        })
+    }
+}
+
+/// Parse the agent of an UndoLand payload
+pub fn parse_undo_land(payload: &[u8]) -> ProtocolResult<UserId> {
+    let mut decoder = MessageDecoder::new(payload);
+    Ok(UserId::from_uuid(decoder.read_uuid()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn modify_land(agent_id: UserId, brush: u8, extended: Option<f32>) -> Vec<u8> {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(agent_id.as_uuid().as_bytes());
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+        payload.push(TerrainAction::Raise as u8);
+        payload.push(brush);
+        payload.extend_from_slice(&0.25f32.to_le_bytes());
+        payload.extend_from_slice(&22.0f32.to_le_bytes());
+        payload.push(1);
+        payload.extend_from_slice(&1i32.to_le_bytes());
+        for edge in [100.0f32, 50.0, 100.0, 50.0] {
+            payload.extend_from_slice(&edge.to_le_bytes());
+        }
+        match extended {
+            Some(size) => {
+                payload.push(1);
+                payload.extend_from_slice(&size.to_le_bytes());
+            }
+            None => payload.push(0),
+        }
+        payload
+    }
+
+    #[test]
+    fn test_parse_modify_land() {
+        let agent_id = UserId::new();
+        let modify = ModifyLand::parse(&modify_land(agent_id, 1, None)).unwrap();
+        assert_eq!(modify.agent_id, agent_id);
+        assert_eq!(TerrainAction::from_u8(modify.action), Some(TerrainAction::Raise));
+        assert_eq!(modify.brush_size, 2.0);
+        assert_eq!(modify.seconds, 0.25);
+        assert_eq!(modify.height, 22.0);
+        assert_eq!(modify.areas.len(), 1);
+        assert!(modify.areas[0].is_point());
+
+        let modify = ModifyLand::parse(&modify_land(agent_id, 1, Some(6.5))).unwrap();
+        assert_eq!(modify.brush_size, 6.5);
+        assert!(ModifyLand::parse(&modify_land(agent_id, 1, None)[..50]).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! AI service access shared by world generation, quests and NPCs
+
+use async_trait::async_trait;
+use mutsea_core::{
+    AIProvider, ConversationReply, ConversationRequest, GenerationRequest, LayoutPlan, MutseaError, MutseaResult,
+    QuestPlan, StoryRequest,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Asks an AI service for plans: each request is posted as JSON to
+/// `endpoint`, which answers with the plan. Conversation replies are
+/// streamed back as plain text, with `X-Model-Version` and `X-Confidence`
+/// headers.
+pub struct HttpProvider {
+    client: reqwest::Client,
+    endpoint: String,
+}
+
+impl HttpProvider {
+    pub fn new(endpoint: &str, timeout: Duration) -> MutseaResult<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| MutseaError::Network(e.to_string()))?;
+        Ok(Self {
+            client,
+            endpoint: endpoint.to_string(),
+        })
+    }
+
+    async fn post<T: DeserializeOwned>(&self, request: &impl Serialize) -> MutseaResult<T> {
+        self.client
+            .post(&self.endpoint)
+            .json(request)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| MutseaError::Network(format!("AI service {}: {}", self.endpoint, e)))?
+            .json()
+            .await
+            .map_err(|e| MutseaError::Network(format!("Invalid plan from {}: {}", self.endpoint, e)))
+    }
+}
+
+#[async_trait]
+impl AIProvider for HttpProvider {
+    fn name(&self) -> &str {
+        "http"
+    }
+
+    async fn plan_layout(&self, request: &GenerationRequest) -> MutseaResult<LayoutPlan> {
+        self.post(request).await
+    }
+
+    async fn plan_quest(&self, request: &StoryRequest) -> MutseaResult<QuestPlan> {
+        self.post(request).await
+    }
+
+    async fn converse(
+        &self,
+        request: &ConversationRequest,
+        partial: mpsc::Sender<String>,
+    ) -> MutseaResult<ConversationReply> {
+        let failed = |e: reqwest::Error| MutseaError::Network(format!("AI service {}: {}", self.endpoint, e));
+        let mut response = self
+            .client
+            .post(&self.endpoint)
+            .json(request)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(failed)?;
+        let header = |name: &str| response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        let model_version = header("x-model-version").unwrap_or_else(|| "unknown".to_string());
+        let confidence = header("x-confidence").and_then(|value| value.parse().ok()).unwrap_or(0.5);
+
+        let mut text = String::new();
+        // Bytes of a character split between chunks
+        let mut pending = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(failed)? {
+            pending.extend_from_slice(&chunk);
+            let valid = match std::str::from_utf8(&pending) {
+                Ok(valid) => valid.len(),
+                Err(e) => e.valid_up_to(),
+            };
+            let piece = String::from_utf8_lossy(&pending[..valid]).into_owned();
+            pending.drain(..valid);
+            if !piece.is_empty() {
+                text.push_str(&piece);
+                // A listener that went away doesn't stop the reply
+                let _ = partial.send(piece).await;
+            }
+        }
+
+        Ok(ConversationReply {
+            text,
+            confidence,
+            model_version,
+        })
+    }
+}
+
+/// Hash of a decision's input, grouping decisions made in the same context
+pub fn context_hash(input: &serde_json::Value) -> String {
+    let mut hasher = DefaultHasher::new();
+    input.to_string().hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
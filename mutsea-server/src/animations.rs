@@ -0,0 +1,267 @@
+//! Animation uploads
+//!
+//! Animations are uploaded through NewFileAgentInventory like other assets:
+//! the request is kept under a new uploader ID, and the file posted to that
+//! uploader is converted and checked before it is stored with an inventory
+//! item. BVH motion files are converted to keyframe animations; keyframe
+//! animations are read to check them. Either is refused if it moves too
+//! many joints or lasts too long, and has priorities above the cap lowered
+//! to it. Uploads count against the agent's daily upload allowance.
+
+use crate::limits::{LimitError, Limits};
+use chrono::Utc;
+use mutsea_core::config::AnimationsConfig;
+use mutsea_core::UserId;
+use mutsea_database::schema::{Asset, InventoryItem};
+use mutsea_database::DatabaseManager;
+use mutsea_protocol::animation::{is_bvh, Animation};
+use mutsea_protocol::constants::{asset_types, inventory_types};
+use mutsea_protocol::snapshot::SnapshotUpload;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::info;
+use uuid::Uuid;
+
+/// Permissions an agent keeps on its own animations
+const OWNER_PERMISSIONS: u32 = 0x7fff_ffff;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AnimationError {
+    #[error("Only animations can be uploaded here, not {0}")]
+    NotAnimation(String),
+    #[error("Unknown or expired upload")]
+    UnknownUpload,
+    #[error("Animations can't be larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("{0}")]
+    Invalid(String),
+    #[error("Animations can move at most {0} joints")]
+    TooManyJoints(usize),
+    #[error("Animations can last at most {0} seconds")]
+    TooLong(f32),
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+    #[error("Animations can't be stored without a database")]
+    NoDatabase,
+    #[error("Failed to store animation: {0}")]
+    Database(String),
+}
+
+#[derive(Debug, Clone)]
+struct PendingUpload {
+    agent: UserId,
+    upload: SnapshotUpload,
+    expires: Instant,
+}
+
+/// An animation stored from an uploader
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredAnimation {
+    pub asset_id: Uuid,
+    pub item_id: Uuid,
+}
+
+/// Takes animation uploads. Clones share the uploads waiting for their
+/// file.
+#[derive(Clone)]
+pub struct Animations {
+    config: AnimationsConfig,
+    database: Option<Arc<DatabaseManager>>,
+    limits: Option<Limits>,
+    pending: Arc<Mutex<HashMap<Uuid, PendingUpload>>>,
+}
+
+impl Animations {
+    pub fn new(config: AnimationsConfig) -> Self {
+        Self {
+            config,
+            database: None,
+            limits: None,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Store uploaded animations in `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Count uploads against their uploaders' daily allowance in `limits`
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Wait for the file of an animation `agent` is uploading, returning
+    /// the ID of its uploader
+    pub fn begin(&self, agent: UserId, upload: SnapshotUpload) -> Result<Uuid, AnimationError> {
+        if !upload.is_animation() {
+            return Err(AnimationError::NotAnimation(upload.asset_type));
+        }
+        let now = Instant::now();
+        let uploader = Uuid::new_v4();
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, upload| upload.expires > now);
+        pending.insert(
+            uploader,
+            PendingUpload {
+                agent,
+                upload,
+                expires: now + Duration::from_secs(self.config.upload_timeout_secs),
+            },
+        );
+        Ok(uploader)
+    }
+
+    /// Convert, check and store the file `agent` posted to `uploader`
+    pub async fn complete(&self, agent: UserId, uploader: Uuid, data: Vec<u8>) -> Result<StoredAnimation, AnimationError> {
+        let pending = {
+            let mut pending = self.pending.lock().unwrap();
+            match pending.get(&uploader) {
+                Some(upload) if upload.agent == agent && upload.expires > Instant::now() => pending.remove(&uploader),
+                _ => None,
+            }
+        }
+        .ok_or(AnimationError::UnknownUpload)?;
+
+        if data.len() > self.config.max_upload_bytes {
+            return Err(AnimationError::TooLarge(self.config.max_upload_bytes));
+        }
+        let animation = self.prepare(&data)?;
+        let database = self.database.as_ref().ok_or(AnimationError::NoDatabase)?;
+        if let Some(limits) = &self.limits {
+            limits.reserve_upload(agent, data.len())?;
+        }
+
+        let upload = pending.upload;
+        let asset_id = Uuid::new_v4();
+        let item_id = Uuid::new_v4();
+        let mut asset = Asset::new(
+            asset_id.to_string(),
+            upload.name.clone(),
+            asset_types::ANIMATION as i32,
+            animation.encode(),
+        );
+        asset.creator_id = agent.to_string();
+        let item = InventoryItem {
+            inventory_id: item_id.to_string(),
+            asset_id: asset.id.clone(),
+            asset_type: asset_types::ANIMATION as i32,
+            parent_folder_id: upload.folder_id.to_string(),
+            avatar_id: agent.to_string(),
+            name: upload.name.clone(),
+            description: upload.description,
+            next_permissions: upload.next_owner_mask as i32,
+            current_permissions: OWNER_PERMISSIONS as i32,
+            inv_type: inventory_types::ANIMATION as i32,
+            creator_id: agent.to_string(),
+            base_permissions: OWNER_PERMISSIONS as i32,
+            everyone_permissions: upload.everyone_mask as i32,
+            creation_date: Utc::now().timestamp() as i32,
+        };
+        let saved = match database.insert_asset(&asset).await {
+            Ok(()) => database.insert_inventory_item(&item).await,
+            Err(e) => Err(e),
+        };
+        saved.map_err(|e| AnimationError::Database(e.to_string()))?;
+
+        info!(
+            "🕺 Agent {} uploaded animation \"{}\" as {}: {} joints, {:.1}s",
+            agent,
+            upload.name,
+            asset_id,
+            animation.joints.len(),
+            animation.duration
+        );
+        Ok(StoredAnimation { asset_id, item_id })
+    }
+
+    /// The animation in an uploaded file, converted from BVH if it is one,
+    /// checked and with its priorities capped
+    fn prepare(&self, data: &[u8]) -> Result<Animation, AnimationError> {
+        let max_priority = self.config.max_priority;
+        let mut animation = if is_bvh(data) {
+            let text = std::str::from_utf8(data)
+                .map_err(|_| AnimationError::Invalid("BVH files must be text".to_string()))?;
+            Animation::from_bvh(text, self.config.bvh_priority.min(max_priority))
+        } else {
+            Animation::parse(data)
+        }
+        .map_err(|e| AnimationError::Invalid(e.to_string()))?;
+
+        if animation.joints.len() > self.config.max_joints {
+            return Err(AnimationError::TooManyJoints(self.config.max_joints));
+        }
+        if animation.duration > self.config.max_duration_secs {
+            return Err(AnimationError::TooLong(self.config.max_duration_secs));
+        }
+        animation.priority = animation.priority.min(max_priority);
+        for joint in &mut animation.joints {
+            joint.priority = joint.priority.min(max_priority);
+        }
+        Ok(animation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BVH: &str = "HIERARCHY\nROOT hip\n{\nOFFSET 0 0 0\nCHANNELS 3 Zrotation Xrotation Yrotation\n}\n\
+                       MOTION\nFrames: 3\nFrame Time: 0.5\n0 0 0\n10 0 0\n20 0 0\n";
+
+    fn upload(asset_type: &str) -> SnapshotUpload {
+        SnapshotUpload {
+            folder_id: Uuid::new_v4(),
+            asset_type: asset_type.to_string(),
+            inventory_type: asset_type.to_string(),
+            name: "Wave".to_string(),
+            description: String::new(),
+            everyone_mask: 0,
+            next_owner_mask: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_uploads_belong_to_their_agent() {
+        let animations = Animations::new(AnimationsConfig::default());
+        let agent = UserId::new();
+        assert_eq!(
+            animations.begin(agent, upload("texture")),
+            Err(AnimationError::NotAnimation("texture".to_string()))
+        );
+        let uploader = animations.begin(agent, upload("animation")).unwrap();
+        assert_eq!(
+            animations.complete(UserId::new(), uploader, BVH.as_bytes().to_vec()).await,
+            Err(AnimationError::UnknownUpload)
+        );
+        // Converted and checked, but there is nowhere to keep it
+        assert_eq!(
+            animations.complete(agent, uploader, BVH.as_bytes().to_vec()).await,
+            Err(AnimationError::NoDatabase)
+        );
+    }
+
+    #[test]
+    fn test_checks() {
+        let animations = Animations::new(AnimationsConfig {
+            max_duration_secs: 2.0,
+            max_priority: 2,
+            ..AnimationsConfig::default()
+        });
+        let converted = animations.prepare(BVH.as_bytes()).unwrap();
+        assert_eq!(converted.joints[0].name, "mPelvis");
+        assert_eq!((converted.priority, converted.joints[0].priority), (2, 2));
+
+        let mut long = converted.clone();
+        long.duration = 3.0;
+        assert_eq!(animations.prepare(&long.encode()), Err(AnimationError::TooLong(2.0)));
+        let mut crowded = converted.clone();
+        crowded.joints = vec![converted.joints[0].clone(); 200];
+        assert_eq!(animations.prepare(&crowded.encode()), Err(AnimationError::TooManyJoints(133)));
+        assert!(matches!(animations.prepare(b"\x02\x00garbage"), Err(AnimationError::Invalid(_))));
+    }
+}
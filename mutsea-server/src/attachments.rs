@@ -0,0 +1,675 @@
+//! Attachments worn by avatars
+//!
+//! Agents wear prims from their inventory or from the region on a body or
+//! HUD attachment point, one prim per point; wearing another takes the
+//! first one off. A worn prim is phantom, its position and rotation are
+//! its offset on the point, and every agent is sent it parented to its
+//! wearer. What is worn is written to the avatarattachments table when
+//! there is a database, each prim kept as an object asset, so it is put
+//! back on at the next login; logging out only takes the prims out of the
+//! region.
+
+use crate::coalesced;
+use crate::limits::{LimitError, Limits};
+use chrono::Utc;
+use mutsea_core::config::AttachmentsConfig;
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{ObjectId, Quaternion, SceneObject, UserId, Vector3};
+use mutsea_database::schema::{Asset, AvatarAttachment};
+use mutsea_database::DatabaseManager;
+use mutsea_network::LLUDPServer;
+use mutsea_physics::navigation::PHANTOM;
+use mutsea_protocol::attachment::{is_hud, is_valid_point, ATTACH_DEFAULT};
+use mutsea_protocol::constants::asset_types;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Where prims are worn when no point is asked for: the chest
+const DEFAULT_POINT: u8 = 1;
+
+#[derive(Debug, Error)]
+pub enum AttachmentError {
+    #[error("Unknown object {0}")]
+    UnknownObject(ObjectId),
+    #[error("No object has local ID {0}")]
+    UnknownLocalId(u32),
+    #[error("Agent {0} doesn't own object {1}")]
+    NotOwner(UserId, ObjectId),
+    #[error("{0} is not an attachment point")]
+    InvalidPoint(u8),
+    #[error("Object {0} is already worn")]
+    AlreadyWorn(ObjectId),
+    #[error("Object {0} is not worn")]
+    NotWorn(ObjectId),
+    #[error("Item {0} is not worn")]
+    ItemNotWorn(Uuid),
+    #[error("No more than {0} attachments can be worn")]
+    TooMany(usize),
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+    #[error("Attachments unavailable: {0}")]
+    Database(String),
+}
+
+/// A prim worn by an avatar
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Attachment {
+    pub object: ObjectId,
+    /// The inventory item it was worn from; prims worn from the region are
+    /// their own item
+    pub item_id: Uuid,
+    pub point: u8,
+    /// Drawn on its wearer's screen rather than its body
+    pub hud: bool,
+}
+
+/// An avatar wearing something
+struct Wearer {
+    /// The parent worn prims are sent with
+    local_id: u32,
+    worn: BTreeMap<u8, Attachment>,
+}
+
+#[derive(Default)]
+struct State {
+    wearers: HashMap<UserId, Wearer>,
+}
+
+impl State {
+    fn wearer_of(&self, object: ObjectId) -> Option<(UserId, Attachment)> {
+        self.wearers.iter().find_map(|(agent, wearer)| {
+            wearer
+                .worn
+                .values()
+                .find(|attachment| attachment.object == object)
+                .map(|attachment| (*agent, *attachment))
+        })
+    }
+}
+
+/// The attachments worn in one region. Clones share them.
+#[derive(Clone)]
+pub struct Attachments {
+    config: AttachmentsConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: Option<LLUDPServer>,
+    database: Option<Arc<DatabaseManager>>,
+    limits: Option<Limits>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Attachments {
+    pub fn new(config: AttachmentsConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            config,
+            scene,
+            lludp_server: None,
+            database: None,
+            limits: None,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Show worn prims to the agents connected to `lludp_server` and take
+    /// their requests to wear and take off
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Keep what is worn in `database`, and wear inventory items from it
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Take how many attachments each avatar may wear from `limits`
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// What `agent` wears, by point
+    pub fn worn(&self, agent: UserId) -> Vec<Attachment> {
+        self.state
+            .lock()
+            .unwrap()
+            .wearers
+            .get(&agent)
+            .map(|wearer| wearer.worn.values().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Who wears `object`, and how
+    pub fn wearer_of(&self, object: ObjectId) -> Option<(UserId, Attachment)> {
+        self.state.lock().unwrap().wearer_of(object)
+    }
+
+    /// `agent` wears the prim `object` from the region at `point`, turned
+    /// by `rotation`
+    pub async fn attach(
+        &self,
+        agent: UserId,
+        object: ObjectId,
+        point: u8,
+        rotation: Quaternion,
+    ) -> Result<Attachment, AttachmentError> {
+        let point = self.check_room(agent, point)?;
+        if self.wearer_of(object).is_some() {
+            return Err(AttachmentError::AlreadyWorn(object));
+        }
+        {
+            let mut scene = self.scene.write().await;
+            let prim = scene
+                .objects
+                .get_mut(&object)
+                .ok_or(AttachmentError::UnknownObject(object))?;
+            if prim.owner_id != agent {
+                return Err(AttachmentError::NotOwner(agent, object));
+            }
+            prim.position = Vector3::ZERO;
+            prim.rotation = rotation;
+            prim.velocity = Vector3::ZERO;
+            prim.angular_velocity = Vector3::ZERO;
+            prim.flags |= PHANTOM;
+            prim.last_updated = Utc::now();
+        }
+        self.wear(agent, object, object.0, point, true).await
+    }
+
+    /// `agent` wears its inventory item `item_id` named `name` at `point`.
+    /// Items whose asset isn't a prim kept by this region are worn as a
+    /// plain box with their name.
+    pub async fn rez_from_inventory(
+        &self,
+        agent: UserId,
+        item_id: Uuid,
+        point: u8,
+        name: &str,
+    ) -> Result<Attachment, AttachmentError> {
+        let point = self.check_room(agent, point)?;
+        let prim = match self.load_item(agent, item_id).await? {
+            Some(prim) => prim,
+            None => {
+                debug!("Item {} of agent {} is worn as a plain prim", item_id, agent);
+                plain_prim(agent, name)
+            }
+        };
+        let object = self.rez(agent, prim).await;
+        self.wear(agent, object, item_id, point, true).await
+    }
+
+    /// `agent` takes off the prim `object`, which leaves the region
+    pub async fn detach(&self, agent: UserId, object: ObjectId) -> Result<Attachment, AttachmentError> {
+        let attachment = {
+            let mut state = self.state.lock().unwrap();
+            let wearer = state.wearers.get_mut(&agent).ok_or(AttachmentError::NotWorn(object))?;
+            let point = wearer
+                .worn
+                .values()
+                .find(|attachment| attachment.object == object)
+                .map(|attachment| attachment.point)
+                .ok_or(AttachmentError::NotWorn(object))?;
+            wearer.worn.remove(&point).unwrap()
+        };
+        self.remove_from_region(&[attachment.object]).await;
+        if let Some(database) = &self.database {
+            if let Err(e) = database
+                .remove_avatar_attachment(&agent.to_string(), attachment.point as i32)
+                .await
+            {
+                warn!("Failed to forget attachment {} of agent {}: {}", object, agent, e);
+            }
+        }
+        info!("Agent {} took off object {}", agent, object);
+        Ok(attachment)
+    }
+
+    /// `agent` takes off what it wore from the item `item_id`
+    pub async fn detach_item(&self, agent: UserId, item_id: Uuid) -> Result<Attachment, AttachmentError> {
+        let object = self
+            .worn(agent)
+            .into_iter()
+            .find(|attachment| attachment.item_id == item_id)
+            .map(|attachment| attachment.object)
+            .ok_or(AttachmentError::ItemNotWorn(item_id))?;
+        self.detach(agent, object).await
+    }
+
+    /// Put back on what `agent` wore when it last left. Returns how many
+    /// prims it now wears.
+    pub async fn restore(&self, agent: UserId) -> Result<usize, AttachmentError> {
+        let Some(database) = self.database.as_ref().filter(|_| self.config.restore_on_login) else {
+            return Ok(0);
+        };
+        let stored = database
+            .get_avatar_attachments(&agent.to_string())
+            .await
+            .map_err(|e| AttachmentError::Database(e.to_string()))?;
+
+        for attachment in stored.into_iter().take(self.config.max_per_avatar) {
+            let (Ok(point), Ok(item_id)) = (u8::try_from(attachment.attach_point), attachment.item_id.parse()) else {
+                warn!("Skipping malformed attachment of agent {}", agent);
+                continue;
+            };
+            let Some(prim) = self.load_asset(&attachment.asset_id).await? else {
+                warn!("Attachment {} of agent {} has lost its prim", item_id, agent);
+                continue;
+            };
+            let object = self.rez(agent, prim).await;
+            self.wear(agent, object, item_id, point, false).await?;
+        }
+        let count = self.worn(agent).len();
+        if count > 0 {
+            info!("Agent {} put {} attachments back on", agent, count);
+        }
+        Ok(count)
+    }
+
+    /// Take what `agent` wears out of the region, keeping it for its next
+    /// login
+    pub async fn take_off_all(&self, agent: UserId) -> usize {
+        let Some(wearer) = self.state.lock().unwrap().wearers.remove(&agent) else {
+            return 0;
+        };
+        let objects: Vec<ObjectId> = wearer.worn.values().map(|attachment| attachment.object).collect();
+        self.remove_from_region(&objects).await;
+        objects.len()
+    }
+
+    /// Send every agent the prims worn in the region, as when one arrives
+    pub async fn send_all(&self) {
+        let agents: Vec<UserId> = self.state.lock().unwrap().wearers.keys().copied().collect();
+        for agent in agents {
+            for attachment in self.worn(agent) {
+                self.send(agent, attachment).await;
+            }
+        }
+    }
+
+    /// Handle attachment requests, logins and logouts from the LLUDP server
+    pub fn spawn(&self) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let attachments = self.clone();
+        let mut events = lludp_server.subscribe_events();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => attachments.handle(event.event_data).await,
+                    Err(RecvError::Lagged(missed)) => warn!("Attachments missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle(&self, event: NetworkEventData) {
+        let (agent, result) = match event {
+            NetworkEventData::AttachmentRezRequested {
+                agent_id,
+                item_id,
+                attachment_point,
+                name,
+                ..
+            } => (
+                agent_id,
+                self.rez_from_inventory(agent_id, item_id, attachment_point, &name)
+                    .await
+                    .map(drop),
+            ),
+            NetworkEventData::ObjectAttachRequested {
+                agent_id,
+                local_id,
+                attachment_point,
+                rotation,
+                ..
+            } => (
+                agent_id,
+                self.attach_by_local_id(agent_id, local_id, attachment_point, rotation)
+                    .await,
+            ),
+            NetworkEventData::ObjectDetachRequested { agent_id, local_id, .. } => {
+                (agent_id, self.detach_by_local_id(agent_id, local_id).await)
+            }
+            NetworkEventData::AttachmentDetachRequested { agent_id, item_id, .. } => {
+                (agent_id, self.detach_item(agent_id, item_id).await.map(drop))
+            }
+            NetworkEventData::AgentLoggedIn { agent_id, .. } => {
+                let restored = self.restore(agent_id).await.map(drop);
+                // Show the newcomer what everyone else wears
+                self.send_all().await;
+                (agent_id, restored)
+            }
+            NetworkEventData::AgentLoggedOut { agent_id, .. } => {
+                self.take_off_all(agent_id).await;
+                return;
+            }
+            _ => return,
+        };
+        if let Err(e) = result {
+            self.alert(agent, &e.to_string()).await;
+        }
+    }
+
+    /// ObjectAttach: `agent` wears the prim with `local_id`
+    async fn attach_by_local_id(
+        &self,
+        agent: UserId,
+        local_id: u32,
+        point: u8,
+        rotation: Quaternion,
+    ) -> Result<(), AttachmentError> {
+        let object = self.by_local_id(local_id).await?;
+        self.attach(agent, object, point, rotation).await.map(drop)
+    }
+
+    /// ObjectDetach: `agent` takes off the prim with `local_id`
+    async fn detach_by_local_id(&self, agent: UserId, local_id: u32) -> Result<(), AttachmentError> {
+        let object = self.by_local_id(local_id).await?;
+        self.detach(agent, object).await.map(drop)
+    }
+
+    /// The point `agent` may wear something new at, `point` or the default
+    fn check_room(&self, agent: UserId, point: u8) -> Result<u8, AttachmentError> {
+        let point = if point == ATTACH_DEFAULT { DEFAULT_POINT } else { point };
+        if !is_valid_point(point) {
+            return Err(AttachmentError::InvalidPoint(point));
+        }
+        let state = self.state.lock().unwrap();
+        let worn = state.wearers.get(&agent).map_or(0, |wearer| {
+            // What is on the point is taken off first
+            wearer.worn.len() - wearer.worn.contains_key(&point) as usize
+        });
+        if let Some(limits) = &self.limits {
+            limits.check_attachments(agent, worn)?;
+        } else if worn >= self.config.max_per_avatar {
+            return Err(AttachmentError::TooMany(self.config.max_per_avatar));
+        }
+        Ok(point)
+    }
+
+    /// Put `object`, already in the region, on `agent` at `point`, taking
+    /// off what was there, and show it to everyone
+    async fn wear(
+        &self,
+        agent: UserId,
+        object: ObjectId,
+        item_id: Uuid,
+        point: u8,
+        persist: bool,
+    ) -> Result<Attachment, AttachmentError> {
+        let attachment = Attachment {
+            object,
+            item_id,
+            point,
+            hud: is_hud(point),
+        };
+        let replaced = {
+            let scene = self.scene.read().await;
+            let mut state = self.state.lock().unwrap();
+            if !state.wearers.contains_key(&agent) {
+                let local_id = next_local_id(&scene, &state);
+                state.wearers.insert(
+                    agent,
+                    Wearer {
+                        local_id,
+                        worn: BTreeMap::new(),
+                    },
+                );
+            }
+            state.wearers.get_mut(&agent).unwrap().worn.insert(point, attachment)
+        };
+        if let Some(replaced) = replaced {
+            self.remove_from_region(&[replaced.object]).await;
+        }
+
+        if persist {
+            self.save(agent, attachment).await;
+        }
+        self.send(agent, attachment).await;
+        info!("Agent {} wears object {} at point {}", agent, object, point);
+        Ok(attachment)
+    }
+
+    /// Add `prim` to the region as a new prim owned by `agent`
+    async fn rez(&self, agent: UserId, mut prim: SceneObject) -> ObjectId {
+        let mut scene = self.scene.write().await;
+        prim.id = ObjectId::new();
+        prim.local_id = next_local_id(&scene, &self.state.lock().unwrap());
+        prim.owner_id = agent;
+        prim.position = Vector3::ZERO;
+        prim.velocity = Vector3::ZERO;
+        prim.angular_velocity = Vector3::ZERO;
+        prim.flags |= PHANTOM;
+        prim.created = Utc::now();
+        prim.last_updated = prim.created;
+        let object = prim.id;
+        scene.add_object(prim);
+        object
+    }
+
+    /// Remove `objects` from the region and from viewers
+    async fn remove_from_region(&self, objects: &[ObjectId]) {
+        let local_ids: Vec<u32> = {
+            let mut scene = self.scene.write().await;
+            objects
+                .iter()
+                .filter_map(|object| scene.objects.remove(object))
+                .map(|prim| prim.local_id)
+                .collect()
+        };
+        if let Some(lludp_server) = &self.lludp_server {
+            if let Err(e) = lludp_server.send_kill_objects(&local_ids).await {
+                warn!("Failed to remove {} attachments from viewers: {}", local_ids.len(), e);
+            }
+        }
+    }
+
+    /// Keep `attachment` of `agent` for its next login, its prim as an
+    /// object asset
+    async fn save(&self, agent: UserId, attachment: Attachment) {
+        let Some(database) = &self.database else {
+            return;
+        };
+        let Some(prim) = self.scene.read().await.objects.get(&attachment.object).cloned() else {
+            return;
+        };
+        let data = coalesced::encode(std::slice::from_ref(&prim));
+        let asset = Asset::new(Uuid::new_v4().to_string(), prim.name, asset_types::OBJECT as i32, data);
+        let stored = AvatarAttachment {
+            user_id: agent.to_string(),
+            attach_point: attachment.point as i32,
+            item_id: attachment.item_id.to_string(),
+            asset_id: asset.id.clone(),
+        };
+        let saved = match database.insert_asset(&asset).await {
+            Ok(()) => database.set_avatar_attachment(&stored).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            warn!(
+                "Failed to keep attachment {} of agent {}: {}",
+                attachment.object, agent, e
+            );
+        }
+    }
+
+    /// The prim in the asset of `agent`'s inventory item `item_id`, if it
+    /// is one this region kept
+    async fn load_item(&self, agent: UserId, item_id: Uuid) -> Result<Option<SceneObject>, AttachmentError> {
+        let Some(database) = &self.database else {
+            return Ok(None);
+        };
+        let asset_id = database
+            .get_inventory_item_asset(&item_id.to_string(), &agent.to_string())
+            .await
+            .map_err(|e| AttachmentError::Database(e.to_string()))?;
+        match asset_id {
+            Some(asset_id) => self.load_asset(&asset_id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// The prim kept in the object asset `asset_id`, the first of a
+    /// coalesced item
+    async fn load_asset(&self, asset_id: &str) -> Result<Option<SceneObject>, AttachmentError> {
+        let Some(database) = &self.database else {
+            return Ok(None);
+        };
+        let asset = database
+            .get_asset(asset_id)
+            .await
+            .map_err(|e| AttachmentError::Database(e.to_string()))?;
+        Ok(asset
+            .filter(|asset| asset.asset_type == asset_types::OBJECT as i32)
+            .and_then(|asset| coalesced::decode(&asset.data))
+            .and_then(|objects| objects.into_iter().next()))
+    }
+
+    /// Show every agent `attachment` worn by `agent`
+    async fn send(&self, agent: UserId, attachment: Attachment) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let Some(parent_local_id) = self
+            .state
+            .lock()
+            .unwrap()
+            .wearers
+            .get(&agent)
+            .map(|wearer| wearer.local_id)
+        else {
+            return;
+        };
+        let Some(prim) = self.scene.read().await.objects.get(&attachment.object).cloned() else {
+            return;
+        };
+        if let Err(e) = lludp_server
+            .send_attachment_update(&prim, parent_local_id, attachment.point, attachment.item_id)
+            .await
+        {
+            warn!(
+                "Failed to send attachment {} of agent {}: {}",
+                attachment.object, agent, e
+            );
+        }
+    }
+
+    async fn alert(&self, agent: UserId, message: &str) {
+        if let Some(lludp_server) = &self.lludp_server {
+            if let Err(e) = lludp_server.send_agent_alert(agent, false, message).await {
+                warn!("Failed to alert agent {}: {}", agent, e);
+            }
+        }
+    }
+
+    async fn by_local_id(&self, local_id: u32) -> Result<ObjectId, AttachmentError> {
+        self.scene
+            .read()
+            .await
+            .objects
+            .values()
+            .find(|object| object.local_id == local_id)
+            .map(|object| object.id)
+            .ok_or(AttachmentError::UnknownLocalId(local_id))
+    }
+}
+
+/// A local ID no prim or wearer in the region has
+fn next_local_id(scene: &RegionScene, state: &State) -> u32 {
+    let prims = scene.objects.values().map(|object| object.local_id);
+    let wearers = state.wearers.values().map(|wearer| wearer.local_id);
+    prims.chain(wearers).max().unwrap_or(0) + 1
+}
+
+/// A half-metre box named `name`, for items whose prim isn't known
+fn plain_prim(owner_id: UserId, name: &str) -> SceneObject {
+    SceneObject {
+        scale: Vector3::new(0.5, 0.5, 0.5),
+        flags: PHANTOM,
+        ..SceneObject::new(0, name, owner_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::RegionInfo;
+    use mutsea_protocol::attachment::FIRST_HUD_POINT;
+
+    #[tokio::test]
+    async fn test_wear_replace_and_detach() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let (wearer, other) = (UserId::new(), UserId::new());
+        let mut hat = plain_prim(wearer, "Hat");
+        hat.local_id = 5;
+        hat.position = Vector3::new(128.0, 128.0, 25.0);
+        let hat_id = hat.id;
+        scene.write().await.add_object(hat);
+        let config = AttachmentsConfig {
+            max_per_avatar: 2,
+            ..AttachmentsConfig::default()
+        };
+        let attachments = Attachments::new(config, Arc::clone(&scene));
+
+        assert!(matches!(
+            attachments.attach(other, hat_id, 2, Quaternion::IDENTITY).await,
+            Err(AttachmentError::NotOwner(..))
+        ));
+        assert!(matches!(
+            attachments.attach(wearer, hat_id, 56, Quaternion::IDENTITY).await,
+            Err(AttachmentError::InvalidPoint(56))
+        ));
+        let worn = attachments
+            .attach(wearer, hat_id, ATTACH_DEFAULT, Quaternion::IDENTITY)
+            .await
+            .unwrap();
+        assert_eq!(worn.point, DEFAULT_POINT);
+        assert!(!worn.hud);
+        assert_eq!(scene.read().await.objects[&hat_id].position, Vector3::ZERO);
+        assert_eq!(attachments.wearer_of(hat_id), Some((wearer, worn)));
+
+        // Without a database the item is worn as a plain prim
+        let item = Uuid::new_v4();
+        let hud = attachments
+            .rez_from_inventory(wearer, item, FIRST_HUD_POINT, "Radar")
+            .await
+            .unwrap();
+        assert!(hud.hud);
+        assert_eq!(scene.read().await.objects[&hud.object].name, "Radar");
+        assert!(matches!(
+            attachments.rez_from_inventory(wearer, Uuid::new_v4(), 3, "Boots").await,
+            Err(AttachmentError::TooMany(2))
+        ));
+
+        // Wearing on an occupied point takes the first prim off
+        let shirt = attachments
+            .rez_from_inventory(wearer, Uuid::new_v4(), DEFAULT_POINT, "Shirt")
+            .await
+            .unwrap();
+        assert!(!scene.read().await.objects.contains_key(&hat_id));
+        assert_eq!(attachments.worn(wearer).len(), 2);
+
+        attachments.detach_item(wearer, item).await.unwrap();
+        assert!(!scene.read().await.objects.contains_key(&hud.object));
+        assert!(matches!(
+            attachments.detach(wearer, hud.object).await,
+            Err(AttachmentError::NotWorn(_))
+        ));
+
+        assert_eq!(attachments.take_off_all(wearer).await, 1);
+        assert!(!scene.read().await.objects.contains_key(&shirt.object));
+        assert!(attachments.worn(wearer).is_empty());
+    }
+}
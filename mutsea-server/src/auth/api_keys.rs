@@ -0,0 +1,103 @@
+//! API key middleware enforcing per-route scopes
+
+use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use mutsea_protocol::api_keys::{ApiKeyRecord, ApiKeyStore, ApiScope, API_KEY_PREFIX};
+use std::sync::Arc;
+use tracing::debug;
+
+/// Header carrying an API key; `Authorization: Bearer msk_...` also works
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Middleware state: the key store and the scope a route requires
+#[derive(Clone)]
+pub struct ScopeGuard {
+    store: Arc<ApiKeyStore>,
+    scope: ApiScope,
+}
+
+impl ScopeGuard {
+    /// Guard requiring `scope`
+    pub fn new(store: Arc<ApiKeyStore>, scope: ApiScope) -> Self {
+        Self { store, scope }
+    }
+}
+
+/// Reject requests whose API key is missing, revoked, or lacks the
+/// guarded scope. The verified [`ApiKeyRecord`] is inserted as a request
+/// extension.
+pub async fn require_scope(State(guard): State<ScopeGuard>, mut request: Request, next: Next) -> Response {
+    let Some(key) = api_key(&request) else {
+        return error(StatusCode::UNAUTHORIZED, "Missing API key");
+    };
+    let Some(record) = guard.store.verify(&key) else {
+        return error(StatusCode::UNAUTHORIZED, "Invalid or revoked API key");
+    };
+    if !record.has_scope(guard.scope) {
+        debug!("API key {} lacks scope {}", record.id, guard.scope);
+        return error(StatusCode::FORBIDDEN, &format!("API key lacks scope {}", guard.scope));
+    }
+
+    request.extensions_mut().insert::<ApiKeyRecord>(record);
+    next.run(request).await
+}
+
+fn api_key(request: &Request) -> Option<String> {
+    let headers = request.headers();
+    if let Some(key) = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok()) {
+        return Some(key.trim().to_string());
+    }
+
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| token.starts_with(API_KEY_PREFIX))
+        .map(str::to_string)
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use mutsea_core::UserId;
+    use tower::ServiceExt;
+
+    fn app(store: Arc<ApiKeyStore>) -> Router {
+        Router::new()
+            .route("/stats", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn_with_state(
+                ScopeGuard::new(store, ApiScope::ReadStats),
+                require_scope,
+            ))
+    }
+
+    async fn status(app: Router, key: Option<&str>) -> StatusCode {
+        let mut request = Request::builder().uri("/stats");
+        if let Some(key) = key {
+            request = request.header(API_KEY_HEADER, key);
+        }
+        app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_scopes_enforced_per_route() {
+        let store = Arc::new(ApiKeyStore::in_memory());
+        let (_, stats_key) = store.issue(UserId::new(), "stats", vec![ApiScope::ReadStats]).unwrap();
+        let (_, users_key) = store.issue(UserId::new(), "users", vec![ApiScope::WriteUsers]).unwrap();
+
+        assert_eq!(status(app(Arc::clone(&store)), None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(app(Arc::clone(&store)), Some("msk_bogus_key")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(app(Arc::clone(&store)), Some(&users_key)).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(app(store), Some(&stats_key)).await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,95 @@
+//! Bearer token middleware for the REST and WebSocket APIs
+
+use super::AuthService;
+use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use mutsea_core::UserId;
+use std::sync::Arc;
+use tracing::debug;
+
+/// The authenticated principal, inserted as a request extension
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthenticatedUser {
+    pub principal_id: UserId,
+    /// Provider that authenticated the user
+    pub provider: String,
+}
+
+/// Reject requests without a valid bearer token.
+///
+/// The token is read from the `Authorization: Bearer` header, or from the
+/// `access_token` query parameter since browsers cannot set headers on
+/// WebSocket upgrade requests.
+pub async fn require_auth(State(auth): State<Arc<AuthService>>, mut request: Request, next: Next) -> Response {
+    let Some(token) = bearer_token(&request) else {
+        return unauthorized("Missing bearer token");
+    };
+
+    match auth.verify_bearer(&token).await {
+        Ok(user) => {
+            request.extensions_mut().insert(user);
+            next.run(request).await
+        }
+        Err(e) => {
+            debug!("Rejected bearer token: {}", e);
+            unauthorized(&e.to_string())
+        }
+    }
+}
+
+fn bearer_token(request: &Request) -> Option<String> {
+    let header = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|t| t.trim().to_string());
+    if header.is_some() {
+        return header;
+    }
+
+    request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == "access_token")
+            .map(|(_, value)| value.to_string())
+    })
+}
+
+fn unauthorized(message: &str) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(serde_json::json!({ "error": message })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    #[test]
+    fn test_bearer_token_from_header_or_query() {
+        let request = Request::builder()
+            .uri("/api/me")
+            .header(AUTHORIZATION, "Bearer abc.def")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(bearer_token(&request).as_deref(), Some("abc.def"));
+
+        let request = Request::builder()
+            .uri("/ws?room=1&access_token=xyz")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(bearer_token(&request).as_deref(), Some("xyz"));
+
+        let request = Request::builder().uri("/api/me").body(Body::empty()).unwrap();
+        assert_eq!(bearer_token(&request), None);
+    }
+}
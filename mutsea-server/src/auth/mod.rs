@@ -0,0 +1,422 @@
+//! Authentication for the web and REST/WebSocket APIs
+//!
+//! Credentials are checked by pluggable [`AuthProvider`]s (local password,
+//! OIDC). The resulting external identity is mapped to a grid principal ID
+//! and exchanged for a Mutsea session token. Legacy viewers keep using the
+//! XML-RPC password login, which does not go through this module.
+
+mod api_keys;
+mod middleware;
+mod oidc;
+mod password;
+
+pub use api_keys::{require_scope, ScopeGuard};
+pub use middleware::{require_auth, AuthenticatedUser};
+pub use oidc::OidcProvider;
+pub use password::PasswordProvider;
+
+use async_trait::async_trait;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use mutsea_core::{config::MutseaConfig, UserId};
+use mutsea_protocol::login::OpenSimLoginService;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+use tracing::info;
+use uuid::Uuid;
+
+/// Issuer used for Mutsea-issued session tokens and local accounts
+pub const LOCAL_ISSUER: &str = "mutsea";
+
+/// Authentication errors
+#[derive(Error, Debug)]
+pub enum AuthError {
+    /// Credentials were rejected
+    #[error("Invalid credentials")]
+    InvalidCredentials,
+
+    /// Token failed validation
+    #[error("Invalid token: {0}")]
+    InvalidToken(String),
+
+    /// No provider accepts this kind of credential
+    #[error("Unsupported credentials")]
+    Unsupported,
+
+    /// The external identity is not linked to a grid account
+    #[error("No grid account is linked to this identity")]
+    NotLinked,
+
+    /// The identity provider could not be reached
+    #[error("Identity provider error: {0}")]
+    Provider(String),
+}
+
+/// Result type for authentication operations
+pub type AuthResult<T> = Result<T, AuthError>;
+
+/// Credentials presented to an auth provider
+#[derive(Debug, Clone)]
+pub enum Credentials {
+    /// First/last name and password
+    Password {
+        first_name: String,
+        last_name: String,
+        password: String,
+    },
+    /// A bearer token issued by an external identity provider
+    BearerToken(String),
+}
+
+/// An identity asserted by a provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalIdentity {
+    /// Issuer of the identity (`mutsea` for local accounts)
+    pub issuer: String,
+    /// Stable subject identifier within the issuer
+    pub subject: String,
+    pub email: Option<String>,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+    pub preferred_username: Option<String>,
+}
+
+impl ExternalIdentity {
+    /// Identity for a local grid account
+    pub fn local(user_id: UserId) -> Self {
+        Self {
+            issuer: LOCAL_ISSUER.to_string(),
+            subject: user_id.to_string(),
+            email: None,
+            given_name: None,
+            family_name: None,
+            preferred_username: None,
+        }
+    }
+}
+
+/// Verifies credentials and returns the asserted identity
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Provider name for logs
+    fn name(&self) -> &str;
+
+    /// Verify credentials; return [`AuthError::Unsupported`] for credential
+    /// kinds this provider does not handle
+    async fn authenticate(&self, credentials: &Credentials) -> AuthResult<ExternalIdentity>;
+}
+
+/// Maps external (issuer, subject) pairs to grid principal IDs
+pub struct IdentityMapper {
+    links: RwLock<HashMap<(String, String), UserId>>,
+    login_service: Arc<OpenSimLoginService>,
+    auto_provision: bool,
+}
+
+impl IdentityMapper {
+    /// Create a mapper; with `auto_provision` unknown subjects get a new account
+    pub fn new(login_service: Arc<OpenSimLoginService>, auto_provision: bool) -> Self {
+        Self {
+            links: RwLock::new(HashMap::new()),
+            login_service,
+            auto_provision,
+        }
+    }
+
+    /// Link an external subject to an existing grid account
+    pub fn link(&self, issuer: &str, subject: &str, principal_id: UserId) {
+        self.links
+            .write()
+            .unwrap()
+            .insert((issuer.to_string(), subject.to_string()), principal_id);
+    }
+
+    /// Look up the principal linked to an external subject
+    pub fn lookup(&self, issuer: &str, subject: &str) -> Option<UserId> {
+        self.links
+            .read()
+            .unwrap()
+            .get(&(issuer.to_string(), subject.to_string()))
+            .copied()
+    }
+
+    /// Resolve an identity to a principal ID, provisioning if allowed
+    pub fn resolve(&self, identity: &ExternalIdentity) -> AuthResult<UserId> {
+        if identity.issuer == LOCAL_ISSUER {
+            return Uuid::parse_str(&identity.subject)
+                .map(UserId::from_uuid)
+                .map_err(|e| AuthError::InvalidToken(e.to_string()));
+        }
+
+        if let Some(principal_id) = self.lookup(&identity.issuer, &identity.subject) {
+            return Ok(principal_id);
+        }
+        if !self.auto_provision {
+            return Err(AuthError::NotLinked);
+        }
+
+        let principal_id = self.provision(identity)?;
+        self.link(&identity.issuer, &identity.subject, principal_id);
+        Ok(principal_id)
+    }
+
+    /// Create a grid account for an external identity. The account gets a
+    /// random password, so it can only be used through the provider until
+    /// the user sets one.
+    fn provision(&self, identity: &ExternalIdentity) -> AuthResult<UserId> {
+        let (first, last) = avatar_name(identity);
+        let password: String = rand::thread_rng()
+            .sample_iter(&rand::distributions::Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+
+        let mut candidate = last.clone();
+        for suffix in 1..100 {
+            if !self.login_service.user_exists(&first, &candidate) {
+                let principal_id = self
+                    .login_service
+                    .create_user(&first, &candidate, &password, identity.email.clone(), false)
+                    .map_err(|e| AuthError::Provider(e.to_string()))?;
+                info!(
+                    "👤 Provisioned {} {} for {} subject {}",
+                    first, candidate, identity.issuer, identity.subject
+                );
+                return Ok(principal_id);
+            }
+            candidate = format!("{}{}", last, suffix);
+        }
+
+        Err(AuthError::Provider(format!("No free avatar name for {} {}", first, last)))
+    }
+}
+
+/// Derive an OpenSim-style first/last name from identity claims
+fn avatar_name(identity: &ExternalIdentity) -> (String, String) {
+    let clean = |value: &str| -> String {
+        value.chars().filter(|c| c.is_ascii_alphanumeric()).take(31).collect()
+    };
+
+    let first = identity
+        .given_name
+        .as_deref()
+        .map(clean)
+        .filter(|n| n.len() >= 2)
+        .or_else(|| identity.preferred_username.as_deref().map(clean).filter(|n| n.len() >= 2))
+        .unwrap_or_else(|| "Resident".to_string());
+    let last = identity
+        .family_name
+        .as_deref()
+        .map(clean)
+        .filter(|n| n.len() >= 2)
+        .unwrap_or_else(|| "Resident".to_string());
+
+    (first, last)
+}
+
+/// Claims in a Mutsea session token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SessionClaims {
+    iss: String,
+    sub: String,
+    iat: i64,
+    exp: i64,
+    /// Provider that authenticated the session
+    idp: String,
+}
+
+/// A session issued after a successful login
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthSession {
+    pub principal_id: String,
+    pub token: String,
+    pub expires_at: i64,
+    pub provider: String,
+}
+
+/// Authentication service shared by HTTP handlers and middleware
+pub struct AuthService {
+    providers: Vec<Arc<dyn AuthProvider>>,
+    mapper: IdentityMapper,
+    secret: Vec<u8>,
+    session_ttl: chrono::Duration,
+}
+
+impl AuthService {
+    /// Build the service from configuration: password login is always
+    /// available, OIDC when `security.oidc` is set
+    pub fn from_config(config: &MutseaConfig, login_service: Arc<OpenSimLoginService>) -> Self {
+        let mut providers: Vec<Arc<dyn AuthProvider>> =
+            vec![Arc::new(PasswordProvider::new(Arc::clone(&login_service)))];
+        let mut auto_provision = false;
+
+        if let Some(oidc) = &config.security.oidc {
+            info!("🔐 OIDC login enabled for issuer {}", oidc.issuer);
+            let retrier = mutsea_core::retry::Retrier::from_config("oidc", &config.retry);
+            providers.push(Arc::new(OidcProvider::new(oidc.clone()).with_retrier(retrier)));
+            auto_provision = oidc.auto_provision;
+        }
+
+        Self {
+            providers,
+            mapper: IdentityMapper::new(login_service, auto_provision),
+            secret: config.security.jwt_secret.as_bytes().to_vec(),
+            session_ttl: chrono::Duration::hours(config.security.session_timeout as i64),
+        }
+    }
+
+    /// Identity mapper, for linking accounts
+    pub fn mapper(&self) -> &IdentityMapper {
+        &self.mapper
+    }
+
+    /// Authenticate credentials and issue a session token
+    pub async fn login(&self, credentials: &Credentials) -> AuthResult<AuthSession> {
+        let (provider, identity) = self.authenticate(credentials).await?;
+        let principal_id = self.mapper.resolve(&identity)?;
+        self.issue(principal_id, &provider)
+    }
+
+    /// Validate a bearer token: a Mutsea session token, or a token from an
+    /// external provider
+    pub async fn verify_bearer(&self, token: &str) -> AuthResult<AuthenticatedUser> {
+        if let Ok(user) = self.verify_session_token(token) {
+            return Ok(user);
+        }
+
+        let (provider, identity) = self.authenticate(&Credentials::BearerToken(token.to_string())).await?;
+        let principal_id = self.mapper.resolve(&identity)?;
+        Ok(AuthenticatedUser { principal_id, provider })
+    }
+
+    async fn authenticate(&self, credentials: &Credentials) -> AuthResult<(String, ExternalIdentity)> {
+        let mut last_error = AuthError::Unsupported;
+        for provider in &self.providers {
+            match provider.authenticate(credentials).await {
+                Ok(identity) => return Ok((provider.name().to_string(), identity)),
+                Err(AuthError::Unsupported) => continue,
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    fn issue(&self, principal_id: UserId, provider: &str) -> AuthResult<AuthSession> {
+        let now = chrono::Utc::now();
+        let expires_at = (now + self.session_ttl).timestamp();
+        let claims = SessionClaims {
+            iss: LOCAL_ISSUER.to_string(),
+            sub: principal_id.to_string(),
+            iat: now.timestamp(),
+            exp: expires_at,
+            idp: provider.to_string(),
+        };
+
+        let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(&self.secret))
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))?;
+
+        Ok(AuthSession {
+            principal_id: principal_id.to_string(),
+            token,
+            expires_at,
+            provider: provider.to_string(),
+        })
+    }
+
+    fn verify_session_token(&self, token: &str) -> AuthResult<AuthenticatedUser> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[LOCAL_ISSUER]);
+
+        let claims = decode::<SessionClaims>(token, &DecodingKey::from_secret(&self.secret), &validation)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))?
+            .claims;
+        let principal_id = Uuid::parse_str(&claims.sub)
+            .map(UserId::from_uuid)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))?;
+
+        Ok(AuthenticatedUser {
+            principal_id,
+            provider: claims.idp,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StaticProvider(ExternalIdentity);
+
+    #[async_trait]
+    impl AuthProvider for StaticProvider {
+        fn name(&self) -> &str {
+            "static"
+        }
+
+        async fn authenticate(&self, credentials: &Credentials) -> AuthResult<ExternalIdentity> {
+            match credentials {
+                Credentials::BearerToken(t) if t == "external" => Ok(self.0.clone()),
+                Credentials::BearerToken(_) => Err(AuthError::InvalidCredentials),
+                _ => Err(AuthError::Unsupported),
+            }
+        }
+    }
+
+    fn external_identity() -> ExternalIdentity {
+        ExternalIdentity {
+            issuer: "https://idp.example.com".to_string(),
+            subject: "abc-123".to_string(),
+            email: Some("ada@example.com".to_string()),
+            given_name: Some("Ada".to_string()),
+            family_name: Some("Lovelace".to_string()),
+            preferred_username: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_password_login_issues_session_token() {
+        let login_service = Arc::new(OpenSimLoginService::new());
+        let user_id = login_service.create_user("Test", "User", "password", None, false).unwrap();
+        let auth = AuthService::from_config(&MutseaConfig::default(), login_service);
+
+        let session = auth
+            .login(&Credentials::Password {
+                first_name: "Test".to_string(),
+                last_name: "User".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(session.principal_id, user_id.to_string());
+
+        let user = auth.verify_bearer(&session.token).await.unwrap();
+        assert_eq!(user.principal_id, user_id);
+        assert_eq!(user.provider, "password");
+    }
+
+    #[tokio::test]
+    async fn test_external_subject_is_provisioned_once() {
+        let login_service = Arc::new(OpenSimLoginService::new());
+        let mut auth = AuthService::from_config(&MutseaConfig::default(), Arc::clone(&login_service));
+        auth.providers.push(Arc::new(StaticProvider(external_identity())));
+        auth.mapper = IdentityMapper::new(Arc::clone(&login_service), true);
+
+        let first = auth.verify_bearer("external").await.unwrap();
+        let second = auth.verify_bearer("external").await.unwrap();
+        assert_eq!(first.principal_id, second.principal_id);
+        assert!(login_service.user_exists("Ada", "Lovelace"));
+        assert!(matches!(auth.verify_bearer("forged").await, Err(AuthError::InvalidCredentials)));
+    }
+
+    #[test]
+    fn test_unlinked_subject_rejected_without_auto_provision() {
+        let mapper = IdentityMapper::new(Arc::new(OpenSimLoginService::new()), false);
+        assert!(matches!(mapper.resolve(&external_identity()), Err(AuthError::NotLinked)));
+
+        let principal_id = UserId::new();
+        mapper.link("https://idp.example.com", "abc-123", principal_id);
+        assert_eq!(mapper.resolve(&external_identity()).unwrap(), principal_id);
+    }
+}
@@ -0,0 +1,246 @@
+//! OpenID Connect ID token verification
+//!
+//! Tokens are verified against the provider's JWKS, which is located via
+//! discovery (or `jwks_uri`) and cached. The cache is refreshed when it is
+//! older than `jwks_refresh_secs` or when a token names an unknown key.
+
+use super::{AuthError, AuthProvider, AuthResult, Credentials, ExternalIdentity};
+use async_trait::async_trait;
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use mutsea_core::config::OidcConfig;
+use mutsea_core::retry::Retrier;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{debug, info};
+
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    sub: String,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    given_name: Option<String>,
+    #[serde(default)]
+    family_name: Option<String>,
+    #[serde(default)]
+    preferred_username: Option<String>,
+}
+
+struct CachedKeys {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+/// Verifies ID tokens issued by an OpenID Connect provider
+pub struct OidcProvider {
+    config: OidcConfig,
+    client: reqwest::Client,
+    retrier: Retrier,
+    keys: RwLock<Option<CachedKeys>>,
+}
+
+/// Timeouts, refused connections, 5xx and 429 responses may succeed later
+fn is_transient(error: &reqwest::Error) -> bool {
+    error.is_timeout()
+        || error.is_connect()
+        || error
+            .status()
+            .map_or(false, |s| s.is_server_error() || s == reqwest::StatusCode::TOO_MANY_REQUESTS)
+}
+
+impl OidcProvider {
+    /// Create a provider; keys are fetched on first use
+    pub fn new(config: OidcConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            retrier: Retrier::disabled("oidc"),
+            keys: RwLock::new(None),
+        }
+    }
+
+    /// Retry transient failures fetching discovery documents and keys
+    pub fn with_retrier(mut self, retrier: Retrier) -> Self {
+        self.retrier = retrier;
+        self
+    }
+
+    async fn fetch_json<T: DeserializeOwned>(&self, url: &str) -> AuthResult<T> {
+        self.retrier
+            .run_if(
+                || async {
+                    self.client
+                        .get(url)
+                        .send()
+                        .await
+                        .and_then(|r| r.error_for_status())?
+                        .json::<T>()
+                        .await
+                },
+                is_transient,
+            )
+            .await
+            .map_err(|e| AuthError::Provider(e.to_string()))
+    }
+
+    /// Create a provider with a fixed key set, skipping discovery
+    #[cfg(test)]
+    fn with_keys(config: OidcConfig, keys: JwkSet) -> Self {
+        let provider = Self::new(config);
+        *provider.keys.try_write().unwrap() = Some(CachedKeys {
+            keys,
+            fetched_at: Instant::now(),
+        });
+        provider
+    }
+
+    async fn jwks_uri(&self) -> AuthResult<String> {
+        if let Some(uri) = &self.config.jwks_uri {
+            return Ok(uri.clone());
+        }
+
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer.trim_end_matches('/')
+        );
+        let discovery: Discovery = self.fetch_json(&url).await?;
+
+        Ok(discovery.jwks_uri)
+    }
+
+    async fn refresh_keys(&self) -> AuthResult<()> {
+        let uri = self.jwks_uri().await?;
+        let keys: JwkSet = self.fetch_json(&uri).await?;
+
+        info!("🔑 Loaded {} signing keys from {}", keys.keys.len(), uri);
+        *self.keys.write().await = Some(CachedKeys {
+            keys,
+            fetched_at: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// Find the decoding key for `kid`, refreshing the cache if it is stale
+    /// or does not contain the key
+    async fn decoding_key(&self, kid: Option<&str>) -> AuthResult<DecodingKey> {
+        let max_age = Duration::from_secs(self.config.jwks_refresh_secs);
+
+        for attempt in 0..2 {
+            {
+                let cached = self.keys.read().await;
+                if let Some(cached) = cached.as_ref().filter(|c| attempt > 0 || c.fetched_at.elapsed() < max_age) {
+                    let jwk = match kid {
+                        Some(kid) => cached.keys.find(kid),
+                        None => cached.keys.keys.first(),
+                    };
+                    if let Some(jwk) = jwk {
+                        return DecodingKey::from_jwk(jwk).map_err(|e| AuthError::InvalidToken(e.to_string()));
+                    }
+                    if attempt > 0 {
+                        break;
+                    }
+                }
+            }
+            debug!("Refreshing JWKS for {}", self.config.issuer);
+            self.refresh_keys().await?;
+        }
+
+        Err(AuthError::InvalidToken(format!("Unknown signing key {:?}", kid)))
+    }
+}
+
+#[async_trait]
+impl AuthProvider for OidcProvider {
+    fn name(&self) -> &str {
+        "oidc"
+    }
+
+    async fn authenticate(&self, credentials: &Credentials) -> AuthResult<ExternalIdentity> {
+        let Credentials::BearerToken(token) = credentials else {
+            return Err(AuthError::Unsupported);
+        };
+
+        let header = decode_header(token).map_err(|e| AuthError::InvalidToken(e.to_string()))?;
+        if !matches!(header.alg, Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 | Algorithm::ES256 | Algorithm::ES384) {
+            return Err(AuthError::Unsupported);
+        }
+
+        let key = self.decoding_key(header.kid.as_deref()).await?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_issuer(&[&self.config.issuer]);
+
+        let claims = decode::<IdTokenClaims>(token, &key, &validation)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))?
+            .claims;
+
+        Ok(ExternalIdentity {
+            issuer: claims.iss,
+            subject: claims.sub,
+            email: claims.email,
+            given_name: claims.given_name,
+            family_name: claims.family_name,
+            preferred_username: claims.preferred_username,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> OidcConfig {
+        OidcConfig {
+            issuer: "https://idp.example.com".to_string(),
+            client_id: "mutsea".to_string(),
+            jwks_uri: None,
+            auto_provision: false,
+            jwks_refresh_secs: 3600,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_bearer_credentials_are_unsupported() {
+        let provider = OidcProvider::with_keys(config(), JwkSet { keys: Vec::new() });
+        let credentials = Credentials::Password {
+            first_name: "Test".to_string(),
+            last_name: "User".to_string(),
+            password: "password".to_string(),
+        };
+        assert!(matches!(provider.authenticate(&credentials).await, Err(AuthError::Unsupported)));
+    }
+
+    #[tokio::test]
+    async fn test_hmac_tokens_are_left_to_session_validation() {
+        // HS256 tokens are Mutsea session tokens; the OIDC provider must not
+        // try to verify them against the provider's public keys
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &serde_json::json!({ "sub": "x", "exp": 0 }),
+            &jsonwebtoken::EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        let provider = OidcProvider::with_keys(config(), JwkSet { keys: Vec::new() });
+        assert!(matches!(
+            provider.authenticate(&Credentials::BearerToken(token)).await,
+            Err(AuthError::Unsupported)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_token_rejected() {
+        let provider = OidcProvider::with_keys(config(), JwkSet { keys: Vec::new() });
+        assert!(matches!(
+            provider.authenticate(&Credentials::BearerToken("not.a.jwt".to_string())).await,
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+}
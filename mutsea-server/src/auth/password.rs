@@ -0,0 +1,36 @@
+//! Local password authentication against grid accounts
+
+use super::{AuthError, AuthProvider, AuthResult, Credentials, ExternalIdentity};
+use async_trait::async_trait;
+use mutsea_protocol::login::OpenSimLoginService;
+use std::sync::Arc;
+
+/// Authenticates first/last name and password against the login service
+pub struct PasswordProvider {
+    login_service: Arc<OpenSimLoginService>,
+}
+
+impl PasswordProvider {
+    /// Create a password provider
+    pub fn new(login_service: Arc<OpenSimLoginService>) -> Self {
+        Self { login_service }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for PasswordProvider {
+    fn name(&self) -> &str {
+        "password"
+    }
+
+    async fn authenticate(&self, credentials: &Credentials) -> AuthResult<ExternalIdentity> {
+        let Credentials::Password { first_name, last_name, password } = credentials else {
+            return Err(AuthError::Unsupported);
+        };
+
+        self.login_service
+            .verify_password(first_name, last_name, password)
+            .map(ExternalIdentity::local)
+            .ok_or(AuthError::InvalidCredentials)
+    }
+}
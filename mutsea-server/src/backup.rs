@@ -0,0 +1,600 @@
+//! Scheduled region backups as OAR snapshots
+//!
+//! Every `backup.interval_minutes` each registered region is written to
+//! `{backup.directory}/{region}/{region}_{timestamp}.oar`, a gzipped tar in
+//! the OpenSim archive layout (`archive.xml`, `terrains/`, `objects/`,
+//! `landdata/`). Old snapshots are pruned by count and age. A snapshot
+//! staged by `mutsea region rollback` is applied when the region is
+//! registered at startup.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use mutsea_core::{
+    config::BackupConfig,
+    scene::{Parcel, RegionScene, Terrain},
+    MutseaError, MutseaResult, ObjectId, ObjectShape, Quaternion, RegionInfo, SceneObject, UserId, Vector3,
+};
+use mutsea_protocol::llsd::xml_escape;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// OAR format version written by Mutsea
+const OAR_MAJOR_VERSION: u32 = 0;
+const OAR_MINOR_VERSION: u32 = 8;
+
+/// A snapshot file on disk
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    pub taken_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Periodically snapshots registered regions to OAR files
+pub struct BackupScheduler {
+    config: BackupConfig,
+    regions: Arc<RwLock<Vec<Arc<RwLock<RegionScene>>>>>,
+    running: Arc<AtomicBool>,
+}
+
+impl BackupScheduler {
+    /// Create a scheduler; nothing runs until `start`
+    pub fn new(config: BackupConfig) -> Self {
+        Self {
+            config,
+            regions: Arc::new(RwLock::new(Vec::new())),
+            running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Register a region for backup, applying a staged rollback if present
+    pub async fn register_region(&self, scene: Arc<RwLock<RegionScene>>) -> MutseaResult<()> {
+        {
+            let mut scene = scene.write().await;
+            let pending = self.config.pending_rollback_path(&scene.info.region_name);
+            if pending.exists() {
+                info!("⏪ Applying staged rollback for {} from {:?}", scene.info.region_name, pending);
+                let restored = read_oar(&pending, &scene.info)?;
+                scene.restore_from(restored);
+                std::fs::remove_file(&pending)?;
+            }
+        }
+
+        self.regions.write().await.push(scene);
+        Ok(())
+    }
+
+    /// Start the backup schedule
+    pub async fn start(&self) -> MutseaResult<()> {
+        if !self.config.enabled {
+            info!("Region backups disabled");
+            return Ok(());
+        }
+        self.running.store(true, Ordering::SeqCst);
+
+        let config = self.config.clone();
+        let regions = Arc::clone(&self.regions);
+        let running = Arc::clone(&self.running);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(config.interval_minutes * 60));
+            // The first tick fires immediately; skip it so startup is not slowed
+            interval.tick().await;
+
+            while running.load(Ordering::SeqCst) {
+                interval.tick().await;
+                for scene in regions.read().await.iter() {
+                    if let Err(e) = snapshot_region(&config, scene).await {
+                        error!("Region backup failed: {}", e);
+                    }
+                }
+            }
+        });
+
+        info!(
+            "💾 Region backups every {} minutes to {} (keeping {})",
+            self.config.interval_minutes, self.config.directory, self.config.keep_last
+        );
+        Ok(())
+    }
+
+    /// Stop the backup schedule
+    pub async fn stop(&self) -> MutseaResult<()> {
+        self.running.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Snapshot every registered region now
+    pub async fn snapshot_all(&self) -> MutseaResult<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for scene in self.regions.read().await.iter() {
+            paths.push(snapshot_region(&self.config, scene).await?);
+        }
+        Ok(paths)
+    }
+}
+
+/// Write one snapshot of `scene` and apply the retention policy
+async fn snapshot_region(config: &BackupConfig, scene: &Arc<RwLock<RegionScene>>) -> MutseaResult<PathBuf> {
+    // Clone under the lock so the simulation is only blocked for the copy
+    let scene = scene.read().await.clone();
+    let scene_name = scene.info.region_name.clone();
+    let dir = config.region_dir(&scene_name);
+    let now = chrono::Utc::now();
+    let path = dir.join(format!(
+        "{}_{}.oar",
+        dir.file_name().and_then(|n| n.to_str()).unwrap_or("region"),
+        now.format("%Y%m%d-%H%M%S")
+    ));
+
+    let write_path = path.clone();
+    tokio::task::spawn_blocking(move || {
+        std::fs::create_dir_all(write_path.parent().unwrap_or(Path::new(".")))?;
+        write_oar(&scene, &write_path)
+    })
+    .await
+    .map_err(|e| MutseaError::Generic(e.to_string()))??;
+
+    info!("💾 Saved region snapshot {:?}", path);
+    prune_snapshots(config, &scene_name, now)?;
+    Ok(path)
+}
+
+/// Snapshots of a region, newest first
+pub fn list_snapshots(config: &BackupConfig, region_name: &str) -> MutseaResult<Vec<Snapshot>> {
+    Ok(config
+        .snapshots(region_name)?
+        .into_iter()
+        .filter_map(|path| {
+            let stem = path.file_stem()?.to_str()?;
+            let stamp = stem.rsplit('_').next()?;
+            let taken_at = chrono::NaiveDateTime::parse_from_str(stamp, "%Y%m%d-%H%M%S").ok()?.and_utc();
+            Some(Snapshot { path, taken_at })
+        })
+        .collect())
+}
+
+/// Delete snapshots beyond `keep_last` or older than `max_age_days`
+fn prune_snapshots(config: &BackupConfig, region_name: &str, now: chrono::DateTime<chrono::Utc>) -> MutseaResult<()> {
+    let max_age = chrono::Duration::days(config.max_age_days as i64);
+    for (index, snapshot) in list_snapshots(config, region_name)?.into_iter().enumerate() {
+        let too_many = index >= config.keep_last;
+        let too_old = config.max_age_days > 0 && now - snapshot.taken_at > max_age;
+        // Always keep the newest snapshot, whatever its age
+        if index > 0 && (too_many || too_old) {
+            if let Err(e) = std::fs::remove_file(&snapshot.path) {
+                warn!("Failed to prune snapshot {:?}: {}", snapshot.path, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write `scene` as an OAR archive
+pub fn write_oar(scene: &RegionScene, path: &Path) -> MutseaResult<()> {
+    let file = std::fs::File::create(path)?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    append(&mut archive, "archive.xml", archive_xml(&scene.info).as_bytes())?;
+
+    append(&mut archive, &format!("terrains/{}.r32", scene.info.region_name), &scene.terrain.to_r32())?;
+
+    for object in scene.objects.values() {
+        let name = format!(
+            "objects/{}_{:.0}-{:.0}-{:.0}__{}.xml",
+            object.name.replace('/', "_"),
+            object.position.x,
+            object.position.y,
+            object.position.z,
+            object.id.as_uuid()
+        );
+        append(&mut archive, &name, object_xml(object).as_bytes())?;
+    }
+
+    for parcel in &scene.parcels {
+        append(&mut archive, &format!("landdata/{}.xml", parcel.id), parcel_xml(parcel).as_bytes())?;
+    }
+
+    archive.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Read an OAR archive into a scene for `info`
+pub fn read_oar(path: &Path, info: &RegionInfo) -> MutseaResult<RegionScene> {
+    let file = std::fs::File::open(path)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+
+    let mut terrain = None;
+    let mut objects = HashMap::new();
+    let mut parcels = Vec::new();
+    let mut saw_control_file = false;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+
+        if name == "archive.xml" {
+            saw_control_file = true;
+        } else if name.starts_with("terrains/") && name.ends_with(".r32") {
+            terrain = Some(parse_r32(&data, info)?);
+        } else if name.starts_with("objects/") && name.ends_with(".xml") {
+            let object = parse_object(&text(&data)?)?;
+            objects.insert(object.id, object);
+        } else if name.starts_with("landdata/") && name.ends_with(".xml") {
+            parcels.push(parse_parcel(&text(&data)?)?);
+        }
+    }
+
+    if !saw_control_file {
+        return Err(oar_error(path, "missing archive.xml"));
+    }
+
+    Ok(RegionScene {
+        info: info.clone(),
+        terrain: terrain.ok_or_else(|| oar_error(path, "missing terrain"))?,
+        objects,
+        parcels,
+    })
+}
+
+fn append<W: std::io::Write>(archive: &mut tar::Builder<W>, name: &str, data: &[u8]) -> MutseaResult<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Utc::now().timestamp() as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+fn oar_error(path: &Path, reason: &str) -> MutseaError {
+    MutseaError::Generic(format!("Invalid OAR {:?}: {}", path, reason))
+}
+
+fn text(data: &[u8]) -> MutseaResult<String> {
+    String::from_utf8(data.to_vec()).map_err(|e| MutseaError::Generic(e.to_string()))
+}
+
+fn archive_xml(info: &RegionInfo) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<archive major_version="{}" minor_version="{}">
+  <creation_info>
+    <datetime>{}</datetime>
+    <id>{}</id>
+  </creation_info>
+  <region_info>
+    <is_megaregion>False</is_megaregion>
+    <size_in_meters>{},{}</size_in_meters>
+  </region_info>
+  <assets_included>False</assets_included>
+</archive>
+"#,
+        OAR_MAJOR_VERSION,
+        OAR_MINOR_VERSION,
+        chrono::Utc::now().timestamp(),
+        info.region_id,
+        info.size_x,
+        info.size_y
+    )
+}
+
+fn parse_r32(data: &[u8], info: &RegionInfo) -> MutseaResult<Terrain> {
+    Terrain::from_r32(data, info.size_x, info.size_y).ok_or_else(|| {
+        MutseaError::Generic(format!(
+            "Terrain has {} bytes, expected {} for a {}x{} region",
+            data.len(),
+            info.size_x * info.size_y * 4,
+            info.size_x,
+            info.size_y
+        ))
+    })
+}
+
+fn vector_xml(out: &mut String, name: &str, v: &Vector3) {
+    let _ = write!(out, "<{name}><X>{}</X><Y>{}</Y><Z>{}</Z></{name}>", v.x, v.y, v.z);
+}
+
+fn uuid_xml(out: &mut String, name: &str, id: Uuid) {
+    let _ = write!(out, "<{name}><UUID>{}</UUID></{name}>", id);
+}
+
+/// Serialize an object using OpenSim's SceneObjectGroup element names for
+/// the fields Mutsea models
+pub(crate) fn object_xml(object: &SceneObject) -> String {
+    let mut out = String::from("<SceneObjectGroup><RootPart><SceneObjectPart>");
+    uuid_xml(&mut out, "CreatorID", object.creator_id.as_uuid());
+    uuid_xml(&mut out, "UUID", object.id.as_uuid());
+    let _ = write!(out, "<LocalId>{}</LocalId>", object.local_id);
+    let _ = write!(out, "<Name>{}</Name>", xml_escape(&object.name));
+    let _ = write!(out, "<Material>{}</Material>", object.material);
+    vector_xml(&mut out, "GroupPosition", &object.position);
+    let r = &object.rotation;
+    let _ = write!(
+        out,
+        "<RotationOffset><X>{}</X><Y>{}</Y><Z>{}</Z><W>{}</W></RotationOffset>",
+        r.x, r.y, r.z, r.w
+    );
+    vector_xml(&mut out, "Velocity", &object.velocity);
+    vector_xml(&mut out, "AngularVelocity", &object.angular_velocity);
+    let _ = write!(out, "<Description>{}</Description>", xml_escape(&object.description));
+    vector_xml(&mut out, "Scale", &object.scale);
+    uuid_xml(&mut out, "OwnerID", object.owner_id.as_uuid());
+    uuid_xml(&mut out, "GroupID", object.group_id.unwrap_or_else(Uuid::nil));
+    let _ = write!(out, "<Flags>{}</Flags>", object.flags);
+    let _ = write!(out, "<ClickAction>{}</ClickAction>", object.click_action);
+    let _ = write!(out, "<CreationDate>{}</CreationDate>", object.created.timestamp());
+
+    let s = &object.shape;
+    out.push_str("<Shape>");
+    let _ = write!(out, "<PathCurve>{}</PathCurve><ProfileCurve>{}</ProfileCurve>", s.path_curve, s.profile_curve);
+    for (name, value) in shape_fields(s) {
+        let _ = write!(out, "<{name}>{value}</{name}>");
+    }
+    out.push_str("</Shape></SceneObjectPart></RootPart><OtherParts /></SceneObjectGroup>");
+    out
+}
+
+fn shape_fields(s: &ObjectShape) -> [(&'static str, f32); 16] {
+    [
+        ("PathBegin", s.path_begin),
+        ("PathEnd", s.path_end),
+        ("PathScaleX", s.path_scale_x),
+        ("PathScaleY", s.path_scale_y),
+        ("PathShearX", s.path_shear_x),
+        ("PathShearY", s.path_shear_y),
+        ("PathTwist", s.path_twist),
+        ("PathTwistBegin", s.path_twist_begin),
+        ("PathRadiusOffset", s.path_radius_offset),
+        ("PathTaperX", s.path_taper_x),
+        ("PathTaperY", s.path_taper_y),
+        ("PathRevolutions", s.path_revolutions),
+        ("PathSkew", s.path_skew),
+        ("ProfileBegin", s.profile_begin),
+        ("ProfileEnd", s.profile_end),
+        ("ProfileHollow", s.profile_hollow),
+    ]
+}
+
+fn parse_xml(xml: &str) -> MutseaResult<roxmltree::Document<'_>> {
+    roxmltree::Document::parse(xml).map_err(|e| MutseaError::Generic(e.to_string()))
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn field<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> MutseaResult<T> {
+    child(node, name)
+        .and_then(|n| n.text().unwrap_or("").trim().parse().ok())
+        .ok_or_else(|| MutseaError::Generic(format!("Missing or invalid <{}>", name)))
+}
+
+fn string_field(node: roxmltree::Node, name: &str) -> String {
+    child(node, name).and_then(|n| n.text()).unwrap_or("").to_string()
+}
+
+fn uuid_field(node: roxmltree::Node, name: &str) -> MutseaResult<Uuid> {
+    child(node, name)
+        .and_then(|n| child(n, "UUID").or(Some(n)))
+        .and_then(|n| n.text())
+        .and_then(|t| Uuid::parse_str(t.trim()).ok())
+        .ok_or_else(|| MutseaError::Generic(format!("Missing or invalid <{}>", name)))
+}
+
+fn vector_field(node: roxmltree::Node, name: &str) -> MutseaResult<Vector3> {
+    let v = child(node, name).ok_or_else(|| MutseaError::Generic(format!("Missing <{}>", name)))?;
+    Ok(Vector3::new(field(v, "X")?, field(v, "Y")?, field(v, "Z")?))
+}
+
+pub(crate) fn parse_object(xml: &str) -> MutseaResult<SceneObject> {
+    let doc = parse_xml(xml)?;
+    let part = doc
+        .descendants()
+        .find(|n| n.has_tag_name("SceneObjectPart"))
+        .ok_or_else(|| MutseaError::Generic("Missing <SceneObjectPart>".to_string()))?;
+
+    let rotation = child(part, "RotationOffset")
+        .ok_or_else(|| MutseaError::Generic("Missing <RotationOffset>".to_string()))?;
+    let shape_node = child(part, "Shape").ok_or_else(|| MutseaError::Generic("Missing <Shape>".to_string()))?;
+    let shape_value = |name: &str| -> MutseaResult<f32> { field(shape_node, name) };
+
+    let group_id = uuid_field(part, "GroupID")?;
+    let created = chrono::DateTime::from_timestamp(field(part, "CreationDate")?, 0).unwrap_or_else(chrono::Utc::now);
+
+    let owner_id = UserId::from_uuid(uuid_field(part, "OwnerID")?);
+    Ok(SceneObject {
+        id: ObjectId::from_uuid(uuid_field(part, "UUID")?),
+        description: string_field(part, "Description"),
+        position: vector_field(part, "GroupPosition")?,
+        rotation: Quaternion::new(field(rotation, "X")?, field(rotation, "Y")?, field(rotation, "Z")?, field(rotation, "W")?),
+        scale: vector_field(part, "Scale")?,
+        velocity: vector_field(part, "Velocity")?,
+        angular_velocity: vector_field(part, "AngularVelocity")?,
+        creator_id: UserId::from_uuid(uuid_field(part, "CreatorID")?),
+        group_id: (!group_id.is_nil()).then_some(group_id),
+        flags: field(part, "Flags")?,
+        material: field(part, "Material")?,
+        click_action: field(part, "ClickAction")?,
+        shape: ObjectShape {
+            path_curve: field(shape_node, "PathCurve")?,
+            profile_curve: field(shape_node, "ProfileCurve")?,
+            path_begin: shape_value("PathBegin")?,
+            path_end: shape_value("PathEnd")?,
+            path_scale_x: shape_value("PathScaleX")?,
+            path_scale_y: shape_value("PathScaleY")?,
+            path_shear_x: shape_value("PathShearX")?,
+            path_shear_y: shape_value("PathShearY")?,
+            path_twist: shape_value("PathTwist")?,
+            path_twist_begin: shape_value("PathTwistBegin")?,
+            path_radius_offset: shape_value("PathRadiusOffset")?,
+            path_taper_x: shape_value("PathTaperX")?,
+            path_taper_y: shape_value("PathTaperY")?,
+            path_revolutions: shape_value("PathRevolutions")?,
+            path_skew: shape_value("PathSkew")?,
+            profile_begin: shape_value("ProfileBegin")?,
+            profile_end: shape_value("ProfileEnd")?,
+            profile_hollow: shape_value("ProfileHollow")?,
+        },
+        created,
+        ..SceneObject::new(field(part, "LocalId")?, string_field(part, "Name"), owner_id)
+    })
+}
+
+fn parcel_xml(parcel: &Parcel) -> String {
+    let mut out = String::from("<LandData>");
+    let _ = write!(out, "<Area>{}</Area>", parcel.area);
+    let _ = write!(out, "<Bitmap>{}</Bitmap>", BASE64.encode(&parcel.bitmap));
+    let _ = write!(out, "<Description>{}</Description>", xml_escape(&parcel.description));
+    let _ = write!(out, "<Flags>{}</Flags>", parcel.flags);
+    let _ = write!(out, "<GlobalID>{}</GlobalID>", parcel.id);
+    let _ = write!(out, "<GroupID>{}</GroupID>", parcel.group_id.unwrap_or_else(Uuid::nil));
+    let _ = write!(out, "<LocalID>{}</LocalID>", parcel.local_id);
+    let _ = write!(out, "<Name>{}</Name>", xml_escape(&parcel.name));
+    let _ = write!(out, "<OtherCleanTime>{}</OtherCleanTime>", parcel.other_clean_time);
+    let _ = write!(out, "<OwnerID>{}</OwnerID>", parcel.owner_id);
+    if let Some(maturity) = parcel.maturity {
+        let _ = write!(out, "<Maturity>{}</Maturity>", maturity.access_letter());
+    }
+    out.push_str("</LandData>");
+    out
+}
+
+fn parse_parcel(xml: &str) -> MutseaResult<Parcel> {
+    let doc = parse_xml(xml)?;
+    let land = doc.root_element();
+    let group_id = uuid_field(land, "GroupID")?;
+
+    Ok(Parcel {
+        id: uuid_field(land, "GlobalID")?,
+        local_id: field(land, "LocalID")?,
+        name: string_field(land, "Name"),
+        description: string_field(land, "Description"),
+        owner_id: UserId::from_uuid(uuid_field(land, "OwnerID")?),
+        group_id: (!group_id.is_nil()).then_some(group_id),
+        area: field(land, "Area")?,
+        flags: field(land, "Flags")?,
+        bitmap: BASE64
+            .decode(string_field(land, "Bitmap").trim())
+            .map_err(|e| MutseaError::Generic(e.to_string()))?,
+        maturity: string_field(land, "Maturity").parse().ok(),
+        other_clean_time: string_field(land, "OtherCleanTime").trim().parse().unwrap_or(0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene() -> RegionScene {
+        let info = RegionInfo::new(
+            "Backup Test".to_string(),
+            1000,
+            1000,
+            "127.0.0.1:9000".to_string(),
+            "127.0.0.1:9000".to_string(),
+        );
+        let mut scene = RegionScene::new(info, UserId::new());
+        scene.terrain.set_height(10, 20, 42.5);
+
+        scene.add_object(SceneObject {
+            description: "test <prim>".to_string(),
+            position: Vector3::new(128.0, 64.0, 22.0),
+            rotation: Quaternion::new(0.0, 0.0, 0.7071, 0.7071),
+            scale: Vector3::new(1.0, 2.0, 0.5),
+            creator_id: UserId::new(),
+            flags: 0x10,
+            material: 3,
+            ..SceneObject::new(7, "Crate & Barrel", UserId::new())
+        });
+        scene
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mutsea-backup-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_oar_round_trip() {
+        let dir = temp_dir();
+        let path = dir.join("test.oar");
+        let original = scene();
+
+        write_oar(&original, &path).unwrap();
+        let restored = read_oar(&path, &original.info).unwrap();
+
+        assert_eq!(restored.terrain, original.terrain);
+        assert_eq!(restored.parcels, original.parcels);
+        assert_eq!(restored.objects.len(), 1);
+        let (id, object) = restored.objects.iter().next().unwrap();
+        let expected = &original.objects[id];
+        assert_eq!(object.name, expected.name);
+        assert_eq!(object.description, expected.description);
+        assert_eq!(object.position, expected.position);
+        assert_eq!(object.rotation, expected.rotation);
+        assert_eq!(object.flags, expected.flags);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_retention_keeps_newest_snapshots() {
+        let dir = temp_dir();
+        let config = BackupConfig {
+            directory: dir.to_string_lossy().into_owned(),
+            keep_last: 3,
+            max_age_days: 7,
+            ..Default::default()
+        };
+        let region_dir = config.region_dir("Region");
+        std::fs::create_dir_all(&region_dir).unwrap();
+
+        let now = chrono::Utc::now();
+        for hours_ago in [0, 1, 2, 3, 200] {
+            let stamp = (now - chrono::Duration::hours(hours_ago)).format("%Y%m%d-%H%M%S");
+            std::fs::write(region_dir.join(format!("Region_{}.oar", stamp)), b"").unwrap();
+        }
+
+        prune_snapshots(&config, "Region", now).unwrap();
+
+        let remaining = list_snapshots(&config, "Region").unwrap();
+        assert_eq!(remaining.len(), 3);
+        assert!(remaining.windows(2).all(|w| w[0].taken_at > w[1].taken_at));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_staged_rollback_applied_on_register() {
+        let dir = temp_dir();
+        let config = BackupConfig {
+            directory: dir.to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+
+        let saved = scene();
+        let pending = config.pending_rollback_path(&saved.info.region_name);
+        std::fs::create_dir_all(pending.parent().unwrap()).unwrap();
+        write_oar(&saved, &pending).unwrap();
+
+        let live = Arc::new(RwLock::new(RegionScene::new(saved.info.clone(), UserId::new())));
+        BackupScheduler::new(config).register_region(Arc::clone(&live)).await.unwrap();
+
+        let live = live.read().await;
+        assert_eq!(live.terrain.height_at(10, 20), 42.5);
+        assert_eq!(live.objects.len(), 1);
+        assert!(!pending.exists());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,350 @@
+//! Avatar bakes made by the region
+//!
+//! When a viewer posts to UpdateAvatarAppearance, the wearables linked from
+//! the agent's Current Outfit folder are read and their textures layered,
+//! skin first and jackets last, into one square bake each for the head, the
+//! upper and the lower body; an alpha wearable cuts holes through its bake.
+//! The bakes are kept as texture assets, served from the AgentTextures path
+//! and shown to everyone in an AvatarAppearance.
+//!
+//! Textures are read and bakes written by a [`TextureCodec`]. The built-in
+//! one handles PNG; textures it can't read are left out of their bake, so
+//! regions whose viewers upload JPEG-2000 should plug in a codec for it.
+
+use chrono::Utc;
+use mutsea_core::config::AppearanceConfig;
+use mutsea_core::UserId;
+use mutsea_database::schema::Asset;
+use mutsea_database::DatabaseManager;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::appearance::{BakeKind, Wearable, DEFAULT_AVATAR_TEXTURE};
+use mutsea_protocol::constants::asset_types;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Folder type of the Current Outfit folder
+const CURRENT_OUTFIT_FOLDER_TYPE: i32 = 46;
+
+/// Asset type of inventory links, whose asset ID is the linked item's ID
+const LINK_ASSET_TYPE: i32 = 24;
+
+/// Colour under every layer, where no skin is worn
+const BASE_COLOUR: [u8; 4] = [128, 128, 128, 255];
+
+#[derive(Debug, Error, PartialEq)]
+pub enum BakeError {
+    #[error("Appearance can't be baked without a database")]
+    NoDatabase,
+    #[error("{0} has no Current Outfit folder")]
+    NoOutfit(UserId),
+    #[error("Unknown bake {0}")]
+    UnknownBake(String),
+    #[error("No {0} bake has been made")]
+    NotBaked(&'static str),
+    #[error("Failed to bake appearance: {0}")]
+    Database(String),
+}
+
+/// An RGBA image, rows top to bottom
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Layer {
+    /// A `width` by `height` image all of `colour`
+    pub fn filled(width: u32, height: u32, colour: [u8; 4]) -> Self {
+        Self {
+            width,
+            height,
+            pixels: colour.repeat((width * height) as usize),
+        }
+    }
+
+    /// The pixel at `x`, `y` of a `size` square the layer is stretched over
+    fn sample(&self, x: u32, y: u32, size: u32) -> [u8; 4] {
+        let x = (x as u64 * self.width as u64 / size as u64) as usize;
+        let y = (y as u64 * self.height as u64 / size as u64) as usize;
+        let at = (y * self.width as usize + x) * 4;
+        self.pixels[at..at + 4].try_into().unwrap()
+    }
+}
+
+/// Reads wearable textures and writes bakes
+pub trait TextureCodec: Send + Sync {
+    /// The image in texture asset `data`, if it can be read
+    fn decode(&self, data: &[u8]) -> Option<Layer>;
+    /// `layer` as texture asset data
+    fn encode(&self, layer: &Layer) -> Vec<u8>;
+    /// The Content-Type bakes are served with
+    fn content_type(&self) -> &'static str;
+}
+
+/// Codec for PNG textures; used until one for JPEG-2000 is plugged in
+pub struct PngCodec;
+
+impl TextureCodec for PngCodec {
+    fn decode(&self, data: &[u8]) -> Option<Layer> {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().ok()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).ok()?;
+        let buffer = &buffer[..info.buffer_size()];
+        let pixels = match info.color_type.samples() {
+            1 => buffer.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+            2 => buffer.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+            3 => buffer.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+            4 => buffer.to_vec(),
+            _ => return None,
+        };
+        (info.width > 0 && info.height > 0).then_some(Layer {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    fn encode(&self, layer: &Layer) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, layer.width, layer.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let written = encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&layer.pixels).and_then(|_| writer.finish()));
+        if let Err(e) = written {
+            warn!("Failed to encode bake: {}", e);
+        }
+        out
+    }
+
+    fn content_type(&self) -> &'static str {
+        "image/png"
+    }
+}
+
+/// `layers` stretched over a `size` square and laid one over another on
+/// the base colour, bottom first, cut by the alpha channel of `alpha`
+pub fn composite(size: u32, layers: &[Layer], alpha: Option<&Layer>) -> Layer {
+    let mut baked = Layer::filled(size, size, BASE_COLOUR);
+    for y in 0..size {
+        for x in 0..size {
+            let at = ((y * size + x) * 4) as usize;
+            let out = &mut baked.pixels[at..at + 4];
+            for layer in layers {
+                let [r, g, b, a] = layer.sample(x, y, size);
+                let a = a as u32;
+                for (channel, value) in out[..3].iter_mut().zip([r, g, b]) {
+                    *channel = ((value as u32 * a + *channel as u32 * (255 - a)) / 255) as u8;
+                }
+            }
+            if let Some(mask) = alpha {
+                out[3] = (out[3] as u32 * mask.sample(x, y, size)[3] as u32 / 255) as u8;
+            }
+        }
+    }
+    baked
+}
+
+/// Bakes avatars' outfits into their textures
+#[derive(Clone)]
+pub struct AvatarBakes {
+    config: AppearanceConfig,
+    lludp_server: Option<LLUDPServer>,
+    database: Option<Arc<DatabaseManager>>,
+    codec: Arc<dyn TextureCodec>,
+    /// Each agent's latest bakes
+    bakes: Arc<Mutex<HashMap<UserId, Vec<(BakeKind, Uuid)>>>>,
+}
+
+impl AvatarBakes {
+    pub fn new(config: AppearanceConfig) -> Self {
+        Self {
+            config,
+            lludp_server: None,
+            database: None,
+            codec: Arc::new(PngCodec),
+            bakes: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Show the agents connected to `lludp_server` the avatars baked
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Read outfits from and keep bakes in `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Read textures and write bakes with `codec` rather than as PNG
+    pub fn with_codec(mut self, codec: Arc<dyn TextureCodec>) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// The Content-Type bakes are served with
+    pub fn content_type(&self) -> &'static str {
+        self.codec.content_type()
+    }
+
+    /// Bake the outfit of `agent`, its Current Outfit folder at
+    /// `cof_version`, and show it to everyone
+    pub async fn bake(&self, agent: UserId, cof_version: i32) -> Result<Vec<(BakeKind, Uuid)>, BakeError> {
+        let database = self.database.as_ref().ok_or(BakeError::NoDatabase)?;
+        let textures = self.outfit_textures(database, agent).await?;
+        let mut made = Vec::with_capacity(BakeKind::ALL.len());
+        for kind in BakeKind::ALL {
+            let mut layers = Vec::new();
+            for face in kind.layers() {
+                for (_, texture) in textures.iter().filter(|(worn_on, _)| worn_on == face) {
+                    layers.extend(self.load_layer(database, *texture).await?);
+                }
+            }
+            let alpha = match textures.iter().rev().find(|(worn_on, _)| *worn_on == kind.alpha_face()) {
+                Some((_, texture)) => self.load_layer(database, *texture).await?,
+                None => None,
+            };
+
+            let texture_id = Uuid::new_v4();
+            let baked = composite(self.config.bake_size, &layers, alpha.as_ref());
+            let mut asset = Asset::new(
+                texture_id.to_string(),
+                format!("Baked {} of {}", kind.name(), agent),
+                asset_types::TEXTURE as i32,
+                self.codec.encode(&baked),
+            );
+            asset.creator_id = agent.to_string();
+            asset.create_time = Utc::now().timestamp() as i32;
+            database
+                .insert_asset(&asset)
+                .await
+                .map_err(|e| BakeError::Database(e.to_string()))?;
+            made.push((kind, texture_id));
+        }
+
+        self.bakes.lock().unwrap().insert(agent, made.clone());
+        if let Some(lludp_server) = &self.lludp_server {
+            if let Err(e) = lludp_server.send_avatar_appearance(agent, cof_version, &made).await {
+                warn!("Failed to send the appearance of {}: {}", agent, e);
+            }
+        }
+        info!("👕 Baked the outfit of {} from {} textures", agent, textures.len());
+        Ok(made)
+    }
+
+    /// The `bake` texture of `agent`, as asset data
+    pub async fn texture(&self, agent: UserId, bake: &str) -> Result<Vec<u8>, BakeError> {
+        let kind = BakeKind::from_name(bake).ok_or_else(|| BakeError::UnknownBake(bake.to_string()))?;
+        let database = self.database.as_ref().ok_or(BakeError::NoDatabase)?;
+        let texture_id = self
+            .bakes
+            .lock()
+            .unwrap()
+            .get(&agent)
+            .and_then(|bakes| bakes.iter().find(|(made, _)| *made == kind).map(|(_, id)| *id))
+            .ok_or(BakeError::NotBaked(kind.name()))?;
+        database
+            .get_asset(&texture_id.to_string())
+            .await
+            .map_err(|e| BakeError::Database(e.to_string()))?
+            .map(|asset| asset.data)
+            .ok_or(BakeError::NotBaked(kind.name()))
+    }
+
+    /// The textures of the wearables `agent` wears, by the face they go on,
+    /// in the order its Current Outfit folder lists them
+    async fn outfit_textures(&self, database: &DatabaseManager, agent: UserId) -> Result<Vec<(u8, Uuid)>, BakeError> {
+        let database_error = |e: mutsea_database::DatabaseError| BakeError::Database(e.to_string());
+        let agent_id = agent.to_string();
+        let folder = database
+            .get_inventory_folder_of_type(&agent_id, CURRENT_OUTFIT_FOLDER_TYPE)
+            .await
+            .map_err(database_error)?
+            .ok_or(BakeError::NoOutfit(agent))?;
+
+        let mut textures = Vec::new();
+        for item in database.get_inventory_folder_items(&folder).await.map_err(database_error)? {
+            let item = match item.asset_type {
+                LINK_ASSET_TYPE => match database.get_inventory_item(&item.asset_id, &agent_id).await.map_err(database_error)? {
+                    Some(linked) => linked,
+                    None => continue,
+                },
+                _ => item,
+            };
+            if item.asset_type != asset_types::BODYPART as i32 && item.asset_type != asset_types::CLOTHING as i32 {
+                continue;
+            }
+            let Some(asset) = database.get_asset(&item.asset_id).await.map_err(database_error)? else {
+                continue;
+            };
+            match Wearable::parse(&String::from_utf8_lossy(&asset.data)) {
+                Ok(wearable) => textures.extend(wearable.textures),
+                Err(e) => debug!("Wearable {} of {} left out: {}", item.name, agent, e),
+            }
+        }
+        Ok(textures)
+    }
+
+    /// The image of `texture`, if it isn't a default and can be read
+    async fn load_layer(&self, database: &DatabaseManager, texture: Uuid) -> Result<Option<Layer>, BakeError> {
+        if texture.is_nil() || texture == DEFAULT_AVATAR_TEXTURE {
+            return Ok(None);
+        }
+        let asset = database
+            .get_asset(&texture.to_string())
+            .await
+            .map_err(|e| BakeError::Database(e.to_string()))?;
+        let Some(asset) = asset.filter(|asset| asset.data.len() <= self.config.max_layer_bytes) else {
+            debug!("Texture {} left out of bake, missing or too large", texture);
+            return Ok(None);
+        };
+        let layer = self.codec.decode(&asset.data);
+        if layer.is_none() {
+            debug!("Texture {} left out of bake, can't be read", texture);
+        }
+        Ok(layer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_composite() {
+        let skin = Layer::filled(2, 2, [200, 150, 100, 255]);
+        // Half see-through, and only on the top row
+        let mut shirt = Layer::filled(1, 2, [0, 0, 255, 128]);
+        shirt.pixels[4..].copy_from_slice(&[0, 0, 0, 0]);
+        let mut alpha = Layer::filled(4, 4, [0, 0, 0, 255]);
+        alpha.pixels[..4].copy_from_slice(&[0, 0, 0, 0]);
+
+        let baked = composite(4, &[skin, shirt], Some(&alpha));
+        assert_eq!((baked.width, baked.height), (4, 4));
+        assert_eq!(&baked.pixels[4..8], &[99, 74, 177, 255]);
+        assert_eq!(&baked.pixels[..4], &[99, 74, 177, 0]);
+        assert_eq!(&baked.pixels[(3 * 4 + 3) * 4..], &[200, 150, 100, 255]);
+
+        let bare = composite(2, &[], None);
+        assert_eq!(&bare.pixels[..4], &BASE_COLOUR);
+    }
+
+    #[test]
+    fn test_png_codec() {
+        let mut layer = Layer::filled(3, 2, [10, 20, 30, 255]);
+        layer.pixels[4..8].copy_from_slice(&[40, 50, 60, 70]);
+        let data = PngCodec.encode(&layer);
+        assert_eq!(PngCodec.decode(&data), Some(layer));
+        assert!(PngCodec.decode(b"not a png").is_none());
+    }
+}
@@ -0,0 +1,320 @@
+//! Bandwidth accounting
+//!
+//! The LLUDP server counts the bytes each circuit sends and receives, by
+//! priority class. Operators billing by bandwidth see what the open
+//! circuits have used so far and, per agent, what each of its sessions
+//! used: the `session_end` rows the session tracker writes to analytics
+//! when there is a database, or the last few sessions kept in memory
+//! otherwise.
+
+use chrono::{DateTime, Utc};
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::{PlayerBehaviorRecord, UserId};
+use mutsea_database::{player_behavior, DatabaseManager};
+use mutsea_network::lludp_server::CircuitUsage;
+use mutsea_network::LLUDPServer;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+use uuid::Uuid;
+
+/// How many sessions of each agent a report lists
+const SESSION_LIMIT: usize = 500;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum UsageError {
+    #[error("Usage history unavailable: {0}")]
+    Database(String),
+}
+
+/// What one finished session used
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionUsage {
+    pub session_id: Uuid,
+    pub ended_at: DateTime<Utc>,
+    pub reason: String,
+    pub duration_secs: f64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub sent_by_class: BTreeMap<String, u64>,
+    pub received_by_class: BTreeMap<String, u64>,
+}
+
+impl SessionUsage {
+    /// The usage in a `session_end` record
+    pub fn from_record(record: &PlayerBehaviorRecord) -> Self {
+        let data = &record.action_data;
+        let by_class = |key: &str| -> BTreeMap<String, u64> {
+            data[key]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter_map(|(class, bytes)| Some((class.clone(), bytes.as_u64()?)))
+                .collect()
+        };
+        let (sent_by_class, received_by_class) = (by_class("sent_by_class"), by_class("received_by_class"));
+        Self {
+            session_id: record.session_id,
+            ended_at: record.timestamp,
+            reason: data["reason"].as_str().unwrap_or_default().to_string(),
+            duration_secs: data["duration_secs"].as_f64().unwrap_or(0.0),
+            bytes_sent: sent_by_class.values().sum(),
+            bytes_received: received_by_class.values().sum(),
+            sent_by_class,
+            received_by_class,
+        }
+    }
+}
+
+/// What an agent used since a point in time
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageReport {
+    pub agent_id: UserId,
+    pub since: DateTime<Utc>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub sent_by_class: BTreeMap<String, u64>,
+    pub received_by_class: BTreeMap<String, u64>,
+    /// Circuits still open, counted so far
+    pub live: Vec<CircuitUsage>,
+    /// Finished sessions, newest first
+    pub sessions: Vec<SessionUsage>,
+}
+
+impl UsageReport {
+    fn new(agent_id: UserId, since: DateTime<Utc>, live: Vec<CircuitUsage>, sessions: Vec<SessionUsage>) -> Self {
+        let mut sent_by_class = BTreeMap::new();
+        let mut received_by_class = BTreeMap::new();
+        let counts = live
+            .iter()
+            .map(|c| (&c.sent_by_class, &c.received_by_class))
+            .chain(sessions.iter().map(|s| (&s.sent_by_class, &s.received_by_class)));
+        for (sent, received) in counts {
+            for (class, bytes) in sent {
+                *sent_by_class.entry(class.clone()).or_insert(0) += bytes;
+            }
+            for (class, bytes) in received {
+                *received_by_class.entry(class.clone()).or_insert(0) += bytes;
+            }
+        }
+        Self {
+            agent_id,
+            since,
+            bytes_sent: sent_by_class.values().sum(),
+            bytes_received: received_by_class.values().sum(),
+            sent_by_class,
+            received_by_class,
+            live,
+            sessions,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Session and start of each open circuit
+    open: HashMap<u32, (Uuid, DateTime<Utc>)>,
+    /// Finished sessions of each agent while there is no database, newest
+    /// last
+    finished: HashMap<UserId, VecDeque<SessionUsage>>,
+}
+
+/// Bandwidth used by circuits and agents. Clones share the sessions kept.
+#[derive(Clone, Default)]
+pub struct Bandwidth {
+    lludp_server: Option<LLUDPServer>,
+    database: Option<Arc<DatabaseManager>>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Bandwidth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Read past sessions from the analytics tables
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Keep the sessions ending while there is no database
+    pub fn spawn(&self) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        if self.database.is_some() {
+            return;
+        }
+        let bandwidth = self.clone();
+        let mut events = lludp_server.subscribe_events();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => bandwidth.follow(&event.event_data),
+                    Err(RecvError::Lagged(missed)) => warn!("Bandwidth accounting missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Keep the usage of sessions ending in `event`
+    fn follow(&self, event: &NetworkEventData) {
+        let mut state = self.state.lock().unwrap();
+        match event {
+            NetworkEventData::AgentLoggedIn {
+                circuit_code,
+                session_id,
+                ..
+            } => {
+                state.open.entry(*circuit_code).or_insert((*session_id, Utc::now()));
+            }
+            NetworkEventData::AgentLoggedOut {
+                circuit_code,
+                agent_id,
+                reason,
+                sent_by_class,
+                received_by_class,
+                ..
+            } => {
+                let Some((session_id, started_at)) = state.open.remove(circuit_code) else {
+                    return;
+                };
+                let ended_at = Utc::now();
+                let sent_by_class: BTreeMap<String, u64> = sent_by_class.clone().into_iter().collect();
+                let received_by_class: BTreeMap<String, u64> = received_by_class.clone().into_iter().collect();
+                let sessions = state.finished.entry(*agent_id).or_default();
+                sessions.push_back(SessionUsage {
+                    session_id,
+                    ended_at,
+                    reason: reason.clone(),
+                    duration_secs: (ended_at - started_at).to_std().unwrap_or_default().as_secs_f64(),
+                    bytes_sent: sent_by_class.values().sum(),
+                    bytes_received: received_by_class.values().sum(),
+                    sent_by_class,
+                    received_by_class,
+                });
+                if sessions.len() > SESSION_LIMIT {
+                    sessions.pop_front();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// What every open circuit has used so far
+    pub async fn live(&self) -> Vec<CircuitUsage> {
+        match &self.lludp_server {
+            Some(lludp_server) => lludp_server.circuit_usage().await,
+            None => Vec::new(),
+        }
+    }
+
+    /// What `agent` used in sessions ended since `since` and in its open
+    /// circuits
+    pub async fn report(&self, agent: UserId, since: DateTime<Utc>) -> Result<UsageReport, UsageError> {
+        let live: Vec<CircuitUsage> = self
+            .live()
+            .await
+            .into_iter()
+            .filter(|circuit| circuit.agent_id == Some(agent))
+            .collect();
+        let sessions = match &self.database {
+            Some(database) => {
+                let backend = database
+                    .get_backend()
+                    .await
+                    .map_err(|e| UsageError::Database(e.to_string()))?;
+                player_behavior::session_ends(backend.as_ref(), agent.as_uuid(), since, SESSION_LIMIT as i64)
+                    .await
+                    .map_err(|e| UsageError::Database(e.to_string()))?
+                    .iter()
+                    .map(SessionUsage::from_record)
+                    .collect()
+            }
+            None => self
+                .state
+                .lock()
+                .unwrap()
+                .finished
+                .get(&agent)
+                .into_iter()
+                .flatten()
+                .rev()
+                .filter(|session| session.ended_at >= since)
+                .cloned()
+                .collect(),
+        };
+        Ok(UsageReport::new(agent, since, live, sessions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::Vector3;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_sessions_are_reported_with_their_usage() {
+        let bandwidth = Bandwidth::new();
+        let (agent, other) = (UserId::new(), UserId::new());
+        let session_id = Uuid::new_v4();
+        let since = Utc::now() - chrono::Duration::days(1);
+
+        bandwidth.follow(&NetworkEventData::AgentLoggedIn {
+            circuit_code: 7,
+            agent_id: agent,
+            session_id,
+        });
+        bandwidth.follow(&NetworkEventData::AgentLoggedOut {
+            circuit_code: 7,
+            agent_id: agent,
+            reason: "logout".to_string(),
+            sent_by_class: HashMap::from([("object".to_string(), 1500), ("ack".to_string(), 20)]),
+            received_by_class: HashMap::from([("avatar".to_string(), 300)]),
+            region_id: None,
+            position: Vector3::ZERO,
+            look_at: Vector3::ZERO,
+        });
+
+        let report = bandwidth.report(agent, since).await.unwrap();
+        assert_eq!(report.sessions.len(), 1);
+        assert_eq!(report.sessions[0].session_id, session_id);
+        assert_eq!((report.bytes_sent, report.bytes_received), (1520, 300));
+        assert_eq!(report.sent_by_class["object"], 1500);
+        assert!(bandwidth
+            .report(agent, Utc::now() + chrono::Duration::seconds(1))
+            .await
+            .unwrap()
+            .sessions
+            .is_empty());
+        assert_eq!(bandwidth.report(other, since).await.unwrap().bytes_sent, 0);
+
+        // Sessions written to analytics read back the same
+        let record = PlayerBehaviorRecord {
+            player_id: agent,
+            session_id,
+            timestamp: report.sessions[0].ended_at,
+            action_type: "session_end".to_string(),
+            action_data: json!({
+                "reason": "logout",
+                "duration_secs": report.sessions[0].duration_secs,
+                "sent_by_class": { "object": 1500, "ack": 20 },
+                "received_by_class": { "avatar": 300 },
+            }),
+            context_data: json!({}),
+        };
+        assert_eq!(SessionUsage::from_record(&record), report.sessions[0]);
+    }
+}
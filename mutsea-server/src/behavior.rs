@@ -0,0 +1,449 @@
+//! Behavior trees and utility scoring for NPC controllers
+//!
+//! Behaviors are defined in JSON or TOML files. Each names the goal types
+//! it works towards and the behavior pattern it belongs to, and has a tree
+//! of conditions and actions. To decide what an NPC does, every behavior is
+//! scored against the NPC's goals, patterns and facts, and the best one
+//! whose tree succeeds gives the NPC its actions. Definitions are reloaded
+//! when their files change.
+
+use mutsea_core::{MutseaError, MutseaResult};
+use mutsea_database::models::{BehaviorPattern, ConditionOperator, GoalStatus, GoalType, NPCGoal};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// How a fact is compared in a condition
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Compare {
+    #[serde(rename = "<")]
+    Less,
+    #[serde(rename = "<=")]
+    LessEqual,
+    #[serde(rename = ">")]
+    Greater,
+    #[serde(rename = ">=")]
+    GreaterEqual,
+    #[serde(rename = "==")]
+    Equal,
+    #[serde(rename = "!=")]
+    NotEqual,
+}
+
+impl Compare {
+    fn test(self, actual: f32, expected: f32) -> bool {
+        match self {
+            Compare::Less => actual < expected,
+            Compare::LessEqual => actual <= expected,
+            Compare::Greater => actual > expected,
+            Compare::GreaterEqual => actual >= expected,
+            Compare::Equal => (actual - expected).abs() < f32::EPSILON,
+            Compare::NotEqual => (actual - expected).abs() >= f32::EPSILON,
+        }
+    }
+}
+
+/// Something an NPC does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// Walk to a point in the region
+    MoveTo { x: f32, y: f32 },
+    /// Walk back to where the NPC started
+    GoHome,
+    /// Walk somewhere within `radius` metres of home
+    Wander { radius: f32 },
+    /// Say `message` in local chat
+    Say { message: String },
+    /// Use the object named `object`
+    Interact { object: String },
+}
+
+/// A node of a behavior tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Node {
+    /// Succeeds if every child does, in order
+    Sequence(Vec<Node>),
+    /// Succeeds with the first child that does
+    Selector(Vec<Node>),
+    /// Succeeds if the child fails
+    Not(Box<Node>),
+    /// Succeeds if the fact compares as given; unknown facts fail
+    Condition { fact: String, op: Compare, value: f32 },
+    /// Succeeds with the given probability
+    Chance(f32),
+    /// Always succeeds, doing the action
+    Action(Action),
+}
+
+impl Node {
+    /// Run the tree, adding the actions of the branches that succeed
+    fn run(&self, facts: &HashMap<String, f32>, rng: &mut impl Rng, actions: &mut Vec<Action>) -> bool {
+        let start = actions.len();
+        let succeeded = match self {
+            Node::Sequence(children) => children.iter().all(|child| child.run(facts, rng, actions)),
+            // Failed children take their actions back with them
+            Node::Selector(children) => children.iter().any(|child| child.run(facts, rng, actions)),
+            Node::Not(child) => {
+                let succeeded = !child.run(facts, rng, actions);
+                // An inverted branch only tests
+                actions.truncate(start);
+                succeeded
+            }
+            Node::Condition { fact, op, value } => facts.get(fact).is_some_and(|actual| op.test(*actual, *value)),
+            Node::Chance(probability) => rng.gen::<f32>() < *probability,
+            Node::Action(action) => {
+                actions.push(action.clone());
+                true
+            }
+        };
+        if !succeeded {
+            actions.truncate(start);
+        }
+        succeeded
+    }
+}
+
+/// One way an NPC can behave
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BehaviorDefinition {
+    pub name: String,
+    /// Score before goals and patterns count
+    #[serde(default)]
+    pub base_score: f32,
+    /// Goal types the behavior works towards
+    #[serde(default)]
+    pub goal_types: Vec<GoalType>,
+    /// Name of the behavior pattern it belongs to
+    #[serde(default)]
+    pub pattern: Option<String>,
+    pub tree: Node,
+}
+
+impl BehaviorDefinition {
+    /// How much the NPC wants to behave this way. Each active goal it works
+    /// towards adds its priority, weighted by urgency and what is left to
+    /// do. An NPC with the behavior's pattern scales the score by how
+    /// reliably it follows the pattern, less for each unmet condition.
+    pub fn score(&self, context: &NpcContext) -> f32 {
+        let goals: f32 = context
+            .goals
+            .iter()
+            .filter(|goal| goal.goal_status == GoalStatus::Active && self.goal_types.contains(&goal.goal_type))
+            .map(|goal| goal.goal_priority * (0.5 + 0.5 * goal.goal_urgency) * (1.0 - goal.goal_progress).clamp(0.0, 1.0))
+            .sum();
+        let mut score = self.base_score + goals;
+
+        let pattern = self
+            .pattern
+            .as_ref()
+            .and_then(|name| context.patterns.iter().find(|pattern| &pattern.pattern_name == name));
+        if let Some(pattern) = pattern {
+            score *= 0.5 + pattern.pattern_reliability;
+            for condition in &pattern.pattern_conditions {
+                let met = context
+                    .facts
+                    .get(&condition.condition_type)
+                    .is_some_and(|actual| operator_met(&condition.condition_operator, *actual, condition.condition_value));
+                if !met {
+                    score *= 1.0 - condition.condition_importance.clamp(0.0, 1.0);
+                }
+            }
+        }
+        score
+    }
+}
+
+/// Pattern conditions compare the fact named by their type; `Contains`
+/// and `InRange` don't apply to facts and are never met
+fn operator_met(operator: &ConditionOperator, actual: f32, expected: f32) -> bool {
+    let compare = match operator {
+        ConditionOperator::Equal => Compare::Equal,
+        ConditionOperator::NotEqual => Compare::NotEqual,
+        ConditionOperator::GreaterThan => Compare::Greater,
+        ConditionOperator::GreaterEqual => Compare::GreaterEqual,
+        ConditionOperator::LessThan => Compare::Less,
+        ConditionOperator::LessEqual => Compare::LessEqual,
+        ConditionOperator::Contains | ConditionOperator::InRange => return false,
+    };
+    compare.test(actual, expected)
+}
+
+/// What an NPC knows when deciding what to do
+pub struct NpcContext<'a> {
+    pub goals: &'a [NPCGoal],
+    pub patterns: &'a [BehaviorPattern],
+    /// Named facts conditions test, e.g. `distance_from_home`
+    pub facts: &'a HashMap<String, f32>,
+}
+
+/// The behavior chosen for an NPC and what it does
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    pub behavior: String,
+    pub score: f32,
+    pub actions: Vec<Action>,
+}
+
+/// A file of behavior definitions
+#[derive(Deserialize)]
+struct BehaviorFile {
+    behaviors: Vec<BehaviorDefinition>,
+}
+
+/// Behavior definitions loaded from a directory
+pub struct BehaviorLibrary {
+    path: PathBuf,
+    behaviors: RwLock<Vec<BehaviorDefinition>>,
+    /// Modification times of the files last loaded
+    loaded: Mutex<HashMap<PathBuf, SystemTime>>,
+}
+
+impl BehaviorLibrary {
+    /// Load the definitions under `path`; a missing directory has none
+    pub fn load(path: impl Into<PathBuf>) -> MutseaResult<Self> {
+        let path = path.into();
+        let files = definition_files(&path)?;
+        let behaviors = read_definitions(&files)?;
+        info!("🧠 Loaded {} NPC behaviors from {}", behaviors.len(), path.display());
+        Ok(Self {
+            path,
+            behaviors: RwLock::new(behaviors),
+            loaded: Mutex::new(files),
+        })
+    }
+
+    /// The best-scoring behavior whose tree succeeds. Behaviors scoring
+    /// zero or less are never chosen.
+    pub fn decide(&self, context: &NpcContext, rng: &mut impl Rng) -> Option<Decision> {
+        let behaviors = self.behaviors.read().unwrap();
+        let mut scored: Vec<_> = behaviors
+            .iter()
+            .map(|behavior| (behavior, behavior.score(context)))
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        scored.into_iter().find_map(|(behavior, score)| {
+            let mut actions = Vec::new();
+            behavior.tree.run(context.facts, rng, &mut actions).then(|| Decision {
+                behavior: behavior.name.clone(),
+                score,
+                actions,
+            })
+        })
+    }
+
+    /// Reload the definitions if any file changed, keeping the old ones if
+    /// the new ones don't load. Returns whether they were reloaded.
+    pub fn reload_if_changed(&self) -> MutseaResult<bool> {
+        let files = definition_files(&self.path)?;
+        if *self.loaded.lock().unwrap() == files {
+            return Ok(false);
+        }
+        let behaviors = read_definitions(&files)?;
+        info!("🧠 Reloaded {} NPC behaviors from {}", behaviors.len(), self.path.display());
+        *self.behaviors.write().unwrap() = behaviors;
+        *self.loaded.lock().unwrap() = files;
+        Ok(true)
+    }
+
+    /// Check for changed definitions every `interval`
+    pub fn spawn_reload(self: &Arc<Self>, interval: Duration) {
+        let library = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = library.reload_if_changed() {
+                    warn!("Keeping the current NPC behaviors: {}", e);
+                }
+            }
+        });
+    }
+}
+
+/// Definition files under `path` with their modification times
+fn definition_files(path: &Path) -> MutseaResult<HashMap<PathBuf, SystemTime>> {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut files = HashMap::new();
+    for entry in entries {
+        let path = entry?.path();
+        if matches!(path.extension().and_then(|e| e.to_str()), Some("json" | "toml")) {
+            let modified = std::fs::metadata(&path)?.modified()?;
+            files.insert(path, modified);
+        }
+    }
+    Ok(files)
+}
+
+/// Every behavior in `files`, in file name order
+fn read_definitions(files: &HashMap<PathBuf, SystemTime>) -> MutseaResult<Vec<BehaviorDefinition>> {
+    let mut paths: Vec<_> = files.keys().collect();
+    paths.sort();
+
+    let mut behaviors = Vec::new();
+    for path in paths {
+        let text = std::fs::read_to_string(path)?;
+        let invalid = |e: String| MutseaError::InvalidConfiguration(format!("Invalid behaviors in {}: {}", path.display(), e));
+        let file: BehaviorFile = if path.extension().is_some_and(|e| e == "json") {
+            serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?
+        } else {
+            toml::from_str(&text).map_err(|e| invalid(e.to_string()))?
+        };
+        behaviors.extend(file.behaviors);
+    }
+    Ok(behaviors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_database::models::{PatternCondition, PatternFrequency, PatternType};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const BEHAVIORS: &str = r#"
+[[behaviors]]
+name = "gossip"
+base_score = 0.1
+goal_types = ["Social"]
+pattern = "market_chatter"
+
+[behaviors.tree]
+sequence = [
+    { condition = { fact = "nearby_avatars", op = ">", value = 0.0 } },
+    { action = { type = "say", message = "Have you heard the news?" } },
+]
+
+[[behaviors]]
+name = "stroll"
+base_score = 0.2
+
+[behaviors.tree]
+selector = [
+    { sequence = [
+        { condition = { fact = "distance_from_home", op = ">", value = 20.0 } },
+        { action = { type = "go_home" } },
+    ] },
+    { action = { type = "wander", radius = 10.0 } },
+]
+"#;
+
+    fn goal(goal_type: GoalType, priority: f32) -> NPCGoal {
+        serde_json::from_value(serde_json::json!({
+            "goal_id": uuid::Uuid::new_v4(),
+            "goal_name": "Make friends",
+            "goal_type": goal_type,
+            "goal_priority": priority,
+            "goal_urgency": 1.0,
+            "goal_progress": 0.0,
+            "goal_status": "Active",
+            "subgoals": [],
+            "required_resources": [],
+            "obstacles": [],
+            "deadline": null,
+            "motivation_sources": [],
+            "success_criteria": [],
+            "abandonment_conditions": [],
+        }))
+        .unwrap()
+    }
+
+    fn library(dir: &Path) -> BehaviorLibrary {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("ambient.toml"), BEHAVIORS).unwrap();
+        BehaviorLibrary::load(dir).unwrap()
+    }
+
+    #[test]
+    fn test_goals_and_patterns_choose_the_behavior() {
+        let dir = std::env::temp_dir().join(format!("mutsea-behaviors-{}", uuid::Uuid::new_v4()));
+        let library = library(&dir);
+        assert_eq!(library.behaviors.read().unwrap().len(), 2);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut facts = HashMap::from([("nearby_avatars".to_string(), 2.0), ("distance_from_home".to_string(), 30.0)]);
+
+        // Without goals strolling scores higher, and heads home when far
+        let context = NpcContext { goals: &[], patterns: &[], facts: &facts };
+        let decision = library.decide(&context, &mut rng).unwrap();
+        assert_eq!(decision.behavior, "stroll");
+        assert_eq!(decision.actions, vec![Action::GoHome]);
+
+        // A social goal makes gossip worth more
+        let goals = vec![goal(GoalType::Social, 0.8), goal(GoalType::Economic, 1.0)];
+        let context = NpcContext { goals: &goals, patterns: &[], facts: &facts };
+        let decision = library.decide(&context, &mut rng).unwrap();
+        assert_eq!(decision.behavior, "gossip");
+        assert!((decision.score - 0.9).abs() < 1e-5);
+
+        // An unreliable pattern whose condition fails weighs it down
+        let patterns = vec![BehaviorPattern {
+            pattern_id: uuid::Uuid::new_v4(),
+            pattern_name: "market_chatter".to_string(),
+            pattern_type: PatternType::Social,
+            behaviors_in_pattern: vec!["gossip".to_string()],
+            pattern_frequency: PatternFrequency::Daily,
+            pattern_reliability: 0.0,
+            pattern_conditions: vec![PatternCondition {
+                condition_type: "hour".to_string(),
+                condition_value: 9.0,
+                condition_operator: ConditionOperator::GreaterEqual,
+                condition_importance: 0.6,
+            }],
+            pattern_variations: Vec::new(),
+            learned_pattern: false,
+            pattern_efficiency: 1.0,
+        }];
+        facts.insert("hour".to_string(), 6.0);
+        let context = NpcContext { goals: &goals, patterns: &patterns, facts: &facts };
+        assert!((library.behaviors.read().unwrap()[0].score(&context) - 0.18).abs() < 1e-5);
+        assert_eq!(library.decide(&context, &mut rng).unwrap().behavior, "stroll");
+
+        // A failing tree falls back to the next best behavior
+        facts.insert("hour".to_string(), 10.0);
+        facts.insert("nearby_avatars".to_string(), 0.0);
+        facts.insert("distance_from_home".to_string(), 5.0);
+        let context = NpcContext { goals: &goals, patterns: &patterns, facts: &facts };
+        let decision = library.decide(&context, &mut rng).unwrap();
+        assert_eq!(decision.behavior, "stroll");
+        assert_eq!(decision.actions, vec![Action::Wander { radius: 10.0 }]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_definitions_reload_when_files_change() {
+        let dir = std::env::temp_dir().join(format!("mutsea-behaviors-{}", uuid::Uuid::new_v4()));
+        let library = library(&dir);
+        assert!(!library.reload_if_changed().unwrap());
+
+        let rest = r#"{ "behaviors": [
+            { "name": "rest", "base_score": 1.0, "tree": { "action": { "type": "interact", "object": "Bench" } } }
+        ] }"#;
+        std::fs::write(dir.join("rest.json"), rest).unwrap();
+        assert!(library.reload_if_changed().unwrap());
+        assert_eq!(library.behaviors.read().unwrap().len(), 3);
+
+        // Broken definitions leave the loaded ones in place
+        std::fs::write(dir.join("broken.toml"), "[[behaviors]]\nname = ").unwrap();
+        assert!(library.reload_if_changed().is_err());
+        assert_eq!(library.behaviors.read().unwrap().len(), 3);
+
+        let facts = HashMap::new();
+        let context = NpcContext { goals: &[], patterns: &[], facts: &facts };
+        let decision = library.decide(&context, &mut StdRng::seed_from_u64(1)).unwrap();
+        assert_eq!(decision.actions, vec![Action::Interact { object: "Bench".to_string() }]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+//! Logging public chat and instant messages for moderation
+//!
+//! When enabled, what agents say in local chat, and the IMs handed to
+//! [`ChatLogger::log_im`], are stored in the database's chat log with the
+//! parcel they were said on, unless the region's estate isn't one of the
+//! logged estates or the parcel is excluded. Logged messages older than
+//! the retention are purged, and moderators search them through the admin
+//! API.
+
+use chrono::{Duration as ChronoDuration, Utc};
+use mutsea_core::config::ChatLogConfig;
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{UserId, Vector3};
+use mutsea_database::chat_log::{ChatKind, ChatLogEntry, ChatLogQuery};
+use mutsea_database::{DatabaseManager, DatabaseResult};
+use mutsea_network::LLUDPServer;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Whether the policy in `config` logs what is said on `parcel` in a
+/// region of `estate_id`
+fn logs(config: &ChatLogConfig, estate_id: u32, parcel: Option<Uuid>) -> bool {
+    (config.estates.is_empty() || config.estates.contains(&estate_id))
+        && parcel.map_or(true, |parcel| !config.excluded_parcels.contains(&parcel))
+}
+
+/// Chat and IMs logged to the database. Clones share the database.
+#[derive(Clone)]
+pub struct ChatLogger {
+    config: ChatLogConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    database: Arc<DatabaseManager>,
+}
+
+impl ChatLogger {
+    pub fn new(config: ChatLogConfig, scene: Arc<RwLock<RegionScene>>, database: Arc<DatabaseManager>) -> Self {
+        Self {
+            config,
+            scene,
+            database,
+        }
+    }
+
+    /// Create the chat log table
+    pub async fn load(&self) {
+        if let Err(e) = self.database.initialize_chat_log().await {
+            warn!("Failed to create the chat log: {}", e);
+        }
+    }
+
+    /// Log the public chat heard on `lludp_server` and purge old messages
+    /// every `purge_interval_secs`
+    pub fn spawn(&self, lludp_server: LLUDPServer) {
+        let logger = self.clone();
+        let interval = Duration::from_secs(self.config.purge_interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let before = Utc::now() - ChronoDuration::days(logger.config.retention_days as i64);
+                match logger.database.purge_chat_log(before).await {
+                    Ok(0) => {}
+                    Ok(purged) => info!("🧹 Purged {} logged messages", purged),
+                    Err(e) => warn!("Failed to purge the chat log: {}", e),
+                }
+            }
+        });
+
+        if !self.config.log_public_chat {
+            return;
+        }
+        let logger = self.clone();
+        let mut events = lludp_server.subscribe_events();
+        tokio::spawn(async move {
+            loop {
+                let (agent_id, message) = match events.recv().await {
+                    Ok(event) => match event.event_data {
+                        NetworkEventData::AgentChatted {
+                            agent_id,
+                            channel: 0,
+                            message,
+                            ..
+                        } if !message.trim().is_empty() => (agent_id, message),
+                        _ => continue,
+                    },
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Chat log missed {} events", missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                let position = lludp_server
+                    .get_all_circuits()
+                    .await
+                    .into_iter()
+                    .find(|circuit| circuit.agent_id == Some(agent_id))
+                    .map(|circuit| circuit.position);
+                logger.log(ChatKind::Chat, agent_id, None, 0, position, message).await;
+            }
+        });
+        info!("📝 Logging public chat");
+    }
+
+    /// Log an IM from `sender` at `position` to `recipient`, if IMs are
+    /// logged
+    pub async fn log_im(&self, sender: UserId, recipient: UserId, position: Option<Vector3>, message: String) {
+        if self.config.log_ims {
+            self.log(ChatKind::Im, sender, Some(recipient), 0, position, message).await;
+        }
+    }
+
+    async fn log(
+        &self,
+        kind: ChatKind,
+        sender: UserId,
+        recipient: Option<UserId>,
+        channel: i32,
+        position: Option<Vector3>,
+        message: String,
+    ) {
+        let (region_id, parcel_id) = {
+            let scene = self.scene.read().await;
+            let parcel_id = position
+                .and_then(|position| scene.parcel_at(position.x, position.y))
+                .map(|parcel| parcel.id);
+            if !logs(&self.config, scene.info.estate_id, parcel_id) {
+                return;
+            }
+            (scene.info.region_id.as_uuid(), parcel_id)
+        };
+        let entry = ChatLogEntry {
+            id: Uuid::new_v4(),
+            kind,
+            region_id,
+            parcel_id,
+            sender_id: sender.as_uuid(),
+            recipient_id: recipient.map(|recipient| recipient.as_uuid()),
+            channel,
+            message,
+            sent_at: Utc::now(),
+        };
+        if let Err(e) = self.database.log_chat(&[entry]).await {
+            warn!("Failed to log a message from {}: {}", sender, e);
+        }
+    }
+
+    /// Logged messages matching `query`, newest first
+    pub async fn search(&self, query: &ChatLogQuery) -> DatabaseResult<Vec<ChatLogEntry>> {
+        self.database.search_chat_log(query).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estate_and_parcel_policy() {
+        let excluded = Uuid::new_v4();
+        let mut config = ChatLogConfig {
+            enabled: true,
+            excluded_parcels: vec![excluded],
+            ..Default::default()
+        };
+        assert!(logs(&config, 7, None));
+        assert!(logs(&config, 7, Some(Uuid::new_v4())));
+        assert!(!logs(&config, 7, Some(excluded)));
+
+        config.estates = vec![1];
+        assert!(logs(&config, 1, None));
+        assert!(!logs(&config, 7, None));
+    }
+}
@@ -0,0 +1,407 @@
+//! Scene checkpoints for crash recovery
+//!
+//! OAR backups are taken an hour or so apart. In between, every
+//! `checkpoint.interval_secs` the region's objects and where its NPCs stand
+//! and are walking to are written to `{checkpoint.directory}/{region}.ckpt`,
+//! and a final checkpoint is written at shutdown. A region starting up
+//! resumes from its checkpoint, so a crash loses seconds rather than
+//! everything since the last backup.
+//!
+//! A checkpoint is a 40 byte header followed by a gzipped bincode payload.
+//! The header holds, little-endian, [`MUTSEA_MAGIC`], the format version, a
+//! reserved `u16`, the time taken in Unix milliseconds, the region ID, the
+//! payload length and its CRC32. A checkpoint that fails any of these
+//! checks is set aside and the region starts without it.
+
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use mutsea_core::{config::CheckpointConfig, scene::RegionScene, RegionId, SceneObject, Vector3, MUTSEA_MAGIC};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::npc_movement::NpcMovement;
+
+/// Checkpoint format written by this version
+const FORMAT_VERSION: u16 = 1;
+
+const HEADER_LEN: usize = 40;
+
+#[derive(Debug, Error)]
+pub enum CheckpointError {
+    #[error("Checkpoint I/O: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Not a Mutsea checkpoint")]
+    BadMagic,
+    #[error("Unsupported checkpoint version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Checkpoint is of region {0}")]
+    WrongRegion(Uuid),
+    #[error("Checkpoint is truncated")]
+    Truncated,
+    #[error("Checkpoint checksum mismatch")]
+    ChecksumMismatch,
+    #[error("Invalid checkpoint payload: {0}")]
+    Payload(String),
+}
+
+/// Where an NPC was and where it was going
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NpcCheckpoint {
+    pub id: Uuid,
+    pub position: Vector3,
+    pub goal: Option<Vector3>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    objects: Vec<SceneObject>,
+    npcs: Vec<NpcCheckpoint>,
+}
+
+/// The volatile state of one region at one moment
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub region_id: RegionId,
+    pub taken_at: DateTime<Utc>,
+    pub objects: Vec<SceneObject>,
+    pub npcs: Vec<NpcCheckpoint>,
+}
+
+impl Checkpoint {
+    /// Capture `scene`, and the NPCs of `movement` if any
+    pub fn capture(scene: &RegionScene, movement: Option<&NpcMovement>, taken_at: DateTime<Utc>) -> Self {
+        Self {
+            region_id: scene.info.region_id,
+            taken_at,
+            objects: scene.objects.values().cloned().collect(),
+            npcs: movement
+                .map(|movement| {
+                    movement
+                        .npcs()
+                        .into_iter()
+                        .map(|npc| NpcCheckpoint {
+                            id: npc.id,
+                            position: npc.position,
+                            goal: npc.goal,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, CheckpointError> {
+        let payload = Payload {
+            objects: self.objects.clone(),
+            npcs: self.npcs.clone(),
+        };
+        let raw = bincode::serialize(&payload).map_err(|e| CheckpointError::Payload(e.to_string()))?;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&raw)?;
+        let compressed = encoder.finish()?;
+
+        let mut data = Vec::with_capacity(HEADER_LEN + compressed.len());
+        data.extend_from_slice(&MUTSEA_MAGIC.to_le_bytes());
+        data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&self.taken_at.timestamp_millis().to_le_bytes());
+        data.extend_from_slice(self.region_id.as_uuid().as_bytes());
+        data.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        data.extend_from_slice(&crc32fast::hash(&compressed).to_le_bytes());
+        data.extend_from_slice(&compressed);
+        Ok(data)
+    }
+
+    /// Decode a checkpoint of `region_id`, verifying its header and checksum
+    pub fn decode(data: &[u8], region_id: RegionId) -> Result<Self, CheckpointError> {
+        if data.len() < HEADER_LEN {
+            return Err(CheckpointError::Truncated);
+        }
+        let u16_at = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+
+        if u32_at(0) != MUTSEA_MAGIC {
+            return Err(CheckpointError::BadMagic);
+        }
+        let version = u16_at(4);
+        if version != FORMAT_VERSION {
+            return Err(CheckpointError::UnsupportedVersion(version));
+        }
+        let millis = i64::from_le_bytes(data[8..16].try_into().unwrap());
+        let region = Uuid::from_slice(&data[16..32]).unwrap();
+        if region != region_id.as_uuid() {
+            return Err(CheckpointError::WrongRegion(region));
+        }
+        let compressed = &data[HEADER_LEN..];
+        if compressed.len() != u32_at(32) as usize {
+            return Err(CheckpointError::Truncated);
+        }
+        if crc32fast::hash(compressed) != u32_at(36) {
+            return Err(CheckpointError::ChecksumMismatch);
+        }
+
+        let mut raw = Vec::new();
+        GzDecoder::new(compressed).read_to_end(&mut raw)?;
+        let payload: Payload = bincode::deserialize(&raw).map_err(|e| CheckpointError::Payload(e.to_string()))?;
+        Ok(Self {
+            region_id,
+            taken_at: DateTime::from_timestamp_millis(millis)
+                .ok_or_else(|| CheckpointError::Payload(format!("invalid time {}", millis)))?,
+            objects: payload.objects,
+            npcs: payload.npcs,
+        })
+    }
+
+    /// Put the checkpointed objects back in `scene`, replacing its own
+    pub fn restore_scene(&self, scene: &mut RegionScene) {
+        scene.objects = self.objects.iter().map(|object| (object.id, object.clone())).collect();
+    }
+
+    /// Put the NPCs `movement` already knows back where they were, walking
+    /// on to where they were going. Ambient NPCs are spawned anew by the
+    /// population manager, so ones it doesn't know are skipped. Returns how
+    /// many were placed.
+    pub fn restore_npcs(&self, movement: &NpcMovement) -> usize {
+        let mut placed = 0;
+        for npc in &self.npcs {
+            if movement.position(npc.id).is_none() {
+                continue;
+            }
+            movement.add_npc(npc.id, npc.position);
+            if let Some(goal) = npc.goal {
+                if let Err(e) = movement.go_to(npc.id, goal) {
+                    debug!("NPC {} can't resume walking: {}", npc.id, e);
+                }
+            }
+            placed += 1;
+        }
+        placed
+    }
+}
+
+/// Writes checkpoints of one region and recovers it from them
+pub struct Checkpointer {
+    config: CheckpointConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    movement: Option<NpcMovement>,
+    running: Arc<AtomicBool>,
+}
+
+impl Checkpointer {
+    pub fn new(config: CheckpointConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            config,
+            scene,
+            movement: None,
+            running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Also checkpoint the NPCs of `movement`
+    pub fn with_npcs(mut self, movement: NpcMovement) -> Self {
+        self.movement = Some(movement);
+        self
+    }
+
+    /// Restore the region's objects from its checkpoint, if it has a valid
+    /// one. The checkpoint is returned so NPCs can be placed once they
+    /// exist.
+    pub async fn recover(&self) -> Option<Checkpoint> {
+        let mut scene = self.scene.write().await;
+        let path = self.config.region_path(&scene.info.region_name);
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                error!("Failed to read checkpoint {:?}: {}", path, e);
+                return None;
+            }
+        };
+        match Checkpoint::decode(&data, scene.info.region_id) {
+            Ok(checkpoint) => {
+                checkpoint.restore_scene(&mut scene);
+                info!(
+                    "⏪ Recovered {} from its checkpoint of {} ({} objects)",
+                    scene.info.region_name,
+                    checkpoint.taken_at,
+                    checkpoint.objects.len()
+                );
+                Some(checkpoint)
+            }
+            Err(e) => {
+                // Keep the file for inspection instead of overwriting it
+                let corrupt = path.with_extension("ckpt.corrupt");
+                warn!("Ignoring checkpoint {:?}: {}; moved to {:?}", path, e, corrupt);
+                if let Err(e) = std::fs::rename(&path, &corrupt) {
+                    warn!("Failed to set checkpoint {:?} aside: {}", path, e);
+                }
+                None
+            }
+        }
+    }
+
+    /// Write a checkpoint now
+    pub async fn checkpoint(&self) -> Result<PathBuf, CheckpointError> {
+        // Copy under the lock so the simulation is only blocked for the copy
+        let (checkpoint, path) = {
+            let scene = self.scene.read().await;
+            let checkpoint = Checkpoint::capture(&scene, self.movement.as_ref(), Utc::now());
+            (checkpoint, self.config.region_path(&scene.info.region_name))
+        };
+
+        let write_path = path.clone();
+        tokio::task::spawn_blocking(move || write_checkpoint(&write_path, &checkpoint.encode()?))
+            .await
+            .map_err(|e| CheckpointError::Io(std::io::Error::other(e)))??;
+        debug!("Checkpointed region to {:?}", path);
+        Ok(path)
+    }
+
+    /// Checkpoint every `interval_secs` until stopped
+    pub fn start(self: &Arc<Self>) {
+        if !self.config.enabled {
+            info!("Scene checkpoints disabled");
+            return;
+        }
+        self.running.store(true, Ordering::SeqCst);
+
+        let checkpointer = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(checkpointer.config.interval_secs));
+            interval.tick().await;
+
+            while checkpointer.running.load(Ordering::SeqCst) {
+                interval.tick().await;
+                if let Err(e) = checkpointer.checkpoint().await {
+                    error!("Scene checkpoint failed: {}", e);
+                }
+            }
+        });
+        info!("📍 Scene checkpoints every {}s to {}", self.config.interval_secs, self.config.directory);
+    }
+
+    /// Stop checkpointing, writing a last checkpoint of the region as it is
+    pub async fn stop(&self) -> Result<(), CheckpointError> {
+        if self.running.swap(false, Ordering::SeqCst) {
+            self.checkpoint().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `data` to `path` so that a crash leaves either the old or the new
+/// checkpoint, never a partial one
+fn write_checkpoint(path: &Path, data: &[u8]) -> Result<(), CheckpointError> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("ckpt.tmp");
+    let mut file = std::fs::File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use mutsea_core::{config::NavigationConfig, RegionInfo, UserId};
+
+    fn prim(local_id: u32, position: Vector3) -> SceneObject {
+        SceneObject {
+            velocity: Vector3::new(0.0, 0.0, -1.0),
+            ..test_support::prim(local_id, UserId::new(), position)
+        }
+    }
+
+    fn scene() -> RegionScene {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        RegionScene::new(info, UserId::new())
+    }
+
+    #[test]
+    fn test_checkpoints_round_trip_and_are_verified() {
+        let mut scene = scene();
+        scene.add_object(prim(1, Vector3::new(10.0, 20.0, 30.0)));
+        scene.add_object(prim(2, Vector3::new(40.0, 50.0, 60.0)));
+        let checkpoint = Checkpoint::capture(&scene, None, Utc::now());
+        let data = checkpoint.encode().unwrap();
+        assert_eq!(&data[..4], &MUTSEA_MAGIC.to_le_bytes());
+
+        let mut restored = scene.clone();
+        restored.objects.clear();
+        Checkpoint::decode(&data, scene.info.region_id)
+            .unwrap()
+            .restore_scene(&mut restored);
+        let positions = |scene: &RegionScene| {
+            let mut positions: Vec<(u32, Vector3)> = scene.objects.values().map(|o| (o.local_id, o.position)).collect();
+            positions.sort_by_key(|(local_id, _)| *local_id);
+            positions
+        };
+        assert_eq!(positions(&restored), positions(&scene));
+
+        assert!(matches!(
+            Checkpoint::decode(&data, RegionId::new()),
+            Err(CheckpointError::WrongRegion(_))
+        ));
+        let mut flipped = data.clone();
+        *flipped.last_mut().unwrap() ^= 0xff;
+        assert!(matches!(
+            Checkpoint::decode(&flipped, scene.info.region_id),
+            Err(CheckpointError::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            Checkpoint::decode(&data[..data.len() - 1], scene.info.region_id),
+            Err(CheckpointError::Truncated)
+        ));
+        let mut foreign = data;
+        foreign[0] = 0;
+        assert!(matches!(
+            Checkpoint::decode(&foreign, scene.info.region_id),
+            Err(CheckpointError::BadMagic)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_regions_recover_from_their_checkpoint() {
+        let config = CheckpointConfig {
+            directory: std::env::temp_dir()
+                .join(format!("mutsea-checkpoints-{}", Uuid::new_v4()))
+                .to_string_lossy()
+                .into_owned(),
+            ..Default::default()
+        };
+        let scene = Arc::new(RwLock::new(scene()));
+        scene.write().await.add_object(prim(1, Vector3::new(10.0, 20.0, 30.0)));
+        let movement = NpcMovement::new(NavigationConfig::default(), Arc::clone(&scene)).await;
+        let npc = Uuid::new_v4();
+        movement.add_npc(npc, Vector3::new(100.5, 100.5, 21.0));
+
+        let checkpointer = Checkpointer::new(config.clone(), Arc::clone(&scene)).with_npcs(movement.clone());
+        let path = checkpointer.checkpoint().await.unwrap();
+
+        // After a crash the scene starts empty and the NPC back at its spawn
+        scene.write().await.objects.clear();
+        movement.add_npc(npc, Vector3::new(50.5, 50.5, 21.0));
+        let recovered = Checkpointer::new(config.clone(), Arc::clone(&scene)).recover().await.unwrap();
+        assert_eq!(scene.read().await.objects.len(), 1);
+        assert_eq!(recovered.restore_npcs(&movement), 1);
+        assert_eq!(movement.position(npc), Some(Vector3::new(100.5, 100.5, 21.0)));
+
+        // A damaged checkpoint is set aside
+        std::fs::write(&path, b"garbage").unwrap();
+        assert!(Checkpointer::new(config.clone(), Arc::clone(&scene)).recover().await.is_none());
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&config.directory).unwrap();
+    }
+}
@@ -0,0 +1,499 @@
+//! mutsea-server/src/cluster.rs
+//! Cluster mode: region servers under a central grid controller
+//!
+//! Region nodes register with the controller, advertising their
+//! capabilities and the regions they host, then heartbeat with their load.
+//! The controller answers both with the regions the node should host. A
+//! node that misses heartbeats for `node_timeout_secs` is dropped and its
+//! regions are handed to the least loaded live node with room; regions no
+//! node has room for wait until one registers.
+
+use mutsea_core::{config::ClusterConfig, config::GrpcConfig, scene::RegionScene};
+use mutsea_network::grpc::proto::{
+    self,
+    grid_controller_client::GridControllerClient,
+    grid_controller_server::{GridController, GridControllerServer},
+};
+use mutsea_network::LLUDPServer;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tonic::{transport::Channel, Request, Response, Status};
+use tracing::{info, warn};
+
+use crate::simulation::SimulationLoop;
+
+/// Nodes taking reassigned regions must advertise this capability
+pub const REGION_CAPABILITY: &str = "regions";
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ClusterError {
+    /// Heartbeat from a node that never registered or was dropped as dead
+    #[error("Unknown cluster node {0}")]
+    UnknownNode(String),
+}
+
+/// A node's load at its last heartbeat
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NodeLoad {
+    /// Agents on the node's regions
+    pub agents: u32,
+    /// Objects in the node's regions
+    pub objects: u32,
+    /// Average simulation frame time in milliseconds
+    pub avg_frame_ms: f64,
+}
+
+struct ClusterNode {
+    endpoint: String,
+    capabilities: Vec<String>,
+    max_regions: u32,
+    load: NodeLoad,
+    last_heartbeat: Instant,
+}
+
+impl ClusterNode {
+    fn hosts_regions(&self) -> bool {
+        self.capabilities.iter().any(|c| c == REGION_CAPABILITY)
+    }
+}
+
+#[derive(Default)]
+struct ControllerState {
+    nodes: HashMap<String, ClusterNode>,
+    /// Every known region and the node hosting it, if any
+    regions: BTreeMap<String, Option<String>>,
+}
+
+impl ControllerState {
+    fn assigned(&self, node_id: &str) -> Vec<String> {
+        self.regions
+            .iter()
+            .filter(|(_, node)| node.as_deref() == Some(node_id))
+            .map(|(region, _)| region.clone())
+            .collect()
+    }
+
+    /// The live node with room that should take another region: fewest
+    /// agents, then fewest regions
+    fn least_loaded(&self) -> Option<String> {
+        self.nodes
+            .iter()
+            .filter(|(id, node)| node.hosts_regions() && (self.assigned(id).len() as u32) < node.max_regions)
+            .min_by_key(|(id, node)| (node.load.agents, self.assigned(id).len(), (*id).clone()))
+            .map(|(id, _)| id.clone())
+    }
+
+    /// Give every unhosted region to a node with room; returns the
+    /// assignments made
+    fn assign_orphans(&mut self) -> Vec<(String, String)> {
+        let orphans: Vec<String> = self
+            .regions
+            .iter()
+            .filter(|(_, node)| node.is_none())
+            .map(|(region, _)| region.clone())
+            .collect();
+        let mut assigned = Vec::new();
+        for region in orphans {
+            let Some(node_id) = self.least_loaded() else {
+                break;
+            };
+            self.regions.insert(region.clone(), Some(node_id.clone()));
+            assigned.push((region, node_id));
+        }
+        assigned
+    }
+}
+
+/// The grid controller's view of the cluster. Clones share the view.
+#[derive(Clone)]
+pub struct ClusterController {
+    config: ClusterConfig,
+    state: Arc<Mutex<ControllerState>>,
+}
+
+impl ClusterController {
+    pub fn new(config: ClusterConfig) -> Self {
+        Self {
+            config,
+            state: Arc::new(Mutex::new(ControllerState::default())),
+        }
+    }
+
+    /// Register (or re-register) `node_id` hosting `regions`, returning the
+    /// regions it should host. Regions hosted elsewhere stay there.
+    pub fn register(
+        &self,
+        node_id: &str,
+        endpoint: &str,
+        capabilities: Vec<String>,
+        max_regions: u32,
+        regions: Vec<String>,
+        now: Instant,
+    ) -> Vec<String> {
+        let mut state = self.state.lock().unwrap();
+        state.nodes.insert(
+            node_id.to_string(),
+            ClusterNode {
+                endpoint: endpoint.to_string(),
+                capabilities,
+                max_regions,
+                load: NodeLoad::default(),
+                last_heartbeat: now,
+            },
+        );
+        for region in regions {
+            let host = state.regions.entry(region.clone()).or_insert(None);
+            match host {
+                Some(other) if other.as_str() != node_id => {
+                    warn!(
+                        "Node {} also hosts region {}, which stays on {}",
+                        node_id, region, other
+                    )
+                }
+                _ => *host = Some(node_id.to_string()),
+            }
+        }
+        for (region, node) in state.assign_orphans() {
+            info!("🗺️  Region {} assigned to node {}", region, node);
+        }
+        info!("🖧 Cluster node {} registered at {}", node_id, endpoint);
+        state.assigned(node_id)
+    }
+
+    /// Record a heartbeat from `node_id`, returning the regions it should host
+    pub fn heartbeat(&self, node_id: &str, load: NodeLoad, now: Instant) -> Result<Vec<String>, ClusterError> {
+        let mut state = self.state.lock().unwrap();
+        let node = state
+            .nodes
+            .get_mut(node_id)
+            .ok_or_else(|| ClusterError::UnknownNode(node_id.to_string()))?;
+        node.load = load;
+        node.last_heartbeat = now;
+        Ok(state.assigned(node_id))
+    }
+
+    /// Drop nodes silent for longer than the timeout and reassign their
+    /// regions; returns each dropped node's regions with their new node
+    pub fn expire(&self, now: Instant) -> Vec<(String, Option<String>)> {
+        let timeout = Duration::from_secs(self.config.node_timeout_secs);
+        let mut state = self.state.lock().unwrap();
+        let dead: Vec<String> = state
+            .nodes
+            .iter()
+            .filter(|(_, node)| now.duration_since(node.last_heartbeat) > timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+        if dead.is_empty() {
+            return Vec::new();
+        }
+
+        let mut orphaned = Vec::new();
+        for node_id in &dead {
+            warn!("🖧 Cluster node {} stopped heartbeating", node_id);
+            state.nodes.remove(node_id);
+            for region in state.assigned(node_id) {
+                state.regions.insert(region.clone(), None);
+                orphaned.push(region);
+            }
+        }
+        let assigned: HashMap<String, String> = state.assign_orphans().into_iter().collect();
+        orphaned
+            .into_iter()
+            .map(|region| {
+                let node = assigned.get(&region).cloned();
+                match &node {
+                    Some(node) => info!("🗺️  Region {} failed over to node {}", region, node),
+                    None => warn!("🗺️  Region {} has no node with room to take it", region),
+                }
+                (region, node)
+            })
+            .collect()
+    }
+
+    /// Drop dead nodes every heartbeat interval
+    pub fn spawn_sweeper(&self) {
+        let controller = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(controller.config.heartbeat_secs));
+            loop {
+                interval.tick().await;
+                controller.expire(Instant::now());
+            }
+        });
+    }
+
+    /// The gRPC service for region nodes
+    pub fn service(&self) -> GridControllerServer<Self> {
+        GridControllerServer::new(self.clone())
+    }
+}
+
+#[tonic::async_trait]
+impl GridController for ClusterController {
+    async fn register(
+        &self,
+        request: Request<proto::RegisterNodeRequest>,
+    ) -> Result<Response<proto::Assignment>, Status> {
+        let request = request.into_inner();
+        if request.node_id.is_empty() {
+            return Err(Status::invalid_argument("node_id is required"));
+        }
+        let regions = ClusterController::register(
+            self,
+            &request.node_id,
+            &request.endpoint,
+            request.capabilities,
+            request.max_regions,
+            request.regions,
+            Instant::now(),
+        );
+        Ok(Response::new(proto::Assignment { regions }))
+    }
+
+    async fn heartbeat(
+        &self,
+        request: Request<proto::HeartbeatRequest>,
+    ) -> Result<Response<proto::Assignment>, Status> {
+        let request = request.into_inner();
+        let load = request
+            .load
+            .map(|load| NodeLoad {
+                agents: load.agents,
+                objects: load.objects,
+                avg_frame_ms: load.avg_frame_ms,
+            })
+            .unwrap_or_default();
+        ClusterController::heartbeat(self, &request.node_id, load, Instant::now())
+            .map(|regions| Response::new(proto::Assignment { regions }))
+            .map_err(|e| Status::not_found(e.to_string()))
+    }
+
+    async fn list_nodes(
+        &self,
+        _request: Request<proto::ListNodesRequest>,
+    ) -> Result<Response<proto::ListNodesResponse>, Status> {
+        let state = self.state.lock().unwrap();
+        let nodes = state
+            .nodes
+            .iter()
+            .map(|(id, node)| proto::NodeStatus {
+                node_id: id.clone(),
+                endpoint: node.endpoint.clone(),
+                capabilities: node.capabilities.clone(),
+                regions: state.assigned(id),
+                load: Some(proto::NodeLoad {
+                    agents: node.load.agents,
+                    objects: node.load.objects,
+                    avg_frame_ms: node.load.avg_frame_ms,
+                }),
+                seconds_since_heartbeat: node.last_heartbeat.elapsed().as_secs(),
+            })
+            .collect();
+        let unassigned_regions = state
+            .regions
+            .iter()
+            .filter(|(_, node)| node.is_none())
+            .map(|(region, _)| region.clone())
+            .collect();
+        Ok(Response::new(proto::ListNodesResponse {
+            nodes,
+            unassigned_regions,
+        }))
+    }
+}
+
+/// A region node's membership of the cluster: registers with the
+/// controller, reports its load and follows its region assignment
+pub struct ClusterAgent {
+    config: ClusterConfig,
+    grpc: GrpcConfig,
+    node_id: String,
+    simulation: SimulationLoop,
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: LLUDPServer,
+}
+
+impl ClusterAgent {
+    pub fn new(
+        config: ClusterConfig,
+        grpc: GrpcConfig,
+        simulation: SimulationLoop,
+        scene: Arc<RwLock<RegionScene>>,
+        lludp_server: LLUDPServer,
+    ) -> Self {
+        let node_id = if config.node_id.is_empty() {
+            uuid::Uuid::new_v4().to_string()
+        } else {
+            config.node_id.clone()
+        };
+        Self {
+            config,
+            grpc,
+            node_id,
+            simulation,
+            scene,
+            lludp_server,
+        }
+    }
+
+    /// Keep the node registered, reconnecting when the controller is lost
+    pub fn spawn(self) {
+        tokio::spawn(async move {
+            let interval = Duration::from_secs(self.config.heartbeat_secs);
+            let mut hosted = Vec::new();
+            loop {
+                match mutsea_network::grpc::connect(&self.grpc, &self.config.controller_url).await {
+                    Ok(channel) => {
+                        self.run(GridControllerClient::new(channel), &mut hosted, interval)
+                            .await
+                    }
+                    Err(e) => warn!("⚠️  Grid controller unreachable: {}", e),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    /// Register and heartbeat until the controller stops answering
+    async fn run(&self, mut controller: GridControllerClient<Channel>, hosted: &mut Vec<String>, interval: Duration) {
+        let mut registered = false;
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let result = if registered {
+                let load = self.load().await;
+                controller
+                    .heartbeat(proto::HeartbeatRequest {
+                        node_id: self.node_id.clone(),
+                        load: Some(load),
+                    })
+                    .await
+            } else {
+                controller.register(self.registration().await).await
+            };
+            match result {
+                Ok(assignment) => {
+                    if !registered {
+                        info!("🖧 Joined the grid as node {}", self.node_id);
+                        registered = true;
+                    }
+                    self.follow(hosted, assignment.into_inner().regions).await;
+                }
+                // The controller dropped us as dead or restarted
+                Err(status) if status.code() == tonic::Code::NotFound => registered = false,
+                Err(status) => {
+                    warn!("⚠️  Lost the grid controller: {}", status.message());
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn registration(&self) -> proto::RegisterNodeRequest {
+        proto::RegisterNodeRequest {
+            node_id: self.node_id.clone(),
+            endpoint: self.config.advertise_url.clone(),
+            capabilities: self.config.capabilities.clone(),
+            max_regions: self.config.max_regions,
+            regions: vec![self.scene.read().await.info.region_id.to_string()],
+        }
+    }
+
+    async fn load(&self) -> proto::NodeLoad {
+        let objects = self.scene.read().await.objects.len() as u32;
+        proto::NodeLoad {
+            agents: self.lludp_server.get_authenticated_circuits_count().await as u32,
+            objects,
+            avg_frame_ms: self.simulation.get_stats().await.avg_frame_ms,
+        }
+    }
+
+    /// Log changes to the regions the controller wants hosted here
+    async fn follow(&self, hosted: &mut Vec<String>, assigned: Vec<String>) {
+        let own = self.scene.read().await.info.region_id.to_string();
+        for region in assigned.iter().filter(|region| !hosted.contains(region)) {
+            if *region != own {
+                info!(
+                    "🗺️  Region {} was failed over to this node; restore its backup to host it",
+                    region
+                );
+            }
+        }
+        if hosted.contains(&own) && !assigned.contains(&own) {
+            warn!("⚠️  Region {} is now hosted by another node", own);
+        }
+        *hosted = assigned;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controller() -> ClusterController {
+        ClusterController::new(ClusterConfig {
+            role: "controller".to_string(),
+            node_timeout_secs: 20,
+            ..Default::default()
+        })
+    }
+
+    fn register(controller: &ClusterController, node: &str, regions: &[&str], now: Instant) -> Vec<String> {
+        let regions = regions.iter().map(|r| r.to_string()).collect();
+        controller.register(node, "", vec![REGION_CAPABILITY.to_string()], 2, regions, now)
+    }
+
+    #[test]
+    fn test_regions_fail_over_to_least_loaded_node() {
+        let controller = controller();
+        let start = Instant::now();
+        assert_eq!(register(&controller, "a", &["r1", "r2"], start), vec!["r1", "r2"]);
+        assert_eq!(register(&controller, "b", &[], start), Vec::<String>::new());
+        assert_eq!(register(&controller, "c", &["r3"], start), vec!["r3"]);
+
+        let later = start + Duration::from_secs(15);
+        let busy = NodeLoad {
+            agents: 10,
+            ..Default::default()
+        };
+        controller.heartbeat("b", NodeLoad::default(), later).unwrap();
+        controller.heartbeat("c", busy, later).unwrap();
+        assert!(controller.expire(later).is_empty());
+
+        // a is dead; b takes both of its regions, c is busier
+        let failed_over = controller.expire(start + Duration::from_secs(25));
+        assert_eq!(
+            failed_over,
+            vec![
+                ("r1".to_string(), Some("b".to_string())),
+                ("r2".to_string(), Some("b".to_string()))
+            ]
+        );
+        assert_eq!(
+            controller.heartbeat("b", NodeLoad::default(), later).unwrap(),
+            vec!["r1", "r2"]
+        );
+        assert_eq!(
+            controller.heartbeat("a", NodeLoad::default(), later),
+            Err(ClusterError::UnknownNode("a".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_orphaned_regions_wait_for_room() {
+        let controller = controller();
+        let start = Instant::now();
+        register(&controller, "a", &["r1"], start);
+        assert_eq!(
+            controller.expire(start + Duration::from_secs(30)),
+            vec![("r1".to_string(), None)]
+        );
+        assert_eq!(
+            register(&controller, "b", &[], start + Duration::from_secs(31)),
+            vec!["r1"]
+        );
+    }
+}
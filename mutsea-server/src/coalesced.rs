@@ -0,0 +1,139 @@
+//! Several prims kept in one inventory item
+//!
+//! Object assets hold one prim as an OpenSim SceneObjectGroup. Prims taken
+//! or returned together are kept in one coalesced item instead, whose asset
+//! is OpenSim's CoalescedObject: the size of the box around the prims, then
+//! each prim's SceneObjectGroup with its offset from the middle of that box,
+//! so that rezzing them again keeps them where they were to one another.
+//! Assets kept as JSON by earlier versions are still read.
+
+use crate::backup::{object_xml, parse_object};
+use mutsea_core::{BoundingBox, SceneObject, Vector3};
+use std::fmt::Write as _;
+
+/// The object asset data of `objects`: the prim itself when there is one,
+/// a CoalescedObject when there are more
+pub fn encode(objects: &[SceneObject]) -> Vec<u8> {
+    if let [object] = objects {
+        return object_xml(object).into_bytes();
+    }
+    let bounds = bounding_box(objects);
+    let (center, size) = (bounds.center(), bounds.size());
+    let mut out = String::new();
+    let _ = write!(out, r#"<CoalescedObject x="{}" y="{}" z="{}">"#, size.x, size.y, size.z);
+    for object in objects {
+        let offset = object.position - center;
+        let group = format!(
+            r#"<SceneObjectGroup offsetx="{}" offsety="{}" offsetz="{}">"#,
+            offset.x, offset.y, offset.z
+        );
+        out.push_str(&object_xml(object).replacen("<SceneObjectGroup>", &group, 1));
+    }
+    out.push_str("</CoalescedObject>");
+    out.into_bytes()
+}
+
+/// The prims kept in object asset data, one or coalesced
+pub fn decode(data: &[u8]) -> Option<Vec<SceneObject>> {
+    let text = std::str::from_utf8(data).ok()?.trim_start();
+    if !text.starts_with('<') {
+        return serde_json::from_slice::<Vec<SceneObject>>(data)
+            .or_else(|_| serde_json::from_slice::<SceneObject>(data).map(|object| vec![object]))
+            .ok();
+    }
+    let doc = roxmltree::Document::parse(text).ok()?;
+    let root = doc.root_element();
+    if !root.has_tag_name("CoalescedObject") {
+        return parse_object(text).ok().map(|object| vec![object]);
+    }
+    let objects: Vec<SceneObject> = root
+        .children()
+        .filter(|node| node.has_tag_name("SceneObjectGroup"))
+        .map(|node| parse_object(&text[node.range()]).ok())
+        .collect::<Option<_>>()?;
+    (!objects.is_empty()).then_some(objects)
+}
+
+/// The box around `objects`, their sizes included
+pub fn bounding_box(objects: &[SceneObject]) -> BoundingBox {
+    let mut boxes = objects
+        .iter()
+        .map(|object| BoundingBox::from_center_size(object.position, object.scale));
+    let Some(mut bounds) = boxes.next() else {
+        return BoundingBox::new(Vector3::ZERO, Vector3::ZERO);
+    };
+    for other in boxes {
+        bounds.expand_to_include_box(&other);
+    }
+    bounds
+}
+
+/// Move `objects` together so the middle of the box around them is at
+/// `position`, keeping where they are to one another
+pub fn place(objects: &mut [SceneObject], position: Vector3) {
+    let center = bounding_box(objects).center();
+    for object in objects {
+        object.position = position + (object.position - center);
+    }
+}
+
+/// The name of a coalesced item, after its first prim
+pub fn item_name(objects: &[SceneObject]) -> String {
+    match objects {
+        [] => String::new(),
+        [object] => object.name.clone(),
+        [first, rest @ ..] => format!("{} and {} more", first.name, rest.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use mutsea_core::UserId;
+
+    fn prim(name: &str, position: Vector3) -> SceneObject {
+        SceneObject {
+            name: name.to_string(),
+            ..test_support::prim(1, UserId::new(), position)
+        }
+    }
+
+    #[test]
+    fn test_one_and_coalesced() {
+        let chair = prim("Chair", Vector3::new(10.0, 10.0, 22.0));
+        let single = encode(std::slice::from_ref(&chair));
+        assert!(single.starts_with(b"<SceneObjectGroup>"));
+        assert_eq!(decode(&single).unwrap()[0].id, chair.id);
+        let legacy = serde_json::to_vec(&chair).unwrap();
+        assert_eq!(decode(&legacy).unwrap()[0].id, chair.id);
+
+        let objects = vec![
+            chair,
+            prim("Table", Vector3::new(12.0, 10.0, 22.0)),
+            prim("Lamp", Vector3::new(12.0, 14.0, 24.0)),
+        ];
+        let data = encode(&objects);
+        let text = String::from_utf8(data.clone()).unwrap();
+        assert!(text.starts_with(r#"<CoalescedObject x="3" y="5" z="3">"#));
+        assert!(text.contains(r#"<SceneObjectGroup offsetx="-1" offsety="-2" offsetz="-1">"#));
+        let decoded = decode(&data).unwrap();
+        assert_eq!(decoded.iter().map(|o| o.id).collect::<Vec<_>>(), objects.iter().map(|o| o.id).collect::<Vec<_>>());
+        assert_eq!(decoded[2].position, Vector3::new(12.0, 14.0, 24.0));
+        assert_eq!(item_name(&objects), "Chair and 2 more");
+        assert!(decode(b"not a prim").is_none());
+        assert!(decode(b"<CoalescedObject />").is_none());
+    }
+
+    #[test]
+    fn test_place_keeps_offsets() {
+        let mut objects = vec![
+            prim("Chair", Vector3::new(10.0, 10.0, 22.0)),
+            prim("Table", Vector3::new(12.0, 10.0, 22.0)),
+        ];
+        place(&mut objects, Vector3::new(100.0, 50.0, 30.0));
+        assert_eq!(objects[0].position, Vector3::new(99.0, 50.0, 30.0));
+        assert_eq!(objects[1].position, Vector3::new(101.0, 50.0, 30.0));
+        assert_eq!(bounding_box(&objects).size(), Vector3::new(3.0, 1.0, 1.0));
+    }
+}
@@ -0,0 +1,264 @@
+//! Collision events
+//!
+//! Every frame the region's prims are checked for contacts with each
+//! other, with avatars and with the ground. Contacts that begin or end are
+//! published as object events for the scripts of the prims touched, which
+//! see them as `collision_start` and `collision_end`, or their `land_`
+//! forms. Avatars bumping into prims can also be written to the player
+//! behavior analytics as `object_collision` rows whose positions feed
+//! collision heatmaps.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use mutsea_core::config::CollisionsConfig;
+use mutsea_core::events::{Collider, ObjectEvent, ObjectEventData};
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{MutseaResult, PlayerBehaviorRecord, PlayerBehaviorRecorder, RegionId, UserId, Vector3};
+use mutsea_network::LLUDPServer;
+use mutsea_physics::{CollisionChange, CollisionTracker};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, RwLock};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::simulation::{FrameContext, SimulationSubsystem};
+
+/// Collision events buffered for slow subscribers
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// An avatar in the region: who, in which session, and where
+pub type Avatar = (UserId, Uuid, Vector3);
+
+struct State {
+    tracker: CollisionTracker,
+    /// Player collisions waiting to be written
+    pending: Vec<PlayerBehaviorRecord>,
+}
+
+/// Detects the collisions of one region. Clones share the contacts and
+/// event channel.
+#[derive(Clone)]
+pub struct Collisions {
+    config: CollisionsConfig,
+    region_id: RegionId,
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: Option<LLUDPServer>,
+    recorder: Option<Arc<dyn PlayerBehaviorRecorder>>,
+    events: broadcast::Sender<ObjectEvent>,
+    state: Arc<Mutex<State>>,
+    since_flush: Duration,
+}
+
+impl Collisions {
+    pub fn new(config: CollisionsConfig, region_id: RegionId, scene: Arc<RwLock<RegionScene>>) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self {
+            config,
+            region_id,
+            scene,
+            lludp_server: None,
+            recorder: None,
+            events,
+            state: Arc::new(Mutex::new(State {
+                tracker: CollisionTracker::new(),
+                pending: Vec::new(),
+            })),
+            since_flush: Duration::ZERO,
+        }
+    }
+
+    /// Collide prims with the avatars connected to `lludp_server`
+    pub fn with_avatars(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Write player collisions through `recorder` when the configuration
+    /// asks for them
+    pub fn with_recorder(mut self, recorder: Arc<dyn PlayerBehaviorRecorder>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Collisions beginning and ending, for the scripts of the objects
+    /// touched
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ObjectEvent> {
+        self.events.subscribe()
+    }
+
+    /// Find this frame's contacts with `avatars`, publishing those that
+    /// began or ended
+    async fn detect(&self, avatars: &[Avatar]) {
+        let positions: Vec<(UserId, Vector3)> =
+            avatars.iter().map(|(agent, _, position)| (*agent, *position)).collect();
+        let sessions: HashMap<UserId, Uuid> = avatars.iter().map(|(agent, session, _)| (*agent, *session)).collect();
+        let record_players = self.recorder.is_some() && self.config.record_player_collisions;
+
+        let scene = self.scene.read().await;
+        let mut state = self.state.lock().unwrap();
+        let State { tracker, pending } = &mut *state;
+        for change in tracker.update(&scene, &positions) {
+            let (object_id, event_data) = match change {
+                CollisionChange::Start {
+                    object,
+                    collider,
+                    position,
+                } => (object, ObjectEventData::CollisionStart { collider, position }),
+                CollisionChange::End { object, collider } => (object, ObjectEventData::CollisionEnd { collider }),
+            };
+
+            match &event_data {
+                ObjectEventData::CollisionStart {
+                    collider: Collider::Avatar(agent),
+                    position,
+                } if record_players => {
+                    let name = scene.objects.get(&object_id).map(|object| object.name.as_str());
+                    pending.push(PlayerBehaviorRecord {
+                        player_id: *agent,
+                        session_id: sessions.get(agent).copied().unwrap_or_default(),
+                        timestamp: Utc::now(),
+                        action_type: "object_collision".to_string(),
+                        action_data: json!({
+                            "object_id": object_id.0,
+                            "object_name": name,
+                            "x": position.x,
+                            "y": position.y,
+                            "z": position.z,
+                        }),
+                        context_data: json!({ "region_id": self.region_id.0 }),
+                    });
+                }
+                _ => {}
+            }
+
+            debug!("Collision of object {}: {:?}", object_id, event_data);
+            // No subscribers is fine
+            let _ = self.events.send(ObjectEvent {
+                event_id: Uuid::new_v4(),
+                timestamp: Utc::now(),
+                object_id,
+                region_id: self.region_id,
+                event_data,
+            });
+        }
+    }
+
+    /// Write the player collisions gathered so far
+    fn flush(&self) {
+        let Some(recorder) = self.recorder.clone() else {
+            return;
+        };
+        let records = std::mem::take(&mut self.state.lock().unwrap().pending);
+        if records.is_empty() {
+            return;
+        }
+        tokio::spawn(async move {
+            if let Err(e) = recorder.record_player_behaviors(&records).await {
+                warn!("Failed to record {} player collisions: {}", records.len(), e);
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl SimulationSubsystem for Collisions {
+    async fn tick(&mut self, frame: &FrameContext) -> MutseaResult<()> {
+        let avatars: Vec<Avatar> = match &self.lludp_server {
+            Some(lludp_server) => lludp_server
+                .get_all_circuits()
+                .await
+                .into_iter()
+                .filter(|circuit| circuit.authenticated)
+                .filter_map(|circuit| {
+                    Some((
+                        circuit.agent_id?,
+                        circuit.session_id.unwrap_or_default(),
+                        circuit.position,
+                    ))
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        self.detect(&avatars).await;
+
+        self.since_flush += frame.dt;
+        if self.since_flush >= Duration::from_secs(self.config.flush_interval_secs.max(1)) {
+            self.since_flush = Duration::ZERO;
+            self.flush();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::{ObjectId, RegionInfo, SceneObject};
+
+    struct NoRecorder;
+
+    #[async_trait]
+    impl PlayerBehaviorRecorder for NoRecorder {
+        async fn record_player_behaviors(&self, _records: &[PlayerBehaviorRecord]) -> MutseaResult<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_avatar_collisions_are_published_and_recorded() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let region_id = info.region_id;
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let id = ObjectId::new();
+        scene.write().await.add_object(SceneObject {
+            id,
+            position: Vector3::new(128.0, 128.0, 22.0),
+            scale: Vector3::new(2.0, 2.0, 2.0),
+            ..SceneObject::new(1, "Fountain", UserId::new())
+        });
+
+        let config = CollisionsConfig {
+            record_player_collisions: true,
+            ..CollisionsConfig::default()
+        };
+        let collisions = Collisions::new(config, region_id, scene).with_recorder(Arc::new(NoRecorder));
+        let mut events = collisions.subscribe_events();
+        let agent = UserId::new();
+        let session = Uuid::new_v4();
+
+        collisions
+            .detect(&[(agent, session, Vector3::new(126.9, 128.0, 22.0))])
+            .await;
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.object_id, id);
+        assert!(matches!(
+            event.event_data,
+            ObjectEventData::CollisionStart { collider: Collider::Avatar(a), .. } if a == agent
+        ));
+        {
+            let state = collisions.state.lock().unwrap();
+            assert_eq!(state.pending.len(), 1);
+            assert_eq!(state.pending[0].session_id, session);
+            assert_eq!(state.pending[0].action_data["object_name"], "Fountain");
+        }
+
+        // Standing against it raises nothing more; walking away ends it
+        collisions
+            .detect(&[(agent, session, Vector3::new(126.9, 128.0, 22.0))])
+            .await;
+        assert!(events.try_recv().is_err());
+        collisions
+            .detect(&[(agent, session, Vector3::new(120.0, 128.0, 22.0))])
+            .await;
+        assert!(matches!(
+            events.try_recv().unwrap().event_data,
+            ObjectEventData::CollisionEnd { .. }
+        ));
+
+        collisions.flush();
+        assert!(collisions.state.lock().unwrap().pending.is_empty());
+    }
+}
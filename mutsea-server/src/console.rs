@@ -0,0 +1,403 @@
+//! Region console commands
+//!
+//! An interactive console like OpenSim's, reached over the admin WebSocket
+//! by `mutsea console`. Each session has a current region, chosen with
+//! `change region`; commands act on it, or on every region from the root.
+
+use axum::extract::ws::{Message, WebSocket};
+use mutsea_core::scene::RegionScene;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::login::OpenSimLoginService;
+use serde::Serialize;
+use std::fmt::Write;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::backup::BackupScheduler;
+
+/// Shown to kicked agents who are not given a reason
+const DEFAULT_KICK_MESSAGE: &str = "You have been logged out by an administrator.";
+
+const HELP: &str = "\
+alert <message>                    Show an alert to every agent
+backup                             Save a snapshot of every region now
+change region <name>|root          Set the region commands act on
+force update                       Resend every object to the viewers
+help                               Show this list
+kick user <first> <last> [message] Log an agent out
+show regions                       List the regions
+show users                         List the connected agents";
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ConsoleError {
+    #[error("Unknown command '{0}'; type 'help' for the list")]
+    UnknownCommand(String),
+    #[error("Usage: {0}")]
+    Usage(&'static str),
+    #[error("No region named '{0}'")]
+    UnknownRegion(String),
+    #[error("{0} is not connected")]
+    NotConnected(String),
+    #[error("{0}")]
+    Failed(String),
+}
+
+/// One attached console and the region it is working in
+#[derive(Debug, Default)]
+pub struct ConsoleSession {
+    /// Current region name, or None at the root
+    region: Option<String>,
+}
+
+impl ConsoleSession {
+    pub fn prompt(&self) -> String {
+        format!("Region ({}) # ", self.region.as_deref().unwrap_or("root"))
+    }
+}
+
+/// What the console sends for each command
+#[derive(Debug, Serialize)]
+struct ConsoleReply {
+    output: String,
+    prompt: String,
+}
+
+pub struct RegionConsole {
+    lludp_server: LLUDPServer,
+    login_service: Arc<OpenSimLoginService>,
+    backups: Arc<BackupScheduler>,
+    regions: Vec<Arc<RwLock<RegionScene>>>,
+}
+
+impl RegionConsole {
+    pub fn new(
+        lludp_server: LLUDPServer,
+        login_service: Arc<OpenSimLoginService>,
+        backups: Arc<BackupScheduler>,
+    ) -> Self {
+        Self {
+            lludp_server,
+            login_service,
+            backups,
+            regions: Vec::new(),
+        }
+    }
+
+    pub fn add_region(&mut self, scene: Arc<RwLock<RegionScene>>) {
+        self.regions.push(scene);
+    }
+
+    /// Answer commands arriving on `socket` until it closes
+    pub async fn serve(self: Arc<Self>, mut socket: WebSocket) {
+        let mut session = ConsoleSession::default();
+        let greeting = "Mutsea region console; type 'help' for the commands".to_string();
+        if send_reply(&mut socket, greeting, &session).await.is_err() {
+            return;
+        }
+
+        while let Some(Ok(message)) = socket.recv().await {
+            let line = match message {
+                Message::Text(line) => line,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            let output = match self.execute(&mut session, &line).await {
+                Ok(output) => output,
+                Err(e) => e.to_string(),
+            };
+            if send_reply(&mut socket, output, &session).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Run one command line, returning what to print
+    pub async fn execute(&self, session: &mut ConsoleSession, line: &str) -> Result<String, ConsoleError> {
+        let words = split_command(line);
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        match words.as_slice() {
+            [] => Ok(String::new()),
+            ["help"] => Ok(HELP.to_string()),
+            ["show", "users"] => Ok(self.show_users(session).await),
+            ["show", "regions"] => Ok(self.show_regions().await),
+            ["change", "region", "root" | ".."] => {
+                session.region = None;
+                Ok(String::new())
+            }
+            ["change", "region", name @ ..] if !name.is_empty() => {
+                let name = name.join(" ");
+                let region = self
+                    .find_region(&name)
+                    .await
+                    .ok_or_else(|| ConsoleError::UnknownRegion(name.clone()))?;
+                session.region = Some(region.read().await.info.region_name.clone());
+                Ok(String::new())
+            }
+            ["change", "region"] => Err(ConsoleError::Usage("change region <name>|root")),
+            ["kick", "user", first, last, message @ ..] => {
+                let name = format!("{} {}", first, last);
+                let agent_id = self
+                    .login_service
+                    .get_user_by_name(first, last)
+                    .ok_or_else(|| ConsoleError::NotConnected(name.clone()))?;
+                let message = if message.is_empty() {
+                    DEFAULT_KICK_MESSAGE.to_string()
+                } else {
+                    message.join(" ")
+                };
+                let kicked = self
+                    .lludp_server
+                    .kick_agent(agent_id, &message)
+                    .await
+                    .map_err(|e| ConsoleError::Failed(e.to_string()))?;
+                if !kicked {
+                    return Err(ConsoleError::NotConnected(name));
+                }
+                info!("Console kicked {}", name);
+                Ok(format!("Kicked {}", name))
+            }
+            ["kick", ..] => Err(ConsoleError::Usage("kick user <first> <last> [message]")),
+            ["alert", message @ ..] if !message.is_empty() => {
+                let count = self
+                    .lludp_server
+                    .broadcast_alert(&message.join(" "))
+                    .await
+                    .map_err(|e| ConsoleError::Failed(e.to_string()))?;
+                Ok(format!("Alerted {} agents", count))
+            }
+            ["alert"] => Err(ConsoleError::Usage("alert <message>")),
+            ["force", "update"] => Ok(self.force_update(session).await),
+            ["backup"] => {
+                let paths = self
+                    .backups
+                    .snapshot_all()
+                    .await
+                    .map_err(|e| ConsoleError::Failed(e.to_string()))?;
+                Ok(paths
+                    .iter()
+                    .fold(format!("Saved {} snapshots", paths.len()), |mut output, path| {
+                        let _ = write!(output, "\n  {}", path.display());
+                        output
+                    }))
+            }
+            _ => Err(ConsoleError::UnknownCommand(line.trim().to_string())),
+        }
+    }
+
+    /// The registered region named `name`, ignoring case
+    async fn find_region(&self, name: &str) -> Option<&Arc<RwLock<RegionScene>>> {
+        for region in &self.regions {
+            if region.read().await.info.region_name.eq_ignore_ascii_case(name) {
+                return Some(region);
+            }
+        }
+        None
+    }
+
+    /// The regions `session` acts on
+    async fn selected(&self, session: &ConsoleSession) -> Vec<&Arc<RwLock<RegionScene>>> {
+        match &session.region {
+            Some(name) => self.find_region(name).await.into_iter().collect(),
+            None => self.regions.iter().collect(),
+        }
+    }
+
+    async fn show_users(&self, session: &ConsoleSession) -> String {
+        let mut regions = Vec::new();
+        for region in self.selected(session).await {
+            let scene = region.read().await;
+            regions.push((scene.info.region_id, scene.info.region_name.clone()));
+        }
+        let circuits: Vec<_> = self
+            .lludp_server
+            .get_all_circuits()
+            .await
+            .into_iter()
+            .filter(|c| c.authenticated)
+            .filter(|c| session.region.is_none() || regions.iter().any(|(id, _)| c.region_id == Some(*id)))
+            .collect();
+
+        let mut output = format!("Agents connected: {}", circuits.len());
+        if circuits.is_empty() {
+            return output;
+        }
+        let _ = write!(
+            output,
+            "\n{:<24} {:<36} {:<16} {:<20} {:>8}",
+            "Name", "Agent ID", "Region", "Position", "Online"
+        );
+        for circuit in circuits {
+            let Some(agent_id) = circuit.agent_id else {
+                continue;
+            };
+            let name = self.login_service.get_user_name(&agent_id).unwrap_or_default();
+            let region = regions
+                .iter()
+                .find(|(id, _)| circuit.region_id == Some(*id))
+                .map(|(_, name)| name.as_str())
+                .unwrap_or("-");
+            let position = format!(
+                "<{:.0}, {:.0}, {:.0}>",
+                circuit.position.x, circuit.position.y, circuit.position.z
+            );
+            let online = circuit.created_at.elapsed().as_secs();
+            let _ = write!(
+                output,
+                "\n{:<24} {:<36} {:<16} {:<20} {:>5}:{:02}",
+                name,
+                agent_id,
+                region,
+                position,
+                online / 60,
+                online % 60
+            );
+        }
+        output
+    }
+
+    async fn show_regions(&self) -> String {
+        let mut output = format!("{:<24} {:<36} {:<12} {:>8}", "Name", "Region ID", "Location", "Objects");
+        for region in &self.regions {
+            let scene = region.read().await;
+            let _ = write!(
+                output,
+                "\n{:<24} {:<36} {:<12} {:>8}",
+                scene.info.region_name,
+                scene.info.region_id,
+                format!("{},{}", scene.info.location_x, scene.info.location_y),
+                scene.objects.len()
+            );
+        }
+        output
+    }
+
+    /// Send a full update of every object in the selected regions
+    async fn force_update(&self, session: &ConsoleSession) -> String {
+        let mut objects = Vec::new();
+        for region in self.selected(session).await {
+            objects.extend(region.read().await.objects.values().cloned());
+        }
+        for object in &objects {
+            if let Err(e) = self.lludp_server.send_object_update(object).await {
+                warn!("Failed to send update of {}: {}", object.id, e);
+            }
+        }
+        format!("Sent updates of {} objects", objects.len())
+    }
+}
+
+async fn send_reply(socket: &mut WebSocket, output: String, session: &ConsoleSession) -> Result<(), axum::Error> {
+    let reply = ConsoleReply {
+        output,
+        prompt: session.prompt(),
+    };
+    let text = serde_json::to_string(&reply).unwrap_or_default();
+    socket.send(Message::Text(text)).await
+}
+
+/// Split a command line into words; double quotes keep spaces in a word
+fn split_command(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut started = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    words.push(std::mem::take(&mut word));
+                    started = false;
+                }
+            }
+            c => {
+                word.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::config::{BackupConfig, LLUDPConfig};
+    use mutsea_core::{RegionInfo, UserId};
+
+    async fn console() -> RegionConsole {
+        let config = LLUDPConfig {
+            port: 0,
+            ..Default::default()
+        };
+        let lludp_server = LLUDPServer::new(&config).await.unwrap();
+        let backups = Arc::new(BackupScheduler::new(BackupConfig::default()));
+        let mut console = RegionConsole::new(lludp_server, Arc::new(OpenSimLoginService::new()), backups);
+        for name in ["Mutsea Central", "Harbor"] {
+            let info = RegionInfo::new(name.to_string(), 1000, 1000, String::new(), String::new());
+            console.add_region(Arc::new(RwLock::new(RegionScene::new(info, UserId::new()))));
+        }
+        console
+    }
+
+    #[test]
+    fn test_split_command_keeps_quoted_words() {
+        assert_eq!(
+            split_command(r#"  change region "Mutsea Central" "#),
+            vec!["change", "region", "Mutsea Central"]
+        );
+        assert_eq!(split_command(r#"alert """#), vec!["alert", ""]);
+    }
+
+    #[tokio::test]
+    async fn test_change_region_sets_the_prompt() {
+        let console = console().await;
+        let mut session = ConsoleSession::default();
+        assert_eq!(session.prompt(), "Region (root) # ");
+
+        console
+            .execute(&mut session, "change region mutsea central")
+            .await
+            .unwrap();
+        assert_eq!(session.prompt(), "Region (Mutsea Central) # ");
+        assert_eq!(
+            console.execute(&mut session, "change region Nowhere").await,
+            Err(ConsoleError::UnknownRegion("Nowhere".to_string()))
+        );
+        console.execute(&mut session, "change region root").await.unwrap();
+        assert_eq!(session.prompt(), "Region (root) # ");
+
+        let regions = console.execute(&mut session, "show regions").await.unwrap();
+        assert!(regions.contains("Harbor"), "{}", regions);
+        assert_eq!(
+            console.execute(&mut session, "show users").await,
+            Ok("Agents connected: 0".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bad_commands_are_explained() {
+        let console = console().await;
+        let mut session = ConsoleSession::default();
+        assert_eq!(
+            console.execute(&mut session, "reboot").await,
+            Err(ConsoleError::UnknownCommand("reboot".to_string()))
+        );
+        assert_eq!(
+            console.execute(&mut session, "kick user Test").await,
+            Err(ConsoleError::Usage("kick user <first> <last> [message]"))
+        );
+        assert_eq!(
+            console.execute(&mut session, "kick user Nobody Here").await,
+            Err(ConsoleError::NotConnected("Nobody Here".to_string()))
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Script dialogs and alerts shown to agents
+//!
+//! `DialogService::dialog` shows an agent a menu of buttons and waits for
+//! the one pressed. Replies arrive as `ScriptDialogReplied` network events
+//! and are matched to the waiting call by the dialog's object ID.
+
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::UserId;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::dialog::ScriptDialog;
+use rand::Rng;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// How long agents have to answer a dialog; viewers keep them open
+/// until answered or ignored
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The button an agent pressed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DialogReply {
+    pub button_index: usize,
+    pub button_label: String,
+}
+
+#[derive(Debug, Error)]
+pub enum DialogError {
+    #[error("Agent {0} is not connected")]
+    NotConnected(UserId),
+    #[error("Invalid dialog: {0}")]
+    Invalid(String),
+    #[error("No reply within {0:?}")]
+    TimedOut(Duration),
+    #[error("Failed to reach the agent: {0}")]
+    Network(String),
+}
+
+/// A dialog waiting for its agent to press a button
+struct Waiting {
+    agent_id: UserId,
+    buttons: Vec<String>,
+    reply: oneshot::Sender<DialogReply>,
+}
+
+/// Dialogs awaiting a reply, by object ID
+#[derive(Default)]
+struct PendingDialogs {
+    waiting: Mutex<HashMap<Uuid, Waiting>>,
+}
+
+impl PendingDialogs {
+    fn wait(&self, object_id: Uuid, agent_id: UserId, buttons: Vec<String>) -> oneshot::Receiver<DialogReply> {
+        let (reply, receiver) = oneshot::channel();
+        self.waiting.lock().unwrap().insert(object_id, Waiting { agent_id, buttons, reply });
+        receiver
+    }
+
+    /// Answer the dialog if `agent_id` was asked and pressed one of its
+    /// buttons; the label comes from the dialog, not the viewer
+    fn resolve(&self, object_id: Uuid, agent_id: UserId, button_index: i32) -> bool {
+        let mut waiting = self.waiting.lock().unwrap();
+        let Some(dialog) = waiting.get(&object_id) else {
+            return false;
+        };
+        let Some(label) = usize::try_from(button_index).ok().and_then(|i| dialog.buttons.get(i)) else {
+            return false;
+        };
+        if dialog.agent_id != agent_id {
+            return false;
+        }
+
+        let reply = DialogReply {
+            button_index: button_index as usize,
+            button_label: label.clone(),
+        };
+        let dialog = waiting.remove(&object_id).unwrap();
+        dialog.reply.send(reply).is_ok()
+    }
+
+    fn cancel(&self, object_id: Uuid) {
+        self.waiting.lock().unwrap().remove(&object_id);
+    }
+}
+
+/// Shows dialogs and alerts to agents on an LLUDP server
+pub struct DialogService {
+    lludp_server: LLUDPServer,
+    /// Name dialogs are shown as coming from
+    sender_name: String,
+    timeout: Duration,
+    pending: PendingDialogs,
+}
+
+impl DialogService {
+    pub fn new(lludp_server: LLUDPServer, sender_name: &str, timeout: Duration) -> Self {
+        Self {
+            lludp_server,
+            sender_name: sender_name.to_string(),
+            timeout,
+            pending: PendingDialogs::default(),
+        }
+    }
+
+    /// Show `agent_id` a dialog and wait for the button pressed
+    pub async fn dialog(&self, agent_id: UserId, text: &str, buttons: &[&str]) -> Result<DialogReply, DialogError> {
+        let dialog = ScriptDialog {
+            object_id: Uuid::new_v4(),
+            first_name: self.sender_name.clone(),
+            last_name: String::new(),
+            object_name: self.sender_name.clone(),
+            message: text.to_string(),
+            // Negative channels can't be typed in chat
+            chat_channel: rand::thread_rng().gen_range(i32::MIN..0),
+            image_id: Uuid::nil(),
+            buttons: buttons.iter().map(|label| label.to_string()).collect(),
+            owner_id: UserId::from_uuid(Uuid::nil()),
+        };
+        dialog.validate().map_err(|e| DialogError::Invalid(e.to_string()))?;
+
+        // Wait before sending so a quick reply isn't missed
+        let reply = self.pending.wait(dialog.object_id, agent_id, dialog.buttons.clone());
+        match self.lludp_server.send_script_dialog(agent_id, &dialog).await {
+            Ok(true) => {}
+            Ok(false) => {
+                self.pending.cancel(dialog.object_id);
+                return Err(DialogError::NotConnected(agent_id));
+            }
+            Err(e) => {
+                self.pending.cancel(dialog.object_id);
+                return Err(DialogError::Network(e.to_string()));
+            }
+        }
+
+        match tokio::time::timeout(self.timeout, reply).await {
+            Ok(Ok(reply)) => Ok(reply),
+            _ => {
+                self.pending.cancel(dialog.object_id);
+                Err(DialogError::TimedOut(self.timeout))
+            }
+        }
+    }
+
+    /// Show `agent_id` an alert; modal alerts must be dismissed
+    pub async fn alert(&self, agent_id: UserId, message: &str, modal: bool) -> Result<(), DialogError> {
+        match self.lludp_server.send_agent_alert(agent_id, modal, message).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(DialogError::NotConnected(agent_id)),
+            Err(e) => Err(DialogError::Network(e.to_string())),
+        }
+    }
+
+    /// Show every connected agent an alert. Returns how many were sent.
+    pub async fn broadcast(&self, message: &str) -> Result<usize, DialogError> {
+        self.lludp_server
+            .broadcast_alert(message)
+            .await
+            .map_err(|e| DialogError::Network(e.to_string()))
+    }
+
+    /// Follow dialog replies from the LLUDP server
+    pub fn spawn(self: &Arc<Self>) {
+        let service = Arc::clone(self);
+        let mut events = self.lludp_server.subscribe_events();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        if let NetworkEventData::ScriptDialogReplied {
+                            agent_id,
+                            object_id,
+                            button_index,
+                            ..
+                        } = event.event_data
+                        {
+                            if !service.pending.resolve(object_id, agent_id, button_index) {
+                                debug!("Ignored reply from {} to dialog {}", agent_id, object_id);
+                            }
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => warn!("Dialog service missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_dialogs_resolve_only_from_asked_agent() {
+        let pending = PendingDialogs::default();
+        let agent_id = UserId::new();
+        let object_id = Uuid::new_v4();
+        let mut reply = pending.wait(object_id, agent_id, vec!["Accept".to_string(), "Decline".to_string()]);
+
+        assert!(!pending.resolve(object_id, UserId::new(), 0));
+        assert!(!pending.resolve(object_id, agent_id, 2));
+        assert!(!pending.resolve(Uuid::new_v4(), agent_id, 0));
+        assert!(reply.try_recv().is_err());
+
+        assert!(pending.resolve(object_id, agent_id, 1));
+        assert_eq!(
+            reply.try_recv().unwrap(),
+            DialogReply {
+                button_index: 1,
+                button_label: "Decline".to_string(),
+            }
+        );
+        // Each dialog is answered once
+        assert!(!pending.resolve(object_id, agent_id, 0));
+
+        let object_id = Uuid::new_v4();
+        let mut reply = pending.wait(object_id, agent_id, vec!["OK".to_string()]);
+        pending.cancel(object_id);
+        assert!(reply.try_recv().is_err());
+        assert!(!pending.resolve(object_id, agent_id, 0));
+    }
+}
@@ -0,0 +1,1127 @@
+//! Classifieds and events directory
+//!
+//! Agents place classifieds from their profile, up to a configured number
+//! each, listed for a set number of days and ordered by what was paid to
+//! list them. Events are listed through the admin API. Both are searched
+//! from the viewer's search floater, which only shows what the searching
+//! agent may see, with classified text matched by the database's
+//! full-text index when there is one, and are kept in the classifieds and events tables when
+//! there is a database. When listings are charged, the price of a
+//! classified, and any raise of it, is taken from its creator's balance,
+//! and classifieds set to renew are charged again when they expire; those
+//! that can't be paid for are removed with the rest of the expired ones.
+
+use crate::economy::{Economy, EconomyError};
+use chrono::Utc;
+use mutsea_core::config::DirectoryConfig;
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{Maturity, UserId, Vector3};
+use mutsea_database::schema::{ListedEvent, UserClassified};
+use mutsea_database::full_text;
+use mutsea_database::DatabaseManager;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::directory::{
+    Classified, DirClassifiedQuery, Event, EventSearch, EVENT_FLAG_ADULT, EVENT_FLAG_MATURE, RESULTS_PER_PAGE,
+};
+use mutsea_protocol::economy::{
+    TRANSACTION_CLASSIFIED_CHARGE, TRANSACTION_CLASSIFIED_RENEW, TRANSACTION_PARCEL_DIR_FEE,
+};
+use mutsea_protocol::login::OpenSimLoginService;
+use mutsea_protocol::search::includes_maturity;
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+/// Most classifieds a full-text search considers
+const MAX_TEXT_MATCHES: usize = 1000;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DirectoryError {
+    #[error("You can only change your own classifieds")]
+    NotCreator,
+    #[error("You can't have more than {0} classifieds")]
+    TooManyClassifieds(usize),
+    #[error("Classifieds cost at least L${0} to list")]
+    PriceTooLow(i32),
+    #[error("Classified {0} doesn't exist")]
+    UnknownClassified(Uuid),
+    #[error("{0} can't list more than {1} events")]
+    TooManyEvents(UserId, usize),
+    #[error("Event {0} doesn't exist")]
+    UnknownEvent(u32),
+    #[error("The event is already over")]
+    EventOver,
+    #[error("The listing wasn't paid for: {0}")]
+    NotPaid(String),
+    #[error("Directory unavailable: {0}")]
+    Database(String),
+}
+
+impl From<EconomyError> for DirectoryError {
+    fn from(e: EconomyError) -> Self {
+        DirectoryError::NotPaid(e.to_string())
+    }
+}
+
+/// An event to list, held in the region
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventListing {
+    pub owner_id: UserId,
+    pub name: String,
+    /// One of the viewer's event categories, 29 for miscellaneous
+    #[serde(default)]
+    pub category: u32,
+    #[serde(default)]
+    pub description: String,
+    /// Unix time the event starts
+    pub start: u32,
+    /// Minutes
+    pub duration: u32,
+    /// L$ charged at the door
+    #[serde(default)]
+    pub cover_charge: u32,
+    /// Where in the region it is held
+    pub position: Vector3,
+    /// The rating of the parcel it is held on when absent
+    #[serde(default)]
+    pub maturity: Option<Maturity>,
+}
+
+#[derive(Default)]
+struct State {
+    classifieds: HashMap<Uuid, Classified>,
+    events: BTreeMap<u32, Event>,
+}
+
+/// Listed classifieds and events. Clones share them.
+#[derive(Clone)]
+pub struct Directory {
+    config: DirectoryConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    login_service: Arc<OpenSimLoginService>,
+    lludp_server: Option<LLUDPServer>,
+    database: Option<Arc<DatabaseManager>>,
+    economy: Option<Economy>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Directory {
+    pub fn new(
+        config: DirectoryConfig,
+        scene: Arc<RwLock<RegionScene>>,
+        login_service: Arc<OpenSimLoginService>,
+    ) -> Self {
+        Self {
+            config,
+            scene,
+            login_service,
+            lludp_server: None,
+            database: None,
+            economy: None,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Answer the searches and classified edits of the agents connected to
+    /// `lludp_server`
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Keep classifieds and events in `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Charge listings to balances in `economy`, if the configuration says so
+    pub fn with_economy(mut self, economy: Economy) -> Self {
+        self.economy = Some(economy);
+        self
+    }
+
+    /// Read the classifieds and events listed in the database
+    pub async fn load(&self) {
+        let Some(database) = &self.database else {
+            return;
+        };
+        if let Err(e) = database.create_search_indexes().await {
+            warn!("Failed to create the search indexes, searching in memory: {}", e);
+        }
+        match database.get_classifieds().await {
+            Ok(rows) => {
+                let mut state = self.state.lock().unwrap();
+                for classified in rows.iter().filter_map(row_classified) {
+                    state.classifieds.insert(classified.classified_id, classified);
+                }
+            }
+            Err(e) => warn!("Failed to load classifieds: {}", e),
+        }
+        match database.get_events().await {
+            Ok(rows) => {
+                let mut state = self.state.lock().unwrap();
+                for event in rows.iter().filter_map(row_event) {
+                    state.events.insert(event.event_id, event);
+                }
+            }
+            Err(e) => warn!("Failed to load events: {}", e),
+        }
+        let state = self.state.lock().unwrap();
+        info!(
+            "📰 Directory has {} classifieds and {} events",
+            state.classifieds.len(),
+            state.events.len()
+        );
+    }
+
+    /// Handle directory requests from the LLUDP server and remove expired
+    /// listings every `expiry_interval_secs`
+    pub fn spawn(&self) {
+        let directory = self.clone();
+        let interval = Duration::from_secs(self.config.expiry_interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                directory.expire(Utc::now().timestamp() as u32).await;
+            }
+        });
+
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let directory = self.clone();
+        let mut events = lludp_server.subscribe_events();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => directory.handle(event.event_data).await,
+                    Err(RecvError::Lagged(missed)) => warn!("Directory missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle(&self, event: NetworkEventData) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let now = Utc::now().timestamp() as u32;
+        let (agent, result) = match event {
+            NetworkEventData::ClassifiedsSearched {
+                agent_id,
+                query_id,
+                text,
+                flags,
+                category,
+                start,
+                ..
+            } => {
+                let query = DirClassifiedQuery {
+                    agent_id,
+                    query_id,
+                    text,
+                    flags,
+                    category,
+                    start,
+                };
+                let classifieds = self
+                    .find_classifieds(&query, self.login_service.access_max(&agent_id), now)
+                    .await;
+                debug!("Classified search \"{}\" found {}", query.text, classifieds.len());
+                if let Err(e) = lludp_server
+                    .send_classifieds_reply(agent_id, query_id, &classifieds)
+                    .await
+                {
+                    warn!("Failed to send classified search results to {}: {}", agent_id, e);
+                }
+                return;
+            }
+            NetworkEventData::EventsSearched {
+                agent_id,
+                query_id,
+                text,
+                flags,
+                start,
+                ..
+            } => {
+                let access_max = self.login_service.access_max(&agent_id);
+                let events = self.search_events(&EventSearch::parse(&text), flags, start, access_max, now);
+                debug!("Event search \"{}\" found {}", text, events.len());
+                if let Err(e) = lludp_server.send_events_reply(agent_id, query_id, &events).await {
+                    warn!("Failed to send event search results to {}: {}", agent_id, e);
+                }
+                return;
+            }
+            NetworkEventData::AvatarClassifiedsRequested {
+                agent_id, target_id, ..
+            } => {
+                let classifieds: Vec<_> = self
+                    .classifieds_of(target_id)
+                    .into_iter()
+                    .map(|classified| (classified.classified_id, classified.name))
+                    .collect();
+                if let Err(e) = lludp_server
+                    .send_avatar_classifieds(agent_id, target_id, &classifieds)
+                    .await
+                {
+                    warn!("Failed to send the classifieds of {} to {}: {}", target_id, agent_id, e);
+                }
+                return;
+            }
+            NetworkEventData::ClassifiedInfoRequested {
+                agent_id,
+                classified_id,
+                ..
+            } => {
+                let classified = self.state.lock().unwrap().classifieds.get(&classified_id).cloned();
+                let result = match classified {
+                    Some(classified) => lludp_server.send_classified_info(agent_id, &classified).await.map(drop),
+                    None => Ok(()),
+                };
+                if let Err(e) = result {
+                    warn!("Failed to send classified {} to {}: {}", classified_id, agent_id, e);
+                }
+                return;
+            }
+            NetworkEventData::EventInfoRequested { agent_id, event_id, .. } => {
+                let event = self.state.lock().unwrap().events.get(&event_id).cloned();
+                let result = match event {
+                    Some(event) => lludp_server.send_event_info(agent_id, &event).await.map(drop),
+                    None => Ok(()),
+                };
+                if let Err(e) = result {
+                    warn!("Failed to send event {} to {}: {}", event_id, agent_id, e);
+                }
+                return;
+            }
+            NetworkEventData::ClassifiedUpdated {
+                agent_id,
+                classified_id,
+                category,
+                name,
+                description,
+                parcel_id,
+                parent_estate,
+                snapshot_id,
+                pos_global,
+                flags,
+                price_for_listing,
+                ..
+            } => {
+                let classified = Classified {
+                    classified_id,
+                    creator_id: agent_id,
+                    creation_date: 0,
+                    expiration_date: 0,
+                    category,
+                    name,
+                    description,
+                    parcel_id,
+                    parent_estate,
+                    snapshot_id,
+                    sim_name: String::new(),
+                    pos_global,
+                    parcel_name: String::new(),
+                    flags,
+                    price_for_listing,
+                };
+                (
+                    agent_id,
+                    self.update_classified(agent_id, classified, now).await.map(drop),
+                )
+            }
+            NetworkEventData::ClassifiedDeleted {
+                agent_id,
+                classified_id,
+                ..
+            } => (agent_id, self.delete_classified(agent_id, classified_id).await),
+            _ => return,
+        };
+
+        if let Err(e) = result {
+            if let Err(e) = lludp_server.send_agent_alert(agent, false, &e.to_string()).await {
+                warn!("Failed to alert agent {}: {}", agent, e);
+            }
+        }
+    }
+
+    /// The page of classifieds listed at `now` matching `query` that an
+    /// agent allowed up to `access_max` may see, the best paid first
+    pub fn search_classifieds(&self, query: &DirClassifiedQuery, access_max: Maturity, now: u32) -> Vec<Classified> {
+        let text = query.text.trim().to_lowercase();
+        self.filter_classifieds(query, access_max, now, |classified| {
+            text.is_empty()
+                || classified.name.to_lowercase().contains(&text)
+                || classified.description.to_lowercase().contains(&text)
+        })
+    }
+
+    /// Like [`search_classifieds`](Self::search_classifieds), matching the
+    /// text against the database's full-text index when there is one
+    pub async fn find_classifieds(&self, query: &DirClassifiedQuery, access_max: Maturity, now: u32) -> Vec<Classified> {
+        let text = query.text.trim();
+        if let (Some(database), false) = (&self.database, text.is_empty()) {
+            match database.search_text(&full_text::CLASSIFIEDS, text, MAX_TEXT_MATCHES).await {
+                Ok(ids) => {
+                    let ids: HashSet<Uuid> = ids.into_iter().collect();
+                    return self.filter_classifieds(query, access_max, now, |classified| {
+                        ids.contains(&classified.classified_id)
+                    });
+                }
+                Err(e) => warn!("Full-text classified search failed, matching in memory: {}", e),
+            }
+        }
+        self.search_classifieds(query, access_max, now)
+    }
+
+    fn filter_classifieds(
+        &self,
+        query: &DirClassifiedQuery,
+        access_max: Maturity,
+        now: u32,
+        matches: impl Fn(&Classified) -> bool,
+    ) -> Vec<Classified> {
+        let state = self.state.lock().unwrap();
+        let mut classifieds: Vec<_> = state
+            .classifieds
+            .values()
+            .filter(|classified| classified.expiration_date > now)
+            .filter(|classified| {
+                let maturity = classified.maturity();
+                maturity <= access_max && query.includes(maturity)
+            })
+            .filter(|classified| query.category == 0 || classified.category == query.category)
+            .filter(|classified| matches(classified))
+            .cloned()
+            .collect();
+        classifieds.sort_by(|a, b| {
+            b.price_for_listing
+                .cmp(&a.price_for_listing)
+                .then_with(|| a.creation_date.cmp(&b.creation_date))
+        });
+        classifieds
+            .into_iter()
+            .skip(query.start.max(0) as usize)
+            .take(RESULTS_PER_PAGE)
+            .collect()
+    }
+
+    /// The page of events matching `search` that an agent allowed up to
+    /// `access_max` may see, the soonest first. Searches without a day find
+    /// events not yet over at `now`.
+    pub fn search_events(
+        &self,
+        search: &EventSearch,
+        flags: u32,
+        start: i32,
+        access_max: Maturity,
+        now: u32,
+    ) -> Vec<Event> {
+        let text = search.text.to_lowercase();
+        let day = search.day.map(|day| {
+            let first = (now / SECONDS_PER_DAY) as i64 + day as i64;
+            let first = (first * SECONDS_PER_DAY as i64).max(0) as u32;
+            first..first.saturating_add(SECONDS_PER_DAY)
+        });
+        let state = self.state.lock().unwrap();
+        let mut events: Vec<_> = state
+            .events
+            .values()
+            .filter(|event| match &day {
+                Some(day) => day.contains(&event.date_utc),
+                None => event.end_utc() > now,
+            })
+            .filter(|event| {
+                let maturity = event.maturity();
+                maturity <= access_max && includes_maturity(flags, maturity)
+            })
+            .filter(|event| search.category == 0 || event.category == search.category)
+            .filter(|event| {
+                text.is_empty()
+                    || event.name.to_lowercase().contains(&text)
+                    || event.description.to_lowercase().contains(&text)
+            })
+            .cloned()
+            .collect();
+        events.sort_by_key(|event| event.date_utc);
+        events
+            .into_iter()
+            .skip(start.max(0) as usize)
+            .take(RESULTS_PER_PAGE)
+            .collect()
+    }
+
+    /// The classifieds of `creator`, the newest first
+    pub fn classifieds_of(&self, creator: UserId) -> Vec<Classified> {
+        let mut classifieds: Vec<_> = self
+            .state
+            .lock()
+            .unwrap()
+            .classifieds
+            .values()
+            .filter(|classified| classified.creator_id == creator)
+            .cloned()
+            .collect();
+        classifieds.sort_by_key(|c| Reverse(c.creation_date));
+        classifieds
+    }
+
+    /// Every event listed, the soonest first
+    pub fn events(&self) -> Vec<Event> {
+        let mut events: Vec<_> = self.state.lock().unwrap().events.values().cloned().collect();
+        events.sort_by_key(|event| event.date_utc);
+        events
+    }
+
+    /// Place or edit a classified of `agent` at `now`, naming its region and
+    /// parcel. New classifieds are listed for `listing_days` and charged
+    /// their price; edits keep their dates and are charged any raise.
+    /// Returns the classified as saved.
+    pub async fn update_classified(
+        &self,
+        agent: UserId,
+        mut classified: Classified,
+        now: u32,
+    ) -> Result<Classified, DirectoryError> {
+        if classified.creator_id != agent {
+            return Err(DirectoryError::NotCreator);
+        }
+        let (existing, listed) = {
+            let state = self.state.lock().unwrap();
+            let listed = state
+                .classifieds
+                .values()
+                .filter(|classified| classified.creator_id == agent)
+                .count();
+            (state.classifieds.get(&classified.classified_id).cloned(), listed)
+        };
+        if existing.as_ref().is_some_and(|existing| existing.creator_id != agent) {
+            return Err(DirectoryError::NotCreator);
+        }
+        if existing.is_none() && listed >= self.config.max_classifieds {
+            return Err(DirectoryError::TooManyClassifieds(self.config.max_classifieds));
+        }
+        if classified.price_for_listing < self.config.min_listing_price {
+            return Err(DirectoryError::PriceTooLow(self.config.min_listing_price));
+        }
+
+        {
+            let scene = self.scene.read().await;
+            let origin_x = scene.info.location_x as f64 * 256.0;
+            let origin_y = scene.info.location_y as f64 * 256.0;
+            let (x, y) = (classified.pos_global[0] - origin_x, classified.pos_global[1] - origin_y);
+            let in_region =
+                (0.0..scene.info.size_x as f64).contains(&x) && (0.0..scene.info.size_y as f64).contains(&y);
+            if in_region {
+                classified.sim_name = scene.info.region_name.clone();
+                if let Some(parcel) = scene.parcel_at(x as f32, y as f32) {
+                    classified.parcel_name = parcel.name.clone();
+                    classified.parcel_id = parcel.id;
+                }
+            } else if let Some(existing) = &existing {
+                classified.sim_name = existing.sim_name.clone();
+                classified.parcel_name = existing.parcel_name.clone();
+            }
+        }
+        let price = match &existing {
+            Some(existing) => {
+                classified.creation_date = existing.creation_date;
+                classified.expiration_date = existing.expiration_date;
+                classified.price_for_listing - existing.price_for_listing
+            }
+            None => {
+                classified.creation_date = now;
+                classified.expiration_date = now.saturating_add(self.config.listing_days * SECONDS_PER_DAY);
+                classified.price_for_listing
+            }
+        };
+
+        if let Some(economy) = self.charging() {
+            if price > 0 {
+                let description = format!("Listing classified \"{}\"", classified.name);
+                economy
+                    .charge(agent, price as i64, TRANSACTION_CLASSIFIED_CHARGE, &description)
+                    .await?;
+            }
+        }
+        if let Some(database) = &self.database {
+            database
+                .set_classified(&stored_classified(&classified))
+                .await
+                .map_err(|e| DirectoryError::Database(e.to_string()))?;
+        }
+        self.state
+            .lock()
+            .unwrap()
+            .classifieds
+            .insert(classified.classified_id, classified.clone());
+        debug!("Agent {} listed classified \"{}\"", agent, classified.name);
+        Ok(classified)
+    }
+
+    /// Remove the classified `classified_id` of `agent`. What was paid for
+    /// it is not given back.
+    pub async fn delete_classified(&self, agent: UserId, classified_id: Uuid) -> Result<(), DirectoryError> {
+        let creator = self
+            .state
+            .lock()
+            .unwrap()
+            .classifieds
+            .get(&classified_id)
+            .map(|classified| classified.creator_id);
+        match creator {
+            None => return Err(DirectoryError::UnknownClassified(classified_id)),
+            Some(creator) if creator != agent => return Err(DirectoryError::NotCreator),
+            Some(_) => {}
+        }
+        if let Some(database) = &self.database {
+            database
+                .remove_classified(&classified_id.to_string())
+                .await
+                .map_err(|e| DirectoryError::Database(e.to_string()))?;
+        }
+        self.state.lock().unwrap().classifieds.remove(&classified_id);
+        debug!("Agent {} removed classified {}", agent, classified_id);
+        Ok(())
+    }
+
+    /// List an event held in the region, charging its owner the event fee.
+    /// Returns the event as listed.
+    pub async fn create_event(&self, listing: EventListing, now: u32) -> Result<Event, DirectoryError> {
+        let owner = listing.owner_id;
+        let mut event = {
+            let state = self.state.lock().unwrap();
+            let listed = state
+                .events
+                .values()
+                .filter(|event| event.owner_id == owner && event.end_utc() > now)
+                .count();
+            if listed >= self.config.max_events {
+                return Err(DirectoryError::TooManyEvents(owner, self.config.max_events));
+            }
+            Event {
+                event_id: state.events.keys().next_back().map_or(1, |last| last + 1),
+                owner_id: owner,
+                name: listing.name,
+                category: listing.category,
+                description: listing.description,
+                date_utc: listing.start,
+                duration: listing.duration,
+                cover_charge: listing.cover_charge,
+                sim_name: String::new(),
+                parcel_id: Uuid::nil(),
+                pos_global: [0.0; 3],
+                flags: 0,
+            }
+        };
+        if event.end_utc() <= now {
+            return Err(DirectoryError::EventOver);
+        }
+
+        let maturity = {
+            let scene = self.scene.read().await;
+            let info = &scene.info;
+            let position = listing.position;
+            event.sim_name = info.region_name.clone();
+            event.pos_global = [
+                info.location_x as f64 * 256.0 + position.x as f64,
+                info.location_y as f64 * 256.0 + position.y as f64,
+                position.z as f64,
+            ];
+            let parcel = scene.parcel_at(position.x, position.y);
+            if let Some(parcel) = parcel {
+                event.parcel_id = parcel.id;
+            }
+            listing
+                .maturity
+                .unwrap_or_else(|| parcel.map_or(info.maturity(), |parcel| parcel.effective_maturity(info.maturity())))
+        };
+        event.flags = match maturity {
+            Maturity::Pg => 0,
+            Maturity::Mature => EVENT_FLAG_MATURE,
+            Maturity::Adult => EVENT_FLAG_ADULT,
+        };
+
+        if let Some(economy) = self.charging() {
+            if self.config.event_fee > 0 {
+                let description = format!("Listing event \"{}\"", event.name);
+                economy
+                    .charge(owner, self.config.event_fee, TRANSACTION_PARCEL_DIR_FEE, &description)
+                    .await?;
+            }
+        }
+        if let Some(database) = &self.database {
+            database
+                .set_event(&stored_event(&event))
+                .await
+                .map_err(|e| DirectoryError::Database(e.to_string()))?;
+        }
+        self.state.lock().unwrap().events.insert(event.event_id, event.clone());
+        info!("Listed event {} \"{}\" of {}", event.event_id, event.name, owner);
+        Ok(event)
+    }
+
+    /// Remove the event `event_id` from the directory
+    pub async fn delete_event(&self, event_id: u32) -> Result<(), DirectoryError> {
+        if !self.state.lock().unwrap().events.contains_key(&event_id) {
+            return Err(DirectoryError::UnknownEvent(event_id));
+        }
+        if let Some(database) = &self.database {
+            database
+                .remove_event(event_id as i64)
+                .await
+                .map_err(|e| DirectoryError::Database(e.to_string()))?;
+        }
+        self.state.lock().unwrap().events.remove(&event_id);
+        Ok(())
+    }
+
+    /// Renew the classifieds expired at `now` that are set to renew and can
+    /// be paid for, and remove the others and the events that are over
+    pub async fn expire(&self, now: u32) {
+        let (expired, over): (Vec<_>, Vec<_>) = {
+            let state = self.state.lock().unwrap();
+            (
+                state
+                    .classifieds
+                    .values()
+                    .filter(|classified| classified.expiration_date <= now)
+                    .cloned()
+                    .collect(),
+                state
+                    .events
+                    .values()
+                    .filter(|event| event.end_utc() <= now)
+                    .map(|event| event.event_id)
+                    .collect(),
+            )
+        };
+
+        for mut classified in expired {
+            if classified.auto_renew() {
+                match self.renew(&mut classified, now).await {
+                    Ok(()) => {
+                        debug!("Renewed classified \"{}\"", classified.name);
+                        self.state
+                            .lock()
+                            .unwrap()
+                            .classifieds
+                            .insert(classified.classified_id, classified);
+                        continue;
+                    }
+                    Err(e) => {
+                        let message = format!("Your classified \"{}\" expired: {}", classified.name, e);
+                        self.alert(classified.creator_id, &message).await;
+                    }
+                }
+            }
+            if let Some(database) = &self.database {
+                if let Err(e) = database.remove_classified(&classified.classified_id.to_string()).await {
+                    warn!(
+                        "Failed to remove expired classified {}: {}",
+                        classified.classified_id, e
+                    );
+                    continue;
+                }
+            }
+            self.state.lock().unwrap().classifieds.remove(&classified.classified_id);
+            debug!("Classified \"{}\" expired", classified.name);
+        }
+
+        for event_id in over {
+            if let Err(e) = self.delete_event(event_id).await {
+                warn!("Failed to remove event {}: {}", event_id, e);
+            }
+        }
+    }
+
+    /// List `classified` for another `listing_days` from `now`, charging
+    /// its price again
+    async fn renew(&self, classified: &mut Classified, now: u32) -> Result<(), DirectoryError> {
+        if let Some(economy) = self.charging() {
+            if classified.price_for_listing > 0 {
+                let description = format!("Renewing classified \"{}\"", classified.name);
+                economy
+                    .charge(
+                        classified.creator_id,
+                        classified.price_for_listing as i64,
+                        TRANSACTION_CLASSIFIED_RENEW,
+                        &description,
+                    )
+                    .await?;
+            }
+        }
+        classified.expiration_date = now.saturating_add(self.config.listing_days * SECONDS_PER_DAY);
+        if let Some(database) = &self.database {
+            database
+                .set_classified(&stored_classified(classified))
+                .await
+                .map_err(|e| DirectoryError::Database(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// The economy to charge listings to, if they are charged
+    fn charging(&self) -> Option<&Economy> {
+        self.economy.as_ref().filter(|_| self.config.charge_listings)
+    }
+
+    async fn alert(&self, agent: UserId, message: &str) {
+        if let Some(lludp_server) = &self.lludp_server {
+            if let Err(e) = lludp_server.send_agent_alert(agent, false, message).await {
+                warn!("Failed to alert agent {}: {}", agent, e);
+            }
+        }
+    }
+}
+
+fn text_uuid(value: &str) -> Uuid {
+    Uuid::parse_str(value.trim()).unwrap_or_default()
+}
+
+/// A global position in OpenSim's `<x,y,z>` vector notation
+fn vector_text([x, y, z]: [f64; 3]) -> String {
+    format!("<{},{},{}>", x, y, z)
+}
+
+fn text_vector(value: &str) -> Option<[f64; 3]> {
+    let trimmed = value.trim().trim_start_matches('<').trim_end_matches('>');
+    let coordinates: Vec<f64> = trimmed
+        .split(',')
+        .map(|part| part.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    coordinates.try_into().ok()
+}
+
+fn stored_classified(classified: &Classified) -> UserClassified {
+    UserClassified {
+        classified_id: classified.classified_id.to_string(),
+        creator_id: classified.creator_id.to_string(),
+        creation_date: classified.creation_date as i64,
+        expiration_date: classified.expiration_date as i64,
+        category: classified.category.to_string(),
+        name: classified.name.clone(),
+        description: classified.description.clone(),
+        parcel_id: classified.parcel_id.to_string(),
+        parent_estate: classified.parent_estate as i32,
+        snapshot_id: classified.snapshot_id.to_string(),
+        sim_name: classified.sim_name.clone(),
+        pos_global: vector_text(classified.pos_global),
+        parcel_name: classified.parcel_name.clone(),
+        flags: classified.flags as i32,
+        price_for_listing: classified.price_for_listing,
+    }
+}
+
+fn row_classified(row: &UserClassified) -> Option<Classified> {
+    Some(Classified {
+        classified_id: Uuid::parse_str(&row.classified_id).ok()?,
+        creator_id: UserId::from_uuid(Uuid::parse_str(&row.creator_id).ok()?),
+        creation_date: row.creation_date.clamp(0, u32::MAX as i64) as u32,
+        expiration_date: row.expiration_date.clamp(0, u32::MAX as i64) as u32,
+        category: row.category.trim().parse().unwrap_or(0),
+        name: row.name.clone(),
+        description: row.description.clone(),
+        parcel_id: text_uuid(&row.parcel_id),
+        parent_estate: row.parent_estate as u32,
+        snapshot_id: text_uuid(&row.snapshot_id),
+        sim_name: row.sim_name.clone(),
+        pos_global: text_vector(&row.pos_global)?,
+        parcel_name: row.parcel_name.clone(),
+        flags: row.flags as u8,
+        price_for_listing: row.price_for_listing,
+    })
+}
+
+fn stored_event(event: &Event) -> ListedEvent {
+    ListedEvent {
+        owner_id: event.owner_id.to_string(),
+        name: event.name.clone(),
+        event_id: event.event_id as i64,
+        creator_id: event.owner_id.to_string(),
+        category: event.category as i32,
+        description: event.description.clone(),
+        date_utc: event.date_utc as i64,
+        duration: event.duration as i32,
+        cover_charge: (event.cover_charge > 0) as i32,
+        cover_amount: event.cover_charge as i32,
+        sim_name: event.sim_name.clone(),
+        parcel_id: event.parcel_id.to_string(),
+        pos_global: vector_text(event.pos_global),
+        flags: event.flags as i32,
+    }
+}
+
+fn row_event(row: &ListedEvent) -> Option<Event> {
+    Some(Event {
+        event_id: u32::try_from(row.event_id).ok()?,
+        owner_id: UserId::from_uuid(Uuid::parse_str(&row.owner_id).ok()?),
+        name: row.name.clone(),
+        category: row.category as u32,
+        description: row.description.clone(),
+        date_utc: row.date_utc.clamp(0, u32::MAX as i64) as u32,
+        duration: row.duration.max(0) as u32,
+        cover_charge: if row.cover_charge != 0 {
+            row.cover_amount.max(0) as u32
+        } else {
+            0
+        },
+        sim_name: row.sim_name.clone(),
+        parcel_id: text_uuid(&row.parcel_id),
+        pos_global: text_vector(&row.pos_global)?,
+        flags: row.flags as u32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::config::EconomyConfig;
+    use mutsea_core::RegionInfo;
+    use mutsea_protocol::directory::{CLASSIFIED_FLAG_AUTO_RENEW, CLASSIFIED_FLAG_MATURE, CLASSIFIED_QUERY_INC_PG};
+    use mutsea_protocol::search::{DFQ_INC_MATURE, DFQ_INC_PG};
+
+    const NOW: u32 = 1_800_000_000;
+
+    fn directory(config: DirectoryConfig) -> (Directory, Economy) {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let mut scene = RegionScene::new(info, UserId::new());
+        scene.parcels[0].name = "Sunny Beach".to_string();
+        let scene = Arc::new(RwLock::new(scene));
+        let economy = Economy::new(EconomyConfig::default(), Arc::clone(&scene));
+        let directory =
+            Directory::new(config, scene, Arc::new(OpenSimLoginService::new())).with_economy(economy.clone());
+        (directory, economy)
+    }
+
+    fn classified(creator_id: UserId, name: &str, price_for_listing: i32) -> Classified {
+        Classified {
+            classified_id: Uuid::new_v4(),
+            creator_id,
+            creation_date: 0,
+            expiration_date: 0,
+            category: 1,
+            name: name.to_string(),
+            description: String::new(),
+            parcel_id: Uuid::nil(),
+            parent_estate: 1,
+            snapshot_id: Uuid::nil(),
+            sim_name: String::new(),
+            pos_global: [256000.0 + 128.0, 256000.0 + 64.0, 22.0],
+            parcel_name: String::new(),
+            flags: 0,
+            price_for_listing,
+        }
+    }
+
+    fn listing(owner_id: UserId, name: &str, start: u32) -> EventListing {
+        EventListing {
+            owner_id,
+            name: name.to_string(),
+            category: 20,
+            description: String::new(),
+            start,
+            duration: 60,
+            cover_charge: 0,
+            position: Vector3::new(128.0, 64.0, 22.0),
+            maturity: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_classifieds_are_charged_limited_and_searched() {
+        let config = DirectoryConfig {
+            max_classifieds: 2,
+            charge_listings: true,
+            ..DirectoryConfig::default()
+        };
+        let (directory, economy) = directory(config);
+        let agent = UserId::new();
+
+        let huts = directory
+            .update_classified(agent, classified(agent, "Beach huts", 100), NOW)
+            .await
+            .unwrap();
+        assert_eq!(
+            (huts.sim_name.as_str(), huts.parcel_name.as_str()),
+            ("Test", "Sunny Beach")
+        );
+        assert_eq!(huts.expiration_date, NOW + 7 * SECONDS_PER_DAY);
+        assert_eq!(economy.balance(agent).await.unwrap(), 900);
+
+        let mut raised = huts.clone();
+        raised.price_for_listing = 150;
+        directory.update_classified(agent, raised, NOW + 60).await.unwrap();
+        assert_eq!(economy.balance(agent).await.unwrap(), 850);
+
+        let mut club = classified(agent, "Club nights", 60);
+        club.flags = CLASSIFIED_FLAG_MATURE;
+        directory.update_classified(agent, club, NOW).await.unwrap();
+        assert_eq!(
+            directory
+                .update_classified(agent, classified(agent, "Rentals", 50), NOW)
+                .await,
+            Err(DirectoryError::TooManyClassifieds(2))
+        );
+        let other = UserId::new();
+        assert_eq!(
+            directory
+                .update_classified(other, classified(other, "Cheap", 10), NOW)
+                .await,
+            Err(DirectoryError::PriceTooLow(50))
+        );
+        let poor = UserId::new();
+        assert!(matches!(
+            directory
+                .update_classified(poor, classified(poor, "Mansion", 5000), NOW)
+                .await,
+            Err(DirectoryError::NotPaid(_))
+        ));
+
+        let query = |text: &str, flags: u32| DirClassifiedQuery {
+            agent_id: agent,
+            query_id: Uuid::new_v4(),
+            text: text.to_string(),
+            flags,
+            category: 0,
+            start: 0,
+        };
+        let names = |classifieds: Vec<Classified>| classifieds.into_iter().map(|c| c.name).collect::<Vec<_>>();
+        assert_eq!(
+            names(directory.search_classifieds(&query("", 0), Maturity::Adult, NOW)),
+            vec!["Beach huts", "Club nights"]
+        );
+        assert_eq!(
+            names(directory.search_classifieds(&query("", 0), Maturity::Pg, NOW)),
+            vec!["Beach huts"]
+        );
+        assert_eq!(
+            names(directory.search_classifieds(&query("club", CLASSIFIED_QUERY_INC_PG), Maturity::Adult, NOW)),
+            Vec::<String>::new()
+        );
+        assert!(directory
+            .search_classifieds(&query("", 0), Maturity::Adult, NOW + 8 * SECONDS_PER_DAY)
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_events_are_rated_searched_and_limited() {
+        let config = DirectoryConfig {
+            max_events: 2,
+            ..DirectoryConfig::default()
+        };
+        let (directory, _) = directory(config);
+        let owner = UserId::new();
+
+        let jazz = directory
+            .create_event(listing(owner, "Jazz night", NOW + 3600), NOW)
+            .await
+            .unwrap();
+        assert_eq!(jazz.pos_global, [256128.0, 256064.0, 22.0]);
+        assert_eq!(jazz.maturity(), Maturity::Pg);
+        let mut party = listing(owner, "Late party", NOW + SECONDS_PER_DAY);
+        party.maturity = Some(Maturity::Mature);
+        let party = directory.create_event(party, NOW).await.unwrap();
+        assert_eq!(party.event_id, jazz.event_id + 1);
+        assert_eq!(
+            directory.create_event(listing(owner, "Quiz", NOW + 60), NOW).await,
+            Err(DirectoryError::TooManyEvents(owner, 2))
+        );
+        assert_eq!(
+            directory
+                .create_event(listing(UserId::new(), "Yesterday", NOW - 7200), NOW)
+                .await,
+            Err(DirectoryError::EventOver)
+        );
+
+        let names = |events: Vec<Event>| events.into_iter().map(|e| e.name).collect::<Vec<_>>();
+        let search = |text: &str| EventSearch::parse(text);
+        assert_eq!(
+            names(directory.search_events(&search("u|0|"), 0, 0, Maturity::Adult, NOW)),
+            vec!["Jazz night", "Late party"]
+        );
+        assert_eq!(
+            names(directory.search_events(&search("u|0|"), DFQ_INC_PG, 0, Maturity::Adult, NOW)),
+            vec!["Jazz night"]
+        );
+        assert_eq!(
+            names(directory.search_events(&search("u|0|party"), DFQ_INC_MATURE, 0, Maturity::Pg, NOW)),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            names(directory.search_events(&search("1|20|"), 0, 0, Maturity::Adult, NOW)),
+            vec!["Late party"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expiry_renews_paid_classifieds_and_removes_the_rest() {
+        let config = DirectoryConfig {
+            charge_listings: true,
+            ..DirectoryConfig::default()
+        };
+        let (directory, economy) = directory(config);
+        let agent = UserId::new();
+        let mut renewing = classified(agent, "Beach huts", 300);
+        renewing.flags = CLASSIFIED_FLAG_AUTO_RENEW;
+        let renewing = directory.update_classified(agent, renewing, NOW).await.unwrap();
+        let mut unpaid = classified(agent, "Rentals", 500);
+        unpaid.flags = CLASSIFIED_FLAG_AUTO_RENEW;
+        directory.update_classified(agent, unpaid, NOW).await.unwrap();
+        directory
+            .update_classified(agent, classified(agent, "Club", 100), NOW)
+            .await
+            .unwrap();
+        directory
+            .create_event(listing(agent, "Jazz night", NOW), NOW)
+            .await
+            .unwrap();
+        assert_eq!(economy.balance(agent).await.unwrap(), 100);
+        economy.grant(agent, 200, "").await.unwrap();
+
+        let later = NOW + 7 * SECONDS_PER_DAY;
+        directory.expire(later).await;
+        let left = directory.classifieds_of(agent);
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].classified_id, renewing.classified_id);
+        assert_eq!(left[0].expiration_date, later + 7 * SECONDS_PER_DAY);
+        assert_eq!(economy.balance(agent).await.unwrap(), 0);
+        assert!(directory.events().is_empty());
+    }
+
+    #[test]
+    fn test_listings_round_trip_through_the_database_rows() {
+        let mut original = classified(UserId::new(), "Beach huts", 75);
+        original.pos_global = [256128.5, 256064.25, 22.0];
+        original.flags = CLASSIFIED_FLAG_AUTO_RENEW;
+        assert_eq!(row_classified(&stored_classified(&original)), Some(original));
+
+        let event = Event {
+            event_id: 7,
+            owner_id: UserId::new(),
+            name: "Jazz night".to_string(),
+            category: 20,
+            description: String::new(),
+            date_utc: NOW,
+            duration: 90,
+            cover_charge: 25,
+            sim_name: "Test".to_string(),
+            parcel_id: Uuid::new_v4(),
+            pos_global: [256128.0, 256064.0, 22.0],
+            flags: EVENT_FLAG_MATURE,
+        };
+        assert_eq!(row_event(&stored_event(&event)), Some(event));
+    }
+}
@@ -0,0 +1,594 @@
+//! Discord bridge
+//!
+//! A plugin relaying the region's chat to Discord channels and back, set
+//! up under `[plugins.settings.discord]`. Each mapped Discord channel is
+//! paired with an in-world chat channel: what agents say on it is posted
+//! to Discord, and what is said in Discord is spoken in world by a relay
+//! named after its author. Status channels also hear of logins, logouts
+//! and the grid going up or down.
+//!
+//! The bridge talks to Discord's REST API with a bot token, polling the
+//! channels it listens to. Lines for Discord are batched into at most one
+//! message per channel every `send_interval_secs`, dropping the oldest
+//! when too many queue up, and each channel may speak in world at most
+//! `max_inbound_per_minute` times a minute.
+
+use async_trait::async_trait;
+use axum::routing::get;
+use axum::{Json, Router};
+use mutsea_core::config::MutseaConfig;
+use mutsea_core::events::{NetworkEvent, NetworkEventData};
+use mutsea_core::{UserId, Vector3};
+use mutsea_network::plugin::{MutseaPlugin, PluginError, ServiceRegistry};
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::chat::ChatFromSimulator;
+use mutsea_protocol::login::OpenSimLoginService;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Longest message Discord accepts
+const DISCORD_MESSAGE_LIMIT: usize = 2000;
+
+/// Longest line of chat a viewer shows
+const CHAT_LIMIT: usize = 1023;
+
+/// Where relayed Discord messages are spoken; they are heard region-wide
+const RELAY_POSITION: Vector3 = Vector3 {
+    x: 128.0,
+    y: 128.0,
+    z: 25.0,
+};
+
+/// Source of relayed Discord messages in world
+const RELAY_ID: Uuid = Uuid::from_u128(0x6d75_7473_6561_4449_5343_4f52_4452_4c59);
+
+/// Which way a mapped channel relays
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    #[default]
+    Both,
+    ToDiscord,
+    FromDiscord,
+}
+
+/// A Discord channel paired with an in-world chat channel
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ChannelMapping {
+    pub discord_channel: String,
+    /// Only public chat, channel 0, is relayed from Discord
+    #[serde(default)]
+    pub chat_channel: i32,
+    #[serde(default)]
+    pub direction: Direction,
+    /// Also post logins, logouts and the grid going up or down
+    #[serde(default)]
+    pub status: bool,
+}
+
+impl ChannelMapping {
+    fn relays_to_discord(&self) -> bool {
+        self.direction != Direction::FromDiscord
+    }
+
+    fn relays_from_discord(&self) -> bool {
+        self.direction != Direction::ToDiscord && self.chat_channel == 0
+    }
+}
+
+/// The plugin's settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DiscordSettings {
+    /// Bot token, with permission to read and send messages in the channels
+    pub token: String,
+    pub api_url: String,
+    pub poll_interval_secs: u64,
+    pub send_interval_secs: u64,
+    pub max_inbound_per_minute: usize,
+    /// Lines kept per channel while waiting to be sent
+    pub max_queued: usize,
+    pub channels: Vec<ChannelMapping>,
+}
+
+impl Default for DiscordSettings {
+    fn default() -> Self {
+        Self {
+            token: String::new(),
+            api_url: "https://discord.com/api/v10".to_string(),
+            poll_interval_secs: 3,
+            send_interval_secs: 2,
+            max_inbound_per_minute: 20,
+            max_queued: 50,
+            channels: Vec::new(),
+        }
+    }
+}
+
+/// What the bridge has relayed
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BridgeStats {
+    pub to_discord: u64,
+    pub from_discord: u64,
+    /// Lines dropped from a full queue
+    pub dropped: u64,
+    /// Discord messages not spoken for the rate limit
+    pub throttled: u64,
+    pub send_errors: u64,
+}
+
+/// A message in a Discord channel, as the REST API returns it
+#[derive(Debug, Clone, Deserialize)]
+struct DiscordMessage {
+    id: String,
+    #[serde(default)]
+    content: String,
+    author: DiscordUser,
+    #[serde(default)]
+    webhook_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DiscordUser {
+    username: String,
+    #[serde(default)]
+    global_name: Option<String>,
+    #[serde(default)]
+    bot: bool,
+}
+
+/// The relaying decisions, apart from Discord and the region
+struct Relay {
+    settings: DiscordSettings,
+    /// Lines waiting to be sent, by Discord channel
+    outbox: Mutex<HashMap<String, VecDeque<String>>>,
+    /// When each Discord channel last spoke in world
+    inbound: Mutex<HashMap<String, VecDeque<Instant>>>,
+    stats: Mutex<BridgeStats>,
+}
+
+impl Relay {
+    fn new(settings: DiscordSettings) -> Self {
+        Self {
+            settings,
+            outbox: Mutex::new(HashMap::new()),
+            inbound: Mutex::new(HashMap::new()),
+            stats: Mutex::new(BridgeStats::default()),
+        }
+    }
+
+    /// Queue what `name` said on chat `channel` for the channels relaying it
+    fn chat(&self, channel: i32, name: &str, message: &str) {
+        let line = format!("**{}**: {}", escape_markdown(name), message);
+        for mapping in &self.settings.channels {
+            if mapping.relays_to_discord() && mapping.chat_channel == channel {
+                self.queue(&mapping.discord_channel, line.clone());
+            }
+        }
+    }
+
+    /// Queue `line` for the status channels
+    fn status(&self, line: &str) {
+        for mapping in self.settings.channels.iter().filter(|mapping| mapping.status) {
+            self.queue(&mapping.discord_channel, line.to_string());
+        }
+    }
+
+    fn queue(&self, discord_channel: &str, line: String) {
+        let mut outbox = self.outbox.lock().unwrap();
+        let lines = outbox.entry(discord_channel.to_string()).or_default();
+        lines.push_back(line);
+        if lines.len() > self.settings.max_queued {
+            lines.pop_front();
+            self.stats.lock().unwrap().dropped += 1;
+        }
+    }
+
+    /// The next message for each channel with lines waiting, as many lines
+    /// as fit in one Discord message
+    fn take_batches(&self) -> Vec<(String, String)> {
+        let mut outbox = self.outbox.lock().unwrap();
+        let mut batches = Vec::new();
+        for (channel, lines) in outbox.iter_mut() {
+            let mut batch = String::new();
+            let mut length = 0;
+            while let Some(line) = lines.front() {
+                let line: String = line.chars().take(DISCORD_MESSAGE_LIMIT).collect();
+                let line_length = line.chars().count();
+                if !batch.is_empty() {
+                    if length + 1 + line_length > DISCORD_MESSAGE_LIMIT {
+                        break;
+                    }
+                    batch.push('\n');
+                    length += 1;
+                }
+                batch.push_str(&line);
+                length += line_length;
+                lines.pop_front();
+            }
+            if !batch.is_empty() {
+                batches.push((channel.clone(), batch));
+            }
+        }
+        outbox.retain(|_, lines| !lines.is_empty());
+        batches
+    }
+
+    /// The chat to speak in world for `message`, unless it comes from a bot
+    /// or the channel has spoken too often
+    fn inbound(&self, discord_channel: &str, message: &DiscordMessage, now: Instant) -> Option<ChatFromSimulator> {
+        if message.author.bot || message.webhook_id.is_some() || message.content.trim().is_empty() {
+            return None;
+        }
+        {
+            let mut inbound = self.inbound.lock().unwrap();
+            let recent = inbound.entry(discord_channel.to_string()).or_default();
+            while recent
+                .front()
+                .is_some_and(|at| now.duration_since(*at) >= Duration::from_secs(60))
+            {
+                recent.pop_front();
+            }
+            if recent.len() >= self.settings.max_inbound_per_minute {
+                self.stats.lock().unwrap().throttled += 1;
+                return None;
+            }
+            recent.push_back(now);
+        }
+
+        let author = message
+            .author
+            .global_name
+            .as_deref()
+            .unwrap_or(&message.author.username);
+        let text: String = message.content.chars().take(CHAT_LIMIT).collect();
+        Some(ChatFromSimulator::said_by_object(
+            &format!("{} (Discord)", author),
+            RELAY_ID,
+            RELAY_POSITION,
+            &text,
+        ))
+    }
+}
+
+/// Escape what Discord would read as formatting in an avatar name
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '_' | '~' | '`' | '|' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A started bridge
+struct Bridge {
+    relay: Relay,
+    client: reqwest::Client,
+    lludp_server: LLUDPServer,
+    login_service: Option<Arc<OpenSimLoginService>>,
+    grid_name: String,
+}
+
+impl Bridge {
+    fn name(&self, agent: UserId) -> String {
+        self.login_service
+            .as_ref()
+            .and_then(|login_service| login_service.get_user_name(&agent))
+            .unwrap_or_else(|| agent.to_string())
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.relay.settings.api_url.trim_end_matches('/'), path)
+    }
+
+    /// Post `content` to `discord_channel`
+    async fn send(&self, discord_channel: &str, content: &str) -> Result<(), String> {
+        let response = self
+            .client
+            .post(self.url(&format!("/channels/{}/messages", discord_channel)))
+            .header("Authorization", format!("Bot {}", self.relay.settings.token))
+            // Chat can't ping anyone in Discord
+            .json(&json!({ "content": content, "allowed_mentions": { "parse": [] } }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("Discord answered {}", response.status()));
+        }
+        Ok(())
+    }
+
+    /// Send the lines waiting for each channel
+    async fn flush(&self) {
+        for (channel, batch) in self.relay.take_batches() {
+            let lines = batch.lines().count() as u64;
+            match self.send(&channel, &batch).await {
+                Ok(()) => self.relay.stats.lock().unwrap().to_discord += lines,
+                Err(e) => {
+                    warn!("Failed to relay chat to Discord channel {}: {}", channel, e);
+                    self.relay.stats.lock().unwrap().send_errors += 1;
+                }
+            }
+        }
+    }
+
+    /// Messages in `discord_channel` after `after`, oldest first, or the
+    /// latest one when `after` is `None`
+    async fn fetch(&self, discord_channel: &str, after: Option<&str>) -> Result<Vec<DiscordMessage>, String> {
+        let query = match after {
+            Some(after) => format!("?after={}&limit=50", after),
+            None => "?limit=1".to_string(),
+        };
+        let response = self
+            .client
+            .get(self.url(&format!("/channels/{}/messages{}", discord_channel, query)))
+            .header("Authorization", format!("Bot {}", self.relay.settings.token))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("Discord answered {}", response.status()));
+        }
+        let mut messages: Vec<DiscordMessage> = response.json().await.map_err(|e| e.to_string())?;
+        // Newest first from Discord
+        messages.reverse();
+        Ok(messages)
+    }
+
+    /// Speak what is said in `discord_channel` in world, starting from now
+    async fn poll(self: Arc<Self>, discord_channel: String) {
+        let interval = Duration::from_secs(self.relay.settings.poll_interval_secs.max(1));
+        // The first fetch only finds where to start
+        let mut after: Option<String> = None;
+        loop {
+            match self.fetch(&discord_channel, after.as_deref()).await {
+                Ok(messages) => {
+                    if after.is_some() {
+                        for message in &messages {
+                            self.speak(&discord_channel, message).await;
+                        }
+                    }
+                    match messages.last() {
+                        Some(newest) => after = Some(newest.id.clone()),
+                        None if after.is_none() => after = Some("0".to_string()),
+                        None => {}
+                    }
+                }
+                Err(e) => debug!("Failed to read Discord channel {}: {}", discord_channel, e),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    async fn speak(&self, discord_channel: &str, message: &DiscordMessage) {
+        let Some(chat) = self.relay.inbound(discord_channel, message, Instant::now()) else {
+            return;
+        };
+        match self.lludp_server.send_local_chat(&chat, f32::MAX).await {
+            Ok(_) => self.relay.stats.lock().unwrap().from_discord += 1,
+            Err(e) => warn!("Failed to relay Discord chat in world: {}", e),
+        }
+    }
+}
+
+/// Bridges chat and grid status with Discord
+#[derive(Default)]
+pub struct DiscordBridge {
+    bridge: OnceLock<Arc<Bridge>>,
+}
+
+impl DiscordBridge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl MutseaPlugin for DiscordBridge {
+    fn name(&self) -> &str {
+        "discord"
+    }
+
+    fn version(&self) -> &str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    async fn init(&self, services: &ServiceRegistry, settings: &serde_json::Value) -> Result<(), PluginError> {
+        let settings: DiscordSettings = serde_json::from_value(settings.clone())
+            .map_err(|e| PluginError::Failed(format!("Invalid Discord settings: {}", e)))?;
+        if settings.token.is_empty() || settings.channels.is_empty() {
+            return Err(PluginError::Failed(
+                "The Discord bridge needs a bot token and at least one channel".to_string(),
+            ));
+        }
+        let lludp_server = services.require::<LLUDPServer>()?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("Mutsea/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|e| PluginError::Failed(e.to_string()))?;
+        let grid_name = services
+            .get::<MutseaConfig>()
+            .map(|config| config.opensim.grid_name.clone())
+            .unwrap_or_else(|| "The grid".to_string());
+
+        let bridge = Arc::new(Bridge {
+            relay: Relay::new(settings),
+            client,
+            lludp_server: (*lludp_server).clone(),
+            login_service: services.get::<OpenSimLoginService>(),
+            grid_name,
+        });
+        if self.bridge.set(Arc::clone(&bridge)).is_err() {
+            return Err(PluginError::Failed("The Discord bridge is already running".to_string()));
+        }
+
+        bridge
+            .relay
+            .status(&format!(":green_circle: {} is up", bridge.grid_name));
+        for mapping in bridge
+            .relay
+            .settings
+            .channels
+            .iter()
+            .filter(|mapping| mapping.relays_from_discord())
+        {
+            tokio::spawn(Arc::clone(&bridge).poll(mapping.discord_channel.clone()));
+        }
+        let sender = Arc::clone(&bridge);
+        tokio::spawn(async move {
+            let interval = Duration::from_secs(sender.relay.settings.send_interval_secs.max(1));
+            loop {
+                sender.flush().await;
+                tokio::time::sleep(interval).await;
+            }
+        });
+        info!(
+            "Bridging chat with {} Discord channels",
+            bridge.relay.settings.channels.len()
+        );
+        Ok(())
+    }
+
+    fn routes(&self) -> Option<Router> {
+        let bridge = Arc::clone(self.bridge.get()?);
+        Some(Router::new().route(
+            "/status",
+            get(move || async move { Json(bridge.relay.stats.lock().unwrap().clone()) }),
+        ))
+    }
+
+    async fn on_event(&self, event: &NetworkEvent) {
+        let Some(bridge) = self.bridge.get() else {
+            return;
+        };
+        match &event.event_data {
+            NetworkEventData::AgentChatted {
+                agent_id,
+                channel,
+                message,
+                ..
+            } => bridge.relay.chat(*channel, &bridge.name(*agent_id), message),
+            NetworkEventData::AgentLoggedIn { agent_id, .. } => {
+                bridge.relay.status(&format!(
+                    ":arrow_right: {} logged in",
+                    escape_markdown(&bridge.name(*agent_id))
+                ));
+            }
+            NetworkEventData::AgentLoggedOut { agent_id, .. } => {
+                bridge.relay.status(&format!(
+                    ":arrow_left: {} logged out",
+                    escape_markdown(&bridge.name(*agent_id))
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    async fn shutdown(&self) {
+        if let Some(bridge) = self.bridge.get() {
+            bridge
+                .relay
+                .status(&format!(":red_circle: {} is going down", bridge.grid_name));
+            bridge.flush().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relay() -> Relay {
+        Relay::new(
+            serde_json::from_value(json!({
+                "token": "secret",
+                "max_inbound_per_minute": 2,
+                "max_queued": 3,
+                "channels": [
+                    { "discord_channel": "100", "status": true },
+                    { "discord_channel": "200", "chat_channel": 5, "direction": "to_discord" },
+                ],
+            }))
+            .unwrap(),
+        )
+    }
+
+    fn message(content: &str, bot: bool) -> DiscordMessage {
+        DiscordMessage {
+            id: "1".to_string(),
+            content: content.to_string(),
+            author: DiscordUser {
+                username: "ada".to_string(),
+                global_name: Some("Ada".to_string()),
+                bot,
+            },
+            webhook_id: None,
+        }
+    }
+
+    #[test]
+    fn test_chat_is_batched_per_mapped_channel() {
+        let relay = relay();
+        relay.chat(0, "Bob_Resident", "Hello");
+        relay.chat(5, "Bob", "On five");
+        relay.chat(7, "Bob", "Unmapped");
+        relay.status("Bob logged in");
+
+        let mut batches = relay.take_batches();
+        batches.sort();
+        assert_eq!(
+            batches,
+            vec![
+                (
+                    "100".to_string(),
+                    "**Bob\\_Resident**: Hello\nBob logged in".to_string()
+                ),
+                ("200".to_string(), "**Bob**: On five".to_string()),
+            ]
+        );
+        assert!(relay.take_batches().is_empty());
+
+        // A full queue drops its oldest lines
+        for i in 0..5 {
+            relay.chat(5, "Bob", &i.to_string());
+        }
+        assert_eq!(relay.take_batches()[0].1, "**Bob**: 2\n**Bob**: 3\n**Bob**: 4");
+        assert_eq!(relay.stats.lock().unwrap().dropped, 2);
+
+        // Long chat is split across messages
+        relay.chat(5, "Bob", &"a".repeat(1500));
+        relay.chat(5, "Bob", &"b".repeat(1500));
+        assert!(relay.take_batches()[0].1.ends_with('a'));
+        assert!(relay.take_batches()[0].1.ends_with('b'));
+    }
+
+    #[test]
+    fn test_discord_messages_are_spoken_within_the_rate_limit() {
+        let relay = relay();
+        let now = Instant::now();
+        assert!(relay.settings.channels[0].relays_from_discord());
+        assert!(!relay.settings.channels[1].relays_from_discord());
+
+        let chat = relay.inbound("100", &message("Hi from Discord", false), now).unwrap();
+        assert_eq!(chat.from_name, "Ada (Discord)");
+        assert_eq!(chat.message, "Hi from Discord");
+        // The bridge's own posts and other bots are not echoed back
+        assert!(relay.inbound("100", &message("**Bob**: Hello", true), now).is_none());
+
+        assert!(relay.inbound("100", &message("Two", false), now).is_some());
+        assert!(relay.inbound("100", &message("Three", false), now).is_none());
+        assert!(relay
+            .inbound("100", &message("Later", false), now + Duration::from_secs(60))
+            .is_some());
+        assert_eq!(relay.stats.lock().unwrap().throttled, 1);
+    }
+}
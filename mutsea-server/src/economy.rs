@@ -0,0 +1,825 @@
+//! The region economy
+//!
+//! Agents hold a balance, starting from the configured amount the first
+//! time they are seen. Owners put prims up for sale as the original, as a
+//! copy or for their contents; a purchase is only made at the type and
+//! price the buyer was shown, moves the price to the owner and hands over
+//! the prim with the next-owner permissions its seller chose. Payments go
+//! to avatars or to the owners of the prims paid, whose scripts hear of
+//! them as `money` events. Every sale and payment is written to the ledger
+//! when there is a database, and the agents involved are sent their new
+//! balances.
+
+use crate::limits::{LimitError, Limits};
+use chrono::Utc;
+use mutsea_core::config::EconomyConfig;
+use mutsea_core::events::{NetworkEventData, ObjectEvent, ObjectEventData};
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{ObjectId, UserId};
+use mutsea_database::economy::{self, LedgerTransaction};
+use mutsea_database::DatabaseManager;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::economy::{SaleType, TRANSACTION_GIFT, TRANSACTION_OBJECT_SALE, TRANSACTION_PAY_OBJECT};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, RwLock};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Money events buffered for slow subscribers
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// The owner may give the prim away or sell it
+pub const PERM_TRANSFER: u32 = 1 << 13;
+/// The owner may copy the prim
+pub const PERM_COPY: u32 = 1 << 15;
+/// Every permission
+pub const PERM_ALL: u32 = 0x7FFF_FFFF;
+
+#[derive(Debug, Error)]
+pub enum EconomyError {
+    #[error("Unknown object {0}")]
+    UnknownObject(ObjectId),
+    #[error("No object has local ID {0}")]
+    UnknownLocalId(u32),
+    #[error("Agent {0} doesn't own object {1}")]
+    NotOwner(UserId, ObjectId),
+    #[error("Object {0} is not for sale")]
+    NotForSale(ObjectId),
+    #[error("The sale of object {0} has changed")]
+    SaleChanged(ObjectId),
+    #[error("Unknown sale type {0}")]
+    UnknownSaleType(u8),
+    #[error("Object {0} can't be sold {1}")]
+    NoPermission(ObjectId, &'static str),
+    #[error("You already own object {0}")]
+    OwnObject(ObjectId),
+    #[error("L${0} is not an amount that can be paid")]
+    InvalidAmount(i64),
+    #[error("You can't pay yourself")]
+    SelfPayment,
+    #[error("Insufficient funds: L${amount} needed, L${balance} held")]
+    InsufficientFunds { balance: i64, amount: i64 },
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+    #[error("Ledger unavailable: {0}")]
+    Database(String),
+}
+
+/// How a prim is for sale
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SaleInfo {
+    pub sale_type: SaleType,
+    pub price: i32,
+}
+
+/// What the owner of a prim may do with it, and what its next owner will
+/// be able to
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Permissions {
+    pub owner: u32,
+    pub next_owner: u32,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self {
+            owner: PERM_ALL,
+            next_owner: PERM_ALL,
+        }
+    }
+}
+
+/// A prim bought
+#[derive(Debug, Clone, Serialize)]
+pub struct Purchase {
+    pub transaction_id: Uuid,
+    /// The prim the buyer now owns: the original, or its copy; the prim
+    /// sold for contents
+    pub object: ObjectId,
+    pub sale_type: SaleType,
+    pub price: i32,
+    pub permissions: Permissions,
+    /// The buyer's balance afterwards
+    pub balance: i64,
+}
+
+/// A payment made
+#[derive(Debug, Clone, Serialize)]
+pub struct Payment {
+    pub transaction_id: Uuid,
+    pub payee: UserId,
+    /// The prim paid, if one was
+    pub object: Option<ObjectId>,
+    pub amount: i32,
+    /// The payer's balance afterwards
+    pub balance: i64,
+}
+
+#[derive(Default)]
+struct State {
+    balances: HashMap<UserId, i64>,
+    sales: HashMap<ObjectId, SaleInfo>,
+    /// Prims without an entry have every permission
+    permissions: HashMap<ObjectId, Permissions>,
+}
+
+impl State {
+    /// Move `amount` from `from` to `to`, both with loaded balances
+    fn transfer(&mut self, from: UserId, to: UserId, amount: i64) -> Result<(), EconomyError> {
+        let balance = self.balances[&from];
+        if balance < amount {
+            return Err(EconomyError::InsufficientFunds { balance, amount });
+        }
+        *self.balances.get_mut(&from).unwrap() -= amount;
+        *self.balances.get_mut(&to).unwrap() += amount;
+        Ok(())
+    }
+}
+
+/// The balances, sales and payments of one region. Clones share them.
+#[derive(Clone)]
+pub struct Economy {
+    config: EconomyConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: Option<LLUDPServer>,
+    database: Option<Arc<DatabaseManager>>,
+    limits: Option<Limits>,
+    events: broadcast::Sender<ObjectEvent>,
+    transactions: broadcast::Sender<LedgerTransaction>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Economy {
+    pub fn new(config: EconomyConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let (transactions, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self {
+            config,
+            scene,
+            lludp_server: None,
+            database: None,
+            limits: None,
+            events,
+            transactions,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Tell the agents connected to `lludp_server` their balances
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Keep balances and write transactions in `database`'s ledger
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Refuse to sell copies that would put the parcel or region over its
+    /// prim limit
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Payments to prims, for their scripts' `money` events
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ObjectEvent> {
+        self.events.subscribe()
+    }
+
+    /// Every sale and payment made, as written to the ledger
+    pub fn subscribe_transactions(&self) -> broadcast::Receiver<LedgerTransaction> {
+        self.transactions.subscribe()
+    }
+
+    /// The balance of `agent`
+    pub async fn balance(&self, agent: UserId) -> Result<i64, EconomyError> {
+        self.load_account(agent).await?;
+        Ok(self.state.lock().unwrap().balances[&agent])
+    }
+
+    /// Add `amount` from the grid to the balance of `agent`
+    pub async fn grant(&self, agent: UserId, amount: i64, description: &str) -> Result<i64, EconomyError> {
+        if amount <= 0 {
+            return Err(EconomyError::InvalidAmount(amount));
+        }
+        self.load_account(agent).await?;
+        let balance = {
+            let mut state = self.state.lock().unwrap();
+            let balance = state.balances.get_mut(&agent).unwrap();
+            *balance += amount;
+            *balance
+        };
+        let transaction = LedgerTransaction {
+            id: Uuid::new_v4(),
+            source_id: None,
+            destination_id: Some(agent.as_uuid()),
+            amount,
+            transaction_type: 0,
+            object_id: None,
+            description: description.to_string(),
+        };
+        self.record(&transaction, &[(agent, balance)]).await;
+        self.notify(agent, transaction.id, true, description).await;
+        Ok(balance)
+    }
+
+    /// Take `amount` from the balance of `agent` for the grid, such as the
+    /// price of a listing
+    pub async fn charge(
+        &self,
+        agent: UserId,
+        amount: i64,
+        transaction_type: i32,
+        description: &str,
+    ) -> Result<i64, EconomyError> {
+        if amount <= 0 {
+            return Err(EconomyError::InvalidAmount(amount));
+        }
+        self.load_account(agent).await?;
+        let balance = {
+            let mut state = self.state.lock().unwrap();
+            let balance = state.balances.get_mut(&agent).unwrap();
+            if *balance < amount {
+                return Err(EconomyError::InsufficientFunds {
+                    balance: *balance,
+                    amount,
+                });
+            }
+            *balance -= amount;
+            *balance
+        };
+        let transaction = LedgerTransaction {
+            id: Uuid::new_v4(),
+            source_id: Some(agent.as_uuid()),
+            destination_id: None,
+            amount,
+            transaction_type,
+            object_id: None,
+            description: description.to_string(),
+        };
+        self.record(&transaction, &[(agent, balance)]).await;
+        self.notify(agent, transaction.id, true, description).await;
+        Ok(balance)
+    }
+
+    /// How `object` is for sale, if it is
+    pub fn sale(&self, object: ObjectId) -> Option<SaleInfo> {
+        self.state.lock().unwrap().sales.get(&object).copied()
+    }
+
+    /// The permissions of `object`
+    pub fn permissions(&self, object: ObjectId) -> Permissions {
+        self.state
+            .lock()
+            .unwrap()
+            .permissions
+            .get(&object)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Set what the next owner of `object` may do with it
+    pub async fn set_next_owner_permissions(&self, object: ObjectId, next_owner: u32) -> Result<(), EconomyError> {
+        if !self.scene.read().await.objects.contains_key(&object) {
+            return Err(EconomyError::UnknownObject(object));
+        }
+        let mut state = self.state.lock().unwrap();
+        let permissions = state.permissions.entry(object).or_default();
+        // Nobody can be given more than the owner has
+        permissions.next_owner = next_owner & permissions.owner;
+        Ok(())
+    }
+
+    /// Put `object` up for sale, or take it off sale with `SaleType::Not`
+    pub async fn set_sale(&self, object: ObjectId, sale: SaleInfo) -> Result<(), EconomyError> {
+        if !self.scene.read().await.objects.contains_key(&object) {
+            return Err(EconomyError::UnknownObject(object));
+        }
+        let mut state = self.state.lock().unwrap();
+        if sale.sale_type == SaleType::Not {
+            state.sales.remove(&object);
+            return Ok(());
+        }
+        if !(0..=self.config.max_price).contains(&sale.price) {
+            return Err(EconomyError::InvalidAmount(sale.price as i64));
+        }
+
+        let permissions = state.permissions.get(&object).copied().unwrap_or_default();
+        if permissions.owner & PERM_TRANSFER == 0 {
+            return Err(EconomyError::NoPermission(object, "without transfer permission"));
+        }
+        if sale.sale_type == SaleType::Copy && permissions.owner & PERM_COPY == 0 {
+            return Err(EconomyError::NoPermission(object, "as a copy without copy permission"));
+        }
+        state.sales.insert(object, sale);
+        info!(
+            "Object {} is for sale as {:?} for L${}",
+            object, sale.sale_type, sale.price
+        );
+        Ok(())
+    }
+
+    /// `buyer` buys `object` as `sale_type` for `price`, which must be how
+    /// it is for sale
+    pub async fn buy(
+        &self,
+        buyer: UserId,
+        object: ObjectId,
+        sale_type: SaleType,
+        price: i32,
+    ) -> Result<Purchase, EconomyError> {
+        let seller = self.owner_of(object).await?;
+        if seller == buyer {
+            return Err(EconomyError::OwnObject(object));
+        }
+        self.load_account(buyer).await?;
+        self.load_account(seller).await?;
+
+        let mut scene = self.scene.write().await;
+        let original = scene.objects.get(&object).ok_or(EconomyError::UnknownObject(object))?;
+        let name = original.name.clone();
+        if let (SaleType::Copy, Some(limits)) = (sale_type, &self.limits) {
+            limits.check_prims(&scene, original.position, 1)?;
+        }
+        let (purchase, balances) = {
+            let mut state = self.state.lock().unwrap();
+            let sale = state
+                .sales
+                .get(&object)
+                .copied()
+                .ok_or(EconomyError::NotForSale(object))?;
+            // Sold to someone else while this buyer looked
+            if original.owner_id != seller || sale.sale_type != sale_type || sale.price != price {
+                return Err(EconomyError::SaleChanged(object));
+            }
+            state.transfer(buyer, seller, price as i64)?;
+
+            let sold = state.permissions.get(&object).copied().unwrap_or_default();
+            let permissions = Permissions {
+                owner: sold.next_owner,
+                next_owner: sold.next_owner,
+            };
+            let bought = match sale_type {
+                SaleType::Original => {
+                    state.sales.remove(&object);
+                    state.permissions.insert(object, permissions);
+                    object
+                }
+                SaleType::Copy => {
+                    let copy = ObjectId::new();
+                    state.permissions.insert(copy, permissions);
+                    copy
+                }
+                SaleType::Contents | SaleType::Not => object,
+            };
+            let balances = [(buyer, state.balances[&buyer]), (seller, state.balances[&seller])];
+            let purchase = Purchase {
+                transaction_id: Uuid::new_v4(),
+                object: bought,
+                sale_type,
+                price,
+                permissions,
+                balance: balances[0].1,
+            };
+            (purchase, balances)
+        };
+
+        match sale_type {
+            SaleType::Original => {
+                let original = scene.objects.get_mut(&object).unwrap();
+                original.owner_id = buyer;
+                original.last_updated = Utc::now();
+            }
+            SaleType::Copy => {
+                let mut copy = scene.objects[&object].clone();
+                copy.id = purchase.object;
+                copy.local_id = scene.objects.values().map(|object| object.local_id).max().unwrap_or(0) + 1;
+                copy.owner_id = buyer;
+                copy.created = Utc::now();
+                copy.last_updated = copy.created;
+                scene.add_object(copy);
+            }
+            // The contents are given from the prim's inventory, which the
+            // scene doesn't keep
+            SaleType::Contents | SaleType::Not => {}
+        }
+        drop(scene);
+
+        info!(
+            "Agent {} bought object {} ({:?}) for L${}",
+            buyer, object, sale_type, price
+        );
+        let transaction = LedgerTransaction {
+            id: purchase.transaction_id,
+            source_id: Some(buyer.as_uuid()),
+            destination_id: Some(seller.as_uuid()),
+            amount: price as i64,
+            transaction_type: TRANSACTION_OBJECT_SALE,
+            object_id: Some(object.0),
+            description: format!("Bought {}", name),
+        };
+        self.record(&transaction, &balances).await;
+        self.notify(
+            buyer,
+            transaction.id,
+            true,
+            &format!("You paid L${} for {}", price, name),
+        )
+        .await;
+        self.notify(
+            seller,
+            transaction.id,
+            true,
+            &format!("You were paid L${} for {}", price, name),
+        )
+        .await;
+        Ok(purchase)
+    }
+
+    /// `payer` pays `amount` to the avatar or prim `destination`; a prim's
+    /// owner is paid and its scripts told
+    pub async fn pay(
+        &self,
+        payer: UserId,
+        destination: Uuid,
+        amount: i32,
+        description: &str,
+    ) -> Result<Payment, EconomyError> {
+        if amount <= 0 || amount > self.config.max_price {
+            return Err(EconomyError::InvalidAmount(amount as i64));
+        }
+        let object = ObjectId::from_uuid(destination);
+        let (payee, object) = match self.owner_of(object).await {
+            Ok(owner) => (owner, Some(object)),
+            Err(_) => (UserId::from_uuid(destination), None),
+        };
+        if payee == payer {
+            return Err(EconomyError::SelfPayment);
+        }
+        self.load_account(payer).await?;
+        self.load_account(payee).await?;
+
+        let balances = {
+            let mut state = self.state.lock().unwrap();
+            state.transfer(payer, payee, amount as i64)?;
+            [(payer, state.balances[&payer]), (payee, state.balances[&payee])]
+        };
+        let payment = Payment {
+            transaction_id: Uuid::new_v4(),
+            payee,
+            object,
+            amount,
+            balance: balances[0].1,
+        };
+
+        if let Some(object_id) = object {
+            let region_id = self.scene.read().await.info.region_id;
+            // No subscribers is fine
+            let _ = self.events.send(ObjectEvent {
+                event_id: Uuid::new_v4(),
+                timestamp: Utc::now(),
+                object_id,
+                region_id,
+                event_data: ObjectEventData::Money {
+                    payer_id: payer,
+                    amount,
+                },
+            });
+        }
+
+        debug!("Agent {} paid {} L${}", payer, destination, amount);
+        let transaction = LedgerTransaction {
+            id: payment.transaction_id,
+            source_id: Some(payer.as_uuid()),
+            destination_id: Some(payee.as_uuid()),
+            amount: amount as i64,
+            transaction_type: if object.is_some() {
+                TRANSACTION_PAY_OBJECT
+            } else {
+                TRANSACTION_GIFT
+            },
+            object_id: object.map(|object| object.0),
+            description: description.to_string(),
+        };
+        self.record(&transaction, &balances).await;
+        self.notify(payer, transaction.id, true, &format!("You paid L${}", amount))
+            .await;
+        self.notify(payee, transaction.id, true, &format!("You were paid L${}", amount))
+            .await;
+        Ok(payment)
+    }
+
+    /// Move `amount` from `payer` to `payee` for something other than a
+    /// prim, such as land, with `description` in the ledger
+    pub async fn transfer(
+        &self,
+        payer: UserId,
+        payee: UserId,
+        amount: i64,
+        transaction_type: i32,
+        description: &str,
+    ) -> Result<i64, EconomyError> {
+        if amount < 0 {
+            return Err(EconomyError::InvalidAmount(amount));
+        }
+        if payee == payer {
+            return Err(EconomyError::SelfPayment);
+        }
+        self.load_account(payer).await?;
+        self.load_account(payee).await?;
+
+        let balances = {
+            let mut state = self.state.lock().unwrap();
+            state.transfer(payer, payee, amount)?;
+            [(payer, state.balances[&payer]), (payee, state.balances[&payee])]
+        };
+        let transaction = LedgerTransaction {
+            id: Uuid::new_v4(),
+            source_id: Some(payer.as_uuid()),
+            destination_id: Some(payee.as_uuid()),
+            amount,
+            transaction_type,
+            object_id: None,
+            description: description.to_string(),
+        };
+        self.record(&transaction, &balances).await;
+        self.notify(payer, transaction.id, true, description).await;
+        self.notify(payee, transaction.id, true, description).await;
+        Ok(balances[0].1)
+    }
+
+    /// Handle sales, purchases, payments and balance requests from the
+    /// LLUDP server
+    pub fn spawn(&self) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let economy = self.clone();
+        let mut events = lludp_server.subscribe_events();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => economy.handle(event.event_data).await,
+                    Err(RecvError::Lagged(missed)) => warn!("Economy missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle(&self, event: NetworkEventData) {
+        match event {
+            NetworkEventData::ObjectSaleInfoSet {
+                agent_id,
+                local_id,
+                sale_type,
+                price,
+                ..
+            } => {
+                if let Err(e) = self.sell_by_local_id(agent_id, local_id, sale_type, price).await {
+                    self.alert(agent_id, &e.to_string()).await;
+                }
+            }
+            NetworkEventData::ObjectBuyRequested {
+                agent_id,
+                local_id,
+                sale_type,
+                price,
+                ..
+            } => {
+                if let Err(e) = self.buy_by_local_id(agent_id, local_id, sale_type, price).await {
+                    self.alert(agent_id, &e.to_string()).await;
+                }
+            }
+            NetworkEventData::MoneyTransferRequested {
+                agent_id,
+                destination,
+                amount,
+                description,
+                ..
+            } => {
+                if let Err(e) = self.pay(agent_id, destination, amount, &description).await {
+                    self.notify(agent_id, Uuid::new_v4(), false, &e.to_string()).await;
+                }
+            }
+            NetworkEventData::MoneyBalanceRequested {
+                agent_id,
+                transaction_id,
+                ..
+            } => self.notify(agent_id, transaction_id, true, "").await,
+            _ => {}
+        }
+    }
+
+    /// ObjectSaleInfo: `agent` sets the sale of the prim it owns with
+    /// `local_id`
+    async fn sell_by_local_id(
+        &self,
+        agent: UserId,
+        local_id: u32,
+        sale_type: u8,
+        price: i32,
+    ) -> Result<(), EconomyError> {
+        let object = self.by_local_id(local_id).await?;
+        if self.owner_of(object).await? != agent {
+            return Err(EconomyError::NotOwner(agent, object));
+        }
+        let sale_type = SaleType::from_u8(sale_type).ok_or(EconomyError::UnknownSaleType(sale_type))?;
+        self.set_sale(object, SaleInfo { sale_type, price }).await
+    }
+
+    /// ObjectBuy: `agent` buys the prim with `local_id`
+    async fn buy_by_local_id(
+        &self,
+        agent: UserId,
+        local_id: u32,
+        sale_type: u8,
+        price: i32,
+    ) -> Result<Purchase, EconomyError> {
+        let object = self.by_local_id(local_id).await?;
+        let sale_type = SaleType::from_u8(sale_type).ok_or(EconomyError::UnknownSaleType(sale_type))?;
+        self.buy(agent, object, sale_type, price).await
+    }
+
+    /// Make sure the balance of `agent` is loaded, from the ledger if it
+    /// has an account there
+    async fn load_account(&self, agent: UserId) -> Result<(), EconomyError> {
+        if self.state.lock().unwrap().balances.contains_key(&agent) {
+            return Ok(());
+        }
+        let stored = match &self.database {
+            Some(database) => {
+                let backend = database
+                    .get_backend()
+                    .await
+                    .map_err(|e| EconomyError::Database(e.to_string()))?;
+                economy::load_balance(backend.as_ref(), agent.as_uuid())
+                    .await
+                    .map_err(|e| EconomyError::Database(e.to_string()))?
+            }
+            None => None,
+        };
+        self.state
+            .lock()
+            .unwrap()
+            .balances
+            .entry(agent)
+            .or_insert(stored.unwrap_or(self.config.starting_balance));
+        Ok(())
+    }
+
+    /// Write `transaction` and the `balances` it left to the ledger
+    async fn record(&self, transaction: &LedgerTransaction, balances: &[(UserId, i64)]) {
+        let _ = self.transactions.send(transaction.clone());
+        let Some(database) = &self.database else {
+            return;
+        };
+        let balances: Vec<(Uuid, i64)> = balances
+            .iter()
+            .map(|(agent, balance)| (agent.as_uuid(), *balance))
+            .collect();
+        let recorded = match database.get_backend().await {
+            Ok(backend) => economy::record_transaction(backend.as_ref(), transaction, &balances).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = recorded {
+            warn!("Failed to write transaction {} to the ledger: {}", transaction.id, e);
+        }
+    }
+
+    /// Send `agent` its balance with `description`, if it is connected
+    async fn notify(&self, agent: UserId, transaction_id: Uuid, success: bool, description: &str) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let balance = match self.balance(agent).await {
+            Ok(balance) => balance.clamp(0, i32::MAX as i64) as i32,
+            Err(e) => {
+                warn!("Failed to load the balance of {}: {}", agent, e);
+                return;
+            }
+        };
+        if let Err(e) = lludp_server
+            .send_money_balance(agent, transaction_id, success, balance, description)
+            .await
+        {
+            warn!("Failed to send agent {} its balance: {}", agent, e);
+        }
+    }
+
+    async fn alert(&self, agent: UserId, message: &str) {
+        if let Some(lludp_server) = &self.lludp_server {
+            if let Err(e) = lludp_server.send_agent_alert(agent, false, message).await {
+                warn!("Failed to alert agent {}: {}", agent, e);
+            }
+        }
+    }
+
+    async fn owner_of(&self, object: ObjectId) -> Result<UserId, EconomyError> {
+        self.scene
+            .read()
+            .await
+            .objects
+            .get(&object)
+            .map(|object| object.owner_id)
+            .ok_or(EconomyError::UnknownObject(object))
+    }
+
+    async fn by_local_id(&self, local_id: u32) -> Result<ObjectId, EconomyError> {
+        self.scene
+            .read()
+            .await
+            .objects
+            .values()
+            .find(|object| object.local_id == local_id)
+            .map(|object| object.id)
+            .ok_or(EconomyError::UnknownLocalId(local_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::prim;
+    use mutsea_core::{RegionInfo, Vector3};
+
+    #[tokio::test]
+    async fn test_sales_transfer_money_and_permissions() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let (seller, buyer) = (UserId::new(), UserId::new());
+        let lamp = prim(1, seller, Vector3::new(128.0, 128.0, 25.0));
+        let lamp_id = lamp.id;
+        scene.write().await.add_object(lamp);
+        let economy = Economy::new(EconomyConfig::default(), Arc::clone(&scene));
+
+        let copy = SaleInfo {
+            sale_type: SaleType::Copy,
+            price: 30,
+        };
+        economy.set_next_owner_permissions(lamp_id, PERM_COPY).await.unwrap();
+        economy.set_sale(lamp_id, copy).await.unwrap();
+        assert!(matches!(
+            economy.buy(buyer, lamp_id, SaleType::Copy, 10).await,
+            Err(EconomyError::SaleChanged(_))
+        ));
+
+        let purchase = economy.buy(buyer, lamp_id, SaleType::Copy, 30).await.unwrap();
+        assert_ne!(purchase.object, lamp_id);
+        assert_eq!(purchase.balance, 970);
+        assert_eq!(economy.balance(seller).await.unwrap(), 1030);
+        assert_eq!(purchase.permissions.owner, PERM_COPY);
+        assert_eq!(scene.read().await.objects[&purchase.object].owner_id, buyer);
+        // The copy can't be resold without transfer permission
+        assert!(matches!(
+            economy.set_sale(purchase.object, copy).await,
+            Err(EconomyError::NoPermission(..))
+        ));
+
+        economy
+            .set_sale(
+                lamp_id,
+                SaleInfo {
+                    sale_type: SaleType::Original,
+                    price: 2000,
+                },
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            economy.buy(buyer, lamp_id, SaleType::Original, 2000).await,
+            Err(EconomyError::InsufficientFunds {
+                balance: 970,
+                amount: 2000
+            })
+        ));
+
+        let mut money = economy.subscribe_events();
+        let mut transactions = economy.subscribe_transactions();
+        let payment = economy.pay(buyer, lamp_id.0, 70, "Thanks").await.unwrap();
+        assert_eq!(payment.payee, seller);
+        assert_eq!(payment.balance, 900);
+        assert!(matches!(
+            money.try_recv().unwrap().event_data,
+            ObjectEventData::Money { payer_id, amount: 70 } if payer_id == buyer
+        ));
+        let transaction = transactions.try_recv().unwrap();
+        assert_eq!((transaction.amount, transaction.object_id), (70, Some(lamp_id.0)));
+        assert!(matches!(
+            economy.pay(seller, lamp_id.0, 5, "").await,
+            Err(EconomyError::SelfPayment)
+        ));
+        assert!(matches!(
+            economy.pay(buyer, seller.as_uuid(), 0, "").await,
+            Err(EconomyError::InvalidAmount(0))
+        ));
+    }
+}
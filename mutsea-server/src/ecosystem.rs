@@ -0,0 +1,504 @@
+//! Ecosystem simulation
+//!
+//! Each region is one biome with plant, herbivore and carnivore species,
+//! two natural resources (water and soil nutrients) and its own weather.
+//! A step advances the weather, grows plants logistically as far as
+//! weather and resources allow, lets each trophic level graze on or hunt
+//! the one below (Holling type II), and updates the resources. Sharp
+//! population swings, resource depletion and weather changes are broadcast
+//! as region events for the AI world engine, and snapshots are stored in
+//! `ecosystem_states` at a slower interval.
+
+use async_trait::async_trait;
+use chrono::{Datelike, Timelike, Utc};
+use mutsea_core::config::EcosystemConfig;
+use mutsea_core::events::{RegionEvent, RegionEventData};
+use mutsea_core::{EcosystemRecorder, EcosystemSnapshot, MutseaResult, RegionId};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+use crate::replay::ReplaySeeds;
+use crate::simulation::{FrameContext, SimulationSubsystem};
+
+/// Capacity of the ecosystem event channel
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+/// Name of the weather RNG among replay seeds
+const SEED_SOURCE: &str = "ecosystem";
+
+/// Plants eaten per herbivore-hour when plants are plentiful
+const GRAZE_RATE: f64 = 2.0;
+/// Plant population at which grazing runs at half its maximum rate
+const GRAZE_HALF_SATURATION: f64 = 2000.0;
+/// Prey taken per carnivore-hour when prey is plentiful
+const HUNT_RATE: f64 = 0.05;
+/// Prey population at which hunting runs at half its maximum rate
+const HUNT_HALF_SATURATION: f64 = 100.0;
+/// Herbivores born per plant eaten
+const HERBIVORE_CONVERSION: f64 = 0.02;
+/// Carnivores born per prey eaten
+const CARNIVORE_CONVERSION: f64 = 0.1;
+/// Largest share of a trophic level eaten in a single step
+const MAX_CONSUMED_FRACTION: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrophicLevel {
+    Producer,
+    Herbivore,
+    Carnivore,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Species {
+    pub name: String,
+    pub trophic_level: TrophicLevel,
+    pub population: f64,
+    /// Per hour: plant growth, or natural deaths of animals
+    pub rate: f64,
+    pub carrying_capacity: f64,
+}
+
+impl Species {
+    fn new(name: &str, trophic_level: TrophicLevel, population: f64, rate: f64, carrying_capacity: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            trophic_level,
+            population,
+            rate,
+            carrying_capacity,
+        }
+    }
+}
+
+/// Weather of a region
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Weather {
+    /// Air temperature in degrees Celsius
+    pub temperature_c: f32,
+    /// 0.0 to 1.0
+    pub humidity: f32,
+    /// 0.0 to 1.0
+    pub cloud_cover: f32,
+    /// 0.0 (dry) to 1.0 (downpour)
+    pub precipitation: f32,
+    /// Metres per second, east and north
+    pub wind: (f32, f32),
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Self {
+            temperature_c: 15.0,
+            humidity: 0.5,
+            cloud_cover: 0.3,
+            precipitation: 0.0,
+            wind: (2.0, 0.0),
+        }
+    }
+}
+
+impl Weather {
+    /// Name of the weather, from `clear` to `storm`
+    pub fn condition(&self) -> &'static str {
+        if self.precipitation > 0.6 && self.wind_speed() > 12.0 {
+            "storm"
+        } else if self.precipitation > 0.05 {
+            if self.temperature_c < 0.0 {
+                "snow"
+            } else {
+                "rain"
+            }
+        } else if self.cloud_cover > 0.6 {
+            "overcast"
+        } else if self.cloud_cover > 0.3 {
+            "cloudy"
+        } else {
+            "clear"
+        }
+    }
+
+    /// Wind speed in metres per second
+    pub fn wind_speed(&self) -> f32 {
+        (self.wind.0 * self.wind.0 + self.wind.1 * self.wind.1).sqrt()
+    }
+
+    /// Advance by `hours`: temperature follows the season and time of day,
+    /// humidity, clouds and wind drift randomly
+    fn advance(&mut self, hours: f64, rng: &mut impl Rng) {
+        let now = Utc::now();
+        let season = (TAU * (now.ordinal() as f64 - 80.0) / 365.0).sin();
+        let daytime = (TAU * (now.hour() as f64 - 9.0) / 24.0).sin();
+        let target = 12.0 + 10.0 * season + 5.0 * daytime;
+
+        self.temperature_c += ((target - self.temperature_c as f64) * (hours / 6.0).min(1.0)) as f32;
+        self.humidity = (self.humidity + 0.1 * drift(rng, hours)).clamp(0.0, 1.0);
+        self.cloud_cover = (self.cloud_cover + 0.15 * drift(rng, hours) + (self.humidity - 0.5) * 0.05).clamp(0.0, 1.0);
+        self.precipitation = ((self.cloud_cover - 0.6) * 2.5 * self.humidity).clamp(0.0, 1.0);
+
+        let (mut x, mut y) = self.wind;
+        x += 2.0 * drift(rng, hours);
+        y += 2.0 * drift(rng, hours);
+        let speed = (x * x + y * y).sqrt();
+        if speed > 20.0 {
+            x *= 20.0 / speed;
+            y *= 20.0 / speed;
+        }
+        self.wind = (x, y);
+    }
+
+    /// How well plants grow in this weather, 0.0 to 1.0
+    fn growth_suitability(&self) -> f64 {
+        (1.0 - ((self.temperature_c as f64 - 20.0).abs() / 25.0)).clamp(0.0, 1.0)
+    }
+}
+
+/// Random walk increment over `hours`
+fn drift(rng: &mut impl Rng, hours: f64) -> f32 {
+    (rng.gen_range(-1.0..1.0) * hours.sqrt()) as f32
+}
+
+/// Ecosystem of one region
+#[derive(Debug, Clone, Serialize)]
+pub struct RegionEcosystem {
+    pub species: Vec<Species>,
+    /// Natural resource levels, 0.0 to 1.0
+    pub resources: HashMap<String, f64>,
+    pub weather: Weather,
+    /// Plants grazed and prey hunted in the last step
+    pub grazed: f64,
+    pub hunted: f64,
+}
+
+impl Default for RegionEcosystem {
+    /// Temperate meadow
+    fn default() -> Self {
+        Self {
+            species: vec![
+                Species::new("grass", TrophicLevel::Producer, 5000.0, 0.08, 10_000.0),
+                Species::new("shrubs", TrophicLevel::Producer, 1500.0, 0.03, 3000.0),
+                Species::new("rabbits", TrophicLevel::Herbivore, 150.0, 0.004, 600.0),
+                Species::new("deer", TrophicLevel::Herbivore, 60.0, 0.002, 200.0),
+                Species::new("wolves", TrophicLevel::Carnivore, 8.0, 0.003, 30.0),
+            ],
+            resources: HashMap::from([("water".to_string(), 0.7), ("soil_nutrients".to_string(), 0.6)]),
+            weather: Weather::default(),
+            grazed: 0.0,
+            hunted: 0.0,
+        }
+    }
+}
+
+impl RegionEcosystem {
+    fn total(&self, level: TrophicLevel) -> f64 {
+        self.species.iter().filter(|s| s.trophic_level == level).map(|s| s.population).sum()
+    }
+
+    fn resource(&self, name: &str) -> f64 {
+        self.resources.get(name).copied().unwrap_or(0.0)
+    }
+
+    /// Advance by `hours`, returning what happened worth reporting
+    fn step(&mut self, hours: f64, config: &EcosystemConfig, rng: &mut impl Rng) -> Vec<RegionEventData> {
+        let mut events = Vec::new();
+
+        let condition = self.weather.condition();
+        self.weather.advance(hours, rng);
+        if self.weather.condition() != condition {
+            events.push(RegionEventData::WeatherChanged {
+                condition: self.weather.condition().to_string(),
+                temperature_c: self.weather.temperature_c,
+                precipitation: self.weather.precipitation,
+                wind: self.weather.wind,
+            });
+        }
+
+        let plants = self.total(TrophicLevel::Producer);
+        let herbivores = self.total(TrophicLevel::Herbivore);
+        let carnivores = self.total(TrophicLevel::Carnivore);
+        let grazed = (GRAZE_RATE * herbivores * plants / (plants + GRAZE_HALF_SATURATION) * hours)
+            .min(plants * MAX_CONSUMED_FRACTION);
+        let hunted = (HUNT_RATE * carnivores * herbivores / (herbivores + HUNT_HALF_SATURATION) * hours)
+            .min(herbivores * MAX_CONSUMED_FRACTION);
+        let vitality = self.weather.growth_suitability()
+            * self.resource("water").min(self.resource("soil_nutrients")).sqrt();
+
+        let mut plant_growth = 0.0;
+        let mut plant_capacity = 0.0;
+        let previous: Vec<f64> = self.species.iter().map(|s| s.population).collect();
+        for species in &mut self.species {
+            let crowding = 1.0 - species.population / species.carrying_capacity.max(1.0);
+            let share = |total: f64| if total > 0.0 { species.population / total } else { 0.0 };
+            let change = match species.trophic_level {
+                TrophicLevel::Producer => {
+                    let growth = species.rate * species.population * crowding * vitality * hours;
+                    plant_growth += growth.max(0.0);
+                    plant_capacity += species.carrying_capacity;
+                    growth - grazed * share(plants)
+                }
+                TrophicLevel::Herbivore => {
+                    HERBIVORE_CONVERSION * grazed * share(herbivores) * crowding.max(0.0)
+                        - hunted * share(herbivores)
+                        - species.rate * species.population * hours
+                }
+                TrophicLevel::Carnivore => {
+                    CARNIVORE_CONVERSION * hunted * share(carnivores) * crowding.max(0.0)
+                        - species.rate * species.population * hours
+                }
+            };
+            species.population = (species.population + change).max(0.0);
+            if species.population < 0.5 {
+                species.population = 0.0;
+            }
+        }
+        self.grazed = grazed;
+        self.hunted = hunted;
+
+        for (species, previous) in self.species.iter().zip(previous) {
+            if previous > 0.0 && (species.population - previous).abs() / previous >= config.population_shift_threshold {
+                events.push(RegionEventData::PopulationShift {
+                    species: species.name.clone(),
+                    previous,
+                    population: species.population,
+                });
+            }
+        }
+
+        // Rain refills water, warmth evaporates it; plants draw on both
+        let evaporation = (self.weather.temperature_c as f64).max(0.0) / 30.0;
+        let uptake = if plant_capacity > 0.0 { plant_growth / plant_capacity } else { 0.0 };
+        let changes = [
+            ("water", (0.3 * self.weather.precipitation as f64 - 0.02 * evaporation) * hours - 0.5 * uptake),
+            ("soil_nutrients", 0.005 * hours + 0.2 * grazed / plant_capacity.max(1.0) - 0.5 * uptake),
+        ];
+        for (name, change) in changes {
+            let level = self.resources.entry(name.to_string()).or_insert(0.0);
+            let before = *level;
+            *level = (*level + change).clamp(0.0, 1.0);
+            if before >= config.depletion_threshold && *level < config.depletion_threshold {
+                events.push(RegionEventData::ResourceDepleted {
+                    resource: name.to_string(),
+                    level: *level,
+                });
+            }
+        }
+
+        events
+    }
+
+    /// Shannon evenness of the species populations, 0.0 to 1.0
+    pub fn biodiversity_index(&self) -> f64 {
+        let total: f64 = self.species.iter().map(|s| s.population).sum();
+        let present: Vec<f64> = self.species.iter().map(|s| s.population).filter(|p| *p > 0.0).collect();
+        if total <= 0.0 || present.len() < 2 {
+            return 0.0;
+        }
+        let entropy: f64 = present.iter().map(|p| p / total).map(|p| -p * p.ln()).sum();
+        (entropy / (present.len() as f64).ln()).clamp(0.0, 1.0)
+    }
+
+    fn snapshot(&self, region_id: RegionId) -> EcosystemSnapshot {
+        let populations: serde_json::Map<_, _> =
+            self.species.iter().map(|s| (s.name.clone(), json!(s.population.round()))).collect();
+        EcosystemSnapshot {
+            timestamp: Utc::now(),
+            biome_id: region_id.0,
+            population_data: serde_json::Value::Object(populations),
+            resource_data: json!(self.resources),
+            interaction_data: json!({ "grazed": self.grazed, "hunted": self.hunted }),
+            health_metrics: json!({
+                "weather": self.weather,
+                "condition": self.weather.condition(),
+                "extinct": self.species.iter().filter(|s| s.population == 0.0).map(|s| &s.name).collect::<Vec<_>>(),
+            }),
+            biodiversity_index: self.biodiversity_index(),
+            carrying_capacity: self.species.iter().map(|s| s.carrying_capacity).sum::<f64>().round() as i64,
+        }
+    }
+}
+
+/// Ecosystems of the regions served here. Clones share the regions and
+/// event channel; the clone registered with the simulation loop steps them.
+#[derive(Clone)]
+pub struct EcosystemSimulator {
+    config: EcosystemConfig,
+    regions: Arc<RwLock<HashMap<RegionId, RegionEcosystem>>>,
+    events: broadcast::Sender<RegionEvent>,
+    recorder: Option<Arc<dyn EcosystemRecorder>>,
+    rng: StdRng,
+    seeds: Option<ReplaySeeds>,
+    since_step: Duration,
+    since_snapshot: Duration,
+}
+
+impl EcosystemSimulator {
+    pub fn new(config: EcosystemConfig) -> Self {
+        let rng = match config.seed {
+            0 => StdRng::from_entropy(),
+            seed => StdRng::seed_from_u64(seed),
+        };
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self {
+            config,
+            regions: Arc::new(RwLock::new(HashMap::new())),
+            events,
+            recorder: None,
+            rng,
+            seeds: None,
+            since_step: Duration::ZERO,
+            since_snapshot: Duration::ZERO,
+        }
+    }
+
+    /// Store snapshots through `recorder`
+    pub fn with_recorder(mut self, recorder: Arc<dyn EcosystemRecorder>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Reseed the weather RNG from `seeds` so runs can be replayed
+    pub fn with_seeds(mut self, seeds: ReplaySeeds) -> Self {
+        seeds.register(SEED_SOURCE);
+        self.seeds = Some(seeds);
+        self
+    }
+
+    /// Simulate `region_id`, starting from a temperate meadow
+    pub fn add_region(&self, region_id: RegionId) {
+        self.regions.write().unwrap().entry(region_id).or_default();
+        info!("Simulating the ecosystem of region {}", region_id.0);
+    }
+
+    pub fn region(&self, region_id: RegionId) -> Option<RegionEcosystem> {
+        self.regions.read().unwrap().get(&region_id).cloned()
+    }
+
+    pub fn weather(&self, region_id: RegionId) -> Option<Weather> {
+        self.regions.read().unwrap().get(&region_id).map(|region| region.weather)
+    }
+
+    /// Population swings, resource depletion and weather changes
+    pub fn subscribe_events(&self) -> broadcast::Receiver<RegionEvent> {
+        self.events.subscribe()
+    }
+
+    /// Advance every region by `hours` of ecosystem time
+    pub fn step(&mut self, hours: f64) {
+        let mut regions = self.regions.write().unwrap();
+        for (region_id, region) in regions.iter_mut() {
+            for event_data in region.step(hours, &self.config, &mut self.rng) {
+                debug!("Ecosystem event in region {}: {:?}", region_id.0, event_data);
+                // No subscribers is fine
+                let _ = self.events.send(RegionEvent {
+                    event_id: uuid::Uuid::new_v4(),
+                    timestamp: Utc::now(),
+                    region_id: *region_id,
+                    event_data,
+                });
+            }
+        }
+    }
+
+    fn record_snapshots(&self) {
+        let Some(recorder) = self.recorder.clone() else {
+            return;
+        };
+        let snapshots: Vec<_> = self
+            .regions
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(region_id, region)| region.snapshot(*region_id))
+            .collect();
+        // Never hold up the frame on the database
+        tokio::spawn(async move {
+            if let Err(e) = recorder.record_ecosystem(&snapshots).await {
+                warn!("Failed to record ecosystem snapshots: {}", e);
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl SimulationSubsystem for EcosystemSimulator {
+    async fn tick(&mut self, frame: &FrameContext) -> MutseaResult<()> {
+        if let Some(seed) = self.seeds.as_ref().and_then(|seeds| seeds.take(SEED_SOURCE)) {
+            self.rng = StdRng::seed_from_u64(seed);
+        }
+        let step = Duration::from_secs(self.config.step_interval_secs.max(1));
+        self.since_step += frame.dt;
+        if self.since_step >= step {
+            let hours = self.since_step.as_secs_f64() / 3600.0 * self.config.time_scale;
+            self.since_step = Duration::ZERO;
+            self.step(hours);
+        }
+
+        self.since_snapshot += frame.dt;
+        if self.since_snapshot >= Duration::from_secs(self.config.snapshot_interval_secs.max(1)) {
+            self.since_snapshot = Duration::ZERO;
+            self.record_snapshots();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulator() -> EcosystemSimulator {
+        EcosystemSimulator::new(EcosystemConfig {
+            seed: 7,
+            ..EcosystemConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_steps_stay_in_bounds() {
+        let mut simulator = simulator();
+        let region_id = RegionId::new();
+        simulator.add_region(region_id);
+        for _ in 0..2000 {
+            simulator.step(0.5);
+        }
+
+        let region = simulator.region(region_id).unwrap();
+        for species in &region.species {
+            assert!(species.population.is_finite() && species.population >= 0.0, "{:?}", species);
+        }
+        assert!(region.resources.values().all(|level| (0.0..=1.0).contains(level)));
+        assert!((0.0..=1.0).contains(&region.biodiversity_index()));
+        assert!(region.weather.wind_speed() <= 20.0 + 1e-3);
+    }
+
+    #[test]
+    fn test_population_crash_is_reported() {
+        let mut simulator = simulator();
+        let region_id = RegionId::new();
+        simulator.add_region(region_id);
+        let mut events = simulator.subscribe_events();
+        {
+            // Wolves with nothing to hunt, dying off fast
+            let mut regions = simulator.regions.write().unwrap();
+            let species = &mut regions.get_mut(&region_id).unwrap().species;
+            species.retain(|s| s.trophic_level != TrophicLevel::Herbivore);
+            species.iter_mut().filter(|s| s.name == "wolves").for_each(|s| s.rate = 0.5);
+        }
+        simulator.step(1.0);
+
+        let shifted: Vec<String> = std::iter::from_fn(|| events.try_recv().ok())
+            .filter_map(|event| match event.event_data {
+                RegionEventData::PopulationShift { species, .. } => Some(species),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(shifted, ["wolves"]);
+    }
+}
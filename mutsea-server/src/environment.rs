@@ -0,0 +1,138 @@
+//! Region environment sent to viewers
+//!
+//! Turns the ecosystem's weather into what viewers render: the Windlight
+//! sky and water of the EnvironmentSettings capability, and the wind and
+//! cloud fields the LLUDP server sends as LayerData. Without a simulated
+//! ecosystem the region keeps clear, calm weather.
+
+use crate::ecosystem::EcosystemSimulator;
+use async_trait::async_trait;
+use mutsea_core::{config::EnvironmentConfig, RegionId};
+use mutsea_network::lludp_server::EnvironmentSource;
+use mutsea_protocol::environment::{DayCycle, EnvironmentSettings, WeatherLook};
+use mutsea_protocol::layer_data::PATCH_SIZE;
+use std::f32::consts::TAU;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Region size in metres, covered by one wind or cloud patch
+const REGION_SIZE: f32 = 256.0;
+
+/// Environment of one region, following its simulated weather
+pub struct RegionEnvironment {
+    config: EnvironmentConfig,
+    region_id: RegionId,
+    ecosystem: EcosystemSimulator,
+}
+
+impl RegionEnvironment {
+    pub fn new(config: EnvironmentConfig, region_id: RegionId, ecosystem: EcosystemSimulator) -> Self {
+        Self {
+            config,
+            region_id,
+            ecosystem,
+        }
+    }
+
+    /// Current weather as viewers should see it
+    pub fn look(&self) -> WeatherLook {
+        match self.ecosystem.weather(self.region_id) {
+            Some(weather) => WeatherLook {
+                condition: weather.condition(),
+                cloud_cover: weather.cloud_cover,
+                wind: weather.wind,
+            },
+            None => WeatherLook::default(),
+        }
+    }
+
+    /// Windlight settings served by the EnvironmentSettings capability
+    pub fn settings(&self) -> EnvironmentSettings {
+        EnvironmentSettings::new(self.region_id.0, &self.look())
+    }
+}
+
+#[async_trait]
+impl EnvironmentSource for RegionEnvironment {
+    fn day_cycle(&self) -> DayCycle {
+        DayCycle {
+            day_length_secs: self.config.day_length_secs,
+            offset_secs: self.config.day_offset_secs,
+        }
+    }
+
+    async fn wind(&self) -> (Vec<f32>, Vec<f32>) {
+        wind_field(self.look().wind, now_secs())
+    }
+
+    async fn clouds(&self) -> Vec<f32> {
+        let look = self.look();
+        cloud_field(look.cloud_cover, look.wind, now_secs())
+    }
+}
+
+fn now_secs() -> f32 {
+    // Wrapped so the f32 keeps sub-second precision
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+    (secs % 86_400.0) as f32
+}
+
+/// Centre of each 16x16 sample, in metres
+fn samples() -> impl Iterator<Item = (f32, f32)> {
+    let step = REGION_SIZE / PATCH_SIZE as f32;
+    (0..PATCH_SIZE).flat_map(move |y| (0..PATCH_SIZE).map(move |x| ((x as f32 + 0.5) * step, (y as f32 + 0.5) * step)))
+}
+
+/// Region-wide `wind` with gusts travelling across the region at time `t`
+fn wind_field(wind: (f32, f32), t: f32) -> (Vec<f32>, Vec<f32>) {
+    let speed = (wind.0 * wind.0 + wind.1 * wind.1).sqrt();
+    let gust = 0.2 * speed + 0.3;
+    samples()
+        .map(|(x, y)| {
+            let (along, across) = (TAU * (x + y) / REGION_SIZE, TAU * (x - y) / REGION_SIZE);
+            (
+                wind.0 + gust * (along + t * 0.31).sin(),
+                wind.1 + gust * (across - t * 0.23).cos(),
+            )
+        })
+        .unzip()
+}
+
+/// Cloud densities averaging `cover`, banks drifting with `wind`
+fn cloud_field(cover: f32, wind: (f32, f32), t: f32) -> Vec<f32> {
+    samples()
+        .map(|(x, y)| {
+            let (x, y) = (x - wind.0 * t, y - wind.1 * t);
+            let bank = (TAU * x / REGION_SIZE).sin() * (TAU * y / (REGION_SIZE * 0.75)).cos();
+            (cover + 0.25 * bank).clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::config::EcosystemConfig;
+
+    #[test]
+    fn test_environment_follows_weather() {
+        let region_id = RegionId::new();
+        let ecosystem = EcosystemSimulator::new(EcosystemConfig::default());
+        let environment = RegionEnvironment::new(EnvironmentConfig::default(), region_id, ecosystem.clone());
+        // Clear and calm until the region's ecosystem is simulated
+        assert_eq!(environment.look(), WeatherLook::default());
+
+        ecosystem.add_region(region_id);
+        let weather = ecosystem.weather(region_id).unwrap();
+        assert_eq!(environment.look().wind, weather.wind);
+        assert_eq!(environment.settings().region_id, region_id.0);
+
+        let (east, north) = wind_field((4.0, -2.0), 100.0);
+        assert_eq!(east.len(), PATCH_SIZE * PATCH_SIZE);
+        let mean = |v: &[f32]| v.iter().sum::<f32>() / v.len() as f32;
+        assert!((mean(&east) - 4.0).abs() < 0.01);
+        assert!((mean(&north) + 2.0).abs() < 0.01);
+
+        let clouds = cloud_field(0.5, (4.0, -2.0), 100.0);
+        assert!(clouds.iter().all(|d| (0.25..=0.75).contains(d)));
+    }
+}
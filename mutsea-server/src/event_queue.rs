@@ -0,0 +1,142 @@
+//! Capability event queues
+//!
+//! Each agent polling its EventQueueGet capability gets a queue of events
+//! waiting for its next poll. The batch last handed out is kept until the
+//! viewer acknowledges it, and handed out again if it doesn't, so events
+//! survive a poll lost on the way back. Agents that stop polling are
+//! dropped once their queue goes stale.
+
+use mutsea_core::UserId;
+use mutsea_protocol::event_queue::event;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// How long a poll is held waiting for events
+pub const POLL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long after its last poll an agent's queue is dropped
+const STALE_AFTER: Duration = Duration::from_secs(120);
+
+struct AgentQueue {
+    next_id: i64,
+    pending: Vec<Value>,
+    /// The batch handed out and not yet acknowledged
+    sent: Option<(i64, Vec<Value>)>,
+    last_poll: Instant,
+    notify: Arc<Notify>,
+}
+
+impl AgentQueue {
+    fn new() -> Self {
+        Self {
+            next_id: 0,
+            pending: Vec::new(),
+            sent: None,
+            last_poll: Instant::now(),
+            notify: Arc::new(Notify::new()),
+        }
+    }
+}
+
+/// The event queues of the agents polling. Clones share the queues.
+#[derive(Clone, Default)]
+pub struct EventQueue {
+    queues: Arc<Mutex<HashMap<UserId, AgentQueue>>>,
+}
+
+impl EventQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue `message` for `agent`, if it is polling
+    pub fn post(&self, agent: UserId, message: &str, body: Value) {
+        if let Some(queue) = self.queues.lock().unwrap().get_mut(&agent) {
+            queue.pending.push(event(message, body));
+            queue.notify.notify_one();
+        }
+    }
+
+    /// Queue `message` for every agent polling, dropping stale queues
+    pub fn broadcast(&self, message: &str, body: Value) {
+        let mut queues = self.queues.lock().unwrap();
+        queues.retain(|_, queue| queue.last_poll.elapsed() < STALE_AFTER);
+        for queue in queues.values_mut() {
+            queue.pending.push(event(message, body.clone()));
+            queue.notify.notify_one();
+        }
+    }
+
+    /// The agents polling
+    pub fn agents(&self) -> Vec<UserId> {
+        self.queues.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Drop the queue of `agent`, as when it says it is done
+    pub fn remove(&self, agent: UserId) {
+        self.queues.lock().unwrap().remove(&agent);
+    }
+
+    /// The next batch for `agent`, which last received batch `ack`, waiting
+    /// up to `timeout` for events; None if none came
+    pub async fn poll(&self, agent: UserId, ack: Option<i64>, timeout: Duration) -> Option<(i64, Vec<Value>)> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let notify = {
+                let mut queues = self.queues.lock().unwrap();
+                let queue = queues.entry(agent).or_insert_with(AgentQueue::new);
+                queue.last_poll = Instant::now();
+                match &queue.sent {
+                    Some((id, events)) if ack != Some(*id) => return Some((*id, events.clone())),
+                    _ => queue.sent = None,
+                }
+                if !queue.pending.is_empty() {
+                    queue.next_id += 1;
+                    let batch = (queue.next_id, std::mem::take(&mut queue.pending));
+                    queue.sent = Some(batch.clone());
+                    return Some(batch);
+                }
+                Arc::clone(&queue.notify)
+            };
+            if tokio::time::timeout_at(deadline, notify.notified()).await.is_err() {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_batches_are_resent_until_acknowledged() {
+        let queue = EventQueue::new();
+        let agent = UserId::new();
+        let short = Duration::from_millis(10);
+
+        queue.post(agent, "Ignored", json!({}));
+        assert_eq!(queue.poll(agent, None, short).await, None);
+
+        queue.post(agent, "First", json!({ "n": 1 }));
+        queue.broadcast("Second", json!({ "n": 2 }));
+        let (id, events) = queue.poll(agent, None, short).await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["message"], "First");
+        assert_eq!(queue.poll(agent, None, short).await, Some((id, events)));
+
+        let waiting = queue.clone();
+        let poll = tokio::spawn(async move { waiting.poll(agent, Some(id), POLL_TIMEOUT).await });
+        tokio::time::sleep(short).await;
+        queue.broadcast("Third", json!({}));
+        let (next, events) = poll.await.unwrap().unwrap();
+        assert_eq!((next, events[0]["message"].as_str()), (id + 1, Some("Third")));
+
+        queue.remove(agent);
+        assert!(queue.agents().is_empty());
+    }
+}
@@ -0,0 +1,610 @@
+//! Region scenes as glTF 2.0
+//!
+//! A region's terrain and prims are written as one binary glTF (GLB) file
+//! that Blender, three.js and other standard tools open, and that clients
+//! not speaking LLUDP can load. Positions are converted from the region's
+//! Z-up meters to glTF's Y-up, with the region's south-west corner at the
+//! origin. The terrain is a height grid whose colours are baked by height
+//! and slope into an embedded PNG. Prims are boxes, cylinders, prisms and
+//! spheres after their profile and path, other shapes their bounding box,
+//! with PBR materials baked from their LL material (wood, glass, light...).
+
+use flate2::{write::ZlibEncoder, Compression};
+use mutsea_core::config::GltfExportConfig;
+use mutsea_core::scene::{RegionScene, Terrain};
+use mutsea_core::SceneObject;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::io::Write;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::RwLock;
+
+/// Segments around round prims
+const ROUND_SEGMENTS: usize = 24;
+
+/// glTF component types and buffer view targets
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("Unknown region {0}")]
+    UnknownRegion(String),
+}
+
+/// Exports a region as glTF. Clones share the scene.
+#[derive(Clone)]
+pub struct GltfExporter {
+    config: GltfExportConfig,
+    scene: Arc<RwLock<RegionScene>>,
+}
+
+impl GltfExporter {
+    pub fn new(config: GltfExportConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self { config, scene }
+    }
+
+    /// The region named `region`, or with that ID, as a GLB file
+    pub async fn export(&self, region: &str) -> Result<Vec<u8>, ExportError> {
+        // Clone under the lock so the simulation is only blocked for the copy
+        let scene = self.scene.read().await.clone();
+        if !scene.info.region_name.eq_ignore_ascii_case(region) && scene.info.region_id.to_string() != region {
+            return Err(ExportError::UnknownRegion(region.to_string()));
+        }
+        Ok(export_scene(&scene, &self.config))
+    }
+}
+
+/// `scene` as a GLB file
+pub fn export_scene(scene: &RegionScene, config: &GltfExportConfig) -> Vec<u8> {
+    let mut gltf = Builder::default();
+    let mut nodes = vec![gltf.terrain(&scene.terrain, config)];
+
+    let mut objects: Vec<&SceneObject> = scene.objects.values().collect();
+    objects.sort_by_key(|object| object.local_id);
+    for object in objects {
+        nodes.push(gltf.prim(object));
+    }
+
+    gltf.into_glb(json!([{ "name": scene.info.region_name, "nodes": nodes }]))
+}
+
+/// Prim shapes, each one mesh of unit size shared by the prims of that shape
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Shape {
+    Box,
+    Cylinder,
+    Prism,
+    Sphere,
+}
+
+impl Shape {
+    fn of(object: &SceneObject) -> Self {
+        const PATH_LINE: u8 = 0x10;
+        const PATH_CIRCLE: u8 = 0x20;
+        match (object.shape.profile_curve & 0x0F, object.shape.path_curve) {
+            (0, PATH_LINE) => Shape::Cylinder,
+            (2..=4, PATH_LINE) => Shape::Prism,
+            (5, PATH_CIRCLE) => Shape::Sphere,
+            _ => Shape::Box,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Shape::Box => "box",
+            Shape::Cylinder => "cylinder",
+            Shape::Prism => "prism",
+            Shape::Sphere => "sphere",
+        }
+    }
+
+    /// Triangles of the shape within the unit cube around the origin, in
+    /// region axes
+    fn triangles(self) -> Vec<[[f32; 3]; 3]> {
+        let mut triangles = Vec::new();
+        match self {
+            Shape::Box => {
+                let corner = |i: usize| {
+                    [
+                        if i & 1 == 0 { -0.5 } else { 0.5 },
+                        if i & 2 == 0 { -0.5 } else { 0.5 },
+                        if i & 4 == 0 { -0.5 } else { 0.5 },
+                    ]
+                };
+                for [a, b, c, d] in [
+                    [0, 1, 3, 2],
+                    [4, 5, 7, 6],
+                    [0, 1, 5, 4],
+                    [2, 3, 7, 6],
+                    [0, 2, 6, 4],
+                    [1, 3, 7, 5],
+                ] {
+                    triangles.push([corner(a), corner(b), corner(c)]);
+                    triangles.push([corner(a), corner(c), corner(d)]);
+                }
+            }
+            Shape::Cylinder | Shape::Prism => {
+                let profile: Vec<[f32; 2]> = if self == Shape::Cylinder {
+                    (0..ROUND_SEGMENTS)
+                        .map(|i| {
+                            let angle = TAU * i as f32 / ROUND_SEGMENTS as f32;
+                            [0.5 * angle.cos(), 0.5 * angle.sin()]
+                        })
+                        .collect()
+                } else {
+                    vec![[-0.5, -0.5], [0.5, -0.5], [0.0, 0.5]]
+                };
+                for (i, [x0, y0]) in profile.iter().copied().enumerate() {
+                    let [x1, y1] = profile[(i + 1) % profile.len()];
+                    triangles.push([[x0, y0, -0.5], [x1, y1, -0.5], [x1, y1, 0.5]]);
+                    triangles.push([[x0, y0, -0.5], [x1, y1, 0.5], [x0, y0, 0.5]]);
+                    triangles.push([[0.0, 0.0, 0.5], [x0, y0, 0.5], [x1, y1, 0.5]]);
+                    triangles.push([[0.0, 0.0, -0.5], [x0, y0, -0.5], [x1, y1, -0.5]]);
+                }
+            }
+            Shape::Sphere => {
+                let rings = ROUND_SEGMENTS / 2;
+                let point = |ring: usize, segment: usize| {
+                    let polar = std::f32::consts::PI * ring as f32 / rings as f32;
+                    let azimuth = TAU * segment as f32 / ROUND_SEGMENTS as f32;
+                    [
+                        0.5 * polar.sin() * azimuth.cos(),
+                        0.5 * polar.sin() * azimuth.sin(),
+                        0.5 * polar.cos(),
+                    ]
+                };
+                for ring in 0..rings {
+                    for segment in 0..ROUND_SEGMENTS {
+                        let (a, b) = (point(ring, segment), point(ring, segment + 1));
+                        let (c, d) = (point(ring + 1, segment + 1), point(ring + 1, segment));
+                        if ring > 0 {
+                            triangles.push([a, b, c]);
+                        }
+                        if ring + 1 < rings {
+                            triangles.push([a, c, d]);
+                        }
+                    }
+                }
+            }
+        }
+        // Every shape is convex around the origin, so a face points outward
+        // when its normal points away from the origin
+        for triangle in &mut triangles {
+            let centroid = add(add(triangle[0], triangle[1]), triangle[2]);
+            if dot(face_normal(triangle), centroid) < 0.0 {
+                triangle.swap(1, 2);
+            }
+        }
+        triangles
+    }
+}
+
+/// A PBR material baked from an LL material
+fn baked_material(material: u8) -> Value {
+    let (name, color, metallic, roughness) = match material {
+        1 => ("metal", [0.75, 0.75, 0.78, 1.0], 1.0, 0.35),
+        2 => ("glass", [0.8, 0.9, 0.95, 0.35], 0.0, 0.05),
+        3 => ("wood", [0.55, 0.38, 0.22, 1.0], 0.0, 0.8),
+        4 => ("flesh", [0.9, 0.7, 0.6, 1.0], 0.0, 0.6),
+        5 => ("plastic", [0.8, 0.8, 0.8, 1.0], 0.0, 0.4),
+        6 => ("rubber", [0.15, 0.15, 0.15, 1.0], 0.0, 0.95),
+        7 => ("light", [1.0, 1.0, 0.9, 1.0], 0.0, 0.5),
+        _ => ("stone", [0.5, 0.5, 0.5, 1.0], 0.0, 0.9),
+    };
+    let mut baked = json!({
+        "name": name,
+        "pbrMetallicRoughness": {
+            "baseColorFactor": color,
+            "metallicFactor": metallic,
+            "roughnessFactor": roughness,
+        },
+    });
+    if material == 2 {
+        baked["alphaMode"] = json!("BLEND");
+    }
+    if material == 7 {
+        baked["emissiveFactor"] = json!([1.0, 1.0, 0.9]);
+    }
+    baked
+}
+
+/// The colour of the terrain at a height and slope
+fn terrain_color(height: f32, slope: f32, water_height: f32) -> [u8; 3] {
+    if slope > 1.0 {
+        [115, 107, 102]
+    } else if height < water_height + 0.5 {
+        [194, 178, 128]
+    } else if height > water_height + 40.0 {
+        [240, 240, 245]
+    } else {
+        // Grass darkens as it climbs
+        let shade = ((height - water_height) / 40.0).clamp(0.0, 1.0);
+        [
+            (77.0 - 30.0 * shade) as u8,
+            (128.0 - 40.0 * shade) as u8,
+            (51.0 - 15.0 * shade) as u8,
+        ]
+    }
+}
+
+/// The document being written, with its binary buffer
+#[derive(Default)]
+struct Builder {
+    bin: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    meshes: Vec<Value>,
+    materials: Vec<Value>,
+    images: Vec<Value>,
+    textures: Vec<Value>,
+    nodes: Vec<Value>,
+    shapes: HashMap<(Shape, u8), usize>,
+}
+
+impl Builder {
+    /// Add the terrain mesh and its baked texture, returning its node
+    fn terrain(&mut self, terrain: &Terrain, config: &GltfExportConfig) -> usize {
+        let (size_x, size_y) = (terrain.size_x.max(1), terrain.size_y.max(1));
+        let samples = |size: u32| {
+            let mut samples: Vec<u32> = (0..size).step_by(config.terrain_step.max(1) as usize).collect();
+            if samples.last() != Some(&(size - 1)) {
+                samples.push(size - 1);
+            }
+            samples
+        };
+        let (xs, ys) = (samples(size_x), samples(size_y));
+        let slope = |x: u32, y: u32| {
+            let dx = terrain.height_at(x + 1, y) - terrain.height_at(x.saturating_sub(1), y);
+            let dy = terrain.height_at(x, y + 1) - terrain.height_at(x, y.saturating_sub(1));
+            (dx / 2.0, dy / 2.0)
+        };
+
+        let (mut positions, mut normals, mut uvs) = (Vec::new(), Vec::new(), Vec::new());
+        for &y in &ys {
+            for &x in &xs {
+                let (dx, dy) = slope(x, y);
+                positions.push(y_up([x as f32, y as f32, terrain.height_at(x, y)]));
+                normals.push(y_up(normalize([-dx, -dy, 1.0])));
+                // Image rows run north to south
+                uvs.push([
+                    (x as f32 + 0.5) / size_x as f32,
+                    (size_y as f32 - y as f32 - 0.5) / size_y as f32,
+                ]);
+            }
+        }
+        let mut indices = Vec::new();
+        let row = xs.len() as u32;
+        for j in 0..ys.len().saturating_sub(1) as u32 {
+            for i in 0..row - 1 {
+                let (a, b) = (j * row + i, j * row + i + 1);
+                let (c, d) = (b + row, a + row);
+                indices.extend_from_slice(&[a, b, c, a, c, d]);
+            }
+        }
+
+        let mut pixels = Vec::with_capacity((size_x * size_y * 3) as usize);
+        for y in (0..size_y).rev() {
+            for x in 0..size_x {
+                let (dx, dy) = slope(x, y);
+                let steepness = (dx * dx + dy * dy).sqrt();
+                pixels.extend_from_slice(&terrain_color(terrain.height_at(x, y), steepness, config.water_height));
+            }
+        }
+        let png = encode_png(size_x, size_y, &pixels);
+        let view = self.push_view(&png, None);
+        self.images
+            .push(json!({ "name": "terrain", "bufferView": view, "mimeType": "image/png" }));
+        self.textures
+            .push(json!({ "sampler": 0, "source": self.images.len() - 1 }));
+        self.materials.push(json!({
+            "name": "terrain",
+            "pbrMetallicRoughness": {
+                "baseColorTexture": { "index": self.textures.len() - 1 },
+                "metallicFactor": 0.0,
+                "roughnessFactor": 1.0,
+            },
+        }));
+
+        let attributes = json!({
+            "POSITION": self.push_vec3(&positions, true),
+            "NORMAL": self.push_vec3(&normals, false),
+            "TEXCOORD_0": self.push_vec2(&uvs),
+        });
+        let indices = self.push_indices(&indices);
+        self.meshes.push(json!({
+            "name": "terrain",
+            "primitives": [{ "attributes": attributes, "indices": indices, "material": self.materials.len() - 1 }],
+        }));
+        self.nodes
+            .push(json!({ "name": "terrain", "mesh": self.meshes.len() - 1 }));
+        self.nodes.len() - 1
+    }
+
+    /// Add a node for `object`, returning it
+    fn prim(&mut self, object: &SceneObject) -> usize {
+        let mesh = self.shape_mesh(Shape::of(object), object.material);
+        let rotation = object.rotation.normalize();
+        let (position, scale) = (object.position, object.scale);
+        self.nodes.push(json!({
+            "name": object.name,
+            "mesh": mesh,
+            "translation": y_up([position.x, position.y, position.z]),
+            "rotation": [rotation.x, rotation.z, -rotation.y, rotation.w],
+            "scale": [scale.x, scale.z, scale.y],
+            "extras": { "id": object.id.to_string(), "local_id": object.local_id },
+        }));
+        self.nodes.len() - 1
+    }
+
+    /// The mesh of `shape` in `material`, added the first time it is used
+    fn shape_mesh(&mut self, shape: Shape, material: u8) -> usize {
+        if let Some(mesh) = self.shapes.get(&(shape, material)) {
+            return *mesh;
+        }
+        let material_index = self.materials.len();
+        self.materials.push(baked_material(material));
+
+        let (mut positions, mut normals) = (Vec::new(), Vec::new());
+        for triangle in shape.triangles() {
+            let normal = y_up(normalize(face_normal(&triangle)));
+            for vertex in triangle {
+                positions.push(y_up(vertex));
+                normals.push(normal);
+            }
+        }
+        let attributes = json!({
+            "POSITION": self.push_vec3(&positions, true),
+            "NORMAL": self.push_vec3(&normals, false),
+        });
+        self.meshes.push(json!({
+            "name": shape.name(),
+            "primitives": [{ "attributes": attributes, "material": material_index }],
+        }));
+        let mesh = self.meshes.len() - 1;
+        self.shapes.insert((shape, material), mesh);
+        mesh
+    }
+
+    /// Append `bytes` to the buffer as a view, 4-byte aligned
+    fn push_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        let offset = self.bin.len();
+        self.bin.extend_from_slice(bytes);
+        self.bin.resize(self.bin.len().next_multiple_of(4), 0);
+        let mut view = json!({ "buffer": 0, "byteOffset": offset, "byteLength": bytes.len() });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+
+    fn push_accessor(&mut self, view: usize, component: u32, count: usize, kind: &str) -> &mut Value {
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": component,
+            "count": count,
+            "type": kind,
+        }));
+        self.accessors.last_mut().unwrap()
+    }
+
+    /// Vectors as a VEC3 accessor, with their bounds when `bounded`, which
+    /// positions need
+    fn push_vec3(&mut self, vectors: &[[f32; 3]], bounded: bool) -> usize {
+        let bytes: Vec<u8> = vectors.iter().flatten().flat_map(|v| v.to_le_bytes()).collect();
+        let view = self.push_view(&bytes, Some(ARRAY_BUFFER));
+        let accessor = self.push_accessor(view, FLOAT, vectors.len(), "VEC3");
+        if bounded {
+            let bound = |pick: fn(f32, f32) -> f32, start: f32| {
+                vectors.iter().fold([start; 3], |acc, v| {
+                    [pick(acc[0], v[0]), pick(acc[1], v[1]), pick(acc[2], v[2])]
+                })
+            };
+            accessor["min"] = json!(bound(f32::min, f32::MAX));
+            accessor["max"] = json!(bound(f32::max, f32::MIN));
+        }
+        self.accessors.len() - 1
+    }
+
+    fn push_vec2(&mut self, vectors: &[[f32; 2]]) -> usize {
+        let bytes: Vec<u8> = vectors.iter().flatten().flat_map(|v| v.to_le_bytes()).collect();
+        let view = self.push_view(&bytes, Some(ARRAY_BUFFER));
+        self.push_accessor(view, FLOAT, vectors.len(), "VEC2");
+        self.accessors.len() - 1
+    }
+
+    fn push_indices(&mut self, indices: &[u32]) -> usize {
+        let bytes: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let view = self.push_view(&bytes, Some(ELEMENT_ARRAY_BUFFER));
+        self.push_accessor(view, UNSIGNED_INT, indices.len(), "SCALAR");
+        self.accessors.len() - 1
+    }
+
+    /// The GLB file: a header, then the JSON and binary chunks
+    fn into_glb(self, scenes: Value) -> Vec<u8> {
+        let document = json!({
+            "asset": { "version": "2.0", "generator": "Mutsea" },
+            "scene": 0,
+            "scenes": scenes,
+            "nodes": self.nodes,
+            "meshes": self.meshes,
+            "materials": self.materials,
+            "images": self.images,
+            // Linear filtering with trilinear mipmaps
+            "samplers": [{ "magFilter": 9729, "minFilter": 9987 }],
+            "textures": self.textures,
+            "accessors": self.accessors,
+            "bufferViews": self.buffer_views,
+            "buffers": [{ "byteLength": self.bin.len() }],
+        });
+        let mut json = serde_json::to_vec(&document).unwrap_or_default();
+        json.resize(json.len().next_multiple_of(4), b' ');
+
+        let mut glb = Vec::with_capacity(12 + 8 + json.len() + 8 + self.bin.len());
+        glb.extend_from_slice(b"glTF");
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((12 + 8 + json.len() + 8 + self.bin.len()) as u32).to_le_bytes());
+        glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"JSON");
+        glb.extend_from_slice(&json);
+        glb.extend_from_slice(&(self.bin.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"BIN\0");
+        glb.extend_from_slice(&self.bin);
+        glb
+    }
+}
+
+/// An 8-bit RGB PNG of `pixels`, rows top to bottom
+fn encode_png(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in pixels.chunks(width as usize * 3) {
+        // Filter type 0, none
+        let _ = encoder.write_all(&[0]);
+        let _ = encoder.write_all(row);
+    }
+    let data = encoder.finish().unwrap_or_default();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, colour type RGB, deflate, no filter method or interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, body) in [
+        (b"IHDR", header.as_slice()),
+        (b"IDAT", data.as_slice()),
+        (b"IEND", &[][..]),
+    ] {
+        png.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(body);
+        let crc = crc32(&png[start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    }
+    png
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// Region axes (Z up) to glTF axes (Y up)
+fn y_up([x, y, z]: [f32; 3]) -> [f32; 3] {
+    [x, z, -y]
+}
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn face_normal([a, b, c]: &[[f32; 3]; 3]) -> [f32; 3] {
+    let (u, v) = (
+        [b[0] - a[0], b[1] - a[1], b[2] - a[2]],
+        [c[0] - a[0], c[1] - a[1], c[2] - a[2]],
+    );
+    [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    if length > 0.0 {
+        [v[0] / length, v[1] / length, v[2] / length]
+    } else {
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use mutsea_core::{ObjectShape, RegionInfo, UserId, Vector3};
+
+    fn prim(local_id: u32, shape: ObjectShape, material: u8) -> SceneObject {
+        SceneObject {
+            scale: Vector3::new(1.0, 2.0, 3.0),
+            material,
+            shape,
+            ..test_support::prim(local_id, UserId::new(), Vector3::new(10.0, 20.0, 30.0))
+        }
+    }
+
+    #[test]
+    fn test_export_scene_as_glb() {
+        let mut info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        info.size_x = 16;
+        info.size_y = 16;
+        let mut scene = RegionScene::new(info, UserId::new());
+        let sphere = ObjectShape {
+            profile_curve: 5,
+            path_curve: 0x20,
+            ..ObjectShape::default()
+        };
+        scene.add_object(prim(1, ObjectShape::default(), 3));
+        scene.add_object(prim(2, ObjectShape::default(), 3));
+        scene.add_object(prim(3, sphere, 2));
+        let config = GltfExportConfig {
+            terrain_step: 4,
+            ..GltfExportConfig::default()
+        };
+
+        let glb = export_scene(&scene, &config);
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize, glb.len());
+        let json_len = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        assert_eq!(&glb[16..20], b"JSON");
+        let document: Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+        assert_eq!(&glb[24 + json_len..28 + json_len], b"BIN\0");
+
+        // Terrain plus three prims, the two wooden boxes sharing a mesh
+        assert_eq!(document["scenes"][0]["nodes"].as_array().unwrap().len(), 4);
+        assert_eq!(document["meshes"].as_array().unwrap().len(), 3);
+        assert_eq!(document["materials"][2]["alphaMode"], "BLEND");
+        let node = &document["nodes"][1];
+        assert_eq!(node["translation"], json!([10.0, 30.0, -20.0]));
+        assert_eq!(node["scale"], json!([1.0, 3.0, 2.0]));
+        // 16 meters sampled every 4, plus the far edge
+        assert_eq!(document["accessors"][0]["count"], 25);
+        assert_eq!(document["accessors"][0]["max"][0], 15.0);
+
+        let view = &document["bufferViews"][document["images"][0]["bufferView"].as_u64().unwrap() as usize];
+        let offset = 28 + json_len + view["byteOffset"].as_u64().unwrap() as usize;
+        assert_eq!(&glb[offset..offset + 8], b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn test_shapes_face_outward() {
+        for shape in [Shape::Box, Shape::Cylinder, Shape::Prism, Shape::Sphere] {
+            let triangles = shape.triangles();
+            assert!(!triangles.is_empty());
+            for triangle in triangles {
+                let centroid = add(add(triangle[0], triangle[1]), triangle[2]);
+                assert!(dot(face_normal(&triangle), centroid) >= 0.0, "{:?}", shape);
+            }
+        }
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+}
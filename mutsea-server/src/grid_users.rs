@@ -0,0 +1,271 @@
+//! Grid user presence and home and last locations
+//!
+//! The login service keeps where each avatar's home is, where it last
+//! left and whether it is online. With a database those are kept in the
+//! GridUser table too: an avatar's row is loaded the first time it logs
+//! in, and written back whenever it logs in, logs out or sets its home.
+
+use chrono::{DateTime, Utc};
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::{RegionId, UserId, Vector3};
+use mutsea_database::schema::GridUser;
+use mutsea_database::DatabaseManager;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::login::{AgentLocation, GridUserInfo, OpenSimLoginService};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+/// Keeps the login service's grid users and the GridUser table in step
+#[derive(Clone)]
+pub struct GridUsers {
+    login_service: Arc<OpenSimLoginService>,
+    lludp_server: Option<LLUDPServer>,
+    database: Option<Arc<DatabaseManager>>,
+}
+
+impl GridUsers {
+    pub fn new(login_service: Arc<OpenSimLoginService>) -> Self {
+        Self {
+            login_service,
+            lludp_server: None,
+            database: None,
+        }
+    }
+
+    /// Follow the logins, logouts and home changes of the agents connected
+    /// to `lludp_server`
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Keep grid users in `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Record logins, logouts and home changes from the LLUDP server
+    pub fn spawn(&self) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let grid_users = self.clone();
+        let mut events = lludp_server.subscribe_events();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => grid_users.handle(event.event_data).await,
+                    Err(RecvError::Lagged(missed)) => warn!("Grid users missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle(&self, event: NetworkEventData) {
+        match event {
+            NetworkEventData::AgentLoggedIn { agent_id, .. } => self.logged_in(agent_id).await,
+            NetworkEventData::AgentLoggedOut {
+                agent_id,
+                region_id,
+                position,
+                look_at,
+                ..
+            } => self.logged_out(agent_id, region_id, position, look_at).await,
+            NetworkEventData::HomeSet {
+                agent_id,
+                region_id,
+                position,
+                look_at,
+                ..
+            } => self.set_home(agent_id, region_id, position, look_at).await,
+            _ => {}
+        }
+    }
+
+    /// Load the stored row of `user` into the login service, unless it is
+    /// already there, so a login starts at its home or last location
+    pub async fn load(&self, user: UserId) {
+        let Some(database) = &self.database else {
+            return;
+        };
+        if self.login_service.get_grid_user(&user).is_some() {
+            return;
+        }
+        match database.get_or_create_grid_user(&user.to_string()).await {
+            Ok(row) => {
+                let grid_user = grid_user_info(user, &row, &self.login_service.list_regions());
+                self.login_service.load_grid_user(grid_user);
+            }
+            Err(e) => warn!("Failed to load grid user {}: {}", user, e),
+        }
+    }
+
+    async fn logged_in(&self, agent: UserId) {
+        if let Some(database) = &self.database {
+            if let Err(e) = database.record_grid_user_login(&agent.to_string()).await {
+                warn!("Failed to record login of {}: {}", agent, e);
+            }
+        }
+    }
+
+    async fn logged_out(&self, agent: UserId, region: Option<RegionId>, position: Vector3, look_at: Vector3) {
+        let location = region.and_then(|region| self.location(region, position, look_at));
+        self.login_service.record_logout(agent, location);
+
+        if let Some(database) = &self.database {
+            let (region, position, look_at) = row_location(region, position, look_at);
+            if let Err(e) = database
+                .record_grid_user_logout(&agent.to_string(), &region, &position, &look_at)
+                .await
+            {
+                warn!("Failed to record logout of {}: {}", agent, e);
+            }
+        }
+    }
+
+    async fn set_home(&self, agent: UserId, region: Option<RegionId>, position: Vector3, look_at: Vector3) {
+        let Some(location) = region.and_then(|region| self.location(region, position, look_at)) else {
+            warn!("Agent {} set its home in an unknown region", agent);
+            return;
+        };
+        debug!("Agent {} set its home in {}", agent, location.region_name);
+        self.login_service.set_home_location(agent, location);
+
+        if let Some(database) = &self.database {
+            let (region, position, look_at) = row_location(region, position, look_at);
+            if let Err(e) = database
+                .set_grid_user_home(&agent.to_string(), &region, &position, &look_at)
+                .await
+            {
+                warn!("Failed to save home of {}: {}", agent, e);
+            }
+        }
+    }
+
+    /// `position` in `region`, if the login service knows the region
+    fn location(&self, region: RegionId, position: Vector3, look_at: Vector3) -> Option<AgentLocation> {
+        let regions = self.login_service.list_regions();
+        let region = regions.into_iter().find(|r| r.region_id == region)?;
+        Some(AgentLocation {
+            position,
+            look_at,
+            ..region
+        })
+    }
+}
+
+/// A region and where in it, as GridUser columns
+fn row_location(region: Option<RegionId>, position: Vector3, look_at: Vector3) -> (String, String, String) {
+    (
+        region.map(|region| region.to_string()).unwrap_or_else(|| uuid::Uuid::nil().to_string()),
+        GridUser::format_vector(position.x, position.y, position.z),
+        GridUser::format_vector(look_at.x, look_at.y, look_at.z),
+    )
+}
+
+/// The grid user stored in `row`, leaving out locations in regions not
+/// among `regions`
+fn grid_user_info(user: UserId, row: &GridUser, regions: &[AgentLocation]) -> GridUserInfo {
+    let location = |region_id: &str, position: &str, look_at: &str| {
+        let region = regions.iter().find(|r| r.region_id.to_string() == region_id)?;
+        let (x, y, z) = GridUser::parse_vector(position)?;
+        let look_at = GridUser::parse_vector(look_at).map_or(Vector3::new(1.0, 0.0, 0.0), |(x, y, z)| Vector3::new(x, y, z));
+        Some(AgentLocation {
+            position: Vector3::new(x, y, z),
+            look_at,
+            ..region.clone()
+        })
+    };
+    let time = |value: &str| {
+        value
+            .parse::<i64>()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+    };
+
+    GridUserInfo {
+        home: row
+            .has_home()
+            .then(|| location(&row.home_region_id, &row.home_position, &row.home_look_at))
+            .flatten(),
+        last: row
+            .has_last_location()
+            .then(|| location(&row.last_region_id, &row.last_position, &row.last_look_at))
+            .flatten(),
+        online: row.is_online(),
+        login_count: row.login_count.max(0) as u32,
+        last_login: time(&row.login),
+        last_logout: time(&row.logout),
+        ..GridUserInfo::new(user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(name: &str) -> AgentLocation {
+        AgentLocation {
+            region_id: RegionId::new(),
+            region_name: name.to_string(),
+            region_x: 1000,
+            region_y: 1000,
+            position: Vector3::new(128.0, 128.0, 25.0),
+            look_at: Vector3::new(1.0, 0.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn test_grid_user_rows_become_locations() {
+        let plaza = region("Plaza");
+        let user = UserId::new();
+        let (home_region, home_position, home_look_at) =
+            row_location(Some(plaza.region_id), Vector3::new(10.0, 20.0, 30.0), Vector3::new(0.0, 1.0, 0.0));
+        let row = GridUser {
+            home_region_id: home_region,
+            home_position,
+            home_look_at,
+            last_region_id: RegionId::new().to_string(),
+            online: "true".to_string(),
+            login: "1700000000".to_string(),
+            login_count: 3,
+            ..GridUser::new(user.to_string())
+        };
+
+        let info = grid_user_info(user, &row, &[plaza.clone()]);
+        let home = info.home.unwrap();
+        assert_eq!(home.region_id, plaza.region_id);
+        assert_eq!(home.region_name, "Plaza");
+        assert_eq!(home.position, Vector3::new(10.0, 20.0, 30.0));
+        assert_eq!(home.look_at, Vector3::new(0.0, 1.0, 0.0));
+        // The last region is gone from the grid
+        assert!(info.last.is_none());
+        assert!(info.online);
+        assert_eq!(info.login_count, 3);
+        assert_eq!(info.last_login.unwrap().timestamp(), 1_700_000_000);
+        assert!(info.last_logout.is_none());
+    }
+
+    #[test]
+    fn test_unset_locations_stay_unset() {
+        let user = UserId::new();
+        let (region_id, position, look_at) = row_location(None, Vector3::ZERO, Vector3::ZERO);
+        let row = GridUser {
+            last_region_id: region_id,
+            last_position: position,
+            last_look_at: look_at,
+            ..GridUser::new(user.to_string())
+        };
+        assert!(!row.has_last_location());
+
+        let info = grid_user_info(user, &row, &[region("Plaza")]);
+        assert!(info.home.is_none());
+        assert!(info.last.is_none());
+        assert!(!info.online);
+    }
+}
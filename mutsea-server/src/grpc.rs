@@ -0,0 +1,281 @@
+//! mutsea-server/src/grpc.rs
+//! Internal gRPC services of this node
+//!
+//! Assets and user accounts are served from the database, presence from the
+//! LLUDP server's circuits and region control from the region scene, so
+//! other nodes of a split deployment can use them remotely.
+
+use mutsea_core::{config::GrpcConfig, scene::RegionScene, UserId};
+use mutsea_database::{schema, DatabaseManager};
+use mutsea_network::grpc::proto::{
+    self,
+    asset_service_server::{AssetService, AssetServiceServer},
+    presence_service_server::{PresenceService, PresenceServiceServer},
+    region_control_server::{RegionControl, RegionControlServer},
+    user_service_server::{UserService, UserServiceServer},
+};
+use mutsea_network::{lludp_server::CircuitInfo, LLUDPServer};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tonic::{Request, Response, Status};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::cluster::ClusterController;
+
+/// The internal services, implemented over this node's components
+#[derive(Clone)]
+pub struct InternalServices {
+    database: Option<Arc<DatabaseManager>>,
+    lludp_server: LLUDPServer,
+    scene: Arc<RwLock<RegionScene>>,
+    controller: Option<ClusterController>,
+}
+
+impl InternalServices {
+    pub fn new(lludp_server: LLUDPServer, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            database: None,
+            lludp_server,
+            scene,
+            controller: None,
+        }
+    }
+
+    /// Serve assets and user accounts from `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Also serve as the cluster's grid controller
+    pub fn with_controller(mut self, controller: ClusterController) -> Self {
+        self.controller = Some(controller);
+        self
+    }
+
+    /// Serve every service on `config.bind_address` over mutual TLS in the
+    /// background
+    pub async fn spawn(self, config: &GrpcConfig) -> Result<(), Box<dyn std::error::Error>> {
+        let addr: SocketAddr = config.bind_address.parse()?;
+        let router = mutsea_network::grpc::server(config)
+            .await?
+            .add_service(AssetServiceServer::new(self.clone()))
+            .add_service(UserServiceServer::new(self.clone()))
+            .add_service(PresenceServiceServer::new(self.clone()))
+            .add_optional_service(self.controller.as_ref().map(ClusterController::service))
+            .add_service(RegionControlServer::new(self));
+        tokio::spawn(async move {
+            if let Err(e) = router.serve(addr).await {
+                error!("❌ gRPC server stopped: {}", e);
+            }
+        });
+        info!("✅ Internal gRPC services listening on {}", addr);
+        Ok(())
+    }
+
+    fn database(&self) -> Result<&DatabaseManager, Status> {
+        self.database
+            .as_deref()
+            .ok_or_else(|| Status::unavailable("this node has no database"))
+    }
+}
+
+#[tonic::async_trait]
+impl AssetService for InternalServices {
+    async fn get_asset(&self, request: Request<proto::GetAssetRequest>) -> Result<Response<proto::Asset>, Status> {
+        let id = request.into_inner().id;
+        match self.database()?.get_asset(&id).await {
+            Ok(Some(asset)) => Ok(Response::new(asset_message(asset))),
+            Ok(None) => Err(Status::not_found(format!("no asset {}", id))),
+            Err(e) => Err(Status::internal(e.to_string())),
+        }
+    }
+
+    async fn store_asset(&self, request: Request<proto::Asset>) -> Result<Response<proto::StoreAssetResponse>, Status> {
+        let asset = stored_asset(request.into_inner(), chrono::Utc::now().timestamp() as i32)?;
+        self.database()?
+            .insert_asset(&asset)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(proto::StoreAssetResponse { id: asset.id }))
+    }
+}
+
+#[tonic::async_trait]
+impl UserService for InternalServices {
+    async fn get_user(&self, request: Request<proto::GetUserRequest>) -> Result<Response<proto::UserAccount>, Status> {
+        let user_id = request.into_inner().user_id;
+        let database = self.database()?;
+        let display_name = match database.get_display_name(&user_id).await {
+            Ok(stored) => stored.map(|stored| stored.display_name).filter(|name| !name.is_empty()),
+            Err(e) => return Err(Status::internal(e.to_string())),
+        };
+        match database.get_user_account(&user_id).await {
+            Ok(Some(account)) => Ok(Response::new(proto::UserAccount {
+                user_id: account.principal_id,
+                first_name: account.first_name,
+                last_name: account.last_name,
+                email: account.email,
+                created: account.created,
+                user_level: account.user_level,
+                user_flags: account.user_flags,
+                user_title: account.user_title,
+                display_name,
+            })),
+            Ok(None) => Err(Status::not_found(format!("no user {}", user_id))),
+            Err(e) => Err(Status::internal(e.to_string())),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl PresenceService for InternalServices {
+    async fn list_agents(
+        &self,
+        _request: Request<proto::ListAgentsRequest>,
+    ) -> Result<Response<proto::ListAgentsResponse>, Status> {
+        let agents = self
+            .lludp_server
+            .get_all_circuits()
+            .await
+            .iter()
+            .filter_map(presence)
+            .collect();
+        Ok(Response::new(proto::ListAgentsResponse { agents }))
+    }
+
+    async fn get_agent(
+        &self,
+        request: Request<proto::GetAgentRequest>,
+    ) -> Result<Response<proto::AgentPresence>, Status> {
+        let agent_id = request.into_inner().agent_id;
+        let agent = Uuid::parse_str(&agent_id)
+            .map(UserId::from_uuid)
+            .map_err(|_| Status::invalid_argument(format!("invalid agent id {}", agent_id)))?;
+        self.lludp_server
+            .get_all_circuits()
+            .await
+            .iter()
+            .filter(|circuit| circuit.agent_id == Some(agent))
+            .find_map(presence)
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found(format!("agent {} is not here", agent_id)))
+    }
+}
+
+#[tonic::async_trait]
+impl RegionControl for InternalServices {
+    async fn get_status(
+        &self,
+        _request: Request<proto::RegionStatusRequest>,
+    ) -> Result<Response<proto::RegionStatus>, Status> {
+        let agents = self.lludp_server.get_authenticated_circuits_count().await as u32;
+        let scene = self.scene.read().await;
+        Ok(Response::new(proto::RegionStatus {
+            region_id: scene.info.region_id.to_string(),
+            region_name: scene.info.region_name.clone(),
+            location_x: scene.info.location_x,
+            location_y: scene.info.location_y,
+            agents,
+            objects: scene.objects.len() as u32,
+        }))
+    }
+
+    async fn broadcast_alert(
+        &self,
+        request: Request<proto::BroadcastAlertRequest>,
+    ) -> Result<Response<proto::BroadcastAlertResponse>, Status> {
+        let recipients = self
+            .lludp_server
+            .broadcast_alert(&request.into_inner().message)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(proto::BroadcastAlertResponse {
+            recipients: recipients as u32,
+        }))
+    }
+}
+
+fn asset_message(asset: schema::Asset) -> proto::Asset {
+    proto::Asset {
+        id: asset.id,
+        name: asset.name,
+        description: asset.description,
+        asset_type: asset.asset_type,
+        local: asset.local,
+        temporary: asset.temporary,
+        data: asset.data,
+        create_time: asset.create_time,
+        asset_flags: asset.asset_flags,
+        creator_id: asset.creator_id,
+    }
+}
+
+/// The row to keep for `asset`, stored at `now`; assets without an id are
+/// given one
+fn stored_asset(asset: proto::Asset, now: i32) -> Result<schema::Asset, Status> {
+    let id = if asset.id.is_empty() {
+        Uuid::new_v4()
+    } else {
+        Uuid::parse_str(&asset.id).map_err(|_| Status::invalid_argument(format!("invalid asset id {}", asset.id)))?
+    };
+    let create_time = if asset.create_time > 0 { asset.create_time } else { now };
+    Ok(schema::Asset {
+        id: id.to_string(),
+        name: asset.name,
+        description: asset.description,
+        asset_type: asset.asset_type,
+        local: asset.local,
+        temporary: asset.temporary,
+        data: asset.data,
+        create_time,
+        access_time: now,
+        asset_flags: asset.asset_flags,
+        creator_id: asset.creator_id,
+    })
+}
+
+/// The presence of the agent on `circuit`, once it is logged in
+fn presence(circuit: &CircuitInfo) -> Option<proto::AgentPresence> {
+    if !circuit.authenticated {
+        return None;
+    }
+    Some(proto::AgentPresence {
+        agent_id: circuit.agent_id?.to_string(),
+        session_id: circuit.session_id.map(|id| id.to_string()).unwrap_or_default(),
+        circuit_code: circuit.circuit_code,
+        region_id: circuit.region_id.map(|id| id.to_string()).unwrap_or_default(),
+        x: circuit.position.x,
+        y: circuit.position.y,
+        z: circuit.position.z,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stored_assets_get_an_id_and_times() {
+        let asset = proto::Asset {
+            name: "Crate".to_string(),
+            data: vec![1, 2, 3],
+            ..Default::default()
+        };
+        let stored = stored_asset(asset, 1_700_000_000).unwrap();
+        assert!(Uuid::parse_str(&stored.id).is_ok());
+        assert_eq!((stored.create_time, stored.access_time), (1_700_000_000, 1_700_000_000));
+        assert_eq!(asset_message(stored).data, vec![1, 2, 3]);
+
+        let invalid = proto::Asset {
+            id: "not-a-uuid".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            stored_asset(invalid, 0).unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! Liveness and readiness checks
+//!
+//! `/health/live` answers as long as the HTTP server is serving requests.
+//! `/health/ready` asks every registered subsystem for its health, in
+//! parallel and with a timeout, and reports each one in the same shape as
+//! the database layer's `HealthCheckResult`.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mutsea_core::circuit_breaker::CircuitBreaker;
+use mutsea_core::{Service, ServiceHealth, ServiceStatus};
+use mutsea_database::{ingest::AnalyticsIngest, DatabaseManager};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Longest a single subsystem check may take before it is reported unknown
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Something that can report its health
+#[async_trait]
+pub trait HealthSource: Send + Sync {
+    async fn health(&self) -> ServiceHealth;
+}
+
+/// Health of a [`Service`], as reported by its own `health_check`
+pub struct ServiceHealthSource<S>(pub S);
+
+#[async_trait]
+impl<S: Service + Send + Sync> HealthSource for ServiceHealthSource<S> {
+    async fn health(&self) -> ServiceHealth {
+        self.0.health_check().await
+    }
+}
+
+/// Database connectivity, reported unhealthy while its breaker is open
+/// without touching the pool
+pub struct DatabaseHealth {
+    database: Arc<DatabaseManager>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl DatabaseHealth {
+    pub fn new(database: Arc<DatabaseManager>) -> Self {
+        let breaker = Arc::clone(database.circuit_breaker());
+        Self { database, breaker }
+    }
+}
+
+#[async_trait]
+impl HealthSource for DatabaseHealth {
+    async fn health(&self) -> ServiceHealth {
+        let mut metrics = HashMap::new();
+        let retry = self.database.retry_stats();
+        metrics.insert("retries".to_string(), retry.retries as f64);
+        metrics.insert("retry_give_ups".to_string(), retry.give_ups as f64);
+
+        if let Some(retry_after) = self.breaker.retry_after() {
+            return ServiceHealth {
+                status: ServiceStatus::Unhealthy,
+                message: format!("circuit open, probing in {}s", retry_after.as_secs().max(1)),
+                metrics,
+            };
+        }
+
+        let db_metrics = self.database.get_metrics().await;
+        metrics.insert("active_connections".to_string(), db_metrics.active_connections as f64);
+        metrics.insert("failed_queries".to_string(), db_metrics.failed_queries as f64);
+        metrics.insert("checked_out".to_string(), db_metrics.pool.checked_out as f64);
+        metrics.insert("pool_wait_ms".to_string(), db_metrics.pool.average_wait.as_secs_f64() * 1000.0);
+        metrics.insert("leaked_connections".to_string(), db_metrics.pool.leaked as f64);
+
+        match self.database.test_connection().await {
+            Ok(()) if db_metrics.pool.leaked > 0 => ServiceHealth {
+                status: ServiceStatus::Degraded,
+                message: format!("{} connection(s) held past the leak threshold", db_metrics.pool.leaked),
+                metrics,
+            },
+            Ok(()) => ServiceHealth {
+                status: ServiceStatus::Healthy,
+                message: format!("{:?} database reachable", self.database.backend_type()),
+                metrics,
+            },
+            Err(e) => ServiceHealth {
+                status: ServiceStatus::Unhealthy,
+                message: e.to_string(),
+                metrics,
+            },
+        }
+    }
+}
+
+/// Analytics write buffer, degraded while nearly full (records are about to
+/// be dropped)
+pub struct AnalyticsIngestHealth(pub Arc<AnalyticsIngest>);
+
+#[async_trait]
+impl HealthSource for AnalyticsIngestHealth {
+    async fn health(&self) -> ServiceHealth {
+        let stats = self.0.stats();
+        let metrics = HashMap::from([
+            ("buffered".to_string(), stats.buffered as f64),
+            ("dropped".to_string(), stats.dropped as f64),
+            ("failed".to_string(), stats.failed as f64),
+            ("flushed".to_string(), stats.flushed as f64),
+        ]);
+        let fill = stats.buffered as f64 / stats.capacity.max(1) as f64;
+        if fill >= 0.9 {
+            ServiceHealth {
+                status: ServiceStatus::Degraded,
+                message: format!("buffer {:.0}% full, {} records dropped", fill * 100.0, stats.dropped),
+                metrics,
+            }
+        } else {
+            ServiceHealth {
+                status: ServiceStatus::Healthy,
+                message: format!("{} records buffered", stats.buffered),
+                metrics,
+            }
+        }
+    }
+}
+
+/// Result of one subsystem check; same JSON shape as the database layer's
+/// `HealthCheckResult`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubsystemHealth {
+    pub status: ServiceStatus,
+    pub response_time_ms: u64,
+    pub details: HashMap<String, String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl SubsystemHealth {
+    fn from_service_health(health: ServiceHealth, elapsed: Duration) -> Self {
+        let mut details: HashMap<String, String> =
+            health.metrics.into_iter().map(|(k, v)| (k, v.to_string())).collect();
+        details.insert("message".to_string(), health.message);
+        Self {
+            status: health.status,
+            response_time_ms: elapsed.as_millis() as u64,
+            details,
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+/// Readiness of the whole process
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadinessReport {
+    /// Worst status of any subsystem
+    pub status: ServiceStatus,
+    /// Whether the process should receive traffic: no subsystem is
+    /// unhealthy or failed to answer (degraded still counts as ready)
+    pub ready: bool,
+    pub services: BTreeMap<String, SubsystemHealth>,
+}
+
+/// Subsystems checked for readiness
+#[derive(Default)]
+pub struct HealthRegistry {
+    sources: RwLock<BTreeMap<String, Arc<dyn HealthSource>>>,
+    started_at: Option<Instant>,
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        Self {
+            sources: RwLock::new(BTreeMap::new()),
+            started_at: Some(Instant::now()),
+        }
+    }
+
+    /// Add or replace the check for `name`
+    pub fn register(&self, name: &str, source: Arc<dyn HealthSource>) {
+        self.sources.write().unwrap().insert(name.to_string(), source);
+    }
+
+    /// Seconds since the registry was created
+    pub fn uptime_secs(&self) -> u64 {
+        self.started_at.map_or(0, |at| at.elapsed().as_secs())
+    }
+
+    /// Check every subsystem concurrently
+    pub async fn readiness(&self) -> ReadinessReport {
+        let sources: Vec<_> = self
+            .sources
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, source)| (name.clone(), Arc::clone(source)))
+            .collect();
+
+        let checks = sources.into_iter().map(|(name, source)| async move {
+            let start = Instant::now();
+            let health = tokio::time::timeout(CHECK_TIMEOUT, source.health())
+                .await
+                .unwrap_or_else(|_| ServiceHealth {
+                    status: ServiceStatus::Unknown,
+                    message: format!("no answer within {}s", CHECK_TIMEOUT.as_secs()),
+                    metrics: HashMap::new(),
+                });
+            (name, SubsystemHealth::from_service_health(health, start.elapsed()))
+        });
+        let services: BTreeMap<_, _> = futures::future::join_all(checks).await.into_iter().collect();
+
+        let status = services
+            .values()
+            .map(|s| s.status)
+            .max_by_key(|s| severity(*s))
+            .unwrap_or(ServiceStatus::Healthy);
+        ReadinessReport {
+            status,
+            ready: severity(status) < severity(ServiceStatus::Unhealthy),
+            services,
+        }
+    }
+}
+
+/// Ordering of statuses from best to worst
+fn severity(status: ServiceStatus) -> u8 {
+    match status {
+        ServiceStatus::Healthy => 0,
+        ServiceStatus::Degraded => 1,
+        ServiceStatus::Unhealthy => 2,
+        ServiceStatus::Unknown => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(ServiceStatus);
+
+    #[async_trait]
+    impl HealthSource for Fixed {
+        async fn health(&self) -> ServiceHealth {
+            ServiceHealth {
+                status: self.0,
+                message: "fixed".to_string(),
+                metrics: HashMap::from([("connections".to_string(), 2.0)]),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_readiness_takes_worst_status() {
+        let registry = HealthRegistry::new();
+        registry.register("lludp", Arc::new(Fixed(ServiceStatus::Healthy)));
+        registry.register("assets", Arc::new(Fixed(ServiceStatus::Degraded)));
+
+        let report = registry.readiness().await;
+        assert_eq!(report.status, ServiceStatus::Degraded);
+        assert!(report.ready);
+        assert_eq!(report.services["lludp"].details["connections"], "2");
+        assert_eq!(report.services["lludp"].details["message"], "fixed");
+
+        registry.register("database", Arc::new(Fixed(ServiceStatus::Unhealthy)));
+        let report = registry.readiness().await;
+        assert_eq!(report.status, ServiceStatus::Unhealthy);
+        assert!(!report.ready);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["services"]["database"]["status"], "Unhealthy");
+        assert!(json["services"]["database"]["response_time_ms"].is_u64());
+    }
+}
@@ -0,0 +1,360 @@
+//! Idempotency keys for the mutating admin API
+//!
+//! A client that may retry a request sends it with an `Idempotency-Key`
+//! header. The first request with a key runs and its response is kept; a
+//! retry with the same key and body gets that response back instead of
+//! creating the user or granting the money a second time. Keys are scoped to
+//! the API key and route, remembered for a configured time and kept in a
+//! JSON file so retries across a restart are safe too.
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header::CONTENT_TYPE, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Duration, Utc};
+use mutsea_protocol::api_keys::ApiKeyRecord;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Header carrying the client's idempotency key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Header set on responses replayed from the store
+pub const IDEMPOTENT_REPLAY_HEADER: &str = "idempotent-replayed";
+
+/// Longest idempotency key accepted
+const MAX_KEY_LEN: usize = 255;
+
+/// Largest request body read to fingerprint a request
+const MAX_BODY_BYTES: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum IdempotencyError {
+    #[error("Idempotency store I/O: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid idempotency store: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// The response to a completed request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredResponse {
+    /// SHA-256 of the request's method, path and body
+    pub request_hash: String,
+    pub status: u16,
+    pub content_type: Option<String>,
+    /// Base64 of the response body
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What to do with a request carrying a key
+#[derive(Debug, PartialEq)]
+pub enum Claim {
+    /// First use of the key; run the request, then complete or release it
+    New,
+    /// The request already ran; answer with its response
+    Replay(StoredResponse),
+    /// The same key is running right now
+    InProgress,
+    /// The key was used for a different request
+    Mismatch,
+}
+
+#[derive(Default)]
+struct StoreState {
+    responses: HashMap<String, StoredResponse>,
+    running: HashSet<String>,
+}
+
+/// Responses by idempotency key, optionally persisted to a JSON file
+pub struct IdempotencyStore {
+    path: Option<PathBuf>,
+    ttl: Duration,
+    state: Mutex<StoreState>,
+}
+
+impl IdempotencyStore {
+    /// In-memory store, used by tests
+    pub fn in_memory(ttl: Duration) -> Self {
+        Self {
+            path: None,
+            ttl,
+            state: Mutex::new(StoreState::default()),
+        }
+    }
+
+    /// Open the store at `path`; a missing file is an empty store
+    pub fn open(path: impl Into<PathBuf>, ttl: Duration) -> Result<Self, IdempotencyError> {
+        let path = path.into();
+        let responses = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: Some(path),
+            ttl,
+            state: Mutex::new(StoreState {
+                responses,
+                running: HashSet::new(),
+            }),
+        })
+    }
+
+    /// Claim `key` for the request fingerprinted by `request_hash`
+    pub fn claim(&self, key: &str, request_hash: &str, now: DateTime<Utc>) -> Claim {
+        let mut state = self.state.lock().unwrap();
+        let ttl = self.ttl;
+        state.responses.retain(|_, response| now - response.created_at < ttl);
+
+        if let Some(response) = state.responses.get(key) {
+            return if response.request_hash == request_hash {
+                Claim::Replay(response.clone())
+            } else {
+                Claim::Mismatch
+            };
+        }
+        if !state.running.insert(key.to_string()) {
+            return Claim::InProgress;
+        }
+        Claim::New
+    }
+
+    /// Keep `response` as the answer to the request that claimed `key`
+    pub fn complete(&self, key: &str, response: StoredResponse) -> Result<(), IdempotencyError> {
+        let mut state = self.state.lock().unwrap();
+        state.running.remove(key);
+        state.responses.insert(key.to_string(), response);
+        self.save(&state)
+    }
+
+    /// Forget the claim on `key` so the request can be retried
+    pub fn release(&self, key: &str) {
+        self.state.lock().unwrap().running.remove(key);
+    }
+
+    /// Number of responses kept
+    pub fn kept(&self) -> usize {
+        self.state.lock().unwrap().responses.len()
+    }
+
+    fn save(&self, state: &StoreState) -> Result<(), IdempotencyError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Write-then-rename so a crash never leaves a partial file
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string(&state.responses)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Run mutating requests that carry an `Idempotency-Key` at most once,
+/// replaying the kept response to retries. Requests without the header, and
+/// reads, pass straight through. Server errors are not kept, so a request
+/// that failed that way may be retried with the same key.
+pub async fn idempotent(State(store): State<Arc<IdempotencyStore>>, request: Request, next: Next) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return next.run(request).await;
+    };
+    let Some(key) = key.to_str().ok().map(str::trim).filter(|key| valid_key(key)).map(str::to_string) else {
+        return error(
+            StatusCode::BAD_REQUEST,
+            &format!("{} must be 1 to {} visible ASCII characters", IDEMPOTENCY_KEY_HEADER, MAX_KEY_LEN),
+        );
+    };
+
+    let principal = request
+        .extensions()
+        .get::<ApiKeyRecord>()
+        .map_or_else(|| "anonymous".to_string(), |record| record.id.clone());
+    let scoped_key = format!("{}:{}:{}", principal, request.uri().path(), key);
+
+    let (parts, body) = request.into_parts();
+    let Ok(body) = to_bytes(body, MAX_BODY_BYTES).await else {
+        return error(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large");
+    };
+    let request_hash = fingerprint(parts.method.as_str(), parts.uri.path(), &body);
+
+    match store.claim(&scoped_key, &request_hash, Utc::now()) {
+        Claim::New => {}
+        Claim::Replay(response) => {
+            debug!("Replaying the response to idempotency key {}", key);
+            return replay(response);
+        }
+        Claim::InProgress => {
+            return error(StatusCode::CONFLICT, "A request with this idempotency key is still running");
+        }
+        Claim::Mismatch => {
+            return error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "This idempotency key was already used for a different request",
+            );
+        }
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if response.status().is_server_error() {
+        store.release(&scoped_key);
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            store.release(&scoped_key);
+            warn!("Failed to read the response to idempotency key {}: {}", key, e);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read response");
+        }
+    };
+    let stored = StoredResponse {
+        request_hash,
+        status: parts.status.as_u16(),
+        content_type: parts
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        body: BASE64.encode(&body),
+        created_at: Utc::now(),
+    };
+    if let Err(e) = store.complete(&scoped_key, stored) {
+        // The request ran; a retry after a restart may run it again
+        warn!("Failed to persist idempotency key {}: {}", key, e);
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+fn valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LEN && key.bytes().all(|b| b.is_ascii_graphic())
+}
+
+fn fingerprint(method: &str, path: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_bytes());
+    hasher.update(b" ");
+    hasher.update(path.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn replay(response: StoredResponse) -> Response {
+    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::OK);
+    let body = BASE64.decode(&response.body).unwrap_or_default();
+    let mut replayed = (status, body).into_response();
+    let headers = replayed.headers_mut();
+    if let Some(content_type) = response.content_type.and_then(|value| HeaderValue::from_str(&value).ok()) {
+        headers.insert(CONTENT_TYPE, content_type);
+    }
+    headers.insert(IDEMPOTENT_REPLAY_HEADER, HeaderValue::from_static("true"));
+    replayed
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{middleware, routing::post, Router};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tower::ServiceExt;
+
+    fn app(store: Arc<IdempotencyStore>, created: Arc<AtomicU32>) -> Router {
+        Router::new()
+            .route(
+                "/users",
+                post(move |body: String| async move {
+                    let id = created.fetch_add(1, Ordering::SeqCst);
+                    (StatusCode::CREATED, Json(serde_json::json!({ "id": id, "name": body })))
+                }),
+            )
+            .route_layer(middleware::from_fn_with_state(store, idempotent))
+    }
+
+    fn request(key: Option<&str>, body: &str) -> Request {
+        let mut request = Request::builder().method(Method::POST).uri("/users");
+        if let Some(key) = key {
+            request = request.header(IDEMPOTENCY_KEY_HEADER, key);
+        }
+        request.body(Body::from(body.to_string())).unwrap()
+    }
+
+    async fn send(app: &Router, request: Request) -> (StatusCode, bool, String) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let replayed = response.headers().contains_key(IDEMPOTENT_REPLAY_HEADER);
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, replayed, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_retries_replay_the_first_response() {
+        let store = Arc::new(IdempotencyStore::in_memory(Duration::hours(1)));
+        let created = Arc::new(AtomicU32::new(0));
+        let app = app(Arc::clone(&store), Arc::clone(&created));
+
+        let first = send(&app, request(Some("create-ann"), "ann")).await;
+        let retry = send(&app, request(Some("create-ann"), "ann")).await;
+        assert_eq!(first.0, StatusCode::CREATED);
+        assert!(!first.1 && retry.1);
+        assert_eq!((retry.0, &retry.2), (first.0, &first.2));
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+
+        // A different body under the same key is refused
+        let reused = send(&app, request(Some("create-ann"), "bob")).await;
+        assert_eq!(reused.0, StatusCode::UNPROCESSABLE_ENTITY);
+
+        // Requests without a key run every time
+        send(&app, request(None, "ann")).await;
+        send(&app, request(None, "ann")).await;
+        assert_eq!(created.load(Ordering::SeqCst), 3);
+
+        assert_eq!(send(&app, request(Some("bad key"), "ann")).await.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_keys_expire_and_survive_restarts() {
+        let path = std::env::temp_dir().join(format!("mutsea-idempotency-{}.json", uuid::Uuid::new_v4()));
+        let now = Utc::now();
+        let response = StoredResponse {
+            request_hash: "abc".to_string(),
+            status: 201,
+            content_type: Some("application/json".to_string()),
+            body: BASE64.encode(b"{}"),
+            created_at: now,
+        };
+
+        let store = IdempotencyStore::open(&path, Duration::hours(1)).unwrap();
+        assert_eq!(store.claim("k", "abc", now), Claim::New);
+        assert_eq!(store.claim("k", "abc", now), Claim::InProgress);
+        store.complete("k", response.clone()).unwrap();
+
+        let reopened = IdempotencyStore::open(&path, Duration::hours(1)).unwrap();
+        assert_eq!(reopened.claim("k", "abc", now), Claim::Replay(response));
+        assert_eq!(reopened.claim("k", "other", now), Claim::Mismatch);
+        assert_eq!(reopened.claim("k", "abc", now + Duration::hours(2)), Claim::New);
+        assert_eq!(reopened.kept(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
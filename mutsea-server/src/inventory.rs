@@ -0,0 +1,271 @@
+//! Agents' inventories, for viewers fetching them over capabilities
+//!
+//! FetchInventoryDescendents2 answers the folders a viewer asks for from
+//! the inventoryfolders and inventoryitems tables, a page at a time: the
+//! folders past `max_folders_per_fetch`, or past `max_entries_per_fetch`
+//! folders and items, are left for the viewer to ask for again. A folder is
+//! always answered whole, however much it holds. Agents only see their own
+//! folders and items.
+//!
+//! Items the region puts in an agent's inventory, such as returned prims,
+//! are sent to its viewer as BulkUpdateInventory events so they show up
+//! without a refetch.
+
+use crate::event_queue::EventQueue;
+use flate2::{write::GzEncoder, Compression};
+use mutsea_core::config::InventoryConfig;
+use mutsea_core::UserId;
+use mutsea_database::schema::{InventoryFolder, InventoryItem};
+use mutsea_database::DatabaseManager;
+use mutsea_protocol::inventory::{
+    encode_bulk_update_inventory, BadFolder, FolderContents, FolderData, FolderRequest, ItemData, ItemRequest,
+};
+use std::io::Write;
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::debug;
+use uuid::Uuid;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum InventoryError {
+    #[error("Inventory can't be fetched without a database")]
+    NoDatabase,
+    #[error("Inventory unavailable: {0}")]
+    Database(String),
+}
+
+/// One answer to FetchInventoryDescendents2
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DescendentsPage {
+    pub folders: Vec<FolderContents>,
+    pub bad_folders: Vec<BadFolder>,
+    /// Folders left for the viewer to ask for again
+    pub deferred: usize,
+}
+
+/// Reads agents' inventories for their viewers
+#[derive(Clone)]
+pub struct Inventory {
+    config: InventoryConfig,
+    database: Option<Arc<DatabaseManager>>,
+    event_queue: Option<EventQueue>,
+}
+
+impl Inventory {
+    pub fn new(config: InventoryConfig) -> Self {
+        Self {
+            config,
+            database: None,
+            event_queue: None,
+        }
+    }
+
+    /// Read inventories from `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Tell the viewers polling `event_queue` of items added to their
+    /// inventories
+    pub fn with_event_queue(mut self, event_queue: EventQueue) -> Self {
+        self.event_queue = Some(event_queue);
+        self
+    }
+
+    /// Whether an answer of `len` bytes is worth gzipping
+    pub fn should_compress(&self, len: usize) -> bool {
+        len >= self.config.compress_min_bytes
+    }
+
+    /// `agent` fetches the folders of `requests`, as many as fit one page
+    pub async fn fetch_descendents(
+        &self,
+        agent: UserId,
+        requests: &[FolderRequest],
+    ) -> Result<DescendentsPage, InventoryError> {
+        let database = self.database.as_ref().ok_or(InventoryError::NoDatabase)?;
+        let database_error = |e: mutsea_database::DatabaseError| InventoryError::Database(e.to_string());
+        let mut page = DescendentsPage::default();
+        let mut entries = 0;
+        for (i, request) in requests.iter().enumerate() {
+            let folder_id = request.folder_id.to_string();
+            let folder = database.get_inventory_folder(&folder_id).await.map_err(database_error)?;
+            let Some(folder) = folder
+                .filter(|folder| folder.agent_id == agent.to_string())
+                .and_then(|folder| folder_data(&folder))
+            else {
+                page.bad_folders.push(BadFolder {
+                    folder_id: request.folder_id,
+                    error: "Unknown folder".to_string(),
+                });
+                continue;
+            };
+            let categories: Vec<FolderData> = database
+                .get_inventory_subfolders(&folder_id)
+                .await
+                .map_err(database_error)?
+                .iter()
+                .filter_map(folder_data)
+                .collect();
+            let items: Vec<ItemData> = database
+                .get_inventory_folder_items(&folder_id)
+                .await
+                .map_err(database_error)?
+                .iter()
+                .filter_map(item_data)
+                .collect();
+
+            let descendents = categories.len() + items.len();
+            let sent = request.fetch_folders as usize * categories.len() + request.fetch_items as usize * items.len();
+            if !fits(&self.config, page.folders.len(), entries, sent) {
+                page.deferred = requests.len() - i;
+                break;
+            }
+            entries += sent;
+            page.folders.push(FolderContents {
+                folder,
+                descendents,
+                categories: if request.fetch_folders { categories } else { Vec::new() },
+                items: if request.fetch_items { items } else { Vec::new() },
+            });
+        }
+        if page.deferred > 0 {
+            debug!("Left {} folders of agent {} for its next fetch", page.deferred, agent);
+        }
+        Ok(page)
+    }
+
+    /// `agent` fetches the items of `requests`; those that aren't its own
+    /// are left out
+    pub async fn fetch_items(&self, agent: UserId, requests: &[ItemRequest]) -> Result<Vec<ItemData>, InventoryError> {
+        let database = self.database.as_ref().ok_or(InventoryError::NoDatabase)?;
+        let mut items = Vec::new();
+        for request in requests.iter().take(self.config.max_entries_per_fetch) {
+            let item = database
+                .get_inventory_item(&request.item_id.to_string(), &agent.to_string())
+                .await
+                .map_err(|e| InventoryError::Database(e.to_string()))?;
+            items.extend(item.as_ref().and_then(item_data));
+        }
+        Ok(items)
+    }
+
+    /// Show `agent` the `items` added to its inventory
+    pub fn announce_items(&self, agent: UserId, items: &[InventoryItem]) {
+        let Some(event_queue) = &self.event_queue else {
+            return;
+        };
+        let items: Vec<ItemData> = items.iter().filter_map(item_data).collect();
+        event_queue.post(agent, "BulkUpdateInventory", encode_bulk_update_inventory(agent, &[], &items));
+    }
+}
+
+/// Whether a folder sending `sent` folders and items fits a page already
+/// holding `folders` folders and `entries` entries. The first always fits.
+fn fits(config: &InventoryConfig, folders: usize, entries: usize, sent: usize) -> bool {
+    folders == 0 || (folders < config.max_folders_per_fetch && entries + sent <= config.max_entries_per_fetch)
+}
+
+/// A stored folder as viewers are told of it
+pub fn folder_data(folder: &InventoryFolder) -> Option<FolderData> {
+    Some(FolderData {
+        folder_id: Uuid::parse_str(&folder.folder_id).ok()?,
+        parent_id: Uuid::parse_str(&folder.parent_folder_id).unwrap_or_default(),
+        owner_id: UserId::from_uuid(Uuid::parse_str(&folder.agent_id).ok()?),
+        name: folder.name.clone(),
+        folder_type: folder.folder_type,
+        version: folder.version,
+    })
+}
+
+/// A stored item as viewers are told of it
+pub fn item_data(item: &InventoryItem) -> Option<ItemData> {
+    let owner_id = UserId::from_uuid(Uuid::parse_str(&item.avatar_id).ok()?);
+    Some(ItemData {
+        item_id: Uuid::parse_str(&item.inventory_id).ok()?,
+        parent_id: Uuid::parse_str(&item.parent_folder_id).unwrap_or_default(),
+        asset_id: Uuid::parse_str(&item.asset_id).unwrap_or_default(),
+        owner_id,
+        creator_id: Uuid::parse_str(&item.creator_id).map(UserId::from_uuid).unwrap_or(owner_id),
+        name: item.name.clone(),
+        description: item.description.clone(),
+        asset_type: item.asset_type,
+        inv_type: item.inv_type,
+        base_mask: item.base_permissions as u32,
+        owner_mask: item.current_permissions as u32,
+        everyone_mask: item.everyone_permissions as u32,
+        next_owner_mask: item.next_permissions as u32,
+        created_at: item.creation_date as i64,
+    })
+}
+
+/// `body` gzipped, for viewers that sent `Accept-Encoding: gzip`
+pub fn gzip(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pages() {
+        let config = InventoryConfig {
+            max_folders_per_fetch: 3,
+            max_entries_per_fetch: 10,
+            ..InventoryConfig::default()
+        };
+        // The first folder is answered however much it holds
+        assert!(fits(&config, 0, 0, 50));
+        assert!(fits(&config, 1, 6, 4));
+        assert!(!fits(&config, 1, 6, 5));
+        assert!(!fits(&config, 3, 0, 0));
+    }
+
+    #[test]
+    fn test_stored_entries() {
+        let (agent, folder) = (Uuid::new_v4(), Uuid::new_v4());
+        let stored = InventoryItem {
+            inventory_id: Uuid::new_v4().to_string(),
+            asset_id: Uuid::new_v4().to_string(),
+            asset_type: 6,
+            parent_folder_id: folder.to_string(),
+            avatar_id: agent.to_string(),
+            name: "Chair".to_string(),
+            description: String::new(),
+            next_permissions: 0x7fff_ffff,
+            current_permissions: 0x7fff_ffff,
+            inv_type: 6,
+            creator_id: String::new(),
+            base_permissions: 0x7fff_ffff,
+            everyone_permissions: 0,
+            creation_date: 1_700_000_000,
+        };
+        let item = item_data(&stored).unwrap();
+        assert_eq!((item.parent_id, item.owner_id), (folder, UserId::from_uuid(agent)));
+        assert_eq!(item.creator_id, item.owner_id);
+        assert!(item_data(&InventoryItem {
+            inventory_id: "nonsense".to_string(),
+            ..stored
+        })
+        .is_none());
+
+        let root = InventoryFolder {
+            folder_id: folder.to_string(),
+            agent_id: agent.to_string(),
+            parent_folder_id: Uuid::nil().to_string(),
+            name: "My Inventory".to_string(),
+            folder_type: 8,
+            version: 1,
+        };
+        assert_eq!(folder_data(&root).unwrap().folder_id, folder);
+
+        let body = vec![b'a'; 4096];
+        let mut decoded = Vec::new();
+        std::io::Read::read_to_end(&mut flate2::read::GzDecoder::new(&gzip(&body).unwrap()[..]), &mut decoded).unwrap();
+        assert_eq!(decoded, body);
+    }
+}
@@ -0,0 +1,620 @@
+//! Parcel sales and land auctions
+//!
+//! Owners put their parcels up for sale from the About Land floater, at a
+//! price, to anyone or to one agent, with or without their prims on it. A
+//! purchase is only made at the price and area the buyer was shown, pays
+//! the owner through the economy and hands the parcel over; prims sold
+//! with it change owner, the others stay with the seller. Administrators
+//! auction parcels through the admin API instead: bids are kept in the
+//! database, and once an auction ends the highest bidder who can still pay
+//! is charged and given the parcel.
+
+use crate::economy::{Economy, EconomyError};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use mutsea_core::config::LandConfig;
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::UserId;
+use mutsea_database::land_auctions::{AuctionStatus, Bid, LandAuction};
+use mutsea_database::{DatabaseError, DatabaseManager};
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::land::{PARCEL_FLAG_FOR_SALE, PARCEL_FLAG_FOR_SALE_OBJECTS, TRANSACTION_LAND_SALE};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Error)]
+pub enum LandError {
+    #[error("No parcel has local ID {0}")]
+    UnknownLocalId(i32),
+    #[error("Parcel {0} doesn't exist")]
+    UnknownParcel(Uuid),
+    #[error("You can only sell land you own")]
+    NotOwner,
+    #[error("The parcel \"{0}\" is not for sale")]
+    NotForSale(String),
+    #[error("The parcel \"{0}\" is for sale to someone else")]
+    NotAuthorized(String),
+    #[error("The sale of the parcel \"{0}\" has changed")]
+    SaleChanged(String),
+    #[error("You already own the parcel \"{0}\"")]
+    OwnParcel(String),
+    #[error("The parcel \"{0}\" is being auctioned")]
+    Auctioned(String),
+    #[error("L${0} is not a price land can be sold at")]
+    InvalidPrice(i64),
+    #[error("Land auction {0} doesn't exist")]
+    UnknownAuction(Uuid),
+    #[error("The bid was refused: {0}")]
+    BidRefused(String),
+    #[error("Land can't be bought without an economy")]
+    NoEconomy,
+    #[error("Land auctions need a database")]
+    NoDatabase,
+    #[error(transparent)]
+    Economy(#[from] EconomyError),
+    #[error("Land auctions unavailable: {0}")]
+    Database(String),
+}
+
+impl From<DatabaseError> for LandError {
+    fn from(e: DatabaseError) -> Self {
+        match e {
+            DatabaseError::Validation(reason) => LandError::BidRefused(reason),
+            e => LandError::Database(e.to_string()),
+        }
+    }
+}
+
+/// How a parcel is for sale
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ParcelSale {
+    pub price: i32,
+    /// The only agent allowed to buy the parcel
+    pub auth_buyer: Option<UserId>,
+    /// The owner's prims on the parcel are sold with it
+    pub include_objects: bool,
+}
+
+/// A parcel bought
+#[derive(Debug, Clone, Serialize)]
+pub struct LandPurchase {
+    pub parcel_id: Uuid,
+    pub seller: UserId,
+    pub buyer: UserId,
+    pub price: i64,
+    /// Prims that changed owner with the parcel
+    pub objects: usize,
+}
+
+/// An auction with its bids, highest first
+#[derive(Debug, Clone, Serialize)]
+pub struct AuctionDetails {
+    #[serde(flatten)]
+    pub auction: LandAuction,
+    pub bids: Vec<Bid>,
+}
+
+/// Give the parcel `parcel_id` of `scene` to `buyer`, taking it off sale,
+/// with its seller's prims on it if `include_objects`. Returns how many
+/// prims changed owner.
+pub fn transfer_parcel(scene: &mut RegionScene, parcel_id: Uuid, buyer: UserId, include_objects: bool) -> usize {
+    let Some(parcel) = scene.parcels.iter_mut().find(|parcel| parcel.id == parcel_id) else {
+        return 0;
+    };
+    let seller = parcel.owner_id;
+    parcel.owner_id = buyer;
+    parcel.group_id = None;
+    parcel.flags &= !(PARCEL_FLAG_FOR_SALE | PARCEL_FLAG_FOR_SALE_OBJECTS);
+    if !include_objects {
+        return 0;
+    }
+
+    let parcel = parcel.clone();
+    let now = Utc::now();
+    let mut transferred = 0;
+    for object in scene.objects.values_mut() {
+        if object.owner_id == seller && parcel.contains(object.position.x, object.position.y) {
+            object.owner_id = buyer;
+            object.last_updated = now;
+            transferred += 1;
+        }
+    }
+    transferred
+}
+
+#[derive(Default)]
+struct State {
+    sales: HashMap<Uuid, ParcelSale>,
+    /// Parcels in an open auction
+    auctioned: HashSet<Uuid>,
+}
+
+/// Parcels for sale and land auctions of one region. Clones share them.
+#[derive(Clone)]
+pub struct LandSales {
+    config: LandConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: Option<LLUDPServer>,
+    economy: Option<Economy>,
+    database: Option<Arc<DatabaseManager>>,
+    state: Arc<Mutex<State>>,
+}
+
+impl LandSales {
+    pub fn new(config: LandConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            config,
+            scene,
+            lludp_server: None,
+            economy: None,
+            database: None,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Handle the sales and purchases of the agents connected to
+    /// `lludp_server`
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Pay for land from balances in `economy`
+    pub fn with_economy(mut self, economy: Economy) -> Self {
+        self.economy = Some(economy);
+        self
+    }
+
+    /// Keep auctions and their bids in `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Create the auction tables and read the open auctions
+    pub async fn load(&self) {
+        let Some(database) = &self.database else {
+            return;
+        };
+        if let Err(e) = database.initialize_land_auctions().await {
+            warn!("Failed to create the land auction tables: {}", e);
+            return;
+        }
+        let region_id = self.scene.read().await.info.region_id.as_uuid();
+        match database.open_land_auctions(region_id).await {
+            Ok(auctions) => {
+                let mut state = self.state.lock().unwrap();
+                state.auctioned = auctions.iter().map(|auction| auction.parcel_id).collect();
+                info!("🏷️ {} land auctions open", auctions.len());
+            }
+            Err(e) => warn!("Failed to load the land auctions: {}", e),
+        }
+    }
+
+    /// How the parcel `parcel_id` is for sale, if it is
+    pub fn sale(&self, parcel_id: Uuid) -> Option<ParcelSale> {
+        self.state.lock().unwrap().sales.get(&parcel_id).copied()
+    }
+
+    /// `agent` puts its parcel `parcel_id` up for sale, or takes it off
+    /// sale with no `sale`
+    pub async fn set_sale(&self, agent: UserId, parcel_id: Uuid, sale: Option<ParcelSale>) -> Result<(), LandError> {
+        if let Some(sale) = sale.filter(|sale| sale.price < 0) {
+            return Err(LandError::InvalidPrice(sale.price as i64));
+        }
+        let mut scene = self.scene.write().await;
+        let parcel = scene
+            .parcels
+            .iter_mut()
+            .find(|parcel| parcel.id == parcel_id)
+            .ok_or(LandError::UnknownParcel(parcel_id))?;
+        if parcel.owner_id != agent {
+            return Err(LandError::NotOwner);
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.auctioned.contains(&parcel_id) {
+            return Err(LandError::Auctioned(parcel.name.clone()));
+        }
+
+        parcel.flags &= !(PARCEL_FLAG_FOR_SALE | PARCEL_FLAG_FOR_SALE_OBJECTS);
+        match sale {
+            Some(sale) => {
+                parcel.flags |= PARCEL_FLAG_FOR_SALE;
+                if sale.include_objects {
+                    parcel.flags |= PARCEL_FLAG_FOR_SALE_OBJECTS;
+                }
+                state.sales.insert(parcel_id, sale);
+                info!("Parcel \"{}\" for sale at L${}", parcel.name, sale.price);
+            }
+            None => {
+                if state.sales.remove(&parcel_id).is_some() {
+                    info!("Parcel \"{}\" taken off sale", parcel.name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `buyer` buys the parcel `parcel_id` at the `price` and `area` it was
+    /// shown
+    pub async fn buy(&self, buyer: UserId, parcel_id: Uuid, price: i32, area: i32) -> Result<LandPurchase, LandError> {
+        let economy = self.economy.as_ref().ok_or(LandError::NoEconomy)?;
+        let (seller, name, sale) = {
+            let scene = self.scene.read().await;
+            let parcel = scene
+                .parcels
+                .iter()
+                .find(|parcel| parcel.id == parcel_id)
+                .ok_or(LandError::UnknownParcel(parcel_id))?;
+            let mut state = self.state.lock().unwrap();
+            let sale = *state
+                .sales
+                .get(&parcel_id)
+                .ok_or_else(|| LandError::NotForSale(parcel.name.clone()))?;
+            if parcel.owner_id == buyer {
+                return Err(LandError::OwnParcel(parcel.name.clone()));
+            }
+            if sale.auth_buyer.map_or(false, |auth_buyer| auth_buyer != buyer) {
+                return Err(LandError::NotAuthorized(parcel.name.clone()));
+            }
+            if sale.price != price || parcel.area as i32 != area {
+                return Err(LandError::SaleChanged(parcel.name.clone()));
+            }
+            // Nobody else buys it while it is paid for
+            state.sales.remove(&parcel_id);
+            (parcel.owner_id, parcel.name.clone(), sale)
+        };
+
+        let description = format!("Land sale: {}", name);
+        if let Err(e) = economy
+            .transfer(buyer, seller, sale.price as i64, TRANSACTION_LAND_SALE, &description)
+            .await
+        {
+            self.state.lock().unwrap().sales.insert(parcel_id, sale);
+            return Err(e.into());
+        }
+        let objects = transfer_parcel(&mut *self.scene.write().await, parcel_id, buyer, sale.include_objects);
+        info!(
+            "Agent {} bought parcel \"{}\" from {} for L${} with {} prims",
+            buyer, name, seller, sale.price, objects
+        );
+        Ok(LandPurchase {
+            parcel_id,
+            seller,
+            buyer,
+            price: sale.price as i64,
+            objects,
+        })
+    }
+
+    /// Auction the parcel `parcel_id` for its owner, starting at
+    /// `starting_bid` and ending after `duration_secs`
+    pub async fn start_auction(
+        &self,
+        parcel_id: Uuid,
+        starting_bid: i64,
+        duration_secs: u64,
+    ) -> Result<LandAuction, LandError> {
+        let database = self.database.as_ref().ok_or(LandError::NoDatabase)?;
+        if starting_bid < 0 {
+            return Err(LandError::InvalidPrice(starting_bid));
+        }
+        let (region_id, seller) = {
+            let mut scene = self.scene.write().await;
+            let region_id = scene.info.region_id.as_uuid();
+            let parcel = scene
+                .parcels
+                .iter_mut()
+                .find(|parcel| parcel.id == parcel_id)
+                .ok_or(LandError::UnknownParcel(parcel_id))?;
+            let mut state = self.state.lock().unwrap();
+            if !state.auctioned.insert(parcel_id) {
+                return Err(LandError::Auctioned(parcel.name.clone()));
+            }
+            // An auctioned parcel can't also be bought outright
+            state.sales.remove(&parcel_id);
+            parcel.flags &= !(PARCEL_FLAG_FOR_SALE | PARCEL_FLAG_FOR_SALE_OBJECTS);
+            (region_id, parcel.owner_id)
+        };
+
+        let started_at = Utc::now();
+        let auction = LandAuction {
+            id: Uuid::new_v4(),
+            region_id,
+            parcel_id,
+            seller_id: seller.as_uuid(),
+            starting_bid,
+            started_at,
+            ends_at: started_at + ChronoDuration::seconds(duration_secs as i64),
+            status: AuctionStatus::Open,
+            winner_id: None,
+            winning_bid: None,
+        };
+        if let Err(e) = database.create_land_auction(&auction).await {
+            self.state.lock().unwrap().auctioned.remove(&parcel_id);
+            return Err(e.into());
+        }
+        info!(
+            "Parcel {} auctioned from L${} until {}",
+            parcel_id, starting_bid, auction.ends_at
+        );
+        Ok(auction)
+    }
+
+    /// The open auctions of the region, ending first first
+    pub async fn auctions(&self) -> Result<Vec<LandAuction>, LandError> {
+        let database = self.database.as_ref().ok_or(LandError::NoDatabase)?;
+        let region_id = self.scene.read().await.info.region_id.as_uuid();
+        Ok(database.open_land_auctions(region_id).await?)
+    }
+
+    /// The auction `auction_id` and its bids
+    pub async fn auction(&self, auction_id: Uuid) -> Result<AuctionDetails, LandError> {
+        let database = self.database.as_ref().ok_or(LandError::NoDatabase)?;
+        let auction = database
+            .get_land_auction(auction_id)
+            .await?
+            .ok_or(LandError::UnknownAuction(auction_id))?;
+        let bids = database.land_auction_bids(auction_id).await?;
+        Ok(AuctionDetails { auction, bids })
+    }
+
+    /// `bidder` bids `amount` on the auction `auction_id`, which it must be
+    /// able to pay now
+    pub async fn bid(&self, auction_id: Uuid, bidder: UserId, amount: i64) -> Result<Bid, LandError> {
+        let database = self.database.as_ref().ok_or(LandError::NoDatabase)?;
+        let economy = self.economy.as_ref().ok_or(LandError::NoEconomy)?;
+        let balance = economy.balance(bidder).await?;
+        if balance < amount {
+            return Err(EconomyError::InsufficientFunds { balance, amount }.into());
+        }
+        let bid = database.place_land_bid(auction_id, bidder.as_uuid(), amount).await?;
+        debug!("Agent {} bid L${} on land auction {}", bidder, amount, auction_id);
+        Ok(bid)
+    }
+
+    /// Settle the auctions that ended by `now`: the highest bidder who can
+    /// pay buys the parcel, and auctions nobody could pay for end unsold
+    pub async fn settle(&self, now: DateTime<Utc>) -> Result<usize, LandError> {
+        let database = self.database.as_ref().ok_or(LandError::NoDatabase)?;
+        let ended: Vec<LandAuction> = self
+            .auctions()
+            .await?
+            .into_iter()
+            .filter(|auction| auction.ends_at <= now)
+            .collect();
+        for auction in &ended {
+            let (status, winner) = self.settle_auction(auction).await?;
+            database.close_land_auction(auction.id, status, winner).await?;
+            self.state.lock().unwrap().auctioned.remove(&auction.parcel_id);
+        }
+        Ok(ended.len())
+    }
+
+    async fn settle_auction(&self, auction: &LandAuction) -> Result<(AuctionStatus, Option<(Uuid, i64)>), LandError> {
+        let database = self.database.as_ref().ok_or(LandError::NoDatabase)?;
+        let seller = UserId::from_uuid(auction.seller_id);
+        let name = {
+            let scene = self.scene.read().await;
+            match scene.parcels.iter().find(|parcel| parcel.id == auction.parcel_id) {
+                Some(parcel) if parcel.owner_id == seller => parcel.name.clone(),
+                // Merged, subdivided or given away since it was auctioned
+                _ => {
+                    warn!("Land auction {} cancelled: its parcel changed", auction.id);
+                    return Ok((AuctionStatus::Cancelled, None));
+                }
+            }
+        };
+        let Some(economy) = &self.economy else {
+            return Err(LandError::NoEconomy);
+        };
+
+        let description = format!("Land auction: {}", name);
+        for bid in database.land_auction_bids(auction.id).await? {
+            let bidder = UserId::from_uuid(bid.bidder_id);
+            match economy
+                .transfer(bidder, seller, bid.amount, TRANSACTION_LAND_SALE, &description)
+                .await
+            {
+                Ok(_) => {
+                    // Auctions sell the land alone
+                    transfer_parcel(&mut *self.scene.write().await, auction.parcel_id, bidder, false);
+                    info!(
+                        "Land auction {} won by {} for L${}: parcel \"{}\"",
+                        auction.id, bidder, bid.amount, name
+                    );
+                    self.alert(bidder, &format!("You won the auction of \"{}\" for L${}", name, bid.amount))
+                        .await;
+                    return Ok((AuctionStatus::Sold, Some((bid.bidder_id, bid.amount))));
+                }
+                Err(e @ (EconomyError::InsufficientFunds { .. } | EconomyError::SelfPayment)) => {
+                    debug!("Skipping bid of L${} by {}: {}", bid.amount, bidder, e);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        info!("Land auction {} ended unsold", auction.id);
+        Ok((AuctionStatus::Unsold, None))
+    }
+
+    /// Handle parcel sales from the LLUDP server and settle ended auctions
+    /// every `auction_interval_secs`
+    pub fn spawn(&self) {
+        if self.database.is_some() {
+            let land = self.clone();
+            let interval = Duration::from_secs(self.config.auction_interval_secs.max(1));
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    ticker.tick().await;
+                    if let Err(e) = land.settle(Utc::now()).await {
+                        warn!("Failed to settle land auctions: {}", e);
+                    }
+                }
+            });
+        }
+
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let land = self.clone();
+        let mut events = lludp_server.subscribe_events();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => land.handle(event.event_data).await,
+                    Err(RecvError::Lagged(missed)) => warn!("Land sales missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle(&self, event: NetworkEventData) {
+        let (agent, result) = match event {
+            NetworkEventData::ParcelSaleSet {
+                agent_id,
+                local_id,
+                for_sale,
+                price,
+                auth_buyer_id,
+                include_objects,
+                ..
+            } => {
+                let sale = for_sale.then_some(ParcelSale {
+                    price,
+                    auth_buyer: auth_buyer_id.map(UserId::from_uuid),
+                    include_objects,
+                });
+                let result = match self.by_local_id(local_id).await {
+                    Ok(parcel_id) if self.sale(parcel_id) != sale => self.set_sale(agent_id, parcel_id, sale).await,
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(e),
+                };
+                (agent_id, result)
+            }
+            NetworkEventData::ParcelBuyRequested {
+                agent_id,
+                local_id,
+                price,
+                area,
+                ..
+            } => {
+                let result = match self.by_local_id(local_id).await {
+                    Ok(parcel_id) => self.buy(agent_id, parcel_id, price, area).await.map(drop),
+                    Err(e) => Err(e),
+                };
+                (agent_id, result)
+            }
+            _ => return,
+        };
+        if let Err(e) = result {
+            self.alert(agent, &e.to_string()).await;
+        }
+    }
+
+    async fn by_local_id(&self, local_id: i32) -> Result<Uuid, LandError> {
+        self.scene
+            .read()
+            .await
+            .parcels
+            .iter()
+            .find(|parcel| parcel.local_id == local_id)
+            .map(|parcel| parcel.id)
+            .ok_or(LandError::UnknownLocalId(local_id))
+    }
+
+    async fn alert(&self, agent: UserId, message: &str) {
+        if let Some(lludp_server) = &self.lludp_server {
+            if let Err(e) = lludp_server.send_agent_alert(agent, false, message).await {
+                warn!("Failed to alert agent {}: {}", agent, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::config::EconomyConfig;
+    use crate::test_support;
+    use mutsea_core::{RegionInfo, SceneObject, Vector3};
+
+    fn prim(owner_id: UserId, position: Vector3) -> SceneObject {
+        test_support::prim(1, owner_id, position)
+    }
+
+    #[test]
+    fn test_transfer_moves_only_the_sellers_prims_sold() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let seller = UserId::new();
+        let mut scene = RegionScene::new(info, seller);
+        let parcel_id = scene.parcels[0].id;
+        scene.parcels[0].flags |= PARCEL_FLAG_FOR_SALE | PARCEL_FLAG_FOR_SALE_OBJECTS;
+        let neighbour = UserId::new();
+        scene.add_object(prim(seller, Vector3::new(10.0, 10.0, 20.0)));
+        scene.add_object(prim(neighbour, Vector3::new(20.0, 10.0, 20.0)));
+
+        let buyer = UserId::new();
+        assert_eq!(transfer_parcel(&mut scene, parcel_id, buyer, true), 1);
+        assert_eq!(scene.parcels[0].owner_id, buyer);
+        assert_eq!(scene.parcels[0].flags & PARCEL_FLAG_FOR_SALE, 0);
+        let owners: HashSet<UserId> = scene.objects.values().map(|object| object.owner_id).collect();
+        assert_eq!(owners, HashSet::from([buyer, neighbour]));
+
+        // Without its prims, the parcel changes hands alone
+        assert_eq!(transfer_parcel(&mut scene, parcel_id, seller, false), 0);
+        assert_eq!(scene.parcels[0].owner_id, seller);
+    }
+
+    #[tokio::test]
+    async fn test_purchases_are_checked_and_paid() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let seller = UserId::new();
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, seller)));
+        let (parcel_id, area) = {
+            let scene = scene.read().await;
+            (scene.parcels[0].id, scene.parcels[0].area as i32)
+        };
+        let economy = Economy::new(EconomyConfig::default(), Arc::clone(&scene));
+        let land = LandSales::new(LandConfig::default(), Arc::clone(&scene)).with_economy(economy.clone());
+        let (buyer, other) = (UserId::new(), UserId::new());
+
+        assert!(matches!(
+            land.buy(buyer, parcel_id, 100, area).await,
+            Err(LandError::NotForSale(_))
+        ));
+        let sale = ParcelSale {
+            price: 100,
+            auth_buyer: Some(buyer),
+            include_objects: false,
+        };
+        assert!(matches!(
+            land.set_sale(other, parcel_id, Some(sale)).await,
+            Err(LandError::NotOwner)
+        ));
+        land.set_sale(seller, parcel_id, Some(sale)).await.unwrap();
+        assert!(matches!(
+            land.buy(other, parcel_id, 100, area).await,
+            Err(LandError::NotAuthorized(_))
+        ));
+        assert!(matches!(
+            land.buy(buyer, parcel_id, 50, area).await,
+            Err(LandError::SaleChanged(_))
+        ));
+
+        let purchase = land.buy(buyer, parcel_id, 100, area).await.unwrap();
+        assert_eq!(purchase.seller, seller);
+        assert_eq!(scene.read().await.parcels[0].owner_id, buyer);
+        assert_eq!(economy.balance(buyer).await.unwrap(), 900);
+        assert_eq!(economy.balance(seller).await.unwrap(), 1100);
+        assert_eq!(land.sale(parcel_id), None);
+    }
+}
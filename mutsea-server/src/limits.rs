@@ -0,0 +1,276 @@
+//! Limits on what agents build, wear, run and upload
+//!
+//! The services adding prims, attachments, scripts and assets ask
+//! [`Limits`] before doing so, and pass its error on to the viewer as is.
+//! Each limit has a default from the configuration; administrators
+//! override it for the whole region or for one parcel or agent, and the
+//! most specific value applies. A parcel's prim limit defaults to its
+//! share of the region's by area. Uploads are counted per UTC day.
+
+use chrono::{NaiveDate, Utc};
+use mutsea_core::config::LimitsConfig;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{UserId, Vector3};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum LimitError {
+    #[error("This region can't hold more than {0} prims")]
+    RegionPrims(u64),
+    #[error("The parcel \"{0}\" can't hold more than {1} prims")]
+    ParcelPrims(String, u64),
+    #[error("You can't wear more than {0} attachments")]
+    Attachments(u64),
+    #[error("You can't run more than {0} scripts in this region")]
+    Scripts(u64),
+    #[error("You can't upload more than {0} KB a day")]
+    Uploads(u64),
+}
+
+/// A limit administrators may override
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Limit {
+    RegionPrims,
+    ParcelPrims,
+    Attachments,
+    Scripts,
+    UploadBytesPerDay,
+}
+
+/// A limit set by an administrator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Override {
+    pub limit: Limit,
+    /// The parcel or agent it applies to, the whole region when absent
+    #[serde(default)]
+    pub subject: Option<Uuid>,
+    pub value: u64,
+}
+
+#[derive(Default)]
+struct State {
+    overrides: HashMap<(Limit, Option<Uuid>), u64>,
+    /// Bytes each agent uploaded on the day
+    uploads: HashMap<UserId, (NaiveDate, u64)>,
+}
+
+/// The limits of one region. Clones share the overrides and upload counts.
+#[derive(Clone)]
+pub struct Limits {
+    config: LimitsConfig,
+    max_attachments: usize,
+    state: Arc<Mutex<State>>,
+}
+
+impl Limits {
+    /// Limits from `config`, with avatars wearing at most `max_attachments`
+    pub fn new(config: LimitsConfig, max_attachments: usize) -> Self {
+        Self {
+            config,
+            max_attachments,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    fn configured(&self, limit: Limit) -> u64 {
+        match limit {
+            Limit::RegionPrims | Limit::ParcelPrims => self.config.max_prims_per_region,
+            Limit::Attachments => self.max_attachments as u64,
+            Limit::Scripts => self.config.max_scripts_per_owner,
+            Limit::UploadBytesPerDay => self.config.max_upload_bytes_per_day,
+        }
+    }
+
+    /// The value of `limit` for `subject`, overridden or configured
+    pub fn limit(&self, limit: Limit, subject: Option<Uuid>) -> u64 {
+        let state = self.state.lock().unwrap();
+        subject
+            .and_then(|subject| state.overrides.get(&(limit, Some(subject))))
+            .or_else(|| state.overrides.get(&(limit, None)))
+            .copied()
+            .unwrap_or_else(|| self.configured(limit))
+    }
+
+    /// Set `limit` for `subject`, or go back to the default with no value
+    pub fn set_override(&self, limit: Limit, subject: Option<Uuid>, value: Option<u64>) {
+        let mut state = self.state.lock().unwrap();
+        match value {
+            Some(value) => {
+                state.overrides.insert((limit, subject), value);
+                info!("Limit {:?} for {:?} overridden to {}", limit, subject, value);
+            }
+            None => {
+                state.overrides.remove(&(limit, subject));
+                info!("Limit {:?} for {:?} back to its default", limit, subject);
+            }
+        }
+    }
+
+    /// Every override in place
+    pub fn overrides(&self) -> Vec<Override> {
+        let state = self.state.lock().unwrap();
+        state
+            .overrides
+            .iter()
+            .map(|(&(limit, subject), &value)| Override { limit, subject, value })
+            .collect()
+    }
+
+    /// Whether `adding` more prims fit in `scene` at `position`
+    pub fn check_prims(&self, scene: &RegionScene, position: Vector3, adding: usize) -> Result<(), LimitError> {
+        let region_limit = self.limit(Limit::RegionPrims, None);
+        if (scene.objects.len() + adding) as u64 > region_limit {
+            return Err(LimitError::RegionPrims(region_limit));
+        }
+        let Some(parcel) = scene.parcel_at(position.x, position.y) else {
+            return Ok(());
+        };
+        let parcel_limit = {
+            let state = self.state.lock().unwrap();
+            state.overrides.get(&(Limit::ParcelPrims, Some(parcel.id))).copied()
+        }
+        .unwrap_or_else(|| {
+            let region_area = (scene.info.size_x as u64 * scene.info.size_y as u64).max(1);
+            region_limit * parcel.area as u64 / region_area
+        });
+        let on_parcel = scene
+            .objects
+            .values()
+            .filter(|object| parcel.contains(object.position.x, object.position.y))
+            .count();
+        if (on_parcel + adding) as u64 > parcel_limit {
+            return Err(LimitError::ParcelPrims(parcel.name.clone(), parcel_limit));
+        }
+        Ok(())
+    }
+
+    /// Whether `agent`, wearing `worn` attachments, may wear another
+    pub fn check_attachments(&self, agent: UserId, worn: usize) -> Result<(), LimitError> {
+        let limit = self.limit(Limit::Attachments, Some(agent.as_uuid()));
+        if worn as u64 >= limit {
+            return Err(LimitError::Attachments(limit));
+        }
+        Ok(())
+    }
+
+    /// Whether `owner`, running `running` scripts, may start another
+    pub fn check_scripts(&self, owner: UserId, running: usize) -> Result<(), LimitError> {
+        let limit = self.limit(Limit::Scripts, Some(owner.as_uuid()));
+        if running as u64 >= limit {
+            return Err(LimitError::Scripts(limit));
+        }
+        Ok(())
+    }
+
+    /// Count an upload of `bytes` by `agent` against its allowance for
+    /// today, refusing it if it doesn't fit
+    pub fn reserve_upload(&self, agent: UserId, bytes: usize) -> Result<(), LimitError> {
+        self.reserve_upload_on(agent, bytes as u64, Utc::now().date_naive())
+    }
+
+    fn reserve_upload_on(&self, agent: UserId, bytes: u64, today: NaiveDate) -> Result<(), LimitError> {
+        let limit = self.limit(Limit::UploadBytesPerDay, Some(agent.as_uuid()));
+        let mut state = self.state.lock().unwrap();
+        let uploaded = state.uploads.entry(agent).or_insert((today, 0));
+        if uploaded.0 != today {
+            *uploaded = (today, 0);
+        }
+        if uploaded.1 + bytes > limit {
+            return Err(LimitError::Uploads(limit / 1024));
+        }
+        uploaded.1 += bytes;
+        Ok(())
+    }
+
+    /// Bytes `agent` uploaded today
+    pub fn uploaded_today(&self, agent: UserId) -> u64 {
+        let today = Utc::now().date_naive();
+        let state = self.state.lock().unwrap();
+        state
+            .uploads
+            .get(&agent)
+            .filter(|(day, _)| *day == today)
+            .map_or(0, |(_, bytes)| *bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use mutsea_core::{RegionInfo, SceneObject};
+
+    fn prim(position: Vector3) -> SceneObject {
+        test_support::prim(1, UserId::new(), position)
+    }
+
+    fn limits() -> Limits {
+        Limits::new(
+            LimitsConfig {
+                enabled: true,
+                max_prims_per_region: 4,
+                max_scripts_per_owner: 2,
+                max_upload_bytes_per_day: 1000,
+            },
+            3,
+        )
+    }
+
+    #[test]
+    fn test_overrides_apply_most_specific_first() {
+        let limits = limits();
+        let agent = UserId::new();
+        assert_eq!(limits.limit(Limit::Scripts, Some(agent.as_uuid())), 2);
+
+        limits.set_override(Limit::Scripts, None, Some(5));
+        limits.set_override(Limit::Scripts, Some(agent.as_uuid()), Some(10));
+        assert_eq!(limits.limit(Limit::Scripts, Some(agent.as_uuid())), 10);
+        assert_eq!(limits.limit(Limit::Scripts, Some(Uuid::new_v4())), 5);
+        assert!(limits.check_scripts(agent, 9).is_ok());
+
+        limits.set_override(Limit::Scripts, Some(agent.as_uuid()), None);
+        assert_eq!(limits.check_scripts(agent, 5), Err(LimitError::Scripts(5)));
+        assert_eq!(limits.overrides().len(), 1);
+    }
+
+    #[test]
+    fn test_parcels_get_a_share_of_the_region() {
+        let limits = limits();
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let mut scene = RegionScene::new(info, UserId::new());
+        // The west half of the region
+        let parcel = &mut scene.parcels[0];
+        parcel.area /= 2;
+        parcel.bitmap = (0..512).map(|byte| if byte % 8 < 4 { 0xFF } else { 0 }).collect();
+        let west = Vector3::new(10.0, 10.0, 20.0);
+
+        scene.add_object(prim(west));
+        assert!(limits.check_prims(&scene, west, 1).is_ok());
+        scene.add_object(prim(west));
+        assert_eq!(
+            limits.check_prims(&scene, west, 1),
+            Err(LimitError::ParcelPrims("Your Parcel".to_string(), 2))
+        );
+        assert!(limits.check_prims(&scene, Vector3::new(200.0, 10.0, 20.0), 2).is_ok());
+        assert_eq!(
+            limits.check_prims(&scene, Vector3::new(200.0, 10.0, 20.0), 3),
+            Err(LimitError::RegionPrims(4))
+        );
+    }
+
+    #[test]
+    fn test_uploads_are_counted_per_day() {
+        let limits = limits();
+        let agent = UserId::new();
+        let today = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        assert!(limits.reserve_upload_on(agent, 600, today).is_ok());
+        assert!(limits.reserve_upload_on(agent, 600, today).is_err());
+        assert!(limits.reserve_upload_on(agent, 600, today.succ_opt().unwrap()).is_ok());
+    }
+}
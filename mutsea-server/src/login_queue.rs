@@ -0,0 +1,228 @@
+//! Login queue
+//!
+//! After a restart every viewer logs back in at once, and each login is
+//! followed by a circuit, an avatar to rez and a region's worth of prims to
+//! stream. Logins whose password checks out are let through while fewer
+//! than `max_concurrent` are still on their way into the region; the rest
+//! fail with their place in the queue and are let through in that order as
+//! they retry and slots free up.
+
+use mutsea_core::config::LoginQueueConfig;
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::UserId;
+use mutsea_network::LLUDPServer;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+/// Whether a login may go ahead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Admitted,
+    /// Waiting, first in line at position 1
+    Queued { position: usize, retry_after: Duration },
+}
+
+impl Admission {
+    /// The login failure shown to a queued agent
+    pub fn message(&self) -> Option<String> {
+        match self {
+            Admission::Admitted => None,
+            Admission::Queued { position, retry_after } => Some(format!(
+                "The grid is busy. You are number {} in the login queue; please log in again in {} seconds to keep your place.",
+                position,
+                retry_after.as_secs().max(1)
+            )),
+        }
+    }
+}
+
+/// Logins on their way in and waiting
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LoginQueueStatus {
+    pub in_flight: usize,
+    pub queued: usize,
+    pub max_concurrent: usize,
+    pub admitted: u64,
+    pub turned_away: u64,
+}
+
+#[derive(Default)]
+struct State {
+    /// When each admitted agent was let through, until it arrives
+    in_flight: HashMap<UserId, Instant>,
+    /// Recent admissions, for the per-second limit
+    recent: VecDeque<Instant>,
+    /// Agents waiting, first in line first, with when they last tried
+    waiting: VecDeque<(UserId, Instant)>,
+    admitted: u64,
+    turned_away: u64,
+}
+
+/// Admits logins while the grid has room for them. Clones share the queue.
+#[derive(Clone)]
+pub struct LoginQueue {
+    config: LoginQueueConfig,
+    lludp_server: Option<LLUDPServer>,
+    state: Arc<Mutex<State>>,
+}
+
+impl LoginQueue {
+    pub fn new(config: LoginQueueConfig) -> Self {
+        Self {
+            config,
+            lludp_server: None,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Free an agent's slot as its circuit reaches the region
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    pub fn spawn(&self) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let queue = self.clone();
+        let mut events = lludp_server.subscribe_events();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        if let NetworkEventData::AgentLoggedIn { agent_id, .. } = event.event_data {
+                            queue.release(agent_id);
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => warn!("Login queue missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Whether `agent`, whose password checked out, may log in now. An
+    /// admitted login holds a slot until it is released.
+    pub fn admit(&self, agent: UserId, now: Instant) -> Admission {
+        let mut state = self.state.lock().unwrap();
+        let arrival_timeout = Duration::from_secs(self.config.arrival_timeout_secs);
+        let queue_timeout = Duration::from_secs(self.config.queue_timeout_secs);
+        state.in_flight.retain(|_, admitted| now.duration_since(*admitted) < arrival_timeout);
+        state.waiting.retain(|(_, tried)| now.duration_since(*tried) < queue_timeout);
+        while state.recent.front().is_some_and(|at| now.duration_since(*at) >= Duration::from_secs(1)) {
+            state.recent.pop_front();
+        }
+
+        // A retry of a login already let through
+        if let Some(admitted) = state.in_flight.get_mut(&agent) {
+            *admitted = now;
+            return Admission::Admitted;
+        }
+
+        let free = self
+            .config
+            .max_concurrent
+            .saturating_sub(state.in_flight.len())
+            .min(self.config.max_per_second.saturating_sub(state.recent.len()));
+        let index = match state.waiting.iter().position(|(waiting, _)| *waiting == agent) {
+            Some(index) => {
+                state.waiting[index].1 = now;
+                index
+            }
+            None => {
+                state.waiting.push_back((agent, now));
+                state.waiting.len() - 1
+            }
+        };
+
+        if index < free {
+            state.waiting.remove(index);
+            state.in_flight.insert(agent, now);
+            state.recent.push_back(now);
+            state.admitted += 1;
+            return Admission::Admitted;
+        }
+
+        state.turned_away += 1;
+        let position = index + 1;
+        // Those ahead are let through at most `max_per_second` a second
+        let retry_after = Duration::from_secs((position / self.config.max_per_second.max(1)) as u64 + 5)
+            .min(queue_timeout / 2);
+        Admission::Queued { position, retry_after }
+    }
+
+    /// Free `agent`'s slot, as its circuit reaches the region or its login
+    /// fails after all
+    pub fn release(&self, agent: UserId) {
+        self.state.lock().unwrap().in_flight.remove(&agent);
+    }
+
+    pub fn status(&self) -> LoginQueueStatus {
+        let state = self.state.lock().unwrap();
+        LoginQueueStatus {
+            in_flight: state.in_flight.len(),
+            queued: state.waiting.len(),
+            max_concurrent: self.config.max_concurrent,
+            admitted: state.admitted,
+            turned_away: state.turned_away,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logins_queue_in_order_until_slots_free() {
+        let queue = LoginQueue::new(LoginQueueConfig {
+            max_concurrent: 2,
+            max_per_second: 10,
+            ..LoginQueueConfig::default()
+        });
+        let agents: Vec<UserId> = (0..4).map(|_| UserId::new()).collect();
+        let now = Instant::now();
+
+        assert_eq!(queue.admit(agents[0], now), Admission::Admitted);
+        assert_eq!(queue.admit(agents[1], now), Admission::Admitted);
+        assert!(matches!(queue.admit(agents[2], now), Admission::Queued { position: 1, .. }));
+        assert!(matches!(queue.admit(agents[3], now), Admission::Queued { position: 2, .. }));
+        assert!(queue.admit(agents[3], now).message().unwrap().contains("number 2"));
+
+        // The second in line waits for the first while only one slot is free
+        queue.release(agents[0]);
+        assert!(matches!(queue.admit(agents[3], now), Admission::Queued { position: 2, .. }));
+        assert_eq!(queue.admit(agents[2], now), Admission::Admitted);
+        assert!(matches!(queue.admit(agents[3], now), Admission::Queued { position: 1, .. }));
+
+        // Slots of logins that never arrive free up, and so do places in
+        // line of agents that stop retrying
+        let later = now + Duration::from_secs(61);
+        assert_eq!(queue.admit(agents[3], later), Admission::Admitted);
+        let status = queue.status();
+        assert_eq!((status.in_flight, status.queued, status.admitted), (1, 0, 4));
+    }
+
+    #[test]
+    fn test_admissions_per_second_are_limited() {
+        let queue = LoginQueue::new(LoginQueueConfig {
+            max_concurrent: 10,
+            max_per_second: 1,
+            ..LoginQueueConfig::default()
+        });
+        let (first, second) = (UserId::new(), UserId::new());
+        let now = Instant::now();
+
+        assert_eq!(queue.admit(first, now), Admission::Admitted);
+        assert!(matches!(queue.admit(second, now), Admission::Queued { position: 1, .. }));
+        assert_eq!(queue.admit(second, now + Duration::from_secs(1)), Admission::Admitted);
+        // Retrying a login already let through doesn't count again
+        assert_eq!(queue.admit(first, now + Duration::from_secs(1)), Admission::Admitted);
+    }
+}
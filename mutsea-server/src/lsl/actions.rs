@@ -0,0 +1,272 @@
+//! Carrying out what compiled scripts ask of the region
+//!
+//! Library functions with effects outside the script, such as chat or
+//! moving the prim, are queued as `ScriptAction`s while the handler runs.
+//! `ActionDispatcher` applies them to the scene and tells the viewers.
+
+use mutsea_core::scene::RegionScene;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::chat::{ChatFromSimulator, SAY_RANGE, SHOUT_RANGE, WHISPER_RANGE};
+use mutsea_protocol::constants::chat_types;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use super::library::{ChatVolume, ScriptAction};
+use crate::remote_data::RemoteData;
+use crate::script_email::ScriptEmail;
+use crate::script_http::ScriptHttp;
+use crate::scripts::{ScriptError, ScriptEvent, ScriptSandbox};
+use crate::sounds::Sounds;
+
+/// Shortest timer interval; llSetTimerEvent below this is raised to it
+const MIN_TIMER_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct ActionDispatcher {
+    lludp_server: LLUDPServer,
+    scene: Arc<RwLock<RegionScene>>,
+    sandbox: ScriptSandbox,
+    http: Arc<ScriptHttp>,
+    email: Arc<ScriptEmail>,
+    remote_data: Arc<RemoteData>,
+    sounds: Option<Sounds>,
+    /// Running llSetTimerEvent timers, by script ID
+    timers: Mutex<HashMap<Uuid, JoinHandle<()>>>,
+}
+
+impl ActionDispatcher {
+    pub fn new(
+        lludp_server: LLUDPServer,
+        scene: Arc<RwLock<RegionScene>>,
+        sandbox: ScriptSandbox,
+        http: Arc<ScriptHttp>,
+        email: Arc<ScriptEmail>,
+        remote_data: Arc<RemoteData>,
+    ) -> Self {
+        Self {
+            lludp_server,
+            scene,
+            sandbox,
+            http,
+            email,
+            remote_data,
+            sounds: None,
+            timers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Play the sounds scripts ask for through `sounds`; without it they
+    /// are silent
+    pub fn with_sounds(mut self, sounds: Sounds) -> Self {
+        self.sounds = Some(sounds);
+        self
+    }
+
+    /// Apply `actions` as scripts queue them
+    pub fn spawn(self: &Arc<Self>, mut actions: UnboundedReceiver<ScriptAction>) {
+        let dispatcher = Arc::clone(self);
+        tokio::spawn(async move {
+            while let Some(action) = actions.recv().await {
+                dispatcher.apply(action).await;
+            }
+        });
+    }
+
+    async fn apply(&self, action: ScriptAction) {
+        match action {
+            ScriptAction::Chat {
+                object_id,
+                name,
+                position,
+                channel,
+                volume,
+                message,
+            } => {
+                // Nothing listens on other channels yet
+                if channel != 0 {
+                    debug!("Dropped chat from {} on channel {}", name, channel);
+                    return;
+                }
+                let mut chat = ChatFromSimulator::said_by_object(&name, object_id.as_uuid(), position, &message);
+                let range = match volume {
+                    ChatVolume::Whisper => {
+                        chat.chat_type = chat_types::WHISPER;
+                        WHISPER_RANGE
+                    }
+                    ChatVolume::Say => SAY_RANGE,
+                    ChatVolume::Shout => {
+                        chat.chat_type = chat_types::SHOUT;
+                        SHOUT_RANGE
+                    }
+                    ChatVolume::Region => f32::INFINITY,
+                };
+                if let Err(e) = self.lludp_server.send_local_chat(&chat, range).await {
+                    warn!("Failed to send chat from {}: {}", name, e);
+                }
+            }
+            ScriptAction::OwnerSay {
+                owner_id,
+                object_id,
+                name,
+                position,
+                message,
+            } => {
+                let mut chat = ChatFromSimulator::said_by_object(&name, object_id.as_uuid(), position, &message);
+                chat.owner_id = owner_id.as_uuid();
+                chat.chat_type = chat_types::OWNER;
+                if let Err(e) = self.lludp_server.send_chat_to_agent(owner_id, &chat).await {
+                    warn!("Failed to send {} a message from {}: {}", owner_id, name, e);
+                }
+            }
+            ScriptAction::SetTimer { script, interval } => self.set_timer(script, interval),
+            ScriptAction::SetPosition { object_id, position } => {
+                let object = {
+                    let mut scene = self.scene.write().await;
+                    scene.objects.get_mut(&object_id).map(|object| {
+                        object.position = position;
+                        object.last_updated = chrono::Utc::now();
+                        object.clone()
+                    })
+                };
+                if let Some(object) = object {
+                    if let Err(e) = self.lludp_server.send_object_update(&object).await {
+                        warn!("Failed to send update of {}: {}", object_id, e);
+                    }
+                }
+            }
+            ScriptAction::SetRotation { object_id, rotation } => {
+                let object = {
+                    let mut scene = self.scene.write().await;
+                    scene.objects.get_mut(&object_id).map(|object| {
+                        object.rotation = rotation;
+                        object.last_updated = chrono::Utc::now();
+                        object.clone()
+                    })
+                };
+                if let Some(object) = object {
+                    if let Err(e) = self.lludp_server.send_object_update(&object).await {
+                        warn!("Failed to send update of {}: {}", object_id, e);
+                    }
+                }
+            }
+            ScriptAction::Die { object_id } => {
+                let removed = self.scene.write().await.objects.remove(&object_id);
+                self.email.forget_object(object_id);
+                if let Some(sounds) = &self.sounds {
+                    sounds.forget(object_id);
+                }
+                for script in self.sandbox.remove_object(object_id) {
+                    self.http.release_script(script);
+                    self.remote_data.release_script(script);
+                    self.set_timer(script, 0.0);
+                }
+                if let Some(object) = removed {
+                    if let Err(e) = self.lludp_server.send_kill_objects(&[object.local_id]).await {
+                        warn!("Failed to send kill of {}: {}", object_id, e);
+                    }
+                }
+            }
+            ScriptAction::HttpRequest {
+                script,
+                owner_id,
+                request_id,
+                request,
+            } => self.http.request(script, owner_id, request_id, request),
+            ScriptAction::HttpResponse {
+                request_id,
+                status,
+                body,
+            } => {
+                if !self.http.respond(request_id, status, body) {
+                    debug!("No request {} waiting for a response", request_id);
+                }
+            }
+            ScriptAction::RequestUrl { script, request_id } => self.http.grant_url(script, request_id),
+            ScriptAction::ReleaseUrl { script, url } => self.http.release_url(script, &url),
+            ScriptAction::Email(email) => {
+                let object_id = email.object_id;
+                if let Err(e) = self.email.send(email) {
+                    debug!("Dropped email from {}: {}", object_id, e);
+                }
+            }
+            ScriptAction::NextEmail {
+                script,
+                object_id,
+                address,
+                subject,
+            } => self.email.next(script, object_id, &address, &subject),
+            ScriptAction::OpenRemoteData { script } => self.remote_data.open(script),
+            ScriptAction::RemoteDataReply {
+                message_id,
+                sdata,
+                idata,
+            } => {
+                if !self.remote_data.reply(message_id, sdata, idata) {
+                    debug!("No remote data call {} waiting for a reply", message_id);
+                }
+            }
+            ScriptAction::CloseRemoteData { script, channel } => self.remote_data.close(script, channel),
+            ScriptAction::PlaySound { .. }
+            | ScriptAction::TriggerSound { .. }
+            | ScriptAction::StopSound { .. }
+            | ScriptAction::PreloadSound { .. }
+            | ScriptAction::AdjustSoundVolume { .. } => self.sound(action).await,
+        }
+    }
+
+    async fn sound(&self, action: ScriptAction) {
+        let Some(sounds) = &self.sounds else {
+            return;
+        };
+        let result = match action {
+            ScriptAction::PlaySound {
+                object_id,
+                sound,
+                gain,
+                looped,
+            } => sounds.play(object_id, sound, gain, looped).await,
+            ScriptAction::TriggerSound { object_id, sound, gain } => sounds.trigger(object_id, sound, gain).await,
+            ScriptAction::StopSound { object_id } => sounds.stop(object_id).await,
+            ScriptAction::PreloadSound { object_id, sound } => sounds.preload(object_id, sound).await,
+            ScriptAction::AdjustSoundVolume { object_id, gain } => sounds.set_volume(object_id, gain).await,
+            _ => return,
+        };
+        if let Err(e) = result {
+            debug!("Dropped a script's sound: {}", e);
+        }
+    }
+
+    /// Post `timer` to `script` every `interval` seconds, replacing its
+    /// previous timer; 0 or less stops it
+    fn set_timer(&self, script: Uuid, interval: f32) {
+        let mut timers = self.timers.lock().unwrap();
+        if let Some(previous) = timers.remove(&script) {
+            previous.abort();
+        }
+        if interval <= 0.0 || !interval.is_finite() {
+            return;
+        }
+
+        let period = Duration::from_secs_f32(interval).max(MIN_TIMER_INTERVAL);
+        let sandbox = self.sandbox.clone();
+        let timer = tokio::spawn(async move {
+            let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                // A full queue drops the tick; the timer stops once the
+                // script is removed
+                if let Err(ScriptError::UnknownScript(_)) = sandbox.post(script, ScriptEvent::new("timer", Vec::new()))
+                {
+                    break;
+                }
+            }
+        });
+        timers.insert(script, timer);
+    }
+}
@@ -0,0 +1,270 @@
+//! Syntax tree of an LSL script
+
+use serde::{Deserialize, Serialize};
+
+/// An LSL value type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Type {
+    Integer,
+    Float,
+    String,
+    Key,
+    Vector,
+    Rotation,
+    List,
+}
+
+impl Type {
+    pub fn name(self) -> &'static str {
+        match self {
+            Type::Integer => "integer",
+            Type::Float => "float",
+            Type::String => "string",
+            Type::Key => "key",
+            Type::Vector => "vector",
+            Type::Rotation => "rotation",
+            Type::List => "list",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Type> {
+        Some(match name {
+            "integer" => Type::Integer,
+            "float" => Type::Float,
+            "string" => Type::String,
+            "key" => Type::Key,
+            "vector" => Type::Vector,
+            "rotation" | "quaternion" => Type::Rotation,
+            "list" => Type::List,
+            _ => return None,
+        })
+    }
+
+    /// Code passed to the host for casts
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    pub fn from_code(code: i32) -> Option<Type> {
+        [
+            Type::Integer,
+            Type::Float,
+            Type::String,
+            Type::Key,
+            Type::Vector,
+            Type::Rotation,
+            Type::List,
+        ]
+        .get(usize::try_from(code).ok()?)
+        .copied()
+    }
+
+    /// Held by the host and referred to by handle
+    pub fn is_handle(self) -> bool {
+        !matches!(self, Type::Integer | Type::Float)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    And,
+    Or,
+}
+
+impl BinOp {
+    /// Code passed to the host for operations on handles
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    pub fn from_code(code: i32) -> Option<BinOp> {
+        use BinOp::*;
+        [
+            Add, Sub, Mul, Div, Mod, Eq, Ne, Lt, Le, Gt, Ge, BitAnd, BitOr, BitXor, Shl, Shr, And, Or,
+        ]
+        .get(usize::try_from(code).ok()?)
+        .copied()
+    }
+
+    pub fn symbol(self) -> &'static str {
+        use BinOp::*;
+        match self {
+            Add => "+",
+            Sub => "-",
+            Mul => "*",
+            Div => "/",
+            Mod => "%",
+            Eq => "==",
+            Ne => "!=",
+            Lt => "<",
+            Le => "<=",
+            Gt => ">",
+            Ge => ">=",
+            BitAnd => "&",
+            BitOr => "|",
+            BitXor => "^",
+            Shl => "<<",
+            Shr => ">>",
+            And => "&&",
+            Or => "||",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+impl UnOp {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    pub fn from_code(code: i32) -> Option<UnOp> {
+        [UnOp::Neg, UnOp::Not, UnOp::BitNot]
+            .get(usize::try_from(code).ok()?)
+            .copied()
+    }
+}
+
+/// A variable, or one component of a vector or rotation variable
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub name: String,
+    /// 0 to 3 for `.x`, `.y`, `.z` and `.s`
+    pub member: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Integer(i32),
+    Float(f32),
+    String(String),
+    Vector(Vec<Expr>),
+    Rotation(Vec<Expr>),
+    List(Vec<Expr>),
+    Variable(Target),
+    /// `target = value`, or `target op= value`
+    Assign {
+        target: Target,
+        op: Option<BinOp>,
+        value: Box<Expr>,
+    },
+    /// `++target`, `target--` and so on
+    Step {
+        target: Target,
+        delta: i32,
+        prefix: bool,
+    },
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Unary(UnOp, Box<Expr>),
+    Cast(Type, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Declare {
+        ty: Type,
+        name: String,
+        init: Option<Expr>,
+        line: u32,
+    },
+    Expr(Expr),
+    If {
+        cond: Expr,
+        then: Box<Stmt>,
+        otherwise: Option<Box<Stmt>>,
+    },
+    While {
+        cond: Expr,
+        body: Box<Stmt>,
+    },
+    DoWhile {
+        body: Box<Stmt>,
+        cond: Expr,
+    },
+    For {
+        init: Vec<Expr>,
+        cond: Option<Expr>,
+        step: Vec<Expr>,
+        body: Box<Stmt>,
+    },
+    Return(Option<Expr>, u32),
+    /// `state name;`
+    ChangeState(String, u32),
+    Block(Vec<Stmt>),
+    Empty,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub ty: Type,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub ty: Type,
+    pub name: String,
+    pub init: Option<Expr>,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub returns: Option<Type>,
+    pub params: Vec<Param>,
+    pub body: Vec<Stmt>,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handler {
+    pub event: String,
+    pub params: Vec<Param>,
+    pub body: Vec<Stmt>,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    pub name: String,
+    pub handlers: Vec<Handler>,
+    pub line: u32,
+}
+
+/// A parsed script: globals and functions, then `default` and other states
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Script {
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+    pub states: Vec<State>,
+}
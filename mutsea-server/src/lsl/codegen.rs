@@ -0,0 +1,1044 @@
+//! Type checking and lowering of a [`Script`] to a WebAssembly module
+//!
+//! Integers and floats are WebAssembly `i32` and `f32` values. Strings,
+//! keys, vectors, rotations and lists are held by the host and referred to
+//! by `i32` handles; operations on them are calls to the functions the
+//! host provides in [`IMPORTS`]. The module exports:
+//!
+//! - `memory`, holding the script's string constants
+//! - `state`, the index of the current state, and `state:<name>` for each
+//! - `g:<type>:<name>` for each global variable
+//! - `init`, which sets the globals to their initial values
+//! - `<state>/<event>` for each event handler
+//!
+//! `state x;` sets `state` and returns; the host runs the `state_exit` and
+//! `state_entry` handlers that follow.
+
+use std::collections::HashMap;
+
+use super::ast::*;
+use super::library::{self, Constant};
+use super::wasm::{op, Code, FuncType, ModuleBuilder, ValType};
+use super::{CompileError, CompiledScript};
+
+/// Functions the host provides to compiled scripts, in module `env`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Import {
+    /// `(ptr, len) -> string`: a constant in memory
+    StrConst,
+    /// `(integer) -> handle`
+    BoxInt,
+    /// `(float) -> handle`
+    BoxFloat,
+    /// `(handle) -> integer`
+    UnboxInt,
+    /// `(handle) -> float`
+    UnboxFloat,
+    /// `(op, a, b) -> handle`: a [`BinOp`] on boxed values
+    Binop,
+    /// `(op, a) -> handle`: a [`UnOp`]
+    Unop,
+    /// `(value, type) -> handle`: a cast to a [`Type`] code
+    Cast,
+    /// `(handle) -> integer`: whether a value counts as true
+    Truthy,
+    /// `(x, y, z) -> vector`
+    VecNew,
+    /// `(x, y, z, s) -> rotation`
+    RotNew,
+    /// `(value, index) -> float`: a component of a vector or rotation
+    Member,
+    /// `(value, index, float) -> handle`: a copy with a component replaced
+    WithMember,
+    /// `() -> list`: a new empty list
+    ListNew,
+    /// `(list, value) -> list`: append to a list still being built
+    ListPush,
+    /// `(function, arguments) -> handle`: call a library function
+    Ll,
+}
+
+use ValType::{F32, I32};
+
+pub const IMPORTS: &[(Import, &str, &[ValType], &[ValType])] = &[
+    (Import::StrConst, "str_const", &[I32, I32], &[I32]),
+    (Import::BoxInt, "box_int", &[I32], &[I32]),
+    (Import::BoxFloat, "box_float", &[F32], &[I32]),
+    (Import::UnboxInt, "unbox_int", &[I32], &[I32]),
+    (Import::UnboxFloat, "unbox_float", &[I32], &[F32]),
+    (Import::Binop, "binop", &[I32, I32, I32], &[I32]),
+    (Import::Unop, "unop", &[I32, I32], &[I32]),
+    (Import::Cast, "cast", &[I32, I32], &[I32]),
+    (Import::Truthy, "truthy", &[I32], &[I32]),
+    (Import::VecNew, "vec_new", &[F32, F32, F32], &[I32]),
+    (Import::RotNew, "rot_new", &[F32, F32, F32, F32], &[I32]),
+    (Import::Member, "member", &[I32, I32], &[F32]),
+    (Import::WithMember, "with_member", &[I32, I32, F32], &[I32]),
+    (Import::ListNew, "list_new", &[], &[I32]),
+    (Import::ListPush, "list_push", &[I32, I32], &[I32]),
+    (Import::Ll, "ll", &[I32, I32], &[I32]),
+];
+
+/// Index of the `state` global
+const STATE_GLOBAL: u32 = 0;
+
+fn val_type(ty: Type) -> ValType {
+    if ty == Type::Float {
+        F32
+    } else {
+        I32
+    }
+}
+
+fn func_type(params: &[Type], returns: Option<Type>) -> FuncType {
+    FuncType {
+        params: params.iter().map(|ty| val_type(*ty)).collect(),
+        results: returns.map(val_type).into_iter().collect(),
+    }
+}
+
+/// Where a name refers to
+#[derive(Debug, Clone, Copy)]
+enum Variable {
+    Local(u32, Type),
+    Global(u32, Type),
+    Constant(Constant),
+}
+
+impl Variable {
+    fn ty(self) -> Type {
+        match self {
+            Variable::Local(_, ty) | Variable::Global(_, ty) => ty,
+            Variable::Constant(constant) => constant.ty(),
+        }
+    }
+}
+
+struct UserFunction {
+    index: u32,
+    returns: Option<Type>,
+    params: Vec<Type>,
+}
+
+/// A function body being generated
+#[derive(Default)]
+struct Body {
+    code: Code,
+    params: u32,
+    locals: Vec<ValType>,
+    scopes: Vec<HashMap<String, (u32, Type)>>,
+    returns: Option<Type>,
+}
+
+struct Generator {
+    module: ModuleBuilder,
+    strings: HashMap<String, u32>,
+    globals: HashMap<String, (u32, Type)>,
+    functions: HashMap<String, UserFunction>,
+    states: HashMap<String, i32>,
+    body: Body,
+}
+
+/// Compile a parsed script
+pub fn generate(script: &Script) -> Result<CompiledScript, CompileError> {
+    let mut gen = Generator {
+        module: ModuleBuilder::default(),
+        strings: HashMap::new(),
+        globals: HashMap::new(),
+        functions: HashMap::new(),
+        states: HashMap::new(),
+        body: Body::default(),
+    };
+    for (_, name, params, results) in IMPORTS {
+        gen.module.import(
+            "env",
+            name,
+            FuncType {
+                params: params.to_vec(),
+                results: results.to_vec(),
+            },
+        );
+    }
+    gen.module.memory();
+
+    // States, functions and globals are known before any code refers to them
+    for (index, state) in script.states.iter().enumerate() {
+        if gen.states.insert(state.name.clone(), index as i32).is_some() {
+            return Err(CompileError::new(
+                state.line,
+                format!("state '{}' is declared twice", state.name),
+            ));
+        }
+    }
+    let first_function = gen.module.next_function();
+    for (index, function) in (first_function..).zip(&script.functions) {
+        if library::lookup(&function.name).is_some() || gen.functions.contains_key(&function.name) {
+            return Err(CompileError::new(
+                function.line,
+                format!("function '{}' is already declared", function.name),
+            ));
+        }
+        gen.functions.insert(
+            function.name.clone(),
+            UserFunction {
+                index,
+                returns: function.returns,
+                params: function.params.iter().map(|param| param.ty).collect(),
+            },
+        );
+    }
+
+    let state_global = gen.module.global(I32, true, zero_code(I32));
+    debug_assert_eq!(state_global, STATE_GLOBAL);
+    gen.module.export_global("state", STATE_GLOBAL);
+    let mut globals = Vec::new();
+    let mut global_indices = Vec::new();
+    for global in &script.globals {
+        if globals.iter().any(|(name, _)| *name == global.name) {
+            return Err(CompileError::new(
+                global.line,
+                format!("'{}' is declared twice", global.name),
+            ));
+        }
+        let index = gen
+            .module
+            .global(val_type(global.ty), true, zero_code(val_type(global.ty)));
+        gen.module
+            .export_global(&format!("g:{}:{}", global.ty.name(), global.name), index);
+        globals.push((global.name.clone(), global.ty));
+        global_indices.push(index);
+    }
+    for (index, state) in script.states.iter().enumerate() {
+        let mut init = Code::default();
+        init.i32_const(index as i32);
+        let global = gen.module.global(I32, false, init);
+        gen.module.export_global(&format!("state:{}", state.name), global);
+    }
+
+    // Globals are visible to the initialisers of those after them
+    gen.begin(&[], None);
+    for (global, index) in script.globals.iter().zip(&global_indices) {
+        match &global.init {
+            Some(init) => {
+                let ty = gen.value(init)?;
+                gen.coerce(ty, global.ty, init.line)?;
+            }
+            None => gen.zero(global.ty),
+        }
+        gen.body.code.op_u32(op::GLOBAL_SET, *index);
+        gen.globals.insert(global.name.clone(), (*index, global.ty));
+    }
+    let init = std::mem::take(&mut gen.body);
+
+    for function in &script.functions {
+        let params: Vec<Type> = function.params.iter().map(|param| param.ty).collect();
+        gen.begin(&function.params, function.returns);
+        gen.stmts(&function.body)?;
+        if let Some(ty) = function.returns {
+            // Falling off the end returns the type's zero value
+            gen.zero(ty);
+        }
+        let body = std::mem::take(&mut gen.body);
+        gen.module
+            .function(func_type(&params, function.returns), body.locals, body.code);
+    }
+
+    for state in &script.states {
+        let mut seen = Vec::new();
+        for handler in &state.handlers {
+            let Some(expected) = library::event_params(&handler.event) else {
+                return Err(CompileError::new(handler.line, format!("no event '{}'", handler.event)));
+            };
+            let params: Vec<Type> = handler.params.iter().map(|param| param.ty).collect();
+            if params != expected {
+                let names: Vec<&str> = expected.iter().map(|ty| ty.name()).collect();
+                return Err(CompileError::new(
+                    handler.line,
+                    format!("{} takes ({})", handler.event, names.join(", ")),
+                ));
+            }
+            if seen.contains(&handler.event) {
+                return Err(CompileError::new(
+                    handler.line,
+                    format!("{} is handled twice in state {}", handler.event, state.name),
+                ));
+            }
+            seen.push(handler.event.clone());
+
+            gen.begin(&handler.params, None);
+            gen.stmts(&handler.body)?;
+            let body = std::mem::take(&mut gen.body);
+            let index = gen.module.function(func_type(&params, None), body.locals, body.code);
+            gen.module
+                .export_function(&format!("{}/{}", state.name, handler.event), index);
+        }
+    }
+
+    let index = gen.module.function(func_type(&[], None), init.locals, init.code);
+    gen.module.export_function("init", index);
+
+    Ok(CompiledScript {
+        wasm: gen.module.finish(),
+        globals,
+        states: script.states.iter().map(|state| state.name.clone()).collect(),
+    })
+}
+
+/// Constant expression for a zero of `ty`
+fn zero_code(ty: ValType) -> Code {
+    let mut code = Code::default();
+    match ty {
+        I32 => code.i32_const(0),
+        F32 => code.f32_const(0.0),
+    };
+    code
+}
+
+impl Generator {
+    fn call(&mut self, import: Import) {
+        let index = IMPORTS.iter().position(|(i, ..)| *i == import).unwrap_or_default();
+        self.body.code.op_u32(op::CALL, index as u32);
+    }
+
+    fn begin(&mut self, params: &[Param], returns: Option<Type>) {
+        let scope = params
+            .iter()
+            .enumerate()
+            .map(|(index, param)| (param.name.clone(), (index as u32, param.ty)))
+            .collect();
+        self.body = Body {
+            code: Code::default(),
+            params: params.len() as u32,
+            locals: Vec::new(),
+            scopes: vec![scope],
+            returns,
+        };
+    }
+
+    fn local(&mut self, ty: ValType) -> u32 {
+        self.body.locals.push(ty);
+        self.body.params + self.body.locals.len() as u32 - 1
+    }
+
+    fn lookup(&self, name: &str, line: u32) -> Result<Variable, CompileError> {
+        for scope in self.body.scopes.iter().rev() {
+            if let Some((index, ty)) = scope.get(name) {
+                return Ok(Variable::Local(*index, *ty));
+            }
+        }
+        if let Some((index, ty)) = self.globals.get(name) {
+            return Ok(Variable::Global(*index, *ty));
+        }
+        library::constant(name)
+            .map(Variable::Constant)
+            .ok_or_else(|| CompileError::new(line, format!("'{}' is not declared", name)))
+    }
+
+    fn string(&mut self, text: &str) {
+        let offset = match self.strings.get(text) {
+            Some(offset) => *offset,
+            None => {
+                let offset = self.module.data(text.as_bytes());
+                self.strings.insert(text.to_string(), offset);
+                offset
+            }
+        };
+        self.body.code.i32_const(offset as i32).i32_const(text.len() as i32);
+        self.call(Import::StrConst);
+    }
+
+    /// Push the zero value of `ty`
+    fn zero(&mut self, ty: Type) {
+        match ty {
+            Type::Integer => {
+                self.body.code.i32_const(0);
+            }
+            Type::Float => {
+                self.body.code.f32_const(0.0);
+            }
+            Type::String | Type::Key => self.string(""),
+            Type::Vector => self.constant(Constant::Vector([0.0; 3])),
+            Type::Rotation => self.constant(Constant::Rotation([0.0, 0.0, 0.0, 1.0])),
+            Type::List => self.call(Import::ListNew),
+        }
+    }
+
+    fn constant(&mut self, constant: Constant) {
+        match constant {
+            Constant::Integer(value) => {
+                self.body.code.i32_const(value);
+            }
+            Constant::Float(value) => {
+                self.body.code.f32_const(value);
+            }
+            Constant::String(text) => self.string(text),
+            Constant::Vector(components) => {
+                for component in components {
+                    self.body.code.f32_const(component);
+                }
+                self.call(Import::VecNew);
+            }
+            Constant::Rotation(components) => {
+                for component in components {
+                    self.body.code.f32_const(component);
+                }
+                self.call(Import::RotNew);
+            }
+        }
+    }
+
+    fn load(&mut self, variable: Variable) {
+        match variable {
+            Variable::Local(index, _) => {
+                self.body.code.op_u32(op::LOCAL_GET, index);
+            }
+            Variable::Global(index, _) => {
+                self.body.code.op_u32(op::GLOBAL_GET, index);
+            }
+            Variable::Constant(constant) => self.constant(constant),
+        }
+    }
+
+    /// Pop into `variable`
+    fn store(&mut self, variable: Variable, name: &str, line: u32) -> Result<(), CompileError> {
+        match variable {
+            Variable::Local(index, _) => {
+                self.body.code.op_u32(op::LOCAL_SET, index);
+            }
+            Variable::Global(index, _) => {
+                self.body.code.op_u32(op::GLOBAL_SET, index);
+            }
+            Variable::Constant(_) => {
+                return Err(CompileError::new(line, format!("cannot assign to constant {}", name)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Turn the value on the stack from `from` to `to`, where LSL does so
+    /// implicitly
+    fn coerce(&mut self, from: Type, to: Type, line: u32) -> Result<(), CompileError> {
+        match (from, to) {
+            _ if from == to => {}
+            (Type::Integer, Type::Float) => {
+                self.body.code.op(op::F32_CONVERT_I32_S);
+            }
+            (Type::String, Type::Key) | (Type::Key, Type::String) => {
+                self.body.code.i32_const(to.code());
+                self.call(Import::Cast);
+            }
+            _ => {
+                return Err(CompileError::new(
+                    line,
+                    format!("type mismatch: {} used as {}", from.name(), to.name()),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Box a number on the stack into a handle
+    fn boxed(&mut self, ty: Type) {
+        match ty {
+            Type::Integer => self.call(Import::BoxInt),
+            Type::Float => self.call(Import::BoxFloat),
+            _ => {}
+        }
+    }
+
+    /// Unbox a handle on the stack holding a number of type `ty`
+    fn unboxed(&mut self, ty: Type) {
+        match ty {
+            Type::Integer => self.call(Import::UnboxInt),
+            Type::Float => self.call(Import::UnboxFloat),
+            _ => {}
+        }
+    }
+
+    /// Generate `expr` into a separate buffer
+    fn detached(&mut self, expr: &Expr) -> Result<(Code, Type), CompileError> {
+        let saved = std::mem::take(&mut self.body.code);
+        let result = self.value(expr);
+        let code = std::mem::replace(&mut self.body.code, saved);
+        Ok((code, result?))
+    }
+
+    /// Generate an expression that must have a value
+    fn value(&mut self, expr: &Expr) -> Result<Type, CompileError> {
+        self.expr(expr)?
+            .ok_or_else(|| CompileError::new(expr.line, "function has no return value"))
+    }
+
+    /// Generate an expression for a condition, leaving an integer
+    fn condition(&mut self, expr: &Expr) -> Result<(), CompileError> {
+        match self.value(expr)? {
+            Type::Integer => {}
+            Type::Float => {
+                self.body.code.f32_const(0.0).op(op::F32_NE);
+            }
+            _ => self.call(Import::Truthy),
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Option<Type>, CompileError> {
+        let line = expr.line;
+        let ty = match &expr.kind {
+            ExprKind::Integer(value) => {
+                self.body.code.i32_const(*value);
+                Type::Integer
+            }
+            ExprKind::Float(value) => {
+                self.body.code.f32_const(*value);
+                Type::Float
+            }
+            ExprKind::String(text) => {
+                self.string(text);
+                Type::String
+            }
+            ExprKind::Vector(components) | ExprKind::Rotation(components) => {
+                for component in components {
+                    let ty = self.value(component)?;
+                    self.coerce(ty, Type::Float, component.line)?;
+                }
+                if components.len() == 3 {
+                    self.call(Import::VecNew);
+                    Type::Vector
+                } else {
+                    self.call(Import::RotNew);
+                    Type::Rotation
+                }
+            }
+            ExprKind::List(items) => {
+                self.call(Import::ListNew);
+                for item in items {
+                    let ty = self.value(item)?;
+                    if ty == Type::List {
+                        return Err(CompileError::new(item.line, "lists cannot contain lists"));
+                    }
+                    self.boxed(ty);
+                    self.call(Import::ListPush);
+                }
+                Type::List
+            }
+            ExprKind::Variable(target) => {
+                let variable = self.lookup(&target.name, line)?;
+                self.load(variable);
+                match target.member {
+                    Some(member) => {
+                        self.check_member(variable.ty(), member, line)?;
+                        self.body.code.i32_const(i32::from(member));
+                        self.call(Import::Member);
+                        Type::Float
+                    }
+                    None => variable.ty(),
+                }
+            }
+            ExprKind::Assign { target, op, value } => self.assign(target, *op, value, line)?,
+            ExprKind::Step { target, delta, prefix } => self.step(target, *delta, *prefix, line)?,
+            ExprKind::Binary(op, a, b) => self.binary(*op, a, b, line)?,
+            ExprKind::Unary(op, operand) => self.unary(*op, operand, line)?,
+            ExprKind::Cast(ty, operand) => self.cast(*ty, operand, line)?,
+            ExprKind::Call(name, args) => return self.call_function(name, args, line),
+        };
+        Ok(Some(ty))
+    }
+
+    fn check_member(&self, ty: Type, member: u8, line: u32) -> Result<(), CompileError> {
+        match (ty, member) {
+            (Type::Vector, 0..=2) | (Type::Rotation, 0..=3) => Ok(()),
+            _ => Err(CompileError::new(line, format!("{} has no such member", ty.name()))),
+        }
+    }
+
+    /// The value to assign for `target op= value`, or `value`
+    fn assigned(&mut self, target: &Target, op: Option<BinOp>, value: &Expr, line: u32) -> Result<Type, CompileError> {
+        match op {
+            Some(op) => {
+                let current = Expr {
+                    kind: ExprKind::Variable(target.clone()),
+                    line,
+                };
+                self.binary(op, &current, value, line)
+            }
+            None => self.value(value),
+        }
+    }
+
+    fn assign(&mut self, target: &Target, op: Option<BinOp>, value: &Expr, line: u32) -> Result<Type, CompileError> {
+        let variable = self.lookup(&target.name, line)?;
+        let Some(member) = target.member else {
+            let ty = self.assigned(target, op, value, line)?;
+            self.coerce(ty, variable.ty(), line)?;
+            let temp = self.local(val_type(variable.ty()));
+            self.body.code.op_u32(op::LOCAL_TEE, temp);
+            self.store(variable, &target.name, line)?;
+            self.body.code.op_u32(op::LOCAL_GET, temp);
+            return Ok(variable.ty());
+        };
+
+        self.check_member(variable.ty(), member, line)?;
+        let ty = self.assigned(target, op, value, line)?;
+        self.coerce(ty, Type::Float, line)?;
+        let component = self.local(F32);
+        self.body.code.op_u32(op::LOCAL_SET, component);
+        self.set_member(variable, member, component, &target.name, line)?;
+        self.body.code.op_u32(op::LOCAL_GET, component);
+        Ok(Type::Float)
+    }
+
+    /// Set component `member` of `variable` to float local `component`
+    fn set_member(
+        &mut self,
+        variable: Variable,
+        member: u8,
+        component: u32,
+        name: &str,
+        line: u32,
+    ) -> Result<(), CompileError> {
+        self.load(variable);
+        self.body
+            .code
+            .i32_const(i32::from(member))
+            .op_u32(op::LOCAL_GET, component);
+        self.call(Import::WithMember);
+        self.store(variable, name, line)
+    }
+
+    fn step(&mut self, target: &Target, delta: i32, prefix: bool, line: u32) -> Result<Type, CompileError> {
+        let variable = self.lookup(&target.name, line)?;
+        let current = Expr {
+            kind: ExprKind::Variable(target.clone()),
+            line,
+        };
+        let ty = self.value(&current)?;
+        let (old, new) = match ty {
+            Type::Integer => {
+                let old = self.local(I32);
+                let new = self.local(I32);
+                self.body
+                    .code
+                    .op_u32(op::LOCAL_TEE, old)
+                    .i32_const(delta)
+                    .op(op::I32_ADD)
+                    .op_u32(op::LOCAL_SET, new);
+                (old, new)
+            }
+            Type::Float => {
+                let old = self.local(F32);
+                let new = self.local(F32);
+                self.body
+                    .code
+                    .op_u32(op::LOCAL_TEE, old)
+                    .f32_const(delta as f32)
+                    .op(op::F32_ADD)
+                    .op_u32(op::LOCAL_SET, new);
+                (old, new)
+            }
+            other => {
+                return Err(CompileError::new(
+                    line,
+                    format!("cannot increment or decrement a {}", other.name()),
+                ))
+            }
+        };
+        match target.member {
+            Some(member) => self.set_member(variable, member, new, &target.name, line)?,
+            None => {
+                self.body.code.op_u32(op::LOCAL_GET, new);
+                self.store(variable, &target.name, line)?;
+            }
+        }
+        self.body.code.op_u32(op::LOCAL_GET, if prefix { new } else { old });
+        Ok(ty)
+    }
+
+    fn binary(&mut self, op: BinOp, a: &Expr, b: &Expr, line: u32) -> Result<Type, CompileError> {
+        let (left, left_ty) = self.detached(a)?;
+        let (right, right_ty) = self.detached(b)?;
+        let mismatch = || {
+            CompileError::new(
+                line,
+                format!("no operator {} {} {}", left_ty.name(), op.symbol(), right_ty.name()),
+            )
+        };
+
+        if matches!(op, BinOp::And | BinOp::Or) {
+            if left_ty != Type::Integer || right_ty != Type::Integer {
+                return Err(mismatch());
+            }
+            // Both sides are evaluated, as in LSL
+            let code = &mut self.body.code;
+            if op == BinOp::And {
+                code.append(left).i32_const(0).op(op::I32_NE);
+                code.append(right).i32_const(0).op(op::I32_NE);
+                code.op(op::I32_AND);
+            } else {
+                code.append(left)
+                    .append(right)
+                    .op(op::I32_OR)
+                    .i32_const(0)
+                    .op(op::I32_NE);
+            }
+            return Ok(Type::Integer);
+        }
+
+        if !left_ty.is_handle() && !right_ty.is_handle() {
+            let float = left_ty == Type::Float || right_ty == Type::Float;
+            let code = &mut self.body.code;
+            code.append(left);
+            if float && left_ty == Type::Integer {
+                code.op(op::F32_CONVERT_I32_S);
+            }
+            code.append(right);
+            if float && right_ty == Type::Integer {
+                code.op(op::F32_CONVERT_I32_S);
+            }
+            let (opcode, ty) = if float {
+                match op {
+                    BinOp::Add => (op::F32_ADD, Type::Float),
+                    BinOp::Sub => (op::F32_SUB, Type::Float),
+                    BinOp::Mul => (op::F32_MUL, Type::Float),
+                    BinOp::Div => (op::F32_DIV, Type::Float),
+                    BinOp::Eq => (op::F32_EQ, Type::Integer),
+                    BinOp::Ne => (op::F32_NE, Type::Integer),
+                    BinOp::Lt => (op::F32_LT, Type::Integer),
+                    BinOp::Le => (op::F32_LE, Type::Integer),
+                    BinOp::Gt => (op::F32_GT, Type::Integer),
+                    BinOp::Ge => (op::F32_GE, Type::Integer),
+                    _ => return Err(mismatch()),
+                }
+            } else {
+                let opcode = match op {
+                    BinOp::Add => op::I32_ADD,
+                    BinOp::Sub => op::I32_SUB,
+                    BinOp::Mul => op::I32_MUL,
+                    BinOp::Div => op::I32_DIV_S,
+                    BinOp::Mod => op::I32_REM_S,
+                    BinOp::Eq => op::I32_EQ,
+                    BinOp::Ne => op::I32_NE,
+                    BinOp::Lt => op::I32_LT_S,
+                    BinOp::Le => op::I32_LE_S,
+                    BinOp::Gt => op::I32_GT_S,
+                    BinOp::Ge => op::I32_GE_S,
+                    BinOp::BitAnd => op::I32_AND,
+                    BinOp::BitOr => op::I32_OR,
+                    BinOp::BitXor => op::I32_XOR,
+                    BinOp::Shl => op::I32_SHL,
+                    BinOp::Shr => op::I32_SHR_S,
+                    BinOp::And | BinOp::Or => unreachable!("handled above"),
+                };
+                (opcode, Type::Integer)
+            };
+            code.op(opcode);
+            return Ok(ty);
+        }
+
+        let ty = handle_result(op, left_ty, right_ty).ok_or_else(mismatch)?;
+        self.body.code.i32_const(op.code()).append(left);
+        self.boxed(left_ty);
+        self.body.code.append(right);
+        self.boxed(right_ty);
+        self.call(Import::Binop);
+        self.unboxed(ty);
+        Ok(ty)
+    }
+
+    fn unary(&mut self, op: UnOp, operand: &Expr, line: u32) -> Result<Type, CompileError> {
+        let (code, ty) = self.detached(operand)?;
+        match (op, ty) {
+            (UnOp::Neg, Type::Integer) => {
+                self.body.code.i32_const(0).append(code).op(op::I32_SUB);
+            }
+            (UnOp::Neg, Type::Float) => {
+                self.body.code.append(code).op(op::F32_NEG);
+            }
+            (UnOp::Neg, Type::Vector | Type::Rotation) => {
+                self.body.code.i32_const(op.code()).append(code);
+                self.call(Import::Unop);
+            }
+            (UnOp::Not, Type::Integer) => {
+                self.body.code.append(code).op(op::I32_EQZ);
+            }
+            (UnOp::BitNot, Type::Integer) => {
+                self.body.code.append(code).i32_const(-1).op(op::I32_XOR);
+            }
+            _ => return Err(CompileError::new(line, format!("no unary operator on {}", ty.name()))),
+        }
+        Ok(ty)
+    }
+
+    fn cast(&mut self, to: Type, operand: &Expr, line: u32) -> Result<Type, CompileError> {
+        let from = self.value(operand)?;
+        let allowed = from == to
+            || to == Type::String
+            || to == Type::List
+            || from == Type::String
+            || (!from.is_handle() && !to.is_handle())
+            || (from == Type::Key && to == Type::Key);
+        if !allowed {
+            return Err(CompileError::new(
+                line,
+                format!("cannot cast {} to {}", from.name(), to.name()),
+            ));
+        }
+        match (from, to) {
+            _ if from == to => {}
+            (Type::Integer, Type::Float) => {
+                self.body.code.op(op::F32_CONVERT_I32_S);
+            }
+            (Type::Float, Type::Integer) => {
+                self.body.code.misc(op::I32_TRUNC_SAT_F32_S);
+            }
+            _ => {
+                self.boxed(from);
+                self.body.code.i32_const(to.code());
+                self.call(Import::Cast);
+                self.unboxed(to);
+            }
+        }
+        Ok(to)
+    }
+
+    fn call_function(&mut self, name: &str, args: &[Expr], line: u32) -> Result<Option<Type>, CompileError> {
+        let arity = |expected: usize| {
+            if args.len() == expected {
+                Ok(())
+            } else {
+                Err(CompileError::new(
+                    line,
+                    format!("{} takes {} arguments, not {}", name, expected, args.len()),
+                ))
+            }
+        };
+
+        if let Some(function) = self.functions.get(name) {
+            let (index, returns, params) = (function.index, function.returns, function.params.clone());
+            arity(params.len())?;
+            for (arg, param) in args.iter().zip(params) {
+                let ty = self.value(arg)?;
+                self.coerce(ty, param, arg.line)?;
+            }
+            self.body.code.op_u32(op::CALL, index);
+            return Ok(returns);
+        }
+
+        let Some((index, function)) = library::lookup(name) else {
+            return Err(CompileError::new(line, format!("no function '{}'", name)));
+        };
+        arity(function.params.len())?;
+        self.body.code.i32_const(index as i32);
+        self.call(Import::ListNew);
+        for (arg, param) in args.iter().zip(function.params) {
+            let ty = self.value(arg)?;
+            self.coerce(ty, *param, arg.line)?;
+            self.boxed(*param);
+            self.call(Import::ListPush);
+        }
+        self.call(Import::Ll);
+        match function.returns {
+            None => {
+                self.body.code.op(op::DROP);
+            }
+            Some(ty) => self.unboxed(ty),
+        }
+        Ok(function.returns)
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> Result<(), CompileError> {
+        self.body.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.stmt(stmt)?;
+        }
+        self.body.scopes.pop();
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), CompileError> {
+        match stmt {
+            Stmt::Declare { ty, name, init, line } => {
+                if self.body.scopes.last().is_some_and(|scope| scope.contains_key(name)) {
+                    return Err(CompileError::new(*line, format!("'{}' is declared twice", name)));
+                }
+                match init {
+                    Some(init) => {
+                        let from = self.value(init)?;
+                        self.coerce(from, *ty, init.line)?;
+                    }
+                    None => self.zero(*ty),
+                }
+                let index = self.local(val_type(*ty));
+                self.body.code.op_u32(op::LOCAL_SET, index);
+                if let Some(scope) = self.body.scopes.last_mut() {
+                    scope.insert(name.clone(), (index, *ty));
+                }
+            }
+            Stmt::Expr(expr) => {
+                if self.expr(expr)?.is_some() {
+                    self.body.code.op(op::DROP);
+                }
+            }
+            Stmt::If { cond, then, otherwise } => {
+                self.condition(cond)?;
+                self.body.code.begin(op::IF);
+                self.scoped(then)?;
+                if let Some(otherwise) = otherwise {
+                    self.body.code.op(op::ELSE);
+                    self.scoped(otherwise)?;
+                }
+                self.body.code.op(op::END);
+            }
+            Stmt::While { cond, body } => {
+                self.body.code.begin(op::BLOCK).begin(op::LOOP);
+                self.condition(cond)?;
+                self.body.code.op(op::I32_EQZ).op_u32(op::BR_IF, 1);
+                self.scoped(body)?;
+                self.body.code.op_u32(op::BR, 0).op(op::END).op(op::END);
+            }
+            Stmt::DoWhile { body, cond } => {
+                self.body.code.begin(op::LOOP);
+                self.scoped(body)?;
+                self.condition(cond)?;
+                self.body.code.op_u32(op::BR_IF, 0).op(op::END);
+            }
+            Stmt::For { init, cond, step, body } => {
+                for expr in init {
+                    self.stmt(&Stmt::Expr(expr.clone()))?;
+                }
+                self.body.code.begin(op::BLOCK).begin(op::LOOP);
+                if let Some(cond) = cond {
+                    self.condition(cond)?;
+                    self.body.code.op(op::I32_EQZ).op_u32(op::BR_IF, 1);
+                }
+                self.scoped(body)?;
+                for expr in step {
+                    self.stmt(&Stmt::Expr(expr.clone()))?;
+                }
+                self.body.code.op_u32(op::BR, 0).op(op::END).op(op::END);
+            }
+            Stmt::Return(value, line) => match (value, self.body.returns) {
+                (Some(value), Some(returns)) => {
+                    let ty = self.value(value)?;
+                    self.coerce(ty, returns, value.line)?;
+                    self.body.code.op(op::RETURN);
+                }
+                (None, None) => {
+                    self.body.code.op(op::RETURN);
+                }
+                (Some(_), None) => {
+                    return Err(CompileError::new(
+                        *line,
+                        "return with a value from an event or void function",
+                    ))
+                }
+                (None, Some(returns)) => {
+                    return Err(CompileError::new(
+                        *line,
+                        format!("return needs a {} value", returns.name()),
+                    ))
+                }
+            },
+            Stmt::ChangeState(name, line) => {
+                let Some(index) = self.states.get(name).copied() else {
+                    return Err(CompileError::new(*line, format!("no state '{}'", name)));
+                };
+                self.body.code.i32_const(index).op_u32(op::GLOBAL_SET, STATE_GLOBAL);
+                if let Some(returns) = self.body.returns {
+                    self.zero(returns);
+                }
+                self.body.code.op(op::RETURN);
+            }
+            Stmt::Block(stmts) => self.stmts(stmts)?,
+            Stmt::Empty => {}
+        }
+        Ok(())
+    }
+
+    /// A statement in a scope of its own, such as the body of a loop
+    fn scoped(&mut self, stmt: &Stmt) -> Result<(), CompileError> {
+        self.stmts(std::slice::from_ref(stmt))
+    }
+}
+
+/// Type of `a op b` where either is held by the host
+fn handle_result(op: BinOp, a: Type, b: Type) -> Option<Type> {
+    use BinOp::*;
+    use Type::*;
+    Some(match (op, a, b) {
+        (Add, List, _) | (Add, _, List) => List,
+        (Eq | Ne, List, List) => Integer,
+        (Add, String | Key, String | Key) => String,
+        (Eq | Ne, String | Key, String | Key) => Integer,
+        (Add | Sub | Mod, Vector, Vector) => Vector,
+        (Mul, Vector, Vector) => Float,
+        (Mul, Vector, Integer | Float) | (Mul, Integer | Float, Vector) | (Div, Vector, Integer | Float) => Vector,
+        (Mul | Div, Vector, Rotation) => Vector,
+        (Add | Sub | Mul | Div, Rotation, Rotation) => Rotation,
+        (Eq | Ne, Vector, Vector) | (Eq | Ne, Rotation, Rotation) => Integer,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::compile;
+
+    fn error(source: &str) -> String {
+        compile(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_compile_exports() {
+        let compiled = compile(
+            r#"
+            integer count = 2;
+            rotation turn;
+            default { touch_start(integer n) { count++; state open; } }
+            state open { state_entry() { turn = llEuler2Rot(<0, 0, PI>); } }
+            "#,
+        )
+        .unwrap();
+        assert!(compiled.wasm.starts_with(b"\0asm\x01\0\0\0"));
+        assert_eq!(
+            compiled.globals,
+            vec![
+                ("count".to_string(), super::Type::Integer),
+                ("turn".to_string(), super::Type::Rotation)
+            ]
+        );
+        assert_eq!(compiled.states, ["default", "open"]);
+        let contains = |name: &str| {
+            compiled
+                .wasm
+                .windows(name.len())
+                .any(|window| window == name.as_bytes())
+        };
+        for export in [
+            "init",
+            "state:open",
+            "g:integer:count",
+            "default/touch_start",
+            "open/state_entry",
+        ] {
+            assert!(contains(export), "{} is not exported", export);
+        }
+    }
+
+    #[test]
+    fn test_type_errors() {
+        assert_eq!(
+            error("default { state_entry() { integer i = \"one\"; } }"),
+            "line 1: type mismatch: string used as integer"
+        );
+        assert!(error("default { state_entry() { float f = 1.5 % 2; } }").contains("no operator float % integer"));
+        assert!(error("default { state_entry() { llSay(0); } }").contains("takes 2 arguments"));
+        assert!(error("default { state_entry() { x = 1; } }").contains("'x' is not declared"));
+        assert!(error("default { state_entry() { state missing; } }").contains("no state 'missing'"));
+        assert!(error("default { touch_start() { } }").contains("touch_start takes (integer)"));
+        assert!(error("default { state_entry() { PI = 3; } }").contains("constant"));
+        assert!(error("integer f() { return; } default { state_entry() { } }").contains("return needs"));
+        assert!(error("default { state_entry() { list l = [[1]]; } }").contains("lists cannot contain lists"));
+        // Keys and strings convert implicitly, integers to floats
+        compile("default { state_entry() { key k = \"x\"; string s = k; float f = 1; } }").unwrap();
+    }
+}
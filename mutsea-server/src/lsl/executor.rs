@@ -0,0 +1,806 @@
+//! wasmtime executor for compiled scripts
+//!
+//! Each script has its own store, so one script cannot reach another's
+//! globals or values. Stores consume fuel and yield every few thousand
+//! instructions; a handler is a future polled until it finishes or its
+//! time slice runs out, in which case it is kept and resumed on the next
+//! call. Values held by the host for a script are collected after every
+//! event, keeping only those its globals refer to.
+
+use mutsea_core::scene::RegionScene;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+use wasmtime::{Caller, Config, Engine, Extern, Func, Global, Instance, Linker, Module, Store, Trap, Val};
+
+use super::ast::{BinOp, Type, UnOp};
+use super::codegen::Import;
+use super::library::{self, CallContext, ScriptAction};
+use super::{compile, ops, CompileError, CompiledScript};
+use crate::scripts::{ScriptEvent, ScriptInfo, ScriptRun, ScriptRuntime, ScriptValue};
+
+/// Instructions between yields to check the time slice
+const YIELD_FUEL: u64 = 10_000;
+
+/// Longest a script may take to set its globals when loaded
+const INIT_BUDGET: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Error)]
+pub enum ExecutorError {
+    #[error("WebAssembly engine error: {0}")]
+    Engine(String),
+    #[error(transparent)]
+    Compile(#[from] CompileError),
+    #[error("Script did not start: {0}")]
+    Instantiate(String),
+    #[error("Bad snapshot: {0}")]
+    Snapshot(String),
+}
+
+/// The state of a script between events: its current state and globals
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptSnapshot {
+    pub state: String,
+    pub globals: Vec<(String, ScriptValue)>,
+}
+
+/// Data of a script's store
+struct Host {
+    /// Values referred to by handle
+    heap: Vec<ScriptValue>,
+    heap_bytes: usize,
+    max_memory: usize,
+    context: CallContext,
+    actions: mpsc::UnboundedSender<ScriptAction>,
+}
+
+impl Host {
+    fn alloc(&mut self, value: ScriptValue) -> wasmtime::Result<i32> {
+        self.heap_bytes += ops::size_of(&value);
+        if self.heap_bytes > self.max_memory {
+            return Err(wasmtime::Error::msg("Stack-Heap Collision"));
+        }
+        self.heap.push(value);
+        Ok(self.heap.len() as i32 - 1)
+    }
+
+    fn get(&self, handle: i32) -> wasmtime::Result<&ScriptValue> {
+        usize::try_from(handle)
+            .ok()
+            .and_then(|handle| self.heap.get(handle))
+            .ok_or_else(|| wasmtime::Error::msg(format!("invalid value handle {}", handle)))
+    }
+}
+
+/// A handler running in a script's store, which it owns until it finishes
+type Running = Pin<Box<dyn Future<Output = (Store<Host>, wasmtime::Result<()>)> + Send>>;
+
+struct LoadedScript {
+    instance: Instance,
+    store: Option<Store<Host>>,
+    running: Option<Running>,
+    /// Handlers still to run for the current event, such as `state_exit`
+    /// and `state_entry` after a state change
+    follow_ups: VecDeque<String>,
+    state_changed: bool,
+    /// State the script was in when its last handler started
+    current_state: i32,
+    states: Vec<String>,
+    globals: Vec<(String, Type, Global)>,
+    memory: usize,
+}
+
+/// Runs compiled LSL scripts for a [`crate::scripts::ScriptSandbox`]
+pub struct WasmExecutor {
+    engine: Engine,
+    linker: Linker<Host>,
+    scene: Arc<RwLock<RegionScene>>,
+    max_memory: usize,
+    actions: mpsc::UnboundedSender<ScriptAction>,
+    scripts: Mutex<HashMap<Uuid, LoadedScript>>,
+}
+
+fn import_name(import: Import) -> &'static str {
+    super::codegen::IMPORTS
+        .iter()
+        .find(|(i, ..)| *i == import)
+        .map(|(_, name, ..)| *name)
+        .unwrap_or_default()
+}
+
+fn fault(message: impl Into<String>) -> wasmtime::Error {
+    wasmtime::Error::msg(message.into())
+}
+
+/// Poll `future` with a waker that does nothing until it completes or
+/// `deadline` passes. Stores yield when they run low on fuel, so a script
+/// cannot hold the thread for long past the deadline.
+fn poll_until<F: Future + ?Sized>(mut future: Pin<&mut F>, deadline: Instant) -> Option<F::Output> {
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return Some(output);
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+    }
+}
+
+/// The message a script's fault is reported with
+fn describe(error: &wasmtime::Error) -> String {
+    match error.downcast_ref::<Trap>() {
+        Some(Trap::IntegerDivisionByZero) => "Math error".to_string(),
+        Some(Trap::StackOverflow) => "Stack-Heap Collision".to_string(),
+        Some(trap) => trap.to_string(),
+        None => error.root_cause().to_string(),
+    }
+}
+
+impl WasmExecutor {
+    /// An executor for scripts in `scene` using at most `max_memory_kb`
+    /// each. Actions the scripts take arrive on the returned receiver.
+    pub fn new(
+        scene: Arc<RwLock<RegionScene>>,
+        max_memory_kb: usize,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<ScriptAction>), ExecutorError> {
+        let mut config = Config::new();
+        config.async_support(true).consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| ExecutorError::Engine(e.to_string()))?;
+        let linker = Self::linker(&engine).map_err(|e| ExecutorError::Engine(e.to_string()))?;
+        let (actions, receiver) = mpsc::unbounded_channel();
+        let executor = Self {
+            engine,
+            linker,
+            scene,
+            max_memory: max_memory_kb * 1024,
+            actions,
+            scripts: Mutex::new(HashMap::new()),
+        };
+        Ok((executor, receiver))
+    }
+
+    /// The host functions of [`super::codegen::IMPORTS`]
+    fn linker(engine: &Engine) -> wasmtime::Result<Linker<Host>> {
+        let mut linker = Linker::new(engine);
+        linker.func_wrap(
+            "env",
+            import_name(Import::StrConst),
+            |mut caller: Caller<'_, Host>, ptr: i32, len: i32| -> wasmtime::Result<i32> {
+                let memory = caller
+                    .get_export("memory")
+                    .and_then(Extern::into_memory)
+                    .ok_or_else(|| fault("script has no memory"))?;
+                let (start, len) = (ptr as u32 as usize, len as u32 as usize);
+                let bytes = memory
+                    .data(&caller)
+                    .get(start..start + len)
+                    .ok_or_else(|| fault("string constant out of bounds"))?;
+                let text = String::from_utf8_lossy(bytes).into_owned();
+                caller.data_mut().alloc(ScriptValue::String(text))
+            },
+        )?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::BoxInt),
+            |mut caller: Caller<'_, Host>, value: i32| caller.data_mut().alloc(ScriptValue::Integer(value)),
+        )?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::BoxFloat),
+            |mut caller: Caller<'_, Host>, value: f32| caller.data_mut().alloc(ScriptValue::Float(value)),
+        )?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::UnboxInt),
+            |caller: Caller<'_, Host>, handle: i32| -> wasmtime::Result<i32> {
+                match caller.data().get(handle)? {
+                    ScriptValue::Integer(value) => Ok(*value),
+                    other => Err(fault(format!("expected an integer, got {:?}", other))),
+                }
+            },
+        )?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::UnboxFloat),
+            |caller: Caller<'_, Host>, handle: i32| -> wasmtime::Result<f32> {
+                match caller.data().get(handle)? {
+                    ScriptValue::Float(value) => Ok(*value),
+                    ScriptValue::Integer(value) => Ok(*value as f32),
+                    other => Err(fault(format!("expected a float, got {:?}", other))),
+                }
+            },
+        )?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::Binop),
+            |mut caller: Caller<'_, Host>, op: i32, a: i32, b: i32| -> wasmtime::Result<i32> {
+                let op = BinOp::from_code(op).ok_or_else(|| fault("bad operator"))?;
+                let host = caller.data();
+                let result = ops::binary(op, host.get(a)?, host.get(b)?).map_err(fault)?;
+                caller.data_mut().alloc(result)
+            },
+        )?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::Unop),
+            |mut caller: Caller<'_, Host>, op: i32, a: i32| -> wasmtime::Result<i32> {
+                let op = UnOp::from_code(op).ok_or_else(|| fault("bad operator"))?;
+                let result = ops::unary(op, caller.data().get(a)?).map_err(fault)?;
+                caller.data_mut().alloc(result)
+            },
+        )?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::Cast),
+            |mut caller: Caller<'_, Host>, handle: i32, ty: i32| -> wasmtime::Result<i32> {
+                let ty = Type::from_code(ty).ok_or_else(|| fault("bad type"))?;
+                let result = ops::cast(caller.data().get(handle)?, ty).map_err(fault)?;
+                caller.data_mut().alloc(result)
+            },
+        )?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::Truthy),
+            |caller: Caller<'_, Host>, handle: i32| -> wasmtime::Result<i32> {
+                Ok(i32::from(ops::truthy(caller.data().get(handle)?)))
+            },
+        )?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::VecNew),
+            |mut caller: Caller<'_, Host>, x: f32, y: f32, z: f32| {
+                caller
+                    .data_mut()
+                    .alloc(ScriptValue::Vector(mutsea_core::Vector3::new(x, y, z)))
+            },
+        )?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::RotNew),
+            |mut caller: Caller<'_, Host>, x: f32, y: f32, z: f32, s: f32| {
+                caller
+                    .data_mut()
+                    .alloc(ScriptValue::Rotation(mutsea_core::Quaternion::new(x, y, z, s)))
+            },
+        )?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::Member),
+            |caller: Caller<'_, Host>, handle: i32, index: i32| -> wasmtime::Result<f32> {
+                ops::member(caller.data().get(handle)?, index).map_err(fault)
+            },
+        )?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::WithMember),
+            |mut caller: Caller<'_, Host>, handle: i32, index: i32, component: f32| -> wasmtime::Result<i32> {
+                let result = ops::with_member(caller.data().get(handle)?, index, component).map_err(fault)?;
+                caller.data_mut().alloc(result)
+            },
+        )?;
+        linker.func_wrap("env", import_name(Import::ListNew), |mut caller: Caller<'_, Host>| {
+            caller.data_mut().alloc(ScriptValue::List(Vec::new()))
+        })?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::ListPush),
+            |mut caller: Caller<'_, Host>, list: i32, value: i32| -> wasmtime::Result<i32> {
+                // Only lists still being built are pushed to, so they are
+                // changed in place
+                let host = caller.data_mut();
+                let value = host.get(value)?.clone();
+                host.heap_bytes += 4 + ops::size_of(&value);
+                if host.heap_bytes > host.max_memory {
+                    return Err(fault("Stack-Heap Collision"));
+                }
+                match host.heap.get_mut(list as u32 as usize) {
+                    Some(ScriptValue::List(items)) => items.push(value),
+                    _ => return Err(fault("expected a list")),
+                }
+                Ok(list)
+            },
+        )?;
+        linker.func_wrap(
+            "env",
+            import_name(Import::Ll),
+            |mut caller: Caller<'_, Host>, function: i32, args: i32| -> wasmtime::Result<i32> {
+                let host = caller.data_mut();
+                let args = match host.get(args)? {
+                    ScriptValue::List(items) => items.clone(),
+                    _ => return Err(fault("expected an argument list")),
+                };
+                let result = library::call(function as u32 as usize, args, &mut host.context).map_err(fault)?;
+                for action in host.context.actions.drain(..) {
+                    let _ = host.actions.send(action);
+                }
+                host.alloc(result.unwrap_or(ScriptValue::Integer(0)))
+            },
+        )?;
+        Ok(linker)
+    }
+
+    /// Load `compiled` as `script`, then restore `snapshot` if given.
+    /// Globals of the snapshot the script no longer has, or has with
+    /// another type, are left at their initial values.
+    pub fn load_compiled(
+        &self,
+        script: &ScriptInfo,
+        compiled: &CompiledScript,
+        snapshot: Option<&ScriptSnapshot>,
+    ) -> Result<(), ExecutorError> {
+        let module = Module::new(&self.engine, &compiled.wasm).map_err(|e| ExecutorError::Engine(e.to_string()))?;
+        let mut store = Store::new(
+            &self.engine,
+            Host {
+                heap: Vec::new(),
+                heap_bytes: 0,
+                max_memory: self.max_memory,
+                context: CallContext::new(script.clone()),
+                actions: self.actions.clone(),
+            },
+        );
+        let instantiate = |error: wasmtime::Error| ExecutorError::Instantiate(describe(&error));
+        store.set_fuel(u64::MAX).map_err(instantiate)?;
+        store.fuel_async_yield_interval(Some(YIELD_FUEL)).map_err(instantiate)?;
+        self.refresh(&mut store);
+
+        let deadline = Instant::now() + INIT_BUDGET;
+        let instance = poll_until(pin!(self.linker.instantiate_async(&mut store, &module)), deadline)
+            .ok_or_else(|| ExecutorError::Instantiate("took too long".to_string()))?
+            .map_err(instantiate)?;
+        let init = instance
+            .get_func(&mut store, "init")
+            .ok_or_else(|| ExecutorError::Instantiate("no init function".to_string()))?;
+        poll_until(pin!(init.call_async(&mut store, &[], &mut [])), deadline)
+            .ok_or_else(|| ExecutorError::Instantiate("global initialisers took too long".to_string()))?
+            .map_err(instantiate)?;
+
+        let mut globals = Vec::new();
+        for (name, ty) in &compiled.globals {
+            let global = instance
+                .get_global(&mut store, &format!("g:{}:{}", ty.name(), name))
+                .ok_or_else(|| ExecutorError::Instantiate(format!("no global {}", name)))?;
+            globals.push((name.clone(), *ty, global));
+        }
+
+        let mut loaded = LoadedScript {
+            instance,
+            store: Some(store),
+            running: None,
+            follow_ups: VecDeque::new(),
+            state_changed: false,
+            current_state: 0,
+            states: compiled.states.clone(),
+            globals,
+            memory: 0,
+        };
+        if let Some(snapshot) = snapshot {
+            loaded.restore(snapshot)?;
+            loaded.current_state = loaded.state().map_err(instantiate)?;
+        }
+        loaded.collect().map_err(instantiate)?;
+        self.scripts.lock().unwrap().insert(script.id, loaded);
+        Ok(())
+    }
+
+    /// The state of `script` between events; `None` while it is running one
+    pub fn snapshot_state(&self, script: Uuid) -> Option<ScriptSnapshot> {
+        let mut scripts = self.scripts.lock().unwrap();
+        let loaded = scripts.get_mut(&script)?;
+        if loaded.running.is_some() {
+            return None;
+        }
+        loaded.snapshot().ok()
+    }
+
+    /// Update what `store`'s script sees of its prim and the region
+    fn refresh(&self, store: &mut Store<Host>) {
+        let Ok(scene) = self.scene.try_read() else {
+            return;
+        };
+        let context = &mut store.data_mut().context;
+        context.region_name = scene.info.region_name.clone();
+        if let Some(object) = scene.objects.get(&context.script.object_id) {
+            context.object_name = object.name.clone();
+            context.position = object.position;
+            context.rotation = object.rotation;
+        }
+    }
+}
+
+impl LoadedScript {
+    fn store(&mut self) -> wasmtime::Result<&mut Store<Host>> {
+        self.store.as_mut().ok_or_else(|| fault("script is running"))
+    }
+
+    fn state_name(&self, state: i32) -> &str {
+        self.states.get(state as usize).map(String::as_str).unwrap_or_default()
+    }
+
+    fn state(&mut self) -> wasmtime::Result<i32> {
+        let instance = self.instance;
+        let store = self.store()?;
+        let global = instance
+            .get_global(&mut *store, "state")
+            .ok_or_else(|| fault("no state global"))?;
+        global.get(&mut *store).i32().ok_or_else(|| fault("bad state global"))
+    }
+
+    /// Start `export` with `params`, if the script has it
+    fn start(&mut self, export: &str, params: Vec<Val>) -> wasmtime::Result<bool> {
+        let instance = self.instance;
+        let Some(func) = instance.get_func(self.store()?, export) else {
+            return Ok(false);
+        };
+        let store = self.store.take().ok_or_else(|| fault("script is running"))?;
+        self.running = Some(Box::pin(run(func, params, store)));
+        Ok(true)
+    }
+
+    /// Parameters for `event`'s handler, allocating the values it takes by
+    /// handle. Missing arguments are zero and extra ones ignored.
+    fn event_params(&mut self, event: &ScriptEvent) -> wasmtime::Result<Vec<Val>> {
+        let types = library::event_params(&event.name).unwrap_or(&[]);
+        let store = self.store()?;
+        let mut params = Vec::with_capacity(types.len());
+        for (index, ty) in types.iter().enumerate() {
+            let value = event.args.get(index).cloned().unwrap_or_else(|| ops::zero(*ty));
+            let value = ops::cast(&value, *ty).unwrap_or_else(|_| ops::zero(*ty));
+            params.push(match value {
+                ScriptValue::Integer(n) => Val::I32(n),
+                ScriptValue::Float(f) => Val::F32(f.to_bits()),
+                other => Val::I32(store.data_mut().alloc(other)?),
+            });
+        }
+        Ok(params)
+    }
+
+    /// Read globals back into values, for snapshots and collection
+    fn global_values(&mut self) -> wasmtime::Result<Vec<ScriptValue>> {
+        let globals = self.globals.clone();
+        let store = self.store()?;
+        let mut values = Vec::with_capacity(globals.len());
+        for (_, ty, global) in &globals {
+            let value = global.get(&mut *store);
+            values.push(match ty {
+                Type::Integer => ScriptValue::Integer(value.i32().unwrap_or_default()),
+                Type::Float => ScriptValue::Float(value.f32().unwrap_or_default()),
+                _ => store.data().get(value.i32().unwrap_or(-1))?.clone(),
+            });
+        }
+        Ok(values)
+    }
+
+    /// Set globals to `values`, allocating those held by handle anew
+    fn set_globals(&mut self, values: Vec<ScriptValue>) -> wasmtime::Result<()> {
+        let globals = self.globals.clone();
+        let store = self.store()?;
+        for ((_, _, global), value) in globals.iter().zip(values) {
+            let value = match value {
+                ScriptValue::Integer(n) => Val::I32(n),
+                ScriptValue::Float(f) => Val::F32(f.to_bits()),
+                other => Val::I32(store.data_mut().alloc(other)?),
+            };
+            global.set(&mut *store, value)?;
+        }
+        Ok(())
+    }
+
+    /// Drop every value no global refers to; run between events only
+    fn collect(&mut self) -> wasmtime::Result<()> {
+        let values = self.global_values()?;
+        let store = self.store()?;
+        store.data_mut().heap.clear();
+        store.data_mut().heap_bytes = 0;
+        self.set_globals(values)?;
+        let count = self.globals.len();
+        self.memory = self.store()?.data().heap_bytes + count * 4;
+        Ok(())
+    }
+
+    fn snapshot(&mut self) -> wasmtime::Result<ScriptSnapshot> {
+        let values = self.global_values()?;
+        let state = self.state()?;
+        Ok(ScriptSnapshot {
+            state: self.state_name(state).to_string(),
+            globals: self.globals.iter().map(|(name, ..)| name.clone()).zip(values).collect(),
+        })
+    }
+
+    fn restore(&mut self, snapshot: &ScriptSnapshot) -> Result<(), ExecutorError> {
+        let error = |e: wasmtime::Error| ExecutorError::Snapshot(describe(&e));
+        let mut values = self.global_values().map_err(error)?;
+        for ((name, ty, _), value) in self.globals.iter().zip(values.iter_mut()) {
+            let saved = snapshot.globals.iter().find(|(saved, _)| saved == name);
+            if let Some((_, saved)) = saved {
+                if ops::type_of(saved) == *ty || (ty.is_handle() && ops::cast(saved, *ty).is_ok()) {
+                    *value = ops::cast(saved, *ty).unwrap_or_else(|_| saved.clone());
+                }
+            }
+        }
+        self.set_globals(values).map_err(error)?;
+
+        let state = self
+            .states
+            .iter()
+            .position(|state| *state == snapshot.state)
+            .ok_or_else(|| ExecutorError::Snapshot(format!("no state {}", snapshot.state)))?;
+        let instance = self.instance;
+        let store = self.store().map_err(error)?;
+        let global = instance
+            .get_global(&mut *store, "state")
+            .ok_or_else(|| ExecutorError::Snapshot("no state global".to_string()))?;
+        global.set(&mut *store, Val::I32(state as i32)).map_err(error)
+    }
+}
+
+/// Call `func` with `params`, owning `store` until it returns
+async fn run(func: Func, params: Vec<Val>, mut store: Store<Host>) -> (Store<Host>, wasmtime::Result<()>) {
+    let result = func.call_async(&mut store, &params, &mut []).await;
+    (store, result)
+}
+
+impl ScriptRuntime for WasmExecutor {
+    fn run(&self, script: Uuid, event: &ScriptEvent, budget: Duration) -> Result<ScriptRun, String> {
+        let deadline = Instant::now() + budget;
+        let mut scripts = self.scripts.lock().unwrap();
+        let loaded = scripts
+            .get_mut(&script)
+            .ok_or_else(|| "script is not loaded".to_string())?;
+        let fail = |error: wasmtime::Error| describe(&error);
+
+        if loaded.running.is_none() {
+            if let Some(store) = loaded.store.as_mut() {
+                self.refresh(store);
+            }
+            let params = loaded.event_params(event).map_err(fail)?;
+            let export = format!("{}/{}", loaded.state_name(loaded.current_state), event.name);
+            loaded.follow_ups.clear();
+            loaded.state_changed = false;
+            if !loaded.start(&export, params).map_err(fail)? {
+                // Events the current state does not handle are dropped
+                loaded.collect().map_err(fail)?;
+                return Ok(ScriptRun {
+                    memory: loaded.memory,
+                    finished: true,
+                    state_changed: false,
+                });
+            }
+        }
+
+        while let Some(running) = loaded.running.as_mut() {
+            let Some((store, result)) = poll_until(running.as_mut(), deadline) else {
+                return Ok(ScriptRun {
+                    memory: loaded.memory,
+                    finished: false,
+                    state_changed: false,
+                });
+            };
+            loaded.running = None;
+            loaded.store = Some(store);
+            result.map_err(fail)?;
+
+            // A state change runs state_exit in the old state, then
+            // state_entry in the new one
+            let state = loaded.state().map_err(fail)?;
+            if state != loaded.current_state {
+                let old = loaded.state_name(loaded.current_state).to_string();
+                let new = loaded.state_name(state).to_string();
+                loaded.follow_ups.push_back(format!("{}/state_exit", old));
+                loaded.follow_ups.push_back(format!("{}/state_entry", new));
+                loaded.current_state = state;
+                loaded.state_changed = true;
+            }
+            while let Some(export) = loaded.follow_ups.pop_front() {
+                if loaded.start(&export, Vec::new()).map_err(fail)? {
+                    break;
+                }
+            }
+        }
+
+        loaded.collect().map_err(fail)?;
+        Ok(ScriptRun {
+            memory: loaded.memory,
+            finished: true,
+            state_changed: loaded.state_changed,
+        })
+    }
+
+    fn load(&self, script: &ScriptInfo, source: &str, snapshot: Option<&[u8]>) -> Result<bool, String> {
+        let compiled = compile(source).map_err(|e| e.to_string())?;
+        let snapshot = match snapshot {
+            Some(bytes) => {
+                Some(bincode::deserialize::<ScriptSnapshot>(bytes).map_err(|e| format!("bad snapshot: {}", e))?)
+            }
+            None => None,
+        };
+        self.load_compiled(script, &compiled, snapshot.as_ref())
+            .map_err(|e| e.to_string())?;
+        Ok(snapshot.is_none())
+    }
+
+    fn unload(&self, script: Uuid) {
+        self.scripts.lock().unwrap().remove(&script);
+    }
+
+    fn snapshot(&self, script: Uuid) -> Option<Vec<u8>> {
+        bincode::serialize(&self.snapshot_state(script)?).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::{ObjectId, RegionInfo, UserId};
+
+    fn executor() -> (WasmExecutor, mpsc::UnboundedReceiver<ScriptAction>) {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        WasmExecutor::new(scene, 64).unwrap()
+    }
+
+    fn script() -> ScriptInfo {
+        ScriptInfo {
+            id: Uuid::new_v4(),
+            name: "Counter".to_string(),
+            object_id: ObjectId::new(),
+            owner_id: UserId::new(),
+        }
+    }
+
+    fn said(actions: &mut mpsc::UnboundedReceiver<ScriptAction>) -> Vec<String> {
+        let mut said = Vec::new();
+        while let Ok(action) = actions.try_recv() {
+            if let ScriptAction::Chat { message, .. } = action {
+                said.push(message);
+            }
+        }
+        said
+    }
+
+    const COUNTER: &str = r#"
+        integer count;
+        list seen;
+
+        default {
+            state_entry() { llSay(0, "ready"); }
+            touch_start(integer n) {
+                count += n;
+                seen += ["touch"];
+                if (count >= 3) state full;
+            }
+        }
+
+        state full {
+            state_entry() { llSay(0, "full at " + (string)count); }
+            touch_start(integer n) { llSay(0, "no more"); }
+        }
+    "#;
+
+    #[test]
+    fn test_handlers_run_and_change_state() {
+        let (executor, mut actions) = executor();
+        let info = script();
+        ScriptRuntime::load(&executor, &info, COUNTER, None).unwrap();
+        let budget = Duration::from_millis(50);
+
+        let entry = executor
+            .run(info.id, &ScriptEvent::new("state_entry", Vec::new()), budget)
+            .unwrap();
+        assert!(entry.finished);
+        let touch = ScriptEvent::new("touch_start", vec![ScriptValue::Integer(2)]);
+        assert!(!executor.run(info.id, &touch, budget).unwrap().state_changed);
+        let run = executor.run(info.id, &touch, budget).unwrap();
+        assert!(run.finished && run.state_changed);
+        executor.run(info.id, &touch, budget).unwrap();
+        assert_eq!(said(&mut actions), ["ready", "full at 4", "no more"]);
+
+        // Unhandled events are dropped
+        assert!(
+            executor
+                .run(info.id, &ScriptEvent::new("timer", Vec::new()), budget)
+                .unwrap()
+                .finished
+        );
+    }
+
+    #[test]
+    fn test_long_handlers_are_preempted_and_resumed() {
+        let (executor, mut actions) = executor();
+        let info = script();
+        ScriptRuntime::load(
+            &executor,
+            &info,
+            r#"
+                integer i;
+                default {
+                    touch_start(integer n) {
+                        while (i < 3000000) i++;
+                        llSay(0, "done " + (string)i);
+                    }
+                }
+                "#,
+            None,
+        )
+        .unwrap();
+        let touch = ScriptEvent::new("touch_start", vec![ScriptValue::Integer(1)]);
+
+        let mut slices = 0;
+        loop {
+            slices += 1;
+            let run = executor.run(info.id, &touch, Duration::from_millis(1)).unwrap();
+            if run.finished {
+                break;
+            }
+            // No snapshot of a script in the middle of an event
+            assert!(executor.snapshot_state(info.id).is_none());
+        }
+        assert!(slices > 1, "ran in one slice");
+        assert_eq!(said(&mut actions), ["done 3000000"]);
+    }
+
+    #[test]
+    fn test_snapshot_restores_globals_and_state() {
+        let (executor, _actions) = executor();
+        let info = script();
+        ScriptRuntime::load(&executor, &info, COUNTER, None).unwrap();
+        let touch = ScriptEvent::new("touch_start", vec![ScriptValue::Integer(3)]);
+        executor.run(info.id, &touch, Duration::from_millis(50)).unwrap();
+
+        let snapshot = ScriptRuntime::snapshot(&executor, info.id).unwrap();
+        let (elsewhere, mut actions) = self::executor();
+        let fresh = ScriptRuntime::load(&elsewhere, &info, COUNTER, Some(&snapshot)).unwrap();
+        assert!(!fresh);
+        assert_eq!(
+            elsewhere.snapshot_state(info.id).unwrap(),
+            ScriptSnapshot {
+                state: "full".to_string(),
+                globals: vec![
+                    ("count".to_string(), ScriptValue::Integer(3)),
+                    (
+                        "seen".to_string(),
+                        ScriptValue::List(vec![ScriptValue::String("touch".to_string())])
+                    ),
+                ],
+            }
+        );
+        elsewhere.run(info.id, &touch, Duration::from_millis(50)).unwrap();
+        assert_eq!(said(&mut actions), ["no more"]);
+    }
+
+    #[test]
+    fn test_faults_are_reported() {
+        let (executor, _actions) = executor();
+        let info = script();
+        ScriptRuntime::load(
+            &executor,
+            &info,
+            r#"
+                list grow;
+                default {
+                    touch_start(integer n) { integer zero; llSay(0, (string)(n / zero)); }
+                    timer() { while (TRUE) grow += ["more text to fill the heap"]; }
+                }
+                "#,
+            None,
+        )
+        .unwrap();
+        let budget = Duration::from_secs(1);
+        let touch = ScriptEvent::new("touch_start", vec![ScriptValue::Integer(1)]);
+        assert_eq!(executor.run(info.id, &touch, budget).unwrap_err(), "Math error");
+        let timer = ScriptEvent::new("timer", Vec::new());
+        assert_eq!(
+            executor.run(info.id, &timer, budget).unwrap_err(),
+            "Stack-Heap Collision"
+        );
+    }
+}
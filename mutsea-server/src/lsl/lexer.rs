@@ -0,0 +1,197 @@
+//! Tokens of LSL source
+
+use super::CompileError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Integer(i32),
+    Float(f32),
+    String(String),
+    /// Punctuation and operators, such as `{`, `<<=` or `++`
+    Punct(&'static str),
+    Eof,
+}
+
+/// Longest first, so `<<=` is not read as `<` `<=`
+const PUNCTUATION: &[&str] = &[
+    "<<=", ">>=", "++", "--", "+=", "-=", "*=", "/=", "%=", "==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "(", ")",
+    "{", "}", "[", "]", ";", ",", ".", "=", "+", "-", "*", "/", "%", "<", ">", "!", "~", "&", "|", "^", "@",
+];
+
+/// Split `source` into tokens, each with its line. The last is [`Token::Eof`].
+pub fn tokenize(source: &str) -> Result<Vec<(Token, u32)>, CompileError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            let start = line;
+            i += 2;
+            loop {
+                match chars.get(i) {
+                    None => return Err(CompileError::new(start, "unterminated comment")),
+                    Some('*') if chars.get(i + 1) == Some(&'/') => {
+                        i += 2;
+                        break;
+                    }
+                    Some('\n') => line += 1,
+                    _ => {}
+                }
+                i += 1;
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), line));
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())) {
+            let (token, end) = number(&chars, i, line)?;
+            tokens.push((token, line));
+            i = end;
+        } else if c == '"' {
+            let start = line;
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(CompileError::new(start, "unterminated string")),
+                    Some('"') => break,
+                    Some('\\') => {
+                        i += 1;
+                        match chars.get(i) {
+                            Some('n') => text.push('\n'),
+                            Some('t') => text.push_str("    "),
+                            Some(&other) => text.push(other),
+                            None => return Err(CompileError::new(start, "unterminated string")),
+                        }
+                    }
+                    Some(&other) => {
+                        if other == '\n' {
+                            line += 1;
+                        }
+                        text.push(other);
+                    }
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push((Token::String(text), line));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+            let Some(punct) = PUNCTUATION.iter().find(|p| rest.starts_with(**p)) else {
+                return Err(CompileError::new(line, format!("unexpected character '{}'", c)));
+            };
+            tokens.push((Token::Punct(punct), line));
+            i += punct.len();
+        }
+    }
+
+    tokens.push((Token::Eof, line));
+    Ok(tokens)
+}
+
+/// Read the integer or float starting at `start`
+fn number(chars: &[char], start: usize, line: u32) -> Result<(Token, usize), CompileError> {
+    let mut i = start;
+    if chars[i] == '0' && matches!(chars.get(i + 1), Some('x' | 'X')) {
+        i += 2;
+        let digits_start = i;
+        while i < chars.len() && chars[i].is_ascii_hexdigit() {
+            i += 1;
+        }
+        let digits: String = chars[digits_start..i].iter().collect();
+        let value = u64::from_str_radix(&digits, 16)
+            .map_err(|_| CompileError::new(line, format!("bad hexadecimal number '0x{}'", digits)))?;
+        // Wider constants keep their low bits, as in the viewer's compiler
+        return Ok((Token::Integer(value as u32 as i32), i));
+    }
+
+    let mut float = false;
+    while i < chars.len() && chars[i].is_ascii_digit() {
+        i += 1;
+    }
+    if chars.get(i) == Some(&'.') {
+        float = true;
+        i += 1;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+    }
+    if matches!(chars.get(i), Some('e' | 'E')) {
+        let mut j = i + 1;
+        if matches!(chars.get(j), Some('+' | '-')) {
+            j += 1;
+        }
+        if chars.get(j).is_some_and(|c| c.is_ascii_digit()) {
+            float = true;
+            i = j;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+        }
+    }
+    let text: String = chars[start..i].iter().collect();
+    if matches!(chars.get(i), Some('f' | 'F')) {
+        float = true;
+        i += 1;
+    }
+
+    if float {
+        let value = text
+            .parse::<f32>()
+            .map_err(|_| CompileError::new(line, format!("bad number '{}'", text)))?;
+        Ok((Token::Float(value), i))
+    } else {
+        let value = text
+            .parse::<u64>()
+            .map_err(|_| CompileError::new(line, format!("bad number '{}'", text)))?;
+        // Integers past i32 become -1, as in the viewer's compiler
+        Ok((Token::Integer(i32::try_from(value).unwrap_or(-1)), i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        let tokens: Vec<Token> = tokenize("x <<= 0x1F; // done\n/* a\nb */ y = .5e1 + \"a\\\"b\" 3")
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("x".to_string()),
+                Token::Punct("<<="),
+                Token::Integer(31),
+                Token::Punct(";"),
+                Token::Ident("y".to_string()),
+                Token::Punct("="),
+                Token::Float(5.0),
+                Token::Punct("+"),
+                Token::String("a\"b".to_string()),
+                Token::Integer(3),
+                Token::Eof,
+            ]
+        );
+        assert_eq!(tokenize("a\n\nb").unwrap()[1].1, 3);
+        assert!(tokenize("\"open").is_err());
+    }
+}
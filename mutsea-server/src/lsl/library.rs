@@ -0,0 +1,1167 @@
+//! The ll* functions, events and constants compiled scripts may use
+
+use mutsea_core::{ObjectId, Quaternion, UserId, Vector3};
+use std::time::Instant;
+use uuid::Uuid;
+
+use super::ast::Type;
+use super::ops::{self, to_string};
+use crate::script_email::OutboundEmail;
+use crate::script_http::OutboundRequest;
+use crate::scripts::{ScriptInfo, ScriptValue};
+
+const I: Type = Type::Integer;
+const F: Type = Type::Float;
+const S: Type = Type::String;
+const K: Type = Type::Key;
+const V: Type = Type::Vector;
+const R: Type = Type::Rotation;
+const L: Type = Type::List;
+
+/// Furthest llSetPos moves a prim in one call, in metres
+const MAX_MOVE: f32 = 10.0;
+
+/// HTTP_BODY_MAXLENGTH unless a script asks for more
+const DEFAULT_HTTP_BODY: usize = 2048;
+
+/// Signature of a library function; its index in [`FUNCTIONS`] is what
+/// compiled scripts pass to the host
+#[derive(Debug)]
+pub struct Function {
+    pub name: &'static str,
+    pub returns: Option<Type>,
+    pub params: &'static [Type],
+}
+
+const fn function(name: &'static str, returns: Option<Type>, params: &'static [Type]) -> Function {
+    Function { name, returns, params }
+}
+
+pub const FUNCTIONS: &[Function] = &[
+    // Chat
+    function("llSay", None, &[I, S]),
+    function("llWhisper", None, &[I, S]),
+    function("llShout", None, &[I, S]),
+    function("llRegionSay", None, &[I, S]),
+    function("llOwnerSay", None, &[S]),
+    // Time
+    function("llSetTimerEvent", None, &[F]),
+    function("llGetTime", Some(F), &[]),
+    function("llResetTime", None, &[]),
+    function("llGetAndResetTime", Some(F), &[]),
+    function("llGetUnixTime", Some(I), &[]),
+    function("llGetTimestamp", Some(S), &[]),
+    // The prim and the script
+    function("llGetPos", Some(V), &[]),
+    function("llSetPos", None, &[V]),
+    function("llGetRot", Some(R), &[]),
+    function("llSetRot", None, &[R]),
+    function("llGetKey", Some(K), &[]),
+    function("llGetOwner", Some(K), &[]),
+    function("llGetObjectName", Some(S), &[]),
+    function("llGetScriptName", Some(S), &[]),
+    function("llGetRegionName", Some(S), &[]),
+    function("llDie", None, &[]),
+    // Maths
+    function("llAbs", Some(I), &[I]),
+    function("llFabs", Some(F), &[F]),
+    function("llFloor", Some(I), &[F]),
+    function("llCeil", Some(I), &[F]),
+    function("llRound", Some(I), &[F]),
+    function("llSqrt", Some(F), &[F]),
+    function("llPow", Some(F), &[F, F]),
+    function("llSin", Some(F), &[F]),
+    function("llCos", Some(F), &[F]),
+    function("llTan", Some(F), &[F]),
+    function("llAtan2", Some(F), &[F, F]),
+    function("llFrand", Some(F), &[F]),
+    function("llVecMag", Some(F), &[V]),
+    function("llVecNorm", Some(V), &[V]),
+    function("llVecDist", Some(F), &[V, V]),
+    function("llEuler2Rot", Some(R), &[V]),
+    function("llRot2Euler", Some(V), &[R]),
+    function("llAxisAngle2Rot", Some(R), &[V, F]),
+    // Strings
+    function("llStringLength", Some(I), &[S]),
+    function("llGetSubString", Some(S), &[S, I, I]),
+    function("llDeleteSubString", Some(S), &[S, I, I]),
+    function("llInsertString", Some(S), &[S, I, S]),
+    function("llSubStringIndex", Some(I), &[S, S]),
+    function("llToUpper", Some(S), &[S]),
+    function("llToLower", Some(S), &[S]),
+    function("llStringTrim", Some(S), &[S, I]),
+    // Lists
+    function("llGetListLength", Some(I), &[L]),
+    function("llGetListEntryType", Some(I), &[L, I]),
+    function("llList2String", Some(S), &[L, I]),
+    function("llList2Integer", Some(I), &[L, I]),
+    function("llList2Float", Some(F), &[L, I]),
+    function("llList2Key", Some(K), &[L, I]),
+    function("llList2Vector", Some(V), &[L, I]),
+    function("llList2Rot", Some(R), &[L, I]),
+    function("llList2List", Some(L), &[L, I, I]),
+    function("llDeleteSubList", Some(L), &[L, I, I]),
+    function("llListFindList", Some(I), &[L, L]),
+    function("llDumpList2String", Some(S), &[L, S]),
+    function("llParseString2List", Some(L), &[S, L, L]),
+    function("llList2CSV", Some(S), &[L]),
+    function("llCSV2List", Some(L), &[S]),
+    // HTTP
+    function("llHTTPRequest", Some(K), &[S, L, S]),
+    function("llHTTPResponse", None, &[K, I, S]),
+    function("llRequestURL", Some(K), &[]),
+    function("llReleaseURL", None, &[S]),
+    // Email and XML-RPC
+    function("llEmail", None, &[S, S, S]),
+    function("llGetNextEmail", None, &[S, S]),
+    function("llOpenRemoteDataChannel", None, &[]),
+    function("llRemoteDataReply", None, &[K, K, S, I]),
+    function("llCloseRemoteDataChannel", None, &[K]),
+    // Sound
+    function("llPlaySound", None, &[S, F]),
+    function("llLoopSound", None, &[S, F]),
+    function("llTriggerSound", None, &[S, F]),
+    function("llStopSound", None, &[]),
+    function("llPreloadSound", None, &[S]),
+    function("llAdjustSoundVolume", None, &[F]),
+];
+
+/// The index and signature of library function `name`
+pub fn lookup(name: &str) -> Option<(usize, &'static Function)> {
+    FUNCTIONS.iter().enumerate().find(|(_, function)| function.name == name)
+}
+
+/// Parameters of event `name`, if it is one
+pub fn event_params(name: &str) -> Option<&'static [Type]> {
+    Some(match name {
+        "state_entry" | "state_exit" | "timer" | "no_sensor" | "moving_start" | "moving_end" => &[],
+        "touch_start" | "touch" | "touch_end" | "collision_start" | "collision" | "collision_end" | "sensor"
+        | "on_rez" | "changed" => &[I],
+        "listen" => &[I, S, K, S],
+        "attach" => &[K],
+        "money" => &[K, I],
+        "link_message" => &[I, I, S, K],
+        "dataserver" => &[K, S],
+        "http_response" => &[K, I, L, S],
+        "http_request" => &[K, S, S],
+        "email" => &[S, S, S, S, I],
+        "remote_data" => &[I, K, K, S, I, S],
+        _ => return None,
+    })
+}
+
+/// A named constant
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Constant {
+    Integer(i32),
+    Float(f32),
+    String(&'static str),
+    Vector([f32; 3]),
+    Rotation([f32; 4]),
+}
+
+impl Constant {
+    pub fn ty(self) -> Type {
+        match self {
+            Constant::Integer(_) => Type::Integer,
+            Constant::Float(_) => Type::Float,
+            Constant::String(_) => Type::String,
+            Constant::Vector(_) => Type::Vector,
+            Constant::Rotation(_) => Type::Rotation,
+        }
+    }
+}
+
+pub fn constant(name: &str) -> Option<Constant> {
+    use std::f32::consts::{FRAC_PI_2, PI, SQRT_2};
+    Some(match name {
+        "TRUE" => Constant::Integer(1),
+        "FALSE" => Constant::Integer(0),
+        "PI" => Constant::Float(PI),
+        "TWO_PI" => Constant::Float(PI * 2.0),
+        "PI_BY_TWO" => Constant::Float(FRAC_PI_2),
+        "DEG_TO_RAD" => Constant::Float(PI / 180.0),
+        "RAD_TO_DEG" => Constant::Float(180.0 / PI),
+        "SQRT2" => Constant::Float(SQRT_2),
+        "NULL_KEY" => Constant::String("00000000-0000-0000-0000-000000000000"),
+        "EOF" => Constant::String("\n\n\n"),
+        "ZERO_VECTOR" => Constant::Vector([0.0; 3]),
+        "ZERO_ROTATION" => Constant::Rotation([0.0, 0.0, 0.0, 1.0]),
+        "PUBLIC_CHANNEL" => Constant::Integer(0),
+        "DEBUG_CHANNEL" => Constant::Integer(i32::MAX),
+        "ALL_SIDES" => Constant::Integer(-1),
+        "STRING_TRIM_HEAD" => Constant::Integer(1),
+        "STRING_TRIM_TAIL" => Constant::Integer(2),
+        "STRING_TRIM" => Constant::Integer(3),
+        "TYPE_INTEGER" => Constant::Integer(1),
+        "TYPE_FLOAT" => Constant::Integer(2),
+        "TYPE_STRING" => Constant::Integer(3),
+        "TYPE_KEY" => Constant::Integer(4),
+        "TYPE_VECTOR" => Constant::Integer(5),
+        "TYPE_ROTATION" => Constant::Integer(6),
+        "TYPE_INVALID" => Constant::Integer(0),
+        "CHANGED_INVENTORY" => Constant::Integer(0x1),
+        "CHANGED_COLOR" => Constant::Integer(0x2),
+        "CHANGED_SHAPE" => Constant::Integer(0x4),
+        "CHANGED_SCALE" => Constant::Integer(0x8),
+        "CHANGED_TEXTURE" => Constant::Integer(0x10),
+        "CHANGED_LINK" => Constant::Integer(0x20),
+        "CHANGED_ALLOWED_DROP" => Constant::Integer(0x40),
+        "CHANGED_OWNER" => Constant::Integer(0x80),
+        "CHANGED_REGION" => Constant::Integer(0x100),
+        "CHANGED_TELEPORT" => Constant::Integer(0x200),
+        "CHANGED_REGION_START" => Constant::Integer(0x400),
+        "HTTP_METHOD" => Constant::Integer(0),
+        "HTTP_MIMETYPE" => Constant::Integer(1),
+        "HTTP_BODY_MAXLENGTH" => Constant::Integer(2),
+        "HTTP_VERIFY_CERT" => Constant::Integer(3),
+        "URL_REQUEST_GRANTED" => Constant::String("URL_REQUEST_GRANTED"),
+        "URL_REQUEST_DENIED" => Constant::String("URL_REQUEST_DENIED"),
+        "REMOTE_DATA_CHANNEL" => Constant::Integer(1),
+        "REMOTE_DATA_REQUEST" => Constant::Integer(2),
+        "REMOTE_DATA_REPLY" => Constant::Integer(3),
+        _ => return None,
+    })
+}
+
+/// How far chat carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatVolume {
+    Whisper,
+    Say,
+    Shout,
+    Region,
+}
+
+/// Something a script asks of the world, carried out by the region
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptAction {
+    Chat {
+        object_id: ObjectId,
+        name: String,
+        position: Vector3,
+        channel: i32,
+        volume: ChatVolume,
+        message: String,
+    },
+    OwnerSay {
+        owner_id: UserId,
+        object_id: ObjectId,
+        name: String,
+        position: Vector3,
+        message: String,
+    },
+    /// Post `timer` to the script every `interval` seconds; 0 stops it
+    SetTimer {
+        script: Uuid,
+        interval: f32,
+    },
+    SetPosition {
+        object_id: ObjectId,
+        position: Vector3,
+    },
+    SetRotation {
+        object_id: ObjectId,
+        rotation: Quaternion,
+    },
+    Die {
+        object_id: ObjectId,
+    },
+    /// Send an llHTTPRequest, posting the reply as `http_response`
+    HttpRequest {
+        script: Uuid,
+        owner_id: UserId,
+        request_id: Uuid,
+        request: OutboundRequest,
+    },
+    /// Answer a request to one of the script's URLs
+    HttpResponse {
+        request_id: Uuid,
+        status: i32,
+        body: String,
+    },
+    /// Grant a URL, posted as `http_request` with URL_REQUEST_GRANTED
+    RequestUrl {
+        script: Uuid,
+        request_id: Uuid,
+    },
+    ReleaseUrl {
+        script: Uuid,
+        url: String,
+    },
+    /// Send an llEmail, or deliver it to a prim in the grid
+    Email(OutboundEmail),
+    /// Post the next matching message in the prim's inbox as `email`
+    NextEmail {
+        script: Uuid,
+        object_id: ObjectId,
+        address: String,
+        subject: String,
+    },
+    /// Open an XML-RPC channel, posted as `remote_data` with
+    /// REMOTE_DATA_CHANNEL
+    OpenRemoteData {
+        script: Uuid,
+    },
+    RemoteDataReply {
+        message_id: Uuid,
+        sdata: String,
+        idata: i32,
+    },
+    CloseRemoteData {
+        script: Uuid,
+        channel: Uuid,
+    },
+    /// Play `sound` from the prim, replacing what it was playing, over and
+    /// over if `looped`
+    PlaySound {
+        object_id: ObjectId,
+        sound: Uuid,
+        gain: f32,
+        looped: bool,
+    },
+    /// Play `sound` once where the prim is
+    TriggerSound {
+        object_id: ObjectId,
+        sound: Uuid,
+        gain: f32,
+    },
+    StopSound {
+        object_id: ObjectId,
+    },
+    PreloadSound {
+        object_id: ObjectId,
+        sound: Uuid,
+    },
+    AdjustSoundVolume {
+        object_id: ObjectId,
+        gain: f32,
+    },
+}
+
+/// What a script sees of its prim and the region during an event, and
+/// what it asked for
+#[derive(Debug, Clone)]
+pub struct CallContext {
+    pub script: ScriptInfo,
+    pub object_name: String,
+    pub position: Vector3,
+    pub rotation: Quaternion,
+    pub region_name: String,
+    /// Start of llGetTime, moved by llResetTime
+    pub time_base: Instant,
+    pub actions: Vec<ScriptAction>,
+}
+
+impl CallContext {
+    pub fn new(script: ScriptInfo) -> Self {
+        Self {
+            script,
+            object_name: String::new(),
+            position: Vector3::ZERO,
+            rotation: Quaternion::IDENTITY,
+            region_name: String::new(),
+            time_base: Instant::now(),
+            actions: Vec::new(),
+        }
+    }
+
+    fn chat(&mut self, channel: i32, volume: ChatVolume, message: String) {
+        self.actions.push(ScriptAction::Chat {
+            object_id: self.script.object_id,
+            name: self.object_name.clone(),
+            position: self.position,
+            channel,
+            volume,
+            message,
+        });
+    }
+
+    /// The sound asset `sound` names. Prims hold no sounds of their own,
+    /// so only keys are found; anything else is reported as in LSL.
+    fn sound(&mut self, sound: &str) -> Option<Uuid> {
+        let key = Uuid::parse_str(sound).ok();
+        if key.is_none() {
+            self.chat(i32::MAX, ChatVolume::Say, format!("Couldn't find sound {}", sound));
+        }
+        key
+    }
+}
+
+/// Read the llHTTPRequest options in `params` into a request for `url`
+fn http_request(url: String, params: &[ScriptValue], body: String) -> Result<OutboundRequest, String> {
+    let mut request = OutboundRequest {
+        method: "GET".to_string(),
+        url,
+        mimetype: "text/plain;charset=utf-8".to_string(),
+        body,
+        max_body: DEFAULT_HTTP_BODY,
+    };
+    let mut params = params.iter();
+    while let Some(option) = params.next() {
+        let value = params.next().ok_or("Missing HTTP parameter value")?;
+        match (option, value) {
+            (ScriptValue::Integer(0), ScriptValue::String(method)) => {
+                if !matches!(method.as_str(), "GET" | "POST" | "PUT" | "DELETE") {
+                    return Err(format!("Unsupported HTTP method {}", method));
+                }
+                request.method = method.clone();
+            }
+            (ScriptValue::Integer(1), ScriptValue::String(mimetype)) => request.mimetype = mimetype.clone(),
+            (ScriptValue::Integer(2), ScriptValue::Integer(length)) => {
+                request.max_body = usize::try_from(*length).map_err(|_| "Invalid HTTP_BODY_MAXLENGTH")?;
+            }
+            // Certificates are always verified
+            (ScriptValue::Integer(3), ScriptValue::Integer(_)) => {}
+            _ => return Err(format!("Invalid HTTP parameter {}", to_string(option))),
+        }
+    }
+    Ok(request)
+}
+
+/// Resolve a possibly negative `index` into a sequence of `len`
+fn position(len: usize, index: i32) -> Option<usize> {
+    let index = if index < 0 {
+        len as i64 + index as i64
+    } else {
+        index as i64
+    };
+    usize::try_from(index).ok().filter(|index| *index < len)
+}
+
+/// Indices kept by an inclusive LSL range from `start` to `end`; a range
+/// with start after end keeps what lies outside it
+fn range(len: usize, start: i32, end: i32) -> Vec<usize> {
+    let resolve = |index: i32| {
+        if index < 0 {
+            len as i64 + index as i64
+        } else {
+            index as i64
+        }
+    };
+    let (start, end) = (resolve(start), resolve(end));
+    (0..len)
+        .filter(|i| {
+            let i = *i as i64;
+            if start <= end {
+                i >= start && i <= end
+            } else {
+                i >= start || i <= end
+            }
+        })
+        .collect()
+}
+
+fn list_item(list: &[ScriptValue], index: i32) -> Option<&ScriptValue> {
+    position(list.len(), index).map(|index| &list[index])
+}
+
+fn euler_to_rot(v: Vector3) -> Quaternion {
+    let (sx, cx) = (v.x * 0.5).sin_cos();
+    let (sy, cy) = (v.y * 0.5).sin_cos();
+    let (sz, cz) = (v.z * 0.5).sin_cos();
+    Quaternion::new(
+        sx * cy * cz + cx * sy * sz,
+        cx * sy * cz - sx * cy * sz,
+        cx * cy * sz + sx * sy * cz,
+        cx * cy * cz - sx * sy * sz,
+    )
+}
+
+fn rot_to_euler(r: Quaternion) -> Vector3 {
+    let (x2, y2, z2, w2) = (r.x * r.x, r.y * r.y, r.z * r.z, r.w * r.w);
+    let m = x2 + y2 + z2 + w2;
+    if m == 0.0 {
+        return Vector3::ZERO;
+    }
+    let n = 2.0 * (r.y * r.w + r.x * r.z);
+    let p = m * m - n * n;
+    if p > 0.0 {
+        Vector3::new(
+            (2.0 * (r.x * r.w - r.y * r.z)).atan2(-x2 - y2 + z2 + w2),
+            n.atan2(p.sqrt()),
+            (2.0 * (r.z * r.w - r.x * r.y)).atan2(x2 - y2 - z2 + w2),
+        )
+    } else {
+        let z = (2.0 * (r.z * r.w + r.x * r.y)).atan2(0.5 - x2 - z2);
+        Vector3::new(0.0, std::f32::consts::FRAC_PI_2.copysign(n), z)
+    }
+}
+
+/// Call library function `index` with `args`, already of its parameter
+/// types. Returns its result, if it has one.
+pub fn call(index: usize, args: Vec<ScriptValue>, ctx: &mut CallContext) -> Result<Option<ScriptValue>, String> {
+    use ScriptValue::*;
+    let function = FUNCTIONS
+        .get(index)
+        .ok_or_else(|| format!("no library function {}", index))?;
+    let mut args = args.into_iter();
+    let mut next = || {
+        args.next()
+            .ok_or_else(|| format!("{}: too few arguments", function.name))
+    };
+    macro_rules! arg {
+        (Integer) => {
+            match next()? {
+                Integer(value) => value,
+                other => return Err(format!("{}: expected integer, got {:?}", function.name, other)),
+            }
+        };
+        (Float) => {
+            match next()? {
+                Float(value) => value,
+                Integer(value) => value as f32,
+                other => return Err(format!("{}: expected float, got {:?}", function.name, other)),
+            }
+        };
+        (String) => {
+            to_string(&next()?)
+        };
+        (Vector) => {
+            match next()? {
+                Vector(value) => value,
+                other => return Err(format!("{}: expected vector, got {:?}", function.name, other)),
+            }
+        };
+        (Rotation) => {
+            match next()? {
+                Rotation(value) => value,
+                other => return Err(format!("{}: expected rotation, got {:?}", function.name, other)),
+            }
+        };
+        (List) => {
+            match next()? {
+                List(items) => items,
+                other => vec![other],
+            }
+        };
+    }
+
+    let result = match function.name {
+        "llSay" | "llWhisper" | "llShout" | "llRegionSay" => {
+            let volume = match function.name {
+                "llWhisper" => ChatVolume::Whisper,
+                "llShout" => ChatVolume::Shout,
+                "llRegionSay" => ChatVolume::Region,
+                _ => ChatVolume::Say,
+            };
+            let (channel, message) = (arg!(Integer), arg!(String));
+            // llRegionSay may not be used on the public channel
+            if !(volume == ChatVolume::Region && channel == 0) {
+                ctx.chat(channel, volume, message);
+            }
+            None
+        }
+        "llOwnerSay" => {
+            let message = arg!(String);
+            ctx.actions.push(ScriptAction::OwnerSay {
+                owner_id: ctx.script.owner_id,
+                object_id: ctx.script.object_id,
+                name: ctx.object_name.clone(),
+                position: ctx.position,
+                message,
+            });
+            None
+        }
+        "llSetTimerEvent" => {
+            let interval = arg!(Float).max(0.0);
+            ctx.actions.push(ScriptAction::SetTimer {
+                script: ctx.script.id,
+                interval,
+            });
+            None
+        }
+        "llGetTime" => Some(Float(ctx.time_base.elapsed().as_secs_f32())),
+        "llResetTime" => {
+            ctx.time_base = Instant::now();
+            None
+        }
+        "llGetAndResetTime" => {
+            let elapsed = ctx.time_base.elapsed().as_secs_f32();
+            ctx.time_base = Instant::now();
+            Some(Float(elapsed))
+        }
+        "llGetUnixTime" => Some(Integer(chrono::Utc::now().timestamp() as i32)),
+        "llGetTimestamp" => Some(String(chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string())),
+        "llGetPos" => Some(Vector(ctx.position)),
+        "llSetPos" => {
+            let target = arg!(Vector);
+            let offset = target - ctx.position;
+            let distance = offset.length();
+            let position = if distance > MAX_MOVE {
+                ctx.position + offset * (MAX_MOVE / distance)
+            } else {
+                target
+            };
+            ctx.position = position;
+            ctx.actions.push(ScriptAction::SetPosition {
+                object_id: ctx.script.object_id,
+                position,
+            });
+            None
+        }
+        "llGetRot" => Some(Rotation(ctx.rotation)),
+        "llSetRot" => {
+            let rotation = arg!(Rotation).normalize();
+            ctx.rotation = rotation;
+            ctx.actions.push(ScriptAction::SetRotation {
+                object_id: ctx.script.object_id,
+                rotation,
+            });
+            None
+        }
+        "llGetKey" => Some(Key(ctx.script.object_id.as_uuid())),
+        "llGetOwner" => Some(Key(ctx.script.owner_id.as_uuid())),
+        "llGetObjectName" => Some(String(ctx.object_name.clone())),
+        "llGetScriptName" => Some(String(ctx.script.name.clone())),
+        "llGetRegionName" => Some(String(ctx.region_name.clone())),
+        "llDie" => {
+            ctx.actions.push(ScriptAction::Die {
+                object_id: ctx.script.object_id,
+            });
+            None
+        }
+
+        "llHTTPRequest" => {
+            let (url, params, body) = (arg!(String), arg!(List), arg!(String));
+            match http_request(url, &params, body) {
+                Ok(request) => {
+                    let request_id = Uuid::new_v4();
+                    ctx.actions.push(ScriptAction::HttpRequest {
+                        script: ctx.script.id,
+                        owner_id: ctx.script.owner_id,
+                        request_id,
+                        request,
+                    });
+                    Some(Key(request_id))
+                }
+                // As in LSL, bad options are reported rather than fatal
+                Err(message) => {
+                    ctx.chat(i32::MAX, ChatVolume::Say, message);
+                    Some(Key(Uuid::nil()))
+                }
+            }
+        }
+        "llHTTPResponse" => {
+            let (request_id, status, body) = (arg!(String), arg!(Integer), arg!(String));
+            if let Ok(request_id) = Uuid::parse_str(&request_id) {
+                ctx.actions.push(ScriptAction::HttpResponse {
+                    request_id,
+                    status,
+                    body,
+                });
+            }
+            None
+        }
+        "llRequestURL" => {
+            let request_id = Uuid::new_v4();
+            ctx.actions.push(ScriptAction::RequestUrl {
+                script: ctx.script.id,
+                request_id,
+            });
+            Some(Key(request_id))
+        }
+        "llReleaseURL" => {
+            let url = arg!(String);
+            ctx.actions.push(ScriptAction::ReleaseUrl {
+                script: ctx.script.id,
+                url,
+            });
+            None
+        }
+        "llEmail" => {
+            let (address, subject, message) = (arg!(String), arg!(String), arg!(String));
+            ctx.actions.push(ScriptAction::Email(OutboundEmail {
+                object_id: ctx.script.object_id,
+                owner_id: ctx.script.owner_id,
+                object_name: ctx.object_name.clone(),
+                region_name: ctx.region_name.clone(),
+                position: ctx.position,
+                address,
+                subject,
+                message,
+            }));
+            None
+        }
+        "llGetNextEmail" => {
+            let (address, subject) = (arg!(String), arg!(String));
+            ctx.actions.push(ScriptAction::NextEmail {
+                script: ctx.script.id,
+                object_id: ctx.script.object_id,
+                address,
+                subject,
+            });
+            None
+        }
+        "llOpenRemoteDataChannel" => {
+            ctx.actions.push(ScriptAction::OpenRemoteData { script: ctx.script.id });
+            None
+        }
+        "llRemoteDataReply" => {
+            let (_channel, message_id, sdata, idata) = (arg!(String), arg!(String), arg!(String), arg!(Integer));
+            if let Ok(message_id) = Uuid::parse_str(&message_id) {
+                ctx.actions.push(ScriptAction::RemoteDataReply {
+                    message_id,
+                    sdata,
+                    idata,
+                });
+            }
+            None
+        }
+        "llCloseRemoteDataChannel" => {
+            if let Ok(channel) = Uuid::parse_str(&arg!(String)) {
+                ctx.actions.push(ScriptAction::CloseRemoteData {
+                    script: ctx.script.id,
+                    channel,
+                });
+            }
+            None
+        }
+
+        "llPlaySound" | "llLoopSound" => {
+            let (sound, volume) = (arg!(String), arg!(Float));
+            if let Some(sound) = ctx.sound(&sound) {
+                ctx.actions.push(ScriptAction::PlaySound {
+                    object_id: ctx.script.object_id,
+                    sound,
+                    gain: volume.clamp(0.0, 1.0),
+                    looped: function.name == "llLoopSound",
+                });
+            }
+            None
+        }
+        "llTriggerSound" => {
+            let (sound, volume) = (arg!(String), arg!(Float));
+            if let Some(sound) = ctx.sound(&sound) {
+                ctx.actions.push(ScriptAction::TriggerSound {
+                    object_id: ctx.script.object_id,
+                    sound,
+                    gain: volume.clamp(0.0, 1.0),
+                });
+            }
+            None
+        }
+        "llStopSound" => {
+            ctx.actions.push(ScriptAction::StopSound {
+                object_id: ctx.script.object_id,
+            });
+            None
+        }
+        "llPreloadSound" => {
+            let sound = arg!(String);
+            if let Some(sound) = ctx.sound(&sound) {
+                ctx.actions.push(ScriptAction::PreloadSound {
+                    object_id: ctx.script.object_id,
+                    sound,
+                });
+            }
+            None
+        }
+        "llAdjustSoundVolume" => {
+            let gain = arg!(Float).clamp(0.0, 1.0);
+            ctx.actions.push(ScriptAction::AdjustSoundVolume {
+                object_id: ctx.script.object_id,
+                gain,
+            });
+            None
+        }
+
+        "llAbs" => Some(Integer(arg!(Integer).wrapping_abs())),
+        "llFabs" => Some(Float(arg!(Float).abs())),
+        "llFloor" => Some(Integer(arg!(Float).floor() as i32)),
+        "llCeil" => Some(Integer(arg!(Float).ceil() as i32)),
+        "llRound" => Some(Integer((arg!(Float) + 0.5).floor() as i32)),
+        "llSqrt" => {
+            let value = arg!(Float);
+            if value < 0.0 {
+                return Err("Math error".to_string());
+            }
+            Some(Float(value.sqrt()))
+        }
+        "llPow" => {
+            let (base, exponent) = (arg!(Float), arg!(Float));
+            Some(Float(base.powf(exponent)))
+        }
+        "llSin" => Some(Float(arg!(Float).sin())),
+        "llCos" => Some(Float(arg!(Float).cos())),
+        "llTan" => Some(Float(arg!(Float).tan())),
+        "llAtan2" => {
+            let (y, x) = (arg!(Float), arg!(Float));
+            Some(Float(y.atan2(x)))
+        }
+        "llFrand" => Some(Float(rand::random::<f32>() * arg!(Float))),
+        "llVecMag" => Some(Float(arg!(Vector).length())),
+        "llVecNorm" => {
+            let v = arg!(Vector);
+            Some(Vector(if v == Vector3::ZERO { v } else { v.normalize() }))
+        }
+        "llVecDist" => {
+            let (a, b) = (arg!(Vector), arg!(Vector));
+            Some(Float((a - b).length()))
+        }
+        "llEuler2Rot" => Some(Rotation(euler_to_rot(arg!(Vector)))),
+        "llRot2Euler" => Some(Vector(rot_to_euler(arg!(Rotation)))),
+        "llAxisAngle2Rot" => {
+            let (axis, angle) = (arg!(Vector), arg!(Float));
+            let axis = if axis == Vector3::ZERO { axis } else { axis.normalize() };
+            Some(Rotation(Quaternion::from_axis_angle(axis, angle)))
+        }
+
+        "llStringLength" => Some(Integer(arg!(String).chars().count() as i32)),
+        "llGetSubString" | "llDeleteSubString" => {
+            let (text, start, end) = (arg!(String), arg!(Integer), arg!(Integer));
+            let chars: Vec<char> = text.chars().collect();
+            let kept = range(chars.len(), start, end);
+            let result: std::string::String = if function.name == "llGetSubString" {
+                kept.iter().map(|i| chars[*i]).collect()
+            } else {
+                (0..chars.len())
+                    .filter(|i| !kept.contains(i))
+                    .map(|i| chars[i])
+                    .collect()
+            };
+            Some(String(result))
+        }
+        "llInsertString" => {
+            let (text, at, insert) = (arg!(String), arg!(Integer), arg!(String));
+            let chars: Vec<char> = text.chars().collect();
+            let at = (at.max(0) as usize).min(chars.len());
+            let result: std::string::String = chars[..at]
+                .iter()
+                .chain(insert.chars().collect::<Vec<_>>().iter())
+                .chain(chars[at..].iter())
+                .collect();
+            Some(String(result))
+        }
+        "llSubStringIndex" => {
+            let (text, pattern) = (arg!(String), arg!(String));
+            Some(Integer(
+                text.find(&pattern)
+                    .map(|byte| text[..byte].chars().count() as i32)
+                    .unwrap_or(-1),
+            ))
+        }
+        "llToUpper" => Some(String(arg!(String).to_uppercase())),
+        "llToLower" => Some(String(arg!(String).to_lowercase())),
+        "llStringTrim" => {
+            let (text, mode) = (arg!(String), arg!(Integer));
+            let text = match mode & 3 {
+                1 => text.trim_start(),
+                2 => text.trim_end(),
+                3 => text.trim(),
+                _ => &text,
+            };
+            Some(String(text.to_string()))
+        }
+
+        "llGetListLength" => Some(Integer(arg!(List).len() as i32)),
+        "llGetListEntryType" => {
+            let (list, index) = (arg!(List), arg!(Integer));
+            let ty = match list_item(&list, index) {
+                Some(Integer(_)) => 1,
+                Some(Float(_)) => 2,
+                Some(String(_)) => 3,
+                Some(Key(_)) => 4,
+                Some(Vector(_)) => 5,
+                Some(Rotation(_)) => 6,
+                _ => 0,
+            };
+            Some(Integer(ty))
+        }
+        "llList2String" | "llList2Integer" | "llList2Float" | "llList2Key" | "llList2Vector" | "llList2Rot" => {
+            let (list, index) = (arg!(List), arg!(Integer));
+            let ty = function.returns.unwrap_or(Type::String);
+            let value = match list_item(&list, index) {
+                Some(item) => ops::cast(item, ty).unwrap_or_else(|_| ops::zero(ty)),
+                None => ops::zero(ty),
+            };
+            Some(value)
+        }
+        "llList2List" | "llDeleteSubList" => {
+            let (list, start, end) = (arg!(List), arg!(Integer), arg!(Integer));
+            let kept = range(list.len(), start, end);
+            let result = if function.name == "llList2List" {
+                kept.iter().map(|i| list[*i].clone()).collect()
+            } else {
+                list.iter()
+                    .enumerate()
+                    .filter(|(i, _)| !kept.contains(i))
+                    .map(|(_, item)| item.clone())
+                    .collect()
+            };
+            Some(List(result))
+        }
+        "llListFindList" => {
+            let (list, pattern) = (arg!(List), arg!(List));
+            let found = if pattern.is_empty() {
+                Some(0)
+            } else {
+                list.windows(pattern.len())
+                    .position(|window| window == pattern.as_slice())
+            };
+            Some(Integer(found.map(|i| i as i32).unwrap_or(-1)))
+        }
+        "llDumpList2String" => {
+            let (list, separator) = (arg!(List), arg!(String));
+            Some(String(list.iter().map(to_string).collect::<Vec<_>>().join(&separator)))
+        }
+        "llParseString2List" => {
+            let (text, separators, spacers) = (arg!(String), arg!(List), arg!(List));
+            Some(List(parse_string_to_list(&text, &separators, &spacers)))
+        }
+        "llList2CSV" => Some(String(arg!(List).iter().map(to_string).collect::<Vec<_>>().join(", "))),
+        "llCSV2List" => Some(List(
+            arg!(String)
+                .split(',')
+                .map(|item| String(item.trim().to_string()))
+                .collect(),
+        )),
+        other => return Err(format!("{} is not implemented", other)),
+    };
+    Ok(result)
+}
+
+/// Split `text` at `separators`, which are dropped, and `spacers`, which
+/// are kept as items; empty items are dropped
+fn parse_string_to_list(text: &str, separators: &[ScriptValue], spacers: &[ScriptValue]) -> Vec<ScriptValue> {
+    let separators: Vec<String> = separators.iter().map(to_string).filter(|s| !s.is_empty()).collect();
+    let spacers: Vec<String> = spacers.iter().map(to_string).filter(|s| !s.is_empty()).collect();
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut rest = text;
+    'scan: while !rest.is_empty() {
+        for (delimiter, keep) in separators
+            .iter()
+            .map(|s| (s, false))
+            .chain(spacers.iter().map(|s| (s, true)))
+        {
+            if let Some(after) = rest.strip_prefix(delimiter.as_str()) {
+                if !current.is_empty() {
+                    items.push(ScriptValue::String(std::mem::take(&mut current)));
+                }
+                if keep {
+                    items.push(ScriptValue::String(delimiter.clone()));
+                }
+                rest = after;
+                continue 'scan;
+            }
+        }
+        let c = rest.chars().next().unwrap_or_default();
+        current.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    if !current.is_empty() {
+        items.push(ScriptValue::String(current));
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> CallContext {
+        CallContext::new(ScriptInfo {
+            id: Uuid::new_v4(),
+            name: "New Script".to_string(),
+            object_id: ObjectId::new(),
+            owner_id: UserId::new(),
+        })
+    }
+
+    fn call_named(name: &str, args: Vec<ScriptValue>, ctx: &mut CallContext) -> Option<ScriptValue> {
+        call(lookup(name).unwrap().0, args, ctx).unwrap()
+    }
+
+    fn text(value: &str) -> ScriptValue {
+        ScriptValue::String(value.to_string())
+    }
+
+    #[test]
+    fn test_string_and_list_functions() {
+        let mut ctx = context();
+        let int = ScriptValue::Integer;
+        assert_eq!(
+            call_named("llGetSubString", vec![text("Mutsea"), int(1), int(-2)], &mut ctx),
+            Some(text("utse"))
+        );
+        // A reversed range keeps the ends
+        assert_eq!(
+            call_named("llGetSubString", vec![text("Mutsea"), int(-1), int(0)], &mut ctx),
+            Some(text("Ma"))
+        );
+        assert_eq!(
+            call_named("llSubStringIndex", vec![text("héllo"), text("llo")], &mut ctx),
+            Some(int(2))
+        );
+        assert_eq!(
+            call_named(
+                "llParseString2List",
+                vec![
+                    text("a,b;;c"),
+                    ScriptValue::List(vec![text(",")]),
+                    ScriptValue::List(vec![text(";")])
+                ],
+                &mut ctx
+            ),
+            Some(ScriptValue::List(vec![
+                text("a"),
+                text("b"),
+                text(";"),
+                text(";"),
+                text("c")
+            ]))
+        );
+        let list = ScriptValue::List(vec![int(1), text("2.5"), int(3)]);
+        assert_eq!(
+            call_named("llList2Float", vec![list.clone(), int(1)], &mut ctx),
+            Some(ScriptValue::Float(2.5))
+        );
+        assert_eq!(
+            call_named("llList2Integer", vec![list.clone(), int(9)], &mut ctx),
+            Some(int(0))
+        );
+        assert_eq!(
+            call_named("llDumpList2String", vec![list, text("-")], &mut ctx),
+            Some(text("1-2.5-3"))
+        );
+        assert!(call(lookup("llSqrt").unwrap().0, vec![ScriptValue::Float(-1.0)], &mut ctx).is_err());
+    }
+
+    #[test]
+    fn test_world_functions_queue_actions() {
+        let mut ctx = context();
+        ctx.object_name = "Door".to_string();
+        call_named("llSay", vec![ScriptValue::Integer(0), text("Opening")], &mut ctx);
+        call_named(
+            "llSetPos",
+            vec![ScriptValue::Vector(Vector3::new(30.0, 0.0, 0.0))],
+            &mut ctx,
+        );
+        assert!(
+            matches!(&ctx.actions[0], ScriptAction::Chat { name, volume: ChatVolume::Say, message, .. }
+            if name == "Door" && message == "Opening")
+        );
+        // llSetPos moves at most 10 m at a time
+        assert_eq!(
+            ctx.actions[1],
+            ScriptAction::SetPosition {
+                object_id: ctx.script.object_id,
+                position: Vector3::new(10.0, 0.0, 0.0)
+            }
+        );
+        assert_eq!(
+            call_named("llGetPos", Vec::new(), &mut ctx),
+            Some(ScriptValue::Vector(Vector3::new(10.0, 0.0, 0.0)))
+        );
+    }
+
+    #[test]
+    fn test_http_requests_read_their_options() {
+        let mut ctx = context();
+        let int = ScriptValue::Integer;
+        let params = ScriptValue::List(vec![
+            int(0),
+            text("POST"),
+            int(1),
+            text("application/json"),
+            int(2),
+            int(512),
+        ]);
+        let Some(ScriptValue::Key(request_id)) = call_named(
+            "llHTTPRequest",
+            vec![text("https://example.com/count"), params, text("{}")],
+            &mut ctx,
+        ) else {
+            panic!("no request ID");
+        };
+        let ScriptAction::HttpRequest { request, .. } = &ctx.actions[0] else {
+            panic!("no request in {:?}", ctx.actions);
+        };
+        assert_eq!((request.method.as_str(), request.max_body), ("POST", 512));
+        assert!(!request_id.is_nil());
+
+        // Bad options are reported on the debug channel and return NULL_KEY
+        let params = ScriptValue::List(vec![int(0), text("TRACE")]);
+        assert_eq!(
+            call_named(
+                "llHTTPRequest",
+                vec![text("https://example.com/"), params, text("")],
+                &mut ctx
+            ),
+            Some(ScriptValue::Key(Uuid::nil()))
+        );
+        assert!(matches!(&ctx.actions[1], ScriptAction::Chat { channel: i32::MAX, .. }));
+    }
+
+    #[test]
+    fn test_email_and_remote_data_queue_actions() {
+        let mut ctx = context();
+        ctx.object_name = "Vendor".to_string();
+        call_named(
+            "llEmail",
+            vec![text("owner@example.com"), text("Sale"), text("Sold one")],
+            &mut ctx,
+        );
+        let ScriptAction::Email(email) = &ctx.actions[0] else {
+            panic!("no email in {:?}", ctx.actions);
+        };
+        assert_eq!(
+            (email.object_name.as_str(), email.address.as_str()),
+            ("Vendor", "owner@example.com")
+        );
+
+        let message_id = Uuid::new_v4();
+        call_named(
+            "llRemoteDataReply",
+            vec![
+                ScriptValue::Key(Uuid::new_v4()),
+                ScriptValue::Key(message_id),
+                text("ok"),
+                ScriptValue::Integer(1),
+            ],
+            &mut ctx,
+        );
+        assert_eq!(
+            ctx.actions[1],
+            ScriptAction::RemoteDataReply {
+                message_id,
+                sdata: "ok".to_string(),
+                idata: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_sound_functions_queue_actions() {
+        let mut ctx = context();
+        let splash = Uuid::new_v4();
+        call_named("llLoopSound", vec![ScriptValue::Key(splash), ScriptValue::Float(2.0)], &mut ctx);
+        assert_eq!(
+            ctx.actions[0],
+            ScriptAction::PlaySound {
+                object_id: ctx.script.object_id,
+                sound: splash,
+                gain: 1.0,
+                looped: true
+            }
+        );
+        call_named("llTriggerSound", vec![text(&splash.to_string()), ScriptValue::Float(0.5)], &mut ctx);
+        assert!(matches!(ctx.actions[1], ScriptAction::TriggerSound { sound, gain, .. } if sound == splash && gain == 0.5));
+
+        // Sounds named rather than keyed aren't in the prim
+        call_named("llPlaySound", vec![text("splash"), ScriptValue::Float(1.0)], &mut ctx);
+        assert!(
+            matches!(&ctx.actions[2], ScriptAction::Chat { channel: i32::MAX, message, .. }
+            if message == "Couldn't find sound splash")
+        );
+        assert_eq!(ctx.actions.len(), 3);
+    }
+
+    #[test]
+    fn test_euler_round_trip() {
+        let euler = Vector3::new(0.3, -0.7, 1.2);
+        let back = rot_to_euler(euler_to_rot(euler));
+        assert!((back - euler).length() < 1e-4, "{:?}", back);
+    }
+}
@@ -0,0 +1,61 @@
+//! LSL scripts compiled to WebAssembly
+//!
+//! Scripts are parsed, type checked and lowered to a WebAssembly module
+//! instead of being interpreted. With the `scripting` feature the modules
+//! run under wasmtime: each script gets its own store, metered with fuel so
+//! a long-running handler is preempted when its time slice is spent and
+//! resumed next frame, and its globals and current state can be snapshot
+//! and restored when its object crosses to another region.
+
+#[cfg(feature = "scripting")]
+mod actions;
+mod ast;
+mod codegen;
+#[cfg(feature = "scripting")]
+mod executor;
+mod lexer;
+mod library;
+mod ops;
+mod parser;
+mod wasm;
+
+use thiserror::Error;
+
+use ast::Type;
+
+#[cfg(feature = "scripting")]
+pub use actions::ActionDispatcher;
+#[cfg(feature = "scripting")]
+pub use executor::WasmExecutor;
+
+/// A syntax or type error in a script
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("line {line}: {message}")]
+pub struct CompileError {
+    pub line: u32,
+    pub message: String,
+}
+
+impl CompileError {
+    fn new(line: u32, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+/// A script compiled to a WebAssembly module
+#[derive(Debug, Clone)]
+pub struct CompiledScript {
+    pub wasm: Vec<u8>,
+    /// Global variables in declaration order
+    pub globals: Vec<(String, Type)>,
+    /// States, `default` first
+    pub states: Vec<String>,
+}
+
+/// Compile LSL `source`
+pub fn compile(source: &str) -> Result<CompiledScript, CompileError> {
+    codegen::generate(&parser::parse(source)?)
+}
@@ -0,0 +1,340 @@
+//! LSL operators and casts on host-held values
+//!
+//! Integers and floats are operated on in WebAssembly; strings, keys,
+//! vectors, rotations and lists are held by the host, and compiled scripts
+//! call back to it for these.
+
+use mutsea_core::{Quaternion, Vector3};
+use uuid::Uuid;
+
+use super::ast::{BinOp, Type, UnOp};
+use crate::scripts::ScriptValue;
+
+/// The LSL type of `value`. Keys that are not UUIDs are held as strings.
+pub fn type_of(value: &ScriptValue) -> Type {
+    match value {
+        ScriptValue::Integer(_) => Type::Integer,
+        ScriptValue::Float(_) => Type::Float,
+        ScriptValue::String(_) => Type::String,
+        ScriptValue::Key(_) => Type::Key,
+        ScriptValue::Vector(_) => Type::Vector,
+        ScriptValue::Rotation(_) => Type::Rotation,
+        ScriptValue::List(_) => Type::List,
+    }
+}
+
+/// Default value of a variable of type `ty`
+pub fn zero(ty: Type) -> ScriptValue {
+    match ty {
+        Type::Integer => ScriptValue::Integer(0),
+        Type::Float => ScriptValue::Float(0.0),
+        Type::String | Type::Key => ScriptValue::String(String::new()),
+        Type::Vector => ScriptValue::Vector(Vector3::ZERO),
+        Type::Rotation => ScriptValue::Rotation(Quaternion::IDENTITY),
+        Type::List => ScriptValue::List(Vec::new()),
+    }
+}
+
+/// Rough bytes `value` takes, counted against the script's memory limit
+pub fn size_of(value: &ScriptValue) -> usize {
+    match value {
+        ScriptValue::Integer(_) | ScriptValue::Float(_) => 4,
+        ScriptValue::String(text) => 4 + text.len(),
+        ScriptValue::Key(_) => 16,
+        ScriptValue::Vector(_) => 12,
+        ScriptValue::Rotation(_) => 16,
+        ScriptValue::List(items) => 4 + items.iter().map(|item| 4 + size_of(item)).sum::<usize>(),
+    }
+}
+
+/// LSL rotations compose left to right: `a * b` is `a` followed by `b`
+fn compose(a: Quaternion, b: Quaternion) -> Quaternion {
+    b * a
+}
+
+fn number(value: &ScriptValue) -> Option<f32> {
+    match value {
+        ScriptValue::Integer(n) => Some(*n as f32),
+        ScriptValue::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+/// Apply `op` to `a` and `b` where either is not a number
+pub fn binary(op: BinOp, a: &ScriptValue, b: &ScriptValue) -> Result<ScriptValue, String> {
+    use ScriptValue::*;
+    let truth = |value: bool| Integer(i32::from(value));
+    Ok(match (op, a, b) {
+        (BinOp::Add, List(items), other) => {
+            let mut items = items.clone();
+            match other {
+                List(more) => items.extend(more.iter().cloned()),
+                other => items.push(other.clone()),
+            }
+            List(items)
+        }
+        (BinOp::Add, other, List(items)) => {
+            let mut joined = vec![other.clone()];
+            joined.extend(items.iter().cloned());
+            List(joined)
+        }
+        // Lists compare by length only
+        (BinOp::Eq, List(a), List(b)) => truth(a.len() == b.len()),
+        (BinOp::Ne, List(a), List(b)) => Integer(a.len() as i32 - b.len() as i32),
+
+        (BinOp::Add, String(_) | Key(_), String(_) | Key(_)) => String(to_string(a) + &to_string(b)),
+        (BinOp::Eq, String(_) | Key(_), String(_) | Key(_)) => truth(to_string(a) == to_string(b)),
+        (BinOp::Ne, String(_) | Key(_), String(_) | Key(_)) => truth(to_string(a) != to_string(b)),
+
+        (BinOp::Add, Vector(a), Vector(b)) => Vector(*a + *b),
+        (BinOp::Sub, Vector(a), Vector(b)) => Vector(*a - *b),
+        (BinOp::Mul, Vector(a), Vector(b)) => Float(a.dot(b)),
+        (BinOp::Mod, Vector(a), Vector(b)) => Vector(a.cross(b)),
+        (BinOp::Mul, Vector(v), n @ (Integer(_) | Float(_))) | (BinOp::Mul, n @ (Integer(_) | Float(_)), Vector(v)) => {
+            Vector(*v * number(n).unwrap_or(0.0))
+        }
+        (BinOp::Div, Vector(v), n @ (Integer(_) | Float(_))) => {
+            let n = number(n).unwrap_or(0.0);
+            if n == 0.0 {
+                return Err("Math error".to_string());
+            }
+            Vector(*v * (1.0 / n))
+        }
+        (BinOp::Mul, Vector(v), Rotation(r)) => Vector(r.rotate(*v)),
+        (BinOp::Div, Vector(v), Rotation(r)) => Vector(r.conjugate().rotate(*v)),
+        (BinOp::Eq, Vector(a), Vector(b)) => truth(a == b),
+        (BinOp::Ne, Vector(a), Vector(b)) => truth(a != b),
+
+        (BinOp::Add, Rotation(a), Rotation(b)) => Rotation(Quaternion::new(a.x + b.x, a.y + b.y, a.z + b.z, a.w + b.w)),
+        (BinOp::Sub, Rotation(a), Rotation(b)) => Rotation(Quaternion::new(a.x - b.x, a.y - b.y, a.z - b.z, a.w - b.w)),
+        (BinOp::Mul, Rotation(a), Rotation(b)) => Rotation(compose(*a, *b)),
+        (BinOp::Div, Rotation(a), Rotation(b)) => Rotation(compose(*a, b.conjugate())),
+        (BinOp::Eq, Rotation(a), Rotation(b)) => truth(a == b),
+        (BinOp::Ne, Rotation(a), Rotation(b)) => truth(a != b),
+
+        _ => {
+            return Err(format!(
+                "no operator {} {} {}",
+                type_of(a).name(),
+                op.symbol(),
+                type_of(b).name()
+            ))
+        }
+    })
+}
+
+/// Apply `op` to a vector or rotation
+pub fn unary(op: UnOp, value: &ScriptValue) -> Result<ScriptValue, String> {
+    match (op, value) {
+        (UnOp::Neg, ScriptValue::Vector(v)) => Ok(ScriptValue::Vector(*v * -1.0)),
+        (UnOp::Neg, ScriptValue::Rotation(r)) => Ok(ScriptValue::Rotation(Quaternion::new(-r.x, -r.y, -r.z, -r.w))),
+        _ => Err(format!("no operator {:?} on {}", op, type_of(value).name())),
+    }
+}
+
+/// Whether `value` counts as true in a condition
+pub fn truthy(value: &ScriptValue) -> bool {
+    match value {
+        ScriptValue::Integer(n) => *n != 0,
+        ScriptValue::Float(f) => *f != 0.0,
+        ScriptValue::String(text) => !text.is_empty(),
+        ScriptValue::Key(key) => !key.is_nil(),
+        ScriptValue::Vector(v) => *v != Vector3::ZERO,
+        ScriptValue::Rotation(r) => *r != Quaternion::IDENTITY,
+        ScriptValue::List(items) => !items.is_empty(),
+    }
+}
+
+/// Component `index` of a vector or rotation
+pub fn member(value: &ScriptValue, index: i32) -> Result<f32, String> {
+    let component = match value {
+        ScriptValue::Vector(v) => [v.x, v.y, v.z].get(index as usize).copied(),
+        ScriptValue::Rotation(r) => [r.x, r.y, r.z, r.w].get(index as usize).copied(),
+        _ => None,
+    };
+    component.ok_or_else(|| format!("no member {} of {}", index, type_of(value).name()))
+}
+
+/// `value` with component `index` set to `component`
+pub fn with_member(value: &ScriptValue, index: i32, component: f32) -> Result<ScriptValue, String> {
+    match (value, index) {
+        (ScriptValue::Vector(v), 0) => Ok(ScriptValue::Vector(Vector3::new(component, v.y, v.z))),
+        (ScriptValue::Vector(v), 1) => Ok(ScriptValue::Vector(Vector3::new(v.x, component, v.z))),
+        (ScriptValue::Vector(v), 2) => Ok(ScriptValue::Vector(Vector3::new(v.x, v.y, component))),
+        (ScriptValue::Rotation(r), 0) => Ok(ScriptValue::Rotation(Quaternion::new(component, r.y, r.z, r.w))),
+        (ScriptValue::Rotation(r), 1) => Ok(ScriptValue::Rotation(Quaternion::new(r.x, component, r.z, r.w))),
+        (ScriptValue::Rotation(r), 2) => Ok(ScriptValue::Rotation(Quaternion::new(r.x, r.y, component, r.w))),
+        (ScriptValue::Rotation(r), 3) => Ok(ScriptValue::Rotation(Quaternion::new(r.x, r.y, r.z, component))),
+        _ => Err(format!("no member {} of {}", index, type_of(value).name())),
+    }
+}
+
+/// `value` as a string, as `(string)value` gives it
+pub fn to_string(value: &ScriptValue) -> String {
+    match value {
+        ScriptValue::Integer(n) => n.to_string(),
+        ScriptValue::Float(f) => format!("{:.6}", f),
+        ScriptValue::String(text) => text.clone(),
+        ScriptValue::Key(key) => key.to_string(),
+        ScriptValue::Vector(v) => format!("<{:.5}, {:.5}, {:.5}>", v.x, v.y, v.z),
+        ScriptValue::Rotation(r) => format!("<{:.5}, {:.5}, {:.5}, {:.5}>", r.x, r.y, r.z, r.w),
+        ScriptValue::List(items) => items.iter().map(to_string).collect(),
+    }
+}
+
+/// The integer `text` starts with, in decimal or hexadecimal; 0 if none
+pub fn parse_integer(text: &str) -> i32 {
+    let text = text.trim_start();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        let end = hex.find(|c: char| !c.is_ascii_hexdigit()).unwrap_or(hex.len());
+        u32::from_str_radix(&hex[..end], 16).unwrap_or(0) as i32
+    } else {
+        let end = digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len());
+        digits[..end].parse::<i64>().map(|n| n as i32).unwrap_or(0)
+    };
+    if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    }
+}
+
+/// The float `text` starts with; 0 if none
+pub fn parse_float(text: &str) -> f32 {
+    let text = text.trim_start();
+    // The longest prefix that parses
+    (1..=text.len())
+        .rev()
+        .filter(|end| text.is_char_boundary(*end))
+        .find_map(|end| text[..end].parse::<f32>().ok())
+        .filter(|f| f.is_finite())
+        .unwrap_or(0.0)
+}
+
+fn parse_components(text: &str) -> Option<Vec<f32>> {
+    let inner = text.trim().strip_prefix('<')?.strip_suffix('>')?;
+    Some(inner.split(',').map(parse_float).collect())
+}
+
+/// `value` cast to `ty`
+pub fn cast(value: &ScriptValue, ty: Type) -> Result<ScriptValue, String> {
+    use ScriptValue::*;
+    Ok(match (value, ty) {
+        (value, Type::List) => match value {
+            List(_) => value.clone(),
+            other => List(vec![other.clone()]),
+        },
+        (value, Type::String) => String(to_string(value)),
+        (String(text), Type::Key) => match Uuid::parse_str(text.trim()) {
+            Ok(key) => Key(key),
+            // Keys that are not UUIDs keep their text
+            Err(_) => String(text.clone()),
+        },
+        (Key(_), Type::Key) => value.clone(),
+        (Integer(n), Type::Integer) => Integer(*n),
+        (Float(f), Type::Integer) => Integer(*f as i32),
+        (String(text), Type::Integer) => Integer(parse_integer(text)),
+        (Integer(n), Type::Float) => Float(*n as f32),
+        (Float(f), Type::Float) => Float(*f),
+        (String(text), Type::Float) => Float(parse_float(text)),
+        (Vector(_), Type::Vector) | (Rotation(_), Type::Rotation) => value.clone(),
+        (String(text), Type::Vector) => match parse_components(text).as_deref() {
+            Some([x, y, z]) => Vector(Vector3::new(*x, *y, *z)),
+            _ => Vector(Vector3::ZERO),
+        },
+        (String(text), Type::Rotation) => match parse_components(text).as_deref() {
+            Some([x, y, z, w]) => Rotation(Quaternion::new(*x, *y, *z, *w)),
+            _ => Rotation(Quaternion::IDENTITY),
+        },
+        _ => return Err(format!("cannot cast {} to {}", type_of(value).name(), ty.name())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_casts() {
+        let cast_to = |value: ScriptValue, ty| cast(&value, ty).unwrap();
+        assert_eq!(
+            cast_to(ScriptValue::String(" -42abc".to_string()), Type::Integer),
+            ScriptValue::Integer(-42)
+        );
+        assert_eq!(
+            cast_to(ScriptValue::String("0x1f".to_string()), Type::Integer),
+            ScriptValue::Integer(31)
+        );
+        assert_eq!(
+            cast_to(ScriptValue::String("2.5m".to_string()), Type::Float),
+            ScriptValue::Float(2.5)
+        );
+        assert_eq!(
+            cast_to(ScriptValue::Vector(Vector3::new(1.0, 2.0, 3.0)), Type::String),
+            ScriptValue::String("<1.00000, 2.00000, 3.00000>".to_string())
+        );
+        assert_eq!(
+            cast_to(ScriptValue::String("<1, 2, 3>".to_string()), Type::Vector),
+            ScriptValue::Vector(Vector3::new(1.0, 2.0, 3.0))
+        );
+        assert_eq!(
+            cast_to(
+                ScriptValue::List(vec![ScriptValue::Integer(1), ScriptValue::Float(0.5)]),
+                Type::String
+            ),
+            ScriptValue::String("10.500000".to_string())
+        );
+        assert!(cast(&ScriptValue::Vector(Vector3::ZERO), Type::Integer).is_err());
+    }
+
+    #[test]
+    fn test_binary_operators() {
+        let v = |x, y, z| ScriptValue::Vector(Vector3::new(x, y, z));
+        assert_eq!(
+            binary(BinOp::Mul, &v(1.0, 2.0, 3.0), &v(4.0, 5.0, 6.0)).unwrap(),
+            ScriptValue::Float(32.0)
+        );
+        assert_eq!(
+            binary(BinOp::Mod, &v(1.0, 0.0, 0.0), &v(0.0, 1.0, 0.0)).unwrap(),
+            v(0.0, 0.0, 1.0)
+        );
+        assert_eq!(
+            binary(BinOp::Mul, &ScriptValue::Integer(2), &v(1.0, 2.0, 3.0)).unwrap(),
+            v(2.0, 4.0, 6.0)
+        );
+        assert_eq!(
+            binary(
+                BinOp::Add,
+                &ScriptValue::String("a".to_string()),
+                &ScriptValue::String("b".to_string())
+            )
+            .unwrap(),
+            ScriptValue::String("ab".to_string())
+        );
+        let list = ScriptValue::List(vec![ScriptValue::Integer(1)]);
+        assert_eq!(
+            binary(BinOp::Add, &list, &ScriptValue::Integer(2)).unwrap(),
+            ScriptValue::List(vec![ScriptValue::Integer(1), ScriptValue::Integer(2)])
+        );
+        assert_eq!(
+            binary(BinOp::Ne, &list, &ScriptValue::List(Vec::new())).unwrap(),
+            ScriptValue::Integer(1)
+        );
+        assert_eq!(
+            binary(BinOp::Div, &v(1.0, 1.0, 1.0), &ScriptValue::Integer(0)),
+            Err("Math error".to_string())
+        );
+
+        // A quarter turn about Z twice is a half turn
+        let quarter = ScriptValue::Rotation(Quaternion::from_axis_angle(Vector3::UP, std::f32::consts::FRAC_PI_2));
+        let half = binary(BinOp::Mul, &quarter, &quarter).unwrap();
+        let ScriptValue::Vector(turned) = binary(BinOp::Mul, &v(1.0, 0.0, 0.0), &half).unwrap() else {
+            panic!("expected a vector");
+        };
+        assert!((turned - Vector3::new(-1.0, 0.0, 0.0)).length() < 1e-5);
+    }
+}
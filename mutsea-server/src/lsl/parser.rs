@@ -0,0 +1,627 @@
+//! Recursive descent parser from tokens to a [`Script`]
+
+use super::ast::*;
+use super::lexer::{tokenize, Token};
+use super::CompileError;
+
+/// Parse LSL `source`
+pub fn parse(source: &str) -> Result<Script, CompileError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+    };
+    parser.script()
+}
+
+/// Binary operators from loosest to tightest. `&&` and `||` share a level
+/// and, as in LSL, bind looser than the bitwise operators.
+const LEVELS: &[&[(&str, BinOp)]] = &[
+    &[("&&", BinOp::And), ("||", BinOp::Or)],
+    &[("|", BinOp::BitOr)],
+    &[("^", BinOp::BitXor)],
+    &[("&", BinOp::BitAnd)],
+    &[("==", BinOp::Eq), ("!=", BinOp::Ne)],
+    &[("<", BinOp::Lt), ("<=", BinOp::Le), (">", BinOp::Gt), (">=", BinOp::Ge)],
+    &[("<<", BinOp::Shl), (">>", BinOp::Shr)],
+    &[("+", BinOp::Add), ("-", BinOp::Sub)],
+    &[("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Mod)],
+];
+
+/// Level of the shifts: components of vector and rotation literals are
+/// parsed from here so the closing `>` is not read as a comparison
+const SHIFT_LEVEL: usize = 6;
+
+const ASSIGNMENTS: &[(&str, Option<BinOp>)] = &[
+    ("=", None),
+    ("+=", Some(BinOp::Add)),
+    ("-=", Some(BinOp::Sub)),
+    ("*=", Some(BinOp::Mul)),
+    ("/=", Some(BinOp::Div)),
+    ("%=", Some(BinOp::Mod)),
+];
+
+struct Parser {
+    tokens: Vec<(Token, u32)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn peek_at(&self, ahead: usize) -> &Token {
+        &self.tokens[(self.pos + ahead).min(self.tokens.len() - 1)].0
+    }
+
+    fn line(&self) -> u32 {
+        self.tokens[self.pos].1
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn error(&self, message: impl Into<String>) -> CompileError {
+        CompileError::new(self.line(), message)
+    }
+
+    fn is_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Token::Punct(p) if *p == punct)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if self.is_punct(punct) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), CompileError> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}', found {}", punct, describe(self.peek()))))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, CompileError> {
+        match self.peek() {
+            Token::Ident(name) if !is_keyword(name) => {
+                let name = name.clone();
+                self.next();
+                Ok(name)
+            }
+            other => Err(self.error(format!("expected a name, found {}", describe(other)))),
+        }
+    }
+
+    fn is_ident(&self, word: &str) -> bool {
+        matches!(self.peek(), Token::Ident(name) if name == word)
+    }
+
+    /// The type named by the next token, if it is one
+    fn peek_type(&self) -> Option<Type> {
+        match self.peek() {
+            Token::Ident(name) => Type::from_name(name),
+            _ => None,
+        }
+    }
+
+    fn script(&mut self) -> Result<Script, CompileError> {
+        let mut script = Script::default();
+
+        // Globals and functions come before the states
+        while !self.is_ident("default") {
+            let line = self.line();
+            if *self.peek() == Token::Eof {
+                return Err(self.error("expected a default state"));
+            }
+            let returns = self.peek_type();
+            if returns.is_some() {
+                self.next();
+            }
+            let name = self.ident()?;
+            if self.eat("(") {
+                let params = self.params()?;
+                let body = self.block()?;
+                script.functions.push(Function {
+                    name,
+                    returns,
+                    params,
+                    body,
+                    line,
+                });
+            } else {
+                let Some(ty) = returns else {
+                    return Err(CompileError::new(line, format!("global '{}' has no type", name)));
+                };
+                let init = if self.eat("=") { Some(self.expr()?) } else { None };
+                self.expect(";")?;
+                script.globals.push(Global { ty, name, init, line });
+            }
+        }
+
+        while *self.peek() != Token::Eof {
+            let line = self.line();
+            let name = if self.is_ident("default") {
+                self.next();
+                "default".to_string()
+            } else if self.is_ident("state") {
+                self.next();
+                let name = self.ident()?;
+                if name == "default" {
+                    return Err(CompileError::new(line, "'default' is declared twice"));
+                }
+                name
+            } else {
+                return Err(self.error(format!("expected a state, found {}", describe(self.peek()))));
+            };
+            self.expect("{")?;
+            let mut handlers = Vec::new();
+            while !self.eat("}") {
+                let line = self.line();
+                let event = self.ident()?;
+                self.expect("(")?;
+                let params = self.params()?;
+                let body = self.block()?;
+                handlers.push(Handler {
+                    event,
+                    params,
+                    body,
+                    line,
+                });
+            }
+            script.states.push(State { name, handlers, line });
+        }
+
+        Ok(script)
+    }
+
+    /// Parameters after the opening parenthesis, and the closing one
+    fn params(&mut self) -> Result<Vec<Param>, CompileError> {
+        let mut params = Vec::new();
+        if self.eat(")") {
+            return Ok(params);
+        }
+        loop {
+            let Some(ty) = self.peek_type() else {
+                return Err(self.error(format!("expected a parameter type, found {}", describe(self.peek()))));
+            };
+            self.next();
+            let name = self.ident()?;
+            params.push(Param { ty, name });
+            if self.eat(")") {
+                return Ok(params);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        self.expect("{")?;
+        let mut stmts = Vec::new();
+        while !self.eat("}") {
+            stmts.push(self.stmt()?);
+        }
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, CompileError> {
+        let line = self.line();
+        if self.is_punct("{") {
+            return Ok(Stmt::Block(self.block()?));
+        }
+        if self.eat(";") {
+            return Ok(Stmt::Empty);
+        }
+        if self.is_punct("@") || self.is_ident("jump") {
+            return Err(self.error("jump and labels are not supported"));
+        }
+        if let Some(ty) = self.peek_type() {
+            // A declaration, unless it is a cast such as `(string)x`
+            self.next();
+            let name = self.ident()?;
+            let init = if self.eat("=") { Some(self.expr()?) } else { None };
+            self.expect(";")?;
+            return Ok(Stmt::Declare { ty, name, init, line });
+        }
+
+        let Token::Ident(word) = self.peek().clone() else {
+            let expr = self.expr()?;
+            self.expect(";")?;
+            return Ok(Stmt::Expr(expr));
+        };
+        match word.as_str() {
+            "if" => {
+                self.next();
+                self.expect("(")?;
+                let cond = self.expr()?;
+                self.expect(")")?;
+                let then = Box::new(self.stmt()?);
+                let otherwise = if self.is_ident("else") {
+                    self.next();
+                    Some(Box::new(self.stmt()?))
+                } else {
+                    None
+                };
+                Ok(Stmt::If { cond, then, otherwise })
+            }
+            "while" => {
+                self.next();
+                self.expect("(")?;
+                let cond = self.expr()?;
+                self.expect(")")?;
+                let body = Box::new(self.stmt()?);
+                Ok(Stmt::While { cond, body })
+            }
+            "do" => {
+                self.next();
+                let body = Box::new(self.stmt()?);
+                if !self.is_ident("while") {
+                    return Err(self.error("expected 'while' after do"));
+                }
+                self.next();
+                self.expect("(")?;
+                let cond = self.expr()?;
+                self.expect(")")?;
+                self.expect(";")?;
+                Ok(Stmt::DoWhile { body, cond })
+            }
+            "for" => {
+                self.next();
+                self.expect("(")?;
+                let init = self.expr_list(";")?;
+                let cond = if self.eat(";") {
+                    None
+                } else {
+                    let cond = self.expr()?;
+                    self.expect(";")?;
+                    Some(cond)
+                };
+                let step = self.expr_list(")")?;
+                let body = Box::new(self.stmt()?);
+                Ok(Stmt::For { init, cond, step, body })
+            }
+            "return" => {
+                self.next();
+                let value = if self.is_punct(";") { None } else { Some(self.expr()?) };
+                self.expect(";")?;
+                Ok(Stmt::Return(value, line))
+            }
+            "state" => {
+                self.next();
+                let name = if self.is_ident("default") {
+                    self.next();
+                    "default".to_string()
+                } else {
+                    self.ident()?
+                };
+                self.expect(";")?;
+                Ok(Stmt::ChangeState(name, line))
+            }
+            _ => {
+                let expr = self.expr()?;
+                self.expect(";")?;
+                Ok(Stmt::Expr(expr))
+            }
+        }
+    }
+
+    /// Comma separated expressions up to and including `end`
+    fn expr_list(&mut self, end: &str) -> Result<Vec<Expr>, CompileError> {
+        let mut exprs = Vec::new();
+        if self.eat(end) {
+            return Ok(exprs);
+        }
+        loop {
+            exprs.push(self.expr()?);
+            if self.eat(end) {
+                return Ok(exprs);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, CompileError> {
+        let line = self.line();
+        // An assignment starts with a name, maybe a member, then `=` or `op=`
+        if let Token::Ident(name) = self.peek().clone() {
+            if !is_keyword(&name) {
+                let (member, op_at) = match (self.peek_at(1), self.peek_at(2)) {
+                    (Token::Punct("."), Token::Ident(member)) => (member_index(member), 3),
+                    _ => (None, 1),
+                };
+                if op_at == 1 || member.is_some() {
+                    if let &Token::Punct(op) = self.peek_at(op_at) {
+                        if let Some((_, op)) = ASSIGNMENTS.iter().find(|(symbol, _)| *symbol == op) {
+                            let target = Target { name, member };
+                            for _ in 0..=op_at {
+                                self.next();
+                            }
+                            let value = Box::new(self.expr()?);
+                            return Ok(Expr {
+                                kind: ExprKind::Assign { target, op: *op, value },
+                                line,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, CompileError> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let line = self.line();
+            let op = match *self.peek() {
+                Token::Punct(p) => LEVELS[level].iter().find(|(symbol, _)| *symbol == p).map(|(_, op)| *op),
+                _ => None,
+            };
+            let Some(op) = op else {
+                return Ok(left);
+            };
+            self.next();
+            let right = self.binary(level + 1)?;
+            left = Expr {
+                kind: ExprKind::Binary(op, Box::new(left), Box::new(right)),
+                line,
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        let line = self.line();
+        let op = match self.peek() {
+            Token::Punct("-") => Some(UnOp::Neg),
+            Token::Punct("!") => Some(UnOp::Not),
+            Token::Punct("~") => Some(UnOp::BitNot),
+            _ => None,
+        };
+        if let Some(op) = op {
+            self.next();
+            let operand = self.unary()?;
+            // Fold negative literals so `-2147483648` stays an integer
+            let kind = match (op, operand.kind) {
+                (UnOp::Neg, ExprKind::Integer(value)) => ExprKind::Integer(value.wrapping_neg()),
+                (UnOp::Neg, ExprKind::Float(value)) => ExprKind::Float(-value),
+                (op, kind) => ExprKind::Unary(
+                    op,
+                    Box::new(Expr {
+                        kind,
+                        line: operand.line,
+                    }),
+                ),
+            };
+            return Ok(Expr { kind, line });
+        }
+        if self.is_punct("++") || self.is_punct("--") {
+            let delta = if self.next() == Token::Punct("++") { 1 } else { -1 };
+            let target = self.target()?;
+            return Ok(Expr {
+                kind: ExprKind::Step {
+                    target,
+                    delta,
+                    prefix: true,
+                },
+                line,
+            });
+        }
+        if self.is_punct("(") {
+            if let (Token::Ident(name), Token::Punct(")")) = (self.peek_at(1), self.peek_at(2)) {
+                if let Some(ty) = Type::from_name(name) {
+                    self.next();
+                    self.next();
+                    self.next();
+                    let operand = self.unary()?;
+                    return Ok(Expr {
+                        kind: ExprKind::Cast(ty, Box::new(operand)),
+                        line,
+                    });
+                }
+            }
+        }
+        self.postfix()
+    }
+
+    fn target(&mut self) -> Result<Target, CompileError> {
+        let name = self.ident()?;
+        let member = if self.eat(".") {
+            let member = self.ident()?;
+            Some(member_index(&member).ok_or_else(|| self.error(format!("no member '{}'", member)))?)
+        } else {
+            None
+        };
+        Ok(Target { name, member })
+    }
+
+    fn postfix(&mut self) -> Result<Expr, CompileError> {
+        let line = self.line();
+        match self.peek().clone() {
+            Token::Integer(value) => {
+                self.next();
+                Ok(Expr {
+                    kind: ExprKind::Integer(value),
+                    line,
+                })
+            }
+            Token::Float(value) => {
+                self.next();
+                Ok(Expr {
+                    kind: ExprKind::Float(value),
+                    line,
+                })
+            }
+            Token::String(text) => {
+                self.next();
+                Ok(Expr {
+                    kind: ExprKind::String(text),
+                    line,
+                })
+            }
+            Token::Punct("(") => {
+                self.next();
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Punct("[") => {
+                self.next();
+                let items = self.expr_list("]")?;
+                Ok(Expr {
+                    kind: ExprKind::List(items),
+                    line,
+                })
+            }
+            Token::Punct("<") => {
+                self.next();
+                let mut components = vec![self.binary(SHIFT_LEVEL)?];
+                while self.eat(",") {
+                    components.push(self.binary(SHIFT_LEVEL)?);
+                }
+                self.expect(">")?;
+                let kind = match components.len() {
+                    3 => ExprKind::Vector(components),
+                    4 => ExprKind::Rotation(components),
+                    n => return Err(CompileError::new(line, format!("a vector has 3 components, not {}", n))),
+                };
+                Ok(Expr { kind, line })
+            }
+            Token::Ident(name) if !is_keyword(&name) => {
+                if *self.peek_at(1) == Token::Punct("(") {
+                    self.next();
+                    self.next();
+                    let args = self.expr_list(")")?;
+                    return Ok(Expr {
+                        kind: ExprKind::Call(name, args),
+                        line,
+                    });
+                }
+                let target = self.target()?;
+                if self.is_punct("++") || self.is_punct("--") {
+                    let delta = if self.next() == Token::Punct("++") { 1 } else { -1 };
+                    return Ok(Expr {
+                        kind: ExprKind::Step {
+                            target,
+                            delta,
+                            prefix: false,
+                        },
+                        line,
+                    });
+                }
+                Ok(Expr {
+                    kind: ExprKind::Variable(target),
+                    line,
+                })
+            }
+            other => Err(self.error(format!("expected an expression, found {}", describe(&other)))),
+        }
+    }
+}
+
+fn member_index(member: &str) -> Option<u8> {
+    match member {
+        "x" => Some(0),
+        "y" => Some(1),
+        "z" => Some(2),
+        "s" => Some(3),
+        _ => None,
+    }
+}
+
+fn is_keyword(word: &str) -> bool {
+    matches!(
+        word,
+        "if" | "else" | "while" | "do" | "for" | "return" | "state" | "default" | "jump"
+    ) || Type::from_name(word).is_some()
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(name) => format!("'{}'", name),
+        Token::Integer(value) => format!("'{}'", value),
+        Token::Float(value) => format!("'{}'", value),
+        Token::String(_) => "a string".to_string(),
+        Token::Punct(p) => format!("'{}'", p),
+        Token::Eof => "the end of the script".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let script = parse(
+            r#"
+            integer count = 1;
+            vector offset;
+
+            integer twice(integer n) { return n * 2; }
+
+            default {
+                touch_start(integer total) {
+                    count += twice(total);
+                    offset.z = (float)count / 2;
+                    if (count > 10 && offset != <0, 0, 1 << 2>) state done;
+                    else llSay(0, "Touched " + (string)count);
+                }
+            }
+
+            state done { state_entry() { llDie(); } }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(script.globals.len(), 2);
+        assert_eq!(script.functions[0].returns, Some(Type::Integer));
+        assert_eq!(script.states.len(), 2);
+        let body = &script.states[0].handlers[0].body;
+        assert!(matches!(
+            &body[1],
+            Stmt::Expr(Expr {
+                kind: ExprKind::Assign {
+                    target: Target { member: Some(2), .. },
+                    op: None,
+                    ..
+                },
+                ..
+            })
+        ));
+        let Stmt::If { cond, otherwise, .. } = &body[2] else {
+            panic!("expected an if");
+        };
+        // `&&` binds looser than the comparisons and the vector keeps its shift
+        let ExprKind::Binary(BinOp::And, _, right) = &cond.kind else {
+            panic!("expected &&");
+        };
+        let ExprKind::Binary(BinOp::Ne, _, vector) = &right.kind else {
+            panic!("expected !=");
+        };
+        assert!(matches!(&vector.kind, ExprKind::Vector(c) if matches!(c[2].kind, ExprKind::Binary(BinOp::Shl, _, _))));
+        assert!(otherwise.is_some());
+    }
+
+    #[test]
+    fn test_parse_errors_have_lines() {
+        let error = parse("default {\n state_entry() {\n x = ;\n }\n}").unwrap_err();
+        assert_eq!(error.line, 3);
+        assert!(parse("integer x;").unwrap_err().message.contains("default"));
+        assert!(parse("default { state_entry() { jump end; } }")
+            .unwrap_err()
+            .message
+            .contains("jump"));
+    }
+}
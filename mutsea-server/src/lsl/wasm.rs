@@ -0,0 +1,387 @@
+//! Minimal WebAssembly binary encoder for compiled scripts
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValType {
+    I32,
+    F32,
+}
+
+impl ValType {
+    fn byte(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7f,
+            ValType::F32 => 0x7d,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+pub mod op {
+    pub const BLOCK: u8 = 0x02;
+    pub const LOOP: u8 = 0x03;
+    pub const IF: u8 = 0x04;
+    pub const ELSE: u8 = 0x05;
+    pub const END: u8 = 0x0b;
+    pub const BR: u8 = 0x0c;
+    pub const BR_IF: u8 = 0x0d;
+    pub const RETURN: u8 = 0x0f;
+    pub const CALL: u8 = 0x10;
+    pub const DROP: u8 = 0x1a;
+    pub const LOCAL_GET: u8 = 0x20;
+    pub const LOCAL_SET: u8 = 0x21;
+    pub const LOCAL_TEE: u8 = 0x22;
+    pub const GLOBAL_GET: u8 = 0x23;
+    pub const GLOBAL_SET: u8 = 0x24;
+    pub const I32_CONST: u8 = 0x41;
+    pub const F32_CONST: u8 = 0x43;
+    pub const I32_EQZ: u8 = 0x45;
+    pub const I32_EQ: u8 = 0x46;
+    pub const I32_NE: u8 = 0x47;
+    pub const I32_LT_S: u8 = 0x48;
+    pub const I32_GT_S: u8 = 0x4a;
+    pub const I32_LE_S: u8 = 0x4c;
+    pub const I32_GE_S: u8 = 0x4e;
+    pub const F32_EQ: u8 = 0x5b;
+    pub const F32_NE: u8 = 0x5c;
+    pub const F32_LT: u8 = 0x5d;
+    pub const F32_GT: u8 = 0x5e;
+    pub const F32_LE: u8 = 0x5f;
+    pub const F32_GE: u8 = 0x60;
+    pub const I32_ADD: u8 = 0x6a;
+    pub const I32_SUB: u8 = 0x6b;
+    pub const I32_MUL: u8 = 0x6c;
+    pub const I32_DIV_S: u8 = 0x6d;
+    pub const I32_REM_S: u8 = 0x6f;
+    pub const I32_AND: u8 = 0x71;
+    pub const I32_OR: u8 = 0x72;
+    pub const I32_XOR: u8 = 0x73;
+    pub const I32_SHL: u8 = 0x74;
+    pub const I32_SHR_S: u8 = 0x75;
+    pub const F32_NEG: u8 = 0x8c;
+    pub const F32_ADD: u8 = 0x92;
+    pub const F32_SUB: u8 = 0x93;
+    pub const F32_MUL: u8 = 0x94;
+    pub const F32_DIV: u8 = 0x95;
+    pub const F32_CONVERT_I32_S: u8 = 0xb2;
+    /// Prefix of the saturating truncations
+    pub const MISC: u8 = 0xfc;
+    /// `i32.trunc_sat_f32_s` after [`MISC`]
+    pub const I32_TRUNC_SAT_F32_S: u32 = 0;
+    /// Block type of blocks without a result
+    pub const EMPTY: u8 = 0x40;
+}
+
+/// Instructions of one function body
+#[derive(Debug, Clone, Default)]
+pub struct Code {
+    bytes: Vec<u8>,
+}
+
+impl Code {
+    pub fn op(&mut self, op: u8) -> &mut Self {
+        self.bytes.push(op);
+        self
+    }
+
+    /// `op` followed by an unsigned immediate, such as a local index
+    pub fn op_u32(&mut self, op: u8, value: u32) -> &mut Self {
+        self.bytes.push(op);
+        unsigned(&mut self.bytes, value);
+        self
+    }
+
+    pub fn i32_const(&mut self, value: i32) -> &mut Self {
+        self.bytes.push(op::I32_CONST);
+        signed(&mut self.bytes, value);
+        self
+    }
+
+    pub fn f32_const(&mut self, value: f32) -> &mut Self {
+        self.bytes.push(op::F32_CONST);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// Start a `block`, `loop` or `if` without a result
+    pub fn begin(&mut self, op: u8) -> &mut Self {
+        self.bytes.push(op);
+        self.bytes.push(op::EMPTY);
+        self
+    }
+
+    pub fn misc(&mut self, op: u32) -> &mut Self {
+        self.bytes.push(op::MISC);
+        unsigned(&mut self.bytes, op);
+        self
+    }
+
+    pub fn append(&mut self, other: Code) -> &mut Self {
+        self.bytes.extend(other.bytes);
+        self
+    }
+}
+
+/// A module under construction. Imported functions come first in the
+/// function index space, then those added with [`ModuleBuilder::function`].
+#[derive(Debug, Default)]
+pub struct ModuleBuilder {
+    types: Vec<FuncType>,
+    imports: Vec<(String, String, u32)>,
+    functions: Vec<(u32, Vec<ValType>, Code)>,
+    globals: Vec<(ValType, bool, Code)>,
+    exports: Vec<(String, u8, u32)>,
+    memory_pages: Option<u32>,
+    data: Vec<u8>,
+}
+
+const EXPORT_FUNC: u8 = 0x00;
+const EXPORT_MEMORY: u8 = 0x02;
+const EXPORT_GLOBAL: u8 = 0x03;
+
+impl ModuleBuilder {
+    fn type_index(&mut self, ty: FuncType) -> u32 {
+        match self.types.iter().position(|existing| *existing == ty) {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(ty);
+                (self.types.len() - 1) as u32
+            }
+        }
+    }
+
+    /// Import function `name` from `module`; returns its function index.
+    /// All imports must be added before any function.
+    pub fn import(&mut self, module: &str, name: &str, ty: FuncType) -> u32 {
+        debug_assert!(self.functions.is_empty());
+        let type_index = self.type_index(ty);
+        self.imports.push((module.to_string(), name.to_string(), type_index));
+        (self.imports.len() - 1) as u32
+    }
+
+    /// Index the next function added will have
+    pub fn next_function(&self) -> u32 {
+        (self.imports.len() + self.functions.len()) as u32
+    }
+
+    /// Add a function with `locals` beyond its parameters and `body`,
+    /// without its final `end`; returns its index
+    pub fn function(&mut self, ty: FuncType, locals: Vec<ValType>, body: Code) -> u32 {
+        let type_index = self.type_index(ty);
+        self.functions.push((type_index, locals, body));
+        self.next_function() - 1
+    }
+
+    /// Add a global initialised by the constant expression `init`, without
+    /// its `end`; returns its index
+    pub fn global(&mut self, ty: ValType, mutable: bool, init: Code) -> u32 {
+        self.globals.push((ty, mutable, init));
+        (self.globals.len() - 1) as u32
+    }
+
+    pub fn export_function(&mut self, name: &str, index: u32) {
+        self.exports.push((name.to_string(), EXPORT_FUNC, index));
+    }
+
+    pub fn export_global(&mut self, name: &str, index: u32) {
+        self.exports.push((name.to_string(), EXPORT_GLOBAL, index));
+    }
+
+    /// Add `bytes` to the data at the start of memory; returns their offset
+    pub fn data(&mut self, bytes: &[u8]) -> u32 {
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(bytes);
+        offset
+    }
+
+    /// Give the module a memory, exported as `memory`, holding the data
+    pub fn memory(&mut self) {
+        self.memory_pages = Some(0);
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let mut module = b"\0asm".to_vec();
+        module.extend_from_slice(&1u32.to_le_bytes());
+
+        section(&mut module, 1, self.types.len(), |out| {
+            for ty in &self.types {
+                out.push(0x60);
+                vector(out, &ty.params, |out, v| out.push(v.byte()));
+                vector(out, &ty.results, |out, v| out.push(v.byte()));
+            }
+        });
+        section(&mut module, 2, self.imports.len(), |out| {
+            for (module, name, type_index) in &self.imports {
+                name_bytes(out, module);
+                name_bytes(out, name);
+                out.push(0x00);
+                unsigned(out, *type_index);
+            }
+        });
+        section(&mut module, 3, self.functions.len(), |out| {
+            for (type_index, _, _) in &self.functions {
+                unsigned(out, *type_index);
+            }
+        });
+
+        // Data takes whole 64 KiB pages, at least one
+        let pages = self
+            .memory_pages
+            .map(|_| (self.data.len() as u32).div_ceil(65536).max(1));
+        if let Some(pages) = pages {
+            section(&mut module, 5, 1, |out| {
+                out.push(0x00);
+                unsigned(out, pages);
+            });
+            self.exports.push(("memory".to_string(), EXPORT_MEMORY, 0));
+        }
+
+        section(&mut module, 6, self.globals.len(), |out| {
+            for (ty, mutable, init) in &self.globals {
+                out.push(ty.byte());
+                out.push(u8::from(*mutable));
+                out.extend_from_slice(&init.bytes);
+                out.push(op::END);
+            }
+        });
+        section(&mut module, 7, self.exports.len(), |out| {
+            for (name, kind, index) in &self.exports {
+                name_bytes(out, name);
+                out.push(*kind);
+                unsigned(out, *index);
+            }
+        });
+        section(&mut module, 10, self.functions.len(), |out| {
+            for (_, locals, body) in &self.functions {
+                let mut function = Vec::new();
+                // Runs of locals of the same type
+                let mut runs: Vec<(u32, ValType)> = Vec::new();
+                for local in locals {
+                    match runs.last_mut() {
+                        Some((count, ty)) if ty == local => *count += 1,
+                        _ => runs.push((1, *local)),
+                    }
+                }
+                vector(&mut function, &runs, |out, (count, ty)| {
+                    unsigned(out, *count);
+                    out.push(ty.byte());
+                });
+                function.extend_from_slice(&body.bytes);
+                function.push(op::END);
+                unsigned(out, function.len() as u32);
+                out.extend(function);
+            }
+        });
+        if pages.is_some() && !self.data.is_empty() {
+            section(&mut module, 11, 1, |out| {
+                out.push(0x00);
+                out.push(op::I32_CONST);
+                signed(out, 0);
+                out.push(op::END);
+                unsigned(out, self.data.len() as u32);
+                out.extend_from_slice(&self.data);
+            });
+        }
+        module
+    }
+}
+
+/// Append section `id` of `count` entries written by `write`, unless empty
+fn section(module: &mut Vec<u8>, id: u8, count: usize, write: impl FnOnce(&mut Vec<u8>)) {
+    if count == 0 {
+        return;
+    }
+    let mut content = Vec::new();
+    unsigned(&mut content, count as u32);
+    write(&mut content);
+    module.push(id);
+    unsigned(module, content.len() as u32);
+    module.extend(content);
+}
+
+fn vector<T>(out: &mut Vec<u8>, items: &[T], mut write: impl FnMut(&mut Vec<u8>, &T)) {
+    unsigned(out, items.len() as u32);
+    for item in items {
+        write(out, item);
+    }
+}
+
+fn name_bytes(out: &mut Vec<u8>, name: &str) {
+    unsigned(out, name.len() as u32);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn unsigned(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn signed(out: &mut Vec<u8>, mut value: i32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leb128() {
+        let mut out = Vec::new();
+        unsigned(&mut out, 624485);
+        assert_eq!(out, [0xe5, 0x8e, 0x26]);
+        out.clear();
+        signed(&mut out, -123456);
+        assert_eq!(out, [0xc0, 0xbb, 0x78]);
+        out.clear();
+        signed(&mut out, 64);
+        assert_eq!(out, [0xc0, 0x00]);
+    }
+
+    #[test]
+    fn test_empty_function_module() {
+        let mut module = ModuleBuilder::default();
+        let add = module.function(
+            FuncType {
+                params: vec![ValType::I32, ValType::I32],
+                results: vec![ValType::I32],
+            },
+            Vec::new(),
+            {
+                let mut code = Code::default();
+                code.op_u32(op::LOCAL_GET, 0).op_u32(op::LOCAL_GET, 1).op(op::I32_ADD);
+                code
+            },
+        );
+        module.export_function("add", add);
+        assert_eq!(
+            module.finish(),
+            [
+                0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+                0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f, // type
+                0x03, 0x02, 0x01, 0x00, // function
+                0x07, 0x07, 0x01, 0x03, b'a', b'd', b'd', 0x00, 0x00, // export
+                0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b, // code
+            ]
+        );
+    }
+}
@@ -1,24 +1,160 @@
 //! mutsea-server/src/main.rs
 //! Updated Mutsea server with full OpenSim compatibility
 
-use mutsea_core::{Service, config::MutseaConfig};
-use mutsea_network::LLUDPServer;
-use mutsea_protocol::login::OpenSimLoginService;
+use mutsea_core::circuit_breaker::CircuitBreakerRegistry;
+use mutsea_core::{Service, config::MutseaConfig, events::NetworkEventData, scene::RegionScene, AIDecisionRecorder, AIProvider, EcosystemRecorder, PerformanceRecorder, PlayerBehaviorRecorder, RegionInfo, UserId};
+use mutsea_database::{ingest::AnalyticsIngest, pool_monitor::PoolMonitor, query_stats::QueryAnalytics, sharding::AssetSharding, DatabaseManager};
+use mutsea_network::{lludp_server::{EnvironmentSource, PacketTap}, nat, LLUDPServer};
+use mutsea_network::plugin::{PluginHost, ServiceRegistry};
+use mutsea_protocol::bans::BanList;
+use mutsea_protocol::login::{OpenSimLoginService, SimAddress};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::Arc;
 use tokio::signal;
 use tracing::{info, error, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod ai;
+mod animations;
+mod attachments;
+mod auth;
+mod backup;
+mod bakes;
+mod bandwidth;
+mod behavior;
+mod checkpoint;
+#[cfg(feature = "grpc")]
+mod chat_log;
+mod cluster;
+mod coalesced;
+mod collisions;
+mod console;
+mod dialogs;
+mod directory;
+mod discord;
+mod economy;
+mod ecosystem;
+mod environment;
+mod event_queue;
+mod gltf;
+mod grid_users;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod health;
+mod idempotency;
+mod inventory;
+mod land;
+mod limits;
+mod login_queue;
+mod maintenance;
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+mod lsl;
+mod meshes;
+mod moderation;
+mod names;
+mod npc_chat;
+mod npc_movement;
+mod object_items;
 mod opensim_server;
+mod population;
+mod prim_streaming;
+mod profiler;
+mod profiles;
+mod quests;
+mod raycast;
+mod registration;
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+mod remote_data;
+mod replay;
+mod returns;
+mod scene_control;
+mod scene_query;
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+mod script_email;
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+mod script_http;
+mod scripts;
+mod search;
+mod sessions;
+mod simulation;
+mod snapshots;
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+mod sounds;
+mod telemetry;
+mod terraform;
+#[cfg(test)]
+mod test_support;
+mod vehicles;
+mod visibility;
+mod web;
+mod webhooks;
+mod worldgen;
+use animations::Animations;
+use attachments::Attachments;
+use backup::BackupScheduler;
+use bakes::AvatarBakes;
+use bandwidth::Bandwidth;
+use checkpoint::Checkpointer;
+use behavior::BehaviorLibrary;
+use collisions::Collisions;
+use console::RegionConsole;
+use economy::Economy;
+use ecosystem::EcosystemSimulator;
+use environment::RegionEnvironment;
+use event_queue::EventQueue;
+use gltf::GltfExporter;
+use grid_users::GridUsers;
+use inventory::Inventory;
+use land::LandSales;
+use terraform::Terraforming;
+use limits::Limits;
+use login_queue::LoginQueue;
+use maintenance::Maintenance;
+use meshes::MeshShapes;
+use moderation::Moderation;
+use names::Names;
+use object_items::ObjectItems;
+use ai::HttpProvider;
+use quests::{ProceduralStoryteller, QuestEngine};
+use raycast::RegionRaycast;
+use replay::ReplayRecorder;
+use returns::Returns;
+use dialogs::DialogService;
+use chat_log::ChatLogger;
+use directory::Directory;
+use discord::DiscordBridge;
+use npc_chat::{NpcConversations, PersonaResponder};
+use npc_movement::NpcMovement;
+use population::PopulationManager;
+use prim_streaming::PrimStreaming;
+use scene_control::SceneControl;
+use scene_query::SceneQuery;
+use snapshots::Snapshots;
+use sounds::Sounds;
+use vehicles::Vehicles;
+use visibility::ObjectVisibility;
+use webhooks::{Webhooks, EVENT_REGION_STARTED, EVENT_REGION_STOPPING};
+use worldgen::{ProceduralProvider, WorldGenerator};
+use health::{AnalyticsIngestHealth, DatabaseHealth, HealthRegistry, ServiceHealthSource};
 use opensim_server::OpenSimServer;
+use profiler::TickProfiler;
+use profiles::Profiles;
+use scripts::ScriptSandbox;
+use search::PlaceSearch;
+use sessions::SessionTracker;
+use simulation::{RegionStatsSource, SimulationLoop, SubsystemKind};
+use telemetry::Telemetry;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize logging with better formatting
+    // Initialize logging with better formatting; trace export is switched
+    // on once the configuration is loaded
+    let (telemetry_layer, telemetry) = Telemetry::layer();
     tracing_subscriber::registry()
+        .with(telemetry_layer)
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "mutsea=info,mutsea_server=info,mutsea_network=info,mutsea_protocol=info".into()),
+                .unwrap_or_else(|_| "mutsea=info,mutsea_server=info,mutsea_network=info,mutsea_protocol=info,mutsea_database=info".into()),
         )
         .with(
             tracing_subscriber::fmt::layer()
@@ -43,9 +179,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     info!("✅ Configuration loaded and validated successfully");
+    telemetry.start(&config.telemetry);
 
-    // Create shared login service
-    let login_service = Arc::new(OpenSimLoginService::new());
+    // Create shared login service, enforcing the ban list
+    let bans = BanList::open(&config.security.bans_file)?;
+    let sim_address = resolve_sim_address(&config).await;
+    check_sim_address_routable(&config, &sim_address).await;
+    let circuit_breakers = Arc::new(CircuitBreakerRegistry::new(config.circuit_breaker.clone()));
+    let login_service = Arc::new(
+        OpenSimLoginService::new()
+            .with_ban_list(Arc::new(bans))
+            .with_sim_address(sim_address)
+            .with_backend_breaker(circuit_breakers.breaker("database"))
+            .with_default_access_max(config.maturity.default_access_max),
+    );
     
     // Add some default test users
     login_service.add_test_user("Test".to_string(), "User".to_string(), "password".to_string());
@@ -56,12 +203,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("👥 Test users created: {}", login_service.list_users().join(", "));
 
     // Create OpenSim HTTP server
-    let opensim_server = OpenSimServer::new(config.clone());
+    let mut opensim_server = OpenSimServer::new(config.clone());
+    opensim_server.set_login_service(Arc::clone(&login_service));
+    opensim_server.set_circuit_breakers(Arc::clone(&circuit_breakers));
+    let health = Arc::new(HealthRegistry::new());
+    opensim_server.set_health(Arc::clone(&health));
+    let event_queue = EventQueue::new();
+    opensim_server.set_event_queue(event_queue.clone());
     
     // Create LLUDP server for viewer connections
     let mut lludp_server = LLUDPServer::new(&config.network.lludp).await?;
     lludp_server.set_login_service(Arc::clone(&login_service));
 
+    // Record simulation inputs so the last moments can be replayed
+    let replay = config.replay.enabled.then(|| Arc::new(ReplayRecorder::new(config.replay.clone())));
+    if let Some(replay) = &replay {
+        lludp_server.set_packet_tap(Arc::clone(replay) as Arc<dyn PacketTap>);
+        opensim_server.set_replay(Arc::clone(replay));
+    }
+
     // Determine server mode and ports
     let (http_port, lludp_port, mode) = if config.opensim.enabled {
         if config.opensim.grid_name.to_lowercase().contains("standalone") {
@@ -80,17 +240,812 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("   Grid Name: {}", config.opensim.grid_name);
     info!("   Login URI: {}", config.opensim.login_uri);
 
+    // Audit circuit migrations between client addresses
+    let mut circuit_events = lludp_server.subscribe_events();
+    tokio::spawn(async move {
+        while let Ok(event) = circuit_events.recv().await {
+            match &event.event_data {
+                NetworkEventData::CircuitMigrated { circuit_code, agent_id, from, to } => {
+                    info!(target: "audit", "circuit {} for agent {} migrated {} -> {}", circuit_code, agent_id, from, to);
+                }
+                NetworkEventData::CircuitMigrationRejected { circuit_code, agent_id, from, to, reason } => {
+                    warn!(target: "audit", "circuit {} for agent {} migration {} -> {} rejected: {}", circuit_code, agent_id, from, to, reason);
+                }
+                _ => {}
+            }
+        }
+    });
+
     // Start LLUDP server first
     if config.opensim.enabled {
         info!("🌐 Starting LLUDP server for viewer connections...");
         lludp_server.start().await?;
         info!("✅ LLUDP server listening on {}:{}", config.network.lludp.bind_address, lludp_port);
+        health.register("lludp", Arc::new(ServiceHealthSource(lludp_server.clone())));
+    }
+
+    // Let browser viewers reach the LLUDP server over WebRTC data channels
+    #[cfg(feature = "webrtc")]
+    if config.webrtc.enabled {
+        let transport = mutsea_network::webrtc::WebRtcTransport::new(config.webrtc.clone(), lludp_server.clone());
+        opensim_server.merge_routes(transport.routes());
+        info!("✅ Browser viewers accepted over WebRTC (up to {})", config.webrtc.max_peers);
+    }
+    #[cfg(not(feature = "webrtc"))]
+    if config.webrtc.enabled {
+        warn!("⚠️  webrtc.enabled is set but this build has no WebRTC support (feature `webrtc`)");
+    }
+
+    // Start the tick profiler, recording into the analytics tables if enabled
+    let profiler = Arc::new(TickProfiler::new(config.profiler.clone()));
+    let mut analytics_ingest: Option<Arc<AnalyticsIngest>> = None;
+    let mut session_tracker: Option<Arc<SessionTracker>> = None;
+    let mut ecosystem_recorder: Option<Arc<dyn EcosystemRecorder>> = None;
+    let mut ai_decision_recorder: Option<Arc<dyn AIDecisionRecorder>> = None;
+    let mut npc_database: Option<Arc<DatabaseManager>> = None;
+    let mut collision_recorder: Option<Arc<dyn PlayerBehaviorRecorder>> = None;
+    let mut asset_database: Option<Arc<DatabaseManager>> = None;
+    let mut economy_database: Option<Arc<DatabaseManager>> = None;
+    let mut attachment_database: Option<Arc<DatabaseManager>> = None;
+    let mut profile_database: Option<Arc<DatabaseManager>> = None;
+    let mut directory_database: Option<Arc<DatabaseManager>> = None;
+    let mut chat_log_database: Option<Arc<DatabaseManager>> = None;
+    let mut land_database: Option<Arc<DatabaseManager>> = None;
+    let mut return_database: Option<Arc<DatabaseManager>> = None;
+    let mut object_item_database: Option<Arc<DatabaseManager>> = None;
+    let mut inventory_database: Option<Arc<DatabaseManager>> = None;
+    let mut bake_database: Option<Arc<DatabaseManager>> = None;
+    let mut name_database: Option<Arc<DatabaseManager>> = None;
+    let mut grid_user_database: Option<Arc<DatabaseManager>> = None;
+    let mut usage_database: Option<Arc<DatabaseManager>> = None;
+    let mut plugin_database: Option<Arc<DatabaseManager>> = None;
+    let mut grpc_database: Option<Arc<DatabaseManager>> = None;
+    if config.profiler.enabled {
+        let database = if config.profiler.record_to_database {
+            match DatabaseManager::new(&config.database.url).await {
+                Ok(database) => Some(Arc::new(
+                    database
+                        .with_retrier(mutsea_core::retry::Retrier::from_config("database", &config.retry))
+                        .with_circuit_breaker(circuit_breakers.breaker("database"))
+                        .with_query_analytics(query_analytics(&config.database))
+                        .with_pool_monitor(PoolMonitor::from_config(&config.database))
+                        // The layout was checked by config validation
+                        .with_asset_sharding(AssetSharding::from_config(&config.database.asset_sharding).unwrap_or_default()),
+                )),
+                Err(e) => {
+                    warn!("⚠️  Tick profiles will not be recorded, database unavailable: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        if let Some(database) = &database {
+            health.register("database", Arc::new(DatabaseHealth::new(Arc::clone(database))));
+            database
+                .query_analytics()
+                .spawn_slow_query_log(std::time::Duration::from_secs(config.database.slow_query_log_secs));
+            database.pool_monitor().spawn_leak_detector();
+            opensim_server.set_database(Arc::clone(database));
+            mutsea_database::rollups::spawn_compaction(Arc::clone(database), &config.metrics_retention);
+            mutsea_database::world_events::spawn_compaction(Arc::clone(database), &config.world_events);
+            mutsea_database::ai_feedback::spawn_evaluation(Arc::clone(database), &config.ai_feedback);
+            // Player sessions are written to the behavior analytics at logout
+            let tracker = Arc::new(SessionTracker::new(Arc::clone(database) as Arc<dyn PlayerBehaviorRecorder>));
+            tracker.spawn(lludp_server.clone());
+            session_tracker = Some(tracker);
+            ecosystem_recorder = Some(Arc::clone(database) as Arc<dyn EcosystemRecorder>);
+            ai_decision_recorder = Some(Arc::clone(database) as Arc<dyn AIDecisionRecorder>);
+            npc_database = Some(Arc::clone(database));
+            collision_recorder = Some(Arc::clone(database) as Arc<dyn PlayerBehaviorRecorder>);
+            asset_database = Some(Arc::clone(database));
+            economy_database = Some(Arc::clone(database));
+            attachment_database = Some(Arc::clone(database));
+            profile_database = Some(Arc::clone(database));
+            directory_database = Some(Arc::clone(database));
+            chat_log_database = Some(Arc::clone(database));
+            land_database = Some(Arc::clone(database));
+            return_database = Some(Arc::clone(database));
+            object_item_database = Some(Arc::clone(database));
+            inventory_database = Some(Arc::clone(database));
+            bake_database = Some(Arc::clone(database));
+            name_database = Some(Arc::clone(database));
+            grid_user_database = Some(Arc::clone(database));
+            usage_database = Some(Arc::clone(database));
+            plugin_database = Some(Arc::clone(database));
+            grpc_database = Some(Arc::clone(database));
+        }
+        // Samples are buffered so a slow database never holds up the profiler
+        let recorder = database.map(|database| {
+            let ingest = Arc::new(AnalyticsIngest::new(config.analytics_ingest.clone()));
+            ingest.spawn_flusher(database);
+            health.register("analytics", Arc::new(AnalyticsIngestHealth(Arc::clone(&ingest))));
+            analytics_ingest = Some(Arc::clone(&ingest));
+            ingest as Arc<dyn PerformanceRecorder>
+        });
+        profiler.start(recorder);
+    }
+
+    // Start region simulation heartbeat
+    let mut simulation = SimulationLoop::new(config.simulation.clone());
+    if config.profiler.enabled {
+        simulation = simulation.with_profiler(Arc::clone(&profiler));
+        opensim_server.set_profiler(Arc::clone(&profiler));
+    }
+    if let Some(replay) = &replay {
+        simulation = simulation.with_replay(Arc::clone(replay));
+    }
+    simulation.start().await?;
+    info!("✅ Simulation loop running at {} Hz", simulation.tick_rate());
+
+    // Region scene, matching the login service's default location
+    let default_location = mutsea_protocol::login::AgentLocation::default();
+    let lludp_endpoint = SocketAddr::new(login_service.sim_address().select(None), lludp_port).to_string();
+    let mut region_info = RegionInfo::new(
+        default_location.region_name.clone(),
+        default_location.region_x,
+        default_location.region_y,
+        lludp_endpoint.clone(),
+        lludp_endpoint,
+    );
+    region_info.region_id = default_location.region_id;
+    region_info.access = config.maturity.region.sim_access();
+    login_service.set_region_maturity(default_location.region_id, config.maturity.region);
+    let region_scene = Arc::new(tokio::sync::RwLock::new(RegionScene::new(region_info, UserId::from_uuid(uuid::Uuid::nil()))));
+
+    // Resume the region from its last checkpoint, so a crash loses seconds
+    let mut checkpointer = Checkpointer::new(config.checkpoint.clone(), Arc::clone(&region_scene));
+    let recovered = if config.checkpoint.enabled { checkpointer.recover().await } else { None };
+
+    // Simulate the region's ecosystem, storing snapshots with the other analytics
+    let mut ecosystem = EcosystemSimulator::new(config.ecosystem.clone());
+    if let Some(recorder) = ecosystem_recorder {
+        ecosystem = ecosystem.with_recorder(recorder);
+    }
+    if let Some(replay) = &replay {
+        ecosystem = ecosystem.with_seeds(replay.seeds());
+    }
+    if config.ecosystem.enabled {
+        ecosystem.add_region(default_location.region_id);
+        simulation.register(SubsystemKind::Ecosystem, Box::new(ecosystem.clone())).await;
+    }
+
+    // Limit the prims, attachments, scripts and uploads of the region and its agents
+    let limits = config.limits.enabled.then(|| {
+        let limits = Limits::new(config.limits.clone(), config.attachments.max_per_avatar);
+        opensim_server.set_limits(limits.clone());
+        limits
+    });
+
+    // Play the sounds of prims, scripts and gestures to the agents in earshot
+    let sounds = config.sounds.enabled.then(|| {
+        let mut sounds = Sounds::new(config.sounds.clone(), Arc::clone(&region_scene)).with_lludp(lludp_server.clone());
+        if let Some(database) = &asset_database {
+            sounds = sounds.with_database(Arc::clone(database));
+        }
+        if let Some(limits) = &limits {
+            sounds = sounds.with_limits(limits.clone());
+        }
+        sounds
+    });
+
+    // Hold scripts to their CPU, memory and event queue quotas
+    let mut scripts = ScriptSandbox::new(config.scripts.clone(), Arc::clone(&region_scene));
+    if let Some(limits) = &limits {
+        scripts = scripts.with_limits(limits.clone());
+    }
+    // Run LSL scripts compiled to WebAssembly, applying what they do to the region
+    #[cfg(feature = "scripting")]
+    let scripts = if config.scripts.enabled {
+        let (executor, actions) = lsl::WasmExecutor::new(Arc::clone(&region_scene), config.scripts.max_memory_kb)?;
+        let scripts = scripts.with_runtime(Arc::new(executor));
+        let http = Arc::new(script_http::ScriptHttp::new(
+            config.script_http.clone(),
+            scripts.clone(),
+            &config.network.http.public_url(),
+        )?);
+        opensim_server.set_script_http(Arc::clone(&http));
+        let email = Arc::new(script_email::ScriptEmail::new(config.script_email.clone(), scripts.clone()));
+        let remote_data = Arc::new(remote_data::RemoteData::new(config.script_xmlrpc.clone(), scripts.clone()));
+        opensim_server.set_remote_data(Arc::clone(&remote_data));
+        let mut dispatcher = lsl::ActionDispatcher::new(
+            lludp_server.clone(),
+            Arc::clone(&region_scene),
+            scripts.clone(),
+            http,
+            email,
+            remote_data,
+        );
+        if let Some(sounds) = &sounds {
+            dispatcher = dispatcher.with_sounds(sounds.clone());
+        }
+        Arc::new(dispatcher).spawn(actions);
+        info!("✅ LSL scripts run under wasmtime");
+        scripts
+    } else {
+        scripts
+    };
+    if config.scripts.enabled {
+        simulation.register(SubsystemKind::Scripts, Box::new(scripts.clone())).await;
+        opensim_server.set_scripts(scripts.clone());
+    }
+
+    // Let API clients make prims and chat without a viewer
+    if config.scene_control.enabled {
+        let mut scene_control = SceneControl::new(config.scene_control.clone(), Arc::clone(&region_scene))
+            .with_lludp(lludp_server.clone())
+            .with_scripts(scripts.clone());
+        if let Some(limits) = &limits {
+            scene_control = scene_control.with_limits(limits.clone());
+        }
+        if let Some(sounds) = &sounds {
+            scene_control = scene_control.with_sounds(sounds.clone());
+        }
+        opensim_server.set_scene_control(scene_control);
+    }
+    opensim_server.set_scene_query(SceneQuery::new(Arc::clone(&region_scene)).with_scripts(scripts.clone()));
+
+    if let Some(sounds) = sounds {
+        sounds.spawn();
+        opensim_server.set_sounds(sounds);
+    }
+
+    // Save snapshots to inventory and publish the postcards senders allow
+    if config.snapshots.enabled {
+        let mut snapshots = Snapshots::new(config.snapshots.clone(), Arc::clone(&region_scene));
+        if let Some(database) = &asset_database {
+            snapshots = snapshots.with_database(Arc::clone(database));
+        }
+        if let Some(limits) = &limits {
+            snapshots = snapshots.with_limits(limits.clone());
+        }
+        opensim_server.set_snapshots(snapshots);
+    }
+
+    // Take animation uploads, converting BVH files and capping priorities
+    if config.animations.enabled {
+        let mut animations = Animations::new(config.animations.clone());
+        match &asset_database {
+            Some(database) => animations = animations.with_database(Arc::clone(database)),
+            None => warn!("⚠️  Animations can't be uploaded, database unavailable"),
+        }
+        if let Some(limits) = &limits {
+            animations = animations.with_limits(limits.clone());
+        }
+        opensim_server.set_animations(animations);
+    }
+
+    // Show viewers the day cycle and the ecosystem's weather
+    if config.environment.enabled {
+        let environment = Arc::new(RegionEnvironment::new(config.environment.clone(), default_location.region_id, ecosystem.clone()));
+        lludp_server.start_environment_task(
+            Arc::clone(&environment) as Arc<dyn EnvironmentSource>,
+            std::time::Duration::from_secs(config.environment.update_interval_secs),
+        );
+        opensim_server.set_environment(environment);
+    }
+
+    // Show agents dialogs and alerts on behalf of the grid
+    let dialogs = Arc::new(DialogService::new(lludp_server.clone(), &config.opensim.grid_name, dialogs::REPLY_TIMEOUT));
+    dialogs.spawn();
+    opensim_server.set_dialogs(Arc::clone(&dialogs));
+
+    // Generate region content from AI layout plans, recorded as AI decisions
+    if config.world_generation.enabled {
+        let generation = &config.world_generation;
+        let provider: Arc<dyn AIProvider> = match generation.provider.as_str() {
+            "http" => Arc::new(HttpProvider::new(
+                &generation.endpoint,
+                std::time::Duration::from_secs(generation.timeout_secs),
+            )?),
+            _ => {
+                let scene = region_scene.read().await;
+                Arc::new(ProceduralProvider::new(scene.info.size_x, scene.info.size_y))
+            }
+        };
+        let mut generator = WorldGenerator::new(generation.clone(), provider, Arc::clone(&region_scene));
+        if let Some(recorder) = &ai_decision_recorder {
+            generator = generator.with_recorder(Arc::clone(recorder));
+        }
+        info!("✅ World generation using the {} layout provider", generation.provider);
+        opensim_server.set_world_generator(Arc::new(generator));
+    }
+
+    // Give players quests, written by hand or by the storyteller
+    if config.quests.enabled {
+        let provider: Arc<dyn AIProvider> = match config.quests.provider.as_str() {
+            "http" => Arc::new(HttpProvider::new(
+                &config.quests.endpoint,
+                std::time::Duration::from_secs(config.quests.timeout_secs),
+            )?),
+            _ => {
+                let scene = region_scene.read().await;
+                Arc::new(ProceduralStoryteller::new(scene.info.size_x, scene.info.size_y))
+            }
+        };
+        let mut engine = QuestEngine::new(config.quests.clone(), default_location.region_id, provider)
+            .with_ecosystem(ecosystem.clone())
+            .with_dialogs(Arc::clone(&dialogs));
+        if let Some(recorder) = &ai_decision_recorder {
+            engine = engine.with_recorder(Arc::clone(recorder));
+        }
+        let engine = Arc::new(engine);
+        engine.spawn(lludp_server.clone());
+        info!("✅ Quests written by the {} storyteller", config.quests.provider);
+        opensim_server.set_quests(engine);
+    }
+
+    // Decode mesh assets into the shapes mesh prims collide as
+    let mut mesh_shapes = None;
+    if config.meshes.enabled {
+        let mut meshes = MeshShapes::new(config.meshes.clone(), Arc::clone(&region_scene));
+        if let Some(database) = asset_database {
+            meshes = meshes.with_database(database);
+        }
+        opensim_server.set_meshes(meshes.clone());
+        mesh_shapes = Some(meshes);
+    }
+
+    // Walk NPCs around the region's terrain and prims
+    let mut npc_movement = None;
+    if config.navigation.enabled {
+        let mut movement = NpcMovement::new(config.navigation.clone(), Arc::clone(&region_scene)).await;
+        if let Some(meshes) = &mesh_shapes {
+            movement = movement.with_meshes(meshes.clone());
+        }
+        for npc in &config.npc_chat.npcs {
+            movement.add_npc(npc.id, npc.position);
+        }
+        if let Some(checkpoint) = &recovered {
+            checkpoint.restore_npcs(&movement);
+        }
+        checkpointer = checkpointer.with_npcs(movement.clone());
+        simulation.register(SubsystemKind::NpcAi, Box::new(movement.clone())).await;
+        opensim_server.set_npc_movement(movement.clone());
+        npc_movement = Some(movement);
+    }
+
+    // Drive prims scripted as vehicles, carrying their riders
+    if config.vehicles.enabled {
+        let vehicles = Vehicles::new(config.vehicles.clone(), Arc::clone(&region_scene)).with_lludp(lludp_server.clone());
+        simulation.register(SubsystemKind::Physics, Box::new(vehicles.clone())).await;
+        opensim_server.set_vehicles(vehicles);
+    }
+
+    // Raise collision events, recording avatar collisions for heatmaps if asked
+    if config.collisions.enabled {
+        let mut collisions = Collisions::new(config.collisions.clone(), default_location.region_id, Arc::clone(&region_scene))
+            .with_avatars(lludp_server.clone());
+        if let Some(recorder) = collision_recorder.filter(|_| config.collisions.record_player_collisions) {
+            collisions = collisions.with_recorder(recorder);
+        }
+        simulation.register(SubsystemKind::Physics, Box::new(collisions)).await;
+    }
+
+    // Cast rays for scripts, NPC sight and click validation
+    if config.raycast.enabled {
+        let raycast = RegionRaycast::new(config.raycast.clone(), Arc::clone(&region_scene)).with_avatars(lludp_server.clone());
+        opensim_server.set_raycast(raycast);
+    }
+
+    // Export the region as glTF for standard 3D tools
+    if config.gltf_export.enabled {
+        opensim_server.set_gltf_exporter(GltfExporter::new(config.gltf_export.clone(), Arc::clone(&region_scene)));
+    }
+
+    // Sell prims and move payments, writing them to the ledger
+    let mut region_economy = None;
+    if config.economy.enabled {
+        let mut economy = Economy::new(config.economy.clone(), Arc::clone(&region_scene)).with_lludp(lludp_server.clone());
+        if let Some(limits) = &limits {
+            economy = economy.with_limits(limits.clone());
+        }
+        if let Some(database) = economy_database {
+            match database.initialize_economy_schema().await {
+                Ok(()) => economy = economy.with_database(database),
+                Err(e) => warn!("⚠️  Transactions will not be written to the ledger: {}", e),
+            }
+        }
+        economy.spawn();
+        region_economy = Some(economy.clone());
+        opensim_server.set_economy(economy);
+    }
+
+    // Let estate managers and gods kick and freeze griefers from the viewer
+    let moderation = config.moderation.enabled.then(|| {
+        let moderation = Moderation::new(config.moderation.clone(), lludp_server.clone(), Arc::clone(&login_service));
+        moderation.spawn();
+        moderation
+    });
+
+    // List the region's parcels in the viewer's place search
+    if config.opensim.enable_search {
+        PlaceSearch::new(Arc::clone(&region_scene), lludp_server.clone(), Arc::clone(&login_service)).spawn();
+    }
+
+    // Let avatars wear prims, putting them back on at login
+    let mut worn_attachments = None;
+    if config.attachments.enabled {
+        let mut attachments =
+            Attachments::new(config.attachments.clone(), Arc::clone(&region_scene)).with_lludp(lludp_server.clone());
+        if let Some(limits) = &limits {
+            attachments = attachments.with_limits(limits.clone());
+        }
+        match attachment_database {
+            Some(database) => attachments = attachments.with_database(database),
+            None => warn!("⚠️  Attachments will not be kept across logins, database unavailable"),
+        }
+        attachments.spawn();
+        worn_attachments = Some(attachments.clone());
+        opensim_server.set_attachments(attachments);
+    }
+
+    // Let viewers fetch inventory over caps, and show them the items the region gives them
+    let mut region_inventory = None;
+    if config.inventory.enabled {
+        let mut inventory = Inventory::new(config.inventory.clone()).with_event_queue(event_queue.clone());
+        match inventory_database {
+            Some(database) => inventory = inventory.with_database(database),
+            None => warn!("⚠️  Inventory can't be fetched over caps, database unavailable"),
+        }
+        region_inventory = Some(inventory.clone());
+        opensim_server.set_inventory(inventory);
+    }
+
+    // Bake avatars' outfits so everyone sees them as their wearers do
+    if config.appearance.enabled {
+        let mut bakes = AvatarBakes::new(config.appearance.clone()).with_lludp(lludp_server.clone());
+        match bake_database {
+            Some(database) => bakes = bakes.with_database(database),
+            None => warn!("⚠️  Avatars can't be baked, database unavailable"),
+        }
+        opensim_server.set_bakes(bakes);
+    }
+
+    // Return prims to their owners from About Land and the estate tools, and auto-return them
+    if config.returns.enabled {
+        let mut returns = Returns::new(config.returns.clone(), Arc::clone(&region_scene))
+            .with_lludp(lludp_server.clone())
+            .with_scripts(scripts.clone());
+        if let Some(moderation) = &moderation {
+            returns = returns.with_moderation(moderation.clone());
+        }
+        if let Some(attachments) = &worn_attachments {
+            returns = returns.with_attachments(attachments.clone());
+        }
+        if let Some(inventory) = &region_inventory {
+            returns = returns.with_inventory(inventory.clone());
+        }
+        match return_database {
+            Some(database) => returns = returns.with_database(database),
+            None => warn!("⚠️  Prims can't be returned to their owners, database unavailable"),
+        }
+        returns.spawn();
+    }
+
+    // Take prims into inventory, coalesced when several are taken together, and rez them again
+    if config.object_items.enabled {
+        let mut object_items = ObjectItems::new(config.object_items.clone(), Arc::clone(&region_scene))
+            .with_lludp(lludp_server.clone())
+            .with_scripts(scripts.clone());
+        if let Some(limits) = &limits {
+            object_items = object_items.with_limits(limits.clone());
+        }
+        if let Some(attachments) = &worn_attachments {
+            object_items = object_items.with_attachments(attachments.clone());
+        }
+        match object_item_database {
+            Some(database) => object_items = object_items.with_database(database),
+            None => warn!("⚠️  Prims can't be taken into inventory, database unavailable"),
+        }
+        object_items.spawn();
+    }
+
+    // Show and save avatar profiles and picks, and make landmarks
+    if config.profiles.enabled {
+        let mut profiles = Profiles::new(
+            config.profiles.clone(),
+            Arc::clone(&region_scene),
+            Arc::clone(&login_service),
+        )
+        .with_lludp(lludp_server.clone());
+        match profile_database {
+            Some(database) => profiles = profiles.with_database(database),
+            None => warn!("⚠️  Profiles and picks will not be kept across restarts, database unavailable"),
+        }
+        profiles.spawn();
+    }
+
+    // List classifieds and events in search, charging listings to balances
+    if config.directory.enabled {
+        let mut directory = Directory::new(
+            config.directory.clone(),
+            Arc::clone(&region_scene),
+            Arc::clone(&login_service),
+        )
+        .with_lludp(lludp_server.clone());
+        match directory_database {
+            Some(database) => directory = directory.with_database(database),
+            None => warn!("⚠️  Classifieds and events will not be kept across restarts, database unavailable"),
+        }
+        if let Some(economy) = region_economy.clone() {
+            directory = directory.with_economy(economy);
+        }
+        directory.load().await;
+        directory.spawn();
+        opensim_server.set_directory(directory);
+    }
+
+    // Log chat and IMs for moderators to search, if the configuration opts in
+    if config.chat_log.enabled {
+        match chat_log_database {
+            Some(database) => {
+                let chat_log = ChatLogger::new(config.chat_log.clone(), Arc::clone(&region_scene), database);
+                chat_log.load().await;
+                chat_log.spawn(lludp_server.clone());
+                opensim_server.set_chat_log(chat_log);
+            }
+            None => warn!("⚠️  Chat will not be logged, database unavailable"),
+        }
+    }
+
+    // Sell parcels from the viewer and settle land auctions
+    if config.land.enabled {
+        let mut land = LandSales::new(config.land.clone(), Arc::clone(&region_scene)).with_lludp(lludp_server.clone());
+        if let Some(economy) = region_economy.clone() {
+            land = land.with_economy(economy);
+        }
+        match land_database {
+            Some(database) => land = land.with_database(database),
+            None => warn!("⚠️  Land auctions unavailable, database unavailable"),
+        }
+        land.load().await;
+        land.spawn();
+        opensim_server.set_land(land);
+    }
+
+    // Terraform with the viewer's land tools, saving the edited heightmap
+    let mut terraform = None;
+    if config.terraform.enabled {
+        let terraforming = Terraforming::new(
+            config.terraform.clone(),
+            Arc::clone(&region_scene),
+            Arc::clone(&login_service),
+        )
+        .with_lludp(lludp_server.clone());
+        terraforming.load().await;
+        terraforming.spawn();
+        terraform = Some(terraforming);
+    }
+
+    if config.names.enabled {
+        let mut names = Names::new(config.names.clone(), Arc::clone(&login_service))
+            .with_lludp(lludp_server.clone())
+            .with_event_queue(event_queue.clone());
+        match name_database {
+            Some(database) => names = names.with_database(database),
+            None => warn!("⚠️  Display names will not be kept across restarts, database unavailable"),
+        }
+        names.spawn();
+        opensim_server.set_names(names);
+    }
+
+    // Homes, last locations and presence, kept across restarts when there
+    // is a database
+    let mut grid_users = GridUsers::new(Arc::clone(&login_service)).with_lludp(lludp_server.clone());
+    match grid_user_database {
+        Some(database) => grid_users = grid_users.with_database(database),
+        None => warn!("⚠️  Homes and last locations will not be kept across restarts, database unavailable"),
+    }
+    grid_users.spawn();
+    opensim_server.set_grid_users(grid_users);
+
+    if config.visibility.enabled {
+        let visibility =
+            ObjectVisibility::new(config.visibility.clone(), Arc::clone(&region_scene)).with_lludp(lludp_server.clone());
+        simulation.register(SubsystemKind::ObjectUpdates, Box::new(visibility.clone())).await;
+        opensim_server.set_visibility(visibility);
+    }
+
+    // Send arriving agents the prims around them first
+    if config.prim_streaming.enabled {
+        let mut streaming = PrimStreaming::new(config.prim_streaming.clone(), Arc::clone(&region_scene))
+            .with_lludp(lludp_server.clone());
+        if let Some(attachments) = worn_attachments {
+            streaming = streaming.with_attachments(attachments);
+        }
+        streaming.spawn();
+        simulation.register(SubsystemKind::ObjectUpdates, Box::new(streaming.clone())).await;
+        opensim_server.set_prim_streaming(streaming);
+    }
+
+    // Report the bandwidth of circuits and past sessions for billing
+    let mut bandwidth = Bandwidth::new().with_lludp(lludp_server.clone());
+    if let Some(database) = usage_database {
+        bandwidth = bandwidth.with_database(database);
+    }
+    bandwidth.spawn();
+    opensim_server.set_bandwidth(bandwidth);
+    opensim_server.set_lludp(lludp_server.clone());
+
+    // Let logins in no faster than the region can take arrivals
+    if config.login_queue.enabled {
+        let login_queue = LoginQueue::new(config.login_queue.clone()).with_lludp(lludp_server.clone());
+        login_queue.spawn();
+        opensim_server.set_login_queue(login_queue);
+    }
+
+    // Close the grid to all but administrators on request
+    let maintenance =
+        Maintenance::new(config.maintenance.clone(), Arc::clone(&login_service)).with_lludp(lludp_server.clone());
+    if config.maintenance.enabled {
+        warn!("🚧 Starting in maintenance mode, only administrators may log in");
+    }
+    opensim_server.set_maintenance(maintenance);
+
+    // Extend the server with the plugins built in and those configured
+    let mut plugins = None;
+    if config.plugins.enabled {
+        let mut host = PluginHost::new().with_lludp(lludp_server.clone());
+        if config.plugins.settings.contains_key("discord") {
+            if let Err(e) = host.register(Arc::new(DiscordBridge::new())) {
+                error!("❌ {}", e);
+            }
+        }
+        #[cfg(feature = "plugins")]
+        for library in &config.plugins.libraries {
+            if let Err(e) = host.load(library) {
+                error!("❌ {}", e);
+            }
+        }
+        #[cfg(not(feature = "plugins"))]
+        if !config.plugins.libraries.is_empty() {
+            warn!("⚠️  Plugin libraries are configured but this build can't load them (feature `plugins`)");
+        }
+
+        let mut services = ServiceRegistry::new();
+        services.insert(Arc::new(config.clone()));
+        services.insert(Arc::new(lludp_server.clone()));
+        services.insert(Arc::clone(&login_service));
+        services.insert(Arc::clone(&region_scene));
+        if let Some(database) = plugin_database {
+            services.insert(database);
+        }
+        opensim_server.merge_routes(host.start(&services, &config.plugins.settings).await);
+        let host = Arc::new(host);
+        opensim_server.set_plugins(Arc::clone(&host));
+        plugins = Some(host);
+    }
+
+    // Post key events to the grid's outside services
+    let mut webhooks = None;
+    if config.webhooks.enabled {
+        match Webhooks::new(config.webhooks.clone()) {
+            Ok(hooks) => {
+                let mut hooks = hooks
+                    .with_lludp(lludp_server.clone())
+                    .with_login_service(Arc::clone(&login_service));
+                if let Some(economy) = region_economy {
+                    hooks = hooks.with_economy(economy);
+                }
+                hooks.spawn();
+                opensim_server.set_webhooks(hooks.clone());
+                webhooks = Some(hooks);
+            }
+            Err(e) => error!("❌ Failed to start webhooks: {}", e),
+        }
+    }
+
+    // Keep parcels populated with ambient NPCs, reporting their AI cost
+    if let Some(movement) = npc_movement.as_ref().filter(|_| config.population.enabled) {
+        let mut population = PopulationManager::new(config.population.clone(), Arc::clone(&region_scene), movement.clone())
+            .with_chat(lludp_server.clone());
+        if let Some(replay) = &replay {
+            population = population.with_seeds(replay.seeds());
+        }
+        if config.behaviors.enabled {
+            match BehaviorLibrary::load(&config.behaviors.path) {
+                Ok(behaviors) => {
+                    let behaviors = Arc::new(behaviors);
+                    behaviors.spawn_reload(std::time::Duration::from_secs(config.behaviors.reload_interval_secs.max(1)));
+                    population = population.with_behaviors(behaviors);
+                }
+                Err(e) => warn!("⚠️  Ambient NPCs will only wander, behaviors failed to load: {}", e),
+            }
+        }
+        if let Some(ingest) = &analytics_ingest {
+            population = population.with_recorder(Arc::clone(ingest) as Arc<dyn PerformanceRecorder>);
+        }
+        simulation.register(SubsystemKind::NpcAi, Box::new(population)).await;
+        info!(
+            "✅ Ambient NPCs at {} per hectare, at most {}",
+            config.population.density, config.population.max_npcs
+        );
+    }
+
+    // Let NPCs answer avatars, remembering their conversations
+    if config.npc_chat.enabled && !config.npc_chat.npcs.is_empty() {
+        let provider: Arc<dyn AIProvider> = match config.npc_chat.provider.as_str() {
+            "http" => Arc::new(HttpProvider::new(
+                &config.npc_chat.endpoint,
+                std::time::Duration::from_secs(config.npc_chat.timeout_secs),
+            )?),
+            _ => Arc::new(PersonaResponder),
+        };
+        let mut conversations = NpcConversations::new(config.npc_chat.clone(), default_location.region_id, provider);
+        if let Some(database) = npc_database {
+            conversations = conversations.with_database(database);
+        }
+        if let Some(movement) = &npc_movement {
+            conversations = conversations.with_movement(movement.clone());
+        }
+        Arc::new(conversations).spawn(lludp_server.clone());
+    }
+
+    // Start scheduled region backups, applying any staged rollback first
+    let backups = Arc::new(BackupScheduler::new(config.backup.clone()));
+    backups.register_region(Arc::clone(&region_scene)).await?;
+    backups.start().await?;
+
+    // Let admins run region console commands over the admin WebSocket
+    let mut console = RegionConsole::new(lludp_server.clone(), Arc::clone(&login_service), Arc::clone(&backups));
+    console.add_region(Arc::clone(&region_scene));
+    opensim_server.set_console(Arc::new(console));
+    let checkpointer = Arc::new(checkpointer);
+    checkpointer.start();
+    if let Some(replay) = &replay {
+        replay.start(Arc::clone(&region_scene), npc_movement.clone());
+    }
+
+    // Send live region statistics to connected viewers
+    lludp_server.start_sim_stats_task(Arc::new(
+        RegionStatsSource::new(simulation.clone(), Arc::clone(&region_scene)).with_scripts(scripts.clone()),
+    ));
+
+    // Serve assets, users, presence and region control to other nodes
+    #[cfg(feature = "grpc")]
+    if config.grpc.enabled {
+        let mut services = grpc::InternalServices::new(lludp_server.clone(), Arc::clone(&region_scene));
+        if let Some(database) = grpc_database {
+            services = services.with_database(database);
+        }
+        if config.cluster.role == "controller" {
+            let controller = cluster::ClusterController::new(config.cluster.clone());
+            controller.spawn_sweeper();
+            services = services.with_controller(controller);
+            info!("🖧 Acting as the grid controller");
+        }
+        services.spawn(&config.grpc).await?;
+    }
+    // Join the grid as one of its region nodes
+    #[cfg(feature = "grpc")]
+    if config.cluster.role == "region" {
+        cluster::ClusterAgent::new(
+            config.cluster.clone(),
+            config.grpc.clone(),
+            simulation.clone(),
+            Arc::clone(&region_scene),
+            lludp_server.clone(),
+        )
+        .spawn();
+    }
+    #[cfg(not(feature = "grpc"))]
+    if config.grpc.enabled || config.cluster.role != "standalone" {
+        let _ = grpc_database;
+        warn!("⚠️  gRPC or cluster mode is configured but this build has no gRPC support (feature `grpc`)");
     }
 
     // Start HTTP server
     info!("🌐 Starting HTTP server for login and web interface...");
     opensim_server.start().await?;
     info!("✅ HTTP server listening on {}:{}", config.network.http.bind_address, http_port);
+    let region = serde_json::json!({
+        "region_id": default_location.region_id,
+        "region_name": default_location.region_name,
+    });
+    if let Some(webhooks) = &webhooks {
+        webhooks.fire(EVENT_REGION_STARTED, region.clone());
+    }
 
     // Display connection information
     info!("");
@@ -99,21 +1054,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("📱 Connect with Firestorm Viewer:");
     info!("   1. Open Firestorm");
     info!("   2. Grid Manager → Add Grid");
-    info!("   3. Login URI: http://{}:{}/", 
-          if config.network.http.bind_address == "0.0.0.0" { "127.0.0.1" } else { &config.network.http.bind_address }, 
-          http_port);
+    info!("   3. Login URI: {}", config.network.http.public_url());
     info!("   4. Grid Name: {}", config.opensim.grid_name);
     info!("   5. Login with test accounts:");
     for user in login_service.list_users() {
         info!("      - {} (password: see console)", user);
     }
     info!("");
-    info!("🌐 Web Interface: http://{}:{}/", 
-          if config.network.http.bind_address == "0.0.0.0" { "127.0.0.1" } else { &config.network.http.bind_address }, 
-          http_port);
-    info!("📊 Health Check: http://{}:{}/health", 
-          if config.network.http.bind_address == "0.0.0.0" { "127.0.0.1" } else { &config.network.http.bind_address }, 
-          http_port);
+    info!("🌐 Web Interface: {}", config.network.http.public_url());
+    info!("📊 Health Check: {}health/ready", config.network.http.public_url());
     info!("");
 
     // Start monitoring task
@@ -130,12 +1079,43 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // Stop services gracefully
+    backups.stop().await?;
+
+    info!("🛑 Stopping simulation loop...");
+    simulation.stop().await?;
+    profiler.stop();
+    if let Some(replay) = &replay {
+        replay.stop();
+    }
+    if let Err(e) = checkpointer.stop().await {
+        error!("❌ Final scene checkpoint failed: {}", e);
+    }
+    if let Some(terraform) = &terraform {
+        if let Err(e) = terraform.save().await {
+            error!("❌ Failed to save the edited terrain: {}", e);
+        }
+    }
+    if let Some(ingest) = &analytics_ingest {
+        ingest.stop().await;
+    }
+    if let Some(tracker) = &session_tracker {
+        tracker.close_all("shutdown").await;
+    }
+    if let Some(plugins) = &plugins {
+        plugins.shutdown().await;
+    }
+    if let Some(webhooks) = &webhooks {
+        webhooks.fire(EVENT_REGION_STOPPING, region);
+        webhooks.flush().await;
+    }
+
     info!("🛑 Stopping LLUDP server...");
     lludp_server.stop().await?;
 
     info!("🛑 Stopping HTTP server...");
     opensim_server.stop().await?;
 
+    telemetry.shutdown();
     info!("✅ Mutsea server stopped successfully");
     Ok(())
 }
@@ -167,6 +1147,102 @@ async fn load_config() -> Result<MutseaConfig, Box<dyn std::error::Error>> {
     Ok(MutseaConfig::default())
 }
 
+/// Query recording with the slow-query and plan-capture thresholds from
+/// `[database]`
+fn query_analytics(config: &mutsea_core::config::DatabaseConfig) -> QueryAnalytics {
+    let explain_threshold =
+        (config.explain_threshold_ms > 0).then(|| std::time::Duration::from_millis(config.explain_threshold_ms));
+    QueryAnalytics::new(std::time::Duration::from_millis(config.slow_query_ms)).with_explain_threshold(explain_threshold)
+}
+
+/// Simulator addresses advertised at login. IPv4 comes from, in order:
+/// `external_address`, `external_hostname`, STUN discovery, then the LLUDP
+/// bind address; IPv6 from `external_address_v6` or an IPv6 bind address.
+async fn resolve_sim_address(config: &MutseaConfig) -> SimAddress {
+    let lludp = &config.network.lludp;
+    let bind = mutsea_core::net::parse_ip(&lludp.bind_address)
+        .map(mutsea_core::net::reachable_ip)
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    let external = |address: &Option<String>| address.as_deref().and_then(|a| mutsea_core::net::parse_ip(a).ok());
+
+    let mut ipv4 = match external(&lludp.external_address) {
+        Some(IpAddr::V4(ip)) => Some(ip),
+        _ => None,
+    };
+    if let (None, Some(host)) = (ipv4, &lludp.external_hostname) {
+        match nat::resolve_host(host).await {
+            Ok(addresses) => {
+                ipv4 = addresses.iter().find_map(|ip| mutsea_core::net::as_ipv4(*ip));
+                if ipv4.is_none() {
+                    warn!("⚠️  external_hostname {} has no IPv4 address", host);
+                }
+            }
+            Err(e) => warn!("⚠️  Could not resolve external_hostname {}: {}", host, e),
+        }
+    }
+    if ipv4.is_none() && !lludp.stun_servers.is_empty() {
+        let timeout = std::time::Duration::from_millis(lludp.stun_timeout_ms);
+        ipv4 = nat::discover_external_ip(&lludp.stun_servers, timeout)
+            .await
+            .and_then(mutsea_core::net::as_ipv4);
+        match ipv4 {
+            Some(ip) => info!("🌍 Discovered external address {} via STUN", ip),
+            None => warn!("⚠️  No STUN server answered, advertising the bind address"),
+        }
+    }
+    let ipv4 = ipv4.or_else(|| mutsea_core::net::as_ipv4(bind));
+
+    let ipv6 = match (external(&lludp.external_address_v6), bind) {
+        (Some(IpAddr::V6(ip)), _) => Some(ip),
+        (_, IpAddr::V6(ip)) if ip.to_ipv4_mapped().is_none() => Some(ip),
+        _ => None,
+    };
+
+    SimAddress {
+        ipv4: ipv4.or_else(|| lludp.dual_stack.then_some(Ipv4Addr::LOCALHOST)),
+        ipv6,
+        port: lludp.port,
+        caps_base: config.network.http.public_url(),
+    }
+}
+
+/// Warn when viewers that reach the login URI could not reach the
+/// advertised simulator address, e.g. 127.0.0.1 behind a public login URI
+async fn check_sim_address_routable(config: &MutseaConfig, sim_address: &SimAddress) {
+    let login_host = config
+        .opensim
+        .login_uri
+        .split("://")
+        .nth(1)
+        .and_then(|rest| rest.split('/').next())
+        .map(|authority| match authority.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() => host,
+            _ => authority,
+        })
+        .unwrap_or_default();
+    if login_host.is_empty() {
+        return;
+    }
+
+    let sources = match nat::resolve_host(login_host).await {
+        Ok(sources) => sources,
+        Err(e) => {
+            warn!("⚠️  Could not resolve login URI host {}: {}", login_host, e);
+            return;
+        }
+    };
+    for source in sources {
+        let advertised = sim_address.select(Some(source));
+        if !mutsea_core::net::is_routable_from(advertised, source) {
+            warn!(
+                "⚠️  Advertised sim address {} is not reachable for viewers logging in via {} ({}); \
+                 set network.lludp.external_address, external_hostname or stun_servers",
+                advertised, login_host, source
+            );
+        }
+    }
+}
+
 async fn start_monitoring_task(lludp_server: &LLUDPServer, opensim_server: &OpenSimServer) {
     let lludp_clone = lludp_server.clone();
     let opensim_clone = opensim_server.clone();
@@ -194,6 +1270,10 @@ async fn start_monitoring_task(lludp_server: &LLUDPServer, opensim_server: &Open
             info!("   Successful Logins: {}", lludp_stats.successful_logins);
             info!("   Heartbeats Sent: {}", lludp_stats.heartbeats_sent);
             info!("   Reliable Resends: {}", lludp_stats.reliable_resends);
+            info!("   Cached Object Updates: {} ({} misses, {:.2} KB saved)",
+                  lludp_stats.object_updates_cached,
+                  lludp_stats.object_cache_misses,
+                  lludp_stats.object_cache_bytes_saved as f64 / 1024.0);
             
             if circuits_count > 0 {
                 info!("🎮 {} active viewer connection(s)", circuits_count);
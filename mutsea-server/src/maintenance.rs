@@ -0,0 +1,341 @@
+//! Maintenance mode
+//!
+//! While the grid is in maintenance, only administrators and the avatars
+//! on the configured allowlist may log in; everyone else is refused with
+//! the maintenance message. Agents already in world can be drained: each
+//! is warned, then logged out after a grace period. Maintenance started
+//! for a fixed time ends on its own.
+
+use chrono::{DateTime, Utc};
+use mutsea_core::config::MaintenanceConfig;
+use mutsea_core::UserId;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::login::OpenSimLoginService;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Whether the grid is in maintenance, and until when
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MaintenanceStatus {
+    pub active: bool,
+    pub message: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    /// When agents being drained will be logged out
+    pub draining_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+struct Window {
+    message: String,
+    started_at: DateTime<Utc>,
+    ends_at: Option<DateTime<Utc>>,
+    /// Tells this window from later ones, for the timer ending it
+    generation: u64,
+    draining_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+struct State {
+    window: Option<Window>,
+    generation: u64,
+}
+
+impl State {
+    /// The window in force at `now`, dropping one that has run out
+    fn current(&mut self, now: DateTime<Utc>) -> Option<&Window> {
+        if self
+            .window
+            .as_ref()
+            .and_then(|w| w.ends_at)
+            .is_some_and(|ends_at| ends_at <= now)
+        {
+            self.window = None;
+            info!("Maintenance ended, logins are open again");
+        }
+        self.window.as_ref()
+    }
+}
+
+/// Grid-wide maintenance mode. Clones share the mode.
+#[derive(Clone)]
+pub struct Maintenance {
+    config: MaintenanceConfig,
+    login_service: Arc<OpenSimLoginService>,
+    lludp_server: Option<LLUDPServer>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Maintenance {
+    /// Maintenance mode, already in force if the configuration says so
+    pub fn new(config: MaintenanceConfig, login_service: Arc<OpenSimLoginService>) -> Self {
+        let mut state = State::default();
+        if config.enabled {
+            state.generation = 1;
+            state.window = Some(Window {
+                message: config.message.clone(),
+                started_at: Utc::now(),
+                ends_at: None,
+                generation: 1,
+                draining_until: None,
+            });
+        }
+        Self {
+            config,
+            login_service,
+            lludp_server: None,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Drain agents from this server's circuits
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Close the grid to all but administrators and the allowlist, with
+    /// `message` or the configured one, for `duration` or until ended.
+    /// Entering again replaces the message and end time.
+    pub fn enter(&self, message: Option<String>, duration: Option<Duration>) -> MaintenanceStatus {
+        let now = Utc::now();
+        let message = message
+            .filter(|m| !m.trim().is_empty())
+            .unwrap_or_else(|| self.config.message.clone());
+        let ends_at = duration
+            .and_then(|d| chrono::Duration::from_std(d).ok())
+            .map(|d| now + d);
+        let generation = {
+            let mut state = self.state.lock().unwrap();
+            state.generation += 1;
+            let generation = state.generation;
+            let (started_at, draining_until) = match state.current(now) {
+                Some(window) => (window.started_at, window.draining_until),
+                None => (now, None),
+            };
+            state.window = Some(Window {
+                message,
+                started_at,
+                ends_at,
+                generation,
+                draining_until,
+            });
+            generation
+        };
+
+        match (duration, ends_at) {
+            (Some(duration), Some(ends_at)) => {
+                info!("Maintenance started, ending at {}", ends_at);
+                let maintenance = self.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(duration).await;
+                    maintenance.end_window(generation);
+                });
+            }
+            _ => info!("Maintenance started"),
+        }
+        self.status()
+    }
+
+    /// Open the grid again. Returns whether it was in maintenance.
+    pub fn exit(&self) -> bool {
+        let ended = self.state.lock().unwrap().window.take().is_some();
+        if ended {
+            info!("Maintenance ended, logins are open again");
+        }
+        ended
+    }
+
+    /// End the window entered as `generation` when its time is up, unless
+    /// it was ended or replaced since
+    fn end_window(&self, generation: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.window.as_ref().is_some_and(|w| w.generation == generation) {
+            state.current(Utc::now());
+        }
+    }
+
+    /// Warn every agent in world that may not stay and log them out after
+    /// the grace period, unless maintenance has ended by then. Returns how
+    /// many agents were warned.
+    pub async fn drain(&self) -> usize {
+        let grace = Duration::from_secs(self.config.drain_grace_secs);
+        let message = {
+            let mut state = self.state.lock().unwrap();
+            state.current(Utc::now());
+            let Some(window) = state.window.as_mut() else {
+                return 0;
+            };
+            window.draining_until = chrono::Duration::from_std(grace).ok().map(|d| Utc::now() + d);
+            window.message.clone()
+        };
+        let Some(lludp_server) = self.lludp_server.clone() else {
+            return 0;
+        };
+
+        let agents: Vec<UserId> = lludp_server
+            .get_all_circuits()
+            .await
+            .into_iter()
+            .filter(|circuit| circuit.authenticated)
+            .filter_map(|circuit| circuit.agent_id)
+            .filter(|agent| !self.exempt(*agent))
+            .collect();
+        let warning = format!("{} You will be logged out in {} seconds.", message, grace.as_secs());
+        for agent in &agents {
+            if let Err(e) = lludp_server.send_agent_alert(*agent, true, &warning).await {
+                warn!("Failed to warn agent {} of maintenance: {}", agent, e);
+            }
+        }
+        info!(
+            "Draining {} agents for maintenance in {}s",
+            agents.len(),
+            grace.as_secs()
+        );
+
+        let maintenance = self.clone();
+        let count = agents.len();
+        tokio::spawn(async move {
+            tokio::time::sleep(grace).await;
+            let Some(message) = maintenance.message() else {
+                info!("Maintenance ended before the drain, agents stay logged in");
+                return;
+            };
+            for agent in agents {
+                if let Err(e) = lludp_server.kick_agent(agent, &message).await {
+                    warn!("Failed to log out agent {} for maintenance: {}", agent, e);
+                }
+            }
+            if let Some(window) = maintenance.state.lock().unwrap().window.as_mut() {
+                window.draining_until = None;
+            }
+        });
+        count
+    }
+
+    /// The message refusing a login by `agent` (`None` when its password
+    /// didn't check out), or `None` if it may log in
+    pub fn refusal(&self, agent: Option<UserId>) -> Option<String> {
+        let message = self.message()?;
+        match agent {
+            Some(agent) if self.exempt(agent) => None,
+            _ => Some(message),
+        }
+    }
+
+    /// The maintenance message while the grid is in maintenance
+    fn message(&self) -> Option<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .current(Utc::now())
+            .map(|w| w.message.clone())
+    }
+
+    /// Whether `agent` may log in and stay during maintenance
+    pub fn exempt(&self, agent: UserId) -> bool {
+        self.login_service.is_admin(&agent)
+            || self.config.allowlist.iter().any(|entry| {
+                let entry = entry.trim();
+                match Uuid::parse_str(entry) {
+                    Ok(id) => UserId::from_uuid(id) == agent,
+                    Err(_) => {
+                        entry
+                            .split_once(' ')
+                            .and_then(|(first, last)| self.login_service.get_user_by_name(first, last))
+                            == Some(agent)
+                    }
+                }
+            })
+    }
+
+    pub fn status(&self) -> MaintenanceStatus {
+        let mut state = self.state.lock().unwrap();
+        match state.current(Utc::now()) {
+            Some(window) => MaintenanceStatus {
+                active: true,
+                message: Some(window.message.clone()),
+                started_at: Some(window.started_at),
+                ends_at: window.ends_at,
+                draining_until: window.draining_until,
+            },
+            None => MaintenanceStatus {
+                active: false,
+                message: None,
+                started_at: None,
+                ends_at: None,
+                draining_until: None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_only_admins_and_the_allowlist_log_in_during_maintenance() {
+        let login_service = Arc::new(OpenSimLoginService::new());
+        let admin = login_service
+            .create_user("Grid", "Admin", "secret", None, true)
+            .unwrap();
+        let builder = login_service
+            .create_user("Ada", "Builder", "secret", None, false)
+            .unwrap();
+        let resident = login_service
+            .create_user("Bob", "Resident", "secret", None, false)
+            .unwrap();
+        let maintenance = Maintenance::new(
+            MaintenanceConfig {
+                allowlist: vec!["Ada Builder".to_string()],
+                ..MaintenanceConfig::default()
+            },
+            Arc::clone(&login_service),
+        );
+
+        assert_eq!(maintenance.refusal(Some(resident)), None);
+        assert!(!maintenance.status().active);
+
+        let status = maintenance.enter(Some("Upgrading the database".to_string()), None);
+        assert!(status.active && status.ends_at.is_none());
+        assert_eq!(maintenance.refusal(Some(admin)), None);
+        assert_eq!(maintenance.refusal(Some(builder)), None);
+        assert_eq!(
+            maintenance.refusal(Some(resident)).as_deref(),
+            Some("Upgrading the database")
+        );
+        assert!(maintenance.refusal(None).is_some());
+
+        assert!(maintenance.exit());
+        assert!(!maintenance.exit());
+        assert_eq!(maintenance.refusal(Some(resident)), None);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_ends_on_its_own() {
+        let maintenance = Maintenance::new(
+            MaintenanceConfig {
+                enabled: true,
+                ..MaintenanceConfig::default()
+            },
+            Arc::new(OpenSimLoginService::new()),
+        );
+        let resident = UserId::new();
+        assert_eq!(
+            maintenance.refusal(Some(resident)),
+            Some(MaintenanceConfig::default().message)
+        );
+
+        let status = maintenance.enter(None, Some(Duration::from_millis(20)));
+        assert!(status.ends_at.is_some());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!maintenance.status().active);
+        assert_eq!(maintenance.refusal(Some(resident)), None);
+        // Nothing to drain once maintenance is over
+        assert_eq!(maintenance.drain().await, 0);
+    }
+}
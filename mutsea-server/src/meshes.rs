@@ -0,0 +1,178 @@
+//! Mesh prims
+//!
+//! A prim is given a mesh by decoding its mesh asset and keeping the
+//! physics shape the uploader chose: the convex hulls if it has them, else
+//! its physics mesh or lowest level of detail split into pieces, else the
+//! single hull around it. NPC navigation walks around and into mesh prims
+//! by these shapes instead of their boxes.
+
+use mutsea_core::config::MeshesConfig;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{ObjectId, Vector3};
+use mutsea_database::DatabaseManager;
+use mutsea_physics::PhysicsShape;
+use mutsea_protocol::mesh::{Lod, MeshAsset, Submesh};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Error)]
+pub enum MeshError {
+    #[error("Unknown object {0}")]
+    UnknownObject(ObjectId),
+    #[error("Unknown mesh asset {0}")]
+    UnknownAsset(Uuid),
+    #[error("Mesh assets can't be loaded without a database")]
+    NoDatabase,
+    #[error("Failed to load mesh asset {0}: {1}")]
+    Load(Uuid, String),
+    #[error("Invalid mesh: {0}")]
+    Invalid(String),
+    #[error("The mesh has no geometry to collide with")]
+    NoShape,
+}
+
+/// A prim's mesh as decoded
+#[derive(Debug, Clone, Serialize)]
+pub struct MeshSummary {
+    pub object: ObjectId,
+    /// Levels of detail present, most detailed first
+    pub lods: Vec<&'static str>,
+    /// Triangles of the most detailed level
+    pub triangles: usize,
+    /// Hulls of the physics shape
+    pub hulls: usize,
+}
+
+/// The physics shapes of a region's mesh prims. Clones share the shapes.
+#[derive(Clone)]
+pub struct MeshShapes {
+    config: MeshesConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    database: Option<Arc<DatabaseManager>>,
+    shapes: Arc<Mutex<HashMap<ObjectId, PhysicsShape>>>,
+}
+
+impl MeshShapes {
+    pub fn new(config: MeshesConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            config,
+            scene,
+            database: None,
+            shapes: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Load mesh assets from `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Give `object` the mesh of asset `asset_id`
+    pub async fn set_mesh(&self, object: ObjectId, asset_id: Uuid) -> Result<MeshSummary, MeshError> {
+        let database = self.database.as_ref().ok_or(MeshError::NoDatabase)?;
+        let asset = database
+            .get_asset(&asset_id.to_string())
+            .await
+            .map_err(|e| MeshError::Load(asset_id, e.to_string()))?
+            .ok_or(MeshError::UnknownAsset(asset_id))?;
+        self.set_mesh_data(object, &asset.data).await
+    }
+
+    /// Give `object` the mesh encoded in `data`
+    pub async fn set_mesh_data(&self, object: ObjectId, data: &[u8]) -> Result<MeshSummary, MeshError> {
+        if !self.scene.read().await.objects.contains_key(&object) {
+            return Err(MeshError::UnknownObject(object));
+        }
+        let mesh =
+            MeshAsset::decode(data, self.config.max_block_bytes).map_err(|e| MeshError::Invalid(e.to_string()))?;
+        let shape = physics_shape(&mesh).ok_or(MeshError::NoShape)?;
+
+        let summary = MeshSummary {
+            object,
+            lods: mesh.lods.iter().map(|(lod, _)| lod.block()).collect(),
+            triangles: mesh
+                .highest_lod()
+                .map(|(_, submeshes)| submeshes.iter().map(|submesh| submesh.triangles.len()).sum())
+                .unwrap_or_default(),
+            hulls: shape.hulls.len(),
+        };
+        info!("Object {} has a mesh of {} hulls", object, summary.hulls);
+        self.shapes.lock().unwrap().insert(object, shape);
+        Ok(summary)
+    }
+
+    /// Make `object` collide as its box again
+    pub fn remove(&self, object: ObjectId) -> bool {
+        self.shapes.lock().unwrap().remove(&object).is_some()
+    }
+
+    /// The shapes of every mesh prim still in the scene
+    pub async fn shapes(&self) -> HashMap<ObjectId, PhysicsShape> {
+        let scene = self.scene.read().await;
+        let mut shapes = self.shapes.lock().unwrap();
+        // Prims deleted since are forgotten
+        shapes.retain(|object, _| scene.objects.contains_key(object));
+        shapes.clone()
+    }
+}
+
+/// The shape `mesh` collides as, preferring the uploader's hulls
+fn physics_shape(mesh: &MeshAsset) -> Option<PhysicsShape> {
+    let shape = if !mesh.hulls.is_empty() {
+        PhysicsShape::new(mesh.hulls.clone())
+    } else if !mesh.physics_mesh.is_empty() {
+        PhysicsShape::decompose(&triangles(&mesh.physics_mesh))
+    } else if !mesh.bounding_hull.is_empty() {
+        PhysicsShape::new(vec![mesh.bounding_hull.clone()])
+    } else {
+        let submeshes = mesh
+            .lod(Lod::Lowest)
+            .or_else(|| mesh.highest_lod().map(|(_, submeshes)| submeshes))?;
+        PhysicsShape::decompose(&triangles(submeshes))
+    };
+    (!shape.is_empty()).then_some(shape)
+}
+
+fn triangles(submeshes: &[Submesh]) -> Vec<[Vector3; 3]> {
+    submeshes
+        .iter()
+        .flat_map(|submesh| {
+            submesh
+                .triangles
+                .iter()
+                .map(|triangle| triangle.map(|index| submesh.positions[index as usize]))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_physics_shape_prefers_hulls() {
+        let face = Submesh {
+            positions: vec![
+                Vector3::new(-0.5, -0.5, -0.5),
+                Vector3::new(0.5, -0.5, -0.5),
+                Vector3::new(-0.5, 0.5, -0.5),
+            ],
+            triangles: vec![[0, 1, 2]],
+        };
+        let mut mesh = MeshAsset {
+            lods: vec![(Lod::High, vec![face.clone()])],
+            ..MeshAsset::default()
+        };
+        assert_eq!(physics_shape(&mesh).unwrap().hulls, vec![face.positions.clone()]);
+
+        mesh.hulls = vec![vec![Vector3::ZERO], vec![Vector3::ONE * 0.5]];
+        assert_eq!(physics_shape(&mesh).unwrap().hulls.len(), 2);
+        assert!(physics_shape(&MeshAsset::default()).is_none());
+    }
+}
@@ -0,0 +1,337 @@
+//! Kicking and freezing agents from the viewer
+//!
+//! Grid administrators may take up god powers; gods kick, freeze and
+//! unfreeze anyone who is not a god themselves. Administrators and the
+//! configured estate managers may do the same from the estate tools
+//! without god powers, but only gods may act on an administrator. God
+//! powers are given up at logout.
+
+use mutsea_core::config::ModerationConfig;
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::UserId;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::login::OpenSimLoginService;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Shown to kicked agents who are not given a reason
+const DEFAULT_KICK_MESSAGE: &str = "You have been logged out by an estate manager.";
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ModerationError {
+    #[error("You don't have god powers")]
+    NotGod,
+    #[error("You are not an estate manager here")]
+    NotManager,
+    #[error("You are not allowed god powers")]
+    NotAdmin,
+    #[error("{0} can't be kicked or frozen by you")]
+    Protected(String),
+    #[error("{0} is not in the region")]
+    NotConnected(String),
+    #[error("{0}")]
+    Failed(String),
+}
+
+/// Estate and god moderation of the agents in the region
+#[derive(Clone)]
+pub struct Moderation {
+    config: ModerationConfig,
+    lludp_server: LLUDPServer,
+    login_service: Arc<OpenSimLoginService>,
+    /// God level of each agent that has taken up its powers
+    gods: Arc<Mutex<HashMap<UserId, u8>>>,
+}
+
+impl Moderation {
+    pub fn new(config: ModerationConfig, lludp_server: LLUDPServer, login_service: Arc<OpenSimLoginService>) -> Self {
+        Self {
+            config,
+            lludp_server,
+            login_service,
+            gods: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// God level of `agent`, 0 unless it has taken up god powers
+    pub fn god_level(&self, agent: UserId) -> u8 {
+        self.gods.lock().unwrap().get(&agent).copied().unwrap_or(0)
+    }
+
+    /// Handle kick, freeze and god power requests from the LLUDP server
+    pub fn spawn(&self) {
+        let moderation = self.clone();
+        let mut events = self.lludp_server.subscribe_events();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => moderation.handle(event.event_data).await,
+                    Err(RecvError::Lagged(missed)) => warn!("Moderation missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle(&self, event: NetworkEventData) {
+        let (agent, result) = match event {
+            NetworkEventData::AgentKickRequested {
+                agent_id,
+                target_id,
+                reason,
+                godlike,
+                ..
+            } => (agent_id, self.kick(agent_id, target_id, &reason, godlike).await),
+            NetworkEventData::AgentFreezeRequested {
+                agent_id,
+                target_id,
+                frozen,
+                godlike,
+                ..
+            } => (agent_id, self.freeze(agent_id, target_id, frozen, godlike).await),
+            NetworkEventData::GodlikePowersRequested {
+                agent_id,
+                session_id,
+                godlike,
+                token,
+                ..
+            } => (
+                agent_id,
+                self.request_powers(agent_id, session_id, godlike, token).await,
+            ),
+            NetworkEventData::AgentLoggedOut { agent_id, .. } => {
+                self.gods.lock().unwrap().remove(&agent_id);
+                return;
+            }
+            _ => return,
+        };
+
+        if let Err(e) = result {
+            self.alert(agent, &e.to_string()).await;
+        }
+    }
+
+    /// Log `target` out at the request of `agent`, acting as a god if
+    /// `godlike`
+    pub async fn kick(
+        &self,
+        agent: UserId,
+        target: UserId,
+        reason: &str,
+        godlike: bool,
+    ) -> Result<(), ModerationError> {
+        self.check(agent, target, godlike)?;
+        let reason = if reason.is_empty() {
+            DEFAULT_KICK_MESSAGE
+        } else {
+            reason
+        };
+        let kicked = self
+            .lludp_server
+            .kick_agent(target, reason)
+            .await
+            .map_err(|e| ModerationError::Failed(e.to_string()))?;
+        if !kicked {
+            return Err(ModerationError::NotConnected(self.name(target)));
+        }
+        info!("{} kicked {}", self.name(agent), self.name(target));
+        Ok(())
+    }
+
+    /// Freeze or unfreeze `target` at the request of `agent`, acting as a
+    /// god if `godlike`
+    pub async fn freeze(
+        &self,
+        agent: UserId,
+        target: UserId,
+        frozen: bool,
+        godlike: bool,
+    ) -> Result<(), ModerationError> {
+        self.check(agent, target, godlike)?;
+        if !self.lludp_server.set_frozen(target, frozen).await {
+            return Err(ModerationError::NotConnected(self.name(target)));
+        }
+
+        let message = if frozen {
+            format!("You have been frozen by {}", self.name(agent))
+        } else {
+            format!("You have been unfrozen by {}", self.name(agent))
+        };
+        self.alert(target, &message).await;
+        info!(
+            "{} {} {}",
+            self.name(agent),
+            if frozen { "froze" } else { "unfroze" },
+            self.name(target)
+        );
+        Ok(())
+    }
+
+    /// Grant `agent` god powers if it asks and is an administrator, or take
+    /// them away. The viewer is always told the level it now has.
+    pub async fn request_powers(
+        &self,
+        agent: UserId,
+        session_id: Uuid,
+        godlike: bool,
+        token: Uuid,
+    ) -> Result<(), ModerationError> {
+        let allowed = godlike && self.login_service.is_admin(&agent);
+        let level = {
+            let mut gods = self.gods.lock().unwrap();
+            if allowed {
+                gods.insert(agent, self.config.god_level);
+                self.config.god_level
+            } else {
+                gods.remove(&agent);
+                0
+            }
+        };
+        if let Err(e) = self
+            .lludp_server
+            .send_godlike_powers(agent, session_id, level, token)
+            .await
+        {
+            warn!("Failed to grant god powers to agent {}: {}", agent, e);
+        }
+
+        if godlike && !allowed {
+            return Err(ModerationError::NotAdmin);
+        }
+        info!(
+            "{} {} god powers",
+            self.name(agent),
+            if allowed { "took up" } else { "gave up" }
+        );
+        Ok(())
+    }
+
+    /// Whether `agent` may kick or freeze `target`
+    fn check(&self, agent: UserId, target: UserId, godlike: bool) -> Result<(), ModerationError> {
+        let god = self.god_level(agent) > 0;
+        if godlike && !god {
+            return Err(ModerationError::NotGod);
+        }
+        if !god && !self.is_manager(agent) {
+            return Err(ModerationError::NotManager);
+        }
+        if self.god_level(target) > 0 || (!god && self.login_service.is_admin(&target)) {
+            return Err(ModerationError::Protected(self.name(target)));
+        }
+        Ok(())
+    }
+
+    /// Whether `agent` is an administrator or a configured estate manager
+    pub fn is_manager(&self, agent: UserId) -> bool {
+        if self.login_service.is_admin(&agent) {
+            return true;
+        }
+        let Some(name) = self.login_service.get_user_name(&agent) else {
+            return false;
+        };
+        self.config
+            .estate_managers
+            .iter()
+            .any(|manager| manager.eq_ignore_ascii_case(&name))
+    }
+
+    fn name(&self, agent: UserId) -> String {
+        self.login_service
+            .get_user_name(&agent)
+            .unwrap_or_else(|| agent.to_string())
+    }
+
+    async fn alert(&self, agent: UserId, message: &str) {
+        if let Err(e) = self.lludp_server.send_agent_alert(agent, false, message).await {
+            warn!("Failed to alert agent {}: {}", agent, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::config::LLUDPConfig;
+
+    async fn moderation(config: ModerationConfig) -> (Moderation, Arc<OpenSimLoginService>) {
+        let lludp_server = LLUDPServer::new(&LLUDPConfig::default()).await.unwrap();
+        let login_service = Arc::new(OpenSimLoginService::new());
+        (
+            Moderation::new(config, lludp_server, Arc::clone(&login_service)),
+            login_service,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_only_admins_get_god_powers() {
+        let (moderation, login_service) = moderation(ModerationConfig::default()).await;
+        let admin = login_service
+            .create_user("Grid", "Admin", "secret", None, true)
+            .unwrap();
+        let resident = login_service
+            .create_user("Some", "Resident", "secret", None, false)
+            .unwrap();
+
+        let token = Uuid::new_v4();
+        assert_eq!(
+            moderation.request_powers(resident, Uuid::new_v4(), true, token).await,
+            Err(ModerationError::NotAdmin)
+        );
+        assert_eq!(moderation.god_level(resident), 0);
+
+        moderation
+            .request_powers(admin, Uuid::new_v4(), true, token)
+            .await
+            .unwrap();
+        assert_eq!(moderation.god_level(admin), 200);
+        moderation
+            .request_powers(admin, Uuid::new_v4(), false, token)
+            .await
+            .unwrap();
+        assert_eq!(moderation.god_level(admin), 0);
+    }
+
+    #[tokio::test]
+    async fn test_permissions_to_kick_and_freeze() {
+        let config = ModerationConfig {
+            estate_managers: vec!["estate manager".to_string()],
+            ..ModerationConfig::default()
+        };
+        let (moderation, login_service) = moderation(config).await;
+        let admin = login_service
+            .create_user("Grid", "Admin", "secret", None, true)
+            .unwrap();
+        let manager = login_service
+            .create_user("Estate", "Manager", "secret", None, false)
+            .unwrap();
+        let griefer = login_service
+            .create_user("Some", "Griefer", "secret", None, false)
+            .unwrap();
+
+        assert_eq!(
+            moderation.check(griefer, manager, false),
+            Err(ModerationError::NotManager)
+        );
+        assert_eq!(moderation.check(manager, griefer, true), Err(ModerationError::NotGod));
+        assert_eq!(moderation.check(manager, griefer, false), Ok(()));
+        assert_eq!(
+            moderation.check(manager, admin, false),
+            Err(ModerationError::Protected("Grid Admin".to_string()))
+        );
+
+        moderation
+            .request_powers(admin, Uuid::new_v4(), true, Uuid::new_v4())
+            .await
+            .unwrap();
+        assert_eq!(moderation.check(admin, griefer, true), Ok(()));
+        assert_eq!(
+            moderation.freeze(admin, griefer, true, true).await,
+            Err(ModerationError::NotConnected("Some Griefer".to_string()))
+        );
+    }
+}
@@ -0,0 +1,628 @@
+//! Avatar names
+//!
+//! Viewers show an avatar by its display name, or by its legacy first and
+//! last name until it picks one. Display names are kept in the
+//! displaynames table when there is a database, and only in memory
+//! otherwise; agents may change theirs once per cooldown. The avatar
+//! picker finds avatars by either name.
+//!
+//! A new display name must pass every `DisplayNameRule`, including the
+//! configured blocked words, and with uniqueness on mustn't be anyone
+//! else's display or legacy name. Every change is kept in the avatar's
+//! history and sent to the viewers polling their event queue as a
+//! DisplayNameUpdate, so name tags change in place.
+
+use chrono::{DateTime, Duration, Utc};
+use mutsea_core::config::NamesConfig;
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::UserId;
+use mutsea_database::schema::{DisplayName, DisplayNameChange};
+use mutsea_database::DatabaseManager;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::login::OpenSimLoginService;
+use mutsea_protocol::names::{encode_display_name_update, AvatarName, RESIDENT_LAST_NAME};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::event_queue::EventQueue;
+
+/// How many changes the history of an avatar lists
+const HISTORY_LIMIT: usize = 50;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum NameError {
+    #[error("Unknown avatar {0}")]
+    UnknownAvatar(UserId),
+    #[error("Display names can't be empty")]
+    Empty,
+    #[error("Display names can't be longer than {0} characters")]
+    TooLong(usize),
+    #[error("Display names can't contain control characters")]
+    InvalidCharacter,
+    #[error("\"{0}\" is already someone's name")]
+    Taken(String),
+    #[error("That display name isn't allowed: {0}")]
+    Rejected(String),
+    #[error("You can change your display name again after {}", .0.format("%Y-%m-%d %H:%M UTC"))]
+    TooSoon(DateTime<Utc>),
+    #[error("Display names unavailable: {0}")]
+    Database(String),
+}
+
+/// A check every new display name must pass, such as a profanity filter.
+/// Resetting to the legacy name is always allowed.
+pub trait DisplayNameRule: Send + Sync {
+    /// Why `display_name` isn't allowed, if it isn't
+    fn check(&self, display_name: &str) -> Result<(), String>;
+}
+
+/// Refuses display names containing any of a list of words, ignoring case
+pub struct BlockedWords {
+    words: Vec<String>,
+}
+
+impl BlockedWords {
+    /// Rule refusing `words`; blank entries are ignored
+    pub fn new(words: &[String]) -> Self {
+        Self {
+            words: words
+                .iter()
+                .map(|word| word.trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+}
+
+impl DisplayNameRule for BlockedWords {
+    fn check(&self, display_name: &str) -> Result<(), String> {
+        let display_name = display_name.to_lowercase();
+        match self.words.iter().any(|word| display_name.contains(word.as_str())) {
+            true => Err("it contains a blocked word".to_string()),
+            false => Ok(()),
+        }
+    }
+}
+
+/// A change of display name, None standing for the legacy name
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameChange {
+    pub old_display_name: Option<String>,
+    pub new_display_name: Option<String>,
+    pub changed: DateTime<Utc>,
+}
+
+/// A display name as stored
+#[derive(Debug, Clone, PartialEq)]
+struct Chosen {
+    /// None once reset to the legacy name
+    display_name: Option<String>,
+    last_changed: DateTime<Utc>,
+}
+
+/// Avatar names and display names. Clones share the display names loaded.
+#[derive(Clone)]
+pub struct Names {
+    config: NamesConfig,
+    login_service: Arc<OpenSimLoginService>,
+    lludp_server: Option<LLUDPServer>,
+    database: Option<Arc<DatabaseManager>>,
+    event_queue: Option<EventQueue>,
+    rules: Vec<Arc<dyn DisplayNameRule>>,
+    /// Display names loaded or saved, None for avatars that never chose one
+    chosen: Arc<Mutex<HashMap<UserId, Option<Chosen>>>>,
+    /// Changes made while there is no database, newest last
+    history: Arc<Mutex<HashMap<UserId, Vec<NameChange>>>>,
+}
+
+impl Names {
+    pub fn new(config: NamesConfig, login_service: Arc<OpenSimLoginService>) -> Self {
+        let blocked_words: Arc<dyn DisplayNameRule> = Arc::new(BlockedWords::new(&config.blocked_words));
+        Self {
+            config,
+            login_service,
+            lludp_server: None,
+            database: None,
+            event_queue: None,
+            rules: vec![blocked_words],
+            chosen: Arc::new(Mutex::new(HashMap::new())),
+            history: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Answer the avatar picker searches of the agents connected to
+    /// `lludp_server`
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Keep display names in `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Tell the viewers polling `event_queue` when display names change
+    pub fn with_event_queue(mut self, event_queue: EventQueue) -> Self {
+        self.event_queue = Some(event_queue);
+        self
+    }
+
+    /// Refuse display names `rule` refuses, besides the configured ones
+    pub fn with_rule(mut self, rule: Arc<dyn DisplayNameRule>) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Handle avatar picker searches from the LLUDP server
+    pub fn spawn(&self) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let names = self.clone();
+        let mut events = lludp_server.subscribe_events();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => names.handle(event.event_data).await,
+                    Err(RecvError::Lagged(missed)) => warn!("Names missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle(&self, event: NetworkEventData) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        if let NetworkEventData::AvatarPickerRequested {
+            agent_id,
+            query_id,
+            name,
+            ..
+        } = event
+        {
+            let avatars = self.search(&name).await;
+            if let Err(e) = lludp_server
+                .send_avatar_picker_reply(agent_id, query_id, &avatars)
+                .await
+            {
+                warn!("Failed to send avatar search results to {}: {}", agent_id, e);
+            }
+        }
+    }
+
+    /// How long viewers may cache the names they look up, in seconds
+    pub fn cache_max_age(&self) -> u64 {
+        self.config.cache_max_age_secs
+    }
+
+    /// When names looked up now go stale
+    pub fn expires(&self) -> DateTime<Utc> {
+        Utc::now() + Duration::seconds(self.config.cache_max_age_secs as i64)
+    }
+
+    /// The names of `avatar`, if it has an account
+    pub async fn name(&self, avatar: UserId) -> Option<AvatarName> {
+        let legacy = self.login_service.get_user_name(&avatar)?;
+        let (first_name, last_name) = legacy.split_once(' ').unwrap_or((legacy.as_str(), ""));
+        let chosen = self.chosen(avatar).await;
+        let last_changed = chosen.as_ref().map(|chosen| chosen.last_changed);
+        Some(AvatarName {
+            agent_id: avatar,
+            first_name: first_name.to_string(),
+            last_name: last_name.to_string(),
+            display_name: chosen.and_then(|chosen| chosen.display_name),
+            next_update: self.next_update(last_changed),
+        })
+    }
+
+    /// The names of each of `ids`, and the IDs that name no avatar
+    pub async fn lookup(&self, ids: &[Uuid]) -> (Vec<AvatarName>, Vec<Uuid>) {
+        let mut names = Vec::new();
+        let mut bad_ids = Vec::new();
+        for id in ids {
+            match self.name(UserId::from_uuid(*id)).await {
+                Some(name) => names.push(name),
+                None => bad_ids.push(*id),
+            }
+        }
+        (names, bad_ids)
+    }
+
+    /// Change the display name of `agent`, at most once per cooldown.
+    /// Choosing its legacy name goes back to showing that.
+    pub async fn rename(&self, agent: UserId, display_name: &str) -> Result<AvatarName, NameError> {
+        let current = self.name(agent).await.ok_or(NameError::UnknownAvatar(agent))?;
+        let display_name = self.check(&current, display_name).await?;
+        if current.next_update > Utc::now() {
+            return Err(NameError::TooSoon(current.next_update));
+        }
+        self.save(&current, display_name, Utc::now()).await?;
+        info!(
+            "Agent {} is now known as \"{}\"",
+            agent,
+            display_name_or_legacy(&current, display_name)
+        );
+        self.name(agent).await.ok_or(NameError::UnknownAvatar(agent))
+    }
+
+    /// Set the display name of `agent` without waiting out or restarting
+    /// its cooldown, as grid administrators do
+    pub async fn set_display_name(&self, agent: UserId, display_name: &str) -> Result<AvatarName, NameError> {
+        let current = self.name(agent).await.ok_or(NameError::UnknownAvatar(agent))?;
+        let display_name = self.check(&current, display_name).await?;
+        let last_changed = self
+            .chosen(agent)
+            .await
+            .map(|chosen| chosen.last_changed)
+            .unwrap_or_default();
+        self.save(&current, display_name, last_changed).await?;
+        info!(
+            "Display name of {} set to \"{}\"",
+            agent,
+            display_name_or_legacy(&current, display_name)
+        );
+        self.name(agent).await.ok_or(NameError::UnknownAvatar(agent))
+    }
+
+    /// The latest display name changes of `agent`, newest first
+    pub async fn history(&self, agent: UserId) -> Result<Vec<NameChange>, NameError> {
+        let Some(database) = &self.database else {
+            let history = self.history.lock().unwrap();
+            let changes = history.get(&agent).map(Vec::as_slice).unwrap_or_default();
+            return Ok(changes.iter().rev().take(HISTORY_LIMIT).cloned().collect());
+        };
+        let stored = database
+            .get_display_name_history(&agent.to_string(), HISTORY_LIMIT as i64)
+            .await
+            .map_err(|e| NameError::Database(e.to_string()))?;
+        Ok(stored
+            .into_iter()
+            .map(|change| NameChange {
+                old_display_name: Some(change.old_name).filter(|name| !name.is_empty()),
+                new_display_name: Some(change.new_name).filter(|name| !name.is_empty()),
+                changed: DateTime::from_timestamp(change.changed, 0).unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Up to the configured number of avatars whose legacy name starts
+    /// with, or whose display name contains, `query`, as (ID, first name,
+    /// last name)
+    pub async fn search(&self, query: &str) -> Vec<(UserId, String, String)> {
+        let limit = self.config.max_picker_results;
+        let mut found = self.login_service.search_users(query, limit);
+        let text = query.trim().to_lowercase();
+        if text.is_empty() {
+            return found;
+        }
+
+        let mut by_display_name: Vec<UserId> = self
+            .chosen
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, chosen)| {
+                chosen
+                    .as_ref()
+                    .and_then(|chosen| chosen.display_name.as_ref())
+                    .is_some_and(|name| name.to_lowercase().contains(&text))
+            })
+            .map(|(avatar, _)| *avatar)
+            .collect();
+        if let Some(database) = &self.database {
+            match database.search_display_names(&text, limit as i64).await {
+                Ok(stored) => by_display_name.extend(
+                    stored
+                        .iter()
+                        .filter(|stored| !stored.display_name.is_empty())
+                        .filter_map(|stored| Uuid::parse_str(&stored.user_id).ok())
+                        .map(UserId::from_uuid),
+                ),
+                Err(e) => warn!("Failed to search display names for \"{}\": {}", query, e),
+            }
+        }
+
+        for avatar in by_display_name {
+            if found.len() >= limit {
+                break;
+            }
+            if found.iter().any(|(id, _, _)| *id == avatar) {
+                continue;
+            }
+            if let Some(legacy) = self.login_service.get_user_name(&avatar) {
+                let (first_name, last_name) = legacy.split_once(' ').unwrap_or((legacy.as_str(), ""));
+                found.push((avatar, first_name.to_string(), last_name.to_string()));
+            }
+        }
+        found
+    }
+
+    /// When an avatar whose display name last changed at `last_changed`
+    /// may change it again
+    fn next_update(&self, last_changed: Option<DateTime<Utc>>) -> DateTime<Utc> {
+        match last_changed {
+            Some(last_changed) => last_changed + Duration::days(self.config.rename_cooldown_days as i64),
+            None => DateTime::default(),
+        }
+    }
+
+    /// The display name `current` would have as `display_name`: None for
+    /// its legacy name
+    async fn check<'a>(&self, current: &AvatarName, display_name: &'a str) -> Result<Option<&'a str>, NameError> {
+        let display_name = display_name.trim();
+        if display_name.is_empty() {
+            return Err(NameError::Empty);
+        }
+        if display_name.chars().count() > self.config.max_display_name_length {
+            return Err(NameError::TooLong(self.config.max_display_name_length));
+        }
+        if display_name.chars().any(char::is_control) {
+            return Err(NameError::InvalidCharacter);
+        }
+        if display_name.eq_ignore_ascii_case(&current.legacy_name()) {
+            return Ok(None);
+        }
+        for rule in &self.rules {
+            rule.check(display_name).map_err(NameError::Rejected)?;
+        }
+        if self.config.unique_display_names && self.is_taken(current.agent_id, display_name).await? {
+            return Err(NameError::Taken(display_name.to_string()));
+        }
+        Ok(Some(display_name))
+    }
+
+    /// Whether an avatar other than `agent` goes by `display_name`, as its
+    /// display name or its legacy name
+    async fn is_taken(&self, agent: UserId, display_name: &str) -> Result<bool, NameError> {
+        let wanted = display_name.to_lowercase();
+        let legacy_names = match display_name.contains(' ') {
+            true => vec![wanted.clone()],
+            false => vec![format!("{} {}", wanted, RESIDENT_LAST_NAME.to_lowercase())],
+        };
+        let legacy_taken = self
+            .login_service
+            .search_users(display_name, usize::MAX)
+            .into_iter()
+            .any(|(avatar, first_name, last_name)| {
+                avatar != agent && legacy_names.contains(&format!("{} {}", first_name, last_name).to_lowercase())
+            });
+        if legacy_taken {
+            return Ok(true);
+        }
+
+        let chosen_taken = self.chosen.lock().unwrap().iter().any(|(avatar, chosen)| {
+            *avatar != agent
+                && chosen
+                    .as_ref()
+                    .and_then(|chosen| chosen.display_name.as_ref())
+                    .is_some_and(|name| name.to_lowercase() == wanted)
+        });
+        if chosen_taken {
+            return Ok(true);
+        }
+        let Some(database) = &self.database else {
+            return Ok(false);
+        };
+        let stored = database
+            .find_display_name(&wanted)
+            .await
+            .map_err(|e| NameError::Database(e.to_string()))?;
+        Ok(stored.is_some_and(|stored| stored.user_id != agent.to_string()))
+    }
+
+    /// Give `current` its new `display_name`, recording the change and
+    /// telling the viewers polling their event queue
+    async fn save(
+        &self,
+        current: &AvatarName,
+        display_name: Option<&str>,
+        last_changed: DateTime<Utc>,
+    ) -> Result<(), NameError> {
+        let agent = current.agent_id;
+        let change = NameChange {
+            old_display_name: current.display_name.clone(),
+            new_display_name: display_name.map(str::to_string),
+            changed: Utc::now(),
+        };
+        match &self.database {
+            Some(database) => {
+                let stored = DisplayName {
+                    user_id: agent.to_string(),
+                    display_name: display_name.unwrap_or_default().to_string(),
+                    last_changed: last_changed.timestamp(),
+                };
+                let recorded = DisplayNameChange {
+                    user_id: agent.to_string(),
+                    old_name: change.old_display_name.clone().unwrap_or_default(),
+                    new_name: change.new_display_name.clone().unwrap_or_default(),
+                    changed: change.changed.timestamp(),
+                };
+                database
+                    .set_display_name(&stored, &recorded)
+                    .await
+                    .map_err(|e| NameError::Database(e.to_string()))?;
+            }
+            None => self.history.lock().unwrap().entry(agent).or_default().push(change),
+        }
+        let chosen = Chosen {
+            display_name: display_name.map(str::to_string),
+            last_changed,
+        };
+        self.chosen.lock().unwrap().insert(agent, Some(chosen));
+
+        if let Some(event_queue) = &self.event_queue {
+            let renamed = AvatarName {
+                display_name: display_name.map(str::to_string),
+                next_update: self.next_update(Some(last_changed)),
+                ..current.clone()
+            };
+            let body = encode_display_name_update(&renamed, &current.display_name(), self.expires());
+            event_queue.broadcast("DisplayNameUpdate", body);
+        }
+        Ok(())
+    }
+
+    async fn chosen(&self, avatar: UserId) -> Option<Chosen> {
+        if let Some(chosen) = self.chosen.lock().unwrap().get(&avatar) {
+            return chosen.clone();
+        }
+        let loaded = self.load(avatar).await;
+        self.chosen.lock().unwrap().entry(avatar).or_insert(loaded).clone()
+    }
+
+    async fn load(&self, avatar: UserId) -> Option<Chosen> {
+        let database = self.database.as_ref()?;
+        match database.get_display_name(&avatar.to_string()).await {
+            Ok(stored) => stored.map(|stored| Chosen {
+                display_name: Some(stored.display_name).filter(|name| !name.is_empty()),
+                last_changed: DateTime::from_timestamp(stored.last_changed, 0).unwrap_or_default(),
+            }),
+            Err(e) => {
+                debug!("Failed to load the display name of {}: {}", avatar, e);
+                None
+            }
+        }
+    }
+}
+
+fn display_name_or_legacy(current: &AvatarName, display_name: Option<&str>) -> String {
+    display_name
+        .map(str::to_string)
+        .unwrap_or_else(|| current.legacy_name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> (Names, Arc<OpenSimLoginService>) {
+        let login_service = Arc::new(OpenSimLoginService::new());
+        (
+            Names::new(NamesConfig::default(), Arc::clone(&login_service)),
+            login_service,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_rename_waits_out_the_cooldown() {
+        let (names, login_service) = names();
+        let agent = login_service
+            .create_user("Some", "Resident", "secret", None, false)
+            .unwrap();
+
+        let name = names.name(agent).await.unwrap();
+        assert_eq!(
+            (name.display_name(), name.username()),
+            ("Some Resident".to_string(), "some".to_string())
+        );
+        assert!(name.next_update < Utc::now());
+
+        let renamed = names.rename(agent, "  Sunny ").await.unwrap();
+        assert_eq!(renamed.display_name.as_deref(), Some("Sunny"));
+        assert!(renamed.next_update > Utc::now() + Duration::days(6));
+        assert_eq!(
+            names.rename(agent, "Cloudy").await,
+            Err(NameError::TooSoon(renamed.next_update))
+        );
+        assert_eq!(names.rename(agent, "").await, Err(NameError::Empty));
+        assert_eq!(names.rename(agent, &"x".repeat(32)).await, Err(NameError::TooLong(31)));
+
+        let reset = names.set_display_name(agent, "some resident").await.unwrap();
+        assert_eq!((reset.display_name, reset.next_update), (None, renamed.next_update));
+        let stranger = UserId::new();
+        assert_eq!(
+            names.rename(stranger, "Nobody").await,
+            Err(NameError::UnknownAvatar(stranger))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_names_are_unique_allowed_and_recorded() {
+        let login_service = Arc::new(OpenSimLoginService::new());
+        let config = NamesConfig {
+            blocked_words: vec!["Grumpy".to_string()],
+            ..NamesConfig::default()
+        };
+        let event_queue = EventQueue::new();
+        let names = Names::new(config, Arc::clone(&login_service)).with_event_queue(event_queue.clone());
+        let ada = login_service
+            .create_user("Ada", "Lovelace", "secret", None, false)
+            .unwrap();
+        let sunny = login_service
+            .create_user("Sunny", "Resident", "secret", None, false)
+            .unwrap();
+
+        assert_eq!(
+            names.set_display_name(ada, "ADA lovelace").await.unwrap().display_name,
+            None
+        );
+        assert_eq!(
+            names.set_display_name(sunny, "Ada Lovelace").await,
+            Err(NameError::Taken("Ada Lovelace".to_string()))
+        );
+        assert_eq!(
+            names.set_display_name(ada, "sunny").await,
+            Err(NameError::Taken("sunny".to_string()))
+        );
+        assert!(matches!(
+            names.set_display_name(ada, "Not grumpy").await,
+            Err(NameError::Rejected(_))
+        ));
+
+        let (id, _) = event_queue
+            .poll(sunny, None, std::time::Duration::ZERO)
+            .await
+            .unwrap_or_default();
+        names.set_display_name(ada, "Countess").await.unwrap();
+        assert_eq!(
+            names.set_display_name(sunny, "countess").await,
+            Err(NameError::Taken("countess".to_string()))
+        );
+        let (_, events) = event_queue
+            .poll(sunny, Some(id), std::time::Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(events[0]["message"], "DisplayNameUpdate");
+        assert_eq!(events[0]["body"]["old_display_name"], "Ada Lovelace");
+        assert_eq!(events[0]["body"]["agent"]["display_name"], "Countess");
+
+        let history = names.history(ada).await.unwrap();
+        let changes: Vec<(Option<&str>, Option<&str>)> = history
+            .iter()
+            .map(|change| (change.old_display_name.as_deref(), change.new_display_name.as_deref()))
+            .collect();
+        assert_eq!(changes, vec![(None, Some("Countess")), (None, None)]);
+    }
+
+    #[tokio::test]
+    async fn test_search_and_lookup_by_either_name() {
+        let (names, login_service) = names();
+        let ada = login_service
+            .create_user("Ada", "Lovelace", "secret", None, false)
+            .unwrap();
+        let grace = login_service
+            .create_user("Grace", "Hopper", "secret", None, false)
+            .unwrap();
+        names.set_display_name(grace, "Amazing Grace").await.unwrap();
+
+        let found: Vec<UserId> = names.search("ada").await.into_iter().map(|(id, _, _)| id).collect();
+        assert_eq!(found, vec![ada]);
+        let found = names.search("amazing").await;
+        assert_eq!(found, vec![(grace, "Grace".to_string(), "Hopper".to_string())]);
+
+        let nobody = Uuid::new_v4();
+        let (found, bad_ids) = names.lookup(&[grace.as_uuid(), nobody]).await;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].display_name(), "Amazing Grace");
+        assert_eq!(bad_ids, vec![nobody]);
+    }
+}
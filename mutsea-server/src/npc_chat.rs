@@ -0,0 +1,435 @@
+//! NPCs that talk back
+//!
+//! An avatar chatting on channel 0 near an NPC is answered by it: the NPC's
+//! personality and what it remembers of the avatar go to the AI provider
+//! with what was said, and the reply is said in local chat a sentence at a
+//! time as it is written. The exchange then joins the NPC's memory and
+//! learning data, which are kept in the database.
+
+use crate::npc_movement::NpcMovement;
+use async_trait::async_trait;
+use chrono::Utc;
+use mutsea_core::config::{NpcChatConfig, NpcConfig};
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::{AIProvider, ConversationReply, ConversationRequest, MutseaResult, RegionId, UserId, Vector3};
+use mutsea_database::models::WorldPosition;
+use mutsea_database::npc_memory::{self, NpcMind};
+use mutsea_database::DatabaseManager;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::chat::{ChatFromSimulator, SAY_RANGE};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Longest line said at once; viewers show up to 1023 bytes
+const MAX_LINE: usize = 1000;
+
+/// An NPC that can be talked to
+pub struct Npc {
+    config: NpcConfig,
+    /// Loaded on first use, and held while the NPC answers so it answers
+    /// one avatar at a time
+    mind: Mutex<Option<NpcMind>>,
+    /// Groups this run's exchanges in the NPC's learning data
+    session_id: Uuid,
+}
+
+impl Npc {
+    pub fn id(&self) -> Uuid {
+        self.config.id
+    }
+}
+
+/// Conversations with the NPCs of one region
+pub struct NpcConversations {
+    config: NpcChatConfig,
+    region_id: RegionId,
+    provider: Arc<dyn AIProvider>,
+    database: Option<Arc<DatabaseManager>>,
+    movement: Option<NpcMovement>,
+    npcs: Vec<Npc>,
+}
+
+impl NpcConversations {
+    pub fn new(config: NpcChatConfig, region_id: RegionId, provider: Arc<dyn AIProvider>) -> Self {
+        let npcs = config
+            .npcs
+            .iter()
+            .map(|npc| Npc {
+                config: npc.clone(),
+                mind: Mutex::new(None),
+                session_id: Uuid::new_v4(),
+            })
+            .collect();
+        Self {
+            config,
+            region_id,
+            provider,
+            database: None,
+            movement: None,
+            npcs,
+        }
+    }
+
+    /// Keep the NPCs' memory and learning data in `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Hear and speak from where `movement` has walked the NPCs
+    pub fn with_movement(mut self, movement: NpcMovement) -> Self {
+        self.movement = Some(movement);
+        self
+    }
+
+    /// Where `npc` is now
+    fn position_of(&self, npc: &Npc) -> Vector3 {
+        self.movement
+            .as_ref()
+            .and_then(|movement| movement.position(npc.id()))
+            .unwrap_or(npc.config.position)
+    }
+
+    /// The NPC nearest `position` that hears chat said there
+    pub fn listener(&self, position: Vector3) -> Option<&Npc> {
+        self.npcs
+            .iter()
+            .map(|npc| (npc, (self.position_of(npc) - position).length()))
+            .filter(|(_, distance)| *distance <= self.config.hearing_range)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(npc, _)| npc)
+    }
+
+    /// Answer `message` from `speaker_id` as `npc`, sending each line on
+    /// `say` as it is written, then remember the exchange
+    pub async fn reply(
+        &self,
+        npc: &Npc,
+        speaker_id: UserId,
+        message: &str,
+        say: mpsc::Sender<String>,
+    ) -> MutseaResult<ConversationReply> {
+        let mut mind = npc.mind.lock().await;
+        if mind.is_none() {
+            *mind = Some(self.load(npc).await);
+        }
+        let mind = mind.as_mut().unwrap();
+
+        let request = ConversationRequest {
+            npc_id: npc.id(),
+            npc_name: npc.config.name.clone(),
+            personality: serde_json::to_value(&mind.personality).unwrap_or_default(),
+            memories: mind.recollections(speaker_id.as_uuid()),
+            speaker_id,
+            message: message.to_string(),
+        };
+
+        let (partial, mut written) = mpsc::channel(16);
+        let speak = async {
+            let mut lines = LineSplitter::default();
+            while let Some(piece) = written.recv().await {
+                for line in lines.push(&piece) {
+                    let _ = say.send(line).await;
+                }
+            }
+            if let Some(line) = lines.finish() {
+                let _ = say.send(line).await;
+            }
+        };
+        let (reply, ()) = tokio::join!(self.provider.converse(&request, partial), speak);
+        let reply = reply?;
+
+        let position = self.position_of(npc);
+        let location = WorldPosition {
+            region_id: Some(self.region_id.0),
+            ..WorldPosition::new(position.x as f64, position.y as f64, position.z as f64)
+        };
+        mind.remember_conversation(speaker_id.as_uuid(), message, &reply.text, location, Utc::now());
+        self.save(npc, mind, &request, &reply).await;
+        Ok(reply)
+    }
+
+    /// The stored mind of `npc`, or a new one
+    async fn load(&self, npc: &Npc) -> NpcMind {
+        let Some(database) = &self.database else {
+            return NpcMind::default();
+        };
+        let loaded = match database.get_backend().await {
+            Ok(backend) => npc_memory::load_npc_mind(backend.as_ref(), npc.id()).await,
+            Err(e) => Err(e),
+        };
+        match loaded {
+            Ok(Some(mind)) => mind,
+            Ok(None) => NpcMind::default(),
+            Err(e) => {
+                warn!("Failed to load the memory of {}: {}", npc.config.name, e);
+                NpcMind::default()
+            }
+        }
+    }
+
+    async fn save(&self, npc: &Npc, mind: &NpcMind, request: &ConversationRequest, reply: &ConversationReply) {
+        let Some(database) = &self.database else {
+            return;
+        };
+        let input = json!({
+            "speaker_id": request.speaker_id,
+            "message": request.message,
+            "memories": request.memories,
+        });
+        let output = json!({
+            "reply": reply.text,
+            "confidence": reply.confidence,
+            "model_version": reply.model_version,
+        });
+        let saved = match database.get_backend().await {
+            Ok(backend) => {
+                let backend = backend.as_ref();
+                match npc_memory::save_npc_mind(backend, npc.id(), mind).await {
+                    Ok(()) => npc_memory::record_npc_learning(backend, npc.id(), npc.session_id, &input, &output).await,
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            warn!("Failed to store the memory of {}: {}", npc.config.name, e);
+        }
+    }
+
+    /// Have the NPC near `position`, if any, answer `speaker_id` in local chat
+    async fn answer(&self, lludp_server: &LLUDPServer, speaker_id: UserId, position: Vector3, message: &str) {
+        let Some(npc) = self.listener(position) else {
+            return;
+        };
+
+        let (say, mut lines) = mpsc::channel(16);
+        let speak = async {
+            while let Some(line) = lines.recv().await {
+                let chat = ChatFromSimulator::said_by_object(&npc.config.name, npc.id(), self.position_of(npc), &line);
+                if let Err(e) = lludp_server.send_local_chat(&chat, SAY_RANGE).await {
+                    warn!("{} could not speak: {}", npc.config.name, e);
+                }
+            }
+        };
+        let (reply, ()) = tokio::join!(self.reply(npc, speaker_id, message, say), speak);
+        match reply {
+            Ok(reply) => debug!("{} answered {}: {}", npc.config.name, speaker_id, reply.text),
+            Err(e) => warn!("{} could not answer {}: {}", npc.config.name, speaker_id, e),
+        }
+    }
+
+    /// Answer chat heard on `lludp_server`
+    pub fn spawn(self: &Arc<Self>, lludp_server: LLUDPServer) {
+        let conversations = Arc::clone(self);
+        let mut events = lludp_server.subscribe_events();
+
+        tokio::spawn(async move {
+            loop {
+                let (agent_id, message) = match events.recv().await {
+                    Ok(event) => match event.event_data {
+                        NetworkEventData::AgentChatted {
+                            agent_id,
+                            channel: 0,
+                            message,
+                            ..
+                        } if !message.trim().is_empty() => (agent_id, message),
+                        _ => continue,
+                    },
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("NPC conversations missed {} events", missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                let position = lludp_server
+                    .get_all_circuits()
+                    .await
+                    .into_iter()
+                    .find(|circuit| circuit.agent_id == Some(agent_id))
+                    .map(|circuit| circuit.position);
+                if let Some(position) = position {
+                    let conversations = Arc::clone(&conversations);
+                    let lludp_server = lludp_server.clone();
+                    tokio::spawn(async move { conversations.answer(&lludp_server, agent_id, position, &message).await });
+                }
+            }
+        });
+        info!("{} NPCs listening for chat", self.npcs.len());
+    }
+}
+
+/// Cuts streamed text into lines of chat, one sentence each
+#[derive(Default)]
+struct LineSplitter {
+    buffer: String,
+}
+
+impl LineSplitter {
+    /// Add `piece`, returning the sentences it completes
+    fn push(&mut self, piece: &str) -> Vec<String> {
+        self.buffer.push_str(piece);
+        let mut lines = Vec::new();
+        loop {
+            let end = self
+                .buffer
+                .char_indices()
+                .zip(self.buffer.chars().skip(1))
+                .find(|((_, c), next)| *c == '\n' || (matches!(c, '.' | '!' | '?') && next.is_whitespace()))
+                .map(|((i, c), _)| i + c.len_utf8());
+            let end = match end {
+                Some(end) => end,
+                None if self.buffer.len() > MAX_LINE => {
+                    // A long run without a sentence end is cut at a space
+                    let mut cut = MAX_LINE;
+                    while !self.buffer.is_char_boundary(cut) {
+                        cut -= 1;
+                    }
+                    self.buffer[..cut].rfind(' ').filter(|&space| space > 0).unwrap_or(cut)
+                }
+                None => break,
+            };
+            let line: String = self.buffer.drain(..end).collect();
+            if !line.trim().is_empty() {
+                lines.push(line.trim().to_string());
+            }
+        }
+        lines
+    }
+
+    /// Whatever is left once the text is complete
+    fn finish(&mut self) -> Option<String> {
+        let line = std::mem::take(&mut self.buffer);
+        (!line.trim().is_empty()).then(|| line.trim().to_string())
+    }
+}
+
+/// Answers from the NPC's personality alone, without an AI service:
+/// outgoing NPCs greet warmly, curious ones ask about what was mentioned
+pub struct PersonaResponder;
+
+#[async_trait]
+impl AIProvider for PersonaResponder {
+    fn name(&self) -> &str {
+        "procedural"
+    }
+
+    async fn converse(
+        &self,
+        request: &ConversationRequest,
+        partial: mpsc::Sender<String>,
+    ) -> MutseaResult<ConversationReply> {
+        let trait_of = |name: &str| {
+            request
+                .personality
+                .pointer(&format!("/big_five/{}", name))
+                .and_then(Value::as_f64)
+                .unwrap_or(0.5)
+        };
+
+        let mut sentences = vec![match (request.memories.is_empty(), trait_of("extraversion") > 0.6) {
+            (true, true) => format!("Well met, traveller! I'm {}.", request.npc_name),
+            (true, false) => format!("Hello. I'm {}.", request.npc_name),
+            (false, true) => "Good to see you again, friend!".to_string(),
+            (false, false) => "Hello again.".to_string(),
+        }];
+        if let Some(topic) = topic(&request.message) {
+            sentences.push(if trait_of("openness") > 0.6 {
+                format!("Tell me more about {}, it sounds fascinating.", topic)
+            } else {
+                format!("I don't know much about {}.", topic)
+            });
+        }
+        if request.message.trim_end().ends_with('?') {
+            sentences.push(if trait_of("agreeableness") > 0.5 {
+                "I wish I had a better answer for you.".to_string()
+            } else {
+                "That's not for me to say.".to_string()
+            });
+        }
+
+        let text = sentences.join(" ");
+        let _ = partial.send(text.clone()).await;
+        Ok(ConversationReply {
+            text,
+            confidence: 0.3,
+            model_version: "procedural-1".to_string(),
+        })
+    }
+}
+
+/// The longest word of `message`, if any is long enough to be a topic
+fn topic(message: &str) -> Option<String> {
+    message
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+        .filter(|word| word.chars().count() > 4)
+        .max_by_key(|word| word.chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_splitter_cuts_sentences() {
+        let mut lines = LineSplitter::default();
+        assert!(lines.push("Well met, trav").is_empty());
+        assert_eq!(lines.push("eller! I'm Mira. Who"), vec!["Well met, traveller!", "I'm Mira."]);
+        assert_eq!(lines.push(" are you?\nSpeak up"), vec!["Who are you?"]);
+        // 3.5 is not a sentence end
+        assert!(lines.push(" - it's 3.5 leagues").is_empty());
+        assert_eq!(lines.finish().as_deref(), Some("Speak up - it's 3.5 leagues"));
+        assert_eq!(lines.finish(), None);
+
+        let long = "word ".repeat(300);
+        let cut = lines.push(&long);
+        assert_eq!(cut.len(), 1);
+        assert!(cut[0].len() <= MAX_LINE && cut[0].ends_with("word"));
+    }
+
+    #[tokio::test]
+    async fn test_reply_is_spoken_and_remembered() {
+        let config = NpcChatConfig {
+            npcs: vec![NpcConfig {
+                id: Uuid::new_v4(),
+                name: "Mira".to_string(),
+                position: Vector3::new(128.0, 128.0, 25.0),
+            }],
+            ..NpcChatConfig::default()
+        };
+        let conversations = NpcConversations::new(config, RegionId::new(), Arc::new(PersonaResponder));
+        assert!(conversations.listener(Vector3::new(160.0, 128.0, 25.0)).is_none());
+        let npc = conversations.listener(Vector3::new(135.0, 128.0, 25.0)).unwrap();
+
+        let speaker = UserId::new();
+        let (say, mut said) = mpsc::channel(16);
+        let reply = conversations.reply(npc, speaker, "Have you seen the lighthouse?", say).await.unwrap();
+        let mut lines = Vec::new();
+        while let Some(line) = said.recv().await {
+            lines.push(line);
+        }
+        assert_eq!(
+            lines,
+            vec![
+                "Hello.",
+                "I'm Mira.",
+                "I don't know much about lighthouse.",
+                "I wish I had a better answer for you."
+            ]
+        );
+        assert_eq!(lines.join(" "), reply.text);
+
+        // The second time, Mira remembers the speaker
+        let (say, mut said) = mpsc::channel(16);
+        conversations.reply(npc, speaker, "Hi", say).await.unwrap();
+        assert_eq!(said.recv().await.as_deref(), Some("Hello again."));
+        let mind = npc.mind.lock().await;
+        assert_eq!(mind.as_ref().unwrap().recollections(speaker.as_uuid()).len(), 2);
+    }
+}
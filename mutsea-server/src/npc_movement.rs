@@ -0,0 +1,226 @@
+//! NPCs walking the region
+//!
+//! An NPC sent somewhere with `go_to` walks there a little each simulation
+//! frame, following a route around the region's terrain and prims. The
+//! navigation grid is rebuilt from the scene every few seconds, and a route
+//! whose way ahead has since been blocked is planned again.
+
+use async_trait::async_trait;
+use mutsea_core::config::NavigationConfig;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{MutseaResult, Vector3};
+use mutsea_physics::{NavGrid, Route};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::meshes::MeshShapes;
+use crate::simulation::{FrameContext, SimulationSubsystem};
+
+#[derive(Debug, Error)]
+pub enum NavError {
+    #[error("Unknown NPC {0}")]
+    UnknownNpc(Uuid),
+    #[error("No way to reach {0:?}")]
+    Unreachable(Vector3),
+}
+
+/// Where an NPC is and where it is going
+#[derive(Debug, Clone, Serialize)]
+pub struct NpcPosition {
+    pub id: Uuid,
+    pub position: Vector3,
+    pub goal: Option<Vector3>,
+}
+
+struct Walker {
+    position: Vector3,
+    route: Option<Route>,
+}
+
+struct State {
+    grid: NavGrid,
+    walkers: HashMap<Uuid, Walker>,
+}
+
+/// Moves the NPCs of one region
+#[derive(Clone)]
+pub struct NpcMovement {
+    config: NavigationConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    meshes: Option<MeshShapes>,
+    state: Arc<Mutex<State>>,
+    since_rebuild: Duration,
+}
+
+impl NpcMovement {
+    pub async fn new(config: NavigationConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        let grid = NavGrid::from_scene(&*scene.read().await, &config);
+        Self {
+            config,
+            scene,
+            meshes: None,
+            state: Arc::new(Mutex::new(State {
+                grid,
+                walkers: HashMap::new(),
+            })),
+            since_rebuild: Duration::ZERO,
+        }
+    }
+
+    /// Walk around and into mesh prims by their physics shapes
+    pub fn with_meshes(mut self, meshes: MeshShapes) -> Self {
+        self.meshes = Some(meshes);
+        self
+    }
+
+    /// Place NPC `id` at `position`, standing still
+    pub fn add_npc(&self, id: Uuid, position: Vector3) {
+        self.state
+            .lock()
+            .unwrap()
+            .walkers
+            .insert(id, Walker { position, route: None });
+    }
+
+    pub fn remove_npc(&self, id: Uuid) {
+        self.state.lock().unwrap().walkers.remove(&id);
+    }
+
+    /// Whether NPC `id` is on its way somewhere
+    pub fn is_walking(&self, id: Uuid) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .walkers
+            .get(&id)
+            .is_some_and(|walker| walker.route.is_some())
+    }
+
+    /// Whether an NPC can stand at `position`
+    pub fn is_walkable(&self, position: Vector3) -> bool {
+        self.state.lock().unwrap().grid.is_walkable(position)
+    }
+
+    pub fn position(&self, id: Uuid) -> Option<Vector3> {
+        self.state.lock().unwrap().walkers.get(&id).map(|walker| walker.position)
+    }
+
+    pub fn npcs(&self) -> Vec<NpcPosition> {
+        self.state
+            .lock()
+            .unwrap()
+            .walkers
+            .iter()
+            .map(|(id, walker)| NpcPosition {
+                id: *id,
+                position: walker.position,
+                goal: walker.route.as_ref().map(Route::goal),
+            })
+            .collect()
+    }
+
+    /// Send NPC `id` walking to `target`, returning the waypoints it will
+    /// pass through
+    pub fn go_to(&self, id: Uuid, target: Vector3) -> Result<Vec<Vector3>, NavError> {
+        let mut state = self.state.lock().unwrap();
+        let State { grid, walkers } = &mut *state;
+        let walker = walkers.get_mut(&id).ok_or(NavError::UnknownNpc(id))?;
+        let route = Route::plan(grid, walker.position, target).ok_or(NavError::Unreachable(target))?;
+        let waypoints = route.waypoints();
+        walker.route = Some(route);
+        Ok(waypoints)
+    }
+
+    /// Rebuild the navigation grid from the scene's current prims
+    async fn rebuild(&self) {
+        let shapes = match &self.meshes {
+            Some(meshes) => meshes.shapes().await,
+            None => HashMap::new(),
+        };
+        // Built before taking the state lock so walking isn't held up
+        let grid = NavGrid::from_scene_with_shapes(&*self.scene.read().await, &self.config, &shapes);
+        self.state.lock().unwrap().grid = grid;
+    }
+
+    /// Move every walking NPC on by `dt`
+    fn walk(&self, dt: Duration) {
+        let distance = self.config.walk_speed * dt.as_secs_f32();
+        let mut state = self.state.lock().unwrap();
+        let State { grid, walkers } = &mut *state;
+        for (id, walker) in walkers.iter_mut() {
+            let Some(route) = &mut walker.route else {
+                continue;
+            };
+            match route.advance(grid, walker.position, distance) {
+                Some(position) => {
+                    walker.position = position;
+                    if route.is_finished() {
+                        debug!("NPC {} arrived at {:?}", id, position);
+                        walker.route = None;
+                    }
+                }
+                None => {
+                    warn!("NPC {} can no longer reach {:?}", id, route.goal());
+                    walker.route = None;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SimulationSubsystem for NpcMovement {
+    async fn tick(&mut self, frame: &FrameContext) -> MutseaResult<()> {
+        self.since_rebuild += frame.dt;
+        if self.since_rebuild >= Duration::from_secs(self.config.rebuild_interval_secs.max(1)) {
+            self.since_rebuild = Duration::ZERO;
+            self.rebuild().await;
+        }
+        self.walk(frame.dt);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::{RegionInfo, UserId};
+
+    #[tokio::test]
+    async fn test_npcs_walk_to_their_goal() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let mut movement = NpcMovement::new(NavigationConfig::default(), scene).await;
+        let npc = Uuid::new_v4();
+        movement.add_npc(npc, Vector3::new(100.5, 100.5, 21.0));
+
+        assert!(matches!(movement.go_to(Uuid::new_v4(), Vector3::ZERO), Err(NavError::UnknownNpc(_))));
+        assert!(matches!(
+            movement.go_to(npc, Vector3::new(-5.0, 100.0, 0.0)),
+            Err(NavError::Unreachable(_))
+        ));
+
+        let waypoints = movement.go_to(npc, Vector3::new(110.5, 100.5, 0.0)).unwrap();
+        assert_eq!(waypoints.len(), 1);
+        assert_eq!(movement.npcs()[0].goal, Some(Vector3::new(110.5, 100.5, 0.0)));
+
+        for frame in 0..8 {
+            let context = FrameContext {
+                frame,
+                dt: Duration::from_secs(1),
+                degrade_level: 0,
+            };
+            movement.tick(&context).await.unwrap();
+        }
+        let position = movement.position(npc).unwrap();
+        assert!((position.x - 110.5).abs() < 1e-3);
+        assert_eq!(position.z, waypoints[0].z);
+        assert!(movement.npcs()[0].goal.is_none());
+    }
+}
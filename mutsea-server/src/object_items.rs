@@ -0,0 +1,514 @@
+//! Taking prims into inventory and rezzing them again
+//!
+//! Agents take the prims they own into their Objects folder, copy them
+//! there, or delete them into the Trash; several prims taken together
+//! become one coalesced item that keeps where they were to one another.
+//! Rezzing an object item puts its prims back where the agent's ray hit,
+//! resting on it, as new prims owned by the agent. A prim only leaves the
+//! region once its item is stored, and worn prims are never taken this way.
+
+use crate::attachments::Attachments;
+use crate::coalesced;
+use crate::limits::{LimitError, Limits};
+use crate::scripts::ScriptSandbox;
+use chrono::Utc;
+use mutsea_core::config::ObjectItemsConfig;
+use mutsea_core::events::{NetworkEventData, ObjectEvent, ObjectEventData};
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{ObjectId, RegionId, SceneObject, UserId, Vector3};
+use mutsea_database::schema::{Asset, InventoryItem};
+use mutsea_database::DatabaseManager;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::constants::{asset_types, inventory_types};
+use mutsea_protocol::rez::{ObjectItem, DEREZ_DELETE, DEREZ_TAKE, DEREZ_TAKE_COPY, OBJECT_PERMISSIONS};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Folder type of My Inventory, where items go for agents without an
+/// Objects or Trash folder
+const ROOT_FOLDER_TYPE: i32 = 8;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ObjectItemError {
+    #[error("None of the selected objects can be taken")]
+    NothingSelected,
+    #[error("You can't take \"{0}\": you don't own it")]
+    NotOwner(String),
+    #[error("At most {0} objects can be taken into one item")]
+    TooMany(usize),
+    #[error("Taking objects to {0} is not supported")]
+    UnsupportedDestination(u8),
+    #[error("Objects can't be taken or rezzed without a database")]
+    NoDatabase,
+    #[error("You have no inventory folder to put objects in")]
+    NoFolder,
+    #[error("Unknown inventory item {0}")]
+    UnknownItem(Uuid),
+    #[error("Inventory item {0} is not an object")]
+    NotAnObject(Uuid),
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+/// A selection arriving over several DeRezObject packets
+#[derive(Debug, Default)]
+struct PendingTake {
+    packets: usize,
+    local_ids: Vec<u32>,
+}
+
+/// Takes prims into inventory and rezzes object items. Clones share the
+/// selections still arriving.
+#[derive(Clone)]
+pub struct ObjectItems {
+    config: ObjectItemsConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: Option<LLUDPServer>,
+    database: Option<Arc<DatabaseManager>>,
+    limits: Option<Limits>,
+    attachments: Option<Attachments>,
+    scripts: Option<ScriptSandbox>,
+    events: broadcast::Sender<ObjectEvent>,
+    /// By agent and DeRezObject transaction
+    pending: Arc<Mutex<HashMap<(UserId, Uuid), PendingTake>>>,
+}
+
+impl ObjectItems {
+    pub fn new(config: ObjectItemsConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self {
+            config,
+            scene,
+            lludp_server: None,
+            database: None,
+            limits: None,
+            attachments: None,
+            scripts: None,
+            events,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Handle the take and rez requests of the agents connected to
+    /// `lludp_server`, and show them what changed
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Keep taken prims as assets and items in `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Refuse to rez more prims than `limits` allow
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Leave the prims worn in `attachments` alone
+    pub fn with_attachments(mut self, attachments: Attachments) -> Self {
+        self.attachments = Some(attachments);
+        self
+    }
+
+    /// Stop the scripts of taken prims in `scripts`
+    pub fn with_scripts(mut self, scripts: ScriptSandbox) -> Self {
+        self.scripts = Some(scripts);
+        self
+    }
+
+    /// Prims taken out of the region and rezzed into it, one event each
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ObjectEvent> {
+        self.events.subscribe()
+    }
+
+    /// `agent` takes the prims `local_ids` to the DeRezObject
+    /// `destination`, into `folder_id` or the default folder when nil.
+    /// Returns the item made.
+    pub async fn take(
+        &self,
+        agent: UserId,
+        local_ids: &[u32],
+        destination: u8,
+        folder_id: Uuid,
+    ) -> Result<ObjectItem, ObjectItemError> {
+        let folder_type = match destination {
+            DEREZ_TAKE | DEREZ_TAKE_COPY => asset_types::OBJECT,
+            DEREZ_DELETE => asset_types::TRASH,
+            destination => return Err(ObjectItemError::UnsupportedDestination(destination)),
+        };
+        let database = self.database.as_ref().ok_or(ObjectItemError::NoDatabase)?;
+        let prims = {
+            let scene = self.scene.read().await;
+            let mut prims: Vec<SceneObject> = scene
+                .objects
+                .values()
+                .filter(|object| local_ids.contains(&object.local_id) && !self.is_worn(object.id))
+                .cloned()
+                .collect();
+            prims.sort_by_key(|object| object.local_id);
+            prims
+        };
+        if prims.is_empty() {
+            return Err(ObjectItemError::NothingSelected);
+        }
+        if let Some(prim) = prims.iter().find(|prim| prim.owner_id != agent) {
+            return Err(ObjectItemError::NotOwner(prim.name.clone()));
+        }
+        if prims.len() > self.config.max_objects_per_item {
+            return Err(ObjectItemError::TooMany(self.config.max_objects_per_item));
+        }
+
+        let folder_id = if folder_id.is_nil() || destination == DEREZ_DELETE {
+            self.folder_of_type(database, agent, folder_type as i32).await?
+        } else {
+            folder_id
+        };
+        let item = self.store(database, agent, folder_id, &prims).await?;
+        if destination != DEREZ_TAKE_COPY {
+            self.remove(agent, &prims).await;
+        }
+        info!("Agent {} took {} prims into item {}", agent, prims.len(), item.item_id);
+        Ok(item)
+    }
+
+    /// `agent` rezzes its object item `item_id` resting on `position`,
+    /// and takes the item out of inventory if `remove_item`. Returns the
+    /// prims made.
+    pub async fn rez(
+        &self,
+        agent: UserId,
+        item_id: Uuid,
+        position: Vector3,
+        remove_item: bool,
+    ) -> Result<Vec<ObjectId>, ObjectItemError> {
+        let database = self.database.as_ref().ok_or(ObjectItemError::NoDatabase)?;
+        let database_error = |e: mutsea_database::DatabaseError| ObjectItemError::Database(e.to_string());
+        let asset_id = database
+            .get_inventory_item_asset(&item_id.to_string(), &agent.to_string())
+            .await
+            .map_err(database_error)?
+            .ok_or(ObjectItemError::UnknownItem(item_id))?;
+        let mut prims = database
+            .get_asset(&asset_id)
+            .await
+            .map_err(database_error)?
+            .filter(|asset| asset.asset_type == asset_types::OBJECT as i32)
+            .and_then(|asset| coalesced::decode(&asset.data))
+            .ok_or(ObjectItemError::NotAnObject(item_id))?;
+
+        let region_id = {
+            let mut scene = self.scene.write().await;
+            let next_local_id = scene.objects.values().map(|object| object.local_id).max().unwrap_or(0) + 1;
+            prepare(&mut prims, agent, position, next_local_id);
+            if let Some(limits) = &self.limits {
+                limits.check_prims(&scene, position, prims.len())?;
+            }
+            for prim in &prims {
+                scene.add_object(prim.clone());
+            }
+            scene.info.region_id
+        };
+        if remove_item {
+            if let Err(e) = database.delete_inventory_item(&item_id.to_string(), &agent.to_string()).await {
+                warn!("Failed to take rezzed item {} out of agent {}'s inventory: {}", item_id, agent, e);
+            }
+        }
+
+        info!("Agent {} rezzed {} prims from item {}", agent, prims.len(), item_id);
+        for prim in &prims {
+            self.send_event(
+                region_id,
+                prim.id,
+                ObjectEventData::Created {
+                    creator_id: prim.creator_id,
+                    position: prim.position,
+                    object_type: "prim".to_string(),
+                },
+            );
+            if let Some(lludp_server) = &self.lludp_server {
+                if let Err(e) = lludp_server.send_object_update(prim).await {
+                    warn!("Failed to send update of {}: {}", prim.id, e);
+                }
+            }
+        }
+        Ok(prims.iter().map(|prim| prim.id).collect())
+    }
+
+    /// Handle take and rez requests from the LLUDP server
+    pub fn spawn(&self) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let object_items = self.clone();
+        let mut events = lludp_server.subscribe_events();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => object_items.handle(event.event_data).await,
+                    Err(RecvError::Lagged(missed)) => warn!("Object items missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle(&self, event: NetworkEventData) {
+        let (agent, result) = match event {
+            NetworkEventData::ObjectsDerezRequested {
+                agent_id,
+                destination,
+                folder_id,
+                transaction_id,
+                packet_count,
+                local_ids,
+                ..
+            } => {
+                let selection = {
+                    let mut pending = self.pending.lock().unwrap();
+                    collect_packet(&mut pending, (agent_id, transaction_id), packet_count, local_ids)
+                };
+                let Some(local_ids) = selection else {
+                    return;
+                };
+                match self.take(agent_id, &local_ids, destination, folder_id).await {
+                    Ok(item) => {
+                        self.send_item(agent_id, transaction_id, &item).await;
+                        (agent_id, Ok(()))
+                    }
+                    Err(e) => (agent_id, Err(e)),
+                }
+            }
+            NetworkEventData::ObjectRezRequested {
+                agent_id,
+                item_id,
+                position,
+                remove_item,
+                ..
+            } => (agent_id, self.rez(agent_id, item_id, position, remove_item).await.map(drop)),
+            _ => return,
+        };
+        if let Err(e) = result {
+            self.alert(agent, &e.to_string()).await;
+        }
+    }
+
+    /// The folder of `folder_type` of `agent`, or its root folder
+    async fn folder_of_type(
+        &self,
+        database: &DatabaseManager,
+        agent: UserId,
+        folder_type: i32,
+    ) -> Result<Uuid, ObjectItemError> {
+        let database_error = |e: mutsea_database::DatabaseError| ObjectItemError::Database(e.to_string());
+        let agent_id = agent.to_string();
+        let folder = match database
+            .get_inventory_folder_of_type(&agent_id, folder_type)
+            .await
+            .map_err(database_error)?
+        {
+            Some(folder) => folder,
+            None => database
+                .get_inventory_folder_of_type(&agent_id, ROOT_FOLDER_TYPE)
+                .await
+                .map_err(database_error)?
+                .ok_or(ObjectItemError::NoFolder)?,
+        };
+        Uuid::parse_str(&folder).map_err(|_| ObjectItemError::NoFolder)
+    }
+
+    /// Keep `prims` of `agent` as one item in `folder_id`
+    async fn store(
+        &self,
+        database: &DatabaseManager,
+        agent: UserId,
+        folder_id: Uuid,
+        prims: &[SceneObject],
+    ) -> Result<ObjectItem, ObjectItemError> {
+        let database_error = |e: mutsea_database::DatabaseError| ObjectItemError::Database(e.to_string());
+        let item = ObjectItem {
+            item_id: Uuid::new_v4(),
+            folder_id,
+            asset_id: Uuid::new_v4(),
+            creator_id: prims[0].creator_id,
+            name: coalesced::item_name(prims),
+            description: prims[0].description.clone(),
+            creation_date: Utc::now().timestamp() as i32,
+        };
+        let mut asset = Asset::new(
+            item.asset_id.to_string(),
+            item.name.clone(),
+            asset_types::OBJECT as i32,
+            coalesced::encode(prims),
+        );
+        asset.creator_id = item.creator_id.to_string();
+        let stored = InventoryItem {
+            inventory_id: item.item_id.to_string(),
+            asset_id: asset.id.clone(),
+            asset_type: asset_types::OBJECT as i32,
+            parent_folder_id: folder_id.to_string(),
+            avatar_id: agent.to_string(),
+            name: item.name.clone(),
+            description: item.description.clone(),
+            next_permissions: OBJECT_PERMISSIONS as i32,
+            current_permissions: OBJECT_PERMISSIONS as i32,
+            inv_type: inventory_types::OBJECT as i32,
+            creator_id: asset.creator_id.clone(),
+            base_permissions: OBJECT_PERMISSIONS as i32,
+            everyone_permissions: 0,
+            creation_date: item.creation_date,
+        };
+        database.insert_asset(&asset).await.map_err(database_error)?;
+        database.insert_inventory_item(&stored).await.map_err(database_error)?;
+        Ok(item)
+    }
+
+    /// Take the `prims` `agent` took out of the region
+    async fn remove(&self, agent: UserId, prims: &[SceneObject]) {
+        let (removed, region_id) = {
+            let mut scene = self.scene.write().await;
+            let removed: Vec<SceneObject> = prims.iter().filter_map(|prim| scene.objects.remove(&prim.id)).collect();
+            (removed, scene.info.region_id)
+        };
+        for prim in &removed {
+            if let Some(scripts) = &self.scripts {
+                scripts.remove_object(prim.id);
+            }
+            self.send_event(region_id, prim.id, ObjectEventData::Destroyed { destroyer_id: agent });
+        }
+
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let local_ids: Vec<u32> = removed.iter().map(|prim| prim.local_id).collect();
+        if let Err(e) = lludp_server.send_kill_objects(&local_ids).await {
+            warn!("Failed to remove {} taken prims from viewers: {}", local_ids.len(), e);
+        }
+    }
+
+    fn send_event(&self, region_id: RegionId, object_id: ObjectId, event_data: ObjectEventData) {
+        // No subscribers is fine
+        let _ = self.events.send(ObjectEvent {
+            event_id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            object_id,
+            region_id,
+            event_data,
+        });
+    }
+
+    /// Tell `agent` of the `item` its DeRezObject `transaction_id` made
+    async fn send_item(&self, agent: UserId, transaction_id: Uuid, item: &ObjectItem) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        if let Err(e) = lludp_server.send_object_item_created(agent, transaction_id, item).await {
+            warn!("Failed to tell agent {} of item {}: {}", agent, item.item_id, e);
+        }
+    }
+
+    fn is_worn(&self, object: ObjectId) -> bool {
+        self.attachments
+            .as_ref()
+            .is_some_and(|attachments| attachments.wearer_of(object).is_some())
+    }
+
+    async fn alert(&self, agent: UserId, message: &str) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        if let Err(e) = lludp_server.send_agent_alert(agent, false, message).await {
+            warn!("Failed to alert agent {}: {}", agent, e);
+        }
+    }
+}
+
+/// Add one DeRezObject packet of `packet_count` to the selection `key`.
+/// Returns the whole selection once its last packet is in.
+fn collect_packet(
+    pending: &mut HashMap<(UserId, Uuid), PendingTake>,
+    key: (UserId, Uuid),
+    packet_count: u8,
+    local_ids: Vec<u32>,
+) -> Option<Vec<u32>> {
+    let take = pending.entry(key).or_default();
+    take.packets += 1;
+    take.local_ids.extend(local_ids);
+    if take.packets < packet_count.max(1) as usize {
+        return None;
+    }
+    pending.remove(&key).map(|take| take.local_ids)
+}
+
+/// Make `prims` from an item new prims of `owner`, numbered from
+/// `next_local_id`, with the bottom of the box around them on `position`
+fn prepare(prims: &mut [SceneObject], owner: UserId, position: Vector3, next_local_id: u32) {
+    let height = coalesced::bounding_box(prims).size().z;
+    coalesced::place(prims, position + Vector3::new(0.0, 0.0, height * 0.5));
+    let now = Utc::now();
+    for (local_id, prim) in (next_local_id..).zip(prims.iter_mut()) {
+        prim.id = ObjectId::new();
+        prim.local_id = local_id;
+        prim.owner_id = owner;
+        prim.velocity = Vector3::ZERO;
+        prim.angular_velocity = Vector3::ZERO;
+        prim.created = now;
+        prim.last_updated = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn prim(position: Vector3, scale: Vector3) -> SceneObject {
+        SceneObject {
+            scale,
+            velocity: Vector3::new(1.0, 0.0, 0.0),
+            ..test_support::prim(1, UserId::new(), position)
+        }
+    }
+
+    #[test]
+    fn test_collect_packets() {
+        let mut pending = HashMap::new();
+        let key = (UserId::new(), Uuid::new_v4());
+        assert_eq!(collect_packet(&mut pending, key, 1, vec![4]), Some(vec![4]));
+        assert!(pending.is_empty());
+
+        assert_eq!(collect_packet(&mut pending, key, 2, vec![7, 9]), None);
+        assert_eq!(collect_packet(&mut pending, key, 2, vec![11]), Some(vec![7, 9, 11]));
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_prepare_rests_prims_on_position() {
+        let owner = UserId::new();
+        let mut prims = vec![
+            prim(Vector3::new(10.0, 10.0, 22.0), Vector3::ONE),
+            prim(Vector3::new(10.0, 12.0, 24.0), Vector3::new(1.0, 1.0, 3.0)),
+        ];
+        let ids: Vec<ObjectId> = prims.iter().map(|prim| prim.id).collect();
+        prepare(&mut prims, owner, Vector3::new(128.0, 128.0, 20.0), 40);
+
+        // The box ran from 21.5 to 25.5 high, so it now runs from 20 to 24
+        assert_eq!(prims[0].position, Vector3::new(128.0, 127.0, 20.5));
+        assert_eq!(prims[1].position, Vector3::new(128.0, 129.0, 22.5));
+        assert_eq!(prims.iter().map(|prim| prim.local_id).collect::<Vec<_>>(), vec![40, 41]);
+        assert!(prims.iter().all(|prim| prim.owner_id == owner && prim.velocity == Vector3::ZERO));
+        assert!(prims.iter().zip(ids).all(|(prim, id)| prim.id != id));
+    }
+}
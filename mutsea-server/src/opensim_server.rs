@@ -1,25 +1,133 @@
 //! OpenSim-compatible server implementation
 
 use axum::{
-    extract::{Path, State},
-    http::{HeaderMap, StatusCode},
-    response::{Html, Response},
-    routing::{get, post},
+    extract::{ws::WebSocketUpgrade, ConnectInfo, Form, Path, Query, RawQuery, State},
+    http::{HeaderMap, Method, StatusCode},
+    middleware,
+    response::{Html, IntoResponse, Json, Response},
+    Extension,
+    routing::{delete, get, patch, post},
     Router,
-    body::Body,
+    body::{Body, Bytes},
 };
-use mutsea_core::{Service, ServiceHealth, ServiceStatus, MutseaResult, config::MutseaConfig};
-use mutsea_protocol::opensim::login::{ParsedLoginRequest, OpenSimLoginService};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use mutsea_core::{Service, ServiceHealth, ServiceStatus, MutseaError, MutseaResult, ErrorClassification, GenerationRequest, QuestObjective, UserId, config::MutseaConfig};
+use mutsea_core::circuit_breaker::CircuitBreakerRegistry;
+use mutsea_database::ai_feedback::DecisionFeedback;
+use mutsea_database::chat_log::ChatLogQuery;
+use mutsea_database::query_stats::QuerySort;
+use mutsea_database::models::{QuestDefinition, QuestOrigin};
+use mutsea_database::DatabaseManager;
+use mutsea_network::plugin::PluginHost;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::appearance::{encode_update_appearance_reply, parse_update_appearance};
+use mutsea_protocol::api_keys::{ApiKeyRecord, ApiKeyStore, ApiScope};
+use mutsea_protocol::directory::Event;
+use mutsea_protocol::event_queue::{encode_events, EventQueuePoll};
+use mutsea_protocol::inventory::{encode_descendents, encode_items, parse_fetch_descendents, parse_fetch_items};
+use mutsea_protocol::login::{ParsedLoginRequest, OpenSimLoginService};
+use mutsea_protocol::snapshot::{encode_upload_complete, encode_upload_error, encode_uploader, Postcard, SnapshotUpload};
+use mutsea_protocol::names::{
+    encode_display_names, encode_set_display_name_reply, parse_display_name_ids, AvatarName, SetDisplayName,
+};
+use serde::Deserialize;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::TcpListener;
 use tower::ServiceBuilder;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use tracing::{info, error, debug};
 
+use crate::attachments::{AttachmentError, Attachments};
+use crate::auth::{require_auth, require_scope, AuthError, AuthService, AuthenticatedUser, Credentials, ScopeGuard};
+use crate::dialogs::{DialogError, DialogService};
+use crate::chat_log::ChatLogger;
+use crate::directory::{Directory, DirectoryError, EventListing};
+use crate::land::{LandError, LandSales};
+use crate::limits::{Limit, LimitError, Limits, Override};
+use crate::economy::{Economy, EconomyError, SaleInfo};
+use crate::environment::RegionEnvironment;
+use crate::event_queue::{EventQueue, POLL_TIMEOUT};
+use crate::gltf::{ExportError, GltfExporter};
+use crate::health::{HealthRegistry, HealthSource};
+use crate::idempotency::{idempotent, IdempotencyStore};
+use crate::meshes::{MeshError, MeshShapes};
+use crate::grid_users::GridUsers;
+use crate::names::{NameChange, NameError, Names};
+use crate::npc_movement::{NavError, NpcMovement};
+use crate::prim_streaming::PrimStreaming;
+use crate::profiler::TickProfiler;
+use crate::bandwidth::{Bandwidth, UsageError};
+use crate::login_queue::LoginQueue;
+use crate::maintenance::Maintenance;
+use crate::webhooks::{Webhooks, EVENT_TEST};
+use crate::quests::{OfferError, QuestEngine};
+use crate::raycast::{RaycastError, RegionRaycast};
+use crate::registration::{LogMailer, RegistrationError, RegistrationOutcome, RegistrationRequest, RegistrationService};
+use crate::console::RegionConsole;
+use crate::remote_data::{self, RemoteData, RemoteDataError};
+use crate::replay::{ReplayError, ReplayRecorder};
+use crate::script_http::ScriptHttp;
+use crate::scene_control::{NewPrim, PrimMove, SceneChat, SceneControl, SceneControlError};
+use crate::scene_query::{SceneQuery, SceneQueryError, SceneSearch};
+use crate::scripts::{ScriptError, ScriptSandbox};
+use crate::animations::{AnimationError, Animations};
+use crate::bakes::{AvatarBakes, BakeError};
+use crate::inventory::{self, Inventory, InventoryError};
+use crate::snapshots::{SnapshotError, Snapshots};
+use crate::sounds::{SoundError, Sounds};
+use crate::vehicles::{VehicleError, Vehicles};
+use crate::visibility::{ObjectVisibility, VisibilityError};
+use crate::web::{html_escape, PageContext, WebPages};
+use crate::worldgen::WorldGenerator;
+
 /// OpenSim-compatible server
 pub struct OpenSimServer {
     config: MutseaConfig,
     login_service: Arc<OpenSimLoginService>,
+    circuit_breakers: Arc<CircuitBreakerRegistry>,
+    health: Arc<HealthRegistry>,
+    event_queue: EventQueue,
+    database: Option<Arc<DatabaseManager>>,
+    environment: Option<Arc<RegionEnvironment>>,
+    world_generator: Option<Arc<WorldGenerator>>,
+    quests: Option<Arc<QuestEngine>>,
+    dialogs: Option<Arc<DialogService>>,
+    npc_movement: Option<NpcMovement>,
+    vehicles: Option<Vehicles>,
+    raycast: Option<RegionRaycast>,
+    meshes: Option<MeshShapes>,
+    economy: Option<Economy>,
+    directory: Option<Directory>,
+    chat_log: Option<ChatLogger>,
+    land: Option<LandSales>,
+    sounds: Option<Sounds>,
+    snapshots: Option<Snapshots>,
+    animations: Option<Animations>,
+    inventory: Option<Inventory>,
+    bakes: Option<AvatarBakes>,
+    scene_control: Option<SceneControl>,
+    scene_query: Option<SceneQuery>,
+    names: Option<Names>,
+    grid_users: Option<GridUsers>,
+    attachments: Option<Attachments>,
+    limits: Option<Limits>,
+    visibility: Option<ObjectVisibility>,
+    prim_streaming: Option<PrimStreaming>,
+    bandwidth: Option<Bandwidth>,
+    login_queue: Option<LoginQueue>,
+    maintenance: Option<Maintenance>,
+    plugins: Option<Arc<PluginHost>>,
+    webhooks: Option<Webhooks>,
+    lludp_server: Option<LLUDPServer>,
+    profiler: Option<Arc<TickProfiler>>,
+    gltf_exporter: Option<GltfExporter>,
+    replay: Option<Arc<ReplayRecorder>>,
+    scripts: Option<ScriptSandbox>,
+    script_http: Option<Arc<ScriptHttp>>,
+    remote_data: Option<Arc<RemoteData>>,
+    console: Option<Arc<RegionConsole>>,
+    /// Routes served by other components, such as transports
+    extra_routes: Vec<Router>,
     running: Arc<std::sync::atomic::AtomicBool>,
 }
 
@@ -28,6 +136,53 @@ pub struct OpenSimServer {
 pub struct OpenSimServerState {
     pub config: MutseaConfig,
     pub login_service: Arc<OpenSimLoginService>,
+    pub pages: Arc<WebPages>,
+    pub registration: Arc<RegistrationService>,
+    pub auth: Arc<AuthService>,
+    pub api_keys: Arc<ApiKeyStore>,
+    pub idempotency: Arc<IdempotencyStore>,
+    pub circuit_breakers: Arc<CircuitBreakerRegistry>,
+    pub health: Arc<HealthRegistry>,
+    pub event_queue: EventQueue,
+    pub database: Option<Arc<DatabaseManager>>,
+    pub environment: Option<Arc<RegionEnvironment>>,
+    pub world_generator: Option<Arc<WorldGenerator>>,
+    pub quests: Option<Arc<QuestEngine>>,
+    pub dialogs: Option<Arc<DialogService>>,
+    pub npc_movement: Option<NpcMovement>,
+    pub vehicles: Option<Vehicles>,
+    pub raycast: Option<RegionRaycast>,
+    pub meshes: Option<MeshShapes>,
+    pub economy: Option<Economy>,
+    pub directory: Option<Directory>,
+    pub chat_log: Option<ChatLogger>,
+    pub land: Option<LandSales>,
+    pub sounds: Option<Sounds>,
+    pub snapshots: Option<Snapshots>,
+    pub animations: Option<Animations>,
+    pub inventory: Option<Inventory>,
+    pub bakes: Option<AvatarBakes>,
+    pub scene_control: Option<SceneControl>,
+    pub scene_query: Option<SceneQuery>,
+    pub names: Option<Names>,
+    pub grid_users: Option<GridUsers>,
+    pub attachments: Option<Attachments>,
+    pub limits: Option<Limits>,
+    pub visibility: Option<ObjectVisibility>,
+    pub prim_streaming: Option<PrimStreaming>,
+    pub bandwidth: Option<Bandwidth>,
+    pub login_queue: Option<LoginQueue>,
+    pub maintenance: Option<Maintenance>,
+    pub plugins: Option<Arc<PluginHost>>,
+    pub webhooks: Option<Webhooks>,
+    pub lludp_server: Option<LLUDPServer>,
+    pub profiler: Option<Arc<TickProfiler>>,
+    pub gltf_exporter: Option<GltfExporter>,
+    pub replay: Option<Arc<ReplayRecorder>>,
+    pub scripts: Option<ScriptSandbox>,
+    pub script_http: Option<Arc<ScriptHttp>>,
+    pub remote_data: Option<Arc<RemoteData>>,
+    pub console: Option<Arc<RegionConsole>>,
 }
 
 impl OpenSimServer {
@@ -36,30 +191,529 @@ impl OpenSimServer {
         Self {
             config: config.clone(),
             login_service: Arc::new(OpenSimLoginService::new()),
+            circuit_breakers: Arc::new(CircuitBreakerRegistry::new(config.circuit_breaker.clone())),
+            health: Arc::new(HealthRegistry::new()),
+            event_queue: EventQueue::new(),
+            database: None,
+            environment: None,
+            world_generator: None,
+            quests: None,
+            dialogs: None,
+            npc_movement: None,
+            vehicles: None,
+            raycast: None,
+            meshes: None,
+            economy: None,
+            directory: None,
+            chat_log: None,
+            land: None,
+            sounds: None,
+            snapshots: None,
+            animations: None,
+            inventory: None,
+            bakes: None,
+            scene_control: None,
+            scene_query: None,
+            names: None,
+            grid_users: None,
+            attachments: None,
+            limits: None,
+            visibility: None,
+            prim_streaming: None,
+            bandwidth: None,
+            login_queue: None,
+            maintenance: None,
+            plugins: None,
+            webhooks: None,
+            lludp_server: None,
+            profiler: None,
+            gltf_exporter: None,
+            replay: None,
+            scripts: None,
+            script_http: None,
+            remote_data: None,
+            console: None,
+            extra_routes: Vec::new(),
             running: Arc::new(std::sync::atomic::AtomicBool::new(false)),
         }
     }
 
+    /// Share a login service with the LLUDP server
+    pub fn set_login_service(&mut self, login_service: Arc<OpenSimLoginService>) {
+        self.login_service = login_service;
+    }
+
+    /// Share the process's circuit breakers, reported in health checks
+    pub fn set_circuit_breakers(&mut self, circuit_breakers: Arc<CircuitBreakerRegistry>) {
+        self.circuit_breakers = circuit_breakers;
+    }
+
+    /// Share the subsystem checks served on `/health/ready`
+    pub fn set_health(&mut self, health: Arc<HealthRegistry>) {
+        self.health = health;
+    }
+
+    /// Share the event queues viewers poll through EventQueueGet
+    pub fn set_event_queue(&mut self, event_queue: EventQueue) {
+        self.event_queue = event_queue;
+    }
+
+    /// Database whose query statistics the admin API reports
+    pub fn set_database(&mut self, database: Arc<DatabaseManager>) {
+        self.database = Some(database);
+    }
+
+    /// Region environment served by the EnvironmentSettings capability
+    pub fn set_environment(&mut self, environment: Arc<RegionEnvironment>) {
+        self.environment = Some(environment);
+    }
+
+    /// World generator driven by the admin API
+    pub fn set_world_generator(&mut self, world_generator: Arc<WorldGenerator>) {
+        self.world_generator = Some(world_generator);
+    }
+
+    /// Quest engine managed through the admin API
+    pub fn set_quests(&mut self, quests: Arc<QuestEngine>) {
+        self.quests = Some(quests);
+    }
+
+    /// Dialogs and alerts sent through the admin API
+    pub fn set_dialogs(&mut self, dialogs: Arc<DialogService>) {
+        self.dialogs = Some(dialogs);
+    }
+
+    /// NPCs walked around through the admin API
+    pub fn set_npc_movement(&mut self, npc_movement: NpcMovement) {
+        self.npc_movement = Some(npc_movement);
+    }
+
+    /// Vehicles set up and inspected through the admin API
+    pub fn set_vehicles(&mut self, vehicles: Vehicles) {
+        self.vehicles = Some(vehicles);
+    }
+
+    /// Rays cast through the admin API
+    pub fn set_raycast(&mut self, raycast: RegionRaycast) {
+        self.raycast = Some(raycast);
+    }
+
+    /// Mesh prims set up through the admin API
+    pub fn set_meshes(&mut self, meshes: MeshShapes) {
+        self.meshes = Some(meshes);
+    }
+
+    /// Balances and prim sales managed through the admin API
+    pub fn set_economy(&mut self, economy: Economy) {
+        self.economy = Some(economy);
+    }
+
+    /// Events listed and removed through the admin API
+    pub fn set_directory(&mut self, directory: Directory) {
+        self.directory = Some(directory);
+    }
+
+    /// Logged chat searched through the admin API
+    pub fn set_chat_log(&mut self, chat_log: ChatLogger) {
+        self.chat_log = Some(chat_log);
+    }
+
+    /// Land auctions started and bid on through the admin API
+    pub fn set_land(&mut self, land: LandSales) {
+        self.land = Some(land);
+    }
+
+    /// Limits overridden through the admin API
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = Some(limits);
+    }
+
+    /// Sounds uploaded and played on prims through the admin API
+    pub fn set_sounds(&mut self, sounds: Sounds) {
+        self.sounds = Some(sounds);
+    }
+
+    /// Photos uploaded through the snapshot and postcard caps
+    pub fn set_snapshots(&mut self, snapshots: Snapshots) {
+        self.snapshots = Some(snapshots);
+    }
+
+    /// Animations uploaded through NewFileAgentInventory
+    pub fn set_animations(&mut self, animations: Animations) {
+        self.animations = Some(animations);
+    }
+
+    /// Agents' inventories fetched through the inventory caps
+    pub fn set_inventory(&mut self, inventory: Inventory) {
+        self.inventory = Some(inventory);
+    }
+
+    /// Avatars baked through UpdateAvatarAppearance and served from
+    /// AgentTextures
+    pub fn set_bakes(&mut self, bakes: AvatarBakes) {
+        self.bakes = Some(bakes);
+    }
+
+    /// Prims and chat driven by API clients through `/api/regions`
+    pub fn set_scene_control(&mut self, scene_control: SceneControl) {
+        self.scene_control = Some(scene_control);
+    }
+
+    /// Objects looked up by API clients through `/api/regions`
+    pub fn set_scene_query(&mut self, scene_query: SceneQuery) {
+        self.scene_query = Some(scene_query);
+    }
+
+    /// Names looked up and display names changed through caps and the
+    /// admin API
+    pub fn set_names(&mut self, names: Names) {
+        self.names = Some(names);
+    }
+
+    /// Grid users loaded from the database as they log in
+    pub fn set_grid_users(&mut self, grid_users: GridUsers) {
+        self.grid_users = Some(grid_users);
+    }
+
+    /// Attachments listed and worn through the admin API
+    pub fn set_attachments(&mut self, attachments: Attachments) {
+        self.attachments = Some(attachments);
+    }
+
+    /// Prims hidden and shown through the admin API
+    pub fn set_visibility(&mut self, visibility: ObjectVisibility) {
+        self.visibility = Some(visibility);
+    }
+
+    /// Prim streams to arriving agents reported through the admin API
+    pub fn set_prim_streaming(&mut self, prim_streaming: PrimStreaming) {
+        self.prim_streaming = Some(prim_streaming);
+    }
+
+    /// Bandwidth usage reported through the admin API
+    pub fn set_bandwidth(&mut self, bandwidth: Bandwidth) {
+        self.bandwidth = Some(bandwidth);
+    }
+
+    /// Queue logins while too many are on their way in
+    pub fn set_login_queue(&mut self, login_queue: LoginQueue) {
+        self.login_queue = Some(login_queue);
+    }
+
+    /// Maintenance mode, checked at login and toggled through the admin API
+    pub fn set_maintenance(&mut self, maintenance: Maintenance) {
+        self.maintenance = Some(maintenance);
+    }
+
+    /// Plugins listed through the admin API
+    pub fn set_plugins(&mut self, plugins: Arc<PluginHost>) {
+        self.plugins = Some(plugins);
+    }
+
+    /// Webhook deliveries listed and tested through the admin API
+    pub fn set_webhooks(&mut self, webhooks: Webhooks) {
+        self.webhooks = Some(webhooks);
+    }
+
+    /// Circuits reported and kicked through the admin API
+    pub fn set_lludp(&mut self, lludp_server: LLUDPServer) {
+        self.lludp_server = Some(lludp_server);
+    }
+
+    /// Frame timings reported through the admin API
+    pub fn set_profiler(&mut self, profiler: Arc<TickProfiler>) {
+        self.profiler = Some(profiler);
+    }
+
+    /// Regions exported as glTF through the admin API
+    pub fn set_gltf_exporter(&mut self, gltf_exporter: GltfExporter) {
+        self.gltf_exporter = Some(gltf_exporter);
+    }
+
+    /// Simulation recording dumped through the admin API
+    pub fn set_replay(&mut self, replay: Arc<ReplayRecorder>) {
+        self.replay = Some(replay);
+    }
+
+    /// Script sandbox profiled through the admin API
+    pub fn set_scripts(&mut self, scripts: ScriptSandbox) {
+        self.scripts = Some(scripts);
+    }
+
+    /// Serve script URLs from the caps server
+    pub fn set_script_http(&mut self, script_http: Arc<ScriptHttp>) {
+        self.script_http = Some(script_http);
+    }
+
+    /// Answer llRemoteData XML-RPC calls alongside logins
+    pub fn set_remote_data(&mut self, remote_data: Arc<RemoteData>) {
+        self.remote_data = Some(remote_data);
+    }
+
+    /// Serve the region console over the admin WebSocket
+    pub fn set_console(&mut self, console: Arc<RegionConsole>) {
+        self.console = Some(console);
+    }
+
+    /// Serve `routes` alongside the server's own
+    pub fn merge_routes(&mut self, routes: Router) {
+        self.extra_routes.push(routes);
+    }
+
     /// Start the server
     pub async fn start(&self) -> MutseaResult<()> {
         self.running.store(true, std::sync::atomic::Ordering::SeqCst);
+        self.health.register(
+            "http",
+            Arc::new(HttpHealth {
+                running: Arc::clone(&self.running),
+                circuit_breakers: Arc::clone(&self.circuit_breakers),
+            }),
+        );
+
+        let state = OpenSimServerState {
+            config: self.config.clone(),
+            login_service: Arc::clone(&self.login_service),
+            pages: Arc::new(WebPages::load(&self.config)?),
+            registration: Arc::new(RegistrationService::new(
+                self.config.registration.clone(),
+                Arc::clone(&self.login_service),
+                Arc::new(LogMailer::new(format!(
+                    "{}register/verify",
+                    self.config.network.http.public_url()
+                ))),
+            )),
+            auth: Arc::new(AuthService::from_config(&self.config, Arc::clone(&self.login_service))),
+            api_keys: Arc::new(
+                ApiKeyStore::open(&self.config.security.api_keys_file)
+                    .map_err(|e| mutsea_core::MutseaError::InvalidConfiguration(e.to_string()))?,
+            ),
+            idempotency: Arc::new(
+                IdempotencyStore::open(
+                    &self.config.security.idempotency_file,
+                    chrono::Duration::seconds(self.config.security.idempotency_ttl_secs as i64),
+                )
+                .map_err(|e| mutsea_core::MutseaError::InvalidConfiguration(e.to_string()))?,
+            ),
+            circuit_breakers: Arc::clone(&self.circuit_breakers),
+            health: Arc::clone(&self.health),
+            event_queue: self.event_queue.clone(),
+            database: self.database.clone(),
+            environment: self.environment.clone(),
+            world_generator: self.world_generator.clone(),
+            quests: self.quests.clone(),
+            dialogs: self.dialogs.clone(),
+            npc_movement: self.npc_movement.clone(),
+            vehicles: self.vehicles.clone(),
+            raycast: self.raycast.clone(),
+            meshes: self.meshes.clone(),
+            economy: self.economy.clone(),
+            directory: self.directory.clone(),
+            chat_log: self.chat_log.clone(),
+            land: self.land.clone(),
+            sounds: self.sounds.clone(),
+            snapshots: self.snapshots.clone(),
+            animations: self.animations.clone(),
+            inventory: self.inventory.clone(),
+            bakes: self.bakes.clone(),
+            scene_control: self.scene_control.clone(),
+            scene_query: self.scene_query.clone(),
+            names: self.names.clone(),
+            grid_users: self.grid_users.clone(),
+            attachments: self.attachments.clone(),
+            limits: self.limits.clone(),
+            visibility: self.visibility.clone(),
+            prim_streaming: self.prim_streaming.clone(),
+            bandwidth: self.bandwidth.clone(),
+            login_queue: self.login_queue.clone(),
+            maintenance: self.maintenance.clone(),
+            plugins: self.plugins.clone(),
+            webhooks: self.webhooks.clone(),
+            lludp_server: self.lludp_server.clone(),
+            profiler: self.profiler.clone(),
+            gltf_exporter: self.gltf_exporter.clone(),
+            replay: self.replay.clone(),
+            scripts: self.scripts.clone(),
+            script_http: self.script_http.clone(),
+            remote_data: self.remote_data.clone(),
+            console: self.console.clone(),
+        };
+
+        // Token-authenticated API routes; legacy viewers keep using the
+        // XML-RPC password login on `/`
+        let protected = Router::new()
+            .route("/api/me", get(me_handler))
+            .route_layer(middleware::from_fn_with_state(Arc::clone(&state.auth), require_auth));
+
+        // Admin and analytics endpoints, each guarded by an API key scope.
+        // Mutations sent with an Idempotency-Key run once per key and API key.
+        let scoped = |router: Router<OpenSimServerState>, scope: ApiScope| {
+            router
+                .route_layer(middleware::from_fn_with_state(Arc::clone(&state.idempotency), idempotent))
+                .route_layer(middleware::from_fn_with_state(
+                    ScopeGuard::new(Arc::clone(&state.api_keys), scope),
+                    require_scope,
+                ))
+        };
+        let admin = Router::new()
+            .merge(scoped(
+                Router::new()
+                    .route("/api/admin/stats", get(admin_stats_handler))
+                    .route("/api/admin/database/queries", get(admin_top_queries_handler))
+                    .route("/api/admin/database/advice", get(admin_index_advice_handler))
+                    .route("/api/admin/scripts/top", get(admin_top_scripts_handler))
+                    .route("/api/admin/usage", get(admin_usage_handler))
+                    .route("/api/admin/usage/:user", get(admin_user_usage_handler)),
+                ApiScope::ReadStats,
+            ))
+            .merge(scoped(Router::new().route("/api/admin/users", post(admin_create_user_handler)), ApiScope::WriteUsers))
+            .merge(scoped(
+                Router::new()
+                    .route("/api/admin/regions", get(admin_regions_handler))
+                    .route("/api/admin/regions/:id/gltf", get(admin_export_gltf_handler))
+                    .route("/api/admin/console", get(admin_console_handler))
+                    .route("/api/admin/replay", post(admin_replay_dump_handler))
+                    .route("/api/admin/worldgen", get(admin_worldgen_batches_handler).post(admin_worldgen_handler))
+                    .route("/api/admin/worldgen/:id/undo", post(admin_worldgen_undo_handler))
+                    .route("/api/admin/quests", get(admin_quests_handler).post(admin_define_quest_handler))
+                    .route("/api/admin/quests/generate", post(admin_generate_quest_handler))
+                    .route("/api/admin/quests/:id/offer", post(admin_offer_quest_handler))
+                    .route("/api/admin/players/:id/quests", get(admin_player_quests_handler))
+                    .route("/api/admin/alerts", post(admin_alert_handler))
+                    .route("/api/admin/dialogs", post(admin_dialog_handler))
+                    .route("/api/admin/npcs", get(admin_npcs_handler))
+                    .route("/api/admin/npcs/:id/goto", post(admin_npc_goto_handler))
+                    .route("/api/admin/vehicles", get(admin_vehicles_handler))
+                    .route("/api/admin/vehicles/:id", post(admin_set_vehicle_handler))
+                    .route("/api/admin/raycast", post(admin_raycast_handler))
+                    .route("/api/admin/objects/:id/scripts", post(admin_load_script_handler))
+                    .route("/api/admin/scripts/:id", delete(admin_remove_script_handler))
+                    .route("/api/admin/scripts/:id/snapshot", get(admin_script_snapshot_handler))
+                    .route(
+                        "/api/admin/objects/:id/mesh",
+                        post(admin_set_mesh_handler).delete(admin_remove_mesh_handler),
+                    )
+                    .route("/api/admin/objects/:id/sale", get(admin_sale_handler).post(admin_set_sale_handler))
+                    .route("/api/admin/economy/:id", get(admin_balance_handler))
+                    .route("/api/admin/economy/:id/grant", post(admin_grant_handler))
+                    .route("/api/admin/events", get(admin_events_handler).post(admin_create_event_handler))
+                    .route("/api/admin/events/:id", delete(admin_delete_event_handler))
+                    .route("/api/admin/chat", get(admin_chat_log_handler))
+                    .route(
+                        "/api/admin/land/auctions",
+                        get(admin_land_auctions_handler).post(admin_start_land_auction_handler),
+                    )
+                    .route("/api/admin/land/auctions/:id", get(admin_land_auction_handler))
+                    .route("/api/admin/land/auctions/:id/bids", post(admin_land_bid_handler))
+                    .route("/api/admin/limits", get(admin_limits_handler).post(admin_override_limit_handler))
+                    .route("/api/admin/sounds", post(admin_upload_sound_handler))
+                    .route(
+                        "/api/admin/objects/:id/sound",
+                        post(admin_play_sound_handler).delete(admin_stop_sound_handler),
+                    )
+                    .route(
+                        "/api/admin/avatars/:id/display_name",
+                        get(admin_display_name_handler).post(admin_set_display_name_handler),
+                    )
+                    .route(
+                        "/api/admin/avatars/:id/display_name/history",
+                        get(admin_display_name_history_handler),
+                    )
+                    .route(
+                        "/api/admin/avatars/:id/attachments",
+                        get(admin_attachments_handler).post(admin_attach_handler),
+                    )
+                    .route("/api/admin/avatars/:id/attachments/:object", delete(admin_detach_handler))
+                    .route("/api/admin/avatars/:id/kick", post(admin_kick_handler))
+                    .route(
+                        "/api/admin/objects/:id/visibility",
+                        get(admin_visibility_handler).post(admin_set_visibility_handler),
+                    )
+                    .route("/api/admin/prim_streaming", get(admin_prim_streaming_handler))
+                    .route(
+                        "/api/admin/maintenance",
+                        get(admin_maintenance_handler)
+                            .post(admin_enter_maintenance_handler)
+                            .delete(admin_exit_maintenance_handler),
+                    )
+                    .route("/api/admin/plugins", get(admin_plugins_handler))
+                    .route("/api/admin/webhooks/deliveries", get(admin_webhook_deliveries_handler))
+                    .route("/api/admin/webhooks/test", post(admin_test_webhooks_handler)),
+                ApiScope::AdminRegions,
+            ))
+            .merge(scoped(
+                Router::new().route("/api/admin/ai/decisions/:id/feedback", post(admin_decision_feedback_handler)),
+                ApiScope::WriteAiFeedback,
+            ))
+            .merge(scoped(
+                Router::new()
+                    .route("/api/regions/:id/objects", post(scene_create_prim_handler))
+                    .route(
+                        "/api/regions/:id/objects/:object",
+                        patch(scene_move_prim_handler).delete(scene_delete_prim_handler),
+                    )
+                    .route("/api/regions/:id/chat", post(scene_chat_handler)),
+                ApiScope::WriteScene,
+            ))
+            .merge(scoped(
+                Router::new().route("/api/regions/:id/objects", get(scene_find_objects_handler)),
+                ApiScope::ReadScene,
+            ))
+            .merge(scoped(
+                Router::new()
+                    .route("/api/admin/keys", get(admin_list_keys_handler).post(admin_issue_key_handler))
+                    .route("/api/admin/keys/:id", delete(admin_revoke_key_handler)),
+                ApiScope::AdminKeys,
+            ));
+
+        // The viewer posts XML-RPC logins to the login URI, which is `/`
+        let app = Router::new()
+            .route("/", get(home_handler).post(login_handler))
+            .route("/splash", get(splash_handler))
+            .route("/register", get(register_form_handler).post(register_form_submit_handler))
+            .route("/register/verify", get(register_verify_handler))
+            .route("/api/register", post(register_api_handler))
+            .route("/api/register/verify", post(register_verify_api_handler))
+            .route("/api/auth/password", post(auth_password_handler))
+            .route("/api/auth/oidc", post(auth_oidc_handler))
+            .merge(protected)
+            .merge(admin)
+            .route("/get_grid_info", get(grid_info_handler))
+            .route("/caps/:cap_id/", post(seed_caps_handler))
+            .route(
+                "/caps/:cap_id/*path",
+                get(caps_handler).post(caps_handler).put(caps_handler).delete(caps_handler),
+            )
+            .route("/health", get(health_handler))
+            .route("/health/live", get(health_live_handler))
+            .route("/health/ready", get(health_ready_handler))
+            .with_state(state);
+        let app = self
+            .extra_routes
+            .iter()
+            .cloned()
+            .fold(app, |app, routes| app.merge(routes))
+            .layer(
+                ServiceBuilder::new()
+                    .layer(TraceLayer::new_for_http())
+                    .layer(CorsLayer::permissive())
+            );
 
-        let bind_addr = format!("{}:{}", self.config.network.http.bind_address, http_port);
-        let listener = TcpListener::bind(&bind_addr).await
+        let http = &self.config.network.http;
+        let bind_addr = mutsea_core::net::socket_addr(&http.bind_address, http.port)
+            .map_err(|e| mutsea_core::MutseaError::InvalidConfiguration(format!("network.http.bind_address: {}", e)))?;
+        let listener = mutsea_network::dual_stack::bind_tcp(bind_addr, http.dual_stack)
             .map_err(|e| mutsea_core::MutseaError::Network(e.to_string()))?;
 
         info!("OpenSim-compatible server listening on {}", bind_addr);
-        if standalone_mode {
-            info!("Running in STANDALONE mode - connect with viewer to: http://{}:{}/", 
-                  self.config.network.http.bind_address, http_port);
-        } else {
-            info!("Running in GRID mode - login URI: http://{}:{}/", 
-                  self.config.network.http.bind_address, http_port);
-        }
+        info!("Login URI: {}", self.config.opensim.login_uri);
 
         let running = Arc::clone(&self.running);
         tokio::spawn(async move {
-            if let Err(e) = axum::serve(listener, app).await {
+            if let Err(e) = axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            ).await {
                 error!("OpenSim server error: {}", e);
             }
         });
@@ -96,116 +750,226 @@ impl Service for OpenSimServer {
     }
 
     async fn health_check(&self) -> ServiceHealth {
-        let status = if self.is_running() {
-            ServiceStatus::Healthy
-        } else {
-            ServiceStatus::Unhealthy
-        };
+        http_health(self.is_running(), &self.circuit_breakers)
+    }
+}
 
-        let mut metrics = std::collections::HashMap::new();
-        metrics.insert("is_opensim_compatible".to_string(), 1.0);
+/// HTTP server health for the readiness registry
+struct HttpHealth {
+    running: Arc<std::sync::atomic::AtomicBool>,
+    circuit_breakers: Arc<CircuitBreakerRegistry>,
+}
 
-        ServiceHealth {
-            status,
-            message: "OpenSim-compatible server".to_string(),
-            metrics,
-        }
+#[async_trait::async_trait]
+impl HealthSource for HttpHealth {
+    async fn health(&self) -> ServiceHealth {
+        http_health(self.running.load(std::sync::atomic::Ordering::SeqCst), &self.circuit_breakers)
+    }
+}
+
+/// Unhealthy when stopped, degraded while any circuit breaker is open
+fn http_health(running: bool, circuit_breakers: &CircuitBreakerRegistry) -> ServiceHealth {
+    let breakers = circuit_breakers.statuses();
+    let open: Vec<&str> = breakers
+        .iter()
+        .filter(|b| b.retry_after_secs.is_some())
+        .map(|b| b.name.as_str())
+        .collect();
+
+    let status = if !running {
+        ServiceStatus::Unhealthy
+    } else if !open.is_empty() {
+        ServiceStatus::Degraded
+    } else {
+        ServiceStatus::Healthy
+    };
+
+    let mut metrics = std::collections::HashMap::new();
+    metrics.insert("is_opensim_compatible".to_string(), 1.0);
+    metrics.insert("circuit_breakers_open".to_string(), open.len() as f64);
+
+    ServiceHealth {
+        status,
+        message: if open.is_empty() {
+            "OpenSim-compatible server".to_string()
+        } else {
+            format!("OpenSim-compatible server, degraded: {} unavailable", open.join(", "))
+        },
+        metrics,
     }
 }
 
 /// Home page handler
 async fn home_handler(State(state): State<OpenSimServerState>) -> Html<String> {
-    let grid_name = &state.config.opensim.grid_name;
-    let html = format!(r#"<!DOCTYPE html>
+    let context = PageContext::new(&state.config, state.login_service.online_count());
+    Html(state.pages.render_home(&context))
+}
+
+/// Viewer login splash page handler (the grid info `welcome` page)
+async fn splash_handler(State(state): State<OpenSimServerState>) -> Html<String> {
+    let context = PageContext::new(&state.config, state.login_service.online_count());
+    Html(state.pages.render_splash(&context))
+}
+
+/// Verification token query/body
+#[derive(Debug, Deserialize)]
+struct VerifyParams {
+    token: String,
+}
+
+/// Registration form page
+async fn register_form_handler(State(state): State<OpenSimServerState>) -> Html<String> {
+    Html(render_register_page(&state, None, None))
+}
+
+/// Registration form submission
+async fn register_form_submit_handler(
+    State(state): State<OpenSimServerState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Form(request): Form<RegistrationRequest>,
+) -> (StatusCode, Html<String>) {
+    match state.registration.register(request, addr.ip()).await {
+        Ok(RegistrationOutcome::Created { .. }) => (
+            StatusCode::OK,
+            Html(render_register_page(&state, Some("Your account is ready. Log in with your viewer."), None)),
+        ),
+        Ok(RegistrationOutcome::VerificationSent { email }) => (
+            StatusCode::OK,
+            Html(render_register_page(&state, Some(&format!("Check {} for a verification link.", email)), None)),
+        ),
+        Err(e) => (registration_status(&e), Html(render_register_page(&state, None, Some(&e.to_string())))),
+    }
+}
+
+/// Email verification link target
+async fn register_verify_handler(
+    State(state): State<OpenSimServerState>,
+    Query(params): Query<VerifyParams>,
+) -> (StatusCode, Html<String>) {
+    match state.registration.verify(&params.token) {
+        Ok(_) => (
+            StatusCode::OK,
+            Html(render_register_page(&state, Some("Email verified. Your account is ready."), None)),
+        ),
+        Err(e) => (registration_status(&e), Html(render_register_page(&state, None, Some(&e.to_string())))),
+    }
+}
+
+/// JSON registration API
+async fn register_api_handler(
+    State(state): State<OpenSimServerState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(request): Json<RegistrationRequest>,
+) -> Response {
+    match state.registration.register(request, addr.ip()).await {
+        Ok(outcome) => (StatusCode::OK, Json(serde_json::to_value(outcome).unwrap_or_default())).into_response(),
+        Err(e) => registration_error_response(&e),
+    }
+}
+
+/// JSON verification API
+async fn register_verify_api_handler(
+    State(state): State<OpenSimServerState>,
+    Json(params): Json<VerifyParams>,
+) -> Response {
+    match state.registration.verify(&params.token) {
+        Ok(user_id) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "status": "created", "user_id": user_id.to_string() })),
+        ).into_response(),
+        Err(e) => registration_error_response(&e),
+    }
+}
+
+fn registration_status(error: &RegistrationError) -> StatusCode {
+    match error {
+        RegistrationError::Disabled => StatusCode::FORBIDDEN,
+        RegistrationError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+        RegistrationError::NameTaken(_) => StatusCode::CONFLICT,
+        RegistrationError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        RegistrationError::Invalid(_) | RegistrationError::InviteRequired | RegistrationError::InvalidToken => {
+            StatusCode::BAD_REQUEST
+        }
+    }
+}
+
+fn registration_error_response(error: &RegistrationError) -> Response {
+    (
+        registration_status(error),
+        Json(serde_json::json!({ "status": "error", "error": error.to_string() })),
+    ).into_response()
+}
+
+fn render_register_page(state: &OpenSimServerState, notice: Option<&str>, error: Option<&str>) -> String {
+    let grid_name = html_escape(&state.config.opensim.grid_name);
+
+    let message = match (notice, error) {
+        (_, Some(error)) => format!(r#"<p class="error">{}</p>"#, html_escape(error)),
+        (Some(notice), None) => format!(r#"<p class="notice">{}</p>"#, html_escape(notice)),
+        (None, None) => String::new(),
+    };
+
+    let form = if !state.registration.is_enabled() {
+        "<p>Registration is currently closed.</p>".to_string()
+    } else if notice.is_some() {
+        String::new()
+    } else {
+        let invite = if state.registration.requires_invite() {
+            r#"<label>Invite code <input name="invite_code" required></label>"#
+        } else {
+            r#"<label>Invite code (optional) <input name="invite_code"></label>"#
+        };
+        format!(r#"<form method="post" action="/register">
+            <label>First name <input name="first_name" required maxlength="31"></label>
+            <label>Last name <input name="last_name" required maxlength="31"></label>
+            <label>Email <input name="email" type="email" required></label>
+            <label>Password <input name="password" type="password" required minlength="{}"></label>
+            {}
+            <button type="submit">Create account</button>
+        </form>"#, state.config.registration.min_password_length, invite)
+    };
+
+    format!(r#"<!DOCTYPE html>
 <html>
 <head>
-    <title>{}</title>
+    <title>Join {grid_name}</title>
     <style>
         body {{ font-family: Arial, sans-serif; margin: 40px; background-color: #f5f5f5; }}
-        .container {{ max-width: 800px; margin: 0 auto; background: white; padding: 30px; border-radius: 10px; box-shadow: 0 2px 10px rgba(0,0,0,0.1); }}
-        h1 {{ color: #333; border-bottom: 2px solid #007acc; padding-bottom: 10px; }}
-        .info {{ background: #e7f3ff; padding: 15px; border-radius: 5px; margin: 20px 0; }}
-        .users {{ background: #f0f8f0; padding: 15px; border-radius: 5px; margin: 20px 0; }}
-        .status {{ background: #fff3cd; padding: 15px; border-radius: 5px; margin: 20px 0; }}
-        code {{ background: #f4f4f4; padding: 2px 5px; border-radius: 3px; font-family: monospace; }}
-        ul {{ list-style-type: none; padding: 0; }}
-        li {{ background: #f8f9fa; margin: 5px 0; padding: 8px; border-radius: 3px; border-left: 3px solid #007acc; }}
+        .container {{ max-width: 480px; margin: 0 auto; background: white; padding: 30px; border-radius: 10px; box-shadow: 0 2px 10px rgba(0,0,0,0.1); }}
+        label {{ display: block; margin: 12px 0; }}
+        input {{ display: block; width: 100%; padding: 6px; margin-top: 4px; }}
+        .error {{ color: #b00020; }}
+        .notice {{ color: #1b5e20; }}
     </style>
 </head>
 <body>
     <div class="container">
-        <h1>Welcome to {}</h1>
-        
-        <div class="info">
-            <h3>🌍 Grid Information</h3>
-            <p><strong>Grid Name:</strong> {}</p>
-            <p><strong>Login URI:</strong> <code>{}</code></p>
-            <p><strong>Server Status:</strong> ✅ Online and Ready</p>
-            <p><strong>Protocol:</strong> OpenSimulator Compatible</p>
-        </div>
-
-        <div class="status">
-            <h3>🔗 How to Connect with Firestorm</h3>
-            <ol>
-                <li>Open Firestorm Viewer</li>
-                <li>Click on "Grid Manager"</li>
-                <li>Click "Add Grid"</li>
-                <li>Set Login URI to: <code>{}</code></li>
-                <li>Set Grid Name to: <code>{}</code></li>
-                <li>Save and select the grid</li>
-                <li>Login with one of the test accounts below</li>
-            </ol>
-        </div>
-
-        <div class="users">
-            <h3>👥 Available Test Users</h3>
-            <p>You can login with any of these test accounts:</p>
-            <ul>
-                <li><strong>Test User</strong> - Password: <code>password</code></li>
-                <li><strong>Admin User</strong> - Password: <code>admin</code></li>
-                <li><strong>Guest User</strong> - Password: <code>guest</code></li>
-            </ul>
-            <p><em>To create more users, use: <code>cargo run --bin mutsea-cli -- user create "First" "Last" --password "yourpassword"</code></em></p>
-        </div>
-
-        <div class="info">
-            <h3>📋 Quick Links</h3>
-            <p>
-                <a href="/get_grid_info">Grid Info</a> | 
-                <a href="/health">Health Check</a> | 
-                <a href="https://github.com/finalverse/mutsea">GitHub Repository</a>
-            </p>
-        </div>
-
-        <footer style="text-align: center; margin-top: 30px; color: #666; border-top: 1px solid #eee; padding-top: 20px;">
-            <p>Powered by <strong>Mutsea</strong> - Next Generation Virtual World Platform</p>
-            <p><em>Built with ❤️ in Rust | OpenSimulator Compatible</em></p>
-        </footer>
+        <h1>Join {grid_name}</h1>
+        {message}
+        {form}
+        <p><a href="/">Back to {grid_name}</a></p>
     </div>
 </body>
-</html>"#, 
-        grid_name, grid_name, grid_name, 
-        state.config.opensim.login_uri,
-        state.config.opensim.login_uri,
-        grid_name
-    );
-    Html(html)
+</html>"#)
 }
 
 /// Grid info handler for OpenSim compatibility
 async fn grid_info_handler(State(state): State<OpenSimServerState>) -> Result<Response<Body>, StatusCode> {
+    let base = state.config.network.http.public_url();
+    let register = state.config.web.register_url.clone()
+        .unwrap_or_else(|| format!("{}register", base));
     let grid_info = serde_json::json!({
         "gridname": state.config.opensim.grid_name,
         "gridnick": state.config.opensim.grid_nick,
         "login": state.config.opensim.login_uri,
-        "welcome": format!("http://{}:{}/", state.config.network.http.bind_address, state.config.network.http.port),
-        "economy": format!("http://{}:{}/", state.config.network.http.bind_address, state.config.network.http.port),
-        "about": format!("http://{}:{}/", state.config.network.http.bind_address, state.config.network.http.port),
-        "register": format!("http://{}:{}/", state.config.network.http.bind_address, state.config.network.http.port),
-        "help": format!("http://{}:{}/", state.config.network.http.bind_address, state.config.network.http.port),
-        "password": format!("http://{}:{}/", state.config.network.http.bind_address, state.config.network.http.port),
-        "gatekeeper": format!("http://{}:{}/", state.config.network.http.bind_address, state.config.network.http.port),
-        "uas": format!("http://{}:{}/", state.config.network.http.bind_address, state.config.network.http.port)
+        "welcome": format!("{}splash", base),
+        "economy": base,
+        "about": base,
+        "register": register,
+        "help": base,
+        "password": base,
+        "gatekeeper": base,
+        "uas": base
     });
 
     let response = Response::builder()
@@ -220,6 +984,7 @@ async fn grid_info_handler(State(state): State<OpenSimServerState>) -> Result<Re
 /// Login handler for XMLRPC compatibility
 async fn login_handler(
     State(state): State<OpenSimServerState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     body: String,
 ) -> Result<Response<Body>, StatusCode> {
@@ -227,12 +992,35 @@ async fn login_handler(
     debug!("Headers: {:?}", headers);
     debug!("Body preview: {}", &body[..std::cmp::min(200, body.len())]);
 
+    // Scripts' XML-RPC channels share the login endpoint, as in OpenSim
+    if remote_data::method_name(&body).as_deref() == Some("llRemoteData") {
+        let reply = match state.remote_data.as_ref() {
+            Some(remote_data) => match remote_data::parse_call(&body) {
+                Ok(message) => remote_data.call(message).await,
+                Err(e) => Err(e),
+            },
+            None => Err(RemoteDataError::Unavailable),
+        };
+        let xml = match reply {
+            Ok(reply) => remote_data::encode_reply(&reply),
+            Err(e) => {
+                debug!("llRemoteData call failed: {}", e);
+                remote_data::encode_fault(&e)
+            }
+        };
+        return Response::builder()
+            .status(200)
+            .header("Content-Type", "text/xml")
+            .body(Body::from(xml))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
     // Parse XMLRPC login request
     let login_request = match ParsedLoginRequest::from_xmlrpc(&body) {
         Ok(req) => req,
         Err(e) => {
             error!("Failed to parse login request: {}", e);
-            let error_response = mutsea_protocol::opensim::login::OpenSimLoginResponse::failure(
+            let error_response = mutsea_protocol::login::OpenSimLoginResponse::failure(
                 "Invalid login request format".to_string()
             );
             let response = Response::builder()
@@ -246,12 +1034,41 @@ async fn login_handler(
 
     info!("Login attempt for user: {} {}", login_request.first, login_request.last);
 
+    // Turn away all but administrators during maintenance, and hold back
+    // logins with the right password while the grid is busy
+    let verified = state
+        .login_service
+        .verify_password(&login_request.first, &login_request.last, &login_request.passwd);
+    let refusal = match state.maintenance.as_ref().and_then(|maintenance| maintenance.refusal(verified)) {
+        Some(message) => Some(message),
+        None => state
+            .login_queue
+            .as_ref()
+            .zip(verified)
+            .and_then(|(queue, agent)| queue.admit(agent, std::time::Instant::now()).message()),
+    };
+    if let Some(message) = refusal {
+        info!("Held back login for {} {}: {}", login_request.first, login_request.last, message);
+        let response = mutsea_protocol::login::OpenSimLoginResponse::failure(message);
+        return Response::builder()
+            .status(200)
+            .header("Content-Type", "text/xml")
+            .header("Cache-Control", "no-cache")
+            .body(Body::from(response.to_xmlrpc()))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // Start from the home and last location stored for the user
+    if let Some((grid_users, agent)) = state.grid_users.as_ref().zip(verified) {
+        grid_users.load(agent).await;
+    }
+
     // Authenticate user
-    let login_response = match state.login_service.authenticate(&login_request) {
+    let login_response = match state.login_service.authenticate_from(&login_request, Some(addr.ip())) {
         Ok(response) => response,
         Err(e) => {
             error!("Authentication error: {}", e);
-            mutsea_protocol::opensim::login::OpenSimLoginResponse::failure(
+            mutsea_protocol::login::OpenSimLoginResponse::failure(
                 "Authentication service error".to_string()
             )
         }
@@ -260,6 +1077,9 @@ async fn login_handler(
     if login_response.login == "true" {
         info!("User {} {} logged in successfully", login_request.first, login_request.last);
     } else {
+        if let Some((queue, agent)) = state.login_queue.as_ref().zip(verified) {
+            queue.release(agent);
+        }
         info!("Login failed for {} {}: {}", login_request.first, login_request.last, login_response.reason);
     }
 
@@ -274,23 +1094,300 @@ async fn login_handler(
     Ok(response)
 }
 
+/// Seed capability: the URLs of the caps a session may use
+async fn seed_caps_handler(
+    Path(cap_id): Path<uuid::Uuid>,
+    State(state): State<OpenSimServerState>,
+) -> Result<Response<Body>, StatusCode> {
+    state.login_service.capability_agent(&cap_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut served = vec!["EventQueueGet"];
+    if state.environment.is_some() {
+        served.push("EnvironmentSettings");
+    }
+    if state.names.is_some() {
+        served.extend(["GetDisplayNames", "SetDisplayName"]);
+    }
+    if state.snapshots.is_some() || state.animations.is_some() {
+        served.push("NewFileAgentInventory");
+    }
+    if state.snapshots.is_some() {
+        served.push("SendPostcard");
+    }
+    if state.inventory.is_some() {
+        served.extend(["FetchInventory2", "FetchInventoryDescendents2"]);
+    }
+    if state.bakes.is_some() {
+        served.extend(["UpdateAvatarAppearance", "AgentTextures"]);
+    }
+    let caps_base = state.login_service.sim_address().caps_base;
+    let caps: serde_json::Map<String, serde_json::Value> = served
+        .into_iter()
+        .map(|name| (name.to_string(), format!("{}caps/{}/{}", caps_base, cap_id, name).into()))
+        .collect();
+    Response::builder()
+        .status(200)
+        .header("Content-Type", "application/llsd+xml")
+        .body(Body::from(mutsea_protocol::llsd::to_xml(&serde_json::Value::Object(caps))))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
 /// Capabilities handler
 async fn caps_handler(
     Path((cap_id, path)): Path<(String, String)>,
-    State(_state): State<OpenSimServerState>,
-    body: String,
+    State(state): State<OpenSimServerState>,
+    method: Method,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> Result<Response<Body>, StatusCode> {
     debug!("Capability request: cap_id={}, path={}", cap_id, path);
 
+    // Photos are posted to their uploader as raw image bytes
+    if let Some(uploader) = path.strip_prefix("SnapshotUploader/") {
+        let snapshots = state.snapshots.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+        let seed_cap = uuid::Uuid::parse_str(&cap_id).map_err(|_| StatusCode::NOT_FOUND)?;
+        let agent = state.login_service.capability_agent(&seed_cap).ok_or(StatusCode::NOT_FOUND)?;
+        let uploader = uuid::Uuid::parse_str(uploader.trim_end_matches('/')).map_err(|_| StatusCode::NOT_FOUND)?;
+        let (status, reply) = match snapshots.complete(agent, uploader, body.to_vec()).await {
+            Ok(photo) => (StatusCode::OK, encode_upload_complete(photo.asset_id, photo.item_id)),
+            Err(e) => (snapshot_status(&e), encode_upload_error(&e.to_string())),
+        };
+        return Response::builder()
+            .status(status)
+            .header("Content-Type", "application/llsd+xml")
+            .body(Body::from(reply))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    // Animations are posted to their uploader as BVH or keyframe files
+    if let Some(uploader) = path.strip_prefix("AnimationUploader/") {
+        let animations = state.animations.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+        let seed_cap = uuid::Uuid::parse_str(&cap_id).map_err(|_| StatusCode::NOT_FOUND)?;
+        let agent = state.login_service.capability_agent(&seed_cap).ok_or(StatusCode::NOT_FOUND)?;
+        let uploader = uuid::Uuid::parse_str(uploader.trim_end_matches('/')).map_err(|_| StatusCode::NOT_FOUND)?;
+        let (status, reply) = match animations.complete(agent, uploader, body.to_vec()).await {
+            Ok(animation) => (StatusCode::OK, encode_upload_complete(animation.asset_id, Some(animation.item_id))),
+            Err(e) => (animation_status(&e), encode_upload_error(&e.to_string())),
+        };
+        return Response::builder()
+            .status(status)
+            .header("Content-Type", "application/llsd+xml")
+            .body(Body::from(reply))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    let body = String::from_utf8_lossy(&body).into_owned();
+
+    // Script URLs from llRequestURL are answered by their script
+    if path == "lslhttp" || path.starts_with("lslhttp/") {
+        let script_http = state.script_http.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+        let url_id = uuid::Uuid::parse_str(&cap_id).map_err(|_| StatusCode::NOT_FOUND)?;
+        let (status, body) = script_http.handle(url_id, method.as_str(), body).await;
+        return Response::builder()
+            .status(status)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(Body::from(body))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // Viewers cache the names they look up for as long as the headers say
+    if path.trim_end_matches('/') == "GetDisplayNames" {
+        let names = state.names.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+        let ids = parse_display_name_ids(query.as_deref().unwrap_or_default());
+        let (found, bad_ids) = names.lookup(&ids).await;
+        let expires = names.expires();
+        return Response::builder()
+            .status(200)
+            .header("Content-Type", "application/llsd+xml")
+            .header("Cache-Control", format!("max-age={}", names.cache_max_age()))
+            .header("Expires", expires.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .body(Body::from(encode_display_names(&found, &bad_ids, expires)))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    if path.trim_end_matches('/') == "SetDisplayName" {
+        let names = state.names.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+        let seed_cap = uuid::Uuid::parse_str(&cap_id).map_err(|_| StatusCode::NOT_FOUND)?;
+        let agent = state.login_service.capability_agent(&seed_cap).ok_or(StatusCode::NOT_FOUND)?;
+        let request = SetDisplayName::parse(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+        let (status, reason, name) = match names.rename(agent, &request.new_name).await {
+            Ok(name) => (StatusCode::OK, "OK".to_string(), name),
+            Err(e) => {
+                let current = names.name(agent).await.ok_or(StatusCode::NOT_FOUND)?;
+                (name_status(&e), e.to_string(), current)
+            }
+        };
+        return Response::builder()
+            .status(status)
+            .header("Content-Type", "application/llsd+xml")
+            .header("Cache-Control", "no-cache")
+            .body(Body::from(encode_set_display_name_reply(
+                &name,
+                status.as_u16(),
+                &reason,
+                names.expires(),
+            )))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // Animation uploads are answered with where to post the file
+    if let Some(animations) = state.animations.as_ref().filter(|_| path.trim_end_matches('/') == "NewFileAgentInventory") {
+        let upload = SnapshotUpload::parse(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+        if upload.is_animation() {
+            let seed_cap = uuid::Uuid::parse_str(&cap_id).map_err(|_| StatusCode::NOT_FOUND)?;
+            let agent = state.login_service.capability_agent(&seed_cap).ok_or(StatusCode::NOT_FOUND)?;
+            let (status, reply) = match animations.begin(agent, upload) {
+                Ok(uploader) => {
+                    let caps_base = state.login_service.sim_address().caps_base;
+                    let url = format!("{}caps/{}/AnimationUploader/{}", caps_base, cap_id, uploader);
+                    (StatusCode::OK, encode_uploader(&url))
+                }
+                Err(e) => (animation_status(&e), encode_upload_error(&e.to_string())),
+            };
+            return Response::builder()
+                .status(status)
+                .header("Content-Type", "application/llsd+xml")
+                .body(Body::from(reply))
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    // Snapshots and postcards are answered with where to post the image
+    let snapshot_cap = path.trim_end_matches('/');
+    if snapshot_cap == "NewFileAgentInventory" || snapshot_cap == "SendPostcard" {
+        let snapshots = state.snapshots.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+        let seed_cap = uuid::Uuid::parse_str(&cap_id).map_err(|_| StatusCode::NOT_FOUND)?;
+        let agent = state.login_service.capability_agent(&seed_cap).ok_or(StatusCode::NOT_FOUND)?;
+        let begun = if snapshot_cap == "SendPostcard" {
+            Postcard::parse(&body).map(|postcard| Ok(snapshots.begin_postcard(agent, postcard)))
+        } else {
+            SnapshotUpload::parse(&body).map(|upload| snapshots.begin_snapshot(agent, upload))
+        }
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+        let (status, reply) = match begun {
+            Ok(uploader) => {
+                let caps_base = state.login_service.sim_address().caps_base;
+                let url = format!("{}caps/{}/SnapshotUploader/{}", caps_base, cap_id, uploader);
+                (StatusCode::OK, encode_uploader(&url))
+            }
+            Err(e) => (snapshot_status(&e), encode_upload_error(&e.to_string())),
+        };
+        return Response::builder()
+            .status(status)
+            .header("Content-Type", "application/llsd+xml")
+            .body(Body::from(reply))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // Outfits are baked by the region once the viewer says they changed
+    if path.trim_end_matches('/') == "UpdateAvatarAppearance" {
+        let bakes = state.bakes.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+        let seed_cap = uuid::Uuid::parse_str(&cap_id).map_err(|_| StatusCode::NOT_FOUND)?;
+        let agent = state.login_service.capability_agent(&seed_cap).ok_or(StatusCode::NOT_FOUND)?;
+        let cof_version = parse_update_appearance(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+        let (status, reply) = match bakes.bake(agent, cof_version).await {
+            Ok(_) => (StatusCode::OK, encode_update_appearance_reply(cof_version, None)),
+            Err(e) => (bake_status(&e), encode_update_appearance_reply(cof_version, Some(&e.to_string()))),
+        };
+        return Response::builder()
+            .status(status)
+            .header("Content-Type", "application/llsd+xml")
+            .body(Body::from(reply))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // Bakes are fetched as AgentTextures/<agent>/<bake>, any texture ID after
+    // that being ignored
+    if let Some(texture) = path.strip_prefix("AgentTextures/") {
+        let bakes = state.bakes.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+        let seed_cap = uuid::Uuid::parse_str(&cap_id).map_err(|_| StatusCode::NOT_FOUND)?;
+        state.login_service.capability_agent(&seed_cap).ok_or(StatusCode::NOT_FOUND)?;
+        let mut segments = texture.split('/');
+        let wearer = segments
+            .next()
+            .and_then(|wearer| uuid::Uuid::parse_str(wearer).ok())
+            .ok_or(StatusCode::NOT_FOUND)?;
+        let bake = segments.next().ok_or(StatusCode::NOT_FOUND)?;
+        let data = bakes
+            .texture(UserId::from_uuid(wearer), bake)
+            .await
+            .map_err(|e| bake_status(&e))?;
+        return Response::builder()
+            .status(200)
+            .header("Content-Type", bakes.content_type())
+            .body(Body::from(data))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // Inventory is fetched a page of folders at a time, gzipped when large
+    let inventory_cap = path.trim_end_matches('/');
+    if matches!(
+        inventory_cap,
+        "FetchInventoryDescendents2" | "WebFetchInventoryDescendents" | "FetchInventory2"
+    ) {
+        let inventory = state.inventory.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+        let seed_cap = uuid::Uuid::parse_str(&cap_id).map_err(|_| StatusCode::NOT_FOUND)?;
+        let agent = state.login_service.capability_agent(&seed_cap).ok_or(StatusCode::NOT_FOUND)?;
+        let reply = if inventory_cap == "FetchInventory2" {
+            let requests = parse_fetch_items(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+            let items = inventory.fetch_items(agent, &requests).await.map_err(|e| inventory_status(&e))?;
+            encode_items(agent, &items)
+        } else {
+            let requests = parse_fetch_descendents(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+            let page = inventory
+                .fetch_descendents(agent, &requests)
+                .await
+                .map_err(|e| inventory_status(&e))?;
+            encode_descendents(agent, &page.folders, &page.bad_folders)
+        };
+        let gzip_accepted = headers
+            .get(axum::http::header::ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("gzip"));
+        let response = Response::builder()
+            .status(200)
+            .header("Content-Type", "application/llsd+xml");
+        return if gzip_accepted && inventory.should_compress(reply.len()) {
+            let compressed = inventory::gzip(reply.as_bytes()).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            response.header("Content-Encoding", "gzip").body(Body::from(compressed))
+        } else {
+            response.body(Body::from(reply))
+        }
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // Viewers long-poll their event queue; 502 tells them nothing came
+    if path == "EventQueueGet" {
+        let cap_id = uuid::Uuid::parse_str(&cap_id).map_err(|_| StatusCode::NOT_FOUND)?;
+        let agent = state.login_service.capability_agent(&cap_id).ok_or(StatusCode::NOT_FOUND)?;
+        let poll = EventQueuePoll::parse(&body);
+        if poll.done {
+            state.event_queue.remove(agent);
+            return Err(StatusCode::NOT_FOUND);
+        }
+        let (id, events) = state
+            .event_queue
+            .poll(agent, poll.ack, POLL_TIMEOUT)
+            .await
+            .ok_or(StatusCode::BAD_GATEWAY)?;
+        return Response::builder()
+            .status(200)
+            .header("Content-Type", "application/llsd+xml")
+            .body(Body::from(encode_events(id, &events)))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // Windlight settings are LLSD rather than JSON
+    if path == "EnvironmentSettings" {
+        let environment = state.environment.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+        return Response::builder()
+            .status(200)
+            .header("Content-Type", "application/llsd+xml")
+            .body(Body::from(environment.settings().to_llsd_xml()))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
     // Handle different capability requests
     let response_data = match path.as_str() {
-        "EventQueueGet" => {
-            // Return empty event queue
-            serde_json::json!({
-                "events": [],
-                "id": 1
-            })
-        }
         "GetTexture" => {
             // Return texture not found
             serde_json::json!({
@@ -303,12 +1400,6 @@ async fn caps_handler(
                 "error": "Mesh not found"
             })
         }
-        "FetchInventoryDescendents2" | "WebFetchInventoryDescendents" => {
-            // Return empty inventory
-            serde_json::json!({
-                "folders": []
-            })
-        }
         _ => {
             // Generic capability response
             serde_json::json!({
@@ -326,22 +1417,2097 @@ async fn caps_handler(
     Ok(response)
 }
 
-/// Health check handler
-async fn health_handler(State(state): State<OpenSimServerState>) -> Result<Response<Body>, StatusCode> {
-    let health_info = serde_json::json!({
-        "status": "healthy",
-        "service": "mutsea-opensim-server",
-        "timestamp": chrono::Utc::now().to_rfc3339(),
-        "grid_name": state.config.opensim.grid_name,
-        "login_uri": state.config.opensim.login_uri,
-        "users_count": state.login_service.list_users().len()
-    });
+/// Password login for the REST API
+#[derive(Debug, Deserialize)]
+struct PasswordLoginRequest {
+    first_name: String,
+    last_name: String,
+    password: String,
+}
 
-    let response = Response::builder()
-        .status(200)
-        .header("Content-Type", "application/json")
-        .body(Body::from(serde_json::to_string(&health_info).unwrap()))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+/// Exchange of an external ID token for a session token
+#[derive(Debug, Deserialize)]
+struct OidcLoginRequest {
+    id_token: String,
+}
 
-    Ok(response)
+/// Issue a session token for first/last name and password
+async fn auth_password_handler(
+    State(state): State<OpenSimServerState>,
+    Json(request): Json<PasswordLoginRequest>,
+) -> Response {
+    let credentials = Credentials::Password {
+        first_name: request.first_name,
+        last_name: request.last_name,
+        password: request.password,
+    };
+    auth_login_response(&state, &credentials).await
+}
+
+/// Issue a session token for an ID token from the configured OIDC provider
+async fn auth_oidc_handler(
+    State(state): State<OpenSimServerState>,
+    Json(request): Json<OidcLoginRequest>,
+) -> Response {
+    auth_login_response(&state, &Credentials::BearerToken(request.id_token)).await
+}
+
+async fn auth_login_response(state: &OpenSimServerState, credentials: &Credentials) -> Response {
+    match state.auth.login(credentials).await {
+        Ok(session) => (StatusCode::OK, Json(serde_json::to_value(session).unwrap_or_default())).into_response(),
+        Err(e) => {
+            let status = match e {
+                AuthError::Provider(_) => StatusCode::BAD_GATEWAY,
+                AuthError::NotLinked => StatusCode::FORBIDDEN,
+                _ => StatusCode::UNAUTHORIZED,
+            };
+            (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+        }
+    }
+}
+
+/// The principal behind the presented token
+async fn me_handler(
+    State(state): State<OpenSimServerState>,
+    Extension(user): Extension<AuthenticatedUser>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "principal_id": user.principal_id.to_string(),
+        "provider": user.provider,
+        "online": state.login_service.get_grid_user(&user.principal_id).map(|g| g.online).unwrap_or(false),
+    }))
+}
+
+/// Server statistics for dashboards (`read:stats`). Counters are totals
+/// since startup; dashboards derive rates from successive polls.
+async fn admin_stats_handler(State(state): State<OpenSimServerState>) -> Json<serde_json::Value> {
+    let network = match &state.lludp_server {
+        Some(lludp_server) => {
+            let stats = lludp_server.get_stats().await;
+            Some(serde_json::json!({
+                "circuits": lludp_server.get_active_circuits_count().await,
+                "packets_received": stats.packets_received,
+                "packets_sent": stats.packets_sent,
+                "bytes_received": stats.bytes_received,
+                "bytes_sent": stats.bytes_sent,
+                "errors": stats.errors,
+                "reliable_resends": stats.reliable_resends,
+                "outbound_queued": stats.outbound_queued,
+                "outbound_dropped": stats.outbound_dropped,
+            }))
+        }
+        None => None,
+    };
+    let frame = state.profiler.as_ref().map(|profiler| {
+        let meter = profiler.lag_meter();
+        serde_json::json!({
+            "ticks": meter.ticks,
+            "avg_ms": meter.tick.avg_ms(),
+            "max_ms": meter.tick.max_ms,
+            "over_budget_ratio": meter.over_budget_ratio(),
+            "heaviest_section": meter.heaviest_section().map(|(name, _)| name),
+            "window_start": meter.window_start.to_rfc3339(),
+        })
+    });
+    let database_pool = match &state.database {
+        Some(database) => {
+            let metrics = database.get_metrics().await;
+            Some(serde_json::json!({
+                "active_connections": metrics.active_connections,
+                "max_connections": metrics.max_connections,
+                "utilization": if metrics.max_connections == 0 {
+                    0.0
+                } else {
+                    metrics.active_connections as f64 / metrics.max_connections as f64
+                },
+                "avg_query_time_ms": metrics.avg_query_time_ms,
+                "failed_queries": metrics.failed_queries,
+                "checked_out": metrics.pool.checked_out,
+                "idle": metrics.pool.idle,
+                "avg_wait_ms": metrics.pool.average_wait.as_secs_f64() * 1000.0,
+                "max_wait_ms": metrics.pool.max_wait.as_secs_f64() * 1000.0,
+                "leaked_connections": metrics.pool.leaked,
+            }))
+        }
+        None => None,
+    };
+    Json(serde_json::json!({
+        "users_count": state.login_service.list_users().len(),
+        "online_count": state.login_service.online_count(),
+        "active_sessions": state.login_service.get_active_sessions_count(),
+        "regions_count": state.login_service.list_regions().len(),
+        "login_queue": state.login_queue.as_ref().map(LoginQueue::status),
+        "maintenance": state.maintenance.as_ref().map(Maintenance::status),
+        "network": network,
+        "frame": frame,
+        "database_pool": database_pool,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    }))
+}
+
+/// Query string for `/api/admin/database/queries`
+#[derive(Debug, Deserialize)]
+struct TopQueriesParams {
+    #[serde(default)]
+    sort: Option<String>,
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// Heaviest database queries by total time, average time, count or errors,
+/// with any plans captured for them
+async fn admin_top_queries_handler(
+    State(state): State<OpenSimServerState>,
+    Query(params): Query<TopQueriesParams>,
+) -> Response {
+    let Some(database) = &state.database else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": "No database in use" })),
+        )
+            .into_response();
+    };
+    let sort = match params.sort.as_deref().map(str::parse::<QuerySort>).transpose() {
+        Ok(sort) => sort.unwrap_or(QuerySort::TotalTime),
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e }))).into_response(),
+    };
+    let analytics = database.query_analytics();
+    let queries = analytics.top(sort, params.limit.unwrap_or(10).min(100));
+    // Plans captured for the listed queries, keyed by query fingerprint
+    let plans: std::collections::HashMap<_, _> = analytics
+        .plans()
+        .into_iter()
+        .filter(|(fingerprint, _)| queries.iter().any(|q| &q.query_name == fingerprint))
+        .collect();
+    Json(serde_json::json!({ "sort": sort, "queries": queries, "plans": plans })).into_response()
+}
+
+/// Query string for `/api/admin/database/advice`
+#[derive(Debug, Deserialize)]
+struct IndexAdviceParams {
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// Missing indexes suggested from the recorded query statistics
+async fn admin_index_advice_handler(
+    State(state): State<OpenSimServerState>,
+    Query(params): Query<IndexAdviceParams>,
+) -> Response {
+    let Some(database) = &state.database else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": "No database in use" })),
+        )
+            .into_response();
+    };
+    match database.index_advice().await {
+        Ok(mut suggestions) => {
+            suggestions.truncate(params.limit.unwrap_or(10).min(100));
+            Json(serde_json::json!({ "suggestions": suggestions })).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": e.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+/// Query string for `/api/admin/scripts/top`
+#[derive(Debug, Deserialize)]
+struct TopScriptsParams {
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// Scripts using the most CPU, with their owner and position (`read:stats`)
+async fn admin_top_scripts_handler(
+    State(state): State<OpenSimServerState>,
+    Query(params): Query<TopScriptsParams>,
+) -> Response {
+    let Some(scripts) = &state.scripts else {
+        return no_scripts();
+    };
+    let top = scripts.top(params.limit.unwrap_or(10).min(100)).await;
+    Json(serde_json::json!({ "scripts": top })).into_response()
+}
+
+fn no_scripts() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Scripts are disabled" })),
+    )
+        .into_response()
+}
+
+fn script_error(e: ScriptError) -> Response {
+    let status = match e {
+        ScriptError::UnknownScript(_) | ScriptError::UnknownObject(_) => StatusCode::NOT_FOUND,
+        ScriptError::Load(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        ScriptError::NoRuntime => StatusCode::SERVICE_UNAVAILABLE,
+        ScriptError::QueueFull(_) | ScriptError::Stopped(_) => StatusCode::CONFLICT,
+        ScriptError::Limit(ref e) => limit_status(e),
+    };
+    (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+}
+
+/// LSL source to load into a prim, with the base64 snapshot of a script
+/// moved from another region (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct LoadScriptRequest {
+    name: String,
+    source: String,
+    #[serde(default)]
+    snapshot: Option<String>,
+}
+
+/// Compile and start a script in a prim (`admin:regions`)
+async fn admin_load_script_handler(
+    State(state): State<OpenSimServerState>,
+    Path(object_id): Path<uuid::Uuid>,
+    Json(request): Json<LoadScriptRequest>,
+) -> Response {
+    let Some(scripts) = &state.scripts else {
+        return no_scripts();
+    };
+    let snapshot = match request.snapshot.as_deref().map(|snapshot| BASE64.decode(snapshot)).transpose() {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "error": format!("Invalid snapshot: {}", e) })),
+            )
+                .into_response()
+        }
+    };
+    let object_id = mutsea_core::ObjectId::from_uuid(object_id);
+    match scripts.load(object_id, &request.name, &request.source, snapshot.as_deref()).await {
+        Ok(script_id) => Json(serde_json::json!({ "script_id": script_id })).into_response(),
+        Err(e) => script_error(e),
+    }
+}
+
+/// The state of a script, base64 encoded, to load in another region
+/// (`admin:regions`)
+async fn admin_script_snapshot_handler(
+    State(state): State<OpenSimServerState>,
+    Path(script_id): Path<uuid::Uuid>,
+) -> Response {
+    let Some(scripts) = &state.scripts else {
+        return no_scripts();
+    };
+    match scripts.snapshot(script_id) {
+        Ok(Some(snapshot)) => Json(serde_json::json!({ "snapshot": BASE64.encode(snapshot) })).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "The script's runtime keeps no state" })),
+        )
+            .into_response(),
+        Err(e) => script_error(e),
+    }
+}
+
+/// Stop and remove a script (`admin:regions`)
+async fn admin_remove_script_handler(
+    State(state): State<OpenSimServerState>,
+    Path(script_id): Path<uuid::Uuid>,
+) -> Response {
+    let Some(scripts) = &state.scripts else {
+        return no_scripts();
+    };
+    let removed = scripts.remove_script(script_id);
+    Json(serde_json::json!({ "removed": removed })).into_response()
+}
+
+/// Account creation through the admin API (`write:users`)
+#[derive(Debug, Deserialize)]
+struct AdminCreateUserRequest {
+    first_name: String,
+    last_name: String,
+    password: String,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    is_admin: bool,
+}
+
+async fn admin_create_user_handler(
+    State(state): State<OpenSimServerState>,
+    Extension(key): Extension<ApiKeyRecord>,
+    Json(request): Json<AdminCreateUserRequest>,
+) -> Response {
+    info!("👤 API key {} creating user {} {}", key.id, request.first_name, request.last_name);
+    match state.login_service.create_user(
+        &request.first_name,
+        &request.last_name,
+        &request.password,
+        request.email,
+        request.is_admin,
+    ) {
+        Ok(user_id) => (StatusCode::CREATED, Json(serde_json::json!({ "user_id": user_id.to_string() }))).into_response(),
+        Err(e) => admin_error_response(StatusCode::CONFLICT, &e),
+    }
+}
+
+/// Outcome or feedback score for a stored AI decision (`write:ai_feedback`)
+async fn admin_decision_feedback_handler(
+    State(state): State<OpenSimServerState>,
+    Extension(key): Extension<ApiKeyRecord>,
+    Path(decision_id): Path<uuid::Uuid>,
+    Json(feedback): Json<DecisionFeedback>,
+) -> Response {
+    let Some(database) = &state.database else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": "No database in use" })),
+        )
+            .into_response();
+    };
+    if let Err(e) = feedback.validate() {
+        return admin_error_response(StatusCode::BAD_REQUEST, &e);
+    }
+    match database.record_decision_feedback(decision_id, &feedback).await {
+        Ok(true) => {
+            debug!("API key {} reported the outcome of AI decision {}", key.id, decision_id);
+            (StatusCode::ACCEPTED, Json(serde_json::json!({ "decision_id": decision_id }))).into_response()
+        }
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": format!("No AI decision {}", decision_id) })),
+        )
+            .into_response(),
+        Err(e) => admin_error_response(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
+
+/// Regions known to the login service (`admin:regions`)
+async fn admin_regions_handler(State(state): State<OpenSimServerState>) -> Json<serde_json::Value> {
+    let regions: Vec<_> = state
+        .login_service
+        .list_regions()
+        .into_iter()
+        .map(|r| {
+            serde_json::json!({
+                "region_id": r.region_id.to_string(),
+                "name": r.region_name,
+                "x": r.region_x,
+                "y": r.region_y,
+            })
+        })
+        .collect();
+    Json(serde_json::json!({ "regions": regions }))
+}
+
+/// A region as a binary glTF file, by name or ID (`admin:regions`)
+async fn admin_export_gltf_handler(State(state): State<OpenSimServerState>, Path(region): Path<String>) -> Response {
+    let Some(exporter) = &state.gltf_exporter else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": "glTF export is disabled" })),
+        )
+            .into_response();
+    };
+    match exporter.export(&region).await {
+        Ok(glb) => Response::builder()
+            .status(200)
+            .header("Content-Type", "model/gltf-binary")
+            .body(Body::from(glb))
+            .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+        Err(e @ ExportError::UnknownRegion(_)) => {
+            (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+        }
+    }
+}
+
+/// The region console over a WebSocket (`admin:regions`); each text message
+/// is a command line, answered with its output and the next prompt
+async fn admin_console_handler(State(state): State<OpenSimServerState>, upgrade: WebSocketUpgrade) -> Response {
+    let Some(console) = state.console.clone() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": "The region console is disabled" })),
+        )
+            .into_response();
+    };
+    upgrade.on_upgrade(move |socket| console.serve(socket))
+}
+
+/// Dump the simulation recording as a replay bundle (`admin:regions`)
+async fn admin_replay_dump_handler(State(state): State<OpenSimServerState>) -> Response {
+    let Some(replay) = &state.replay else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": "Replay recording is disabled" })),
+        )
+            .into_response();
+    };
+    match replay.dump().await {
+        Ok(dump) => Json(dump).into_response(),
+        Err(e @ ReplayError::NotRecording) => {
+            (StatusCode::CONFLICT, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+        }
+        Err(e) => {
+            error!("Failed to dump replay: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+        }
+    }
+}
+
+fn no_world_generator() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "World generation is disabled" })),
+    )
+        .into_response()
+}
+
+/// Generation batches that can still be undone (`admin:regions`)
+async fn admin_worldgen_batches_handler(State(state): State<OpenSimServerState>) -> Response {
+    let Some(generator) = &state.world_generator else {
+        return no_world_generator();
+    };
+    Json(serde_json::json!({ "batches": generator.batches() })).into_response()
+}
+
+/// Generate content into the region from an AI layout plan (`admin:regions`)
+async fn admin_worldgen_handler(
+    State(state): State<OpenSimServerState>,
+    Extension(key): Extension<ApiKeyRecord>,
+    Json(request): Json<GenerationRequest>,
+) -> Response {
+    let Some(generator) = &state.world_generator else {
+        return no_world_generator();
+    };
+    if let Err(e) = request.validate() {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e }))).into_response();
+    }
+    match generator.generate(request).await {
+        Ok(batch) => {
+            info!("API key {} generated world batch {}", key.id, batch.id);
+            (StatusCode::CREATED, Json(batch)).into_response()
+        }
+        Err(e @ MutseaError::RegionNotFound(_)) => admin_error_response(StatusCode::NOT_FOUND, &e),
+        Err(e) => admin_error_response(StatusCode::BAD_GATEWAY, &e),
+    }
+}
+
+/// Undo a generation batch (`admin:regions`)
+async fn admin_worldgen_undo_handler(
+    State(state): State<OpenSimServerState>,
+    Extension(key): Extension<ApiKeyRecord>,
+    Path(batch_id): Path<uuid::Uuid>,
+) -> Response {
+    let Some(generator) = &state.world_generator else {
+        return no_world_generator();
+    };
+    match generator.undo(batch_id).await {
+        Some(batch) => {
+            info!("API key {} undid world batch {}", key.id, batch_id);
+            Json(batch).into_response()
+        }
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": format!("No generation batch {}", batch_id) })),
+        )
+            .into_response(),
+    }
+}
+
+fn no_quests() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Quests are disabled" })),
+    )
+        .into_response()
+}
+
+/// Quest written through the admin API (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct DefineQuestRequest {
+    title: String,
+    #[serde(default)]
+    synopsis: String,
+    objectives: Vec<QuestObjective>,
+}
+
+/// Quest generation request (`admin:regions`); the quest is given to the
+/// player unless `offer` is false
+#[derive(Debug, Deserialize)]
+struct GenerateQuestRequest {
+    player_id: uuid::Uuid,
+    #[serde(default)]
+    theme: Option<String>,
+    #[serde(default = "default_true")]
+    offer: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct OfferQuestRequest {
+    player_id: uuid::Uuid,
+}
+
+/// Defined quests (`admin:regions`)
+async fn admin_quests_handler(State(state): State<OpenSimServerState>) -> Response {
+    let Some(quests) = &state.quests else {
+        return no_quests();
+    };
+    Json(serde_json::json!({ "quests": quests.quests() })).into_response()
+}
+
+/// Define a quest (`admin:regions`)
+async fn admin_define_quest_handler(
+    State(state): State<OpenSimServerState>,
+    Extension(key): Extension<ApiKeyRecord>,
+    Json(request): Json<DefineQuestRequest>,
+) -> Response {
+    let Some(quests) = &state.quests else {
+        return no_quests();
+    };
+    let quest = QuestDefinition::new(request.title, request.synopsis, request.objectives, QuestOrigin::Authored);
+    match quests.define(quest) {
+        Ok(quest) => {
+            info!("API key {} defined quest {}", key.id, quest.id);
+            (StatusCode::CREATED, Json(quest)).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e }))).into_response(),
+    }
+}
+
+/// Have the storyteller write a quest for a player (`admin:regions`)
+async fn admin_generate_quest_handler(
+    State(state): State<OpenSimServerState>,
+    Extension(key): Extension<ApiKeyRecord>,
+    Json(request): Json<GenerateQuestRequest>,
+) -> Response {
+    let Some(quests) = &state.quests else {
+        return no_quests();
+    };
+    let player_id = UserId::from_uuid(request.player_id);
+    let theme = request.theme.unwrap_or_else(|| state.config.quests.default_theme.clone());
+    let quest = match quests.generate(player_id, &theme).await {
+        Ok(quest) => quest,
+        Err(e) => return admin_error_response(StatusCode::BAD_GATEWAY, &e),
+    };
+    info!("API key {} generated quest {} for {}", key.id, quest.id, player_id);
+
+    let offered = request.offer && quests.offer(player_id, quest.id).is_ok();
+    (StatusCode::CREATED, Json(serde_json::json!({ "quest": quest, "offered": offered }))).into_response()
+}
+
+/// Give a player a quest (`admin:regions`)
+async fn admin_offer_quest_handler(
+    State(state): State<OpenSimServerState>,
+    Path(quest_id): Path<uuid::Uuid>,
+    Json(request): Json<OfferQuestRequest>,
+) -> Response {
+    let Some(quests) = &state.quests else {
+        return no_quests();
+    };
+    match quests.offer(UserId::from_uuid(request.player_id), quest_id) {
+        Ok(progress) => Json(progress).into_response(),
+        Err(e) => {
+            let status = match e {
+                OfferError::UnknownQuest(_) => StatusCode::NOT_FOUND,
+                OfferError::AlreadyGiven(_) | OfferError::TooManyActive(_) => StatusCode::CONFLICT,
+            };
+            (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+        }
+    }
+}
+
+/// A player's quest progress (`admin:regions`)
+async fn admin_player_quests_handler(
+    State(state): State<OpenSimServerState>,
+    Path(player_id): Path<uuid::Uuid>,
+) -> Response {
+    let Some(quests) = &state.quests else {
+        return no_quests();
+    };
+    Json(serde_json::json!({ "progress": quests.progress(UserId::from_uuid(player_id)) })).into_response()
+}
+
+fn no_dialogs() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "The LLUDP server is not running" })),
+    )
+        .into_response()
+}
+
+fn dialog_error_response(error: DialogError) -> Response {
+    let status = match error {
+        DialogError::NotConnected(_) => StatusCode::NOT_FOUND,
+        DialogError::Invalid(_) => StatusCode::BAD_REQUEST,
+        DialogError::TimedOut(_) => StatusCode::GATEWAY_TIMEOUT,
+        DialogError::Network(_) => StatusCode::BAD_GATEWAY,
+    };
+    (status, Json(serde_json::json!({ "error": error.to_string() }))).into_response()
+}
+
+/// Alert for one agent, or everyone when `agent_id` is absent (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct AlertRequest {
+    message: String,
+    #[serde(default)]
+    agent_id: Option<uuid::Uuid>,
+    #[serde(default)]
+    modal: bool,
+}
+
+/// Dialog shown to an agent (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct DialogRequest {
+    agent_id: uuid::Uuid,
+    message: String,
+    buttons: Vec<String>,
+}
+
+/// Show agents an alert (`admin:regions`)
+async fn admin_alert_handler(
+    State(state): State<OpenSimServerState>,
+    Extension(key): Extension<ApiKeyRecord>,
+    Json(request): Json<AlertRequest>,
+) -> Response {
+    let Some(dialogs) = &state.dialogs else {
+        return no_dialogs();
+    };
+    let result = match request.agent_id {
+        Some(agent_id) => dialogs.alert(UserId::from_uuid(agent_id), &request.message, request.modal).await.map(|()| 1),
+        None => dialogs.broadcast(&request.message).await,
+    };
+    match result {
+        Ok(sent) => {
+            info!("API key {} sent an alert to {} agents", key.id, sent);
+            Json(serde_json::json!({ "sent": sent })).into_response()
+        }
+        Err(e) => dialog_error_response(e),
+    }
+}
+
+/// Show an agent a dialog and answer with the button pressed (`admin:regions`)
+async fn admin_dialog_handler(State(state): State<OpenSimServerState>, Json(request): Json<DialogRequest>) -> Response {
+    let Some(dialogs) = &state.dialogs else {
+        return no_dialogs();
+    };
+    let buttons: Vec<&str> = request.buttons.iter().map(String::as_str).collect();
+    match dialogs.dialog(UserId::from_uuid(request.agent_id), &request.message, &buttons).await {
+        Ok(reply) => Json(reply).into_response(),
+        Err(e) => dialog_error_response(e),
+    }
+}
+
+fn no_navigation() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Navigation is disabled" })),
+    )
+        .into_response()
+}
+
+/// Where to send an NPC (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct NpcGotoRequest {
+    x: f32,
+    y: f32,
+}
+
+/// The region's NPCs and where they are heading (`admin:regions`)
+async fn admin_npcs_handler(State(state): State<OpenSimServerState>) -> Response {
+    let Some(movement) = &state.npc_movement else {
+        return no_navigation();
+    };
+    let npcs: Vec<_> = movement
+        .npcs()
+        .into_iter()
+        .map(|npc| {
+            let name = state.config.npc_chat.npcs.iter().find(|config| config.id == npc.id).map(|config| config.name.clone());
+            serde_json::json!({ "id": npc.id, "name": name, "position": npc.position, "goal": npc.goal })
+        })
+        .collect();
+    Json(serde_json::json!({ "npcs": npcs })).into_response()
+}
+
+/// Send an NPC walking and answer with its waypoints (`admin:regions`)
+async fn admin_npc_goto_handler(
+    State(state): State<OpenSimServerState>,
+    Path(npc_id): Path<uuid::Uuid>,
+    Json(request): Json<NpcGotoRequest>,
+) -> Response {
+    let Some(movement) = &state.npc_movement else {
+        return no_navigation();
+    };
+    match movement.go_to(npc_id, mutsea_core::Vector3::new(request.x, request.y, 0.0)) {
+        Ok(waypoints) => Json(serde_json::json!({ "waypoints": waypoints })).into_response(),
+        Err(e) => {
+            let status = match e {
+                NavError::UnknownNpc(_) => StatusCode::NOT_FOUND,
+                NavError::Unreachable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+        }
+    }
+}
+
+fn no_vehicles() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Vehicles are disabled" })),
+    )
+        .into_response()
+}
+
+/// Make a prim a vehicle, as llSetVehicleType and the llSetVehicle*Param
+/// calls would (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct VehicleRequest {
+    #[serde(rename = "type")]
+    vehicle_type: mutsea_physics::VehicleType,
+    #[serde(default)]
+    float_params: std::collections::HashMap<i32, f32>,
+    #[serde(default)]
+    vector_params: std::collections::HashMap<i32, mutsea_core::Vector3>,
+    #[serde(default)]
+    flags: u32,
+}
+
+/// The region's vehicles and their riders (`admin:regions`)
+async fn admin_vehicles_handler(State(state): State<OpenSimServerState>) -> Response {
+    let Some(vehicles) = &state.vehicles else {
+        return no_vehicles();
+    };
+    Json(serde_json::json!({ "vehicles": vehicles.vehicles() })).into_response()
+}
+
+/// Set a prim's vehicle type and parameters (`admin:regions`)
+async fn admin_set_vehicle_handler(
+    State(state): State<OpenSimServerState>,
+    Path(object_id): Path<uuid::Uuid>,
+    Json(request): Json<VehicleRequest>,
+) -> Response {
+    let Some(vehicles) = &state.vehicles else {
+        return no_vehicles();
+    };
+    let id = mutsea_core::ObjectId::from_uuid(object_id);
+    match configure_vehicle(vehicles, id, &request).await {
+        Ok(()) => Json(serde_json::json!({ "id": id, "type": request.vehicle_type })).into_response(),
+        Err(e) => {
+            let status = match e {
+                VehicleError::UnknownObject(_) | VehicleError::NotAVehicle(_) => StatusCode::NOT_FOUND,
+                VehicleError::UnknownParam(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+        }
+    }
+}
+
+async fn configure_vehicle(
+    vehicles: &Vehicles,
+    id: mutsea_core::ObjectId,
+    request: &VehicleRequest,
+) -> Result<(), VehicleError> {
+    vehicles.set_type(id, request.vehicle_type).await?;
+    if request.vehicle_type == mutsea_physics::VehicleType::None {
+        return Ok(());
+    }
+    for (param, value) in &request.float_params {
+        vehicles.set_float_param(id, *param, *value)?;
+    }
+    for (param, value) in &request.vector_params {
+        vehicles.set_vector_param(id, *param, *value)?;
+    }
+    vehicles.set_flags(id, request.flags)
+}
+
+fn no_raycast() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Raycasts are disabled" })),
+    )
+        .into_response()
+}
+
+/// Rays cast together through the region (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct RaycastRequest {
+    rays: Vec<mutsea_physics::RayQuery>,
+}
+
+/// What each ray hits, nearest first (`admin:regions`)
+async fn admin_raycast_handler(
+    State(state): State<OpenSimServerState>,
+    Json(request): Json<RaycastRequest>,
+) -> Response {
+    let Some(raycast) = &state.raycast else {
+        return no_raycast();
+    };
+    match raycast.cast_batch(&request.rays).await {
+        Ok(hits) => Json(serde_json::json!({ "hits": hits })).into_response(),
+        Err(e) => {
+            let status = match e {
+                RaycastError::BatchTooLarge(..) | RaycastError::TooLong(..) => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+        }
+    }
+}
+
+fn no_meshes() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Mesh prims are disabled" })),
+    )
+        .into_response()
+}
+
+/// Mesh asset to give a prim (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct MeshRequest {
+    asset_id: uuid::Uuid,
+}
+
+/// Give a prim a mesh, colliding as its physics shape (`admin:regions`)
+async fn admin_set_mesh_handler(
+    State(state): State<OpenSimServerState>,
+    Path(object_id): Path<uuid::Uuid>,
+    Json(request): Json<MeshRequest>,
+) -> Response {
+    let Some(meshes) = &state.meshes else {
+        return no_meshes();
+    };
+    match meshes.set_mesh(mutsea_core::ObjectId::from_uuid(object_id), request.asset_id).await {
+        Ok(summary) => Json(serde_json::json!(summary)).into_response(),
+        Err(e) => {
+            let status = match e {
+                MeshError::UnknownObject(_) | MeshError::UnknownAsset(_) => StatusCode::NOT_FOUND,
+                MeshError::Invalid(_) | MeshError::NoShape => StatusCode::UNPROCESSABLE_ENTITY,
+                MeshError::NoDatabase | MeshError::Load(..) => StatusCode::SERVICE_UNAVAILABLE,
+            };
+            (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+        }
+    }
+}
+
+/// Make a mesh prim collide as its box again (`admin:regions`)
+async fn admin_remove_mesh_handler(
+    State(state): State<OpenSimServerState>,
+    Path(object_id): Path<uuid::Uuid>,
+) -> Response {
+    let Some(meshes) = &state.meshes else {
+        return no_meshes();
+    };
+    let removed = meshes.remove(mutsea_core::ObjectId::from_uuid(object_id));
+    Json(serde_json::json!({ "removed": removed })).into_response()
+}
+
+fn no_economy() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "The economy is disabled" })),
+    )
+        .into_response()
+}
+
+fn economy_error(e: EconomyError) -> Response {
+    let status = match e {
+        EconomyError::UnknownObject(_) | EconomyError::UnknownLocalId(_) => StatusCode::NOT_FOUND,
+        EconomyError::NotOwner(..) | EconomyError::NoPermission(..) => StatusCode::FORBIDDEN,
+        EconomyError::NotForSale(_) | EconomyError::SaleChanged(_) | EconomyError::OwnObject(_) => StatusCode::CONFLICT,
+        EconomyError::UnknownSaleType(_)
+        | EconomyError::InvalidAmount(_)
+        | EconomyError::SelfPayment
+        | EconomyError::InsufficientFunds { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        EconomyError::Limit(ref e) => limit_status(e),
+        EconomyError::Database(_) => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+}
+
+/// How a prim is for sale and its permissions (`admin:regions`)
+async fn admin_sale_handler(State(state): State<OpenSimServerState>, Path(object_id): Path<uuid::Uuid>) -> Response {
+    let Some(economy) = &state.economy else {
+        return no_economy();
+    };
+    let object = mutsea_core::ObjectId::from_uuid(object_id);
+    Json(serde_json::json!({
+        "sale": economy.sale(object),
+        "permissions": economy.permissions(object),
+    }))
+    .into_response()
+}
+
+/// Sale to set on a prim (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct SaleRequest {
+    #[serde(flatten)]
+    sale: SaleInfo,
+    /// What buyers may do with the prim, e.g. 32768 for copy only
+    #[serde(default)]
+    next_owner_permissions: Option<u32>,
+}
+
+/// Put a prim up for sale, or take it off sale (`admin:regions`)
+async fn admin_set_sale_handler(
+    State(state): State<OpenSimServerState>,
+    Path(object_id): Path<uuid::Uuid>,
+    Json(request): Json<SaleRequest>,
+) -> Response {
+    let Some(economy) = &state.economy else {
+        return no_economy();
+    };
+    let object = mutsea_core::ObjectId::from_uuid(object_id);
+    if let Some(next_owner) = request.next_owner_permissions {
+        if let Err(e) = economy.set_next_owner_permissions(object, next_owner).await {
+            return economy_error(e);
+        }
+    }
+    match economy.set_sale(object, request.sale).await {
+        Ok(()) => Json(serde_json::json!({
+            "sale": economy.sale(object),
+            "permissions": economy.permissions(object),
+        }))
+        .into_response(),
+        Err(e) => economy_error(e),
+    }
+}
+
+/// An agent's balance (`admin:regions`)
+async fn admin_balance_handler(State(state): State<OpenSimServerState>, Path(agent_id): Path<uuid::Uuid>) -> Response {
+    let Some(economy) = &state.economy else {
+        return no_economy();
+    };
+    match economy.balance(UserId::from_uuid(agent_id)).await {
+        Ok(balance) => Json(serde_json::json!({ "balance": balance })).into_response(),
+        Err(e) => economy_error(e),
+    }
+}
+
+/// Money to give an agent (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct GrantRequest {
+    amount: i64,
+    #[serde(default)]
+    description: String,
+}
+
+/// Give an agent money from the grid (`admin:regions`)
+async fn admin_grant_handler(
+    State(state): State<OpenSimServerState>,
+    Path(agent_id): Path<uuid::Uuid>,
+    Json(request): Json<GrantRequest>,
+) -> Response {
+    let Some(economy) = &state.economy else {
+        return no_economy();
+    };
+    match economy.grant(UserId::from_uuid(agent_id), request.amount, &request.description).await {
+        Ok(balance) => Json(serde_json::json!({ "balance": balance })).into_response(),
+        Err(e) => economy_error(e),
+    }
+}
+
+fn no_directory() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "The directory is disabled" })),
+    )
+        .into_response()
+}
+
+fn directory_error(e: DirectoryError) -> Response {
+    let status = match e {
+        DirectoryError::UnknownClassified(_) | DirectoryError::UnknownEvent(_) => StatusCode::NOT_FOUND,
+        DirectoryError::NotCreator => StatusCode::FORBIDDEN,
+        DirectoryError::TooManyClassifieds(_) | DirectoryError::TooManyEvents(..) => StatusCode::CONFLICT,
+        DirectoryError::PriceTooLow(_) | DirectoryError::EventOver | DirectoryError::NotPaid(_) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        DirectoryError::Database(_) => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+}
+
+fn limit_status(e: &LimitError) -> StatusCode {
+    match e {
+        LimitError::Uploads(_) => StatusCode::TOO_MANY_REQUESTS,
+        _ => StatusCode::CONFLICT,
+    }
+}
+
+/// A limit to override, or to reset with no value (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct LimitOverride {
+    limit: Limit,
+    #[serde(default)]
+    subject: Option<uuid::Uuid>,
+    #[serde(default)]
+    value: Option<u64>,
+}
+
+/// The region's limits and their overrides (`admin:regions`)
+async fn admin_limits_handler(State(state): State<OpenSimServerState>) -> Response {
+    let Some(limits) = &state.limits else {
+        return no_limits();
+    };
+    let config = &state.config.limits;
+    Json(serde_json::json!({
+        "max_prims_per_region": config.max_prims_per_region,
+        "max_scripts_per_owner": config.max_scripts_per_owner,
+        "max_upload_bytes_per_day": config.max_upload_bytes_per_day,
+        "max_attachments": state.config.attachments.max_per_avatar,
+        "overrides": limits.overrides(),
+    }))
+    .into_response()
+}
+
+/// Override a limit for the region, a parcel or an agent (`admin:regions`)
+async fn admin_override_limit_handler(
+    State(state): State<OpenSimServerState>,
+    Json(request): Json<LimitOverride>,
+) -> Response {
+    let Some(limits) = &state.limits else {
+        return no_limits();
+    };
+    limits.set_override(request.limit, request.subject, request.value);
+    let value = limits.limit(request.limit, request.subject);
+    Json(Override {
+        limit: request.limit,
+        subject: request.subject,
+        value,
+    })
+    .into_response()
+}
+
+fn no_limits() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Limits are disabled" })),
+    )
+        .into_response()
+}
+
+fn event_json(event: &Event) -> serde_json::Value {
+    serde_json::json!({
+        "event_id": event.event_id,
+        "owner_id": event.owner_id,
+        "name": event.name,
+        "category": event.category,
+        "description": event.description,
+        "start": event.date_utc,
+        "duration": event.duration,
+        "cover_charge": event.cover_charge,
+        "sim_name": event.sim_name,
+        "parcel_id": event.parcel_id,
+        "maturity": event.maturity(),
+    })
+}
+
+/// Events listed in the directory (`admin:regions`)
+async fn admin_events_handler(State(state): State<OpenSimServerState>) -> Response {
+    let Some(directory) = &state.directory else {
+        return no_directory();
+    };
+    let events: Vec<_> = directory.events().iter().map(event_json).collect();
+    Json(serde_json::json!({ "events": events })).into_response()
+}
+
+/// List an event held in the region (`admin:regions`)
+async fn admin_create_event_handler(
+    State(state): State<OpenSimServerState>,
+    Json(listing): Json<EventListing>,
+) -> Response {
+    let Some(directory) = &state.directory else {
+        return no_directory();
+    };
+    match directory.create_event(listing, chrono::Utc::now().timestamp() as u32).await {
+        Ok(event) => (StatusCode::CREATED, Json(event_json(&event))).into_response(),
+        Err(e) => directory_error(e),
+    }
+}
+
+/// Remove an event from the directory (`admin:regions`)
+async fn admin_delete_event_handler(State(state): State<OpenSimServerState>, Path(event_id): Path<u32>) -> Response {
+    let Some(directory) = &state.directory else {
+        return no_directory();
+    };
+    match directory.delete_event(event_id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => directory_error(e),
+    }
+}
+
+/// Logged chat and IMs matching the query, newest first (`admin:regions`)
+async fn admin_chat_log_handler(
+    State(state): State<OpenSimServerState>,
+    Query(query): Query<ChatLogQuery>,
+) -> Response {
+    let Some(chat_log) = &state.chat_log else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": "Chat logging is disabled" })),
+        )
+            .into_response();
+    };
+    match chat_log.search(&query).await {
+        Ok(messages) => Json(serde_json::json!({ "messages": messages })).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": e.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+fn no_land() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Land sales are disabled" })),
+    )
+        .into_response()
+}
+
+fn land_error(e: LandError) -> Response {
+    let status = match e {
+        LandError::UnknownLocalId(_) | LandError::UnknownParcel(_) | LandError::UnknownAuction(_) => {
+            StatusCode::NOT_FOUND
+        }
+        LandError::NotOwner | LandError::NotAuthorized(_) => StatusCode::FORBIDDEN,
+        LandError::NotForSale(_) | LandError::SaleChanged(_) | LandError::OwnParcel(_) | LandError::Auctioned(_) => {
+            StatusCode::CONFLICT
+        }
+        LandError::InvalidPrice(_) | LandError::BidRefused(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        LandError::Economy(e) => return economy_error(e),
+        LandError::NoEconomy | LandError::NoDatabase | LandError::Database(_) => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+}
+
+/// A parcel to auction (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct LandAuctionRequest {
+    parcel_id: uuid::Uuid,
+    #[serde(default)]
+    starting_bid: i64,
+    duration_secs: u64,
+}
+
+/// A bid on a land auction (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct LandBidRequest {
+    bidder_id: uuid::Uuid,
+    amount: i64,
+}
+
+/// The region's open land auctions (`admin:regions`)
+async fn admin_land_auctions_handler(State(state): State<OpenSimServerState>) -> Response {
+    let Some(land) = &state.land else {
+        return no_land();
+    };
+    match land.auctions().await {
+        Ok(auctions) => Json(serde_json::json!({ "auctions": auctions })).into_response(),
+        Err(e) => land_error(e),
+    }
+}
+
+/// Auction a parcel for its owner (`admin:regions`)
+async fn admin_start_land_auction_handler(
+    State(state): State<OpenSimServerState>,
+    Json(request): Json<LandAuctionRequest>,
+) -> Response {
+    let Some(land) = &state.land else {
+        return no_land();
+    };
+    match land
+        .start_auction(request.parcel_id, request.starting_bid, request.duration_secs)
+        .await
+    {
+        Ok(auction) => (StatusCode::CREATED, Json(auction)).into_response(),
+        Err(e) => land_error(e),
+    }
+}
+
+/// A land auction with its bids, highest first (`admin:regions`)
+async fn admin_land_auction_handler(
+    State(state): State<OpenSimServerState>,
+    Path(auction_id): Path<uuid::Uuid>,
+) -> Response {
+    let Some(land) = &state.land else {
+        return no_land();
+    };
+    match land.auction(auction_id).await {
+        Ok(details) => Json(details).into_response(),
+        Err(e) => land_error(e),
+    }
+}
+
+/// Bid on a land auction for an agent (`admin:regions`)
+async fn admin_land_bid_handler(
+    State(state): State<OpenSimServerState>,
+    Path(auction_id): Path<uuid::Uuid>,
+    Json(request): Json<LandBidRequest>,
+) -> Response {
+    let Some(land) = &state.land else {
+        return no_land();
+    };
+    match land
+        .bid(auction_id, UserId::from_uuid(request.bidder_id), request.amount)
+        .await
+    {
+        Ok(bid) => (StatusCode::CREATED, Json(bid)).into_response(),
+        Err(e) => land_error(e),
+    }
+}
+
+fn bake_status(error: &BakeError) -> StatusCode {
+    match error {
+        BakeError::NoOutfit(_) | BakeError::UnknownBake(_) | BakeError::NotBaked(_) => StatusCode::NOT_FOUND,
+        BakeError::NoDatabase => StatusCode::SERVICE_UNAVAILABLE,
+        BakeError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn inventory_status(error: &InventoryError) -> StatusCode {
+    match error {
+        InventoryError::NoDatabase => StatusCode::SERVICE_UNAVAILABLE,
+        InventoryError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn animation_status(error: &AnimationError) -> StatusCode {
+    match error {
+        AnimationError::NotAnimation(_)
+        | AnimationError::Invalid(_)
+        | AnimationError::TooManyJoints(_)
+        | AnimationError::TooLong(_) => StatusCode::BAD_REQUEST,
+        AnimationError::UnknownUpload => StatusCode::NOT_FOUND,
+        AnimationError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        AnimationError::Limit(_) => StatusCode::TOO_MANY_REQUESTS,
+        AnimationError::NoDatabase => StatusCode::SERVICE_UNAVAILABLE,
+        AnimationError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn snapshot_status(error: &SnapshotError) -> StatusCode {
+    match error {
+        SnapshotError::NotTexture(_) | SnapshotError::Invalid(_) => StatusCode::BAD_REQUEST,
+        SnapshotError::UnknownUpload => StatusCode::NOT_FOUND,
+        SnapshotError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        SnapshotError::Limit(_) => StatusCode::TOO_MANY_REQUESTS,
+        SnapshotError::NoDatabase => StatusCode::SERVICE_UNAVAILABLE,
+        SnapshotError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn no_sounds() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Sounds are disabled" })),
+    )
+        .into_response()
+}
+
+fn no_scene_control() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Scene control is disabled" })),
+    )
+        .into_response()
+}
+
+fn scene_control_error(e: SceneControlError) -> Response {
+    let status = match e {
+        SceneControlError::UnknownRegion(_) | SceneControlError::UnknownObject(_) => StatusCode::NOT_FOUND,
+        SceneControlError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        SceneControlError::Limit(ref e) => limit_status(e),
+    };
+    (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+}
+
+/// What to look for in a region, as query parameters
+#[derive(Debug, Deserialize)]
+struct FindObjectsParams {
+    /// Part of the name, in any case
+    name: Option<String>,
+    owner: Option<uuid::Uuid>,
+    /// Corners of the area, as `x,y,z`
+    min: Option<String>,
+    max: Option<String>,
+    scripted: Option<bool>,
+    limit: Option<usize>,
+}
+
+fn parse_vector(text: &str) -> Option<mutsea_core::Vector3> {
+    let mut parts = text.split(',').map(|part| part.trim().parse::<f32>());
+    let vector = mutsea_core::Vector3::new(parts.next()?.ok()?, parts.next()?.ok()?, parts.next()?.ok()?);
+    parts.next().is_none().then_some(vector)
+}
+
+/// Objects in a region by name, owner, area and scripts (`read:scene`)
+async fn scene_find_objects_handler(
+    State(state): State<OpenSimServerState>,
+    Path(region): Path<String>,
+    Query(params): Query<FindObjectsParams>,
+) -> Response {
+    let Some(scene_query) = &state.scene_query else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": "Scene queries are disabled" })),
+        )
+            .into_response();
+    };
+    let area = match (params.min.as_deref(), params.max.as_deref()) {
+        (None, None) => None,
+        (Some(min), Some(max)) => match parse_vector(min).zip(parse_vector(max)) {
+            Some(area) => Some(area),
+            None => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({ "error": "min and max must be x,y,z" })),
+                )
+                    .into_response()
+            }
+        },
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "error": "An area needs both min and max" })),
+            )
+                .into_response()
+        }
+    };
+    let search = SceneSearch {
+        query: mutsea_core::scene::ObjectQuery {
+            name: params.name,
+            owner_id: params.owner.map(UserId::from_uuid),
+            area,
+        },
+        scripted: params.scripted,
+        limit: params.limit,
+    };
+    match scene_query.find(&region, &search).await {
+        Ok(objects) => Json(serde_json::json!({ "objects": objects })).into_response(),
+        Err(e @ SceneQueryError::UnknownRegion(_)) => {
+            (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+        }
+    }
+}
+
+/// Make a simple prim in a region (`write:scene`)
+async fn scene_create_prim_handler(
+    State(state): State<OpenSimServerState>,
+    Path(region): Path<String>,
+    Json(prim): Json<NewPrim>,
+) -> Response {
+    let Some(scene_control) = &state.scene_control else {
+        return no_scene_control();
+    };
+    match scene_control.create(&region, prim).await {
+        Ok(prim) => (StatusCode::CREATED, Json(prim)).into_response(),
+        Err(e) => scene_control_error(e),
+    }
+}
+
+/// Move, turn or resize a prim (`write:scene`)
+async fn scene_move_prim_handler(
+    State(state): State<OpenSimServerState>,
+    Path((region, object_id)): Path<(String, uuid::Uuid)>,
+    Json(change): Json<PrimMove>,
+) -> Response {
+    let Some(scene_control) = &state.scene_control else {
+        return no_scene_control();
+    };
+    match scene_control
+        .update(&region, mutsea_core::ObjectId::from_uuid(object_id), change)
+        .await
+    {
+        Ok(prim) => Json(prim).into_response(),
+        Err(e) => scene_control_error(e),
+    }
+}
+
+/// Delete a prim (`write:scene`)
+async fn scene_delete_prim_handler(
+    State(state): State<OpenSimServerState>,
+    Path((region, object_id)): Path<(String, uuid::Uuid)>,
+) -> Response {
+    let Some(scene_control) = &state.scene_control else {
+        return no_scene_control();
+    };
+    match scene_control.delete(&region, mutsea_core::ObjectId::from_uuid(object_id)).await {
+        Ok(prim) => Json(prim).into_response(),
+        Err(e) => scene_control_error(e),
+    }
+}
+
+/// Say something in a region's local chat (`write:scene`)
+async fn scene_chat_handler(
+    State(state): State<OpenSimServerState>,
+    Path(region): Path<String>,
+    Json(chat): Json<SceneChat>,
+) -> Response {
+    let Some(scene_control) = &state.scene_control else {
+        return no_scene_control();
+    };
+    match scene_control.say(&region, chat).await {
+        Ok(heard) => Json(serde_json::json!({ "heard": heard })).into_response(),
+        Err(e) => scene_control_error(e),
+    }
+}
+
+fn sound_error(e: SoundError) -> Response {
+    let status = match e {
+        SoundError::UnknownObject(_) => StatusCode::NOT_FOUND,
+        SoundError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        SoundError::TooLong(_) | SoundError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        SoundError::Limit(ref e) => limit_status(e),
+        SoundError::NoDatabase | SoundError::Database(_) => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+}
+
+/// An Ogg Vorbis sound to store, base64 encoded (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct SoundUpload {
+    name: String,
+    creator_id: uuid::Uuid,
+    data: String,
+}
+
+/// Check and store a sound asset (`admin:regions`)
+async fn admin_upload_sound_handler(
+    State(state): State<OpenSimServerState>,
+    Json(upload): Json<SoundUpload>,
+) -> Response {
+    let Some(sounds) = &state.sounds else {
+        return no_sounds();
+    };
+    let data = match BASE64.decode(&upload.data) {
+        Ok(data) => data,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "error": format!("Invalid sound data: {}", e) })),
+            )
+                .into_response()
+        }
+    };
+    match sounds.upload(&upload.name, UserId::from_uuid(upload.creator_id), data).await {
+        Ok(stored) => (
+            StatusCode::CREATED,
+            Json(serde_json::json!({
+                "asset_id": stored.asset_id,
+                "channels": stored.info.channels,
+                "sample_rate": stored.info.sample_rate,
+                "duration": stored.info.duration,
+            })),
+        )
+            .into_response(),
+        Err(e) => sound_error(e),
+    }
+}
+
+/// A sound for a prim to play (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct PlaySoundRequest {
+    sound_id: uuid::Uuid,
+    #[serde(default = "default_gain")]
+    gain: f32,
+    /// Play it over and over, as ambient sound
+    #[serde(default, rename = "loop")]
+    looped: bool,
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+/// Have a prim play a sound to the agents in earshot (`admin:regions`)
+async fn admin_play_sound_handler(
+    State(state): State<OpenSimServerState>,
+    Path(object_id): Path<uuid::Uuid>,
+    Json(request): Json<PlaySoundRequest>,
+) -> Response {
+    let Some(sounds) = &state.sounds else {
+        return no_sounds();
+    };
+    let object = mutsea_core::ObjectId::from_uuid(object_id);
+    match sounds.play(object, request.sound_id, request.gain, request.looped).await {
+        Ok(sent) => Json(serde_json::json!({ "sent": sent })).into_response(),
+        Err(e) => sound_error(e),
+    }
+}
+
+/// Stop the sound a prim is playing (`admin:regions`)
+async fn admin_stop_sound_handler(
+    State(state): State<OpenSimServerState>,
+    Path(object_id): Path<uuid::Uuid>,
+) -> Response {
+    let Some(sounds) = &state.sounds else {
+        return no_sounds();
+    };
+    match sounds.stop(mutsea_core::ObjectId::from_uuid(object_id)).await {
+        Ok(sent) => Json(serde_json::json!({ "sent": sent })).into_response(),
+        Err(e) => sound_error(e),
+    }
+}
+
+fn no_names() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Names are disabled" })),
+    )
+        .into_response()
+}
+
+fn name_status(e: &NameError) -> StatusCode {
+    match e {
+        NameError::UnknownAvatar(_) => StatusCode::NOT_FOUND,
+        NameError::Empty | NameError::TooLong(_) | NameError::InvalidCharacter | NameError::Rejected(_) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        NameError::Taken(_) => StatusCode::CONFLICT,
+        NameError::TooSoon(_) => StatusCode::TOO_MANY_REQUESTS,
+        NameError::Database(_) => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+fn name_json(name: &AvatarName) -> serde_json::Value {
+    serde_json::json!({
+        "id": name.agent_id,
+        "username": name.username(),
+        "legacy_name": name.legacy_name(),
+        "display_name": name.display_name(),
+        "is_display_name_default": name.display_name.is_none(),
+        "display_name_next_update": name.next_update.to_rfc3339(),
+    })
+}
+
+/// The names of an avatar (`admin:regions`)
+async fn admin_display_name_handler(
+    State(state): State<OpenSimServerState>,
+    Path(agent_id): Path<uuid::Uuid>,
+) -> Response {
+    let Some(names) = &state.names else {
+        return no_names();
+    };
+    let agent = UserId::from_uuid(agent_id);
+    match names.name(agent).await {
+        Some(name) => Json(name_json(&name)).into_response(),
+        None => {
+            let e = NameError::UnknownAvatar(agent);
+            (name_status(&e), Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+        }
+    }
+}
+
+/// The display name changes of an avatar, newest first (`admin:regions`)
+async fn admin_display_name_history_handler(
+    State(state): State<OpenSimServerState>,
+    Path(agent_id): Path<uuid::Uuid>,
+) -> Response {
+    let Some(names) = &state.names else {
+        return no_names();
+    };
+    let agent = UserId::from_uuid(agent_id);
+    if names.name(agent).await.is_none() {
+        let e = NameError::UnknownAvatar(agent);
+        return (name_status(&e), Json(serde_json::json!({ "error": e.to_string() }))).into_response();
+    }
+    match names.history(agent).await {
+        Ok(history) => {
+            let changes: Vec<serde_json::Value> = history.iter().map(name_change_json).collect();
+            Json(serde_json::json!({ "changes": changes })).into_response()
+        }
+        Err(e) => (name_status(&e), Json(serde_json::json!({ "error": e.to_string() }))).into_response(),
+    }
+}
+
+fn name_change_json(change: &NameChange) -> serde_json::Value {
+    serde_json::json!({
+        "old_display_name": change.old_display_name,
+        "new_display_name": change.new_display_name,
+        "changed": change.changed.to_rfc3339(),
+    })
+}
+
+/// A display name to give an avatar (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct DisplayNameRequest {
+    /// The avatar's legacy name to go back to showing that
+    display_name: String,
+}
+
+/// Set an avatar's display name, ignoring its rename cooldown
+/// (`admin:regions`)
+async fn admin_set_display_name_handler(
+    State(state): State<OpenSimServerState>,
+    Path(agent_id): Path<uuid::Uuid>,
+    Json(request): Json<DisplayNameRequest>,
+) -> Response {
+    let Some(names) = &state.names else {
+        return no_names();
+    };
+    match names.set_display_name(UserId::from_uuid(agent_id), &request.display_name).await {
+        Ok(name) => Json(name_json(&name)).into_response(),
+        Err(e) => (name_status(&e), Json(serde_json::json!({ "error": e.to_string() }))).into_response(),
+    }
+}
+
+fn no_attachments() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Attachments are disabled" })),
+    )
+        .into_response()
+}
+
+fn attachment_error(e: AttachmentError) -> Response {
+    let status = match e {
+        AttachmentError::UnknownObject(_)
+        | AttachmentError::UnknownLocalId(_)
+        | AttachmentError::NotWorn(_)
+        | AttachmentError::ItemNotWorn(_) => StatusCode::NOT_FOUND,
+        AttachmentError::NotOwner(..) => StatusCode::FORBIDDEN,
+        AttachmentError::AlreadyWorn(_) | AttachmentError::TooMany(_) => StatusCode::CONFLICT,
+        AttachmentError::InvalidPoint(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        AttachmentError::Limit(ref e) => limit_status(e),
+        AttachmentError::Database(_) => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+}
+
+/// What an avatar wears (`admin:regions`)
+async fn admin_attachments_handler(
+    State(state): State<OpenSimServerState>,
+    Path(agent_id): Path<uuid::Uuid>,
+) -> Response {
+    let Some(attachments) = &state.attachments else {
+        return no_attachments();
+    };
+    Json(serde_json::json!({ "attachments": attachments.worn(UserId::from_uuid(agent_id)) })).into_response()
+}
+
+/// A prim for an avatar to wear (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct AttachRequest {
+    object: uuid::Uuid,
+    /// 0 or absent for the chest
+    #[serde(default)]
+    point: u8,
+}
+
+/// Have an avatar wear a prim it owns (`admin:regions`)
+async fn admin_attach_handler(
+    State(state): State<OpenSimServerState>,
+    Path(agent_id): Path<uuid::Uuid>,
+    Json(request): Json<AttachRequest>,
+) -> Response {
+    let Some(attachments) = &state.attachments else {
+        return no_attachments();
+    };
+    let object = mutsea_core::ObjectId::from_uuid(request.object);
+    match attachments
+        .attach(UserId::from_uuid(agent_id), object, request.point, mutsea_core::Quaternion::IDENTITY)
+        .await
+    {
+        Ok(attachment) => Json(serde_json::json!({ "attachment": attachment })).into_response(),
+        Err(e) => attachment_error(e),
+    }
+}
+
+/// Take a prim off an avatar (`admin:regions`)
+async fn admin_detach_handler(
+    State(state): State<OpenSimServerState>,
+    Path((agent_id, object_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> Response {
+    let Some(attachments) = &state.attachments else {
+        return no_attachments();
+    };
+    let object = mutsea_core::ObjectId::from_uuid(object_id);
+    match attachments.detach(UserId::from_uuid(agent_id), object).await {
+        Ok(attachment) => Json(serde_json::json!({ "detached": attachment })).into_response(),
+        Err(e) => attachment_error(e),
+    }
+}
+
+/// Body of `POST /api/admin/avatars/:id/kick`
+#[derive(Debug, Default, Deserialize)]
+struct KickRequest {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Disconnect an avatar from the region (`admin:regions`)
+async fn admin_kick_handler(
+    State(state): State<OpenSimServerState>,
+    Extension(key): Extension<ApiKeyRecord>,
+    Path(agent_id): Path<uuid::Uuid>,
+    request: Option<Json<KickRequest>>,
+) -> Response {
+    let Some(lludp_server) = &state.lludp_server else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": "No region is running" })),
+        )
+            .into_response();
+    };
+    let request = request.map(|Json(request)| request).unwrap_or_default();
+    let reason = request.reason.unwrap_or_else(|| "You have been disconnected by an administrator.".to_string());
+    match lludp_server.kick_agent(UserId::from_uuid(agent_id), &reason).await {
+        Ok(true) => {
+            info!("API key {} kicked {}", key.id, agent_id);
+            Json(serde_json::json!({ "kicked": agent_id })).into_response()
+        }
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": format!("{} is not connected", agent_id) })),
+        )
+            .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({ "error": e.to_string() }))).into_response(),
+    }
+}
+
+fn no_visibility() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Visibility rules are disabled" })),
+    )
+        .into_response()
+}
+
+fn visibility_error(e: VisibilityError) -> Response {
+    let status = match e {
+        VisibilityError::UnknownObject(_) => StatusCode::NOT_FOUND,
+        VisibilityError::NoNetwork | VisibilityError::Network(_) => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+}
+
+/// Whether a prim is hidden from everyone but its owner (`admin:regions`)
+async fn admin_visibility_handler(
+    State(state): State<OpenSimServerState>,
+    Path(object_id): Path<uuid::Uuid>,
+) -> Response {
+    let Some(visibility) = &state.visibility else {
+        return no_visibility();
+    };
+    match visibility.is_hidden(mutsea_core::ObjectId::from_uuid(object_id)).await {
+        Ok(hidden) => Json(serde_json::json!({ "hidden": hidden })).into_response(),
+        Err(e) => visibility_error(e),
+    }
+}
+
+/// Whether to hide a prim from everyone but its owner (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct VisibilityRequest {
+    hidden: bool,
+}
+
+/// Hide a prim from everyone but its owner, or show it again (`admin:regions`)
+async fn admin_set_visibility_handler(
+    State(state): State<OpenSimServerState>,
+    Path(object_id): Path<uuid::Uuid>,
+    Json(request): Json<VisibilityRequest>,
+) -> Response {
+    let Some(visibility) = &state.visibility else {
+        return no_visibility();
+    };
+    match visibility.set_hidden(mutsea_core::ObjectId::from_uuid(object_id), request.hidden).await {
+        Ok(()) => Json(serde_json::json!({ "hidden": request.hidden })).into_response(),
+        Err(e) => visibility_error(e),
+    }
+}
+
+/// Prim streams under way and the latest finished, to tune streaming by
+/// (`admin:regions`)
+async fn admin_prim_streaming_handler(State(state): State<OpenSimServerState>) -> Response {
+    let Some(prim_streaming) = &state.prim_streaming else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": "Prim streaming is disabled" })),
+        )
+            .into_response();
+    };
+    Json(serde_json::json!({
+        "streams": prim_streaming.progress(),
+        "finished": prim_streaming.finished(),
+    }))
+    .into_response()
+}
+
+/// Maintenance started through the admin API (`admin:regions`)
+#[derive(Debug, Deserialize)]
+struct MaintenanceRequest {
+    /// Shown to refused logins, the configured message if absent
+    #[serde(default)]
+    message: Option<String>,
+    /// Minutes until maintenance ends on its own, until ended if absent
+    #[serde(default)]
+    minutes: Option<u64>,
+    /// Warn the agents in world and log them out after the grace period
+    #[serde(default)]
+    drain: bool,
+}
+
+/// Whether the grid is in maintenance (`admin:regions`)
+async fn admin_maintenance_handler(State(state): State<OpenSimServerState>) -> Response {
+    let Some(maintenance) = &state.maintenance else {
+        return no_maintenance();
+    };
+    Json(maintenance.status()).into_response()
+}
+
+/// Close the grid to all but administrators, draining it if asked
+/// (`admin:regions`)
+async fn admin_enter_maintenance_handler(
+    State(state): State<OpenSimServerState>,
+    Extension(key): Extension<ApiKeyRecord>,
+    Json(request): Json<MaintenanceRequest>,
+) -> Response {
+    let Some(maintenance) = &state.maintenance else {
+        return no_maintenance();
+    };
+    maintenance.enter(request.message, request.minutes.map(|m| std::time::Duration::from_secs(m * 60)));
+    let drained = if request.drain { maintenance.drain().await } else { 0 };
+    info!("API key {} started maintenance, draining {} agents", key.id, drained);
+    Json(serde_json::json!({ "status": maintenance.status(), "drained": drained })).into_response()
+}
+
+/// Open the grid again (`admin:regions`)
+async fn admin_exit_maintenance_handler(
+    State(state): State<OpenSimServerState>,
+    Extension(key): Extension<ApiKeyRecord>,
+) -> Response {
+    let Some(maintenance) = &state.maintenance else {
+        return no_maintenance();
+    };
+    if maintenance.exit() {
+        info!("API key {} ended maintenance", key.id);
+    }
+    Json(maintenance.status()).into_response()
+}
+
+/// Plugins and whether each started (`admin:regions`)
+async fn admin_plugins_handler(State(state): State<OpenSimServerState>) -> Json<serde_json::Value> {
+    let plugins = state.plugins.as_ref().map(|host| host.plugins()).unwrap_or_default();
+    Json(serde_json::json!({ "plugins": plugins }))
+}
+
+/// Query string for `/api/admin/webhooks/deliveries`
+#[derive(Debug, Deserialize)]
+struct WebhookDeliveriesParams {
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// Recent webhook deliveries, newest first, and how each went
+/// (`admin:regions`)
+async fn admin_webhook_deliveries_handler(
+    State(state): State<OpenSimServerState>,
+    Query(params): Query<WebhookDeliveriesParams>,
+) -> Response {
+    let Some(webhooks) = &state.webhooks else {
+        return no_webhooks();
+    };
+    let deliveries = webhooks.deliveries(params.limit.unwrap_or(50).min(500));
+    Json(serde_json::json!({ "deliveries": deliveries })).into_response()
+}
+
+/// Send a test event to every endpoint (`admin:regions`)
+async fn admin_test_webhooks_handler(
+    State(state): State<OpenSimServerState>,
+    Extension(key): Extension<ApiKeyRecord>,
+) -> Response {
+    let Some(webhooks) = &state.webhooks else {
+        return no_webhooks();
+    };
+    let event_id = webhooks.fire(EVENT_TEST, serde_json::json!({ "requested_by": key.id }));
+    (StatusCode::ACCEPTED, Json(serde_json::json!({ "event_id": event_id }))).into_response()
+}
+
+fn no_webhooks() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Webhooks are disabled" })),
+    )
+        .into_response()
+}
+
+fn no_maintenance() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Maintenance mode is unavailable" })),
+    )
+        .into_response()
+}
+
+/// Query string for `/api/admin/usage/:user`
+#[derive(Debug, Deserialize)]
+struct UsageParams {
+    /// Days of finished sessions to count, 30 by default
+    #[serde(default)]
+    days: Option<i64>,
+}
+
+/// Bandwidth used so far by each open circuit (`read:stats`)
+async fn admin_usage_handler(State(state): State<OpenSimServerState>) -> Response {
+    let Some(bandwidth) = &state.bandwidth else {
+        return no_bandwidth();
+    };
+    Json(serde_json::json!({ "circuits": bandwidth.live().await })).into_response()
+}
+
+/// Bandwidth used by an avatar, given by ID or "First Last", in its open
+/// circuits and the sessions of the last days (`read:stats`)
+async fn admin_user_usage_handler(
+    State(state): State<OpenSimServerState>,
+    Path(user): Path<String>,
+    Query(params): Query<UsageParams>,
+) -> Response {
+    let Some(bandwidth) = &state.bandwidth else {
+        return no_bandwidth();
+    };
+    let agent = match uuid::Uuid::parse_str(&user) {
+        Ok(id) => Some(UserId::from_uuid(id)),
+        Err(_) => match user.split_once(' ') {
+            Some((first, last)) => state.login_service.get_user_by_name(first, last),
+            None => None,
+        },
+    };
+    let Some(agent) = agent else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": format!("Unknown avatar {}", user) })),
+        )
+            .into_response();
+    };
+    let since = chrono::Utc::now() - chrono::Duration::days(params.days.unwrap_or(30).clamp(1, 3650));
+    match bandwidth.report(agent, since).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e @ UsageError::Database(_)) => {
+            (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+        }
+    }
+}
+
+fn no_bandwidth() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "Bandwidth accounting is unavailable" })),
+    )
+        .into_response()
+}
+
+/// Issue request for the admin key API (`admin:keys`)
+#[derive(Debug, Deserialize)]
+struct IssueKeyRequest {
+    owner: uuid::Uuid,
+    name: String,
+    scopes: Vec<ApiScope>,
+}
+
+async fn admin_list_keys_handler(State(state): State<OpenSimServerState>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "keys": key_summaries(&state.api_keys.list(None)) }))
+}
+
+async fn admin_issue_key_handler(
+    State(state): State<OpenSimServerState>,
+    Json(request): Json<IssueKeyRequest>,
+) -> Response {
+    match state
+        .api_keys
+        .issue(mutsea_core::UserId::from_uuid(request.owner), &request.name, request.scopes)
+    {
+        // The plaintext key is returned once and never stored
+        Ok((record, key)) => (
+            StatusCode::CREATED,
+            Json(serde_json::json!({ "id": record.id, "key": key, "scopes": record.scopes })),
+        )
+            .into_response(),
+        Err(e) => admin_error_response(StatusCode::BAD_REQUEST, &e),
+    }
+}
+
+async fn admin_revoke_key_handler(State(state): State<OpenSimServerState>, Path(id): Path<String>) -> Response {
+    match state.api_keys.revoke(&id) {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "No active key with that ID" }))).into_response(),
+        Err(e) => admin_error_response(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
+
+/// Admin API error body: the message under `error` plus the error's stable
+/// code, category and context chain
+fn admin_error_response(status: StatusCode, error: &dyn ErrorClassification) -> Response {
+    let report = error.report();
+    tracing::warn!(code = %report.code, category = ?report.category, "admin API error: {}", report);
+    (
+        status,
+        Json(serde_json::json!({
+            "error": error.to_string(),
+            "code": report.code,
+            "category": report.category,
+            "context": report.context,
+        })),
+    )
+        .into_response()
+}
+
+fn key_summaries(keys: &[ApiKeyRecord]) -> Vec<serde_json::Value> {
+    keys.iter()
+        .map(|k| {
+            serde_json::json!({
+                "id": k.id,
+                "owner": k.owner.to_string(),
+                "name": k.name,
+                "scopes": k.scopes,
+                "created_at": k.created_at.to_rfc3339(),
+                "revoked_at": k.revoked_at.map(|t| t.to_rfc3339()),
+            })
+        })
+        .collect()
+}
+
+/// Health check handler
+async fn health_handler(State(state): State<OpenSimServerState>) -> Result<Response<Body>, StatusCode> {
+    let degraded = state.circuit_breakers.any_open();
+    let health_info = serde_json::json!({
+        "status": if degraded { "degraded" } else { "healthy" },
+        "service": "mutsea-opensim-server",
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "grid_name": state.config.opensim.grid_name,
+        "login_uri": state.config.opensim.login_uri,
+        "users_count": state.login_service.list_users().len(),
+        "online_count": state.login_service.online_count(),
+        "circuit_breakers": state.circuit_breakers.statuses(),
+        "uptime_secs": state.health.uptime_secs()
+    });
+
+    let response = Response::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&health_info).unwrap()))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(response)
+}
+
+/// Liveness probe: answers while the process can serve HTTP at all
+async fn health_live_handler(State(state): State<OpenSimServerState>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "status": "alive",
+        "uptime_secs": state.health.uptime_secs(),
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    }))
+}
+
+/// Readiness probe: per-subsystem health, 503 while any subsystem is
+/// unhealthy so orchestrators stop routing traffic here
+async fn health_ready_handler(State(state): State<OpenSimServerState>) -> impl IntoResponse {
+    let report = state.health.readiness().await;
+    let status = if report.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(report))
 }
\ No newline at end of file
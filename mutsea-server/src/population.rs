@@ -0,0 +1,510 @@
+//! Ambient NPC population
+//!
+//! Parcels are kept populated with ambient NPCs at the configured density,
+//! spawning and despawning them as parcels or rules change. Each NPC thinks
+//! in turn, a few per frame within the AI budget, so a crowd never holds
+//! up the simulation; NPCs left waiting go first next frame. What an NPC
+//! does is decided by the behavior definitions, or it wanders about home.
+//! NPC counts and what their thinking costs go to the performance
+//! analytics.
+
+use async_trait::async_trait;
+use chrono::{Timelike, Utc};
+use mutsea_core::config::PopulationConfig;
+use mutsea_core::scene::{Parcel, RegionScene};
+use mutsea_core::{MutseaResult, PerformanceRecorder, PerformanceSample, Vector3};
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::chat::{ChatFromSimulator, SAY_RANGE};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::behavior::{Action, BehaviorLibrary, NpcContext};
+use crate::npc_movement::NpcMovement;
+use crate::replay::ReplaySeeds;
+use crate::simulation::{FrameContext, SimulationSubsystem};
+
+/// Square metres in a hectare
+const HECTARE: f32 = 10_000.0;
+/// Name of the NPC RNG among replay seeds
+const SEED_SOURCE: &str = "population";
+
+/// Parcel bitmaps are 64 cells a side
+const BITMAP_CELLS: u32 = 64;
+
+/// Tries at finding somewhere walkable to spawn or wander to
+const PLACEMENT_ATTEMPTS: usize = 10;
+
+struct Ambient {
+    id: Uuid,
+    parcel_id: Uuid,
+    /// Where it spawned; it wanders around here
+    home: Vector3,
+    /// Simulated time it next thinks at
+    think_at: Duration,
+}
+
+/// AI cost since the last report
+#[derive(Default)]
+struct CostWindow {
+    frames: u64,
+    thoughts: u64,
+    deferred: u64,
+    busy: Duration,
+    max_frame: Duration,
+}
+
+struct Population {
+    /// Ambient NPCs in the order they think
+    npcs: VecDeque<Ambient>,
+    /// Simulated time since the manager started
+    clock: Duration,
+    rng: StdRng,
+    cost: CostWindow,
+}
+
+/// Keeps one region populated with ambient NPCs
+#[derive(Clone)]
+pub struct PopulationManager {
+    config: PopulationConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    movement: NpcMovement,
+    recorder: Option<Arc<dyn PerformanceRecorder>>,
+    behaviors: Option<Arc<BehaviorLibrary>>,
+    lludp_server: Option<LLUDPServer>,
+    seeds: Option<ReplaySeeds>,
+    state: Arc<Mutex<Population>>,
+    since_census: Duration,
+    since_report: Duration,
+}
+
+impl PopulationManager {
+    pub fn new(config: PopulationConfig, scene: Arc<RwLock<RegionScene>>, movement: NpcMovement) -> Self {
+        Self {
+            config,
+            scene,
+            movement,
+            recorder: None,
+            behaviors: None,
+            lludp_server: None,
+            seeds: None,
+            state: Arc::new(Mutex::new(Population {
+                npcs: VecDeque::new(),
+                clock: Duration::ZERO,
+                rng: StdRng::from_entropy(),
+                cost: CostWindow::default(),
+            })),
+            // The first census runs on the first frame
+            since_census: Duration::MAX,
+            since_report: Duration::ZERO,
+        }
+    }
+
+    /// Report NPC counts and AI cost to `recorder`
+    pub fn with_recorder(mut self, recorder: Arc<dyn PerformanceRecorder>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Decide what NPCs do with `behaviors`
+    pub fn with_behaviors(mut self, behaviors: Arc<BehaviorLibrary>) -> Self {
+        self.behaviors = Some(behaviors);
+        self
+    }
+
+    /// Let NPCs speak in local chat on `lludp_server`
+    pub fn with_chat(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Reseed the NPCs' RNG from `seeds` so runs can be replayed
+    pub fn with_seeds(mut self, seeds: ReplaySeeds) -> Self {
+        seeds.register(SEED_SOURCE);
+        self.seeds = Some(seeds);
+        self
+    }
+
+    /// How many ambient NPCs each parcel should have, most densely
+    /// populated parcels first when the region's limit is reached
+    fn targets(&self, parcels: &[Parcel]) -> HashMap<Uuid, usize> {
+        let mut wanted: Vec<(Uuid, f32)> = parcels
+            .iter()
+            .map(|parcel| {
+                let density = self.config.parcel_density.get(&parcel.name).copied().unwrap_or(self.config.density);
+                (parcel.id, density * parcel.area as f32 / HECTARE)
+            })
+            .collect();
+        wanted.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let mut remaining = self.config.max_npcs;
+        wanted
+            .into_iter()
+            .map(|(parcel_id, count)| {
+                let count = (count.round() as usize).min(remaining);
+                remaining -= count;
+                (parcel_id, count)
+            })
+            .collect()
+    }
+
+    /// Spawn and despawn NPCs to match each parcel's target
+    async fn census(&self) {
+        let (parcels, cell_x, cell_y) = {
+            let scene = self.scene.read().await;
+            let cell_x = (scene.info.size_x / BITMAP_CELLS).max(1) as f32;
+            let cell_y = (scene.info.size_y / BITMAP_CELLS).max(1) as f32;
+            (scene.parcels.clone(), cell_x, cell_y)
+        };
+        let targets = self.targets(&parcels);
+
+        let mut state = self.state.lock().unwrap();
+        let Population { npcs, clock, rng, .. } = &mut *state;
+
+        // NPCs over their parcel's target, or whose parcel is gone, leave
+        let mut counts: HashMap<Uuid, usize> = HashMap::new();
+        npcs.retain(|npc| {
+            let count = counts.entry(npc.parcel_id).or_default();
+            if *count < targets.get(&npc.parcel_id).copied().unwrap_or(0) {
+                *count += 1;
+                return true;
+            }
+            self.movement.remove_npc(npc.id);
+            debug!("Despawned ambient NPC {}", npc.id);
+            false
+        });
+
+        for parcel in &parcels {
+            let missing = targets[&parcel.id].saturating_sub(counts.get(&parcel.id).copied().unwrap_or(0));
+            let cells = parcel_cells(parcel);
+            if missing == 0 || cells.is_empty() {
+                continue;
+            }
+            for _ in 0..missing {
+                let spot = (0..PLACEMENT_ATTEMPTS)
+                    .map(|_| {
+                        let (x, y) = cells[rng.gen_range(0..cells.len())];
+                        Vector3::new(
+                            (x as f32 + rng.gen::<f32>()) * cell_x,
+                            (y as f32 + rng.gen::<f32>()) * cell_y,
+                            0.0,
+                        )
+                    })
+                    .find(|spot| self.movement.is_walkable(*spot));
+                let Some(home) = spot else {
+                    debug!("No room to spawn an ambient NPC in {}", parcel.name);
+                    break;
+                };
+
+                let id = Uuid::new_v4();
+                self.movement.add_npc(id, home);
+                npcs.push_back(Ambient {
+                    id,
+                    parcel_id: parcel.id,
+                    home,
+                    // Spread first thoughts out so NPCs don't all think at once
+                    think_at: *clock + self.think_interval().mul_f32(rng.gen()),
+                });
+                debug!("Spawned ambient NPC {} in {}", id, parcel.name);
+            }
+        }
+    }
+
+    fn think_interval(&self) -> Duration {
+        Duration::from_secs(self.config.think_interval_secs.max(1))
+    }
+
+    /// Let NPCs due to think do so until the frame's AI budget is spent
+    fn think(&self, dt: Duration) {
+        let budget = Duration::from_secs_f64(self.config.ai_budget_ms / 1000.0);
+        let interval = self.think_interval();
+        let started = Instant::now();
+
+        let mut state = self.state.lock().unwrap();
+        let Population {
+            npcs, clock, rng, cost,
+        } = &mut *state;
+        *clock += dt;
+
+        let mut thoughts = 0;
+        for turn in 0..npcs.len() {
+            if started.elapsed() >= budget {
+                // Those still due keep their place at the front
+                cost.deferred += npcs.iter().take(npcs.len() - turn).filter(|npc| npc.think_at <= *clock).count() as u64;
+                break;
+            }
+            let mut npc = npcs.pop_front().unwrap();
+            if npc.think_at <= *clock {
+                self.act(&npc, rng);
+                npc.think_at = *clock + interval;
+                thoughts += 1;
+            }
+            npcs.push_back(npc);
+        }
+
+        let busy = started.elapsed();
+        cost.frames += 1;
+        cost.thoughts += thoughts;
+        cost.busy += busy;
+        cost.max_frame = cost.max_frame.max(busy);
+    }
+
+    /// Do what the behaviors decide, or wander when none applies
+    fn act(&self, npc: &Ambient, rng: &mut StdRng) {
+        let Some(behaviors) = &self.behaviors else {
+            return self.wander(npc, self.config.wander_radius, rng);
+        };
+        let position = self.movement.position(npc.id).unwrap_or(npc.home);
+        let walking = self.movement.is_walking(npc.id);
+        let facts = HashMap::from([
+            ("walking".to_string(), if walking { 1.0 } else { 0.0 }),
+            ("distance_from_home".to_string(), (position.x - npc.home.x).hypot(position.y - npc.home.y)),
+            ("hour".to_string(), Utc::now().hour() as f32),
+        ]);
+        let context = NpcContext {
+            goals: &[],
+            patterns: &[],
+            facts: &facts,
+        };
+        let Some(decision) = behaviors.decide(&context, rng) else {
+            return self.wander(npc, self.config.wander_radius, rng);
+        };
+
+        debug!("Ambient NPC {} chose {} ({:.2})", npc.id, decision.behavior, decision.score);
+        for action in decision.actions {
+            let walk = match action {
+                Action::MoveTo { x, y } => self.movement.go_to(npc.id, Vector3::new(x, y, 0.0)).err(),
+                Action::GoHome => self.movement.go_to(npc.id, npc.home).err(),
+                Action::Wander { radius } => {
+                    self.wander(npc, radius, rng);
+                    None
+                }
+                Action::Say { message } => {
+                    self.say(npc.id, position, &message);
+                    None
+                }
+                // Nothing in the region reacts to NPCs using objects yet
+                Action::Interact { object } => {
+                    debug!("Ambient NPC {} uses {}", npc.id, object);
+                    None
+                }
+            };
+            if let Some(e) = walk {
+                debug!("Ambient NPC {} stays put: {}", npc.id, e);
+            }
+        }
+    }
+
+    /// Say `message` in local chat as NPC `id`
+    fn say(&self, id: Uuid, position: Vector3, message: &str) {
+        let Some(lludp_server) = self.lludp_server.clone() else {
+            return;
+        };
+        let chat = ChatFromSimulator::said_by_object(&self.config.name, id, position, message);
+        tokio::spawn(async move {
+            if let Err(e) = lludp_server.send_local_chat(&chat, SAY_RANGE).await {
+                warn!("Ambient NPC {} could not speak: {}", id, e);
+            }
+        });
+    }
+
+    /// Send an idle NPC somewhere within `radius` of home
+    fn wander(&self, npc: &Ambient, radius: f32, rng: &mut StdRng) {
+        if self.movement.is_walking(npc.id) {
+            return;
+        }
+        let radius = radius.abs();
+        for _ in 0..PLACEMENT_ATTEMPTS {
+            let target = Vector3::new(
+                npc.home.x + rng.gen_range(-radius..=radius),
+                npc.home.y + rng.gen_range(-radius..=radius),
+                0.0,
+            );
+            if self.movement.go_to(npc.id, target).is_ok() {
+                return;
+            }
+        }
+    }
+
+    /// Rows for the `performance_metrics` table, starting a new window
+    fn samples(&self) -> Vec<PerformanceSample> {
+        let mut state = self.state.lock().unwrap();
+        let npcs = state.npcs.len();
+        let cost = std::mem::take(&mut state.cost);
+
+        let now = Utc::now();
+        let context = json!({ "frames": cost.frames, "thoughts": cost.thoughts });
+        let alert_level = if cost.deferred > 0 { "warning" } else { "info" };
+        let frame_avg_ms = cost.busy.as_secs_f64() * 1000.0 / cost.frames.max(1) as f64;
+        [
+            ("npc_count", npcs as f64, "count"),
+            ("ai_frame_avg_ms", frame_avg_ms, "ms"),
+            ("ai_frame_max_ms", cost.max_frame.as_secs_f64() * 1000.0, "ms"),
+            ("deferred_thoughts", cost.deferred as f64, "count"),
+        ]
+        .into_iter()
+        .map(|(metric, value, unit)| PerformanceSample {
+            timestamp: now,
+            component_name: "population".to_string(),
+            metric_type: metric.to_string(),
+            metric_value: value,
+            unit_of_measure: unit.to_string(),
+            context_data: context.clone(),
+            alert_level: Some(alert_level.to_string()),
+        })
+        .collect()
+    }
+
+    fn report(&self) {
+        let samples = self.samples();
+        let Some(recorder) = self.recorder.clone() else {
+            return;
+        };
+        // Never hold up the frame on the database
+        tokio::spawn(async move {
+            if let Err(e) = recorder.record_performance(&samples).await {
+                warn!("Failed to record population analytics: {}", e);
+            }
+        });
+    }
+}
+
+/// Bitmap cells belonging to `parcel`
+fn parcel_cells(parcel: &Parcel) -> Vec<(u32, u32)> {
+    (0..BITMAP_CELLS * BITMAP_CELLS)
+        .filter(|bit| parcel.bitmap.get((bit / 8) as usize).is_some_and(|byte| byte & (1 << (bit % 8)) != 0))
+        .map(|bit| (bit % BITMAP_CELLS, bit / BITMAP_CELLS))
+        .collect()
+}
+
+#[async_trait]
+impl SimulationSubsystem for PopulationManager {
+    async fn tick(&mut self, frame: &FrameContext) -> MutseaResult<()> {
+        if let Some(seed) = self.seeds.as_ref().and_then(|seeds| seeds.take(SEED_SOURCE)) {
+            self.state.lock().unwrap().rng = StdRng::seed_from_u64(seed);
+        }
+        self.since_census = self.since_census.saturating_add(frame.dt);
+        if self.since_census >= Duration::from_secs(self.config.census_interval_secs.max(1)) {
+            self.since_census = Duration::ZERO;
+            self.census().await;
+        }
+
+        self.think(frame.dt);
+
+        self.since_report += frame.dt;
+        if self.since_report >= Duration::from_secs(self.config.report_interval_secs.max(1)) {
+            self.since_report = Duration::ZERO;
+            self.report();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::config::NavigationConfig;
+    use mutsea_core::{RegionInfo, UserId};
+
+    async fn manager(config: PopulationConfig) -> PopulationManager {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let movement = NpcMovement::new(NavigationConfig::default(), Arc::clone(&scene)).await;
+        PopulationManager::new(config, scene, movement)
+    }
+
+    fn frame(frame: u64) -> FrameContext {
+        FrameContext {
+            frame,
+            dt: Duration::from_secs(1),
+            degrade_level: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parcels_are_populated_by_density() {
+        let mut config = PopulationConfig {
+            enabled: true,
+            density: 1.0,
+            ..PopulationConfig::default()
+        };
+        config.parcel_density.insert("Market".to_string(), 10.0);
+        let mut manager = manager(config).await;
+
+        // A whole 256 m region is 6.55 hectares
+        manager.tick(&frame(0)).await.unwrap();
+        assert_eq!(manager.movement.npcs().len(), 7);
+
+        // The busy market fills up to the region's limit
+        manager.scene.write().await.parcels[0].name = "Market".to_string();
+        let parcels = manager.scene.read().await.parcels.clone();
+        assert_eq!(manager.targets(&parcels)[&parcels[0].id], 20);
+        manager.census().await;
+        assert_eq!(manager.movement.npcs().len(), 20);
+
+        // Emptied parcels lose their NPCs
+        manager.scene.write().await.parcels[0].name = "Quiet".to_string();
+        manager.config.parcel_density.insert("Quiet".to_string(), 0.0);
+        manager.census().await;
+        assert!(manager.movement.npcs().is_empty());
+        assert!(manager.state.lock().unwrap().npcs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_npcs_think_within_the_ai_budget() {
+        let mut manager = manager(PopulationConfig {
+            enabled: true,
+            density: 2.0,
+            ai_budget_ms: 1000.0,
+            think_interval_secs: 1,
+            ..PopulationConfig::default()
+        })
+        .await;
+        manager.census().await;
+        for n in 0..3 {
+            manager.tick(&frame(n)).await.unwrap();
+        }
+        assert!(manager.movement.npcs().iter().any(|npc| npc.goal.is_some()));
+        let samples = manager.samples();
+        assert_eq!(samples[0].metric_type, "npc_count");
+        assert_eq!(samples[0].metric_value, 13.0);
+        assert_eq!(samples[3].metric_value, 0.0);
+
+        // With no budget every due NPC waits
+        manager.config.ai_budget_ms = 0.0;
+        manager.state.lock().unwrap().clock += Duration::from_secs(5);
+        manager.think(Duration::from_secs(1));
+        let samples = manager.samples();
+        assert_eq!(samples[3].metric_value, 13.0);
+        assert_eq!(samples[3].alert_level.as_deref(), Some("warning"));
+    }
+
+    #[tokio::test]
+    async fn test_behaviors_decide_what_npcs_do() {
+        let dir = std::env::temp_dir().join(format!("mutsea-population-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let gather = "[[behaviors]]\nname = \"gather\"\nbase_score = 1.0\ntree = { action = { type = \"move_to\", x = 128.0, y = 128.0 } }\n";
+        std::fs::write(dir.join("gather.toml"), gather).unwrap();
+
+        let manager = manager(PopulationConfig {
+            enabled: true,
+            ai_budget_ms: 1000.0,
+            ..PopulationConfig::default()
+        })
+        .await
+        .with_behaviors(Arc::new(BehaviorLibrary::load(&dir).unwrap()));
+        manager.census().await;
+        manager.think(Duration::from_secs(10));
+
+        let npcs = manager.movement.npcs();
+        assert_eq!(npcs.len(), 3);
+        assert!(npcs.iter().all(|npc| npc.goal == Some(Vector3::new(128.0, 128.0, 0.0))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
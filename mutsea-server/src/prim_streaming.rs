@@ -0,0 +1,378 @@
+//! Streaming a region's prims to arriving agents
+//!
+//! An agent logging into a large region would otherwise wait for every
+//! prim before it sees the ones around it. Instead each arriving agent gets
+//! a queue of the region's prims, nearest first, with small prims far away
+//! deferred until everything else is sent. Each simulation frame sends a
+//! few from every queue, within the circuit's object throttle, so the rest
+//! of the region fills in in the background. Progress is kept per circuit
+//! to tune the settings by. Prims a viewer turns out not to have cached
+//! are sent in full when it asks for them.
+
+use async_trait::async_trait;
+use mutsea_core::config::PrimStreamingConfig;
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{MutseaResult, ObjectId, SceneObject, UserId, Vector3};
+use mutsea_network::LLUDPServer;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::attachments::Attachments;
+use crate::simulation::{FrameContext, SimulationSubsystem};
+
+/// Where an agent is assumed to be before the region knows its position
+const ARRIVAL_POSITION: Vector3 = Vector3 {
+    x: 128.0,
+    y: 128.0,
+    z: 25.0,
+};
+
+/// How far the prims of one region have reached an agent
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StreamProgress {
+    pub agent_id: UserId,
+    pub circuit_code: u32,
+    /// Prims queued on arrival
+    pub total: usize,
+    /// Prims sent, or skipped because they left the region
+    pub sent: usize,
+    /// Prims held back as small and distant
+    pub deferred: usize,
+    /// Frames the circuit's object throttle cut short
+    pub throttled: u64,
+    /// Seconds until every prim but the deferred ones was sent
+    pub priority_secs: Option<f64>,
+    /// Seconds since arrival, or until the last prim was sent
+    pub elapsed_secs: f64,
+    /// Whether every prim was sent, rather than the agent leaving first
+    pub completed: bool,
+}
+
+struct Stream {
+    queue: VecDeque<ObjectId>,
+    started: Instant,
+    progress: StreamProgress,
+}
+
+impl Stream {
+    fn update_elapsed(&mut self) {
+        self.progress.elapsed_secs = self.started.elapsed().as_secs_f64();
+        if self.progress.priority_secs.is_none() && self.progress.sent >= self.progress.total - self.progress.deferred {
+            self.progress.priority_secs = Some(self.progress.elapsed_secs);
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    streams: HashMap<UserId, Stream>,
+    /// Streams that ended, oldest first
+    finished: VecDeque<StreamProgress>,
+}
+
+/// Prims streamed to the agents arriving in one region
+#[derive(Clone)]
+pub struct PrimStreaming {
+    config: PrimStreamingConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: Option<LLUDPServer>,
+    attachments: Option<Attachments>,
+    state: Arc<Mutex<State>>,
+}
+
+impl PrimStreaming {
+    pub fn new(config: PrimStreamingConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            config,
+            scene,
+            lludp_server: None,
+            attachments: None,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Stream prims to the agents arriving through `lludp_server`
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Leave out the prims `attachments` says are worn, which it sends
+    /// with their wearer
+    pub fn with_attachments(mut self, attachments: Attachments) -> Self {
+        self.attachments = Some(attachments);
+        self
+    }
+
+    /// Start and stop streams as agents arrive and leave, and answer their
+    /// cache misses
+    pub fn spawn(&self) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let streaming = self.clone();
+        let lludp_server = lludp_server.clone();
+        let mut events = lludp_server.subscribe_events();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => match event.event_data {
+                        NetworkEventData::AgentLoggedIn {
+                            circuit_code, agent_id, ..
+                        } => {
+                            let position = lludp_server.agent_position(agent_id).await;
+                            streaming
+                                .start(agent_id, circuit_code, position.unwrap_or(ARRIVAL_POSITION))
+                                .await;
+                        }
+                        NetworkEventData::ObjectsRequested {
+                            agent_id, local_ids, ..
+                        } => {
+                            let objects = streaming.objects_by_local_id(&local_ids).await;
+                            if let Err(e) = lludp_server.send_cache_misses(agent_id, &objects).await {
+                                warn!("Failed to send {} missed prims to {}: {}", objects.len(), agent_id, e);
+                            }
+                        }
+                        NetworkEventData::AgentLoggedOut { agent_id, .. } => streaming.stop(agent_id),
+                        _ => {}
+                    },
+                    Err(RecvError::Lagged(missed)) => warn!("Prim streaming missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// The region's prims with `local_ids`, skipping any gone
+    async fn objects_by_local_id(&self, local_ids: &[u32]) -> Vec<SceneObject> {
+        let scene = self.scene.read().await;
+        scene
+            .objects
+            .values()
+            .filter(|object| local_ids.contains(&object.local_id))
+            .cloned()
+            .collect()
+    }
+
+    /// Queue the region's prims for `agent`, arriving at `position` over
+    /// `circuit_code`, replacing any stream it had
+    pub async fn start(&self, agent: UserId, circuit_code: u32, position: Vector3) {
+        let (queue, deferred) = {
+            let scene = self.scene.read().await;
+            self.plan(scene.objects.values(), position)
+        };
+        debug!(
+            "Streaming {} prims to {}, {} of them deferred",
+            queue.len(),
+            agent,
+            deferred
+        );
+        let mut stream = Stream {
+            progress: StreamProgress {
+                agent_id: agent,
+                circuit_code,
+                total: queue.len(),
+                sent: 0,
+                deferred,
+                throttled: 0,
+                priority_secs: None,
+                elapsed_secs: 0.0,
+                completed: false,
+            },
+            queue,
+            started: Instant::now(),
+        };
+        stream.update_elapsed();
+        self.state.lock().unwrap().streams.insert(agent, stream);
+    }
+
+    /// Stop streaming to `agent`, which left
+    pub fn stop(&self, agent: UserId) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(mut stream) = state.streams.remove(&agent) {
+            stream.update_elapsed();
+            self.finish(&mut state, stream.progress);
+        }
+    }
+
+    /// The streams under way
+    pub fn progress(&self) -> Vec<StreamProgress> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .streams
+            .values_mut()
+            .map(|stream| {
+                stream.update_elapsed();
+                stream.progress.clone()
+            })
+            .collect()
+    }
+
+    /// The latest streams that ended, oldest first
+    pub fn finished(&self) -> Vec<StreamProgress> {
+        self.state.lock().unwrap().finished.iter().cloned().collect()
+    }
+
+    /// The order the prims in `objects` are sent to an agent at `position`:
+    /// nearest first, then the small distant ones, nearest first. Returns
+    /// the order and how many were deferred.
+    fn plan<'a>(
+        &self,
+        objects: impl Iterator<Item = &'a SceneObject>,
+        position: Vector3,
+    ) -> (VecDeque<ObjectId>, usize) {
+        let mut ranked: Vec<(bool, f32, ObjectId)> = objects
+            .filter(|object| match &self.attachments {
+                Some(attachments) => attachments.wearer_of(object.id).is_none(),
+                None => true,
+            })
+            .map(|object| {
+                let distance = (object.position - position).length();
+                let size = object.scale.x.max(object.scale.y).max(object.scale.z);
+                let deferred = distance > self.config.near_radius && size < self.config.small_prim_size;
+                (deferred, distance, object.id)
+            })
+            .collect();
+        ranked.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+        let deferred = ranked.iter().filter(|(deferred, _, _)| *deferred).count();
+        (ranked.into_iter().map(|(_, _, id)| id).collect(), deferred)
+    }
+
+    /// Send each stream its next prims, ending those that are done
+    async fn pump(&self, lludp_server: &LLUDPServer) {
+        let agents: Vec<UserId> = self.state.lock().unwrap().streams.keys().copied().collect();
+        for agent in agents {
+            let (started, batch) = {
+                let mut state = self.state.lock().unwrap();
+                let Some(stream) = state.streams.get_mut(&agent) else {
+                    continue;
+                };
+                let count = self.config.prims_per_tick.min(stream.queue.len());
+                (stream.started, stream.queue.drain(..count).collect::<Vec<_>>())
+            };
+
+            let mut sent = 0;
+            let mut throttled = false;
+            let mut gone = false;
+            for id in &batch {
+                let object = self.scene.read().await.objects.get(id).cloned();
+                let Some(object) = object else {
+                    sent += 1;
+                    continue;
+                };
+                match lludp_server.send_object_update_to(agent, &object).await {
+                    Ok(true) => sent += 1,
+                    Ok(false) => {
+                        throttled = true;
+                        break;
+                    }
+                    Err(e) => {
+                        debug!("Stopped streaming prims to {}: {}", agent, e);
+                        gone = true;
+                        break;
+                    }
+                }
+            }
+
+            let mut state = self.state.lock().unwrap();
+            // The agent may have left or arrived again meanwhile
+            let Some(stream) = state.streams.get_mut(&agent).filter(|stream| stream.started == started) else {
+                continue;
+            };
+            for id in batch[sent..].iter().rev() {
+                stream.queue.push_front(*id);
+            }
+            stream.progress.sent += sent;
+            stream.progress.throttled += throttled as u64;
+            stream.update_elapsed();
+            if gone || stream.queue.is_empty() {
+                let mut stream = state.streams.remove(&agent).unwrap();
+                stream.progress.completed = !gone;
+                if !gone {
+                    info!(
+                        "Streamed {} prims to {} in {:.1}s",
+                        stream.progress.total, agent, stream.progress.elapsed_secs
+                    );
+                }
+                self.finish(&mut state, stream.progress);
+            }
+        }
+    }
+
+    fn finish(&self, state: &mut State, progress: StreamProgress) {
+        state.finished.push_back(progress);
+        while state.finished.len() > self.config.keep_finished {
+            state.finished.pop_front();
+        }
+    }
+}
+
+#[async_trait]
+impl SimulationSubsystem for PrimStreaming {
+    async fn tick(&mut self, _frame: &FrameContext) -> MutseaResult<()> {
+        if let Some(lludp_server) = &self.lludp_server {
+            self.pump(lludp_server).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use mutsea_core::RegionInfo;
+
+    fn prim(position: Vector3, size: f32) -> SceneObject {
+        SceneObject {
+            scale: Vector3::new(size, size, size),
+            ..test_support::prim(1, UserId::new(), position)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_nearest_prims_first_and_small_distant_ones_last() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let prims = [
+            prim(Vector3::new(200.0, 128.0, 25.0), 0.1),
+            prim(Vector3::new(200.0, 128.0, 25.0), 10.0),
+            prim(Vector3::new(130.0, 128.0, 25.0), 0.1),
+            prim(Vector3::new(250.0, 128.0, 25.0), 0.2),
+            prim(Vector3::new(128.0, 128.0, 25.0), 1.0),
+        ];
+        let ids: Vec<ObjectId> = prims.iter().map(|prim| prim.id).collect();
+        for prim in prims {
+            scene.write().await.add_object(prim);
+        }
+        let streaming = PrimStreaming::new(PrimStreamingConfig::default(), Arc::clone(&scene));
+        let agent = UserId::new();
+
+        streaming.start(agent, 7, ARRIVAL_POSITION).await;
+        let order: Vec<ObjectId> = streaming.state.lock().unwrap().streams[&agent]
+            .queue
+            .iter()
+            .copied()
+            .collect();
+        assert_eq!(order, vec![ids[4], ids[2], ids[1], ids[0], ids[3]]);
+
+        let progress = streaming.progress();
+        assert_eq!(
+            (progress[0].circuit_code, progress[0].total, progress[0].deferred),
+            (7, 5, 2)
+        );
+        assert_eq!(progress[0].priority_secs, None);
+
+        streaming.stop(agent);
+        assert!(streaming.progress().is_empty());
+        let finished = streaming.finished();
+        assert_eq!((finished.len(), finished[0].sent, finished[0].completed), (1, 0, false));
+    }
+}
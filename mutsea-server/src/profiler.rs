@@ -0,0 +1,376 @@
+//! Simulation tick profiler and lag meter
+//!
+//! Samples the time spent in each section of every simulation tick
+//! (physics, scripts, network sends, database flushes, ...), aggregates the
+//! samples into `performance_metrics` rows on an interval, and dumps folded
+//! stacks for ticks that run over budget so they can be rendered as
+//! flamegraphs.
+
+use mutsea_core::{config::ProfilerConfig, PerformanceRecorder, PerformanceSample};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Root frame of every folded stack
+const ROOT_FRAME: &str = "tick";
+
+/// Aggregated timings for one profiled section
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SectionTiming {
+    pub samples: u64,
+    pub total_ms: f64,
+    pub max_ms: f64,
+}
+
+impl SectionTiming {
+    fn record(&mut self, elapsed: Duration) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        self.samples += 1;
+        self.total_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+    }
+
+    /// Mean time per sample in milliseconds
+    pub fn avg_ms(&self) -> f64 {
+        if self.samples == 0 {
+            0.0
+        } else {
+            self.total_ms / self.samples as f64
+        }
+    }
+}
+
+/// Lag meter: tick timings since the last flush
+#[derive(Debug, Clone, Serialize)]
+pub struct LagMeter {
+    pub window_start: chrono::DateTime<chrono::Utc>,
+    pub ticks: u64,
+    pub over_budget_ticks: u64,
+    pub tick: SectionTiming,
+    pub sections: BTreeMap<String, SectionTiming>,
+}
+
+impl LagMeter {
+    fn new() -> Self {
+        Self {
+            window_start: chrono::Utc::now(),
+            ticks: 0,
+            over_budget_ticks: 0,
+            tick: SectionTiming::default(),
+            sections: BTreeMap::new(),
+        }
+    }
+
+    /// Fraction of ticks that ran over budget
+    pub fn over_budget_ratio(&self) -> f64 {
+        if self.ticks == 0 {
+            0.0
+        } else {
+            self.over_budget_ticks as f64 / self.ticks as f64
+        }
+    }
+
+    /// Section with the most total time, if any
+    pub fn heaviest_section(&self) -> Option<(&str, &SectionTiming)> {
+        self.sections
+            .iter()
+            .max_by(|a, b| a.1.total_ms.total_cmp(&b.1.total_ms))
+            .map(|(name, timing)| (name.as_str(), timing))
+    }
+
+    /// Rows for the `performance_metrics` table
+    pub fn to_samples(&self) -> Vec<PerformanceSample> {
+        let now = chrono::Utc::now();
+        let window = serde_json::json!({
+            "window_start": self.window_start.to_rfc3339(),
+            "ticks": self.ticks,
+        });
+        let alert_level = match self.over_budget_ratio() {
+            r if r > 0.25 => "critical",
+            r if r > 0.05 => "warning",
+            _ => "info",
+        };
+
+        let sample = |component: String, metric: &str, value: f64, unit: &str, context: serde_json::Value| {
+            PerformanceSample {
+                timestamp: now,
+                component_name: component,
+                metric_type: metric.to_string(),
+                metric_value: value,
+                unit_of_measure: unit.to_string(),
+                context_data: context,
+                alert_level: Some(alert_level.to_string()),
+            }
+        };
+
+        let mut samples = vec![
+            sample("simulation".to_string(), "tick_avg_ms", self.tick.avg_ms(), "ms", window.clone()),
+            sample("simulation".to_string(), "tick_max_ms", self.tick.max_ms, "ms", window.clone()),
+            sample(
+                "simulation".to_string(),
+                "over_budget_ticks",
+                self.over_budget_ticks as f64,
+                "count",
+                window.clone(),
+            ),
+        ];
+
+        for (name, timing) in &self.sections {
+            let context = serde_json::json!({
+                "window_start": self.window_start.to_rfc3339(),
+                "ticks": self.ticks,
+                "samples": timing.samples,
+                "total_ms": timing.total_ms,
+                "max_ms": timing.max_ms,
+            });
+            samples.push(sample(format!("simulation.{}", name), "tick_avg_ms", timing.avg_ms(), "ms", context));
+        }
+
+        samples
+    }
+}
+
+/// Per-tick section profiler shared by the simulation loop and anything
+/// else that does work on behalf of a tick
+pub struct TickProfiler {
+    config: ProfilerConfig,
+    current: Mutex<Vec<(String, Duration)>>,
+    window: Mutex<LagMeter>,
+    running: Arc<AtomicBool>,
+}
+
+impl TickProfiler {
+    /// Create a profiler
+    pub fn new(config: ProfilerConfig) -> Self {
+        Self {
+            config,
+            current: Mutex::new(Vec::new()),
+            window: Mutex::new(LagMeter::new()),
+            running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Attribute `elapsed` to `section` in the current tick. Nested
+    /// sections are separated by `;`, e.g. `net_send;object_updates`.
+    pub fn record(&self, section: &str, elapsed: Duration) {
+        self.current.lock().unwrap().push((section.to_string(), elapsed));
+    }
+
+    /// Time a section until the returned guard is dropped
+    pub fn scope<'a>(&'a self, section: &'a str) -> ProfileScope<'a> {
+        ProfileScope {
+            profiler: self,
+            section,
+            started: Instant::now(),
+        }
+    }
+
+    /// Close the current tick. Its sections are folded into the lag meter,
+    /// and if it ran over `budget` its stacks are dumped for a flamegraph.
+    pub fn finish_tick(&self, frame: u64, frame_time: Duration, budget: Duration) {
+        let sections = std::mem::take(&mut *self.current.lock().unwrap());
+        let over_budget = frame_time > budget;
+
+        {
+            let mut window = self.window.lock().unwrap();
+            window.ticks += 1;
+            window.tick.record(frame_time);
+            if over_budget {
+                window.over_budget_ticks += 1;
+            }
+            for (section, elapsed) in &sections {
+                window.sections.entry(section.clone()).or_default().record(*elapsed);
+            }
+        }
+
+        if over_budget && self.config.max_flamegraphs > 0 {
+            let folded = folded_stacks(&sections, frame_time);
+            let dir = PathBuf::from(&self.config.flamegraph_dir);
+            let keep = self.config.max_flamegraphs;
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = write_dump(&dir, frame, &folded, keep) {
+                    warn!("Failed to write tick profile: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Current lag meter window
+    pub fn lag_meter(&self) -> LagMeter {
+        self.window.lock().unwrap().clone()
+    }
+
+    /// Close the current window and return it
+    pub fn take_window(&self) -> LagMeter {
+        std::mem::replace(&mut *self.window.lock().unwrap(), LagMeter::new())
+    }
+
+    /// Flush aggregated samples every `flush_interval_secs`, to `recorder`
+    /// if one is given
+    pub fn start(self: &Arc<Self>, recorder: Option<Arc<dyn PerformanceRecorder>>) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let profiler = Arc::clone(self);
+        let interval_secs = self.config.flush_interval_secs.max(1);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            interval.tick().await;
+
+            while profiler.running.load(Ordering::SeqCst) {
+                interval.tick().await;
+                let window = profiler.take_window();
+                if window.ticks == 0 {
+                    continue;
+                }
+
+                if let Some((name, timing)) = window.heaviest_section() {
+                    debug!(
+                        "Lag meter: {} ticks, {} over budget, avg {:.2} ms, heaviest {} ({:.2} ms avg)",
+                        window.ticks,
+                        window.over_budget_ticks,
+                        window.tick.avg_ms(),
+                        name,
+                        timing.avg_ms()
+                    );
+                }
+
+                if let Some(recorder) = &recorder {
+                    if let Err(e) = recorder.record_performance(&window.to_samples()).await {
+                        warn!("Failed to record tick profile: {}", e);
+                    }
+                }
+            }
+        });
+
+        info!("Tick profiler started, flushing every {}s", interval_secs);
+    }
+
+    /// Stop the flush task
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Guard returned by [`TickProfiler::scope`]
+pub struct ProfileScope<'a> {
+    profiler: &'a TickProfiler,
+    section: &'a str,
+    started: Instant,
+}
+
+impl Drop for ProfileScope<'_> {
+    fn drop(&mut self) {
+        self.profiler.record(self.section, self.started.elapsed());
+    }
+}
+
+/// Folded stacks (`frame;frame microseconds` per line) for one tick. Time
+/// not covered by any section is attributed to the root frame.
+pub fn folded_stacks(sections: &[(String, Duration)], frame_time: Duration) -> String {
+    let mut totals: BTreeMap<&str, Duration> = BTreeMap::new();
+    for (section, elapsed) in sections {
+        *totals.entry(section.as_str()).or_default() += *elapsed;
+    }
+
+    let covered: Duration = totals.values().sum();
+    let mut folded = String::new();
+    let untracked = frame_time.saturating_sub(covered).as_micros();
+    if untracked > 0 {
+        folded.push_str(&format!("{} {}\n", ROOT_FRAME, untracked));
+    }
+    for (section, elapsed) in totals {
+        folded.push_str(&format!("{};{} {}\n", ROOT_FRAME, section, elapsed.as_micros()));
+    }
+    folded
+}
+
+/// Write a folded-stack dump and delete all but the newest `keep`
+fn write_dump(dir: &Path, frame: u64, folded: &str, keep: usize) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!(
+        "tick_{}_{:010}.folded",
+        chrono::Utc::now().format("%Y%m%d-%H%M%S"),
+        frame
+    ));
+    std::fs::write(&path, folded)?;
+
+    let mut dumps: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "folded"))
+        .collect();
+    dumps.sort();
+    let excess = dumps.len().saturating_sub(keep);
+    for old in dumps.into_iter().take(excess) {
+        std::fs::remove_file(old)?;
+    }
+
+    debug!("Wrote tick profile {}", path.display());
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ProfilerConfig {
+        ProfilerConfig {
+            max_flamegraphs: 0,
+            ..ProfilerConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_folded_stacks_include_untracked_time() {
+        let sections = vec![
+            ("physics".to_string(), Duration::from_millis(30)),
+            ("net_send".to_string(), Duration::from_millis(5)),
+            ("physics".to_string(), Duration::from_millis(10)),
+        ];
+        let folded = folded_stacks(&sections, Duration::from_millis(50));
+        assert_eq!(folded, "tick 5000\ntick;net_send 5000\ntick;physics 40000\n");
+    }
+
+    #[test]
+    fn test_lag_meter_aggregates_ticks() {
+        let profiler = TickProfiler::new(config());
+        let budget = Duration::from_millis(20);
+
+        profiler.record("physics", Duration::from_millis(4));
+        profiler.finish_tick(0, Duration::from_millis(10), budget);
+        {
+            let _scope = profiler.scope("scripts");
+        }
+        profiler.record("physics", Duration::from_millis(8));
+        profiler.finish_tick(1, Duration::from_millis(40), budget);
+
+        let window = profiler.take_window();
+        assert_eq!(window.ticks, 2);
+        assert_eq!(window.over_budget_ticks, 1);
+        assert_eq!(window.sections["physics"].samples, 2);
+        assert_eq!(window.sections["physics"].avg_ms(), 6.0);
+        assert_eq!(window.heaviest_section().unwrap().0, "physics");
+
+        let samples = window.to_samples();
+        assert!(samples.iter().any(|s| s.component_name == "simulation.scripts"));
+        assert_eq!(samples[0].alert_level.as_deref(), Some("critical"));
+        assert_eq!(profiler.lag_meter().ticks, 0);
+    }
+
+    #[test]
+    fn test_dumps_are_pruned() {
+        let dir = std::env::temp_dir().join(format!("mutsea-profiles-{}", uuid::Uuid::new_v4()));
+        for frame in 0..4 {
+            write_dump(&dir, frame, "tick 1\n", 2).unwrap();
+        }
+        let remaining: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(remaining.len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,669 @@
+//! Avatar profiles, picks and landmarks
+//!
+//! Agents save their own profile and up to a configured number of picks,
+//! places shown in their profile, and anyone may look at them. Both are
+//! kept in the userprofile and userpicks tables when there is a database,
+//! and only in memory otherwise. Landmarks to where an agent stands are
+//! made as landmark assets and put in its inventory, which needs the
+//! database.
+
+use chrono::Utc;
+use mutsea_core::config::ProfilesConfig;
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{UserId, Vector3};
+use mutsea_database::schema::{Asset, InventoryItem, UserPick, UserProfile};
+use mutsea_database::DatabaseManager;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::constants::{asset_types, inventory_types};
+use mutsea_protocol::login::OpenSimLoginService;
+use mutsea_protocol::profile::{landmark_asset, AvatarProperties, LandmarkItem, Pick, LANDMARK_PERMISSIONS};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ProfileError {
+    #[error("You can only change your own picks")]
+    NotCreator,
+    #[error("You can't have more than {0} picks")]
+    TooManyPicks(usize),
+    #[error("Pick {0} doesn't exist")]
+    UnknownPick(Uuid),
+    #[error("You are not in the region")]
+    NotConnected,
+    #[error("Landmarks can't be made without a database")]
+    NoDatabase,
+    #[error("Profiles unavailable: {0}")]
+    Database(String),
+}
+
+#[derive(Default)]
+struct State {
+    /// Profiles loaded or saved, by avatar
+    profiles: HashMap<UserId, AvatarProperties>,
+    /// Picks loaded or saved, by creator, in the order they are shown
+    picks: HashMap<UserId, Vec<Pick>>,
+}
+
+/// Avatar profiles and picks. Clones share them.
+#[derive(Clone)]
+pub struct Profiles {
+    config: ProfilesConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    login_service: Arc<OpenSimLoginService>,
+    lludp_server: Option<LLUDPServer>,
+    database: Option<Arc<DatabaseManager>>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Profiles {
+    pub fn new(
+        config: ProfilesConfig,
+        scene: Arc<RwLock<RegionScene>>,
+        login_service: Arc<OpenSimLoginService>,
+    ) -> Self {
+        Self {
+            config,
+            scene,
+            login_service,
+            lludp_server: None,
+            database: None,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Answer the profile, pick and landmark requests of the agents
+    /// connected to `lludp_server`
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Keep profiles and picks in `database`, and make landmarks there
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Handle profile requests from the LLUDP server
+    pub fn spawn(&self) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let profiles = self.clone();
+        let mut events = lludp_server.subscribe_events();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => profiles.handle(event.event_data).await,
+                    Err(RecvError::Lagged(missed)) => warn!("Profiles missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle(&self, event: NetworkEventData) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let (agent, result) = match event {
+            NetworkEventData::AvatarPropertiesRequested {
+                agent_id, avatar_id, ..
+            } => {
+                let properties = self.properties(avatar_id).await;
+                let online = lludp_server.agent_position(avatar_id).await.is_some();
+                if let Err(e) = lludp_server
+                    .send_avatar_properties(agent_id, avatar_id, &properties, online)
+                    .await
+                {
+                    warn!("Failed to send the profile of {} to {}: {}", avatar_id, agent_id, e);
+                }
+                return;
+            }
+            NetworkEventData::AvatarPropertiesUpdated {
+                agent_id,
+                image_id,
+                fl_image_id,
+                about_text,
+                fl_about_text,
+                profile_url,
+                allow_publish,
+                mature_publish,
+                ..
+            } => {
+                let properties = AvatarProperties {
+                    image_id,
+                    fl_image_id,
+                    about_text,
+                    fl_about_text,
+                    profile_url,
+                    allow_publish,
+                    mature_publish,
+                    ..AvatarProperties::default()
+                };
+                (agent_id, self.update_properties(agent_id, properties).await)
+            }
+            NetworkEventData::AvatarPicksRequested {
+                agent_id, target_id, ..
+            } => {
+                let picks: Vec<_> = self
+                    .picks(target_id)
+                    .await
+                    .into_iter()
+                    .filter(|pick| pick.enabled || agent_id == target_id)
+                    .map(|pick| (pick.pick_id, pick.name))
+                    .collect();
+                if let Err(e) = lludp_server.send_avatar_picks(agent_id, target_id, &picks).await {
+                    warn!("Failed to send the picks of {} to {}: {}", target_id, agent_id, e);
+                }
+                return;
+            }
+            NetworkEventData::PickInfoRequested {
+                agent_id,
+                creator_id,
+                pick_id,
+                ..
+            } => {
+                let pick = self
+                    .picks(creator_id)
+                    .await
+                    .into_iter()
+                    .find(|pick| pick.pick_id == pick_id);
+                let result = match pick {
+                    Some(pick) => lludp_server.send_pick_info(agent_id, &pick).await.map(drop),
+                    None => Ok(()),
+                };
+                if let Err(e) = result {
+                    warn!("Failed to send pick {} to {}: {}", pick_id, agent_id, e);
+                }
+                return;
+            }
+            NetworkEventData::PickUpdated {
+                agent_id,
+                pick_id,
+                top_pick,
+                parcel_id,
+                name,
+                description,
+                snapshot_id,
+                pos_global,
+                sort_order,
+                enabled,
+                ..
+            } => {
+                let pick = Pick {
+                    pick_id,
+                    creator_id: agent_id,
+                    top_pick,
+                    parcel_id,
+                    name,
+                    description,
+                    snapshot_id,
+                    user: String::new(),
+                    original_name: String::new(),
+                    sim_name: String::new(),
+                    pos_global,
+                    sort_order,
+                    enabled,
+                };
+                (agent_id, self.update_pick(agent_id, pick).await.map(drop))
+            }
+            NetworkEventData::PickDeleted { agent_id, pick_id, .. } => {
+                (agent_id, self.delete_pick(agent_id, pick_id).await)
+            }
+            NetworkEventData::LandmarkRequested {
+                agent_id,
+                folder_id,
+                name,
+                ..
+            } => {
+                let result = match self.create_landmark(agent_id, folder_id, &name).await {
+                    Ok(item) => lludp_server
+                        .send_landmark_created(agent_id, &item)
+                        .await
+                        .map(drop)
+                        .map_err(|e| ProfileError::Database(e.to_string())),
+                    Err(e) => Err(e),
+                };
+                (agent_id, result)
+            }
+            _ => return,
+        };
+
+        if let Err(e) = result {
+            if let Err(e) = lludp_server.send_agent_alert(agent, false, &e.to_string()).await {
+                warn!("Failed to alert agent {}: {}", agent, e);
+            }
+        }
+    }
+
+    /// The profile of `avatar`, with its birth date and account kind
+    pub async fn properties(&self, avatar: UserId) -> AvatarProperties {
+        let cached = self.state.lock().unwrap().profiles.get(&avatar).cloned();
+        let mut properties = match cached {
+            Some(properties) => properties,
+            None => {
+                let loaded = self.load_properties(avatar).await.unwrap_or_default();
+                self.state
+                    .lock()
+                    .unwrap()
+                    .profiles
+                    .entry(avatar)
+                    .or_insert(loaded)
+                    .clone()
+            }
+        };
+        if let Some(created) = self.login_service.user_created(&avatar) {
+            properties.born_on = created.format("%m/%d/%Y").to_string();
+        }
+        properties.charter_member = "Resident".to_string();
+        properties
+    }
+
+    /// Save the profile of `agent`, keeping its partner
+    pub async fn update_properties(&self, agent: UserId, mut properties: AvatarProperties) -> Result<(), ProfileError> {
+        properties.partner_id = self.properties(agent).await.partner_id;
+        properties.born_on.clear();
+        properties.charter_member.clear();
+        self.state.lock().unwrap().profiles.insert(agent, properties.clone());
+
+        if let Some(database) = &self.database {
+            database
+                .set_user_profile(&stored_profile(agent, &properties))
+                .await
+                .map_err(|e| ProfileError::Database(e.to_string()))?;
+        }
+        debug!("Agent {} saved its profile", agent);
+        Ok(())
+    }
+
+    /// The picks of `creator`, in the order they are shown
+    pub async fn picks(&self, creator: UserId) -> Vec<Pick> {
+        if let Some(picks) = self.state.lock().unwrap().picks.get(&creator) {
+            return picks.clone();
+        }
+        let loaded = self.load_picks(creator).await.unwrap_or_default();
+        self.state
+            .lock()
+            .unwrap()
+            .picks
+            .entry(creator)
+            .or_insert(loaded)
+            .clone()
+    }
+
+    /// Save a pick made by `agent`, naming its creator, parcel and region.
+    /// Returns the pick as saved.
+    pub async fn update_pick(&self, agent: UserId, mut pick: Pick) -> Result<Pick, ProfileError> {
+        if pick.creator_id != agent {
+            return Err(ProfileError::NotCreator);
+        }
+        let picks = self.picks(agent).await;
+        let existing = picks.iter().find(|existing| existing.pick_id == pick.pick_id);
+        if existing.is_none() && picks.len() >= self.config.max_picks {
+            return Err(ProfileError::TooManyPicks(self.config.max_picks));
+        }
+
+        pick.user = self.login_service.get_user_name(&agent).unwrap_or_default();
+        {
+            let scene = self.scene.read().await;
+            let origin_x = scene.info.location_x as f64 * 256.0;
+            let origin_y = scene.info.location_y as f64 * 256.0;
+            let (x, y) = (pick.pos_global[0] - origin_x, pick.pos_global[1] - origin_y);
+            let in_region =
+                (0.0..scene.info.size_x as f64).contains(&x) && (0.0..scene.info.size_y as f64).contains(&y);
+            if in_region {
+                pick.sim_name = scene.info.region_name.clone();
+                if let Some(parcel) = scene.parcel_at(x as f32, y as f32) {
+                    pick.original_name = parcel.name.clone();
+                    pick.parcel_id = parcel.id;
+                }
+            } else if let Some(existing) = existing {
+                pick.sim_name = existing.sim_name.clone();
+                pick.original_name = existing.original_name.clone();
+            }
+        }
+
+        if let Some(database) = &self.database {
+            database
+                .set_user_pick(&stored_pick(&pick))
+                .await
+                .map_err(|e| ProfileError::Database(e.to_string()))?;
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            let picks = state.picks.entry(agent).or_default();
+            picks.retain(|existing| existing.pick_id != pick.pick_id);
+            picks.push(pick.clone());
+            picks.sort_by(|a, b| a.sort_order.cmp(&b.sort_order).then_with(|| a.name.cmp(&b.name)));
+        }
+        debug!("Agent {} saved pick \"{}\"", agent, pick.name);
+        Ok(pick)
+    }
+
+    /// Remove the pick `pick_id` of `agent`
+    pub async fn delete_pick(&self, agent: UserId, pick_id: Uuid) -> Result<(), ProfileError> {
+        if !self.picks(agent).await.iter().any(|pick| pick.pick_id == pick_id) {
+            return Err(ProfileError::UnknownPick(pick_id));
+        }
+        if let Some(database) = &self.database {
+            database
+                .remove_user_pick(&pick_id.to_string(), &agent.to_string())
+                .await
+                .map_err(|e| ProfileError::Database(e.to_string()))?;
+        }
+        if let Some(picks) = self.state.lock().unwrap().picks.get_mut(&agent) {
+            picks.retain(|pick| pick.pick_id != pick_id);
+        }
+        debug!("Agent {} removed pick {}", agent, pick_id);
+        Ok(())
+    }
+
+    /// Make a landmark to where `agent` stands and put it in its inventory
+    /// folder `folder_id`. Unnamed landmarks are named after the parcel.
+    pub async fn create_landmark(
+        &self,
+        agent: UserId,
+        folder_id: Uuid,
+        name: &str,
+    ) -> Result<LandmarkItem, ProfileError> {
+        let database = self.database.as_ref().ok_or(ProfileError::NoDatabase)?;
+        let position = match &self.lludp_server {
+            Some(lludp_server) => lludp_server.agent_position(agent).await,
+            None => None,
+        }
+        .ok_or(ProfileError::NotConnected)?;
+
+        let (data, name, description) = {
+            let scene = self.scene.read().await;
+            let info = &scene.info;
+            let region_handle = ((info.location_x as u64 * 256) << 32) | (info.location_y as u64 * 256);
+            let parcel_name = scene
+                .parcel_at(position.x, position.y)
+                .map(|parcel| parcel.name.clone())
+                .unwrap_or_else(|| info.region_name.clone());
+            let name = match name.trim() {
+                "" => parcel_name,
+                name => name.to_string(),
+            };
+            let description = location_description(&info.region_name, position);
+            (
+                landmark_asset(info.region_id, region_handle, position),
+                name,
+                description,
+            )
+        };
+
+        let asset = Asset::new(
+            Uuid::new_v4().to_string(),
+            name.clone(),
+            asset_types::LANDMARK as i32,
+            data,
+        );
+        let item = LandmarkItem {
+            item_id: Uuid::new_v4(),
+            folder_id,
+            asset_id: Uuid::parse_str(&asset.id).unwrap_or_default(),
+            name,
+            description,
+            creation_date: Utc::now().timestamp() as i32,
+        };
+        let stored = InventoryItem {
+            inventory_id: item.item_id.to_string(),
+            asset_id: asset.id.clone(),
+            asset_type: asset_types::LANDMARK as i32,
+            parent_folder_id: folder_id.to_string(),
+            avatar_id: agent.to_string(),
+            name: item.name.clone(),
+            description: item.description.clone(),
+            next_permissions: LANDMARK_PERMISSIONS as i32,
+            current_permissions: LANDMARK_PERMISSIONS as i32,
+            inv_type: inventory_types::LANDMARK as i32,
+            creator_id: agent.to_string(),
+            base_permissions: LANDMARK_PERMISSIONS as i32,
+            everyone_permissions: 0,
+            creation_date: item.creation_date,
+        };
+        let saved = match database.insert_asset(&asset).await {
+            Ok(()) => database.insert_inventory_item(&stored).await,
+            Err(e) => Err(e),
+        };
+        saved.map_err(|e| ProfileError::Database(e.to_string()))?;
+
+        info!("Agent {} made landmark \"{}\"", agent, item.name);
+        Ok(item)
+    }
+
+    async fn load_properties(&self, avatar: UserId) -> Option<AvatarProperties> {
+        let database = self.database.as_ref()?;
+        match database.get_user_profile(&avatar.to_string()).await {
+            Ok(profile) => profile.map(|profile| profile_properties(&profile)),
+            Err(e) => {
+                warn!("Failed to load the profile of {}: {}", avatar, e);
+                None
+            }
+        }
+    }
+
+    async fn load_picks(&self, creator: UserId) -> Option<Vec<Pick>> {
+        let database = self.database.as_ref()?;
+        match database.get_user_picks(&creator.to_string()).await {
+            Ok(picks) => Some(picks.iter().filter_map(stored_pick_to_pick).collect()),
+            Err(e) => {
+                warn!("Failed to load the picks of {}: {}", creator, e);
+                None
+            }
+        }
+    }
+}
+
+/// Where a landmark points, as its description: region, x, y, z
+fn location_description(region_name: &str, position: Vector3) -> String {
+    format!(
+        "{}, {}, {}, {}",
+        region_name,
+        position.x.round() as i32,
+        position.y.round() as i32,
+        position.z.round() as i32
+    )
+}
+
+fn bool_text(value: bool) -> String {
+    value.to_string()
+}
+
+fn text_bool(value: &str) -> bool {
+    value.trim().eq_ignore_ascii_case("true")
+}
+
+fn text_uuid(value: &str) -> Uuid {
+    Uuid::parse_str(value.trim()).unwrap_or_default()
+}
+
+fn stored_profile(agent: UserId, properties: &AvatarProperties) -> UserProfile {
+    UserProfile {
+        user_id: agent.to_string(),
+        partner_id: properties.partner_id.to_string(),
+        allow_publish: bool_text(properties.allow_publish),
+        mature_publish: bool_text(properties.mature_publish),
+        url: properties.profile_url.clone(),
+        image_id: properties.image_id.to_string(),
+        about_text: properties.about_text.clone(),
+        first_image_id: properties.fl_image_id.to_string(),
+        first_text: properties.fl_about_text.clone(),
+    }
+}
+
+fn profile_properties(profile: &UserProfile) -> AvatarProperties {
+    AvatarProperties {
+        image_id: text_uuid(&profile.image_id),
+        fl_image_id: text_uuid(&profile.first_image_id),
+        partner_id: text_uuid(&profile.partner_id),
+        about_text: profile.about_text.clone(),
+        fl_about_text: profile.first_text.clone(),
+        profile_url: profile.url.clone(),
+        allow_publish: text_bool(&profile.allow_publish),
+        mature_publish: text_bool(&profile.mature_publish),
+        ..AvatarProperties::default()
+    }
+}
+
+fn stored_pick(pick: &Pick) -> UserPick {
+    let [x, y, z] = pick.pos_global;
+    UserPick {
+        pick_id: pick.pick_id.to_string(),
+        creator_id: pick.creator_id.to_string(),
+        top_pick: bool_text(pick.top_pick),
+        parcel_id: pick.parcel_id.to_string(),
+        name: pick.name.clone(),
+        description: pick.description.clone(),
+        snapshot_id: pick.snapshot_id.to_string(),
+        user: pick.user.clone(),
+        original_name: pick.original_name.clone(),
+        sim_name: pick.sim_name.clone(),
+        pos_global: format!("<{},{},{}>", x, y, z),
+        sort_order: pick.sort_order,
+        enabled: bool_text(pick.enabled),
+    }
+}
+
+fn stored_pick_to_pick(stored: &UserPick) -> Option<Pick> {
+    let trimmed = stored.pos_global.trim().trim_start_matches('<').trim_end_matches('>');
+    let coordinates: Vec<f64> = trimmed
+        .split(',')
+        .map(|part| part.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    Some(Pick {
+        pick_id: Uuid::parse_str(&stored.pick_id).ok()?,
+        creator_id: UserId::from_uuid(Uuid::parse_str(&stored.creator_id).ok()?),
+        top_pick: text_bool(&stored.top_pick),
+        parcel_id: text_uuid(&stored.parcel_id),
+        name: stored.name.clone(),
+        description: stored.description.clone(),
+        snapshot_id: text_uuid(&stored.snapshot_id),
+        user: stored.user.clone(),
+        original_name: stored.original_name.clone(),
+        sim_name: stored.sim_name.clone(),
+        pos_global: coordinates.try_into().ok()?,
+        sort_order: stored.sort_order,
+        enabled: text_bool(&stored.enabled),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::RegionInfo;
+
+    fn profiles(max_picks: usize) -> (Profiles, Arc<OpenSimLoginService>) {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let mut scene = RegionScene::new(info, UserId::new());
+        scene.parcels[0].name = "Sunny Beach".to_string();
+        let login_service = Arc::new(OpenSimLoginService::new());
+        let config = ProfilesConfig {
+            max_picks,
+            ..ProfilesConfig::default()
+        };
+        (
+            Profiles::new(config, Arc::new(RwLock::new(scene)), Arc::clone(&login_service)),
+            login_service,
+        )
+    }
+
+    fn pick(creator_id: UserId, name: &str, sort_order: i32) -> Pick {
+        Pick {
+            pick_id: Uuid::new_v4(),
+            creator_id,
+            top_pick: false,
+            parcel_id: Uuid::nil(),
+            name: name.to_string(),
+            description: String::new(),
+            snapshot_id: Uuid::nil(),
+            user: String::new(),
+            original_name: String::new(),
+            sim_name: String::new(),
+            pos_global: [256000.0 + 128.0, 256000.0 + 64.0, 22.0],
+            sort_order,
+            enabled: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_profile_keeps_partner_and_shows_birth_date() {
+        let (profiles, login_service) = profiles(10);
+        let agent = login_service
+            .create_user("Some", "Resident", "secret", None, false)
+            .unwrap();
+        let partner = Uuid::new_v4();
+        profiles.state.lock().unwrap().profiles.insert(
+            agent,
+            AvatarProperties {
+                partner_id: partner,
+                ..AvatarProperties::default()
+            },
+        );
+
+        let update = AvatarProperties {
+            about_text: "Builder of things".to_string(),
+            allow_publish: true,
+            ..AvatarProperties::default()
+        };
+        profiles.update_properties(agent, update).await.unwrap();
+        let properties = profiles.properties(agent).await;
+        assert_eq!(properties.about_text, "Builder of things");
+        assert_eq!(properties.partner_id, partner);
+        assert_eq!(properties.born_on, Utc::now().format("%m/%d/%Y").to_string());
+    }
+
+    #[tokio::test]
+    async fn test_picks_are_named_ordered_and_limited() {
+        let (profiles, login_service) = profiles(2);
+        let agent = login_service
+            .create_user("Some", "Resident", "secret", None, false)
+            .unwrap();
+
+        let beach = profiles.update_pick(agent, pick(agent, "Beach", 2)).await.unwrap();
+        assert_eq!(beach.user, "Some Resident");
+        assert_eq!(
+            (beach.sim_name.as_str(), beach.original_name.as_str()),
+            ("Test", "Sunny Beach")
+        );
+        profiles.update_pick(agent, pick(agent, "Club", 1)).await.unwrap();
+        assert_eq!(
+            profiles.update_pick(agent, pick(agent, "Park", 3)).await,
+            Err(ProfileError::TooManyPicks(2))
+        );
+        assert_eq!(
+            profiles.update_pick(UserId::new(), pick(agent, "Park", 3)).await,
+            Err(ProfileError::NotCreator)
+        );
+
+        let names: Vec<_> = profiles.picks(agent).await.into_iter().map(|pick| pick.name).collect();
+        assert_eq!(names, vec!["Club", "Beach"]);
+        profiles.delete_pick(agent, beach.pick_id).await.unwrap();
+        assert_eq!(profiles.picks(agent).await.len(), 1);
+        assert_eq!(
+            profiles.delete_pick(agent, beach.pick_id).await,
+            Err(ProfileError::UnknownPick(beach.pick_id))
+        );
+        assert_eq!(
+            profiles.create_landmark(agent, Uuid::new_v4(), "Home").await,
+            Err(ProfileError::NoDatabase)
+        );
+    }
+
+    #[test]
+    fn test_picks_round_trip_through_the_database_row() {
+        let agent = UserId::new();
+        let mut original = pick(agent, "Beach", 1);
+        original.pos_global = [256128.5, 256064.25, 22.0];
+        assert_eq!(stored_pick_to_pick(&stored_pick(&original)), Some(original));
+    }
+}
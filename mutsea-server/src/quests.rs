@@ -0,0 +1,659 @@
+//! Quests given to players
+//!
+//! A quest is a series of objectives, defined through the admin API or
+//! written for one player by the configured [`AIProvider`]; every written
+//! quest is recorded as a `StoryGeneration` AI decision. The engine follows
+//! chat, agent positions and region events to tick objectives off in
+//! order, and the narrator tells players about their quests in chat.
+
+use crate::ai::context_hash;
+use crate::dialogs::DialogService;
+use crate::ecosystem::EcosystemSimulator;
+use async_trait::async_trait;
+use chrono::Utc;
+use mutsea_core::config::QuestsConfig;
+use mutsea_core::events::{NetworkEventData, RegionEvent, RegionEventData};
+use mutsea_core::{
+    AIDecisionRecord, AIDecisionRecorder, AIProvider, MutseaError, MutseaResult, QuestObjective, QuestPlan,
+    QuestTrigger, RegionId, StoryRequest, UserId, Vector3,
+};
+use mutsea_database::models::{
+    AIDecisionType, DatabaseModel, QuestDefinition, QuestId, QuestOrigin, QuestProgress, QuestStatus,
+};
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::instant_message::InstantMessage;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// How often agent positions are checked against `Reach` objectives
+const POSITION_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Something the narrator tells a player
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuestNotice {
+    pub player_id: UserId,
+    pub message: String,
+}
+
+/// Why a quest could not be given to a player
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum OfferError {
+    #[error("No quest {0}")]
+    UnknownQuest(QuestId),
+
+    #[error("The player already has quest {0}")]
+    AlreadyGiven(QuestId),
+
+    #[error("The player already has {0} active quests")]
+    TooManyActive(usize),
+}
+
+/// Quest definitions and every player's progress through them
+pub struct QuestEngine {
+    config: QuestsConfig,
+    region_id: RegionId,
+    provider: Arc<dyn AIProvider>,
+    recorder: Option<Arc<dyn AIDecisionRecorder>>,
+    ecosystem: Option<EcosystemSimulator>,
+    /// Asks players whether they want quests offered at login
+    dialogs: Option<Arc<DialogService>>,
+    quests: Mutex<HashMap<QuestId, QuestDefinition>>,
+    progress: Mutex<HashMap<UserId, Vec<QuestProgress>>>,
+    /// Notices waiting to be delivered
+    outbox: Mutex<Vec<QuestNotice>>,
+    delivery: Notify,
+}
+
+impl QuestEngine {
+    pub fn new(config: QuestsConfig, region_id: RegionId, provider: Arc<dyn AIProvider>) -> Self {
+        Self {
+            config,
+            region_id,
+            provider,
+            recorder: None,
+            ecosystem: None,
+            dialogs: None,
+            quests: Mutex::new(HashMap::new()),
+            progress: Mutex::new(HashMap::new()),
+            outbox: Mutex::new(Vec::new()),
+            delivery: Notify::new(),
+        }
+    }
+
+    /// Record each written quest as an AI decision through `recorder`
+    pub fn with_recorder(mut self, recorder: Arc<dyn AIDecisionRecorder>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Tell the storyteller about the region's weather and follow its
+    /// ecosystem events
+    pub fn with_ecosystem(mut self, ecosystem: EcosystemSimulator) -> Self {
+        self.ecosystem = Some(ecosystem);
+        self
+    }
+
+    /// Ask before giving quests at login rather than just giving them
+    pub fn with_dialogs(mut self, dialogs: Arc<DialogService>) -> Self {
+        self.dialogs = Some(dialogs);
+        self
+    }
+
+    /// Add a quest players can be given
+    pub fn define(&self, quest: QuestDefinition) -> Result<QuestDefinition, String> {
+        quest.validate().map_err(|e| format!("{}: {}", e.field, e.message))?;
+        self.quests.lock().unwrap().insert(quest.id, quest.clone());
+        info!("Defined quest {} \"{}\"", quest.id, quest.title);
+        Ok(quest)
+    }
+
+    pub fn quests(&self) -> Vec<QuestDefinition> {
+        let mut quests: Vec<_> = self.quests.lock().unwrap().values().cloned().collect();
+        quests.sort_by_key(|quest| quest.created_at);
+        quests
+    }
+
+    /// Every quest `player_id` has been given
+    pub fn progress(&self, player_id: UserId) -> Vec<QuestProgress> {
+        self.progress.lock().unwrap().get(&player_id).cloned().unwrap_or_default()
+    }
+
+    /// Have the provider write a `theme` quest for `player_id` and define it
+    pub async fn generate(&self, player_id: UserId, theme: &str) -> MutseaResult<QuestDefinition> {
+        let request = StoryRequest {
+            region_id: self.region_id,
+            player_id,
+            theme: theme.to_string(),
+            context: self.context(),
+            seed: 0,
+        };
+
+        let started = Instant::now();
+        let plan = self.provider.plan_quest(&request).await?;
+        let elapsed = started.elapsed();
+
+        let decision_id = Uuid::new_v4();
+        let mut quest = QuestDefinition::new(
+            plan.title.clone(),
+            plan.synopsis.clone(),
+            plan.objectives.clone(),
+            QuestOrigin::Generated {
+                decision_id,
+                model_version: plan.model_version.clone(),
+            },
+        );
+        quest.region_id = Some(self.region_id.0);
+        let quest = self
+            .define(quest)
+            .map_err(|e| MutseaError::Generic(format!("{} wrote an invalid quest: {}", self.provider.name(), e)))?;
+
+        self.record(decision_id, &request, &plan, &quest, elapsed).await;
+        Ok(quest)
+    }
+
+    /// Give `player_id` quest `quest_id`, telling them about it
+    pub fn offer(&self, player_id: UserId, quest_id: QuestId) -> Result<QuestProgress, OfferError> {
+        let quest = self.quests.lock().unwrap().get(&quest_id).cloned().ok_or(OfferError::UnknownQuest(quest_id))?;
+
+        let progress = {
+            let mut progress = self.progress.lock().unwrap();
+            let given = progress.entry(player_id).or_default();
+            if given
+                .iter()
+                .any(|p| p.quest_id == quest_id && p.status != QuestStatus::Abandoned)
+            {
+                return Err(OfferError::AlreadyGiven(quest_id));
+            }
+            let active = given.iter().filter(|p| p.status == QuestStatus::Active).count();
+            if active >= self.config.max_active {
+                return Err(OfferError::TooManyActive(active));
+            }
+            let started = QuestProgress::start(quest_id, player_id.0);
+            given.push(started.clone());
+            started
+        };
+
+        info!("Gave quest {} \"{}\" to {}", quest.id, quest.title, player_id);
+        self.notify(vec![QuestNotice {
+            player_id,
+            message: format!(
+                "New quest: {}. {} First: {}",
+                quest.title, quest.synopsis, quest.objectives[0].description
+            ),
+        }]);
+        Ok(progress)
+    }
+
+    /// Stop working on a quest. False if the player does not have it active.
+    pub fn abandon(&self, player_id: UserId, quest_id: QuestId) -> bool {
+        let mut progress = self.progress.lock().unwrap();
+        let Some(active) = progress
+            .get_mut(&player_id)
+            .and_then(|given| given.iter_mut().find(|p| p.quest_id == quest_id && p.status == QuestStatus::Active))
+        else {
+            return false;
+        };
+        active.status = QuestStatus::Abandoned;
+        active.updated_at = Utc::now();
+        true
+    }
+
+    /// Chat from `player_id` on `channel`
+    pub fn on_chat(&self, player_id: UserId, channel: i32, message: &str) {
+        let message = message.to_lowercase();
+        self.advance(Some(player_id), |trigger| match trigger {
+            QuestTrigger::Say { channel: wanted, phrase } => {
+                *wanted == channel && message.contains(&phrase.to_lowercase())
+            }
+            _ => false,
+        });
+    }
+
+    /// `player_id` is at `position` in `region_id`
+    pub fn on_position(&self, player_id: UserId, region_id: Option<RegionId>, position: Vector3) {
+        if region_id.is_some_and(|region_id| region_id != self.region_id) {
+            return;
+        }
+        self.advance(Some(player_id), |trigger| match trigger {
+            QuestTrigger::Reach { x, y, radius } => {
+                (position.x - x).powi(2) + (position.y - y).powi(2) <= radius * radius
+            }
+            _ => false,
+        });
+    }
+
+    /// An event in a region, meeting objectives of every player here
+    pub fn on_region_event(&self, event: &RegionEvent) {
+        if event.region_id != self.region_id {
+            return;
+        }
+        let (name, detail) = event_name(&event.event_data);
+        self.advance(None, |trigger| match trigger {
+            QuestTrigger::RegionEvent { event, detail: wanted } => {
+                *event == name && wanted.as_ref().map_or(true, |wanted| Some(wanted.as_str()) == detail)
+            }
+            _ => false,
+        });
+    }
+
+    /// Notices not yet delivered, oldest first
+    pub fn take_notices(&self) -> Vec<QuestNotice> {
+        std::mem::take(&mut *self.outbox.lock().unwrap())
+    }
+
+    /// Move every active quest of `player_id`, or of everyone, whose current
+    /// objective is `met` on to its next objective
+    fn advance(&self, player_id: Option<UserId>, met: impl Fn(&QuestTrigger) -> bool) {
+        let quests = self.quests.lock().unwrap();
+        let mut progress = self.progress.lock().unwrap();
+        let mut notices = Vec::new();
+
+        for (player, given) in progress.iter_mut() {
+            if player_id.is_some_and(|player_id| player_id != *player) {
+                continue;
+            }
+            for active in given.iter_mut().filter(|p| p.status == QuestStatus::Active) {
+                let Some(quest) = quests.get(&active.quest_id) else {
+                    continue;
+                };
+                let Some(objective) = quest.objectives.get(active.current_objective) else {
+                    continue;
+                };
+                if !met(&objective.trigger) {
+                    continue;
+                }
+
+                let now = Utc::now();
+                active.current_objective += 1;
+                active.updated_at = now;
+                let message = match quest.objectives.get(active.current_objective) {
+                    Some(next) => format!("{}: {}", quest.title, next.description),
+                    None => {
+                        active.status = QuestStatus::Completed;
+                        active.completed_at = Some(now);
+                        info!("{} completed quest {} \"{}\"", player, quest.id, quest.title);
+                        format!("Quest complete: {}", quest.title)
+                    }
+                };
+                notices.push(QuestNotice {
+                    player_id: *player,
+                    message,
+                });
+            }
+        }
+
+        drop(progress);
+        drop(quests);
+        self.notify(notices);
+    }
+
+    fn notify(&self, notices: Vec<QuestNotice>) {
+        if notices.is_empty() {
+            return;
+        }
+        self.outbox.lock().unwrap().extend(notices);
+        self.delivery.notify_one();
+    }
+
+    /// What is going on in the region, for the storyteller
+    fn context(&self) -> Vec<String> {
+        let weather = self.ecosystem.as_ref().and_then(|ecosystem| ecosystem.weather(self.region_id));
+        match weather {
+            Some(weather) => vec![
+                format!("weather: {}", weather.condition()),
+                format!("temperature_c: {:.0}", weather.temperature_c),
+            ],
+            None => Vec::new(),
+        }
+    }
+
+    async fn record(
+        &self,
+        decision_id: Uuid,
+        request: &StoryRequest,
+        plan: &QuestPlan,
+        quest: &QuestDefinition,
+        elapsed: Duration,
+    ) {
+        let Some(recorder) = &self.recorder else {
+            return;
+        };
+        let decision_type = AIDecisionType::StoryGeneration {
+            narrative_type: request.theme.clone(),
+            target_audience: vec![request.player_id.0],
+            complexity_level: plan.objectives.len() as f32,
+        };
+        let input_data = serde_json::to_value(request).unwrap_or_default();
+        let decision = AIDecisionRecord {
+            id: decision_id,
+            decision_type: "story_generation".to_string(),
+            context_hash: context_hash(&input_data),
+            decision_data: json!({ "decision_type": decision_type, "plan": plan }),
+            input_data,
+            confidence_score: plan.confidence.clamp(0.0, 1.0) as f64,
+            execution_time_ms: elapsed.as_millis() as i64,
+            model_version: plan.model_version.clone(),
+            metadata: json!({ "provider": self.provider.name(), "quest_id": quest.id }),
+        };
+        if let Err(e) = recorder.record_ai_decision(&decision).await {
+            warn!("Failed to record story generation decision {}: {}", decision_id, e);
+        }
+    }
+
+    /// Write a quest for a player who just logged in without one
+    async fn offer_on_login(&self, player_id: UserId) {
+        let has_active = self.progress(player_id).iter().any(|p| p.status == QuestStatus::Active);
+        if has_active {
+            return;
+        }
+        let quest = match self.generate(player_id, &self.config.default_theme).await {
+            Ok(quest) => quest,
+            Err(e) => {
+                warn!("Failed to write {} a quest at login: {}", player_id, e);
+                return;
+            }
+        };
+        if let Some(dialogs) = &self.dialogs {
+            let text = format!("{}\n\n{}", quest.title, quest.synopsis);
+            match dialogs.dialog(player_id, &text, &["Accept", "Decline"]).await {
+                Ok(reply) if reply.button_index == 0 => {}
+                Ok(_) => {
+                    debug!("{} declined quest {}", player_id, quest.id);
+                    return;
+                }
+                Err(e) => {
+                    debug!("{} was not asked about quest {}: {}", player_id, quest.id, e);
+                    return;
+                }
+            }
+        }
+        if let Err(e) = self.offer(player_id, quest.id) {
+            warn!("Failed to give {} a quest at login: {}", player_id, e);
+        }
+    }
+
+    /// Follow `lludp_server`'s agents and the ecosystem's events, and
+    /// deliver notices as chat from the narrator
+    pub fn spawn(self: &Arc<Self>, lludp_server: LLUDPServer) {
+        let engine = Arc::clone(self);
+        let mut agent_events = lludp_server.subscribe_events();
+        let mut region_events = self.ecosystem.as_ref().map(|ecosystem| ecosystem.subscribe_events());
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(POSITION_INTERVAL);
+            loop {
+                tokio::select! {
+                    event = agent_events.recv() => match event {
+                        Ok(event) => match event.event_data {
+                            NetworkEventData::AgentLoggedIn { agent_id, .. } if engine.config.offer_on_login => {
+                                let engine = Arc::clone(&engine);
+                                tokio::spawn(async move { engine.offer_on_login(agent_id).await });
+                            }
+                            NetworkEventData::AgentChatted { agent_id, channel, message, .. } => {
+                                engine.on_chat(agent_id, channel, &message);
+                            }
+                            _ => {}
+                        },
+                        Err(RecvError::Lagged(missed)) => warn!("Quest engine missed {} agent events", missed),
+                        Err(RecvError::Closed) => break,
+                    },
+                    event = async { region_events.as_mut().unwrap().recv().await }, if region_events.is_some() => match event {
+                        Ok(event) => engine.on_region_event(&event),
+                        Err(RecvError::Lagged(missed)) => warn!("Quest engine missed {} region events", missed),
+                        Err(RecvError::Closed) => region_events = None,
+                    },
+                    _ = interval.tick() => {
+                        for circuit in lludp_server.get_all_circuits().await {
+                            if let Some(agent_id) = circuit.agent_id {
+                                engine.on_position(agent_id, circuit.region_id, circuit.position);
+                            }
+                        }
+                    }
+                    _ = engine.delivery.notified() => {}
+                }
+
+                for notice in engine.take_notices() {
+                    let message =
+                        InstantMessage::from_object(&engine.config.narrator, notice.player_id, engine.region_id, &notice.message);
+                    match lludp_server.send_instant_message(&message).await {
+                        Ok(true) => debug!("Told {}: {}", notice.player_id, notice.message),
+                        Ok(false) => debug!("{} is not connected to hear: {}", notice.player_id, notice.message),
+                        Err(e) => warn!("Failed to send a quest message to {}: {}", notice.player_id, e),
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Name and detail of a region event as quest triggers match them
+fn event_name(event: &RegionEventData) -> (&'static str, Option<&str>) {
+    match event {
+        RegionEventData::Created { .. } => ("created", None),
+        RegionEventData::Started { .. } => ("started", None),
+        RegionEventData::Stopped { .. } => ("stopped", None),
+        RegionEventData::UserEntered { .. } => ("user_entered", None),
+        RegionEventData::UserLeft { .. } => ("user_left", None),
+        RegionEventData::ObjectAdded { .. } => ("object_added", None),
+        RegionEventData::ObjectRemoved { .. } => ("object_removed", None),
+        RegionEventData::PopulationShift { species, .. } => ("population_shift", Some(species.as_str())),
+        RegionEventData::ResourceDepleted { resource, .. } => ("resource_depleted", Some(resource.as_str())),
+        RegionEventData::WeatherChanged { condition, .. } => ("weather_changed", Some(condition.as_str())),
+    }
+}
+
+/// Writes quests without an AI service: a journey between landmarks with a
+/// word to speak on the way
+pub struct ProceduralStoryteller {
+    /// Region width and depth in metres
+    region_size: (f32, f32),
+}
+
+impl ProceduralStoryteller {
+    pub fn new(size_x: u32, size_y: u32) -> Self {
+        Self {
+            region_size: (size_x as f32, size_y as f32),
+        }
+    }
+}
+
+const LANDMARKS: [&str; 6] = ["old well", "standing stone", "fallen oak", "hidden spring", "ruined tower", "lone cairn"];
+const WORDS: [&str; 6] = ["lantern", "ember", "echo", "willow", "tide", "thorn"];
+const ADJECTIVES: [&str; 6] = ["Silent", "Forgotten", "Whispering", "Lost", "Hollow", "Wandering"];
+
+/// Distance in metres from the region edge landmarks keep
+const EDGE_MARGIN: f32 = 24.0;
+
+#[async_trait]
+impl AIProvider for ProceduralStoryteller {
+    fn name(&self) -> &str {
+        "procedural"
+    }
+
+    async fn plan_quest(&self, request: &StoryRequest) -> MutseaResult<QuestPlan> {
+        let mut rng = match request.seed {
+            0 => StdRng::from_entropy(),
+            seed => StdRng::seed_from_u64(seed),
+        };
+        let (width, depth) = self.region_size;
+        let place = |rng: &mut StdRng| {
+            (
+                rng.gen_range(EDGE_MARGIN..(width - EDGE_MARGIN).max(EDGE_MARGIN + 1.0)),
+                rng.gen_range(EDGE_MARGIN..(depth - EDGE_MARGIN).max(EDGE_MARGIN + 1.0)),
+            )
+        };
+
+        let first = rng.gen_range(0..LANDMARKS.len());
+        let second = (first + rng.gen_range(1..LANDMARKS.len())) % LANDMARKS.len();
+        let word = WORDS[rng.gen_range(0..WORDS.len())];
+        let (x1, y1) = place(&mut rng);
+        let (x2, y2) = place(&mut rng);
+
+        let mut objectives = vec![
+            QuestObjective {
+                description: format!("Find the {} near {:.0}, {:.0}", LANDMARKS[first], x1, y1),
+                trigger: QuestTrigger::Reach { x: x1, y: y1, radius: 8.0 },
+            },
+            QuestObjective {
+                description: format!("Say \"{}\" aloud", word),
+                trigger: QuestTrigger::Say {
+                    channel: 0,
+                    phrase: word.to_string(),
+                },
+            },
+            QuestObjective {
+                description: format!("Carry the word to the {} near {:.0}, {:.0}", LANDMARKS[second], x2, y2),
+                trigger: QuestTrigger::Reach { x: x2, y: y2, radius: 8.0 },
+            },
+        ];
+        // Under clear skies, make the player wait for the weather to turn
+        if request.context.iter().any(|c| c == "weather: clear") {
+            objectives.push(QuestObjective {
+                description: "Wait for the clouds to gather".to_string(),
+                trigger: QuestTrigger::RegionEvent {
+                    event: "weather_changed".to_string(),
+                    detail: None,
+                },
+            });
+        }
+
+        let adjective = ADJECTIVES[rng.gen_range(0..ADJECTIVES.len())];
+        Ok(QuestPlan {
+            title: format!("The {} {}", adjective, title_case(LANDMARKS[first])),
+            synopsis: format!(
+                "A tale of {}: something waits at the {}, and only those who speak the right word will learn what.",
+                request.theme, LANDMARKS[first]
+            ),
+            objectives,
+            confidence: 0.5,
+            model_version: "procedural-1".to_string(),
+        })
+    }
+}
+
+fn title_case(text: &str) -> String {
+    text.split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default()
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine() -> QuestEngine {
+        QuestEngine::new(QuestsConfig::default(), RegionId::new(), Arc::new(ProceduralStoryteller::new(256, 256)))
+    }
+
+    #[tokio::test]
+    async fn test_objectives_advance_in_order() {
+        let engine = engine();
+        let player = UserId::new();
+        let quest = engine
+            .define(QuestDefinition::new(
+                "Rain Dance".to_string(),
+                "Call the rain".to_string(),
+                vec![
+                    QuestObjective {
+                        description: "Go to the hill".to_string(),
+                        trigger: QuestTrigger::Reach { x: 100.0, y: 100.0, radius: 5.0 },
+                    },
+                    QuestObjective {
+                        description: "Sing".to_string(),
+                        trigger: QuestTrigger::Say { channel: 0, phrase: "Rain".to_string() },
+                    },
+                    QuestObjective {
+                        description: "Wait".to_string(),
+                        trigger: QuestTrigger::RegionEvent {
+                            event: "weather_changed".to_string(),
+                            detail: Some("rain".to_string()),
+                        },
+                    },
+                ],
+                QuestOrigin::Authored,
+            ))
+            .unwrap();
+
+        engine.offer(player, quest.id).unwrap();
+        assert_eq!(engine.offer(player, quest.id), Err(OfferError::AlreadyGiven(quest.id)));
+        assert!(engine.take_notices()[0].message.starts_with("New quest: Rain Dance"));
+
+        // Out of order triggers do nothing
+        engine.on_chat(player, 0, "come, rain!");
+        engine.on_position(player, None, Vector3::new(103.0, 98.0, 20.0));
+        engine.on_chat(player, 5, "rain");
+        engine.on_chat(player, 0, "Come, RAIN!");
+        let weather = |condition: &str| RegionEvent {
+            event_id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            region_id: engine.region_id,
+            event_data: RegionEventData::WeatherChanged {
+                condition: condition.to_string(),
+                temperature_c: 12.0,
+                precipitation: 0.5,
+                wind: (1.0, 0.0),
+            },
+        };
+        engine.on_region_event(&weather("snow"));
+        assert_eq!(engine.progress(player)[0].current_objective, 2);
+        engine.on_region_event(&weather("rain"));
+
+        let progress = &engine.progress(player)[0];
+        assert_eq!(progress.status, QuestStatus::Completed);
+        let notices: Vec<_> = engine.take_notices().into_iter().map(|n| n.message).collect();
+        assert_eq!(notices, ["Rain Dance: Sing", "Rain Dance: Wait", "Quest complete: Rain Dance"]);
+    }
+
+    #[tokio::test]
+    async fn test_generated_quests() {
+        let engine = engine();
+        let player = UserId::new();
+        let quest = engine.generate(player, "exploration").await.unwrap();
+        assert!(matches!(quest.origin, QuestOrigin::Generated { .. }));
+        assert!(quest.objectives.len() >= 3);
+        assert_eq!(engine.quests(), vec![quest.clone()]);
+
+        let storyteller = ProceduralStoryteller::new(256, 256);
+        let request = StoryRequest {
+            region_id: engine.region_id,
+            player_id: player,
+            theme: "mystery".to_string(),
+            context: vec!["weather: clear".to_string()],
+            seed: 11,
+        };
+        let plan = storyteller.plan_quest(&request).await.unwrap();
+        assert_eq!(plan, storyteller.plan_quest(&request).await.unwrap());
+        assert_eq!(plan.objectives.len(), 4);
+        for objective in &plan.objectives {
+            if let QuestTrigger::Reach { x, y, .. } = objective.trigger {
+                assert!((EDGE_MARGIN..=256.0 - EDGE_MARGIN).contains(&x));
+                assert!((EDGE_MARGIN..=256.0 - EDGE_MARGIN).contains(&y));
+            }
+        }
+
+        let config = QuestsConfig {
+            max_active: 1,
+            ..QuestsConfig::default()
+        };
+        let limited = QuestEngine::new(config, engine.region_id, Arc::new(storyteller));
+        let first = limited.generate(player, "mystery").await.unwrap();
+        let second = limited.generate(player, "mystery").await.unwrap();
+        limited.offer(player, first.id).unwrap();
+        assert_eq!(limited.offer(player, second.id), Err(OfferError::TooManyActive(1)));
+        assert!(limited.abandon(player, first.id));
+        assert!(limited.offer(player, second.id).is_ok());
+    }
+}
@@ -0,0 +1,174 @@
+//! Region raycasts
+//!
+//! One service answers the region's rays: llCastRay calls from scripts,
+//! line of sight between NPCs and what they look at, and checks that a prim
+//! a viewer clicked can really be seen from its camera. Rays sent together
+//! are cast against a single read of the scene.
+
+use mutsea_core::config::RaycastConfig;
+use mutsea_core::events::Collider;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{ObjectId, UserId, Vector3};
+use mutsea_network::LLUDPServer;
+use mutsea_physics::{RayHit, RayOptions, RayQuery, Raycaster};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::RwLock;
+
+#[derive(Debug, Error)]
+pub enum RaycastError {
+    #[error("{0} rays were sent together, at most {1} are cast")]
+    BatchTooLarge(usize, usize),
+    #[error("A ray of {0:.1}m is longer than the {1:.1}m allowed")]
+    TooLong(f32, f32),
+}
+
+/// Casts rays through a region. Clones share the scene.
+#[derive(Clone)]
+pub struct RegionRaycast {
+    config: RaycastConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: Option<LLUDPServer>,
+}
+
+impl RegionRaycast {
+    pub fn new(config: RaycastConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            config,
+            scene,
+            lludp_server: None,
+        }
+    }
+
+    /// Let rays hit the avatars connected to `lludp_server`
+    pub fn with_avatars(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// What each ray hits, nearest first. Each ray answers at most the
+    /// configured `max_hits`, however many it asks for.
+    pub async fn cast_batch(&self, queries: &[RayQuery]) -> Result<Vec<Vec<RayHit>>, RaycastError> {
+        if queries.len() > self.config.max_batch {
+            return Err(RaycastError::BatchTooLarge(queries.len(), self.config.max_batch));
+        }
+        if let Some(length) = queries
+            .iter()
+            .map(|query| (query.end - query.start).length())
+            .find(|length| *length > self.config.max_distance)
+        {
+            return Err(RaycastError::TooLong(length, self.config.max_distance));
+        }
+        let queries: Vec<RayQuery> = queries
+            .iter()
+            .cloned()
+            .map(|mut query| {
+                query.options.max_hits = query.options.max_hits.min(self.config.max_hits);
+                query
+            })
+            .collect();
+
+        let avatars = self.avatars().await;
+        let scene = self.scene.read().await;
+        Ok(Raycaster::new(&scene, &avatars).cast_batch(&queries))
+    }
+
+    /// What one ray hits, nearest first
+    pub async fn cast(&self, query: RayQuery) -> Result<Vec<RayHit>, RaycastError> {
+        Ok(self.cast_batch(&[query]).await?.pop().unwrap_or_default())
+    }
+
+    /// Whether an NPC at `from` can see `to`, looking past its own prims in
+    /// `ignore`
+    pub async fn line_of_sight(&self, from: Vector3, to: Vector3, ignore: &[ObjectId]) -> bool {
+        let scene = self.scene.read().await;
+        Raycaster::new(&scene, &[]).line_of_sight(from, to, ignore)
+    }
+
+    /// Whether `object`, clicked from a camera at `camera`, is within reach
+    /// and the first thing the camera sees in its direction
+    pub async fn validate_click(&self, camera: Vector3, object: ObjectId) -> bool {
+        let scene = self.scene.read().await;
+        let Some(target) = scene.objects.get(&object).map(|object| object.position) else {
+            return false;
+        };
+        if (target - camera).length() > self.config.click_range {
+            return false;
+        }
+        let options = RayOptions {
+            avatars: false,
+            phantom: true,
+            ..RayOptions::default()
+        };
+        let hits = Raycaster::new(&scene, &[]).cast(&RayQuery::new(camera, target).with_options(options));
+        // The ray ends inside the prim, so missing everything means the
+        // camera is inside it
+        hits.first().is_none_or(|hit| hit.target == Collider::Object(object))
+    }
+
+    /// Where the region's avatars stand
+    async fn avatars(&self) -> Vec<(UserId, Vector3)> {
+        let Some(lludp_server) = &self.lludp_server else {
+            return Vec::new();
+        };
+        lludp_server
+            .get_all_circuits()
+            .await
+            .into_iter()
+            .filter(|circuit| circuit.authenticated)
+            .filter_map(|circuit| Some((circuit.agent_id?, circuit.position)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use mutsea_core::{RegionInfo, SceneObject};
+
+    fn wall(position: Vector3) -> SceneObject {
+        SceneObject {
+            scale: Vector3::new(0.5, 4.0, 4.0),
+            ..test_support::prim(1, UserId::new(), position)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batches_are_limited_and_clicks_validated() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let (front, behind) = (wall(Vector3::new(110.0, 100.0, 30.0)), wall(Vector3::new(120.0, 100.0, 30.0)));
+        let (front_id, behind_id) = (front.id, behind.id);
+        scene.write().await.add_object(front);
+        scene.write().await.add_object(behind);
+        let config = RaycastConfig {
+            max_batch: 2,
+            max_hits: 1,
+            ..RaycastConfig::default()
+        };
+        let raycast = RegionRaycast::new(config, scene);
+
+        let across = RayQuery::new(Vector3::new(100.0, 100.0, 30.0), Vector3::new(130.0, 100.0, 30.0)).with_options(
+            RayOptions {
+                max_hits: 10,
+                ..RayOptions::default()
+            },
+        );
+        let hits = raycast.cast(across.clone()).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].target, Collider::Object(front_id));
+        assert!(matches!(
+            raycast.cast_batch(&[across.clone(), across.clone(), across]).await,
+            Err(RaycastError::BatchTooLarge(3, 2))
+        ));
+        let far = RayQuery::new(Vector3::ZERO, Vector3::new(500.0, 0.0, 0.0));
+        assert!(matches!(raycast.cast(far).await, Err(RaycastError::TooLong(..))));
+
+        let camera = Vector3::new(100.0, 100.0, 30.0);
+        assert!(raycast.validate_click(camera, front_id).await);
+        assert!(!raycast.validate_click(camera, behind_id).await);
+        assert!(!raycast.validate_click(Vector3::new(300.0, 100.0, 30.0), front_id).await);
+        assert!(!raycast.line_of_sight(camera, Vector3::new(125.0, 100.0, 30.0), &[]).await);
+    }
+}
@@ -0,0 +1,448 @@
+//! Self-service account registration
+//!
+//! Accounts are created through the same `OpenSimLoginService::create_user`
+//! path as `mutsea user create`. When email verification is enabled the
+//! registration is held until the emailed token is redeemed.
+
+use async_trait::async_trait;
+use mutsea_core::{config::RegistrationConfig, UserId};
+use mutsea_protocol::login::OpenSimLoginService;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Registration errors, surfaced to the client as-is
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum RegistrationError {
+    /// Registration is turned off
+    #[error("Registration is disabled")]
+    Disabled,
+
+    /// Too many attempts from this address
+    #[error("Too many registration attempts, try again later")]
+    RateLimited,
+
+    /// Field validation failed
+    #[error("{0}")]
+    Invalid(String),
+
+    /// Missing, unknown or already used invite code
+    #[error("A valid invite code is required")]
+    InviteRequired,
+
+    /// Name already taken
+    #[error("The name {0} is already taken")]
+    NameTaken(String),
+
+    /// Unknown or expired verification token
+    #[error("Verification link is invalid or has expired")]
+    InvalidToken,
+
+    /// Account creation failed
+    #[error("Account creation failed: {0}")]
+    Failed(String),
+}
+
+/// A registration request from the form or JSON API
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegistrationRequest {
+    pub first_name: String,
+    pub last_name: String,
+    pub email: String,
+    pub password: String,
+    #[serde(default)]
+    pub invite_code: Option<String>,
+}
+
+/// Result of a successful registration request
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RegistrationOutcome {
+    /// The account exists and can log in
+    Created { user_id: String },
+    /// A verification email was sent; the account is created on verify
+    VerificationSent { email: String },
+}
+
+/// Delivers verification emails
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    /// Send a verification message containing `token` to `email`
+    async fn send_verification(&self, email: &str, name: &str, token: &str);
+}
+
+/// Mailer that writes verification links to the log; used until an SMTP
+/// transport is configured
+pub struct LogMailer {
+    verify_url: String,
+}
+
+impl LogMailer {
+    /// Create a log mailer building links from `verify_url`
+    pub fn new(verify_url: String) -> Self {
+        Self { verify_url }
+    }
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send_verification(&self, email: &str, name: &str, token: &str) {
+        info!("📧 Verification for {} <{}>: {}?token={}", name, email, self.verify_url, token);
+    }
+}
+
+struct PendingRegistration {
+    request: RegistrationRequest,
+    created_at: Instant,
+}
+
+#[derive(Default)]
+struct RegistrationState {
+    pending: HashMap<String, PendingRegistration>,
+    used_invites: HashSet<String>,
+    attempts: HashMap<IpAddr, Vec<Instant>>,
+}
+
+/// Self-service registration service
+pub struct RegistrationService {
+    config: RegistrationConfig,
+    login_service: Arc<OpenSimLoginService>,
+    mailer: Arc<dyn Mailer>,
+    state: Mutex<RegistrationState>,
+}
+
+impl RegistrationService {
+    /// Create a new registration service
+    pub fn new(
+        config: RegistrationConfig,
+        login_service: Arc<OpenSimLoginService>,
+        mailer: Arc<dyn Mailer>,
+    ) -> Self {
+        Self {
+            config,
+            login_service,
+            mailer,
+            state: Mutex::new(RegistrationState::default()),
+        }
+    }
+
+    /// Whether registration is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Whether the form should ask for an invite code
+    pub fn requires_invite(&self) -> bool {
+        self.config.require_invite
+    }
+
+    /// Handle a registration request from `client`
+    pub async fn register(
+        &self,
+        request: RegistrationRequest,
+        client: IpAddr,
+    ) -> Result<RegistrationOutcome, RegistrationError> {
+        if !self.config.enabled {
+            return Err(RegistrationError::Disabled);
+        }
+        self.check_rate_limit(client)?;
+
+        let request = RegistrationRequest {
+            first_name: request.first_name.trim().to_string(),
+            last_name: request.last_name.trim().to_string(),
+            email: request.email.trim().to_lowercase(),
+            invite_code: request.invite_code.map(|c| c.trim().to_string()).filter(|c| !c.is_empty()),
+            ..request
+        };
+        self.validate(&request)?;
+
+        let name = format!("{} {}", request.first_name, request.last_name);
+        if self.login_service.user_exists(&request.first_name, &request.last_name) || self.is_pending(&name) {
+            return Err(RegistrationError::NameTaken(name));
+        }
+
+        // The invite is held while the account is created or verification
+        // is pending, and given back if neither comes to anything
+        self.claim_invite(request.invite_code.as_deref())?;
+
+        if !self.config.require_email_verification {
+            let user_id = self.create_account(&request)?;
+            return Ok(RegistrationOutcome::Created { user_id: user_id.to_string() });
+        }
+
+        let token = Uuid::new_v4().simple().to_string();
+        let email = request.email.clone();
+        self.state.lock().unwrap().pending.insert(
+            token.clone(),
+            PendingRegistration {
+                request,
+                created_at: Instant::now(),
+            },
+        );
+
+        self.mailer.send_verification(&email, &name, &token).await;
+        Ok(RegistrationOutcome::VerificationSent { email })
+    }
+
+    /// Redeem a verification token and create the account
+    pub fn verify(&self, token: &str) -> Result<UserId, RegistrationError> {
+        let pending = {
+            let mut state = self.state.lock().unwrap();
+            self.expire_pending(&mut state);
+            state.pending.remove(token).ok_or(RegistrationError::InvalidToken)?
+        };
+
+        self.create_account(&pending.request)
+    }
+
+    /// Number of registrations awaiting verification
+    pub fn pending_count(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        self.expire_pending(&mut state);
+        state.pending.len()
+    }
+
+    /// Create the account, giving its invite back if that fails
+    fn create_account(&self, request: &RegistrationRequest) -> Result<UserId, RegistrationError> {
+        let user_id = self
+            .login_service
+            .create_user(
+                &request.first_name,
+                &request.last_name,
+                &request.password,
+                Some(request.email.clone()),
+                false,
+            )
+            .map_err(|e| {
+                if let Some(code) = &request.invite_code {
+                    self.state.lock().unwrap().used_invites.remove(code);
+                }
+                RegistrationError::Failed(e.to_string())
+            })?;
+
+        info!("👤 Registered {} {} ({})", request.first_name, request.last_name, user_id);
+        Ok(user_id)
+    }
+
+    fn validate(&self, request: &RegistrationRequest) -> Result<(), RegistrationError> {
+        for (label, name) in [("First name", &request.first_name), ("Last name", &request.last_name)] {
+            if name.len() < 2 || name.len() > 31 {
+                return Err(RegistrationError::Invalid(format!("{} must be 2-31 characters", label)));
+            }
+            if !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(RegistrationError::Invalid(format!("{} may only contain letters and digits", label)));
+            }
+        }
+
+        let valid_email = request
+            .email
+            .split_once('@')
+            .map(|(user, domain)| !user.is_empty() && domain.contains('.') && !domain.ends_with('.'))
+            .unwrap_or(false);
+        if !valid_email {
+            return Err(RegistrationError::Invalid("Email address is not valid".to_string()));
+        }
+
+        if request.password.len() < self.config.min_password_length {
+            return Err(RegistrationError::Invalid(format!(
+                "Password must be at least {} characters",
+                self.config.min_password_length
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn claim_invite(&self, code: Option<&str>) -> Result<(), RegistrationError> {
+        let Some(code) = code else {
+            return if self.config.require_invite {
+                Err(RegistrationError::InviteRequired)
+            } else {
+                Ok(())
+            };
+        };
+
+        if !self.config.invite_codes.iter().any(|c| c == code) {
+            return Err(RegistrationError::InviteRequired);
+        }
+        if !self.state.lock().unwrap().used_invites.insert(code.to_string()) {
+            return Err(RegistrationError::InviteRequired);
+        }
+        Ok(())
+    }
+
+    fn check_rate_limit(&self, client: IpAddr) -> Result<(), RegistrationError> {
+        let window = Duration::from_secs(3600);
+        let mut state = self.state.lock().unwrap();
+        let attempts = state.attempts.entry(client).or_default();
+        attempts.retain(|t| t.elapsed() < window);
+
+        if attempts.len() >= self.config.max_attempts_per_hour as usize {
+            warn!("Registration rate limit hit for {}", client);
+            return Err(RegistrationError::RateLimited);
+        }
+        attempts.push(Instant::now());
+        Ok(())
+    }
+
+    fn is_pending(&self, name: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        self.expire_pending(&mut state);
+        state.pending.values().any(|p| {
+            format!("{} {}", p.request.first_name, p.request.last_name).eq_ignore_ascii_case(name)
+        })
+    }
+
+    /// Drop unverified registrations past their TTL, giving their invites back
+    fn expire_pending(&self, state: &mut RegistrationState) {
+        let ttl = Duration::from_secs(self.config.verification_ttl_hours * 3600);
+        let RegistrationState { pending, used_invites, .. } = state;
+        pending.retain(|_, p| {
+            let live = p.created_at.elapsed() < ttl;
+            if let Some(code) = p.request.invite_code.as_ref().filter(|_| !live) {
+                used_invites.remove(code);
+            }
+            live
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct CapturingMailer {
+        tokens: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Mailer for CapturingMailer {
+        async fn send_verification(&self, _email: &str, _name: &str, token: &str) {
+            self.tokens.lock().unwrap().push(token.to_string());
+        }
+    }
+
+    fn request(first: &str) -> RegistrationRequest {
+        RegistrationRequest {
+            first_name: first.to_string(),
+            last_name: "Resident".to_string(),
+            email: "new@example.com".to_string(),
+            password: "correct horse".to_string(),
+            invite_code: None,
+        }
+    }
+
+    fn client() -> IpAddr {
+        "127.0.0.1".parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_registration_requires_verification() {
+        let login_service = Arc::new(OpenSimLoginService::new());
+        let mailer = Arc::new(CapturingMailer::default());
+        let service = RegistrationService::new(RegistrationConfig::default(), Arc::clone(&login_service), mailer.clone());
+
+        let outcome = service.register(request("Alice"), client()).await.unwrap();
+        assert!(matches!(outcome, RegistrationOutcome::VerificationSent { .. }));
+        assert!(!login_service.user_exists("Alice", "Resident"));
+
+        // The name is reserved while verification is pending
+        let duplicate = service.register(request("alice"), client()).await;
+        assert!(matches!(duplicate, Err(RegistrationError::NameTaken(_))));
+
+        let token = mailer.tokens.lock().unwrap()[0].clone();
+        service.verify(&token).unwrap();
+        assert!(login_service.user_exists("Alice", "Resident"));
+        assert_eq!(service.verify(&token), Err(RegistrationError::InvalidToken));
+    }
+
+    #[tokio::test]
+    async fn test_invite_codes_are_single_use() {
+        let config = RegistrationConfig {
+            require_email_verification: false,
+            require_invite: true,
+            invite_codes: vec!["WELCOME".to_string()],
+            ..Default::default()
+        };
+        let service = RegistrationService::new(config, Arc::new(OpenSimLoginService::new()), Arc::new(CapturingMailer::default()));
+
+        let missing = service.register(request("Bob"), client()).await;
+        assert_eq!(missing, Err(RegistrationError::InviteRequired));
+
+        let mut first = request("Bob");
+        first.invite_code = Some("WELCOME".to_string());
+        assert!(matches!(service.register(first, client()).await, Ok(RegistrationOutcome::Created { .. })));
+
+        let mut second = request("Carol");
+        second.invite_code = Some("WELCOME".to_string());
+        assert_eq!(service.register(second, client()).await, Err(RegistrationError::InviteRequired));
+    }
+
+    #[tokio::test]
+    async fn test_invites_are_given_back_when_no_account_is_made() {
+        let config = RegistrationConfig {
+            require_email_verification: false,
+            require_invite: true,
+            invite_codes: vec!["WELCOME".to_string()],
+            ..Default::default()
+        };
+        let login_service = Arc::new(OpenSimLoginService::new());
+        let service = RegistrationService::new(config, Arc::clone(&login_service), Arc::new(CapturingMailer::default()));
+
+        // A name taken between the check and the account being created
+        login_service.create_user("Grace", "Resident", "correct horse", None, false).unwrap();
+        let mut taken = request("Grace");
+        taken.invite_code = Some("WELCOME".to_string());
+        service.claim_invite(taken.invite_code.as_deref()).unwrap();
+        assert!(matches!(service.create_account(&taken), Err(RegistrationError::Failed(_))));
+
+        let mut retry = request("Heidi");
+        retry.invite_code = Some("WELCOME".to_string());
+        assert!(matches!(service.register(retry, client()).await, Ok(RegistrationOutcome::Created { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_expired_verifications_give_their_invites_back() {
+        let config = RegistrationConfig {
+            require_invite: true,
+            invite_codes: vec!["WELCOME".to_string()],
+            verification_ttl_hours: 0,
+            ..Default::default()
+        };
+        let service = RegistrationService::new(config, Arc::new(OpenSimLoginService::new()), Arc::new(CapturingMailer::default()));
+
+        let mut first = request("Ivan");
+        first.invite_code = Some("WELCOME".to_string());
+        assert!(matches!(service.register(first, client()).await, Ok(RegistrationOutcome::VerificationSent { .. })));
+        assert_eq!(service.pending_count(), 0);
+
+        let mut second = request("Judy");
+        second.invite_code = Some("WELCOME".to_string());
+        assert!(matches!(service.register(second, client()).await, Ok(RegistrationOutcome::VerificationSent { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_and_validation() {
+        let config = RegistrationConfig {
+            max_attempts_per_hour: 2,
+            ..Default::default()
+        };
+        let service = RegistrationService::new(config, Arc::new(OpenSimLoginService::new()), Arc::new(CapturingMailer::default()));
+
+        let mut bad = request("Dave");
+        bad.email = "not-an-email".to_string();
+        assert!(matches!(service.register(bad, client()).await, Err(RegistrationError::Invalid(_))));
+
+        let mut short = request("Erin");
+        short.password = "short".to_string();
+        assert!(matches!(service.register(short, client()).await, Err(RegistrationError::Invalid(_))));
+
+        assert_eq!(service.register(request("Frank"), client()).await, Err(RegistrationError::RateLimited));
+    }
+}
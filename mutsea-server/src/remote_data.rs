@@ -0,0 +1,353 @@
+//! XML-RPC remote data channels for scripts
+//!
+//! llOpenRemoteDataChannel gives a script a channel key. An external
+//! `llRemoteData` XML-RPC call naming the channel is posted to the script
+//! as `remote_data` with REMOTE_DATA_REQUEST, and the caller is answered
+//! with the script's llRemoteDataReply, or a fault if none comes in time.
+
+use mutsea_core::config::ScriptXmlRpcConfig;
+use mutsea_protocol::llsd::xml_escape;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::oneshot;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::scripts::{ScriptEvent, ScriptSandbox, ScriptValue};
+
+/// `remote_data` event types
+pub const REMOTE_DATA_CHANNEL: i32 = 1;
+pub const REMOTE_DATA_REQUEST: i32 = 2;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RemoteDataError {
+    #[error("Invalid llRemoteData call: {0}")]
+    Invalid(String),
+    #[error("No channel {0}")]
+    UnknownChannel(Uuid),
+    #[error("Script is not accepting requests")]
+    Unavailable,
+    #[error("Script did not reply")]
+    TimedOut,
+}
+
+impl RemoteDataError {
+    /// XML-RPC fault code
+    pub fn code(&self) -> i32 {
+        match self {
+            RemoteDataError::Invalid(_) => 1,
+            RemoteDataError::UnknownChannel(_) => 2,
+            RemoteDataError::Unavailable => 3,
+            RemoteDataError::TimedOut => 4,
+        }
+    }
+}
+
+/// The struct passed to and returned by `llRemoteData`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteDataMessage {
+    pub channel: Uuid,
+    pub int_value: i32,
+    pub string_value: String,
+}
+
+/// Script replies awaited by callers, by message ID
+type Pending = HashMap<Uuid, oneshot::Sender<(String, i32)>>;
+
+pub struct RemoteData {
+    config: ScriptXmlRpcConfig,
+    sandbox: ScriptSandbox,
+    /// Script holding each channel
+    channels: Mutex<HashMap<Uuid, Uuid>>,
+    pending: Mutex<Pending>,
+}
+
+impl RemoteData {
+    pub fn new(config: ScriptXmlRpcConfig, sandbox: ScriptSandbox) -> Self {
+        Self {
+            config,
+            sandbox,
+            channels: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Open a channel for `script`, or give it the one it holds, posting it
+    /// as `remote_data` with REMOTE_DATA_CHANNEL
+    pub fn open(&self, script: Uuid) {
+        let channel = {
+            let mut channels = self.channels.lock().unwrap();
+            let held = channels
+                .iter()
+                .find(|(_, holder)| **holder == script)
+                .map(|(channel, _)| *channel);
+            match held {
+                Some(channel) => channel,
+                None if self.config.enabled && channels.len() < self.config.max_channels => {
+                    let channel = Uuid::new_v4();
+                    channels.insert(channel, script);
+                    info!("Script {} opened remote data channel {}", script, channel);
+                    channel
+                }
+                None => {
+                    debug!("Refused remote data channel for script {}", script);
+                    return;
+                }
+            }
+        };
+        self.post(script, REMOTE_DATA_CHANNEL, channel, Uuid::nil(), 0, String::new());
+    }
+
+    /// Close `channel` if `script` holds it
+    pub fn close(&self, script: Uuid, channel: Uuid) {
+        let mut channels = self.channels.lock().unwrap();
+        if channels.get(&channel) == Some(&script) {
+            channels.remove(&channel);
+        }
+    }
+
+    /// Close the channel of `script`, as when it is removed
+    pub fn release_script(&self, script: Uuid) {
+        self.channels.lock().unwrap().retain(|_, holder| *holder != script);
+    }
+
+    /// Answer message `message_id`, from llRemoteDataReply
+    pub fn reply(&self, message_id: Uuid, string_value: String, int_value: i32) -> bool {
+        match self.pending.lock().unwrap().remove(&message_id) {
+            Some(reply) => reply.send((string_value, int_value)).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Pass an `llRemoteData` call to the script holding its channel and
+    /// wait for the reply
+    pub async fn call(&self, message: RemoteDataMessage) -> Result<RemoteDataMessage, RemoteDataError> {
+        let Some(script) = self.channels.lock().unwrap().get(&message.channel).copied() else {
+            return Err(RemoteDataError::UnknownChannel(message.channel));
+        };
+
+        let message_id = Uuid::new_v4();
+        let (reply, answer) = oneshot::channel();
+        self.pending.lock().unwrap().insert(message_id, reply);
+        if !self.post(
+            script,
+            REMOTE_DATA_REQUEST,
+            message.channel,
+            message_id,
+            message.int_value,
+            message.string_value,
+        ) {
+            self.pending.lock().unwrap().remove(&message_id);
+            return Err(RemoteDataError::Unavailable);
+        }
+
+        match tokio::time::timeout(Duration::from_secs(self.config.reply_timeout_secs), answer).await {
+            Ok(Ok((string_value, int_value))) => Ok(RemoteDataMessage {
+                channel: message.channel,
+                int_value,
+                string_value,
+            }),
+            _ => {
+                self.pending.lock().unwrap().remove(&message_id);
+                Err(RemoteDataError::TimedOut)
+            }
+        }
+    }
+
+    fn post(&self, script: Uuid, kind: i32, channel: Uuid, message_id: Uuid, idata: i32, sdata: String) -> bool {
+        let event = ScriptEvent::new(
+            "remote_data",
+            vec![
+                ScriptValue::Integer(kind),
+                ScriptValue::Key(channel),
+                ScriptValue::Key(message_id),
+                ScriptValue::String(String::new()),
+                ScriptValue::Integer(idata),
+                ScriptValue::String(sdata),
+            ],
+        );
+        match self.sandbox.post(script, event) {
+            Ok(()) => true,
+            Err(e) => {
+                debug!("Dropped remote data for script {}: {}", script, e);
+                false
+            }
+        }
+    }
+}
+
+/// The method named by XML-RPC call `xml`
+pub fn method_name(xml: &str) -> Option<String> {
+    let document = roxmltree::Document::parse(xml).ok()?;
+    let method = document.descendants().find(|node| node.has_tag_name("methodName"))?;
+    method.text().map(|name| name.trim().to_string())
+}
+
+/// Read the struct of an `llRemoteData` call
+pub fn parse_call(xml: &str) -> Result<RemoteDataMessage, RemoteDataError> {
+    let invalid = |message: &str| RemoteDataError::Invalid(message.to_string());
+    let document = roxmltree::Document::parse(xml).map_err(|e| RemoteDataError::Invalid(e.to_string()))?;
+
+    let mut channel = None;
+    let mut int_value = 0;
+    let mut string_value = String::new();
+    for member in document.descendants().filter(|node| node.has_tag_name("member")) {
+        let Some(name) = member
+            .children()
+            .find(|node| node.has_tag_name("name"))
+            .and_then(|node| node.text())
+        else {
+            continue;
+        };
+        // Untyped values are strings
+        let value = member
+            .children()
+            .find(|node| node.has_tag_name("value"))
+            .map(|value| {
+                value
+                    .children()
+                    .find(|node| node.is_element())
+                    .map(|typed| typed.text().unwrap_or_default())
+                    .unwrap_or_else(|| value.text().unwrap_or_default())
+            })
+            .unwrap_or_default();
+        match name.trim() {
+            "Channel" => channel = Some(Uuid::parse_str(value.trim()).map_err(|_| invalid("Channel is not a key"))?),
+            "IntValue" => {
+                int_value = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("IntValue is not an integer"))?
+            }
+            "StringValue" => string_value = value.to_string(),
+            _ => {}
+        }
+    }
+
+    Ok(RemoteDataMessage {
+        channel: channel.ok_or_else(|| invalid("No Channel"))?,
+        int_value,
+        string_value,
+    })
+}
+
+/// The XML-RPC response carrying a script's reply
+pub fn encode_reply(reply: &RemoteDataMessage) -> String {
+    format!(
+        "<?xml version=\"1.0\"?><methodResponse><params><param><value><struct>\
+         <member><name>Channel</name><value><string>{}</string></value></member>\
+         <member><name>StringValue</name><value><string>{}</string></value></member>\
+         <member><name>IntValue</name><value><int>{}</int></value></member>\
+         </struct></value></param></params></methodResponse>",
+        reply.channel,
+        xml_escape(&reply.string_value),
+        reply.int_value
+    )
+}
+
+/// The XML-RPC fault for `error`
+pub fn encode_fault(error: &RemoteDataError) -> String {
+    format!(
+        "<?xml version=\"1.0\"?><methodResponse><fault><value><struct>\
+         <member><name>faultCode</name><value><int>{}</int></value></member>\
+         <member><name>faultString</name><value><string>{}</string></value></member>\
+         </struct></value></fault></methodResponse>",
+        error.code(),
+        xml_escape(&error.to_string())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::{ScriptInfo, ScriptRun, ScriptRuntime};
+    use crate::simulation::{FrameContext, SimulationSubsystem};
+    use mutsea_core::config::ScriptsConfig;
+    use mutsea_core::scene::RegionScene;
+    use mutsea_core::{ObjectId, RegionInfo, UserId};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    /// Records the events it is run with
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<ScriptEvent>>,
+    }
+
+    impl ScriptRuntime for Recorder {
+        fn run(&self, _script: Uuid, event: &ScriptEvent, _budget: Duration) -> Result<ScriptRun, String> {
+            self.events.lock().unwrap().push(event.clone());
+            Ok(ScriptRun {
+                memory: 0,
+                finished: true,
+                state_changed: false,
+            })
+        }
+    }
+
+    const CALL: &str = r#"<?xml version="1.0"?>
+        <methodCall><methodName>llRemoteData</methodName><params><param><value><struct>
+        <member><name>Channel</name><value><string>CHANNEL</string></value></member>
+        <member><name>IntValue</name><value><int>7</int></value></member>
+        <member><name>StringValue</name><value>buy &amp; sell</value></member>
+        </struct></value></param></params></methodCall>"#;
+
+    #[tokio::test]
+    async fn test_calls_are_answered_by_the_channel_script() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let recorder = Arc::new(Recorder::default());
+        let mut sandbox = ScriptSandbox::new(ScriptsConfig::default(), scene).with_runtime(recorder.clone());
+        let script = Uuid::new_v4();
+        sandbox.add_script(ScriptInfo {
+            id: script,
+            name: "Gateway".to_string(),
+            object_id: ObjectId::new(),
+            owner_id: UserId::new(),
+        });
+        let remote_data = Arc::new(RemoteData::new(ScriptXmlRpcConfig::default(), sandbox.clone()));
+        let frame = FrameContext {
+            frame: 0,
+            dt: Duration::from_millis(50),
+            degrade_level: 0,
+        };
+
+        remote_data.open(script);
+        sandbox.tick(&frame).await.unwrap();
+        let opened = recorder.events.lock().unwrap().pop().unwrap();
+        assert_eq!(opened.args[0], ScriptValue::Integer(REMOTE_DATA_CHANNEL));
+        let ScriptValue::Key(channel) = opened.args[1] else {
+            panic!("no channel in {:?}", opened);
+        };
+
+        let xml = CALL.replace("CHANNEL", &channel.to_string());
+        assert_eq!(method_name(&xml).as_deref(), Some("llRemoteData"));
+        let message = parse_call(&xml).unwrap();
+        assert_eq!((message.int_value, message.string_value.as_str()), (7, "buy & sell"));
+        let call = tokio::spawn({
+            let remote_data = Arc::clone(&remote_data);
+            async move { remote_data.call(message).await }
+        });
+        while recorder.events.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+            sandbox.tick(&frame).await.unwrap();
+        }
+        let request = recorder.events.lock().unwrap().pop().unwrap();
+        assert_eq!(request.args[0], ScriptValue::Integer(REMOTE_DATA_REQUEST));
+        let ScriptValue::Key(message_id) = request.args[2] else {
+            panic!("no message ID in {:?}", request);
+        };
+        assert!(remote_data.reply(message_id, "sold <1>".to_string(), 1));
+        let reply = call.await.unwrap().unwrap();
+        assert!(encode_reply(&reply).contains("<string>sold &lt;1&gt;</string>"));
+
+        remote_data.close(script, channel);
+        let unknown = parse_call(&xml).unwrap();
+        assert_eq!(
+            remote_data.call(unknown).await,
+            Err(RemoteDataError::UnknownChannel(channel))
+        );
+    }
+}
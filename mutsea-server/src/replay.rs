@@ -0,0 +1,592 @@
+//! Deterministic simulation replay for debugging
+//!
+//! While recording, the inputs to the simulation are kept in memory: every
+//! packet the LLUDP server receives, every simulation frame with the
+//! subsystems it ran and the time step each was given, and the RNG seeds
+//! handed to subsystems. Every `replay.window_secs` a new segment starts
+//! with a scene checkpoint, and every subsystem RNG registered through
+//! [`ReplaySeeds`] is reseeded, so the last one or two segments are enough
+//! to re-run the simulation from the older checkpoint onwards.
+//!
+//! `POST /api/admin/replay` dumps the recording to
+//! `{replay.directory}/{region}-{time}.replay`. A [`Replayer`] loads such a
+//! bundle in a test harness, restores the scene it starts from and feeds the
+//! recorded packets, seeds and frames to freshly built subsystems in order,
+//! as fast as they run. State a subsystem keeps outside the scene and its
+//! RNG, such as timers since its last periodic job, is not in the bundle.
+//!
+//! A bundle is [`MUTSEA_MAGIC`], the bundle version and a reserved `u16`,
+//! little-endian, followed by a gzipped bincode payload holding the starting
+//! checkpoint in its own format.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use mutsea_core::{config::ReplayConfig, scene::RegionScene, RegionId, MUTSEA_MAGIC};
+use mutsea_network::{lludp_server::PacketTap, LLUDPServer};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+
+use crate::checkpoint::{Checkpoint, CheckpointError};
+use crate::npc_movement::NpcMovement;
+use crate::simulation::{FrameContext, SimulationSubsystem, SubsystemKind};
+
+/// Bundle format written by this version
+const BUNDLE_VERSION: u16 = 1;
+
+const HEADER_LEN: usize = 8;
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("Replay I/O: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Replay checkpoint: {0}")]
+    Checkpoint(#[from] CheckpointError),
+    #[error("Not a Mutsea replay bundle")]
+    BadMagic,
+    #[error("Unsupported replay bundle version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Invalid replay bundle payload: {0}")]
+    Payload(String),
+    #[error("Nothing has been recorded yet")]
+    NotRecording,
+}
+
+/// One external input to the simulation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ReplayInput {
+    /// A packet received from a viewer
+    Packet { from: SocketAddr, data: Vec<u8> },
+    /// A simulation frame, with the subsystems that ran and their time steps
+    Tick {
+        frame: u64,
+        degrade_level: u8,
+        runs: Vec<(SubsystemKind, Duration)>,
+    },
+    /// A seed issued to a subsystem RNG
+    Seed { source: String, seed: u64 },
+}
+
+/// An input and when it came, from the start of the recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayEvent {
+    pub offset: Duration,
+    pub input: ReplayInput,
+}
+
+/// Seeds for the RNGs of simulation subsystems. A subsystem registers its
+/// source and takes a seed at the start of each tick, reseeding its RNG
+/// when one was issued since it last asked.
+#[derive(Debug, Clone, Default)]
+pub struct ReplaySeeds {
+    sources: Arc<Mutex<BTreeMap<String, Option<u64>>>>,
+}
+
+impl ReplaySeeds {
+    pub fn register(&self, source: &str) {
+        self.sources.lock().unwrap().entry(source.to_string()).or_insert(None);
+    }
+
+    /// The seed issued to `source` since it last asked, if any
+    pub fn take(&self, source: &str) -> Option<u64> {
+        self.sources.lock().unwrap().get_mut(source).and_then(Option::take)
+    }
+
+    pub fn issue(&self, source: &str, seed: u64) {
+        self.sources.lock().unwrap().insert(source.to_string(), Some(seed));
+    }
+
+    fn sources(&self) -> Vec<String> {
+        self.sources.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+/// A recorded stretch of simulation: the scene it starts from and the
+/// inputs that followed, with the inputs of later segments appended
+#[derive(Debug, Clone)]
+pub struct ReplayBundle {
+    pub base: Checkpoint,
+    pub events: Vec<ReplayEvent>,
+    /// Inputs were dropped because the recording was full
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BundlePayload {
+    region_id: RegionId,
+    base: Vec<u8>,
+    events: Vec<ReplayEvent>,
+    truncated: bool,
+}
+
+impl ReplayBundle {
+    pub fn encode(&self) -> Result<Vec<u8>, ReplayError> {
+        let payload = BundlePayload {
+            region_id: self.base.region_id,
+            base: self.base.encode()?,
+            events: self.events.clone(),
+            truncated: self.truncated,
+        };
+        let raw = bincode::serialize(&payload).map_err(|e| ReplayError::Payload(e.to_string()))?;
+        let mut data = Vec::with_capacity(HEADER_LEN + raw.len() / 4);
+        data.extend_from_slice(&MUTSEA_MAGIC.to_le_bytes());
+        data.extend_from_slice(&BUNDLE_VERSION.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        let mut encoder = GzEncoder::new(data, Compression::fast());
+        encoder.write_all(&raw)?;
+        Ok(encoder.finish()?)
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, ReplayError> {
+        if data.len() < HEADER_LEN || u32::from_le_bytes(data[..4].try_into().unwrap()) != MUTSEA_MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != BUNDLE_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let mut raw = Vec::new();
+        GzDecoder::new(&data[HEADER_LEN..]).read_to_end(&mut raw)?;
+        let payload: BundlePayload = bincode::deserialize(&raw).map_err(|e| ReplayError::Payload(e.to_string()))?;
+        Ok(Self {
+            base: Checkpoint::decode(&payload.base, payload.region_id)?,
+            events: payload.events,
+            truncated: payload.truncated,
+        })
+    }
+
+    /// How long the recording runs
+    pub fn duration(&self) -> Duration {
+        self.events.last().map_or(Duration::ZERO, |event| event.offset)
+    }
+}
+
+/// A dumped bundle
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayDump {
+    pub path: PathBuf,
+    pub starts_at: DateTime<Utc>,
+    pub seconds: f64,
+    pub events: usize,
+    pub truncated: bool,
+}
+
+struct Recorded {
+    at: Instant,
+    input: ReplayInput,
+}
+
+struct Segment {
+    started: Instant,
+    base: Checkpoint,
+    events: Vec<Recorded>,
+    truncated: bool,
+}
+
+/// Records the inputs of one region's simulation
+pub struct ReplayRecorder {
+    config: ReplayConfig,
+    seeds: ReplaySeeds,
+    segments: Mutex<VecDeque<Segment>>,
+    running: AtomicBool,
+}
+
+impl ReplayRecorder {
+    pub fn new(config: ReplayConfig) -> Self {
+        Self {
+            config,
+            seeds: ReplaySeeds::default(),
+            segments: Mutex::new(VecDeque::new()),
+            running: AtomicBool::new(false),
+        }
+    }
+
+    /// Seeds for subsystem RNGs, reissued with every segment
+    pub fn seeds(&self) -> ReplaySeeds {
+        self.seeds.clone()
+    }
+
+    /// Record a simulation frame
+    pub fn record_tick(&self, frame: u64, degrade_level: u8, runs: Vec<(SubsystemKind, Duration)>) {
+        self.record(ReplayInput::Tick {
+            frame,
+            degrade_level,
+            runs,
+        });
+    }
+
+    fn record(&self, input: ReplayInput) {
+        let mut segments = self.segments.lock().unwrap();
+        let recorded: usize = segments.iter().map(|segment| segment.events.len()).sum();
+        if recorded >= self.config.max_events.max(1) && segments.len() > 1 {
+            segments.pop_front();
+        }
+        let Some(segment) = segments.back_mut() else {
+            return;
+        };
+        if segment.truncated || segment.events.len() >= self.config.max_events.max(1) {
+            segment.truncated = true;
+            return;
+        }
+        segment.events.push(Recorded {
+            at: Instant::now(),
+            input,
+        });
+    }
+
+    /// Start a segment from the scene as it is, reseeding subsystem RNGs
+    async fn rotate(&self, scene: &RwLock<RegionScene>, movement: Option<&NpcMovement>) {
+        // Hold the scene so nothing changes it between the checkpoint and
+        // the start of the segment
+        let scene = scene.read().await;
+        let started = Instant::now();
+        let mut segment = Segment {
+            started,
+            base: Checkpoint::capture(&scene, movement, Utc::now()),
+            events: Vec::new(),
+            truncated: false,
+        };
+        for source in self.seeds.sources() {
+            let seed = rand::random();
+            self.seeds.issue(&source, seed);
+            segment.events.push(Recorded {
+                at: started,
+                input: ReplayInput::Seed { source, seed },
+            });
+        }
+
+        let mut segments = self.segments.lock().unwrap();
+        segments.push_back(segment);
+        while segments.len() > 2 {
+            segments.pop_front();
+        }
+    }
+
+    /// The recording so far, from the older segment's checkpoint
+    pub fn bundle(&self) -> Option<ReplayBundle> {
+        let segments = self.segments.lock().unwrap();
+        let first = segments.front()?;
+        Some(ReplayBundle {
+            base: first.base.clone(),
+            events: segments
+                .iter()
+                .flat_map(|segment| &segment.events)
+                .map(|recorded| ReplayEvent {
+                    offset: recorded.at.saturating_duration_since(first.started),
+                    input: recorded.input.clone(),
+                })
+                .collect(),
+            truncated: segments.iter().any(|segment| segment.truncated),
+        })
+    }
+
+    /// Write the recording so far to the replay directory
+    pub async fn dump(&self) -> Result<ReplayDump, ReplayError> {
+        let bundle = self.bundle().ok_or(ReplayError::NotRecording)?;
+        let file_name = format!(
+            "{}-{}.replay",
+            bundle.base.region_id.as_uuid(),
+            Utc::now().format("%Y%m%d-%H%M%S%.3f")
+        );
+        let path = PathBuf::from(&self.config.directory).join(file_name);
+        let dump = ReplayDump {
+            path: path.clone(),
+            starts_at: bundle.base.taken_at,
+            seconds: bundle.duration().as_secs_f64(),
+            events: bundle.events.len(),
+            truncated: bundle.truncated,
+        };
+
+        tokio::task::spawn_blocking(move || write_bundle(&path, &bundle.encode()?))
+            .await
+            .map_err(|e| ReplayError::Io(std::io::Error::other(e)))??;
+        info!(
+            "🎞️ Dumped {} inputs over {:.1}s to {:?}",
+            dump.events, dump.seconds, dump.path
+        );
+        if dump.truncated {
+            warn!("Replay {:?} is missing inputs; raise replay.max_events", dump.path);
+        }
+        Ok(dump)
+    }
+
+    /// Start recording, beginning a new segment every `window_secs`
+    pub fn start(self: &Arc<Self>, scene: Arc<RwLock<RegionScene>>, movement: Option<NpcMovement>) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let recorder = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(recorder.config.window_secs.max(1)));
+            while recorder.running.load(Ordering::SeqCst) {
+                interval.tick().await;
+                recorder.rotate(&scene, movement.as_ref()).await;
+            }
+        });
+        info!(
+            "🎞️ Recording simulation inputs for replay, {}s per segment",
+            self.config.window_secs
+        );
+    }
+
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+impl PacketTap for ReplayRecorder {
+    fn received(&self, from: SocketAddr, packet: &[u8]) {
+        self.record(ReplayInput::Packet {
+            from,
+            data: packet.to_vec(),
+        });
+    }
+}
+
+fn write_bundle(path: &Path, data: &[u8]) -> Result<(), ReplayError> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, data)?;
+    Ok(())
+}
+
+/// Where replayed packets go, usually an LLUDP server bound for the harness
+#[async_trait]
+pub trait PacketSink: Send {
+    async fn deliver(&mut self, from: SocketAddr, packet: &[u8]);
+}
+
+#[async_trait]
+impl PacketSink for LLUDPServer {
+    async fn deliver(&mut self, from: SocketAddr, packet: &[u8]) {
+        self.replay_datagram(from, packet).await;
+    }
+}
+
+/// What re-running a bundle did
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayReport {
+    pub packets: usize,
+    pub frames: usize,
+    pub seeds: usize,
+    /// Frames and subsystems whose tick failed, with the error
+    pub failures: Vec<(u64, SubsystemKind, String)>,
+}
+
+/// Re-runs a replay bundle
+pub struct Replayer {
+    bundle: ReplayBundle,
+}
+
+impl Replayer {
+    pub fn new(bundle: ReplayBundle) -> Self {
+        Self { bundle }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Ok(Self::new(ReplayBundle::decode(&std::fs::read(path)?)?))
+    }
+
+    pub fn bundle(&self) -> &ReplayBundle {
+        &self.bundle
+    }
+
+    /// Put the scene, and the NPCs `movement` knows, back as they were when
+    /// the recording starts
+    pub fn restore(&self, scene: &mut RegionScene, movement: Option<&NpcMovement>) {
+        self.bundle.base.restore_scene(scene);
+        if let Some(movement) = movement {
+            self.bundle.base.restore_npcs(movement);
+        }
+    }
+
+    /// Feed every recorded input in order: packets to `packets`, seeds to
+    /// `seeds` and frames to the subsystems of the kinds that ran in them
+    pub async fn run(
+        &self,
+        subsystems: &mut [(SubsystemKind, &mut dyn SimulationSubsystem)],
+        seeds: &ReplaySeeds,
+        packets: &mut dyn PacketSink,
+    ) -> ReplayReport {
+        let mut report = ReplayReport::default();
+        for event in &self.bundle.events {
+            match &event.input {
+                ReplayInput::Packet { from, data } => {
+                    packets.deliver(*from, data).await;
+                    report.packets += 1;
+                }
+                ReplayInput::Seed { source, seed } => {
+                    seeds.issue(source, *seed);
+                    report.seeds += 1;
+                }
+                ReplayInput::Tick {
+                    frame,
+                    degrade_level,
+                    runs,
+                } => {
+                    for (kind, dt) in runs {
+                        let Some((_, subsystem)) = subsystems.iter_mut().find(|(registered, _)| registered == kind)
+                        else {
+                            continue;
+                        };
+                        let context = FrameContext {
+                            frame: *frame,
+                            dt: *dt,
+                            degrade_level: *degrade_level,
+                        };
+                        if let Err(e) = subsystem.tick(&context).await {
+                            error!("Replayed {} failed on frame {}: {}", kind.as_str(), frame, e);
+                            report.failures.push((*frame, *kind, e.to_string()));
+                        }
+                    }
+                    report.frames += 1;
+                }
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::{MutseaResult, RegionInfo, SceneObject, UserId, Vector3};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Nudges every prim by a random step each tick
+    struct Jitter {
+        scene: Arc<RwLock<RegionScene>>,
+        seeds: ReplaySeeds,
+        rng: StdRng,
+    }
+
+    impl Jitter {
+        fn new(scene: Arc<RwLock<RegionScene>>, seeds: ReplaySeeds) -> Self {
+            seeds.register("jitter");
+            Self {
+                scene,
+                seeds,
+                rng: StdRng::from_entropy(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl SimulationSubsystem for Jitter {
+        async fn tick(&mut self, frame: &FrameContext) -> MutseaResult<()> {
+            if let Some(seed) = self.seeds.take("jitter") {
+                self.rng = StdRng::seed_from_u64(seed);
+            }
+            let mut scene = self.scene.write().await;
+            for object in scene.objects.values_mut() {
+                object.position.x += self.rng.gen_range(-1.0f32..1.0) * frame.dt.as_secs_f32();
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl PacketSink for Vec<(SocketAddr, Vec<u8>)> {
+        async fn deliver(&mut self, from: SocketAddr, packet: &[u8]) {
+            self.push((from, packet.to_vec()));
+        }
+    }
+
+    fn scene() -> Arc<RwLock<RegionScene>> {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let mut scene = RegionScene::new(info, UserId::new());
+        scene.add_object(SceneObject {
+            position: Vector3::new(128.0, 128.0, 25.0),
+            ..SceneObject::new(1, "Crate", UserId::new())
+        });
+        Arc::new(RwLock::new(scene))
+    }
+
+    async fn position(scene: &RwLock<RegionScene>) -> Vector3 {
+        scene.read().await.objects.values().next().unwrap().position
+    }
+
+    #[tokio::test]
+    async fn test_replays_reproduce_the_recorded_run() {
+        let live = scene();
+        let recorder = ReplayRecorder::new(ReplayConfig::default());
+        let mut jitter = Jitter::new(Arc::clone(&live), recorder.seeds());
+        // Nothing is kept before the first segment starts
+        recorder.received("127.0.0.1:9000".parse().unwrap(), b"early");
+        assert!(recorder.bundle().is_none());
+
+        recorder.rotate(&live, None).await;
+        let started_at = position(&live).await;
+        for frame in 0..20 {
+            if frame == 10 {
+                recorder.received("127.0.0.1:9000".parse().unwrap(), b"UseCircuitCode");
+                recorder.rotate(&live, None).await;
+            }
+            let dt = Duration::from_millis(20 + frame);
+            jitter
+                .tick(&FrameContext {
+                    frame,
+                    dt,
+                    degrade_level: 0,
+                })
+                .await
+                .unwrap();
+            recorder.record_tick(frame, 0, vec![(SubsystemKind::Physics, dt)]);
+        }
+        let ended_at = position(&live).await;
+
+        let data = recorder.bundle().unwrap().encode().unwrap();
+        let replayer = Replayer::new(ReplayBundle::decode(&data).unwrap());
+        assert_eq!(replayer.bundle().events.len(), 23);
+        assert!(!replayer.bundle().truncated);
+
+        let replayed = scene();
+        replayer.restore(&mut *replayed.write().await, None);
+        assert_eq!(position(&replayed).await, started_at);
+        let seeds = ReplaySeeds::default();
+        let mut jitter = Jitter::new(Arc::clone(&replayed), seeds.clone());
+        let mut packets = Vec::new();
+        let report = replayer
+            .run(
+                &mut [(SubsystemKind::Physics, &mut jitter as &mut dyn SimulationSubsystem)],
+                &seeds,
+                &mut packets,
+            )
+            .await;
+        assert_eq!((report.packets, report.frames, report.seeds), (1, 20, 2));
+        assert_eq!(packets[0].1, b"UseCircuitCode".to_vec());
+        assert_eq!(position(&replayed).await, ended_at);
+    }
+
+    #[tokio::test]
+    async fn test_recordings_keep_at_most_max_events() {
+        let live = scene();
+        let recorder = ReplayRecorder::new(ReplayConfig {
+            max_events: 3,
+            ..Default::default()
+        });
+        recorder.rotate(&live, None).await;
+        for frame in 0..3 {
+            recorder.record_tick(frame, 0, Vec::new());
+        }
+        assert!(recorder.bundle().unwrap().truncated);
+
+        // A new segment lets the full one go
+        recorder.rotate(&live, None).await;
+        recorder.record_tick(3, 0, Vec::new());
+        let bundle = recorder.bundle().unwrap();
+        assert_eq!((bundle.events.len(), bundle.truncated), (1, false));
+        assert!(matches!(ReplayBundle::decode(b"garbage!"), Err(ReplayError::BadMagic)));
+    }
+}
@@ -0,0 +1,640 @@
+//! Returning prims to their owners
+//!
+//! Parcels with an auto-return time send other people's prims back once
+//! they have been on the parcel that many minutes; the parcel owner's prims
+//! and those set to the parcel's group stay. Parcel owners return prims by
+//! hand from About Land, and estate managers return an agent's prims across
+//! the region from the estate tools. Returned prims go to their owner's
+//! Lost And Found folder, all those of one owner returned together in one
+//! coalesced item. A prim only leaves the region once its item is stored,
+//! so nothing is lost while the database is down; worn prims are never
+//! returned, and returns don't touch balances or the ledger.
+
+use crate::attachments::Attachments;
+use crate::coalesced;
+use crate::inventory::Inventory;
+use crate::moderation::Moderation;
+use crate::scripts::ScriptSandbox;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use mutsea_core::config::ReturnsConfig;
+use mutsea_core::events::{NetworkEventData, ObjectEvent, ObjectEventData};
+use mutsea_core::scene::{Parcel, RegionScene};
+use mutsea_core::{ObjectId, SceneObject, UserId};
+use mutsea_database::schema::{Asset, InventoryItem};
+use mutsea_database::DatabaseManager;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::constants::{asset_types, inventory_types};
+use mutsea_protocol::land::{
+    ESTATE_RETURN_OTHERS_LAND_ONLY, ESTATE_RETURN_SCRIPTED_ONLY, RETURN_GROUP, RETURN_LIST, RETURN_OTHER,
+    RETURN_OWNER,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Permissions an owner keeps on its returned prims
+const OWNER_PERMISSIONS: u32 = 0x7fff_ffff;
+
+/// Folder type of My Inventory, where prims go for owners without a Lost
+/// And Found folder
+const ROOT_FOLDER_TYPE: i32 = 8;
+
+/// Longest auto-return time, a little over a month, in minutes
+const MAX_CLEAN_TIME: i32 = 45_000;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ReturnError {
+    #[error("No parcel has local ID {0}")]
+    UnknownLocalId(i32),
+    #[error("You can only return objects from land you own")]
+    NotParcelOwner,
+    #[error("You are not an estate manager here")]
+    NotManager,
+    #[error("{0} minutes is not an auto-return time")]
+    InvalidTime(i32),
+    #[error("Objects can't be returned without a database")]
+    NoDatabase,
+    #[error("{0} has no inventory folder to return objects to")]
+    NoFolder(UserId),
+    #[error("Failed to return objects: {0}")]
+    Database(String),
+}
+
+/// The prims of one owner returned together
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReturnedObjects {
+    pub owner_id: UserId,
+    /// The coalesced item they were put in
+    pub item_id: Uuid,
+    pub objects: usize,
+}
+
+/// Where and since when a prim liable to auto-return has been
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Arrival {
+    parcel_id: Uuid,
+    since: DateTime<Utc>,
+}
+
+/// Returns prims of the region to their owners. Clones share the
+/// auto-return timers.
+#[derive(Clone)]
+pub struct Returns {
+    config: ReturnsConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: Option<LLUDPServer>,
+    database: Option<Arc<DatabaseManager>>,
+    moderation: Option<Moderation>,
+    attachments: Option<Attachments>,
+    inventory: Option<Inventory>,
+    scripts: Option<ScriptSandbox>,
+    events: broadcast::Sender<ObjectEvent>,
+    /// Prims liable to auto-return, from when they were first seen on
+    /// their parcel
+    arrivals: Arc<Mutex<HashMap<ObjectId, Arrival>>>,
+}
+
+impl Returns {
+    pub fn new(config: ReturnsConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self {
+            config,
+            scene,
+            lludp_server: None,
+            database: None,
+            moderation: None,
+            attachments: None,
+            inventory: None,
+            scripts: None,
+            events,
+            arrivals: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Handle the return requests of the agents connected to
+    /// `lludp_server`, and take returned prims out of their view
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Keep returned prims as assets and items in `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Let the estate managers of `moderation` return prims anywhere
+    pub fn with_moderation(mut self, moderation: Moderation) -> Self {
+        self.moderation = Some(moderation);
+        self
+    }
+
+    /// Leave the prims worn in `attachments` alone
+    pub fn with_attachments(mut self, attachments: Attachments) -> Self {
+        self.attachments = Some(attachments);
+        self
+    }
+
+    /// Show owners' viewers their returned items through `inventory`
+    pub fn with_inventory(mut self, inventory: Inventory) -> Self {
+        self.inventory = Some(inventory);
+        self
+    }
+
+    /// Stop the scripts of returned prims in `scripts`, and tell scripted
+    /// prims apart for the estate tools
+    pub fn with_scripts(mut self, scripts: ScriptSandbox) -> Self {
+        self.scripts = Some(scripts);
+        self
+    }
+
+    /// Prims returned, one event each
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ObjectEvent> {
+        self.events.subscribe()
+    }
+
+    /// `agent` sets the auto-return time of the parcel `local_id` to
+    /// `minutes`, 0 for never
+    pub async fn set_clean_time(&self, agent: UserId, local_id: i32, minutes: i32) -> Result<(), ReturnError> {
+        if !(0..=MAX_CLEAN_TIME).contains(&minutes) {
+            return Err(ReturnError::InvalidTime(minutes));
+        }
+        let mut scene = self.scene.write().await;
+        let parcel = scene
+            .parcels
+            .iter_mut()
+            .find(|parcel| parcel.local_id == local_id)
+            .ok_or(ReturnError::UnknownLocalId(local_id))?;
+        if parcel.owner_id != agent && !self.is_manager(agent) {
+            return Err(ReturnError::NotParcelOwner);
+        }
+        if parcel.other_clean_time != minutes {
+            parcel.other_clean_time = minutes;
+            info!("Parcel \"{}\" returns other people's prims after {} minutes", parcel.name, minutes);
+        }
+        Ok(())
+    }
+
+    /// `agent` returns the prims on the parcel `local_id` picked by the
+    /// ParcelReturnObjects `return_type`, and the `task_ids` on it
+    pub async fn return_from_parcel(
+        &self,
+        agent: UserId,
+        local_id: i32,
+        return_type: u32,
+        owners: &[UserId],
+        task_ids: &[ObjectId],
+    ) -> Result<Vec<ReturnedObjects>, ReturnError> {
+        let objects = {
+            let scene = self.scene.read().await;
+            let parcel = scene
+                .parcels
+                .iter()
+                .find(|parcel| parcel.local_id == local_id)
+                .ok_or(ReturnError::UnknownLocalId(local_id))?;
+            if parcel.owner_id != agent && !self.is_manager(agent) {
+                return Err(ReturnError::NotParcelOwner);
+            }
+            parcel_selection(&scene, parcel, return_type, owners, task_ids)
+        };
+        self.return_objects(objects, Some(agent)).await
+    }
+
+    /// Estate manager `agent` returns the prims of `target` across the
+    /// region, as the `estateobjectreturn` `flags` ask
+    pub async fn return_from_estate(
+        &self,
+        agent: UserId,
+        target: UserId,
+        flags: u32,
+    ) -> Result<Vec<ReturnedObjects>, ReturnError> {
+        if !self.is_manager(agent) {
+            return Err(ReturnError::NotManager);
+        }
+        let scripts = match &self.scripts {
+            Some(scripts) if flags & ESTATE_RETURN_SCRIPTED_ONLY != 0 => scripts.scripts_per_object(),
+            _ => HashMap::new(),
+        };
+        let objects = {
+            let scene = self.scene.read().await;
+            let mut objects: Vec<&SceneObject> = scene
+                .objects
+                .values()
+                .filter(|object| object.owner_id == target)
+                .filter(|object| {
+                    flags & ESTATE_RETURN_OTHERS_LAND_ONLY == 0
+                        || scene
+                            .parcel_at(object.position.x, object.position.y)
+                            .is_none_or(|parcel| parcel.owner_id != target)
+                })
+                .filter(|object| flags & ESTATE_RETURN_SCRIPTED_ONLY == 0 || scripts.get(&object.id).is_some_and(|n| *n > 0))
+                .collect();
+            objects.sort_by_key(|object| object.local_id);
+            objects.into_iter().map(|object| object.id).collect()
+        };
+        self.return_objects(objects, Some(agent)).await
+    }
+
+    /// Return the prims that have outstayed their parcels' auto-return
+    /// time at `now`. Returns how many were returned.
+    pub async fn sweep(&self, now: DateTime<Utc>) -> Result<usize, ReturnError> {
+        let mut due = {
+            let scene = self.scene.read().await;
+            due_for_return(&scene, &mut self.arrivals.lock().unwrap(), now)
+        };
+        due.retain(|object| !self.is_worn(*object));
+        due.truncate(self.config.max_returns_per_sweep);
+        if due.is_empty() {
+            return Ok(0);
+        }
+        let returned = self.return_objects(due, None).await?;
+        Ok(returned.iter().map(|returned| returned.objects).sum())
+    }
+
+    /// Handle return requests from the LLUDP server and auto-return prims
+    /// every `sweep_interval_secs`
+    pub fn spawn(&self) {
+        if self.database.is_some() {
+            let returns = self.clone();
+            let interval = Duration::from_secs(self.config.sweep_interval_secs.max(1));
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    ticker.tick().await;
+                    match returns.sweep(Utc::now()).await {
+                        Ok(0) => {}
+                        Ok(returned) => info!("Auto-returned {} prims", returned),
+                        Err(e) => warn!("Failed to auto-return prims: {}", e),
+                    }
+                }
+            });
+        }
+
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let returns = self.clone();
+        let mut events = lludp_server.subscribe_events();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => returns.handle(event.event_data).await,
+                    Err(RecvError::Lagged(missed)) => warn!("Returns missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle(&self, event: NetworkEventData) {
+        let (agent, result) = match event {
+            NetworkEventData::ParcelCleanTimeSet {
+                agent_id,
+                local_id,
+                minutes,
+                ..
+            } => (agent_id, self.set_clean_time(agent_id, local_id, minutes).await),
+            NetworkEventData::ParcelObjectsReturnRequested {
+                agent_id,
+                local_id,
+                return_type,
+                task_ids,
+                owner_ids,
+                ..
+            } => {
+                let task_ids: Vec<ObjectId> = task_ids.into_iter().map(ObjectId::from_uuid).collect();
+                let result = self
+                    .return_from_parcel(agent_id, local_id, return_type, &owner_ids, &task_ids)
+                    .await;
+                (agent_id, result.map(drop))
+            }
+            NetworkEventData::EstateObjectsReturnRequested {
+                agent_id,
+                target_id,
+                flags,
+                ..
+            } => (agent_id, self.return_from_estate(agent_id, target_id, flags).await.map(drop)),
+            _ => return,
+        };
+        if let Err(e) = result {
+            self.alert(agent, &e.to_string()).await;
+        }
+    }
+
+    /// Put `objects` back in their owners' inventories, one coalesced item
+    /// per owner, and take them out of the region
+    async fn return_objects(
+        &self,
+        objects: Vec<ObjectId>,
+        returner: Option<UserId>,
+    ) -> Result<Vec<ReturnedObjects>, ReturnError> {
+        let database = self.database.as_ref().ok_or(ReturnError::NoDatabase)?;
+        let mut by_owner: HashMap<UserId, Vec<SceneObject>> = HashMap::new();
+        {
+            let scene = self.scene.read().await;
+            for object in objects {
+                if self.is_worn(object) {
+                    continue;
+                }
+                if let Some(object) = scene.objects.get(&object) {
+                    by_owner.entry(object.owner_id).or_default().push(object.clone());
+                }
+            }
+        }
+
+        let mut returned = Vec::new();
+        let mut failure = None;
+        for (owner, prims) in by_owner {
+            match self.store(database, owner, &prims).await {
+                Ok(item_id) => {
+                    self.remove(owner, &prims, returner).await;
+                    returned.push(ReturnedObjects {
+                        owner_id: owner,
+                        item_id,
+                        objects: prims.len(),
+                    });
+                }
+                Err(e) => {
+                    warn!("Failed to return {} prims of {}: {}", prims.len(), owner, e);
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+        match failure {
+            Some(e) if returned.is_empty() => Err(e),
+            _ => Ok(returned),
+        }
+    }
+
+    /// Keep `prims` of `owner` as one item in its Lost And Found folder
+    async fn store(&self, database: &DatabaseManager, owner: UserId, prims: &[SceneObject]) -> Result<Uuid, ReturnError> {
+        let database_error = |e: mutsea_database::DatabaseError| ReturnError::Database(e.to_string());
+        let owner_id = owner.to_string();
+        let folder = match database
+            .get_inventory_folder_of_type(&owner_id, asset_types::LOST_AND_FOUND as i32)
+            .await
+            .map_err(database_error)?
+        {
+            Some(folder) => folder,
+            None => database
+                .get_inventory_folder_of_type(&owner_id, ROOT_FOLDER_TYPE)
+                .await
+                .map_err(database_error)?
+                .ok_or(ReturnError::NoFolder(owner))?,
+        };
+
+        let data = coalesced::encode(prims);
+        let name = coalesced::item_name(prims);
+        let creator_id = prims[0].creator_id.to_string();
+        let mut asset = Asset::new(Uuid::new_v4().to_string(), name.clone(), asset_types::OBJECT as i32, data);
+        asset.creator_id = creator_id.clone();
+        let item_id = Uuid::new_v4();
+        let item = InventoryItem {
+            inventory_id: item_id.to_string(),
+            asset_id: asset.id.clone(),
+            asset_type: asset_types::OBJECT as i32,
+            parent_folder_id: folder,
+            avatar_id: owner_id,
+            name,
+            description: prims[0].description.clone(),
+            next_permissions: OWNER_PERMISSIONS as i32,
+            current_permissions: OWNER_PERMISSIONS as i32,
+            inv_type: inventory_types::OBJECT as i32,
+            creator_id,
+            base_permissions: OWNER_PERMISSIONS as i32,
+            everyone_permissions: 0,
+            creation_date: Utc::now().timestamp() as i32,
+        };
+        database.insert_asset(&asset).await.map_err(database_error)?;
+        database.insert_inventory_item(&item).await.map_err(database_error)?;
+        if let Some(inventory) = &self.inventory {
+            inventory.announce_items(owner, std::slice::from_ref(&item));
+        }
+        Ok(item_id)
+    }
+
+    /// Take the returned `prims` of `owner` out of the region
+    async fn remove(&self, owner: UserId, prims: &[SceneObject], returner: Option<UserId>) {
+        let mut removed = Vec::with_capacity(prims.len());
+        let region_id = {
+            let mut scene = self.scene.write().await;
+            for prim in prims {
+                let parcel_id = scene.parcel_at(prim.position.x, prim.position.y).map(|parcel| parcel.id);
+                if let Some(object) = scene.objects.remove(&prim.id) {
+                    removed.push((object.id, object.local_id, parcel_id));
+                }
+            }
+            scene.info.region_id
+        };
+        {
+            let mut arrivals = self.arrivals.lock().unwrap();
+            for (object, _, _) in &removed {
+                arrivals.remove(object);
+            }
+        }
+        if let Some(scripts) = &self.scripts {
+            for (object, _, _) in &removed {
+                scripts.remove_object(*object);
+            }
+        }
+
+        match returner {
+            Some(returner) => info!("Agent {} returned {} prims of {}", returner, removed.len(), owner),
+            None => info!("Auto-returned {} prims of {}", removed.len(), owner),
+        }
+        for (object, _, parcel_id) in &removed {
+            // No subscribers is fine
+            let _ = self.events.send(ObjectEvent {
+                event_id: Uuid::new_v4(),
+                timestamp: Utc::now(),
+                object_id: *object,
+                region_id,
+                event_data: ObjectEventData::Returned {
+                    owner_id: owner,
+                    returner_id: returner,
+                    parcel_id: *parcel_id,
+                },
+            });
+        }
+
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let local_ids: Vec<u32> = removed.iter().map(|(_, local_id, _)| *local_id).collect();
+        if let Err(e) = lludp_server.send_kill_objects(&local_ids).await {
+            warn!("Failed to remove {} returned prims from viewers: {}", local_ids.len(), e);
+        }
+        let message = format!(
+            "{} of your objects were returned to your Lost And Found folder",
+            removed.len()
+        );
+        if let Err(e) = lludp_server.send_agent_alert(owner, false, &message).await {
+            warn!("Failed to tell agent {} of its returned prims: {}", owner, e);
+        }
+    }
+
+    fn is_manager(&self, agent: UserId) -> bool {
+        self.moderation
+            .as_ref()
+            .is_some_and(|moderation| moderation.is_manager(agent))
+    }
+
+    fn is_worn(&self, object: ObjectId) -> bool {
+        self.attachments
+            .as_ref()
+            .is_some_and(|attachments| attachments.wearer_of(object).is_some())
+    }
+
+    async fn alert(&self, agent: UserId, message: &str) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        if let Err(e) = lludp_server.send_agent_alert(agent, false, message).await {
+            warn!("Failed to alert agent {}: {}", agent, e);
+        }
+    }
+}
+
+/// Whether `object` may stay on `parcel` whatever its auto-return time:
+/// it is the parcel owner's, or set to the parcel's group
+fn belongs_on(parcel: &Parcel, object: &SceneObject) -> bool {
+    object.owner_id == parcel.owner_id || (parcel.group_id.is_some() && object.group_id == parcel.group_id)
+}
+
+/// The prims on `parcel` of `scene` a ParcelReturnObjects picks, by its
+/// `return_type`, the `owners` it lists and the `task_ids` it names, in
+/// local ID order
+pub fn parcel_selection(
+    scene: &RegionScene,
+    parcel: &Parcel,
+    return_type: u32,
+    owners: &[UserId],
+    task_ids: &[ObjectId],
+) -> Vec<ObjectId> {
+    let picked = |object: &SceneObject| {
+        let owners_prim = object.owner_id == parcel.owner_id;
+        let group_prim = !owners_prim && parcel.group_id.is_some() && object.group_id == parcel.group_id;
+        task_ids.contains(&object.id)
+            || (return_type & RETURN_OWNER != 0 && owners_prim)
+            || (return_type & RETURN_GROUP != 0 && group_prim)
+            || (return_type & RETURN_OTHER != 0 && !belongs_on(parcel, object))
+            || (return_type & RETURN_LIST != 0 && owners.contains(&object.owner_id))
+    };
+    let mut objects: Vec<&SceneObject> = scene
+        .objects
+        .values()
+        .filter(|object| parcel.contains(object.position.x, object.position.y) && picked(object))
+        .collect();
+    objects.sort_by_key(|object| object.local_id);
+    objects.into_iter().map(|object| object.id).collect()
+}
+
+/// The prims of `scene` that have outstayed their parcel's auto-return time
+/// at `now`, longest first. `arrivals` is updated to the prims liable to
+/// auto-return, a prim's time starting when it is first seen on a parcel.
+fn due_for_return(scene: &RegionScene, arrivals: &mut HashMap<ObjectId, Arrival>, now: DateTime<Utc>) -> Vec<ObjectId> {
+    let mut liable = HashMap::new();
+    let mut due = Vec::new();
+    for object in scene.objects.values() {
+        let Some(parcel) = scene.parcel_at(object.position.x, object.position.y) else {
+            continue;
+        };
+        if parcel.other_clean_time <= 0 || belongs_on(parcel, object) {
+            continue;
+        }
+        let arrival = match arrivals.get(&object.id) {
+            Some(arrival) if arrival.parcel_id == parcel.id => *arrival,
+            _ => Arrival {
+                parcel_id: parcel.id,
+                since: now,
+            },
+        };
+        if now - arrival.since >= ChronoDuration::minutes(parcel.other_clean_time as i64) {
+            due.push((arrival.since, object.id));
+        }
+        liable.insert(object.id, arrival);
+    }
+    *arrivals = liable;
+    due.sort_by_key(|(since, _)| *since);
+    due.into_iter().map(|(_, object)| object).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use mutsea_core::{RegionInfo, Vector3};
+
+    fn prim(local_id: u32, owner_id: UserId, x: f32) -> SceneObject {
+        test_support::prim(local_id, owner_id, Vector3::new(x, 10.0, 22.0))
+    }
+
+    fn scene(parcel_owner: UserId) -> RegionScene {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        RegionScene::new(info, parcel_owner)
+    }
+
+    #[test]
+    fn test_parcel_selection() {
+        let (owner, visitor, other) = (UserId::new(), UserId::new(), UserId::new());
+        let group = Uuid::new_v4();
+        let mut scene = scene(owner);
+        scene.parcels[0].group_id = Some(group);
+        let mut group_prim = prim(3, visitor, 30.0);
+        group_prim.group_id = Some(group);
+        for object in [prim(1, owner, 10.0), prim(2, visitor, 20.0), group_prim, prim(4, other, 40.0)] {
+            scene.add_object(object);
+        }
+        let parcel = scene.parcels[0].clone();
+        let local_ids = |return_type: u32, owners: &[UserId]| -> Vec<u32> {
+            parcel_selection(&scene, &parcel, return_type, owners, &[])
+                .iter()
+                .map(|id| scene.objects[id].local_id)
+                .collect()
+        };
+
+        assert_eq!(local_ids(RETURN_OWNER, &[]), vec![1]);
+        assert_eq!(local_ids(RETURN_GROUP, &[]), vec![3]);
+        assert_eq!(local_ids(RETURN_OTHER, &[]), vec![2, 4]);
+        assert_eq!(local_ids(RETURN_LIST, &[visitor]), vec![2, 3]);
+        assert!(local_ids(0, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_auto_return_timers() {
+        let (owner, visitor) = (UserId::new(), UserId::new());
+        let mut scene = scene(owner);
+        scene.add_object(prim(1, owner, 10.0));
+        let visiting = prim(2, visitor, 20.0);
+        let visiting_id = visiting.id;
+        scene.add_object(visiting);
+        let mut arrivals = HashMap::new();
+        let start = Utc::now();
+
+        // No auto-return time, nothing is timed
+        assert!(due_for_return(&scene, &mut arrivals, start).is_empty());
+        assert!(arrivals.is_empty());
+
+        scene.parcels[0].other_clean_time = 10;
+        assert!(due_for_return(&scene, &mut arrivals, start).is_empty());
+        assert_eq!(arrivals.len(), 1);
+        let later = start + ChronoDuration::minutes(9);
+        assert!(due_for_return(&scene, &mut arrivals, later).is_empty());
+        let due = due_for_return(&scene, &mut arrivals, start + ChronoDuration::minutes(10));
+        assert_eq!(due, vec![visiting_id]);
+
+        // Given to the parcel owner, it is no longer timed
+        scene.objects.get_mut(&visiting_id).unwrap().owner_id = owner;
+        assert!(due_for_return(&scene, &mut arrivals, later).is_empty());
+        assert!(arrivals.is_empty());
+    }
+}
@@ -0,0 +1,415 @@
+//! Driving the scene from outside
+//!
+//! External game logic and integration tests create, move and delete simple
+//! prims and speak in local chat through the REST API, without a viewer.
+//! Changes are made to the live scene and sent straight to the agents that
+//! can see or hear them.
+
+use crate::limits::{LimitError, Limits};
+use crate::scripts::ScriptSandbox;
+use crate::sounds::Sounds;
+use chrono::Utc;
+use mutsea_core::config::SceneControlConfig;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{ObjectId, ObjectShape, Quaternion, SceneObject, UserId, Vector3};
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::chat::ChatFromSimulator;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SceneControlError {
+    #[error("Unknown region {0}")]
+    UnknownRegion(String),
+    #[error("Unknown object {0}")]
+    UnknownObject(ObjectId),
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+}
+
+/// Shape of a prim made through the API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrimShape {
+    #[default]
+    Box,
+    Cylinder,
+    Sphere,
+}
+
+impl PrimShape {
+    fn object_shape(self) -> ObjectShape {
+        match self {
+            PrimShape::Box => ObjectShape::default(),
+            PrimShape::Cylinder => ObjectShape {
+                profile_curve: 0,
+                ..ObjectShape::default()
+            },
+            PrimShape::Sphere => ObjectShape {
+                path_curve: 32,
+                profile_curve: 5,
+                ..ObjectShape::default()
+            },
+        }
+    }
+}
+
+fn default_name() -> String {
+    "Object".to_string()
+}
+
+/// A prim to make
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewPrim {
+    #[serde(default = "default_name")]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub shape: PrimShape,
+    pub position: Vector3,
+    #[serde(default)]
+    pub rotation: Option<Quaternion>,
+    /// Defaults to half a metre on each side
+    #[serde(default)]
+    pub scale: Option<Vector3>,
+    /// Defaults to the region's owner
+    #[serde(default)]
+    pub owner_id: Option<Uuid>,
+}
+
+/// Where to move a prim; left-out fields stay as they are
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PrimMove {
+    #[serde(default)]
+    pub position: Option<Vector3>,
+    #[serde(default)]
+    pub rotation: Option<Quaternion>,
+    #[serde(default)]
+    pub scale: Option<Vector3>,
+}
+
+/// A line said in local chat
+#[derive(Debug, Clone, Deserialize)]
+pub struct SceneChat {
+    pub message: String,
+    #[serde(default = "default_name")]
+    pub from_name: String,
+    /// Defaults to the middle of the region, on the ground
+    #[serde(default)]
+    pub position: Option<Vector3>,
+}
+
+/// A prim as the API shows it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PrimSummary {
+    pub id: ObjectId,
+    pub local_id: u32,
+    pub name: String,
+    pub owner_id: UserId,
+    pub position: Vector3,
+    pub rotation: Quaternion,
+    pub scale: Vector3,
+}
+
+impl From<&SceneObject> for PrimSummary {
+    fn from(object: &SceneObject) -> Self {
+        Self {
+            id: object.id,
+            local_id: object.local_id,
+            name: object.name.clone(),
+            owner_id: object.owner_id,
+            position: object.position,
+            rotation: object.rotation,
+            scale: object.scale,
+        }
+    }
+}
+
+/// Changes the scene on behalf of API clients
+#[derive(Clone)]
+pub struct SceneControl {
+    config: SceneControlConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: Option<LLUDPServer>,
+    limits: Option<Limits>,
+    sounds: Option<Sounds>,
+    scripts: Option<ScriptSandbox>,
+}
+
+impl SceneControl {
+    pub fn new(config: SceneControlConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            config,
+            scene,
+            lludp_server: None,
+            limits: None,
+            sounds: None,
+            scripts: None,
+        }
+    }
+
+    /// Send the changes to the agents connected to `lludp_server`
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Hold new prims to the region's prim limits in `limits`
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Stop the sounds of deleted prims in `sounds`
+    pub fn with_sounds(mut self, sounds: Sounds) -> Self {
+        self.sounds = Some(sounds);
+        self
+    }
+
+    /// Stop the scripts of deleted prims in `scripts`
+    pub fn with_scripts(mut self, scripts: ScriptSandbox) -> Self {
+        self.scripts = Some(scripts);
+        self
+    }
+
+    /// Make `prim` in `region`
+    pub async fn create(&self, region: &str, prim: NewPrim) -> Result<PrimSummary, SceneControlError> {
+        let scale = prim.scale.unwrap_or(Vector3::new(0.5, 0.5, 0.5));
+        self.check_scale(scale)?;
+        let object = {
+            let mut scene = self.scene.write().await;
+            check_region(&scene, region)?;
+            check_position(&scene, prim.position)?;
+            if let Some(limits) = &self.limits {
+                limits.check_prims(&scene, prim.position, 1)?;
+            }
+            let owner = prim
+                .owner_id
+                .map(UserId::from_uuid)
+                .or_else(|| scene.parcels.first().map(|parcel| parcel.owner_id))
+                .unwrap_or_else(|| UserId::from_uuid(Uuid::nil()));
+            let local_id = scene.objects.values().map(|object| object.local_id).max().unwrap_or(0) + 1;
+            let object = SceneObject {
+                description: prim.description,
+                position: prim.position,
+                rotation: prim.rotation.unwrap_or(Quaternion::IDENTITY),
+                scale,
+                shape: prim.shape.object_shape(),
+                ..SceneObject::new(local_id, prim.name, owner)
+            };
+            scene.add_object(object.clone());
+            object
+        };
+
+        info!("Made prim \"{}\" ({}) through the scene API", object.name, object.id);
+        self.send_update(&object).await;
+        Ok(PrimSummary::from(&object))
+    }
+
+    /// Move, turn or resize `object` in `region`
+    pub async fn update(&self, region: &str, object: ObjectId, change: PrimMove) -> Result<PrimSummary, SceneControlError> {
+        if let Some(scale) = change.scale {
+            self.check_scale(scale)?;
+        }
+        let updated = {
+            let mut scene = self.scene.write().await;
+            check_region(&scene, region)?;
+            if let Some(position) = change.position {
+                check_position(&scene, position)?;
+            }
+            let prim = scene
+                .objects
+                .get_mut(&object)
+                .ok_or(SceneControlError::UnknownObject(object))?;
+            if let Some(position) = change.position {
+                prim.position = position;
+            }
+            if let Some(rotation) = change.rotation {
+                prim.rotation = rotation;
+            }
+            if let Some(scale) = change.scale {
+                prim.scale = scale;
+            }
+            prim.last_updated = Utc::now();
+            prim.clone()
+        };
+
+        self.send_update(&updated).await;
+        Ok(PrimSummary::from(&updated))
+    }
+
+    /// Delete `object` from `region`
+    pub async fn delete(&self, region: &str, object: ObjectId) -> Result<PrimSummary, SceneControlError> {
+        let removed = {
+            let mut scene = self.scene.write().await;
+            check_region(&scene, region)?;
+            scene
+                .objects
+                .remove(&object)
+                .ok_or(SceneControlError::UnknownObject(object))?
+        };
+        if let Some(sounds) = &self.sounds {
+            sounds.forget(object);
+        }
+        if let Some(scripts) = &self.scripts {
+            scripts.remove_object(object);
+        }
+
+        info!("Deleted prim \"{}\" ({}) through the scene API", removed.name, removed.id);
+        if let Some(lludp_server) = &self.lludp_server {
+            if let Err(e) = lludp_server.send_kill_objects(&[removed.local_id]).await {
+                warn!("Failed to send kill of {}: {}", object, e);
+            }
+        }
+        Ok(PrimSummary::from(&removed))
+    }
+
+    /// Say `chat` in `region`'s local chat. Returns how many agents heard it.
+    pub async fn say(&self, region: &str, chat: SceneChat) -> Result<usize, SceneControlError> {
+        if chat.message.trim().is_empty() {
+            return Err(SceneControlError::Invalid("Chat messages can't be empty".to_string()));
+        }
+        let position = {
+            let scene = self.scene.read().await;
+            check_region(&scene, region)?;
+            match chat.position {
+                Some(position) => {
+                    check_position(&scene, position)?;
+                    position
+                }
+                None => {
+                    let (x, y) = (scene.info.size_x / 2, scene.info.size_y / 2);
+                    Vector3::new(x as f32, y as f32, scene.terrain.height_at(x, y))
+                }
+            }
+        };
+
+        let Some(lludp_server) = &self.lludp_server else {
+            return Ok(0);
+        };
+        let said = ChatFromSimulator::said_by_object(&chat.from_name, Uuid::nil(), position, &chat.message);
+        Ok(lludp_server
+            .send_local_chat(&said, self.config.chat_range)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Failed to send chat from {}: {}", chat.from_name, e);
+                0
+            }))
+    }
+
+    fn check_scale(&self, scale: Vector3) -> Result<(), SceneControlError> {
+        let max = self.config.max_prim_scale;
+        if [scale.x, scale.y, scale.z].iter().any(|side| !(0.01..=max).contains(side)) {
+            return Err(SceneControlError::Invalid(format!(
+                "Prims must be between 0.01 and {} metres on each side",
+                max
+            )));
+        }
+        Ok(())
+    }
+
+    async fn send_update(&self, object: &SceneObject) {
+        if let Some(lludp_server) = &self.lludp_server {
+            if let Err(e) = lludp_server.send_object_update(object).await {
+                warn!("Failed to send update of {}: {}", object.id, e);
+            }
+        }
+    }
+}
+
+/// Whether `region` names the scene, or is its ID
+fn check_region(scene: &RegionScene, region: &str) -> Result<(), SceneControlError> {
+    if scene.info.region_name.eq_ignore_ascii_case(region) || scene.info.region_id.to_string() == region {
+        Ok(())
+    } else {
+        Err(SceneControlError::UnknownRegion(region.to_string()))
+    }
+}
+
+fn check_position(scene: &RegionScene, position: Vector3) -> Result<(), SceneControlError> {
+    let inside = (0.0..scene.info.size_x as f32).contains(&position.x)
+        && (0.0..scene.info.size_y as f32).contains(&position.y)
+        && position.z.is_finite();
+    if !inside {
+        return Err(SceneControlError::Invalid(format!(
+            "<{}, {}, {}> is outside the region",
+            position.x, position.y, position.z
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::RegionInfo;
+
+    fn control() -> SceneControl {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        SceneControl::new(SceneControlConfig::default(), scene)
+    }
+
+    fn new_prim(position: Vector3) -> NewPrim {
+        NewPrim {
+            name: "Crate".to_string(),
+            description: String::new(),
+            shape: PrimShape::Box,
+            position,
+            rotation: None,
+            scale: None,
+            owner_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prims_are_made_moved_and_deleted() {
+        let control = control();
+        let prim = control.create("test", new_prim(Vector3::new(10.0, 20.0, 25.0))).await.unwrap();
+        assert_eq!(prim.local_id, 1);
+        assert_eq!(prim.scale, Vector3::new(0.5, 0.5, 0.5));
+
+        let change = PrimMove {
+            position: Some(Vector3::new(12.0, 20.0, 25.0)),
+            ..PrimMove::default()
+        };
+        let moved = control.update("Test", prim.id, change).await.unwrap();
+        assert_eq!(moved.position, Vector3::new(12.0, 20.0, 25.0));
+
+        assert_eq!(control.delete("Test", prim.id).await.unwrap().id, prim.id);
+        assert_eq!(
+            control.delete("Test", prim.id).await,
+            Err(SceneControlError::UnknownObject(prim.id))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_checked() {
+        let control = control();
+        assert_eq!(
+            control.create("Elsewhere", new_prim(Vector3::new(10.0, 20.0, 25.0))).await,
+            Err(SceneControlError::UnknownRegion("Elsewhere".to_string()))
+        );
+        assert!(matches!(
+            control.create("Test", new_prim(Vector3::new(300.0, 20.0, 25.0))).await,
+            Err(SceneControlError::Invalid(_))
+        ));
+        let mut huge = new_prim(Vector3::new(10.0, 20.0, 25.0));
+        huge.scale = Some(Vector3::new(100.0, 1.0, 1.0));
+        assert!(matches!(control.create("Test", huge).await, Err(SceneControlError::Invalid(_))));
+
+        let chat = SceneChat {
+            message: "Round one!".to_string(),
+            from_name: "Game".to_string(),
+            position: None,
+        };
+        assert_eq!(control.say("Test", chat).await, Ok(0));
+    }
+}
@@ -0,0 +1,154 @@
+//! Finding objects in the live scene
+//!
+//! Moderation tools, clean-up jobs and the AI world engine look for objects
+//! by name, owner, area and whether they run scripts. Queries read the
+//! scene the simulation is running rather than the last backup, and answer
+//! with summaries rather than whole prims.
+
+use crate::scripts::ScriptSandbox;
+use mutsea_core::scene::{ObjectQuery, RegionScene};
+use mutsea_core::{ObjectId, SceneObject, UserId, Vector3};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::RwLock;
+
+/// Most objects a query answers with
+pub const MAX_RESULTS: usize = 1000;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SceneQueryError {
+    #[error("Unknown region {0}")]
+    UnknownRegion(String),
+}
+
+/// An object found, with what its scripts are
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ObjectSummary {
+    pub id: ObjectId,
+    pub local_id: u32,
+    pub name: String,
+    pub owner_id: UserId,
+    pub creator_id: UserId,
+    pub position: Vector3,
+    pub scale: Vector3,
+    /// Scripts running in it
+    pub scripts: usize,
+    pub last_updated: chrono::DateTime<chrono::Utc>,
+}
+
+impl ObjectSummary {
+    fn new(object: &SceneObject, scripts: usize) -> Self {
+        Self {
+            id: object.id,
+            local_id: object.local_id,
+            name: object.name.clone(),
+            owner_id: object.owner_id,
+            creator_id: object.creator_id,
+            position: object.position,
+            scale: object.scale,
+            scripts,
+            last_updated: object.last_updated,
+        }
+    }
+}
+
+/// What to look for: the scene criteria, whether the objects must (or must
+/// not) be scripted, and how many to answer with
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SceneSearch {
+    pub query: ObjectQuery,
+    pub scripted: Option<bool>,
+    pub limit: Option<usize>,
+}
+
+/// Answers queries about the region's objects. Clones share the scene.
+#[derive(Clone)]
+pub struct SceneQuery {
+    scene: Arc<RwLock<RegionScene>>,
+    scripts: Option<ScriptSandbox>,
+}
+
+impl SceneQuery {
+    pub fn new(scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self { scene, scripts: None }
+    }
+
+    /// Count the scripts each object runs in `scripts`
+    pub fn with_scripts(mut self, scripts: ScriptSandbox) -> Self {
+        self.scripts = Some(scripts);
+        self
+    }
+
+    /// The objects of `region`, by name or ID, matching `search`, in local
+    /// ID order
+    pub async fn find(&self, region: &str, search: &SceneSearch) -> Result<Vec<ObjectSummary>, SceneQueryError> {
+        let scripts = self
+            .scripts
+            .as_ref()
+            .map(ScriptSandbox::scripts_per_object)
+            .unwrap_or_default();
+        let scene = self.scene.read().await;
+        if !scene.info.region_name.eq_ignore_ascii_case(region) && scene.info.region_id.to_string() != region {
+            return Err(SceneQueryError::UnknownRegion(region.to_string()));
+        }
+        Ok(search_scene(&scene, &scripts, search))
+    }
+}
+
+/// The objects of `scene` matching `search`, given how many scripts each
+/// object runs
+pub fn search_scene(
+    scene: &RegionScene,
+    scripts: &HashMap<ObjectId, usize>,
+    search: &SceneSearch,
+) -> Vec<ObjectSummary> {
+    let limit = search.limit.unwrap_or(MAX_RESULTS).min(MAX_RESULTS);
+    scene
+        .find_objects(&search.query)
+        .into_iter()
+        .map(|object| ObjectSummary::new(object, scripts.get(&object.id).copied().unwrap_or(0)))
+        .filter(|summary| search.scripted.is_none_or(|scripted| (summary.scripts > 0) == scripted))
+        .take(limit)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use mutsea_core::RegionInfo;
+
+    fn prim(local_id: u32, owner_id: UserId) -> SceneObject {
+        test_support::prim(local_id, owner_id, Vector3::new(10.0 * local_id as f32, 10.0, 22.0))
+    }
+
+    #[test]
+    fn test_search_by_scripts_and_limit() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let mut scene = RegionScene::new(info, UserId::new());
+        let owner = UserId::new();
+        let prims: Vec<SceneObject> = (1..=4).map(|local_id| prim(local_id, owner)).collect();
+        let scripts = HashMap::from([(prims[1].id, 2), (prims[3].id, 1)]);
+        for prim in prims {
+            scene.add_object(prim);
+        }
+        let local_ids = |search: &SceneSearch| -> Vec<u32> {
+            search_scene(&scene, &scripts, search).iter().map(|summary| summary.local_id).collect()
+        };
+
+        let scripted = SceneSearch {
+            scripted: Some(true),
+            ..SceneSearch::default()
+        };
+        assert_eq!(local_ids(&scripted), vec![2, 4]);
+        assert_eq!(search_scene(&scene, &scripts, &scripted)[0].scripts, 2);
+        let unscripted = SceneSearch {
+            scripted: Some(false),
+            limit: Some(1),
+            ..SceneSearch::default()
+        };
+        assert_eq!(local_ids(&unscripted), vec![1]);
+    }
+}
@@ -0,0 +1,434 @@
+//! Email for scripts
+//!
+//! llEmail sends from the object's address, `<object-id>@<domain>`. Mail
+//! to another object in the grid goes straight to its inbox, to be read
+//! with llGetNextEmail as the `email` event; other mail is relayed through
+//! the configured SMTP server. Each owner's objects share an hourly limit.
+
+use mutsea_core::config::ScriptEmailConfig;
+use mutsea_core::{ObjectId, UserId, Vector3};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::scripts::{ScriptEvent, ScriptSandbox, ScriptValue};
+
+/// Window of the per-owner email limit
+const RATE_WINDOW: Duration = Duration::from_secs(3600);
+
+/// Longest an SMTP relay may take to answer a command
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An llEmail call
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboundEmail {
+    pub object_id: ObjectId,
+    pub owner_id: UserId,
+    pub object_name: String,
+    pub region_name: String,
+    pub position: Vector3,
+    pub address: String,
+    pub subject: String,
+    pub message: String,
+}
+
+/// An email waiting in an object's inbox
+#[derive(Debug, Clone)]
+struct Email {
+    time: i64,
+    from: String,
+    subject: String,
+    message: String,
+}
+
+pub struct ScriptEmail {
+    config: ScriptEmailConfig,
+    sandbox: ScriptSandbox,
+    /// Recent emails sent, by owner
+    sent: Mutex<HashMap<UserId, VecDeque<Instant>>>,
+    inboxes: Mutex<HashMap<ObjectId, VecDeque<Email>>>,
+}
+
+impl ScriptEmail {
+    pub fn new(config: ScriptEmailConfig, sandbox: ScriptSandbox) -> Self {
+        Self {
+            config,
+            sandbox,
+            sent: Mutex::new(HashMap::new()),
+            inboxes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The address of `object_id`
+    pub fn address(&self, object_id: ObjectId) -> String {
+        format!("{}@{}", object_id.as_uuid(), self.config.domain)
+    }
+
+    /// Deliver `email` to an object in the grid or relay it by SMTP.
+    /// Returns why it was refused, if it was.
+    pub fn send(self: &Arc<Self>, email: OutboundEmail) -> Result<(), String> {
+        if !self.config.enabled {
+            return Err("Email is disabled".to_string());
+        }
+        let (local, domain) =
+            parse_address(&email.address).ok_or_else(|| format!("Invalid address {}", email.address))?;
+        self.count(email.owner_id, Instant::now())?;
+
+        // As on other grids, the message starts with where it came from
+        let p = email.position;
+        let mut message = format!(
+            "Object-Name: {}\nRegion: {}\nLocal-Position: ({}, {}, {})\n\n{}",
+            email.object_name, email.region_name, p.x as i32, p.y as i32, p.z as i32, email.message
+        );
+        truncate(&mut message, self.config.max_message_bytes);
+        let from = self.address(email.object_id);
+        let subject = email.subject.replace(['\r', '\n'], " ");
+
+        if domain.eq_ignore_ascii_case(&self.config.domain) {
+            let to = Uuid::parse_str(local)
+                .map(ObjectId::from_uuid)
+                .map_err(|_| format!("No object {}", email.address))?;
+            self.deliver(
+                to,
+                Email {
+                    time: chrono::Utc::now().timestamp(),
+                    from,
+                    subject,
+                    message,
+                },
+            );
+            return Ok(());
+        }
+
+        if self.config.smtp_host.is_empty() {
+            return Err("Email outside the grid is disabled".to_string());
+        }
+        let email = Arc::clone(self);
+        let to = format!("{}@{}", local, domain);
+        tokio::spawn(async move {
+            match email.relay(&from, &to, &subject, &message).await {
+                Ok(()) => info!("📧 Relayed email from {} to {}", from, to),
+                Err(e) => warn!("Failed to relay email from {} to {}: {}", from, to, e),
+            }
+        });
+        Ok(())
+    }
+
+    /// Count an email from `owner_id`, unless they have sent their limit
+    fn count(&self, owner_id: UserId, now: Instant) -> Result<(), String> {
+        let mut sent = self.sent.lock().unwrap();
+        let recent = sent.entry(owner_id).or_default();
+        while recent.front().is_some_and(|at| now.duration_since(*at) >= RATE_WINDOW) {
+            recent.pop_front();
+        }
+        if recent.len() >= self.config.emails_per_hour as usize {
+            return Err("Too many emails".to_string());
+        }
+        recent.push_back(now);
+        Ok(())
+    }
+
+    fn deliver(&self, to: ObjectId, email: Email) {
+        let mut inboxes = self.inboxes.lock().unwrap();
+        let inbox = inboxes.entry(to).or_default();
+        if inbox.len() >= self.config.max_inbox {
+            debug!("Dropped email to full inbox of {}", to);
+            return;
+        }
+        inbox.push_back(email);
+    }
+
+    /// Post the first email in the inbox of `object_id` from `address` with
+    /// `subject` to `script` as `email`; empty filters match any
+    pub fn next(&self, script: Uuid, object_id: ObjectId, address: &str, subject: &str) {
+        let (email, left) = {
+            let mut inboxes = self.inboxes.lock().unwrap();
+            let Some(inbox) = inboxes.get_mut(&object_id) else {
+                return;
+            };
+            let found = inbox.iter().position(|email| {
+                (address.is_empty() || email.from.eq_ignore_ascii_case(address))
+                    && (subject.is_empty() || email.subject == subject)
+            });
+            let Some(email) = found.and_then(|index| inbox.remove(index)) else {
+                return;
+            };
+            (email, inbox.len())
+        };
+
+        let event = ScriptEvent::new(
+            "email",
+            vec![
+                ScriptValue::String(email.time.to_string()),
+                ScriptValue::String(email.from),
+                ScriptValue::String(email.subject),
+                ScriptValue::String(email.message),
+                ScriptValue::Integer(left as i32),
+            ],
+        );
+        if let Err(e) = self.sandbox.post(script, event) {
+            debug!("Dropped email for script {}: {}", script, e);
+        }
+    }
+
+    /// Discard the inbox of `object_id`, as when it is deleted
+    pub fn forget_object(&self, object_id: ObjectId) {
+        self.inboxes.lock().unwrap().remove(&object_id);
+    }
+
+    async fn relay(&self, from: &str, to: &str, subject: &str, message: &str) -> io::Result<()> {
+        let stream = TcpStream::connect((self.config.smtp_host.as_str(), self.config.smtp_port)).await?;
+        let mut smtp = SmtpSession::new(stream);
+        smtp.expect(220).await?;
+        smtp.command(&format!("EHLO {}", self.config.domain), 250).await?;
+        smtp.command(&format!("MAIL FROM:<{}>", from), 250).await?;
+        smtp.command(&format!("RCPT TO:<{}>", to), 250).await?;
+        smtp.command("DATA", 354).await?;
+        let data = format!(
+            "From: <{}>\r\nTo: <{}>\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\n\
+             Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{}",
+            from,
+            to,
+            subject,
+            chrono::Utc::now().to_rfc2822(),
+            dot_stuff(message)
+        );
+        smtp.command(&format!("{}\r\n.", data), 250).await?;
+        smtp.command("QUIT", 221).await?;
+        Ok(())
+    }
+}
+
+/// The local part and domain of `address`, if it is one plain address
+fn parse_address(address: &str) -> Option<(&str, &str)> {
+    let (local, domain) = address.trim().split_once('@')?;
+    let valid = |part: &str| {
+        !part.is_empty()
+            && !part
+                .chars()
+                .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '<' | '>' | '@' | ',' | ';'))
+    };
+    (valid(local) && valid(domain)).then_some((local, domain))
+}
+
+/// Cut `text` to at most `max` bytes on a character boundary
+fn truncate(text: &mut String, max: usize) {
+    if text.len() > max {
+        let mut end = max;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+}
+
+/// `message` with CRLF line endings, and lines starting with `.` escaped
+/// so they cannot end the DATA command
+fn dot_stuff(message: &str) -> String {
+    message
+        .lines()
+        .map(|line| {
+            if line.starts_with('.') {
+                format!(".{}", line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+/// Commands and replies of one SMTP connection
+struct SmtpSession {
+    stream: BufReader<TcpStream>,
+}
+
+impl SmtpSession {
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    async fn command(&mut self, command: &str, expected: u16) -> io::Result<()> {
+        self.stream
+            .get_mut()
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .await?;
+        self.expect(expected).await
+    }
+
+    /// Read a reply, which may span lines, and check its code
+    async fn expect(&mut self, expected: u16) -> io::Result<()> {
+        loop {
+            let mut line = String::new();
+            let read = tokio::time::timeout(SMTP_TIMEOUT, self.stream.read_line(&mut line))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "SMTP server did not answer"))??;
+            if read == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "SMTP server closed the connection",
+                ));
+            }
+            let code = line.get(..3).and_then(|code| code.parse::<u16>().ok());
+            if code != Some(expected) {
+                return Err(io::Error::other(format!("SMTP server replied {}", line.trim_end())));
+            }
+            // `250-` continues the reply, `250 ` ends it
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::{ScriptInfo, ScriptRun, ScriptRuntime};
+    use crate::simulation::{FrameContext, SimulationSubsystem};
+    use mutsea_core::config::ScriptsConfig;
+    use mutsea_core::scene::RegionScene;
+    use mutsea_core::RegionInfo;
+    use tokio::net::TcpListener;
+    use tokio::sync::RwLock;
+
+    /// Records the events it is run with
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<ScriptEvent>>,
+    }
+
+    impl ScriptRuntime for Recorder {
+        fn run(&self, _script: Uuid, event: &ScriptEvent, _budget: Duration) -> Result<ScriptRun, String> {
+            self.events.lock().unwrap().push(event.clone());
+            Ok(ScriptRun {
+                memory: 0,
+                finished: true,
+                state_changed: false,
+            })
+        }
+    }
+
+    fn email_service(config: ScriptEmailConfig) -> (Arc<ScriptEmail>, ScriptSandbox, Arc<Recorder>) {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let recorder = Arc::new(Recorder::default());
+        let sandbox = ScriptSandbox::new(ScriptsConfig::default(), scene).with_runtime(recorder.clone());
+        (Arc::new(ScriptEmail::new(config, sandbox.clone())), sandbox, recorder)
+    }
+
+    fn email(from: ObjectId, owner_id: UserId, address: &str, subject: &str) -> OutboundEmail {
+        OutboundEmail {
+            object_id: from,
+            owner_id,
+            object_name: "Vendor".to_string(),
+            region_name: "Test".to_string(),
+            position: Vector3::new(128.4, 64.0, 22.0),
+            address: address.to_string(),
+            subject: subject.to_string(),
+            message: "Sold one hat".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_objects_email_each_other_within_the_grid() {
+        let (service, mut sandbox, recorder) = email_service(ScriptEmailConfig {
+            emails_per_hour: 2,
+            ..Default::default()
+        });
+        let (vendor, server, owner) = (ObjectId::new(), ObjectId::new(), UserId::new());
+        let to = service.address(server);
+
+        service.send(email(vendor, owner, &to, "sale")).unwrap();
+        service.send(email(vendor, owner, &to, "restock")).unwrap();
+        assert_eq!(
+            service.send(email(vendor, owner, &to, "sale")),
+            Err("Too many emails".to_string())
+        );
+        assert!(service
+            .send(email(vendor, UserId::new(), "not an address", "sale"))
+            .is_err());
+
+        let script = Uuid::new_v4();
+        sandbox.add_script(ScriptInfo {
+            id: script,
+            name: "Server".to_string(),
+            object_id: server,
+            owner_id: owner,
+        });
+        service.next(script, server, "", "restock");
+        let frame = FrameContext {
+            frame: 0,
+            dt: Duration::from_millis(50),
+            degrade_level: 0,
+        };
+        sandbox.tick(&frame).await.unwrap();
+
+        let events = recorder.events.lock().unwrap();
+        assert_eq!(events[0].name, "email");
+        assert_eq!(events[0].args[1], ScriptValue::String(service.address(vendor)));
+        assert_eq!(events[0].args[2], ScriptValue::String("restock".to_string()));
+        let ScriptValue::String(message) = &events[0].args[3] else {
+            panic!("no message in {:?}", events[0]);
+        };
+        assert!(message.starts_with("Object-Name: Vendor\nRegion: Test\nLocal-Position: (128, 64, 22)\n\n"));
+        assert_eq!(events[0].args[4], ScriptValue::Integer(1));
+    }
+
+    #[tokio::test]
+    async fn test_other_email_is_relayed_by_smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let relay = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            let mut received = Vec::new();
+            write.write_all(b"220 relay ready\r\n").await.unwrap();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let reply: &[u8] = if line.starts_with("EHLO") {
+                    b"250-relay\r\n250 8BITMIME\r\n"
+                } else if line == "DATA" {
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    b"221 bye\r\n"
+                } else if line == "." || line.starts_with("MAIL") || line.starts_with("RCPT") {
+                    b"250 ok\r\n"
+                } else {
+                    b""
+                };
+                write.write_all(reply).await.unwrap();
+                received.push(line);
+                if received.last().unwrap() == "QUIT" {
+                    break;
+                }
+            }
+            received
+        });
+
+        let (service, _, _) = email_service(ScriptEmailConfig {
+            smtp_host: "127.0.0.1".to_string(),
+            smtp_port: port,
+            ..Default::default()
+        });
+        let vendor = ObjectId::new();
+        let mut sale = email(vendor, UserId::new(), "owner@example.com", "Sale\r\nBcc: x@example.com");
+        sale.message = "Totals:\n.hidden".to_string();
+        service.send(sale).unwrap();
+
+        let received = relay.await.unwrap();
+        assert_eq!(received[1], format!("MAIL FROM:<{}>", service.address(vendor)));
+        assert_eq!(received[2], "RCPT TO:<owner@example.com>");
+        // Headers cannot be injected through the subject, and the body
+        // cannot end the message early
+        assert!(received.contains(&"Subject: Sale  Bcc: x@example.com".to_string()));
+        assert!(received.contains(&"..hidden".to_string()));
+    }
+}
@@ -0,0 +1,523 @@
+//! HTTP for scripts
+//!
+//! llHTTPRequest sends a request from the region and posts the reply to the
+//! script as `http_response`. Each owner's scripts share a rate limit, and
+//! requests may only go to the configured hosts, never to the region's own
+//! network. llRequestURL gives a script a URL under the caps server;
+//! requests to it are posted as `http_request` and answered by the script's
+//! llHTTPResponse, or time out.
+
+use mutsea_core::config::ScriptHttpConfig;
+use mutsea_core::UserId;
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::scripts::{ScriptEvent, ScriptSandbox, ScriptValue};
+
+/// Window of the per-owner request limit
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Status posted to scripts for requests that failed or were refused,
+/// as in OpenSim
+pub const STATUS_FAILED: i32 = 499;
+
+/// An llHTTPRequest, its options already parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboundRequest {
+    pub method: String,
+    pub url: String,
+    pub mimetype: String,
+    pub body: String,
+    /// HTTP_BODY_MAXLENGTH; the response body is cut to this many bytes
+    pub max_body: usize,
+}
+
+/// Requests to script URLs waiting for llHTTPResponse, by request ID
+type Pending = HashMap<Uuid, oneshot::Sender<(u16, String)>>;
+
+pub struct ScriptHttp {
+    config: ScriptHttpConfig,
+    sandbox: ScriptSandbox,
+    client: reqwest::Client,
+    /// Public base of the caps server, ending in `/`
+    base_url: String,
+    /// Recent request times, by owner
+    requests: Mutex<HashMap<UserId, VecDeque<Instant>>>,
+    /// Script holding each URL, by URL ID
+    urls: Mutex<HashMap<Uuid, Uuid>>,
+    pending: Mutex<Pending>,
+}
+
+impl ScriptHttp {
+    pub fn new(config: ScriptHttpConfig, sandbox: ScriptSandbox, base_url: &str) -> Result<Self, reqwest::Error> {
+        let client = client_builder(&config).build()?;
+        let base_url = if base_url.ends_with('/') {
+            base_url.to_string()
+        } else {
+            format!("{}/", base_url)
+        };
+        Ok(Self {
+            config,
+            sandbox,
+            client,
+            base_url,
+            requests: Mutex::new(HashMap::new()),
+            urls: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Send `request` for `script` and post the response as `http_response`
+    /// with `request_id`
+    pub fn request(self: &Arc<Self>, script: Uuid, owner_id: UserId, request_id: Uuid, request: OutboundRequest) {
+        if let Err(reason) = self.check(owner_id, &request.url, Instant::now()) {
+            debug!("Refused HTTP request from script {}: {}", script, reason);
+            self.post_response(script, request_id, STATUS_FAILED, reason);
+            return;
+        }
+
+        let http = Arc::clone(self);
+        tokio::spawn(async move {
+            let (status, body) = match http.send(&request).await {
+                Ok(response) => response,
+                Err(e) => (STATUS_FAILED, e),
+            };
+            http.post_response(script, request_id, status, body);
+        });
+    }
+
+    /// Whether `owner_id` may send a request to `url` now, counting it if so
+    fn check(&self, owner_id: UserId, url: &str, now: Instant) -> Result<(), String> {
+        if !self.config.enabled {
+            return Err("HTTP requests are disabled".to_string());
+        }
+        let url = reqwest::Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("Unsupported scheme {}", url.scheme()));
+        }
+        let host = url
+            .host_str()
+            .unwrap_or_default()
+            .trim_matches(['[', ']'])
+            .to_ascii_lowercase();
+        if !self.host_allowed(&host) {
+            return Err(format!("Host {} is not allowed", host));
+        }
+
+        let mut requests = self.requests.lock().unwrap();
+        let recent = requests.entry(owner_id).or_default();
+        while recent.front().is_some_and(|at| now.duration_since(*at) >= RATE_WINDOW) {
+            recent.pop_front();
+        }
+        if recent.len() >= self.config.requests_per_minute as usize {
+            return Err("Too many HTTP requests".to_string());
+        }
+        recent.push_back(now);
+        Ok(())
+    }
+
+    fn host_allowed(&self, host: &str) -> bool {
+        if host.is_empty() || host == "localhost" || host.ends_with(".localhost") {
+            return false;
+        }
+        if host.parse::<IpAddr>().is_ok_and(is_local) {
+            return false;
+        }
+        self.config.allowed_hosts.is_empty()
+            || self.config.allowed_hosts.iter().any(|allowed| {
+                let allowed = allowed.to_ascii_lowercase();
+                match allowed.strip_prefix("*.") {
+                    Some(domain) => host
+                        .strip_suffix(domain)
+                        .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.')),
+                    None => host == allowed,
+                }
+            })
+    }
+
+    async fn send(&self, request: &OutboundRequest) -> Result<(i32, String), String> {
+        // Names may resolve to the region's own network too
+        let url = reqwest::Url::parse(&request.url).map_err(|e| e.to_string())?;
+        let host = url.host_str().unwrap_or_default().trim_matches(['[', ']']);
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| e.to_string())?
+            .collect();
+        if addresses.iter().any(|address| is_local(address.ip())) {
+            return Err(format!("Host {} is not allowed", host));
+        }
+        let address = *addresses.first().ok_or_else(|| format!("Host {} has no address", host))?;
+
+        // Connect to the address just checked, not to whatever the name
+        // resolves to by the time the request is sent
+        let client = if host.parse::<IpAddr>().is_ok() {
+            self.client.clone()
+        } else {
+            client_builder(&self.config)
+                .resolve(host, address)
+                .build()
+                .map_err(|e| e.to_string())?
+        };
+        self.fetch(&client, request).await.map_err(|e| e.to_string())
+    }
+
+    async fn fetch(&self, client: &reqwest::Client, request: &OutboundRequest) -> Result<(i32, String), reqwest::Error> {
+        let method = reqwest::Method::from_bytes(request.method.as_bytes()).unwrap_or(reqwest::Method::GET);
+        let mut builder = client.request(method, &request.url);
+        if !request.body.is_empty() {
+            builder = builder
+                .header(reqwest::header::CONTENT_TYPE, &request.mimetype)
+                .body(request.body.clone());
+        }
+        let mut response = builder.send().await?;
+        let status = response.status().as_u16() as i32;
+
+        // Stop reading once the script's limit is reached
+        let limit = request.max_body.min(self.config.max_body_bytes);
+        let mut body = Vec::new();
+        while body.len() < limit {
+            let Some(chunk) = response.chunk().await? else {
+                break;
+            };
+            body.extend_from_slice(&chunk);
+        }
+        body.truncate(limit);
+        Ok((status, String::from_utf8_lossy(&body).into_owned()))
+    }
+
+    fn post_response(&self, script: Uuid, request_id: Uuid, status: i32, body: String) {
+        let event = ScriptEvent::new(
+            "http_response",
+            vec![
+                ScriptValue::Key(request_id),
+                ScriptValue::Integer(status),
+                ScriptValue::List(Vec::new()),
+                ScriptValue::String(body),
+            ],
+        );
+        if let Err(e) = self.sandbox.post(script, event) {
+            debug!("Dropped HTTP response for script {}: {}", script, e);
+        }
+    }
+
+    /// Give `script` a URL, posting it as `http_request` with `request_id`
+    /// and URL_REQUEST_GRANTED, or URL_REQUEST_DENIED if none are left
+    pub fn grant_url(&self, script: Uuid, request_id: Uuid) {
+        let granted = {
+            let mut urls = self.urls.lock().unwrap();
+            if self.config.enabled && urls.len() < self.config.max_urls {
+                let url_id = Uuid::new_v4();
+                urls.insert(url_id, script);
+                Some(self.url(url_id))
+            } else {
+                None
+            }
+        };
+        let (method, body) = match granted {
+            Some(url) => {
+                info!("Script {} was granted URL {}", script, url);
+                ("URL_REQUEST_GRANTED", url)
+            }
+            None => ("URL_REQUEST_DENIED", "No free URLs".to_string()),
+        };
+        self.post_request(script, request_id, method, body);
+    }
+
+    fn url(&self, url_id: Uuid) -> String {
+        format!("{}caps/{}/lslhttp/", self.base_url, url_id)
+    }
+
+    /// Release `url` if `script` holds it
+    pub fn release_url(&self, script: Uuid, url: &str) {
+        let mut urls = self.urls.lock().unwrap();
+        urls.retain(|url_id, holder| !(*holder == script && self.url(*url_id) == url));
+    }
+
+    /// Release the URLs of `script`, as when it is removed
+    pub fn release_script(&self, script: Uuid) {
+        self.urls.lock().unwrap().retain(|_, holder| *holder != script);
+    }
+
+    fn post_request(&self, script: Uuid, request_id: Uuid, method: &str, body: String) -> bool {
+        let event = ScriptEvent::new(
+            "http_request",
+            vec![
+                ScriptValue::Key(request_id),
+                ScriptValue::String(method.to_string()),
+                ScriptValue::String(body),
+            ],
+        );
+        match self.sandbox.post(script, event) {
+            Ok(()) => true,
+            Err(e) => {
+                debug!("Dropped HTTP request for script {}: {}", script, e);
+                false
+            }
+        }
+    }
+
+    /// Answer request `request_id` to a script URL, from llHTTPResponse
+    pub fn respond(&self, request_id: Uuid, status: i32, body: String) -> bool {
+        let Some(reply) = self.pending.lock().unwrap().remove(&request_id) else {
+            return false;
+        };
+        let status = u16::try_from(status)
+            .ok()
+            .filter(|status| (100..600).contains(status))
+            .unwrap_or(500);
+        reply.send((status, body)).is_ok()
+    }
+
+    /// Pass a `method` request with `body` to the script holding URL
+    /// `url_id` and wait for its answer. Returns the status and body to
+    /// send back.
+    pub async fn handle(&self, url_id: Uuid, method: &str, mut body: String) -> (u16, String) {
+        let Some(script) = self.urls.lock().unwrap().get(&url_id).copied() else {
+            return (404, "Not found".to_string());
+        };
+        if body.len() > self.config.max_body_bytes {
+            let mut end = self.config.max_body_bytes;
+            while !body.is_char_boundary(end) {
+                end -= 1;
+            }
+            body.truncate(end);
+        }
+
+        let request_id = Uuid::new_v4();
+        let (reply, answer) = oneshot::channel();
+        self.pending.lock().unwrap().insert(request_id, reply);
+        if !self.post_request(script, request_id, method, body) {
+            self.pending.lock().unwrap().remove(&request_id);
+            return (503, "Script is not accepting requests".to_string());
+        }
+
+        match tokio::time::timeout(Duration::from_secs(self.config.url_timeout_secs), answer).await {
+            Ok(Ok(answer)) => answer,
+            _ => {
+                self.pending.lock().unwrap().remove(&request_id);
+                (504, "Script did not respond".to_string())
+            }
+        }
+    }
+}
+
+/// Clients for script requests. Redirects are not followed, as they could
+/// lead anywhere, the region's own network included; scripts get the
+/// redirect itself.
+fn client_builder(config: &ScriptHttpConfig) -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .user_agent(concat!("Mutsea/", env!("CARGO_PKG_VERSION")))
+        .redirect(reqwest::redirect::Policy::none())
+}
+
+/// Whether `ip` is on the loopback, a private, shared (CGNAT) or link-local
+/// network, in "this network" (0.0.0.0/8), or reached through a NAT64
+/// gateway that could translate it to any of those
+fn is_local(ip: IpAddr) -> bool {
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ip => ip,
+    };
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || a == 0
+                || (a == 100 && (b & 0xc0) == 64)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            let first = segments[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || (first & 0xffc0) == 0xfec0
+                || segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::{ScriptInfo, ScriptRun, ScriptRuntime};
+    use crate::simulation::{FrameContext, SimulationSubsystem};
+    use mutsea_core::config::ScriptsConfig;
+    use mutsea_core::scene::RegionScene;
+    use mutsea_core::{ObjectId, RegionInfo};
+    use tokio::sync::RwLock;
+
+    /// Records the events it is run with
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<ScriptEvent>>,
+    }
+
+    impl ScriptRuntime for Recorder {
+        fn run(&self, _script: Uuid, event: &ScriptEvent, _budget: Duration) -> Result<ScriptRun, String> {
+            self.events.lock().unwrap().push(event.clone());
+            Ok(ScriptRun {
+                memory: 0,
+                finished: true,
+                state_changed: false,
+            })
+        }
+    }
+
+    fn http(config: ScriptHttpConfig) -> (Arc<ScriptHttp>, ScriptSandbox, Arc<Recorder>, Uuid) {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let recorder = Arc::new(Recorder::default());
+        let sandbox = ScriptSandbox::new(ScriptsConfig::default(), scene).with_runtime(recorder.clone());
+        let script = Uuid::new_v4();
+        sandbox.add_script(ScriptInfo {
+            id: script,
+            name: "Counter".to_string(),
+            object_id: ObjectId::new(),
+            owner_id: UserId::new(),
+        });
+        let http = ScriptHttp::new(config, sandbox.clone(), "http://grid.example.com:9000").unwrap();
+        (Arc::new(http), sandbox, recorder, script)
+    }
+
+    async fn run_events(sandbox: &mut ScriptSandbox) {
+        let frame = FrameContext {
+            frame: 0,
+            dt: Duration::from_millis(50),
+            degrade_level: 0,
+        };
+        sandbox.tick(&frame).await.unwrap();
+    }
+
+    #[test]
+    fn test_requests_are_limited_by_host_and_owner() {
+        let config = ScriptHttpConfig {
+            allowed_hosts: vec!["*.example.com".to_string(), "api.test".to_string()],
+            requests_per_minute: 2,
+            ..Default::default()
+        };
+        let (http, _, _, _) = http(config);
+        let (owner, other) = (UserId::new(), UserId::new());
+        let now = Instant::now();
+
+        assert!(http.check(owner, "http://10.0.0.5/", now).is_err());
+        assert!(http.check(owner, "http://[::1]:8080/", now).is_err());
+        assert!(http.check(owner, "http://[::ffff:127.0.0.1]/", now).is_err());
+        assert!(http.check(owner, "ftp://files.example.com/", now).is_err());
+        assert!(http.check(owner, "https://example.com.evil.net/", now).is_err());
+        assert!(http.check(owner, "https://shop.example.com/buy", now).is_ok());
+        assert!(http.check(owner, "https://API.test/", now).is_ok());
+        // Refused requests do not count, but each owner has their own limit
+        assert!(http.check(owner, "https://shop.example.com/buy", now).is_err());
+        assert!(http.check(other, "https://shop.example.com/buy", now).is_ok());
+        assert!(http
+            .check(owner, "https://shop.example.com/buy", now + RATE_WINDOW)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_local_addresses() {
+        for local in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "255.255.255.255",
+            "0.0.0.0", "0.1.2.3", "100.64.0.1", "100.127.255.254", "::1", "::", "fd00::1", "fe80::1",
+            "fec0::1", "64:ff9b::a9fe:a9fe", "::ffff:100.64.0.1",
+        ] {
+            assert!(is_local(local.parse().unwrap()), "{} is local", local);
+        }
+        for public in ["8.8.8.8", "100.63.255.255", "100.128.0.1", "1.0.0.1", "2001:db8::1", "64:ff9b:1::1"] {
+            assert!(!is_local(public.parse().unwrap()), "{} is public", public);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_script_urls_are_answered_by_the_script() {
+        let (http, mut sandbox, recorder, script) = http(ScriptHttpConfig::default());
+        let request_id = Uuid::new_v4();
+        http.grant_url(script, request_id);
+        run_events(&mut sandbox).await;
+        let granted = recorder.events.lock().unwrap().pop().unwrap();
+        assert_eq!(granted.args[1], ScriptValue::String("URL_REQUEST_GRANTED".to_string()));
+        let ScriptValue::String(url) = &granted.args[2] else {
+            panic!("no URL in {:?}", granted);
+        };
+        let url_id: Uuid = url
+            .strip_prefix("http://grid.example.com:9000/caps/")
+            .and_then(|rest| rest.strip_suffix("/lslhttp/"))
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        let visit = tokio::spawn({
+            let http = Arc::clone(&http);
+            async move { http.handle(url_id, "GET", String::new()).await }
+        });
+        while recorder.events.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+            run_events(&mut sandbox).await;
+        }
+        let request = recorder.events.lock().unwrap().pop().unwrap();
+        assert_eq!(request.name, "http_request");
+        let ScriptValue::Key(id) = request.args[0] else {
+            panic!("no request ID in {:?}", request);
+        };
+        assert!(http.respond(id, 200, "Visitors: 3".to_string()));
+        assert_eq!(visit.await.unwrap(), (200, "Visitors: 3".to_string()));
+
+        http.release_url(script, url);
+        assert_eq!(http.handle(url_id, "GET", String::new()).await.0, 404);
+    }
+
+    #[tokio::test]
+    async fn test_urls_are_denied_when_none_are_left() {
+        let (http, mut sandbox, recorder, script) = http(ScriptHttpConfig {
+            max_urls: 1,
+            ..Default::default()
+        });
+        http.grant_url(script, Uuid::new_v4());
+        http.grant_url(script, Uuid::new_v4());
+        run_events(&mut sandbox).await;
+        run_events(&mut sandbox).await;
+
+        let events = recorder.events.lock().unwrap();
+        assert_eq!(events[1].args[1], ScriptValue::String("URL_REQUEST_DENIED".to_string()));
+        assert_eq!(http.urls.lock().unwrap().len(), 1);
+        http.release_script(script);
+        assert_eq!(http.urls.lock().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_redirects_are_not_followed() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            let redirect = "HTTP/1.1 302 Found\r\nLocation: http://127.0.0.1:1/admin\r\nContent-Length: 0\r\n\r\n";
+            stream.write_all(redirect.as_bytes()).await.unwrap();
+        });
+
+        let (http, _, _, _) = http(ScriptHttpConfig::default());
+        let request = OutboundRequest {
+            method: "GET".to_string(),
+            url: format!("http://{}/", address),
+            mimetype: "text/plain".to_string(),
+            body: String::new(),
+            max_body: 2048,
+        };
+        assert_eq!(http.fetch(&http.client, &request).await.unwrap().0, 302);
+    }
+}
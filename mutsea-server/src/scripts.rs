@@ -0,0 +1,643 @@
+//! Script sandbox: resource quotas, throttling and per-script profiling
+//!
+//! Scripts are executed by a [`ScriptRuntime`]; the sandbox decides when
+//! each one runs and holds it to its quotas. Events wait in a bounded queue
+//! per script and are handed to the runtime in turn, within the frame's
+//! script budget and what is left of the script's CPU quota for the current
+//! second. A script over its quota for several seconds in a row is
+//! suspended for a while, and one using more memory than allowed, or
+//! failing, is stopped. The time each script takes is kept so the heaviest
+//! can be listed with their owner and position.
+//!
+//! Runtimes that compile sources, such as the LSL to WebAssembly executor,
+//! also load scripts and snapshot their state so it survives a move to
+//! another region.
+
+use async_trait::async_trait;
+use mutsea_core::config::ScriptsConfig;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{MutseaResult, ObjectId, Quaternion, UserId, Vector3};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::limits::{LimitError, Limits};
+use crate::simulation::{FrameContext, SimulationSubsystem};
+
+/// Length of a CPU accounting window
+const WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum ScriptError {
+    #[error("No script {0}")]
+    UnknownScript(Uuid),
+    #[error("Event queue of script {0} is full")]
+    QueueFull(Uuid),
+    #[error("Script {0} is stopped")]
+    Stopped(Uuid),
+    #[error("No object {0} in the region")]
+    UnknownObject(ObjectId),
+    #[error("No script runtime loads sources")]
+    NoRuntime,
+    #[error("Script did not load: {0}")]
+    Load(String),
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+}
+
+/// A value passed to a script event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ScriptValue {
+    Integer(i32),
+    Float(f32),
+    String(String),
+    Key(Uuid),
+    Vector(Vector3),
+    Rotation(Quaternion),
+    List(Vec<ScriptValue>),
+}
+
+/// An event for a script, such as `touch_start` or `timer`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptEvent {
+    pub name: String,
+    pub args: Vec<ScriptValue>,
+}
+
+impl ScriptEvent {
+    pub fn new(name: impl Into<String>, args: Vec<ScriptValue>) -> Self {
+        Self {
+            name: name.into(),
+            args,
+        }
+    }
+}
+
+/// A script in a prim's inventory
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptInfo {
+    /// Inventory item ID of the script
+    pub id: Uuid,
+    pub name: String,
+    pub object_id: ObjectId,
+    pub owner_id: UserId,
+}
+
+/// How far a runtime got with an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptRun {
+    /// Bytes the script uses now
+    pub memory: usize,
+    /// The handler returned; otherwise it was preempted and resumes with the
+    /// same event next time
+    pub finished: bool,
+    /// The script changed state, dropping the events queued for the old one
+    pub state_changed: bool,
+}
+
+/// Executes scripts for the sandbox
+pub trait ScriptRuntime: Send + Sync {
+    /// Run `script`'s handler for `event`, yielding once `budget` is spent.
+    /// An error is a runtime fault and stops the script.
+    fn run(&self, script: Uuid, event: &ScriptEvent, budget: Duration) -> Result<ScriptRun, String>;
+
+    /// Compile `source` and load it as `script`, restoring its state from
+    /// `snapshot` if given. Returns whether it starts afresh and should be
+    /// sent `state_entry`.
+    fn load(&self, script: &ScriptInfo, source: &str, snapshot: Option<&[u8]>) -> Result<bool, String> {
+        let _ = (script, source, snapshot);
+        Err("this runtime does not load sources".to_string())
+    }
+
+    fn unload(&self, script: Uuid) {
+        let _ = script;
+    }
+
+    /// The state of `script` between events, to restore with [`ScriptRuntime::load`]
+    fn snapshot(&self, script: Uuid) -> Option<Vec<u8>> {
+        let _ = script;
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptStatus {
+    Running,
+    /// Suspended for using too much CPU
+    Throttled,
+    /// Stopped by a fault or the memory limit
+    Stopped,
+}
+
+/// What a script costs, heaviest first in [`ScriptSandbox::top`]
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptProfile {
+    #[serde(flatten)]
+    pub info: ScriptInfo,
+    /// Where the script's prim is, if it is in the scene
+    pub position: Option<Vector3>,
+    pub status: ScriptStatus,
+    /// Recent CPU use in milliseconds per second
+    pub cpu_ms_per_sec: f64,
+    pub total_cpu_ms: f64,
+    pub events_run: u64,
+    pub events_queued: usize,
+    pub events_dropped: u64,
+    pub memory_kb: f64,
+    pub times_throttled: u32,
+    pub fault: Option<String>,
+}
+
+struct Script {
+    info: ScriptInfo,
+    queue: VecDeque<ScriptEvent>,
+    status: ScriptStatus,
+    /// Sandbox time at which a throttled script resumes
+    resume_at: Duration,
+    fault: Option<String>,
+    memory: usize,
+    window_cpu: Duration,
+    /// Windows in a row the script went over its quota
+    over_windows: u32,
+    cpu_ms_per_sec: f64,
+    total_cpu: Duration,
+    events_run: u64,
+    events_dropped: u64,
+    times_throttled: u32,
+}
+
+impl Script {
+    fn new(info: ScriptInfo) -> Self {
+        Self {
+            info,
+            queue: VecDeque::new(),
+            status: ScriptStatus::Running,
+            resume_at: Duration::ZERO,
+            fault: None,
+            memory: 0,
+            window_cpu: Duration::ZERO,
+            over_windows: 0,
+            cpu_ms_per_sec: 0.0,
+            total_cpu: Duration::ZERO,
+            events_run: 0,
+            events_dropped: 0,
+            times_throttled: 0,
+        }
+    }
+
+    fn stop(&mut self, fault: String) {
+        warn!("Script {} ({}) stopped: {}", self.info.name, self.info.id, fault);
+        self.status = ScriptStatus::Stopped;
+        self.fault = Some(fault);
+        self.queue.clear();
+    }
+}
+
+#[derive(Default)]
+struct Sandbox {
+    scripts: HashMap<Uuid, Script>,
+    /// Round-robin order of turns
+    turns: VecDeque<Uuid>,
+    /// Sandbox time, advanced by frame time steps
+    clock: Duration,
+    window_elapsed: Duration,
+}
+
+/// Runs the scripts of one region within their quotas
+#[derive(Clone)]
+pub struct ScriptSandbox {
+    config: ScriptsConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    runtime: Option<Arc<dyn ScriptRuntime>>,
+    limits: Option<Limits>,
+    state: Arc<Mutex<Sandbox>>,
+}
+
+impl ScriptSandbox {
+    pub fn new(config: ScriptsConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            config,
+            scene,
+            runtime: None,
+            limits: None,
+            state: Arc::new(Mutex::new(Sandbox::default())),
+        }
+    }
+
+    /// Execute scripts with `runtime`
+    pub fn with_runtime(mut self, runtime: Arc<dyn ScriptRuntime>) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Refuse to load scripts beyond each owner's limit in `limits`
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    pub fn add_script(&self, info: ScriptInfo) {
+        let mut state = self.state.lock().unwrap();
+        state.turns.push_back(info.id);
+        state.scripts.insert(info.id, Script::new(info));
+    }
+
+    pub fn remove_script(&self, id: Uuid) -> bool {
+        if let Some(runtime) = &self.runtime {
+            runtime.unload(id);
+        }
+        let mut state = self.state.lock().unwrap();
+        state.turns.retain(|turn| *turn != id);
+        state.scripts.remove(&id).is_some()
+    }
+
+    /// Remove the scripts of object `object_id`, as when it is deleted.
+    /// Returns the IDs of those removed.
+    pub fn remove_object(&self, object_id: ObjectId) -> Vec<Uuid> {
+        let ids: Vec<Uuid> = {
+            let state = self.state.lock().unwrap();
+            state
+                .scripts
+                .values()
+                .filter(|script| script.info.object_id == object_id)
+                .map(|script| script.info.id)
+                .collect()
+        };
+        ids.into_iter().filter(|id| self.remove_script(*id)).collect()
+    }
+
+    /// Load `source` as script `name` in object `object_id`, restoring
+    /// `snapshot` if given, and add it. Returns the new script's ID.
+    pub async fn load(
+        &self,
+        object_id: ObjectId,
+        name: &str,
+        source: &str,
+        snapshot: Option<&[u8]>,
+    ) -> Result<Uuid, ScriptError> {
+        let runtime = self.runtime.as_ref().ok_or(ScriptError::NoRuntime)?;
+        let owner_id = match self.scene.read().await.objects.get(&object_id) {
+            Some(object) => object.owner_id,
+            None => return Err(ScriptError::UnknownObject(object_id)),
+        };
+        if let Some(limits) = &self.limits {
+            let running = {
+                let state = self.state.lock().unwrap();
+                state.scripts.values().filter(|script| script.info.owner_id == owner_id).count()
+            };
+            limits.check_scripts(owner_id, running)?;
+        }
+        let info = ScriptInfo {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            object_id,
+            owner_id,
+        };
+        let fresh = runtime.load(&info, source, snapshot).map_err(ScriptError::Load)?;
+        let id = info.id;
+        info!("Loaded script {} ({}) into {}", info.name, id, object_id);
+        self.add_script(info);
+        if fresh {
+            self.post(id, ScriptEvent::new("state_entry", Vec::new()))?;
+        }
+        Ok(id)
+    }
+
+    /// The state of script `id` to load elsewhere, if its runtime keeps one
+    pub fn snapshot(&self, id: Uuid) -> Result<Option<Vec<u8>>, ScriptError> {
+        if !self.state.lock().unwrap().scripts.contains_key(&id) {
+            return Err(ScriptError::UnknownScript(id));
+        }
+        Ok(self.runtime.as_ref().and_then(|runtime| runtime.snapshot(id)))
+    }
+
+    /// How many scripts each object with any has
+    pub fn scripts_per_object(&self) -> HashMap<ObjectId, usize> {
+        let state = self.state.lock().unwrap();
+        let mut counts = HashMap::new();
+        for script in state.scripts.values() {
+            *counts.entry(script.info.object_id).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Scripts that are not stopped
+    pub fn active_scripts(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .scripts
+            .values()
+            .filter(|script| script.status != ScriptStatus::Stopped)
+            .count()
+    }
+
+    /// Queue `event` for script `id`
+    pub fn post(&self, id: Uuid, event: ScriptEvent) -> Result<(), ScriptError> {
+        let mut state = self.state.lock().unwrap();
+        let script = state.scripts.get_mut(&id).ok_or(ScriptError::UnknownScript(id))?;
+        if script.status == ScriptStatus::Stopped {
+            return Err(ScriptError::Stopped(id));
+        }
+        if script.queue.len() >= self.config.max_queued_events {
+            script.events_dropped += 1;
+            return Err(ScriptError::QueueFull(id));
+        }
+        script.queue.push_back(event);
+        Ok(())
+    }
+
+    /// The `limit` scripts using the most CPU
+    pub async fn top(&self, limit: usize) -> Vec<ScriptProfile> {
+        let mut profiles: Vec<ScriptProfile> = {
+            let state = self.state.lock().unwrap();
+            state
+                .scripts
+                .values()
+                .map(|script| ScriptProfile {
+                    info: script.info.clone(),
+                    position: None,
+                    status: script.status,
+                    cpu_ms_per_sec: script.cpu_ms_per_sec,
+                    total_cpu_ms: script.total_cpu.as_secs_f64() * 1000.0,
+                    events_run: script.events_run,
+                    events_queued: script.queue.len(),
+                    events_dropped: script.events_dropped,
+                    memory_kb: script.memory as f64 / 1024.0,
+                    times_throttled: script.times_throttled,
+                    fault: script.fault.clone(),
+                })
+                .collect()
+        };
+        profiles.sort_by(|a, b| {
+            b.cpu_ms_per_sec
+                .total_cmp(&a.cpu_ms_per_sec)
+                .then(b.total_cpu_ms.total_cmp(&a.total_cpu_ms))
+        });
+        profiles.truncate(limit);
+
+        let scene = self.scene.read().await;
+        for profile in &mut profiles {
+            profile.position = scene.objects.get(&profile.info.object_id).map(|object| object.position);
+        }
+        profiles
+    }
+
+    /// Close the CPU window every second: update each script's rate and
+    /// throttle scripts that keep going over their quota
+    fn close_window(&self, state: &mut Sandbox) {
+        let quota = self.quota();
+        let seconds = state.window_elapsed.as_secs_f64();
+        let clock = state.clock;
+        for script in state.scripts.values_mut() {
+            let rate = script.window_cpu.as_secs_f64() * 1000.0 / seconds;
+            script.cpu_ms_per_sec = script.cpu_ms_per_sec * 0.5 + rate * 0.5;
+            if script.window_cpu >= quota {
+                script.over_windows += 1;
+            } else {
+                script.over_windows = 0;
+            }
+            script.window_cpu = Duration::ZERO;
+
+            if script.status == ScriptStatus::Running && script.over_windows >= self.config.throttle_after_secs {
+                warn!(
+                    "Throttling script {} ({}) of {} for {}s, using {:.1} ms/s",
+                    script.info.name,
+                    script.info.id,
+                    script.info.owner_id,
+                    self.config.throttle_secs,
+                    script.cpu_ms_per_sec
+                );
+                script.status = ScriptStatus::Throttled;
+                script.resume_at = clock + Duration::from_secs(self.config.throttle_secs);
+                script.over_windows = 0;
+                script.times_throttled += 1;
+            } else if script.status == ScriptStatus::Throttled && clock >= script.resume_at {
+                info!("Script {} ({}) resumes", script.info.name, script.info.id);
+                script.status = ScriptStatus::Running;
+            }
+        }
+        state.window_elapsed = Duration::ZERO;
+    }
+
+    /// CPU a script may use per window
+    fn quota(&self) -> Duration {
+        Duration::from_secs_f64(self.config.cpu_ms_per_sec / 1000.0 * WINDOW.as_secs_f64())
+    }
+
+    /// Hand queued events to the runtime in turn until the frame's budget
+    /// is spent or no script can run
+    fn run_events(&self, runtime: &dyn ScriptRuntime, state: &mut Sandbox) {
+        let budget = Duration::from_secs_f64(self.config.frame_budget_ms / 1000.0);
+        let quota = self.quota();
+        let max_memory = self.config.max_memory_kb * 1024;
+        let started = Instant::now();
+
+        let mut idle_turns = 0;
+        while idle_turns < state.turns.len() {
+            let spent = started.elapsed();
+            if spent >= budget {
+                break;
+            }
+            let Some(id) = state.turns.pop_front() else {
+                break;
+            };
+            state.turns.push_back(id);
+            let Some(script) = state.scripts.get_mut(&id) else {
+                idle_turns += 1;
+                continue;
+            };
+            if script.status != ScriptStatus::Running || script.window_cpu >= quota {
+                idle_turns += 1;
+                continue;
+            }
+            let Some(event) = script.queue.front() else {
+                idle_turns += 1;
+                continue;
+            };
+            idle_turns = 0;
+
+            let allowance = (budget - spent).min(quota - script.window_cpu);
+            let run_started = Instant::now();
+            let result = runtime.run(id, event, allowance);
+            let cpu = run_started.elapsed();
+            script.window_cpu += cpu;
+            script.total_cpu += cpu;
+
+            match result {
+                Ok(run) => {
+                    script.memory = run.memory;
+                    if run.finished {
+                        script.queue.pop_front();
+                        script.events_run += 1;
+                    }
+                    if run.state_changed {
+                        script.queue.clear();
+                    }
+                    if run.memory > max_memory {
+                        script.stop(format!("memory limit exceeded ({} KB)", run.memory / 1024));
+                    }
+                }
+                Err(fault) => script.stop(fault),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SimulationSubsystem for ScriptSandbox {
+    async fn tick(&mut self, frame: &FrameContext) -> MutseaResult<()> {
+        let mut state = self.state.lock().unwrap();
+        state.clock += frame.dt;
+        state.window_elapsed += frame.dt;
+        if state.window_elapsed >= WINDOW {
+            self.close_window(&mut state);
+        }
+        if let Some(runtime) = &self.runtime {
+            self.run_events(runtime.as_ref(), &mut state);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::{RegionInfo, SceneObject};
+
+    /// Spends `cost` on every event and reports `memory`
+    struct FixedCost {
+        costs: HashMap<Uuid, (Duration, usize)>,
+    }
+
+    impl ScriptRuntime for FixedCost {
+        fn run(&self, script: Uuid, event: &ScriptEvent, _budget: Duration) -> Result<ScriptRun, String> {
+            if event.name == "crash" {
+                return Err("Math error".to_string());
+            }
+            let (cost, memory) = self.costs[&script];
+            std::thread::sleep(cost);
+            Ok(ScriptRun {
+                memory,
+                finished: true,
+                state_changed: false,
+            })
+        }
+    }
+
+    fn sandbox(costs: &[(Uuid, Duration, usize)]) -> ScriptSandbox {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let runtime = FixedCost {
+            costs: costs.iter().map(|(id, cost, memory)| (*id, (*cost, *memory))).collect(),
+        };
+        let config = ScriptsConfig {
+            frame_budget_ms: 50.0,
+            cpu_ms_per_sec: 5.0,
+            max_queued_events: 4,
+            throttle_after_secs: 2,
+            throttle_secs: 5,
+            ..Default::default()
+        };
+        let sandbox = ScriptSandbox::new(config, scene).with_runtime(Arc::new(runtime));
+        for (id, _, _) in costs {
+            sandbox.add_script(ScriptInfo {
+                id: *id,
+                name: format!("script {}", id),
+                object_id: ObjectId::new(),
+                owner_id: UserId::new(),
+            });
+        }
+        sandbox
+    }
+
+    fn frame(frame: u64) -> FrameContext {
+        FrameContext {
+            frame,
+            dt: Duration::from_millis(250),
+            degrade_level: 0,
+        }
+    }
+
+    fn profile(profiles: &[ScriptProfile], id: Uuid) -> &ScriptProfile {
+        profiles.iter().find(|profile| profile.info.id == id).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_abusive_scripts_are_throttled() {
+        let (heavy, light) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut sandbox = sandbox(&[(heavy, Duration::from_millis(6), 1024), (light, Duration::ZERO, 1024)]);
+
+        for n in 0..12 {
+            for id in [heavy, light] {
+                while sandbox.post(id, ScriptEvent::new("timer", Vec::new())).is_ok() {}
+            }
+            sandbox.tick(&frame(n)).await.unwrap();
+        }
+
+        let top = sandbox.top(10).await;
+        assert_eq!(top[0].info.id, heavy);
+        let (heavy, light) = (profile(&top, heavy), profile(&top, light));
+        assert_eq!(heavy.status, ScriptStatus::Throttled);
+        assert_eq!(heavy.times_throttled, 1);
+        // One event a frame at most: the first uses up the second's quota
+        assert!(heavy.events_run <= 8, "{} events", heavy.events_run);
+        assert!(heavy.events_dropped > 0);
+        assert_eq!(light.status, ScriptStatus::Running);
+        assert_eq!(light.events_run, 12 * 4);
+
+        // After its suspension the script runs again
+        for n in 12..40 {
+            sandbox.tick(&frame(n)).await.unwrap();
+            if profile(&sandbox.top(10).await, heavy.info.id).status == ScriptStatus::Running {
+                return;
+            }
+        }
+        panic!("the throttled script never resumed");
+    }
+
+    #[tokio::test]
+    async fn test_scripts_over_memory_or_faulting_are_stopped() {
+        let (greedy, faulty) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut sandbox = sandbox(&[(greedy, Duration::ZERO, 80 * 1024), (faulty, Duration::ZERO, 1024)]);
+        sandbox
+            .post(greedy, ScriptEvent::new("state_entry", Vec::new()))
+            .unwrap();
+        sandbox.post(faulty, ScriptEvent::new("crash", Vec::new())).unwrap();
+        sandbox.tick(&frame(0)).await.unwrap();
+
+        let top = sandbox.top(10).await;
+        assert_eq!(profile(&top, greedy).status, ScriptStatus::Stopped);
+        assert!(profile(&top, greedy)
+            .fault
+            .as_deref()
+            .unwrap()
+            .starts_with("memory limit"));
+        assert_eq!(profile(&top, faulty).fault.as_deref(), Some("Math error"));
+        assert_eq!(sandbox.active_scripts(), 0);
+        assert!(matches!(
+            sandbox.post(faulty, ScriptEvent::new("timer", Vec::new())),
+            Err(ScriptError::Stopped(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_top_scripts_report_where_they_are() {
+        let id = Uuid::new_v4();
+        let sandbox = sandbox(&[(id, Duration::ZERO, 0)]);
+        let object_id = sandbox.state.lock().unwrap().scripts[&id].info.object_id;
+        sandbox.scene.write().await.add_object(SceneObject {
+            id: object_id,
+            position: Vector3::new(10.0, 20.0, 30.0),
+            ..SceneObject::new(1, "Vendor", UserId::new())
+        });
+
+        let top = sandbox.top(1).await;
+        assert_eq!(top[0].position, Some(Vector3::new(10.0, 20.0, 30.0)));
+    }
+}
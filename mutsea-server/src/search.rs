@@ -0,0 +1,176 @@
+//! Place search
+//!
+//! Answers the viewer's place searches with the parcels of the region
+//! whose name or description contains the search text. Only places of the
+//! content ratings asked for, and that the searching agent may enter, are
+//! listed.
+
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::Maturity;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::login::OpenSimLoginService;
+use mutsea_protocol::search::{DirPlacesQuery, PlaceResult, PLACES_PER_PAGE};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+
+/// Parcel search over the region
+#[derive(Clone)]
+pub struct PlaceSearch {
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: LLUDPServer,
+    login_service: Arc<OpenSimLoginService>,
+}
+
+impl PlaceSearch {
+    pub fn new(
+        scene: Arc<RwLock<RegionScene>>,
+        lludp_server: LLUDPServer,
+        login_service: Arc<OpenSimLoginService>,
+    ) -> Self {
+        Self {
+            scene,
+            lludp_server,
+            login_service,
+        }
+    }
+
+    /// Answer place searches from the LLUDP server
+    pub fn spawn(&self) {
+        let search = self.clone();
+        let mut events = self.lludp_server.subscribe_events();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        if let NetworkEventData::PlacesSearched {
+                            agent_id,
+                            query_id,
+                            text,
+                            flags,
+                            start,
+                            ..
+                        } = event.event_data
+                        {
+                            let query = DirPlacesQuery {
+                                agent_id,
+                                query_id,
+                                text,
+                                flags,
+                                start,
+                            };
+                            search.answer(&query).await;
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => warn!("Place search missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn answer(&self, query: &DirPlacesQuery) {
+        let access_max = self.login_service.access_max(&query.agent_id);
+        let places = self.search(query, access_max).await;
+        debug!("Place search \"{}\" found {} parcels", query.text, places.len());
+        if let Err(e) = self
+            .lludp_server
+            .send_places_reply(query.agent_id, query.query_id, &places)
+            .await
+        {
+            warn!("Failed to send place search results to {}: {}", query.agent_id, e);
+        }
+    }
+
+    /// The page of parcels matching `query` that an agent allowed up to
+    /// `access_max` may see
+    pub async fn search(&self, query: &DirPlacesQuery, access_max: Maturity) -> Vec<PlaceResult> {
+        let text = query.text.trim().to_lowercase();
+        let scene = self.scene.read().await;
+        let region = scene.info.maturity();
+        scene
+            .parcels
+            .iter()
+            .filter(|parcel| {
+                let maturity = parcel.effective_maturity(region);
+                maturity <= access_max && query.includes(maturity)
+            })
+            .filter(|parcel| {
+                text.is_empty()
+                    || parcel.name.to_lowercase().contains(&text)
+                    || parcel.description.to_lowercase().contains(&text)
+            })
+            .skip(query.start.max(0) as usize)
+            .take(PLACES_PER_PAGE)
+            .map(|parcel| PlaceResult {
+                parcel_id: parcel.id,
+                name: parcel.name.clone(),
+                for_sale: false,
+                dwell: 0.0,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::config::LLUDPConfig;
+    use mutsea_core::scene::Parcel;
+    use mutsea_core::{RegionInfo, UserId};
+    use mutsea_protocol::search::{DFQ_INC_ADULT, DFQ_INC_MATURE, DFQ_INC_PG};
+    use uuid::Uuid;
+
+    /// A search by `agent_id` for `text` among places rated as in `flags`
+    fn query(agent_id: UserId, text: &str, flags: u32) -> DirPlacesQuery {
+        DirPlacesQuery {
+            agent_id,
+            query_id: Uuid::new_v4(),
+            text: text.to_string(),
+            flags,
+            start: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_filters_by_maturity() {
+        let mut info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        info.access = Maturity::Adult.sim_access();
+        let mut scene = RegionScene::new(info, UserId::new());
+        scene.parcels[0].name = "Sunny Beach".to_string();
+        scene.parcels[0].maturity = Some(Maturity::Pg);
+        let mut club = Parcel::whole_region(UserId::new(), 256, 256);
+        club.name = "Beach Club".to_string();
+        scene.parcels.push(club);
+
+        let lludp_server = LLUDPServer::new(&LLUDPConfig::default()).await.unwrap();
+        let search = PlaceSearch::new(
+            Arc::new(RwLock::new(scene)),
+            lludp_server,
+            Arc::new(OpenSimLoginService::new()),
+        );
+        let agent_id = UserId::new();
+        let all = DFQ_INC_PG | DFQ_INC_MATURE | DFQ_INC_ADULT;
+
+        let names = |places: Vec<PlaceResult>| places.into_iter().map(|p| p.name).collect::<Vec<_>>();
+        assert_eq!(
+            names(search.search(&query(agent_id, "beach", all), Maturity::Adult).await).len(),
+            2
+        );
+        assert_eq!(
+            names(search.search(&query(agent_id, "BEACH", all), Maturity::Mature).await),
+            vec!["Sunny Beach"]
+        );
+        assert_eq!(
+            names(
+                search
+                    .search(&query(agent_id, "club", DFQ_INC_PG), Maturity::Adult)
+                    .await
+            ),
+            Vec::<String>::new()
+        );
+    }
+}
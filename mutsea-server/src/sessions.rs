@@ -0,0 +1,307 @@
+//! Per-agent session activity for player analytics
+//!
+//! The tracker follows logins, chat and logouts on the LLUDP event stream
+//! and samples circuit positions every few seconds. When a session ends
+//! its activity is written to `player_behaviors`: a `session_start` row, an
+//! `area_visit` row per area entered and a closing `session_end` summary,
+//! which is what the player analytics queries group into sessions. The
+//! summary includes the bandwidth the session's circuit used, by class.
+
+use chrono::{DateTime, Utc};
+use mutsea_core::{events::NetworkEventData, PlayerBehaviorRecord, PlayerBehaviorRecorder, RegionId, UserId, Vector3};
+use mutsea_network::LLUDPServer;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// How often circuit positions are sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Side of the square cells counted as areas, in metres
+const AREA_SIZE: f32 = 64.0;
+
+/// Movement between samples below this, in metres, counts as standing still
+const IDLE_DISTANCE: f32 = 0.5;
+
+/// Grid cell of a region an agent has been in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Area {
+    region_id: Option<RegionId>,
+    x: i32,
+    y: i32,
+}
+
+impl Area {
+    fn at(region_id: Option<RegionId>, position: Vector3) -> Self {
+        Self {
+            region_id,
+            x: (position.x / AREA_SIZE).floor() as i32,
+            y: (position.y / AREA_SIZE).floor() as i32,
+        }
+    }
+}
+
+/// Activity of one agent between login and logout
+#[derive(Debug, Clone)]
+pub struct AgentSession {
+    pub agent_id: UserId,
+    pub session_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    /// Metres moved, summed between samples
+    pub distance: f64,
+    pub chat_messages: u32,
+    /// Time spent neither moving nor chatting
+    pub idle: Duration,
+    /// Bytes sent to the agent by priority class, known once it left
+    pub sent_by_class: HashMap<String, u64>,
+    /// Bytes received from the agent by priority class
+    pub received_by_class: HashMap<String, u64>,
+    /// Areas in order of first visit
+    areas: Vec<(Area, DateTime<Utc>)>,
+    last_position: Option<Vector3>,
+    last_sample: DateTime<Utc>,
+    chatted_since_sample: bool,
+}
+
+impl AgentSession {
+    pub fn new(agent_id: UserId, session_id: Uuid, started_at: DateTime<Utc>) -> Self {
+        Self {
+            agent_id,
+            session_id,
+            started_at,
+            distance: 0.0,
+            chat_messages: 0,
+            idle: Duration::ZERO,
+            sent_by_class: HashMap::new(),
+            received_by_class: HashMap::new(),
+            areas: Vec::new(),
+            last_position: None,
+            last_sample: started_at,
+            chatted_since_sample: false,
+        }
+    }
+
+    pub fn areas_visited(&self) -> usize {
+        self.areas.len()
+    }
+
+    pub fn chat(&mut self) {
+        self.chat_messages += 1;
+        self.chatted_since_sample = true;
+    }
+
+    /// Account for the agent being at `position` at `now`
+    pub fn observe(&mut self, region_id: Option<RegionId>, position: Vector3, now: DateTime<Utc>) {
+        if let Some(last) = self.last_position {
+            let moved = (position - last).length();
+            self.distance += moved as f64;
+            if moved < IDLE_DISTANCE && !self.chatted_since_sample {
+                self.idle += (now - self.last_sample).to_std().unwrap_or_default();
+            }
+        }
+
+        let area = Area::at(region_id, position);
+        if !self.areas.iter().any(|(visited, _)| *visited == area) {
+            self.areas.push((area, now));
+        }
+
+        self.last_position = Some(position);
+        self.last_sample = now;
+        self.chatted_since_sample = false;
+    }
+
+    /// Rows describing this session, ended at `ended_at` for `reason`
+    pub fn records(&self, ended_at: DateTime<Utc>, reason: &str) -> Vec<PlayerBehaviorRecord> {
+        let record = |timestamp, action_type: &str, action_data, context_data| PlayerBehaviorRecord {
+            player_id: self.agent_id,
+            session_id: self.session_id,
+            timestamp,
+            action_type: action_type.to_string(),
+            action_data,
+            context_data,
+        };
+
+        let mut records = vec![record(self.started_at, "session_start", json!({}), json!({}))];
+        for (area, entered_at) in &self.areas {
+            let region_id = area.region_id.map(|region| region.0.to_string());
+            records.push(record(
+                *entered_at,
+                "area_visit",
+                json!({ "cell_x": area.x, "cell_y": area.y, "cell_size": AREA_SIZE }),
+                json!({ "region_id": region_id }),
+            ));
+        }
+        let duration = (ended_at - self.started_at).to_std().unwrap_or_default();
+        records.push(record(
+            ended_at,
+            "session_end",
+            json!({
+                "reason": reason,
+                "duration_secs": duration.as_secs_f64(),
+                "distance_m": self.distance,
+                "chat_messages": self.chat_messages,
+                "areas_visited": self.areas.len(),
+                "idle_secs": self.idle.as_secs_f64(),
+                "bytes_sent": self.sent_by_class.values().sum::<u64>(),
+                "bytes_received": self.received_by_class.values().sum::<u64>(),
+                "sent_by_class": self.sent_by_class,
+                "received_by_class": self.received_by_class,
+            }),
+            json!({}),
+        ));
+        records
+    }
+}
+
+/// Open sessions by circuit code, written out as they end
+pub struct SessionTracker {
+    sessions: Mutex<HashMap<u32, AgentSession>>,
+    recorder: Arc<dyn PlayerBehaviorRecorder>,
+}
+
+impl SessionTracker {
+    pub fn new(recorder: Arc<dyn PlayerBehaviorRecorder>) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            recorder,
+        }
+    }
+
+    pub fn open_sessions(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    /// Follow `event`, writing the session out if it ended
+    pub async fn handle_event(&self, event: &NetworkEventData) {
+        let ended = match event {
+            NetworkEventData::AgentLoggedIn { circuit_code, agent_id, session_id } => {
+                let mut sessions = self.sessions.lock().unwrap();
+                // UseCircuitCode is resent until acknowledged
+                if sessions.get(circuit_code).map(|s| s.session_id) != Some(*session_id) {
+                    sessions.insert(*circuit_code, AgentSession::new(*agent_id, *session_id, Utc::now()));
+                }
+                None
+            }
+            NetworkEventData::AgentChatted { circuit_code, .. } => {
+                if let Some(session) = self.sessions.lock().unwrap().get_mut(circuit_code) {
+                    session.chat();
+                }
+                None
+            }
+            NetworkEventData::AgentLoggedOut {
+                circuit_code,
+                reason,
+                sent_by_class,
+                received_by_class,
+                ..
+            } => {
+                let session = self.sessions.lock().unwrap().remove(circuit_code);
+                session.map(|mut session| {
+                    session.sent_by_class = sent_by_class.clone();
+                    session.received_by_class = received_by_class.clone();
+                    (session, reason.as_str())
+                })
+            }
+            _ => None,
+        };
+
+        if let Some((session, reason)) = ended {
+            self.write(&session, reason).await;
+        }
+    }
+
+    /// Record where each tracked circuit's agent is now
+    pub fn sample(&self, positions: impl IntoIterator<Item = (u32, Option<RegionId>, Vector3)>) {
+        let now = Utc::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        for (circuit_code, region_id, position) in positions {
+            if let Some(session) = sessions.get_mut(&circuit_code) {
+                session.observe(region_id, position, now);
+            }
+        }
+    }
+
+    /// End and write every open session, e.g. at shutdown
+    pub async fn close_all(&self, reason: &str) {
+        let sessions: Vec<_> = self.sessions.lock().unwrap().drain().map(|(_, session)| session).collect();
+        for session in &sessions {
+            self.write(session, reason).await;
+        }
+    }
+
+    async fn write(&self, session: &AgentSession, reason: &str) {
+        let records = session.records(Utc::now(), reason);
+        match self.recorder.record_player_behaviors(&records).await {
+            Ok(()) => debug!(
+                "Recorded session {} of agent {}: {:.0} m, {} chats, {} areas",
+                session.session_id,
+                session.agent_id,
+                session.distance,
+                session.chat_messages,
+                session.areas_visited()
+            ),
+            Err(e) => warn!("Failed to record session {} of agent {}: {}", session.session_id, session.agent_id, e),
+        }
+    }
+
+    /// Follow `lludp_server`'s agent events and sample its circuits
+    pub fn spawn(self: &Arc<Self>, lludp_server: LLUDPServer) {
+        let tracker = Arc::clone(self);
+        let mut events = lludp_server.subscribe_events();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+            loop {
+                tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(event) => tracker.handle_event(&event.event_data).await,
+                        Err(RecvError::Lagged(missed)) => warn!("Session tracker missed {} agent events", missed),
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = interval.tick() => {
+                        let circuits = lludp_server.get_all_circuits().await;
+                        tracker.sample(circuits.iter().map(|c| (c.circuit_code, c.region_id, c.position)));
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_accumulates_activity() {
+        let start = Utc::now();
+        let region = Some(RegionId::new());
+        let mut session = AgentSession::new(UserId::new(), Uuid::new_v4(), start);
+        let at = |secs| start + chrono::Duration::seconds(secs);
+
+        session.observe(region, Vector3::new(10.0, 10.0, 20.0), at(5));
+        session.observe(region, Vector3::new(10.0, 40.0, 20.0), at(10));
+        // Standing still counts as idle, unless chatting
+        session.observe(region, Vector3::new(10.0, 40.0, 20.0), at(15));
+        session.chat();
+        session.observe(region, Vector3::new(10.0, 40.0, 20.0), at(20));
+        session.observe(region, Vector3::new(10.0, 80.0, 20.0), at(25));
+
+        assert_eq!(session.distance, 70.0);
+        assert_eq!(session.chat_messages, 1);
+        assert_eq!(session.idle, Duration::from_secs(5));
+        assert_eq!(session.areas_visited(), 2);
+
+        session.sent_by_class = HashMap::from([("object".to_string(), 900), ("control".to_string(), 100)]);
+        let records = session.records(at(30), "logout");
+        let kinds: Vec<_> = records.iter().map(|r| r.action_type.as_str()).collect();
+        assert_eq!(kinds, ["session_start", "area_visit", "area_visit", "session_end"]);
+        assert_eq!(records[3].action_data["duration_secs"], 30.0);
+        assert_eq!(records[3].action_data["reason"], "logout");
+        assert_eq!(records[3].action_data["bytes_sent"], 1000);
+        assert_eq!(records[3].action_data["sent_by_class"]["object"], 900);
+    }
+}
@@ -0,0 +1,564 @@
+//! Region simulation heartbeat
+//!
+//! Drives physics, script events, NPC AI and object update flushes at a fixed
+//! tick rate. Each frame has a time budget; when frames keep running over it,
+//! sheddable subsystems are run less often until the simulator recovers.
+
+use async_trait::async_trait;
+use mutsea_core::{config::SimulationConfig, scene::RegionScene, MetricsCollector, MutseaResult};
+use mutsea_network::lludp_server::SimStatsSource;
+use mutsea_protocol::sim_stats::SimStats;
+use crate::profiler::TickProfiler;
+use crate::replay::ReplayRecorder;
+use crate::scripts::ScriptSandbox;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, error, info, warn};
+
+/// Subsystems driven by the simulation loop, in the order they run each frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum SubsystemKind {
+    Physics,
+    Scripts,
+    NpcAi,
+    Ecosystem,
+    ObjectUpdates,
+}
+
+impl SubsystemKind {
+    /// Name used for logging and metric labels
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubsystemKind::Physics => "physics",
+            SubsystemKind::Scripts => "scripts",
+            SubsystemKind::NpcAi => "npc_ai",
+            SubsystemKind::Ecosystem => "ecosystem",
+            SubsystemKind::ObjectUpdates => "object_updates",
+        }
+    }
+
+    /// Whether this subsystem may run at a reduced rate under load.
+    /// Physics always runs so the scene stays consistent.
+    pub fn sheddable(&self) -> bool {
+        !matches!(self, SubsystemKind::Physics)
+    }
+}
+
+/// Per-frame information handed to each subsystem
+#[derive(Debug, Clone)]
+pub struct FrameContext {
+    /// Frame counter since the loop started
+    pub frame: u64,
+    /// Simulated time elapsed since this subsystem last ran
+    pub dt: Duration,
+    /// Current overload degrade level (0 = full rate)
+    pub degrade_level: u8,
+}
+
+/// A subsystem ticked by the simulation loop
+#[async_trait]
+pub trait SimulationSubsystem: Send + Sync {
+    /// Advance the subsystem by one step
+    async fn tick(&mut self, frame: &FrameContext) -> MutseaResult<()>;
+}
+
+/// Timing statistics for a single subsystem
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SubsystemTiming {
+    pub runs: u64,
+    pub skipped: u64,
+    pub errors: u64,
+    pub last_ms: f64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+}
+
+impl SubsystemTiming {
+    fn record(&mut self, elapsed: Duration) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        self.runs += 1;
+        self.last_ms = ms;
+        self.max_ms = self.max_ms.max(ms);
+        self.avg_ms = if self.runs == 1 {
+            ms
+        } else {
+            self.avg_ms * 0.9 + ms * 0.1
+        };
+    }
+}
+
+/// Snapshot of simulation loop statistics
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SimulationStats {
+    pub frames: u64,
+    pub overrun_frames: u64,
+    pub last_frame_ms: f64,
+    pub avg_frame_ms: f64,
+    pub degrade_level: u8,
+    pub subsystems: HashMap<SubsystemKind, SubsystemTiming>,
+}
+
+impl SimulationStats {
+    /// Simulator frames per second implied by the average frame time
+    pub fn effective_fps(&self, tick_rate: u32) -> f64 {
+        let period_ms = 1000.0 / tick_rate.max(1) as f64;
+        if self.avg_frame_ms > period_ms {
+            1000.0 / self.avg_frame_ms
+        } else {
+            tick_rate as f64
+        }
+    }
+
+    /// Simulator-side SimStats values: time dilation, frame rates and the
+    /// per-subsystem frame time breakdown
+    pub fn sim_stats(&self, config: &SimulationConfig) -> SimStats {
+        let period_ms = 1000.0 / config.tick_rate.max(1) as f64;
+        let avg_ms = |kind: SubsystemKind| self.subsystems.get(&kind).map_or(0.0, |t| t.avg_ms) as f32;
+        let sim_fps = self.effective_fps(config.tick_rate);
+        let time_dilation = if self.avg_frame_ms > period_ms {
+            period_ms / self.avg_frame_ms
+        } else {
+            1.0
+        };
+        let physics_fps = match self.subsystems.get(&SubsystemKind::Physics) {
+            Some(timing) if timing.runs > 0 => sim_fps,
+            _ => 0.0,
+        };
+
+        SimStats {
+            time_dilation: time_dilation as f32,
+            sim_fps: sim_fps as f32,
+            physics_fps: physics_fps as f32,
+            frame_ms: self.avg_frame_ms as f32,
+            physics_ms: avg_ms(SubsystemKind::Physics),
+            script_ms: avg_ms(SubsystemKind::Scripts),
+            agent_ms: avg_ms(SubsystemKind::NpcAi),
+            other_ms: avg_ms(SubsystemKind::ObjectUpdates),
+            spare_ms: (config.frame_budget_ms as f64 - self.avg_frame_ms).max(0.0) as f32,
+            sleep_ms: (period_ms - self.avg_frame_ms).max(0.0) as f32,
+            ..SimStats::default()
+        }
+    }
+}
+
+struct RegisteredSubsystem {
+    kind: SubsystemKind,
+    subsystem: Box<dyn SimulationSubsystem>,
+    last_run: Instant,
+}
+
+/// Tracks frame overruns and decides the current degrade level
+#[derive(Debug, Clone)]
+struct OverloadGovernor {
+    budget: Duration,
+    overload_frames: u32,
+    recovery_frames: u32,
+    max_level: u8,
+    level: u8,
+    over_streak: u32,
+    under_streak: u32,
+}
+
+impl OverloadGovernor {
+    fn new(config: &SimulationConfig) -> Self {
+        Self {
+            budget: Duration::from_millis(config.frame_budget_ms),
+            overload_frames: config.overload_frames.max(1),
+            recovery_frames: config.recovery_frames.max(1),
+            max_level: config.max_degrade_level,
+            level: 0,
+            over_streak: 0,
+            under_streak: 0,
+        }
+    }
+
+    /// Whether a sheddable subsystem should run on this frame
+    fn should_run(&self, frame: u64) -> bool {
+        1u64.checked_shl(self.level.into()).is_some_and(|period| frame % period == 0)
+    }
+
+    /// Record a finished frame; returns the new level if it changed
+    fn observe(&mut self, frame_time: Duration) -> Option<u8> {
+        if frame_time > self.budget {
+            self.over_streak += 1;
+            self.under_streak = 0;
+            if self.over_streak >= self.overload_frames && self.level < self.max_level {
+                self.level += 1;
+                self.over_streak = 0;
+                return Some(self.level);
+            }
+        } else if frame_time < self.budget / 2 {
+            self.under_streak += 1;
+            self.over_streak = 0;
+            if self.under_streak >= self.recovery_frames && self.level > 0 {
+                self.level -= 1;
+                self.under_streak = 0;
+                return Some(self.level);
+            }
+        } else {
+            self.over_streak = 0;
+            self.under_streak = 0;
+        }
+        None
+    }
+}
+
+/// Fixed-rate simulation loop for a region
+pub struct SimulationLoop {
+    config: SimulationConfig,
+    subsystems: Arc<Mutex<Vec<RegisteredSubsystem>>>,
+    stats: Arc<RwLock<SimulationStats>>,
+    running: Arc<AtomicBool>,
+    metrics: Option<Arc<dyn MetricsCollector>>,
+    profiler: Option<Arc<TickProfiler>>,
+    replay: Option<Arc<ReplayRecorder>>,
+}
+
+impl SimulationLoop {
+    /// Create a new simulation loop
+    pub fn new(config: SimulationConfig) -> Self {
+        Self {
+            config,
+            subsystems: Arc::new(Mutex::new(Vec::new())),
+            stats: Arc::new(RwLock::new(SimulationStats::default())),
+            running: Arc::new(AtomicBool::new(false)),
+            metrics: None,
+            profiler: None,
+            replay: None,
+        }
+    }
+
+    /// Export per-subsystem timings through a metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<dyn MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Sample subsystem timings into a tick profiler
+    pub fn with_profiler(mut self, profiler: Arc<TickProfiler>) -> Self {
+        self.profiler = Some(profiler);
+        self
+    }
+
+    /// Record every frame's time steps for replay
+    pub fn with_replay(mut self, replay: Arc<ReplayRecorder>) -> Self {
+        self.replay = Some(replay);
+        self
+    }
+
+    /// Register a subsystem; subsystems run in `SubsystemKind` order each frame
+    pub async fn register(&self, kind: SubsystemKind, subsystem: Box<dyn SimulationSubsystem>) {
+        let mut subsystems = self.subsystems.lock().await;
+        subsystems.push(RegisteredSubsystem {
+            kind,
+            subsystem,
+            last_run: Instant::now(),
+        });
+        subsystems.sort_by_key(|s| s.kind);
+        info!("Registered simulation subsystem: {}", kind.as_str());
+    }
+
+    /// Start the heartbeat task
+    pub async fn start(&self) -> MutseaResult<()> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let tick_period = Duration::from_secs_f64(1.0 / self.config.tick_rate.max(1) as f64);
+        let subsystems = Arc::clone(&self.subsystems);
+        let stats = Arc::clone(&self.stats);
+        let running = Arc::clone(&self.running);
+        let metrics = self.metrics.clone();
+        let profiler = self.profiler.clone();
+        let replay = self.replay.clone();
+        let mut governor = OverloadGovernor::new(&self.config);
+        let budget = governor.budget;
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tick_period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            let mut frame: u64 = 0;
+
+            while running.load(Ordering::SeqCst) {
+                interval.tick().await;
+                let frame_start = Instant::now();
+                let mut timings = Vec::new();
+                let mut runs = Vec::new();
+
+                {
+                    let mut subsystems = subsystems.lock().await;
+                    for registered in subsystems.iter_mut() {
+                        let sheddable = registered.kind.sheddable();
+                        if sheddable && (!governor.should_run(frame) || frame_start.elapsed() > budget) {
+                            timings.push((registered.kind, None, false));
+                            continue;
+                        }
+
+                        let context = FrameContext {
+                            frame,
+                            dt: registered.last_run.elapsed(),
+                            degrade_level: governor.level,
+                        };
+                        registered.last_run = Instant::now();
+                        runs.push((registered.kind, context.dt));
+
+                        let started = Instant::now();
+                        let result = registered.subsystem.tick(&context).await;
+                        if let Err(e) = &result {
+                            error!("Simulation subsystem {} failed: {}", registered.kind.as_str(), e);
+                        }
+                        let elapsed = started.elapsed();
+                        if let Some(profiler) = &profiler {
+                            profiler.record(registered.kind.as_str(), elapsed);
+                        }
+                        timings.push((registered.kind, Some(elapsed), result.is_err()));
+                    }
+                }
+
+                let frame_time = frame_start.elapsed();
+                if let Some(replay) = &replay {
+                    replay.record_tick(frame, governor.level, runs);
+                }
+                if let Some(profiler) = &profiler {
+                    profiler.finish_tick(frame, frame_time, budget);
+                }
+                if let Some(level) = governor.observe(frame_time) {
+                    if level > 0 {
+                        warn!(
+                            "Simulation overloaded, degrading update rates to 1/{}",
+                            1u64.checked_shl(level.into()).unwrap_or(u64::MAX)
+                        );
+                    } else {
+                        info!("Simulation recovered, running at full update rate");
+                    }
+                }
+
+                {
+                    let mut stats = stats.write().await;
+                    stats.frames += 1;
+                    let frame_ms = frame_time.as_secs_f64() * 1000.0;
+                    stats.last_frame_ms = frame_ms;
+                    stats.avg_frame_ms = if stats.frames == 1 {
+                        frame_ms
+                    } else {
+                        stats.avg_frame_ms * 0.9 + frame_ms * 0.1
+                    };
+                    if frame_time > budget {
+                        stats.overrun_frames += 1;
+                    }
+                    stats.degrade_level = governor.level;
+
+                    for (kind, elapsed, failed) in timings {
+                        let timing = stats.subsystems.entry(kind).or_default();
+                        match elapsed {
+                            Some(elapsed) => timing.record(elapsed),
+                            None => timing.skipped += 1,
+                        }
+                        if failed {
+                            timing.errors += 1;
+                        }
+                    }
+                }
+
+                if let Some(metrics) = &metrics {
+                    metrics.record_timing("sim_frame_time", frame_time, &[]);
+                }
+
+                frame += 1;
+            }
+
+            debug!("Simulation loop exited after {} frames", frame);
+        });
+
+        if let Some(metrics) = self.metrics.clone() {
+            self.start_stats_export(metrics);
+        }
+
+        info!(
+            "Simulation loop started at {} Hz with {} ms frame budget",
+            self.config.tick_rate, self.config.frame_budget_ms
+        );
+        Ok(())
+    }
+
+    /// Periodically push per-subsystem timings to the metrics collector
+    fn start_stats_export(&self, metrics: Arc<dyn MetricsCollector>) {
+        let stats = Arc::clone(&self.stats);
+        let running = Arc::clone(&self.running);
+        let interval_secs = self.config.stats_interval.max(1);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            while running.load(Ordering::SeqCst) {
+                interval.tick().await;
+                let stats = stats.read().await;
+
+                metrics.set_gauge("sim_avg_frame_ms", stats.avg_frame_ms, &[]);
+                metrics.set_gauge("sim_degrade_level", stats.degrade_level as f64, &[]);
+                metrics.set_gauge("sim_overrun_frames", stats.overrun_frames as f64, &[]);
+
+                for (kind, timing) in &stats.subsystems {
+                    let labels = [("subsystem", kind.as_str())];
+                    metrics.set_gauge("sim_subsystem_avg_ms", timing.avg_ms, &labels);
+                    metrics.set_gauge("sim_subsystem_max_ms", timing.max_ms, &labels);
+                    metrics.set_gauge("sim_subsystem_skipped", timing.skipped as f64, &labels);
+                }
+            }
+        });
+    }
+
+    /// Stop the heartbeat task
+    pub async fn stop(&self) -> MutseaResult<()> {
+        self.running.store(false, Ordering::SeqCst);
+        info!("Simulation loop stopped");
+        Ok(())
+    }
+
+    /// Whether the loop is running
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Get a snapshot of the current statistics
+    pub async fn get_stats(&self) -> SimulationStats {
+        self.stats.read().await.clone()
+    }
+
+    /// Tick rate in Hz
+    pub fn tick_rate(&self) -> u32 {
+        self.config.tick_rate
+    }
+}
+
+/// Maximum prims per region reported to viewers
+const OBJECT_CAPACITY: u32 = 15000;
+
+/// SimStats for one region: loop timings plus the region's scene counts
+pub struct RegionStatsSource {
+    simulation: SimulationLoop,
+    scene: Arc<RwLock<RegionScene>>,
+    scripts: Option<ScriptSandbox>,
+}
+
+impl RegionStatsSource {
+    /// Report stats for `scene` as driven by `simulation`
+    pub fn new(simulation: SimulationLoop, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            simulation,
+            scene,
+            scripts: None,
+        }
+    }
+
+    /// Count the running scripts of `scripts`
+    pub fn with_scripts(mut self, scripts: ScriptSandbox) -> Self {
+        self.scripts = Some(scripts);
+        self
+    }
+}
+
+#[async_trait]
+impl SimStatsSource for RegionStatsSource {
+    async fn sim_stats(&self) -> SimStats {
+        let mut stats = self.simulation.get_stats().await.sim_stats(&self.simulation.config);
+        let scene = self.scene.read().await;
+        stats.region_x = scene.info.location_x;
+        stats.region_y = scene.info.location_y;
+        stats.region_flags = scene.info.flags;
+        stats.object_capacity = OBJECT_CAPACITY;
+        stats.total_prims = scene.objects.len() as u32;
+        stats.active_prims = scene.objects.values().filter(|o| o.velocity.length() > 0.0).count() as u32;
+        stats.active_scripts = self.scripts.as_ref().map_or(0, |scripts| scripts.active_scripts() as u32);
+        stats
+    }
+}
+
+impl Clone for SimulationLoop {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            subsystems: Arc::clone(&self.subsystems),
+            stats: Arc::clone(&self.stats),
+            running: Arc::clone(&self.running),
+            metrics: self.metrics.clone(),
+            profiler: self.profiler.clone(),
+            replay: self.replay.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_governor_degrades_and_recovers() {
+        let config = SimulationConfig {
+            frame_budget_ms: 10,
+            overload_frames: 2,
+            recovery_frames: 3,
+            max_degrade_level: 2,
+            ..SimulationConfig::default()
+        };
+        let mut governor = OverloadGovernor::new(&config);
+
+        assert_eq!(governor.observe(Duration::from_millis(20)), None);
+        assert_eq!(governor.observe(Duration::from_millis(20)), Some(1));
+        assert!(governor.should_run(2));
+        assert!(!governor.should_run(3));
+
+        governor.observe(Duration::from_millis(20));
+        assert_eq!(governor.observe(Duration::from_millis(20)), Some(2));
+        governor.observe(Duration::from_millis(20));
+        assert_eq!(governor.observe(Duration::from_millis(20)), None);
+
+        governor.observe(Duration::from_millis(1));
+        governor.observe(Duration::from_millis(1));
+        assert_eq!(governor.observe(Duration::from_millis(1)), Some(1));
+    }
+
+    #[test]
+    fn test_governor_past_the_shift_width() {
+        let mut governor = OverloadGovernor::new(&SimulationConfig::default());
+        governor.level = 64;
+        assert!(!governor.should_run(0));
+        assert!(!governor.should_run(1));
+    }
+
+    #[test]
+    fn test_sim_stats_reflect_frame_times() {
+        let config = SimulationConfig {
+            tick_rate: 10,
+            frame_budget_ms: 80,
+            ..SimulationConfig::default()
+        };
+        let mut stats = SimulationStats {
+            avg_frame_ms: 20.0,
+            ..SimulationStats::default()
+        };
+        stats.subsystems.entry(SubsystemKind::Physics).or_default().record(Duration::from_millis(5));
+
+        let sim = stats.sim_stats(&config);
+        assert_eq!(sim.time_dilation, 1.0);
+        assert_eq!(sim.sim_fps, 10.0);
+        assert_eq!(sim.physics_fps, 10.0);
+        assert_eq!(sim.spare_ms, 60.0);
+        assert_eq!(sim.sleep_ms, 80.0);
+
+        stats.avg_frame_ms = 200.0;
+        let sim = stats.sim_stats(&config);
+        assert_eq!(sim.time_dilation, 0.5);
+        assert_eq!(sim.sim_fps, 5.0);
+        assert_eq!(sim.spare_ms, 0.0);
+    }
+
+    #[test]
+    fn test_physics_is_never_shed() {
+        assert!(!SubsystemKind::Physics.sheddable());
+        assert!(SubsystemKind::ObjectUpdates.sheddable());
+    }
+}
@@ -0,0 +1,365 @@
+//! Snapshots and postcards
+//!
+//! Photos taken with the viewer's snapshot floater are uploaded in two
+//! steps: the request is kept under a new uploader ID, and the image posted
+//! to that uploader is checked, counted against the agent's daily upload
+//! allowance and stored as an asset. Snapshots are given an inventory item;
+//! postcards are kept as JPEG assets and, when the sender allows it and a
+//! gallery is configured, published to the gallery rather than mailed.
+
+use crate::limits::{LimitError, Limits};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::Utc;
+use mutsea_core::config::SnapshotsConfig;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::UserId;
+use mutsea_database::schema::{Asset, InventoryItem};
+use mutsea_database::DatabaseManager;
+use mutsea_protocol::constants::{asset_types, inventory_types};
+use mutsea_protocol::snapshot::{is_j2c, is_jpeg, Postcard, SnapshotUpload};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Permissions an agent keeps on its own snapshots
+const OWNER_PERMISSIONS: u32 = 0x7fff_ffff;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SnapshotError {
+    #[error("Only textures can be uploaded here, not {0}")]
+    NotTexture(String),
+    #[error("Unknown or expired upload")]
+    UnknownUpload,
+    #[error("Photos can't be larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+    #[error("Photos can't be stored without a database")]
+    NoDatabase,
+    #[error("Failed to store photo: {0}")]
+    Database(String),
+}
+
+/// What an uploader is waiting for
+#[derive(Debug, Clone)]
+enum Request {
+    Snapshot(SnapshotUpload),
+    Postcard(Postcard),
+}
+
+#[derive(Debug, Clone)]
+struct PendingUpload {
+    agent: UserId,
+    request: Request,
+    expires: Instant,
+}
+
+/// A photo stored from an uploader
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredPhoto {
+    pub asset_id: Uuid,
+    /// The inventory item of a snapshot; postcards have none
+    pub item_id: Option<Uuid>,
+    /// Whether the photo is being published to the gallery
+    pub published: bool,
+}
+
+/// Takes photo uploads and publishes postcards. Clones share the uploads
+/// waiting for their image.
+#[derive(Clone)]
+pub struct Snapshots {
+    config: SnapshotsConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    database: Option<Arc<DatabaseManager>>,
+    limits: Option<Limits>,
+    client: reqwest::Client,
+    pending: Arc<Mutex<HashMap<Uuid, PendingUpload>>>,
+}
+
+impl Snapshots {
+    pub fn new(config: SnapshotsConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.gallery_timeout_secs))
+            .build()
+            .unwrap_or_default();
+        Self {
+            config,
+            scene,
+            database: None,
+            limits: None,
+            client,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Store uploaded photos in `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Count uploads against their uploaders' daily allowance in `limits`
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Wait for the image of a snapshot `agent` is saving to inventory,
+    /// returning the ID of its uploader
+    pub fn begin_snapshot(&self, agent: UserId, upload: SnapshotUpload) -> Result<Uuid, SnapshotError> {
+        if !upload.is_texture() {
+            return Err(SnapshotError::NotTexture(upload.asset_type));
+        }
+        Ok(self.begin(agent, Request::Snapshot(upload)))
+    }
+
+    /// Wait for the image of a postcard `agent` is sending, returning the
+    /// ID of its uploader
+    pub fn begin_postcard(&self, agent: UserId, postcard: Postcard) -> Uuid {
+        self.begin(agent, Request::Postcard(postcard))
+    }
+
+    fn begin(&self, agent: UserId, request: Request) -> Uuid {
+        let now = Instant::now();
+        let uploader = Uuid::new_v4();
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, upload| upload.expires > now);
+        pending.insert(
+            uploader,
+            PendingUpload {
+                agent,
+                request,
+                expires: now + Duration::from_secs(self.config.upload_timeout_secs),
+            },
+        );
+        uploader
+    }
+
+    /// Store the image `agent` posted to `uploader`
+    pub async fn complete(&self, agent: UserId, uploader: Uuid, data: Vec<u8>) -> Result<StoredPhoto, SnapshotError> {
+        let upload = {
+            let mut pending = self.pending.lock().unwrap();
+            match pending.get(&uploader) {
+                Some(upload) if upload.agent == agent && upload.expires > Instant::now() => pending.remove(&uploader),
+                _ => None,
+            }
+        }
+        .ok_or(SnapshotError::UnknownUpload)?;
+
+        if data.len() > self.config.max_upload_bytes {
+            return Err(SnapshotError::TooLarge(self.config.max_upload_bytes));
+        }
+        match &upload.request {
+            Request::Snapshot(_) if !is_j2c(&data) => {
+                return Err(SnapshotError::Invalid("Snapshots must be JPEG-2000 images".to_string()))
+            }
+            Request::Postcard(_) if !is_jpeg(&data) => {
+                return Err(SnapshotError::Invalid("Postcards must be JPEG images".to_string()))
+            }
+            _ => {}
+        }
+        let database = self.database.as_ref().ok_or(SnapshotError::NoDatabase)?;
+        if let Some(limits) = &self.limits {
+            limits.reserve_upload(agent, data.len())?;
+        }
+
+        match upload.request {
+            Request::Snapshot(snapshot) => self.store_snapshot(database, agent, snapshot, data).await,
+            Request::Postcard(postcard) => self.store_postcard(database, agent, postcard, data).await,
+        }
+    }
+
+    async fn store_snapshot(
+        &self,
+        database: &DatabaseManager,
+        agent: UserId,
+        snapshot: SnapshotUpload,
+        data: Vec<u8>,
+    ) -> Result<StoredPhoto, SnapshotError> {
+        let asset_id = Uuid::new_v4();
+        let item_id = Uuid::new_v4();
+        let mut asset = Asset::new(
+            asset_id.to_string(),
+            snapshot.name.clone(),
+            asset_types::TEXTURE as i32,
+            data,
+        );
+        asset.creator_id = agent.to_string();
+        let inv_type = match snapshot.inventory_type.as_str() {
+            "snapshot" => inventory_types::SNAPSHOT,
+            _ => inventory_types::TEXTURE,
+        };
+        let item = InventoryItem {
+            inventory_id: item_id.to_string(),
+            asset_id: asset.id.clone(),
+            asset_type: asset_types::TEXTURE as i32,
+            parent_folder_id: snapshot.folder_id.to_string(),
+            avatar_id: agent.to_string(),
+            name: snapshot.name.clone(),
+            description: snapshot.description,
+            next_permissions: snapshot.next_owner_mask as i32,
+            current_permissions: OWNER_PERMISSIONS as i32,
+            inv_type: inv_type as i32,
+            creator_id: agent.to_string(),
+            base_permissions: OWNER_PERMISSIONS as i32,
+            everyone_permissions: snapshot.everyone_mask as i32,
+            creation_date: Utc::now().timestamp() as i32,
+        };
+        let saved = match database.insert_asset(&asset).await {
+            Ok(()) => database.insert_inventory_item(&item).await,
+            Err(e) => Err(e),
+        };
+        saved.map_err(|e| SnapshotError::Database(e.to_string()))?;
+
+        info!("📸 Agent {} saved snapshot \"{}\" as {}", agent, snapshot.name, asset_id);
+        Ok(StoredPhoto {
+            asset_id,
+            item_id: Some(item_id),
+            published: false,
+        })
+    }
+
+    async fn store_postcard(
+        &self,
+        database: &DatabaseManager,
+        agent: UserId,
+        postcard: Postcard,
+        data: Vec<u8>,
+    ) -> Result<StoredPhoto, SnapshotError> {
+        let asset_id = Uuid::new_v4();
+        let mut asset = Asset::new(
+            asset_id.to_string(),
+            postcard.subject.clone(),
+            asset_types::IMAGE_JPEG as i32,
+            data,
+        );
+        asset.creator_id = agent.to_string();
+        database
+            .insert_asset(&asset)
+            .await
+            .map_err(|e| SnapshotError::Database(e.to_string()))?;
+        info!("📮 Agent {} sent postcard \"{}\" as {}", agent, postcard.subject, asset_id);
+
+        let published = postcard.allow_publish && !self.config.gallery_url.is_empty();
+        if published {
+            let entry = self.gallery_entry(agent, asset_id, &postcard, &asset.data).await;
+            let snapshots = self.clone();
+            tokio::spawn(async move { snapshots.publish(asset_id, entry).await });
+        }
+        Ok(StoredPhoto {
+            asset_id,
+            item_id: None,
+            published,
+        })
+    }
+
+    /// What the gallery is sent for a postcard
+    async fn gallery_entry(&self, agent: UserId, asset_id: Uuid, postcard: &Postcard, image: &[u8]) -> serde_json::Value {
+        let scene = self.scene.read().await;
+        let origin = [
+            (scene.info.location_x * 256) as f64,
+            (scene.info.location_y * 256) as f64,
+        ];
+        serde_json::json!({
+            "asset_id": asset_id,
+            "agent_id": agent.to_string(),
+            "sender_name": postcard.name,
+            "subject": postcard.subject,
+            "message": postcard.message,
+            "region": scene.info.region_name,
+            "position": [
+                postcard.pos_global[0] - origin[0],
+                postcard.pos_global[1] - origin[1],
+                postcard.pos_global[2],
+            ],
+            "mature": postcard.mature_publish,
+            "taken_at": Utc::now().to_rfc3339(),
+            "content_type": "image/jpeg",
+            "image": BASE64.encode(image),
+        })
+    }
+
+    async fn publish(&self, asset_id: Uuid, entry: serde_json::Value) {
+        let mut request = self.client.post(&self.config.gallery_url).json(&entry);
+        if !self.config.gallery_api_key.is_empty() {
+            request = request.bearer_auth(&self.config.gallery_api_key);
+        }
+        match request.send().await.and_then(|response| response.error_for_status()) {
+            Ok(_) => info!("🖼️ Published postcard {} to the gallery", asset_id),
+            Err(e) => warn!("Failed to publish postcard {} to the gallery: {}", asset_id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::RegionInfo;
+
+    fn snapshots(config: SnapshotsConfig) -> Snapshots {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        Snapshots::new(config, Arc::new(RwLock::new(RegionScene::new(info, UserId::new()))))
+    }
+
+    fn snapshot(asset_type: &str) -> SnapshotUpload {
+        SnapshotUpload {
+            folder_id: Uuid::new_v4(),
+            asset_type: asset_type.to_string(),
+            inventory_type: "snapshot".to_string(),
+            name: "Sunset".to_string(),
+            description: String::new(),
+            everyone_mask: 0,
+            next_owner_mask: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_uploads_belong_to_their_agent() {
+        let snapshots = snapshots(SnapshotsConfig::default());
+        let agent = UserId::new();
+        assert_eq!(
+            snapshots.begin_snapshot(agent, snapshot("sound")),
+            Err(SnapshotError::NotTexture("sound".to_string()))
+        );
+
+        let uploader = snapshots.begin_snapshot(agent, snapshot("texture")).unwrap();
+        let j2c = vec![0xff, 0x4f, 0xff, 0x51, 0x00];
+        assert_eq!(
+            snapshots.complete(UserId::new(), uploader, j2c.clone()).await,
+            Err(SnapshotError::UnknownUpload)
+        );
+        assert_eq!(
+            snapshots.complete(agent, uploader, j2c.clone()).await,
+            Err(SnapshotError::NoDatabase)
+        );
+        // Each uploader takes one image
+        assert_eq!(snapshots.complete(agent, uploader, j2c).await, Err(SnapshotError::UnknownUpload));
+    }
+
+    #[tokio::test]
+    async fn test_images_are_checked() {
+        let config = SnapshotsConfig {
+            max_upload_bytes: 16,
+            ..SnapshotsConfig::default()
+        };
+        let snapshots = snapshots(config);
+        let agent = UserId::new();
+
+        let uploader = snapshots.begin_snapshot(agent, snapshot("texture")).unwrap();
+        assert_eq!(
+            snapshots.complete(agent, uploader, vec![0xff; 17]).await,
+            Err(SnapshotError::TooLarge(16))
+        );
+        let uploader = snapshots.begin_snapshot(agent, snapshot("texture")).unwrap();
+        assert!(matches!(
+            snapshots.complete(agent, uploader, vec![0xff, 0xd8, 0xff, 0xe0]).await,
+            Err(SnapshotError::Invalid(_))
+        ));
+    }
+}
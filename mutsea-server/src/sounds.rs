@@ -0,0 +1,381 @@
+//! Sounds played in the region
+//!
+//! Scripts play, loop, trigger and preload sounds from their prims, and
+//! agents play gesture sounds; each is sent to the agents within hearing
+//! distance. Sounds a prim loops are also sent to agents as they arrive.
+//! New sounds are uploaded through the admin API and stored as assets
+//! once they are checked to be Ogg Vorbis streams viewers can play.
+
+use crate::limits::{LimitError, Limits};
+use mutsea_core::config::SoundsConfig;
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{ObjectId, UserId, Vector3};
+use mutsea_database::schema::Asset;
+use mutsea_database::DatabaseManager;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::constants::asset_types;
+use mutsea_protocol::sound::{AttachedSound, SoundInfo, SoundTrigger, SOUND_FLAG_LOOP};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SoundError {
+    #[error("Unknown object {0}")]
+    UnknownObject(ObjectId),
+    #[error("Sounds can't be larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("Sounds can't be longer than {0} seconds")]
+    TooLong(f32),
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+    #[error("Sounds can't be stored without a database")]
+    NoDatabase,
+    #[error("Failed to store sound: {0}")]
+    Database(String),
+}
+
+/// An uploaded sound, as stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredSound {
+    pub asset_id: Uuid,
+    pub info: SoundInfo,
+}
+
+/// Plays sounds to the agents that can hear them. Clones share what each
+/// prim is looping.
+#[derive(Clone)]
+pub struct Sounds {
+    config: SoundsConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: Option<LLUDPServer>,
+    database: Option<Arc<DatabaseManager>>,
+    limits: Option<Limits>,
+    /// The sound each prim is looping
+    looping: Arc<Mutex<HashMap<ObjectId, AttachedSound>>>,
+}
+
+impl Sounds {
+    pub fn new(config: SoundsConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            config,
+            scene,
+            lludp_server: None,
+            database: None,
+            limits: None,
+            looping: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Play sounds to the agents connected to `lludp_server`, and the
+    /// sounds they play to each other
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Store uploaded sounds in `database`
+    pub fn with_database(mut self, database: Arc<DatabaseManager>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Count uploads against their uploaders' daily allowance in `limits`
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Pass on the sounds agents play and send arriving agents the sounds
+    /// prims are looping
+    pub fn spawn(&self) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        let sounds = self.clone();
+        let mut events = lludp_server.subscribe_events();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => sounds.handle(event.event_data).await,
+                    Err(RecvError::Lagged(missed)) => warn!("Sounds missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle(&self, event: NetworkEventData) {
+        let Some(lludp_server) = &self.lludp_server else {
+            return;
+        };
+        match event {
+            NetworkEventData::SoundTriggered {
+                agent_id,
+                sound_id,
+                object_id,
+                parent_id,
+                position,
+                gain,
+                ..
+            } => {
+                let trigger = SoundTrigger {
+                    sound_id,
+                    owner_id: agent_id.as_uuid(),
+                    object_id,
+                    parent_id,
+                    region_handle: self.region_handle().await,
+                    position,
+                    gain: gain.clamp(0.0, 1.0),
+                };
+                self.send_trigger(&trigger).await;
+            }
+            NetworkEventData::AgentLoggedIn { agent_id, .. } => {
+                let looping: Vec<AttachedSound> = self.looping.lock().unwrap().values().cloned().collect();
+                for sound in looping {
+                    if let Err(e) = lludp_server.send_attached_sound_to(agent_id, &sound).await {
+                        debug!("Failed to send {} the sound of {}: {}", agent_id, sound.object_id, e);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Play `sound_id` from `object`, over and over if `looped`, replacing
+    /// what it was playing. Returns how many agents were sent it.
+    pub async fn play(&self, object: ObjectId, sound_id: Uuid, gain: f32, looped: bool) -> Result<usize, SoundError> {
+        let (position, owner_id) = self.prim(object).await?;
+        let sound = AttachedSound {
+            sound_id,
+            object_id: object.as_uuid(),
+            owner_id: owner_id.as_uuid(),
+            gain: gain.clamp(0.0, 1.0),
+            flags: if looped { SOUND_FLAG_LOOP } else { 0 },
+        };
+        {
+            let mut looping = self.looping.lock().unwrap();
+            if looped {
+                looping.insert(object, sound.clone());
+            } else {
+                looping.remove(&object);
+            }
+        }
+        self.send_attached(&sound, position).await
+    }
+
+    /// Play `sound_id` once where `object` is, staying there if it moves.
+    /// Returns how many agents were sent it.
+    pub async fn trigger(&self, object: ObjectId, sound_id: Uuid, gain: f32) -> Result<usize, SoundError> {
+        let (position, owner_id) = self.prim(object).await?;
+        let trigger = SoundTrigger {
+            sound_id,
+            owner_id: owner_id.as_uuid(),
+            object_id: object.as_uuid(),
+            parent_id: Uuid::nil(),
+            region_handle: self.region_handle().await,
+            position,
+            gain: gain.clamp(0.0, 1.0),
+        };
+        Ok(self.send_trigger(&trigger).await)
+    }
+
+    /// Stop the sound `object` is playing
+    pub async fn stop(&self, object: ObjectId) -> Result<usize, SoundError> {
+        let (position, owner_id) = self.prim(object).await?;
+        self.looping.lock().unwrap().remove(&object);
+        self.send_attached(&AttachedSound::stop(object.as_uuid(), owner_id.as_uuid()), position)
+            .await
+    }
+
+    /// Change the volume of the sound `object` is playing
+    pub async fn set_volume(&self, object: ObjectId, gain: f32) -> Result<usize, SoundError> {
+        let (position, _) = self.prim(object).await?;
+        let gain = gain.clamp(0.0, 1.0);
+        if let Some(sound) = self.looping.lock().unwrap().get_mut(&object) {
+            sound.gain = gain;
+        }
+        let Some(lludp_server) = &self.lludp_server else {
+            return Ok(0);
+        };
+        let sent = lludp_server
+            .send_attached_sound_gain(object.as_uuid(), gain, position, self.config.hearing_distance)
+            .await;
+        Ok(sent.unwrap_or_else(|e| {
+            warn!("Failed to send the volume of {}: {}", object, e);
+            0
+        }))
+    }
+
+    /// Have the viewers near `object` fetch `sound_id` before it is played
+    pub async fn preload(&self, object: ObjectId, sound_id: Uuid) -> Result<usize, SoundError> {
+        let (position, owner_id) = self.prim(object).await?;
+        let Some(lludp_server) = &self.lludp_server else {
+            return Ok(0);
+        };
+        let sent = lludp_server
+            .send_preload_sound(
+                object.as_uuid(),
+                owner_id.as_uuid(),
+                sound_id,
+                position,
+                self.config.hearing_distance,
+            )
+            .await;
+        Ok(sent.unwrap_or_else(|e| {
+            warn!("Failed to preload {} near {}: {}", sound_id, object, e);
+            0
+        }))
+    }
+
+    /// The sound `object` is looping, if any
+    pub fn looping(&self, object: ObjectId) -> Option<AttachedSound> {
+        self.looping.lock().unwrap().get(&object).cloned()
+    }
+
+    /// Stop tracking the sound of a deleted prim
+    pub fn forget(&self, object: ObjectId) {
+        self.looping.lock().unwrap().remove(&object);
+    }
+
+    /// Check `data` is a sound viewers can play, within the configured
+    /// size and length
+    pub fn check(&self, data: &[u8]) -> Result<SoundInfo, SoundError> {
+        if data.len() > self.config.max_upload_bytes {
+            return Err(SoundError::TooLarge(self.config.max_upload_bytes));
+        }
+        let info = SoundInfo::decode(data).map_err(|e| SoundError::Invalid(e.to_string()))?;
+        if info.duration > self.config.max_duration_secs {
+            return Err(SoundError::TooLong(self.config.max_duration_secs));
+        }
+        Ok(info)
+    }
+
+    /// Check and store the sound in `data` as an asset named `name`
+    pub async fn upload(&self, name: &str, creator_id: UserId, data: Vec<u8>) -> Result<StoredSound, SoundError> {
+        let info = self.check(&data)?;
+        let database = self.database.as_ref().ok_or(SoundError::NoDatabase)?;
+        if let Some(limits) = &self.limits {
+            limits.reserve_upload(creator_id, data.len())?;
+        }
+
+        let asset_id = Uuid::new_v4();
+        let mut asset = Asset::new(asset_id.to_string(), name.to_string(), asset_types::SOUND as i32, data);
+        asset.creator_id = creator_id.to_string();
+        database
+            .insert_asset(&asset)
+            .await
+            .map_err(|e| SoundError::Database(e.to_string()))?;
+
+        info!(
+            "Stored sound \"{}\" as {}: {:.1}s, {} channel(s)",
+            name, asset_id, info.duration, info.channels
+        );
+        Ok(StoredSound { asset_id, info })
+    }
+
+    async fn prim(&self, object: ObjectId) -> Result<(Vector3, UserId), SoundError> {
+        let scene = self.scene.read().await;
+        let prim = scene.objects.get(&object).ok_or(SoundError::UnknownObject(object))?;
+        Ok((prim.position, prim.owner_id))
+    }
+
+    async fn region_handle(&self) -> u64 {
+        let scene = self.scene.read().await;
+        ((scene.info.location_x as u64 * 256) << 32) | (scene.info.location_y as u64 * 256)
+    }
+
+    async fn send_attached(&self, sound: &AttachedSound, position: Vector3) -> Result<usize, SoundError> {
+        let Some(lludp_server) = &self.lludp_server else {
+            return Ok(0);
+        };
+        let sent = lludp_server
+            .send_attached_sound(sound, position, self.config.hearing_distance)
+            .await;
+        Ok(sent.unwrap_or_else(|e| {
+            warn!("Failed to send the sound of {}: {}", sound.object_id, e);
+            0
+        }))
+    }
+
+    async fn send_trigger(&self, trigger: &SoundTrigger) -> usize {
+        let Some(lludp_server) = &self.lludp_server else {
+            return 0;
+        };
+        let sent = lludp_server
+            .send_sound_trigger(trigger, self.config.full_gain_distance, self.config.hearing_distance)
+            .await;
+        sent.unwrap_or_else(|e| {
+            warn!("Failed to send sound {}: {}", trigger.sound_id, e);
+            0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::prim;
+    use mutsea_core::RegionInfo;
+
+    #[tokio::test]
+    async fn test_prims_loop_and_stop_sounds() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let fountain = prim(1, UserId::new(), Vector3::new(128.0, 128.0, 25.0));
+        let fountain_id = fountain.id;
+        scene.write().await.add_object(fountain);
+        let sounds = Sounds::new(SoundsConfig::default(), scene);
+        let splash = Uuid::new_v4();
+
+        sounds.play(fountain_id, splash, 1.5, true).await.unwrap();
+        let looping = sounds.looping(fountain_id).unwrap();
+        assert_eq!(
+            (looping.sound_id, looping.gain, looping.flags),
+            (splash, 1.0, SOUND_FLAG_LOOP)
+        );
+        sounds.set_volume(fountain_id, 0.25).await.unwrap();
+        assert_eq!(sounds.looping(fountain_id).unwrap().gain, 0.25);
+
+        sounds.play(fountain_id, splash, 0.5, false).await.unwrap();
+        assert_eq!(sounds.looping(fountain_id), None);
+        sounds.play(fountain_id, splash, 0.5, true).await.unwrap();
+        sounds.stop(fountain_id).await.unwrap();
+        assert_eq!(sounds.looping(fountain_id), None);
+
+        let missing = ObjectId::new();
+        assert_eq!(
+            sounds.trigger(missing, splash, 1.0).await,
+            Err(SoundError::UnknownObject(missing))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_uploads_are_limited() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let config = SoundsConfig {
+            max_upload_bytes: 64,
+            ..SoundsConfig::default()
+        };
+        let sounds = Sounds::new(config, scene);
+
+        assert_eq!(sounds.check(&[0; 65]), Err(SoundError::TooLarge(64)));
+        assert!(matches!(
+            sounds.check(b"RIFF\0\0\0\0WAVEfmt "),
+            Err(SoundError::Invalid(_))
+        ));
+        assert!(matches!(
+            sounds.upload("Splash", UserId::new(), b"OggS".to_vec()).await,
+            Err(SoundError::Invalid(_))
+        ));
+    }
+}
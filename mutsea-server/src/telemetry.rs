@@ -0,0 +1,89 @@
+//! OpenTelemetry trace export
+//!
+//! LLUDP packets, packet handlers, logins and database queries each open a
+//! span, nested so that one teleport or asset fetch forms a single trace.
+//! The subscriber is set up before the configuration is read, so the OTLP
+//! layer starts out empty and is swapped in once `[telemetry]` is known.
+
+use mutsea_core::config::TelemetryConfig;
+use tracing::{info, warn};
+use tracing_subscriber::{reload, Registry};
+
+#[cfg(feature = "otlp")]
+type OtelLayer = tracing_opentelemetry::OpenTelemetryLayer<Registry, opentelemetry_sdk::trace::Tracer>;
+#[cfg(not(feature = "otlp"))]
+type OtelLayer = tracing_subscriber::layer::Identity;
+
+/// Subscriber layer that exports spans once [`Telemetry::start`] succeeds
+pub type TelemetryLayer = reload::Layer<Option<OtelLayer>, Registry>;
+
+/// Handle for starting and flushing the trace exporter
+pub struct Telemetry {
+    handle: reload::Handle<Option<OtelLayer>, Registry>,
+}
+
+impl Telemetry {
+    /// An inactive export layer and the handle that activates it
+    pub fn layer() -> (TelemetryLayer, Self) {
+        let (layer, handle) = reload::Layer::new(None);
+        (layer, Self { handle })
+    }
+
+    /// Start exporting spans over OTLP if enabled in `config`
+    #[cfg(feature = "otlp")]
+    pub fn start(&self, config: &TelemetryConfig) {
+        use opentelemetry::KeyValue;
+        use opentelemetry_otlp::WithExportConfig;
+        use opentelemetry_sdk::trace::{self, Sampler};
+        use opentelemetry_sdk::Resource;
+
+        if !config.enabled {
+            return;
+        }
+
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(config.otlp_endpoint.clone()),
+            )
+            .with_trace_config(
+                trace::config()
+                    .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio))))
+                    .with_resource(Resource::new([KeyValue::new("service.name", config.service_name.clone())])),
+            )
+            .install_batch(opentelemetry_sdk::runtime::Tokio);
+
+        let tracer = match tracer {
+            Ok(tracer) => tracer,
+            Err(e) => {
+                warn!("⚠️  Trace export disabled, OTLP exporter failed to start: {}", e);
+                return;
+            }
+        };
+        match self.handle.reload(Some(tracing_opentelemetry::layer().with_tracer(tracer))) {
+            Ok(()) => info!("📡 Exporting traces to {}", config.otlp_endpoint),
+            Err(e) => warn!("⚠️  Trace export disabled: {}", e),
+        }
+    }
+
+    /// Built without the `otlp` feature: warn if export was asked for
+    #[cfg(not(feature = "otlp"))]
+    pub fn start(&self, config: &TelemetryConfig) {
+        if config.enabled {
+            warn!("⚠️  telemetry.enabled is set but this build has no OTLP support (feature `otlp`)");
+        }
+    }
+
+    /// Flush spans still queued for export
+    #[cfg(feature = "otlp")]
+    pub fn shutdown(&self) {
+        if self.handle.with_current(|layer| layer.is_some()).unwrap_or(false) {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+
+    #[cfg(not(feature = "otlp"))]
+    pub fn shutdown(&self) {}
+}
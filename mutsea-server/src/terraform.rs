@@ -0,0 +1,510 @@
+//! Terraforming with the viewer's land tools
+//!
+//! Each ModifyLand applies a brush to the terrain: around the point clicked,
+//! fading out to the brush's edge, or evenly over a selection. Agents may
+//! terraform parcels they own and parcels that allow anyone to; admins
+//! terraform anywhere. Strokes closer together than [`STROKE_GAP`] make up
+//! one step of the agent's undo history, which keeps the heights they
+//! replaced. Edited 16x16 patches are sent to viewers a few times a second,
+//! and the heightmap is saved at most every `save_interval_secs` so a
+//! stream of brush strokes doesn't rewrite it on every one.
+
+use mutsea_core::config::TerraformConfig;
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::scene::{RegionScene, Terrain};
+use mutsea_core::UserId;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::layer_data::{self, PATCH_SIZE};
+use mutsea_protocol::login::OpenSimLoginService;
+use mutsea_protocol::terraform::{TerrainAction, PARCEL_FLAG_ALLOW_TERRAFORM};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+/// Brush applications closer together than this are one undo step
+pub const STROKE_GAP: Duration = Duration::from_millis(500);
+
+/// How often edited patches are sent to viewers
+const PATCH_SEND_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TerraformError {
+    #[error("Unknown land tool action {0}")]
+    UnknownAction(u8),
+    #[error("You can't terraform the parcel \"{0}\"")]
+    NoPermission(String),
+    #[error("There is no terrain edit to undo")]
+    NothingToUndo,
+}
+
+/// One application of a land tool
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Brush {
+    pub action: TerrainAction,
+    /// Radius in metres, for a brush applied at a point
+    pub radius: f32,
+    pub seconds: f32,
+    /// Height to flatten to
+    pub height: f32,
+    /// `(west, south, east, north)` in region metres
+    pub area: (f32, f32, f32, f32),
+}
+
+impl Brush {
+    /// The cells the brush reaches, with how strongly: fading from the
+    /// centre of a point to its radius, evenly over a selection
+    fn cells(&self, size_x: u32, size_y: u32) -> Vec<(u32, u32, f32)> {
+        let (west, south, east, north) = self.area;
+        let point = west == east && south == north;
+        let reach = if point { self.radius.max(0.5) } else { 0.0 };
+        let columns = (west - reach).floor().max(0.0) as u32..=((east + reach).ceil().max(0.0) as u32).min(size_x - 1);
+        let rows = (south - reach).floor().max(0.0) as u32..=((north + reach).ceil().max(0.0) as u32).min(size_y - 1);
+
+        let mut cells = Vec::new();
+        for y in rows {
+            for x in columns.clone() {
+                let strength = if point {
+                    let distance = ((x as f32 - west).powi(2) + (y as f32 - south).powi(2)).sqrt();
+                    1.0 - (distance / reach).powi(2)
+                } else {
+                    1.0
+                };
+                if strength > 0.0 {
+                    cells.push((x, y, strength));
+                }
+            }
+        }
+        cells
+    }
+}
+
+/// Apply `brush` to the cells of `terrain` that `allowed` accepts, moving
+/// them at up to `rate` metres a second within `range`. `revert` is the
+/// terrain the Revert tool returns to. The heights replaced are added to
+/// `before`, unless it already has the cell. Returns the cells changed.
+pub fn apply_brush(
+    terrain: &mut Terrain,
+    revert: &Terrain,
+    brush: &Brush,
+    rate: f32,
+    range: (f32, f32),
+    allowed: impl Fn(u32, u32) -> bool,
+    before: &mut HashMap<(u32, u32), f32>,
+) -> Vec<(u32, u32)> {
+    // Work out every new height from the terrain as it was, so smoothing
+    // doesn't feed on its own output
+    let updates: Vec<(u32, u32, f32)> = brush
+        .cells(terrain.size_x, terrain.size_y)
+        .into_iter()
+        .filter(|&(x, y, _)| allowed(x, y))
+        .map(|(x, y, strength)| {
+            let height = terrain.height_at(x, y);
+            let amount = rate * brush.seconds * strength;
+            let toward = |target: f32| height + (target - height) * amount.min(1.0);
+            let new = match brush.action {
+                TerrainAction::Raise => height + amount,
+                TerrainAction::Lower => height - amount,
+                TerrainAction::Flatten => toward(brush.height),
+                TerrainAction::Smooth => {
+                    let mut sum = 0.0;
+                    for (dx, dy) in (-1i32..=1).flat_map(|dx| (-1i32..=1).map(move |dy| (dx, dy))) {
+                        sum += terrain.height_at((x as i32 + dx).max(0) as u32, (y as i32 + dy).max(0) as u32);
+                    }
+                    toward(sum / 9.0)
+                }
+                TerrainAction::Roughen => height + (rand::random::<f32>() * 2.0 - 1.0) * amount,
+                TerrainAction::Revert => toward(revert.height_at(x, y)),
+            };
+            (x, y, new.clamp(range.0, range.1))
+        })
+        .collect();
+
+    let mut changed = Vec::new();
+    for (x, y, height) in updates {
+        let old = terrain.height_at(x, y);
+        if height != old {
+            before.entry((x, y)).or_insert(old);
+            terrain.set_height(x, y, height);
+            changed.push((x, y));
+        }
+    }
+    changed
+}
+
+/// The patch holding the cell `x`, `y`
+fn patch_of(x: u32, y: u32) -> (u32, u32) {
+    (x / PATCH_SIZE as u32, y / PATCH_SIZE as u32)
+}
+
+/// A stroke of one agent, and the heights it replaced
+struct Stroke {
+    before: HashMap<(u32, u32), f32>,
+    last_applied: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    undo: HashMap<UserId, VecDeque<Stroke>>,
+    /// The terrain as loaded, which the Revert tool returns to
+    revert: Option<Terrain>,
+    /// Patches edited since they were last sent
+    dirty_patches: HashSet<(u32, u32)>,
+    /// Edited since last saved
+    unsaved: bool,
+}
+
+/// The terrain edits of one region. Clones share the undo history.
+#[derive(Clone)]
+pub struct Terraforming {
+    config: TerraformConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    login_service: Arc<OpenSimLoginService>,
+    lludp_server: Option<LLUDPServer>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Terraforming {
+    pub fn new(
+        config: TerraformConfig,
+        scene: Arc<RwLock<RegionScene>>,
+        login_service: Arc<OpenSimLoginService>,
+    ) -> Self {
+        Self {
+            config,
+            scene,
+            login_service,
+            lludp_server: None,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Take land tool strokes from the agents connected to `lludp_server`
+    /// and send them the patches edited
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Load the region's saved terrain, if it has been edited before
+    pub async fn load(&self) {
+        let mut scene = self.scene.write().await;
+        let path = self.config.region_path(&scene.info.region_name);
+        match std::fs::read(&path) {
+            Ok(data) => match Terrain::from_r32(&data, scene.info.size_x, scene.info.size_y) {
+                Some(terrain) => {
+                    scene.terrain = terrain;
+                    info!("⛰️ Loaded terrain of {} from {:?}", scene.info.region_name, path);
+                }
+                None => warn!("Ignoring terrain {:?}: not the size of the region", path),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to read terrain {:?}: {}", path, e),
+        }
+        self.state.lock().unwrap().revert = Some(scene.terrain.clone());
+    }
+
+    /// Apply `brush` for `agent`, recording the heights it replaced in the
+    /// agent's undo history
+    pub async fn modify(&self, agent: UserId, brush: Brush) -> Result<usize, TerraformError> {
+        let mut scene = self.scene.write().await;
+        let scene = &mut *scene;
+        let admin = self.login_service.is_admin(&agent);
+        let (west, south, east, north) = brush.area;
+        if !admin {
+            let centre = scene.parcel_at((west + east) / 2.0, (south + north) / 2.0);
+            if let Some(parcel) = centre.filter(|parcel| !may_terraform(parcel.owner_id, parcel.flags, agent)) {
+                return Err(TerraformError::NoPermission(parcel.name.clone()));
+            }
+        }
+
+        // Cells on parcels the agent may not terraform are left alone
+        let parcels = &scene.parcels;
+        let allowed = |x: u32, y: u32| {
+            admin
+                || parcels
+                    .iter()
+                    .find(|parcel| parcel.contains(x as f32 + 0.5, y as f32 + 0.5))
+                    .is_some_and(|parcel| may_terraform(parcel.owner_id, parcel.flags, agent))
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let loaded;
+        let revert = match &state.revert {
+            Some(revert) => revert,
+            None => {
+                loaded = scene.terrain.clone();
+                &loaded
+            }
+        };
+        let now = Instant::now();
+        let history = state.undo.entry(agent).or_default();
+        let continues = history
+            .back()
+            .is_some_and(|stroke| now.duration_since(stroke.last_applied) < STROKE_GAP);
+        if !continues {
+            history.push_back(Stroke {
+                before: HashMap::new(),
+                last_applied: now,
+            });
+            while history.len() > self.config.max_undo_steps.max(1) {
+                history.pop_front();
+            }
+        }
+        let stroke = history.back_mut().unwrap();
+        stroke.last_applied = now;
+        let changed = apply_brush(
+            &mut scene.terrain,
+            revert,
+            &brush,
+            self.config.brush_rate,
+            (self.config.min_height, self.config.max_height),
+            allowed,
+            &mut stroke.before,
+        );
+        if stroke.before.is_empty() {
+            history.pop_back();
+        }
+
+        state.dirty_patches.extend(changed.iter().map(|&(x, y)| patch_of(x, y)));
+        state.unsaved |= !changed.is_empty();
+        Ok(changed.len())
+    }
+
+    /// Undo the last stroke of `agent`
+    pub async fn undo(&self, agent: UserId) -> Result<usize, TerraformError> {
+        let mut scene = self.scene.write().await;
+        let mut state = self.state.lock().unwrap();
+        let stroke = state
+            .undo
+            .get_mut(&agent)
+            .and_then(|history| history.pop_back())
+            .ok_or(TerraformError::NothingToUndo)?;
+        for (&(x, y), &height) in &stroke.before {
+            scene.terrain.set_height(x, y, height);
+        }
+        state
+            .dirty_patches
+            .extend(stroke.before.keys().map(|&(x, y)| patch_of(x, y)));
+        state.unsaved |= !stroke.before.is_empty();
+        debug!("Undid a terrain stroke of {} over {} cells", agent, stroke.before.len());
+        Ok(stroke.before.len())
+    }
+
+    /// Save the terrain if it was edited since it was last saved
+    pub async fn save(&self) -> std::io::Result<bool> {
+        if !std::mem::take(&mut self.state.lock().unwrap().unsaved) {
+            return Ok(false);
+        }
+        let (data, path) = {
+            let scene = self.scene.read().await;
+            (scene.terrain.to_r32(), self.config.region_path(&scene.info.region_name))
+        };
+        let written = tokio::task::spawn_blocking(move || write_terrain(&path, &data))
+            .await
+            .map_err(std::io::Error::other)?;
+        if written.is_err() {
+            // Try again next time
+            self.state.lock().unwrap().unsaved = true;
+        }
+        written.map(|()| true)
+    }
+
+    /// Take land tool strokes from the LLUDP server, send edited patches
+    /// every [`PATCH_SEND_INTERVAL`] and save the terrain every
+    /// `save_interval_secs`
+    pub fn spawn(&self) {
+        let terraform = self.clone();
+        let interval = Duration::from_secs(self.config.save_interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match terraform.save().await {
+                    Ok(true) => debug!("Saved the edited terrain"),
+                    Ok(false) => {}
+                    Err(e) => warn!("Failed to save the terrain: {}", e),
+                }
+            }
+        });
+
+        let Some(lludp_server) = self.lludp_server.clone() else {
+            return;
+        };
+        let terraform = self.clone();
+        let sender = lludp_server.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(PATCH_SEND_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let dirty = std::mem::take(&mut terraform.state.lock().unwrap().dirty_patches);
+                if dirty.is_empty() {
+                    continue;
+                }
+                let patches: Vec<_> = {
+                    let scene = terraform.scene.read().await;
+                    dirty
+                        .iter()
+                        .map(|&(x, y)| layer_data::land_patch(&scene.terrain, x, y))
+                        .collect()
+                };
+                if let Err(e) = sender.send_terrain_patches(&patches).await {
+                    warn!("Failed to send {} terrain patches: {}", patches.len(), e);
+                }
+            }
+        });
+
+        let terraform = self.clone();
+        let mut events = lludp_server.subscribe_events();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => terraform.handle(event.event_data).await,
+                    Err(RecvError::Lagged(missed)) => warn!("Terraforming missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle(&self, event: NetworkEventData) {
+        let (agent, result) = match event {
+            NetworkEventData::LandModified {
+                agent_id,
+                action,
+                brush_size,
+                seconds,
+                height,
+                areas,
+                ..
+            } => {
+                let result = match TerrainAction::from_u8(action) {
+                    Some(action) => {
+                        let mut result = Ok(0);
+                        for area in areas {
+                            let brush = Brush {
+                                action,
+                                radius: brush_size,
+                                seconds,
+                                height,
+                                area,
+                            };
+                            result = self.modify(agent_id, brush).await;
+                            if result.is_err() {
+                                break;
+                            }
+                        }
+                        result
+                    }
+                    None => Err(TerraformError::UnknownAction(action)),
+                };
+                (agent_id, result)
+            }
+            NetworkEventData::LandUndoRequested { agent_id, .. } => (agent_id, self.undo(agent_id).await),
+            _ => return,
+        };
+        if let Err(e) = result {
+            self.alert(agent, &e.to_string()).await;
+        }
+    }
+
+    async fn alert(&self, agent: UserId, message: &str) {
+        if let Some(lludp_server) = &self.lludp_server {
+            if let Err(e) = lludp_server.send_agent_alert(agent, false, message).await {
+                warn!("Failed to alert agent {}: {}", agent, e);
+            }
+        }
+    }
+}
+
+/// Whether `agent` may terraform a parcel owned by `owner` with `flags`
+fn may_terraform(owner: UserId, flags: u32, agent: UserId) -> bool {
+    owner == agent || flags & PARCEL_FLAG_ALLOW_TERRAFORM != 0
+}
+
+/// Write `data` to `path` so that a crash leaves either the old or the new
+/// terrain
+fn write_terrain(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temp = path.with_extension("r32.tmp");
+    std::fs::write(&temp, data)?;
+    std::fs::rename(&temp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::RegionInfo;
+
+    fn raise(x: f32, y: f32) -> Brush {
+        Brush {
+            action: TerrainAction::Raise,
+            radius: 2.0,
+            seconds: 0.5,
+            height: 0.0,
+            area: (x, y, x, y),
+        }
+    }
+
+    #[test]
+    fn test_brushes_fade_and_stay_in_range() {
+        let mut terrain = Terrain::flat(32, 32, 20.0);
+        let revert = terrain.clone();
+        let mut before = HashMap::new();
+        let changed = apply_brush(&mut terrain, &revert, &raise(10.0, 10.0), 4.0, (0.0, 21.5), |_, _| true, &mut before);
+        assert!(changed.contains(&(10, 10)) && !changed.contains(&(12, 10)));
+        assert_eq!(terrain.height_at(10, 10), 21.5);
+        assert_eq!(terrain.height_at(11, 11), 21.0);
+        assert_eq!(before[&(10, 10)], 20.0);
+
+        // Cells that aren't allowed are left alone
+        let changed = apply_brush(&mut terrain, &revert, &raise(20.0, 20.0), 4.0, (0.0, 30.0), |x, _| x < 20, &mut before);
+        assert!(changed.iter().all(|&(x, _)| x < 20));
+        assert_eq!(terrain.height_at(20, 20), 20.0);
+
+        let revert_brush = Brush {
+            action: TerrainAction::Revert,
+            seconds: 1.0,
+            ..raise(10.0, 10.0)
+        };
+        apply_brush(&mut terrain, &revert, &revert_brush, 4.0, (0.0, 30.0), |_, _| true, &mut before);
+        assert_eq!(terrain.height_at(10, 10), 20.0);
+    }
+
+    #[tokio::test]
+    async fn test_strokes_are_checked_and_undone() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let owner = UserId::new();
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, owner)));
+        let terraform = Terraforming::new(
+            TerraformConfig::default(),
+            Arc::clone(&scene),
+            Arc::new(OpenSimLoginService::new()),
+        );
+
+        let stranger = UserId::new();
+        assert!(matches!(
+            terraform.modify(stranger, raise(10.0, 10.0)).await,
+            Err(TerraformError::NoPermission(_))
+        ));
+        scene.write().await.parcels[0].flags |= PARCEL_FLAG_ALLOW_TERRAFORM;
+        assert!(terraform.modify(stranger, raise(10.0, 10.0)).await.unwrap() > 0);
+
+        // Both applications are one stroke
+        terraform.modify(owner, raise(50.0, 50.0)).await.unwrap();
+        terraform.modify(owner, raise(50.0, 50.0)).await.unwrap();
+        assert!(scene.read().await.terrain.height_at(50, 50) > 22.0);
+        terraform.undo(owner).await.unwrap();
+        assert_eq!(scene.read().await.terrain.height_at(50, 50), 21.0);
+        assert!(scene.read().await.terrain.height_at(10, 10) > 21.0);
+        assert_eq!(terraform.undo(owner).await, Err(TerraformError::NothingToUndo));
+        assert!(terraform.state.lock().unwrap().dirty_patches.contains(&(3, 3)));
+    }
+}
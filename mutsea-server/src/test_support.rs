@@ -0,0 +1,11 @@
+//! Fixtures shared by the service tests
+
+use mutsea_core::{SceneObject, UserId, Vector3};
+
+/// A plain one-metre box at `position`, owned by `owner_id`
+pub fn prim(local_id: u32, owner_id: UserId, position: Vector3) -> SceneObject {
+    SceneObject {
+        position,
+        ..SceneObject::new(local_id, format!("Prim {}", local_id), owner_id)
+    }
+}
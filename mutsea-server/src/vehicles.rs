@@ -0,0 +1,407 @@
+//! Scripted vehicles
+//!
+//! Prims given a vehicle type move by the vehicle model each simulation
+//! frame and carry the avatars sitting on them. The first avatar to sit
+//! drives, and its camera follows the vehicle with hints suited to the
+//! vehicle type unless a script has set its own. This server simulates one
+//! region, so a vehicle reaching the edge is held at the border with its
+//! riders rather than crossing into the neighbouring region.
+
+use async_trait::async_trait;
+use mutsea_core::config::VehiclesConfig;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{MutseaResult, ObjectId, Quaternion, UserId, Vector3};
+use mutsea_network::LLUDPServer;
+use mutsea_physics::vehicle::{self, Surface, Vehicle, VehicleBody, VehicleType};
+use mutsea_protocol::follow_cam::FollowCamParam;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::simulation::{FrameContext, SimulationSubsystem};
+
+#[derive(Debug, Error)]
+pub enum VehicleError {
+    #[error("Unknown object {0}")]
+    UnknownObject(ObjectId),
+    #[error("Object {0} is not a vehicle")]
+    NotAVehicle(ObjectId),
+    #[error("Unknown vehicle parameter {0}")]
+    UnknownParam(i32),
+}
+
+/// A vehicle and who is riding it
+#[derive(Debug, Clone, Serialize)]
+pub struct VehicleStatus {
+    pub id: ObjectId,
+    pub vehicle_type: VehicleType,
+    pub position: Vector3,
+    pub velocity: Vector3,
+    /// Riders, the driver first
+    pub riders: Vec<UserId>,
+    pub at_border: bool,
+}
+
+struct Rider {
+    agent: UserId,
+    /// Seat position relative to the vehicle
+    offset: Vector3,
+}
+
+struct Driven {
+    vehicle: Vehicle,
+    body: VehicleBody,
+    riders: Vec<Rider>,
+    /// Camera set by a script, replacing the vehicle type's hints
+    camera: Option<Vec<(FollowCamParam, f32)>>,
+    at_border: bool,
+}
+
+impl Driven {
+    fn camera(&self) -> Vec<(FollowCamParam, f32)> {
+        self.camera.clone().unwrap_or_else(|| follow_cam(self.vehicle.vehicle_type()))
+    }
+}
+
+/// Drives the vehicles of one region
+#[derive(Clone)]
+pub struct Vehicles {
+    config: VehiclesConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: Option<LLUDPServer>,
+    state: Arc<Mutex<HashMap<ObjectId, Driven>>>,
+}
+
+impl Vehicles {
+    pub fn new(config: VehiclesConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            config,
+            scene,
+            lludp_server: None,
+            state: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Move riders and send follow camera hints on `lludp_server`
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// llSetVehicleType: make prim `id` a vehicle of `vehicle_type`, or an
+    /// ordinary prim again with `VehicleType::None`
+    pub async fn set_type(&self, id: ObjectId, vehicle_type: VehicleType) -> Result<(), VehicleError> {
+        let body = {
+            let scene = self.scene.read().await;
+            let object = scene.objects.get(&id).ok_or(VehicleError::UnknownObject(id))?;
+            VehicleBody {
+                position: object.position,
+                rotation: object.rotation,
+                velocity: Vector3::ZERO,
+                angular_velocity: Vector3::ZERO,
+            }
+        };
+
+        if vehicle_type == VehicleType::None {
+            let removed = self.state.lock().unwrap().remove(&id);
+            for rider in removed.into_iter().flat_map(|driven| driven.riders) {
+                self.release_camera(rider.agent, id);
+            }
+            return Ok(());
+        }
+
+        let mut state = self.state.lock().unwrap();
+        match state.get_mut(&id) {
+            Some(driven) => driven.vehicle.set_type(vehicle_type),
+            None => {
+                state.insert(
+                    id,
+                    Driven {
+                        vehicle: Vehicle::new(vehicle_type),
+                        body,
+                        riders: Vec::new(),
+                        camera: None,
+                        at_border: false,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// llSetVehicleFloatParam
+    pub fn set_float_param(&self, id: ObjectId, param: i32, value: f32) -> Result<(), VehicleError> {
+        self.with_vehicle(id, |vehicle| vehicle.set_float_param(param, value))?
+            .then_some(())
+            .ok_or(VehicleError::UnknownParam(param))
+    }
+
+    /// llSetVehicleVectorParam
+    pub fn set_vector_param(&self, id: ObjectId, param: i32, value: Vector3) -> Result<(), VehicleError> {
+        self.with_vehicle(id, |vehicle| vehicle.set_vector_param(param, value))?
+            .then_some(())
+            .ok_or(VehicleError::UnknownParam(param))
+    }
+
+    /// llSetVehicleRotationParam
+    pub fn set_rotation_param(&self, id: ObjectId, param: i32, value: Quaternion) -> Result<(), VehicleError> {
+        self.with_vehicle(id, |vehicle| vehicle.set_rotation_param(param, value))?
+            .then_some(())
+            .ok_or(VehicleError::UnknownParam(param))
+    }
+
+    /// llSetVehicleFlags
+    pub fn set_flags(&self, id: ObjectId, flags: u32) -> Result<(), VehicleError> {
+        self.with_vehicle(id, |vehicle| vehicle.set_flags(flags))
+    }
+
+    /// llRemoveVehicleFlags
+    pub fn remove_flags(&self, id: ObjectId, flags: u32) -> Result<(), VehicleError> {
+        self.with_vehicle(id, |vehicle| vehicle.remove_flags(flags))
+    }
+
+    /// llSetCameraParams: replace the camera hints of vehicle `id`, sending
+    /// them to its driver
+    pub fn set_camera(&self, id: ObjectId, properties: Vec<(FollowCamParam, f32)>) -> Result<(), VehicleError> {
+        let driver = {
+            let mut state = self.state.lock().unwrap();
+            let driven = state.get_mut(&id).ok_or(VehicleError::NotAVehicle(id))?;
+            driven.camera = Some(properties.clone());
+            driven.riders.first().map(|rider| rider.agent)
+        };
+        if let Some(driver) = driver {
+            self.follow(driver, id, properties);
+        }
+        Ok(())
+    }
+
+    /// Seat `agent` on vehicle `id` at `offset`. The first to sit drives,
+    /// with its camera following the vehicle.
+    pub fn sit(&self, id: ObjectId, agent: UserId, offset: Vector3) -> Result<(), VehicleError> {
+        let camera = {
+            let mut state = self.state.lock().unwrap();
+            let driven = state.get_mut(&id).ok_or(VehicleError::NotAVehicle(id))?;
+            driven.riders.retain(|rider| rider.agent != agent);
+            driven.riders.push(Rider { agent, offset });
+            (driven.riders.len() == 1).then(|| driven.camera())
+        };
+        if let Some(camera) = camera {
+            self.follow(agent, id, camera);
+        }
+        Ok(())
+    }
+
+    /// Stand `agent` up from vehicle `id`; the next rider takes over driving
+    pub fn unsit(&self, id: ObjectId, agent: UserId) -> Result<(), VehicleError> {
+        let next_driver = {
+            let mut state = self.state.lock().unwrap();
+            let driven = state.get_mut(&id).ok_or(VehicleError::NotAVehicle(id))?;
+            let Some(seat) = driven.riders.iter().position(|rider| rider.agent == agent) else {
+                return Ok(());
+            };
+            driven.riders.remove(seat);
+            (seat == 0).then(|| driven.riders.first().map(|rider| (rider.agent, driven.camera()))).flatten()
+        };
+        self.release_camera(agent, id);
+        if let Some((driver, camera)) = next_driver {
+            self.follow(driver, id, camera);
+        }
+        Ok(())
+    }
+
+    pub fn vehicles(&self) -> Vec<VehicleStatus> {
+        self.state
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, driven)| VehicleStatus {
+                id: *id,
+                vehicle_type: driven.vehicle.vehicle_type(),
+                position: driven.body.position,
+                velocity: driven.body.velocity,
+                riders: driven.riders.iter().map(|rider| rider.agent).collect(),
+                at_border: driven.at_border,
+            })
+            .collect()
+    }
+
+    fn with_vehicle<T>(&self, id: ObjectId, f: impl FnOnce(&mut Vehicle) -> T) -> Result<T, VehicleError> {
+        let mut state = self.state.lock().unwrap();
+        let driven = state.get_mut(&id).ok_or(VehicleError::NotAVehicle(id))?;
+        Ok(f(&mut driven.vehicle))
+    }
+
+    /// Step every vehicle by `dt` seconds, moving its prim, and answer
+    /// where each rider now sits
+    async fn drive(&self, dt: f32) -> Vec<(UserId, Vector3)> {
+        let mut scene = self.scene.write().await;
+        let scene = &mut *scene;
+        let (size_x, size_y) = (scene.info.size_x as f32, scene.info.size_y as f32);
+        let mut state = self.state.lock().unwrap();
+
+        // Vehicles whose prim has been deleted stop being vehicles
+        state.retain(|id, _| scene.objects.contains_key(id));
+
+        let mut riders = Vec::new();
+        for (id, driven) in state.iter_mut() {
+            let object = scene.objects.get_mut(id).expect("vehicles without prims were dropped");
+            let position = driven.body.position;
+            let ground = scene.terrain.height_at(position.x.max(0.0) as u32, position.y.max(0.0) as u32);
+            let surface = Surface {
+                ground: ground + object.scale.z / 2.0,
+                water: self.config.water_height,
+            };
+            driven.vehicle.step(&mut driven.body, surface, dt);
+
+            let body = &mut driven.body;
+            let crossed = vehicle::crossed_border(body.position, size_x, size_y);
+            if let Some((dx, dy)) = crossed {
+                if !driven.at_border {
+                    info!(
+                        "Vehicle {} held at the border, no region at ({}, {})",
+                        id,
+                        scene.info.location_x as i64 + dx as i64,
+                        scene.info.location_y as i64 + dy as i64
+                    );
+                }
+                body.position.x = body.position.x.clamp(0.0, size_x - 0.01);
+                body.position.y = body.position.y.clamp(0.0, size_y - 0.01);
+                if dx != 0 {
+                    body.velocity.x = 0.0;
+                }
+                if dy != 0 {
+                    body.velocity.y = 0.0;
+                }
+            }
+            driven.at_border = crossed.is_some();
+
+            object.position = body.position;
+            object.rotation = body.rotation;
+            object.velocity = body.velocity;
+            object.angular_velocity = body.angular_velocity;
+            object.last_updated = chrono::Utc::now();
+            riders.extend(
+                driven
+                    .riders
+                    .iter()
+                    .map(|rider| (rider.agent, body.position + body.rotation.rotate(rider.offset))),
+            );
+        }
+        riders
+    }
+
+    fn follow(&self, agent: UserId, id: ObjectId, camera: Vec<(FollowCamParam, f32)>) {
+        let Some(lludp_server) = self.lludp_server.clone() else {
+            return;
+        };
+        if camera.is_empty() {
+            return;
+        }
+        tokio::spawn(async move {
+            if let Err(e) = lludp_server.send_follow_cam(agent, id.0, &camera).await {
+                warn!("Could not set the camera of {} to follow vehicle {}: {}", agent, id, e);
+            }
+        });
+    }
+
+    fn release_camera(&self, agent: UserId, id: ObjectId) {
+        let Some(lludp_server) = self.lludp_server.clone() else {
+            return;
+        };
+        tokio::spawn(async move {
+            if let Err(e) = lludp_server.clear_follow_cam(agent, id.0).await {
+                warn!("Could not release the camera of {} from vehicle {}: {}", agent, id, e);
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl SimulationSubsystem for Vehicles {
+    async fn tick(&mut self, frame: &FrameContext) -> MutseaResult<()> {
+        let riders = self.drive(frame.dt.as_secs_f32()).await;
+        if let Some(lludp_server) = &self.lludp_server {
+            for (agent, position) in riders {
+                lludp_server.set_agent_position(agent, position).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a driver's camera follows each type of vehicle
+fn follow_cam(vehicle_type: VehicleType) -> Vec<(FollowCamParam, f32)> {
+    // Distance, pitch, behindness angle and lag
+    let (distance, pitch, behindness, lag) = match vehicle_type {
+        VehicleType::None => return Vec::new(),
+        VehicleType::Sled | VehicleType::Car => (6.0, 10.0, 10.0, 0.1),
+        VehicleType::Boat => (8.0, 15.0, 20.0, 0.3),
+        VehicleType::Airplane => (12.0, 5.0, 5.0, 0.2),
+        VehicleType::Balloon => (10.0, 30.0, 180.0, 1.0),
+    };
+    vec![
+        (FollowCamParam::Active, 1.0),
+        (FollowCamParam::Distance, distance),
+        (FollowCamParam::Pitch, pitch),
+        (FollowCamParam::BehindnessAngle, behindness),
+        (FollowCamParam::BehindnessLag, lag),
+        (FollowCamParam::PositionLag, lag),
+        (FollowCamParam::FocusLag, lag / 2.0),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::{RegionInfo, SceneObject};
+    use mutsea_physics::vehicle::params;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_vehicles_carry_riders_and_stop_at_the_border() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let id = ObjectId::new();
+        scene.write().await.add_object(SceneObject {
+            id,
+            position: Vector3::new(240.0, 128.0, 21.5),
+            ..SceneObject::new(1, "Car", UserId::new())
+        });
+
+        let mut vehicles = Vehicles::new(VehiclesConfig::default(), Arc::clone(&scene));
+        assert!(matches!(
+            vehicles.set_type(ObjectId::new(), VehicleType::Car).await,
+            Err(VehicleError::UnknownObject(_))
+        ));
+        vehicles.set_type(id, VehicleType::Car).await.unwrap();
+        vehicles.set_vector_param(id, params::LINEAR_MOTOR_DIRECTION, Vector3::new(20.0, 0.0, 0.0)).unwrap();
+        assert!(matches!(vehicles.set_float_param(id, 99, 1.0), Err(VehicleError::UnknownParam(99))));
+
+        let driver = UserId::new();
+        vehicles.sit(id, driver, Vector3::new(0.0, 0.0, 1.0)).unwrap();
+        let context = FrameContext {
+            frame: 0,
+            dt: Duration::from_millis(50),
+            degrade_level: 0,
+        };
+        for _ in 0..60 {
+            vehicles.tick(&context).await.unwrap();
+        }
+
+        let status = &vehicles.vehicles()[0];
+        assert_eq!(status.riders, vec![driver]);
+        assert!(status.at_border);
+        assert!(status.position.x < 256.0 && status.position.x > 255.0);
+        assert_eq!(scene.read().await.objects[&id].position, status.position);
+
+        let riders = vehicles.drive(0.05).await;
+        assert_eq!(riders[0].0, driver);
+        assert!((riders[0].1.z - status.position.z - 1.0).abs() < 0.1);
+
+        vehicles.set_type(id, VehicleType::None).await.unwrap();
+        assert!(vehicles.vehicles().is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+//! Who prims are shown to
+//!
+//! The LLUDP server sends HUDs only to their wearer and hidden prims only
+//! to their owner. This service hides and shows prims for the admin API,
+//! and removes temporary-on-rez prims such as bullets once they are
+//! `temp_on_rez_secs` old, telling viewers they are gone.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mutsea_core::config::VisibilityConfig;
+use mutsea_core::scene::RegionScene;
+use mutsea_core::{MutseaResult, ObjectId};
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::constants::prim_flags::TEMPORARY_ON_REZ;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+
+use crate::simulation::{FrameContext, SimulationSubsystem};
+
+/// How often the region is swept for expired temporary prims
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum VisibilityError {
+    #[error("Unknown object {0}")]
+    UnknownObject(ObjectId),
+    #[error("Prims can't be hidden without the LLUDP server")]
+    NoNetwork,
+    #[error("Failed to update viewers: {0}")]
+    Network(String),
+}
+
+/// Hidden and temporary prims of one region
+#[derive(Clone)]
+pub struct ObjectVisibility {
+    config: VisibilityConfig,
+    scene: Arc<RwLock<RegionScene>>,
+    lludp_server: Option<LLUDPServer>,
+    last_sweep: Option<Instant>,
+}
+
+impl ObjectVisibility {
+    pub fn new(config: VisibilityConfig, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            config,
+            scene,
+            lludp_server: None,
+            last_sweep: None,
+        }
+    }
+
+    /// Hide prims from and remove them for the agents connected to
+    /// `lludp_server`
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Whether `object` is hidden from everyone but its owner
+    pub async fn is_hidden(&self, object: ObjectId) -> Result<bool, VisibilityError> {
+        let lludp_server = self.lludp_server.as_ref().ok_or(VisibilityError::NoNetwork)?;
+        let local_id = self.local_id(object).await?;
+        Ok(lludp_server.is_hidden_from_others(local_id).await)
+    }
+
+    /// Hide `object` from everyone but its owner, or show it to everyone
+    /// again
+    pub async fn set_hidden(&self, object: ObjectId, hidden: bool) -> Result<(), VisibilityError> {
+        let lludp_server = self.lludp_server.as_ref().ok_or(VisibilityError::NoNetwork)?;
+        let prim = self
+            .scene
+            .read()
+            .await
+            .objects
+            .get(&object)
+            .cloned()
+            .ok_or(VisibilityError::UnknownObject(object))?;
+        lludp_server
+            .set_hidden_from_others(&prim, hidden)
+            .await
+            .map_err(|e| VisibilityError::Network(e.to_string()))?;
+        debug!("Object {} is {}", object, if hidden { "hidden" } else { "shown" });
+        Ok(())
+    }
+
+    /// Remove the temporary prims that are too old at `now`, returning them
+    pub async fn remove_expired(&self, now: DateTime<Utc>) -> Vec<ObjectId> {
+        let lifetime = chrono::Duration::seconds(self.config.temp_on_rez_secs as i64);
+        let expired: Vec<(ObjectId, u32)> = {
+            let mut scene = self.scene.write().await;
+            let expired: Vec<ObjectId> = scene
+                .objects
+                .values()
+                .filter(|object| object.flags & TEMPORARY_ON_REZ != 0 && now - object.created >= lifetime)
+                .map(|object| object.id)
+                .collect();
+            expired
+                .into_iter()
+                .filter_map(|id| scene.objects.remove(&id))
+                .map(|object| (object.id, object.local_id))
+                .collect()
+        };
+        if expired.is_empty() {
+            return Vec::new();
+        }
+
+        debug!("Removed {} temporary prims", expired.len());
+        if let Some(lludp_server) = &self.lludp_server {
+            let local_ids: Vec<u32> = expired.iter().map(|(_, local_id)| *local_id).collect();
+            if let Err(e) = lludp_server.send_kill_objects(&local_ids).await {
+                warn!(
+                    "Failed to remove {} temporary prims from viewers: {}",
+                    local_ids.len(),
+                    e
+                );
+            }
+        }
+        expired.into_iter().map(|(id, _)| id).collect()
+    }
+
+    async fn local_id(&self, object: ObjectId) -> Result<u32, VisibilityError> {
+        self.scene
+            .read()
+            .await
+            .objects
+            .get(&object)
+            .map(|object| object.local_id)
+            .ok_or(VisibilityError::UnknownObject(object))
+    }
+}
+
+#[async_trait]
+impl SimulationSubsystem for ObjectVisibility {
+    async fn tick(&mut self, _frame: &FrameContext) -> MutseaResult<()> {
+        if self.last_sweep.is_some_and(|swept| swept.elapsed() < SWEEP_INTERVAL) {
+            return Ok(());
+        }
+        self.last_sweep = Some(Instant::now());
+        self.remove_expired(Utc::now()).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use mutsea_core::{RegionInfo, SceneObject, UserId, Vector3};
+
+    fn prim(local_id: u32, flags: u32, created: DateTime<Utc>) -> SceneObject {
+        SceneObject {
+            scale: Vector3::new(0.5, 0.5, 0.5),
+            flags,
+            created,
+            last_updated: created,
+            ..test_support::prim(local_id, UserId::new(), Vector3::new(128.0, 128.0, 25.0))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expired_temporary_prims_are_removed() {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        let scene = Arc::new(RwLock::new(RegionScene::new(info, UserId::new())));
+        let now = Utc::now();
+        let old = now - chrono::Duration::seconds(90);
+        let (expired, fresh, permanent) = (
+            prim(1, TEMPORARY_ON_REZ, old),
+            prim(2, TEMPORARY_ON_REZ, now),
+            prim(3, 0, old),
+        );
+        let expired_id = expired.id;
+        for object in [expired, fresh, permanent] {
+            scene.write().await.add_object(object);
+        }
+        let visibility = ObjectVisibility::new(VisibilityConfig::default(), Arc::clone(&scene));
+
+        assert_eq!(visibility.remove_expired(now).await, vec![expired_id]);
+        assert_eq!(scene.read().await.objects.len(), 2);
+        assert!(visibility.remove_expired(now).await.is_empty());
+        assert!(matches!(
+            visibility.set_hidden(expired_id, true).await,
+            Err(VisibilityError::NoNetwork)
+        ));
+    }
+}
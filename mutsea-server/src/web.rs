@@ -0,0 +1,170 @@
+//! Front page and viewer login splash page
+//!
+//! Pages are rendered from simple `{{name}}` templates. The built-in
+//! templates can be replaced by pointing `web.home_template` or
+//! `web.splash_template` in `MutseaConfig` at a file on disk.
+
+use mutsea_core::{config::MutseaConfig, MutseaError, MutseaResult};
+use std::collections::HashMap;
+use tracing::info;
+
+const DEFAULT_HOME_TEMPLATE: &str = include_str!("../templates/home.html");
+const DEFAULT_SPLASH_TEMPLATE: &str = include_str!("../templates/splash.html");
+
+/// Loaded page templates
+#[derive(Debug, Clone)]
+pub struct WebPages {
+    home: String,
+    splash: String,
+}
+
+impl WebPages {
+    /// Load templates from the configured paths, falling back to the
+    /// built-in ones
+    pub fn load(config: &MutseaConfig) -> MutseaResult<Self> {
+        Ok(Self {
+            home: load_template(config.web.home_template.as_deref(), DEFAULT_HOME_TEMPLATE)?,
+            splash: load_template(config.web.splash_template.as_deref(), DEFAULT_SPLASH_TEMPLATE)?,
+        })
+    }
+
+    /// Render the front page
+    pub fn render_home(&self, context: &PageContext) -> String {
+        render(&self.home, &context.variables())
+    }
+
+    /// Render the viewer login splash page
+    pub fn render_splash(&self, context: &PageContext) -> String {
+        render(&self.splash, &context.variables())
+    }
+}
+
+impl Default for WebPages {
+    fn default() -> Self {
+        Self {
+            home: DEFAULT_HOME_TEMPLATE.to_string(),
+            splash: DEFAULT_SPLASH_TEMPLATE.to_string(),
+        }
+    }
+}
+
+/// Values available to page templates
+#[derive(Debug, Clone)]
+pub struct PageContext {
+    pub grid_name: String,
+    pub grid_nick: String,
+    pub login_uri: String,
+    pub register_url: String,
+    pub welcome_message: String,
+    pub online_count: usize,
+}
+
+impl PageContext {
+    /// Build a context from configuration and the current online count
+    pub fn new(config: &MutseaConfig, online_count: usize) -> Self {
+        Self {
+            grid_name: config.opensim.grid_name.clone(),
+            grid_nick: config.opensim.grid_nick.clone(),
+            login_uri: config.opensim.login_uri.clone(),
+            register_url: config
+                .web
+                .register_url
+                .clone()
+                .unwrap_or_else(|| "/register".to_string()),
+            welcome_message: config.web.welcome_message.clone(),
+            online_count,
+        }
+    }
+
+    fn variables(&self) -> HashMap<&'static str, String> {
+        HashMap::from([
+            ("grid_name", self.grid_name.clone()),
+            ("grid_nick", self.grid_nick.clone()),
+            ("login_uri", self.login_uri.clone()),
+            ("register_url", self.register_url.clone()),
+            ("welcome_message", self.welcome_message.clone()),
+            ("online_count", self.online_count.to_string()),
+            ("version", mutsea_core::VERSION.to_string()),
+        ])
+    }
+}
+
+fn load_template(path: Option<&str>, default: &str) -> MutseaResult<String> {
+    match path {
+        Some(path) => {
+            info!("📄 Loading web template from {}", path);
+            std::fs::read_to_string(path)
+                .map_err(|e| MutseaError::InvalidConfiguration(format!("Failed to read template {}: {}", path, e)))
+        }
+        None => Ok(default.to_string()),
+    }
+}
+
+/// Replace `{{name}}` placeholders with HTML-escaped values. Unknown
+/// placeholders are left untouched so typos are visible on the page.
+fn render(template: &str, variables: &HashMap<&'static str, String>) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                match variables.get(name) {
+                    Some(value) => output.push_str(&html_escape(value)),
+                    None => output.push_str(&rest[start..start + 2 + end + 2]),
+                }
+                rest = &after[end + 2..];
+            }
+            None => {
+                output.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    output.push_str(rest);
+
+    output
+}
+
+/// `value` made safe to put in HTML text or a quoted attribute
+pub fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_substitutes_and_escapes() {
+        let variables = HashMap::from([("grid_name", "<Mutsea & Co>".to_string())]);
+        let html = render("<h1>{{ grid_name }}</h1>{{unknown}}", &variables);
+        assert_eq!(html, "<h1>&lt;Mutsea &amp; Co&gt;</h1>{{unknown}}");
+    }
+
+    #[test]
+    fn test_default_pages_show_grid_details() {
+        let mut config = MutseaConfig::default();
+        config.opensim.grid_name = "Test Grid".to_string();
+        config.web.register_url = Some("https://example.com/join".to_string());
+
+        let pages = WebPages::load(&config).unwrap();
+        let context = PageContext::new(&config, 42);
+
+        let home = pages.render_home(&context);
+        assert!(home.contains("Test Grid"));
+        assert!(home.contains("42"));
+        assert!(home.contains("https://example.com/join"));
+
+        let splash = pages.render_splash(&context);
+        assert!(splash.contains("42 online now"));
+        assert!(!splash.contains("{{"));
+    }
+}
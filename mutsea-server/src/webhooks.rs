@@ -0,0 +1,541 @@
+//! Webhooks
+//!
+//! Key events are posted as JSON to the configured endpoints: agents
+//! logging in and out, the region starting and stopping, abuse reports and
+//! economy transactions. A request is signed with HMAC-SHA256 of its
+//! timestamp and body under the endpoint's secret, so receivers can tell
+//! it came from this grid. Failed deliveries wait in an outbox and are
+//! tried again with exponential backoff; the outcome of recent deliveries
+//! is kept for the admin API.
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use mutsea_core::config::{WebhookEndpointConfig, WebhooksConfig};
+use mutsea_core::events::NetworkEventData;
+use mutsea_core::UserId;
+use mutsea_database::economy::LedgerTransaction;
+use mutsea_network::LLUDPServer;
+use mutsea_protocol::login::OpenSimLoginService;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::economy::Economy;
+
+pub const EVENT_AGENT_LOGIN: &str = "agent.login";
+pub const EVENT_AGENT_LOGOUT: &str = "agent.logout";
+pub const EVENT_REGION_STARTED: &str = "region.started";
+pub const EVENT_REGION_STOPPING: &str = "region.stopping";
+pub const EVENT_ABUSE_REPORTED: &str = "abuse.reported";
+pub const EVENT_ECONOMY_TRANSACTION: &str = "economy.transaction";
+/// Sent from the admin API to check an endpoint, whatever it subscribes to
+pub const EVENT_TEST: &str = "webhook.test";
+
+/// Every event an endpoint can subscribe to
+pub const EVENTS: [&str; 6] = [
+    EVENT_AGENT_LOGIN,
+    EVENT_AGENT_LOGOUT,
+    EVENT_REGION_STARTED,
+    EVENT_REGION_STOPPING,
+    EVENT_ABUSE_REPORTED,
+    EVENT_ECONOMY_TRANSACTION,
+];
+
+pub const HEADER_EVENT: &str = "X-Mutsea-Event";
+pub const HEADER_DELIVERY: &str = "X-Mutsea-Delivery";
+pub const HEADER_TIMESTAMP: &str = "X-Mutsea-Timestamp";
+/// `sha256=` and the hex HMAC of the timestamp, a `.` and the body
+pub const HEADER_SIGNATURE: &str = "X-Mutsea-Signature";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Waiting for its first or next attempt
+    Pending,
+    Delivered,
+    /// Given up on
+    Failed,
+}
+
+/// One event posted to one endpoint
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Delivery {
+    pub id: Uuid,
+    pub event_id: Uuid,
+    pub event: String,
+    pub url: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// HTTP status of the last attempt, if it got an answer
+    pub response_status: Option<u16>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+}
+
+/// A delivery waiting in the outbox
+struct Queued {
+    delivery_id: Uuid,
+    endpoint: WebhookEndpointConfig,
+    event: String,
+    body: String,
+    attempts: u32,
+    due: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    outbox: Vec<Queued>,
+    /// Recent deliveries, newest last
+    log: VecDeque<Delivery>,
+}
+
+impl State {
+    fn delivery(&mut self, id: Uuid) -> Option<&mut Delivery> {
+        self.log.iter_mut().rev().find(|delivery| delivery.id == id)
+    }
+}
+
+/// Posts events to the configured endpoints. Clones share the outbox.
+#[derive(Clone)]
+pub struct Webhooks {
+    config: WebhooksConfig,
+    client: reqwest::Client,
+    lludp_server: Option<LLUDPServer>,
+    login_service: Option<Arc<OpenSimLoginService>>,
+    economy: Option<Economy>,
+    state: Arc<Mutex<State>>,
+    wake: Arc<Notify>,
+}
+
+impl Webhooks {
+    pub fn new(config: WebhooksConfig) -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .user_agent(concat!("Mutsea/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        for endpoint in &config.endpoints {
+            for event in endpoint.events.iter().filter(|event| !EVENTS.contains(&event.as_str())) {
+                warn!("Webhook {} subscribes to unknown event {}", endpoint.url, event);
+            }
+        }
+        Ok(Self {
+            config,
+            client,
+            lludp_server: None,
+            login_service: None,
+            economy: None,
+            state: Arc::new(Mutex::new(State::default())),
+            wake: Arc::new(Notify::new()),
+        })
+    }
+
+    /// Post logins, logouts and abuse reports on `lludp_server`'s circuits
+    pub fn with_lludp(mut self, lludp_server: LLUDPServer) -> Self {
+        self.lludp_server = Some(lludp_server);
+        self
+    }
+
+    /// Name the agents in events
+    pub fn with_login_service(mut self, login_service: Arc<OpenSimLoginService>) -> Self {
+        self.login_service = Some(login_service);
+        self
+    }
+
+    /// Post the sales and payments made in `economy`
+    pub fn with_economy(mut self, economy: Economy) -> Self {
+        self.economy = Some(economy);
+        self
+    }
+
+    pub fn spawn(&self) {
+        let webhooks = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let wait = webhooks.next_due(Instant::now());
+                match wait {
+                    Some(wait) if wait.is_zero() => {}
+                    Some(wait) => {
+                        tokio::select! {
+                            _ = tokio::time::sleep(wait) => {}
+                            _ = webhooks.wake.notified() => {}
+                        }
+                    }
+                    None => webhooks.wake.notified().await,
+                }
+                webhooks.deliver_due(Instant::now()).await;
+            }
+        });
+
+        if let Some(lludp_server) = &self.lludp_server {
+            let webhooks = self.clone();
+            let mut events = lludp_server.subscribe_events();
+            tokio::spawn(async move {
+                loop {
+                    match events.recv().await {
+                        Ok(event) => webhooks.follow(&event.event_data),
+                        Err(RecvError::Lagged(missed)) => warn!("Webhooks missed {} events", missed),
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            });
+        }
+
+        if let Some(economy) = &self.economy {
+            let webhooks = self.clone();
+            let mut transactions = economy.subscribe_transactions();
+            tokio::spawn(async move {
+                loop {
+                    match transactions.recv().await {
+                        Ok(transaction) => {
+                            webhooks.fire(EVENT_ECONOMY_TRANSACTION, transaction_data(&transaction));
+                        }
+                        Err(RecvError::Lagged(missed)) => warn!("Webhooks missed {} transactions", missed),
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            });
+        }
+    }
+
+    /// Post the events of note in `event`
+    fn follow(&self, event: &NetworkEventData) {
+        match event {
+            NetworkEventData::AgentLoggedIn {
+                agent_id, session_id, ..
+            } => {
+                self.fire(
+                    EVENT_AGENT_LOGIN,
+                    json!({
+                        "agent_id": agent_id,
+                        "name": self.name(*agent_id),
+                        "session_id": session_id,
+                    }),
+                );
+            }
+            NetworkEventData::AgentLoggedOut { agent_id, reason, .. } => {
+                self.fire(
+                    EVENT_AGENT_LOGOUT,
+                    json!({
+                        "agent_id": agent_id,
+                        "name": self.name(*agent_id),
+                        "reason": reason,
+                    }),
+                );
+            }
+            NetworkEventData::AbuseReported {
+                agent_id,
+                abuser_id,
+                object_id,
+                category,
+                position,
+                summary,
+                details,
+                screenshot_id,
+                ..
+            } => {
+                self.fire(
+                    EVENT_ABUSE_REPORTED,
+                    json!({
+                        "reporter_id": agent_id,
+                        "reporter_name": self.name(*agent_id),
+                        "abuser_id": abuser_id,
+                        "abuser_name": self.name(*abuser_id),
+                        "object_id": object_id,
+                        "category": category,
+                        "position": [position.x, position.y, position.z],
+                        "summary": summary,
+                        "details": details,
+                        "screenshot_id": screenshot_id,
+                    }),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn name(&self, agent: UserId) -> Option<String> {
+        self.login_service.as_ref()?.get_user_name(&agent)
+    }
+
+    /// Queue `event` with `data` for every endpoint subscribed to it.
+    /// Returns the ID of the event, shared by its deliveries.
+    pub fn fire(&self, event: &str, data: Value) -> Uuid {
+        let endpoints: Vec<WebhookEndpointConfig> = self
+            .config
+            .endpoints
+            .iter()
+            .filter(|endpoint| {
+                event == EVENT_TEST || endpoint.events.is_empty() || endpoint.events.iter().any(|e| e == event)
+            })
+            .cloned()
+            .collect();
+        self.queue(event, data, endpoints)
+    }
+
+    fn queue(&self, event: &str, data: Value, endpoints: Vec<WebhookEndpointConfig>) -> Uuid {
+        let event_id = Uuid::new_v4();
+        if !self.config.enabled || endpoints.is_empty() {
+            return event_id;
+        }
+        let now = Utc::now();
+        let body = json!({
+            "id": event_id,
+            "event": event,
+            "timestamp": now,
+            "data": data,
+        })
+        .to_string();
+
+        let mut state = self.state.lock().unwrap();
+        for endpoint in endpoints {
+            let delivery_id = Uuid::new_v4();
+            state.log.push_back(Delivery {
+                id: delivery_id,
+                event_id,
+                event: event.to_string(),
+                url: endpoint.url.clone(),
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                response_status: None,
+                error: None,
+                created_at: now,
+                last_attempt_at: None,
+                next_attempt_at: Some(now),
+            });
+            state.outbox.push(Queued {
+                delivery_id,
+                endpoint,
+                event: event.to_string(),
+                body: body.clone(),
+                attempts: 0,
+                due: Instant::now(),
+            });
+        }
+        while state.log.len() > self.config.delivery_log_size {
+            state.log.pop_front();
+        }
+        drop(state);
+        debug!("Queued webhook {} {}", event, event_id);
+        self.wake.notify_one();
+        event_id
+    }
+
+    /// How long until the next delivery is due, if any is queued
+    fn next_due(&self, now: Instant) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        state
+            .outbox
+            .iter()
+            .map(|queued| queued.due.saturating_duration_since(now))
+            .min()
+    }
+
+    /// Attempt every delivery due by `now`
+    async fn deliver_due(&self, now: Instant) {
+        let due: Vec<Queued> = {
+            let mut state = self.state.lock().unwrap();
+            let (due, later) = std::mem::take(&mut state.outbox)
+                .into_iter()
+                .partition(|queued| queued.due <= now);
+            state.outbox = later;
+            due
+        };
+        futures::future::join_all(due.into_iter().map(|queued| self.attempt(queued))).await;
+    }
+
+    /// Attempt every queued delivery once now, as before shutting down
+    pub async fn flush(&self) {
+        self.deliver_due(Instant::now() + Duration::from_secs(self.config.max_backoff_secs))
+            .await;
+    }
+
+    /// Post `queued` and record how it went, queueing it again after a
+    /// failure unless it has used up its attempts
+    async fn attempt(&self, mut queued: Queued) {
+        queued.attempts += 1;
+        let result = self.post(&queued).await;
+        let now = Utc::now();
+
+        let mut state = self.state.lock().unwrap();
+        let retry = match &result {
+            Ok(status) if (200..300).contains(status) => None,
+            // The receiver turned the request down, and will again
+            Ok(status) if (400..500).contains(status) && !matches!(status, 408 | 429) => None,
+            _ if queued.attempts >= self.config.max_attempts => None,
+            _ => Some(self.backoff(queued.attempts)),
+        };
+        if let Some(delivery) = state.delivery(queued.delivery_id) {
+            delivery.attempts = queued.attempts;
+            delivery.last_attempt_at = Some(now);
+            (delivery.response_status, delivery.error) = match &result {
+                Ok(status) => (Some(*status), None),
+                Err(e) => (None, Some(e.clone())),
+            };
+            delivery.status = match (&result, retry) {
+                (Ok(status), _) if (200..300).contains(status) => DeliveryStatus::Delivered,
+                (_, Some(_)) => DeliveryStatus::Pending,
+                (_, None) => DeliveryStatus::Failed,
+            };
+            delivery.next_attempt_at = retry
+                .and_then(|wait| chrono::Duration::from_std(wait).ok())
+                .map(|wait| now + wait);
+        }
+
+        match retry {
+            Some(wait) => {
+                debug!(
+                    "Webhook {} to {} failed, attempt {} again in {}s",
+                    queued.event,
+                    queued.endpoint.url,
+                    queued.attempts + 1,
+                    wait.as_secs()
+                );
+                queued.due = Instant::now() + wait;
+                state.outbox.push(queued);
+            }
+            None if !matches!(result, Ok(status) if (200..300).contains(&status)) => {
+                warn!(
+                    "Giving up on webhook {} to {} after {} attempts",
+                    queued.event, queued.endpoint.url, queued.attempts
+                );
+            }
+            None => {}
+        }
+    }
+
+    /// Post `queued`, returning the response status
+    async fn post(&self, queued: &Queued) -> Result<u16, String> {
+        let timestamp = Utc::now().timestamp().to_string();
+        let mut request = self
+            .client
+            .post(&queued.endpoint.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(HEADER_EVENT, &queued.event)
+            .header(HEADER_DELIVERY, queued.delivery_id.to_string())
+            .header(HEADER_TIMESTAMP, &timestamp);
+        if !queued.endpoint.secret.is_empty() {
+            request = request.header(
+                HEADER_SIGNATURE,
+                signature(&queued.endpoint.secret, &timestamp, &queued.body),
+            );
+        }
+        request
+            .body(queued.body.clone())
+            .send()
+            .await
+            .map(|response| response.status().as_u16())
+            .map_err(|e| e.to_string())
+    }
+
+    /// The wait before attempt `attempts + 1`
+    fn backoff(&self, attempts: u32) -> Duration {
+        let secs = self
+            .config
+            .initial_backoff_secs
+            .saturating_mul(1 << attempts.saturating_sub(1).min(30))
+            .min(self.config.max_backoff_secs);
+        Duration::from_secs(secs)
+    }
+
+    /// Recent deliveries, newest first
+    pub fn deliveries(&self, limit: usize) -> Vec<Delivery> {
+        let state = self.state.lock().unwrap();
+        state.log.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+/// The [`HEADER_SIGNATURE`] of `body` sent at `timestamp`
+pub fn signature(secret: &str, timestamp: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256={}", digest)
+}
+
+fn transaction_data(transaction: &LedgerTransaction) -> Value {
+    json!({
+        "transaction_id": transaction.id,
+        "source_id": transaction.source_id,
+        "destination_id": transaction.destination_id,
+        "amount": transaction.amount,
+        "transaction_type": transaction.transaction_type,
+        "object_id": transaction.object_id,
+        "description": transaction.description,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(url: &str, events: &[&str]) -> WebhookEndpointConfig {
+        WebhookEndpointConfig {
+            url: url.to_string(),
+            secret: "Jefe".to_string(),
+            events: events.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_signature() {
+        assert_eq!(
+            signature("Jefe", "1700000000", r#"{"event":"webhook.test"}"#),
+            "sha256=f0347cd058b62a6da85150d19582c706d7a55a97fe1dc3c4737d200404d76e7b"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_deliveries_back_off_then_give_up() {
+        // Nothing listens on the discard port
+        let webhooks = Webhooks::new(WebhooksConfig {
+            max_attempts: 2,
+            initial_backoff_secs: 10,
+            max_backoff_secs: 15,
+            endpoints: vec![
+                endpoint("http://127.0.0.1:9/billing", &[EVENT_ECONOMY_TRANSACTION]),
+                endpoint("http://127.0.0.1:9/all", &[]),
+            ],
+            ..WebhooksConfig::default()
+        })
+        .unwrap();
+        assert_eq!(webhooks.backoff(1), Duration::from_secs(10));
+        assert_eq!(webhooks.backoff(3), Duration::from_secs(15));
+
+        webhooks.fire(EVENT_AGENT_LOGIN, json!({ "agent_id": UserId::new() }));
+        let deliveries = webhooks.deliveries(10);
+        assert_eq!(deliveries.len(), 1);
+        assert!(deliveries[0].url.ends_with("/all"));
+        assert_eq!(webhooks.next_due(Instant::now()), Some(Duration::ZERO));
+
+        webhooks.deliver_due(Instant::now()).await;
+        let delivery = &webhooks.deliveries(1)[0];
+        assert_eq!((delivery.status, delivery.attempts), (DeliveryStatus::Pending, 1));
+        assert!(delivery.error.is_some() && delivery.next_attempt_at.is_some());
+        assert!(webhooks.next_due(Instant::now()).unwrap() > Duration::from_secs(5));
+
+        webhooks.flush().await;
+        let delivery = &webhooks.deliveries(1)[0];
+        assert_eq!((delivery.status, delivery.attempts), (DeliveryStatus::Failed, 2));
+        assert_eq!(webhooks.next_due(Instant::now()), None);
+
+        // A test reaches every endpoint
+        webhooks.fire(EVENT_TEST, json!({}));
+        assert_eq!(webhooks.deliveries(10).len(), 3);
+    }
+}
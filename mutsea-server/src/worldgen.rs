@@ -0,0 +1,519 @@
+//! AI world generation
+//!
+//! A generation request names a biome, a theme and a density. The
+//! configured [`AIProvider`] answers with a layout plan whose terrain edits
+//! and prims are applied to the region scene as one batch. Each batch is
+//! recorded as a `WorldGeneration` AI decision, so its outcome can be
+//! reported and scored later, and remembers what it changed so it can be
+//! undone.
+
+use crate::ai::context_hash;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mutsea_core::config::WorldGenerationConfig;
+use mutsea_core::scene::{RegionScene, Terrain, DEFAULT_TERRAIN_HEIGHT};
+use mutsea_core::{
+    AIDecisionRecord, AIDecisionRecorder, AIProvider, GenerationRequest, LayoutPlan, MutseaError, MutseaResult,
+    ObjectId, ObjectShape, PlannedObject, Quaternion, SceneObject, TerrainEdit, TerrainEditKind, UserId, Vector3,
+};
+use mutsea_database::models::{AIDecisionType, GenerationScope};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::f32::consts::{PI, TAU};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Share of the region under which a generation counts as local
+const LOCAL_AREA_FRACTION: f32 = 0.25;
+
+/// Changes made by one generation
+struct GenerationBatch {
+    id: Uuid,
+    request: GenerationRequest,
+    provider: String,
+    created_at: DateTime<Utc>,
+    objects: Vec<ObjectId>,
+    /// `(x, y, before, after)` of every terrain cell changed
+    terrain: Vec<(u32, u32, f32, f32)>,
+}
+
+impl GenerationBatch {
+    fn summary(&self) -> BatchSummary {
+        BatchSummary {
+            id: self.id,
+            region_id: self.request.region_id.0,
+            biome: self.request.biome.clone(),
+            theme: self.request.theme.clone(),
+            provider: self.provider.clone(),
+            created_at: self.created_at,
+            objects: self.objects.len(),
+            terrain_cells: self.terrain.len(),
+        }
+    }
+}
+
+/// What a generation batch changed; its ID is also its AI decision's ID
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BatchSummary {
+    pub id: Uuid,
+    pub region_id: Uuid,
+    pub biome: String,
+    pub theme: String,
+    pub provider: String,
+    pub created_at: DateTime<Utc>,
+    pub objects: usize,
+    pub terrain_cells: usize,
+}
+
+/// Generates content into one region scene
+pub struct WorldGenerator {
+    config: WorldGenerationConfig,
+    provider: Arc<dyn AIProvider>,
+    scene: Arc<RwLock<RegionScene>>,
+    recorder: Option<Arc<dyn AIDecisionRecorder>>,
+    /// Undoable batches, oldest first
+    batches: Mutex<VecDeque<GenerationBatch>>,
+}
+
+impl WorldGenerator {
+    pub fn new(config: WorldGenerationConfig, provider: Arc<dyn AIProvider>, scene: Arc<RwLock<RegionScene>>) -> Self {
+        Self {
+            config,
+            provider,
+            scene,
+            recorder: None,
+            batches: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Record each generation as an AI decision through `recorder`
+    pub fn with_recorder(mut self, recorder: Arc<dyn AIDecisionRecorder>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Batches that can still be undone, oldest first
+    pub fn batches(&self) -> Vec<BatchSummary> {
+        self.batches.lock().unwrap().iter().map(GenerationBatch::summary).collect()
+    }
+
+    /// Plan `request` with the provider and apply the plan to the scene
+    pub async fn generate(&self, request: GenerationRequest) -> MutseaResult<BatchSummary> {
+        request.validate().map_err(MutseaError::Generic)?;
+        let (region_id, region_area) = {
+            let scene = self.scene.read().await;
+            (scene.info.region_id, (scene.info.size_x * scene.info.size_y) as f32)
+        };
+        if request.region_id != region_id {
+            return Err(MutseaError::RegionNotFound(request.region_id.0.to_string()));
+        }
+        let scope = match request.area {
+            Some((_, _, radius)) if PI * radius * radius < LOCAL_AREA_FRACTION * region_area => GenerationScope::Local,
+            _ => GenerationScope::Regional,
+        };
+
+        let started = Instant::now();
+        let mut plan = self.provider.plan_layout(&request).await?;
+        let elapsed = started.elapsed();
+        plan.objects.truncate(self.config.max_objects);
+
+        let batch = apply(&mut *self.scene.write().await, &request, &plan, self.provider.name());
+        info!(
+            "Generated {} {} in region {}: {} objects, {} terrain cells",
+            request.biome,
+            request.theme,
+            region_id.0,
+            batch.objects.len(),
+            batch.terrain.len()
+        );
+        self.record(&plan, &batch, scope, elapsed).await;
+
+        let summary = batch.summary();
+        let mut batches = self.batches.lock().unwrap();
+        batches.push_back(batch);
+        while batches.len() > self.config.undo_history {
+            batches.pop_front();
+        }
+        Ok(summary)
+    }
+
+    /// Remove the objects batch `id` placed and put back the terrain it
+    /// changed, except cells changed again since. None if there is no such
+    /// batch.
+    pub async fn undo(&self, id: Uuid) -> Option<BatchSummary> {
+        let batch = {
+            let mut batches = self.batches.lock().unwrap();
+            let index = batches.iter().position(|batch| batch.id == id)?;
+            batches.remove(index)?
+        };
+
+        let mut scene = self.scene.write().await;
+        for object_id in &batch.objects {
+            scene.objects.remove(object_id);
+        }
+        for &(x, y, before, after) in &batch.terrain {
+            if scene.terrain.height_at(x, y) == after {
+                scene.terrain.set_height(x, y, before);
+            }
+        }
+        info!("Undid world generation batch {}", id);
+        Some(batch.summary())
+    }
+
+    async fn record(&self, plan: &LayoutPlan, batch: &GenerationBatch, scope: GenerationScope, elapsed: Duration) {
+        let Some(recorder) = &self.recorder else {
+            return;
+        };
+        let request = &batch.request;
+        let decision_type = AIDecisionType::WorldGeneration {
+            generation_type: request.biome.clone(),
+            scope,
+            parameters: HashMap::from([
+                ("density".to_string(), request.density),
+                ("objects".to_string(), batch.objects.len() as f32),
+                ("terrain_cells".to_string(), batch.terrain.len() as f32),
+            ]),
+        };
+        let input_data = serde_json::to_value(request).unwrap_or_default();
+        let decision = AIDecisionRecord {
+            id: batch.id,
+            decision_type: "world_generation".to_string(),
+            context_hash: context_hash(&input_data),
+            decision_data: json!({ "decision_type": decision_type, "plan": plan }),
+            input_data,
+            confidence_score: plan.confidence.clamp(0.0, 1.0) as f64,
+            execution_time_ms: elapsed.as_millis() as i64,
+            model_version: plan.model_version.clone(),
+            metadata: json!({ "provider": batch.provider, "region_id": request.region_id.0 }),
+        };
+        if let Err(e) = recorder.record_ai_decision(&decision).await {
+            warn!("Failed to record world generation decision {}: {}", batch.id, e);
+        }
+    }
+}
+
+/// Apply `plan` to `scene`, remembering what changed
+fn apply(scene: &mut RegionScene, request: &GenerationRequest, plan: &LayoutPlan, provider: &str) -> GenerationBatch {
+    let mut before = HashMap::new();
+    for edit in &plan.terrain {
+        edit_terrain(&mut scene.terrain, edit, &mut before);
+    }
+    let mut terrain: Vec<_> = before
+        .into_iter()
+        .map(|((x, y), old)| (x, y, old, scene.terrain.height_at(x, y)))
+        .filter(|(_, _, before, after)| before != after)
+        .collect();
+    terrain.sort_by_key(|&(x, y, _, _)| (y, x));
+
+    let owner = scene
+        .parcels
+        .first()
+        .map(|parcel| parcel.owner_id)
+        .unwrap_or_else(|| UserId::from_uuid(Uuid::nil()));
+    let description = format!("Generated {} ({})", request.biome, request.theme);
+    let mut local_id = scene.objects.values().map(|object| object.local_id).max().unwrap_or(0);
+    let mut objects = Vec::with_capacity(plan.objects.len());
+    for planned in &plan.objects {
+        local_id += 1;
+        let object = scene_object(planned, &scene.terrain, owner, local_id, &description);
+        objects.push(object.id);
+        scene.add_object(object);
+    }
+
+    GenerationBatch {
+        id: Uuid::new_v4(),
+        request: request.clone(),
+        provider: provider.to_string(),
+        created_at: Utc::now(),
+        objects,
+        terrain,
+    }
+}
+
+/// Apply one brush stroke, keeping the first height seen of each cell
+fn edit_terrain(terrain: &mut Terrain, edit: &TerrainEdit, before: &mut HashMap<(u32, u32), f32>) {
+    let radius = edit.radius.max(1.0);
+    let range = |centre: f32, size: u32| {
+        let low = (centre - radius).floor().max(0.0) as u32;
+        let high = ((centre + radius).ceil().max(0.0) as u32).min(size.saturating_sub(1));
+        low..=high
+    };
+
+    for y in range(edit.y, terrain.size_y) {
+        for x in range(edit.x, terrain.size_x) {
+            let distance = ((x as f32 - edit.x).powi(2) + (y as f32 - edit.y).powi(2)).sqrt();
+            if distance > radius {
+                continue;
+            }
+            let falloff = 1.0 - (distance / radius).powi(2);
+            let height = terrain.height_at(x, y);
+            before.entry((x, y)).or_insert(height);
+            let height = match edit.kind {
+                TerrainEditKind::Raise => height + edit.amount * falloff,
+                TerrainEditKind::Lower => height - edit.amount * falloff,
+                TerrainEditKind::Flatten => height + (edit.amount - height) * falloff,
+            };
+            terrain.set_height(x, y, height.max(0.0));
+        }
+    }
+}
+
+/// A prim for `planned`, resting on the terrain
+fn scene_object(planned: &PlannedObject, terrain: &Terrain, owner: UserId, local_id: u32, description: &str) -> SceneObject {
+    let x = planned.x.clamp(0.0, terrain.size_x.saturating_sub(1) as f32);
+    let y = planned.y.clamp(0.0, terrain.size_y.saturating_sub(1) as f32);
+    let ground = terrain.height_at(x as u32, y as u32);
+    let shape = match planned.shape.as_str() {
+        "cylinder" => ObjectShape {
+            profile_curve: 0,
+            ..ObjectShape::default()
+        },
+        "sphere" => ObjectShape {
+            path_curve: 32,
+            profile_curve: 5,
+            ..ObjectShape::default()
+        },
+        _ => ObjectShape::default(),
+    };
+
+    SceneObject {
+        description: description.to_string(),
+        position: Vector3::new(x, y, ground + planned.scale.z / 2.0),
+        rotation: Quaternion::from_axis_angle(Vector3::new(0.0, 0.0, 1.0), planned.rotation),
+        scale: planned.scale,
+        shape,
+        ..SceneObject::new(local_id, planned.name.clone(), owner)
+    }
+}
+
+/// Terrain and vegetation of a biome for the procedural planner
+struct BiomeStyle {
+    edit: TerrainEditKind,
+    strokes: usize,
+    /// Range of each stroke's amount, in metres
+    amount: (f32, f32),
+    /// Range of each stroke's radius, as a fraction of the area's radius
+    radius: (f32, f32),
+    /// Name, shape and size of what grows or lies around
+    palette: &'static [(&'static str, &'static str, [f32; 3])],
+}
+
+impl BiomeStyle {
+    fn of(biome: &str) -> Self {
+        match biome.to_lowercase().as_str() {
+            "mountains" | "mountain" | "alpine" => Self {
+                edit: TerrainEditKind::Raise,
+                strokes: 4,
+                amount: (20.0, 40.0),
+                radius: (0.3, 0.5),
+                palette: &[("Pine", "cylinder", [0.6, 0.6, 10.0]), ("Boulder", "sphere", [3.0, 2.5, 2.0])],
+            },
+            "desert" | "dunes" => Self {
+                edit: TerrainEditKind::Raise,
+                strokes: 10,
+                amount: (1.0, 3.0),
+                radius: (0.1, 0.2),
+                palette: &[("Cactus", "cylinder", [0.5, 0.5, 3.0]), ("Rock", "sphere", [1.5, 1.2, 0.8])],
+            },
+            "lake" | "swamp" | "wetland" | "coast" => Self {
+                edit: TerrainEditKind::Lower,
+                strokes: 2,
+                amount: (3.0, 8.0),
+                radius: (0.3, 0.6),
+                palette: &[("Reeds", "cylinder", [0.2, 0.2, 2.0]), ("Stone", "sphere", [1.0, 1.0, 0.6])],
+            },
+            "plains" | "grassland" | "tundra" => Self {
+                edit: TerrainEditKind::Flatten,
+                strokes: 1,
+                amount: (DEFAULT_TERRAIN_HEIGHT, DEFAULT_TERRAIN_HEIGHT),
+                radius: (1.0, 1.0),
+                palette: &[("Shrub", "sphere", [1.5, 1.5, 1.0]), ("Boulder", "sphere", [2.0, 2.0, 1.5])],
+            },
+            _ => Self {
+                edit: TerrainEditKind::Raise,
+                strokes: 6,
+                amount: (2.0, 6.0),
+                radius: (0.2, 0.4),
+                palette: &[
+                    ("Tree", "cylinder", [0.8, 0.8, 8.0]),
+                    ("Bush", "sphere", [2.0, 2.0, 1.5]),
+                    ("Rock", "sphere", [1.5, 1.2, 0.8]),
+                ],
+            },
+        }
+    }
+}
+
+/// Plans layouts without an AI service: biome-shaped terrain strokes and
+/// prims scattered at the requested density
+pub struct ProceduralProvider {
+    /// Region width and depth in metres
+    region_size: (f32, f32),
+}
+
+impl ProceduralProvider {
+    pub fn new(size_x: u32, size_y: u32) -> Self {
+        Self {
+            region_size: (size_x as f32, size_y as f32),
+        }
+    }
+}
+
+/// Square metres per object at full density
+const AREA_PER_OBJECT: f32 = 200.0;
+
+#[async_trait]
+impl AIProvider for ProceduralProvider {
+    fn name(&self) -> &str {
+        "procedural"
+    }
+
+    async fn plan_layout(&self, request: &GenerationRequest) -> MutseaResult<LayoutPlan> {
+        let mut rng = match request.seed {
+            0 => StdRng::from_entropy(),
+            seed => StdRng::seed_from_u64(seed),
+        };
+        let (width, depth) = self.region_size;
+        let (cx, cy, radius) = request.area.unwrap_or((width / 2.0, depth / 2.0, width.max(depth) / 2.0));
+        let point = |rng: &mut StdRng, within: f32| {
+            let angle = rng.gen_range(0.0..TAU);
+            let distance = within * rng.gen::<f32>().sqrt();
+            (cx + distance * angle.cos(), cy + distance * angle.sin())
+        };
+        let style = BiomeStyle::of(&request.biome);
+
+        let terrain = (0..style.strokes)
+            .map(|_| {
+                let (x, y) = point(&mut rng, radius * 0.6);
+                TerrainEdit {
+                    kind: style.edit,
+                    x,
+                    y,
+                    radius: radius * rng.gen_range(style.radius.0..=style.radius.1),
+                    amount: rng.gen_range(style.amount.0..=style.amount.1),
+                }
+            })
+            .collect();
+
+        let count = (request.density * PI * radius * radius / AREA_PER_OBJECT).round() as usize;
+        let mut objects: Vec<PlannedObject> = (0..count)
+            .map(|_| {
+                let (name, shape, size) = style.palette[rng.gen_range(0..style.palette.len())];
+                let grow = rng.gen_range(0.7..1.3);
+                let (x, y) = point(&mut rng, radius);
+                PlannedObject {
+                    name: name.to_string(),
+                    shape: shape.to_string(),
+                    x,
+                    y,
+                    scale: Vector3::new(size[0] * grow, size[1] * grow, size[2] * grow),
+                    rotation: rng.gen_range(0.0..TAU),
+                }
+            })
+            .collect();
+
+        // A few buildings in the requested style
+        let theme = request.theme.trim();
+        if !theme.is_empty() {
+            for _ in 0..1 + (request.density * 4.0) as usize {
+                let (x, y) = point(&mut rng, radius * 0.8);
+                objects.push(PlannedObject {
+                    name: format!("{} structure", theme),
+                    shape: "box".to_string(),
+                    x,
+                    y,
+                    scale: Vector3::new(rng.gen_range(4.0..10.0), rng.gen_range(4.0..10.0), rng.gen_range(3.0..8.0)),
+                    rotation: rng.gen_range(0.0..TAU),
+                });
+            }
+        }
+
+        Ok(LayoutPlan {
+            terrain,
+            objects,
+            confidence: 0.5,
+            model_version: "procedural-1".to_string(),
+            rationale: format!("{} terrain strokes for {} with scattered vegetation", style.strokes, request.biome),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutsea_core::{RegionId, RegionInfo};
+
+    fn scene() -> Arc<RwLock<RegionScene>> {
+        let info = RegionInfo::new("Test".to_string(), 1000, 1000, String::new(), String::new());
+        Arc::new(RwLock::new(RegionScene::new(info, UserId::new())))
+    }
+
+    #[tokio::test]
+    async fn test_generate_and_undo() {
+        let scene = scene();
+        let region_id = scene.read().await.info.region_id;
+        let generator = WorldGenerator::new(
+            WorldGenerationConfig::default(),
+            Arc::new(ProceduralProvider::new(256, 256)),
+            Arc::clone(&scene),
+        );
+        let request = GenerationRequest {
+            region_id,
+            biome: "forest".to_string(),
+            theme: "ruins".to_string(),
+            density: 0.5,
+            area: Some((128.0, 128.0, 40.0)),
+            seed: 7,
+        };
+
+        let wrong_region = GenerationRequest {
+            region_id: RegionId::new(),
+            ..request.clone()
+        };
+        assert!(generator.generate(wrong_region).await.is_err());
+
+        let batch = generator.generate(request.clone()).await.unwrap();
+        assert!(batch.objects > 0 && batch.terrain_cells > 0);
+        {
+            let scene = scene.read().await;
+            assert_eq!(scene.objects.len(), batch.objects);
+            assert!(scene.objects.values().all(|o| (o.position.x - 128.0).abs() <= 40.0));
+            assert!(scene.terrain.heights.iter().any(|&h| h > DEFAULT_TERRAIN_HEIGHT));
+        }
+        assert_eq!(generator.batches(), vec![batch.clone()]);
+
+        assert_eq!(generator.undo(batch.id).await, Some(batch.clone()));
+        assert_eq!(generator.undo(batch.id).await, None);
+        let scene = scene.read().await;
+        assert!(scene.objects.is_empty());
+        assert!(scene.terrain.heights.iter().all(|&h| h == DEFAULT_TERRAIN_HEIGHT));
+    }
+
+    #[tokio::test]
+    async fn test_procedural_plans_follow_request() {
+        let provider = ProceduralProvider::new(256, 256);
+        let request = |biome: &str, density, seed| GenerationRequest {
+            region_id: RegionId::new(),
+            biome: biome.to_string(),
+            theme: String::new(),
+            density,
+            area: None,
+            seed,
+        };
+
+        let plan = provider.plan_layout(&request("desert", 0.5, 3)).await.unwrap();
+        assert_eq!(plan, provider.plan_layout(&request("desert", 0.5, 3)).await.unwrap());
+        assert!(plan.objects.iter().all(|o| o.name == "Cactus" || o.name == "Rock"));
+
+        let sparse = provider.plan_layout(&request("desert", 0.1, 3)).await.unwrap();
+        assert!(sparse.objects.len() < plan.objects.len());
+        let lake = provider.plan_layout(&request("lake", 0.5, 3)).await.unwrap();
+        assert!(lake.terrain.iter().all(|edit| edit.kind == TerrainEditKind::Lower));
+    }
+}